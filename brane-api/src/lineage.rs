@@ -0,0 +1,27 @@
+use anyhow::{Context as _, Result};
+use scylla::Session;
+
+/// Creates the table that keeps the lineage of jobs (which session ran which package where, and how that went), as reported by brane-drv.
+pub async fn ensure_db_table(scylla: &Session) -> Result<()> {
+    scylla
+        .query(
+            "CREATE TABLE IF NOT EXISTS brane.lineage (
+                  session text
+                , correlation_id text
+                , package text
+                , version text
+                , digest text
+                , location text
+                , started bigint
+                , finished bigint
+                , outcome text
+                , error text
+                , PRIMARY KEY (session, correlation_id)
+            )",
+            &[],
+        )
+        .await
+        .context("Failed to create 'brane.lineage' table.")?;
+
+    Ok(())
+}
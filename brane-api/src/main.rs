@@ -10,6 +10,7 @@ mod errors;
 mod health;
 mod version;
 /*******/
+mod lineage;
 mod packages;
 mod schema;
 
@@ -95,6 +96,7 @@ async fn main() -> Result<()> {
 
     ensure_db_keyspace(&scylla).await?;
     packages::ensure_db_table(&scylla).await?;
+    lineage::ensure_db_table(&scylla).await?;
 
     let scylla = Arc::new(scylla);
     let registry = opts.registry.clone();
@@ -125,6 +127,26 @@ async fn main() -> Result<()> {
         .and(warp::filters::body::bytes())
         .and(context.clone())
         .and_then(packages::upload);
+
+    // Configure the (detached) package signatures
+    let download_signature = warp::path("packages")
+        .and(warp::get())
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path("signature"))
+        .and(warp::path::end())
+        .and(context.clone())
+        .and_then(packages::download_signature);
+
+    let upload_signature = warp::path("packages")
+        .and(warp::post())
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path("signature"))
+        .and(warp::path::end())
+        .and(warp::filters::body::bytes())
+        .and(context.clone())
+        .and_then(packages::upload_signature);
     
     /* TIM */
     // Configure the health & version
@@ -136,7 +158,7 @@ async fn main() -> Result<()> {
         .and_then(version::handle);
     /*******/
 
-    let packages = download_package.or(upload_package);
+    let packages = download_package.or(upload_package).or(download_signature).or(upload_signature);
     /* TIM */
     // let routes = graphql.or(packages).with(warp::log("brane-api"));
     let routes = health.or(version.or(graphql.or(packages))).with(warp::log("brane-api"));
@@ -6,7 +6,10 @@ use scylla::cql_to_rust::FromCqlVal;
 use scylla::macros::{FromUserType, IntoUserType};
 use scylla::Session;
 use specifications::package::{PackageInfo, PackageKind};
+use specifications::signing::{verify_package, PackageSignature, TrustedKey};
+use specifications::version::Version;
 use std::convert::{TryFrom, TryInto};
+use std::str::FromStr;
 use std::sync::Arc;
 use tar::Archive;
 use tokio::fs::File as TokioFile;
@@ -31,6 +34,9 @@ pub struct PackageUdt {
     pub owners: Vec<String>,
     pub types_as_json: String,
     pub version: String,
+    pub dependencies_as_json: Option<String>,
+    pub requirements_as_json: Option<String>,
+    pub environment_as_json: Option<String>,
 }
 
 impl TryFrom<PackageInfo> for PackageUdt {
@@ -39,6 +45,9 @@ impl TryFrom<PackageInfo> for PackageUdt {
     fn try_from(package: PackageInfo) -> Result<Self> {
         let functions_as_json = serde_json::to_string(&package.functions)?;
         let types_as_json = serde_json::to_string(&package.types)?;
+        let dependencies_as_json = serde_json::to_string(&package.dependencies)?;
+        let requirements_as_json = serde_json::to_string(&package.requirements)?;
+        let environment_as_json = serde_json::to_string(&package.environment)?;
 
         Ok(Self {
             created: package.created.timestamp_millis(),
@@ -52,10 +61,30 @@ impl TryFrom<PackageInfo> for PackageUdt {
             owners: package.owners,
             types_as_json,
             version: package.version.to_string(),
+            dependencies_as_json: Some(dependencies_as_json),
+            requirements_as_json: Some(requirements_as_json),
+            environment_as_json: Some(environment_as_json),
         })
     }
 }
 
+impl TryFrom<PackageUdt> for PackageInfo {
+    type Error = anyhow::Error;
+
+    fn try_from(row: PackageUdt) -> Result<Self> {
+        let version = Version::from_str(&row.version).with_context(|| format!("Illegal version '{}'", row.version))?;
+        let kind = PackageKind::from_str(&row.kind).with_context(|| format!("Illegal package kind '{}'", row.kind))?;
+        let functions = serde_json::from_str(&row.functions_as_json)?;
+        let types = serde_json::from_str(&row.types_as_json)?;
+        let dependencies = row.dependencies_as_json.as_deref().map(serde_json::from_str).transpose()?.unwrap_or_default();
+
+        let mut package = PackageInfo::new(row.name, version, kind, row.owners, row.description, row.detached, functions, types, dependencies);
+        package.id = row.id;
+        package.digest = Some(row.digest);
+        Ok(package)
+    }
+}
+
 ///
 ///
 ///
@@ -74,12 +103,35 @@ pub async fn ensure_db_table(scylla: &Session) -> Result<()> {
                 , owners list<text>
                 , types_as_json text
                 , version text
+                , dependencies_as_json text
+                , requirements_as_json text
+                , environment_as_json text
             )",
             &[],
         )
         .await
         .context("Failed to create 'brane.package' type.")?;
 
+    // Types created by older versions lack the dependencies, requirements and environment; add them (this fails harmlessly if the field already exists)
+    if let Err(err) = scylla
+        .query("ALTER TYPE brane.package ADD dependencies_as_json text", &[])
+        .await
+    {
+        debug!("Did not add 'dependencies_as_json' to 'brane.package' type: {}", err);
+    }
+    if let Err(err) = scylla
+        .query("ALTER TYPE brane.package ADD requirements_as_json text", &[])
+        .await
+    {
+        debug!("Did not add 'requirements_as_json' to 'brane.package' type: {}", err);
+    }
+    if let Err(err) = scylla
+        .query("ALTER TYPE brane.package ADD environment_as_json text", &[])
+        .await
+    {
+        debug!("Did not add 'environment_as_json' to 'brane.package' type: {}", err);
+    }
+
     scylla
         .query(
             "CREATE TABLE IF NOT EXISTS brane.packages (
@@ -93,6 +145,19 @@ pub async fn ensure_db_table(scylla: &Session) -> Result<()> {
         .await
         .context("Failed to create 'brane.packages' table.")?;
 
+    scylla
+        .query(
+            "CREATE TABLE IF NOT EXISTS brane.package_signatures (
+                  name text
+                , version text
+                , signature text
+                , PRIMARY KEY (name, version)
+            )",
+            &[],
+        )
+        .await
+        .context("Failed to create 'brane.package_signatures' table.")?;
+
     Ok(())
 }
 
@@ -247,3 +312,115 @@ pub async fn upload(
 
     Ok(StatusCode::OK)
 }
+
+/// Returns the (detached) signature of a package, as uploaded by `brane push --sign`.
+/// 
+/// **Arguments**
+///  * `name`: The name of the package.
+///  * `version`: The version of the package.
+///  * `context`: The Context with the database connection.
+/// 
+/// **Returns**  
+/// The signature as JSON, or a 404 if the package is not signed.
+pub async fn download_signature(
+    name: String,
+    version: String,
+    context: Context,
+) -> Result<impl Reply, Rejection> {
+    let query = "SELECT signature FROM brane.package_signatures WHERE name = ? AND version = ?";
+    let rows = context.scylla.query(query, &(&name, &version)).await.map_err(|e| {
+        error!("An error occured while reading the signature of package '{}' (version {}): {}", name, version, e);
+        warp::reject::reject()
+    })?.rows;
+
+    let signature = rows.and_then(|rows| rows.into_typed::<(String,)>().next()).and_then(|row| row.ok()).map(|(signature,)| signature);
+    match signature {
+        Some(signature) => {
+            let mut response = Response::new(Body::from(signature));
+            response.headers_mut().insert("Content-Type", HeaderValue::from_static("application/json"));
+            Ok(response)
+        },
+        None => Err(warp::reject::not_found()),
+    }
+}
+
+/// Stores the (detached) signature of a package. The signature must be valid for the package as it is stored, and may only replace a signature made by the same key; so whoever pushes a package first decides who signs it.
+/// 
+/// **Arguments**
+///  * `name`: The name of the package.
+///  * `version`: The version of the package.
+///  * `signature`: The signature, as JSON (see `PackageSignature`).
+///  * `context`: The Context with the database connection.
+/// 
+/// **Returns**  
+/// 200 OK on success, 400 if the body is not a valid signature of the package, 404 if there is no such package, 409 if the package is already signed by another key, or a rejection if it could not be stored.
+pub async fn upload_signature(
+    name: String,
+    version: String,
+    signature: Bytes,
+    context: Context,
+) -> Result<impl Reply, Rejection> {
+    let signature: PackageSignature = match serde_json::from_slice(&signature) {
+        Ok(signature) => signature,
+        Err(e)        => {
+            debug!("Refusing illegal signature for package '{}' (version {}): {}", name, version, e);
+            return Ok(StatusCode::BAD_REQUEST);
+        },
+    };
+
+    // Only accept signatures that its own key says are valid for the package as we have it
+    let query = "SELECT package FROM brane.packages WHERE name = ? AND version = ?";
+    let rows = context.scylla.query(query, &(&name, &version)).await.map_err(|e| {
+        error!("An error occured while reading package '{}' (version {}): {}", name, version, e);
+        warp::reject::reject()
+    })?.rows;
+    let package: PackageInfo = match rows.and_then(|rows| rows.into_typed::<(PackageUdt,)>().next()) {
+        Some(Ok((package,))) => package.try_into().map_err(|e| {
+            error!("An error occured while reading package '{}' (version {}): {}", name, version, e);
+            warp::reject::reject()
+        })?,
+        Some(Err(e)) => {
+            error!("An error occured while reading package '{}' (version {}): {}", name, version, e);
+            return Err(warp::reject::reject());
+        },
+        None => { return Ok(StatusCode::NOT_FOUND); },
+    };
+    let signer = match TrustedKey::from_base64("signer", &signature.public_key) {
+        Ok(signer) => signer,
+        Err(e)     => {
+            debug!("Refusing signature with illegal key for package '{}' (version {}): {}", name, version, e);
+            return Ok(StatusCode::BAD_REQUEST);
+        },
+    };
+    if let Err(e) = verify_package(&package, &signature, std::slice::from_ref(&signer)) {
+        debug!("Refusing invalid signature for package '{}' (version {}): {}", name, version, e);
+        return Ok(StatusCode::BAD_REQUEST);
+    }
+
+    // Don't let anyone replace the signature of someone else
+    let query = "SELECT signature FROM brane.package_signatures WHERE name = ? AND version = ?";
+    let rows = context.scylla.query(query, &(&name, &version)).await.map_err(|e| {
+        error!("An error occured while reading the signature of package '{}' (version {}): {}", name, version, e);
+        warp::reject::reject()
+    })?.rows;
+    let existing = rows.and_then(|rows| rows.into_typed::<(String,)>().next()).and_then(|row| row.ok()).and_then(|(existing,)| serde_json::from_str::<PackageSignature>(&existing).ok());
+    if let Some(existing) = existing {
+        if existing.public_key.trim() != signature.public_key.trim() {
+            debug!("Refusing signature for package '{}' (version {}), as it is already signed by another key", name, version);
+            return Ok(StatusCode::CONFLICT);
+        }
+    }
+
+    let signature = serde_json::to_string(&signature).map_err(|e| {
+        error!("An error occured while serializing a signature: {}", e);
+        warp::reject::reject()
+    })?;
+
+    let query = "INSERT INTO brane.package_signatures (name, version, signature) VALUES (?, ?, ?)";
+    context.scylla.query(query, (&name, &version, &signature)).await.map_err(|e| {
+        error!("An error occured while storing the signature of package '{}' (version {}): {}", name, version, e);
+        warp::reject::reject()
+    })?;
+
+    Ok(StatusCode::OK)
+}
@@ -1,8 +1,11 @@
 use crate::packages::PackageUdt;
 use crate::Context;
 use chrono::{DateTime, TimeZone, Utc};
-use juniper::{EmptySubscription, FieldResult, GraphQLObject, RootNode};
+use juniper::{EmptySubscription, FieldResult, GraphQLInputObject, GraphQLObject, RootNode};
 use scylla::IntoTypedRows;
+use specifications::version::Version;
+use std::collections::HashMap;
+use std::str::FromStr;
 use uuid::Uuid;
 
 pub type Schema = RootNode<'static, Query, Mutations, EmptySubscription<Context>>;
@@ -21,6 +24,9 @@ pub struct Package {
     pub version: String,
     pub functions_as_json: Option<String>,
     pub types_as_json: Option<String>,
+    pub dependencies_as_json: Option<String>,
+    pub requirements_as_json: Option<String>,
+    pub environment_as_json: Option<String>,
 }
 
 impl From<PackageUdt> for Package {
@@ -39,10 +45,56 @@ impl From<PackageUdt> for Package {
             version: row.version,
             functions_as_json: Some(row.functions_as_json),
             types_as_json: Some(row.types_as_json),
+            dependencies_as_json: row.dependencies_as_json,
+            requirements_as_json: row.requirements_as_json,
+            environment_as_json: row.environment_as_json,
         }
     }
 }
 
+/// A single page of the results of a package search.
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct PackageSearchResult {
+    /// The total number of packages that match the search (on all pages).
+    pub total: i32,
+    /// The packages on the requested page, each in its latest version.
+    pub packages: Vec<Package>,
+}
+
+/// The lineage of a single job: which session ran which package where, and how that went.
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct Lineage {
+    pub session: String,
+    pub correlation_id: String,
+    pub package: String,
+    pub version: String,
+    pub digest: String,
+    pub location: Option<String>,
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    /// Either 'success' or 'failure'.
+    pub outcome: String,
+    /// The error the job failed with, if it failed.
+    pub error: Option<String>,
+}
+
+/// The lineage of a job as reported by brane-drv.
+#[derive(Clone, Debug, GraphQLInputObject)]
+pub struct LineageInput {
+    pub session: String,
+    pub correlation_id: String,
+    pub package: String,
+    pub version: String,
+    pub digest: String,
+    pub location: Option<String>,
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    /// Either 'success' or 'failure'.
+    pub outcome: String,
+    /// The error the job failed with, if it failed.
+    pub error: Option<String>,
+}
+
 pub struct Query;
 
 #[graphql_object(context = Context)]
@@ -91,6 +143,100 @@ impl Query {
 
         Ok(packages)
     }
+
+    /// Searches the packages whose name contains the given term, optionally filtered by kind and owner.
+    ///
+    /// Only the latest version of every matching package is returned, sorted by name. Results are paginated using `limit` (default: all) and `offset` (default: 0).
+    async fn search_packages(
+        term: Option<String>,
+        kind: Option<String>,
+        author: Option<String>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        context: &Context,
+    ) -> FieldResult<PackageSearchResult> {
+        let scylla = context.scylla.clone();
+
+        let like = format!("%{}%", term.unwrap_or_default());
+        let query = "SELECT package FROM brane.packages WHERE name LIKE ? ALLOW FILTERING";
+
+        // Collect the latest version of every package that matches the filters
+        let mut latest: HashMap<String, (Version, PackageUdt)> = HashMap::new();
+        if let Some(rows) = scylla.query(query, &(like,)).await?.rows {
+            for row in rows.into_typed::<(PackageUdt,)>() {
+                let (package,) = row?;
+
+                if let Some(kind) = &kind {
+                    if kind != &package.kind {
+                        continue;
+                    }
+                }
+
+                if let Some(author) = &author {
+                    if !package.owners.contains(author) {
+                        continue;
+                    }
+                }
+
+                let version = match Version::from_str(&package.version) {
+                    Ok(version) => version,
+                    Err(err) => {
+                        warn!("Skipping package '{}' with illegal version '{}': {}", package.name, package.version, err);
+                        continue;
+                    }
+                };
+                match latest.get(&package.name) {
+                    Some((latest_version, _)) if latest_version >= &version => {}
+                    _ => {
+                        latest.insert(package.name.clone(), (version, package));
+                    }
+                }
+            }
+        }
+
+        // Sort and select the requested page
+        let mut packages: Vec<Package> = latest.into_values().map(|(_, package)| package.into()).collect();
+        packages.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+
+        let total = packages.len() as i32;
+        let offset = offset.unwrap_or(0).max(0) as usize;
+        let limit = limit.map(|limit| limit.max(0) as usize).unwrap_or(packages.len());
+        let packages = packages.into_iter().skip(offset).take(limit).collect();
+
+        Ok(PackageSearchResult { total, packages })
+    }
+
+    /// Returns the lineage of every job that the given session ran, in the order they were started.
+    async fn lineage(
+        session: String,
+        context: &Context,
+    ) -> FieldResult<Vec<Lineage>> {
+        let scylla = context.scylla.clone();
+
+        let query = "SELECT session, correlation_id, package, version, digest, location, started, finished, outcome, error FROM brane.lineage WHERE session = ?";
+
+        let mut lineage = vec![];
+        if let Some(rows) = scylla.query(query, &(session,)).await?.rows {
+            for row in rows.into_typed::<(String, String, String, String, String, Option<String>, i64, i64, String, Option<String>)>() {
+                let (session, correlation_id, package, version, digest, location, started, finished, outcome, error) = row?;
+                lineage.push(Lineage {
+                    session,
+                    correlation_id,
+                    package,
+                    version,
+                    digest,
+                    location,
+                    started: Utc.timestamp_millis(started),
+                    finished: Utc.timestamp_millis(finished),
+                    outcome,
+                    error,
+                });
+            }
+        }
+        lineage.sort_by_key(|job| job.started);
+
+        Ok(lineage)
+    }
 }
 
 pub struct Mutations;
@@ -120,6 +266,37 @@ impl Mutations {
 
         let query = "DELETE FROM brane.packages WHERE name = ? AND version = ?";
         scylla.query(query, &(&name, &version)).await?;
+        let query = "DELETE FROM brane.package_signatures WHERE name = ? AND version = ?";
+        scylla.query(query, &(&name, &version)).await?;
+
+        Ok("OK!")
+    }
+
+    /// Records the lineage of a job. Recording the same job twice overwrites the first record.
+    async fn record_lineage(
+        record: LineageInput,
+        context: &Context,
+    ) -> FieldResult<&str> {
+        let scylla = context.scylla.clone();
+
+        let query = "INSERT INTO brane.lineage (session, correlation_id, package, version, digest, location, started, finished, outcome, error) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+        scylla
+            .query(
+                query,
+                (
+                    &record.session,
+                    &record.correlation_id,
+                    &record.package,
+                    &record.version,
+                    &record.digest,
+                    &record.location,
+                    record.started.timestamp_millis(),
+                    record.finished.timestamp_millis(),
+                    &record.outcome,
+                    &record.error,
+                ),
+            )
+            .await?;
 
         Ok("OK!")
     }
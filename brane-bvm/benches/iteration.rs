@@ -0,0 +1,114 @@
+use std::sync::{Arc, Mutex};
+
+use brane_bvm::bytecode::{ChunkMut, FunctionMut, Opcode};
+use brane_bvm::debugger::VmDebugger;
+use brane_bvm::{executor::NoExtExecutor, vm::Vm};
+use brane_dsl::{Compiler, CompilerOptions};
+use criterion::async_executor::FuturesExecutor;
+use criterion::Criterion;
+use criterion::{criterion_group, criterion_main};
+use specifications::common::Value;
+use specifications::package::PackageIndex;
+
+const N: i64 = 10_000;
+
+const INDEX_CODE: &str = r#"
+    let total := 0;
+    let values := args.values;
+    for (let i := 0; i < xyz; i := i + 1) {
+        total := total + values[i];
+    }
+"#;
+
+/// Debugger that only counts the instructions executed.
+struct CountingDebugger(Arc<Mutex<usize>>);
+
+impl VmDebugger for CountingDebugger {
+    fn on_instruction(&mut self, _opcode: Opcode, _ip: usize, _stack_len: usize) {
+        *self.0.lock().unwrap() += 1;
+    }
+}
+
+/// Compiles the loop that indexes the array by hand.
+fn index_loop() -> FunctionMut {
+    let mut compiler = Compiler::new(
+        CompilerOptions::new(brane_dsl::Lang::BraneScript),
+        PackageIndex::empty(),
+    );
+
+    compiler.compile(INDEX_CODE.replace("xyz", &format!("{}", N))).unwrap()
+}
+
+/// Assembles the same loop with OP_ITER and OP_ITER_NEXT.
+fn iter_loop() -> FunctionMut {
+    let mut chunk = ChunkMut::default();
+    let zero = chunk.add_constant(Value::Integer(0));
+    let total = chunk.add_constant(String::from("total").into());
+    let args = chunk.add_constant(String::from("args").into());
+    let values = chunk.add_constant(String::from("values").into());
+
+    chunk.write_pair(Opcode::CONSTANT, zero);
+    chunk.write_pair(Opcode::DEFINE_GLOBAL, total);
+    chunk.write_pair(Opcode::GET_GLOBAL, args);
+    chunk.write_pair(Opcode::GET_PROPERTY, values);
+    chunk.write(Opcode::ITER);
+
+    let loop_start = chunk.code.len();
+    chunk.write_pair(Opcode::ITER_NEXT, 0u8);
+    chunk.write(Opcode::JUMP_IF_FALSE);
+    let exit = chunk.code.len();
+    chunk.write_pair(0x00, 0x00);
+    chunk.write(Opcode::POP);
+
+    chunk.write_pair(Opcode::GET_GLOBAL, total);
+    chunk.write_pair(Opcode::GET_LOCAL, 2u8);
+    chunk.write(Opcode::ADD);
+    chunk.write_pair(Opcode::SET_GLOBAL, total);
+    chunk.write(Opcode::POP);
+
+    chunk.write(Opcode::JUMP_BACK);
+    let jump_back = (chunk.code.len() - loop_start + 2) as u16;
+    chunk.write_bytes(&jump_back.to_be_bytes()[..]);
+    let jump = (chunk.code.len() - exit - 2) as u16;
+    chunk.code[exit..exit + 2].copy_from_slice(&jump.to_be_bytes());
+
+    chunk.write(Opcode::POP);
+    chunk.write_pair(Opcode::POP_N, 2u8);
+    FunctionMut::main(chunk)
+}
+
+fn vm() -> Vm<NoExtExecutor> {
+    let mut vm = Vm::<NoExtExecutor>::default();
+    let values = Value::Array{ data_type: String::from("integer[]"), entries: (0..N).map(Value::Integer).collect() };
+    vm.set_args(vec![ (String::from("values"), values) ].into_iter().collect()).unwrap();
+    vm
+}
+
+async fn run(f: FunctionMut) {
+    let mut vm = vm();
+    vm.main(f).await.unwrap();
+}
+
+/// Returns the number of instructions the given function executes.
+fn count(f: FunctionMut) -> usize {
+    let count = Arc::new(Mutex::new(0));
+    let mut vm = vm();
+    vm.set_debugger(Box::new(CountingDebugger(count.clone())));
+    futures::executor::block_on(vm.main(f)).unwrap();
+    let count = *count.lock().unwrap();
+    count
+}
+
+fn from_elem(c: &mut Criterion) {
+    println!("Instructions executed for {} elements: {} with indexing, {} with OP_ITER", N, count(index_loop()), count(iter_loop()));
+
+    c.bench_function("index loop 10k", |b| {
+        b.to_async(FuturesExecutor).iter(|| run(index_loop()));
+    });
+    c.bench_function("iter loop 10k", move |b| {
+        b.to_async(FuturesExecutor).iter(|| run(iter_loop()));
+    });
+}
+
+criterion_group!(benches, from_elem);
+criterion_main!(benches);
@@ -0,0 +1,231 @@
+/* ARGS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   14 Oct 2026, 21:48:12
+ * Last edited:
+ *   14 Oct 2026, 21:48:12
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Parses the arguments given to a script (e.g., `brane run script.bs --
+ *   key=value`), which the VM exposes to the script as the global `args`.
+**/
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FResult};
+
+use serde_json::Value as JValue;
+use specifications::common::Value;
+
+
+/***** CONSTANTS *****/
+/// The name of the global (and of its class) that holds the script arguments.
+pub const ARGS_GLOBAL: &str = "args";
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_typed_when_unambiguous() {
+        assert_eq!(parse_value("42"), Value::Integer(42));
+        assert_eq!(parse_value("-3"), Value::Integer(-3));
+        assert_eq!(parse_value("0.5"), Value::Real(0.5));
+        assert_eq!(parse_value("true"), Value::Boolean(true));
+        assert_eq!(parse_value("False"), Value::Unicode("False".to_string()));
+        assert_eq!(parse_value("007"), Value::Unicode("007".to_string()));
+        assert_eq!(parse_value("1e5"), Value::Unicode("1e5".to_string()));
+        assert_eq!(parse_value("1."), Value::Unicode("1.".to_string()));
+        assert_eq!(parse_value("nan"), Value::Unicode("nan".to_string()));
+        assert_eq!(parse_value(""), Value::Unicode(String::new()));
+        assert_eq!(parse_value("data/input.csv"), Value::Unicode("data/input.csv".to_string()));
+    }
+
+    #[test]
+    fn args_split_on_first_equals() {
+        let args = parse_args(&["query=a=b".to_string(), "n=1".to_string(), "n=2".to_string()]).unwrap();
+        assert_eq!(args["query"], Value::Unicode("a=b".to_string()));
+        assert_eq!(args["n"], Value::Integer(2));
+
+        assert!(matches!(parse_args(&["flag".to_string()]), Err(ArgsError::MissingSeparator{ .. })));
+        assert!(matches!(parse_args(&["=1".to_string()]), Err(ArgsError::EmptyKey{ .. })));
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let args = args_from_json(r#"{ "name": "world", "n": 3, "t": 0.25, "verbose": true, "files": ["a", "b"] }"#).unwrap();
+        assert_eq!(args["name"], Value::Unicode("world".to_string()));
+        assert_eq!(args["n"], Value::Integer(3));
+        assert_eq!(args_from_json(&args_to_json(&args)).unwrap(), args);
+
+        assert!(matches!(args_from_json("[1, 2]"), Err(ArgsError::JsonNotAnObject{ .. })));
+        assert!(matches!(args_from_json(r#"{ "a": null }"#), Err(ArgsError::IllegalJsonValue{ .. })));
+        assert!(matches!(args_from_json(r#"{ "a": [] }"#), Err(ArgsError::IllegalJsonValue{ .. })));
+        assert!(matches!(args_from_json(r#"{ "a": { "b": 1 } }"#), Err(ArgsError::IllegalJsonValue{ .. })));
+        assert!(matches!(args_from_json(r#"{ "a": [1, "b"] }"#), Err(ArgsError::IllegalJsonValue{ .. })));
+    }
+}
+
+
+
+
+
+/***** ERRORS *****/
+/// Collects errors that relate to parsing script arguments.
+#[derive(Debug)]
+pub enum ArgsError {
+    /// An argument was not given as 'key=value'
+    MissingSeparator{ arg: String },
+    /// An argument had nothing before the '='
+    EmptyKey{ arg: String },
+
+    /// The given JSON could not be parsed
+    JsonParseError{ err: serde_json::Error },
+    /// The given JSON was not an object
+    JsonNotAnObject{ got: String },
+    /// A value in the JSON object cannot be passed as an argument
+    IllegalJsonValue{ key: String, got: String },
+}
+
+impl Display for ArgsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            ArgsError::MissingSeparator{ arg } => write!(f, "Illegal script argument '{}': expected 'key=value'", arg),
+            ArgsError::EmptyKey{ arg }         => write!(f, "Illegal script argument '{}': key cannot be empty", arg),
+
+            ArgsError::JsonParseError{ err }        => write!(f, "Could not parse script arguments as JSON: {}", err),
+            ArgsError::JsonNotAnObject{ got }       => write!(f, "Script arguments must be given as a JSON object, not as {}", got),
+            ArgsError::IllegalJsonValue{ key, got } => write!(f, "Script argument '{}' cannot be {}; only booleans, numbers, strings and non-empty arrays of those are supported", key, got),
+        }
+    }
+}
+
+impl Error for ArgsError {}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Parses the value of a script argument.
+/// 
+/// The value becomes an integer, real or boolean if it unambiguously looks like one, and a string otherwise. Leading zeroes (e.g., '007') are taken as a sign that the value is a string.
+/// 
+/// **Arguments**
+///  * `raw`: The value as given on the command line.
+/// 
+/// **Returns**  
+/// The parsed Value.
+pub fn parse_value(raw: &str) -> Value {
+    match raw {
+        "true"  => { return Value::Boolean(true); },
+        "false" => { return Value::Boolean(false); },
+        _       => {},
+    }
+
+    // Only consider numbers that consist of digits, an optional sign and at most one dot
+    let digits = raw.strip_prefix('-').unwrap_or(raw);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit() || c == '.') || digits.starts_with('.') || digits.ends_with('.') {
+        return Value::Unicode(raw.to_string());
+    }
+    let integral = digits.split('.').next().unwrap();
+    if integral.len() > 1 && integral.starts_with('0') {
+        return Value::Unicode(raw.to_string());
+    }
+
+    if let Ok(integer) = raw.parse::<i64>() { return Value::Integer(integer); }
+    match raw.parse::<f64>() {
+        Ok(real) if real.is_finite() && digits.contains('.') => Value::Real(real),
+        _                                                    => Value::Unicode(raw.to_string()),
+    }
+}
+
+/// Parses a list of 'key=value' script arguments.
+/// Later arguments override earlier ones with the same key.
+/// 
+/// **Arguments**
+///  * `args`: The arguments as given on the command line.
+/// 
+/// **Returns**  
+/// A map of argument names to their values, or an ArgsError if one of them is malformed.
+pub fn parse_args(args: &[String]) -> Result<HashMap<String, Value>, ArgsError> {
+    let mut result = HashMap::with_capacity(args.len());
+    for arg in args {
+        let (key, value) = match arg.split_once('=') {
+            Some(pair) => pair,
+            None       => { return Err(ArgsError::MissingSeparator{ arg: arg.clone() }); }
+        };
+        if key.is_empty() { return Err(ArgsError::EmptyKey{ arg: arg.clone() }); }
+        result.insert(key.to_string(), parse_value(value));
+    }
+    Ok(result)
+}
+
+/// Parses script arguments from a JSON object.
+/// 
+/// **Arguments**
+///  * `json`: The JSON text to parse.
+/// 
+/// **Returns**  
+/// A map of argument names to their values, or an ArgsError if the JSON was malformed or contained unsupported values (nulls, objects, empty or nested arrays).
+pub fn args_from_json(json: &str) -> Result<HashMap<String, Value>, ArgsError> {
+    let json: JValue = match serde_json::from_str(json) {
+        Ok(json) => json,
+        Err(err) => { return Err(ArgsError::JsonParseError{ err }); }
+    };
+    let object = match json {
+        JValue::Object(object) => object,
+        json                   => { return Err(ArgsError::JsonNotAnObject{ got: json_type(&json).to_string() }); }
+    };
+
+    let mut result = HashMap::with_capacity(object.len());
+    for (key, value) in object {
+        let supported = match &value {
+            JValue::Bool(_) | JValue::Number(_) | JValue::String(_) => true,
+            JValue::Array(entries) => !entries.is_empty() && entries.iter().all(|e| json_type(e) == json_type(&entries[0]) && matches!(e, JValue::Bool(_) | JValue::Number(_) | JValue::String(_))),
+            _ => false,
+        };
+        if !supported { return Err(ArgsError::IllegalJsonValue{ key, got: json_type(&value).to_string() }); }
+        result.insert(key, Value::from_json(&value));
+    }
+    Ok(result)
+}
+
+/// Serializes script arguments as a JSON object, such that args_from_json() returns them again.
+/// 
+/// **Arguments**
+///  * `args`: The arguments to serialize.
+/// 
+/// **Returns**  
+/// The JSON text.
+pub fn args_to_json(args: &HashMap<String, Value>) -> String {
+    let object: serde_json::Map<String, JValue> = args.iter().map(|(key, value)| (key.clone(), value.as_json())).collect();
+    JValue::Object(object).to_string()
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Returns a human-readable name for the type of a JSON value.
+fn json_type(value: &JValue) -> &'static str {
+    match value {
+        JValue::Null      => "null",
+        JValue::Bool(_)   => "a boolean",
+        // Integers and reals may be mixed in arrays, so report them the same
+        JValue::Number(_) => "a number",
+        JValue::String(_) => "a string",
+        JValue::Array(_)  => "an array",
+        JValue::Object(_) => "an object",
+    }
+}
+
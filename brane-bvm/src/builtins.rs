@@ -4,8 +4,13 @@ use crate::{
     stack::Slot,
 };
 use crate::heap::{Heap, HeapError};
+use crate::vm::CancelToken;
 use fnv::FnvHashMap;
 use specifications::common::Value;
+use specifications::errors::EnvironmentError;
+use specifications::package::check_environment;
+use specifications::pretty;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /* TIM */
 // const BUILTIN_PRINT_NAME: &str = "print";
@@ -16,6 +21,21 @@ use specifications::common::Value;
 
 // const BUILTIN_SERVICE_NAME: &str = "Service";
 
+/// The builtin functions that scripts can call directly, as registered by `register()`.
+pub const CALLABLE_BUILTINS: [BuiltinFunction; 17] = [
+    BuiltinFunction::Print, BuiltinFunction::Div, BuiltinFunction::Int, BuiltinFunction::Real, BuiltinFunction::Str,
+    BuiltinFunction::Map, BuiltinFunction::Keys, BuiltinFunction::Values, BuiltinFunction::Has,
+    BuiltinFunction::IsUnit, BuiltinFunction::Help, BuiltinFunction::Format,
+    BuiltinFunction::Sleep, BuiltinFunction::Now, BuiltinFunction::Elapsed,
+    BuiltinFunction::WithEnv, BuiltinFunction::MapCall,
+];
+
+/// The builtin classes, as registered by `register()`.
+pub const BUILTIN_CLASSES: [BuiltinClass; 4] = [ BuiltinClass::Service, BuiltinClass::Artifact, BuiltinClass::Output, BuiltinClass::Error ];
+
+/// The longest that `sleep()` waits before it checks whether the run has been cancelled.
+pub const SLEEP_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
 /// Defines the builtin function codes
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -29,6 +49,45 @@ pub enum BuiltinFunction {
     WaitUntilStarted = 0x02,
     /// Waits until a job has been done
     WaitUntilDone = 0x03,
+
+    /// Divides two integers, truncating the result (since '/' always results in a real)
+    Div = 0x04,
+    /// Converts a value to an integer
+    Int = 0x05,
+    /// Converts a value to a real
+    Real = 0x06,
+    /// Converts a value to a string
+    Str = 0x07,
+
+    /// Creates a new, empty map
+    Map = 0x08,
+    /// Returns the keys of a map as an array of strings
+    Keys = 0x09,
+    /// Returns the values of a map as an array
+    Values = 0x0A,
+    /// Checks whether a map has the given key
+    Has = 0x0B,
+
+    /// Checks whether a value is unit (e.g., a null field in the result of an external function)
+    IsUnit = 0x0C,
+
+    /// Returns the documentation of a function (its signature and description) as a string
+    Help = 0x0D,
+
+    /// Renders a value as a string the way print() shows it
+    Format = 0x0E,
+
+    /// Waits for the given number of seconds
+    Sleep = 0x0F,
+    /// Returns the current time as seconds since the Unix epoch
+    Now = 0x10,
+    /// Returns the number of seconds since the given time (as returned by now())
+    Elapsed = 0x11,
+
+    /// Returns an external function that sets the given environment variables of its package when called
+    WithEnv = 0x12,
+    /// Calls an external function once for every map of arguments in an array, returning an array with the results (or Errors) in the same order
+    MapCall = 0x13,
 }
 
 impl BuiltinFunction {
@@ -38,8 +97,49 @@ impl BuiltinFunction {
     /// The string that represents the given Builtin, or else None if the string isn't meant to be accessed directly.
     pub fn signature(&self) -> Option<&str> {
         match self {
-            BuiltinFunction::Print => Some("print"),
-            _                      => None,
+            BuiltinFunction::Print   => Some("print"),
+            BuiltinFunction::Div     => Some("div"),
+            BuiltinFunction::Int     => Some("int"),
+            BuiltinFunction::Real    => Some("real"),
+            BuiltinFunction::Str     => Some("str"),
+            BuiltinFunction::Map     => Some("map"),
+            BuiltinFunction::Keys    => Some("keys"),
+            BuiltinFunction::Values  => Some("values"),
+            BuiltinFunction::Has     => Some("has"),
+            BuiltinFunction::IsUnit  => Some("is_unit"),
+            BuiltinFunction::Help    => Some("help"),
+            BuiltinFunction::Format  => Some("format"),
+            BuiltinFunction::Sleep   => Some("sleep"),
+            BuiltinFunction::Now     => Some("now"),
+            BuiltinFunction::Elapsed => Some("elapsed"),
+            BuiltinFunction::WithEnv => Some("with_env"),
+            BuiltinFunction::MapCall => Some("map_call"),
+            _                        => None,
+        }
+    }
+
+    /// Returns the declared parameters of this Builtin, as (name, type) pairs. Builtins that check their arguments themselves declare them as `any`.
+    /// 
+    /// **Returns**  
+    /// The parameters that the VM checks the arguments against before calling the builtin.
+    pub fn parameters(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            BuiltinFunction::Print   => &[ ("value", "any") ],
+            BuiltinFunction::Div     => &[ ("lhs", "integer"), ("rhs", "integer") ],
+            BuiltinFunction::Int     => &[ ("value", "any") ],
+            BuiltinFunction::Real    => &[ ("value", "any") ],
+            BuiltinFunction::Str     => &[ ("value", "any") ],
+            BuiltinFunction::Keys    => &[ ("map", "map") ],
+            BuiltinFunction::Values  => &[ ("map", "map") ],
+            BuiltinFunction::Has     => &[ ("map", "map"), ("key", "string") ],
+            BuiltinFunction::IsUnit  => &[ ("value", "any") ],
+            BuiltinFunction::Help    => &[ ("function", "any") ],
+            BuiltinFunction::Format  => &[ ("value", "any") ],
+            BuiltinFunction::Sleep   => &[ ("seconds", "any") ],
+            BuiltinFunction::Elapsed => &[ ("start", "any") ],
+            BuiltinFunction::WithEnv => &[ ("function", "any"), ("env", "map") ],
+            BuiltinFunction::MapCall => &[ ("function", "any"), ("arguments", "any") ],
+            _                        => &[],
         }
     }
 }
@@ -50,6 +150,22 @@ impl From<u8> for BuiltinFunction {
             0x01 => BuiltinFunction::Print,
             0x02 => BuiltinFunction::WaitUntilStarted,
             0x03 => BuiltinFunction::WaitUntilDone,
+            0x04 => BuiltinFunction::Div,
+            0x05 => BuiltinFunction::Int,
+            0x06 => BuiltinFunction::Real,
+            0x07 => BuiltinFunction::Str,
+            0x08 => BuiltinFunction::Map,
+            0x09 => BuiltinFunction::Keys,
+            0x0A => BuiltinFunction::Values,
+            0x0B => BuiltinFunction::Has,
+            0x0C => BuiltinFunction::IsUnit,
+            0x0D => BuiltinFunction::Help,
+            0x0E => BuiltinFunction::Format,
+            0x0F => BuiltinFunction::Sleep,
+            0x10 => BuiltinFunction::Now,
+            0x11 => BuiltinFunction::Elapsed,
+            0x12 => BuiltinFunction::WithEnv,
+            0x13 => BuiltinFunction::MapCall,
             _    => BuiltinFunction::Undefined,
         }
     }
@@ -62,6 +178,22 @@ impl std::fmt::Display for BuiltinFunction {
             BuiltinFunction::Print            => write!(f, "print [raw: {}]", *self as u8),
             BuiltinFunction::WaitUntilStarted => write!(f, "wait_until_started [raw: {}]", *self as u8),
             BuiltinFunction::WaitUntilDone    => write!(f, "wait_until_done [raw: {}]", *self as u8),
+            BuiltinFunction::Div              => write!(f, "div [raw: {}]", *self as u8),
+            BuiltinFunction::Int              => write!(f, "int [raw: {}]", *self as u8),
+            BuiltinFunction::Real             => write!(f, "real [raw: {}]", *self as u8),
+            BuiltinFunction::Str              => write!(f, "str [raw: {}]", *self as u8),
+            BuiltinFunction::Map              => write!(f, "map [raw: {}]", *self as u8),
+            BuiltinFunction::Keys             => write!(f, "keys [raw: {}]", *self as u8),
+            BuiltinFunction::Values           => write!(f, "values [raw: {}]", *self as u8),
+            BuiltinFunction::Has              => write!(f, "has [raw: {}]", *self as u8),
+            BuiltinFunction::IsUnit           => write!(f, "is_unit [raw: {}]", *self as u8),
+            BuiltinFunction::Help             => write!(f, "help [raw: {}]", *self as u8),
+            BuiltinFunction::Format           => write!(f, "format [raw: {}]", *self as u8),
+            BuiltinFunction::Sleep            => write!(f, "sleep [raw: {}]", *self as u8),
+            BuiltinFunction::Now              => write!(f, "now [raw: {}]", *self as u8),
+            BuiltinFunction::Elapsed          => write!(f, "elapsed [raw: {}]", *self as u8),
+            BuiltinFunction::WithEnv          => write!(f, "with_env [raw: {}]", *self as u8),
+            BuiltinFunction::MapCall          => write!(f, "map_call [raw: {}]", *self as u8),
         }
     }
 }
@@ -73,12 +205,21 @@ impl std::fmt::Display for BuiltinFunction {
 pub enum BuiltinClass {
     /// The Service class, which represents an asynchronous function
     Service,
+    /// The Artifact class, which refers to a file that an external function stored on shared storage (with its `name`, `size`, `path` and `sha256`)
+    Artifact,
+    /// The Output class, which is what an external function that declares outputs returns: its `value`, plus the `artifacts` it stored
+    Output,
+    /// The Error class, which describes a failed external call: its exit `code`, `stdout`, `stderr` and `message` (see `vm::ERROR_CLASS`)
+    Error,
 }
 
 impl std::fmt::Display for BuiltinClass {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             BuiltinClass::Service   => write!(f, "Service"),
+            BuiltinClass::Artifact  => write!(f, "Artifact"),
+            BuiltinClass::Output    => write!(f, "Output"),
+            BuiltinClass::Error     => write!(f, "Error"),
         }
     }
 }
@@ -97,11 +238,32 @@ pub enum BuiltinError {
     InvalidInstanceError{ builtin: BuiltinFunction },
     /// Error for when an external function could not be scheduled
     ScheduleError{ builtin: BuiltinFunction, function: String, err: ExecutorError },
+    /// Error for when a service did not reach the state we waited for
+    ServiceWaitError{ builtin: BuiltinFunction, service: String, err: ExecutorError },
 
     /// Error for when there are too few arguments passed to a builtin
     NotEnoughArgumentsError{ builtin: BuiltinFunction, expected: usize, got: usize },
     /// Error for when a builtin got too much arguments
     TooManyArgumentsError{ builtin: BuiltinFunction, expected: usize, got: usize },
+    /// Error for when a builtin got an argument of a type it cannot work with
+    IllegalArgumentError{ builtin: BuiltinFunction, expected: String, got: String },
+
+    /// Error for when a value could not be converted to the requested type
+    ConversionError{ builtin: BuiltinFunction, value: String, target: String },
+    /// Error for when an integer division by zero is attempted
+    DivisionByZeroError{ builtin: BuiltinFunction, lhs: i64 },
+    /// Error for when an integer division overflows (i.e., the minimum integer divided by -1)
+    DivisionOverflowError{ builtin: BuiltinFunction, lhs: i64, rhs: i64 },
+
+    /// Error for when a number of seconds is negative or too large to wait for
+    IllegalDurationError{ builtin: BuiltinFunction, seconds: String },
+    /// Error for when the executor could not wait
+    SleepError{ builtin: BuiltinFunction, err: ExecutorError },
+    /// Error for when the run was cancelled while the builtin waited
+    Cancelled{ builtin: BuiltinFunction },
+
+    /// Error for when a script sets environment variables that the package does not allow it to set
+    EnvironmentError{ builtin: BuiltinFunction, function: String, err: EnvironmentError },
 
     /// Error for when an allocation on the Heap failed
     HeapAllocError{ what: String, err: HeapError },
@@ -115,9 +277,21 @@ impl std::fmt::Display for BuiltinError {
             BuiltinError::UnknownOpcode{ opcode } => write!(f, "Unknown builtin opcode '{}'", opcode),
             BuiltinError::InvalidInstanceError{ builtin } => write!(f, "{}: Argument is not an Instance description (either not a struct or doesn't have the 'identifier' field)", builtin),
             BuiltinError::ScheduleError{ builtin, function, err } => write!(f, "{}: Could not schedule function '{}' for execution: {}", builtin, function, err),
+            BuiltinError::ServiceWaitError{ builtin, service, err } => write!(f, "{}: Could not wait for service '{}': {}", builtin, service, err),
 
             BuiltinError::NotEnoughArgumentsError{ builtin, expected, got } => write!(f, "{}: Not enough arguments (got {}, expected {})", builtin, got, expected),
             BuiltinError::TooManyArgumentsError{ builtin, expected, got } => write!(f, "{}: Too many arguments (got {}, expected {})", builtin, got, expected),
+            BuiltinError::IllegalArgumentError{ builtin, expected, got } => write!(f, "{}: Illegal argument of type {} (expected {})", builtin, got, expected),
+
+            BuiltinError::ConversionError{ builtin, value, target } => write!(f, "{}: Cannot convert '{}' to {}", builtin, value, target),
+            BuiltinError::DivisionByZeroError{ builtin, lhs } => write!(f, "{}: Cannot divide {} by zero (use '/' instead to get a real, which is inf on division by zero)", builtin, lhs),
+            BuiltinError::DivisionOverflowError{ builtin, lhs, rhs } => write!(f, "{}: Dividing {} by {} does not fit in an integer", builtin, lhs, rhs),
+
+            BuiltinError::IllegalDurationError{ builtin, seconds } => write!(f, "{}: Cannot wait for {} seconds (expected a non-negative number that is not too large)", builtin, seconds),
+            BuiltinError::SleepError{ builtin, err }               => write!(f, "{}: Could not wait: {}", builtin, err),
+            BuiltinError::Cancelled{ builtin }                     => write!(f, "{}: Cancelled while waiting", builtin),
+
+            BuiltinError::EnvironmentError{ builtin, function, err } => write!(f, "{}: Cannot configure function '{}': {}", builtin, function, err),
 
             BuiltinError::HeapAllocError{ what, err }  => write!(f, "Could not allocate {} on the heap: {}", what, err),
         }
@@ -143,19 +317,31 @@ pub fn register(
     heap: &mut Heap<Object>,
 ) -> Result<(), BuiltinError>{
     // Classes
-    let service_name = format!("{}", BuiltinClass::Service);
-    let service = match heap.alloc(class(service_name.clone())) {
-        Ok(s)       => s,
-        Err(reason) => { return Err(BuiltinError::HeapAllocError{ what: "the Service class".to_string(), err: reason }); }
-    };
-    globals.insert(service_name, Slot::Object(service));
+    for builtin in BUILTIN_CLASSES {
+        let name = format!("{}", builtin);
+        let class = match heap.alloc(class(name.clone())) {
+            Ok(class)   => class,
+            Err(reason) => { return Err(BuiltinError::HeapAllocError{ what: format!("the {} class", name), err: reason }); }
+        };
+        globals.insert(name, Slot::Object(class));
+    }
 
     // Functions
-    globals.insert(BuiltinFunction::Print.signature().unwrap().to_string(), Slot::BuiltIn(BuiltinFunction::Print));
+    for builtin in CALLABLE_BUILTINS {
+        globals.insert(builtin.signature().unwrap().to_string(), Slot::BuiltIn(builtin));
+    }
 
     // Done
     Ok(())
 }
+
+/// Returns whether the global with the given name is one of the builtins that `register()` defines.
+/// 
+/// **Arguments**
+///  * `name`: The name of the global.
+pub fn is_builtin(name: &str) -> bool {
+    BUILTIN_CLASSES.iter().any(|builtin| name == format!("{}", builtin)) || CALLABLE_BUILTINS.iter().any(|builtin| builtin.signature() == Some(name))
+}
 /*******/
 
 ///
@@ -178,6 +364,8 @@ fn class(name: String) -> Object {
 ///  * `arguments`: The arguments for this builtin, as a list of Values
 ///  * `executor`: The executor to run external functions on and to communicate with the client with
 ///  * `_location`: The location where the external buildin will be run at (only here for compatibility reasons)
+///  * `compact_print`: If true, print() shows values that fit on a single line that way (see `pretty::echo()`) instead of indenting them.
+///  * `cancel`: The token with which the run may be cancelled, if any. Builtins that wait (i.e., sleep()) stop waiting once it's cancelled.
 /// 
 /// **Returns**  
 /// The return Value of the builtin on success, or a BuiltinError if it failed.
@@ -186,6 +374,8 @@ pub async fn call<E>(
     arguments: Vec<Value>,
     executor: &E,
     _location: Option<String>,
+    compact_print: bool,
+    cancel: Option<&CancelToken>,
 ) -> Result<Value, BuiltinError>
 where
     E: VmExecutor,
@@ -201,7 +391,7 @@ where
             // Get the argument for this builtin
             let value = arguments.first().unwrap();
             // Get the string representation of the value
-            let text = value.to_string();
+            let text = if compact_print { pretty::echo(value) } else { pretty::pretty(value) };
 
             // Delegate printing to executor.
             if let Err(reason) = executor.stdout(text.clone()).await { return Err(BuiltinError::ClientTxError{ text, err: reason }); }
@@ -217,11 +407,223 @@ where
             debug!("Calling builtin function 'wait_until_done()'");
             wait_until_state(BuiltinFunction::WaitUntilDone, &arguments, executor, ServiceState::Done).await
         }
+        BuiltinFunction::Div => {
+            debug!("Calling builtin function 'div()'");
+            check_arity(builtin, &arguments, 2)?;
+
+            let (lhs, rhs) = match (&arguments[0], &arguments[1]) {
+                (Value::Integer(lhs), Value::Integer(rhs)) => (*lhs, *rhs),
+                (Value::Integer(_), rhs) => { return Err(BuiltinError::IllegalArgumentError{ builtin, expected: "two integers".to_string(), got: rhs.data_type() }); },
+                (lhs, _)                 => { return Err(BuiltinError::IllegalArgumentError{ builtin, expected: "two integers".to_string(), got: lhs.data_type() }); },
+            };
+            if rhs == 0 { return Err(BuiltinError::DivisionByZeroError{ builtin, lhs }); }
+            match lhs.checked_div(rhs) {
+                Some(res) => Ok(Value::Integer(res)),
+                None      => Err(BuiltinError::DivisionOverflowError{ builtin, lhs, rhs }),
+            }
+        }
+        BuiltinFunction::Int => {
+            debug!("Calling builtin function 'int()'");
+            check_arity(builtin, &arguments, 1)?;
+
+            let conversion_error = |value: &Value| BuiltinError::ConversionError{ builtin, value: value.to_string(), target: "an integer".to_string() };
+            match &arguments[0] {
+                Value::Integer(i) => Ok(Value::Integer(*i)),
+                // Truncates towards zero, like a cast would
+                Value::Real(r) if r.is_finite() && *r >= i64::MIN as f64 && *r < i64::MAX as f64 => Ok(Value::Integer(r.trunc() as i64)),
+                Value::Boolean(b) => Ok(Value::Integer(*b as i64)),
+                Value::Unicode(s) => s.trim().parse::<i64>().map(Value::Integer).map_err(|_| conversion_error(&arguments[0])),
+                value @ Value::Real(_) => Err(conversion_error(value)),
+                value => Err(BuiltinError::IllegalArgumentError{ builtin, expected: "a boolean, integer, real or string".to_string(), got: value.data_type() }),
+            }
+        }
+        BuiltinFunction::Real => {
+            debug!("Calling builtin function 'real()'");
+            check_arity(builtin, &arguments, 1)?;
+
+            match &arguments[0] {
+                Value::Integer(i) => Ok(Value::Real(*i as f64)),
+                Value::Real(r)    => Ok(Value::Real(*r)),
+                Value::Boolean(b) => Ok(Value::Real(if *b { 1.0 } else { 0.0 })),
+                Value::Unicode(s) => s.trim().parse::<f64>().map(Value::Real).map_err(|_| BuiltinError::ConversionError{ builtin, value: s.clone(), target: "a real".to_string() }),
+                value => Err(BuiltinError::IllegalArgumentError{ builtin, expected: "a boolean, integer, real or string".to_string(), got: value.data_type() }),
+            }
+        }
+        BuiltinFunction::Str => {
+            debug!("Calling builtin function 'str()'");
+            check_arity(builtin, &arguments, 1)?;
+
+            // The plain representation; format() gives the one that print() uses
+            Ok(Value::Unicode(arguments[0].to_string()))
+        }
+        BuiltinFunction::Map => {
+            debug!("Calling builtin function 'map()'");
+            check_arity(builtin, &arguments, 0)?;
+
+            Ok(Value::Map(Default::default()))
+        }
+        BuiltinFunction::Keys => {
+            debug!("Calling builtin function 'keys()'");
+            check_arity(builtin, &arguments, 1)?;
+
+            let entries = sorted_entries(builtin, &arguments[0])?;
+            let entries = entries.into_iter().map(|(key, _)| Value::Unicode(key.clone())).collect();
+            Ok(Value::Array{ data_type: "string[]".to_string(), entries })
+        }
+        BuiltinFunction::Values => {
+            debug!("Calling builtin function 'values()'");
+            check_arity(builtin, &arguments, 1)?;
+
+            let entries: Vec<Value> = sorted_entries(builtin, &arguments[0])?.into_iter().map(|(_, value)| value.clone()).collect();
+            let data_type = match entries.first() {
+                Some(value) => format!("{}[]", value.data_type()),
+                None        => "unit[]".to_string(),
+            };
+            Ok(Value::Array{ data_type, entries })
+        }
+        BuiltinFunction::Has => {
+            debug!("Calling builtin function 'has()'");
+            check_arity(builtin, &arguments, 2)?;
+
+            match (&arguments[0], &arguments[1]) {
+                (Value::Map(map), Value::Unicode(key)) => Ok(Value::Boolean(map.contains_key(key))),
+                (Value::Map(_), key)                   => Err(BuiltinError::IllegalArgumentError{ builtin, expected: "a map and a string".to_string(), got: key.data_type() }),
+                (map, _)                               => Err(BuiltinError::IllegalArgumentError{ builtin, expected: "a map and a string".to_string(), got: map.data_type() }),
+            }
+        }
+        BuiltinFunction::IsUnit => {
+            debug!("Calling builtin function 'is_unit()'");
+            check_arity(builtin, &arguments, 1)?;
+
+            Ok(Value::Boolean(matches!(arguments[0], Value::Unit)))
+        }
+        BuiltinFunction::Help => {
+            debug!("Calling builtin function 'help()'");
+            check_arity(builtin, &arguments, 1)?;
+
+            match &arguments[0] {
+                Value::FunctionExt(function) => Ok(Value::Unicode(function.help())),
+                Value::Function(function)    => Ok(Value::Unicode(format!("{}({} argument(s))", function.name, function.arity))),
+                value                        => Err(BuiltinError::IllegalArgumentError{ builtin, expected: "a function".to_string(), got: value.data_type() }),
+            }
+        }
+        BuiltinFunction::Format => {
+            debug!("Calling builtin function 'format()'");
+            check_arity(builtin, &arguments, 1)?;
+
+            Ok(Value::Unicode(pretty::pretty(&arguments[0])))
+        }
+        BuiltinFunction::Sleep => {
+            debug!("Calling builtin function 'sleep()'");
+            check_arity(builtin, &arguments, 1)?;
+
+            let seconds = match &arguments[0] {
+                Value::Integer(i) => *i as f64,
+                Value::Real(r)    => *r,
+                value             => { return Err(BuiltinError::IllegalArgumentError{ builtin, expected: "an integer or real".to_string(), got: value.data_type() }); }
+            };
+            if !seconds.is_finite() || seconds < 0.0 || seconds >= u64::MAX as f64 { return Err(BuiltinError::IllegalDurationError{ builtin, seconds: arguments[0].to_string() }); }
+            let deadline = match Instant::now().checked_add(Duration::from_secs_f64(seconds)) {
+                Some(deadline) => deadline,
+                None           => { return Err(BuiltinError::IllegalDurationError{ builtin, seconds: arguments[0].to_string() }); }
+            };
+
+            // Wait in small steps, so a cancelled run doesn't hang on to the session until the sleep is over
+            loop {
+                if cancel.map(|token| token.is_cancelled()).unwrap_or(false) { return Err(BuiltinError::Cancelled{ builtin }); }
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() { break; }
+                if let Err(err) = executor.sleep(remaining.min(SLEEP_CHECK_INTERVAL)).await { return Err(BuiltinError::SleepError{ builtin, err }); }
+            }
+            Ok(Value::Unit)
+        }
+        BuiltinFunction::Now => {
+            debug!("Calling builtin function 'now()'");
+            check_arity(builtin, &arguments, 0)?;
+
+            Ok(Value::Integer(epoch_seconds().trunc() as i64))
+        }
+        BuiltinFunction::Elapsed => {
+            debug!("Calling builtin function 'elapsed()'");
+            check_arity(builtin, &arguments, 1)?;
+
+            let start = match &arguments[0] {
+                Value::Integer(i) => *i as f64,
+                Value::Real(r)    => *r,
+                value             => { return Err(BuiltinError::IllegalArgumentError{ builtin, expected: "an integer or real".to_string(), got: value.data_type() }); }
+            };
+            Ok(Value::Real(epoch_seconds() - start))
+        }
+        BuiltinFunction::WithEnv => {
+            debug!("Calling builtin function 'with_env()'");
+            check_arity(builtin, &arguments, 2)?;
+
+            let mut function = match &arguments[0] {
+                Value::FunctionExt(function) => function.clone(),
+                value                        => { return Err(BuiltinError::IllegalArgumentError{ builtin, expected: "an external function".to_string(), got: value.data_type() }); }
+            };
+            let mut env = function.env.clone();
+            for (name, value) in sorted_entries(builtin, &arguments[1])? {
+                let value = match value {
+                    Value::Unicode(value) => value.clone(),
+                    Value::Integer(_) | Value::Real(_) | Value::Boolean(_) => value.to_string(),
+                    value => { return Err(BuiltinError::IllegalArgumentError{ builtin, expected: "a map of strings, numbers or booleans".to_string(), got: value.data_type() }); }
+                };
+                env.insert(name.clone(), value);
+            }
+
+            // Only what the package declares as configurable may be set
+            if let Err(err) = check_environment(&function.package, &function.environment, &env) { return Err(BuiltinError::EnvironmentError{ builtin, function: function.name, err }); }
+            function.env = env;
+            Ok(Value::FunctionExt(function))
+        }
         _ => Err(BuiltinError::UnknownOpcode{ opcode: 0 }),
     }
 }
 /*******/
 
+/// Checks that a builtin got exactly the given number of arguments.
+/// 
+/// **Arguments**
+///  * `builtin`: The builtin that is being called.
+///  * `arguments`: The arguments it got.
+///  * `expected`: The number of arguments it takes.
+/// 
+/// **Returns**  
+/// Nothing if the number matches, or a NotEnoughArgumentsError or TooManyArgumentsError otherwise.
+pub(crate) fn check_arity(builtin: BuiltinFunction, arguments: &[Value], expected: usize) -> Result<(), BuiltinError> {
+    if arguments.len() < expected { return Err(BuiltinError::NotEnoughArgumentsError{ builtin, expected, got: arguments.len() }); }
+    else if arguments.len() > expected { return Err(BuiltinError::TooManyArgumentsError{ builtin, expected, got: arguments.len() }); }
+    Ok(())
+}
+
+/// Returns the current time as (fractional) seconds since the Unix epoch, or a negative number if the clock is set before it.
+fn epoch_seconds() -> f64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since)  => since.as_secs_f64(),
+        Err(until) => -until.duration().as_secs_f64(),
+    }
+}
+
+/// Returns the entries of a map argument, sorted by key.
+/// 
+/// **Arguments**
+///  * `builtin`: The builtin that is being called.
+///  * `map`: The argument that should be a map.
+/// 
+/// **Returns**  
+/// The (key, value) pairs of the map on success, or an IllegalArgumentError if the argument is not a map.
+fn sorted_entries(builtin: BuiltinFunction, map: &Value) -> Result<Vec<(&String, &Value)>, BuiltinError> {
+    match map {
+        Value::Map(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+            Ok(entries)
+        }
+        value => Err(BuiltinError::IllegalArgumentError{ builtin, expected: "a map".to_string(), got: value.data_type() }),
+    }
+}
+
 /* TIM */
 /// Helper function that starts a shared job and waits until the desired status has been reached.  
 /// The job is read from the list of arguments this function got passed to it.
@@ -249,10 +651,9 @@ async fn wait_until_state<E>(builtin: BuiltinFunction, arguments: &[Value], exec
         if identifier.is_none() { return Err(BuiltinError::InvalidInstanceError{ builtin }); }
         let identifier = identifier.unwrap().to_string();
 
-        // Schedule the job for execution
-        // TODO: Doesn't seem to be implemented in all executors, so need to know more about the errors
+        // Wait for the service; if it fails, that becomes the result of the builtin
         if let Err(reason) = executor.wait_until(identifier.clone(), desired_state).await {
-            return Err(BuiltinError::ScheduleError{ builtin, function: identifier, err: reason });
+            return Err(BuiltinError::ServiceWaitError{ builtin, service: identifier, err: reason });
         }
     } else {
         return Err(BuiltinError::InvalidInstanceError{ builtin });
@@ -94,6 +94,36 @@ pub enum Opcode {
     ///  * A new Array handle on top of the stack, and the actual Array allocated on the heap.
     ARRAY = 0x03,
 
+    /// Performs a bitwise conjunction on the top two items on the stack.
+    /// 
+    /// **Stack arguments**
+    ///  * The righthandside (an integer) of the calculation on the top of the stack.
+    ///  * The lefthandside (an integer) of the calculation as second on the stack.
+    /// 
+    /// **Results**
+    ///  * The result of the calculation on top of the stack, as an integer.
+    BIT_AND = 0x2E,
+
+    /// Performs a bitwise disjunction on the top two items on the stack.
+    /// 
+    /// **Stack arguments**
+    ///  * The righthandside (an integer) of the calculation on the top of the stack.
+    ///  * The lefthandside (an integer) of the calculation as second on the stack.
+    /// 
+    /// **Results**
+    ///  * The result of the calculation on top of the stack, as an integer.
+    BIT_OR = 0x2F,
+
+    /// Performs a bitwise exclusive disjunction on the top two items on the stack.
+    /// 
+    /// **Stack arguments**
+    ///  * The righthandside (an integer) of the calculation on the top of the stack.
+    ///  * The lefthandside (an integer) of the calculation as second on the stack.
+    /// 
+    /// **Results**
+    ///  * The result of the calculation on top of the stack, as an integer.
+    BIT_XOR = 0x30,
+
     /// Performs a function call, possibly external if the function on top of the stack is.
     /// 
     /// **Code arguments**
@@ -107,6 +137,12 @@ pub enum Opcode {
     ///  * The result of the call on top of the stack.
     CALL = 0x04,
 
+    /// Removes the error handler that the matching OP_TRY installed, marking the end of the protected code.
+    /// 
+    /// **Results**
+    ///  * Nothing on the stack, but the topmost error handler (which must belong to the current call frame) is removed.
+    CATCH_END = 0x2D,
+
     /// Creates a new class type on the stack
     /// 
     /// **Code arguments**
@@ -116,6 +152,18 @@ pub enum Opcode {
     ///  * A handle to the new Class object describing the custom type.
     CLASS = 0x05,
 
+    /// Coalesces the top item on the stack, i.e., keeps the lefthandside unless it is Unit. The fallback is only evaluated if it is needed, like an OP_JUMP_IF_FALSE skips a branch.
+    /// 
+    /// **Code arguments**
+    ///  * The offset to jump over the code of the fallback, as an unsigned, 16-bit integer (so that's two bytes).
+    /// 
+    /// **Stack arguments**
+    ///  * The lefthandside (any value) on top of the stack.
+    /// 
+    /// **Results**
+    ///  * The lefthandside on top of the stack if it is not Unit, in which case the callframe pointer jumps over the fallback. Otherwise, the lefthandside is popped and the fallback that follows is evaluated in its place.
+    COALESCE = 0x2B,
+
     /// Moves a constant from the callframe to the stack
     /// 
     /// **Code arguments**
@@ -229,26 +277,67 @@ pub enum Opcode {
     ///  * The result of the comparison on top of the stack, as a boolean.
     GREATER = 0x0E,
 
+    /// Checks if the top two values on the stack if the lefthandside is larger than or equal to the righthandside. Integers are compared to floats as floats, like OP_GREATER does.
+    /// 
+    /// **Stack arguments**
+    ///  * The righthandside (an integer or a float) of the comparison on the top of the stack.
+    ///  * The lefthandside (an integer or a float) of the comparison as second on the stack.
+    /// 
+    /// **Results**
+    ///  * The result of the comparison on top of the stack, as a boolean.
+    GREATER_EQUAL = 0x31,
+
     /// Imports the functions and types of a package into global memory.
     /// 
     /// **Code arguments**
     ///  * The identifier of the package stored as a string in the callframe constant area (so it's actually a byte pointing to it).
+    ///  * The version constraint that the imported version must satisfy (e.g., `^1.2`), stored as a string in the callframe constant area, or a unit constant to import the latest version.
     /// 
     /// **Results**
     ///  * Each of the functions the package exports as a global variable (so that's a FunctionExt).
     ///  * Each of the types the package exports as a global variable (so that's a Class).
     IMPORT = 0x0F,
 
-    /// Indexes a given array and returns the value of the referred element.
+    /// Indexes a given array or map and returns the value of the referred element.
     /// 
     /// **Stack arguments**
-    ///  * The index of the array on the top of the stack, as an integer.
-    ///  * A handle to the array itself, just below that.
+    ///  * The index of the array on the top of the stack, as an integer (or the key of the map, as a string).
+    ///  * A handle to the array or map itself, just below that.
     /// 
     /// **Results**
-    ///  * The value of the indexed element of the array on top of the stack.
+    ///  * The value of the indexed element of the array or map on top of the stack.
     INDEX = 0x10,
 
+    /// Sets an element of a given array or map. Because objects on the heap are immutable, this creates a copy with the element replaced (or, for maps, inserted).
+    /// 
+    /// **Stack arguments**
+    ///  * The new value of the element on top of the stack.
+    ///  * The index of the array as an integer (or the key of the map, as a string) below that.
+    ///  * A handle to the array or map itself, below that.
+    /// 
+    /// **Results**
+    ///  * A handle to the updated array or map on top of the stack.
+    INDEX_SET = 0x28,
+
+    /// Prepares iterating over an Array or a Map by turning it into iterator state of two slots, which is left on the stack until the loop is done.
+    /// 
+    /// **Stack arguments**
+    ///  * A handle to the array or map to iterate over on top of the stack.
+    /// 
+    /// **Results**
+    ///  * A handle to the array to iterate over (for maps, a new array with their keys in sorted order), and above that, the position of the iterator as an integer (starting at 0).
+    ITER = 0x29,
+
+    /// Advances an iterator created by OP_ITER, pushing its next element (if any). Meant to be followed by an OP_JUMP_IF_FALSE that leaves the loop.
+    /// 
+    /// **Code arguments**
+    ///  * The offset of the iterator state (i.e., of the array handle, with the position directly above it) in the current stack, as a single byte.
+    /// 
+    /// **Results**
+    ///  * If the iterator has elements left, the next element and a True boolean above it on top of the stack, and the iterator's position is advanced.
+    ///  * Otherwise, only a False boolean on top of the stack.
+    ITER_NEXT = 0x2A,
+
     /// Moves the instruction pointer in the current frame _forward_.
     /// 
     /// **Code arguments**
@@ -289,6 +378,16 @@ pub enum Opcode {
     ///  * The result of the comparison on top of the stack, as a boolean.
     LESS = 0x14,
 
+    /// Checks if the top two values on the stack if the lefthandside is smaller than or equal to the righthandside. Integers are compared to floats as floats, like OP_LESS does.
+    /// 
+    /// **Stack arguments**
+    ///  * The righthandside (an integer or a float) of the comparison on the top of the stack.
+    ///  * The lefthandside (an integer or a float) of the comparison as second on the stack.
+    /// 
+    /// **Results**
+    ///  * The result of the comparison on top of the stack, as a boolean.
+    LESS_EQUAL = 0x32,
+
     /// Pops the top location off the location stack, returning it on the stack.
     /// 
     /// **Location arguments**
@@ -354,6 +453,16 @@ pub enum Opcode {
     ///  * The flipped version of the top boolean in its place.
     NOT = 0x1A,
 
+    /// Checks if the top two values on the stack are different from each other. This is always the opposite of OP_EQUAL.
+    /// 
+    /// **Stack arguments**
+    ///  * The righthandside (anything) of the comparison on the top of the stack.
+    ///  * The lefthandside (anything) of the comparison as second on the stack.
+    /// 
+    /// **Results**
+    ///  * The result of the comparison on top of the stack, as a boolean.
+    NOT_EQUAL = 0x33,
+
     /// Performs a logical disjunction on the top two items on the stack.
     /// 
     /// **Stack arguments**
@@ -416,6 +525,26 @@ pub enum Opcode {
     ///  * Nothing on top of the stack, but a new value for the given local somewhere down in the stack.
     SET_LOCAL = 0x21,
 
+    /// Shifts the bits of the lefthandside to the left by the righthandside.
+    /// 
+    /// **Stack arguments**
+    ///  * The righthandside (an integer between 0 and 63) of the calculation on the top of the stack.
+    ///  * The lefthandside (an integer) of the calculation as second on the stack.
+    /// 
+    /// **Results**
+    ///  * The result of the calculation on top of the stack, as an integer.
+    SHL = 0x34,
+
+    /// Shifts the bits of the lefthandside to the right by the righthandside. The shift is arithmetic, i.e., it keeps the sign of the lefthandside.
+    /// 
+    /// **Stack arguments**
+    ///  * The righthandside (an integer between 0 and 63) of the calculation on the top of the stack.
+    ///  * The lefthandside (an integer) of the calculation as second on the stack.
+    /// 
+    /// **Results**
+    ///  * The result of the calculation on top of the stack, as an integer.
+    SHR = 0x35,
+
     /// Performs an arithmetic subtraction on the top two items on the stack.
     /// 
     /// **Stack arguments**
@@ -432,6 +561,15 @@ pub enum Opcode {
     ///  * A new boolean that is True on top of the stack.
     TRUE = 0x23,
 
+    /// Installs an error handler for the code that follows, up to the matching OP_CATCH_END. If an external call (or a builtin call) in that code fails, the VM unwinds the call frames and the stack to what they were at this instruction, pushes an Instance describing the error (with the properties `code`, `stdout`, `stderr` and `message`) and continues at the handler.
+    /// 
+    /// **Code arguments**
+    ///  * The offset of the handler code, relative to the instruction after this one, as an unsigned, 16-bit integer (so that's two bytes).
+    /// 
+    /// **Results**
+    ///  * Nothing on the stack, but a new error handler for the current call frame.
+    TRY = 0x2C,
+
     /// Pushes a simple Unit (void value) onto the stack.
     /// 
     /// **Results**
@@ -439,6 +577,40 @@ pub enum Opcode {
     UNIT = 0x24,
 }
 
+impl Opcode {
+    /// Returns the number of bytes of code arguments that follow this opcode in the bytecode.
+    #[inline]
+    pub fn operands(&self) -> usize {
+        match self {
+            Opcode::ARRAY         |
+            Opcode::CALL          |
+            Opcode::CLASS         |
+            Opcode::CONSTANT      |
+            Opcode::DEFINE_GLOBAL |
+            Opcode::DOT           |
+            Opcode::GET_GLOBAL    |
+            Opcode::GET_LOCAL     |
+            Opcode::GET_METHOD    |
+            Opcode::GET_PROPERTY  |
+            Opcode::ITER_NEXT     |
+            Opcode::NEW           |
+            Opcode::PARALLEL      |
+            Opcode::POP_N         |
+            Opcode::SET_GLOBAL    |
+            Opcode::SET_LOCAL     => 1,
+
+            Opcode::COALESCE      |
+            Opcode::IMPORT        |
+            Opcode::JUMP          |
+            Opcode::JUMP_BACK     |
+            Opcode::JUMP_IF_FALSE |
+            Opcode::TRY           => 2,
+
+            _ => 0,
+        }
+    }
+}
+
 impl From<Opcode> for u8 {
     #[inline]
     fn from(value: Opcode) -> Self {
@@ -456,6 +628,30 @@ impl Display for Opcode {
 
 
 /***** HELPER FUNCTIONS *****/
+/// Looks up the line of the instruction at the given offset in a line table.
+/// 
+/// **Arguments**
+///  * `code`: The bytecode that the line table belongs to, used to find where instructions start.
+///  * `lines`: The line table, with one line difference per instruction.
+///  * `offset`: The offset of the instruction's opcode in the bytecode.
+/// 
+/// **Returns**  
+/// The line number, or None if the offset is not covered by the line table or the instruction has no line.
+fn line_at(code: &[u8], lines: &[i32], offset: usize) -> Option<usize> {
+    let mut line: i32 = 0;
+    let mut start: usize = 0;
+    for delta in lines {
+        if start > offset { return None; }
+        line += delta;
+        if start == offset { return if line > 0 { Some(line as usize) } else { None }; }
+
+        // Skip to the next instruction
+        let opcode = Opcode::from_u8(*code.get(start)?)?;
+        start += 1 + opcode.operands();
+    }
+    None
+}
+
 /// Prints out a jump instruction neatly.
 /// 
 /// **Arguments**
@@ -506,6 +702,28 @@ fn constant_instruction(
     }
 }
 
+/// Prints out an import instruction neatly.
+/// 
+/// **Arguments**
+///  * `name`: The name of the instruction.
+///  * `chunk`: The bytecode Chunk to get the package and version constraint from.
+///  * `offset`: The offset into the bytecode where instruction opcode is located.
+///  * `result`: The String to write to.
+fn import_instruction(
+    name: &str,
+    chunk: &Chunk,
+    offset: usize,
+    result: &mut String,
+) {
+    let package = chunk.code[offset + 1];
+    let constraint = chunk.code[offset + 2];
+    write!(result, "{:<16} {:4} | ", name, package).unwrap();
+
+    if let (Some(package), Some(constraint)) = (chunk.constants.get(package as usize), chunk.constants.get(constraint as usize)) {
+        writeln!(result, "{:?} {:?}", package, constraint).unwrap();
+    }
+}
+
 /// Prints out a stack instruction neatly.
 /// 
 /// **Arguments**
@@ -671,7 +889,7 @@ impl FunctionMut {
 
 impl From<SpecFunction> for FunctionMut {
     fn from(f: SpecFunction) -> Self {
-        let chunk = ChunkMut::new(f.bytecode.code[..].into(), f.bytecode.constants);
+        let chunk = ChunkMut::new(f.bytecode.code[..].into(), f.bytecode.constants, f.bytecode.lines);
         Self::new(f.name, f.arity, chunk)
     }
 }
@@ -684,6 +902,7 @@ impl From<FunctionMut> for SpecFunction {
             bytecode: Bytecode {
                 code: f.chunk.code[..].to_vec(),
                 constants: f.chunk.constants,
+                lines: f.chunk.lines,
             },
         }
     }
@@ -698,9 +917,24 @@ pub struct Chunk {
     pub code      : Bytes,
     /// A list of extra constants that are part of this Chunk.
     pub constants : Vec<Slot>,
+    /// The line table of this Chunk (see `ChunkMut::lines`).
+    pub lines     : Vec<i32>,
 }
 
 impl Chunk {
+    /// Returns the line in the source of the instruction at the given offset.
+    /// 
+    /// **Arguments**
+    ///  * `offset`: The offset of the instruction's opcode in the bytecode.
+    /// 
+    /// **Returns**  
+    /// The line number (starting at 1), or None if the Chunk has no line information for that instruction.
+    #[inline]
+    pub fn line(&self, offset: usize) -> Option<usize> {
+        line_at(&self.code, &self.lines, offset)
+    }
+
+
     /// **Edited: now using Opcodes instead of numbers and returning BytecodeErrors.**
     /// 
     /// Disassembles the Chunk into a String showing human-readable assembly from the bytecode.
@@ -729,26 +963,37 @@ impl Chunk {
             write!(result, "{:04} ", offset)?;
             match instruction {
                 // Opcodes we can immediately print without hassle
-                Opcode::ADD       |
-                Opcode::AND       |
-                Opcode::DIVIDE    |
-                Opcode::EQUAL     |
-                Opcode::FALSE     |
-                Opcode::GREATER   |
-                Opcode::INDEX     |
-                Opcode::LESS      |
-                Opcode::LOC       |
-                Opcode::LOC_POP   |
-                Opcode::LOC_PUSH  |
-                Opcode::MULTIPLY  |
-                Opcode::NEGATE    |
-                Opcode::NOT       |
-                Opcode::OR        |
-                Opcode::POP       |
-                Opcode::RETURN    |
-                Opcode::SUBSTRACT |
-                Opcode::TRUE      |
-                Opcode::UNIT      => {
+                Opcode::ADD           |
+                Opcode::AND           |
+                Opcode::BIT_AND       |
+                Opcode::BIT_OR        |
+                Opcode::BIT_XOR       |
+                Opcode::CATCH_END     |
+                Opcode::DIVIDE        |
+                Opcode::EQUAL         |
+                Opcode::FALSE         |
+                Opcode::GREATER       |
+                Opcode::GREATER_EQUAL |
+                Opcode::INDEX         |
+                Opcode::INDEX_SET     |
+                Opcode::ITER          |
+                Opcode::LESS          |
+                Opcode::LESS_EQUAL    |
+                Opcode::LOC           |
+                Opcode::LOC_POP       |
+                Opcode::LOC_PUSH      |
+                Opcode::MULTIPLY      |
+                Opcode::NEGATE        |
+                Opcode::NOT           |
+                Opcode::NOT_EQUAL     |
+                Opcode::OR            |
+                Opcode::POP           |
+                Opcode::RETURN        |
+                Opcode::SHL           |
+                Opcode::SHR           |
+                Opcode::SUBSTRACT     |
+                Opcode::TRUE          |
+                Opcode::UNIT          => {
                     writeln!(result, "{}", &format!("{}", instruction))?;
                 }
 
@@ -759,16 +1004,22 @@ impl Chunk {
                 Opcode::DOT           |
                 Opcode::GET_GLOBAL    |
                 Opcode::GET_METHOD    |
-                Opcode::GET_PROPERTY  |
-                Opcode::IMPORT        => {
+                Opcode::GET_PROPERTY  => {
                     constant_instruction(&format!("{}", instruction), self, offset, &mut result);
                     skip = 1;
                 }
 
+                // Imports are written with the package and the version constraint
+                Opcode::IMPORT => {
+                    import_instruction(&format!("{}", instruction), self, offset, &mut result);
+                    skip = 2;
+                }
+
                 // Opcodes which we write as an instruction with some extra byte argument
                 Opcode::ARRAY      |
                 Opcode::CALL       |
                 Opcode::GET_LOCAL  |
+                Opcode::ITER_NEXT  |
                 Opcode::NEW        |
                 Opcode::PARALLEL   |
                 Opcode::POP_N      |
@@ -787,7 +1038,9 @@ impl Chunk {
                     jump_instruction(&format!("{}", instruction), -1, self, offset, &mut result);
                     skip = 2;
                 }
-                Opcode::JUMP_IF_FALSE => {
+                Opcode::COALESCE      |
+                Opcode::JUMP_IF_FALSE |
+                Opcode::TRY           => {
                     jump_instruction(&format!("{}", instruction), 1, self, offset, &mut result);
                     skip = 2;
                 }
@@ -808,7 +1061,7 @@ impl Chunk {
         // Translate the constant Slots into constant Values.
         let constants = self.constants.into_iter().map(|s| s.into_value()).collect();
         // Return them in a ChunkMut
-        ChunkMut::new(BytesMut::from(&self.code[..]), constants)
+        ChunkMut::new(BytesMut::from(&self.code[..]), constants, self.lines)
     }
 }
 
@@ -821,6 +1074,15 @@ pub struct ChunkMut {
     pub code      : BytesMut,
     /// A list of extra constants that are part of this ChunkMut.
     pub constants : Vec<Value>,
+    /// The line table, with one entry per instruction: the difference between its line in the source and that of the previous instruction.
+    pub lines     : Vec<i32>,
+
+    /// The line that new instructions are attributed to (0 if unknown).
+    line      : usize,
+    /// The line of the last instruction in the line table.
+    last_line : usize,
+    /// The number of code arguments of the last instruction that still have to be written.
+    operands  : usize,
 }
 
 impl Default for ChunkMut {
@@ -830,6 +1092,11 @@ impl Default for ChunkMut {
         Self {
             code: BytesMut::default(),
             constants: Vec::default(),
+            lines: Vec::default(),
+
+            line      : 0,
+            last_line : 0,
+            operands  : 0,
         }
     }
 }
@@ -840,23 +1107,61 @@ impl ChunkMut {
     /// **Arguments**
     ///  * `code`: The (muteable) bytecode to wrap this chunk around.
     ///  * `constants`: The list of extra constants that will be part of this ChunkMut.
+    ///  * `lines`: The line table that belongs to the bytecode (may be empty if there is none).
     #[inline]
     pub fn new(
         code: BytesMut,
         constants: Vec<Value>,
+        lines: Vec<i32>,
     ) -> Self {
-        ChunkMut { code, constants }
+        let last_line = lines.iter().sum::<i32>().max(0) as usize;
+        ChunkMut { code, constants, lines, line: last_line, last_line, operands: 0 }
     }
 
 
 
+    /// Sets the line in the source that the instructions written from now on originate from.
+    /// 
+    /// **Arguments**
+    ///  * `line`: The line number (starting at 1).
+    #[inline]
+    pub fn set_line(&mut self, line: usize) {
+        self.line = line;
+    }
+
+    /// Returns the line in the source of the instruction at the given offset.
+    /// 
+    /// **Arguments**
+    ///  * `offset`: The offset of the instruction's opcode in the bytecode.
+    /// 
+    /// **Returns**  
+    /// The line number (starting at 1), or None if the ChunkMut has no line information for that instruction.
+    #[inline]
+    pub fn line(&self, offset: usize) -> Option<usize> {
+        line_at(&self.code, &self.lines, offset)
+    }
+
+
+
+    /// **Edited: now also keeping track of the line table.**
+    /// 
     /// Writes a new byte to this chunk.
     /// 
     /// **Arguments**
     ///  * `byte`: The byte(-like) to add to the chunk.
     #[inline]
     pub fn write<B: Into<u8>>(&mut self, byte: B) {
-        self.code.put_u8(byte.into());
+        let byte = byte.into();
+        if self.operands > 0 {
+            // It's an argument of the previous instruction
+            self.operands -= 1;
+        } else {
+            // It's a new instruction, so it gets an entry in the line table
+            self.lines.push(self.line as i32 - self.last_line as i32);
+            self.last_line = self.line;
+            self.operands = Opcode::from_u8(byte).map(|opcode| opcode.operands()).unwrap_or(0);
+        }
+        self.code.put_u8(byte);
     }
 
     /// Writes a new set of two bytes to this chunk.  
@@ -880,7 +1185,9 @@ impl ChunkMut {
     ///  * `bytes`: Vector of bytes to add to the of this chunk.
     #[inline]
     pub fn write_bytes(&mut self, bytes: &[u8]) {
-        self.code.extend(bytes);
+        for byte in bytes {
+            self.write(*byte);
+        }
     }
 
 
@@ -973,6 +1280,7 @@ impl ChunkMut {
         Ok(Chunk {
             code: self.code.freeze(),
             constants,
+            lines: self.lines,
         })
     }
 }
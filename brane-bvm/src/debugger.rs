@@ -0,0 +1,68 @@
+/* DEBUGGER.rs
+ *   by Lut99
+ *
+ * Created:
+ *   14 Oct 2026, 19:05:12
+ * Last edited:
+ *   14 Oct 2026, 19:05:12
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Defines the hooks that a debugger can use to follow the VM while it
+ *   executes a program.
+**/
+
+use crate::bytecode::Opcode;
+
+
+/***** LIBRARY TRAITS *****/
+/// Defines the callbacks that the VM calls while it's running a program, if a debugger is attached.
+/// 
+/// All callbacks do nothing by default, so implementations only have to override the ones they're interested in.
+pub trait VmDebugger: Send + Sync {
+    /// Called right before an instruction is executed.
+    /// 
+    /// **Arguments**
+    ///  * `opcode`: The instruction that is about to be executed.
+    ///  * `ip`: The offset of the instruction in the bytecode of the current function.
+    ///  * `stack_len`: The number of slots on the stack before the instruction is executed.
+    fn on_instruction(&mut self, _opcode: Opcode, _ip: usize, _stack_len: usize) {}
+
+    /// Called when a function is called (local, builtin or external).
+    /// 
+    /// **Arguments**
+    ///  * `name`: The name of the function that is called.
+    ///  * `arity`: The number of arguments passed to the function.
+    ///  * `depth`: The depth of the call (i.e., the number of call frames including the callee's).
+    fn on_call(&mut self, _name: &str, _arity: u8, _depth: usize) {}
+
+    /// Called when a function returns.
+    /// 
+    /// **Arguments**
+    ///  * `depth`: The depth of the call that returned (the same as that given to the matching `on_call()`).
+    fn on_return(&mut self, _depth: usize) {}
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// A VmDebugger that simply writes everything it's notified of to the debug log. Used when `VmOptions::debug` is set but no other debugger is attached.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogDebugger;
+
+impl VmDebugger for LogDebugger {
+    fn on_instruction(&mut self, opcode: Opcode, ip: usize, stack_len: usize) {
+        debug!("{:04} {} (stack: {} slots)", ip, opcode, stack_len);
+    }
+
+    fn on_call(&mut self, name: &str, arity: u8, depth: usize) {
+        debug!("Calling '{}' with {} argument(s) (depth {})", name, arity, depth);
+    }
+
+    fn on_return(&mut self, depth: usize) {
+        debug!("Returning from depth {}", depth);
+    }
+}
@@ -0,0 +1,241 @@
+/* DIFF.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 23:59:12
+ * Last edited:
+ *   15 Oct 2026, 23:59:12
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Defines the difference between two VmStates (see `VmState::diff()`),
+ *   i.e., which global variables a statement defined, removed or
+ *   changed.
+**/
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FResult};
+
+use serde::{Deserialize, Serialize};
+use specifications::common::Value;
+
+use crate::builtins::is_builtin;
+
+
+/***** CONSTANTS *****/
+/// The maximum number of characters of a string in a summary; longer ones are cut off.
+pub const MAX_SUMMARY_LENGTH: usize = 16;
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn globals(values: Vec<(&str, Value)>) -> HashMap<String, Value> {
+        values.into_iter().map(|(name, value)| (name.to_string(), value)).collect()
+    }
+
+    fn reals(n: usize) -> Value {
+        Value::Array{ data_type: String::from("real[]"), entries: vec![ Value::Real(1.0); n ] }
+    }
+
+    #[test]
+    fn test_added_and_removed() {
+        let old = globals(vec![ ("a", Value::Integer(1)), ("gone", Value::Unicode(String::from("bye"))) ]);
+        let new = globals(vec![ ("a", Value::Integer(1)), ("b", Value::Boolean(true)), ("x", Value::Integer(42)) ]);
+
+        let diff = diff_globals(&old, &new);
+        assert_eq!(diff.added, vec![
+            (String::from("b"), GlobalSummary{ data_type: String::from("boolean"), value: String::from("true") }),
+            (String::from("x"), GlobalSummary{ data_type: String::from("integer"), value: String::from("42") }),
+        ]);
+        assert_eq!(diff.removed, vec![ (String::from("gone"), GlobalSummary{ data_type: String::from("string"), value: String::from("\"bye\"") }) ]);
+        assert!(diff.changed.is_empty());
+        assert_eq!(format!("{}", diff), "defined b: boolean, defined x: integer, removed gone: string");
+    }
+
+    #[test]
+    fn test_changed() {
+        let old = globals(vec![ ("n", Value::Integer(1)), ("results", reals(3)), ("same", reals(2)) ]);
+        let new = globals(vec![ ("n", Value::Unicode(String::from("one"))), ("results", reals(5)), ("same", reals(2)) ]);
+
+        let diff = diff_globals(&old, &new);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        let names: Vec<&str> = diff.changed.iter().map(|(name, _, _)| name.as_str()).collect();
+        assert_eq!(names, vec![ "n", "results" ]);
+        assert_eq!(format!("{}", diff), "changed n: integer → string, changed results: real[] (3 → 5)");
+
+        // Values that change without changing their summary are still changed
+        let old = globals(vec![ ("xs", Value::Array{ data_type: String::from("integer[]"), entries: vec![ Value::Integer(1) ] }) ]);
+        let new = globals(vec![ ("xs", Value::Array{ data_type: String::from("integer[]"), entries: vec![ Value::Integer(2) ] }) ]);
+        assert_eq!(diff_globals(&old, &new).changed.len(), 1);
+    }
+
+    #[test]
+    fn test_ignores_internal_globals() {
+        let old = globals(vec![]);
+        let new = globals(vec![ ("print", Value::Unit), ("Service", Value::Unit) ]);
+        let diff = diff_globals(&old, &new);
+        assert!(diff.is_empty(), "Unexpected diff: {}", diff);
+        assert_eq!(format!("{}", diff), "");
+    }
+
+    #[test]
+    fn test_summaries() {
+        assert_eq!(summarize(&Value::Unicode("x".repeat(MAX_SUMMARY_LENGTH * 2))), format!("\"{}...", "x".repeat(MAX_SUMMARY_LENGTH - 1)));
+        assert_eq!(summarize(&Value::Real(0.5)), "0.5");
+        assert_eq!(summarize(&Value::Pointer{ data_type: String::from("string"), variable: String::from("token"), secret: true }), "token");
+    }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// The type of a global variable together with a shallow summary of its value (see `summarize()`).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct GlobalSummary {
+    /// The data type of the variable.
+    pub data_type : String,
+    /// The summary of its value.
+    pub value     : String,
+}
+
+impl GlobalSummary {
+    /// Constructor for the GlobalSummary.
+    /// 
+    /// **Arguments**
+    ///  * `value`: The Value of the global variable to summarize.
+    pub fn new(value: &Value) -> Self {
+        Self {
+            data_type : value.data_type(),
+            value     : summarize(value),
+        }
+    }
+}
+
+
+
+/// The global variables that were defined, removed or changed between two VmStates, each sorted by name.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct StateDiff {
+    /// The variables that were defined, with their new value.
+    pub added   : Vec<(String, GlobalSummary)>,
+    /// The variables that were removed, with their old value.
+    pub removed : Vec<(String, GlobalSummary)>,
+    /// The variables that got another value, with their old and new value.
+    pub changed : Vec<(String, GlobalSummary, GlobalSummary)>,
+}
+
+impl StateDiff {
+    /// Returns whether nothing changed.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() }
+}
+
+impl Display for StateDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        let mut parts: Vec<String> = Vec::with_capacity(self.added.len() + self.removed.len() + self.changed.len());
+        parts.extend(self.added.iter().map(|(name, new)| format!("defined {}: {}", name, new.data_type)));
+        parts.extend(self.removed.iter().map(|(name, old)| format!("removed {}: {}", name, old.data_type)));
+        parts.extend(self.changed.iter().map(|(name, old, new)| if old.data_type != new.data_type {
+            format!("changed {}: {} → {}", name, old.data_type, new.data_type)
+        } else {
+            format!("changed {}: {} ({} → {})", name, new.data_type, old.value, new.value)
+        }));
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Returns whether the global with the given name and value is a variable defined by the user, instead of a builtin, function or type.
+/// 
+/// **Arguments**
+///  * `name`: The name of the global.
+///  * `value`: Its value.
+/// 
+/// **Returns**  
+/// Whether the global is a user variable.
+pub fn is_variable(name: &str, value: &Value) -> bool {
+    !is_builtin(name) && !matches!(value, Value::Class(_) | Value::Function(_) | Value::FunctionExt(_))
+}
+
+/// Summarizes a value without going into it: scalars are shown as they are (strings cut off at `MAX_SUMMARY_LENGTH` characters), arrays, maps and structs by their number of entries.
+/// 
+/// **Arguments**
+///  * `value`: The Value to summarize.
+/// 
+/// **Returns**  
+/// The summary of the value.
+pub fn summarize(value: &Value) -> String {
+    match value {
+        Value::Boolean(boolean)            => format!("{}", boolean),
+        Value::Integer(integer)            => format!("{}", integer),
+        Value::Real(real)                  => format!("{}", real),
+        Value::Unicode(string)             => {
+            let quoted = format!("\"{}\"", string);
+            if quoted.chars().count() <= MAX_SUMMARY_LENGTH { return quoted; }
+            format!("{}...", quoted.chars().take(MAX_SUMMARY_LENGTH).collect::<String>())
+        },
+        Value::Array{ entries, .. }        => format!("{}", entries.len()),
+        Value::Map(map)                    => format!("{}", map.len()),
+        Value::Struct{ properties, .. }    => format!("{}", properties.len()),
+        // Never show what a pointer points to, as it may be a secret
+        Value::Pointer{ variable, .. }     => variable.clone(),
+        value                              => value.data_type(),
+    }
+}
+
+/// Computes which variables were defined, removed or changed between the given globals. Builtins, functions and types are ignored (see `is_variable()`).
+/// 
+/// **Arguments**
+///  * `old`: The globals before.
+///  * `new`: The globals after.
+/// 
+/// **Returns**  
+/// The StateDiff between the two.
+pub(crate) fn diff_globals<S1, S2>(old: &HashMap<String, Value, S1>, new: &HashMap<String, Value, S2>) -> StateDiff
+where
+    S1: std::hash::BuildHasher,
+    S2: std::hash::BuildHasher,
+{
+    let mut diff = StateDiff::default();
+    for (name, value) in new.iter().filter(|(name, value)| is_variable(name, value)) {
+        match old.get(name).filter(|old| is_variable(name, old)) {
+            None                                 => diff.added.push((name.clone(), GlobalSummary::new(value))),
+            Some(old) if !same_value(old, value) => diff.changed.push((name.clone(), GlobalSummary::new(old), GlobalSummary::new(value))),
+            Some(_)                              => {},
+        }
+    }
+    for (name, value) in old.iter().filter(|(name, value)| is_variable(name, value)) {
+        if !new.get(name).map(|new| is_variable(name, new)).unwrap_or(false) { diff.removed.push((name.clone(), GlobalSummary::new(value))); }
+    }
+
+    diff.added.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+    diff.removed.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+    diff.changed.sort_by(|(lhs, _, _), (rhs, _, _)| lhs.cmp(rhs));
+    diff
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Returns whether two values are the same, comparing them by their serialized form (since Values cannot be compared directly).
+fn same_value(lhs: &Value, rhs: &Value) -> bool {
+    match (serde_json::to_value(lhs), serde_json::to_value(rhs)) {
+        (Ok(lhs), Ok(rhs)) => lhs == rhs,
+        _                  => false,
+    }
+}
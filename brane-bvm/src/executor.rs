@@ -1,8 +1,10 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use anyhow::Result;
 use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
 
 use specifications::common::{FunctionExt, Value};
 use specifications::errors::EncodeDecodeError;
@@ -10,6 +12,10 @@ use specifications::package::PackageInfoError;
 use specifications::version::Version;
 
 
+/// The number of calls of a `map_call()` that run at the same time if the function does not say otherwise (see `FunctionExt::concurrency`).
+pub const DEFAULT_MAP_CONCURRENCY: usize = 16;
+
+
 /* TIM */
 /// Public enum representing various errors for the Executor
 #[derive(Debug)]
@@ -40,10 +46,14 @@ pub enum ExecutorError {
     DockerImportError{ path: PathBuf, err: bollard::errors::Error },
     /// Could not create the given image
     DockerCreateImageError{ image: String, err: bollard::errors::Error },
+    /// The given image is not available locally, but we may not pull it because we are running offline
+    OfflineImageError{ image: String },
     /// Could not create the given container from the given image
     DockerCreateContainerError{ name: String, image: String, err: bollard::errors::Error },
     /// Could not start the given container from the given image
     DockerStartError{ name: String, image: String, err: bollard::errors::Error },
+    /// Could not create or start the given container, which was restricted by the given sandbox settings
+    DockerSandboxError{ name: String, image: String, sandbox: String, err: bollard::errors::Error },
     /// Could not wait for container to complete
     DockerWaitError{ name: String, image: String, err: bollard::errors::Error },
     /// Could not get logs from the given container
@@ -62,17 +72,30 @@ pub enum ExecutorError {
     /// A container did not have a network while we expected one
     DockerContainerNoNetwork{ name: String },
 
+    /// The session ID of the client is not a valid UUID
+    InvalidSessionIdError{ session_uuid: String, err: String },
+    /// The location that a job was created on is not (or no longer) in the infrastructure file
+    UnknownLocationError{ correlation_id: String, location: String, err: String },
+    /// The call did not name a location, and none could be picked for it (e.g., because none satisfies the requirements of its package)
+    LocationSelectionError{ name: String, package: String, version: Version, err: String },
     /// Could not schedule the command for brane-job
     CommandScheduleError{ topic: String, err: String },
     /// The external job failed to be created / started / w/e
     ExternalCallError{ name: String, package: String, version: Version, err: String },
     /// The external job failed, returning a non-zero exit code
     ExternalCallFailed{ name: String, package: String, version: Version, code: i32, stdout: String, stderr: String },
+    /// The external job failed, but its output could not be parsed into an exit code and stdout/stderr, so only the raw output is known
+    ExternalCallFailedRaw{ name: String, package: String, version: Version, output: String },
     /// The output of the external job could not be decoded properly.
     OutputDecodeError{ name: String, package: String, version: Version, stdout: String, err: EncodeDecodeError },
 
+    /// A service failed (or timed out) while we waited for it to reach a certain state
+    ServiceFailed{ service: String, err: String },
+
     /// Could not send a message to the client
     ClientTxError{ err: String },
+    /// The client did not read its replies, so there was no room for output that may not be dropped in time
+    ClientBackpressure{ what: String, pending: usize, timeout: Duration },
 }
 
 impl std::fmt::Display for ExecutorError {
@@ -92,8 +115,10 @@ impl std::fmt::Display for ExecutorError {
             ExecutorError::DockerConnectionFailed{ err }                  => write!(f, "Could not connect to local Docker instance: {}", err),
             ExecutorError::DockerImportError{ path, err }                 => write!(f, "Cannot import Docker image '{}': {}", path.display(), err),
             ExecutorError::DockerCreateImageError{ image, err }           => write!(f, "Cannot create Docker image '{}': {}", image, err),
+            ExecutorError::OfflineImageError{ image }                     => write!(f, "Image '{}' is not available in the local Docker daemon, and cannot be pulled in offline mode", image),
             ExecutorError::DockerCreateContainerError{ name, image, err } => write!(f, "Could not create Docker container '{}' from image '{}': {}", name, image, err),
             ExecutorError::DockerStartError{ name, image, err }           => write!(f, "Could not start Docker container '{}' from image '{}': {}", name, image, err),
+            ExecutorError::DockerSandboxError{ name, image, sandbox, err } => write!(f, "Could not start Docker container '{}' from image '{}' with sandbox settings ({}): {}", name, image, sandbox, err),
             ExecutorError::DockerWaitError{ name, image, err }            => write!(f, "Could not wait for Docker container '{}' (from image '{}') to complete: {}", name, image, err),
             ExecutorError::DockerLogsError{ name, image, err }            => write!(f, "Could not retrieve logs from Docker container '{}' (from image '{}'): {}", name, image, err),
             ExecutorError::DockerInspectContainerError{ name, err }       => write!(f, "Could not inspect Docker container '{}': {}", name, err),
@@ -104,12 +129,19 @@ impl std::fmt::Display for ExecutorError {
             ExecutorError::DockerContainerNoExitCode{ name } => write!(f, "Docker container '{}' has no exit code after running", name),
             ExecutorError::DockerContainerNoNetwork{ name }  => write!(f, "Docker container '{}' has no networks: expected at least 1", name),
 
+            ExecutorError::InvalidSessionIdError{ session_uuid, err }                         => write!(f, "Session ID '{}' is not a valid UUID: {}", session_uuid, err),
+            ExecutorError::UnknownLocationError{ correlation_id, location, err }              => write!(f, "Could not resolve location '{}' of job '{}': {}", location, correlation_id, err),
+            ExecutorError::LocationSelectionError{ name, package, version, err }              => write!(f, "Could not pick a location for function '{}' from package '{}' (version {}): {}", name, package, version, err),
             ExecutorError::CommandScheduleError{ topic, err }                                 => write!(f, "Could not schedule command on Kafka topic '{}': {}", topic, err),
             ExecutorError::ExternalCallError{ name, package, version, err }                   => write!(f, "External call to function '{}' from package '{}' (version {}) failed to launch:\n{}", name, package, version, err),
             ExecutorError::ExternalCallFailed{ name, package, version, code, stdout, stderr } => write!(f, "External call to function '{}' from package '{}' (version {}) failed with exit code {}:\n\nstdout:\n-------------------------------------------------------------------------------\n{}\n-------------------------------------------------------------------------------\n\nstderr:\n-------------------------------------------------------------------------------\n{}-------------------------------------------------------------------------------\n\n", name, package, version, code, stdout, stderr),
+            ExecutorError::ExternalCallFailedRaw{ name, package, version, output }            => write!(f, "External call to function '{}' from package '{}' (version {}) failed with an unknown exit code; raw output:\n-------------------------------------------------------------------------------\n{}\n-------------------------------------------------------------------------------\n\n", name, package, version, output),
             ExecutorError::OutputDecodeError{ name, package, version, stdout, err }           => write!(f, "Could not decode output of function '{}' from package {} (version {}) from Base64: {}\n\nstdout:\n-------------------------------------------------------------------------------\n{}\n-------------------------------------------------------------------------------\n\n", name, package, version, err, stdout),
 
+            ExecutorError::ServiceFailed{ service, err } => write!(f, "Service '{}' failed: {}", service, err),
+
             ExecutorError::ClientTxError{ err } => write!(f, "Could not write message to remote client: {}", err),
+            ExecutorError::ClientBackpressure{ what, pending, timeout } => write!(f, "Remote client does not read its replies: could not send {} within {:?} ({} replies are still waiting)", what, timeout, pending),
         }
     }
 }
@@ -146,6 +178,49 @@ pub trait VmExecutor {
     ) -> Result<Value, ExecutorError>;
     /*******/
 
+    /// Simulates an external function call instead of performing it, which the VM does in dry-run mode (see `VmOptions::dry_run`).
+    /// 
+    /// Executors may use this to validate the call (e.g., whether its location exists), but should never schedule anything. By default, the call is accepted as-is.
+    /// 
+    /// **Arguments**
+    ///  * `call`: The external function call that would be performed.
+    ///  * `arguments`: Arguments for the function as key/value pairs.
+    ///  * `location`: The location where the function would be run.
+    ///  * `default`: The value synthesized by the VM for the declared return type of the function.
+    /// 
+    /// **Returns**  
+    /// The value to continue the script with (usually `default`), or an ExecutorError if the call would not be possible.
+    async fn dry_call(
+        &self,
+        _call: FunctionExt,
+        _arguments: HashMap<String, Value>,
+        _location: Option<String>,
+        default: Value,
+    ) -> Result<Value, ExecutorError> {
+        Ok(default)
+    }
+
+    /// Calls an external function once for every set of arguments, which is what the `map_call()` builtin does.
+    /// 
+    /// By default, the calls are made with `call()` in batches of at most the function's concurrency (see `call_in_batches()`). Executors may do better, e.g. by scheduling all calls as a single job array.
+    /// 
+    /// **Arguments**
+    ///  * `call`: The external function to call.
+    ///  * `arguments`: The arguments of every call, as key/value pairs.
+    ///  * `location`: The location where the calls should be run.
+    /// 
+    /// **Returns**  
+    /// The result of every call, in the order of `arguments`, where calls that failed don't fail the others. Only if the calls could not be made at all is an ExecutorError returned instead.
+    async fn map_call(
+        &self,
+        call: FunctionExt,
+        arguments: Vec<HashMap<String, Value>>,
+        location: Option<String>,
+    ) -> Result<Vec<Result<Value, ExecutorError>>, ExecutorError> {
+        let concurrency = call.concurrency.map(|concurrency| concurrency as usize).unwrap_or(DEFAULT_MAP_CONCURRENCY);
+        Ok(call_in_batches(self, &call, arguments, location, concurrency).await)
+    }
+
     /* TIM */
     /// **Edited: changed return type to also return ExecutorErrors.**
     /// 
@@ -211,6 +286,51 @@ pub trait VmExecutor {
         state: ServiceState,
     ) -> Result<(), ExecutorError>;
     /*******/
+
+    /// Waits for the given duration, which is how the `sleep()` builtin passes time. The builtin waits in short steps (see `builtins::SLEEP_CHECK_INTERVAL`) and checks whether the run was cancelled in between.
+    /// 
+    /// By default, this waits on the tokio timer, which lets other tasks (e.g., other sessions in the driver) run in the meantime.
+    /// 
+    /// **Arguments**
+    ///  * `duration`: The time to wait.
+    /// 
+    /// **Returns**  
+    /// Nothing once the time has passed, or an ExecutorError if this executor cannot wait.
+    async fn sleep(
+        &self,
+        duration: Duration,
+    ) -> Result<(), ExecutorError> {
+        tokio::time::sleep(duration).await;
+        Ok(())
+    }
+}
+
+/// Calls an external function once for every set of arguments, with at most the given number of calls running at the same time.
+/// 
+/// **Arguments**
+///  * `executor`: The executor to make the calls with.
+///  * `call`: The external function to call.
+///  * `arguments`: The arguments of every call, as key/value pairs.
+///  * `location`: The location where the calls should be run.
+///  * `concurrency`: The maximum number of calls that run at the same time (at least 1).
+/// 
+/// **Returns**  
+/// The result of every call, in the order of `arguments`.
+pub async fn call_in_batches<E>(
+    executor: &E,
+    call: &FunctionExt,
+    arguments: Vec<HashMap<String, Value>>,
+    location: Option<String>,
+    concurrency: usize,
+) -> Vec<Result<Value, ExecutorError>>
+where
+    E: VmExecutor + Sync + ?Sized,
+{
+    stream::iter(arguments)
+        .map(|arguments| executor.call(call.clone(), arguments, location.clone()))
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
 }
 
 #[derive(Clone, Default)]
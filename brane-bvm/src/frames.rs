@@ -152,3 +152,18 @@ impl CallFrame {
         Ok(constant.unwrap())
     }
 }
+
+
+
+/// An error handler installed by OP_TRY, which tells the VM where to continue if an external call fails before the matching OP_CATCH_END.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ErrorHandler {
+    /// The number of CallFrames when the handler was installed (i.e., the handler belongs to the topmost of them).
+    pub depth      : usize,
+    /// The instruction pointer of the handler code in that frame.
+    pub target     : usize,
+    /// The length of the stack when the handler was installed.
+    pub stack_len  : usize,
+    /// The length of the location stack when the handler was installed.
+    pub locations  : usize,
+}
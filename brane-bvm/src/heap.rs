@@ -23,6 +23,63 @@ use std::sync::Arc;
 /***** CONSTANTS *****/
 /// Default recommended heap size to start with
 const DEFAULT_HEAP_SIZE: usize = 512;
+/// Default maximum number of slots the heap may grow to
+pub const DEFAULT_MAX_HEAP_SIZE: usize = 16 * 1024 * 1024;
+/// The factor by which the heap grows when it runs out of slots
+const GROWTH_FACTOR: usize = 2;
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alloc_grow() {
+        // Allocate way more objects than the initial capacity while keeping them alive
+        let mut heap: Heap<usize> = Heap::with_capacity(16, DEFAULT_MAX_HEAP_SIZE);
+        let handles: Vec<Handle<usize>> = (0..50_000).map(|i| heap.alloc(i).expect("Could not allocate object")).collect();
+        assert_eq!(heap.len(), 50_000);
+        assert!(heap.capacity() >= 50_000);
+
+        // The old handles should all still be valid
+        for (i, handle) in handles.iter().enumerate() {
+            assert_eq!(*handle.get(), i);
+        }
+    }
+
+    #[test]
+    fn test_alloc_collect() {
+        // Allocate a lot of objects, but don't keep them alive
+        let mut heap: Heap<usize> = Heap::with_capacity(16, DEFAULT_MAX_HEAP_SIZE);
+        let handle = heap.alloc(42).expect("Could not allocate object");
+        for i in 0..50_000 {
+            heap.alloc(i).expect("Could not allocate object");
+        }
+
+        // The heap should have re-used the slots instead of growing, and kept the live object
+        assert_eq!(heap.capacity(), 16);
+        assert_eq!(*handle.get(), 42);
+    }
+
+    #[test]
+    fn test_alloc_max_size() {
+        // Fill the heap up to its maximum size
+        let mut heap: Heap<usize> = Heap::with_capacity(4, 10);
+        let handles: Vec<Handle<usize>> = (0..10).map(|i| heap.alloc(i).expect("Could not allocate object")).collect();
+        assert_eq!(heap.capacity(), 10);
+
+        // The next one should fail gracefully
+        assert_eq!(heap.alloc(10).err(), Some(HeapError::OutOfMemoryError{ capacity: 10 }));
+
+        // But once we free something, it should work again
+        drop(handles);
+        assert!(heap.alloc(10).is_ok());
+    }
+}
 
 
 
@@ -57,19 +114,6 @@ impl Error for HeapError {}
 
 
 
-/***** HELPER ENUMS *****/
-/// Simple enum defining some states for the garbage collection loop.
-enum GarbageCollectorState<T> {
-    /// We can still insert a new element
-    Pending(Arc<T>),
-    /// We should just remove old ones
-    Remove,
-}
-
-
-
-
-
 /***** HEAP *****/
 /// A Handle to an object for our custom heap implementation.  
 /// Basically just a wrapper around an Arc.
@@ -113,12 +157,16 @@ where
 
 /// Custom Heap implementation that can be used to allocate heap-side data for the VM.
 /// 
+/// The heap starts out with a limited number of slots, and grows by a constant factor whenever it is full (and garbage collection didn't help enough) until it reaches its maximum size.
+/// 
 /// **Generic types**
 ///  * `T`: The type of the objects on the Heap. Since this means every element is always the same, this considerably speeds up allocation times.
 #[derive(Debug)]
 pub struct Heap<T> {
     /// The storage for the T.
     data     : Vec<Arc<T>>,
+    /// The number of slots we currently have available
+    capacity : usize,
     /// Determines the maximum heap size
     max_size : usize,
 }
@@ -130,8 +178,20 @@ impl<T> Heap<T> {
     ///  * `max_size`: The maximum size the Heap can grow. Use something ridiculously high to rely on memory limits instead.
     #[inline]
     pub fn new(max_size: usize) -> Heap<T> {
+        Heap::with_capacity(DEFAULT_HEAP_SIZE, max_size)
+    }
+
+    /// Constructor for the Heap that starts with the given number of slots.
+    /// 
+    /// **Arguments**
+    ///  * `capacity`: The number of slots to start with. Clipped to the maximum size.
+    ///  * `max_size`: The maximum size the Heap can grow. Use something ridiculously high to rely on memory limits instead.
+    #[inline]
+    pub fn with_capacity(capacity: usize, max_size: usize) -> Heap<T> {
+        let capacity = std::cmp::min(capacity, max_size);
         Heap {
-            data     : Vec::with_capacity(max_size),
+            data : Vec::with_capacity(capacity),
+            capacity,
             max_size,
         }
     }
@@ -150,58 +210,59 @@ impl<T> Heap<T> {
         let elem   = Arc::new(obj);
         let handle = Handle{ object: elem.clone() };
 
-        // First: check if there are any free slots in the vector
-        let mut state = GarbageCollectorState::Pending(elem);
-        for i in 0..self.data.len() {
-            // Add extra checking to make sure we don't go out-of-bounds after garbage collection
-            if i >= self.data.len() { break; }
-            
-            // Check if we need to remove this element
-            if Arc::strong_count(&self.data[i]) == 1 {
-                // Match the state
-                state = match state {
-                    GarbageCollectorState::Pending(elem) => {
-                        // Replace it with the element
-                        self.data[i] = elem;
-                        GarbageCollectorState::Remove
-                    },
-                    GarbageCollectorState::Remove => {
-                        // Remove it
-                        self.data.swap_remove(i);
-                        GarbageCollectorState::Remove
-                    },
-                };
-            }
-        }
-        
-        // If it wasn't found, we have to append to the end of the vector
-        if let GarbageCollectorState::Pending(elem) = state {
+        // If we're out of slots, first get rid of any objects that are no longer referenced
+        if self.data.len() >= self.capacity {
+            self.collect();
+
+            // Grow if that didn't free up a decent amount of slots, so we don't have to collect again on the next allocation
+            if self.data.len() >= self.capacity / GROWTH_FACTOR { self.grow(); }
+
             // Make sure we have space
-            if self.data.len() >= self.max_size {
+            if self.data.len() >= self.capacity {
                 return Err(HeapError::OutOfMemoryError{ capacity: self.max_size });
             }
-
-            // Add it
-            self.data.push(elem);
         }
 
+        // Add it
+        self.data.push(elem);
+
         // Done! Return the handle
         Ok(handle)
     }
 
+    /// Removes all objects from the Heap that are not referenced by any Handle anymore.
+    fn collect(&mut self) {
+        self.data.retain(|elem| Arc::strong_count(elem) > 1);
+    }
+
+    /// Grows the number of available slots in the Heap by the growth factor, respecting the maximum size.
+    /// 
+    /// Existing Handles remain valid, since they refer to the objects themselves instead of to their slots.
+    fn grow(&mut self) {
+        let capacity = std::cmp::min(std::cmp::max(self.capacity * GROWTH_FACTOR, 1), self.max_size);
+        if capacity > self.capacity {
+            self.data.reserve_exact(capacity - self.data.len());
+            self.capacity = capacity;
+        }
+    }
+
 
 
     /// Returns the current number of occupied slots on the Heap.
     #[inline]
     pub fn len(&self) -> usize { self.data.len() }
 
-    /// Returns the total capacity of the Heap.
+    /// Returns the number of slots currently available on the Heap (before it has to grow).
+    #[inline]
+    pub fn capacity(&self) -> usize { self.capacity }
+
+    /// Returns the maximum number of slots the Heap may grow to.
     #[inline]
-    pub fn capacity(&self) -> usize { self.max_size }
+    pub fn max_size(&self) -> usize { self.max_size }
 }
 
 impl<T> Default for Heap<T> {
     /// Default constructor for the Heap
     #[inline]
-    fn default() -> Heap<T> { Heap::new(DEFAULT_HEAP_SIZE) }
+    fn default() -> Heap<T> { Heap::new(DEFAULT_MAX_HEAP_SIZE) }
 }
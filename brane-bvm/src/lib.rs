@@ -5,13 +5,17 @@ extern crate log;
 #[macro_use]
 extern crate num_derive;
 
-mod builtins;
+pub mod args;
+pub mod builtins;
 pub mod bytecode;
+pub mod debugger;
+pub mod diff;
 pub mod executor;
 mod frames;
 mod heap;
 pub mod objects;
 mod stack;
+pub mod trace;
 pub mod values;
 pub mod vm;
 
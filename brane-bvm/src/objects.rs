@@ -7,6 +7,7 @@ use specifications::common::FunctionExt;
 use crate::bytecode::{ClassMut, FunctionMut};
 use crate::{bytecode::Chunk, stack::Slot};
 use crate::heap::Handle;
+use crate::vm::ERROR_CLASS;
 
 
 /***** ERRORS *****/
@@ -48,6 +49,8 @@ pub enum Object {
     FunctionExt(FunctionExt),
     /// An instance of a class.
     Instance(Instance),
+    /// A map from string keys to values.
+    Map(FnvHashMap<String, Slot>),
     /// A string.
     String(String),
 }
@@ -79,6 +82,19 @@ impl Object {
         }
     }
 
+    /// Tries to cast the Object to a Map.
+    /// 
+    /// **Returns**  
+    /// A reference to the Map's entries on success, or None otherwise.
+    #[inline]
+    pub fn as_map(&self) -> Option<&FnvHashMap<String, Slot>> {
+        if let Object::Map(map) = self {
+            Some(map)
+        } else {
+            None
+        }
+    }
+
     /// Tries to cast the Object to a String.
     /// 
     /// **Returns**  
@@ -102,6 +118,7 @@ impl Object {
             Object::Function(f)    => format!("Function<{}>", f.name),
             Object::FunctionExt(f) => format!("FunctionExt<{}; {}>", f.name, f.kind),
             Object::Instance(i)    => format!("Instance<{}>", i.class.get().as_class().expect("Instance parent is not a Class").name),
+            Object::Map(_)         => "Map".to_string(),
             Object::String(_)      => "String".to_string(),
         }
     }
@@ -115,6 +132,20 @@ impl Display for Object {
             Object::Function(func) => write!(f, "{}", func),
             Object::FunctionExt(func_ext) => write!(f, "{}", func_ext),
             Object::Instance(instance) => write!(f, "{}", instance),
+            Object::Map(map) => {
+                // Sort the keys to keep the output stable
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+
+                write!(f, "{{")?;
+                let mut first = true;
+                for key in keys {
+                    if first { first = false; }
+                    else { write!(f, ",")?; }
+                    write!(f, "{:?}:{}", key, map[key])?;
+                }
+                write!(f, "}}")
+            }
             Object::String(string) => write!(f, "{}", string),
         }
     }
@@ -135,6 +166,8 @@ pub struct Array {
 impl Array {
     /// Constructor for the Array.
     /// 
+    /// Errors (i.e., instances of `vm::ERROR_CLASS`) fit in an array of any type, since that is how `map_call()` returns the calls that failed.
+    /// 
     /// **Arguments**
     ///  * `elements`: The list of elements that are in this Array. Will be used to deduce the Array's type from.
     /// 
@@ -144,10 +177,11 @@ impl Array {
         // Try to deduce the type from the elements
         let element_type = {
             // Iterate through the slots to find the subtype
-            let mut subtype = String::from("unit");
+            let mut subtype = String::new();
             for elem in &elements {
                 let elemval = elem.clone().into_value();
                 let elemtype = elemval.data_type();
+                if elemtype == ERROR_CLASS { continue; }
                 if subtype.is_empty() { subtype = elemtype; }
                 else if !elemtype.eq(&subtype) {
                     return Err(ObjectError::ArrayError{
@@ -157,6 +191,8 @@ impl Array {
                     });
                 }
             }
+            // Empty arrays have no elements to deduce a type from (and arrays of only Errors are arrays of Errors)
+            if subtype.is_empty() { subtype = if elements.is_empty() { String::from("unit") } else { String::from(ERROR_CLASS) }; }
             subtype
         };
 
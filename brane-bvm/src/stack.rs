@@ -8,7 +8,7 @@ use specifications::common::SpecClass;
 use specifications::common::Value;
 
 use crate::builtins::BuiltinFunction;
-use crate::bytecode::{BytecodeError, ClassMut};
+use crate::bytecode::{BytecodeError, ClassMut, FunctionMut};
 use crate::heap::{Handle, Heap, HeapError};
 use crate::objects::Array;
 use crate::objects::Instance;
@@ -190,6 +190,19 @@ impl Slot {
                     Err(reason) => Err(StackError::HeapAllocError{ what: "an external Function (FunctionExt)".to_string(), err: reason }),
                 }
             }
+            Value::Function(f) => {
+                // Freeze the function on the heap
+                let function = match FunctionMut::from(f).freeze(heap) {
+                    Ok(f)       => f,
+                    Err(reason) => { return Err(StackError::HeapFreezeError{ what: "a Function".to_string(), err: reason }); }
+                };
+
+                // Now try to allocate the frozen function
+                match heap.alloc(Object::Function(function)) {
+                    Ok(handle)  => Ok(Slot::Object(handle)),
+                    Err(reason) => Err(StackError::HeapAllocError{ what: "a Function".to_string(), err: reason }),
+                }
+            }
             Value::Class(c) => {
                 // Freeze the class on the heap
                 let class: ClassMut = c.into();
@@ -226,6 +239,19 @@ impl Slot {
                     Err(reason) => Err(StackError::HeapAllocError{ what: "an Instance of a Struct".to_string(), err: reason }),
                 }
             }
+            Value::Map(entries) => {
+                // Put all values on the heap first
+                let mut map = FnvHashMap::default();
+                for (key, value) in entries {
+                    map.insert(key, Slot::from_value(value, globals, heap)?);
+                }
+
+                // Put the Map itself on the heap
+                match heap.alloc(Object::Map(map)) {
+                    Ok(handle)  => Ok(Slot::Object(handle)),
+                    Err(reason) => Err(StackError::HeapAllocError{ what: "a Map".to_string(), err: reason }),
+                }
+            }
             Value::Array { entries, .. } => {
                 // Put the entries on the stack first
                 let mut new_entries: Vec<Slot> = Vec::with_capacity(entries.len());
@@ -277,7 +303,7 @@ impl Slot {
                     let class: SpecClass = class.into();
                     Value::Class(class)
                 }
-                Object::Function(_)    => panic!("Cannot convert function to value."),
+                Object::Function(f)    => Value::Function(f.clone().unfreeze().into()),
                 Object::FunctionExt(f) => Value::FunctionExt(f.clone()),
                 Object::Instance(i)    => {
                     // Convert the Object-Instance to a Value-Struct
@@ -291,6 +317,11 @@ impl Slot {
                     // Return the Struct
                     Value::Struct { data_type, properties }
                 }
+                Object::Map(m) => {
+                    // Convert the Object-Map to a Value-Map
+                    let entries = m.iter().map(|(k, v)| (k.clone(), v.clone().into_value())).collect();
+                    Value::Map(entries)
+                }
                 Object::String(s) => Value::Unicode(s.clone()),
             },
         }
@@ -318,6 +349,7 @@ impl Slot {
                 Object::Function(f)    => format!("Function<{}>", f.name),
                 Object::FunctionExt(f) => format!("FunctionExt<{}; {}>", f.name, f.kind),
                 Object::Instance(i)    => format!("Instance<{}>", i.class.get().as_class().expect("Instance parent is not a Class").name),
+                Object::Map(_)         => "Map".to_string(),
                 Object::String(_)      => "String".to_string(),
             },
         }
@@ -344,6 +376,7 @@ impl Display for Slot {
                 Object::Function(f) => format!("function<{}>", f.name),
                 Object::FunctionExt(f) => format!("function<{}; {}>", f.name, f.kind),
                 Object::Instance(i) => format!("instance<{}>", i.class.get().as_class().expect("Instance parent is not a Class").name),
+                Object::Map(m) => format!("map<{}>", m.len()),
                 Object::String(s) => format!("{:?}", s),
             },
         };
@@ -0,0 +1,173 @@
+/* TRACE.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 13:02:41
+ * Last edited:
+ *   15 Oct 2026, 13:02:41
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Defines the execution trace that the VM records of the external
+ *   functions it calls if `VmOptions::trace` is set.
+**/
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use specifications::common::{Parameter, Value};
+use specifications::version::Version;
+
+
+/***** CONSTANTS *****/
+/// The maximum number of characters of a single argument in a trace; longer ones are cut off.
+pub const MAX_ARGUMENT_LENGTH: usize = 64;
+
+/// What is shown in a trace instead of the value of a secret argument.
+pub const SECRET_PLACEHOLDER: &str = "<secret>";
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameter(name: &str, secret: Option<&str>) -> Parameter {
+        Parameter::new(name.to_string(), String::from("string"), None, None, secret.map(String::from))
+    }
+
+    #[test]
+    fn truncates_long_arguments() {
+        let long = "x".repeat(MAX_ARGUMENT_LENGTH * 2);
+        let summary = summarize_arguments(&[ parameter("data", None) ], &[ Value::Unicode(long) ]);
+        assert_eq!(summary, format!("data=\"{}...", "x".repeat(MAX_ARGUMENT_LENGTH - 1)));
+
+        // Counts characters, not bytes
+        let summary = summarize_arguments(&[ parameter("data", None) ], &[ Value::Unicode("é".repeat(MAX_ARGUMENT_LENGTH)) ]);
+        assert!(summary.ends_with("é..."));
+    }
+
+    #[test]
+    fn hides_secrets() {
+        let pointer = Value::Pointer{ data_type: String::from("string"), variable: String::from("token"), secret: true };
+        let nested = Value::Array{ data_type: String::from("string[]"), entries: vec![ Value::Unicode(String::from("public")), pointer.clone() ] };
+        let summary = summarize_arguments(
+            &[ parameter("password", Some("password")), parameter("token", None), parameter("list", None), parameter("n", None) ],
+            &[ Value::Unicode(String::from("hunter2")), pointer, nested, Value::Integer(42) ],
+        );
+        assert_eq!(summary, "password=<secret>, token=<secret>, list=[\"public\", <secret>], n=42");
+    }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// How an external function call in a trace ended.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TraceOutcome {
+    /// The function returned a value.
+    Success,
+    /// The function failed with the given error.
+    Failure{ error: String },
+}
+
+impl std::fmt::Display for TraceOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceOutcome::Success          => write!(f, "success"),
+            TraceOutcome::Failure{ error } => write!(f, "failure: {}", error),
+        }
+    }
+}
+
+
+
+/// A single external function call in the execution trace of a VM.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TraceEntry {
+    /// The name of the function that was called.
+    pub function    : String,
+    /// The package that provides the function.
+    pub package     : String,
+    /// The version of that package.
+    pub version     : Version,
+    /// The location the function ran on, if the script chose one.
+    pub location    : Option<String>,
+    /// A summary of the arguments (see `summarize_arguments()`).
+    pub arguments   : String,
+    /// How long the call took (wall-clock), in milliseconds.
+    pub duration_ms : u64,
+    /// Whether the call succeeded.
+    pub outcome     : TraceOutcome,
+    /// Whether the call was only simulated, in a dry run.
+    #[serde(default)]
+    pub simulated   : bool,
+}
+
+impl TraceEntry {
+    /// Returns how long the call took as a Duration.
+    #[inline]
+    pub fn duration(&self) -> Duration { Duration::from_millis(self.duration_ms) }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Summarizes the arguments of an external function call as `name=value` pairs for in a trace.
+///
+/// Secret parameters and secret values (anywhere in the argument) are replaced by `<secret>`, and every argument is cut off after MAX_ARGUMENT_LENGTH characters.
+///
+/// **Arguments**
+///  * `parameters`: The parameters of the function.
+///  * `arguments`: The values given for those parameters, in the same order.
+///
+/// **Returns**
+/// The summary as a single line.
+pub fn summarize_arguments(parameters: &[Parameter], arguments: &[Value]) -> String {
+    parameters.iter().zip(arguments)
+        .map(|(parameter, argument)| {
+            let value = if parameter.secret.is_some() { SECRET_PLACEHOLDER.to_string() } else { truncate(summarize_value(argument)) };
+            format!("{}={}", parameter.name, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+
+
+/// Writes the given value for in a trace, hiding any secrets in it.
+fn summarize_value(value: &Value) -> String {
+    match value {
+        Value::Pointer{ secret: true, .. } => SECRET_PLACEHOLDER.to_string(),
+        Value::Unicode(s)                  => format!("{:?}", s),
+        Value::Array{ entries, .. }        => format!("[{}]", entries.iter().map(summarize_value).collect::<Vec<_>>().join(", ")),
+        Value::Map(entries) => {
+            let mut entries: Vec<String> = entries.iter().map(|(k, v)| format!("{:?}: {}", k, summarize_value(v))).collect();
+            entries.sort();
+            format!("{{{}}}", entries.join(", "))
+        },
+        Value::Struct{ data_type, properties } => {
+            let mut properties: Vec<String> = properties.iter().map(|(n, p)| format!("{}: {}", n, summarize_value(p))).collect();
+            properties.sort();
+            format!("{} {{{}}}", data_type, properties.join(", "))
+        },
+        value => format!("{}", value),
+    }
+}
+
+/// Cuts off the given summary of an argument after MAX_ARGUMENT_LENGTH characters.
+fn truncate(summary: String) -> String {
+    match summary.char_indices().nth(MAX_ARGUMENT_LENGTH) {
+        Some((end, _)) => format!("{}...", &summary[..end]),
+        None           => summary,
+    }
+}
@@ -1,19 +1,36 @@
 use std::cmp::max;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
 use fnv::FnvHashMap;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
-use specifications::common::{FunctionExt, Value};
-use specifications::package::PackageIndex;
+use specifications::common::{FunctionExt, Type, Value};
+use specifications::package::{PackageIndex, PackageIndexError};
+use specifications::version::{ConstraintParseError, Version, VersionConstraint};
 use tokio::runtime::Runtime;
 
-use crate::builtins::{self, BuiltinError, BuiltinFunction};
+use crate::args::ARGS_GLOBAL;
+use crate::builtins::{self, BuiltinClass, BuiltinError, BuiltinFunction, CALLABLE_BUILTINS};
 use crate::bytecode::{BytecodeError, FunctionMut, FromPrimitive, Opcode};
+use crate::debugger::{LogDebugger, VmDebugger};
+use crate::diff::{self, StateDiff};
 use crate::executor::{VmExecutor, ExecutorError};
-use crate::frames::{CallFrame, CallFrameError};
-use crate::heap::{Handle, Heap, HeapError};
+use crate::frames::{CallFrame, CallFrameError, ErrorHandler};
+use crate::heap::{Handle, Heap, HeapError, DEFAULT_MAX_HEAP_SIZE};
 use crate::objects::{Array, Class, Instance, Object, ObjectError};
 use crate::stack::{Slot, Stack, StackError};
+use crate::trace::{self, TraceEntry, TraceOutcome};
+
+
+/// The number of instructions between two checks of the CancelToken (if any). Checking is cheap, but not free.
+pub const CANCEL_CHECK_INTERVAL: u64 = 1024;
+/// The name of the class of the Instances that OP_TRY handlers receive when an external call fails.
+pub const ERROR_CLASS: &str = "Error";
 
 
 /* TIM */
@@ -37,8 +54,16 @@ pub enum VmError {
     NotMultiplicable{ lhs: String, rhs: String },
     /// Error for when the two most recent values on the stack are not divisible
     NotDivisible{ lhs: String, rhs: String },
+    /// Error for when the two most recent values on the stack are not both integers, as the bitwise operators need
+    NotBitwise{ lhs: String, rhs: String },
+    /// Error for when the user shifts by a negative amount of bits, or by more than an integer has
+    IllegalShift{ amount: i64 },
     /// Error for when the user tries to index a non-Array object
     IllegalIndexError{ target: String },
+    /// Error for when the user tries to iterate over something that is not an Array or a Map
+    IllegalIterError{ target: String },
+    /// Error for when the user uses a non-string key to index a Map
+    IllegalKeyError{ key: String },
     /// Error for when the user uses a dot ('.') on a non-object
     IllegalDotError{ target: String },
     /// A bit more specific error for when the user uses a method on a non-object
@@ -47,23 +72,37 @@ pub enum VmError {
     IllegalPropertyError{ target: String },
     /// Error for when we try to import an illegal type of value
     IllegalImportError{ target: String },
+    /// Error for when the version constraint of an import is not a string
+    IllegalConstraintError{ package: String, target: String },
+    /// Error for when the version constraint of an import is not a valid constraint
+    VersionConstraintError{ package: String, err: ConstraintParseError },
     /// Error for when we use the new operation on a non-class type
     IllegalNewError{ target: String },
     /// Error for when we encounter a non-function type as a parallel branch
     IllegalBranchError{ target: String },
     /// Error for when we call return() outside of a function and it doesn't stop the global context
     IllegalReturnError,
+    /// Error for when an OP_CATCH_END is executed without a matching OP_TRY in the same function
+    IllegalCatchEndError,
+    /// Error for when a single run executed more instructions than allowed by `VmOptions::max_instructions`
+    InstructionBudgetExceeded{ limit: u64 },
+    /// Error for when the run was aborted through its CancelToken
+    Cancelled,
 
     /// Error for when the given opcode is unknown
     UndefinedOpcodeError{ opcode: u8 },
-    /// Error for when an import refers an unknown package
-    UndefinedImportError{ package: String },
+    /// Error for when an import refers an unknown package. Lists the known packages that depend on it, if any.
+    UndefinedImportError{ package: String, required_by: Vec<String> },
+    /// Error for when an import refers to a known package, but none of its versions satisfies the import's version constraint. Lists the versions that are known.
+    UnsatisfiedImportError{ package: String, constraint: String, available: Vec<String> },
     /// Error for when we encountered a package without digest
     PackageWithoutDigest{ package: String, function: String },
     /// Error for when a package import causes function name conlicts
     DuplicateFunctionImport{ package: String, function: String },
     /// Error for when a package import causes type name conlicts
     DuplicateTypeImport{ package: String, type_name: String },
+    /// Error for when a package is dropped that was never imported
+    PackageNotImported{ package: String },
     /// Error for when a global has an incorrect identifier
     IllegalGlobalIdentifierError{ target: String },
     /// Error for when a global is unknown to us
@@ -83,6 +122,14 @@ pub enum VmError {
 
     /// Error for when a given function does not have enough arguments on the stack before calling
     FunctionArityError{ name: String, got: u8, expected: u8 },
+    /// Error for when an external function is called without some of its required arguments (i.e., those without a default value)
+    MissingArgumentsError{ name: String, missing: Vec<String> },
+    /// Error for when an external function is called with more arguments than it has parameters
+    TooManyArgumentsError{ name: String, got: u8, expected: usize },
+    /// Error for when an argument of an external function (or a builtin) does not have the declared type of its parameter
+    ArgumentTypeError{ function: String, parameter: String, expected: String, got: String },
+    /// Error for when an external function is given an argument by name (i.e., in `map_call()`) that is not one of its parameters
+    UnknownArgumentError{ name: String, argument: String },
     /// Error for when a given array does not have enough values on the stack
     ArrayArityError{ got: u8, expected: u8 },
     /// Error for when a class is created but not enough properties are found on the stack
@@ -94,6 +141,8 @@ pub enum VmError {
     UnsupportedPackageKindError{ name: String, kind: String },
     /// Error for when an Array index goes out of bounds
     ArrayOutOfBoundsError{ index: usize, max: usize },
+    /// Error for when a Map is indexed with a key it does not have
+    MissingKeyError{ key: String },
     /// Could not resolve the subtype of an Array
     ArrayTypeError{ err: ObjectError },
 
@@ -116,6 +165,8 @@ pub enum VmError {
     HeapAllocError{ what: String, err: HeapError },
     /// Error for when we could not freeze something on the Heap
     HeapFreezeError{ what: String, err: BytecodeError },
+    /// Error for when we could not disassemble a function's bytecode
+    DisassembleError{ function: String, err: BytecodeError },
     /// Error for when we could not access the Heap
     HeapReadError{ what: String, err: HeapError },
     /// An error occurred while working with objects
@@ -128,6 +179,29 @@ pub enum VmError {
     ExternalCallError{ function: String, err: ExecutorError },
     /// Could not send a message to the client
     ClientTxError{ err: ExecutorError },
+
+    /// Wraps another error with the line in the script where it occurred
+    AtLine{ line: usize, err: Box<VmError> },
+}
+
+impl VmError {
+    /// Returns the error without any line information attached to it.
+    #[inline]
+    pub fn inner(&self) -> &VmError {
+        match self {
+            VmError::AtLine{ err, .. } => err.inner(),
+            err                        => err,
+        }
+    }
+
+    /// Returns the line in the script where this error occurred, if known.
+    #[inline]
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            VmError::AtLine{ line, .. } => Some(*line),
+            _                           => None,
+        }
+    }
 }
 
 impl std::fmt::Display for VmError {
@@ -141,21 +215,36 @@ impl std::fmt::Display for VmError {
             VmError::NotAddable{ lhs, rhs }         => write!(f, "Cannot add value of type {} to a value of type {}: expected two numeric values or two strings", lhs, rhs),
             VmError::NotSubtractable{ lhs, rhs }    => write!(f, "Cannot subtract value of type {} with a value of type {}: expected two numeric values", lhs, rhs),
             VmError::NotMultiplicable{ lhs, rhs }   => write!(f, "Cannot multiply value of type {} with a value of type {}: expected two numeric values", lhs, rhs),
-            VmError::NotDivisible{ lhs, rhs }       => write!(f, "Cannot divide value of type {} by a value of type {}: expected two numeric values", lhs, rhs),
-            VmError::IllegalIndexError{ target }    => write!(f, "Cannot index type {}: expected an Array", target),
-            VmError::IllegalDotError{ target }      => write!(f, "Cannot apply dot operator to type {}: expected an Instance", target),
+            VmError::NotDivisible{ lhs, rhs }       => write!(f, "Cannot divide value of type {} by a value of type {}: expected two numeric values (note that '/' always results in a real; use div(a, b) for integer division)", lhs, rhs),
+            VmError::NotBitwise{ lhs, rhs }         => write!(f, "Cannot apply a bitwise operator to a value of type {} and a value of type {}: expected two integers", lhs, rhs),
+            VmError::IllegalShift{ amount }         => write!(f, "Cannot shift by {} bits: expected an amount between 0 and 63", amount),
+            VmError::IllegalIndexError{ target }    => write!(f, "Cannot index type {}: expected an Array or a Map", target),
+            VmError::IllegalIterError{ target }     => write!(f, "Cannot iterate over type {}: expected an Array or a Map", target),
+            VmError::IllegalKeyError{ key }         => write!(f, "Cannot use value of type {} as a Map key: expected a string", key),
+            VmError::IllegalDotError{ target }      => write!(f, "Cannot apply dot operator to type {}: expected an Instance{}", target, if target == "unit" { " (if the value may be missing, use '??' to provide a default)" } else { "" }),
             VmError::MethodDotError{ target }       => write!(f, "Cannot call a method on a {}: expected an Instance", target),
             VmError::IllegalPropertyError{ target } => write!(f, "Illegal object property {}: expected a string identifier", target),
             VmError::IllegalImportError{ target }   => write!(f, "Cannot import package of type {}: expected a string identifier", target),
+            VmError::IllegalConstraintError{ package, target } => write!(f, "Cannot import package '{}' with a version constraint of type {}: expected a string", package, target),
+            VmError::VersionConstraintError{ package, err }    => write!(f, "Cannot import package '{}': {}", package, err),
             VmError::IllegalNewError{ target }      => write!(f, "Cannot instantiate object of type {}: expected a Class", target),
             VmError::IllegalBranchError{ target }   => write!(f, "Cannot run branch of type {} in parallel: expected a Function", target),
             VmError::IllegalReturnError             => write!(f, "Cannot call return outside of a function"),
+            VmError::IllegalCatchEndError           => write!(f, "Encountered OP_CATCH_END without a matching OP_TRY in the same function"),
+            VmError::InstructionBudgetExceeded{ limit } => write!(f, "Execution exceeded the budget of {} instructions (is there an infinite loop?)", limit),
+            VmError::Cancelled                          => write!(f, "Execution was cancelled"),
 
             VmError::UndefinedOpcodeError{ opcode }               => write!(f, "Undefined opcode '{}' encountered", opcode),
-            VmError::UndefinedImportError{ package }              => write!(f, "Undefined package '{}'", package),
+            VmError::UndefinedImportError{ package, required_by } => if required_by.is_empty() {
+                write!(f, "Undefined package '{}'", package)
+            } else {
+                write!(f, "Undefined package '{}' (required by {}); make sure it is pulled", package, required_by.join(", "))
+            },
+            VmError::UnsatisfiedImportError{ package, constraint, available } => write!(f, "No version of package '{}' satisfies '{}' (available: {}); make sure it is pulled", package, constraint, available.join(", ")),
             VmError::PackageWithoutDigest{ package, function }    => write!(f, "Could not run function '{}': Package '{}' has no digest set.", package, function),
             VmError::DuplicateFunctionImport{ package, function } => write!(f, "Package '{}' imports function '{}', but that global variable already exists", package, function),
             VmError::DuplicateTypeImport{ package, type_name }    => write!(f, "Package '{}' imports type '{}', but that global variable already exists", package, type_name),
+            VmError::PackageNotImported{ package }                => write!(f, "Package '{}' is not imported", package),
             VmError::IllegalGlobalIdentifierError{ target }       => write!(f, "Illegal identifier of type {}: expected a String", target),
             VmError::UndefinedGlobalError{ identifier }           => write!(f, "Undefined global '{}'", identifier),
             VmError::UndefinedPropertyError{ instance, property } => write!(f, "Class '{}' has no property '{}' defined", instance, property),
@@ -166,12 +255,17 @@ impl std::fmt::Display for VmError {
             VmError::BranchResultError{ result, err }             => write!(f, "Could not retrieve result '{}' of parallel branch: {}", result, err),
 
             VmError::FunctionArityError{ name, got, expected } => write!(f, "Function '{}' expects {} arguments, but got {}", name, expected, got),
+            VmError::MissingArgumentsError{ name, missing }    => write!(f, "Function '{}' is missing required argument{} {}", name, if missing.len() == 1 { "" } else { "s" }, missing.iter().map(|name| format!("'{}'", name)).collect::<Vec<String>>().join(", ")),
+            VmError::TooManyArgumentsError{ name, got, expected } => write!(f, "Function '{}' takes at most {} arguments, but got {}", name, expected, got),
+            VmError::ArgumentTypeError{ function, parameter, expected, got } => write!(f, "Argument '{}' of function '{}' should be of type {}, but got {}", parameter, function, expected, got),
+            VmError::UnknownArgumentError{ name, argument }    => write!(f, "Function '{}' has no parameter '{}'", name, argument),
             VmError::ArrayArityError{ got, expected }          => write!(f, "Array expects {} values, but got {}", expected, got),
             VmError::ClassArityError{ name, got, expected }    => write!(f, "Instance of type {} requires {} properties, but got {}", name, expected, got),
             VmError::ParallelArityError{ got, expected }       => write!(f, "Parallel expects {} branches, but got {}", expected, got),
 
             VmError::UnsupportedPackageKindError{ name, kind } => write!(f, "Package '{}' has unsupported package kind '{}'", name, kind),
             VmError::ArrayOutOfBoundsError{ index, max }       => write!(f, "Array index {} is out-of-bounds for Array of size {}", index, max),
+            VmError::MissingKeyError{ key }                    => write!(f, "Map has no key '{}'", key),
             VmError::ArrayTypeError{ err }                     => write!(f, "Could not resolve type of Array: {}", err),
 
             VmError::IllegalHandleError{ handle, err: HeapError::DanglingHandleError{ handle: _ } } => write!(f, "Encountered dangling handle '{}' on the stack", handle),
@@ -185,12 +279,15 @@ impl std::fmt::Display for VmError {
             VmError::SlotCreateError{ what, err }       => write!(f, "Could not properly create Stack slot for {}: {}", what, err),
             VmError::HeapAllocError{ what, err }        => write!(f, "Could not allocate {} on the heap: {}", what, err),
             VmError::HeapFreezeError{ what, err }       => write!(f, "Could not freeze {} on the heap: {}", what, err),
+            VmError::DisassembleError{ function, err }  => write!(f, "Could not disassemble function '{}': {}", function, err),
             VmError::HeapReadError{ what, err }         => write!(f, "Could not read {} from the heap: {}", what, err),
             VmError::ObjectError{ err }                 => write!(f, "An error occurred while working with objects: {}", err),
             VmError::BuiltinRegisterError{ err }        => write!(f, "Could not register builtins: {}", err),
             VmError::BuiltinCallError{ builtin, err }   => write!(f, "Could not perform builtin call to builtin '{}': {}", builtin, err),
             VmError::ExternalCallError{ function, err } => write!(f, "Could not perform external call to function '{}': {}", function, err),
             VmError::ClientTxError{ err }               => write!(f, "{}", err),
+
+            VmError::AtLine{ line, err } => write!(f, "line {}: {}", line, err),
         }
     }
 }
@@ -200,7 +297,7 @@ impl std::error::Error for VmError {}
 
 
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct VmOptions {
     ///
     ///
@@ -211,12 +308,75 @@ pub struct VmOptions {
     ///
     ///
     pub global_return_halts: bool,
+
+    /// The maximum number of objects the VM's heap may grow to before allocations fail.
+    pub max_heap_slots: usize,
+
+    /// If true, the VM logs every instruction, call and return (unless another VmDebugger is attached with `Vm::set_debugger()`).
+    pub debug: bool,
+
+    /// If true, the VM records every external function call in its execution trace (see `Vm::take_trace()`).
+    #[serde(default)]
+    pub trace: bool,
+
+    /// The maximum number of instructions that a single run (e.g., a REPL statement) may execute before it is aborted. Unlimited if None.
+    #[serde(default)]
+    pub max_instructions: Option<u64>,
+
+    /// If true, print() shows values that fit on a single line that way instead of indenting them (e.g., for a REPL).
+    #[serde(default)]
+    pub compact_print: bool,
+
+    /// If true, external functions are not called but only checked, continuing with a default value for their return type instead (see `VmExecutor::dry_call()`).
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl Default for VmOptions {
+    fn default() -> Self {
+        Self {
+            clear_after_main    : false,
+            global_return_halts : false,
+            max_heap_slots      : DEFAULT_MAX_HEAP_SIZE,
+            debug               : false,
+            trace               : false,
+            max_instructions    : None,
+            compact_print       : false,
+            dry_run             : false,
+        }
+    }
+}
+
+/// A token with which a running VM can be cancelled from another task or thread. Clones share the same state.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Constructor for the CancelToken, which is not cancelled yet.
+    #[inline]
+    pub fn new() -> Self { Self::default() }
+
+    /// Cancels the token, which makes every VM it is attached to abort its run at the next check.
+    #[inline]
+    pub fn cancel(&self) { self.0.store(true, Ordering::SeqCst); }
+
+    /// Returns whether the token has been cancelled.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool { self.0.load(Ordering::SeqCst) }
+
+    /// Returns whether this token and the given one are clones of each other.
+    #[inline]
+    pub fn ptr_eq(&self, other: &CancelToken) -> bool { Arc::ptr_eq(&self.0, &other.0) }
 }
 
-#[derive(Clone, Default, Debug)]
+/// The state of a Vm in between statements (its globals and imports), which can be captured, (de)serialized and used to create a new Vm with.
+#[derive(Clone, Default, Debug, Deserialize, Serialize)]
 pub struct VmState {
     globals: FnvHashMap<String, Value>,
     options: VmOptions,
+    args: Option<HashMap<String, Value>>,
+    package_globals: FnvHashMap<String, Vec<String>>,
+    package_versions: FnvHashMap<String, Version>,
 }
 
 unsafe impl Send for VmState {}
@@ -225,8 +385,11 @@ impl VmState {
     fn new(
         globals: FnvHashMap<String, Value>,
         options: VmOptions,
+        args: Option<HashMap<String, Value>>,
+        package_globals: FnvHashMap<String, Vec<String>>,
+        package_versions: FnvHashMap<String, Version>,
     ) -> Self {
-        Self { globals, options }
+        Self { globals, options, args, package_globals, package_versions }
     }
 
     /* TIM */
@@ -273,6 +436,79 @@ impl VmState {
         Ok(globals)
     }
     /*******/
+
+    /// Returns the variables defined in this state (i.e., the globals that are no builtin, function or type), sorted by name.
+    /// 
+    /// **Returns**  
+    /// A list of the names of the variables together with their data types.
+    pub fn variables(&self) -> Vec<(String, String)> {
+        let mut variables: Vec<(String, String)> = self.globals
+            .iter()
+            .filter(|(name, value)| diff::is_variable(name, value))
+            .map(|(name, value)| (name.clone(), value.data_type()))
+            .collect();
+        if self.args.is_some() { variables.push((ARGS_GLOBAL.to_string(), ARGS_GLOBAL.to_string())); }
+        variables.sort();
+        variables
+    }
+
+    /// Returns the functions that can be called in this state, both those defined by the user and those imported from packages, sorted by name.
+    /// 
+    /// **Returns**  
+    /// A list of the names of the functions together with their signatures.
+    pub fn functions(&self) -> Vec<(String, String)> {
+        let mut functions: Vec<(String, String)> = self.globals
+            .iter()
+            .filter_map(|(name, value)| match value {
+                Value::Function(function)    => Some((name.clone(), format!("{}({} argument(s))", function.name, function.arity))),
+                Value::FunctionExt(function) => {
+                    let parameters: Vec<String> = function.parameters.iter().map(|p| format!("{}: {}", p.name, p.data_type)).collect();
+                    Some((name.clone(), format!("{}({}) from {} {}", function.name, parameters.join(", "), function.package, function.version)))
+                },
+                _ => None,
+            })
+            .collect();
+        functions.sort();
+        functions
+    }
+
+    /// Returns the documentation of the function with the given name, which is a builtin or a function defined in (or imported into) this state.
+    /// 
+    /// **Arguments**
+    ///  * `name`: The name of the function.
+    /// 
+    /// **Returns**  
+    /// The signature and description of the function, or None if this state has no function with that name.
+    pub fn doc(&self, name: &str) -> Option<String> {
+        if let Some(builtin) = CALLABLE_BUILTINS.iter().find(|builtin| builtin.signature() == Some(name)) {
+            let parameters: Vec<String> = builtin.parameters().iter().map(|(parameter, data_type)| format!("{}: {}", parameter, data_type)).collect();
+            return Some(format!("{}({}) (builtin)", name, parameters.join(", ")));
+        }
+        match self.globals.get(name)? {
+            Value::Function(function)    => Some(format!("{}({} argument(s))", function.name, function.arity)),
+            Value::FunctionExt(function) => Some(function.help()),
+            _                            => None,
+        }
+    }
+
+    /// Returns which variables were defined, removed or changed in the given (later) state compared to this one. Builtins, functions and types are ignored.
+    /// 
+    /// **Arguments**
+    ///  * `other`: The VmState to compare with, e.g., the state after running a statement.
+    /// 
+    /// **Returns**  
+    /// The StateDiff from this state to the other.
+    pub fn diff(&self, other: &VmState) -> StateDiff { diff::diff_globals(&self.globals, &other.globals) }
+
+    /// Returns the packages imported in this state, sorted by name.
+    /// 
+    /// **Returns**  
+    /// A list of the names of the packages together with the version that was imported.
+    pub fn packages(&self) -> Vec<(String, Version)> {
+        let mut packages: Vec<(String, Version)> = self.package_versions.iter().map(|(name, version)| (name.clone(), version.clone())).collect();
+        packages.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+        packages
+    }
 }
 
 /// **Edited: now using custom, thread-safe Heap.**
@@ -291,6 +527,19 @@ where
     package_index: PackageIndex,
     options: VmOptions,
     stack: Stack,
+    debugger: Option<Box<dyn VmDebugger>>,
+    /// The script arguments exposed as the `args` global, if any. Kept separately because their class is not a global.
+    args: Option<HashMap<String, Value>>,
+    /// The globals (functions and types) created by each imported package, so the package can be dropped again.
+    package_globals: FnvHashMap<String, Vec<String>>,
+    /// The version of each imported package.
+    package_versions: FnvHashMap<String, Version>,
+    /// The external function calls made so far, if `VmOptions::trace` is set.
+    trace: Vec<TraceEntry>,
+    /// The token with which the current run may be cancelled from the outside, if any.
+    cancel: Option<CancelToken>,
+    /// The error handlers installed by OP_TRY that are currently active, innermost last.
+    handlers: Vec<ErrorHandler>,
 }
 
 impl<E> Default for Vm<E>
@@ -361,6 +610,9 @@ where
             return Err(VmError::BuiltinRegisterError{ err: reason });
         }
 
+        // Only pay for debugging if asked
+        let debugger: Option<Box<dyn VmDebugger>> = if options.debug { Some(Box::new(LogDebugger)) } else { None };
+
         Ok(Self {
             executor,
             frames,
@@ -370,6 +622,13 @@ where
             package_index,
             options,
             stack,
+            debugger,
+            args: None,
+            package_globals: FnvHashMap::default(),
+            package_versions: FnvHashMap::default(),
+            trace: Vec::new(),
+            cancel: None,
+            handlers: Vec::new(),
         })
     }
 
@@ -419,10 +678,10 @@ where
     ) -> Result<Self, VmError> {
         // Initialize the parts of the VM
         let package_index = package_index.unwrap_or_default();
-        let mut heap = Heap::default();
+        let mut heap = Heap::new(state.options.max_heap_slots);
 
         // Create itself
-        Self::new(
+        let mut vm = Self::new(
             executor,
             Default::default(),
             state.get_globals(&mut heap)?,
@@ -431,21 +690,179 @@ where
             package_index,
             state.options,
             Stack::default(),
-        )
+        )?;
+        if let Some(args) = state.args {
+            vm.set_args(args)?;
+        }
+        vm.package_globals = state.package_globals;
+        vm.package_versions = state.package_versions;
+        Ok(vm)
     }
     /*******/
 
+    /// Attaches a debugger to the VM, which is notified of every instruction, call and return from now on.
+    /// 
+    /// **Arguments**
+    ///  * `debugger`: The VmDebugger to attach. Replaces any debugger that was attached before.
+    pub fn set_debugger(&mut self, debugger: Box<dyn VmDebugger>) {
+        self.debugger = Some(debugger);
+    }
+
+    /// Detaches the current debugger from the VM, if any.
+    /// 
+    /// **Returns**  
+    /// The debugger that was attached, or None if there wasn't any.
+    pub fn take_debugger(&mut self) -> Option<Box<dyn VmDebugger>> {
+        self.debugger.take()
+    }
+
+    /// Attaches a CancelToken to the VM, which is checked every few instructions (see `CANCEL_CHECK_INTERVAL`) and aborts the run with a VmError::Cancelled once it's cancelled.
+    /// 
+    /// **Arguments**
+    ///  * `token`: The CancelToken to attach. Replaces any token that was attached before.
+    pub fn set_cancel_token(&mut self, token: CancelToken) {
+        self.cancel = Some(token);
+    }
+
+    /// Turns recording the execution trace on or off. The calls recorded so far are kept either way.
+    /// 
+    /// **Arguments**
+    ///  * `trace`: Whether to record external function calls from now on.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.options.trace = trace;
+    }
+
+    /// Turns dry-run mode on or off, in which external functions are simulated instead of called.
+    /// 
+    /// **Arguments**
+    ///  * `dry_run`: Whether to simulate external function calls from now on.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.options.dry_run = dry_run;
+    }
+
+    /// Sets the maximum number of instructions that a single run may execute before it is aborted.
+    /// 
+    /// **Arguments**
+    ///  * `max_instructions`: The new limit, or None to make runs unlimited.
+    pub fn set_max_instructions(&mut self, max_instructions: Option<u64>) {
+        self.options.max_instructions = max_instructions;
+    }
+
+    /// Returns the external function calls recorded since the trace was last taken.
+    #[inline]
+    pub fn trace(&self) -> &[TraceEntry] {
+        &self.trace
+    }
+
+    /// Takes the external function calls recorded since the trace was last taken, clearing it.
+    /// 
+    /// **Returns**  
+    /// The recorded calls, in the order they were made (or finished, for calls in parallel branches).
+    pub fn take_trace(&mut self) -> Vec<TraceEntry> {
+        std::mem::take(&mut self.trace)
+    }
+
+    /// Returns the disassembled bytecode of the given function, as if it were run as main function.
+    /// 
+    /// **Arguments**
+    ///  * `function`: The (compiled) function to disassemble.
+    /// 
+    /// **Returns**  
+    /// The human-readable assembly of the function on success, or a VmError otherwise.
+    pub fn disassemble_main(&mut self, function: &FunctionMut) -> Result<String, VmError> {
+        let name = function.name.clone();
+        let ffunction = match function.clone().freeze(&mut self.heap) {
+            Ok(f)       => f,
+            Err(reason) => { return Err(VmError::HeapFreezeError{ what: format!("function '{}'", name), err: reason }); }
+        };
+
+        match ffunction.chunk.disassemble() {
+            Ok(result)  => Ok(result),
+            Err(reason) => Err(VmError::DisassembleError{ function: name, err: reason }),
+        }
+    }
+
+    /// Returns the number of slots that the VM's heap currently has. It grows when needed, up to `VmOptions::max_heap_slots`.
+    #[inline]
+    pub fn heap_capacity(&self) -> usize { self.heap.capacity() }
+
     ///
     ///
     ///
     pub fn capture_state(&self) -> VmState {
         let mut globals = FnvHashMap::default();
         for (name, slot) in &self.globals {
+            // The args are restored from the state's args instead
+            if self.args.is_some() && name == ARGS_GLOBAL { continue; }
             let value = slot.clone().into_value();
             globals.insert(name.clone(), value);
         }
 
-        VmState::new(globals, self.options.clone())
+        VmState::new(globals, self.options.clone(), self.args.clone(), self.package_globals.clone(), self.package_versions.clone())
+    }
+
+    /// Replaces the PackageIndex that determines which packages can be imported (e.g., after new packages have been pulled).
+    /// 
+    /// Packages that are already imported stay imported as they are; import them again to switch to the version in the new index.
+    /// 
+    /// **Arguments**
+    ///  * `package_index`: The new PackageIndex.
+    pub fn set_package_index(&mut self, package_index: PackageIndex) {
+        self.package_index = package_index;
+    }
+
+    /// Removes the globals (functions and types) that an import of the given package created, such that it can be imported again.
+    /// 
+    /// **Arguments**
+    ///  * `name`: The name of the package to drop.
+    /// 
+    /// **Returns**  
+    /// The names of the globals that were removed, or a VmError if the package was not imported.
+    pub fn drop_package(&mut self, name: &str) -> Result<Vec<String>, VmError> {
+        let globals = match self.package_globals.remove(name) {
+            Some(globals) => globals,
+            None          => { return Err(VmError::PackageNotImported{ package: name.to_string() }); }
+        };
+        self.package_versions.remove(name);
+        for global in &globals {
+            self.globals.remove(global);
+        }
+        Ok(globals)
+    }
+
+    /// Exposes the given script arguments to the script as the global `args`, an instance with one property per argument.
+    /// 
+    /// Replaces any arguments set before. Reading an argument that was not given results in an UndefinedPropertyError.
+    /// 
+    /// **Arguments**
+    ///  * `args`: The arguments to expose, as parsed by the functions in `brane_bvm::args`.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or a VmError if the arguments could not be put on the heap.
+    pub fn set_args(&mut self, args: HashMap<String, Value>) -> Result<(), VmError> {
+        // The class only exists to give the instance its name; it is deliberately not a global, so it cannot be instantiated
+        let class = Class{ name: ARGS_GLOBAL.to_string(), methods: FnvHashMap::default() };
+        let class = match self.heap.alloc(Object::Class(class)) {
+            Ok(class)   => class,
+            Err(reason) => { return Err(VmError::HeapAllocError{ what: "the class of the script arguments".to_string(), err: reason }); }
+        };
+
+        let mut properties = FnvHashMap::default();
+        for (name, value) in &args {
+            let slot = match Slot::from_value(value.clone(), &self.globals, &mut self.heap) {
+                Ok(slot)    => slot,
+                Err(reason) => { return Err(VmError::SlotCreateError{ what: format!("script argument '{}'", name), err: reason }); }
+            };
+            properties.insert(name.clone(), slot);
+        }
+        let instance = match self.heap.alloc(Object::Instance(Instance::new(class, properties))) {
+            Ok(instance) => instance,
+            Err(reason)  => { return Err(VmError::HeapAllocError{ what: "the script arguments".to_string(), err: reason }); }
+        };
+
+        self.globals.insert(ARGS_GLOBAL.to_string(), Slot::Object(instance));
+        self.args = Some(args);
+        Ok(())
     }
 
     /* TIM */
@@ -475,6 +892,7 @@ where
         };
 
         self.stack.push_object(handle);
+        self.handlers.clear();
         if let Err(reason) = self.call(0).await { return Err(reason); }
         let res = self.run().await;
 
@@ -489,6 +907,18 @@ where
     }
     /*******/
 
+    /// Takes the value that the last main function returned with a top-level `return`, if it did.
+    /// 
+    /// Only works if `global_return_halts` is set (without it, a top-level `return` is an error) and `clear_after_main` is not (as that clears the value).
+    /// 
+    /// **Returns**  
+    /// The returned value, or None if the main function simply ran to its end.
+    pub fn take_main_result(&mut self) -> Option<Value> {
+        // A top-level return pops the main frame, leaving only its value
+        if !self.frames.is_empty() { return None; }
+        self.stack.try_pop().map(Slot::into_value)
+    }
+
     /* TIM */
     /// **Edited: Changed to return VmErrors and handle the new, custom Heap.**
     /// 
@@ -519,6 +949,7 @@ where
 
         // Run it
         self.stack.push_object(handle);
+        self.handlers.clear();
         if let Err(reason) = self.call(0).await { return Err(reason); }
         if let Err(reason) = self.run().await { return Err(reason); }
 
@@ -550,6 +981,9 @@ where
 
         let function = self.stack.get(frame_first).as_object().expect("");
         if let Object::Function(_f) = function.get() {
+            // Local functions have no defaults, so they need exactly as many arguments as they have parameters
+            if arity != _f.arity { return Err(VmError::FunctionArityError{ name: _f.name.clone(), got: arity, expected: _f.arity }); }
+
             // Debug to the client what we're going to call
             if let Err(reason) = self.executor.debug(_f.chunk.disassemble().unwrap().to_string()).await {
                 let err = VmError::ClientTxError{ err: reason };
@@ -558,8 +992,9 @@ where
             }
 
             // Position 0 is the main function, never allow it as root for a nested call frame.
-            let frame = CallFrame::new(function, max(frame_first, 1));
+            let frame = CallFrame::new(function.clone(), max(frame_first, 1));
             self.frames.push(frame);
+            if let Some(debugger) = &mut self.debugger { debugger.on_call(&_f.name, arity, self.frames.len()); }
 
             // Done
             return Ok(());
@@ -577,6 +1012,7 @@ where
     /// Nothing if it was successfull, but if an error occurred the user should
     /// know about then it is returned as an Err.
     async fn run(&mut self) -> Result<(), VmError> {
+        let mut executed: u64 = 0;
         loop {
             // Get the next instruction, stopping if there aren't any anymore (and erroring on everything else)
             let instruction: Opcode;
@@ -591,54 +1027,91 @@ where
                 };
             }
 
+            // Remember where this instruction lives, so errors can be traced back to the script
+            let depth = self.frames.len();
+            let ip = self.frames.last().map(|frame| frame.ip - 1).unwrap_or_default();
+
+            // Stop runaway scripts, either because they're over budget or because someone asked us to
+            if let Some(limit) = self.options.max_instructions {
+                if executed >= limit { return Err(self.at_line(depth, ip, VmError::InstructionBudgetExceeded{ limit })); }
+            }
+            if executed % CANCEL_CHECK_INTERVAL == 0 && self.cancel.as_ref().map(|token| token.is_cancelled()).unwrap_or(false) {
+                return Err(self.at_line(depth, ip, VmError::Cancelled));
+            }
+            executed += 1;
+
+            // Notify the debugger, if any
+            if let Some(debugger) = &mut self.debugger {
+                debugger.on_instruction(instruction, ip, self.stack.len());
+            }
+
             // Otherwise, switch on the byte we found
-            match instruction {
-                Opcode::ADD => self.op_add()?,
-                Opcode::AND => self.op_and()?,
-                Opcode::ARRAY => self.op_array()?,
-                Opcode::CALL => self.op_call().await?,
-                Opcode::CLASS => self.op_class()?,
-                Opcode::CONSTANT => self.op_constant()?,
-                Opcode::DEFINE_GLOBAL => self.op_define_global()?,
-                Opcode::DIVIDE => self.op_divide()?,
-                Opcode::DOT => self.op_dot()?,
-                Opcode::EQUAL => self.op_equal()?,
-                Opcode::FALSE => self.op_false(),
-                Opcode::GET_GLOBAL => self.op_get_global()?,
-                Opcode::GET_LOCAL => self.op_get_local()?,
-                Opcode::GET_METHOD => self.op_get_method()?,
-                Opcode::GET_PROPERTY => self.op_get_property()?,
-                Opcode::GREATER => self.op_greater()?,
-                Opcode::IMPORT => self.op_import().await?,
-                Opcode::INDEX => self.op_index()?,
-                Opcode::JUMP => self.op_jump()?,
-                Opcode::JUMP_BACK => self.op_jump_back()?,
-                Opcode::JUMP_IF_FALSE => self.op_jump_if_false()?,
-                Opcode::LESS => self.op_less()?,
-                Opcode::LOC => self.op_loc(),
-                Opcode::LOC_POP => self.op_loc_pop(),
-                Opcode::LOC_PUSH => self.op_loc_push()?,
-                Opcode::MULTIPLY => self.op_multiply()?,
-                Opcode::NEGATE => self.op_negate()?,
-                Opcode::NEW => self.op_new()?,
-                Opcode::NOT => self.op_not()?,
-                Opcode::OR => self.op_or()?,
-                Opcode::PARALLEL => self.op_parallel()?,
-                Opcode::POP => self.op_pop()?,
-                Opcode::POP_N => self.op_pop_n()?,
+            let result = match instruction {
+                Opcode::ADD => self.op_add(),
+                Opcode::AND => self.op_and(),
+                Opcode::ARRAY => self.op_array(),
+                Opcode::BIT_AND => self.op_bit_and(),
+                Opcode::BIT_OR => self.op_bit_or(),
+                Opcode::BIT_XOR => self.op_bit_xor(),
+                Opcode::CALL => self.op_call().await,
+                Opcode::CATCH_END => self.op_catch_end(),
+                Opcode::CLASS => self.op_class(),
+                Opcode::COALESCE => self.op_coalesce(),
+                Opcode::CONSTANT => self.op_constant(),
+                Opcode::DEFINE_GLOBAL => self.op_define_global(),
+                Opcode::DIVIDE => self.op_divide(),
+                Opcode::DOT => self.op_dot(),
+                Opcode::EQUAL => self.op_equal(),
+                Opcode::FALSE => { self.op_false(); Ok(()) },
+                Opcode::GET_GLOBAL => self.op_get_global(),
+                Opcode::GET_LOCAL => self.op_get_local(),
+                Opcode::GET_METHOD => self.op_get_method(),
+                Opcode::GET_PROPERTY => self.op_get_property(),
+                Opcode::GREATER => self.op_greater(),
+                Opcode::GREATER_EQUAL => self.op_greater_equal(),
+                Opcode::IMPORT => self.op_import().await,
+                Opcode::INDEX => self.op_index(),
+                Opcode::INDEX_SET => self.op_index_set(),
+                Opcode::ITER => self.op_iter(),
+                Opcode::ITER_NEXT => self.op_iter_next(),
+                Opcode::JUMP => self.op_jump(),
+                Opcode::JUMP_BACK => self.op_jump_back(),
+                Opcode::JUMP_IF_FALSE => self.op_jump_if_false(),
+                Opcode::LESS => self.op_less(),
+                Opcode::LESS_EQUAL => self.op_less_equal(),
+                Opcode::LOC => { self.op_loc(); Ok(()) },
+                Opcode::LOC_POP => { self.op_loc_pop(); Ok(()) },
+                Opcode::LOC_PUSH => self.op_loc_push(),
+                Opcode::MULTIPLY => self.op_multiply(),
+                Opcode::NEGATE => self.op_negate(),
+                Opcode::NEW => self.op_new(),
+                Opcode::NOT => self.op_not(),
+                Opcode::NOT_EQUAL => self.op_not_equal(),
+                Opcode::OR => self.op_or(),
+                Opcode::PARALLEL => self.op_parallel(),
+                Opcode::POP => self.op_pop(),
+                Opcode::POP_N => self.op_pop_n(),
                 Opcode::RETURN => {
-                    self.op_return()?;
-                    // Stop if that was the last frame
-                    if self.options.global_return_halts && self.frames.is_empty() {
-                        break;
+                    let result = self.op_return();
+                    if result.is_ok() {
+                        if let Some(debugger) = &mut self.debugger { debugger.on_return(depth); }
+                        // Stop if that was the last frame
+                        if self.options.global_return_halts && self.frames.is_empty() {
+                            break;
+                        }
                     }
+                    result
                 }
-                Opcode::SET_GLOBAL => self.op_set_global(false)?,
-                Opcode::SET_LOCAL => self.op_set_local()?,
-                Opcode::SUBSTRACT => self.op_substract()?,
-                Opcode::TRUE => self.op_true(),
-                Opcode::UNIT => self.op_unit(),
-            }
+                Opcode::SET_GLOBAL => self.op_set_global(false),
+                Opcode::SET_LOCAL => self.op_set_local(),
+                Opcode::SHL => self.op_shl(),
+                Opcode::SHR => self.op_shr(),
+                Opcode::SUBSTRACT => self.op_substract(),
+                Opcode::TRUE => { self.op_true(); Ok(()) },
+                Opcode::TRY => self.op_try(),
+                Opcode::UNIT => { self.op_unit(); Ok(()) },
+            };
+            if let Err(err) = result { return Err(self.at_line(depth, ip, err)); }
 
             // // Try to log
             // // No deadlock found...?
@@ -660,6 +1133,144 @@ where
     }
     /*******/
 
+    /// Attaches the line of the given instruction to an error that occurred while executing it.
+    /// 
+    /// **Arguments**
+    ///  * `depth`: The number of CallFrames at the time the instruction was executed (i.e., it's from the topmost frame at that time).
+    ///  * `ip`: The offset of the instruction in that frame's bytecode.
+    ///  * `err`: The VmError to attach the line to.
+    /// 
+    /// **Returns**  
+    /// A VmError::AtLine wrapping the given error, or the error itself if it already has a line or the bytecode has no line information.
+    fn at_line(&self, depth: usize, ip: usize, err: VmError) -> VmError {
+        if let VmError::AtLine{ .. } = err { return err; }
+        let line = match self.frames.get(depth.wrapping_sub(1)).map(|frame| frame.function.get()) {
+            Some(Object::Function(function)) => function.chunk.line(ip),
+            _                                => None,
+        };
+        match line {
+            Some(line) => VmError::AtLine{ line, err: Box::new(err) },
+            None       => err,
+        }
+    }
+
+    /// Hands the error of a failed call to the innermost error handler (see OP_TRY), if the error can be handled and there is a handler.
+    /// 
+    /// This unwinds the call frames, the stack and the location stack to what they were when the handler was installed, removes the handler, pushes an Instance describing the error and continues at the handler code.
+    /// 
+    /// **Arguments**
+    ///  * `err`: The VmError that the call failed with.
+    /// 
+    /// **Returns**  
+    /// Nothing if the error was handled, or the error itself if it wasn't (i.e., it propagates like any other error).
+    fn catch(&mut self, err: VmError) -> Result<(), VmError> {
+        let (code, stdout, stderr) = match catchable(&err) {
+            Some(fields) => fields,
+            None         => { return Err(err); }
+        };
+        let handler = match self.handlers.pop() {
+            Some(handler) => handler,
+            None          => { return Err(err); }
+        };
+        debug!("Handling failed call with the error handler at {} in frame {}: {}", handler.target, handler.depth, err);
+
+        // Unwind to where the handler was installed
+        while self.frames.len() > handler.depth {
+            let depth = self.frames.len();
+            self.frames.pop();
+            if let Some(debugger) = &mut self.debugger { debugger.on_return(depth); }
+        }
+        self.stack.clear_from(handler.stack_len);
+        self.locations.truncate(handler.locations);
+        let frames_len = self.frames.len();
+        self.frames[frames_len - 1].ip = handler.target;
+
+        // Describe the error to the handler
+        let class = Class{ name: ERROR_CLASS.to_string(), methods: FnvHashMap::default() };
+        let class = match self.heap.alloc(Object::Class(class)) {
+            Ok(class)   => class,
+            Err(reason) => { return Err(VmError::HeapAllocError{ what: "the class of a caught error".to_string(), err: reason }); }
+        };
+        let mut properties = FnvHashMap::default();
+        properties.insert("code".to_string(), Slot::Integer(code));
+        for (name, value) in [ ("stdout", stdout), ("stderr", stderr), ("message", format!("{}", err)) ] {
+            let value = match self.heap.alloc(Object::String(value)) {
+                Ok(value)   => value,
+                Err(reason) => { return Err(VmError::HeapAllocError{ what: format!("the {} of a caught error", name), err: reason }); }
+            };
+            properties.insert(name.to_string(), Slot::Object(value));
+        }
+        let instance = match self.heap.alloc(Object::Instance(Instance::new(class, properties))) {
+            Ok(instance) => instance,
+            Err(reason)  => { return Err(VmError::HeapAllocError{ what: "a caught error".to_string(), err: reason }); }
+        };
+        self.stack.push_object(instance);
+        Ok(())
+    }
+
+    /// Calls an external function once for every map of arguments in an array (i.e., the `map_call()` builtin), leaving it to the executor how to make the calls (e.g., as a single job array).
+    /// 
+    /// Calls that fail don't fail the others; instead, their place in the result is taken by an Error like the ones that OP_TRY handlers receive.
+    /// 
+    /// **Arguments**
+    ///  * `arguments`: The arguments of the builtin: the external function, and an array with a map of arguments for every call.
+    ///  * `location`: The location to run the calls at, if any.
+    /// 
+    /// **Returns**  
+    /// An array with the result of every call in the order of the inputs, or a VmError if the arguments are invalid or the calls could not be made at all.
+    async fn map_call(&mut self, arguments: Vec<Value>, location: Option<String>) -> Result<Value, VmError> {
+        let builtin = BuiltinFunction::MapCall;
+        builtins::check_arity(builtin, &arguments, 2).map_err(|err| VmError::BuiltinCallError{ builtin, err })?;
+        let illegal = |expected: &str, got: &Value| VmError::BuiltinCallError{ builtin, err: BuiltinError::IllegalArgumentError{ builtin, expected: expected.to_string(), got: got.data_type() } };
+
+        // Get the function and its inputs
+        let mut arguments = arguments.into_iter();
+        let function = match arguments.next().unwrap() {
+            Value::FunctionExt(function) => function,
+            value                        => { return Err(illegal("an external function", &value)); }
+        };
+        let inputs = match arguments.next().unwrap() {
+            Value::Array{ entries, .. } => entries,
+            value                       => { return Err(illegal("an array of maps", &value)); }
+        };
+
+        // Check every input like the arguments of a single call, before anything is scheduled
+        let mut calls = Vec::with_capacity(inputs.len());
+        for (i, input) in inputs.into_iter().enumerate() {
+            match input {
+                Value::Map(input) => { calls.push(named_arguments(&function, i, input)?); },
+                value             => { return Err(illegal("an array of maps", &value)); }
+            }
+        }
+
+        // Do the calls (or only pretend to, in a dry run)
+        let results = if self.options.dry_run {
+            debug!(" > Simulating {} external call(s)", calls.len());
+            let types = self.package_index.get(&function.package, Some(&function.version)).map(|package| package.types.clone()).unwrap_or_default();
+            let default = default_value(function.return_type.as_deref(), &types);
+            let mut results = Vec::with_capacity(calls.len());
+            for arguments in calls {
+                results.push(self.executor.dry_call(function.clone(), arguments, location.clone(), default.clone()).await);
+            }
+            results
+        } else {
+            debug!(" > Handing {} call(s) to external executor", calls.len());
+            match self.executor.map_call(function.clone(), calls, location).await {
+                Ok(results) => results,
+                Err(err)    => { return Err(VmError::ExternalCallError{ function: function.name.clone(), err }); }
+            }
+        };
+
+        // Put an Error in the place of every call that failed
+        let entries = results.into_iter()
+            .map(|result| match result {
+                Ok(value) => value,
+                Err(err)  => error_value(&VmError::ExternalCallError{ function: function.name.clone(), err }),
+            })
+            .collect();
+        Ok(Value::Array{ data_type: format!("{}[]", function.return_type.as_deref().unwrap_or("unit")), entries })
+    }
+
     /* TIM */
     /// **Edited: working with the new StackError.**
     ///
@@ -782,6 +1393,24 @@ where
     }
     /*******/
 
+    /// Pops the two topmost values off the stack for a bitwise operator, which only works on integers.
+    /// 
+    /// **Returns**  
+    /// The lefthandside and the righthandside as integers, or a VmError if they aren't both integers.
+    fn pop_integers(&mut self) -> Result<(i64, i64), VmError> {
+        // Get the righthand side from the stack
+        let rhs = self.stack.pop();
+        if let Err(reason) = rhs { return Err(VmError::StackReadError{ what: "an integer".to_string(), err: reason }); }
+        // Get the lefthand side next
+        let lhs = self.stack.pop();
+        if let Err(reason) = lhs { return Err(VmError::StackReadError{ what: "an integer".to_string(), err: reason }); }
+
+        match (lhs.unwrap(), rhs.unwrap()) {
+            (Slot::Integer(lhs), Slot::Integer(rhs)) => Ok((lhs, rhs)),
+            (lhs, rhs)                               => Err(VmError::NotBitwise{ lhs: lhs.data_type(), rhs: rhs.data_type() }),
+        }
+    }
+
     /* TIM */
     /// **Edited: working with the new StackError, so also returning VmErrors to accomodate that now.**
     /// 
@@ -904,6 +1533,39 @@ where
     }
     /*******/
 
+    /// Performs a bitwise and on the two topmost values on the stack.
+    /// 
+    /// **Returns**  
+    /// Nothing if it was successfull, or a VmError detailling why if it wasn't.
+    #[inline]
+    pub fn op_bit_and(&mut self) -> Result<(), VmError> {
+        let (lhs, rhs) = self.pop_integers()?;
+        self.stack.push_integer(lhs & rhs);
+        Ok(())
+    }
+
+    /// Performs a bitwise or on the two topmost values on the stack.
+    /// 
+    /// **Returns**  
+    /// Nothing if it was successfull, or a VmError detailling why if it wasn't.
+    #[inline]
+    pub fn op_bit_or(&mut self) -> Result<(), VmError> {
+        let (lhs, rhs) = self.pop_integers()?;
+        self.stack.push_integer(lhs | rhs);
+        Ok(())
+    }
+
+    /// Performs a bitwise exclusive or on the two topmost values on the stack.
+    /// 
+    /// **Returns**  
+    /// Nothing if it was successfull, or a VmError detailling why if it wasn't.
+    #[inline]
+    pub fn op_bit_xor(&mut self) -> Result<(), VmError> {
+        let (lhs, rhs) = self.pop_integers()?;
+        self.stack.push_integer(lhs ^ rhs);
+        Ok(())
+    }
+
     /* TIM */
     /// **Edited: now returning errors from buildins (see builtins.rs), local functions and external functions; also edited doc comment**
     ///
@@ -939,15 +1601,36 @@ where
                 let function = *code;
                 let arguments = self.arguments(arity);
                 if let Err(i) = arguments { return Err(VmError::FunctionArityError{ name: format!("{}", function), got: i, expected: arity }); }
+                let arguments = arguments.unwrap();
+                if let Some(debugger) = &mut self.debugger { debugger.on_call(&format!("{}", function), arity, self.frames.len() + 1); }
+
+                // Check the arguments against the declared signature (the builtin checks their number itself)
+                let name = function.signature().map(String::from).unwrap_or_else(|| format!("{}", function));
+                for ((parameter, data_type), argument) in function.parameters().iter().zip(&arguments) {
+                    check_argument_type(&name, parameter, data_type, false, argument)?;
+                }
 
-                // Do the call
-                match builtins::call(function, arguments.unwrap(), &self.executor, location).await {
-                    Ok(res)  => res,
-                    Err(err) => {
-                        // Do an early error print
-                        let err = VmError::BuiltinCallError{ builtin: function, err };
-                        error!("{}", &err);
-                        return Err(err);
+                // Mapping a function over inputs needs the checks (and dry runs) of external calls, so the VM does that itself
+                if function == BuiltinFunction::MapCall {
+                    match self.map_call(arguments, location).await {
+                        Ok(res)  => res,
+                        Err(err) => {
+                            error!("{}", &err);
+                            return self.catch(err);
+                        }
+                    }
+                } else {
+                    // Do the call
+                    match builtins::call(function, arguments, &self.executor, location, self.options.compact_print, self.cancel.as_ref()).await {
+                        Ok(res)  => res,
+                        // Being cancelled is not a failure that the script may handle
+                        Err(BuiltinError::Cancelled{ .. }) => { return Err(VmError::Cancelled); }
+                        Err(err) => {
+                            // Do an early error print
+                            let err = VmError::BuiltinCallError{ builtin: function, err };
+                            error!("{}", &err);
+                            return self.catch(err);
+                        }
                     }
                 }
             }
@@ -973,15 +1656,53 @@ where
                     let arguments = self.arguments(arity);
                     if let Err(i) = arguments { return Err(VmError::FunctionArityError{ name: function.name.clone(), got: i, expected: arity }); }
 
+                    if let Some(debugger) = &mut self.debugger { debugger.on_call(&function.name, arity, self.frames.len() + 1); }
+
+                    // Fill in the defaults of any trailing parameters that weren't given
+                    let arguments = fill_defaults(&function, arguments.unwrap())?;
+
+                    // Make sure every argument has the declared type before anything is scheduled
+                    for (parameter, argument) in function.parameters.iter().zip(&arguments) {
+                        check_argument_type(&function.name, &parameter.name, &parameter.data_type, parameter.optional.unwrap_or_default(), argument)?;
+                    }
+
+                    // Summarize the arguments before they are moved into the call, if we're tracing
+                    let traced = if self.options.trace {
+                        Some((trace::summarize_arguments(&function.parameters, &arguments), location.clone(), function.package.clone(), function.version.clone(), Instant::now()))
+                    } else {
+                        None
+                    };
+
                     // Map the arguments to key/value pairs
-                    let arguments = itertools::zip(&function.parameters, arguments.unwrap())
+                    let arguments = itertools::zip(&function.parameters, arguments)
                         .map(|(p, a)| (p.name.clone(), a))
                         .collect();
 
-                    // Do the call
+                    // Do the call (or only pretend to, in a dry run)
                     let function_name = function.name.clone();
-                    debug!(" > Handing control to external executor");
-                    match self.executor.call(function, arguments, location).await {
+                    let simulated = self.options.dry_run;
+                    let result = if simulated {
+                        debug!(" > Simulating external call");
+                        let types = self.package_index.get(&function.package, Some(&function.version)).map(|package| package.types.clone()).unwrap_or_default();
+                        let default = default_value(function.return_type.as_deref(), &types);
+                        self.executor.dry_call(function, arguments, location, default).await
+                    } else {
+                        debug!(" > Handing control to external executor");
+                        self.executor.call(function, arguments, location).await
+                    };
+                    if let Some((arguments, location, package, version, start)) = traced {
+                        self.trace.push(TraceEntry {
+                            function    : function_name.clone(),
+                            package,
+                            version,
+                            location,
+                            arguments,
+                            duration_ms : start.elapsed().as_millis() as u64,
+                            outcome     : match &result { Ok(_) => TraceOutcome::Success, Err(err) => TraceOutcome::Failure{ error: format!("{}", err) } },
+                            simulated,
+                        });
+                    }
+                    match result {
                         Ok(value) => {
                             debug!("Value from function '{}' (external): \n{:#?}", function_name, value);
                             value
@@ -990,7 +1711,7 @@ where
                             // Do an early debug print
                             let err = VmError::ExternalCallError{ function: function_name, err: reason };
                             debug!("{}", &err);
-                            return Err(err);
+                            return self.catch(err);
                         }
                     }
                 }
@@ -1005,6 +1726,7 @@ where
 
         // Remove (built-in or external) function from the stack.
         self.stack.pop().unwrap();
+        if let Some(debugger) = &mut self.debugger { debugger.on_return(self.frames.len() + 1); }
 
         // Store return value on the stack.
         self.stack.push(match Slot::from_value(value, &self.globals, &mut self.heap) {
@@ -1017,6 +1739,21 @@ where
     }
     /*******/
 
+    /// Removes the error handler that the matching OP_TRY installed.
+    /// 
+    /// **Returns**  
+    /// Nothing if it was successfull, or a VmError if the current function has no error handler.
+    #[inline]
+    pub fn op_catch_end(&mut self) -> Result<(), VmError> {
+        match self.handlers.last() {
+            Some(handler) if handler.depth == self.frames.len() => {
+                self.handlers.pop();
+                Ok(())
+            },
+            _ => Err(VmError::IllegalCatchEndError),
+        }
+    }
+
     /* TIM */
     /// **Edited: now returning VmErrors**
     ///
@@ -1033,6 +1770,31 @@ where
     }
     /*******/
 
+    /// Coalesces the top element of the stack, keeping it and jumping over the fallback unless it is Unit (in which case it is popped, so that the fallback that follows takes its place).
+    /// 
+    /// **Returns**  
+    /// Nothing if it was successfull, or a VmError detailling why if it wasn't.
+    #[inline]
+    pub fn op_coalesce(&mut self) -> Result<(), VmError> {
+        // Get the value itself from the stack
+        let lhs = self.stack.pop();
+        if let Err(reason) = lhs { return Err(VmError::StackReadError{ what: "a value".to_string(), err: reason }); }
+
+        // Keep it and skip the fallback if it is not Unit
+        match lhs.unwrap() {
+            Slot::Unit => {
+                // Skip the next two bytes detailling the offset, so the fallback is evaluated instead
+                let frames_len = self.frames.len();
+                self.frames[frames_len - 1].ip += 2;
+                Ok(())
+            },
+            lhs => {
+                self.stack.push(lhs);
+                self.op_jump()
+            },
+        }
+    }
+
     /* TIM */
     /// **Edited: now returning VmErrors**
     ///
@@ -1063,7 +1825,7 @@ where
     /*******/
 
     /* TIM */
-    /// **Edited: now returning VmErrors**
+    /// **Edited: now returning VmErrors, and always producing a real.**
     ///
     /// Performs a division on the two most recent values on the stack.
     /// 
@@ -1080,10 +1842,9 @@ where
         if let Err(reason) = lhs { return Err(VmError::StackReadError{ what: "a numeric value".to_string(), err: reason }); }
         let lhs = lhs.unwrap();
 
-        // Do the division based on what is given to us
-        // TODO: Talk about integer VS float division in the documentation.
+        // Division always results in a real (so 1 / 0 is inf); truncating integer division is done with the div() builtin
         match (lhs, rhs) {
-            (Slot::Integer(lhs), Slot::Integer(rhs)) => self.stack.push_integer(lhs / rhs),
+            (Slot::Integer(lhs), Slot::Integer(rhs)) => self.stack.push_real(lhs as f64 / rhs as f64),
             (Slot::Integer(lhs), Slot::Real(rhs))    => self.stack.push_real(lhs as f64 / rhs),
             (Slot::Real(lhs), Slot::Real(rhs))       => self.stack.push_real(lhs / rhs),
             (Slot::Real(lhs), Slot::Integer(rhs))    => self.stack.push_real(lhs / rhs as f64),
@@ -1358,12 +2119,12 @@ where
         let lhs = lhs.unwrap();
 
         // Run the comparison
-        let value = match (rhs, lhs) {
-            (Slot::Integer(rhs), Slot::Integer(lhs)) => rhs > lhs,
-            (Slot::Integer(rhs), Slot::Real(lhs)   ) => (rhs as f64) > lhs,
-            (Slot::Real(rhs),    Slot::Integer(lhs)) => rhs > (lhs as f64),
-            (Slot::Real(rhs),    Slot::Real(lhs)   ) => rhs > lhs,
-            (rhs, lhs)                               => { return Err(VmError::NotComparable{ rhs: rhs.data_type(), lhs: lhs.data_type() }); }
+        let value = match (lhs, rhs) {
+            (Slot::Integer(lhs), Slot::Integer(rhs)) => lhs > rhs,
+            (Slot::Integer(lhs), Slot::Real(rhs)   ) => (lhs as f64) > rhs,
+            (Slot::Real(lhs),    Slot::Integer(rhs)) => lhs > (rhs as f64),
+            (Slot::Real(lhs),    Slot::Real(rhs)   ) => lhs > rhs,
+            (lhs, rhs)                               => { return Err(VmError::NotComparable{ lhs: lhs.data_type(), rhs: rhs.data_type() }); }
         };
 
         // Push the result on the stack
@@ -1372,11 +2133,42 @@ where
     }
     /*******/
 
+    /// Compares the top two values on the stack in terms of the lefthandside being greater than or equal to the righthandside. Integers and reals are compared like OP_GREATER does.
+    /// 
+    /// **Returns**  
+    /// Nothing if the call was alright, but an Err(VmError) if it couldn't be completed somehow.
+    #[inline]
+    pub fn op_greater_equal(&mut self) -> Result<(), VmError> {
+        // Get the righthand side from the stack
+        let rhs = self.stack.pop();
+        if let Err(reason) = rhs { return Err(VmError::StackReadError{ what: "a numeric value".to_string(), err: reason }); }
+        let rhs = rhs.unwrap();
+        // Get the lefthand side next
+        let lhs = self.stack.pop();
+        if let Err(reason) = lhs { return Err(VmError::StackReadError{ what: "a numeric value".to_string(), err: reason }); }
+        let lhs = lhs.unwrap();
+
+        // Run the comparison
+        let value = match (lhs, rhs) {
+            (Slot::Integer(lhs), Slot::Integer(rhs)) => lhs >= rhs,
+            (Slot::Integer(lhs), Slot::Real(rhs)   ) => (lhs as f64) >= rhs,
+            (Slot::Real(lhs),    Slot::Integer(rhs)) => lhs >= (rhs as f64),
+            (Slot::Real(lhs),    Slot::Real(rhs)   ) => lhs >= rhs,
+            (lhs, rhs)                               => { return Err(VmError::NotComparable{ lhs: lhs.data_type(), rhs: rhs.data_type() }); }
+        };
+
+        // Push the result on the stack
+        self.stack.push_boolean(value);
+        Ok(())
+    }
+
     /* TIM */
-    /// **Edited: now supports returning VmErrors instead of panicking.**
+    /// **Edited: now supports returning VmErrors instead of panicking. Also replaces an earlier import of another version of the package.**
     ///
     /// Tries to import a given package.
     /// 
+    /// The latest version that satisfies the import's version constraint is imported (or simply the latest, without one). Importing a version that is already imported does nothing.
+    /// 
     /// **Returns**  
     /// Nothing if it was successfull, or a VmError detailling why if it wasn't.
     #[inline]
@@ -1394,11 +2186,58 @@ where
             object  => { return Err(VmError::IllegalImportError{ target: object.data_type() }); },
         };
 
-        // Try to get the package from the list
         let p_name = p_name.clone();
-        let package = self.package_index.get(&p_name, None);
-        if package.is_none() { return Err(VmError::UndefinedImportError{ package: p_name }); }
-        let package = package.unwrap();
+
+        // Then get the version constraint, if any
+        let constraint = self.frame_const("a version constraint")?;
+        let constraint = match (constraint, constraint.as_object()) {
+            (Slot::Unit, _)    => VersionConstraint::Latest,
+            (_, Some(handle))  => match handle.get() {
+                Object::String(constraint) => match VersionConstraint::from_str(constraint) {
+                    Ok(constraint) => constraint,
+                    Err(err)       => { return Err(VmError::VersionConstraintError{ package: p_name, err }); }
+                },
+                object => { return Err(VmError::IllegalConstraintError{ package: p_name, target: object.data_type() }); },
+            },
+            (constraint, None) => { return Err(VmError::IllegalConstraintError{ package: p_name, target: constraint.clone().into_value().data_type() }); },
+        };
+
+        // Try to get the matching version of the package from the list
+        let package = match self.package_index.resolve(&p_name, &constraint) {
+            Ok(package) => package.clone(),
+            Err(PackageIndexError::NoMatchingVersion{ available, .. }) => {
+                return Err(VmError::UnsatisfiedImportError{ package: p_name, constraint: constraint.to_string(), available: available.iter().map(|version| version.to_string()).collect() });
+            },
+            Err(_) => {
+                let required_by = self.package_index.dependents(&p_name).into_iter().map(|dependent| format!("'{}' (version {})", dependent.name, dependent.version)).collect();
+                return Err(VmError::UndefinedImportError{ package: p_name, required_by });
+            },
+        };
+
+        // Importing the same version again changes nothing, but another version replaces the one we have
+        if self.package_versions.get(&p_name) == Some(&package.version) {
+            if let Err(reason) = self.executor.debug(format!("Package '{}' (version {}) is already imported", p_name, package.version)).await {
+                error!("Could not send debug message to client: {}", reason);
+            };
+            return Ok(());
+        }
+        {
+            // Only the globals of the old version may be replaced; any other global with the same name is a conflict
+            let owned: &[String] = self.package_globals.get(&p_name).map(|globals| globals.as_slice()).unwrap_or(&[]);
+            if let Some(f_name) = package.functions.keys().find(|f_name| self.globals.contains_key(*f_name) && !owned.contains(f_name)) {
+                return Err(VmError::DuplicateFunctionImport{ package: p_name, function: f_name.clone() });
+            }
+            if let Some(t_name) = package.types.keys().find(|t_name| self.globals.contains_key(*t_name) && !owned.contains(t_name)) {
+                return Err(VmError::DuplicateTypeImport{ package: p_name, type_name: t_name.clone() });
+            }
+        }
+        if self.package_globals.contains_key(&p_name) {
+            let old_version = self.package_versions.get(&p_name).map(|version| version.to_string()).unwrap_or_else(|| String::from("?"));
+            self.drop_package(&p_name)?;
+            if let Err(reason) = self.executor.debug(format!("Replacing version {} of package '{}' with version {}", old_version, p_name, package.version)).await {
+                error!("Could not send debug message to client: {}", reason);
+            };
+        }
 
         // Try to resolve the list of functions behind the package
         if !package.functions.is_empty() {
@@ -1421,6 +2260,13 @@ where
                     kind: package.kind,
                     version: package.version.clone(),
                     parameters: function.parameters.clone(),
+                    return_type: Some(function.return_type.clone()),
+                    description: function.description.clone(),
+                    requirements: package.requirements.clone(),
+                    environment: package.environment.clone(),
+                    env: Default::default(),
+                    pure: function.pure,
+                    concurrency: function.concurrency,
                 };
 
                 // Write it to the heap
@@ -1433,6 +2279,7 @@ where
                 // Insert the global
                 if self.globals.contains_key(f_name) { return Err(VmError::DuplicateFunctionImport{ package: p_name.clone(), function: f_name.clone() }); }
                 self.globals.insert(f_name.clone(), object);
+                self.package_globals.entry(p_name.clone()).or_default().push(f_name.clone());
 
                 // Update the list of functions
                 if !sfunctions.is_empty() { sfunctions += ", "; }
@@ -1467,6 +2314,7 @@ where
                 // Insert the global
                 if self.globals.contains_key(t_name) { return Err(VmError::DuplicateTypeImport{ package: p_name.clone(), type_name: t_name.clone() }); }
                 self.globals.insert(t_name.clone(), object);
+                self.package_globals.entry(p_name.clone()).or_default().push(t_name.clone());
 
                 // Update the list of types
                 if !stypes.is_empty() { stypes += ", "; }
@@ -1482,6 +2330,8 @@ where
         }
 
         // Done!
+        self.package_versions.insert(p_name.clone(), package.version.clone());
+        self.package_globals.entry(p_name.clone()).or_default();
         if let Err(reason) = self.executor.debug(format!("Imported package '{}' successfully", p_name)).await {
             error!("Could not send debug message to client: {}", reason);
         };
@@ -1492,39 +2342,211 @@ where
     /* TIM */
     /// **Edited: now supports returning VmErrors instead of panicking.**
     ///
-    /// Indexes the given Array and returns its value at that location on the stack.
+    /// Indexes the given Array or Map and returns its value at that location on the stack.
     /// 
     /// **Returns**  
     /// Nothing if it was successfull, or a VmError detailling why if it wasn't.
     #[inline]
     pub fn op_index(&mut self) -> Result<(), VmError> {
         // Get the index from the stack
-        let index = self.stack.pop_integer();
-        if let Err(reason) = index { return Err(VmError::StackReadError{ what: "an array index".to_string(), err: reason }); }
-        let index = index.unwrap();
+        let index = match self.stack.pop() {
+            Ok(index)   => index,
+            Err(reason) => { return Err(VmError::StackReadError{ what: "an index".to_string(), err: reason }); }
+        };
 
-        // Get the array object from the stack
+        // Get the array (or map) object from the stack
         let array = self.stack.pop_object();
         if let Err(reason) = array { return Err(VmError::StackReadError{ what: "an array handle".to_string(), err: reason }); }
         let array_handle = array.unwrap();
 
-        // Try to get the Array behind the stack object
-        let array = match array_handle.get() {
-            Object::Array(array) => array,
-            object               => { return Err(VmError::IllegalIndexError{ target: object.data_type() }); },
+        // Try to get the element behind the index
+        let element = match array_handle.get() {
+            Object::Array(array) => {
+                let index = self.array_index(&index)?;
+                match array.elements.get(index as usize) {
+                    Some(element) => element.clone(),
+                    None          => { return Err(VmError::ArrayOutOfBoundsError{ index: index as usize, max: array.elements.len() }); }
+                }
+            },
+            Object::Map(map) => {
+                let key = self.map_key(index)?;
+                match map.get(&key) {
+                    Some(element) => element.clone(),
+                    None          => { return Err(VmError::MissingKeyError{ key }); }
+                }
+            },
+            object => { return Err(VmError::IllegalIndexError{ target: object.data_type() }); },
         };
 
-        // Try to get the element from the array
-        if let Some(element) = array.elements.get(index as usize) {
-            // Put the value on the stack
-            self.stack.push(element.clone());
-            Ok(())
-        } else {
-            Err(VmError::ArrayOutOfBoundsError{ index: index as usize, max: array.elements.len() })
+        // Put the value on the stack
+        self.stack.push(element);
+        Ok(())
+    }
+    /*******/
+
+    /// Sets the element of the given Array or Map at the given index.  
+    /// Since heap objects are immutable, this allocates an updated copy and pushes its handle on the stack; it is up to the caller to store it again.
+    /// 
+    /// **Returns**  
+    /// Nothing if it was successfull, or a VmError detailling why if it wasn't.
+    #[inline]
+    pub fn op_index_set(&mut self) -> Result<(), VmError> {
+        // Get the new value and the index from the stack
+        let value = match self.stack.pop() {
+            Ok(value)   => value,
+            Err(reason) => { return Err(VmError::StackReadError{ what: "an element value".to_string(), err: reason }); }
+        };
+        let index = match self.stack.pop() {
+            Ok(index)   => index,
+            Err(reason) => { return Err(VmError::StackReadError{ what: "an index".to_string(), err: reason }); }
+        };
+
+        // Get the array (or map) object from the stack
+        let handle = match self.stack.pop_object() {
+            Ok(handle)  => handle,
+            Err(reason) => { return Err(VmError::StackReadError{ what: "an array or map handle".to_string(), err: reason }); }
+        };
+
+        // Create the updated copy of the object
+        let object = match handle.get() {
+            Object::Array(array) => {
+                let index = self.array_index(&index)?;
+                if index < 0 || index as usize >= array.elements.len() { return Err(VmError::ArrayOutOfBoundsError{ index: index as usize, max: array.elements.len() }); }
+
+                let mut elements = array.elements.clone();
+                elements[index as usize] = value;
+                match Array::new(elements) {
+                    Ok(array) => Object::Array(array),
+                    Err(err)  => { return Err(VmError::ObjectError{ err }); }
+                }
+            },
+            Object::Map(map) => {
+                let key = self.map_key(index)?;
+                let mut map = map.clone();
+                map.insert(key, value);
+                Object::Map(map)
+            },
+            object => { return Err(VmError::IllegalIndexError{ target: object.data_type() }); },
+        };
+
+        // Allocate it and push the new handle
+        let handle = match self.heap.alloc(object) {
+            Ok(handle)  => handle,
+            Err(reason) => { return Err(VmError::HeapAllocError{ what: "an updated array or map".to_string(), err: reason }); }
+        };
+        self.stack.push(Slot::Object(handle));
+        Ok(())
+    }
+
+    /// Resolves the given Slot to an Array index.
+    /// 
+    /// **Arguments**
+    ///  * `index`: The Slot that is used as index.
+    /// 
+    /// **Returns**  
+    /// The index as an integer if the Slot is one, or a VmError::StackReadError otherwise.
+    #[inline]
+    fn array_index(&self, index: &Slot) -> Result<i64, VmError> {
+        match index.clone().into_value() {
+            Value::Integer(index) => Ok(index),
+            _                     => Err(VmError::StackReadError{ what: "an array index".to_string(), err: StackError::UnexpectedType{ got: index.data_type(), expected: "Integer".to_string() } }),
         }
     }
+
+    /// Resolves the given Slot to a Map key.
+    /// 
+    /// **Arguments**
+    ///  * `key`: The Slot that is used as key.
+    /// 
+    /// **Returns**  
+    /// The key as a String if the Slot is a string, or a VmError::IllegalKeyError otherwise.
+    #[inline]
+    fn map_key(&self, key: Slot) -> Result<String, VmError> {
+        if let Slot::Object(handle) = &key {
+            if let Object::String(key) = handle.get() { return Ok(key.clone()); }
+        }
+        Err(VmError::IllegalKeyError{ key: key.data_type() })
+    }
     /*******/
 
+    /// Turns the Array or Map on top of the stack into iterator state: the Array to iterate over (for Maps, a new Array with their keys in sorted order) and the position in it.
+    /// 
+    /// **Returns**  
+    /// Nothing if it was successfull, or a VmError detailling why if it wasn't.
+    #[inline]
+    pub fn op_iter(&mut self) -> Result<(), VmError> {
+        // Get the array (or map) object from the stack
+        let handle = match self.stack.pop() {
+            Ok(Slot::Object(handle)) => handle,
+            Ok(slot)                 => { return Err(VmError::IllegalIterError{ target: slot.data_type() }); }
+            Err(reason)              => { return Err(VmError::StackReadError{ what: "an array or map handle".to_string(), err: reason }); }
+        };
+
+        // Maps are iterated over by their keys, so collect those first
+        let handle = match handle.get() {
+            Object::Array(_) => handle,
+            Object::Map(map) => {
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+                let mut elements: Vec<Slot> = Vec::with_capacity(keys.len());
+                for key in keys {
+                    match self.heap.alloc(Object::String(key.clone())) {
+                        Ok(key)     => { elements.push(Slot::Object(key)); },
+                        Err(reason) => { return Err(VmError::HeapAllocError{ what: "a map key".to_string(), err: reason }); }
+                    }
+                }
+                let array = match Array::new(elements) {
+                    Ok(array) => array,
+                    Err(err)  => { return Err(VmError::ObjectError{ err }); }
+                };
+                match self.heap.alloc(Object::Array(array)) {
+                    Ok(handle)  => handle,
+                    Err(reason) => { return Err(VmError::HeapAllocError{ what: "the keys of a map".to_string(), err: reason }); }
+                }
+            },
+            object => { return Err(VmError::IllegalIterError{ target: object.data_type() }); },
+        };
+
+        // Leave the iterator state on the stack
+        self.stack.push(Slot::Object(handle));
+        self.stack.push_integer(0);
+        Ok(())
+    }
+
+    /// Advances the iterator at the local offset embedded in the function code, pushing its next element and a True if there is one, or only a False if it's exhausted.
+    /// 
+    /// **Returns**  
+    /// Nothing if it was successfull, or a VmError detailling why if it wasn't.
+    #[inline]
+    pub fn op_iter_next(&mut self) -> Result<(), VmError> {
+        // Find the iterator state on the stack
+        let index = *self.frame_u8("an iterator offset")? as usize;
+        let offset = self.frame_stack_offset()?;
+        let position = self.array_index(self.stack.get(offset + index + 1))?;
+        let handle = match self.stack.get_object(offset + index) {
+            Ok(handle)  => handle,
+            Err(reason) => { return Err(VmError::StackReadError{ what: "an iterator".to_string(), err: reason }); }
+        };
+
+        // Get the next element, if any
+        let element = match handle.get() {
+            Object::Array(array) => array.elements.get(position as usize).cloned(),
+            object               => { return Err(VmError::IllegalIterError{ target: object.data_type() }); },
+        };
+        match element {
+            Some(element) => {
+                // Move the position forward (copy_pop() swaps the new position into place)
+                self.stack.push_integer(position + 1);
+                self.stack.copy_pop(offset + index + 1);
+
+                self.stack.push(element);
+                self.stack.push_boolean(true);
+            },
+            None => { self.stack.push_boolean(false); },
+        }
+        Ok(())
+    }
+
     /* TIM */
     /// **Edited: now supports returning VmErrors instead of panicking.**
     /// 
@@ -1608,12 +2630,12 @@ where
         let lhs = lhs.unwrap();
 
         // Run the comparison
-        let value = match (rhs, lhs) {
-            (Slot::Integer(rhs), Slot::Integer(lhs)) => rhs < lhs,
-            (Slot::Integer(rhs), Slot::Real(lhs)   ) => (rhs as f64) < lhs,
-            (Slot::Real(rhs),    Slot::Integer(lhs)) => rhs < (lhs as f64),
-            (Slot::Real(rhs),    Slot::Real(lhs)   ) => rhs < lhs,
-            (rhs, lhs)                               => { return Err(VmError::NotComparable{ rhs: rhs.data_type(), lhs: lhs.data_type() }); }
+        let value = match (lhs, rhs) {
+            (Slot::Integer(lhs), Slot::Integer(rhs)) => lhs < rhs,
+            (Slot::Integer(lhs), Slot::Real(rhs)   ) => (lhs as f64) < rhs,
+            (Slot::Real(lhs),    Slot::Integer(rhs)) => lhs < (rhs as f64),
+            (Slot::Real(lhs),    Slot::Real(rhs)   ) => lhs < rhs,
+            (lhs, rhs)                               => { return Err(VmError::NotComparable{ lhs: lhs.data_type(), rhs: rhs.data_type() }); }
         };
 
         // Push the result of the comparison on the stack
@@ -1624,6 +2646,35 @@ where
     }
     /*******/
 
+    /// Compares the top two values on the stack in terms of the lefthandside being less than or equal to the righthandside. Integers and reals are compared like OP_LESS does.
+    /// 
+    /// **Returns**  
+    /// Nothing if the call was alright, but an Err(VmError) if it couldn't be completed somehow.
+    #[inline]
+    pub fn op_less_equal(&mut self) -> Result<(), VmError> {
+        // Get the righthand side from the stack
+        let rhs = self.stack.pop();
+        if let Err(reason) = rhs { return Err(VmError::StackReadError{ what: "a numeric value".to_string(), err: reason }); }
+        let rhs = rhs.unwrap();
+        // Get the lefthand side next
+        let lhs = self.stack.pop();
+        if let Err(reason) = lhs { return Err(VmError::StackReadError{ what: "a numeric value".to_string(), err: reason }); }
+        let lhs = lhs.unwrap();
+
+        // Run the comparison
+        let value = match (lhs, rhs) {
+            (Slot::Integer(lhs), Slot::Integer(rhs)) => lhs <= rhs,
+            (Slot::Integer(lhs), Slot::Real(rhs)   ) => (lhs as f64) <= rhs,
+            (Slot::Real(lhs),    Slot::Integer(rhs)) => lhs <= (rhs as f64),
+            (Slot::Real(lhs),    Slot::Real(rhs)   ) => lhs <= rhs,
+            (lhs, rhs)                               => { return Err(VmError::NotComparable{ lhs: lhs.data_type(), rhs: rhs.data_type() }); }
+        };
+
+        // Push the result on the stack
+        self.stack.push_boolean(value);
+        Ok(())
+    }
+
     ///
     ///
     ///
@@ -1809,6 +2860,26 @@ where
     }
     /*******/
 
+    /// Tests whether the top two values on the stack are different, which is always the opposite of OP_EQUAL.
+    /// 
+    /// **Returns**  
+    /// Nothing if the call was alright, but an Err(VmError) if it couldn't be completed somehow.
+    #[inline]
+    pub fn op_not_equal(&mut self) -> Result<(), VmError> {
+        // Get the righthand side from the stack
+        let rhs = self.stack.pop();
+        if let Err(reason) = rhs { return Err(VmError::StackReadError{ what: "anything".to_string(), err: reason }); }
+        let rhs = rhs.unwrap();
+        // Get the lefthand side next
+        let lhs = self.stack.pop();
+        if let Err(reason) = lhs { return Err(VmError::StackReadError{ what: "anything".to_string(), err: reason }); }
+        let lhs = lhs.unwrap();
+
+        // Push the result of the comparison
+        self.stack.push_boolean(lhs != rhs);
+        Ok(())
+    }
+
     /* TIM */
     /// **Edited: working with the new StackError, so also returning VmErrors to accomodate that now.**
     ///
@@ -1871,6 +2942,7 @@ where
             let executor = self.executor.clone();
             let package_index = self.package_index.clone();
             let state = self.capture_state();
+            let cancel = self.cancel.clone();
 
             // Use the parallel iterator package to do the parallelism for each branch
             let branch_results = branches
@@ -1881,11 +2953,12 @@ where
                         Ok(vm)   => vm,
                         Err(err) => { return Err(VmError::BranchCreateError{ err: format!("{}", err) }); }
                     };
+                    if let Some(cancel) = &cancel { vm.set_cancel_token(cancel.clone()); }
 
                     // Run the VM for this branch
                     // TEMP: needed because the VM is not completely `send`.
                     let rt = Runtime::new().unwrap();
-                    rt.block_on(vm.anonymous(f))
+                    rt.block_on(vm.anonymous(f)).map(|value| (value, vm.take_trace()))
                 })
                 // We synchronize / join the branches here
                 .collect::<Vec<_>>();
@@ -1894,7 +2967,8 @@ where
             let mut results = Vec::with_capacity(branch_results.len());
             for result in branch_results {
                 // Check if an error occurred during execution of the VM
-                let value = result?;
+                let (value, trace) = result?;
+                self.trace.extend(trace);
 
                 // Try to create a Slot from that
                 results.push(match Slot::from_value(value.clone(), &self.globals, &mut self.heap) {
@@ -1978,6 +3052,11 @@ where
             return Err(VmError::IllegalReturnError);
         }
 
+        // Any error handlers of the function are gone with it
+        while self.handlers.last().map(|handler| handler.depth >= self.frames.len()).unwrap_or(false) {
+            self.handlers.pop();
+        }
+
         // Check if we have to remove stack stuff
         if let Some(frame) = self.frames.pop() {
             // We do, so remove everything except for the return value
@@ -2054,6 +3133,30 @@ where
     }
     /*******/
 
+    /// Shifts the bits of the second value on the stack to the left by the top value on the stack. Bits shifted out are lost.
+    /// 
+    /// **Returns**  
+    /// Nothing if it was successfull, or a VmError detailling why if it wasn't.
+    #[inline]
+    pub fn op_shl(&mut self) -> Result<(), VmError> {
+        let (lhs, rhs) = self.pop_integers()?;
+        if !(0..64).contains(&rhs) { return Err(VmError::IllegalShift{ amount: rhs }); }
+        self.stack.push_integer(lhs << rhs);
+        Ok(())
+    }
+
+    /// Shifts the bits of the second value on the stack to the right by the top value on the stack, keeping its sign.
+    /// 
+    /// **Returns**  
+    /// Nothing if it was successfull, or a VmError detailling why if it wasn't.
+    #[inline]
+    pub fn op_shr(&mut self) -> Result<(), VmError> {
+        let (lhs, rhs) = self.pop_integers()?;
+        if !(0..64).contains(&rhs) { return Err(VmError::IllegalShift{ amount: rhs }); }
+        self.stack.push_integer(lhs >> rhs);
+        Ok(())
+    }
+
     /* TIM */
     /// **Edited: now returning VmErrors**
     ///
@@ -2095,6 +3198,26 @@ where
         self.stack.push(Slot::True);
     }
 
+    /// Installs an error handler for the code up to the matching OP_CATCH_END, which continues at the embedded (forward) offset if an external call in that code fails.
+    /// 
+    /// **Returns**  
+    /// Nothing if it was successfull, or a VmError detailling why if it wasn't.
+    #[inline]
+    pub fn op_try(&mut self) -> Result<(), VmError> {
+        // Read the offset of the handler code
+        let offset = self.frame_u16("an error handler offset")?;
+
+        // Remember where we are, so we can come back here if needed
+        let depth = self.frames.len();
+        self.handlers.push(ErrorHandler {
+            depth,
+            target    : self.frames[depth - 1].ip + offset as usize,
+            stack_len : self.stack.len(),
+            locations : self.locations.len(),
+        });
+        Ok(())
+    }
+
     ///
     ///
     ///
@@ -2103,3 +3226,170 @@ where
         self.stack.push(Slot::Unit);
     }
 }
+
+
+
+/// Completes the arguments for a call to an external function by filling in the default values of any trailing parameters that weren't given.
+/// 
+/// Optional parameters without a default are given a Unit, which the branelet treats as absent.
+/// 
+/// **Arguments**
+///  * `function`: The external function that is called.
+///  * `arguments`: The arguments that were given, in order.
+/// 
+/// **Returns**  
+/// The arguments for every parameter of the function, or a VmError if too many were given or if a required one is missing.
+fn fill_defaults(function: &FunctionExt, mut arguments: Vec<Value>) -> Result<Vec<Value>, VmError> {
+    if arguments.len() > function.parameters.len() {
+        return Err(VmError::TooManyArgumentsError{ name: function.name.clone(), got: arguments.len() as u8, expected: function.parameters.len() });
+    }
+
+    // Go through the parameters that weren't given
+    let mut missing = Vec::new();
+    for parameter in &function.parameters[arguments.len()..] {
+        match &parameter.default {
+            Some(default)                                  => { arguments.push(default.clone()); },
+            None if parameter.optional.unwrap_or_default() => { arguments.push(Value::Unit); },
+            None                                           => { missing.push(parameter.name.clone()); },
+        }
+    }
+    if !missing.is_empty() { return Err(VmError::MissingArgumentsError{ name: function.name.clone(), missing }); }
+
+    Ok(arguments)
+}
+
+/// Matches the arguments of one of the calls of `map_call()`, given by name, to the parameters of the external function, like `fill_defaults()` and `check_argument_type()` do for a single call.
+/// 
+/// **Arguments**
+///  * `function`: The external function that is called.
+///  * `index`: The index of the call in the inputs of `map_call()` (used for debugging purposes).
+///  * `input`: The arguments that were given, by name.
+/// 
+/// **Returns**  
+/// The arguments for every parameter of the function, or a VmError if a required one is missing, one has the wrong type or one is not a parameter at all.
+fn named_arguments(function: &FunctionExt, index: usize, mut input: HashMap<String, Value>) -> Result<HashMap<String, Value>, VmError> {
+    let name = format!("{} (input {})", function.name, index);
+    let mut arguments = HashMap::with_capacity(function.parameters.len());
+    let mut missing = Vec::new();
+    for parameter in &function.parameters {
+        let optional = parameter.optional.unwrap_or_default();
+        match input.remove(&parameter.name) {
+            Some(argument) => {
+                check_argument_type(&name, &parameter.name, &parameter.data_type, optional, &argument)?;
+                arguments.insert(parameter.name.clone(), argument);
+            },
+            None => match &parameter.default {
+                Some(default)    => { arguments.insert(parameter.name.clone(), default.clone()); },
+                None if optional => { arguments.insert(parameter.name.clone(), Value::Unit); },
+                None             => { missing.push(parameter.name.clone()); },
+            },
+        }
+    }
+    if !missing.is_empty() { return Err(VmError::MissingArgumentsError{ name, missing }); }
+    if let Some(argument) = input.keys().min() { return Err(VmError::UnknownArgumentError{ name, argument: argument.clone() }); }
+
+    Ok(arguments)
+}
+
+/// Synthesizes the value that a simulated external call returns in a dry run, based on the declared return type of the function.
+/// 
+/// Numbers are zero, booleans false, strings and arrays empty and classes have every (required) property filled in the same way. An Output has a Unit value and no artifacts. Unknown types (and classes that contain themselves) become a Unit.
+/// 
+/// **Arguments**
+///  * `data_type`: The declared return type of the function, if any.
+///  * `types`: The types of the package that provides the function.
+/// 
+/// **Returns**  
+/// The default Value for the given type.
+fn default_value(data_type: Option<&str>, types: &HashMap<String, Type>) -> Value {
+    fn default_of(data_type: &str, types: &HashMap<String, Type>, visiting: &mut Vec<String>) -> Value {
+        match data_type {
+            "integer" => Value::Integer(0),
+            "real"    => Value::Real(0.0),
+            "boolean" => Value::Boolean(false),
+            "string"  => Value::Unicode(String::new()),
+            data_type if data_type.ends_with("[]") => Value::Array{ data_type: data_type.to_string(), entries: vec![] },
+            // Functions with outputs return what the driver wraps their value in, which is not a declared type
+            data_type if data_type == format!("{}", BuiltinClass::Output) && !types.contains_key(data_type) => {
+                let mut properties = HashMap::new();
+                properties.insert(String::from("value"), Value::Unit);
+                properties.insert(String::from("artifacts"), Value::Array{ data_type: format!("{}[]", BuiltinClass::Artifact), entries: vec![] });
+                Value::Struct{ data_type: data_type.to_string(), properties }
+            },
+            data_type => match types.get(data_type) {
+                Some(class) if !visiting.iter().any(|name| name == data_type) => {
+                    visiting.push(data_type.to_string());
+                    let properties = class.properties.iter()
+                        .filter(|property| !property.optional.unwrap_or_default() || property.default.is_some())
+                        .map(|property| (property.name.clone(), property.default.clone().unwrap_or_else(|| default_of(&property.data_type, types, visiting))))
+                        .collect();
+                    visiting.pop();
+                    Value::Struct{ data_type: data_type.to_string(), properties }
+                },
+                _ => Value::Unit,
+            },
+        }
+    }
+
+    match data_type {
+        Some(data_type) => default_of(data_type, types, &mut vec![]),
+        None            => Value::Unit,
+    }
+}
+
+/// Checks that an argument of a call has the declared type of its parameter.
+/// 
+/// Mounts are not checked (like the branelet does), and optional parameters may always be given a Unit.
+/// 
+/// **Arguments**
+///  * `function`: The name of the function that is called.
+///  * `parameter`: The name of the parameter.
+///  * `data_type`: The declared type of the parameter.
+///  * `optional`: Whether the parameter is optional.
+///  * `argument`: The argument that is given for it.
+/// 
+/// **Returns**  
+/// Nothing if the argument matches, or a VmError::ArgumentTypeError otherwise.
+fn check_argument_type(function: &str, parameter: &str, data_type: &str, optional: bool, argument: &Value) -> Result<(), VmError> {
+    if data_type.starts_with("mount") || (optional && matches!(argument, Value::Unit)) || argument.conforms_to(data_type) { return Ok(()); }
+
+    // Arrays created by the VM carry their element type, so always show them as arrays
+    let got = match argument {
+        Value::Array{ data_type, .. } if !data_type.ends_with("[]") => format!("{}[]", data_type),
+        argument                                                   => argument.data_type(),
+    };
+    Err(VmError::ArgumentTypeError{ function: function.to_string(), parameter: parameter.to_string(), expected: data_type.to_string(), got })
+}
+
+/// Describes the given error of a call as an instance of the Error class, like the ones that OP_TRY handlers receive (see `Vm::catch()`).
+/// 
+/// **Arguments**
+///  * `err`: The VmError that the call failed with.
+/// 
+/// **Returns**  
+/// The Error as a Value, with the exit code, stdout and stderr of the call (if known; see `catchable()`) and the message of the error.
+fn error_value(err: &VmError) -> Value {
+    let (code, stdout, stderr) = catchable(err).unwrap_or_else(|| (-1, String::new(), String::new()));
+    let mut properties = HashMap::new();
+    properties.insert("code".to_string(), Value::Integer(code));
+    properties.insert("stdout".to_string(), Value::Unicode(stdout));
+    properties.insert("stderr".to_string(), Value::Unicode(stderr));
+    properties.insert("message".to_string(), Value::Unicode(format!("{}", err)));
+    Value::Struct{ data_type: ERROR_CLASS.to_string(), properties }
+}
+
+/// Determines whether the given error of a call can be handled by an error handler (see OP_TRY): that's the case if the external job itself failed or if a builtin failed.
+/// 
+/// **Arguments**
+///  * `err`: The VmError that the call failed with.
+/// 
+/// **Returns**  
+/// The exit code, stdout and stderr to describe the error with (where builtins and jobs without an exit code get -1), or None if the error cannot be handled.
+fn catchable(err: &VmError) -> Option<(i64, String, String)> {
+    match err {
+        VmError::ExternalCallError{ err: ExecutorError::ExternalCallFailed{ code, stdout, stderr, .. }, .. } => Some((*code as i64, stdout.clone(), stderr.clone())),
+        VmError::ExternalCallError{ err: ExecutorError::ExternalCallFailedRaw{ output, .. }, .. }           => Some((-1, output.clone(), String::new())),
+        VmError::BuiltinCallError{ .. }                                                                      => Some((-1, String::new(), String::new())),
+        _                                                                                                    => None,
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use brane_bvm::args::{args_from_json, args_to_json, parse_args};
+use brane_bvm::executor::{ExecutorError, ServiceState, VmExecutor};
+use brane_bvm::vm::{Vm, VmError};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use specifications::common::{FunctionExt, Value};
+use specifications::package::PackageIndex;
+
+/// An executor that remembers everything printed to stdout.
+#[derive(Clone, Default)]
+struct EchoExecutor {
+    stdout: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl VmExecutor for EchoExecutor {
+    async fn call(&self, _: FunctionExt, _: HashMap<String, Value>, _: Option<String>) -> Result<Value, ExecutorError> {
+        Err(ExecutorError::UnsupportedError{ executor: String::from("EchoExecutor"), operation: String::from("external function calls") })
+    }
+
+    async fn debug(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stderr(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stdout(&self, text: String) -> Result<(), ExecutorError> {
+        self.stdout.lock().unwrap().push(text);
+        Ok(())
+    }
+
+    async fn wait_until(&self, _: String, _: ServiceState) -> Result<(), ExecutorError> { Ok(()) }
+}
+
+fn run(code: &str, args: &[&str]) -> (Result<(), VmError>, Vec<String>) {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), PackageIndex::empty());
+    let function = compiler.compile(code).unwrap();
+
+    let executor = EchoExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), None, None).unwrap();
+    let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+    vm.set_args(parse_args(&args).unwrap()).unwrap();
+
+    let res = futures::executor::block_on(vm.main(function));
+    let stdout = executor.stdout.lock().unwrap().clone();
+    (res, stdout)
+}
+
+#[test]
+fn echo_script_prints_arguments() {
+    let (res, stdout) = run(include_str!("../../examples/branescript/args/echo.bs"), &["name=world", "times=2"]);
+    res.unwrap();
+    assert_eq!(stdout, vec!["Hello, world!", "And again: hello, world!"]);
+}
+
+#[test]
+fn missing_argument_is_undefined_property() {
+    let (res, _) = run("print(args.name);", &["other=1"]);
+    match res.unwrap_err().inner() {
+        VmError::UndefinedPropertyError{ instance, property } => {
+            assert_eq!(instance, "args");
+            assert_eq!(property, "name");
+        },
+        err => panic!("Expected an UndefinedPropertyError, got {:?}", err),
+    }
+}
+
+#[test]
+fn arguments_survive_state_capture() {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), PackageIndex::empty());
+    let executor = EchoExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), None, None).unwrap();
+    vm.set_args(args_from_json(&args_to_json(&parse_args(&["threshold=0.5".to_string()]).unwrap())).unwrap()).unwrap();
+
+    // Restore the VM from its state as the driver does between two requests of a session
+    let mut vm = Vm::new_with_state(executor.clone(), None, vm.capture_state()).unwrap();
+    futures::executor::block_on(vm.main(compiler.compile("print(args.threshold * 2.0);").unwrap())).unwrap();
+    assert_eq!(*executor.stdout.lock().unwrap(), vec!["1"]);
+}
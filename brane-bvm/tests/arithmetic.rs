@@ -0,0 +1,70 @@
+mod common;
+
+use brane_bvm::builtins::{BuiltinError, BuiltinFunction};
+use brane_bvm::vm::{Vm, VmError};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use common::EchoExecutor;
+use specifications::package::PackageIndex;
+
+fn run(code: &str) -> (Result<(), VmError>, Vec<String>) {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), PackageIndex::empty());
+    let function = compiler.compile(code).unwrap();
+
+    let executor = EchoExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), None, None).unwrap();
+    let res = futures::executor::block_on(vm.main(function));
+    let stdout = executor.stdout.lock().unwrap().clone();
+    (res, stdout)
+}
+
+fn print(code: &str) -> Vec<String> {
+    let (res, stdout) = run(code);
+    res.unwrap();
+    stdout
+}
+
+fn builtin_error(code: &str) -> VmError {
+    let err = run(code).0.unwrap_err();
+    assert!(matches!(err.inner(), VmError::BuiltinCallError{ .. }), "Expected a BuiltinCallError, got {:?}", err);
+    err
+}
+
+#[test]
+fn division_always_results_in_a_real() {
+    assert_eq!(print("print(7 / 2);"), vec!["3.5"]);
+    assert_eq!(print("print(6 / 3);\nprint(6 / 3 == 2.0);"), vec!["2", "true"]);
+    assert_eq!(print("print(-7 / 2);"), vec!["-3.5"]);
+    assert_eq!(print("print(7.0 / 2);\nprint(7 / 2.0);"), vec!["3.5", "3.5"]);
+}
+
+#[test]
+fn real_division_by_zero_is_inf() {
+    assert_eq!(print("print(1 / 0);\nprint(-1 / 0);\nprint(1.0 / 0.0);"), vec!["inf", "-inf", "inf"]);
+}
+
+#[test]
+fn div_truncates_toward_zero() {
+    assert_eq!(print("print(div(7, 2));\nprint(div(-7, 2));\nprint(div(7, -2));"), vec!["3", "-3", "-3"]);
+}
+
+#[test]
+fn div_rejects_zero_and_non_integers() {
+    let err = builtin_error("div(1, 0);");
+    assert!(matches!(err.inner(), VmError::BuiltinCallError{ builtin: BuiltinFunction::Div, err: BuiltinError::DivisionByZeroError{ lhs: 1, .. } }));
+    assert!(format!("{}", err).contains("use '/' instead"));
+
+    let err = run("div(7.0, 2);").0.unwrap_err();
+    assert!(matches!(err.inner(), VmError::ArgumentTypeError{ parameter, got, .. } if parameter == "lhs" && got == "real"), "Expected an ArgumentTypeError, got {:?}", err);
+    assert!(matches!(builtin_error("div(7);").inner(), VmError::BuiltinCallError{ err: BuiltinError::NotEnoughArgumentsError{ expected: 2, got: 1, .. }, .. }));
+}
+
+#[test]
+fn conversions() {
+    assert_eq!(print("print(int(3.9));\nprint(int(-3.9));\nprint(int(\" 42 \"));\nprint(int(true));"), vec!["3", "-3", "42", "1"]);
+    assert_eq!(print("print(real(3));\nprint(real(\"0.25\"));\nprint(real(false));"), vec!["3", "0.25", "0"]);
+    assert_eq!(print("print(str(1 / 4) + \"!\");\nprint(str(12) + str(true));"), vec!["0.25!", "12true"]);
+
+    assert!(matches!(builtin_error("int(\"twelve\");").inner(), VmError::BuiltinCallError{ err: BuiltinError::ConversionError{ builtin: BuiltinFunction::Int, .. }, .. }));
+    assert!(matches!(builtin_error("int(1 / 0);").inner(), VmError::BuiltinCallError{ err: BuiltinError::ConversionError{ .. }, .. }));
+    assert!(matches!(builtin_error("real(\"\");").inner(), VmError::BuiltinCallError{ err: BuiltinError::ConversionError{ builtin: BuiltinFunction::Real, .. }, .. }));
+}
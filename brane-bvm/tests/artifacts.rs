@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use brane_bvm::executor::{ExecutorError, ServiceState, VmExecutor};
+use brane_bvm::vm::Vm;
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use specifications::common::{Function, FunctionExt, Parameter, Value};
+use specifications::package::{PackageIndex, PackageInfo, PackageKind};
+use specifications::version::Version;
+
+/// An executor whose 'render' returns an Output with one artifact (as the driver does for functions that declare outputs), and that remembers the arguments of 'show'.
+#[derive(Clone, Default)]
+struct ArtifactExecutor {
+    shown  : Arc<Mutex<Vec<Value>>>,
+    stdout : Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl VmExecutor for ArtifactExecutor {
+    async fn call(&self, function: FunctionExt, mut arguments: HashMap<String, Value>, _: Option<String>) -> Result<Value, ExecutorError> {
+        if function.name == "show" {
+            self.shown.lock().unwrap().push(arguments.remove("plot").unwrap());
+            return Ok(Value::Unit);
+        }
+
+        let mut artifact = HashMap::new();
+        artifact.insert(String::from("name"), Value::Unicode(String::from("plot.png")));
+        artifact.insert(String::from("size"), Value::Integer(3));
+        artifact.insert(String::from("path"), Value::Unicode(String::from("/data/artifacts/job-1/plot.png")));
+        artifact.insert(String::from("sha256"), Value::Unicode(String::from("ab12")));
+        let mut output = HashMap::new();
+        output.insert(String::from("value"), Value::Unicode(String::from("rendered")));
+        output.insert(String::from("artifacts"), Value::Array{ data_type: String::from("Artifact[]"), entries: vec![ Value::Struct{ data_type: String::from("Artifact"), properties: artifact } ] });
+        Ok(Value::Struct{ data_type: String::from("Output"), properties: output })
+    }
+
+    async fn debug(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stderr(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stdout(&self, text: String) -> Result<(), ExecutorError> {
+        self.stdout.lock().unwrap().push(text);
+        Ok(())
+    }
+
+    async fn wait_until(&self, _: String, _: ServiceState) -> Result<(), ExecutorError> { Ok(()) }
+}
+
+/// The 'plots' package, with render() and show(plot: Artifact).
+fn index() -> PackageIndex {
+    let mut functions = HashMap::new();
+    functions.insert(String::from("render"), Function::new(vec![], None, String::from("Output")));
+    functions.insert(String::from("show"), Function::new(vec![
+        Parameter::new(String::from("plot"), String::from("Artifact"), None, None, None),
+    ], None, String::from("unit")));
+
+    let mut package = PackageInfo::new(String::from("plots"), Version::from_str("1.0.0").unwrap(), PackageKind::Ecu, vec![], String::new(), false, functions, HashMap::new(), vec![]);
+    package.digest = Some(String::from("sha256:plots"));
+    PackageIndex::new(vec![ (String::from("plots-1.0.0"), package) ].into_iter().collect())
+}
+
+#[test]
+fn artifacts_can_be_passed_to_later_calls() {
+    let code = "import plots;\nlet out := render();\nprint(out.value);\nlet artifacts := out.artifacts;\nlet plot := artifacts[0];\nprint(plot.path);\nprint(plot.size);\nshow(plot);\n";
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), index());
+    let function = compiler.compile(code).unwrap();
+
+    let executor = ArtifactExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), Some(index()), None).unwrap();
+    futures::executor::block_on(vm.main(function)).unwrap();
+
+    assert_eq!(*executor.stdout.lock().unwrap(), vec![ "rendered", "/data/artifacts/job-1/plot.png", "3" ]);
+    let shown = executor.shown.lock().unwrap();
+    assert_eq!(shown.len(), 1);
+    assert!(shown[0].conforms_to("Artifact"), "Expected an Artifact, got {:?}", shown[0]);
+    match &shown[0] {
+        Value::Struct{ properties, .. } => assert_eq!(properties["sha256"], Value::Unicode(String::from("ab12"))),
+        value => panic!("Expected an Artifact struct, got {:?}", value),
+    }
+}
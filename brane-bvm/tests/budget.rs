@@ -0,0 +1,74 @@
+use std::thread;
+use std::time::Duration;
+
+use brane_bvm::bytecode::FunctionMut;
+use brane_bvm::executor::NoExtExecutor;
+use brane_bvm::vm::{CancelToken, Vm, VmError, VmOptions};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use specifications::package::PackageIndex;
+
+const INFINITE_LOOP: &str = "let i := 0;\nwhile (true) {\n    i := i + 1;\n}\n";
+
+fn compile(code: &str) -> FunctionMut {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), PackageIndex::empty());
+    compiler.compile(code).unwrap()
+}
+
+/// Creates a fresh VM with the given instruction budget, keeping its globals between statements (like the REPL does).
+fn vm(max_instructions: Option<u64>) -> Vm<NoExtExecutor> {
+    let options = VmOptions {
+        clear_after_main: true,
+        max_instructions,
+        ..Default::default()
+    };
+    Vm::new_with(NoExtExecutor::default(), None, Some(options)).unwrap()
+}
+
+#[test]
+fn budget_stops_infinite_loop() {
+    let mut vm = vm(Some(10_000));
+    match futures::executor::block_on(vm.main(compile(INFINITE_LOOP))) {
+        Err(err) => {
+            assert!(matches!(err.inner(), VmError::InstructionBudgetExceeded{ limit: 10_000 }));
+            assert!(err.line().is_some());
+        },
+        res => panic!("Expected the budget to be exceeded, got {:?}", res),
+    }
+}
+
+#[test]
+fn budget_is_per_statement() {
+    let mut vm = vm(Some(10_000));
+    let statement = "let i := 0;\nwhile (i != 100) {\n    i := i + 1;\n}\n";
+
+    // Each statement fits in the budget, even though together they don't
+    for _ in 0..100 {
+        futures::executor::block_on(vm.main(compile(statement))).unwrap();
+    }
+}
+
+#[test]
+fn cancel_token_stops_infinite_loop() {
+    let mut vm = vm(None);
+    let token = CancelToken::new();
+    vm.set_cancel_token(token.clone());
+
+    let canceller = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        token.cancel();
+    });
+    let res = futures::executor::block_on(vm.main(compile(INFINITE_LOOP)));
+    canceller.join().unwrap();
+    assert!(matches!(res.as_ref().map_err(|err| err.inner()), Err(VmError::Cancelled)), "Expected the run to be cancelled, got {:?}", res);
+}
+
+#[test]
+fn cancelled_token_stops_before_running() {
+    let mut vm = vm(None);
+    let token = CancelToken::new();
+    token.cancel();
+    vm.set_cancel_token(token);
+
+    let res = futures::executor::block_on(vm.main(compile("let a := 1;\n")));
+    assert!(matches!(res.as_ref().map_err(|err| err.inner()), Err(VmError::Cancelled)), "Expected the run to be cancelled, got {:?}", res);
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use brane_bvm::bytecode::{ChunkMut, FunctionMut, Opcode};
+use brane_bvm::executor::{ExecutorError, ServiceState, VmExecutor};
+use brane_bvm::vm::{Vm, VmError};
+use specifications::common::{FunctionExt, Value};
+use specifications::package::PackageKind;
+use specifications::version::Version;
+
+/// An executor for which 'crash' fails and every other function returns 42, and that remembers everything printed to stdout.
+#[derive(Clone, Default)]
+struct CrashExecutor {
+    stdout: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl VmExecutor for CrashExecutor {
+    async fn call(&self, function: FunctionExt, _: HashMap<String, Value>, _: Option<String>) -> Result<Value, ExecutorError> {
+        match function.name.as_str() {
+            "crash" => Err(ExecutorError::ExternalCallFailed{ name: function.name, package: function.package, version: function.version, code: 3, stdout: String::from("partial"), stderr: String::from("boom") }),
+            _       => Ok(Value::Integer(42)),
+        }
+    }
+
+    async fn debug(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stderr(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stdout(&self, text: String) -> Result<(), ExecutorError> {
+        self.stdout.lock().unwrap().push(text);
+        Ok(())
+    }
+
+    async fn wait_until(&self, _: String, _: ServiceState) -> Result<(), ExecutorError> { Ok(()) }
+}
+
+/// Returns an external function with the given name and no parameters.
+fn external(name: &str) -> Value {
+    Value::FunctionExt(FunctionExt {
+        detached     : false,
+        digest       : String::from("sha256:test"),
+        kind         : PackageKind::Ecu,
+        name         : name.to_string(),
+        package      : String::from("test"),
+        parameters   : vec![],
+        version      : Version::from_str("1.0.0").unwrap(),
+        return_type  : Some(String::from("integer")),
+        description  : None,
+        requirements : Default::default(),
+        environment  : Default::default(),
+        env          : Default::default(),
+        pure         : false,
+        concurrency  : None,
+    })
+}
+
+/// Writes a jump-like instruction with a placeholder offset, returning where to patch it.
+fn write_jump(chunk: &mut ChunkMut, opcode: Opcode) -> usize {
+    chunk.write(opcode);
+    let at = chunk.code.len();
+    chunk.write_pair(0x00, 0x00);
+    at
+}
+
+/// Points the jump-like instruction with its offset at the given position to the current end of the chunk.
+fn patch_jump(chunk: &mut ChunkMut, at: usize) {
+    let offset = (chunk.code.len() - at - 2) as u16;
+    chunk.code[at..at + 2].copy_from_slice(&offset.to_be_bytes());
+}
+
+/// Writes `print(<global>)`.
+fn write_print_global(chunk: &mut ChunkMut, print: u8, global: u8) {
+    chunk.write_pair(Opcode::GET_GLOBAL, print);
+    chunk.write_pair(Opcode::GET_GLOBAL, global);
+    chunk.write_pair(Opcode::CALL, 1u8);
+    chunk.write(Opcode::POP);
+}
+
+/// Assembles a main function that does the equivalent of:
+/// ```text
+/// try { result := <function>(); print(result); }
+/// catch (err) { code := err.code; print(code); stderr := err.stderr; print(stderr); }
+/// done := "done"; print(done);
+/// ```
+fn protected(function: &str) -> FunctionMut {
+    let mut chunk = ChunkMut::default();
+    let callee = chunk.add_constant(external(function));
+    let print = chunk.add_constant(String::from("print").into());
+    let result = chunk.add_constant(String::from("result").into());
+    let code = chunk.add_constant(String::from("code").into());
+    let stderr = chunk.add_constant(String::from("stderr").into());
+    let err = chunk.add_constant(String::from("err").into());
+    let done = chunk.add_constant(String::from("done").into());
+
+    // The protected code
+    let handler = write_jump(&mut chunk, Opcode::TRY);
+    chunk.write_pair(Opcode::CONSTANT, callee);
+    chunk.write_pair(Opcode::CALL, 0u8);
+    chunk.write_pair(Opcode::DEFINE_GLOBAL, result);
+    write_print_global(&mut chunk, print, result);
+    chunk.write(Opcode::CATCH_END);
+    let end = write_jump(&mut chunk, Opcode::JUMP);
+
+    // The handler, which starts with the error on top of the stack
+    patch_jump(&mut chunk, handler);
+    chunk.write_pair(Opcode::DEFINE_GLOBAL, err);
+    chunk.write_pair(Opcode::GET_GLOBAL, err);
+    chunk.write_pair(Opcode::GET_PROPERTY, code);
+    chunk.write_pair(Opcode::DEFINE_GLOBAL, code);
+    write_print_global(&mut chunk, print, code);
+    chunk.write_pair(Opcode::GET_GLOBAL, err);
+    chunk.write_pair(Opcode::GET_PROPERTY, stderr);
+    chunk.write_pair(Opcode::DEFINE_GLOBAL, stderr);
+    write_print_global(&mut chunk, print, stderr);
+
+    // Either way, we continue here
+    patch_jump(&mut chunk, end);
+    chunk.write_pair(Opcode::CONSTANT, done);
+    chunk.write_pair(Opcode::DEFINE_GLOBAL, done);
+    write_print_global(&mut chunk, print, done);
+    FunctionMut::main(chunk)
+}
+
+/// Runs the given main function, returning the result and what was printed.
+fn run(function: FunctionMut) -> (Result<(), VmError>, Vec<String>) {
+    let executor = CrashExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), None, None).unwrap();
+    let res = futures::executor::block_on(vm.main(function));
+    let stdout = executor.stdout.lock().unwrap().clone();
+    (res, stdout)
+}
+
+#[test]
+fn failed_call_continues_at_handler() {
+    let (res, stdout) = run(protected("crash"));
+    res.unwrap();
+    assert_eq!(stdout, vec![ "3", "boom", "done" ]);
+}
+
+#[test]
+fn successful_call_skips_handler() {
+    let (res, stdout) = run(protected("answer"));
+    res.unwrap();
+    assert_eq!(stdout, vec![ "42", "done" ]);
+}
+
+#[test]
+fn uncaught_failure_stops_the_vm() {
+    let mut chunk = ChunkMut::default();
+    let callee = chunk.add_constant(external("crash"));
+    chunk.write_pair(Opcode::CONSTANT, callee);
+    chunk.write_pair(Opcode::CALL, 0u8);
+    chunk.write(Opcode::POP);
+
+    let (res, stdout) = run(FunctionMut::main(chunk));
+    assert!(stdout.is_empty());
+    let err = res.unwrap_err();
+    assert!(matches!(err.inner(), VmError::ExternalCallError{ .. }), "Expected an ExternalCallError, got {:?}", err);
+}
+
+#[test]
+fn handler_is_removed_at_catch_end() {
+    // try { } catch { print("caught") }; crash();
+    let mut chunk = ChunkMut::default();
+    let callee = chunk.add_constant(external("crash"));
+    let print = chunk.add_constant(String::from("print").into());
+    let caught = chunk.add_constant(String::from("caught").into());
+
+    let handler = write_jump(&mut chunk, Opcode::TRY);
+    chunk.write(Opcode::CATCH_END);
+    let end = write_jump(&mut chunk, Opcode::JUMP);
+    patch_jump(&mut chunk, handler);
+    chunk.write(Opcode::POP);
+    chunk.write_pair(Opcode::CONSTANT, caught);
+    chunk.write_pair(Opcode::DEFINE_GLOBAL, caught);
+    write_print_global(&mut chunk, print, caught);
+    patch_jump(&mut chunk, end);
+    chunk.write_pair(Opcode::CONSTANT, callee);
+    chunk.write_pair(Opcode::CALL, 0u8);
+    chunk.write(Opcode::POP);
+
+    let (res, stdout) = run(FunctionMut::main(chunk));
+    assert!(stdout.is_empty());
+    let err = res.unwrap_err();
+    assert!(matches!(err.inner(), VmError::ExternalCallError{ .. }), "Expected an ExternalCallError, got {:?}", err);
+}
+
+#[test]
+fn catch_end_without_try_fails() {
+    let mut chunk = ChunkMut::default();
+    chunk.write(Opcode::CATCH_END);
+
+    let (res, _) = run(FunctionMut::main(chunk));
+    let err = res.unwrap_err();
+    assert!(matches!(err.inner(), VmError::IllegalCatchEndError), "Expected an IllegalCatchEndError, got {:?}", err);
+}
+
+#[test]
+fn disassembly_shows_handler_offset() {
+    let mut vm = Vm::new_with(CrashExecutor::default(), None, None).unwrap();
+    let disassembly = vm.disassemble_main(&protected("crash")).unwrap();
+
+    let lines: Vec<&str> = disassembly.lines().collect();
+    assert!(lines[0].starts_with("0000 OP_TRY"), "Unexpected disassembly:\n{}", disassembly);
+    assert!(disassembly.contains("OP_CATCH_END"), "Unexpected disassembly:\n{}", disassembly);
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use brane_bvm::vm::{Vm, VmError};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use common::EchoExecutor;
+use serde_json::json;
+use specifications::common::Value;
+use specifications::package::PackageIndex;
+
+/// Runs the given code with a `person` argument decoded from JSON (as an external function's result would be), where `nickname` is null.
+fn run(code: &str) -> (Result<(), VmError>, Vec<String>) {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), PackageIndex::empty());
+    let function = compiler.compile(code).unwrap();
+
+    let executor = EchoExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), None, None).unwrap();
+    let person = Value::from_json(&json!({ "name": "Alice", "nickname": null, "age": 42 }));
+    vm.set_args(vec![ (String::from("person"), person) ].into_iter().collect()).unwrap();
+
+    let res = futures::executor::block_on(vm.main(function));
+    let stdout = executor.stdout.lock().unwrap().clone();
+    (res, stdout)
+}
+
+fn print(code: &str) -> Vec<String> {
+    let (res, stdout) = run(code);
+    res.unwrap();
+    stdout
+}
+
+#[test]
+fn coalesce_keeps_lhs_unless_unit() {
+    assert_eq!(print("print(unit ?? 1);\nprint(2 ?? 1);\nprint(false ?? true);\nprint(\"\" ?? \"fallback\");"), vec![ "1", "2", "false", "" ]);
+    assert_eq!(print("let x := unit;\nprint(x ?? \"default\");\nx := 3;\nprint(x ?? \"default\");"), vec![ "default", "3" ]);
+}
+
+#[test]
+fn coalesce_chains() {
+    assert_eq!(print("print(unit ?? unit ?? 3);\nprint(unit ?? 2 ?? 3);\nprint(1 ?? unit ?? 3);\nprint(unit ?? unit ?? unit);"), vec![ "3", "2", "1", "unit" ]);
+
+    // Binds weaker than arithmetic, but stronger than comparisons
+    assert_eq!(print("print(1 ?? 2 + 3);\nprint(unit ?? 2 * 3);\nprint(unit ?? 1 == 1);"), vec![ "1", "6", "true" ]);
+}
+
+#[test]
+fn null_fields_of_results_are_unit() {
+    assert_eq!(print("print(args.person.nickname ?? args.person.name);\nprint(args.person.age ?? 0);"), vec![ "Alice", "42" ]);
+    assert_eq!(print("print(is_unit(args.person.nickname));\nprint(is_unit(args.person.name));\nprint(is_unit(unit));"), vec![ "true", "false", "true" ]);
+}
+
+#[test]
+fn dotting_into_unit_suggests_coalescing() {
+    let err = run("let x := args.person.nickname;\nprint(x.length);").0.unwrap_err();
+    assert!(matches!(err.inner(), VmError::IllegalDotError{ target } if target == "unit"), "Expected an IllegalDotError, got {:?}", err);
+    assert!(err.inner().to_string().contains("??"), "Expected a hint to use '??', got '{}'", err.inner());
+
+    // Other types don't get the hint
+    let err = run("let x := 1;\nprint(x.length);").0.unwrap_err();
+    assert!(!err.inner().to_string().contains("??"));
+}
+
+#[test]
+fn fallback_is_only_evaluated_when_needed() {
+    let code = "func fallback() {\n    print(\"evaluated\");\n    return 2;\n}\nprint(1 ?? fallback());\nprint(unit ?? fallback());\nprint(unit ?? unit ?? fallback());";
+    assert_eq!(print(code), vec![ "1", "evaluated", "2", "evaluated", "2" ]);
+}
@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use brane_bvm::executor::{ExecutorError, ServiceState, VmExecutor};
+use specifications::common::{FunctionExt, Value};
+
+/// An executor that remembers everything printed to stdout.
+#[derive(Clone, Default)]
+pub struct EchoExecutor {
+    pub stdout: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl VmExecutor for EchoExecutor {
+    async fn call(&self, _: FunctionExt, _: HashMap<String, Value>, _: Option<String>) -> Result<Value, ExecutorError> {
+        Err(ExecutorError::UnsupportedError{ executor: String::from("EchoExecutor"), operation: String::from("external function calls") })
+    }
+
+    async fn debug(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stderr(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stdout(&self, text: String) -> Result<(), ExecutorError> {
+        self.stdout.lock().unwrap().push(text);
+        Ok(())
+    }
+
+    async fn wait_until(&self, _: String, _: ServiceState) -> Result<(), ExecutorError> { Ok(()) }
+}
@@ -0,0 +1,88 @@
+use std::sync::{Arc, Mutex};
+
+use brane_bvm::bytecode::{FunctionMut, Opcode};
+use brane_bvm::debugger::VmDebugger;
+use brane_bvm::executor::NoExtExecutor;
+use brane_bvm::vm::Vm;
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use specifications::package::PackageIndex;
+
+/// Everything the RecordingDebugger has seen.
+#[derive(Default)]
+struct Recording {
+    instructions: Vec<(Opcode, usize)>,
+    calls: Vec<(String, u8, usize)>,
+    returns: Vec<usize>,
+}
+
+/// Debugger that records all hooks it receives.
+struct RecordingDebugger(Arc<Mutex<Recording>>);
+
+impl VmDebugger for RecordingDebugger {
+    fn on_instruction(&mut self, opcode: Opcode, ip: usize, _stack_len: usize) {
+        self.0.lock().unwrap().instructions.push((opcode, ip));
+    }
+
+    fn on_call(&mut self, name: &str, arity: u8, depth: usize) {
+        self.0.lock().unwrap().calls.push((name.to_string(), arity, depth));
+    }
+
+    fn on_return(&mut self, depth: usize) {
+        self.0.lock().unwrap().returns.push(depth);
+    }
+}
+
+fn compile(code: &str) -> FunctionMut {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), PackageIndex::empty());
+    compiler.compile(code).unwrap()
+}
+
+/// Runs the given code with a RecordingDebugger attached, returning the disassembly of main and what was recorded.
+fn run(code: &str) -> (String, Recording) {
+    let function = compile(code);
+    let recording = Arc::new(Mutex::new(Recording::default()));
+
+    let mut vm = Vm::<NoExtExecutor>::default();
+    let disassembly = vm.disassemble_main(&function).unwrap();
+    vm.set_debugger(Box::new(RecordingDebugger(recording.clone())));
+    futures::executor::block_on(vm.main(function)).unwrap();
+    drop(vm);
+
+    let recording = Arc::try_unwrap(recording).ok().unwrap().into_inner().unwrap();
+    (disassembly, recording)
+}
+
+#[test]
+fn hook_fires_once_per_instruction() {
+    let (disassembly, recording) = run("let a := 1;\nlet b := a + 2;\nlet c := b * a;\n");
+
+    // Without jumps or calls, every instruction of main is executed exactly once, in order
+    let offsets: Vec<usize> = disassembly
+        .lines()
+        .map(|line| line.split_whitespace().next().unwrap().parse().unwrap())
+        .collect();
+    let ips: Vec<usize> = recording.instructions.iter().map(|(_, ip)| *ip).collect();
+    assert_eq!(ips, offsets);
+
+    // Only main itself has been called
+    assert_eq!(recording.calls, vec![(String::from("main"), 0, 1)]);
+    assert!(recording.returns.is_empty());
+}
+
+#[test]
+fn hook_fires_on_call_and_return() {
+    let (_, recording) = run("func add(x) {\n    return x + 1;\n}\nlet y := add(1);\n");
+
+    assert_eq!(recording.calls, vec![(String::from("main"), 0, 1), (String::from("add"), 1, 2)]);
+    assert_eq!(recording.returns, vec![2]);
+    assert_eq!(recording.instructions.iter().filter(|(opcode, _)| matches!(opcode, Opcode::RETURN)).count(), 1);
+}
+
+#[test]
+fn disassemble_main() {
+    let function = compile("let a := 1;\n");
+    let mut vm = Vm::<NoExtExecutor>::default();
+
+    let disassembly = vm.disassemble_main(&function).unwrap();
+    assert!(disassembly.contains("OP_DEFINE_GLOBAL"));
+}
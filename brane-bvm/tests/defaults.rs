@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use brane_bvm::executor::{ExecutorError, ServiceState, VmExecutor};
+use brane_bvm::vm::{Vm, VmError};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use specifications::common::{Function, FunctionExt, Parameter, Value};
+use specifications::package::{PackageIndex, PackageInfo, PackageKind};
+use specifications::version::Version;
+
+/// An executor that remembers the arguments of every external call.
+#[derive(Clone, Default)]
+struct ArgsExecutor {
+    calls: Arc<Mutex<Vec<HashMap<String, Value>>>>,
+}
+
+#[async_trait]
+impl VmExecutor for ArgsExecutor {
+    async fn call(&self, _: FunctionExt, arguments: HashMap<String, Value>, _: Option<String>) -> Result<Value, ExecutorError> {
+        self.calls.lock().unwrap().push(arguments);
+        Ok(Value::Unit)
+    }
+
+    async fn debug(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stderr(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stdout(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn wait_until(&self, _: String, _: ServiceState) -> Result<(), ExecutorError> { Ok(()) }
+}
+
+/// The 'greet' package, with hello(name, greeting = "hello", times = 1, suffix?) and shout(name = "world").
+fn index() -> PackageIndex {
+    let mut functions = HashMap::new();
+    functions.insert(String::from("hello"), Function::new(vec![
+        Parameter::new(String::from("name"), String::from("string"), None, None, None),
+        Parameter::new(String::from("greeting"), String::from("string"), None, Some(Value::Unicode(String::from("hello"))), None),
+        Parameter::new(String::from("times"), String::from("integer"), None, Some(Value::Integer(1)), None),
+        Parameter::new(String::from("suffix"), String::from("string"), Some(true), None, None),
+    ], None, String::from("unit")));
+    functions.insert(String::from("shout"), Function::new(vec![
+        Parameter::new(String::from("name"), String::from("string"), None, Some(Value::Unicode(String::from("world"))), None),
+    ], None, String::from("unit")));
+
+    let mut package = PackageInfo::new(String::from("greet"), Version::from_str("1.0.0").unwrap(), PackageKind::Ecu, vec![], String::new(), false, functions, HashMap::new(), vec![]);
+    package.digest = Some(String::from("sha256:greet"));
+    PackageIndex::new(vec![ (String::from("greet-1.0.0"), package) ].into_iter().collect())
+}
+
+/// Runs the given code, returning the result and the arguments of every external call it made.
+fn run(code: &str) -> (Result<(), VmError>, Vec<HashMap<String, Value>>) {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), index());
+    let function = compiler.compile(code).unwrap();
+
+    let executor = ArgsExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), Some(index()), None).unwrap();
+    let res = futures::executor::block_on(vm.main(function));
+    let calls = executor.calls.lock().unwrap().clone();
+    (res, calls)
+}
+
+fn string(value: &Value) -> &str {
+    value.as_string().unwrap()
+}
+
+#[test]
+fn all_defaults() {
+    let (res, calls) = run("import greet;\nshout();\n");
+    res.unwrap();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(string(&calls[0]["name"]), "world");
+}
+
+#[test]
+fn partial_arguments() {
+    let (res, calls) = run("import greet;\nhello(\"alice\");\nhello(\"bob\", \"hi\");\nhello(\"carol\", \"hey\", 3, \"!\");\n");
+    res.unwrap();
+    assert_eq!(calls.len(), 3);
+
+    // Only the name given
+    assert_eq!(string(&calls[0]["name"]), "alice");
+    assert_eq!(string(&calls[0]["greeting"]), "hello");
+    assert!(matches!(calls[0]["times"], Value::Integer(1)));
+    assert!(matches!(calls[0]["suffix"], Value::Unit));
+
+    // Some defaults overridden
+    assert_eq!(string(&calls[1]["greeting"]), "hi");
+    assert!(matches!(calls[1]["times"], Value::Integer(1)));
+
+    // Nothing left to fill in
+    assert!(matches!(calls[2]["times"], Value::Integer(3)));
+    assert_eq!(string(&calls[2]["suffix"]), "!");
+}
+
+#[test]
+fn missing_required() {
+    let (res, calls) = run("import greet;\nhello();\n");
+    match res.as_ref().map_err(|err| err.inner()) {
+        Err(VmError::MissingArgumentsError{ name, missing }) => {
+            assert_eq!(name, "hello");
+            assert_eq!(missing, &vec![ String::from("name") ]);
+        },
+        res => panic!("Expected missing arguments, got {:?}", res),
+    }
+    assert!(calls.is_empty());
+}
+
+#[test]
+fn over_supplied() {
+    let (res, calls) = run("import greet;\nshout(\"alice\", \"bob\");\n");
+    match res.as_ref().map_err(|err| err.inner()) {
+        Err(err @ VmError::TooManyArgumentsError{ .. }) => {
+            assert!(matches!(err, VmError::TooManyArgumentsError{ got: 2, expected: 1, .. }));
+            assert_eq!(format!("{}", err), "Function 'shout' takes at most 1 arguments, but got 2");
+        },
+        res => panic!("Expected too many arguments, got {:?}", res),
+    }
+    assert!(calls.is_empty());
+}
+
+#[test]
+fn local_functions_check_arity() {
+    let (res, calls) = run("import greet;\nfunc greet_all(name, greeting) {\n    hello(name, greeting);\n}\ngreet_all(\"alice\");\n");
+    match res.as_ref().map_err(|err| err.inner()) {
+        Err(err @ VmError::FunctionArityError{ .. }) => {
+            assert!(matches!(err, VmError::FunctionArityError{ got: 1, expected: 2, .. }));
+            assert_eq!(format!("{}", err), "Function 'greet_all' expects 2 arguments, but got 1");
+        },
+        res => panic!("Expected an arity error, got {:?}", res),
+    }
+    assert!(calls.is_empty());
+
+    // Too many is just as wrong
+    let (res, _) = run("import greet;\nfunc greet_all(name) {\n    hello(name);\n}\ngreet_all(\"alice\", \"bob\");\n");
+    assert!(matches!(res.as_ref().map_err(|err| err.inner()), Err(VmError::FunctionArityError{ got: 2, expected: 1, .. })));
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use brane_bvm::diff::StateDiff;
+use brane_bvm::vm::{Vm, VmOptions};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use common::EchoExecutor;
+use specifications::package::PackageIndex;
+
+/// Runs the given statements one after the other in the same VM (as the REPL does), returning what each of them changed.
+fn diffs(statements: &[&str]) -> Vec<StateDiff> {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), PackageIndex::empty());
+    let options = VmOptions{ clear_after_main: true, ..Default::default() };
+    let mut vm = Vm::new_with(EchoExecutor::default(), None, Some(options)).unwrap();
+
+    statements.iter().map(|statement| {
+        let before = vm.capture_state();
+        futures::executor::block_on(vm.main(compiler.compile(*statement).unwrap())).unwrap();
+        before.diff(&vm.capture_state())
+    }).collect()
+}
+
+#[test]
+fn statements_report_what_they_changed() {
+    let diffs = diffs(&[ "let x := 1;", "let results := [1.0, 2.0, 3.0];\nfunc double(n) { return n * 2; }", "x := x + 1;", "print(x);" ]);
+
+    assert_eq!(format!("{}", diffs[0]), "defined x: integer");
+    // Functions (and builtins) are no variables
+    let added: Vec<&str> = diffs[1].added.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(added, vec![ "results" ]);
+    assert_eq!(diffs[1].added[0].1.value, "3");
+    assert_eq!(format!("{}", diffs[2]), "changed x: integer (1 → 2)");
+    assert!(diffs[3].is_empty());
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use brane_bvm::executor::{ExecutorError, ServiceState, VmExecutor};
+use brane_bvm::trace::{TraceEntry, TraceOutcome};
+use brane_bvm::vm::{Vm, VmError, VmOptions};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use specifications::common::{Function, FunctionExt, Parameter, Property, Type, Value};
+use specifications::package::{PackageIndex, PackageInfo, PackageKind};
+use specifications::version::Version;
+
+/// An executor that remembers which functions it was asked to call and which it was asked to simulate, where simulating them on 'nowhere' fails.
+#[derive(Clone, Default)]
+struct DryExecutor {
+    calls     : Arc<Mutex<Vec<String>>>,
+    dry_calls : Arc<Mutex<Vec<(String, Option<String>)>>>,
+}
+
+#[async_trait]
+impl VmExecutor for DryExecutor {
+    async fn call(&self, function: FunctionExt, _: HashMap<String, Value>, _: Option<String>) -> Result<Value, ExecutorError> {
+        self.calls.lock().unwrap().push(function.name);
+        Ok(Value::Unit)
+    }
+
+    async fn dry_call(&self, function: FunctionExt, _: HashMap<String, Value>, location: Option<String>, default: Value) -> Result<Value, ExecutorError> {
+        self.dry_calls.lock().unwrap().push((function.name.clone(), location.clone()));
+        match location.as_deref() {
+            Some("nowhere") => Err(ExecutorError::UnknownLocationError{ correlation_id: function.name, location: String::from("nowhere"), err: String::from("not in the infrastructure file") }),
+            _               => Ok(default),
+        }
+    }
+
+    async fn debug(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stderr(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stdout(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn wait_until(&self, _: String, _: ServiceState) -> Result<(), ExecutorError> { Ok(()) }
+}
+
+/// The 'pipeline' package, with load(path) -> Dataset, count(data: Dataset) -> integer, ratio(n: integer) -> real, labels() -> string[], plot() -> Output (as for functions with outputs) and finish() -> Mystery (which is not a known type).
+fn index() -> PackageIndex {
+    let mut functions = HashMap::new();
+    functions.insert(String::from("load"), Function::new(vec![ Parameter::new(String::from("path"), String::from("string"), None, None, None) ], None, String::from("Dataset")));
+    functions.insert(String::from("count"), Function::new(vec![ Parameter::new(String::from("data"), String::from("Dataset"), None, None, None) ], None, String::from("integer")));
+    functions.insert(String::from("ratio"), Function::new(vec![ Parameter::new(String::from("n"), String::from("integer"), None, None, None) ], None, String::from("real")));
+    functions.insert(String::from("labels"), Function::new(vec![], None, String::from("string[]")));
+    functions.insert(String::from("plot"), Function::new(vec![], None, String::from("Output")));
+    functions.insert(String::from("finish"), Function::new(vec![], None, String::from("Mystery")));
+
+    let mut types = HashMap::new();
+    types.insert(String::from("Dataset"), Type::new(String::from("Dataset"), vec![ Property::new_quick("name", "string"), Property::new_quick("rows", "integer"), Property::new_quick("valid", "boolean") ]));
+
+    let mut package = PackageInfo::new(String::from("pipeline"), Version::from_str("1.0.0").unwrap(), PackageKind::Ecu, vec![], String::new(), false, functions, types, vec![]);
+    package.digest = Some(String::from("sha256:pipeline"));
+    PackageIndex::new(vec![ (String::from("pipeline-1.0.0"), package) ].into_iter().collect())
+}
+
+/// Runs the given code in dry-run mode, returning the result, the value of a top-level return, the executor and the trace.
+fn dry_run(code: &str) -> (Result<(), VmError>, Option<Value>, DryExecutor, Vec<TraceEntry>) {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), index());
+    let function = compiler.compile(code).unwrap();
+
+    let executor = DryExecutor::default();
+    let options = VmOptions{ global_return_halts: true, trace: true, dry_run: true, ..Default::default() };
+    let mut vm = Vm::new_with(executor.clone(), Some(index()), Some(options)).unwrap();
+    let res = futures::executor::block_on(vm.main(function));
+    let value = vm.take_main_result();
+    (res, value, executor, vm.take_trace())
+}
+
+#[test]
+fn nothing_is_called_in_a_dry_run() {
+    let code = "import pipeline;\nlet data := load(\"input.csv\");\nlet n := count(data);\nlet r := ratio(n);\nlet names := labels();\nfinish();\nreturn r;\n";
+    let (res, value, executor, trace) = dry_run(code);
+    assert!(res.is_ok(), "Dry run failed: {:?}", res);
+    assert!(executor.calls.lock().unwrap().is_empty(), "Called {:?} in a dry run", executor.calls.lock().unwrap());
+    let simulated: Vec<String> = executor.dry_calls.lock().unwrap().iter().map(|(name, _)| name.clone()).collect();
+    assert_eq!(simulated, vec![ "load", "count", "ratio", "labels", "finish" ]);
+    assert!(matches!(value, Some(Value::Real(r)) if r == 0.0));
+
+    // Every call shows up in the trace, marked as simulated
+    assert_eq!(trace.len(), 5);
+    assert!(trace.iter().all(|entry| entry.simulated && entry.outcome == TraceOutcome::Success));
+}
+
+#[test]
+fn defaults_follow_the_return_type() {
+    let (_, value, _, _) = dry_run("import pipeline;\nreturn load(\"input.csv\");\n");
+    match value {
+        Some(Value::Struct{ data_type, properties }) => {
+            assert_eq!(data_type, "Dataset");
+            assert!(matches!(&properties["name"], Value::Unicode(name) if name.is_empty()));
+            assert!(matches!(properties["rows"], Value::Integer(0)));
+            assert!(matches!(properties["valid"], Value::Boolean(false)));
+        },
+        value => panic!("Expected a Dataset, got {:?}", value),
+    }
+
+    let (_, value, _, _) = dry_run("import pipeline;\nreturn labels();\n");
+    assert!(matches!(value, Some(Value::Array{ entries, .. }) if entries.is_empty()));
+    let (_, value, _, _) = dry_run("import pipeline;\nreturn count(load(\"input.csv\"));\n");
+    assert!(matches!(value, Some(Value::Integer(0))));
+    let (_, value, _, _) = dry_run("import pipeline;\nreturn plot();\n");
+    match value {
+        Some(Value::Struct{ data_type, properties }) => {
+            assert_eq!(data_type, "Output");
+            assert_eq!(properties["value"], Value::Unit);
+            assert!(matches!(&properties["artifacts"], Value::Array{ data_type, entries } if data_type == "Artifact[]" && entries.is_empty()));
+        },
+        value => panic!("Expected an Output, got {:?}", value),
+    }
+    // Unknown types become a unit
+    let (res, value, _, _) = dry_run("import pipeline;\nreturn finish();\n");
+    assert!(res.is_ok());
+    assert!(matches!(value, None | Some(Value::Unit)));
+}
+
+#[test]
+fn arguments_and_locations_are_still_checked() {
+    let (res, _, executor, _) = dry_run("import pipeline;\ncount(42);\n");
+    assert!(matches!(res.as_ref().map_err(|err| err.inner()), Err(VmError::ArgumentTypeError{ parameter, .. }) if parameter == "data"), "Expected an argument type error, got {:?}", res);
+    assert!(executor.dry_calls.lock().unwrap().is_empty());
+
+    let (res, _, executor, trace) = dry_run("import pipeline;\non \"site1\" {\n    labels();\n}\non \"nowhere\" {\n    labels();\n}\n");
+    assert!(res.is_err());
+    assert_eq!(*executor.dry_calls.lock().unwrap(), vec![ (String::from("labels"), Some(String::from("site1"))), (String::from("labels"), Some(String::from("nowhere"))) ]);
+    assert!(executor.calls.lock().unwrap().is_empty());
+    assert!(matches!(&trace[1].outcome, TraceOutcome::Failure{ error } if error.contains("nowhere")));
+}
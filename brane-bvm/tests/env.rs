@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use brane_bvm::builtins::{BuiltinError, BuiltinFunction};
+use brane_bvm::executor::{ExecutorError, ServiceState, VmExecutor};
+use brane_bvm::vm::{Vm, VmError};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use specifications::common::{Function, FunctionExt, Value};
+use specifications::errors::EnvironmentError;
+use specifications::package::{EnvironmentVariable, PackageIndex, PackageInfo, PackageKind};
+use specifications::version::Version;
+
+/// An executor that remembers the package environment of every external call.
+#[derive(Clone, Default)]
+struct EnvExecutor {
+    calls: Arc<Mutex<Vec<HashMap<String, String>>>>,
+}
+
+#[async_trait]
+impl VmExecutor for EnvExecutor {
+    async fn call(&self, function: FunctionExt, _: HashMap<String, Value>, _: Option<String>) -> Result<Value, ExecutorError> {
+        self.calls.lock().unwrap().push(function.env);
+        Ok(Value::Unit)
+    }
+
+    async fn debug(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stderr(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stdout(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn wait_until(&self, _: String, _: ServiceState) -> Result<(), ExecutorError> { Ok(()) }
+}
+
+/// The 'model' package, with train() and a configurable THREADS, a configurable LOG_LEVEL (defaults to 'info') and a fixed MODE.
+fn index() -> PackageIndex {
+    let mut functions = HashMap::new();
+    functions.insert(String::from("train"), Function::new(vec![], None, String::from("unit")));
+
+    let mut package = PackageInfo::new(String::from("model"), Version::from_str("1.0.0").unwrap(), PackageKind::Ecu, vec![], String::new(), false, functions, HashMap::new(), vec![]);
+    package.digest = Some(String::from("sha256:model"));
+    package.environment.insert(String::from("THREADS"), EnvironmentVariable::Declared{ default: None, configurable: true });
+    package.environment.insert(String::from("LOG_LEVEL"), EnvironmentVariable::Declared{ default: Some(String::from("info")), configurable: true });
+    package.environment.insert(String::from("MODE"), EnvironmentVariable::Fixed(String::from("batch")));
+    PackageIndex::new(vec![ (String::from("model-1.0.0"), package) ].into_iter().collect())
+}
+
+/// Runs the given code, returning the result and the package environment of every external call it made.
+fn run(code: &str) -> (Result<(), VmError>, Vec<HashMap<String, String>>) {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), index());
+    let function = compiler.compile(code).unwrap();
+
+    let executor = EnvExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), Some(index()), None).unwrap();
+    let res = futures::executor::block_on(vm.main(function));
+    let calls = executor.calls.lock().unwrap().clone();
+    (res, calls)
+}
+
+/// Returns the EnvironmentError that running the given code fails with.
+fn env_error(code: &str) -> EnvironmentError {
+    let (res, calls) = run(code);
+    assert!(calls.is_empty());
+    match res.as_ref().map_err(|err| err.inner()) {
+        Err(VmError::BuiltinCallError{ builtin: BuiltinFunction::WithEnv, err: BuiltinError::EnvironmentError{ err, .. } }) => err.clone(),
+        res => panic!("Expected an environment error, got {:?}", res),
+    }
+}
+
+#[test]
+fn sets_configurable_variables() {
+    let (res, calls) = run("import model;\nlet env := map();\nenv[\"THREADS\"] := 8;\nlet configured := with_env(train, env);\nconfigured();\ntrain();\n");
+    res.unwrap();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0], vec![ (String::from("THREADS"), String::from("8")) ].into_iter().collect());
+    // The original function is left alone
+    assert!(calls[1].is_empty());
+}
+
+#[test]
+fn later_calls_override_earlier_ones() {
+    let (res, calls) = run("import model;\nlet a := map();\na[\"THREADS\"] := 2;\na[\"LOG_LEVEL\"] := \"debug\";\nlet b := map();\nb[\"THREADS\"] := 4;\nlet configured := with_env(with_env(train, a), b);\nconfigured();\n");
+    res.unwrap();
+    assert_eq!(calls[0]["THREADS"], "4");
+    assert_eq!(calls[0]["LOG_LEVEL"], "debug");
+}
+
+#[test]
+fn rejects_undeclared_variables() {
+    let err = env_error("import model;\nlet env := map();\nenv[\"THREDS\"] := 8;\nlet configured := with_env(train, env);\nconfigured();\n");
+    assert_eq!(err, EnvironmentError::Undeclared{ package: String::from("model"), name: String::from("THREDS"), configurable: vec![ String::from("LOG_LEVEL"), String::from("THREADS") ] });
+    assert_eq!(format!("{}", err), "Package 'model' does not declare environment variable 'THREDS' (its configurable variables are 'LOG_LEVEL', 'THREADS')");
+}
+
+#[test]
+fn rejects_fixed_and_reserved_variables() {
+    let err = env_error("import model;\nlet env := map();\nenv[\"MODE\"] := \"stream\";\nlet configured := with_env(train, env);\nconfigured();\n");
+    assert_eq!(err, EnvironmentError::NotConfigurable{ package: String::from("model"), name: String::from("MODE") });
+
+    let err = env_error("import model;\nlet env := map();\nenv[\"BRANE_CALLBACK_TO\"] := \"elsewhere\";\nlet configured := with_env(train, env);\nconfigured();\n");
+    assert_eq!(err, EnvironmentError::Reserved{ name: String::from("BRANE_CALLBACK_TO") });
+}
+
+#[test]
+fn rejects_illegal_values() {
+    let (res, calls) = run("import model;\nlet env := map();\nenv[\"THREADS\"] := [1, 2];\nlet configured := with_env(train, env);\nconfigured();\n");
+    assert!(calls.is_empty());
+    assert!(matches!(res.as_ref().map_err(|err| err.inner()), Err(VmError::BuiltinCallError{ err: BuiltinError::IllegalArgumentError{ builtin: BuiltinFunction::WithEnv, .. }, .. })), "Unexpected result: {:?}", res);
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use brane_bvm::vm::{Vm, VmOptions};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use common::EchoExecutor;
+use specifications::package::PackageIndex;
+
+const RECORD: &str = "let m := map();\nm[\"points\"] := [1, 2, 3];\nm[\"name\"] := \"line\";\n";
+
+/// Runs the given code and returns what it printed, optionally printing like a REPL does.
+fn print(code: &str, compact_print: bool) -> Vec<String> {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), PackageIndex::empty());
+    let function = compiler.compile(code).unwrap();
+
+    let executor = EchoExecutor::default();
+    let options = VmOptions{ compact_print, ..Default::default() };
+    let mut vm = Vm::new_with(executor.clone(), None, Some(options)).unwrap();
+    futures::executor::block_on(vm.main(function)).unwrap();
+    let stdout = executor.stdout.lock().unwrap().clone();
+    stdout
+}
+
+/// Returns an array literal with the integers from 0 up to n.
+fn numbers(n: usize) -> String {
+    format!("[{}]", (0..n).map(|i| i.to_string()).collect::<Vec<String>>().join(", "))
+}
+
+#[test]
+fn print_indents_nested_values() {
+    let expected = "{\n  \"name\": \"line\",\n  \"points\": [1, 2, 3]\n}";
+    assert_eq!(print(&format!("{}print(m);", RECORD), false), vec![ expected ]);
+}
+
+#[test]
+fn format_returns_what_print_shows() {
+    let code = format!("{}let text := format(m);\nprint(text);\nprint(m);\nprint(format(\"raw\"));\nprint(format(42));", RECORD);
+    let stdout = print(&code, false);
+    assert_eq!(stdout[0], stdout[1]);
+    assert_eq!(&stdout[2..], &[ "raw", "42" ]);
+}
+
+#[test]
+fn compact_print_keeps_small_values_on_one_line() {
+    assert_eq!(print(&format!("{}print(m);", RECORD), true), vec![ "{\"name\": \"line\", \"points\": [1, 2, 3]}" ]);
+
+    // Values that don't fit are still indented
+    let stdout = print(&format!("print({});", numbers(40)), true);
+    assert_eq!(stdout[0].lines().count(), 42);
+}
+
+#[test]
+fn huge_arrays_are_cut_off() {
+    let stdout = print(&format!("print({});", numbers(150)), false);
+    let lines: Vec<&str> = stdout[0].lines().collect();
+    assert_eq!(lines.len(), 103);
+    assert_eq!(lines[100], "  99,");
+    assert_eq!(lines[101], "  ... (50 more)");
+}
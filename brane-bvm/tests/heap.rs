@@ -0,0 +1,43 @@
+use brane_bvm::bytecode::FunctionMut;
+use brane_bvm::executor::NoExtExecutor;
+use brane_bvm::vm::{Vm, VmError, VmOptions};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use specifications::package::PackageIndex;
+
+/// Compiles a script that keeps `n` freshly allocated strings alive, in arrays of at most 200 elements (the most an array literal may have is 255).
+fn compile(n: usize) -> FunctionMut {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), PackageIndex::empty());
+
+    let mut code = String::from("let s := \"x\";\n");
+    for (i, start) in (0..n).step_by(200).enumerate() {
+        let elements = vec!["s + s"; (n - start).min(200)].join(", ");
+        code.push_str(&format!("let xs{} := [{}];\n", i, elements));
+    }
+    compiler.compile(code).unwrap()
+}
+
+/// Runs the given function on a fresh VM with the given maximum heap size, and returns the VM so its heap can be inspected.
+fn run(function: FunctionMut, max_heap_slots: usize) -> Result<Vm<NoExtExecutor>, VmError> {
+    let options = VmOptions {
+        max_heap_slots,
+        ..Default::default()
+    };
+    let mut vm = Vm::new_with(NoExtExecutor::default(), None, Some(options))?;
+    futures::executor::block_on(vm.main(function))?;
+    Ok(vm)
+}
+
+#[test]
+fn heap_grows_beyond_initial_size() {
+    // The heap starts with 512 slots, which is not enough to keep 1000 strings alive
+    let vm = run(compile(1000), VmOptions::default().max_heap_slots).unwrap();
+    assert!(vm.heap_capacity() > 512, "Heap did not grow: it has {} slots", vm.heap_capacity());
+}
+
+#[test]
+fn heap_max_size_is_respected() {
+    match run(compile(200), 64).map(|_| ()) {
+        Err(err) if matches!(err.inner(), VmError::HeapAllocError{ .. }) => {},
+        res                                                              => { panic!("Expected a HeapAllocError, got {:?}", res); }
+    }
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use brane_bvm::vm::{Vm, VmError};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use common::EchoExecutor;
+use specifications::common::Function;
+use specifications::container::ContainerInfo;
+use specifications::package::{PackageIndex, PackageInfo};
+
+/// A container.yml with descriptions on a function, its parameters and a type.
+const CONTAINER: &str = r#"
+name: greeter
+version: 1.0.0
+kind: ecu
+
+entrypoint:
+  kind: task
+  exec: run.sh
+
+actions:
+  'greet':
+    description: |
+      Greets someone.
+      Politely, too.
+    input:
+      - type: string
+        name: name
+        description: Who to greet.
+      - type: integer
+        name: times
+    output:
+      - type: Greeting
+        name: greeting
+
+types:
+  'Greeting':
+    name: Greeting
+    description: What greet() returns.
+    properties:
+      - type: string
+        name: text
+        description: The greeting itself.
+"#;
+
+/// Builds the package as `brane build` would, then reads it back as the package index and the registry do.
+fn index() -> PackageIndex {
+    let container = ContainerInfo::from_string(CONTAINER.to_string()).unwrap();
+    let mut package = PackageInfo::from(&container);
+    package.digest = Some(String::from("sha256:greeter"));
+
+    // The package.yml in the local package directory
+    let mut yaml = Vec::new();
+    package.to_writer(&mut yaml).unwrap();
+    let package = PackageInfo::from_string(String::from_utf8(yaml).unwrap()).unwrap();
+
+    // The functions as the registry stores them
+    let functions = serde_json::to_string(&package.functions).unwrap();
+    let functions: std::collections::HashMap<String, Function> = serde_json::from_str(&functions).unwrap();
+    assert_eq!(functions["greet"].description, package.functions["greet"].description);
+
+    PackageIndex::from_packages(vec![ package ]).unwrap()
+}
+
+fn run(code: &str) -> (Result<(), VmError>, Vec<String>) {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), index());
+    let function = compiler.compile(code).unwrap();
+
+    let executor = EchoExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), Some(index()), None).unwrap();
+    let res = futures::executor::block_on(vm.main(function));
+    let stdout = executor.stdout.lock().unwrap().clone();
+    (res, stdout)
+}
+
+#[test]
+fn descriptions_survive_building_and_indexing() {
+    let index = index();
+    let package = index.get("greeter", None).unwrap();
+    let greet = &package.functions["greet"];
+    assert_eq!(greet.description.as_deref(), Some("Greets someone.\nPolitely, too.\n"));
+    assert_eq!(greet.parameters[0].description.as_deref(), Some("Who to greet."));
+    assert_eq!(greet.parameters[1].description, None);
+
+    let greeting = &package.types["Greeting"];
+    assert_eq!(greeting.description.as_deref(), Some("What greet() returns."));
+    assert_eq!(greeting.properties[0].description.as_deref(), Some("The greeting itself."));
+}
+
+#[test]
+fn help_shows_signature_and_description() {
+    let (res, stdout) = run("import greeter;\nprint(help(greet));");
+    res.unwrap();
+    assert_eq!(stdout, vec![ "greet(name: string, times: integer) -> Greeting (from greeter 1.0.0)\n  Greets someone.\n  Politely, too.\n\n  Parameters:\n    name: Who to greet." ]);
+
+    // The same text is what the REPL's ':doc' shows
+    let mut vm = Vm::new_with(EchoExecutor::default(), Some(index()), None).unwrap();
+    let function = Compiler::new(CompilerOptions::new(Lang::BraneScript), index()).compile("import greeter;").unwrap();
+    futures::executor::block_on(vm.main(function)).unwrap();
+    let state = vm.capture_state();
+    assert_eq!(state.doc("greet").as_deref(), Some(stdout[0].as_str()));
+    assert_eq!(state.doc("print").as_deref(), Some("print(value: any) (builtin)"));
+    assert_eq!(state.doc("nope"), None);
+}
+
+#[test]
+fn help_of_non_functions_fails() {
+    let (res, stdout) = run("print(help(42));");
+    assert!(stdout.is_empty());
+    let err = res.unwrap_err();
+    assert!(err.inner().to_string().contains("expected a function"), "Unexpected error: {}", err.inner());
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use brane_bvm::executor::{ExecutorError, ServiceState, VmExecutor};
+use brane_bvm::vm::{Vm, VmError, VmOptions};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use specifications::common::{Function, FunctionExt, Parameter, Type, Value};
+use specifications::package::{PackageIndex, PackageInfo, PackageKind};
+use specifications::version::Version;
+
+/// An executor that remembers which external functions were called (as version and parameter names).
+#[derive(Clone, Default)]
+struct CallExecutor {
+    calls: Arc<Mutex<Vec<(String, Vec<String>)>>>,
+}
+
+#[async_trait]
+impl VmExecutor for CallExecutor {
+    async fn call(&self, function: FunctionExt, _: HashMap<String, Value>, _: Option<String>) -> Result<Value, ExecutorError> {
+        let parameters = function.parameters.iter().map(|p| p.name.clone()).collect();
+        self.calls.lock().unwrap().push((function.version.to_string(), parameters));
+        Ok(Value::Unit)
+    }
+
+    async fn debug(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stderr(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stdout(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn wait_until(&self, _: String, _: ServiceState) -> Result<(), ExecutorError> { Ok(()) }
+}
+
+/// Version 1.0.0 of 'greet' has hello(name); version 2.0.0 has hello(name, greeting) and a Greeting type.
+fn greet(version: &str) -> PackageInfo {
+    let mut parameters = vec![ Parameter::new(String::from("name"), String::from("string"), None, None, None) ];
+    let mut types = HashMap::new();
+    if version != "1.0.0" {
+        parameters.push(Parameter::new(String::from("greeting"), String::from("string"), None, None, None));
+        types.insert(String::from("Greeting"), Type{ name: String::from("Greeting"), properties: vec![], description: None });
+    }
+    let mut functions = HashMap::new();
+    functions.insert(String::from("hello"), Function::new(parameters, None, String::from("unit")));
+
+    let mut package = PackageInfo::new(String::from("greet"), Version::from_str(version).unwrap(), PackageKind::Ecu, vec![], String::new(), false, functions, types, vec![]);
+    package.digest = Some(format!("sha256:{}", version));
+    package
+}
+
+fn index(versions: &[&str]) -> PackageIndex {
+    PackageIndex::new(versions.iter().map(|v| (format!("greet-{}", v), greet(v))).collect())
+}
+
+struct Session {
+    compiler : Compiler,
+    executor : CallExecutor,
+    vm       : Vm<CallExecutor>,
+}
+
+impl Session {
+    fn new(index: PackageIndex) -> Self {
+        let executor = CallExecutor::default();
+        let options = VmOptions{ clear_after_main: true, ..Default::default() };
+        let vm = Vm::new_with(executor.clone(), Some(index.clone()), Some(options)).unwrap();
+        Self{ compiler: Compiler::new(CompilerOptions::new(Lang::BraneScript), index), executor, vm }
+    }
+
+    fn run(&mut self, code: &str) -> Result<(), VmError> {
+        let function = self.compiler.compile(code).unwrap();
+        futures::executor::block_on(self.vm.main(function))
+    }
+
+    fn last_call(&self) -> (String, Vec<String>) {
+        self.executor.calls.lock().unwrap().last().cloned().expect("No external function was called")
+    }
+}
+
+#[test]
+fn import_unimport_reimport() {
+    let mut session = Session::new(index(&["1.0.0"]));
+    session.run("import greet; hello(\"world\");").unwrap();
+    assert_eq!(session.last_call(), (String::from("1.0.0"), vec![String::from("name")]));
+
+    assert_eq!(session.vm.drop_package("greet").unwrap(), vec![String::from("hello")]);
+    assert!(matches!(session.run("hello(\"world\");").unwrap_err().inner(), VmError::UndefinedGlobalError{ .. }));
+    assert!(matches!(session.vm.drop_package("greet"), Err(VmError::PackageNotImported{ .. })));
+
+    // A newer version with another signature has been pulled in the meantime
+    session.vm.set_package_index(index(&["1.0.0", "2.0.0"]));
+    session.run("import greet; hello(\"world\", \"hi\");").unwrap();
+    assert_eq!(session.last_call(), (String::from("2.0.0"), vec![String::from("name"), String::from("greeting")]));
+    let mut dropped = session.vm.drop_package("greet").unwrap();
+    dropped.sort();
+    assert_eq!(dropped, vec![String::from("Greeting"), String::from("hello")]);
+}
+
+#[test]
+fn reimport_replaces_other_version() {
+    let mut session = Session::new(index(&["1.0.0"]));
+    session.run("import greet;").unwrap();
+    // The same version again is fine
+    session.run("import greet;").unwrap();
+
+    session.vm.set_package_index(index(&["1.0.0", "2.0.0"]));
+    session.run("import greet; hello(\"world\", \"hi\");").unwrap();
+    assert_eq!(session.last_call().0, "2.0.0");
+}
+
+#[test]
+fn user_globals_still_conflict() {
+    let mut session = Session::new(index(&["1.0.0"]));
+    session.run("let hello := 42;").unwrap();
+    match session.run("import greet;").unwrap_err().inner() {
+        VmError::DuplicateFunctionImport{ package, function } => {
+            assert_eq!(package, "greet");
+            assert_eq!(function, "hello");
+        },
+        err => panic!("Expected a DuplicateFunctionImport, got {:?}", err),
+    }
+
+    // Nor may a new version claim a global that the old one didn't own
+    let mut session = Session::new(index(&["1.0.0"]));
+    session.run("import greet; let Greeting := 1;").unwrap();
+    session.vm.set_package_index(index(&["1.0.0", "2.0.0"]));
+    assert!(matches!(session.run("import greet;").unwrap_err().inner(), VmError::DuplicateTypeImport{ .. }));
+    // ...and the old version is still there
+    session.run("hello(\"world\");").unwrap();
+    assert_eq!(session.last_call().0, "1.0.0");
+}
+
+#[test]
+fn imports_survive_state_capture() {
+    let mut session = Session::new(index(&["1.0.0"]));
+    session.run("import greet;").unwrap();
+
+    // Restore the VM from its state as the driver does between two requests of a session
+    session.vm = Vm::new_with_state(session.executor.clone(), Some(index(&["1.0.0"])), session.vm.capture_state()).unwrap();
+    assert_eq!(session.vm.drop_package("greet").unwrap(), vec![String::from("hello")]);
+}
+
+#[test]
+fn state_lists_globals() {
+    let mut session = Session::new(index(&["1.0.0"]));
+    session.run("import greet; let answer := 42; let name := \"world\"; func twice(x) { return x * 2; }").unwrap();
+
+    let state = session.vm.capture_state();
+    assert_eq!(state.variables(), vec![(String::from("answer"), String::from("integer")), (String::from("name"), String::from("string"))]);
+    assert_eq!(state.functions(), vec![
+        (String::from("hello"), String::from("hello(name: string) from greet 1.0.0")),
+        (String::from("twice"), String::from("twice(1 argument(s))")),
+    ]);
+    assert_eq!(state.packages(), vec![(String::from("greet"), Version::from_str("1.0.0").unwrap())]);
+}
+
+#[test]
+fn state_survives_serialization() {
+    let mut session = Session::new(index(&["1.0.0"]));
+    session.run("import greet; let answer := 42; func twice(x) { return x * 2; }").unwrap();
+
+    let json = serde_json::to_string(&session.vm.capture_state()).unwrap();
+    session.vm = Vm::new_with_state(session.executor.clone(), Some(index(&["1.0.0"])), serde_json::from_str(&json).unwrap()).unwrap();
+    session.run("hello(\"world\"); let result := twice(answer);").unwrap();
+    assert_eq!(session.last_call().0, "1.0.0");
+    assert_eq!(session.vm.capture_state().variables()[1], (String::from("result"), String::from("integer")));
+}
+
+#[test]
+fn imports_resolve_version_constraints() {
+    let mut session = Session::new(index(&["1.0.0", "1.2.0", "2.0.0"]));
+    session.run("import greet[1.0.0]; hello(\"world\");").unwrap();
+    assert_eq!(session.last_call(), (String::from("1.0.0"), vec![String::from("name")]));
+
+    session.run("import greet[\"^1.0\"]; hello(\"world\", \"hi\");").unwrap();
+    assert_eq!(session.last_call().0, "1.2.0");
+
+    session.run("import greet[\">=1.0.0, <2\"]; hello(\"world\", \"hi\");").unwrap();
+    assert_eq!(session.last_call().0, "1.2.0");
+
+    // Without a constraint, the latest version is imported
+    session.run("import greet; hello(\"world\", \"hi\");").unwrap();
+    assert_eq!(session.last_call().0, "2.0.0");
+}
+
+#[test]
+fn unsatisfied_constraints_list_available_versions() {
+    let mut session = Session::new(index(&["1.0.0", "1.2.0", "2.0.0"]));
+    for code in [ "import greet[\">=3\"];", "import greet[1.1.0];" ] {
+        let err = session.run(code).unwrap_err();
+        match err.inner() {
+            VmError::UnsatisfiedImportError{ package, available, .. } => {
+                assert_eq!(package, "greet");
+                assert_eq!(available, &vec![String::from("1.0.0"), String::from("1.2.0"), String::from("2.0.0")]);
+            },
+            err => panic!("Expected an UnsatisfiedImportError, got {:?}", err),
+        }
+    }
+    let err = session.run("import greet[\">=3\"];").unwrap_err();
+    assert_eq!(err.inner().to_string(), "No version of package 'greet' satisfies '>=3' (available: 1.0.0, 1.2.0, 2.0.0); make sure it is pulled");
+
+    // Unknown packages are reported as before
+    assert!(matches!(session.run("import shout;").unwrap_err().inner(), VmError::UndefinedImportError{ .. }));
+}
+
+#[test]
+fn invalid_constraints_are_rejected() {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), index(&["1.0.0"]));
+    for code in [ "import greet[\">=a\"];", "import greet[\"not a version\"];", "import greet[hello];" ] {
+        let err = compiler.compile(code).unwrap_err().to_string();
+        assert!(err.contains("version constraint"), "Unexpected error for '{}': {}", code, err);
+    }
+}
+
+#[test]
+fn index_knows_versions_by_name() {
+    let index = index(&["2.0.0", "1.0.0", "1.2.0"]);
+    assert_eq!(index.names(), vec![ "greet" ]);
+    assert_eq!(index.versions("greet").into_iter().map(|version| version.to_string()).collect::<Vec<String>>(), vec![ "1.0.0", "1.2.0", "2.0.0" ]);
+    assert!(index.versions("shout").is_empty());
+    assert_eq!(index.get("greet", None).unwrap().version.to_string(), "2.0.0");
+}
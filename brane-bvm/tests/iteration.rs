@@ -0,0 +1,137 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use brane_bvm::bytecode::{ChunkMut, FunctionMut, Opcode};
+use brane_bvm::debugger::VmDebugger;
+use brane_bvm::vm::{Vm, VmError};
+use common::EchoExecutor;
+use specifications::common::Value;
+
+/// Debugger that only counts the instructions executed.
+struct CountingDebugger(Arc<Mutex<usize>>);
+
+impl VmDebugger for CountingDebugger {
+    fn on_instruction(&mut self, _opcode: Opcode, _ip: usize, _stack_len: usize) {
+        *self.0.lock().unwrap() += 1;
+    }
+}
+
+/// Assembles a main function that prints every element of `args.values`, using OP_ITER and OP_ITER_NEXT.
+fn print_each() -> FunctionMut {
+    let mut chunk = ChunkMut::default();
+    let args = chunk.add_constant(String::from("args").into());
+    let values = chunk.add_constant(String::from("values").into());
+    let print = chunk.add_constant(String::from("print").into());
+
+    // The iterator state becomes locals 0 and 1
+    chunk.write_pair(Opcode::GET_GLOBAL, args);
+    chunk.write_pair(Opcode::GET_PROPERTY, values);
+    chunk.write(Opcode::ITER);
+
+    let loop_start = chunk.code.len();
+    chunk.write_pair(Opcode::ITER_NEXT, 0u8);
+    chunk.write(Opcode::JUMP_IF_FALSE);
+    let exit = chunk.code.len();
+    chunk.write_pair(0x00, 0x00);
+    chunk.write(Opcode::POP);
+
+    // The element is local 2
+    chunk.write_pair(Opcode::GET_GLOBAL, print);
+    chunk.write_pair(Opcode::GET_LOCAL, 2u8);
+    chunk.write_pair(Opcode::CALL, 1u8);
+    chunk.write(Opcode::POP);
+    chunk.write(Opcode::POP);
+
+    chunk.write(Opcode::JUMP_BACK);
+    let jump_back = (chunk.code.len() - loop_start + 2) as u16;
+    chunk.write_bytes(&jump_back.to_be_bytes()[..]);
+    let jump = (chunk.code.len() - exit - 2) as u16;
+    chunk.code[exit..exit + 2].copy_from_slice(&jump.to_be_bytes());
+
+    // Drop the exhausted marker and the iterator state
+    chunk.write(Opcode::POP);
+    chunk.write_pair(Opcode::POP_N, 2u8);
+    FunctionMut::main(chunk)
+}
+
+/// Runs print_each() over the given value, returning the result, what was printed and the number of instructions executed.
+fn run(values: Value) -> (Result<(), VmError>, Vec<String>, usize) {
+    let executor = EchoExecutor::default();
+    let count = Arc::new(Mutex::new(0));
+    let mut vm = Vm::new_with(executor.clone(), None, None).unwrap();
+    vm.set_args(vec![ (String::from("values"), values) ].into_iter().collect()).unwrap();
+    vm.set_debugger(Box::new(CountingDebugger(count.clone())));
+
+    let res = futures::executor::block_on(vm.main(print_each()));
+    let stdout = executor.stdout.lock().unwrap().clone();
+    let count = *count.lock().unwrap();
+    (res, stdout, count)
+}
+
+fn integers(n: i64) -> Value {
+    Value::Array{ data_type: String::from("integer[]"), entries: (0..n).map(Value::Integer).collect() }
+}
+
+#[test]
+fn iterates_array_in_order() {
+    let (res, stdout, _) = run(integers(3));
+    res.unwrap();
+    assert_eq!(stdout, vec![ "0", "1", "2" ]);
+
+    let (res, stdout, _) = run(Value::Array{ data_type: String::from("string[]"), entries: vec![ Value::Unicode(String::from("a")), Value::Unicode(String::from("b")) ] });
+    res.unwrap();
+    assert_eq!(stdout, vec![ "a", "b" ]);
+}
+
+#[test]
+fn iterates_map_keys_in_order() {
+    let mut map = HashMap::new();
+    map.insert(String::from("y"), Value::Integer(2));
+    map.insert(String::from("x"), Value::Integer(1));
+    map.insert(String::from("z"), Value::Integer(3));
+
+    let (res, stdout, _) = run(Value::Map(map));
+    res.unwrap();
+    assert_eq!(stdout, vec![ "x", "y", "z" ]);
+}
+
+#[test]
+fn empty_collections_skip_the_body() {
+    let (res, stdout, empty) = run(integers(0));
+    res.unwrap();
+    assert!(stdout.is_empty());
+
+    let (res, stdout, _) = run(Value::Map(HashMap::new()));
+    res.unwrap();
+    assert!(stdout.is_empty());
+
+    // Every element costs exactly one round of the loop
+    let (_, _, one) = run(integers(1));
+    let (_, _, ten) = run(integers(10));
+    assert_eq!(ten - one, 9 * (one - empty));
+}
+
+#[test]
+fn iterating_non_collections_fails() {
+    let (res, stdout, _) = run(Value::Integer(3));
+    assert!(stdout.is_empty());
+    let err = res.unwrap_err();
+    assert!(matches!(err.inner(), VmError::IllegalIterError{ .. }), "Expected an IllegalIterError, got {:?}", err);
+
+    let (res, _, _) = run(Value::Unicode(String::from("abc")));
+    let err = res.unwrap_err();
+    assert!(matches!(err.inner(), VmError::IllegalIterError{ .. }), "Expected an IllegalIterError, got {:?}", err);
+}
+
+#[test]
+fn disassembly_shows_iterator_offset() {
+    let mut vm = Vm::new_with(EchoExecutor::default(), None, None).unwrap();
+    let disassembly = vm.disassemble_main(&print_each()).unwrap();
+
+    let lines: Vec<&str> = disassembly.lines().collect();
+    assert!(lines[2].ends_with("OP_ITER"), "Unexpected disassembly:\n{}", disassembly);
+    assert!(lines[3].contains("OP_ITER_NEXT") && lines[3].contains("   0 |"), "Unexpected disassembly:\n{}", disassembly);
+    assert!(lines[4].starts_with("0007 OP_JUMP_IF_FALSE"), "Unexpected disassembly:\n{}", disassembly);
+}
@@ -0,0 +1,55 @@
+use brane_bvm::bytecode::FunctionMut;
+use brane_bvm::executor::NoExtExecutor;
+use brane_bvm::vm::{Vm, VmError};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use specifications::package::PackageIndex;
+
+fn compile(code: &str) -> FunctionMut {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), PackageIndex::empty());
+    compiler.compile(code).unwrap()
+}
+
+fn run(code: &str) -> Result<(), VmError> {
+    let mut vm = Vm::<NoExtExecutor>::default();
+    futures::executor::block_on(vm.main(compile(code)))
+}
+
+#[test]
+fn line_table_has_an_entry_per_instruction() {
+    let function = compile("let a := 1;\n\nlet b := a + 2;\n");
+
+    // 'let a = 1;' is a CONSTANT + DEFINE_GLOBAL, so the first line ends at offset 4
+    assert_eq!(function.chunk.line(0), Some(1));
+    assert_eq!(function.chunk.line(2), Some(1));
+    assert_eq!(function.chunk.line(4), Some(3));
+    // Offsets in the middle of an instruction have no line
+    assert_eq!(function.chunk.line(1), None);
+}
+
+#[test]
+fn type_error_reports_line() {
+    let err = run("let a := 1;\nlet b := 2;\nlet c := a + unit;\nlet d := c;\n").unwrap_err();
+
+    assert_eq!(err.line(), Some(3));
+    assert!(matches!(err.inner(), VmError::NotAddable{ .. }));
+    assert!(format!("{}", err).starts_with("line 3: Cannot add value of type"));
+}
+
+#[test]
+fn type_error_in_function_reports_line_in_function() {
+    let err = run("func broken(x) {\n    let y := x;\n    return -y;\n}\n\nbroken(\"text\");\n").unwrap_err();
+
+    assert_eq!(err.line(), Some(3));
+    assert!(matches!(err.inner(), VmError::NotNegatable{ .. }));
+}
+
+#[test]
+fn line_survives_spec_function_roundtrip() {
+    use specifications::common::SpecFunction;
+
+    let function = compile("let a := 1;\nlet b := -a;\n");
+    let lines = function.chunk.lines.clone();
+    let function: FunctionMut = SpecFunction::from(function).into();
+    assert_eq!(function.chunk.lines, lines);
+    assert_eq!(function.chunk.line(4), Some(2));
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use brane_bvm::executor::{ExecutorError, ServiceState, VmExecutor};
+use brane_bvm::vm::{Vm, VmError};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use specifications::common::{Function, FunctionExt, Parameter, Value};
+use specifications::package::{PackageIndex, PackageInfo, PackageKind};
+use specifications::version::Version;
+
+/// A future that is pending the first time it is polled, so that other calls get the chance to start.
+struct YieldOnce(bool);
+
+impl Future for YieldOnce {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 { return Poll::Ready(()); }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// An executor whose 'square' fails for 2, and that keeps track of how many calls were in flight at the same time.
+#[derive(Clone, Default)]
+struct SquareExecutor {
+    running : Arc<Mutex<(usize, usize)>>,
+    stdout  : Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl VmExecutor for SquareExecutor {
+    async fn call(&self, function: FunctionExt, arguments: HashMap<String, Value>, _: Option<String>) -> Result<Value, ExecutorError> {
+        {
+            let mut running = self.running.lock().unwrap();
+            running.0 += 1;
+            running.1 = running.1.max(running.0);
+        }
+        YieldOnce(false).await;
+        self.running.lock().unwrap().0 -= 1;
+
+        match arguments["n"] {
+            Value::Integer(2) => Err(ExecutorError::ExternalCallFailed{ name: function.name, package: function.package, version: function.version, code: 3, stdout: String::new(), stderr: String::from("two is not allowed") }),
+            Value::Integer(n) => Ok(Value::Integer(n * n)),
+            ref value         => panic!("Expected an integer, got {:?}", value),
+        }
+    }
+
+    async fn debug(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stderr(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stdout(&self, text: String) -> Result<(), ExecutorError> {
+        self.stdout.lock().unwrap().push(text);
+        Ok(())
+    }
+
+    async fn wait_until(&self, _: String, _: ServiceState) -> Result<(), ExecutorError> { Ok(()) }
+}
+
+/// The 'maths' package, with square(n: integer), which hints that at most two calls should run at the same time.
+fn index() -> PackageIndex {
+    let mut square = Function::new(vec![
+        Parameter::new(String::from("n"), String::from("integer"), None, None, None),
+    ], None, String::from("integer"));
+    square.concurrency = Some(2);
+    let mut functions = HashMap::new();
+    functions.insert(String::from("square"), square);
+
+    let mut package = PackageInfo::new(String::from("maths"), Version::from_str("1.0.0").unwrap(), PackageKind::Ecu, vec![], String::new(), false, functions, HashMap::new(), vec![]);
+    package.digest = Some(String::from("sha256:maths"));
+    PackageIndex::new(vec![ (String::from("maths-1.0.0"), package) ].into_iter().collect())
+}
+
+/// Runs the given code after defining maps 'a' to 'e' with 'n' set to 1 to 5, returning the result and the executor.
+fn run(code: &str) -> (Result<(), VmError>, SquareExecutor) {
+    let mut prelude = String::from("import maths;\n");
+    for (i, name) in [ "a", "b", "c", "d", "e" ].iter().enumerate() {
+        prelude.push_str(&format!("let {} := map();\n{}[\"n\"] := {};\n", name, name, i + 1));
+    }
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), index());
+    let function = compiler.compile(&format!("{}{}", prelude, code)).unwrap();
+
+    let executor = SquareExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), Some(index()), None).unwrap();
+    let res = futures::executor::block_on(vm.main(function));
+    (res, executor)
+}
+
+#[test]
+fn results_keep_the_order_of_the_inputs() {
+    let (res, executor) = run("let results := map_call(square, [e, c, a, d]);\nprint(results);\n");
+    res.unwrap();
+    assert_eq!(*executor.stdout.lock().unwrap(), vec![ "[25, 9, 1, 16]" ]);
+}
+
+#[test]
+fn failed_calls_become_errors() {
+    let (res, executor) = run("let results := map_call(square, [a, b, c]);\nprint(results[0]);\nlet failed := results[1];\nprint(failed.code);\nprint(failed.stderr);\nprint(results[2]);\n");
+    res.unwrap();
+    assert_eq!(*executor.stdout.lock().unwrap(), vec![ "1", "3", "two is not allowed", "9" ]);
+}
+
+#[test]
+fn calls_respect_the_concurrency_hint() {
+    let (res, executor) = run("map_call(square, [a, c, d, e, a, c]);\n");
+    res.unwrap();
+    let (running, max) = *executor.running.lock().unwrap();
+    assert_eq!(running, 0);
+    assert_eq!(max, 2);
+}
+
+#[test]
+fn inputs_are_checked_before_anything_runs() {
+    let (res, executor) = run("let f := map();\nf[\"m\"] := 1;\nmap_call(square, [a, f]);\n");
+    match res.unwrap_err().inner() {
+        VmError::MissingArgumentsError{ name, missing } => { assert_eq!(name, "square (input 1)"); assert_eq!(missing, &vec![ String::from("n") ]); },
+        err => panic!("Expected a MissingArgumentsError, got {:?}", err),
+    }
+    assert_eq!(executor.running.lock().unwrap().1, 0);
+
+    let (res, _) = run("a[\"m\"] := 1;\nmap_call(square, [a]);\n");
+    match res.unwrap_err().inner() {
+        VmError::UnknownArgumentError{ name, argument } => { assert_eq!(name, "square (input 0)"); assert_eq!(argument, "m"); },
+        err => panic!("Expected an UnknownArgumentError, got {:?}", err),
+    }
+
+    let (res, _) = run("map_call(square, [1, 2]);\n");
+    assert!(matches!(res.unwrap_err().inner(), VmError::BuiltinCallError{ .. }));
+}
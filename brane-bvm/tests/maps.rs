@@ -0,0 +1,80 @@
+mod common;
+
+use brane_bvm::vm::{Vm, VmError};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use common::EchoExecutor;
+use specifications::common::Value;
+use specifications::package::PackageIndex;
+
+fn run(code: &str) -> (Result<(), VmError>, Vec<String>) {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), PackageIndex::empty());
+    let function = compiler.compile(code).unwrap();
+
+    let executor = EchoExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), None, None).unwrap();
+    let res = futures::executor::block_on(vm.main(function));
+    let stdout = executor.stdout.lock().unwrap().clone();
+    (res, stdout)
+}
+
+fn print(code: &str) -> Vec<String> {
+    let (res, stdout) = run(code);
+    res.unwrap();
+    stdout
+}
+
+#[test]
+fn indexed_assignment_and_lookup() {
+    let code = "let m := map();\nm[\"a\"] := 1;\nm[\"b\"] := \"two\";\nm[\"a\"] := m[\"a\"] + 41;\nprint(m[\"a\"]);\nprint(m[\"b\"]);\nprint(m);";
+    assert_eq!(print(code), vec!["42", "two", "{\"a\": 42, \"b\": \"two\"}"]);
+}
+
+#[test]
+fn keys_values_and_has() {
+    let code = "let m := map();\nm[\"y\"] := 2;\nm[\"x\"] := 1;\nprint(keys(m));\nprint(values(m));\nprint(has(m, \"x\"));\nprint(has(m, \"z\"));\nprint(keys(map()));";
+    assert_eq!(print(code), vec!["[\"x\", \"y\"]", "[1, 2]", "true", "false", "[]"]);
+}
+
+#[test]
+fn nested_maps() {
+    let code = "let outer := map();\nlet inner := map();\ninner[\"x\"] := 1;\nouter[\"inner\"] := inner;\ninner[\"x\"] := 2;\nprint(outer[\"inner\"][\"x\"]);\nprint(inner[\"x\"]);\nprint(outer);";
+    // Maps are values: changing `inner` afterwards does not change the copy in `outer`
+    assert_eq!(print(code), vec!["1", "2", "{\n  \"inner\": {\"x\": 1}\n}"]);
+}
+
+#[test]
+fn maps_in_function_locals() {
+    let code = "func count(n) {\n  let m := map();\n  m[\"n\"] := n;\n  return m[\"n\"] * 2;\n}\nprint(count(21));";
+    assert_eq!(print(code), vec!["42"]);
+}
+
+#[test]
+fn missing_keys_and_illegal_indices() {
+    let err = run("let m := map();\nprint(m[\"nope\"]);").0.unwrap_err();
+    assert!(matches!(err.inner(), VmError::MissingKeyError{ key } if key == "nope"), "Expected a MissingKeyError, got {:?}", err);
+
+    let err = run("let m := map();\nm[1] := 2;").0.unwrap_err();
+    assert!(matches!(err.inner(), VmError::IllegalKeyError{ .. }), "Expected an IllegalKeyError, got {:?}", err);
+
+    let err = run("print(has(1, \"a\"));").0.unwrap_err();
+    assert!(matches!(err.inner(), VmError::ArgumentTypeError{ function, parameter, .. } if function == "has" && parameter == "map"), "Expected an ArgumentTypeError, got {:?}", err);
+}
+
+#[test]
+fn map_arguments_are_converted_to_heap_maps() {
+    let mut inner = std::collections::HashMap::new();
+    inner.insert(String::from("x"), Value::Integer(1));
+    let mut outer = std::collections::HashMap::new();
+    outer.insert(String::from("inner"), Value::Map(inner));
+
+    let mut args = std::collections::HashMap::new();
+    args.insert(String::from("config"), Value::Map(outer));
+
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), PackageIndex::empty());
+    let function = compiler.compile("print(args.config[\"inner\"][\"x\"]);\nprint(args.config);").unwrap();
+    let executor = EchoExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), None, None).unwrap();
+    vm.set_args(args).unwrap();
+    futures::executor::block_on(vm.main(function)).unwrap();
+    assert_eq!(executor.stdout.lock().unwrap().clone(), vec!["1", "{\n  \"inner\": {\"x\": 1}\n}"]);
+}
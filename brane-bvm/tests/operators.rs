@@ -0,0 +1,188 @@
+mod common;
+
+use brane_bvm::bytecode::{ChunkMut, FunctionMut, Opcode};
+use brane_bvm::vm::{Vm, VmError};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use common::EchoExecutor;
+use specifications::common::Value;
+use specifications::package::PackageIndex;
+
+/// Assembles a main function that does the equivalent of `result := <lhs> <opcode> <rhs>; print(result);`.
+fn binary(lhs: Value, opcode: Opcode, rhs: Value) -> FunctionMut {
+    let mut chunk = ChunkMut::default();
+    let lhs = chunk.add_constant(lhs);
+    let rhs = chunk.add_constant(rhs);
+    let print = chunk.add_constant(String::from("print").into());
+    let result = chunk.add_constant(String::from("result").into());
+
+    chunk.write_pair(Opcode::CONSTANT, lhs);
+    chunk.write_pair(Opcode::CONSTANT, rhs);
+    chunk.write(opcode);
+    chunk.write_pair(Opcode::DEFINE_GLOBAL, result);
+    chunk.write_pair(Opcode::GET_GLOBAL, print);
+    chunk.write_pair(Opcode::GET_GLOBAL, result);
+    chunk.write_pair(Opcode::CALL, 1u8);
+    chunk.write(Opcode::POP);
+    FunctionMut::main(chunk)
+}
+
+/// Runs the given main function, returning what it printed.
+fn run(function: FunctionMut) -> Result<String, VmError> {
+    let executor = EchoExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), None, None).unwrap();
+    futures::executor::block_on(vm.main(function))?;
+    let stdout = executor.stdout.lock().unwrap().clone();
+    Ok(stdout.join("\n"))
+}
+
+fn eval(lhs: Value, opcode: Opcode, rhs: Value) -> String {
+    run(binary(lhs, opcode, rhs)).unwrap()
+}
+
+fn eval_err(lhs: Value, opcode: Opcode, rhs: Value) -> VmError {
+    run(binary(lhs, opcode, rhs)).unwrap_err()
+}
+
+/// Returns one value of every kind that isn't a number.
+fn non_numeric() -> Vec<Value> {
+    vec![ Value::Boolean(true), Value::Unicode(String::from("1")), Value::Unit ]
+}
+
+fn compile(code: &str) -> FunctionMut {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), PackageIndex::empty());
+    compiler.compile(code).unwrap()
+}
+
+#[test]
+fn comparisons_promote_like_greater_and_less() {
+    // Every combination of integers and reals, on either side of the boundary
+    let cases: Vec<(Value, Value, [ &str; 4 ])> = vec![
+        // (lhs, rhs, [ >, >=, <, <= ])
+        (Value::Integer(1),   Value::Integer(2),   [ "false", "false", "true",  "true"  ]),
+        (Value::Integer(2),   Value::Integer(2),   [ "false", "true",  "false", "true"  ]),
+        (Value::Integer(3),   Value::Integer(2),   [ "true",  "true",  "false", "false" ]),
+        (Value::Integer(2),   Value::Real(2.5),    [ "false", "false", "true",  "true"  ]),
+        (Value::Integer(2),   Value::Real(2.0),    [ "false", "true",  "false", "true"  ]),
+        (Value::Integer(3),   Value::Real(2.5),    [ "true",  "true",  "false", "false" ]),
+        (Value::Real(1.5),    Value::Integer(2),   [ "false", "false", "true",  "true"  ]),
+        (Value::Real(2.0),    Value::Integer(2),   [ "false", "true",  "false", "true"  ]),
+        (Value::Real(2.5),    Value::Integer(2),   [ "true",  "true",  "false", "false" ]),
+        (Value::Real(-0.5),   Value::Real(0.5),    [ "false", "false", "true",  "true"  ]),
+        (Value::Real(0.5),    Value::Real(0.5),    [ "false", "true",  "false", "true"  ]),
+        (Value::Real(0.5),    Value::Real(-0.5),   [ "true",  "true",  "false", "false" ]),
+        // NaN is not ordered, not even with itself
+        (Value::Real(f64::NAN), Value::Real(f64::NAN), [ "false", "false", "false", "false" ]),
+        (Value::Integer(1),   Value::Real(f64::NAN), [ "false", "false", "false", "false" ]),
+    ];
+    for (lhs, rhs, expected) in cases {
+        for (opcode, expected) in [ Opcode::GREATER, Opcode::GREATER_EQUAL, Opcode::LESS, Opcode::LESS_EQUAL ].iter().copied().zip(expected) {
+            assert_eq!(eval(lhs.clone(), opcode, rhs.clone()), expected, "{:?} {} {:?}", lhs, opcode, rhs);
+        }
+    }
+}
+
+#[test]
+fn comparisons_reject_non_numeric_values() {
+    for opcode in [ Opcode::GREATER, Opcode::GREATER_EQUAL, Opcode::LESS, Opcode::LESS_EQUAL ] {
+        for value in non_numeric() {
+            for (lhs, rhs) in [ (value.clone(), Value::Integer(1)), (Value::Real(1.0), value.clone()), (value.clone(), value.clone()) ] {
+                let err = eval_err(lhs.clone(), opcode, rhs.clone());
+                assert!(matches!(err.inner(), VmError::NotComparable{ .. }), "Expected {:?} {} {:?} to be incomparable, got {:?}", lhs, opcode, rhs, err);
+            }
+        }
+    }
+
+    // The error names the operands in the order they were written
+    match eval_err(Value::Unicode(String::from("a")), Opcode::GREATER_EQUAL, Value::Integer(1)).inner() {
+        VmError::NotComparable{ lhs, rhs } => { assert_eq!(lhs, "String"); assert_eq!(rhs, "Integer"); },
+        err => panic!("Expected a NotComparable, got {:?}", err),
+    }
+}
+
+#[test]
+fn not_equal_is_the_opposite_of_equal() {
+    let values = vec![
+        Value::Integer(2), Value::Integer(3), Value::Real(2.0), Value::Real(2.5),
+        Value::Boolean(true), Value::Boolean(false), Value::Unicode(String::from("2")), Value::Unit,
+    ];
+    for lhs in &values {
+        for rhs in &values {
+            let equal = eval(lhs.clone(), Opcode::EQUAL, rhs.clone());
+            let not_equal = eval(lhs.clone(), Opcode::NOT_EQUAL, rhs.clone());
+            assert_ne!(equal, not_equal, "{:?} == {:?} is {} but {:?} != {:?} is {}", lhs, rhs, equal, lhs, rhs, not_equal);
+            // Strings are compared by identity, so only check the rest by value
+            if !matches!(lhs, Value::Unicode(_)) { assert_eq!(not_equal, if lhs == rhs { "false" } else { "true" }, "{:?} != {:?}", lhs, rhs); }
+        }
+    }
+}
+
+#[test]
+fn bitwise_operators_work_on_integers() {
+    let cases: Vec<(i64, i64, [ i64; 3 ])> = vec![
+        // (lhs, rhs, [ &, |, ^ ])
+        (0b1100, 0b1010, [ 0b1000, 0b1110, 0b0110 ]),
+        (0, 0, [ 0, 0, 0 ]),
+        (-1, 0x0F, [ 0x0F, -1, !0x0F ]),
+        (i64::MIN, i64::MAX, [ 0, -1, -1 ]),
+    ];
+    for (lhs, rhs, expected) in cases {
+        for (opcode, expected) in [ Opcode::BIT_AND, Opcode::BIT_OR, Opcode::BIT_XOR ].iter().copied().zip(expected) {
+            assert_eq!(eval(Value::Integer(lhs), opcode, Value::Integer(rhs)), expected.to_string(), "{} {} {}", lhs, opcode, rhs);
+        }
+    }
+
+    assert_eq!(eval(Value::Integer(1), Opcode::SHL, Value::Integer(4)), "16");
+    assert_eq!(eval(Value::Integer(5), Opcode::SHL, Value::Integer(0)), "5");
+    assert_eq!(eval(Value::Integer(1), Opcode::SHL, Value::Integer(63)), i64::MIN.to_string());
+    assert_eq!(eval(Value::Integer(256), Opcode::SHR, Value::Integer(4)), "16");
+    // Shifting right keeps the sign
+    assert_eq!(eval(Value::Integer(-16), Opcode::SHR, Value::Integer(2)), "-4");
+    assert_eq!(eval(Value::Integer(-1), Opcode::SHR, Value::Integer(63)), "-1");
+}
+
+#[test]
+fn bitwise_operators_reject_everything_but_integers() {
+    let mut others = non_numeric();
+    others.push(Value::Real(1.0));
+    for opcode in [ Opcode::BIT_AND, Opcode::BIT_OR, Opcode::BIT_XOR, Opcode::SHL, Opcode::SHR ] {
+        for value in &others {
+            for (lhs, rhs) in [ (value.clone(), Value::Integer(1)), (Value::Integer(1), value.clone()), (value.clone(), value.clone()) ] {
+                match eval_err(lhs.clone(), opcode, rhs.clone()).inner() {
+                    VmError::NotBitwise{ lhs: lhs_type, rhs: rhs_type } => {
+                        assert_eq!(lhs_type == "Integer", matches!(lhs, Value::Integer(_)));
+                        assert_eq!(rhs_type == "Integer", matches!(rhs, Value::Integer(_)));
+                    },
+                    err => panic!("Expected {:?} {} {:?} to be rejected, got {:?}", lhs, opcode, rhs, err),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn shifts_reject_illegal_amounts() {
+    for opcode in [ Opcode::SHL, Opcode::SHR ] {
+        for amount in [ -1, 64, i64::MAX, i64::MIN ] {
+            let err = eval_err(Value::Integer(1), opcode, Value::Integer(amount));
+            assert!(matches!(err.inner(), VmError::IllegalShift{ amount: a } if *a == amount), "Expected shifting by {} to be rejected, got {:?}", amount, err);
+        }
+    }
+    assert!(format!("{}", VmError::IllegalShift{ amount: 64 }).contains("between 0 and 63"));
+}
+
+#[test]
+fn compiler_emits_single_instructions() {
+    let mut vm = Vm::<EchoExecutor>::default();
+    for (operator, opcode) in [ (">=", "OP_GREATER_EQUAL"), ("<=", "OP_LESS_EQUAL"), ("!=", "OP_NOT_EQUAL") ] {
+        let disassembly = vm.disassemble_main(&compile(&format!("let a := 1 {} 2;\n", operator))).unwrap();
+        assert!(disassembly.contains(opcode), "Expected {} in:\n{}", opcode, disassembly);
+        assert!(!disassembly.contains("OP_NOT\n") && !disassembly.contains("OP_NOT "), "Expected no OP_NOT in:\n{}", disassembly);
+    }
+
+    // And they still mean what they say
+    let executor = EchoExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), None, None).unwrap();
+    let code = "print(1 < 2);\nprint(2 > 1);\nprint(2 >= 2);\nprint(3 <= 2);\nprint(1 != 1.0);\nprint(2 != 2);\n";
+    futures::executor::block_on(vm.main(compile(code))).unwrap();
+    assert_eq!(*executor.stdout.lock().unwrap(), vec![ "true", "true", "true", "false", "true", "false" ]);
+}
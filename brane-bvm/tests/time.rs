@@ -0,0 +1,78 @@
+mod common;
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use brane_bvm::builtins::{BuiltinError, BuiltinFunction};
+use brane_bvm::bytecode::FunctionMut;
+use brane_bvm::vm::{CancelToken, Vm, VmError, VmOptions};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use common::EchoExecutor;
+use specifications::package::PackageIndex;
+
+fn compile(code: &str) -> FunctionMut {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), PackageIndex::empty());
+    compiler.compile(code).unwrap()
+}
+
+async fn run(code: &str, cancel: Option<CancelToken>) -> (Result<(), VmError>, Vec<String>) {
+    let executor = EchoExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), None, None).unwrap();
+    if let Some(cancel) = cancel { vm.set_cancel_token(cancel); }
+    let res = vm.main(compile(code)).await;
+    let stdout = executor.stdout.lock().unwrap().clone();
+    (res, stdout)
+}
+
+#[tokio::test]
+async fn now_returns_epoch_seconds() {
+    let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    let (res, stdout) = run("print(now());", None).await;
+    res.unwrap();
+    let after = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+
+    let now: i64 = stdout[0].parse().unwrap();
+    assert!(before <= now && now <= after, "{} is not between {} and {}", now, before, after);
+}
+
+#[tokio::test]
+async fn sleep_waits_and_elapsed_measures_it() {
+    let start = Instant::now();
+    let (res, stdout) = run("let start := now();\nsleep(0.2);\nsleep(0);\nprint(elapsed(start));", None).await;
+    res.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    let passed: f64 = stdout[0].parse().unwrap();
+    assert!((0.2..30.0).contains(&passed), "elapsed() returned {}", passed);
+}
+
+#[tokio::test]
+async fn illegal_arguments_are_rejected() {
+    for (code, builtin) in [ ("sleep(-1);", BuiltinFunction::Sleep), ("sleep(\"soon\");", BuiltinFunction::Sleep), ("elapsed(true);", BuiltinFunction::Elapsed) ] {
+        let err = run(code, None).await.0.unwrap_err();
+        assert!(matches!(err.inner(), VmError::BuiltinCallError{ builtin: b, .. } if *b == builtin), "Expected a BuiltinCallError for '{}', got {:?}", code, err);
+    }
+    for code in [ "sleep(-0.5);", "sleep(9223372036854775807);" ] {
+        let err = run(code, None).await.0.unwrap_err();
+        assert!(matches!(err.inner(), VmError::BuiltinCallError{ err: BuiltinError::IllegalDurationError{ .. }, .. }), "Unexpected error for '{}': {:?}", code, err);
+    }
+    let err = run("now(1);", None).await.0.unwrap_err();
+    assert!(matches!(err.inner(), VmError::BuiltinCallError{ err: BuiltinError::TooManyArgumentsError{ expected: 0, got: 1, .. }, .. }), "Unexpected error: {:?}", err);
+}
+
+#[tokio::test]
+async fn cancelling_interrupts_a_long_sleep() {
+    let token = CancelToken::new();
+    let canceller = {
+        let token = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            token.cancel();
+        })
+    };
+
+    let start = Instant::now();
+    let (res, stdout) = run("print(\"before\");\nsleep(3600);\nprint(\"after\");", Some(token)).await;
+    canceller.await.unwrap();
+    assert!(matches!(res.as_ref().map_err(|err| err.inner()), Err(VmError::Cancelled)), "Expected the run to be cancelled, got {:?}", res);
+    assert!(start.elapsed() < Duration::from_secs(2), "Cancelling took {:?}", start.elapsed());
+    assert_eq!(stdout, vec![ "before" ]);
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use brane_bvm::executor::{ExecutorError, ServiceState, VmExecutor};
+use brane_bvm::trace::{TraceEntry, TraceOutcome};
+use brane_bvm::vm::{Vm, VmOptions};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use specifications::common::{Function, FunctionExt, Parameter, Value};
+use specifications::package::{PackageIndex, PackageInfo, PackageKind};
+use specifications::version::Version;
+
+/// An executor for which 'login' succeeds and 'crash' fails.
+#[derive(Clone, Default)]
+struct TraceExecutor;
+
+#[async_trait]
+impl VmExecutor for TraceExecutor {
+    async fn call(&self, function: FunctionExt, _: HashMap<String, Value>, _: Option<String>) -> Result<Value, ExecutorError> {
+        match function.name.as_str() {
+            "crash" => Err(ExecutorError::ExternalCallFailed{ name: function.name, package: function.package, version: function.version, code: 1, stdout: String::new(), stderr: String::from("boom") }),
+            _       => Ok(Value::Boolean(true)),
+        }
+    }
+
+    async fn debug(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stderr(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stdout(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn wait_until(&self, _: String, _: ServiceState) -> Result<(), ExecutorError> { Ok(()) }
+}
+
+/// The 'auth' package, with login(user, password) where the password is secret, and crash().
+fn index() -> PackageIndex {
+    let mut functions = HashMap::new();
+    functions.insert(String::from("login"), Function::new(vec![
+        Parameter::new(String::from("user"), String::from("string"), None, None, None),
+        Parameter::new(String::from("password"), String::from("string"), None, None, Some(String::from("password"))),
+    ], None, String::from("boolean")));
+    functions.insert(String::from("crash"), Function::new(vec![], None, String::from("unit")));
+
+    let mut package = PackageInfo::new(String::from("auth"), Version::from_str("1.2.0").unwrap(), PackageKind::Ecu, vec![], String::new(), false, functions, HashMap::new(), vec![]);
+    package.digest = Some(String::from("sha256:auth"));
+    PackageIndex::new(vec![ (String::from("auth-1.2.0"), package) ].into_iter().collect())
+}
+
+/// Runs the given code on a VM with the given options, returning whether it succeeded and the trace it recorded.
+fn run(code: &str, trace: bool) -> (bool, Vec<TraceEntry>) {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), index());
+    let function = compiler.compile(code).unwrap();
+
+    let options = VmOptions{ trace, ..Default::default() };
+    let mut vm = Vm::new_with(TraceExecutor, Some(index()), Some(options)).unwrap();
+    let ok = futures::executor::block_on(vm.main(function)).is_ok();
+    (ok, vm.take_trace())
+}
+
+#[test]
+fn records_external_calls() {
+    let (ok, trace) = run("import auth;\nlogin(\"alice\", \"hunter2\");\ncrash();\n", true);
+    assert!(!ok);
+    assert_eq!(trace.len(), 2);
+
+    assert_eq!(trace[0].function, "login");
+    assert_eq!(trace[0].package, "auth");
+    assert_eq!(trace[0].version.to_string(), "1.2.0");
+    assert_eq!(trace[0].location, None);
+    assert_eq!(trace[0].arguments, "user=\"alice\", password=<secret>");
+    assert_eq!(trace[0].outcome, TraceOutcome::Success);
+
+    assert_eq!(trace[1].function, "crash");
+    assert_eq!(trace[1].arguments, "");
+    assert!(matches!(&trace[1].outcome, TraceOutcome::Failure{ error } if error.contains("boom")));
+
+    // The trace can be emitted as JSON, without the secret in it
+    let json = serde_json::to_string(&trace).unwrap();
+    assert!(!json.contains("hunter2"));
+    assert!(json.contains("\"status\":\"failure\""));
+    let parsed: Vec<TraceEntry> = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed[0].arguments, trace[0].arguments);
+}
+
+#[test]
+fn records_nothing_unless_asked() {
+    let (ok, trace) = run("import auth;\nlogin(\"alice\", \"hunter2\");\n", false);
+    assert!(ok);
+    assert!(trace.is_empty());
+}
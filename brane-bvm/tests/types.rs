@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use brane_bvm::executor::{ExecutorError, ServiceState, VmExecutor};
+use brane_bvm::vm::{Vm, VmError};
+use brane_dsl::{Compiler, CompilerOptions, Lang};
+use specifications::common::{Function, FunctionExt, Parameter, Property, Type, Value};
+use specifications::package::{PackageIndex, PackageInfo, PackageKind};
+use specifications::version::Version;
+
+/// An executor that remembers the name of every external function it was asked to call.
+#[derive(Clone, Default)]
+struct NameExecutor {
+    calls: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl VmExecutor for NameExecutor {
+    async fn call(&self, function: FunctionExt, _: HashMap<String, Value>, _: Option<String>) -> Result<Value, ExecutorError> {
+        self.calls.lock().unwrap().push(function.name);
+        Ok(Value::Unit)
+    }
+
+    async fn debug(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stderr(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stdout(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn wait_until(&self, _: String, _: ServiceState) -> Result<(), ExecutorError> { Ok(()) }
+}
+
+/// The 'shapes' package, with the Point and Size types, repeat(text: string, times: integer, tag?: string), sum(values: integer[]), move(point: Point) and show(value: any).
+fn index() -> PackageIndex {
+    let mut functions = HashMap::new();
+    functions.insert(String::from("repeat"), Function::new(vec![
+        Parameter::new(String::from("text"), String::from("string"), None, None, None),
+        Parameter::new(String::from("times"), String::from("integer"), None, None, None),
+        Parameter::new(String::from("tag"), String::from("string"), Some(true), None, None),
+    ], None, String::from("string")));
+    functions.insert(String::from("sum"), Function::new(vec![
+        Parameter::new(String::from("values"), String::from("integer[]"), None, None, None),
+    ], None, String::from("integer")));
+    functions.insert(String::from("move"), Function::new(vec![
+        Parameter::new(String::from("point"), String::from("Point"), None, None, None),
+    ], None, String::from("unit")));
+    functions.insert(String::from("show"), Function::new(vec![
+        Parameter::new(String::from("value"), String::from("any"), None, None, None),
+    ], None, String::from("unit")));
+
+    let mut types = HashMap::new();
+    types.insert(String::from("Point"), Type::new(String::from("Point"), vec![ Property::new_quick("x", "integer"), Property::new_quick("y", "integer") ]));
+    types.insert(String::from("Size"), Type::new(String::from("Size"), vec![ Property::new_quick("width", "integer"), Property::new_quick("height", "integer") ]));
+
+    let mut package = PackageInfo::new(String::from("shapes"), Version::from_str("1.0.0").unwrap(), PackageKind::Ecu, vec![], String::new(), false, functions, types, vec![]);
+    package.digest = Some(String::from("sha256:shapes"));
+    PackageIndex::new(vec![ (String::from("shapes-1.0.0"), package) ].into_iter().collect())
+}
+
+/// Runs the given code, returning the result and the external functions that were called.
+fn run(code: &str) -> (Result<(), VmError>, Vec<String>) {
+    let mut compiler = Compiler::new(CompilerOptions::new(Lang::BraneScript), index());
+    let function = compiler.compile(code).unwrap();
+
+    let executor = NameExecutor::default();
+    let mut vm = Vm::new_with(executor.clone(), Some(index()), None).unwrap();
+    let res = futures::executor::block_on(vm.main(function));
+    let calls = executor.calls.lock().unwrap().clone();
+    (res, calls)
+}
+
+/// Runs the given code, asserting that it fails with an ArgumentTypeError before anything is called.
+fn type_error(code: &str) -> (String, String, String, String) {
+    let (res, calls) = run(code);
+    assert!(calls.is_empty(), "Expected nothing to be called, but called {:?}", calls);
+    match res.as_ref().map_err(|err| err.inner()) {
+        Err(VmError::ArgumentTypeError{ function, parameter, expected, got }) => (function.clone(), parameter.clone(), expected.clone(), got.clone()),
+        res => panic!("Expected an argument type error, got {:?}", res),
+    }
+}
+
+#[test]
+fn matching_arguments_are_dispatched() {
+    let code = "import shapes;\nrepeat(\"a\", 3);\nrepeat(\"a\", 3, \"b\");\nsum([1, 2, 3]);\nsum([]);\nmove(new Point{ x := 1, y := 2 });\nshow(1.5);\nshow([\"any\", \"thing\"]);\n";
+    let (res, calls) = run(code);
+    res.unwrap();
+    assert_eq!(calls, vec![ "repeat", "repeat", "sum", "sum", "move", "show", "show" ]);
+}
+
+#[test]
+fn scalar_mismatch() {
+    let (function, parameter, expected, got) = type_error("import shapes;\nrepeat(\"a\", \"3\");\n");
+    assert_eq!((function.as_str(), parameter.as_str(), expected.as_str(), got.as_str()), ("repeat", "times", "integer", "string"));
+
+    // Integers are not silently promoted either
+    let (_, parameter, _, got) = type_error("import shapes;\nrepeat(1.0, 3);\n");
+    assert_eq!((parameter.as_str(), got.as_str()), ("text", "real"));
+}
+
+#[test]
+fn array_mismatch() {
+    let (function, parameter, expected, got) = type_error("import shapes;\nsum([\"1\", \"2\"]);\n");
+    assert_eq!((function.as_str(), parameter.as_str(), expected.as_str(), got.as_str()), ("sum", "values", "integer[]", "string[]"));
+
+    let (_, _, _, got) = type_error("import shapes;\nsum(1);\n");
+    assert_eq!(got, "integer");
+}
+
+#[test]
+fn instance_mismatch() {
+    let (function, parameter, expected, got) = type_error("import shapes;\nmove(new Size{ width := 1, height := 2 });\n");
+    assert_eq!((function.as_str(), parameter.as_str(), expected.as_str(), got.as_str()), ("move", "point", "Point", "Size"));
+
+    let (res, _) = run("import shapes;\nmove(new Size{ width := 1, height := 2 });\n");
+    assert_eq!(format!("{}", res.unwrap_err().inner()), "Argument 'point' of function 'move' should be of type Point, but got Size");
+}
+
+#[test]
+fn builtin_mismatch() {
+    let (function, parameter, expected, got) = type_error("print(div(7, \"2\"));\n");
+    assert_eq!((function.as_str(), parameter.as_str(), expected.as_str(), got.as_str()), ("div", "rhs", "integer", "string"));
+
+    let (function, parameter, _, got) = type_error("print(keys([1]));\n");
+    assert_eq!((function.as_str(), parameter.as_str(), got.as_str()), ("keys", "map", "integer[]"));
+
+    // Builtins without a declared type still take anything
+    run("print(str([1, 2]));\nprint(int(\"3\"));\n").0.unwrap();
+}
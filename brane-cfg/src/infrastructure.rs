@@ -2,13 +2,24 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufReader, Read};
 use std::path::PathBuf;
+use std::time::Duration;
 
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use serde::de::Error as _;
+use url::Url;
 
 use crate::Secrets;
 use crate::store::{Store, StoreError};
 
 
+/***** CONSTANTS *****/
+/// The longest timeout (in seconds) that a location may configure for any stage of a job, which catches values that were meant to be in milliseconds.
+pub const MAX_TIMEOUT: u64 = 7 * 24 * 60 * 60;
+
+
+
+
+
 /***** ERRORS *****/
 /// Lists errors that can occur while working with infrastructure files
 #[derive(Debug)]
@@ -26,6 +37,12 @@ pub enum InfrastructureError {
     InvalidInfraFile{ path: PathBuf, err: serde_yaml::Error },
     /// The given location does not appear in the infrastructure file
     UnknownLocation{ location: String },
+    /// The address of a Docker location is not a valid URL
+    InvalidDockerAddress{ location: String, address: String, err: url::ParseError },
+    /// A Docker location on another host has no TLS material to connect with
+    MissingDockerTls{ location: String, address: String },
+    /// A location overrides a timeout with zero or an absurdly large value
+    IllegalTimeout{ location: String, timeout: &'static str, value: u64 },
 
     /// The Database functionality of a remote infrastructure file isn't implemented yet
     DatabaseNotImplemented,
@@ -41,6 +58,9 @@ impl std::fmt::Display for InfrastructureError {
             InfrastructureError::EmptyInfraFile{ path }        => write!(f, "Infrastructure file '{}' is empty", path.display()),
             InfrastructureError::InvalidInfraFile{ path, err } => write!(f, "Invalid infrastructure file '{}': {}", path.display(), err),
             InfrastructureError::UnknownLocation{ location }   => write!(f, "Unknown location identifier '{}'", location),
+            InfrastructureError::InvalidDockerAddress{ location, address, err } => write!(f, "Invalid Docker address '{}' for location '{}': {}", address, location, err),
+            InfrastructureError::MissingDockerTls{ location, address }          => write!(f, "Location '{}' connects to the Docker daemon at '{}', which is not on localhost, but has no 'tls' set", location, address),
+            InfrastructureError::IllegalTimeout{ location, timeout, value }     => write!(f, "Timeout '{}' of location '{}' is {} seconds, but should be at least 1 and at most {} seconds", timeout, location, value, MAX_TIMEOUT),
 
             InfrastructureError::DatabaseNotImplemented => write!(f, "Storing infra.yml in a remote database is not yet implemented"),
         }
@@ -51,6 +71,34 @@ impl std::error::Error for InfrastructureError {}
 
 
 
+/// Lists errors that can occur while parsing resource limits
+#[derive(Debug, PartialEq)]
+pub enum ResourceLimitError {
+    /// The memory size is not a (positive) number with an optional unit
+    IllegalMemorySize{ raw: String },
+    /// The memory size has a unit we don't know
+    UnknownMemoryUnit{ raw: String, unit: String },
+    /// The number of CPUs is not a positive number
+    IllegalCpus{ raw: String },
+    /// The limit is too large to be represented
+    LimitTooLarge{ raw: String },
+}
+
+impl std::fmt::Display for ResourceLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResourceLimitError::IllegalMemorySize{ raw }       => write!(f, "Illegal memory size '{}': expected a positive number with an optional unit (e.g., '512MiB' or '2GiB')", raw),
+            ResourceLimitError::UnknownMemoryUnit{ raw, unit } => write!(f, "Unknown unit '{}' in memory size '{}' (expected one of B, K, KB, KiB, M, MB, MiB, G, GB, GiB, T, TB or TiB)", unit, raw),
+            ResourceLimitError::IllegalCpus{ raw }             => write!(f, "Illegal number of CPUs '{}': expected a positive (fractional) number (e.g., '1.5')", raw),
+            ResourceLimitError::LimitTooLarge{ raw }           => write!(f, "Resource limit '{}' is too large", raw),
+        }
+    }
+}
+
+impl std::error::Error for ResourceLimitError {}
+
+
+
 
 
 /***** DOCUMENTS *****/
@@ -74,6 +122,39 @@ pub enum Location {
         credentials: LocationCredentials,
         proxy_address: Option<String>,
         mount_dfs: Option<String>,
+        /// The name of a docker-registry Secret in the namespace that the cluster uses to pull the job images
+        image_pull_secret: Option<String>,
+        /// If given, brane-job creates the image pull Secret from these credentials the first time it needs it
+        registry_credentials: Option<RegistryCredentials>,
+        /// How often Kubernetes restarts the pod of a job that failed (the Job's `backoffLimit`)
+        #[serde(default = "default_backoff_limit")]
+        backoff_limit: u32,
+        /// How long (in seconds) Kubernetes keeps a finished Job before removing it (the Job's `ttlSecondsAfterFinished`)
+        #[serde(default = "default_ttl_seconds")]
+        ttl_seconds: u32,
+        /// Whether brane-job may create the namespace if it does not exist; many clusters do not allow this, so it has to exist by default
+        #[serde(default)]
+        create_namespace: bool,
+        /// Whether the cluster runs Indexed Jobs, so that a function mapped over many inputs (see `map_call`) may be created as a single job array instead of one Job per input
+        #[serde(default)]
+        supports_arrays: bool,
+        /// How often to retry creating a job on this location if that fails for a reason that may go away by itself (e.g., a pull timeout)
+        #[serde(default)]
+        max_create_retries: u32,
+        /// Overrides the driver's timeouts for the jobs on this location
+        #[serde(default)]
+        timeouts: LocationTimeouts,
+        /// What this location offers to jobs, used to pick a location for calls that don't name one
+        #[serde(default)]
+        capabilities: LocationCapabilities,
+        /// Whether the jobs of every session get their own subdirectory of the data directory (the default), instead of sharing it with all other sessions
+        #[serde(default = "default_isolate_sessions")]
+        isolate_sessions: bool,
+        /// The directory, shared with the driver, where brane-job writes the results of jobs that are too large to send over Kafka. If omitted, such jobs fail instead.
+        payload_dir: Option<String>,
+        /// Where the jobs on this location store the files that their packages declare as outputs, and how large these may be
+        #[serde(default)]
+        artifacts: LocationArtifacts,
     },
     Local {
         address: Option<String>,
@@ -82,6 +163,85 @@ pub enum Location {
         registry: String,
         proxy_address: Option<String>,
         mount_dfs: Option<String>,
+        /// Whether to stream the output of running jobs to the driver (as Log events), instead of only sending it once they are done
+        #[serde(default)]
+        stream_logs: bool,
+        /// Whether to create the Docker network of this location (as a bridge network, labelled 'brane=true') if it does not exist yet
+        #[serde(default)]
+        create_network: bool,
+        /// The credentials to pull images from the registry with, if it isn't public
+        registry_credentials: Option<RegistryCredentials>,
+        /// How often to retry creating a job on this location if that fails for a reason that may go away by itself (e.g., a pull timeout)
+        #[serde(default)]
+        max_create_retries: u32,
+        /// Overrides the driver's timeouts for the jobs on this location
+        #[serde(default)]
+        timeouts: LocationTimeouts,
+        /// What this location offers to jobs, used to pick a location for calls that don't name one
+        #[serde(default)]
+        capabilities: LocationCapabilities,
+        /// Whether the jobs of every session get their own subdirectory of the data directory (the default), instead of sharing it with all other sessions
+        #[serde(default = "default_isolate_sessions")]
+        isolate_sessions: bool,
+        /// The directory, shared with the driver, where brane-job writes the results of jobs that are too large to send over Kafka. If omitted, such jobs fail instead.
+        payload_dir: Option<String>,
+        /// Where the jobs on this location store the files that their packages declare as outputs, and how large these may be
+        #[serde(default)]
+        artifacts: LocationArtifacts,
+        /// The maximum amount of memory that the container of a job may use (e.g., '2GiB')
+        memory_limit: Option<MemoryLimit>,
+        /// The maximum number of CPUs that the container of a job may use (e.g., '1.5')
+        cpu_limit: Option<CpuLimit>,
+        /// The maximum number of processes in the container of a job
+        pids_limit: Option<u32>,
+        /// Whether to run the containers of jobs in privileged mode. If not, they only get the capabilities (and device) they need.
+        #[serde(default)]
+        privileged: bool,
+    },
+    Docker {
+        /// The address of the Docker daemon (e.g., 'tcp://host:2376')
+        address: String,
+        callback_to: String,
+        network: String,
+        registry: String,
+        proxy_address: Option<String>,
+        mount_dfs: Option<String>,
+        /// The TLS material to connect to the daemon with. Required unless the daemon runs on localhost.
+        tls: Option<DockerTls>,
+        /// Whether to stream the output of running jobs to the driver (as Log events), instead of only sending it once they are done
+        #[serde(default)]
+        stream_logs: bool,
+        /// Whether to create the Docker network of this location (as a bridge network, labelled 'brane=true') if it does not exist yet
+        #[serde(default)]
+        create_network: bool,
+        /// The credentials to pull images from the registry with, if it isn't public
+        registry_credentials: Option<RegistryCredentials>,
+        /// How often to retry creating a job on this location if that fails for a reason that may go away by itself (e.g., a pull timeout)
+        #[serde(default)]
+        max_create_retries: u32,
+        /// Overrides the driver's timeouts for the jobs on this location
+        #[serde(default)]
+        timeouts: LocationTimeouts,
+        /// What this location offers to jobs, used to pick a location for calls that don't name one
+        #[serde(default)]
+        capabilities: LocationCapabilities,
+        /// Whether the jobs of every session get their own subdirectory of the data directory (the default), instead of sharing it with all other sessions
+        #[serde(default = "default_isolate_sessions")]
+        isolate_sessions: bool,
+        /// The directory, shared with the driver, where brane-job writes the results of jobs that are too large to send over Kafka. If omitted, such jobs fail instead.
+        payload_dir: Option<String>,
+        /// Where the jobs on this location store the files that their packages declare as outputs, and how large these may be
+        #[serde(default)]
+        artifacts: LocationArtifacts,
+        /// The maximum amount of memory that the container of a job may use (e.g., '2GiB')
+        memory_limit: Option<MemoryLimit>,
+        /// The maximum number of CPUs that the container of a job may use (e.g., '1.5')
+        cpu_limit: Option<CpuLimit>,
+        /// The maximum number of processes in the container of a job
+        pids_limit: Option<u32>,
+        /// Whether to run the containers of jobs in privileged mode. If not, they only get the capabilities (and device) they need.
+        #[serde(default)]
+        privileged: bool,
     },
     Vm {
         address: String,
@@ -91,6 +251,30 @@ pub enum Location {
         credentials: LocationCredentials,
         proxy_address: Option<String>,
         mount_dfs: Option<String>,
+        /// How often to retry creating a job on this location if that fails for a reason that may go away by itself (e.g., a pull timeout)
+        #[serde(default)]
+        max_create_retries: u32,
+        /// Overrides the driver's timeouts for the jobs on this location
+        #[serde(default)]
+        timeouts: LocationTimeouts,
+        /// What this location offers to jobs, used to pick a location for calls that don't name one
+        #[serde(default)]
+        capabilities: LocationCapabilities,
+        /// Whether the jobs of every session get their own subdirectory of the data directory (the default), instead of sharing it with all other sessions
+        #[serde(default = "default_isolate_sessions")]
+        isolate_sessions: bool,
+        /// The directory, shared with the driver, where brane-job writes the results of jobs that are too large to send over Kafka. If omitted, such jobs fail instead.
+        payload_dir: Option<String>,
+        /// Where the jobs on this location store the files that their packages declare as outputs, and how large these may be
+        #[serde(default)]
+        artifacts: LocationArtifacts,
+        /// The directory on the location to write the stdout/stderr files of jobs to. If omitted, they end up in the working directory of the job.
+        output_dir: Option<String>,
+        /// How long (in seconds) to keep the stdout/stderr files in the output directory before they are removed. If omitted, they are kept forever.
+        output_retention: Option<u64>,
+        /// Never removes the stdout/stderr files of jobs, regardless of the retention (e.g., for debugging)
+        #[serde(default)]
+        keep_job_output: bool,
     },
     Slurm {
         address: String,
@@ -100,6 +284,33 @@ pub enum Location {
         credentials: LocationCredentials,
         proxy_address: Option<String>,
         mount_dfs: Option<String>,
+        /// How often to retry creating a job on this location if that fails for a reason that may go away by itself (e.g., a pull timeout)
+        #[serde(default)]
+        max_create_retries: u32,
+        /// Overrides the driver's timeouts for the jobs on this location
+        #[serde(default)]
+        timeouts: LocationTimeouts,
+        /// What this location offers to jobs, used to pick a location for calls that don't name one
+        #[serde(default)]
+        capabilities: LocationCapabilities,
+        /// Whether the jobs of every session get their own subdirectory of the data directory (the default), instead of sharing it with all other sessions
+        #[serde(default = "default_isolate_sessions")]
+        isolate_sessions: bool,
+        /// The directory, shared with the driver, where brane-job writes the results of jobs that are too large to send over Kafka. If omitted, such jobs fail instead.
+        payload_dir: Option<String>,
+        /// Where the jobs on this location store the files that their packages declare as outputs, and how large these may be
+        #[serde(default)]
+        artifacts: LocationArtifacts,
+        /// The directory on the location to write the stdout/stderr files of jobs to. If omitted, they end up in the working directory of the job.
+        output_dir: Option<String>,
+        /// How long (in seconds) to keep the stdout/stderr files in the output directory before they are removed. If omitted, they are kept forever.
+        output_retention: Option<u64>,
+        /// Never removes the stdout/stderr files of jobs, regardless of the retention (e.g., for debugging)
+        #[serde(default)]
+        keep_job_output: bool,
+        /// Whether the scheduler accepts array jobs (`--array`), so that a function mapped over many inputs (see `map_call`) may be submitted as a single job
+        #[serde(default)]
+        supports_arrays: bool,
     },
 }
 
@@ -110,7 +321,7 @@ impl Location {
     /// The address, or (if it's optional) the address of the localhost.
     pub fn get_address(&self) -> String {
         match self {
-            Location::Kube { address, .. } | Location::Vm { address, .. } | Location::Slurm { address, .. } => {
+            Location::Kube { address, .. } | Location::Docker { address, .. } | Location::Vm { address, .. } | Location::Slurm { address, .. } => {
                 address.clone()
             }
             Location::Local { address, .. } => address.clone().unwrap_or_else(|| String::from("127.0.0.1")),
@@ -121,11 +332,262 @@ impl Location {
     pub fn get_registry(&self) -> String {
         match self {
             Location::Kube { registry, .. }
+            | Location::Docker { registry, .. }
             | Location::Vm { registry, .. }
             | Location::Slurm { registry, .. }
             | Location::Local { registry, .. } => registry.clone(),
         }
     }
+
+    /// Returns how often to retry creating a job on this location, across the multiple location kinds.
+    pub fn get_max_create_retries(&self) -> u32 {
+        match self {
+            Location::Kube { max_create_retries, .. }
+            | Location::Docker { max_create_retries, .. }
+            | Location::Vm { max_create_retries, .. }
+            | Location::Slurm { max_create_retries, .. }
+            | Location::Local { max_create_retries, .. } => *max_create_retries,
+        }
+    }
+
+    /// Returns the timeouts that this location overrides, across the multiple location kinds.
+    pub fn get_timeouts(&self) -> LocationTimeouts {
+        match self {
+            Location::Kube { timeouts, .. }
+            | Location::Docker { timeouts, .. }
+            | Location::Vm { timeouts, .. }
+            | Location::Slurm { timeouts, .. }
+            | Location::Local { timeouts, .. } => *timeouts,
+        }
+    }
+
+    /// Returns what this location offers to jobs, across the multiple location kinds.
+    pub fn get_capabilities(&self) -> &LocationCapabilities {
+        match self {
+            Location::Kube { capabilities, .. }
+            | Location::Docker { capabilities, .. }
+            | Location::Vm { capabilities, .. }
+            | Location::Slurm { capabilities, .. }
+            | Location::Local { capabilities, .. } => capabilities,
+        }
+    }
+
+    /// Returns whether the jobs on this location get a data directory of their own session, across the multiple location kinds.
+    pub fn isolates_sessions(&self) -> bool {
+        match self {
+            Location::Kube { isolate_sessions, .. }
+            | Location::Docker { isolate_sessions, .. }
+            | Location::Vm { isolate_sessions, .. }
+            | Location::Slurm { isolate_sessions, .. }
+            | Location::Local { isolate_sessions, .. } => *isolate_sessions,
+        }
+    }
+
+    /// Returns whether calls that map a function over many inputs may be created as a single job array on this location.
+    /// 
+    /// **Returns**  
+    /// The `supports_arrays` of a Kube or Slurm location, or false for the other kinds, as they have no notion of job arrays.
+    pub fn supports_arrays(&self) -> bool {
+        match self {
+            Location::Kube { supports_arrays, .. } | Location::Slurm { supports_arrays, .. } => *supports_arrays,
+            Location::Docker { .. } | Location::Vm { .. } | Location::Local { .. } => false,
+        }
+    }
+
+    /// Returns the directory where brane-job writes the results of jobs that are too large to send over Kafka, across the multiple location kinds.
+    pub fn get_payload_dir(&self) -> Option<&str> {
+        match self {
+            Location::Kube { payload_dir, .. }
+            | Location::Docker { payload_dir, .. }
+            | Location::Vm { payload_dir, .. }
+            | Location::Slurm { payload_dir, .. }
+            | Location::Local { payload_dir, .. } => payload_dir.as_deref(),
+        }
+    }
+
+    /// Returns where the jobs on this location store their artifacts and how large these may be, across the multiple location kinds.
+    pub fn get_artifacts(&self) -> &LocationArtifacts {
+        match self {
+            Location::Kube { artifacts, .. }
+            | Location::Docker { artifacts, .. }
+            | Location::Vm { artifacts, .. }
+            | Location::Slurm { artifacts, .. }
+            | Location::Local { artifacts, .. } => artifacts,
+        }
+    }
+
+    /// Returns where the jobs on this location write their stdout/stderr files, and how long they are kept.
+    /// 
+    /// **Returns**  
+    /// The JobOutputs of a Vm or Slurm location, or the default (no output directory, no cleanup) for the other kinds, as they don't write such files.
+    pub fn get_job_outputs(&self) -> JobOutputs {
+        match self {
+            Location::Vm { output_dir, output_retention, keep_job_output, .. }
+            | Location::Slurm { output_dir, output_retention, keep_job_output, .. } => JobOutputs {
+                dir       : output_dir.clone(),
+                retention : *output_retention,
+                keep      : *keep_job_output,
+            },
+            Location::Kube { .. } | Location::Docker { .. } | Location::Local { .. } => JobOutputs::default(),
+        }
+    }
+
+    /// Returns the names of the secrets (i.e., the values written as `s$<name>`) that the credentials, TLS material and registry credentials of this location refer to.
+    /// 
+    /// **Returns**  
+    /// The names of the referenced secrets, in the order they appear in and without duplicates.
+    pub fn secret_references(&self) -> Vec<String> {
+        let mut values: Vec<&String> = vec![];
+        match self {
+            Location::Kube { credentials, registry_credentials, .. } => {
+                values.extend(credentials.values());
+                if let Some(registry_credentials) = registry_credentials { values.extend([ &registry_credentials.username, &registry_credentials.password ]); }
+            },
+            Location::Vm { credentials, .. } | Location::Slurm { credentials, .. } => { values.extend(credentials.values()); },
+            Location::Docker { tls, registry_credentials, .. } => {
+                if let Some(tls) = tls { values.extend([ &tls.ca, &tls.cert, &tls.key ]); }
+                if let Some(registry_credentials) = registry_credentials { values.extend([ &registry_credentials.username, &registry_credentials.password ]); }
+            },
+            Location::Local { registry_credentials, .. } => {
+                if let Some(registry_credentials) = registry_credentials { values.extend([ &registry_credentials.username, &registry_credentials.password ]); }
+            },
+        }
+
+        let mut names: Vec<String> = vec![];
+        for name in values.into_iter().filter_map(|value| value.strip_prefix("s$")) {
+            if !names.iter().any(|n| n == name) { names.push(name.to_string()); }
+        }
+        names
+    }
+
+    /// Checks the parts of the location that its kind alone cannot express: that a Docker location on another host has TLS material, and that its timeouts are sane.
+    /// 
+    /// **Arguments**
+    ///  * `name`: The name of the location (used for debugging purposes).
+    /// 
+    /// **Returns**  
+    /// Nothing if the location is valid, or an InfrastructureError describing why it isn't otherwise.
+    pub fn validate(&self, name: &str) -> Result<(), InfrastructureError> {
+        if let Location::Docker{ address, tls: None, .. } = self {
+            if !is_local_docker_address(address).map_err(|err| InfrastructureError::InvalidDockerAddress{ location: name.to_string(), address: address.clone(), err })? {
+                return Err(InfrastructureError::MissingDockerTls{ location: name.to_string(), address: address.clone() });
+            }
+        }
+        self.get_timeouts().validate(name)
+    }
+}
+
+
+
+/// Defines the timeouts (in seconds) that the driver uses for the jobs on a location, overriding its global defaults.
+/// 
+/// Each of them is optional; the ones that are missing fall back to the defaults.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LocationTimeouts {
+    /// How long a job may take to be created (i.e., until the job service reports Created or CreateFailed)
+    pub created   : Option<u64>,
+    /// How long a created job may take to report that it's alive (Ready)
+    pub ready     : Option<u64>,
+    /// How long an initialized job may take to start running (Started or StartFailed)
+    pub started   : Option<u64>,
+    /// How long a running job may go without sending a heartbeat before it's considered dead
+    pub heartbeat : Option<u64>,
+    /// How long a completed job may take to return its result
+    pub result    : Option<u64>,
+}
+
+impl LocationTimeouts {
+    /// Checks that every timeout that is given is positive and at most `MAX_TIMEOUT`.
+    /// 
+    /// **Arguments**
+    ///  * `name`: The name of the location (used for debugging purposes).
+    /// 
+    /// **Returns**  
+    /// Nothing if the timeouts are valid, or an InfrastructureError::IllegalTimeout for the first one that isn't.
+    pub fn validate(&self, name: &str) -> Result<(), InfrastructureError> {
+        for (timeout, value) in [ ("created", self.created), ("ready", self.ready), ("started", self.started), ("heartbeat", self.heartbeat), ("result", self.result) ] {
+            if let Some(value) = value {
+                if value == 0 || value > MAX_TIMEOUT { return Err(InfrastructureError::IllegalTimeout{ location: name.to_string(), timeout, value }); }
+            }
+        }
+        Ok(())
+    }
+}
+
+
+
+/// Defines what a location offers to the jobs that run on it, which the driver matches against the requirements of packages to pick a location for calls that don't name one.
+/// 
+/// Capabilities that are not given are not offered; a location without capabilities only runs packages without requirements.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LocationCapabilities {
+    /// Whether jobs may use a GPU
+    #[serde(default)]
+    pub gpu    : bool,
+    /// The amount of memory available to a job (e.g., '16GiB')
+    pub memory : Option<MemoryLimit>,
+    /// The operating system that jobs run on (e.g., 'linux')
+    pub os     : Option<String>,
+    /// The CPU architecture that jobs run on (e.g., 'amd64')
+    pub arch   : Option<String>,
+    /// The relative cost of running a job here; of the locations that satisfy a package, the cheapest one is picked
+    #[serde(default)]
+    pub cost   : u64,
+}
+
+
+
+/// Defines where the jobs on a location store the files that their packages declare as outputs (i.e., their artifacts), and how large these may be.
+/// 
+/// Settings that are not given fall back to the defaults of the branelet: artifacts go to the mounted DFS (if any), with its own size limits.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LocationArtifacts {
+    /// The directory (as the jobs see it) to store artifacts in, instead of the mounted DFS
+    pub dir       : Option<String>,
+    /// The directory on the host (or Kubernetes node) that is mounted as `dir` in Docker and Kubernetes jobs; defaults to `dir` itself
+    pub host_dir  : Option<String>,
+    /// The maximum size of a single artifact, in bytes
+    pub max_size  : Option<u64>,
+    /// The maximum size of all artifacts of a single job together, in bytes
+    pub max_total : Option<u64>,
+}
+
+impl LocationArtifacts {
+    /// Returns which directory to mount in container-based jobs so that they can reach the artifact directory.
+    /// 
+    /// **Returns**  
+    /// The directory on the host and the directory in the container, or None if the jobs store their artifacts on the mounted DFS.
+    pub fn mount(&self) -> Option<(&str, &str)> {
+        let dir = self.dir.as_deref()?;
+        Some((self.host_dir.as_deref().unwrap_or(dir), dir))
+    }
+}
+
+
+
+/// Defines where the jobs on a Xenon location (Vm or Slurm) write their stdout/stderr files, and how long these are kept.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct JobOutputs {
+    /// The directory to write the files to, or None to write them to the working directory of the job
+    pub dir       : Option<String>,
+    /// How long (in seconds) to keep the files in `dir` before they are removed
+    pub retention : Option<u64>,
+    /// Whether to keep the files anyway (overriding `retention`)
+    pub keep      : bool,
+}
+
+impl JobOutputs {
+    /// Returns how old files in the output directory may get before they are removed.
+    /// 
+    /// **Returns**  
+    /// The retention window, or None if nothing should be removed (because there is no output directory or retention, or because the files are kept on purpose).
+    pub fn cleanup_after(&self) -> Option<Duration> {
+        if self.keep || self.dir.is_none() { return None; }
+        self.retention.map(Duration::from_secs)
+    }
 }
 
 
@@ -187,16 +649,7 @@ impl LocationCredentials {
     ) -> Self {
         use LocationCredentials::*;
 
-        let resolve = |value: &String| {
-            // Try to resolve secret, but use the value as-is otherwise.
-            if let Some(value) = value.strip_prefix("s$") {
-                if let Ok(secret) = secrets.get(value) {
-                    return secret;
-                }
-            }
-
-            value.clone()
-        };
+        let resolve = |value: &String| resolve_secret(value, secrets);
 
         match self {
             Config { file } => {
@@ -228,6 +681,15 @@ impl LocationCredentials {
         }
     }
 
+    /// Returns the values of the credentials, each of which may refer to a secret.
+    pub fn values(&self) -> Vec<&String> {
+        match self {
+            LocationCredentials::Config{ file }                                      => vec![ file ],
+            LocationCredentials::SshCertificate{ username, certificate, passphrase } => vec![ Some(username), Some(certificate), passphrase.as_ref() ].into_iter().flatten().collect(),
+            LocationCredentials::SshPassword{ username, password }                   => vec![ username, password ],
+        }
+    }
+
     /// Returns a human-readable name of the credential type.
     #[inline]
     pub fn cred_type(&self) -> &'static str {
@@ -243,6 +705,171 @@ impl LocationCredentials {
 
 
 
+/// Defines the credentials to pull images from a private registry with.
+/// 
+/// Both fields may refer to a secret in the secrets.yml (as `s$<name>`).
+#[derive(Clone, Debug, Deserialize)]
+pub struct RegistryCredentials {
+    /// The username to log in with
+    pub username: String,
+    /// The password (or access token) to log in with
+    pub password: String,
+}
+
+impl RegistryCredentials {
+    /// Resolves the secrets stored in the RegistryCredentials.
+    /// 
+    /// **Arguments**
+    ///  * `secrets`: The parsed Secrets document that we use to resolve.
+    /// 
+    /// **Returns**  
+    /// A copy of itself, but then with secrets resolved.
+    pub fn resolve_secrets(&self, secrets: &Secrets) -> Self {
+        Self {
+            username : resolve_secret(&self.username, secrets),
+            password : resolve_secret(&self.password, secrets),
+        }
+    }
+}
+
+
+
+
+
+/// Defines the TLS material to connect to a remote Docker daemon with.
+/// 
+/// Every field is either the path to a PEM file, or a reference to a secret in the secrets.yml (as `s$<name>`) that holds the PEM contents.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DockerTls {
+    /// The certificate of the CA that signed the daemon's certificate
+    pub ca: String,
+    /// The client certificate to authenticate with
+    pub cert: String,
+    /// The private key of the client certificate
+    pub key: String,
+}
+
+impl DockerTls {
+    /// Resolves the secrets stored in the DockerTls.
+    /// 
+    /// **Arguments**
+    ///  * `secrets`: The parsed Secrets document that we use to resolve.
+    /// 
+    /// **Returns**  
+    /// A copy of itself, but then with secrets resolved.
+    pub fn resolve_secrets(&self, secrets: &Secrets) -> Self {
+        Self {
+            ca   : resolve_secret(&self.ca, secrets),
+            cert : resolve_secret(&self.cert, secrets),
+            key  : resolve_secret(&self.key, secrets),
+        }
+    }
+}
+
+
+
+
+
+/***** RESOURCE LIMITS *****/
+/// A memory limit in bytes, which is written in infra.yml as a number of bytes or as a human-friendly size (e.g., '512MiB' or '2GiB').
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MemoryLimit(pub i64);
+
+impl std::str::FromStr for MemoryLimit {
+    type Err = ResourceLimitError;
+
+    /// Parses a memory size, where 'K', 'M', 'G' and 'T' (like Docker) and 'KiB' to 'TiB' are powers of 1024, and 'KB' to 'TB' are powers of 1000. Units are case-insensitive.
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let trimmed = raw.trim();
+        let split = trimmed.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(trimmed.len());
+        let (number, unit) = (&trimmed[..split], trimmed[split..].trim());
+
+        let number: f64 = match number.parse() {
+            Ok(number) if number > 0.0 => number,
+            _                          => { return Err(ResourceLimitError::IllegalMemorySize{ raw: raw.to_string() }); }
+        };
+        let factor: f64 = match unit.to_lowercase().as_str() {
+            "" | "b"            => 1.0,
+            "k" | "kib"         => 1024.0,
+            "kb"                => 1000.0,
+            "m" | "mib"         => 1024.0 * 1024.0,
+            "mb"                => 1000.0 * 1000.0,
+            "g" | "gib"         => 1024.0 * 1024.0 * 1024.0,
+            "gb"                => 1000.0 * 1000.0 * 1000.0,
+            "t" | "tib"         => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+            "tb"                => 1000.0 * 1000.0 * 1000.0 * 1000.0,
+            _                   => { return Err(ResourceLimitError::UnknownMemoryUnit{ raw: raw.to_string(), unit: unit.to_string() }); }
+        };
+
+        let bytes = (number * factor).round();
+        if bytes >= i64::MAX as f64 { return Err(ResourceLimitError::LimitTooLarge{ raw: raw.to_string() }); }
+        if bytes < 1.0 { return Err(ResourceLimitError::IllegalMemorySize{ raw: raw.to_string() }); }
+        Ok(Self(bytes as i64))
+    }
+}
+
+impl<'de> Deserialize<'de> for MemoryLimit {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = RawLimit::deserialize(deserializer)?.into_string();
+        raw.parse().map_err(D::Error::custom)
+    }
+}
+
+
+
+/// A CPU limit in nano-CPUs (i.e., 10^-9 CPUs), which is written in infra.yml as a (fractional) number of CPUs (e.g., '1.5').
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CpuLimit(pub i64);
+
+impl std::str::FromStr for CpuLimit {
+    type Err = ResourceLimitError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let cpus: f64 = match raw.trim().parse() {
+            Ok(cpus) if cpus > 0.0 && f64::is_finite(cpus) => cpus,
+            _                                               => { return Err(ResourceLimitError::IllegalCpus{ raw: raw.to_string() }); }
+        };
+
+        let nano_cpus = (cpus * 1e9).round();
+        if nano_cpus >= i64::MAX as f64 { return Err(ResourceLimitError::LimitTooLarge{ raw: raw.to_string() }); }
+        if nano_cpus < 1.0 { return Err(ResourceLimitError::IllegalCpus{ raw: raw.to_string() }); }
+        Ok(Self(nano_cpus as i64))
+    }
+}
+
+impl<'de> Deserialize<'de> for CpuLimit {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = RawLimit::deserialize(deserializer)?.into_string();
+        raw.parse().map_err(D::Error::custom)
+    }
+}
+
+
+
+/// A resource limit as written in infra.yml, which YAML may parse as a number or as a string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawLimit {
+    Integer(u64),
+    Float(f64),
+    String(String),
+}
+
+impl RawLimit {
+    /// Returns the limit as it was written.
+    fn into_string(self) -> String {
+        match self {
+            RawLimit::Integer(limit) => limit.to_string(),
+            RawLimit::Float(limit)   => limit.to_string(),
+            RawLimit::String(limit)  => limit,
+        }
+    }
+}
+
+
+
+
+
 /***** LIBRARY STRUCTS *****/
 /// A 'handle' to either a local or remote infra.yml file.
 #[derive(Clone, Debug)]
@@ -313,11 +940,12 @@ impl Infrastructure {
     /// **Returns**  
     /// Nothing if the file was valid, or an InfrastructureError detailling why it wasn't otherwise.
     pub fn validate(&self) -> Result<(), InfrastructureError> {
-        // Simply check if we can read it without any problems
-        match Self::read_store(&self.store) {
-            Ok(_)       => Ok(()),
-            Err(reason) => Err(reason),
+        // Check if we can read it without any problems, and if every location makes sense
+        let infra_document = Self::read_store(&self.store)?;
+        for (name, location) in &infra_document.locations {
+            location.validate(name)?;
         }
+        Ok(())
     }
 
 
@@ -357,8 +985,51 @@ impl Infrastructure {
 
         // Return the location
         match infra_document.locations.get(&location) {
-            Some(location) => Ok(location.clone()),
-            None           => Err(InfrastructureError::UnknownLocation{ location }),
+            Some(metadata) => {
+                metadata.validate(&location)?;
+                Ok(metadata.clone())
+            },
+            None => Err(InfrastructureError::UnknownLocation{ location }),
+        }
+    }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Returns whether the given address of a Docker daemon is on this host (a Unix socket, or a TCP address of localhost).
+/// 
+/// **Arguments**
+///  * `address`: The address to check (e.g., 'tcp://host:2376' or 'unix:///var/run/docker.sock').
+/// 
+/// **Returns**  
+/// Whether the address is local, or a url::ParseError if it isn't a valid URL.
+pub fn is_local_docker_address(address: &str) -> Result<bool, url::ParseError> {
+    let url = Url::parse(address)?;
+    if url.scheme() == "unix" { return Ok(true); }
+    Ok(matches!(url.host_str(), Some("localhost") | Some("127.0.0.1") | Some("[::1]")))
+}
+
+/// Returns the default for whether a location isolates the data of sessions, which is that it does.
+#[inline]
+fn default_isolate_sessions() -> bool { true }
+
+/// Returns the default number of times Kubernetes restarts the pod of a failed job.
+#[inline]
+fn default_backoff_limit() -> u32 { 3 }
+
+/// Returns the default number of seconds that Kubernetes keeps a finished job.
+#[inline]
+fn default_ttl_seconds() -> u32 { 120 }
+
+/// Resolves the given value as a reference to a secret (`s$<name>`), but returns it as-is if it isn't one (or the secret is unknown).
+fn resolve_secret(value: &str, secrets: &Secrets) -> String {
+    if let Some(name) = value.strip_prefix("s$") {
+        if let Ok(secret) = secrets.get(name) {
+            return secret;
         }
     }
+    value.to_string()
 }
@@ -0,0 +1,97 @@
+/* MAIN.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 14:21:09
+ * Last edited:
+ *   15 Oct 2026, 14:21:09
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Entrypoint to the brane-secrets tool, which creates keys for and
+ *   encrypts (or decrypts) secrets.yml files.
+**/
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use brane_cfg::secrets::{self, SecretsKey};
+use clap::Parser;
+
+
+/***** ARGUMENTS *****/
+#[derive(Parser)]
+#[clap(name = "brane-secrets", version = env!("CARGO_PKG_VERSION"), about = "Manage encrypted secrets.yml files")]
+enum Opts {
+    #[clap(name = "keygen", about = "Generate a new key and print it (in base64)")]
+    Keygen,
+
+    #[clap(name = "encrypt", about = "Encrypt a plaintext secrets.yml")]
+    Encrypt {
+        #[clap(name = "FILE", help = "The plaintext secrets file")]
+        file: PathBuf,
+        #[clap(short, long, help = "Where to write the encrypted file (defaults to overwriting FILE)")]
+        output: Option<PathBuf>,
+        #[clap(short, long, help = "The file with the key (defaults to the BRANE_SECRETS_KEY environment variable)")]
+        key_file: Option<PathBuf>,
+    },
+
+    #[clap(name = "decrypt", about = "Decrypt an encrypted secrets.yml, e.g., to edit it")]
+    Decrypt {
+        #[clap(name = "FILE", help = "The encrypted secrets file")]
+        file: PathBuf,
+        #[clap(short, long, help = "Where to write the plaintext file (defaults to stdout)")]
+        output: Option<PathBuf>,
+        #[clap(short, long, help = "The file with the key (defaults to the BRANE_SECRETS_KEY environment variable)")]
+        key_file: Option<PathBuf>,
+    },
+}
+
+
+
+
+
+/***** ENTRYPOINT *****/
+fn main() -> Result<()> {
+    match Opts::parse() {
+        Opts::Keygen => {
+            println!("{}", SecretsKey::generate().to_base64());
+        },
+
+        Opts::Encrypt{ file, output, key_file } => {
+            let key = resolve_key(key_file)?;
+            let plaintext = fs::read_to_string(&file).with_context(|| format!("Could not read '{}'", file.display()))?;
+            if secrets::is_encrypted(&plaintext) { bail!("'{}' is already encrypted", file.display()); }
+            // Don't encrypt garbage, as the mistake would only show once brane-job decrypts it
+            serde_yaml::from_str::<secrets::SecretsDocument>(&plaintext).with_context(|| format!("'{}' is not a valid secrets file", file.display()))?;
+
+            let encrypted = secrets::encrypt_secrets(&plaintext, &key)?;
+            let output = output.unwrap_or(file);
+            fs::write(&output, encrypted).with_context(|| format!("Could not write '{}'", output.display()))?;
+        },
+
+        Opts::Decrypt{ file, output, key_file } => {
+            let key = resolve_key(key_file)?;
+            let contents = fs::read_to_string(&file).with_context(|| format!("Could not read '{}'", file.display()))?;
+            if !secrets::is_encrypted(&contents) { bail!("'{}' is not encrypted", file.display()); }
+
+            let plaintext = secrets::decrypt_secrets(&contents, &key, &file)?;
+            match output {
+                Some(output) => fs::write(&output, plaintext).with_context(|| format!("Could not write '{}'", output.display()))?,
+                None         => print!("{}", plaintext),
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Finds the key to use from the given key file or the environment, failing if there is none.
+fn resolve_key(key_file: Option<PathBuf>) -> Result<SecretsKey> {
+    match SecretsKey::resolve(key_file.as_deref())? {
+        Some(key) => Ok(key),
+        None      => bail!("No key given; pass --key-file or set {} (generate one with 'brane-secrets keygen')", secrets::SECRETS_KEY_ENV),
+    }
+}
@@ -1,8 +1,27 @@
 use crate::store::{Store, StoreError};
+use crypto_secretbox::aead::{Aead, KeyInit};
+use crypto_secretbox::{Key, Nonce, XSalsa20Poly1305};
+use log::warn;
+use rand::RngCore;
 use std::collections::HashMap;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{BufReader, Read};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+
+/***** CONSTANTS *****/
+/// The first line of an encrypted secrets file, which tells it apart from a plaintext one.
+pub const ENCRYPTED_SECRETS_HEADER: &str = "brane-secrets:v1:xsalsa20poly1305";
+
+/// The environment variable that may hold the (base64-encoded) key of an encrypted secrets file.
+pub const SECRETS_KEY_ENV: &str = "BRANE_SECRETS_KEY";
+
+/// The size of the key of an encrypted secrets file, in bytes.
+const KEY_SIZE: usize = 32;
+/// The size of the nonce that precedes the ciphertext, in bytes.
+const NONCE_SIZE: usize = 24;
+/// The size of the authentication tag in the ciphertext, in bytes.
+const TAG_SIZE: usize = 16;
 
 
 /* TIM */
@@ -24,6 +43,23 @@ pub enum SecretsError {
     /// The given secret does not appear in the secrets file
     UnknownSecret{ secret: String },
 
+    /// The secrets file is encrypted, but we were not given a key
+    MissingSecretsKey{ path: PathBuf },
+    /// The key given for the secrets file is not a base64-encoded 32-byte key
+    InvalidSecretsKey{ reason: String },
+    /// Could not read the file with the key
+    KeyFileReadError{ path: PathBuf, err: std::io::Error },
+    /// The body of an encrypted secrets file is not valid base64
+    InvalidSecretsEncoding{ path: PathBuf, err: base64::DecodeError },
+    /// The body of an encrypted secrets file is too short to be a ciphertext
+    TruncatedSecretsFile{ path: PathBuf },
+    /// The ciphertext could not be authenticated (wrong key or a damaged file)
+    SecretsDecryptError{ path: PathBuf },
+    /// The decrypted secrets are not valid UTF-8
+    SecretsNotUtf8{ path: PathBuf },
+    /// Could not encrypt the secrets
+    SecretsEncryptError,
+
     /// The Database functionality of a remote secrets file isn't implemented yet
     DatabaseNotImplemented,
 }
@@ -39,6 +75,15 @@ impl std::fmt::Display for SecretsError {
             SecretsError::InvalidSecretsFile{ path, err } => write!(f, "Invalid secrets file '{}': {}", path.display(), err),
             SecretsError::UnknownSecret{ secret }         => write!(f, "Unknown secret identifier '{}'", secret),
 
+            SecretsError::MissingSecretsKey{ path }           => write!(f, "Secrets file '{}' is encrypted, but no key was given (set {} or pass a key file)", path.display(), SECRETS_KEY_ENV),
+            SecretsError::InvalidSecretsKey{ reason }         => write!(f, "Invalid secrets key: {}", reason),
+            SecretsError::KeyFileReadError{ path, err }       => write!(f, "Could not read secrets key file '{}': {}", path.display(), err),
+            SecretsError::InvalidSecretsEncoding{ path, err } => write!(f, "Encrypted secrets file '{}' is not valid base64: {}", path.display(), err),
+            SecretsError::TruncatedSecretsFile{ path }        => write!(f, "Encrypted secrets file '{}' is truncated", path.display()),
+            SecretsError::SecretsDecryptError{ path }         => write!(f, "Could not decrypt secrets file '{}' (wrong key, or the file is damaged)", path.display()),
+            SecretsError::SecretsNotUtf8{ path }              => write!(f, "Decrypted secrets file '{}' is not valid UTF-8", path.display()),
+            SecretsError::SecretsEncryptError                 => write!(f, "Could not encrypt secrets"),
+
             SecretsError::DatabaseNotImplemented => write!(f, "Storing secrets.yml in a remote database is not yet implemented"),
         }
     }
@@ -52,9 +97,122 @@ impl std::error::Error for SecretsError {}
 pub type SecretsDocument = HashMap<String, String>;
 
 
+
+/// The key of an encrypted secrets file (an XSalsa20-Poly1305 secretbox key).
+#[derive(Clone)]
+pub struct SecretsKey([u8; KEY_SIZE]);
+
+impl SecretsKey {
+    /// Generates a new, random key.
+    pub fn generate() -> Self {
+        let mut key = [0; KEY_SIZE];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self(key)
+    }
+
+    /// Parses a key from its base64 representation (as written by `to_base64()`).
+    /// 
+    /// **Arguments**
+    ///  * `key`: The base64-encoded key. Surrounding whitespace is ignored.
+    /// 
+    /// **Returns**  
+    /// The key on success, or a SecretsError::InvalidSecretsKey if it's not base64 or not 32 bytes.
+    pub fn from_base64(key: &str) -> Result<Self, SecretsError> {
+        let bytes = base64::decode(key.trim()).map_err(|err| SecretsError::InvalidSecretsKey{ reason: err.to_string() })?;
+        if bytes.len() != KEY_SIZE { return Err(SecretsError::InvalidSecretsKey{ reason: format!("expected {} bytes, got {}", KEY_SIZE, bytes.len()) }); }
+        let mut key = [0; KEY_SIZE];
+        key.copy_from_slice(&bytes);
+        Ok(Self(key))
+    }
+
+    /// Reads a key from the given file, which contains it in base64.
+    pub fn from_file(path: &Path) -> Result<Self, SecretsError> {
+        let key = fs::read_to_string(path).map_err(|err| SecretsError::KeyFileReadError{ path: path.to_path_buf(), err })?;
+        Self::from_base64(&key)
+    }
+
+    /// Finds the key to decrypt the secrets file with: from the given key file if any, or else from the `BRANE_SECRETS_KEY` environment variable.
+    /// 
+    /// **Arguments**
+    ///  * `key_file`: The file with the key, if one was given on the command line.
+    /// 
+    /// **Returns**  
+    /// The key, None if neither is given, or a SecretsError if the key could not be read.
+    pub fn resolve(key_file: Option<&Path>) -> Result<Option<Self>, SecretsError> {
+        if let Some(key_file) = key_file { return Self::from_file(key_file).map(Some); }
+        match std::env::var(SECRETS_KEY_ENV) {
+            Ok(key) => Self::from_base64(&key).map(Some),
+            Err(_)  => Ok(None),
+        }
+    }
+
+    /// Returns the key in base64, which is how it's given to brane-job.
+    pub fn to_base64(&self) -> String {
+        base64::encode(&self.0)
+    }
+}
+
+impl std::fmt::Debug for SecretsKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never write the key to a log by accident
+        write!(f, "SecretsKey(..)")
+    }
+}
+
+
+
+/// Returns whether the given contents of a secrets file are encrypted, i.e., start with the ENCRYPTED_SECRETS_HEADER.
+pub fn is_encrypted(contents: &str) -> bool {
+    contents.lines().next().map(|line| line.trim_end() == ENCRYPTED_SECRETS_HEADER).unwrap_or(false)
+}
+
+/// Encrypts the given (plaintext) secrets file.
+/// 
+/// **Arguments**
+///  * `plaintext`: The contents of the plaintext secrets.yml.
+///  * `key`: The key to encrypt it with.
+/// 
+/// **Returns**  
+/// The contents of the encrypted secrets file: the ENCRYPTED_SECRETS_HEADER, followed by a line with the base64-encoded nonce and ciphertext.
+pub fn encrypt_secrets(plaintext: &str, key: &SecretsKey) -> Result<String, SecretsError> {
+    let mut nonce = [0; NONCE_SIZE];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let cipher = XSalsa20Poly1305::new(Key::from_slice(&key.0));
+    let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes()).map_err(|_| SecretsError::SecretsEncryptError)?;
+
+    let mut body = nonce.to_vec();
+    body.extend(ciphertext);
+    Ok(format!("{}\n{}\n", ENCRYPTED_SECRETS_HEADER, base64::encode(&body)))
+}
+
+/// Decrypts the given encrypted secrets file in memory.
+/// 
+/// **Arguments**
+///  * `contents`: The contents of the encrypted file (including the header).
+///  * `key`: The key to decrypt it with.
+///  * `path`: The path of the file, for in errors.
+/// 
+/// **Returns**  
+/// The plaintext secrets.yml, or a SecretsError if it could not be decrypted.
+pub fn decrypt_secrets(contents: &str, key: &SecretsKey, path: &Path) -> Result<String, SecretsError> {
+    let body: String = contents.lines().skip(1).map(str::trim).collect();
+    let body = base64::decode(&body).map_err(|err| SecretsError::InvalidSecretsEncoding{ path: path.to_path_buf(), err })?;
+    if body.len() < NONCE_SIZE + TAG_SIZE { return Err(SecretsError::TruncatedSecretsFile{ path: path.to_path_buf() }); }
+
+    let (nonce, ciphertext) = body.split_at(NONCE_SIZE);
+    let cipher = XSalsa20Poly1305::new(Key::from_slice(&key.0));
+    let plaintext = cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| SecretsError::SecretsDecryptError{ path: path.to_path_buf() })?;
+    String::from_utf8(plaintext).map_err(|_| SecretsError::SecretsNotUtf8{ path: path.to_path_buf() })
+}
+
+
+
 #[derive(Clone, Debug)]
 pub struct Secrets {
     store: Store,
+    /// The key to decrypt the secrets file with, if it's encrypted.
+    key: Option<SecretsKey>,
 }
 
 impl Secrets {
@@ -70,21 +228,38 @@ impl Secrets {
     /// A new instance of a Secrets on success or an SecretsError otherwise.
     pub fn new<S: Into<String>>(store: S) -> Result<Self, SecretsError> {
         match Store::from(store) {
-            Ok(store)   => Ok(Secrets{ store }),
+            Ok(store)   => Ok(Secrets{ store, key: None }),
             Err(reason) => Err(SecretsError::StoreError{ err: reason }),
         }
     }
     /*******/
 
+    /// Constructor for Secrets that are stored in an encrypted file, which are decrypted in memory whenever they are read.
+    /// 
+    /// Plaintext files can still be read this way (see `validate()`).
+    /// 
+    /// **Arguments**
+    ///  * `store`: The location of the secrets file (see `Secrets::new()`).
+    ///  * `key`: The key to decrypt the file with.
+    /// 
+    /// **Returns**  
+    /// A new instance of a Secrets on success or an SecretsError otherwise.
+    pub fn new_encrypted<S: Into<String>>(store: S, key: SecretsKey) -> Result<Self, SecretsError> {
+        let mut secrets = Self::new(store)?;
+        secrets.key = Some(key);
+        Ok(secrets)
+    }
+
     /* TIM */
-    /// Helper function that opens, reads and parses a secrets.yml file.
+    /// Helper function that opens, reads and parses a secrets.yml file, decrypting it first if it's encrypted.
     /// 
     /// **Arguments**
     ///  * `store`: The Store describing where the file is located.
+    ///  * `key`: The key to decrypt the file with, if any.
     /// 
     /// **Returns**  
     /// The file's contents as a map of secrets on success, or a description of the failure as a SecretsError.
-    fn read_store(store: &Store) -> Result<SecretsDocument, SecretsError> {
+    fn read_store(store: &Store, key: Option<&SecretsKey>) -> Result<SecretsDocument, SecretsError> {
         if let Store::File(store_file) = store {
             // Open a handle to the local file
             let infra_handle = match File::open(store_file) {
@@ -98,6 +273,15 @@ impl Secrets {
             if let Err(reason) = infra_reader.read_to_string(&mut infra_file) { return Err(SecretsError::LocalIOError{ path: store_file.clone(), err: reason }); }
             if infra_file.is_empty() { return Err(SecretsError::EmptySecretsFile{ path: store_file.clone() }); }
 
+            // Decrypt it (in memory only) if it's encrypted
+            if is_encrypted(&infra_file) {
+                let key = match key {
+                    Some(key) => key,
+                    None      => { return Err(SecretsError::MissingSecretsKey{ path: store_file.clone() }); }
+                };
+                infra_file = decrypt_secrets(&infra_file, key, store_file)?;
+            }
+
             // Finally, try to parse using serde
             match serde_yaml::from_str::<SecretsDocument>(&infra_file) {
                 Ok(result)  => Ok(result),
@@ -116,11 +300,19 @@ impl Secrets {
     /// Validates the Secrets file.  
     /// Note that this function is slow, as the secrets file isn't actually read from memory.
     /// 
+    /// Also warns if the file is not encrypted, since it's meant to be called at startup.
+    /// 
     /// **Returns**  
     /// Nothing if the file was valid, or a SecretsError detailling why it wasn't otherwise.
     pub fn validate(&self) -> Result<(), SecretsError> {
+        if let Store::File(path) = &self.store {
+            if !fs::read_to_string(path).map(|contents| is_encrypted(&contents)).unwrap_or(true) {
+                warn!("Secrets file '{}' is not encrypted; consider encrypting it with 'brane-secrets encrypt'", path.display());
+            }
+        }
+
         // Simply check if we can read it without any problems
-        match Self::read_store(&self.store) {
+        match Self::read_store(&self.store, self.key.as_ref()) {
             Ok(_)       => Ok(()),
             Err(reason) => Err(reason),
         }
@@ -145,7 +337,7 @@ impl Secrets {
         let secret_key = secret_key.into();
 
         // Read the secrets file
-        let secrets_document = Self::read_store(&self.store)?;
+        let secrets_document = Self::read_store(&self.store, self.key.as_ref())?;
 
         // Return the value
         match secrets_document.get(&secret_key) {
@@ -0,0 +1,394 @@
+use std::fs;
+use std::time::Duration;
+
+use brane_cfg::infrastructure::{self, CpuLimit, InfrastructureError, JobOutputs, Location, LocationArtifacts, LocationCapabilities, LocationTimeouts, MemoryLimit, ResourceLimitError};
+use brane_cfg::Infrastructure;
+
+const INFRA: &str = "locations:
+  limited:
+    kind: local
+    network: brane
+    registry: \"localhost:5000\"
+    callback_to: \"http://brane-clb:50052\"
+    memory_limit: 2GiB
+    cpu_limit: 1.5
+    pids_limit: 256
+  unlimited:
+    kind: local
+    network: brane
+    registry: \"localhost:5000\"
+    callback_to: \"http://brane-clb:50052\"
+    privileged: true
+";
+
+const DOCKER_INFRA: &str = "locations:
+  remote:
+    kind: docker
+    address: \"tcp://docker.example.com:2376\"
+    network: brane
+    registry: \"registry.example.com:5000\"
+    callback_to: \"http://brane-clb:50052\"
+    tls:
+      ca: /etc/brane/docker/ca.pem
+      cert: s$docker-cert
+      key: s$docker-key
+    memory_limit: 1GiB
+  nearby:
+    kind: docker
+    address: \"tcp://localhost:2375\"
+    network: brane
+    registry: \"localhost:5000\"
+    callback_to: \"http://brane-clb:50052\"
+";
+
+/// Writes the given infra.yml to a temporary directory and opens it.
+fn infra(dir: &tempfile::TempDir, contents: &str) -> Infrastructure {
+    let path = dir.path().join("infra.yml");
+    fs::write(&path, contents).unwrap();
+    Infrastructure::new(path.to_string_lossy().to_string()).unwrap()
+}
+
+/// Writes INFRA with the given lines added to its 'unlimited' location, and opens it.
+fn infra_with(dir: &tempfile::TempDir, lines: &str) -> Infrastructure {
+    infra(dir, &INFRA.replace("    privileged: true\n", &format!("    privileged: true\n{}", lines)))
+}
+
+#[test]
+fn parses_memory_sizes() {
+    assert_eq!("2GiB".parse(), Ok(MemoryLimit(2 * 1024 * 1024 * 1024)));
+    assert_eq!("2g".parse(), Ok(MemoryLimit(2 * 1024 * 1024 * 1024)));
+    assert_eq!("512 MB".parse(), Ok(MemoryLimit(512 * 1000 * 1000)));
+    assert_eq!("1.5KiB".parse(), Ok(MemoryLimit(1536)));
+    assert_eq!("1048576".parse(), Ok(MemoryLimit(1048576)));
+
+    assert!(matches!("lots".parse::<MemoryLimit>(), Err(ResourceLimitError::IllegalMemorySize{ .. })));
+    assert!(matches!("-1GiB".parse::<MemoryLimit>(), Err(ResourceLimitError::IllegalMemorySize{ .. })));
+    assert!(matches!("0".parse::<MemoryLimit>(), Err(ResourceLimitError::IllegalMemorySize{ .. })));
+    assert!(matches!("2 gallons".parse::<MemoryLimit>(), Err(ResourceLimitError::UnknownMemoryUnit{ .. })));
+    assert!(matches!("99999999TiB".parse::<MemoryLimit>(), Err(ResourceLimitError::LimitTooLarge{ .. })));
+}
+
+#[test]
+fn parses_cpus() {
+    assert_eq!("1.5".parse(), Ok(CpuLimit(1_500_000_000)));
+    assert_eq!("4".parse(), Ok(CpuLimit(4_000_000_000)));
+    assert_eq!("0.25".parse(), Ok(CpuLimit(250_000_000)));
+
+    assert!(matches!("0".parse::<CpuLimit>(), Err(ResourceLimitError::IllegalCpus{ .. })));
+    assert!(matches!("-2".parse::<CpuLimit>(), Err(ResourceLimitError::IllegalCpus{ .. })));
+    assert!(matches!("many".parse::<CpuLimit>(), Err(ResourceLimitError::IllegalCpus{ .. })));
+}
+
+#[test]
+fn reads_limits_from_infra_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("infra.yml");
+    fs::write(&path, INFRA).unwrap();
+    let infra = Infrastructure::new(path.to_string_lossy().to_string()).unwrap();
+    infra.validate().unwrap();
+
+    match infra.get_location_metadata("limited").unwrap() {
+        Location::Local{ memory_limit, cpu_limit, pids_limit, privileged, .. } => {
+            assert_eq!(memory_limit, Some(MemoryLimit(2 * 1024 * 1024 * 1024)));
+            assert_eq!(cpu_limit, Some(CpuLimit(1_500_000_000)));
+            assert_eq!(pids_limit, Some(256));
+            assert!(!privileged);
+        },
+        location => panic!("Expected a local location, got {:?}", location),
+    }
+    match infra.get_location_metadata("unlimited").unwrap() {
+        Location::Local{ memory_limit, cpu_limit, pids_limit, privileged, .. } => {
+            assert_eq!((memory_limit, cpu_limit, pids_limit), (None, None, None));
+            assert!(privileged);
+        },
+        location => panic!("Expected a local location, got {:?}", location),
+    }
+}
+
+#[test]
+fn rejects_illegal_limits() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("infra.yml");
+    fs::write(&path, INFRA.replace("2GiB", "2 bananas")).unwrap();
+    let infra = Infrastructure::new(path.to_string_lossy().to_string()).unwrap();
+    let err = infra.validate().unwrap_err().to_string();
+    assert!(err.contains("bananas"), "{}", err);
+}
+
+#[test]
+fn reads_docker_locations() {
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra(&dir, DOCKER_INFRA);
+    infra.validate().unwrap();
+
+    let remote = infra.get_location_metadata("remote").unwrap();
+    assert_eq!(remote.get_address(), "tcp://docker.example.com:2376");
+    assert_eq!(remote.get_registry(), "registry.example.com:5000");
+    match remote {
+        Location::Docker{ tls: Some(tls), memory_limit, privileged, .. } => {
+            assert_eq!(tls.ca, "/etc/brane/docker/ca.pem");
+            assert_eq!(tls.cert, "s$docker-cert");
+            assert_eq!(memory_limit, Some(MemoryLimit(1024 * 1024 * 1024)));
+            assert!(!privileged);
+        },
+        location => panic!("Expected a Docker location with TLS, got {:?}", location),
+    }
+
+    // Daemons on localhost don't need TLS
+    assert!(matches!(infra.get_location_metadata("nearby").unwrap(), Location::Docker{ tls: None, .. }));
+}
+
+#[test]
+fn requires_tls_for_remote_docker() {
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra(&dir, &DOCKER_INFRA.replace("tcp://localhost:2375", "tcp://10.0.0.5:2375"));
+    assert!(matches!(infra.validate(), Err(InfrastructureError::MissingDockerTls{ ref location, .. }) if location == "nearby"));
+    assert!(matches!(infra.get_location_metadata("nearby"), Err(InfrastructureError::MissingDockerTls{ .. })));
+    // The other location is still fine by itself
+    assert!(infra.get_location_metadata("remote").is_ok());
+
+    assert_eq!(infrastructure::is_local_docker_address("unix:///var/run/docker.sock"), Ok(true));
+    assert_eq!(infrastructure::is_local_docker_address("tcp://127.0.0.1:2375"), Ok(true));
+    assert_eq!(infrastructure::is_local_docker_address("tcp://[::1]:2375"), Ok(true));
+    assert_eq!(infrastructure::is_local_docker_address("tcp://localhost.example.com:2375"), Ok(false));
+    assert!(infrastructure::is_local_docker_address("not an address").is_err());
+}
+
+#[test]
+fn reads_location_timeouts() {
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra_with(&dir, "    timeouts:\n      created: 600\n      heartbeat: 120\n");
+    infra.validate().unwrap();
+
+    assert_eq!(infra.get_location_metadata("unlimited").unwrap().get_timeouts(), LocationTimeouts{ created: Some(600), heartbeat: Some(120), ..Default::default() });
+    // Locations without timeouts use all the driver's defaults
+    assert_eq!(infra.get_location_metadata("limited").unwrap().get_timeouts(), LocationTimeouts::default());
+}
+
+#[test]
+fn rejects_illegal_timeouts() {
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra_with(&dir, "    timeouts:\n      heartbeat: 0\n");
+    assert!(matches!(infra.validate(), Err(InfrastructureError::IllegalTimeout{ ref location, timeout: "heartbeat", value: 0 }) if location == "unlimited"));
+
+    let infra = infra_with(&dir, &format!("    timeouts:\n      result: {}\n", infrastructure::MAX_TIMEOUT + 1));
+    assert!(matches!(infra.validate(), Err(InfrastructureError::IllegalTimeout{ timeout: "result", .. })));
+
+    // Typos are not silently ignored
+    let infra = infra_with(&dir, "    timeouts:\n      heartbeats: 10\n");
+    assert!(infra.validate().is_err());
+}
+
+#[test]
+fn isolates_sessions_by_default() {
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra_with(&dir, "    isolate_sessions: false\n");
+    infra.validate().unwrap();
+
+    assert!(infra.get_location_metadata("limited").unwrap().isolates_sessions());
+    assert!(!infra.get_location_metadata("unlimited").unwrap().isolates_sessions());
+}
+
+#[test]
+fn reads_payload_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra_with(&dir, "    payload_dir: /brane/payloads\n");
+    infra.validate().unwrap();
+
+    assert_eq!(infra.get_location_metadata("unlimited").unwrap().get_payload_dir(), Some("/brane/payloads"));
+    assert_eq!(infra.get_location_metadata("limited").unwrap().get_payload_dir(), None);
+}
+
+#[test]
+fn reads_artifacts() {
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra_with(&dir, "    artifacts:\n      dir: /brane/artifacts\n      max_size: 1048576\n");
+    infra.validate().unwrap();
+
+    assert_eq!(infra.get_location_metadata("unlimited").unwrap().get_artifacts(), &LocationArtifacts{ dir: Some(String::from("/brane/artifacts")), host_dir: None, max_size: Some(1048576), max_total: None });
+    assert_eq!(infra.get_location_metadata("limited").unwrap().get_artifacts(), &LocationArtifacts::default());
+
+    // Containers mount the same directory from the host, unless told otherwise
+    assert_eq!(infra.get_location_metadata("unlimited").unwrap().get_artifacts().mount(), Some(("/brane/artifacts", "/brane/artifacts")));
+    assert_eq!(infra.get_location_metadata("limited").unwrap().get_artifacts().mount(), None);
+    let infra = infra_with(&dir, "    artifacts:\n      dir: /brane/artifacts\n      host_dir: /srv/brane/artifacts\n");
+    assert_eq!(infra.get_location_metadata("unlimited").unwrap().get_artifacts().mount(), Some(("/srv/brane/artifacts", "/brane/artifacts")));
+
+    // Typos are not silently ignored
+    let infra = infra_with(&dir, "    artifacts:\n      max_totl: 1\n");
+    assert!(infra.validate().is_err());
+}
+
+#[test]
+fn reads_job_outputs() {
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra(&dir, "locations:
+  cluster:
+    kind: slurm
+    address: \"slurm.example.com\"
+    runtime: singularity
+    registry: \"registry.example.com:5000\"
+    callback_to: \"http://brane-clb:50052\"
+    credentials:
+      mechanism: ssh-password
+      username: brane
+      password: brane
+    output_dir: /scratch/brane/outputs/
+    output_retention: 86400
+  debugging:
+    kind: vm
+    address: \"vm.example.com\"
+    runtime: docker
+    registry: \"registry.example.com:5000\"
+    callback_to: \"http://brane-clb:50052\"
+    credentials:
+      mechanism: ssh-password
+      username: brane
+      password: brane
+    output_dir: /tmp/brane
+    output_retention: 3600
+    keep_job_output: true
+");
+
+    let outputs = infra.get_location_metadata("cluster").unwrap().get_job_outputs();
+    assert_eq!(outputs, JobOutputs{ dir: Some(String::from("/scratch/brane/outputs/")), retention: Some(86400), keep: false });
+    assert_eq!(outputs.cleanup_after(), Some(Duration::from_secs(86400)));
+
+    // Keeping the output disables the cleanup
+    let outputs = infra.get_location_metadata("debugging").unwrap().get_job_outputs();
+    assert!(outputs.keep);
+    assert_eq!(outputs.cleanup_after(), None);
+
+    // As does omitting the directory, and non-Xenon locations never have one
+    assert_eq!(JobOutputs{ dir: None, retention: Some(60), keep: false }.cleanup_after(), None);
+    assert_eq!(infra_with(&dir, "").get_location_metadata("limited").unwrap().get_job_outputs(), JobOutputs::default());
+}
+
+#[test]
+fn reads_kube_job_settings() {
+    let location = |lines: &str| format!("  {}:
+    kind: kube
+    address: \"https://kube.example.com:6443\"
+    namespace: brane
+    registry: \"registry.example.com:5000\"
+    callback_to: \"http://brane-clb:50052\"
+    credentials:
+      mechanism: config
+      file: s$kubeconfig
+{}", if lines.is_empty() { "defaults" } else { "custom" }, lines);
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra(&dir, &format!("locations:\n{}{}", location(""), location("    backoff_limit: 0\n    ttl_seconds: 3600\n    create_namespace: true\n")));
+    infra.validate().unwrap();
+
+    // The defaults are what brane-job always used, and namespaces have to exist
+    match infra.get_location_metadata("defaults").unwrap() {
+        Location::Kube{ backoff_limit, ttl_seconds, create_namespace, .. } => assert_eq!((backoff_limit, ttl_seconds, create_namespace), (3, 120, false)),
+        location => panic!("Expected a Kube location, got {:?}", location),
+    }
+    match infra.get_location_metadata("custom").unwrap() {
+        Location::Kube{ backoff_limit, ttl_seconds, create_namespace, .. } => assert_eq!((backoff_limit, ttl_seconds, create_namespace), (0, 3600, true)),
+        location => panic!("Expected a Kube location, got {:?}", location),
+    }
+}
+
+#[test]
+fn reads_array_support() {
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra(&dir, "locations:
+  cluster:
+    kind: slurm
+    address: \"slurm.example.com\"
+    runtime: singularity
+    registry: \"registry.example.com:5000\"
+    callback_to: \"http://brane-clb:50052\"
+    credentials:
+      mechanism: ssh-password
+      username: brane
+      password: brane
+    supports_arrays: true
+  kube:
+    kind: kube
+    address: \"https://kube.example.com:6443\"
+    namespace: brane
+    registry: \"registry.example.com:5000\"
+    callback_to: \"http://brane-clb:50052\"
+    credentials:
+      mechanism: config
+      file: s$kubeconfig
+");
+    infra.validate().unwrap();
+
+    // Arrays have to be enabled explicitly, and locations without job arrays never have them
+    assert!(infra.get_location_metadata("cluster").unwrap().supports_arrays());
+    assert!(!infra.get_location_metadata("kube").unwrap().supports_arrays());
+    assert!(!infra_with(&dir, "").get_location_metadata("unlimited").unwrap().supports_arrays());
+}
+
+#[test]
+fn reads_location_capabilities() {
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra(&dir, "locations:
+  gpu:
+    kind: local
+    network: brane
+    registry: \"localhost:5000\"
+    callback_to: \"http://brane-clb:50052\"
+    capabilities:
+      gpu: true
+      memory: 16GiB
+      os: linux
+      arch: amd64
+      cost: 10
+  plain:
+    kind: local
+    network: brane
+    registry: \"localhost:5000\"
+    callback_to: \"http://brane-clb:50052\"
+");
+    infra.validate().unwrap();
+
+    assert_eq!(infra.get_location_metadata("gpu").unwrap().get_capabilities(), &LocationCapabilities {
+        gpu    : true,
+        memory : Some(MemoryLimit(16 * 1024 * 1024 * 1024)),
+        os     : Some(String::from("linux")),
+        arch   : Some(String::from("amd64")),
+        cost   : 10,
+    });
+    // Locations that declare nothing offer nothing
+    assert_eq!(infra.get_location_metadata("plain").unwrap().get_capabilities(), &LocationCapabilities::default());
+}
+
+#[test]
+fn lists_secret_references() {
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra(&dir, &format!("{}  kube:
+    kind: kube
+    address: \"https://kube.example.com:6443\"
+    namespace: brane
+    registry: \"registry.example.com:5000\"
+    callback_to: \"http://brane-clb:50052\"
+    credentials:
+      mechanism: config
+      file: s$kubeconfig
+    registry_credentials:
+      username: brane
+      password: s$registry-password
+  slurm:
+    kind: slurm
+    address: slurm.example.com
+    runtime: singularity
+    registry: \"registry.example.com:5000\"
+    callback_to: \"http://brane-clb:50052\"
+    credentials:
+      mechanism: ssh-certificate
+      username: s$slurm-user
+      certificate: s$slurm-certificate
+      passphrase: s$slurm-user
+", DOCKER_INFRA));
+
+    let references = |location: &str| infra.get_location_metadata(location).unwrap().secret_references();
+    assert_eq!(references("kube"), vec![ "kubeconfig", "registry-password" ]);
+    assert_eq!(references("slurm"), vec![ "slurm-user", "slurm-certificate" ]);
+    assert_eq!(references("remote"), vec![ "docker-cert", "docker-key" ]);
+    assert!(references("nearby").is_empty());
+}
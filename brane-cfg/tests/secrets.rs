@@ -0,0 +1,80 @@
+use std::fs;
+use std::path::Path;
+
+use brane_cfg::secrets::{self, Secrets, SecretsError, SecretsKey};
+
+const PLAINTEXT: &str = "ssh-password: hunter2\nkube-config: |\n  apiVersion: v1\n";
+
+/// Writes the given contents to a secrets file in the given directory, returning its path.
+fn write(dir: &Path, contents: &str) -> String {
+    let path = dir.join("secrets.yml");
+    fs::write(&path, contents).unwrap();
+    path.to_string_lossy().to_string()
+}
+
+#[test]
+fn encrypted_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = SecretsKey::generate();
+    let encrypted = secrets::encrypt_secrets(PLAINTEXT, &key).unwrap();
+    assert!(secrets::is_encrypted(&encrypted));
+    assert!(!encrypted.contains("hunter2"));
+
+    let secrets = Secrets::new_encrypted(write(dir.path(), &encrypted), key.clone()).unwrap();
+    secrets.validate().unwrap();
+    assert_eq!(secrets.get("ssh-password").unwrap(), "hunter2");
+    assert_eq!(secrets.get("kube-config").unwrap(), "apiVersion: v1\n");
+
+    // The key survives being written down
+    let key = SecretsKey::from_base64(&format!("{}\n", key.to_base64())).unwrap();
+    assert_eq!(secrets::decrypt_secrets(&encrypted, &key, Path::new("secrets.yml")).unwrap(), PLAINTEXT);
+}
+
+#[test]
+fn wrong_key_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let encrypted = secrets::encrypt_secrets(PLAINTEXT, &SecretsKey::generate()).unwrap();
+
+    let secrets = Secrets::new_encrypted(write(dir.path(), &encrypted), SecretsKey::generate()).unwrap();
+    assert!(matches!(secrets.validate(), Err(SecretsError::SecretsDecryptError{ .. })));
+
+    // Nor can it be read without any key
+    let secrets = Secrets::new(write(dir.path(), &encrypted)).unwrap();
+    assert!(matches!(secrets.get("ssh-password"), Err(SecretsError::MissingSecretsKey{ .. })));
+}
+
+#[test]
+fn truncated_ciphertext_is_rejected() {
+    let key = SecretsKey::generate();
+    let encrypted = secrets::encrypt_secrets(PLAINTEXT, &key).unwrap();
+    let (header, body) = encrypted.split_once('\n').unwrap();
+    let body = base64::decode(body.trim()).unwrap();
+
+    // Too short to even hold the nonce and tag
+    let truncated = format!("{}\n{}\n", header, base64::encode(&body[..20]));
+    assert!(matches!(secrets::decrypt_secrets(&truncated, &key, Path::new("secrets.yml")), Err(SecretsError::TruncatedSecretsFile{ .. })));
+
+    // Missing only the end, which the authentication catches
+    let truncated = format!("{}\n{}\n", header, base64::encode(&body[..body.len() - 1]));
+    assert!(matches!(secrets::decrypt_secrets(&truncated, &key, Path::new("secrets.yml")), Err(SecretsError::SecretsDecryptError{ .. })));
+}
+
+#[test]
+fn plaintext_still_works() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write(dir.path(), PLAINTEXT);
+
+    let secrets = Secrets::new(path.clone()).unwrap();
+    secrets.validate().unwrap();
+    assert_eq!(secrets.get("ssh-password").unwrap(), "hunter2");
+
+    // Also when a key is given
+    let secrets = Secrets::new_encrypted(path, SecretsKey::generate()).unwrap();
+    assert_eq!(secrets.get("ssh-password").unwrap(), "hunter2");
+}
+
+#[test]
+fn invalid_keys_are_rejected() {
+    assert!(matches!(SecretsKey::from_base64("not base64!"), Err(SecretsError::InvalidSecretsKey{ .. })));
+    assert!(matches!(SecretsKey::from_base64(&base64::encode([0u8; 16])), Err(SecretsError::InvalidSecretsKey{ .. })));
+}
@@ -15,32 +15,17 @@ pub struct CallbackHandler {
     pub producer: FutureProducer,
 }
 
-#[tonic::async_trait]
-impl grpc::CallbackService for CallbackHandler {
-    async fn callback(
-        &self,
-        request: Request<grpc::CallbackRequest>,
-    ) -> Result<Response<grpc::CallbackReply>, Status> {
-        let message = request.into_inner();
-
-        let kind = CallbackKind::from_i32(message.kind).unwrap();
-        let application = message.application;
-        let job = message.job;
-        let location = message.location;
-        let order = message.order;
-        let payload = message.payload;
-
-        info!(
-            "Received '{:?}' callback for job '{}' at location '{}', with payload size: {} (bytes).",
-            kind,
-            job,
-            location,
-            payload.len(),
-        );
-
+impl CallbackHandler {
+    /// Sends the given callback on the output topic.
+    /// 
+    /// **Arguments**
+    ///  * `callback`: The Callback (or Batch of them) to send.
+    /// 
+    /// **Returns**  
+    /// The reply to give to the branelet.
+    async fn send(&self, callback: Callback) -> grpc::CallbackReply {
         // Turn callback into a Kafka message
-        let msg_key = format!("{}+{}", job, order);
-        let callback = Callback::new(kind, job, application, location, order, payload);
+        let msg_key = format!("{}+{}", callback.job, callback.order);
         let mut msg_payload = BytesMut::with_capacity(64);
         callback.encode(&mut msg_payload).unwrap();
 
@@ -56,7 +41,51 @@ impl grpc::CallbackService for CallbackHandler {
             (String::from("202"), String::new())
         };
 
-        let reply = grpc::CallbackReply { status, message };
-        Ok(Response::new(reply))
+        grpc::CallbackReply { status, message }
+    }
+}
+
+/// Converts a callback as received over gRPC to the one sent on Kafka.
+fn to_callback(message: grpc::CallbackRequest) -> Callback {
+    let kind = CallbackKind::from_i32(message.kind).unwrap();
+    Callback::new(kind, message.job, message.application, message.location, message.order, message.payload)
+}
+
+#[tonic::async_trait]
+impl grpc::CallbackService for CallbackHandler {
+    async fn callback(
+        &self,
+        request: Request<grpc::CallbackRequest>,
+    ) -> Result<Response<grpc::CallbackReply>, Status> {
+        let callback = to_callback(request.into_inner());
+
+        info!(
+            "Received '{:?}' callback for job '{}' at location '{}', with payload size: {} (bytes).",
+            callback.kind(),
+            callback.job,
+            callback.location,
+            callback.payload.len(),
+        );
+
+        Ok(Response::new(self.send(callback).await))
+    }
+
+    async fn callback_batch(
+        &self,
+        request: Request<grpc::CallbackBatchRequest>,
+    ) -> Result<Response<grpc::CallbackReply>, Status> {
+        let callbacks: Vec<Callback> = request.into_inner().callbacks.into_iter().map(to_callback).collect();
+        if callbacks.is_empty() { return Err(Status::invalid_argument("Empty callback batch")); }
+
+        info!(
+            "Received batch of {} callbacks ({}) for job '{}' at location '{}'.",
+            callbacks.len(),
+            callbacks.iter().map(|callback| format!("{:?}", callback.kind())).collect::<Vec<String>>().join(", "),
+            callbacks[0].job,
+            callbacks[0].location,
+        );
+
+        // Send them as one message, so they are handled in order
+        Ok(Response::new(self.send(Callback::batch(callbacks)).await))
     }
 }
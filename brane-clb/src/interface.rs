@@ -1,4 +1,5 @@
-use prost::{Enumeration, Message};
+use bytes::BytesMut;
+use prost::{DecodeError, Enumeration, Message};
 use std::fmt::{Display, Formatter, Result as FResult};
 
 #[derive(Clone, PartialEq, Message)]
@@ -42,6 +43,42 @@ impl Callback {
             payload: payload.into(),
         }
     }
+
+    /// Packs the given callbacks into a single Batch callback, which is sent as one message. It takes the job, application, location and order of the first callback.
+    /// 
+    /// **Arguments**
+    ///  * `callbacks`: The callbacks to pack, in the order in which they happened.
+    /// 
+    /// **Returns**  
+    /// A new Callback of kind Batch, with the encoded CallbackBatch as payload.
+    pub fn batch(callbacks: Vec<Callback>) -> Self {
+        let (job, application, location, order) = match callbacks.first() {
+            Some(first) => (first.job.clone(), first.application.clone(), first.location.clone(), first.order),
+            None        => (String::new(), String::new(), String::new(), 0),
+        };
+
+        let batch = CallbackBatch { callbacks };
+        let mut payload = BytesMut::with_capacity(batch.encoded_len());
+        batch.encode(&mut payload).unwrap();
+        Callback::new(CallbackKind::Batch, job, application, location, order, payload.to_vec())
+    }
+
+    /// Unpacks this callback into the callbacks it carries.
+    /// 
+    /// **Returns**  
+    /// The callbacks in a Batch callback (in order), this callback on its own if it isn't one, or a DecodeError if the batch could not be decoded.
+    pub fn unpack(self) -> Result<Vec<Callback>, DecodeError> {
+        if self.kind != CallbackKind::Batch as i32 { return Ok(vec![ self ]); }
+        Ok(CallbackBatch::decode(&self.payload[..])?.callbacks)
+    }
+}
+
+/// The payload of a Batch callback: a number of callbacks that are sent as one message.
+#[derive(Clone, PartialEq, Message)]
+pub struct CallbackBatch {
+    /// The callbacks, in the order in which they happened.
+    #[prost(tag = "1", repeated, message)]
+    pub callbacks: Vec<Callback>,
 }
 
 /// **Edited: adding failure states.**
@@ -65,6 +102,12 @@ pub enum CallbackKind {
     Stopped = 10,
     Failed = 11,
     Finished = 12,
+
+    /// Carries a CallbackBatch as payload. Only used on Kafka.
+    Batch = 13,
+
+    /// The outputs of the package call could not be stored as artifacts.
+    StoreFailed = 14,
 }
 
 impl Display for CallbackKind {
@@ -72,3 +115,14 @@ impl Display for CallbackKind {
         write!(f, "{}", format!("{:?}", self).to_uppercase())
     }
 }
+
+/// Decodes a message from the callback topic into the callbacks it carries, unpacking it if it's a batch.
+/// 
+/// **Arguments**
+///  * `payload`: The raw payload of the message.
+/// 
+/// **Returns**  
+/// The callbacks in the message (in order), or a DecodeError if it could not be decoded.
+pub fn decode_callbacks(payload: &[u8]) -> Result<Vec<Callback>, DecodeError> {
+    Callback::decode(payload)?.unpack()
+}
@@ -0,0 +1,41 @@
+use brane_clb::interface::{decode_callbacks, Callback, CallbackBatch, CallbackKind};
+use prost::Message;
+
+fn callback(kind: CallbackKind, order: i32, payload: &str) -> Callback {
+    Callback::new(kind, String::from("job-1"), String::from("app-1"), String::from("local"), order, payload.as_bytes().to_vec())
+}
+
+fn encode(callback: &Callback) -> Vec<u8> {
+    let mut payload = Vec::new();
+    callback.encode(&mut payload).unwrap();
+    payload
+}
+
+#[test]
+fn single_callback_roundtrip() {
+    let original = callback(CallbackKind::Started, 3, "");
+    assert_eq!(decode_callbacks(&encode(&original)).unwrap(), vec![ original ]);
+}
+
+#[test]
+fn batch_roundtrip_keeps_order() {
+    let callbacks = vec![
+        callback(CallbackKind::Heartbeat, 4, ""),
+        callback(CallbackKind::Heartbeat, 5, ""),
+        callback(CallbackKind::Finished, 6, "{\"value\": 42}"),
+    ];
+    let batch = Callback::batch(callbacks.clone());
+    assert_eq!(batch.kind(), CallbackKind::Batch);
+    assert_eq!((batch.job.as_str(), batch.order), ("job-1", 4));
+
+    // The payload is a plain CallbackBatch
+    assert_eq!(CallbackBatch::decode(&batch.payload[..]).unwrap().callbacks, callbacks);
+    assert_eq!(decode_callbacks(&encode(&batch)).unwrap(), callbacks);
+}
+
+#[test]
+fn broken_batch_is_rejected() {
+    let mut batch = Callback::batch(vec![ callback(CallbackKind::Heartbeat, 1, "") ]);
+    batch.payload = vec![ 0xff, 0xff, 0xff ];
+    assert!(decode_callbacks(&encode(&batch)).is_err());
+}
@@ -0,0 +1,27 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// The architectures that we release init binaries for.
+const ARCHS: [&str; 2] = [ "amd64", "arm64" ];
+
+/// Embeds the SHA-256 digests of the released init binaries, so the CLI can check the ones it downloads without trusting a digest that comes from the same place. The digests are read from the `branelet-<arch>.sha256` files (as `sha256sum` writes them) in the directory given with `BRANELET_DIGESTS_DIR`; without it, none are embedded.
+fn main() -> Result<(), std::io::Error> {
+    println!("cargo:rerun-if-env-changed=BRANELET_DIGESTS_DIR");
+    let mut digests = vec![];
+    if let Some(dir) = env::var_os("BRANELET_DIGESTS_DIR").map(PathBuf::from) {
+        for arch in ARCHS.iter() {
+            let path = dir.join(format!("branelet-{}.sha256", arch));
+            println!("cargo:rerun-if-changed={}", path.display());
+            if !path.exists() { continue; }
+            let digest = fs::read_to_string(&path)?.split_whitespace().next().unwrap_or_default().to_lowercase();
+            if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("'{}' does not start with a SHA-256 digest", path.display())));
+            }
+            digests.push(format!("(\"{}\", \"{}\")", arch, digest));
+        }
+    }
+
+    let out = PathBuf::from(env::var_os("OUT_DIR").expect("Cargo sets OUT_DIR")).join("branelet_digests.rs");
+    fs::write(out, format!("&[{}]", digests.join(", ")))
+}
@@ -0,0 +1,577 @@
+/* ARCHIVE.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 20:31:44
+ * Last edited:
+ *   15 Oct 2026, 20:31:44
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Exports packages to and imports them from self-contained archives, to
+ *   move them to machines that cannot reach a registry.
+ *
+ *   An archive is a gzipped tarball with the package directory (without
+ *   its image) under `package/`, a `docker save` of the image as
+ *   `image.tar`, and an `export.json` manifest that lists the sha256
+ *   digest of every other file in the archive.
+**/
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bollard::Docker;
+use bollard::image::{ImportImageOptions, TagImageOptions};
+use console::style;
+use dialoguer::Confirm;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use futures_util::stream::{StreamExt, TryStreamExt};
+use hyper::Body;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs::File as TokioFile;
+use tokio_util::codec::{BytesCodec, FramedRead};
+
+use specifications::package::{PackageInfo, PackageInfoError};
+use specifications::version::Version;
+
+use crate::errors::UtilError;
+use crate::index_cache;
+use crate::lock::{lock_timeout, LockError, PackageLock};
+use crate::runtime;
+use crate::utils::{ensure_package_dir, ensure_packages_dir};
+
+
+/***** CONSTANTS *****/
+/// The version of the archive layout that we write (and the only one we read).
+pub const ARCHIVE_FORMAT: u32 = 1;
+
+/// The name of the manifest in an archive.
+pub const MANIFEST_FILE: &str = "export.json";
+/// The directory in an archive that contains the package directory.
+pub const PACKAGE_DIR: &str = "package";
+/// The name of the image file, both in an archive and in a package directory.
+pub const IMAGE_FILE: &str = "image.tar";
+
+/// The marker that hides a directory in a package directory from being read as a package version.
+const HIDDEN_MARKER: &str = ".lock";
+
+
+
+
+
+/***** ERRORS *****/
+/// Collects errors that relate to exporting and importing package archives.
+#[derive(Debug)]
+pub enum ArchiveError {
+    /// Could not find the package to export
+    PackageDirError{ err: UtilError },
+    /// Could not lock the package
+    LockError{ err: LockError },
+    /// Could not read a package.yml
+    PackageInfoError{ path: PathBuf, err: PackageInfoError },
+
+    /// Could not connect to the local Docker daemon
+    DockerConnectError{ err: bollard::errors::Error },
+    /// Could not save an image from the Docker daemon
+    ImageSaveError{ image: String, err: bollard::errors::Error },
+    /// Could not load an image into the Docker daemon
+    ImageLoadError{ path: PathBuf, err: bollard::errors::Error },
+    /// The Docker daemon refused to load an image
+    ImageLoadFailure{ path: PathBuf, message: String },
+    /// Could not find an image in the Docker daemon
+    ImageInspectError{ image: String, err: bollard::errors::Error },
+    /// The image of a package is not the one its package info says it is
+    ImageDigestMismatch{ name: String, version: Version, expected: String, got: String },
+
+    /// The manifest of an archive could not be parsed
+    ManifestParseError{ path: PathBuf, err: serde_json::Error },
+    /// The manifest of an archive could not be serialized
+    ManifestEncodeError{ err: serde_json::Error },
+    /// The archive was written in a layout that we don't know
+    UnsupportedFormat{ path: PathBuf, format: u32 },
+    /// The manifest refers to a file outside of the archive
+    IllegalFile{ path: PathBuf, file: String },
+    /// A file that the manifest lists is not in the archive
+    MissingFile{ path: PathBuf, file: String },
+    /// The archive contains a file that the manifest does not list
+    UnlistedFile{ path: PathBuf, file: String },
+    /// A file in the archive does not match its digest
+    DigestMismatch{ path: PathBuf, file: String, expected: String, got: String },
+    /// The package in the archive is not the one that the manifest describes
+    ManifestMismatch{ path: PathBuf, what: &'static str, manifest: String, package: String },
+
+    /// The package already exists locally, and we were not allowed to overwrite it
+    PackageConflict{ name: String, version: Version },
+    /// Could not ask the user whether to overwrite a package
+    ConfirmError{ err: io::Error },
+
+    /// Could not create a temporary directory or file
+    TempDirError{ err: io::Error },
+    /// Could not read a file
+    FileReadError{ path: PathBuf, err: io::Error },
+    /// Could not write a file
+    FileWriteError{ path: PathBuf, err: io::Error },
+    /// Could not pack or unpack an archive
+    TarError{ path: PathBuf, err: io::Error },
+}
+
+impl Display for ArchiveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        use ArchiveError::*;
+        match self {
+            PackageDirError{ err }           => write!(f, "{}", err),
+            ArchiveError::LockError{ err }   => write!(f, "{}", err),
+            ArchiveError::PackageInfoError{ path, err } => write!(f, "Could not read package info '{}': {}", path.display(), err),
+
+            DockerConnectError{ err }                           => write!(f, "Could not connect to local Docker daemon: {}", err),
+            ImageSaveError{ image, err }                        => write!(f, "Could not save image '{}': {}", image, err),
+            ImageLoadError{ path, err }                         => write!(f, "Could not load image file '{}': {}", path.display(), err),
+            ImageLoadFailure{ path, message }                   => write!(f, "Docker refused to load image file '{}': {}", path.display(), message),
+            ImageInspectError{ image, err }                     => write!(f, "Could not find image '{}' in the local Docker daemon: {}", image, err),
+            ImageDigestMismatch{ name, version, expected, got } => write!(f, "Image of package '{}' (version {}) has digest '{}', but its package info says it should be '{}'", name, version, got, expected),
+
+            ManifestParseError{ path, err }                      => write!(f, "Could not parse the manifest of archive '{}': {}", path.display(), err),
+            ManifestEncodeError{ err }                           => write!(f, "Could not serialize archive manifest: {}", err),
+            UnsupportedFormat{ path, format }                    => write!(f, "Archive '{}' has format {}, but only format {} is supported (export it again with this version of brane)", path.display(), format, ARCHIVE_FORMAT),
+            IllegalFile{ path, file }                            => write!(f, "Manifest of archive '{}' lists illegal path '{}'", path.display(), file),
+            MissingFile{ path, file }                            => write!(f, "Archive '{}' does not contain '{}'", path.display(), file),
+            UnlistedFile{ path, file }                           => write!(f, "Archive '{}' contains '{}', which its manifest does not list", path.display(), file),
+            DigestMismatch{ path, file, expected, got }          => write!(f, "Digest of '{}' in archive '{}' does not match: expected '{}', got '{}' (the archive is corrupt)", file, path.display(), expected, got),
+            ManifestMismatch{ path, what, manifest, package }    => write!(f, "Archive '{}' claims to contain {} '{}', but its package.yml says '{}'", path.display(), what, manifest, package),
+
+            PackageConflict{ name, version } => write!(f, "Package '{}' (version {}) already exists locally (use '--force' to overwrite it)", name, version),
+            ConfirmError{ err }              => write!(f, "Could not ask for confirmation: {}", err),
+
+            TempDirError{ err }         => write!(f, "Could not create temporary directory: {}", err),
+            FileReadError{ path, err }  => write!(f, "Could not read file '{}': {}", path.display(), err),
+            FileWriteError{ path, err } => write!(f, "Could not write file '{}': {}", path.display(), err),
+            TarError{ path, err }       => write!(f, "Could not pack or unpack archive '{}': {}", path.display(), err),
+        }
+    }
+}
+
+impl Error for ArchiveError {}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// The manifest of an archive, which describes the package in it and every file that belongs to it.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ExportManifest {
+    /// The layout of the archive (see `ARCHIVE_FORMAT`).
+    pub format  : u32,
+    /// The name of the package in the archive.
+    pub name    : String,
+    /// The version of the package in the archive.
+    pub version : Version,
+    /// The digest of the package's image, if it has one.
+    pub digest  : Option<String>,
+    /// The sha256 digest (as `sha256:<hex>`) of every file in the archive except the manifest, by its path in the archive.
+    pub files   : BTreeMap<String, String>,
+}
+
+
+
+/// Removes a directory when dropped, unless it is disarmed first. Used to clean up after an operation that fails halfway.
+struct Cleanup {
+    /// The directory to remove, if still armed.
+    path : Option<PathBuf>,
+}
+
+impl Cleanup {
+    /// Constructor for the Cleanup, which removes the given directory unless disarmed.
+    #[inline]
+    fn new(path: PathBuf) -> Self { Self{ path: Some(path) } }
+
+    /// Keeps the directory after all.
+    #[inline]
+    fn disarm(mut self) { self.path = None; }
+}
+
+impl Drop for Cleanup {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            debug!("Cleaning up '{}'", path.display());
+            if let Err(err) = fs::remove_dir_all(&path) {
+                if err.kind() != io::ErrorKind::NotFound { warn!("Could not remove '{}': {}", path.display(), err); }
+            }
+        }
+    }
+}
+
+
+
+
+
+/***** SUBCOMMANDS *****/
+/// Exports the given package, including its image, to an archive that can be imported with `brane import --archive`.
+/// 
+/// **Arguments**
+///  * `name`: The name of the package to export.
+///  * `version`: The version of the package to export. Might be an unresolved 'latest'.
+///  * `output`: The path of the archive to write.
+/// 
+/// **Returns**  
+/// Nothing on success, or an ArchiveError otherwise.
+pub async fn export(name: String, version: Version, output: PathBuf) -> Result<(), ArchiveError> {
+    let _lock = PackageLock::acquire_async(&name, "export").await.map_err(|err| ArchiveError::LockError{ err })?;
+    let package_dir = ensure_package_dir(&name, Some(&version), false).map_err(|err| ArchiveError::PackageDirError{ err })?;
+    let docker = runtime::connect().map_err(|err| ArchiveError::DockerConnectError{ err })?;
+
+    let info = export_package(&docker, &package_dir, &output).await?;
+    println!(
+        "Successfully exported version {} of package {} to '{}'.",
+        style(&info.version).bold().cyan(),
+        style(&info.name).bold().cyan(),
+        output.display(),
+    );
+    Ok(())
+}
+
+/// Imports the package in the given archive (as written by `brane export`), loading its image into the local Docker daemon.
+/// 
+/// **Arguments**
+///  * `archive`: The path of the archive to import.
+///  * `force`: If true, overwrites the package if it already exists locally instead of asking first.
+/// 
+/// **Returns**  
+/// Nothing on success, or an ArchiveError otherwise. If the import fails, neither the package directory nor the Docker daemon are left changed.
+pub async fn import(archive: PathBuf, force: bool) -> Result<(), ArchiveError> {
+    let packages_dir = ensure_packages_dir(true).map_err(|err| ArchiveError::PackageDirError{ err })?;
+    let docker = runtime::connect().map_err(|err| ArchiveError::DockerConnectError{ err })?;
+
+    let info = import_archive(&docker, &archive, &packages_dir, |info| {
+        if force { return Ok(true); }
+        println!("Version {} of package {} already exists locally. Do you want to overwrite it?", info.version, info.name);
+        Confirm::new().interact().map_err(|err| ArchiveError::ConfirmError{ err })
+    }).await?;
+    index_cache::invalidate(&info.name, Some(&info.version));
+
+    println!(
+        "Successfully imported version {} of package {}.",
+        style(&info.version).bold().cyan(),
+        style(&info.name).bold().cyan(),
+    );
+    Ok(())
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Exports the package in the given package directory to an archive, saving its image from the local Docker daemon (and loading it there first, if needed).
+/// 
+/// **Arguments**
+///  * `docker`: The connection to the local Docker daemon.
+///  * `package_dir`: The directory of the package version to export, with its package.yml.
+///  * `output`: The path of the archive to write. Only appears once the archive is complete.
+/// 
+/// **Returns**  
+/// The PackageInfo of the exported package, or an ArchiveError otherwise.
+pub async fn export_package(docker: &Docker, package_dir: &Path, output: &Path) -> Result<PackageInfo, ArchiveError> {
+    let info_path = package_dir.join("package.yml");
+    let info = PackageInfo::from_path(info_path.clone()).map_err(|err| ArchiveError::PackageInfoError{ path: info_path, err })?;
+    let image = format!("{}:{}", info.name, info.version);
+
+    // Make sure the daemon has the image, so we can save it
+    if docker.inspect_image(&image).await.is_err() {
+        debug!("Image '{}' is not loaded; loading it from '{}'", image, package_dir.display());
+        load_image(docker, &package_dir.join(IMAGE_FILE)).await?;
+    }
+    verify_image(docker, &info).await?;
+
+    // Save it next to the package
+    let staging = tempfile::tempdir().map_err(|err| ArchiveError::TempDirError{ err })?;
+    let image_file = staging.path().join(IMAGE_FILE);
+    let mut handle = File::create(&image_file).map_err(|err| ArchiveError::FileWriteError{ path: image_file.clone(), err })?;
+    let mut chunks = Box::pin(docker.export_image(&image));
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(|err| ArchiveError::ImageSaveError{ image: image.clone(), err })?;
+        handle.write_all(&chunk).map_err(|err| ArchiveError::FileWriteError{ path: image_file.clone(), err })?;
+    }
+    drop(handle);
+
+    pack(package_dir, &image_file, output)?;
+    Ok(info)
+}
+
+/// Writes the given package directory and image file to an archive.
+/// 
+/// **Arguments**
+///  * `package_dir`: The directory of the package version to pack, with its package.yml. Its own image file (if any) is left out.
+///  * `image_file`: The `docker save` of the package's image.
+///  * `output`: The path of the archive to write. Only appears once the archive is complete.
+/// 
+/// **Returns**  
+/// The manifest of the written archive, or an ArchiveError otherwise.
+pub fn pack(package_dir: &Path, image_file: &Path, output: &Path) -> Result<ExportManifest, ArchiveError> {
+    let info_path = package_dir.join("package.yml");
+    let info = PackageInfo::from_path(info_path.clone()).map_err(|err| ArchiveError::PackageInfoError{ path: info_path, err })?;
+
+    // Compute the digests of everything that goes in
+    let mut sources = BTreeMap::new();
+    for file in list_files(package_dir)? {
+        if file == IMAGE_FILE || file == HIDDEN_MARKER { continue; }
+        sources.insert(format!("{}/{}", PACKAGE_DIR, file), package_dir.join(&file));
+    }
+    sources.insert(IMAGE_FILE.to_string(), image_file.to_path_buf());
+    let mut files = BTreeMap::new();
+    for (file, path) in &sources { files.insert(file.clone(), sha256_file(path)?); }
+    let manifest = ExportManifest {
+        format  : ARCHIVE_FORMAT,
+        name    : info.name,
+        version : info.version,
+        digest  : info.digest,
+        files,
+    };
+    let raw_manifest = serde_json::to_vec_pretty(&manifest).map_err(|err| ArchiveError::ManifestEncodeError{ err })?;
+
+    // Write to a temporary file next to the output first, so a failed export leaves nothing behind
+    let parent = match output.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _                                               => Path::new("."),
+    };
+    let temp = tempfile::NamedTempFile::new_in(parent).map_err(|err| ArchiveError::FileWriteError{ path: output.to_path_buf(), err })?;
+    let tar_err = |err| ArchiveError::TarError{ path: output.to_path_buf(), err };
+    let mut archive = tar::Builder::new(GzEncoder::new(temp.as_file(), Compression::default()));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(raw_manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or_default());
+    header.set_cksum();
+    archive.append_data(&mut header, MANIFEST_FILE, raw_manifest.as_slice()).map_err(tar_err)?;
+    for (file, path) in &sources {
+        archive.append_path_with_name(path, file).map_err(tar_err)?;
+    }
+    archive.into_inner().and_then(|gz| gz.finish()).map_err(tar_err)?;
+    temp.persist(output).map_err(|err| ArchiveError::FileWriteError{ path: output.to_path_buf(), err: err.error })?;
+
+    Ok(manifest)
+}
+
+/// Unpacks the given archive into the given directory, and checks that it is complete and uncorrupted.
+/// 
+/// **Arguments**
+///  * `archive`: The path of the archive to unpack.
+///  * `dest`: The (existing, empty) directory to unpack the archive in.
+/// 
+/// **Returns**  
+/// The manifest of the archive and the PackageInfo of the package in it, or an ArchiveError if the archive could not be read or does not match its manifest.
+pub fn unpack(archive: &Path, dest: &Path) -> Result<(ExportManifest, PackageInfo), ArchiveError> {
+    let handle = File::open(archive).map_err(|err| ArchiveError::FileReadError{ path: archive.to_path_buf(), err })?;
+    tar::Archive::new(GzDecoder::new(handle)).unpack(dest).map_err(|err| ArchiveError::TarError{ path: archive.to_path_buf(), err })?;
+
+    // Read the manifest
+    let manifest_path = dest.join(MANIFEST_FILE);
+    if !manifest_path.exists() { return Err(ArchiveError::MissingFile{ path: archive.to_path_buf(), file: MANIFEST_FILE.to_string() }); }
+    let raw_manifest = fs::read(&manifest_path).map_err(|err| ArchiveError::FileReadError{ path: manifest_path, err })?;
+    let manifest: ExportManifest = serde_json::from_slice(&raw_manifest).map_err(|err| ArchiveError::ManifestParseError{ path: archive.to_path_buf(), err })?;
+    if manifest.format != ARCHIVE_FORMAT { return Err(ArchiveError::UnsupportedFormat{ path: archive.to_path_buf(), format: manifest.format }); }
+
+    // Check that everything is there, and nothing else
+    let package_file = format!("{}/package.yml", PACKAGE_DIR);
+    for file in [ package_file.as_str(), IMAGE_FILE ] {
+        if !manifest.files.contains_key(file) { return Err(ArchiveError::MissingFile{ path: archive.to_path_buf(), file: file.to_string() }); }
+    }
+    for (file, expected) in &manifest.files {
+        if !Path::new(file).components().all(|component| matches!(component, Component::Normal(_))) {
+            return Err(ArchiveError::IllegalFile{ path: archive.to_path_buf(), file: file.clone() });
+        }
+        let path = dest.join(file);
+        if !path.is_file() { return Err(ArchiveError::MissingFile{ path: archive.to_path_buf(), file: file.clone() }); }
+        let got = sha256_file(&path)?;
+        if &got != expected { return Err(ArchiveError::DigestMismatch{ path: archive.to_path_buf(), file: file.clone(), expected: expected.clone(), got }); }
+    }
+    for file in list_files(dest)? {
+        if file != MANIFEST_FILE && !manifest.files.contains_key(&file) { return Err(ArchiveError::UnlistedFile{ path: archive.to_path_buf(), file }); }
+    }
+
+    // Check that the package is the one the manifest promised
+    let info_path = dest.join(&package_file);
+    let info = PackageInfo::from_path(info_path.clone()).map_err(|err| ArchiveError::PackageInfoError{ path: info_path, err })?;
+    if info.name != manifest.name {
+        return Err(ArchiveError::ManifestMismatch{ path: archive.to_path_buf(), what: "package", manifest: manifest.name, package: info.name });
+    }
+    if info.version != manifest.version {
+        return Err(ArchiveError::ManifestMismatch{ path: archive.to_path_buf(), what: "version", manifest: manifest.version.to_string(), package: info.version.to_string() });
+    }
+    if info.digest != manifest.digest {
+        return Err(ArchiveError::ManifestMismatch{ path: archive.to_path_buf(), what: "image digest", manifest: manifest.digest.unwrap_or_default(), package: info.digest.unwrap_or_default() });
+    }
+
+    Ok((manifest, info))
+}
+
+/// Imports the package in the given archive into the given packages directory, loading its image into the local Docker daemon.
+/// 
+/// **Arguments**
+///  * `docker`: The connection to the local Docker daemon.
+///  * `archive`: The path of the archive to import.
+///  * `packages_dir`: The directory with all packages (see `ensure_packages_dir()`).
+///  * `confirm`: Called with the package if it already exists locally; it is only overwritten if this returns true.
+/// 
+/// **Returns**  
+/// The PackageInfo of the imported package, or an ArchiveError otherwise. If the import fails, neither the packages directory nor the Docker daemon are left changed.
+pub async fn import_archive<F>(docker: &Docker, archive: &Path, packages_dir: &Path, confirm: F) -> Result<PackageInfo, ArchiveError>
+where
+    F: FnOnce(&PackageInfo) -> Result<bool, ArchiveError>,
+{
+    // Check the archive before we touch anything
+    let staging = tempfile::tempdir().map_err(|err| ArchiveError::TempDirError{ err })?;
+    let (_, info) = unpack(archive, staging.path())?;
+
+    fs::create_dir_all(packages_dir).map_err(|err| ArchiveError::FileWriteError{ path: packages_dir.to_path_buf(), err })?;
+    let _lock = PackageLock::acquire_in_async(packages_dir, &info.name, "import", lock_timeout()).await.map_err(|err| ArchiveError::LockError{ err })?;
+    if packages_dir.join(&info.name).join(info.version.to_string()).exists() && !confirm(&info)? {
+        return Err(ArchiveError::PackageConflict{ name: info.name, version: info.version });
+    }
+
+    // Load the image, unless the daemon already has this exact one
+    let image = format!("{}:{}", info.name, info.version);
+    let previous = docker.inspect_image(&image).await.ok().map(|image| runtime::normalize_image_id(&image.id));
+    let loaded = info.digest.is_none() || previous != info.digest;
+    if loaded { load_image(docker, &staging.path().join(IMAGE_FILE)).await?; }
+
+    // Put the package in place, undoing the load if anything fails
+    let res = match verify_image(docker, &info).await {
+        Ok(_)    => install(staging.path(), packages_dir, &info),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = res {
+        if loaded { restore_image(docker, &image, previous).await; }
+        return Err(err);
+    }
+    Ok(info)
+}
+
+/// Moves an unpacked archive into the packages directory, replacing the package version if it already exists.
+/// 
+/// The new version only appears once it is complete, and the old version is only removed once the new one is in place.
+/// 
+/// **Arguments**
+///  * `staging`: The directory with the unpacked archive (see `unpack()`).
+///  * `packages_dir`: The directory with all packages (see `ensure_packages_dir()`).
+///  * `info`: The PackageInfo of the package in the archive.
+/// 
+/// **Returns**  
+/// Nothing on success, or an ArchiveError otherwise. On failure, the packages directory is left as it was.
+pub fn install(staging: &Path, packages_dir: &Path, info: &PackageInfo) -> Result<(), ArchiveError> {
+    let package_dir = packages_dir.join(&info.name);
+    let version = info.version.to_string();
+    let target = package_dir.join(&version);
+    let temp = package_dir.join(format!(".{}.import", version));
+    let backup = package_dir.join(format!(".{}.old", version));
+    let write_err = |path: &Path| { let path = path.to_path_buf(); move |err: io::Error| ArchiveError::FileWriteError{ path, err } };
+
+    // Copy the package to a hidden directory first (clearing whatever an earlier, killed import left behind)
+    if temp.exists() { fs::remove_dir_all(&temp).map_err(write_err(&temp))?; }
+    fs::create_dir_all(&temp).map_err(write_err(&temp))?;
+    let cleanup = Cleanup::new(temp.clone());
+    File::create(temp.join(HIDDEN_MARKER)).map_err(write_err(&temp))?;
+    for file in list_files(&staging.join(PACKAGE_DIR))? {
+        let path = temp.join(&file);
+        if let Some(parent) = path.parent() { fs::create_dir_all(parent).map_err(write_err(parent))?; }
+        fs::copy(staging.join(PACKAGE_DIR).join(&file), &path).map_err(write_err(&path))?;
+    }
+    fs::copy(staging.join(IMAGE_FILE), temp.join(IMAGE_FILE)).map_err(write_err(&temp.join(IMAGE_FILE)))?;
+
+    // Swap it with the old version (if any)
+    let replacing = target.exists();
+    if replacing {
+        if backup.exists() { fs::remove_dir_all(&backup).map_err(write_err(&backup))?; }
+        File::create(target.join(HIDDEN_MARKER)).map_err(write_err(&target))?;
+        if let Err(err) = fs::rename(&target, &backup) {
+            let _ = fs::remove_file(target.join(HIDDEN_MARKER));
+            return Err(ArchiveError::FileWriteError{ path: target, err });
+        }
+    }
+    if let Err(err) = fs::rename(&temp, &target) {
+        if replacing && fs::rename(&backup, &target).is_ok() { let _ = fs::remove_file(target.join(HIDDEN_MARKER)); }
+        return Err(ArchiveError::FileWriteError{ path: target, err });
+    }
+    cleanup.disarm();
+    fs::remove_file(target.join(HIDDEN_MARKER)).map_err(write_err(&target))?;
+    if replacing {
+        if let Err(err) = fs::remove_dir_all(&backup) { warn!("Could not remove old version '{}': {}", backup.display(), err); }
+    }
+
+    Ok(())
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Loads the given image file into the local Docker daemon, as `docker load` does.
+async fn load_image(docker: &Docker, image_file: &Path) -> Result<(), ArchiveError> {
+    let handle = TokioFile::open(image_file).await.map_err(|err| ArchiveError::FileReadError{ path: image_file.to_path_buf(), err })?;
+    let body = Body::wrap_stream(FramedRead::new(handle, BytesCodec::new()).map_ok(|bytes| bytes.freeze()));
+    let infos = docker.import_image(ImportImageOptions{ quiet: true }, body, None).try_collect::<Vec<_>>().await.map_err(|err| ArchiveError::ImageLoadError{ path: image_file.to_path_buf(), err })?;
+    if let Some(message) = infos.into_iter().find_map(|info| info.error) {
+        return Err(ArchiveError::ImageLoadFailure{ path: image_file.to_path_buf(), message });
+    }
+    Ok(())
+}
+
+/// Checks that the image of the given package in the local Docker daemon is the one its package info says it is.
+async fn verify_image(docker: &Docker, info: &PackageInfo) -> Result<(), ArchiveError> {
+    let image = format!("{}:{}", info.name, info.version);
+    let got = docker.inspect_image(&image).await.map_err(|err| ArchiveError::ImageInspectError{ image: image.clone(), err })?.id;
+    let got = runtime::normalize_image_id(&got);
+    match &info.digest {
+        Some(expected) if &got != expected => Err(ArchiveError::ImageDigestMismatch{ name: info.name.clone(), version: info.version.clone(), expected: expected.clone(), got }),
+        Some(_)                            => Ok(()),
+        None                               => { warn!("Package '{}' (version {}) has no image digest; cannot verify its image", info.name, info.version); Ok(()) },
+    }
+}
+
+/// Points the given image name back to the image it referred to before we loaded a new one, or removes it if it didn't refer to any. Failures are only logged, as this is called while handling another error.
+async fn restore_image(docker: &Docker, image: &str, previous: Option<String>) {
+    let res = match previous {
+        Some(id) => {
+            let (repo, tag) = image.rsplit_once(':').unwrap_or((image, "latest"));
+            docker.tag_image(&id, Some(TagImageOptions{ repo, tag })).await
+        },
+        None => docker.remove_image(image, None, None).await.map(|_| ()),
+    };
+    if let Err(err) = res { warn!("Could not undo loading image '{}': {}", image, err); }
+}
+
+/// Returns the paths of all files in the given directory (recursively), relative to it and separated by '/', in sorted order.
+fn list_files(dir: &Path) -> Result<Vec<String>, ArchiveError> {
+    fn walk(dir: &Path, prefix: &str, files: &mut Vec<String>) -> Result<(), ArchiveError> {
+        let entries = fs::read_dir(dir).map_err(|err| ArchiveError::FileReadError{ path: dir.to_path_buf(), err })?;
+        for entry in entries {
+            let entry = entry.map_err(|err| ArchiveError::FileReadError{ path: dir.to_path_buf(), err })?;
+            let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            if entry.path().is_dir() { walk(&entry.path(), &format!("{}/", name), files)?; } else { files.push(name); }
+        }
+        Ok(())
+    }
+
+    let mut files = vec![];
+    walk(dir, "", &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+/// Returns the sha256 digest of the given file, as `sha256:<hex>`.
+fn sha256_file(path: &Path) -> Result<String, ArchiveError> {
+    let mut handle = File::open(path).map_err(|err| ArchiveError::FileReadError{ path: path.to_path_buf(), err })?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut handle, &mut hasher).map_err(|err| ArchiveError::FileReadError{ path: path.to_path_buf(), err })?;
+    Ok(format!("sha256:{:x}", hasher.finalize()))
+}
@@ -13,11 +13,20 @@
  *   package kinds.
 **/
 
+use std::collections::BTreeMap;
 use std::fs::{self, File};
-use std::path::Path;
-use std::process::Command;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
+use flate2::{Compression, GzBuilder};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tar::{Archive, Builder, Header, HeaderMode};
+use tokio::process::Command;
+
+use crate::build_dag::{lock_tag, run_prefixed};
 use crate::errors::BuildError;
+use crate::runtime::{self, BuildBackend};
 
 
 /***** COMMON MACROS *****/
@@ -40,23 +49,119 @@ macro_rules! writeln_build {
 
 
 /***** COMMON CONSTANTS */
-/// The URL which we use to pull the latest branelet executable from.
-pub const BRANELET_URL: &str = concat!(
+/// The URL of the release which we download the prebuilt branelet executables from (as `branelet-<arch>`, with their SHA-256 hash in `branelet-<arch>.sha256`).
+pub const BRANELET_RELEASE_URL: &str = concat!(
     "https://github.com/epi-project/brane/releases/download/",
-    concat!("v", env!("CARGO_PKG_VERSION")),
-    "/branelet"
+    concat!("v", env!("CARGO_PKG_VERSION"))
 );
 
 /// The URL Which we use to pull the latest JuiceFS executable from.
 pub const JUICE_URL: &str =
     "https://github.com/juicedata/juicefs/releases/download/v0.12.1/juicefs-0.12.1-linux-amd64.tar.gz";
 
+/// The URL of the JuiceFS executable for the architecture that an image is built for, as a Dockerfile expression (after an `ARG TARGETARCH`).
+pub const JUICE_URL_TARGETARCH: &str =
+    "https://github.com/juicedata/juicefs/releases/download/v0.12.1/juicefs-0.12.1-linux-${TARGETARCH}.tar.gz";
+
+/// The environment variable with the SOURCE_DATE_EPOCH for reproducible builds (see https://reproducible-builds.org/specs/source-date-epoch/).
+pub const SOURCE_DATE_EPOCH_ENV: &str = "SOURCE_DATE_EPOCH";
+
+
+
+
+
+/***** COMMON TYPES *****/
+/// Defines what `docker buildx build` does with the image it built.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ImageOutput<'a> {
+    /// Only builds up to the given stage of the Dockerfile to fill the build cache, without writing an image.tar or tagging anything.
+    Cache(&'a str),
+    /// Writes the image to image.tar in the package directory, as `--load` would load it into Docker. Only works for a single platform.
+    Tar,
+    /// Pushes the image to the given image reference (e.g., 'ghcr.io/org/package:1.0.0') in a registry. Works for any number of platforms.
+    Push(&'a str),
+}
+
+/// Defines how a single `docker buildx build` (or `podman build`) builds, rather than what it builds.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BuildFlags {
+    /// The SOURCE_DATE_EPOCH to set every timestamp in the image to, which makes the build reproducible. None for a regular build.
+    pub source_date_epoch : Option<u64>,
+    /// Whether to ignore the build cache.
+    pub no_cache          : bool,
+}
+
+/// Defines which platforms to build a package image for, where to push it and whether to build it reproducibly.
+#[derive(Clone, Debug, Default)]
+pub struct ImageOptions {
+    /// The platforms (e.g., 'linux/amd64') to build for. If empty, builds for the host platform.
+    pub platforms           : Vec<String>,
+    /// The registry (e.g., 'ghcr.io/my-org') to push the image to with buildx, if any. Required to build for multiple platforms.
+    pub push                : Option<String>,
+    /// The SOURCE_DATE_EPOCH to build the image reproducibly with, or None for a regular build.
+    pub source_date_epoch   : Option<u64>,
+    /// Whether to build the image a second time (without cache) and fail if it differs from the first. Only makes sense for reproducible builds.
+    pub verify_reproducible : bool,
+}
+
+impl ImageOptions {
+    /// Returns the platform to write to image.tar (which may only have one): the host platform if we're building for it, or else the first one.
+    /// 
+    /// **Returns**  
+    /// The platform, or None if no platforms are given (so the host platform is implied).
+    pub fn tar_platform(&self) -> Option<String> {
+        if self.platforms.is_empty() { return None; }
+        let host = host_platform();
+        if self.platforms.contains(&host) { Some(host) } else { self.platforms.first().cloned() }
+    }
+
+    /// Returns the platforms that the image is built for, which are recorded in the package info.
+    pub fn built_platforms(&self) -> Vec<String> {
+        if self.platforms.is_empty() { vec![ host_platform() ] } else { self.platforms.clone() }
+    }
+
+    /// Returns the BuildFlags of the regular build steps of the image.
+    #[inline]
+    pub fn flags(&self) -> BuildFlags { BuildFlags{ source_date_epoch: self.source_date_epoch, no_cache: false } }
+}
+
+
+
+/// The interesting fields of the manifest.json in a Docker image tar.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct ImageManifest {
+    /// The path of the config blob, which is named after the image digest.
+    #[serde(rename = "Config")]
+    pub config : String,
+    /// The paths of the layer blobs, which are named after their contents, from the bottom up.
+    #[serde(rename = "Layers", default)]
+    pub layers : Vec<String>,
+}
+
+/// Describes where two builds of the same image first differ.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ImageDifference {
+    /// The layer at the given index (from the bottom up) differs, or only one of the images has it.
+    Layer{ index: usize, first: Option<String>, second: Option<String> },
+    /// All layers are the same, but the image config is not (e.g., because of its creation time).
+    Config{ first: String, second: String },
+}
+
+impl std::fmt::Display for ImageDifference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageDifference::Layer{ index, first, second } => write!(f, "layer {} differs (first build: {}, second build: {})", index, first.as_deref().unwrap_or("<none>"), second.as_deref().unwrap_or("<none>")),
+            ImageDifference::Config{ first, second }       => write!(f, "all layers are the same, but the image config differs (first build: {}, second build: {})", first, second),
+        }
+    }
+}
+
 
 
 
 
 /***** COMMON FUNCTIONS *****/
-/// **Edited: now returning BuildErrors. Also leaving .lock removal to the main handle function.**
+/// **Edited: now returning BuildErrors.**
 /// 
 /// Cleans the resulting build directory from the build files (but only if the build files should be removed).
 /// 
@@ -89,99 +194,373 @@ pub fn clean_directory(
 
 
 
-/// Creates a new lock file in the given package directory.
+/// **Edited: now async, and split into ensure_buildx() and buildx_build().**
+/// 
+/// Builds the docker image in the given package directory.
+/// 
+/// **Generic types**
+///  * `P`: The Path-like type of the container directory path.
 /// 
 /// **Arguments**
-///  * `package_dir`: The directory to create the lock file in. We assume this path is already canonicalized and thus exists.
+///  * `package_dir`: The build directory for this image. We expect the actual image files to be under ./container.
+///  * `tag`: Tag to give to the image so we can find it later (probably just <package name>:<package version>)
 /// 
 /// **Returns**  
-/// Nothing if we created the .lock file successfully, or a BuildError otherwise (if the directory is already locked, for example).
-pub fn lock_directory(
-    package_dir: &Path,
+/// Nothing if the image was build successfully, or a BuildError otherwise.
+pub async fn build_docker_image<P: AsRef<Path>>(
+    package_dir : P,
+    tag         : String,
 ) -> Result<(), BuildError> {
-    debug!("Using package directory: '{}'", package_dir.display());
+    ensure_buildx().await?;
+    buildx_build(package_dir, &tag, ImageOutput::Tar, &[], BuildFlags::default(), None).await
+}
+
 
-    // Make sure there is no lock file
-    let lock = package_dir.join(".lock");
-    if lock.exists() {
-        return Err(BuildError::LockFileExists{ path: package_dir.to_path_buf() });
+
+/// Checks that Docker and its BuildKit plugin are installed (and launches the buildx image, presumably).
+/// 
+/// Podman without buildx builds with `podman build` instead, so there is nothing to check then.
+/// 
+/// **Returns**  
+/// Nothing if we can build images, or a BuildError otherwise.
+pub async fn ensure_buildx() -> Result<(), BuildError> {
+    let info = runtime::runtime_info().await;
+    if info.build_backend() == BuildBackend::PodmanBuild {
+        debug!("buildx is not available; building images with 'podman build'");
+        return Ok(());
     }
 
-    // Create the lock file
-    if let Err(err) = File::create(&lock) {
-        return Err(BuildError::LockCreateError{ path: lock, err });
+    let mut command = Command::new("docker");
+    command.arg("buildx");
+    let buildx = match command.output().await {
+        Ok(buildx) => buildx,
+        Err(err)   => { return Err(BuildError::BuildKitLaunchError{ command: format!("{:?}", command.as_std()), err }); }
+    };
+    // Check if it was successfull
+    if !buildx.status.success() {
+        return Err(BuildError::BuildKitError{ command: format!("{:?}", command.as_std()), code: buildx.status.code().unwrap_or(-1), stdout: String::from_utf8_lossy(&buildx.stdout).to_string(), stderr: String::from_utf8_lossy(&buildx.stderr).to_string() });
+    }
+    Ok(())
+}
+
+
+
+/// Returns the platform of this machine as Docker calls it (e.g., 'linux/amd64' or 'linux/arm64'), which is what images are built for if no platform is given.
+pub fn host_platform() -> String {
+    let arch = match std::env::consts::ARCH {
+        "x86_64"  => "amd64",
+        "aarch64" => "arm64",
+        "x86"     => "386",
+        "arm"     => "arm",
+        arch      => arch,
     };
+    // Docker Desktop builds Linux images on other operating systems too
+    format!("linux/{}", arch)
+}
 
-    // Success!
+/// Checks that the given platforms look like Docker platforms ('os/arch' or 'os/arch/variant') and are not given twice.
+/// 
+/// **Arguments**
+///  * `platforms`: The platforms to check.
+/// 
+/// **Returns**  
+/// Nothing if they are fine, or a BuildError::IllegalPlatform otherwise.
+pub fn check_platforms(platforms: &[String]) -> Result<(), BuildError> {
+    for (i, platform) in platforms.iter().enumerate() {
+        let parts: Vec<&str> = platform.split('/').collect();
+        if parts.len() < 2 || parts.len() > 3 || parts.iter().any(|part| part.is_empty() || part.contains(char::is_whitespace)) {
+            return Err(BuildError::IllegalPlatform{ platform: platform.clone(), reason: "expected 'os/arch' or 'os/arch/variant'" });
+        }
+        if platforms[..i].contains(platform) {
+            return Err(BuildError::IllegalPlatform{ platform: platform.clone(), reason: "given more than once" });
+        }
+    }
     Ok(())
 }
 
-/// Removes the lock file in the given package directory.
+/// Constructs the arguments for `docker buildx build` (i.e., everything after `docker`).
 /// 
 /// **Arguments**
-///  * `package_dir`: The directory to remove the lock file from. We assume this path is already canonicalized and thus exists.
+///  * `tag`: The tag of the image we're building.
+///  * `output`: What to do with the built image.
+///  * `platforms`: The platforms to build the image for. If empty, builds for the host platform.
+///  * `flags`: The BuildFlags that determine how to build. Reproducible builds need BuildKit 0.13 or newer to rewrite the timestamps in the layers.
 /// 
 /// **Returns**  
-/// Nothing - but does write a warning to stderr if we could not remove the lock.
-pub fn unlock_directory(
-    package_dir: &Path,
-) {
-    let lock = package_dir.join(".lock");
-    if let Err(err) = fs::remove_file(&lock) {
-        warn!("{}", BuildError::LockCleanupError{ path: lock, err });
+/// The arguments, or a BuildError::MultiPlatformTar if multiple platforms should be written to an image.tar (which buildx cannot do, like it cannot `--load` them).
+pub fn buildx_args(tag: &str, output: ImageOutput, platforms: &[String], flags: BuildFlags) -> Result<Vec<String>, BuildError> {
+    let mut args: Vec<String> = vec![ "buildx".into(), "build".into() ];
+    if !platforms.is_empty() {
+        args.push("--platform".into());
+        args.push(platforms.join(","));
+    }
+    // Timestamps are rewritten by the exporter, so reproducible builds name it explicitly
+    let rewrite = if let Some(epoch) = flags.source_date_epoch {
+        args.push("--build-arg".into());
+        args.push(format!("{}={}", SOURCE_DATE_EPOCH_ENV, epoch));
+        // The provenance attestation records when the image was built
+        args.push("--provenance=false".into());
+        ",rewrite-timestamp=true"
+    } else {
+        ""
+    };
+    if flags.no_cache { args.push("--no-cache".into()); }
+    match output {
+        ImageOutput::Cache(target) => {
+            args.push("--target".into());
+            args.push(target.into());
+        },
+        ImageOutput::Tar => {
+            if platforms.len() > 1 { return Err(BuildError::MultiPlatformTar{ platforms: platforms.to_vec() }); }
+            args.push("--output".into());
+            args.push(format!("type=docker,dest=image.tar{}", rewrite));
+            args.push("--tag".into());
+            args.push(tag.into());
+        },
+        ImageOutput::Push(image) => {
+            if rewrite.is_empty() {
+                args.push("--push".into());
+            } else {
+                args.push("--output".into());
+                args.push(format!("type=registry{}", rewrite));
+            }
+            args.push("--tag".into());
+            args.push(image.into());
+        },
     }
+    args.push(".".into());
+    Ok(args)
 }
 
+/// Constructs the arguments for `podman build` and the commands after it (i.e., everything after `podman`), which we use instead of buildx on Podman without it.
+/// 
+/// **Arguments**
+///  * `tag`: The tag of the image we're building.
+///  * `output`: What to do with the built image. Pushing is not supported.
+///  * `platforms`: The platforms to build the image for, of which there may be one at most. If empty, builds for the host platform.
+///  * `flags`: The BuildFlags that determine how to build.
+/// 
+/// **Returns**  
+/// The arguments of every command to run in order, or a BuildError::RuntimeUnsupported if Podman cannot build the image like this without buildx.
+pub fn podman_build_args(tag: &str, output: ImageOutput, platforms: &[String], flags: BuildFlags) -> Result<Vec<Vec<String>>, BuildError> {
+    if platforms.len() > 1 { return Err(BuildError::RuntimeUnsupported{ runtime: String::from("Podman"), feature: "build for multiple platforms" }); }
 
+    let mut build: Vec<String> = vec![ "build".into() ];
+    if let Some(platform) = platforms.first() {
+        build.push("--platform".into());
+        build.push(platform.clone());
+    }
+    if let Some(epoch) = flags.source_date_epoch {
+        // Podman sets the creation time and the timestamps of every file in the layers to this
+        build.push("--timestamp".into());
+        build.push(epoch.to_string());
+        build.push("--build-arg".into());
+        build.push(format!("{}={}", SOURCE_DATE_EPOCH_ENV, epoch));
+    }
+    if flags.no_cache { build.push("--no-cache".into()); }
+    let commands = match output {
+        ImageOutput::Cache(target) => {
+            build.extend(vec![ "--target".into(), target.into(), ".".into() ]);
+            vec![ build ]
+        },
+        ImageOutput::Tar => {
+            // Podman cannot write the image to a file while building it, so save it afterwards
+            build.extend(vec![ "--tag".into(), tag.into(), ".".into() ]);
+            vec![ build, vec![ "save".into(), "--format".into(), "docker-archive".into(), "--output".into(), "image.tar".into(), tag.into() ] ]
+        },
+        ImageOutput::Push(_) => { return Err(BuildError::RuntimeUnsupported{ runtime: String::from("Podman"), feature: "push images while building" }); },
+    };
+    Ok(commands)
+}
 
-/// **Edited: now returning BuildErrors.**
+/// Constructs the commands that build an image with the given backend.
 /// 
-/// Builds the docker image in the given package directory.
+/// **Arguments**
+///  * `backend`: How to build the image.
+///  * `tag`: The tag of the image we're building.
+///  * `output`: What to do with the built image.
+///  * `platforms`: The platforms to build the image for. If empty, builds for the host platform.
+///  * `flags`: The BuildFlags that determine how to build.
+/// 
+/// **Returns**  
+/// The program and arguments of every command to run in order, or a BuildError if the backend cannot build the image like this.
+pub fn build_commands(backend: BuildBackend, tag: &str, output: ImageOutput, platforms: &[String], flags: BuildFlags) -> Result<Vec<(&'static str, Vec<String>)>, BuildError> {
+    match backend {
+        BuildBackend::Buildx      => Ok(vec![ ("docker", buildx_args(tag, output, platforms, flags)?) ]),
+        BuildBackend::PodmanBuild => Ok(podman_build_args(tag, output, platforms, flags)?.into_iter().map(|args| ("podman", args)).collect()),
+    }
+}
+
+/// Runs `docker buildx build` (or `podman build`, on Podman without buildx) in the given package directory.
+/// 
+/// Invocations for the same tag never run at the same time (see build_dag::lock_tag()).
 /// 
 /// **Generic types**
 ///  * `P`: The Path-like type of the container directory path.
 /// 
 /// **Arguments**
-///  * `package_dir`: The build directory for this image. We expect the actual image files to be under ./container.
-///  * `tag`: Tag to give to the image so we can find it later (probably just <package name>:<package version>)
+///  * `package_dir`: The build directory for this image, with the Dockerfile in it.
+///  * `tag`: The tag of the image we're building.
+///  * `output`: What to do with the built image (see ImageOutput).
+///  * `platforms`: The platforms to build the image for. If empty, builds for the host platform.
+///  * `flags`: The BuildFlags that determine how to build.
+///  * `step`: If given, the output of Docker is prefixed with this build step name instead of being passed through as-is.
 /// 
 /// **Returns**  
-/// Nothing if the image was build successfully, or a BuildError otherwise.
-pub fn build_docker_image<P: AsRef<Path>>(
+/// Nothing if the build succeeded, or a BuildError otherwise.
+pub async fn buildx_build<P: AsRef<Path>>(
     package_dir : P,
-    tag         : String,
+    tag         : &str,
+    output      : ImageOutput<'_>,
+    platforms   : &[String],
+    flags       : BuildFlags,
+    step        : Option<&str>,
 ) -> Result<(), BuildError> {
-    // Prepare the command to check for buildx (and launch the buildx image, presumably)
-    let mut command = Command::new("docker");
-    command.arg("buildx");
-    let buildx = match command.output() {
-        Ok(buildx) => buildx,
-        Err(err)   => { return Err(BuildError::BuildKitLaunchError{ command: format!("{:?}", command), err }); }
-    };
-    // Check if it was successfull
-    if !buildx.status.success() {
-        return Err(BuildError::BuildKitError{ command: format!("{:?}", command), code: buildx.status.code().unwrap_or(-1), stdout: String::from_utf8_lossy(&buildx.stdout).to_string(), stderr: String::from_utf8_lossy(&buildx.stdout).to_string() });
-    }
+    let backend = runtime::runtime_info().await.build_backend();
+    let commands = build_commands(backend, tag, output, platforms, flags)?;
 
-    // Next, launch the command to actually build the image
-    let mut command = Command::new("docker");
-    command.arg("buildx");
-    command.arg("build");
-    command.arg("--output");
-    command.arg("type=docker,dest=image.tar");
-    command.arg("--tag");
-    command.arg(tag);
-    command.arg(".");
-    command.current_dir(package_dir);
-    let output = match command.status() {
-        Ok(output) => output,
-        Err(err)   => { return Err(BuildError::ImageBuildLaunchError{ command: format!("{:?}", command), err }); }
-    };
-    // Check if it was successfull
-    if !output.success() {
-        return Err(BuildError::ImageBuildError{ command: format!("{:?}", command), code: output.code().unwrap_or(-1) });
+    let _lock = lock_tag(tag).await;
+    for (program, args) in commands {
+        let mut command = Command::new(program);
+        command.args(args);
+        command.current_dir(package_dir.as_ref());
+
+        let status = match step {
+            Some(step) => run_prefixed(step, &mut command).await,
+            None       => command.status().await,
+        };
+        let status = match status {
+            Ok(status) => status,
+            Err(err)   => { return Err(BuildError::ImageBuildLaunchError{ command: format!("{:?}", command.as_std()), err }); }
+        };
+        // Check if it was successfull
+        if !status.success() {
+            return Err(BuildError::ImageBuildError{ command: format!("{:?}", command.as_std()), code: status.code().unwrap_or(-1) });
+        }
     }
 
     // Done! :D
     Ok(())
 }
+
+
+
+
+
+/***** REPRODUCIBILITY FUNCTIONS *****/
+/// Returns the SOURCE_DATE_EPOCH to build reproducibly with: the one in the environment if it is set, or else 0 (1 Jan 1970).
+/// 
+/// **Returns**  
+/// The number of seconds since the Unix epoch, or a BuildError::IllegalSourceDateEpoch if the environment variable is not a number.
+pub fn source_date_epoch() -> Result<u64, BuildError> {
+    match std::env::var(SOURCE_DATE_EPOCH_ENV) {
+        Ok(value) => value.trim().parse().map_err(|_| BuildError::IllegalSourceDateEpoch{ value }),
+        Err(_)    => Ok(0),
+    }
+}
+
+/// Archives a directory as a `.tar.gz` that only depends on the contents of the directory: entries are sorted by name, owned by root, have their modification time set to the SOURCE_DATE_EPOCH and only keep whether they are executable of their permissions. The gzip header carries no timestamp either.
+/// 
+/// **Arguments**
+///  * `dir`: The directory to archive. It ends up in the archive under its own name.
+///  * `target`: The path of the archive to write.
+///  * `source_date_epoch`: The modification time of every entry.
+/// 
+/// **Returns**  
+/// Nothing if the archive was written successfully, or a BuildError::WdArchiveError otherwise.
+pub fn archive_normalized(dir: &Path, target: &Path, source_date_epoch: u64) -> Result<(), BuildError> {
+    let to_err = |err| BuildError::WdArchiveError{ path: target.to_path_buf(), err };
+    let name = PathBuf::from(dir.file_name().unwrap_or_else(|| panic!("Directory '{}' to archive does not have a name; this should never happen!", dir.display())));
+
+    let handle = File::create(target).map_err(to_err)?;
+    let mut builder = Builder::new(GzBuilder::new().mtime(0).write(handle, Compression::default()));
+    append_normalized(&mut builder, dir, &name, source_date_epoch).map_err(to_err)?;
+    builder.into_inner().and_then(|encoder| encoder.finish()).map_err(to_err)?;
+    Ok(())
+}
+
+/// Adds a file, symlink or (recursively) directory to an archive as described in `archive_normalized()`.
+fn append_normalized<W: Write>(builder: &mut Builder<W>, path: &Path, name: &Path, source_date_epoch: u64) -> Result<(), std::io::Error> {
+    let metadata = fs::symlink_metadata(path)?;
+    let mut header = Header::new_gnu();
+    header.set_metadata_in_mode(&metadata, HeaderMode::Deterministic);
+    header.set_mtime(source_date_epoch);
+
+    if metadata.file_type().is_symlink() {
+        header.set_size(0);
+        builder.append_link(&mut header, name, fs::read_link(path)?)
+    } else if metadata.is_dir() {
+        header.set_size(0);
+        builder.append_data(&mut header, name, std::io::empty())?;
+
+        let mut entries: Vec<PathBuf> = fs::read_dir(path)?.map(|entry| entry.map(|entry| entry.path())).collect::<Result<_, _>>()?;
+        entries.sort();
+        for entry in entries {
+            let entry_name = name.join(entry.file_name().unwrap_or_else(|| panic!("Directory entry '{}' does not have a name; this should never happen!", entry.display())));
+            append_normalized(builder, &entry, &entry_name, source_date_epoch)?;
+        }
+        Ok(())
+    } else {
+        builder.append_data(&mut header, name, File::open(path)?)
+    }
+}
+
+/// Computes the SHA-256 hashes of the given files in the package directory, which are recorded in the package info of reproducible builds.
+/// 
+/// **Arguments**
+///  * `package_dir`: The package directory.
+///  * `inputs`: The paths of the files, relative to the package directory. Files that do not exist are skipped.
+/// 
+/// **Returns**  
+/// The hashes (as `sha256:<hex>`) by path, or a BuildError::InputHashError if a file could not be read.
+pub fn hash_inputs(package_dir: &Path, inputs: &[&str]) -> Result<BTreeMap<String, String>, BuildError> {
+    let mut hashes = BTreeMap::new();
+    for input in inputs {
+        let path = package_dir.join(input);
+        if !path.exists() { continue; }
+        let mut handle = File::open(&path).map_err(|err| BuildError::InputHashError{ path: path.clone(), err })?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut handle, &mut hasher).map_err(|err| BuildError::InputHashError{ path: path.clone(), err })?;
+        hashes.insert(input.to_string(), format!("sha256:{:x}", hasher.finalize()));
+    }
+    Ok(hashes)
+}
+
+/// Reads the manifest.json from a Docker image tar (like the image.tar of a package).
+/// 
+/// **Arguments**
+///  * `path`: The path of the image tar.
+/// 
+/// **Returns**  
+/// The ImageManifest of the (single) image in the tar, or a BuildError if it could not be read.
+pub fn read_image_manifest(path: &Path) -> Result<ImageManifest, BuildError> {
+    let handle = File::open(path).map_err(|err| BuildError::ImageTarOpenError{ path: path.to_path_buf(), err })?;
+    let mut archive = Archive::new(handle);
+    let entries = archive.entries().map_err(|err| BuildError::ImageTarEntriesError{ path: path.to_path_buf(), err })?;
+    for entry in entries {
+        let entry = entry.map_err(|err| BuildError::ImageTarEntriesError{ path: path.to_path_buf(), err })?;
+        if entry.path().map(|entry_path| entry_path != Path::new("manifest.json")).unwrap_or(true) { continue; }
+
+        let mut manifest: Vec<ImageManifest> = serde_json::from_reader(entry).map_err(|err| BuildError::ManifestParseError{ path: path.to_path_buf(), err })?;
+        if manifest.len() != 1 { return Err(BuildError::ManifestNotOneEntry{ path: path.to_path_buf(), n: manifest.len() }); }
+        return Ok(manifest.pop().unwrap());
+    }
+    Err(BuildError::NoManifest{ path: path.to_path_buf() })
+}
+
+/// Compares the manifests of two builds of the same image.
+/// 
+/// **Arguments**
+///  * `first`: The ImageManifest of the first build.
+///  * `second`: The ImageManifest of the second build.
+/// 
+/// **Returns**  
+/// Where the builds first differ (from the bottom layer up), or None if they are the same.
+pub fn compare_images(first: &ImageManifest, second: &ImageManifest) -> Option<ImageDifference> {
+    for index in 0..first.layers.len().max(second.layers.len()) {
+        let (lhs, rhs) = (first.layers.get(index), second.layers.get(index));
+        if lhs != rhs { return Some(ImageDifference::Layer{ index, first: lhs.cloned(), second: rhs.cloned() }); }
+    }
+    if first.config != second.config { return Some(ImageDifference::Config{ first: first.config.clone(), second: second.config.clone() }); }
+    None
+}
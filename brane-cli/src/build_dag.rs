@@ -0,0 +1,268 @@
+/* BUILD DAG.rs
+ *   by Lut99
+ *
+ * Created:
+ *   14 Oct 2026, 23:58:40
+ * Last edited:
+ *   14 Oct 2026, 23:58:40
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Runs the stages of a package build as a small DAG, such that
+ *   independent stages (e.g., building the dependency layer and archiving
+ *   the working directory) run concurrently.
+**/
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+
+use console::style;
+use futures::stream::{FuturesUnordered, StreamExt};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard, Semaphore};
+
+use crate::errors::BuildError;
+
+
+/***** GLOBALS *****/
+lazy_static! {
+    /// Per image tag, a lock that Docker invocations for that tag have to hold.
+    static ref TAG_LOCKS: Mutex<HashMap<String, Arc<AsyncMutex<()>>>> = Mutex::new(HashMap::new());
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// The future that performs a single build step.
+type StepFuture = Pin<Box<dyn Future<Output = Result<(), BuildError>> + Send>>;
+
+/// A single build step in the BuildDag.
+struct BuildStep {
+    /// The name of the step (used for dependencies and to prefix its output).
+    name         : String,
+    /// The steps that have to be done before this one may start.
+    dependencies : Vec<String>,
+    /// The work to do.
+    run          : StepFuture,
+}
+
+/// A set of build steps, which runs every step as soon as the ones it depends on are done.
+#[derive(Default)]
+pub struct BuildDag {
+    /// The steps in the DAG, in the order they were added.
+    steps : Vec<BuildStep>,
+}
+
+impl BuildDag {
+    /// Constructor for the BuildDag, which creates an empty one.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+
+
+    /// Adds a new step to the DAG.
+    /// 
+    /// **Arguments**
+    ///  * `name`: The name of the step. Must be unique within the DAG.
+    ///  * `dependencies`: The names of the steps that have to be done before this one starts.
+    ///  * `step`: The future that performs the step. It is not polled until its dependencies are done.
+    pub fn add<F>(&mut self, name: &str, dependencies: &[&str], step: F)
+    where
+        F: 'static + Future<Output = Result<(), BuildError>> + Send,
+    {
+        self.steps.push(BuildStep {
+            name         : name.to_string(),
+            dependencies : dependencies.iter().map(|d| d.to_string()).collect(),
+            run          : Box::pin(step),
+        });
+    }
+
+
+
+    /// Checks that the DAG can be run, i.e., that all dependencies exist and that there are no cycles.
+    /// 
+    /// **Returns**  
+    /// Nothing if the DAG is valid, or a BuildError otherwise.
+    fn check(&self) -> Result<(), BuildError> {
+        let mut names: HashSet<&str> = HashSet::with_capacity(self.steps.len());
+        for step in &self.steps {
+            if !names.insert(&step.name) { return Err(BuildError::BuildStepDuplicate{ step: step.name.clone() }); }
+        }
+        for step in &self.steps {
+            if let Some(dependency) = step.dependencies.iter().find(|d| !names.contains(d.as_str())) {
+                return Err(BuildError::BuildStepUnknownDependency{ step: step.name.clone(), dependency: dependency.clone() });
+            }
+        }
+
+        // Keep marking steps whose dependencies are all marked; whatever remains is part of (or waits on) a cycle
+        let mut done: HashSet<&str> = HashSet::with_capacity(self.steps.len());
+        loop {
+            let ready: Vec<&str> = self.steps.iter()
+                .filter(|s| !done.contains(s.name.as_str()) && s.dependencies.iter().all(|d| done.contains(d.as_str())))
+                .map(|s| s.name.as_str())
+                .collect();
+            if ready.is_empty() { break; }
+            done.extend(ready);
+        }
+        if done.len() < self.steps.len() {
+            return Err(BuildError::BuildStepCycle{ steps: self.steps.iter().filter(|s| !done.contains(s.name.as_str())).map(|s| s.name.clone()).collect() });
+        }
+        Ok(())
+    }
+
+    /// Runs all steps in the DAG, with at most `jobs` steps at the same time.
+    /// 
+    /// If a step fails, no new steps are started; the ones that are already running are allowed to finish.
+    /// 
+    /// **Arguments**
+    ///  * `jobs`: The maximum number of steps to run concurrently (at least 1).
+    /// 
+    /// **Returns**  
+    /// Nothing if all steps succeeded, or else the BuildError of the first step that failed.
+    pub async fn run(self, jobs: usize) -> Result<(), BuildError> {
+        self.check()?;
+
+        let semaphore = Arc::new(Semaphore::new(jobs.max(1)));
+        let mut pending = self.steps;
+        let mut done: HashSet<String> = HashSet::with_capacity(pending.len());
+        let mut running = FuturesUnordered::new();
+        let mut error: Option<BuildError> = None;
+        loop {
+            // Start all steps that may start
+            if error.is_none() {
+                let (ready, rest): (Vec<BuildStep>, Vec<BuildStep>) = std::mem::take(&mut pending).into_iter().partition(|s| s.dependencies.iter().all(|d| done.contains(d)));
+                pending = rest;
+                for step in ready {
+                    let semaphore = semaphore.clone();
+                    let name = step.name.clone();
+                    let handle = tokio::spawn(async move {
+                        let _permit = semaphore.acquire_owned().await.expect("Build step semaphore was closed; this should never happen!");
+                        debug!("[{}] Starting build step...", step.name);
+                        step.run.await
+                    });
+                    running.push(async move { (name, handle.await) });
+                }
+            }
+
+            // Wait for the next one to finish
+            let (name, result) = match running.next().await {
+                Some(result) => result,
+                None         => { break; }
+            };
+            match result {
+                Ok(Ok(()))   => { debug!("[{}] Build step done", name); done.insert(name); },
+                Ok(Err(err)) => { if error.is_none() { error = Some(err); } },
+                Err(err)     => { if error.is_none() { error = Some(BuildError::BuildStepPanicked{ step: name, err }); } },
+            }
+        }
+
+        match error {
+            Some(err) => Err(err),
+            None      => Ok(()),
+        }
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Returns the number of build steps to run concurrently if the user didn't say (i.e., the number of CPUs).
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+
+
+/// Runs a build step that does blocking work (e.g., copying files) on a separate thread, so it doesn't hold up the other steps.
+/// 
+/// **Arguments**
+///  * `step`: The name of the build step.
+///  * `work`: The work to do.
+/// 
+/// **Returns**  
+/// Whatever the work returned, or a BuildError if we could not run it.
+pub async fn run_blocking<F>(step: &str, work: F) -> Result<(), BuildError>
+where
+    F: 'static + FnOnce() -> Result<(), BuildError> + Send,
+{
+    match tokio::task::spawn_blocking(work).await {
+        Ok(result) => result,
+        Err(err)   => Err(BuildError::BuildStepSpawnError{ step: step.to_string(), err }),
+    }
+}
+
+
+
+/// Locks the given image tag, such that no other Docker invocation for the same tag runs at the same time.
+/// 
+/// **Arguments**
+///  * `tag`: The image tag to lock.
+/// 
+/// **Returns**  
+/// A guard that releases the lock when dropped.
+pub async fn lock_tag(tag: &str) -> OwnedMutexGuard<()> {
+    let lock = {
+        let mut locks = TAG_LOCKS.lock().unwrap();
+        locks.entry(tag.to_string()).or_insert_with(|| Arc::new(AsyncMutex::new(()))).clone()
+    };
+    lock.lock_owned().await
+}
+
+
+
+/// Runs the given command, printing its output with the name of the build step in front of every line.
+/// 
+/// **Arguments**
+///  * `step`: The name of the build step that runs the command.
+///  * `command`: The command to run.
+/// 
+/// **Returns**  
+/// The exit status of the command, or an IO error if we failed to run it.
+pub async fn run_prefixed(step: &str, command: &mut Command) -> Result<ExitStatus, std::io::Error> {
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+    command.kill_on_drop(true);
+    let mut child = command.spawn()?;
+
+    let stdout = child.stdout.take().map(|stdout| tokio::spawn(print_prefixed(step.to_string(), stdout, false)));
+    let stderr = child.stderr.take().map(|stderr| tokio::spawn(print_prefixed(step.to_string(), stderr, true)));
+    let status = child.wait().await?;
+    // Make sure we've printed everything before returning
+    if let Some(stdout) = stdout { let _ = stdout.await; }
+    if let Some(stderr) = stderr { let _ = stderr.await; }
+    Ok(status)
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Prints every line read from the given reader, prefixed with the name of the build step.
+/// 
+/// **Arguments**
+///  * `step`: The name of the build step.
+///  * `reader`: The stream to read the output from.
+///  * `stderr`: Whether to write to stderr (true) or stdout (false).
+async fn print_prefixed<R: AsyncRead + Unpin>(step: String, reader: R, stderr: bool) {
+    let prefix = style(format!("[{}]", step)).dim();
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => if stderr { eprintln!("{} {}", prefix, line) } else { println!("{} {}", prefix, line) },
+            Ok(None)       => { break; },
+            Err(err)       => { warn!("Could not read output of build step '{}': {}", step, err); break; },
+        }
+    }
+}
@@ -1,29 +1,46 @@
 use std::fs::{self, File};
 use std::io::{BufReader, Write};
 use std::path::PathBuf;
-use std::process::Command;
 use std::{fmt::Write as FmtWrite, path::Path};
 
 use console::style;
 use fs_extra::dir::CopyOptions;
 use path_clean::clean as clean_path;
+use tokio::process::Command;
 
 use specifications::container::{ContainerInfo, LocalContainerInfo};
-use specifications::package::PackageInfo;
+use specifications::package::{PackageInfo, ReproducibleBuild};
 
-use crate::build_common::{BRANELET_URL, JUICE_URL, build_docker_image, clean_directory, lock_directory, unlock_directory};
+use crate::build_common::{JUICE_URL_TARGETARCH, BuildFlags, ImageDifference, ImageOptions, ImageOutput, archive_normalized, buildx_build, check_platforms, clean_directory, compare_images, ensure_buildx, hash_inputs, read_image_manifest};
+use crate::build_dag::{BuildDag, default_jobs, run_blocking};
 use crate::errors::BuildError;
+use crate::index_cache;
+use crate::init_binary::{self, SelectedInit};
+use crate::lock::PackageLock;
 use crate::utils::ensure_package_dir;
 
 
+/***** CONSTANTS *****/
+/// The name of the Dockerfile stage with everything that does not depend on the package's own files.
+const DEPS_STAGE: &str = "deps";
+
+/// The files in the package directory that make up the build context (besides the init binaries), which are hashed for reproducible builds.
+const BUILD_INPUTS: [&str; 2] = [ "Dockerfile", "container/wd.tar.gz" ];
+
+
+
+
+
 /***** BUILD FUNCTIONS *****/
-/// **Edited: Now wrapping around build() to handle the lock file properly.
+/// **Edited: Now wrapping around build() to hold the package lock while building.
 /// 
 /// **Arguments**
 ///  * `context`: The directory to copy additional files (executable, working directory files) from.
 ///  * `file`: Path to the package's main file (a container file, in this case).
-///  * `branelet_path`: Optional path to a custom branelet executable. If left empty, will pull the standard one from Github instead.
+///  * `branelet_path`: Optional path to a custom branelet executable (or a directory with one per architecture). If left empty, will use the prebuilt one for every platform instead.
 ///  * `keep_files`: Determines whether or not to keep the build files after building.
+///  * `jobs`: The maximum number of build steps to run at the same time.
+///  * `image`: The platforms to build the image for, the registry to push it to (if any) and whether to build it reproducibly.
 /// 
 /// **Returns**  
 /// Nothing if the package is build successfully, but a BuildError otherwise.
@@ -32,10 +49,18 @@ pub async fn handle(
     file: PathBuf,
     branelet_path: Option<PathBuf>,
     keep_files: bool,
+    jobs: usize,
+    image: ImageOptions,
 ) -> Result<(), BuildError> {
     debug!("Building ecu package from container file '{}'...", file.display());
     debug!("Using {} as build context", context.display());
 
+    // Buildx can only load a single platform, so more than one must be pushed somewhere
+    check_platforms(&image.platforms)?;
+    if image.platforms.len() > 1 && image.push.is_none() {
+        return Err(BuildError::MultiPlatformWithoutRegistry{ platforms: image.platforms });
+    }
+
     // Read the package into a ContainerInfo.
     let handle = match File::open(&file) {
         Ok(handle) => handle,
@@ -47,32 +72,41 @@ pub async fn handle(
         Err(err)     => { return Err(BuildError::ContainerInfoParseError{ file, err }); }
     };
 
+    // Check that it makes sense before we invoke Docker
+    if let Err(err) = document.validate(&context) {
+        return Err(BuildError::ContainerInfoValidationError{ file, err });
+    }
+
+    // Make sure nobody else is working on (any version of) the package
+    let _lock = match PackageLock::acquire_async(&document.name, "build").await {
+        Ok(lock) => lock,
+        Err(err) => { return Err(BuildError::LockError{ err }); }
+    };
+
     // Prepare package directory
     let package_dir = match ensure_package_dir(&document.name, Some(&document.version), true) {
         Ok(package_dir) => package_dir,
         Err(err)        => { return Err(BuildError::PackageDirError{ err }); }
     };
 
-    // Lock the directory, build, unlock the directory
-    lock_directory(&package_dir)?;
-    let res = build(document, context, &package_dir, branelet_path, keep_files).await;
-    unlock_directory(&package_dir);
-
-    // Return the result of the build process
-    res
+    // Build (the lock is released when we return)
+    build(document, context, &package_dir, branelet_path, keep_files, jobs, image).await
 }
 
 
 
+/// **Edited: now running the build steps concurrently, and returning the error if the build failed.**
+/// 
 /// Actually builds a new Ecu package from the given file(s).
 /// 
 /// **Arguments**
 ///  * `document`: The ContainerInfo document describing the package.
 ///  * `context`: The directory to copy additional files (executable, working directory files) from.
 ///  * `package_dir`: The package directory to use as the build folder.
-///  * `package_info`: The PackageInfo document also describing the package, but in a package-kind-oblivious way.
-///  * `branelet_path`: Optional path to a custom branelet executable. If left empty, will pull the standard one from Github instead.
+///  * `branelet_path`: Optional path to a custom branelet executable (or a directory with one per architecture). If left empty, will use the prebuilt one for every platform instead.
 ///  * `keep_files`: Determines whether or not to keep the build files after building.
+///  * `jobs`: The maximum number of build steps to run at the same time.
+///  * `image`: The platforms to build the image for, the registry to push it to (if any) and whether to build it reproducibly.
 /// 
 /// **Returns**  
 /// Nothing if the package is build successfully, but a BuildError otherwise.
@@ -82,22 +116,26 @@ async fn build(
     package_dir: &Path,
     branelet_path: Option<PathBuf>,
     keep_files: bool,
+    jobs: usize,
+    image: ImageOptions,
 ) -> Result<(), BuildError> {
+    // Select an init binary for every architecture we build for, so we fail before Docker does
+    let inits = init_binary::select_all(branelet_path, &image.built_platforms()).await?;
+
     // Prepare the build directory
-    let dockerfile = generate_dockerfile(&document, &context, branelet_path.is_some())?;
-    prepare_directory(
-        &document,
-        dockerfile,
-        branelet_path,
-        &context,
-        package_dir,
-    )?;
+    let dockerfile = generate_dockerfile(&document, &context)?;
+    let container_dir = prepare_directory(dockerfile, package_dir)?;
     debug!("Successfully prepared package directory.");
 
     // Build Docker image
     let tag = format!("{}:{}", document.name, document.version);
-    debug!("Launching Docker in directory '{}'", package_dir.display());
-    match build_docker_image(package_dir, tag) {
+    debug!("Launching Docker in directory '{}' (with at most {} build steps at a time)", package_dir.display(), jobs);
+    let steps = build_steps(&document, context, package_dir, container_dir, inits.clone(), tag.clone(), &image);
+    let result = match steps.run(jobs).await {
+        Ok(_) if image.verify_reproducible => verify_reproducible(package_dir, &tag, &image).await,
+        result                             => result,
+    };
+    match result {
         Ok(_) => {
             println!(
                 "Successfully built version {} of container (ECU) package {}.",
@@ -110,12 +148,20 @@ async fn build(
             if let Err(err) = package_info.resolve_digest(package_dir.join("image.tar")) {
                 return Err(BuildError::DigestError{ err });
             }
+            package_info.platforms = image.built_platforms();
+            if let Some(source_date_epoch) = image.source_date_epoch {
+                let inputs = build_inputs(&inits);
+                let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+                package_info.reproducible = Some(ReproducibleBuild{ source_date_epoch, inputs: hash_inputs(package_dir, &inputs)? });
+            }
+            package_info.init = inits.into_iter().map(|init| init.binary).collect();
 
             // Write it to package directory
             let package_path = package_dir.join("package.yml");
             if let Err(err) = package_info.to_path(&package_path) {
                 return Err(BuildError::PackageFileCreateError{ err });
             }
+            index_cache::invalidate(&package_info.name, Some(&package_info.version));
     
             // // Check if previous build is still loaded in Docker
             // let image_name = format!("{}:{}", package_info.name, package_info.version);
@@ -127,15 +173,15 @@ async fn build(
     
             // Remove all non-essential files.
             if !keep_files { clean_directory(package_dir, vec![ "Dockerfile", "container" ]); }
+
+            // Done
+            Ok(())
         },
 
         Err(err) => {
-            // Print the error first
-            eprintln!("{}", err);
-
-            // Print some output message, and then cleanup
+            // Print some output message, and then cleanup (the error itself is printed by the caller)
             println!(
-                "Failed to build version {} of container (ECU) package {}. See error output above.",
+                "Failed to build version {} of container (ECU) package {}.",
                 style(&document.version).bold().cyan(),
                 style(&document.name).bold().cyan(),
             );
@@ -144,11 +190,203 @@ async fn build(
             if !keep_files {
                 if let Err(err) = fs::remove_dir_all(&package_dir) { return Err(BuildError::CleanupError{ path: package_dir.to_path_buf(), err }); }
             }
+            Err(err)
         }
     }
+}
 
-    // Done
-    Ok(())
+/// Collects the steps that build the package image into a BuildDag.
+/// 
+/// The dependency layer (base image, dependencies, branelet and JuiceFS) is built while the working directory is being prepared, and only the final image waits for both.
+/// 
+/// **Arguments**
+///  * `document`: The ContainerInfo document describing the package.
+///  * `context`: The directory to copy additional files (executable, working directory files) from.
+///  * `package_dir`: The package directory with the Dockerfile in it.
+///  * `container_dir`: The container directory within the package directory.
+///  * `inits`: The init binaries to put in the container directory.
+///  * `tag`: The tag of the image to build.
+///  * `image`: The platforms to build the image for, the registry to push it to (if any) and whether to build it reproducibly.
+/// 
+/// **Returns**  
+/// The BuildDag that, when run, leaves the image.tar in the package directory (and pushes the image, if asked).
+fn build_steps(
+    document: &ContainerInfo,
+    context: PathBuf,
+    package_dir: &Path,
+    container_dir: PathBuf,
+    inits: Vec<SelectedInit>,
+    tag: String,
+    image: &ImageOptions,
+) -> BuildDag {
+    let mut steps = BuildDag::new();
+
+    steps.add("buildx", &[], ensure_buildx());
+
+    // Fill the container directory, and meanwhile build everything that doesn't depend on the package's own files (only on the init binaries)
+    context_steps(&mut steps, document, context, container_dir, inits, image.source_date_epoch);
+    {
+        let package_dir = package_dir.to_path_buf();
+        let tag = tag.clone();
+        let platforms = image.platforms.clone();
+        let flags = image.flags();
+        steps.add("deps", &[ "buildx", "branelet" ], async move { buildx_build(package_dir, &tag, ImageOutput::Cache(DEPS_STAGE), &platforms, flags, Some("deps")).await });
+    }
+
+    // Push the image for all platforms if asked; the image.tar is then built from the cache
+    let mut image_dependencies = vec![ "deps", "archive" ];
+    if let Some(registry) = &image.push {
+        let package_dir = package_dir.to_path_buf();
+        let tag = tag.clone();
+        let reference = format!("{}/{}:{}", registry.trim_end_matches('/'), document.name, document.version);
+        let platforms = image.platforms.clone();
+        let flags = image.flags();
+        steps.add("push", &[ "deps", "archive" ], async move { buildx_build(package_dir, &tag, ImageOutput::Push(&reference), &platforms, flags, Some("push")).await });
+        image_dependencies = vec![ "push" ];
+    }
+
+    // Finally, build the image itself (for a single platform, as that's all an image.tar can hold)
+    let package_dir = package_dir.to_path_buf();
+    let platforms: Vec<String> = image.tar_platform().into_iter().collect();
+    let flags = image.flags();
+    steps.add("image", &image_dependencies, async move { buildx_build(package_dir, &tag, ImageOutput::Tar, &platforms, flags, Some("image")).await });
+
+    steps
+}
+
+/// Adds the steps that fill the container directory to a BuildDag: the "branelet" step that copies the init binaries there, the "workdir" step that fills the working directory and the "archive" step that archives it.
+/// 
+/// **Arguments**
+///  * `steps`: The BuildDag to add the steps to.
+///  * `document`: The ContainerInfo document describing the package.
+///  * `context`: The directory to copy additional files (executable, working directory files) from.
+///  * `container_dir`: The container directory within the package directory.
+///  * `inits`: The init binaries to copy to the container directory (as `branelet-<arch>`).
+///  * `source_date_epoch`: If given, the build is reproducible: the working directory is archived with normalized metadata.
+fn context_steps(
+    steps: &mut BuildDag,
+    document: &ContainerInfo,
+    context: PathBuf,
+    container_dir: PathBuf,
+    inits: Vec<SelectedInit>,
+    source_date_epoch: Option<u64>,
+) {
+    {
+        let container_dir = container_dir.clone();
+        steps.add("branelet", &[], run_blocking("branelet", move || init_binary::copy_all(&inits, &container_dir)));
+    }
+    {
+        let document = document.clone();
+        let container_dir = container_dir.clone();
+        steps.add("workdir", &[], run_blocking("workdir", move || prepare_workdir(&document, &context, &container_dir)));
+    }
+    match source_date_epoch {
+        Some(epoch) => steps.add("archive", &[ "workdir" ], run_blocking("archive", move || archive_normalized(&container_dir.join("wd"), &container_dir.join("wd.tar.gz"), epoch))),
+        None        => steps.add("archive", &[ "workdir" ], archive_workdir(container_dir)),
+    }
+}
+
+/// Returns the files in the package directory that make up the build context, which are hashed for reproducible builds.
+/// 
+/// **Arguments**
+///  * `inits`: The init binaries that were copied to the container directory.
+/// 
+/// **Returns**  
+/// The paths of the files, relative to the package directory.
+fn build_inputs(inits: &[SelectedInit]) -> Vec<String> {
+    let mut inputs: Vec<String> = BUILD_INPUTS.iter().map(|input| input.to_string()).collect();
+    inputs.extend(inits.iter().map(|init| format!("container/{}", init_binary::binary_name(&init.binary.arch))));
+    inputs
+}
+
+/// Writes the build context of a package (the Dockerfile and the container directory) to the package directory, without building the image.
+/// 
+/// This runs the same steps as a build does before it invokes Docker, so for reproducible builds, running it twice gives the exact same files.
+/// 
+/// **Arguments**
+///  * `document`: The ContainerInfo document describing the package.
+///  * `context`: The directory to copy additional files (executable, working directory files) from.
+///  * `package_dir`: The directory to write the build context to.
+///  * `branelet_path`: Optional path to a custom branelet executable (or a directory with one per architecture). If left empty, the prebuilt one is used for every platform.
+///  * `platforms`: The platforms that the image would be built for.
+///  * `source_date_epoch`: The SOURCE_DATE_EPOCH of a reproducible build, or None for a regular build.
+/// 
+/// **Returns**  
+/// Nothing if the build context was written successfully, or a BuildError otherwise.
+pub async fn build_context(
+    document: &ContainerInfo,
+    context: PathBuf,
+    package_dir: &Path,
+    branelet_path: Option<PathBuf>,
+    platforms: &[String],
+    source_date_epoch: Option<u64>,
+) -> Result<(), BuildError> {
+    let inits = init_binary::select_all(branelet_path, platforms).await?;
+    let dockerfile = generate_dockerfile(document, &context)?;
+    let container_dir = prepare_directory(dockerfile, package_dir)?;
+
+    let mut steps = BuildDag::new();
+    context_steps(&mut steps, document, context, container_dir, inits, source_date_epoch);
+    steps.run(default_jobs()).await
+}
+
+/// Builds the image of a package a second time, without cache, and checks that it is the same as the image.tar of the first build.
+/// 
+/// **Arguments**
+///  * `package_dir`: The package directory with the Dockerfile and the image.tar of the first build in it.
+///  * `tag`: The tag of the image.
+///  * `image`: The ImageOptions of the first build.
+/// 
+/// **Returns**  
+/// Nothing if the image is the same (the image.tar of the second build is left in the package directory), or a BuildError::NotReproducible with the first difference otherwise.
+async fn verify_reproducible(
+    package_dir: &Path,
+    tag: &str,
+    image: &ImageOptions,
+) -> Result<(), BuildError> {
+    // Move the first image aside
+    let image_tar = package_dir.join("image.tar");
+    let first_tar = package_dir.join("image.first.tar");
+    if let Err(err) = fs::rename(&image_tar, &first_tar) {
+        return Err(BuildError::ImageTarMoveError{ source: image_tar, target: first_tar, err });
+    }
+
+    // Build it again from scratch and compare them, removing the first image whether that works or not
+    let difference = rebuild_and_compare(package_dir, tag, image, &first_tar, &image_tar).await;
+    if let Err(err) = fs::remove_file(&first_tar) { warn!("{}", BuildError::FileCleanupError{ path: first_tar, err }); }
+    match difference? {
+        Some(difference) => Err(BuildError::NotReproducible{ difference }),
+        None             => {
+            println!("Image {} is reproducible: building it again gave the same image.", style(tag).bold().cyan());
+            Ok(())
+        },
+    }
+}
+
+/// Builds the image of a package a second time, without cache, and compares it with the image of the first build (see `verify_reproducible()`).
+/// 
+/// **Arguments**
+///  * `package_dir`: The package directory with the Dockerfile in it.
+///  * `tag`: The tag of the image.
+///  * `image`: The ImageOptions of the first build.
+///  * `first_tar`: The image.tar of the first build.
+///  * `image_tar`: Where the second build writes its image.tar.
+/// 
+/// **Returns**  
+/// The first difference between the images if they differ, None if they are the same, or a BuildError if we could not build or read them.
+async fn rebuild_and_compare(
+    package_dir: &Path,
+    tag: &str,
+    image: &ImageOptions,
+    first_tar: &Path,
+    image_tar: &Path,
+) -> Result<Option<ImageDifference>, BuildError> {
+    println!("Building the image again to verify that it is reproducible...");
+    let platforms: Vec<String> = image.tar_platform().into_iter().collect();
+    let flags = BuildFlags{ source_date_epoch: image.source_date_epoch, no_cache: true };
+    buildx_build(package_dir, tag, ImageOutput::Tar, &platforms, flags, Some("verify")).await?;
+
+    Ok(compare_images(&read_image_manifest(first_tar)?, &read_image_manifest(image_tar)?))
 }
 
 /// **Edited: now returning BuildErrors.**
@@ -158,14 +396,12 @@ async fn build(
 /// **Arguments**
 ///  * `document`: The ContainerInfo describing the package to build.
 ///  * `context`: The directory to find the executable in.
-///  * `override_branelet`: Whether or not to override the branelet executable. If so, assumes the new one is copied to the temporary build folder by the time the DockerFile is run.
 /// 
 /// **Returns**  
 /// A String that is the new DockerFile on success, or a BuildError otherwise.
 fn generate_dockerfile(
     document: &ContainerInfo,
     context: &Path,
-    override_branelet: bool,
 ) -> Result<String, BuildError> {
     let mut contents = String::new();
 
@@ -174,10 +410,12 @@ fn generate_dockerfile(
 
     // Add default heading
     writeln_build!(contents, "# Generated by Brane")?;
-    writeln_build!(contents, "FROM {}", base)?;
+    writeln_build!(contents, "FROM {} AS {}", base, DEPS_STAGE)?;
 
-    // Add environemt variables
+    // Add environemt variables (sorted, so the Dockerfile is the same for every build); those without a default are only set at call time
     if let Some(environment) = &document.environment {
+        let mut environment: Vec<(&String, &str)> = environment.iter().filter_map(|(key, variable)| variable.default_value().map(|value| (key, value))).collect();
+        environment.sort();
         for (key, value) in environment {
             writeln_build!(contents, "ENV {}={}", key, value)?;
         }
@@ -199,24 +437,22 @@ fn generate_dockerfile(
     }
     writeln_build!(contents)?;
 
-    // Add the branelet executable
-    if override_branelet {
-        // It's the custom in the temp dir
-        writeln_build!(contents, "ADD ./container/branelet /branelet")?;
-    } else {
-        // It's the prebuild one
-        writeln_build!(contents, "ADD {} /branelet", BRANELET_URL)?;
-    }
+    // Add the branelet executable (for the architecture we're building for; they're all in the container directory)
+    writeln_build!(contents, "ARG TARGETARCH")?;
+    writeln_build!(contents, "ADD ./container/branelet-${{TARGETARCH}} /branelet")?;
     // Always make it executable
     writeln_build!(contents, "RUN chmod +x /branelet")?;
 
-    // Add JuiceFS
-    writeln_build!(contents, "ADD {} /juicefs.tar.gz", JUICE_URL)?;
+    // Add JuiceFS (for the architecture we're building for)
+    writeln_build!(contents, "ADD {} /juicefs.tar.gz", JUICE_URL_TARGETARCH)?;
     writeln_build!(
         contents,
         "RUN tar -xzf /juicefs.tar.gz && rm /juicefs.tar.gz && mkdir /data"
     )?;
 
+    // Everything above does not depend on the package files, and may be built while we prepare them
+    writeln_build!(contents, "FROM {}", DEPS_STAGE)?;
+
     // Copy the package files
     writeln_build!(contents, "ADD ./container/wd.tar.gz /opt")?;
     writeln_build!(contents, "WORKDIR /opt/wd")?;
@@ -243,27 +479,20 @@ fn generate_dockerfile(
     Ok(contents)
 }
 
-/// **Edited: now returning BuildErrors.**
+/// **Edited: now only writing the Dockerfile and creating the container directory; the rest is done by the build steps.**
 /// 
 /// Prepares the build directory for building the package.
 /// 
 /// **Arguments**
-///  * `document`: The ContainerInfo document carrying metadata about the package.
 ///  * `dockerfile`: The generated DockerFile that will be used to build the package.
-///  * `branelet_path`: The optional branelet path in case we want it overriden.
-///  * `context`: The directory to copy additional files (executable, working directory files) from.
-///  * `package_info`: The generated PackageInfo from the ContainerInfo document.
 ///  * `package_dir`: The directory where we can build the package and store it once done.
 /// 
 /// **Returns**  
-/// Nothing if the directory was created successfully, or a BuildError otherwise.
+/// The path of the container directory if the directory was prepared successfully, or a BuildError otherwise.
 fn prepare_directory(
-    document: &ContainerInfo,
     dockerfile: String,
-    branelet_path: Option<PathBuf>,
-    context: &Path,
     package_dir: &Path,
-) -> Result<(), BuildError> {
+) -> Result<PathBuf, BuildError> {
     // Write Dockerfile to package directory
     let file_path = package_dir.join("Dockerfile");
     match File::create(&file_path) {
@@ -285,19 +514,23 @@ fn prepare_directory(
         }
     }
 
-    // Copy custom branelet binary to package directory if needed
-    if let Some(branelet_path) = branelet_path {
-        // Try to resole the branelet's path
-        let source = match std::fs::canonicalize(&branelet_path) {
-            Ok(source) => source,
-            Err(err)   => { return Err(BuildError::BraneletCanonicalizeError{ path: branelet_path, err }); }
-        };
-        let target = container_dir.join("branelet");
-        if let Err(err) = fs::copy(&source, &target) {
-            return Err(BuildError::BraneletCopyError{ source, target, err });
-        }
-    }
+    Ok(container_dir)
+}
 
+/// Fills the working directory in the container directory with the package files.
+/// 
+/// **Arguments**
+///  * `document`: The ContainerInfo document carrying metadata about the package.
+///  * `context`: The directory to copy additional files (executable, working directory files) from.
+///  * `container_dir`: The container directory to create the working directory in.
+/// 
+/// **Returns**  
+/// Nothing if the working directory was created successfully, or a BuildError otherwise.
+fn prepare_workdir(
+    document: &ContainerInfo,
+    context: &Path,
+    container_dir: &Path,
+) -> Result<(), BuildError> {
     // Create a workdirectory and make sure it's empty
     let wd = container_dir.join("wd");
     if wd.exists() {
@@ -356,18 +589,31 @@ fn prepare_directory(
         }
     }
 
+    Ok(())
+}
+
+/// Archives the working directory, such that the Dockerfile can add it in one go.
+/// 
+/// **Arguments**
+///  * `container_dir`: The container directory with the working directory in it.
+/// 
+/// **Returns**  
+/// Nothing if the working directory was archived successfully, or a BuildError otherwise.
+async fn archive_workdir(
+    container_dir: PathBuf,
+) -> Result<(), BuildError> {
     // Archive the working directory
     let mut command = Command::new("tar");
     command.arg("-zcf");
     command.arg("wd.tar.gz");
     command.arg("wd");
     command.current_dir(&container_dir);
-    let output = match command.output() {
+    let output = match command.output().await {
         Ok(output) => output,
-        Err(err)   => { return Err(BuildError::WdCompressionLaunchError{ command: format!("{:?}", command), err }); }
+        Err(err)   => { return Err(BuildError::WdCompressionLaunchError{ command: format!("{:?}", command.as_std()), err }); }
     };
     if !output.status.success() {
-        return Err(BuildError::WdCompressionError{ command: format!("{:?}", command), code: output.status.code().unwrap_or(-1), stdout: String::from_utf8_lossy(&output.stdout).to_string(), stderr: String::from_utf8_lossy(&output.stderr).to_string() });
+        return Err(BuildError::WdCompressionError{ command: format!("{:?}", command.as_std()), code: output.status.code().unwrap_or(-1), stdout: String::from_utf8_lossy(&output.stdout).to_string(), stderr: String::from_utf8_lossy(&output.stderr).to_string() });
     }
 
     // We're done with the working directory zip!
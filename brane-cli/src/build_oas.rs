@@ -12,18 +12,21 @@ use openapiv3::OpenAPI;
 use specifications::package::{PackageKind, PackageInfo};
 use specifications::version::Version;
 
-use crate::build_common::{BRANELET_URL, JUICE_URL, build_docker_image, clean_directory, lock_directory, unlock_directory};
+use crate::build_common::{JUICE_URL, build_docker_image, clean_directory, host_platform};
 use crate::errors::BuildError;
+use crate::index_cache;
+use crate::init_binary::{self, SelectedInit};
+use crate::lock::PackageLock;
 use crate::utils::ensure_package_dir;
 
 
 /***** BUILD FUNCTIONS *****/
-/// **Edited: Now wrapping around build() to handle the lock file properly.
+/// **Edited: Now wrapping around build() to hold the package lock while building.
 /// 
 /// **Arguments**
 ///  * `context`: The directory to copy additional files (executable, working directory files) from.
 ///  * `file`: Path to the package's main file (a container file, in this case).
-///  * `branelet_path`: Optional path to a custom branelet executable. If left empty, will pull the standard one from Github instead.
+///  * `branelet_path`: Optional path to a custom branelet executable (or a directory with one per architecture). If left empty, will use the prebuilt one for the host platform instead.
 ///  * `keep_files`: Determines whether or not to keep the build files after building.
 /// 
 /// **Returns**  
@@ -43,20 +46,19 @@ pub async fn handle(
         Err(err)     => { return Err(BuildError::OasDocumentParseError{ file, err }); }
     };
 
-    // Prepare package directory
+    // Prepare package directory, making sure nobody else is working on (any version of) the package
     let package_info = create_package_info(&document)?;
+    let _lock = match PackageLock::acquire_async(&package_info.name, "build").await {
+        Ok(lock) => lock,
+        Err(err) => { return Err(BuildError::LockError{ err }); }
+    };
     let package_dir = match ensure_package_dir(&package_info.name, Some(&package_info.version), true) {
         Ok(package_dir) => package_dir,
         Err(err)        => { return Err(BuildError::PackageDirError{ err }); }
     };
 
-    // Lock the directory, build, unlock the directory
-    lock_directory(&package_dir)?;
-    let res = build(document, package_info, &package_dir, branelet_path, keep_files).await;
-    unlock_directory(&package_dir);
-
-    // Return the result of the build process
-    res
+    // Build (the lock is released when we return)
+    build(document, package_info, &package_dir, branelet_path, keep_files).await
 }
 
 /// **Edited: now returning BuildErrors.**
@@ -95,6 +97,7 @@ fn create_package_info(
         false,
         functions,
         types,
+        vec![],
     ))
 }
 
@@ -106,7 +109,7 @@ fn create_package_info(
 ///  * `document`: The OpenAPI document describing the package.
 ///  * `package_dir`: The package directory to use as the build folder.
 ///  * `package_info`: The PackageInfo document also describing the package, but in a package-kind-oblivious way.
-///  * `branelet_path`: Optional path to a custom branelet executable. If left empty, will pull the standard one from Github instead.
+///  * `branelet_path`: Optional path to a custom branelet executable (or a directory with one per architecture). If left empty, will use the prebuilt one for the host platform instead.
 ///  * `keep_files`: Determines whether or not to keep the build files after building.
 /// 
 /// **Returns**  
@@ -118,12 +121,13 @@ async fn build(
     branelet_path: Option<PathBuf>,
     keep_files: bool,
 ) -> Result<(), BuildError> {
-    // Prepare package directory.
-    let dockerfile = generate_dockerfile(branelet_path.is_some())?;
+    // Prepare package directory (the image is built for the host platform, so that's the init binary we need)
+    let inits = init_binary::select_all(branelet_path, &[ host_platform() ]).await?;
+    let dockerfile = generate_dockerfile()?;
     prepare_directory(
         &document,
         dockerfile,
-        branelet_path,
+        &inits,
         package_dir
     )?;
     debug!("Successfully prepared package directory.");
@@ -135,7 +139,7 @@ async fn build(
     // Build Docker image
     let tag = format!("{}:{}", package_info.name, package_info.version);
     debug!("Launching Docker in directory '{}'", package_dir.display());
-    match build_docker_image(package_dir, tag) {
+    match build_docker_image(package_dir, tag).await {
         Ok(_) => {
             println!(
                 "Successfully built version {} of Web API (OAS) package {}.",
@@ -148,12 +152,14 @@ async fn build(
             if let Err(err) = package_info.resolve_digest(package_dir.join("image.tar")) {
                 return Err(BuildError::DigestError{ err });
             }
+            package_info.init = inits.into_iter().map(|init| init.binary).collect();
 
             // Write it to package directory
             let package_path = package_dir.join("package.yml");
             if let Err(err) = package_info.to_path(&package_path) {
                 return Err(BuildError::PackageFileCreateError{ err });
             }
+            index_cache::invalidate(&package_info.name, Some(&package_info.version));
 
             // // Check if previous build is still loaded in Docker
             // let image_name = format!("{}:{}", package_info.name, package_info.version);
@@ -185,19 +191,13 @@ async fn build(
     Ok(())
 }
 
-/// **Edited: now returning BuildErrors + removing oas_file argument since it wasn't used.**
+/// **Edited: now returning BuildErrors + removing oas_file argument since it wasn't used + always adding the branelet for the TARGETARCH from the container directory.**
 /// 
 /// Generates a new DockerFile that can be used to build the package into a Docker container.
 /// 
-/// **Arguments**
-///  * `document`: The OpenAPI document describing the package to build.
-///  * `override_branelet`: Whether or not to override the branelet executable. If so, assumes the new one is copied to the temporary build folder by the time the DockerFile is run.
-/// 
 /// **Returns**  
 /// A String that is the new DockerFile on success, or a BuildError otherwise.
-fn generate_dockerfile(
-    override_branelet: bool,
-) -> Result<String, BuildError> {
+fn generate_dockerfile() -> Result<String, BuildError> {
     let mut contents = String::new();
 
     // Add default heading
@@ -207,12 +207,9 @@ fn generate_dockerfile(
     // Add dependencies
     writeln_build!(contents, "RUN apk add --no-cache iptables")?;
 
-    // Add the branelet executable
-    if override_branelet {
-        writeln_build!(contents, "ADD ./container/branelet /branelet")?;
-    } else {
-        writeln_build!(contents, "ADD {} /branelet", BRANELET_URL)?;
-    }
+    // Add the branelet executable (for the architecture we're building for)
+    writeln_build!(contents, "ARG TARGETARCH")?;
+    writeln_build!(contents, "ADD ./container/branelet-${{TARGETARCH}} /branelet")?;
     writeln_build!(contents, "RUN chmod +x /branelet")?;
 
     // Add JuiceFS
@@ -241,7 +238,7 @@ fn generate_dockerfile(
 /// **Arguments**
 ///  * `document`: The OpenAPI document carrying metadata about the package.
 ///  * `dockerfile`: The generated DockerFile that will be used to build the package.
-///  * `branelet_path`: The optional branelet path in case we want it overriden.
+///  * `inits`: The init binaries to copy to the container directory.
 ///  * `package_info`: The generated PackageInfo from the ContainerInfo document.
 ///  * `package_dir`: The directory where we can build the package and store it once done.
 /// 
@@ -250,7 +247,7 @@ fn generate_dockerfile(
 fn prepare_directory(
     document: &OpenAPI,
     dockerfile: String,
-    branelet_path: Option<PathBuf>,
+    inits: &[SelectedInit],
     package_dir: &Path,
 ) -> Result<(), BuildError> {
     // Write the Dockerfile to the package directory
@@ -274,18 +271,8 @@ fn prepare_directory(
         }
     }
 
-    // Copy custom branelet binary to container directory if needed
-    if let Some(branelet_path) = branelet_path {
-        // Try to resole the branelet's path
-        let source = match std::fs::canonicalize(&branelet_path) {
-            Ok(source) => source,
-            Err(err)   => { return Err(BuildError::BraneletCanonicalizeError{ path: branelet_path, err }); }
-        };
-        let target = container_dir.join("branelet");
-        if let Err(err) = fs::copy(&source, &target) {
-            return Err(BuildError::BraneletCopyError{ source, target, err });
-        }
-    }
+    // Copy the init binaries to the container directory
+    init_binary::copy_all(inits, &container_dir)?;
 
     // Create a workdirectory and make sure it's empty
     let wd = container_dir.join("wd");
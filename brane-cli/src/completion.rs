@@ -0,0 +1,198 @@
+/* COMPLETION.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 23:51:17
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Generates shell completion scripts for the `brane` command (see
+ *   `brane completion`).
+ *
+ *   On top of what clap generates, the bash, zsh and fish scripts
+ *   complete the names and versions of local packages by calling the
+ *   hidden `brane __complete packages` and
+ *   `brane __complete versions <package>` helpers, which print one
+ *   candidate per line (and nothing at all if anything goes wrong).
+**/
+
+use clap::Command;
+use clap_complete::Shell;
+
+use specifications::package::PackageIndex;
+
+use crate::packages;
+
+
+/***** CONSTANTS *****/
+/// The subcommands whose first positional argument is the name of a local package.
+pub const PACKAGE_COMMANDS: &[&str] = &[ "export", "inspect", "load", "push", "remove", "test", "unpublish" ];
+/// The subcommands whose second positional argument is the version of that package.
+pub const VERSION_COMMANDS: &[&str] = &[ "export", "inspect", "push", "remove", "unpublish" ];
+/// The subcommands that take the version of that package with '-v' / '--version' instead.
+pub const VERSION_FLAG_COMMANDS: &[&str] = &[ "load", "test" ];
+
+/// Completes package names and versions in bash, falling back to clap's completion otherwise.
+const BASH_DYNAMIC: &str = r#"
+# Complete the names and versions of local packages
+_brane_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    local prev="${COMP_WORDS[COMP_CWORD-1]}"
+    if [[ ${COMP_CWORD} -eq 2 && " {PACKAGE_COMMANDS} " == *" ${COMP_WORDS[1]} "* ]]; then
+        COMPREPLY=( $(compgen -W "$(brane __complete packages 2>/dev/null)" -- "${cur}") )
+        return 0
+    fi
+    if [[ ( ${COMP_CWORD} -eq 3 && " {VERSION_COMMANDS} " == *" ${COMP_WORDS[1]} "* ) || ( ${COMP_CWORD} -gt 3 && ( "${prev}" == "-v" || "${prev}" == "--version" ) && " {VERSION_FLAG_COMMANDS} " == *" ${COMP_WORDS[1]} "* ) ]]; then
+        COMPREPLY=( $(compgen -W "$(brane __complete versions "${COMP_WORDS[2]}" 2>/dev/null)" -- "${cur}") )
+        return 0
+    fi
+    _brane "$@"
+}
+complete -F _brane_dynamic -o bashdefault -o default brane
+"#;
+
+/// Completes package names and versions in zsh, falling back to clap's completion otherwise.
+const ZSH_DYNAMIC: &str = r#"
+# Complete the names and versions of local packages
+_brane_dynamic() {
+    local -a candidates
+    if (( CURRENT == 3 )) && [[ " {PACKAGE_COMMANDS} " == *" ${words[2]} "* ]]; then
+        candidates=( ${(f)"$(brane __complete packages 2>/dev/null)"} )
+        compadd -a candidates
+    elif { (( CURRENT == 4 )) && [[ " {VERSION_COMMANDS} " == *" ${words[2]} "* ]]; } || { (( CURRENT > 4 )) && [[ ${words[CURRENT-1]} == (-v|--version) && " {VERSION_FLAG_COMMANDS} " == *" ${words[2]} "* ]]; }; then
+        candidates=( ${(f)"$(brane __complete versions ${words[3]} 2>/dev/null)"} )
+        compadd -a candidates
+    else
+        _brane "$@"
+    fi
+}
+
+compdef _brane_dynamic brane
+if [ "$funcstack[1]" = "_brane" ]; then
+    _brane_dynamic "$@"
+fi
+"#;
+
+/// Completes package names and versions in fish, on top of clap's completion.
+const FISH_DYNAMIC: &str = r#"
+# Complete the names and versions of local packages
+function __brane_needs_package
+    set -l cmd (commandline -opc)
+    test (count $cmd) -eq 2; and contains -- $cmd[2] {PACKAGE_COMMANDS}
+end
+function __brane_needs_version
+    set -l cmd (commandline -opc)
+    if test (count $cmd) -eq 3; and contains -- $cmd[2] {VERSION_COMMANDS}
+        return 0
+    end
+    test (count $cmd) -gt 3; and contains -- $cmd[-1] -v --version; and contains -- $cmd[2] {VERSION_FLAG_COMMANDS}
+end
+complete -c brane -n __brane_needs_package -f -a "(brane __complete packages 2>/dev/null)"
+complete -c brane -n __brane_needs_version -f -a "(brane __complete versions (commandline -opc)[3] 2>/dev/null)"
+"#;
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Generates the completion script of the given command for the given shell.
+/// 
+/// **Arguments**
+///  * `shell`: The shell to generate the script for.
+///  * `command`: The (clap) description of the `brane` command.
+/// 
+/// **Returns**  
+/// The script, which completes package names and versions too for bash, zsh and fish.
+pub fn script(shell: Shell, command: &mut Command) -> String {
+    let mut script: Vec<u8> = vec![];
+    clap_complete::generate(shell, command, "brane", &mut script);
+    let mut script = String::from_utf8_lossy(&script).to_string();
+
+    let dynamic = match shell {
+        Shell::Bash => BASH_DYNAMIC,
+        Shell::Fish => FISH_DYNAMIC,
+        Shell::Zsh  => match strip_zsh_dispatch(&script) {
+            // We decide which function completes, so clap's may not run (or be registered) on its own
+            Some(stripped) => { script = stripped.to_string(); ZSH_DYNAMIC },
+            // If clap's script doesn't end the way we know, we can't take over without breaking it; so leave it as it is
+            None           => { return script; },
+        },
+        _ => { return script; }
+    };
+    script.push_str(&dynamic
+        .replace("{PACKAGE_COMMANDS}", &PACKAGE_COMMANDS.join(" "))
+        .replace("{VERSION_COMMANDS}", &VERSION_COMMANDS.join(" "))
+        .replace("{VERSION_FLAG_COMMANDS}", &VERSION_FLAG_COMMANDS.join(" ")));
+    script
+}
+
+
+
+/// Removes the code at the end of clap's zsh completion script that runs or registers its completion function (`_brane`).
+/// 
+/// Depending on its version, clap either simply calls the function, or only calls it if the script is autoloaded as `_brane` (and registers it with `compdef` otherwise).
+/// 
+/// **Arguments**
+///  * `script`: The zsh completion script as generated by clap.
+/// 
+/// **Returns**  
+/// The script without the code that runs the function, or None if the script doesn't end in a way that we recognize.
+pub fn strip_zsh_dispatch(script: &str) -> Option<&str> {
+    let script = script.trim_end();
+    if let Some(stripped) = script.strip_suffix("\n_brane \"$@\"") { return Some(stripped); }
+
+    // The newer dispatch is a block of its own at the end of the script
+    let start = script.rfind("\nif [ \"$funcstack[1]\" = \"_brane\" ]; then")?;
+    if !script.ends_with("\nfi") { return None; }
+    Some(&script[..start])
+}
+
+
+
+/// Returns the candidates for the given completion request.
+/// 
+/// **Arguments**
+///  * `index`: The PackageIndex with the local packages.
+///  * `request`: What to complete: `packages`, or `versions <package>`.
+/// 
+/// **Returns**  
+/// The candidates, sorted (versions from old to new). Unknown requests have none.
+pub fn candidates(index: &PackageIndex, request: &[String]) -> Vec<String> {
+    match request {
+        [ what ] if what == "packages" => index.names().into_iter().map(String::from).collect(),
+        [ what, name ] if what == "versions" => index.versions(name).into_iter().map(|version| version.to_string()).collect(),
+        _ => vec![],
+    }
+}
+
+/// Renders the candidates for the given completion request the way the completion scripts expect them: one per line.
+/// 
+/// **Arguments**
+///  * `index`: The PackageIndex with the local packages.
+///  * `request`: What to complete: `packages`, or `versions <package>`.
+/// 
+/// **Returns**  
+/// The candidates, each followed by a newline.
+pub fn output(index: &PackageIndex, request: &[String]) -> String {
+    candidates(index, request).into_iter().map(|candidate| format!("{}\n", candidate)).collect()
+}
+
+/// Completes the given request from the local package index (see `output()`).
+/// 
+/// The index is read through the index cache, so this is fast as long as no packages changed. Errors are swallowed, since the shell would only show them in the middle of the command line.
+/// 
+/// **Arguments**
+///  * `request`: What to complete: `packages`, or `versions <package>`.
+/// 
+/// **Returns**  
+/// The candidates, one per line, or nothing at all if the index could not be read.
+pub fn complete(request: &[String]) -> String {
+    match packages::get_package_index() {
+        Ok(index) => output(&index, request),
+        Err(_)    => String::new(),
+    }
+}
@@ -0,0 +1,435 @@
+/* CREDENTIALS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   14 Oct 2026, 22:14:52
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Manages the credentials that `brane login` stores for the registry.
+ *   They are kept in the OS keyring if there is one, and in the plaintext
+ *   registry file otherwise (or if the user asks for it).
+ *
+ *   Older versions of brane-cli always kept the username in the registry
+ *   file; those credentials are moved to the keyring the first time they
+ *   are used.
+**/
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use specifications::registry::{OidcSession, RegistryConfig, RegistryConfigError};
+
+use crate::errors::UtilError;
+use crate::utils::ensure_config_dir;
+
+
+/***** CONSTANTS *****/
+/// The name of the service under which we store credentials in the OS keyring.
+const KEYRING_SERVICE: &str = "brane";
+/// The account we use to check whether the OS keyring works at all.
+const KEYRING_PROBE: &str = "brane-keyring-probe";
+
+lazy_static! {
+    /// Whether the OS has a keyring we can use. Only probed once, since every probe is a round trip to the keyring daemon.
+    static ref KEYRING_AVAILABLE: bool = KeyringStore::probe();
+}
+
+
+
+
+
+/***** ERRORS *****/
+/// Collects errors that relate to storing registry credentials.
+#[derive(Debug)]
+pub enum CredentialError {
+    /// Could not find the Brane configuration directory
+    ConfigDirError{ err: UtilError },
+    /// Could not read or write the registry file
+    RegistryFileError{ err: RegistryConfigError },
+    /// Could not remove the registry file
+    RegistryFileRemoveError{ path: PathBuf, err: std::io::Error },
+
+    /// The OS keyring returned an error
+    KeyringError{ url: String, err: keyring::Error },
+    /// Could not serialize credentials for the keyring
+    KeyringEncodeError{ url: String, err: serde_json::Error },
+    /// The credentials in the keyring are not ours (or are corrupted)
+    KeyringDecodeError{ url: String, err: serde_json::Error },
+}
+
+impl Display for CredentialError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            CredentialError::ConfigDirError{ err }                => write!(f, "Could not find the Brane configuration directory: {}", err),
+            CredentialError::RegistryFileError{ err }             => write!(f, "{}", err),
+            CredentialError::RegistryFileRemoveError{ path, err } => write!(f, "Could not remove registry file '{}': {}", path.display(), err),
+
+            CredentialError::KeyringError{ url, err }       => write!(f, "Could not access credentials for registry '{}' in the OS keyring: {} (use 'brane login --insecure-store' to keep them in a plaintext file instead)", url, err),
+            CredentialError::KeyringEncodeError{ url, err } => write!(f, "Could not serialize credentials for registry '{}': {}", url, err),
+            CredentialError::KeyringDecodeError{ url, err } => write!(f, "Could not parse credentials for registry '{}' from the OS keyring: {} (run 'brane login' again)", url, err),
+        }
+    }
+}
+
+impl Error for CredentialError {}
+
+
+
+
+
+/***** LIBRARY TRAITS *****/
+/// Abstracts over places where registry credentials can be kept.
+pub trait CredentialStore {
+    /// Returns a human-readable name of the store, to tell the user where their credentials went.
+    fn name(&self) -> &'static str;
+
+    /// Loads the credentials for the given registry.
+    /// 
+    /// **Arguments**
+    ///  * `url`: The URL of the registry.
+    /// 
+    /// **Returns**  
+    /// The credentials if the store has any for this registry, None if it doesn't, or a CredentialError if we could not read the store.
+    fn load(&self, url: &str) -> Result<Option<Credentials>, CredentialError>;
+
+    /// Stores the credentials for the given registry, replacing any previous ones.
+    /// 
+    /// **Arguments**
+    ///  * `url`: The URL of the registry.
+    ///  * `credentials`: The credentials to store.
+    fn store(&self, url: &str, credentials: &Credentials) -> Result<(), CredentialError>;
+
+    /// Removes the credentials for the given registry.
+    /// 
+    /// **Arguments**
+    ///  * `url`: The URL of the registry.
+    /// 
+    /// **Returns**  
+    /// Whether there were any credentials to remove, or a CredentialError if we could not update the store.
+    fn delete(&self, url: &str) -> Result<bool, CredentialError>;
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// The credentials with which we identify ourselves to a registry.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Credentials {
+    /// The username with which we sign packages.
+    pub username : String,
+    /// The token that is sent along with requests to the registry, if any.
+    pub token    : Option<String>,
+    /// How to refresh the token, if it is an access token we got through OpenID Connect.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oidc     : Option<OidcSession>,
+}
+
+
+
+/// Keeps credentials in the OS keyring (e.g., the Secret Service on Linux, the Keychain on macOS), one entry per registry.
+#[derive(Debug, Default)]
+pub struct KeyringStore;
+
+impl KeyringStore {
+    /// Returns whether the OS has a keyring we can use.
+    #[inline]
+    pub fn available() -> bool { *KEYRING_AVAILABLE }
+
+    /// Checks whether the OS has a keyring we can use by trying to read from it.
+    fn probe() -> bool {
+        match keyring::Entry::new(KEYRING_SERVICE, KEYRING_PROBE).get_password() {
+            Ok(_) | Err(keyring::Error::NoEntry) => true,
+            Err(err) => {
+                debug!("OS keyring is not available: {}", err);
+                false
+            },
+        }
+    }
+}
+
+impl CredentialStore for KeyringStore {
+    fn name(&self) -> &'static str { "the OS keyring" }
+
+    fn load(&self, url: &str) -> Result<Option<Credentials>, CredentialError> {
+        let secret = match keyring::Entry::new(KEYRING_SERVICE, url).get_password() {
+            Ok(secret)                   => secret,
+            Err(keyring::Error::NoEntry) => { return Ok(None); },
+            Err(err)                     => { return Err(CredentialError::KeyringError{ url: url.to_string(), err }); }
+        };
+        match serde_json::from_str(&secret) {
+            Ok(credentials) => Ok(Some(credentials)),
+            Err(err)        => Err(CredentialError::KeyringDecodeError{ url: url.to_string(), err }),
+        }
+    }
+
+    fn store(&self, url: &str, credentials: &Credentials) -> Result<(), CredentialError> {
+        let secret = match serde_json::to_string(credentials) {
+            Ok(secret) => secret,
+            Err(err)   => { return Err(CredentialError::KeyringEncodeError{ url: url.to_string(), err }); }
+        };
+        match keyring::Entry::new(KEYRING_SERVICE, url).set_password(&secret) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(CredentialError::KeyringError{ url: url.to_string(), err }),
+        }
+    }
+
+    fn delete(&self, url: &str) -> Result<bool, CredentialError> {
+        match keyring::Entry::new(KEYRING_SERVICE, url).delete_password() {
+            Ok(_)                        => Ok(true),
+            Err(keyring::Error::NoEntry) => Ok(false),
+            Err(err)                     => Err(CredentialError::KeyringError{ url: url.to_string(), err }),
+        }
+    }
+}
+
+
+
+/// Keeps credentials in plaintext in the registry file. Used if there is no keyring, or if the user asked for it with `--insecure-store`.
+#[derive(Debug)]
+pub struct PlaintextStore {
+    /// The registry file to keep the credentials in.
+    path : PathBuf,
+}
+
+impl PlaintextStore {
+    /// Constructor for the PlaintextStore.
+    /// 
+    /// **Arguments**
+    ///  * `path`: The registry file to keep the credentials in.
+    #[inline]
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self{ path: path.into() }
+    }
+
+    /// Reads the registry file, or returns None if it does not exist.
+    fn read(&self) -> Result<Option<RegistryConfig>, CredentialError> {
+        match RegistryConfig::from_path(&self.path) {
+            Ok(config)                                  => Ok(Some(config)),
+            Err(RegistryConfigError::NotLoggedIn{ .. }) => Ok(None),
+            Err(err)                                    => Err(CredentialError::RegistryFileError{ err }),
+        }
+    }
+}
+
+impl CredentialStore for PlaintextStore {
+    fn name(&self) -> &'static str { "the registry file (plaintext)" }
+
+    fn load(&self, url: &str) -> Result<Option<Credentials>, CredentialError> {
+        Ok(match self.read()? {
+            Some(RegistryConfig{ url: file_url, username: Some(username), token, oidc, .. }) if file_url == url => Some(Credentials{ username, token, oidc }),
+            _ => None,
+        })
+    }
+
+    fn store(&self, url: &str, credentials: &Credentials) -> Result<(), CredentialError> {
+        let mut config = self.read()?.unwrap_or_default();
+        config.url      = url.to_string();
+        config.username = Some(credentials.username.clone());
+        config.token    = credentials.token.clone();
+        config.oidc     = credentials.oidc.clone();
+        write_config(&self.path, &config)
+    }
+
+    fn delete(&self, url: &str) -> Result<bool, CredentialError> {
+        let mut config = match self.read()? {
+            Some(config) if config.url == url => config,
+            _                                 => { return Ok(false); }
+        };
+        if config.username.is_none() && config.token.is_none() { return Ok(false); }
+        config.username = None;
+        config.token    = None;
+        config.oidc     = None;
+        write_config(&self.path, &config)?;
+        Ok(true)
+    }
+}
+
+
+
+/// Decides where the credentials for the registry we're logged into live, and reads them from there.
+pub struct CredentialManager {
+    /// The registry file, which says which registry we're logged into (and may hold the credentials in plaintext).
+    path     : PathBuf,
+    /// The store we prefer over the registry file, if there is any.
+    keyring  : Option<Box<dyn CredentialStore>>,
+}
+
+impl CredentialManager {
+    /// Constructor for the CredentialManager that uses the registry file in the default configuration directory and the OS keyring, if any.
+    /// 
+    /// **Returns**  
+    /// A new CredentialManager, or a CredentialError if we could not find the configuration directory.
+    pub fn new() -> Result<Self, CredentialError> {
+        let config_dir = match ensure_config_dir(true) {
+            Ok(config_dir) => config_dir,
+            Err(err)       => { return Err(CredentialError::ConfigDirError{ err }); }
+        };
+        let keyring: Option<Box<dyn CredentialStore>> = if KeyringStore::available() { Some(Box::new(KeyringStore)) } else { None };
+        Ok(Self::with_stores(config_dir.join("registry.yml"), keyring))
+    }
+
+    /// Constructor for the CredentialManager that uses the given registry file and keyring.
+    /// 
+    /// **Arguments**
+    ///  * `path`: The registry file to use.
+    ///  * `keyring`: The store to prefer over the registry file, or None to always keep credentials in the registry file.
+    #[inline]
+    pub fn with_stores(path: impl Into<PathBuf>, keyring: Option<Box<dyn CredentialStore>>) -> Self {
+        Self{ path: path.into(), keyring }
+    }
+
+    /// Returns the path of the registry file.
+    #[inline]
+    pub fn path(&self) -> &Path { &self.path }
+
+    /// Returns the registry we're logged into, if any.
+    /// 
+    /// **Returns**  
+    /// The contents of the registry file, or a CredentialError if we're not logged in (or the file is broken).
+    pub fn registry(&self) -> Result<RegistryConfig, CredentialError> {
+        match RegistryConfig::from_path(&self.path) {
+            Ok(config) => Ok(config),
+            Err(err)   => Err(CredentialError::RegistryFileError{ err }),
+        }
+    }
+
+    /// Logs into the given registry, storing the credentials in the keyring unless told otherwise (or there isn't any).
+    /// 
+    /// **Arguments**
+    ///  * `url`: The URL of the registry.
+    ///  * `credentials`: The credentials to store.
+    ///  * `insecure`: If true, always keeps the credentials in the plaintext registry file.
+    ///  * `oci`: If true, the registry is a standard OCI registry instead of a Brane registry.
+    /// 
+    /// **Returns**  
+    /// The name of the store that now holds the credentials, or a CredentialError if we failed to store them.
+    pub fn login(&self, url: &str, credentials: &Credentials, insecure: bool, oci: bool) -> Result<&'static str, CredentialError> {
+        // Clear whatever we had for the previous registry, wherever it is
+        if let Ok(previous) = self.registry() {
+            if previous.url != url { self.delete_everywhere(&previous.url)?; }
+        }
+
+        let plaintext = PlaintextStore::new(&self.path);
+        let config = RegistryConfig{ url: url.to_string(), username: None, token: None, insecure_store: insecure, oci, platform: None, oidc: None };
+        match (&self.keyring, insecure) {
+            (Some(keyring), false) => {
+                keyring.store(url, credentials)?;
+                write_config(&self.path, &config)?;
+                Ok(keyring.name())
+            },
+            (keyring, _) => {
+                if let Some(keyring) = keyring { keyring.delete(url)?; }
+                write_config(&self.path, &config)?;
+                plaintext.store(url, credentials)?;
+                Ok(plaintext.name())
+            },
+        }
+    }
+
+    /// Sets the platform that the default cluster of the registry we're logged into runs packages on.
+    /// 
+    /// **Arguments**
+    ///  * `platform`: The platform (e.g., 'linux/arm64'), or None to use the default.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or a CredentialError if we're not logged in or could not write the registry file.
+    pub fn set_platform(&self, platform: Option<String>) -> Result<(), CredentialError> {
+        let mut config = self.registry()?;
+        config.platform = platform;
+        write_config(&self.path, &config)
+    }
+
+    /// Returns the credentials for the registry we're logged into.
+    /// 
+    /// If the credentials are still in the registry file even though the user didn't ask for that (i.e., they were stored by an older brane-cli), they are moved to the keyring first.
+    /// 
+    /// **Returns**  
+    /// The URL of the registry and its credentials (if any), or a CredentialError if we're not logged in or could not read the credentials.
+    pub fn credentials(&self) -> Result<(String, Option<Credentials>), CredentialError> {
+        let config = self.registry()?;
+        let plaintext = PlaintextStore::new(&self.path);
+        let keyring = match (&self.keyring, config.insecure_store) {
+            (Some(keyring), false) => keyring,
+            _                      => { return Ok((config.url.clone(), plaintext.load(&config.url)?)); }
+        };
+
+        if let Some(credentials) = plaintext.load(&config.url)? {
+            info!("Moving credentials for registry '{}' from '{}' to {}", config.url, self.path.display(), keyring.name());
+            keyring.store(&config.url, &credentials)?;
+            plaintext.delete(&config.url)?;
+            return Ok((config.url, Some(credentials)));
+        }
+        Ok((config.url.clone(), keyring.load(&config.url)?))
+    }
+
+    /// Replaces the credentials for the registry we're logged into (e.g., after refreshing its token), keeping them in the same store.
+    /// 
+    /// **Arguments**
+    ///  * `credentials`: The new credentials.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or a CredentialError if we're not logged in or could not store the credentials.
+    pub fn update(&self, credentials: &Credentials) -> Result<(), CredentialError> {
+        let config = self.registry()?;
+        match (&self.keyring, config.insecure_store) {
+            (Some(keyring), false) => keyring.store(&config.url, credentials),
+            _                      => PlaintextStore::new(&self.path).store(&config.url, credentials),
+        }
+    }
+
+    /// Logs out of the registry we're logged into, removing its credentials from whichever store holds them.
+    /// 
+    /// **Returns**  
+    /// Nothing on success (including if we weren't logged in), or a CredentialError if we could not remove the credentials.
+    pub fn logout(&self) -> Result<(), CredentialError> {
+        let config = match RegistryConfig::from_path(&self.path) {
+            Ok(config)                                  => config,
+            Err(RegistryConfigError::NotLoggedIn{ .. }) => { return Ok(()); },
+            Err(err)                                    => { return Err(CredentialError::RegistryFileError{ err }); }
+        };
+        self.delete_everywhere(&config.url)?;
+        if let Err(err) = fs::remove_file(&self.path) {
+            return Err(CredentialError::RegistryFileRemoveError{ path: self.path.clone(), err });
+        }
+        Ok(())
+    }
+
+    /// Removes the credentials of the given registry from all stores.
+    fn delete_everywhere(&self, url: &str) -> Result<(), CredentialError> {
+        if let Some(keyring) = &self.keyring { keyring.delete(url)?; }
+        PlaintextStore::new(&self.path).delete(url)?;
+        Ok(())
+    }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Writes the given registry file, making sure only the user can read it (as it may contain credentials).
+/// 
+/// **Arguments**
+///  * `path`: The path of the registry file.
+///  * `config`: The RegistryConfig to write.
+fn write_config(path: &Path, config: &RegistryConfig) -> Result<(), CredentialError> {
+    if let Err(err) = config.to_path(path) { return Err(CredentialError::RegistryFileError{ err }); }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Err(err) = fs::set_permissions(path, fs::Permissions::from_mode(0o600)) {
+            warn!("Could not restrict permissions of registry file '{}': {}", path.display(), err);
+        }
+    }
+    Ok(())
+}
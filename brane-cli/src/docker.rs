@@ -13,10 +13,10 @@ use bollard::image::{CreateImageOptions, ImportImageOptions, RemoveImageOptions}
 use bollard::models::{DeviceRequest, HostConfig};
 use bollard::Docker;
 use brane_bvm::executor::{VmExecutor, ExecutorError};
+use brane_drv::executor::decode_finished;
 use futures_util::stream::TryStreamExt;
 use futures_util::StreamExt;
 use hyper::Body;
-use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use tokio::fs::File as TFile;
 use tokio_util::codec::{BytesCodec, FramedRead};
@@ -25,8 +25,9 @@ use uuid::Uuid;
 use specifications::common::{FunctionExt, Value};
 use specifications::errors::EncodeDecodeError;
 use specifications::package::PackageInfo;
-use specifications::version::Version;
 
+use crate::runtime;
+use crate::sandbox::{data_bind, SandboxOptions, DATA_MOUNT};
 use crate::utils::ensure_package_dir;
 
 
@@ -59,6 +60,12 @@ pub struct ExecuteInfo {
     pub mounts     : Option<Vec<String>>,
     /// The command(s) to pass to Branelet.
     pub command    : Option<Vec<String>>,
+    /// The options that restrict the container.
+    #[serde(default)]
+    pub sandbox    : SandboxOptions,
+    /// The environment variables of the package that the script configured (see `with_env()`).
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
 }
 
 impl ExecuteInfo {
@@ -81,8 +88,36 @@ impl ExecuteInfo {
             image_file,
             mounts,
             command,
+            sandbox: SandboxOptions::default(),
+            environment: HashMap::new(),
         }
     }
+
+    /// Restricts the container with the given sandbox options.
+    /// 
+    /// **Arguments**
+    ///  * `sandbox`: The SandboxOptions to run the container with.
+    /// 
+    /// **Returns**  
+    /// The same ExecuteInfo, with the sandbox options set.
+    #[inline]
+    pub fn with_sandbox(mut self, sandbox: SandboxOptions) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Sets the given environment variables in the container.
+    /// 
+    /// **Arguments**
+    ///  * `environment`: The environment variables to set, as name / value pairs.
+    /// 
+    /// **Returns**  
+    /// The same ExecuteInfo, with the environment variables set.
+    #[inline]
+    pub fn with_environment(mut self, environment: HashMap<String, String>) -> Self {
+        self.environment = environment;
+        self
+    }
 }
 
 
@@ -92,17 +127,14 @@ impl ExecuteInfo {
 /***** HELPER FUNCTIONS *****/
 /// **Edited: Changed to return ExecutorErrors.**
 ///
-/// Tries to decode the given output from Base64, as UTF-8 and then as JSON.
+/// Tries to decode the given output from Base64, as UTF-8 and then as the JSON payload of a Finished event (so that artifacts are wrapped the same way as on remote instances).
 /// 
 /// **Arguments**
 ///  * `input`: The input string to decode.
 /// 
 /// **Returns**  
 /// The decoded output on success, or an ExecutorError otherwise.
-fn decode_b64<T>(input: String) -> Result<T, EncodeDecodeError>
-where
-    T: DeserializeOwned,
-{
+fn decode_b64(input: String) -> Result<Value, EncodeDecodeError> {
     // First, try to decode the raw base64
     let input = match base64::decode(input) {
         Ok(bin)     => bin,
@@ -116,9 +148,9 @@ where
     };
 
     // Finally, try to decode the JSON
-    match serde_json::from_str(&input) {
-        Ok(json)    => Ok(json),
-        Err(reason) => Err(EncodeDecodeError::JsonDecodeError{ err: reason }),
+    match decode_finished(&input) {
+        Ok((value, _)) => Ok(value),
+        Err(reason)    => Err(EncodeDecodeError::JsonDecodeError{ err: reason }),
     }
 }
 
@@ -128,18 +160,19 @@ where
 ///
 /// **Arguments**
 ///  * `exec`: The ExecuteInfo that describes the job to launch.
+///  * `offline`: If true, fails instead of pulling the image if it is not available locally.
 /// 
 /// **Returns**  
 /// The name of the job (from Docker) if successful, or an ExecutorError upon failure.
-pub async fn run(exec: ExecuteInfo) -> Result<String, ExecutorError> {
+pub async fn run(exec: ExecuteInfo, offline: bool) -> Result<String, ExecutorError> {
     // Connect to docker
-    let docker = match Docker::connect_with_local_defaults() {
+    let docker = match runtime::connect() {
         Ok(res)     => res,
         Err(reason) => { return Err(ExecutorError::DockerConnectionFailed{ err: reason }); }
     };
 
     // Either import or pull image, if not already present
-    ensure_image(&docker, &exec).await?;
+    ensure_image(&docker, &exec, offline).await?;
 
     // Start container, return immediately (propagating any errors that occurred)
     create_and_start_container(&docker, &exec).await
@@ -150,18 +183,19 @@ pub async fn run(exec: ExecuteInfo) -> Result<String, ExecutorError> {
 ///
 /// **Arguments**
 ///  * `exec`: The ExecuteInfo describing what to launch and how.
+///  * `offline`: If true, fails instead of pulling the image if it is not available locally.
 /// 
 /// **Returns**  
 /// The return code of the docker container, its stdout and its stderr (in that order).
-pub async fn run_and_wait(exec: ExecuteInfo) -> Result<(i32, String, String), ExecutorError> {
+pub async fn run_and_wait(exec: ExecuteInfo, offline: bool) -> Result<(i32, String, String), ExecutorError> {
     // Connect to docker
-    let docker = match Docker::connect_with_local_defaults() {
+    let docker = match runtime::connect() {
         Ok(res)     => res,
         Err(reason) => { return Err(ExecutorError::DockerConnectionFailed{ err: reason }); }
     };
 
     // Either import or pull image, if not already present
-    ensure_image(&docker, &exec).await?;
+    ensure_image(&docker, &exec, offline).await?;
 
     // Start container and wait for completion
     let name = create_and_start_container(&docker, &exec).await?;
@@ -213,7 +247,7 @@ pub async fn run_and_wait(exec: ExecuteInfo) -> Result<(i32, String, String), Ex
 /// The address of the container as a string on success, or an ExecutorError otherwise.
 pub async fn get_container_address(name: &str) -> Result<String, ExecutorError> {
     // Try to connect to the local instance
-    let docker = match Docker::connect_with_local_defaults() {
+    let docker = match runtime::connect() {
         Ok(conn)    => conn,
         Err(reason) => { return Err(ExecutorError::DockerConnectionFailed{ err: reason }); }
     };
@@ -243,19 +277,21 @@ pub async fn get_container_address(name: &str) -> Result<String, ExecutorError>
     }
 }
 
-/// **Edited: Now returns ExecutorErrors.**
+/// **Edited: Now returns ExecutorErrors, and can refuse to pull.**
 ///
 /// Tries to import/pull the given image if it does not exist in the local Docker instance.
 /// 
 /// **Arguments**
 ///  * `docker`: An already connected local instance of Docker.
 ///  * `exec`: The ExecuteInfo describing the image to pull.
+///  * `offline`: If true, fails with an OfflineImageError instead of pulling the image (importing it from a local image file is still fine).
 /// 
 /// **Returns**  
 /// Nothing on success (whether we imported/pulled it or found it already existed), but an ExecutorError upon failure.
 async fn ensure_image(
     docker: &Docker,
     exec: &ExecuteInfo,
+    offline: bool,
 ) -> Result<(), ExecutorError> {
     // Abort if image is already loaded
     if docker.inspect_image(&exec.image).await.is_ok() {
@@ -267,6 +303,10 @@ async fn ensure_image(
     }
 
     // Otherwise, import it if it is described or pull it
+    if offline && !exec.image_file.as_ref().map(|image_file| image_file.exists()).unwrap_or(false) {
+        debug!(" > Not pulling image '{}' in offline mode", exec.image);
+        return Err(ExecutorError::OfflineImageError{ image: exec.image.clone() });
+    }
     if let Some(image_file) = &exec.image_file {
         debug!(" > Importing file '{}'...", image_file.display());
         import_image(docker, image_file).await
@@ -323,18 +363,20 @@ async fn create_and_start_container(
         exec.mounts.clone().unwrap_or_default()
     };
 
-    // Add the docker socket
-    binds.push(String::from("/var/run/docker.sock:/var/run/docker.sock"));
+    // Add the docker socket (which may be that of rootless Docker or Podman on the host)
+    let socket = runtime::socket_path().unwrap_or_else(|| PathBuf::from(runtime::DEFAULT_SOCKET));
+    binds.push(format!("{}:{}", socket.display(), runtime::DEFAULT_SOCKET));
 
-    // Combine the properties
-    let host_config = HostConfig {
+    // Combine the properties, restricted by the sandbox
+    let host_config = exec.sandbox.apply(HostConfig {
         binds: Some(binds),
         network_mode: Some(DOCKER_NETWORK.to_string()),
         privileged: Some(DOCKER_PRIVILEGED.as_str() == "true"),
         volumes_from,
         device_requests,
         ..Default::default()
-    };
+    });
+    debug!("Sandbox settings: {}", exec.sandbox);
 
     // Possibly remove the hash from the image name
     let image: &str = if exec.image.contains('@') {
@@ -343,18 +385,26 @@ async fn create_and_start_container(
         &exec.image
     };
 
-    // Create the container confic
+    // Create the container confic (sorting the environment, so the same call always creates the same container)
+    let mut env: Vec<String> = exec.environment.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+    env.sort();
     let create_config = Config {
         image: Some(image.to_string()),
         cmd: exec.command.clone(),
+        env: if env.is_empty() { None } else { Some(env) },
         host_config: Some(host_config),
         ..Default::default()
     };
 
-    if let Err(reason) = docker.create_container(Some(create_options), create_config).await { return Err(ExecutorError::DockerCreateContainerError{ name, image: image.to_string(), err: reason }); }
+    // Mention the sandbox if it fails, since its settings may be why
+    if let Err(reason) = docker.create_container(Some(create_options), create_config).await {
+        if !exec.sandbox.is_default() { return Err(ExecutorError::DockerSandboxError{ name, image: image.to_string(), sandbox: exec.sandbox.to_string(), err: reason }); }
+        return Err(ExecutorError::DockerCreateContainerError{ name, image: image.to_string(), err: reason });
+    }
     match docker.start_container(&name, None::<StartContainerOptions<String>>).await {
-        Ok(_)       => Ok(name),
-        Err(reason) => Err(ExecutorError::DockerStartError{ name, image: image.to_string(), err: reason })
+        Ok(_)                                     => Ok(name),
+        Err(reason) if !exec.sandbox.is_default() => Err(ExecutorError::DockerSandboxError{ name, image: image.to_string(), sandbox: exec.sandbox.to_string(), err: reason }),
+        Err(reason)                               => Err(ExecutorError::DockerStartError{ name, image: image.to_string(), err: reason })
     }
 }
 
@@ -485,6 +535,10 @@ async fn pull_image(
 #[derive(Clone, Default)]
 pub struct DockerExecutor {
     pub data: Option<PathBuf>,
+    /// If true, never pulls images but fails instead.
+    pub offline: bool,
+    /// The options that restrict the containers of external calls.
+    pub sandbox: SandboxOptions,
 }
 
 impl DockerExecutor {
@@ -492,9 +546,23 @@ impl DockerExecutor {
     /// 
     /// **Arguments**
     ///  * `data`: If given, references the path that should be mounted under the JuiceFS filesystem.
+    ///  * `offline`: If true, external calls whose image is not available locally fail instead of pulling it.
     #[inline]
-    pub fn new(data: Option<PathBuf>) -> Self {
-        Self { data }
+    pub fn new(data: Option<PathBuf>, offline: bool) -> Self {
+        Self { data, offline, sandbox: SandboxOptions::default() }
+    }
+
+    /// Restricts the containers of external calls with the given sandbox options.
+    /// 
+    /// **Arguments**
+    ///  * `sandbox`: The SandboxOptions to run the containers with.
+    /// 
+    /// **Returns**  
+    /// The same DockerExecutor, with the sandbox options set.
+    #[inline]
+    pub fn with_sandbox(mut self, sandbox: SandboxOptions) -> Self {
+        self.sandbox = sandbox;
+        self
     }
 }
 
@@ -517,8 +585,8 @@ impl VmExecutor for DockerExecutor {
         arguments: HashMap<String, Value>,
         location: Option<String>,
     ) -> Result<Value, ExecutorError> {
-        // Try to get the package directory
-        let package_dir = match ensure_package_dir(&function.package, Some(&Version::latest()), false) {
+        // Try to get the package directory of the version that the script imported
+        let package_dir = match ensure_package_dir(&function.package, Some(&function.version), false) {
             Ok(res) => res,
             Err(reason) => { return Err(ExecutorError::PackageDirError{ package: function.package.clone(), err: format!("{}", reason) }); }
         };
@@ -547,19 +615,22 @@ impl VmExecutor for DockerExecutor {
             Err(reason) => { return Err(ExecutorError::IllegalArguments{ args: arguments, err: reason }); }
         };
 
-        // Prepare the command
-        let command = vec![
+        // Prepare the command (with a job ID of its own, so that calls don't overwrite each other's artifacts)
+        let mut command = vec![
             String::from("-d"),
             String::from("--application-id"),
             String::from("test"),
             String::from("--location-id"),
             String::from("localhost"),
             String::from("--job-id"),
-            String::from("1"),
-            String::from(package_info.kind),
-            function.name.clone(),
-            base64::encode(arguments_json),
+            Uuid::new_v4().to_string(),
         ];
+        // Without a DFS, the outputs of the function can only be stored in the data directory
+        if self.data.is_some() {
+            command.push(String::from("--artifact-dir"));
+            command.push(format!("{}/artifacts", DATA_MOUNT));
+        }
+        command.extend([ String::from(package_info.kind), function.name.clone(), base64::encode(arguments_json) ]);
 
         // Collect the mounts to add
         debug!("Collecting mount folders...");
@@ -581,19 +652,19 @@ impl VmExecutor for DockerExecutor {
             if data_path.contains(':') { return Err(ExecutorError::IllegalDataDirColon{ path: data }); }
 
             // Now return
-            Some(vec![format!("{}:/data", data_path)])
+            Some(vec![data_bind(&data_path)])
         } else {
             None
         };
 
         // With the arguments fully prepared, run the function
         debug!("About to call docker with \"{:?}\"", command);
-        let exec = ExecuteInfo::new(image, image_file, mounts, Some(command));
+        let exec = ExecuteInfo::new(image, image_file, mounts, Some(command)).with_sandbox(self.sandbox.clone()).with_environment(function.env.clone());
         if function.detached {
             // Launch the function and return a struct detailling the job
 
             // Launch the container and get its address
-            let name = run(exec).await?;
+            let name = run(exec, self.offline).await?;
             let address = get_container_address(&name).await?;
 
             // Prepare a hashmap listing the properties of the this job
@@ -610,7 +681,7 @@ impl VmExecutor for DockerExecutor {
             // Launch the function and await its result
 
             // Launch it and wait until its completed
-            let (code, stdout, stderr) = run_and_wait(exec).await?;
+            let (code, stdout, stderr) = run_and_wait(exec, self.offline).await?;
             debug!("return code: {}", code);
             debug!("stderr: {}", stderr);
             debug!("stdout: {}", stdout);
@@ -701,7 +772,7 @@ impl VmExecutor for DockerExecutor {
         }
 
         // Connect to docker
-        let docker = match Docker::connect_with_local_defaults() {
+        let docker = match runtime::connect() {
             Ok(res)     => res,
             Err(reason) => { return Err(ExecutorError::DockerConnectionFailed{ err: reason }); }
         };
@@ -733,7 +804,7 @@ impl VmExecutor for DockerExecutor {
 /// Nothing on success, or an ExecutorError otherwise.
 pub async fn remove_image(name: &str) -> Result<(), ExecutorError> {
     // Try to connect to the local instance
-    let docker = match Docker::connect_with_local_defaults() {
+    let docker = match runtime::connect() {
         Ok(conn)    => conn,
         Err(reason) => { return Err(ExecutorError::DockerConnectionFailed{ err: reason }); }
     };
@@ -18,10 +18,14 @@ use std::fmt::{Display, Formatter, Result as FResult};
 use std::path::PathBuf;
 
 use brane_bvm::vm::VmError;
-use specifications::package::{PackageInfoError, PackageKindError};
+use specifications::package::{PackageInfoError, PackageKind, PackageKindError};
 use specifications::container::{ContainerInfoError, LocalContainerInfoError};
 use specifications::version::{ParseError as VersionParseError, Version};
 
+use crate::archive::ArchiveError;
+use crate::build_common::ImageDifference;
+use crate::lock::LockError;
+use crate::proxy::ProxyError;
 use crate::packages::PackageError;
 
 
@@ -37,16 +41,24 @@ lazy_static! { static ref CLI_LINE_SEPARATOR: String = (0..80).map(|_| '-').coll
 #[derive(Debug)]
 pub enum CliError {
     // Toplevel errors for the subcommands
+    /// Errors that occur during the export command or while importing an archive
+    ArchiveError{ err: ArchiveError },
     /// Errors that occur during the build command
     BuildError{ err: BuildError },
     /// Errors that occur during the import command
     ImportError{ err: ImportError },
+    /// Errors that occur during the logs command
+    LogsError{ err: LogsError },
     /// Errors that occur during the repl command
     ReplError{ err: ReplError },
+    /// Errors that occur during the run command
+    RunError{ err: RunError },
     /// Errors that occur in the version command
     VersionError{ err: VersionError },
     /// Errors that occur in some inter-subcommand utility
     UtilError{ err: UtilError },
+    /// Something would have to be fetched over the network while running in offline mode
+    OfflineError{ err: OfflineError },
     /// Temporary wrapper around any anyhow error
     OtherError{ err: anyhow::Error },
 
@@ -62,11 +74,15 @@ pub enum CliError {
 impl Display for CliError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
         match self {
+            CliError::ArchiveError{ err } => write!(f, "{}", err),
             CliError::BuildError{ err }   => write!(f, "{}", err),
             CliError::ImportError{ err }  => write!(f, "{}", err),
+            CliError::LogsError{ err }    => write!(f, "{}", err),
             CliError::ReplError{ err }    => write!(f, "{}", err),
+            CliError::RunError{ err }     => write!(f, "{}", err),
             CliError::UtilError{ err }    => write!(f, "{}", err),
             CliError::VersionError{ err } => write!(f, "{}", err),
+            CliError::OfflineError{ err } => write!(f, "{}", err),
             CliError::OtherError{ err }   => write!(f, "{}", err),
 
             CliError::PackageFileCanonicalizeError{ path, err } => write!(f, "Could not resolve package file path '{}': {}", path.display(), err),
@@ -87,6 +103,8 @@ pub enum BuildError {
     ContainerInfoOpenError{ file: PathBuf, err: std::io::Error },
     /// Could not read/open the given container info file
     ContainerInfoParseError{ file: PathBuf, err: ContainerInfoError },
+    /// The container info file does not describe a valid package
+    ContainerInfoValidationError{ file: PathBuf, err: ContainerInfoError },
     /// Could not create/resolve the package directory
     PackageDirError{ err: UtilError },
 
@@ -97,12 +115,8 @@ pub enum BuildError {
     /// Could not properly convert the OpenAPI document into a PackageInfo
     PackageInfoFromOpenAPIError{ err: anyhow::Error },
 
-    /// A lock file exists for the current building package, so wait
-    LockFileExists{ path: PathBuf },
-    /// Could not create a file lock for system reasons
-    LockCreateError{ path: PathBuf, err: std::io::Error },
-    /// Failed to cleanup the .lock file from the build directory after a successfull build.
-    LockCleanupError{ path: PathBuf, err: std::io::Error },
+    /// Could not lock the package we're building
+    LockError{ err: LockError },
 
     /// Could not write to the DockerFile string.
     DockerfileStrWriteError{ err: std::fmt::Error },
@@ -121,6 +135,24 @@ pub enum BuildError {
     BraneletCanonicalizeError{ path: PathBuf, err: std::io::Error },
     /// Could not copy the branelet executable
     BraneletCopyError{ source: PathBuf, target: PathBuf, err: std::io::Error },
+    /// Could not download the prebuilt branelet executable
+    BraneletDownloadError{ url: String, err: reqwest::Error },
+    /// Could not write the downloaded branelet executable
+    BraneletWriteError{ path: PathBuf, err: std::io::Error },
+    /// Could not read an init binary (to check its architecture or hash it)
+    InitBinaryReadError{ path: PathBuf, err: std::io::Error },
+    /// The given init binary is built for another architecture than the one we need it for
+    InitBinaryArchMismatch{ path: PathBuf, expected: String, got: String },
+    /// There is no init binary for one of the architectures the image is built for
+    MissingInitBinary{ arch: String, searched: Vec<PathBuf>, err: Option<String> },
+    /// The downloaded init binary does not match the digest built into the CLI
+    InitBinaryDigestMismatch{ url: String, expected: String, got: String },
+    /// The CLI was built without the digest of the init binary for an architecture, so it cannot check a downloaded one
+    InitBinaryUnknownDigest{ arch: String },
+    /// Could not find the cache directory for downloaded init binaries
+    InitCacheDirError{ err: UtilError },
+    /// Could not create the cache directory for downloaded init binaries
+    InitCacheCreateError{ path: PathBuf, err: std::io::Error },
     /// Could not clear an existing working directory
     WdClearError{ path: PathBuf, err: std::io::Error },
     /// Could not create a new working directory
@@ -141,6 +173,8 @@ pub enum BuildError {
     WdCompressionLaunchError{ command: String, err: std::io::Error },
     /// Command to compress the working directory returned a non-zero exit code
     WdCompressionError{ command: String, code: i32, stdout: String, stderr: String },
+    /// Could not write the normalized archive of the working directory
+    WdArchiveError{ path: PathBuf, err: std::io::Error },
 
     /// Could not serialize the OPenAPI file
     OpenAPISerializeError{ err: serde_yaml::Error },
@@ -170,11 +204,40 @@ pub enum BuildError {
     ImageBuildLaunchError{ command: String, err: std::io::Error },
     /// The command to build the image returned a non-zero exit code (we don't accept stdout or stderr here, as the command's output itself will be passed to stdout & stderr)
     ImageBuildError{ command: String, code: i32 },
+    /// A platform to build for is not a Docker platform
+    IllegalPlatform{ platform: String, reason: &'static str },
+    /// Multiple platforms were asked for, but without a registry to push the image to
+    MultiPlatformWithoutRegistry{ platforms: Vec<String> },
+    /// Multiple platforms were asked to be written to an image.tar
+    MultiPlatformTar{ platforms: Vec<String> },
+    /// The container runtime cannot build images the way that was asked for
+    RuntimeUnsupported{ runtime: String, feature: &'static str },
+    /// The SOURCE_DATE_EPOCH in the environment is not a number of seconds
+    IllegalSourceDateEpoch{ value: String },
+
+    /// Two build steps were given the same name
+    BuildStepDuplicate{ step: String },
+    /// A build step depends on a step that does not exist
+    BuildStepUnknownDependency{ step: String, dependency: String },
+    /// The build steps depend on each other in a cycle
+    BuildStepCycle{ steps: Vec<String> },
+    /// A build step panicked or was cancelled
+    BuildStepPanicked{ step: String, err: tokio::task::JoinError },
+    /// Could not run a blocking build step in the background
+    BuildStepSpawnError{ step: String, err: tokio::task::JoinError },
 
     /// Could not get the digest from the just-built image
     DigestError{ err: PackageInfoError },
     /// Could not write the PackageFile to the build directory.
     PackageFileCreateError{ err: PackageInfoError },
+    /// The package was built, but (running) its test cases failed
+    TestError{ err: anyhow::Error },
+    /// Could not hash one of the files in the build context
+    InputHashError{ path: PathBuf, err: std::io::Error },
+    /// Could not move the image.tar of the first build aside to build it again
+    ImageTarMoveError{ source: PathBuf, target: PathBuf, err: std::io::Error },
+    /// Building the image a second time gave a different image
+    NotReproducible{ difference: ImageDifference },
 
     // /// Failed to remove an existing build of this package/version from the docker daemon
     // DockerCleanupError{ image: String, err: ExecutorError },
@@ -206,17 +269,16 @@ pub enum BuildError {
 impl Display for BuildError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
         match self {
-            BuildError::ContainerInfoOpenError{ file, err }  => write!(f, "Could not open the container info file '{}': {}", file.display(), err),
-            BuildError::ContainerInfoParseError{ file, err } => write!(f, "Could not parse the container info file '{}': {}", file.display(), err),
-            BuildError::PackageDirError{ err }               => write!(f, "Could not create package directory: '{}'", err),
+            BuildError::ContainerInfoOpenError{ file, err }       => write!(f, "Could not open the container info file '{}': {}", file.display(), err),
+            BuildError::ContainerInfoParseError{ file, err }      => write!(f, "Could not parse the container info file '{}': {}", file.display(), err),
+            BuildError::ContainerInfoValidationError{ file, err } => write!(f, "Could not build package from container info file '{}': {}", file.display(), err),
+            BuildError::PackageDirError{ err }                    => write!(f, "Could not create package directory: '{}'", err),
 
             BuildError::OasDocumentParseError{ file, err } => write!(f, "Could not parse the OAS Document '{}': {}", file.display(), err),
             BuildError::VersionParseError{ err }           => write!(f, "Could not parse OAS Document version number: {}", err),
             BuildError::PackageInfoFromOpenAPIError{ err } => write!(f, "Could not convert the OAS Document into a Package Info file: {}", err),
 
-            BuildError::LockFileExists{ path }        => write!(f, "The build directory '{}' is busy; try again later (a lock file exists)", path.display()),
-            BuildError::LockCreateError{ path, err }  => write!(f, "Could not create lock file '{}': {}", path.display(), err),
-            BuildError::LockCleanupError{ path, err } => write!(f, "Could not clean the lock file ('{}') from build directory: {}", path.display(), err),
+            BuildError::LockError{ err } => write!(f, "{}", err),
 
             BuildError::DockerfileStrWriteError{ err } => write!(f, "Could not write to the internal DockerFile: {}", err),
             BuildError::UnsafePath{ path }             => write!(f, "File '{}' tries to escape package working directory; consider moving Brane's working directory up (using --workdir) and avoid '..'", path),
@@ -226,7 +288,20 @@ impl Display for BuildError {
             BuildError::DockerfileWriteError{ path, err }                   => write!(f, "Could not write to Dockerfile '{}': {}", path.display(), err),
             BuildError::ContainerDirCreateError{ path, err }                => write!(f, "Could not create container directory '{}': {}", path.display(), err),
             BuildError::BraneletCanonicalizeError{ path, err }              => write!(f, "Could not resolve custom init binary path '{}': {}", path.display(), err),
-            BuildError::BraneletCopyError{ source, target, err }            => write!(f, "Could not copy custom init binary from '{}' to '{}': {}", source.display(), target.display(), err),
+            BuildError::BraneletCopyError{ source, target, err }            => write!(f, "Could not copy init binary from '{}' to '{}': {}", source.display(), target.display(), err),
+            BuildError::BraneletDownloadError{ url, err }                   => write!(f, "Could not download init binary from '{}': {}", url, err),
+            BuildError::BraneletWriteError{ path, err }                     => write!(f, "Could not write downloaded init binary to '{}': {}", path.display(), err),
+            BuildError::InitBinaryReadError{ path, err }                    => write!(f, "Could not read init binary '{}': {}", path.display(), err),
+            BuildError::InitBinaryArchMismatch{ path, expected, got }       => write!(f, "Init binary '{}' is built for '{}', but the image is built for '{}'; give a '{}' binary (or a directory with 'branelet-<arch>' binaries) with --init", path.display(), got, expected, expected),
+            BuildError::MissingInitBinary{ arch, searched, err }            => {
+                write!(f, "No init binary for architecture '{}' (looked in {})", arch, if searched.is_empty() { String::from("nothing") } else { searched.iter().map(|dir| format!("'{}'", dir.display())).collect::<Vec<String>>().join(", ") })?;
+                if let Some(err) = err { write!(f, " and could not download one: {}", err)?; }
+                write!(f, "; give one with --init")
+            },
+            BuildError::InitBinaryDigestMismatch{ url, expected, got }      => write!(f, "Downloaded init binary '{}' does not match the digest of its release (expected '{}', got '{}')", url, expected, got),
+            BuildError::InitBinaryUnknownDigest{ arch }                     => write!(f, "This CLI was built without the digest of the init binary for architecture '{}', so it cannot check a downloaded one", arch),
+            BuildError::InitCacheDirError{ err }                            => write!(f, "Could not find init binary cache directory: {}", err),
+            BuildError::InitCacheCreateError{ path, err }                   => write!(f, "Could not create init binary cache directory '{}': {}", path.display(), err),
             BuildError::WdClearError{ path, err }                           => write!(f, "Could not clear existing package working directory '{}': {}", path.display(), err),
             BuildError::WdCreateError{ path, err }                          => write!(f, "Could not create package working directory '{}': {}", path.display(), err),
             BuildError::LocalContainerInfoCreateError{ err }                => write!(f, "Could not write local container info to container directory: {}", err),
@@ -237,6 +312,7 @@ impl Display for BuildError {
             BuildError::WdDirCopyError{ source, target, err }               => write!(f, "Could not copy directory '{}' to '{}' in the package working directory: {}", source.display(), target.display(), err),
             BuildError::WdCompressionLaunchError{ command, err }            => write!(f, "Could not run command '{}' to compress working directory: {}", command, err),
             BuildError::WdCompressionError{ command, code, stdout, stderr } => write!(f, "Command '{}' to compress working directory returned exit code {}:\n\nstdout:\n{}\n{}\n{}\n\nstderr:\n{}\n{}\n{}\n\n", command, code, *CLI_LINE_SEPARATOR, stdout, *CLI_LINE_SEPARATOR, *CLI_LINE_SEPARATOR, stderr, *CLI_LINE_SEPARATOR),
+            BuildError::WdArchiveError{ path, err }                         => write!(f, "Could not archive working directory to '{}': {}", path.display(), err),
 
             BuildError::OpenAPISerializeError{ err }        => write!(f, "Could not re-serialize OpenAPI document: {}", err),
             BuildError::OpenAPIFileCreateError{ path, err } => write!(f, "Could not create OpenAPI file '{}': {}", path.display(), err),
@@ -252,9 +328,24 @@ impl Display for BuildError {
             BuildError::BuildKitError{ command, code, stdout, stderr } => write!(f, "Could not run a Docker BuildKit (command '{}' returned exit code {}): is BuildKit installed?\n\nstdout:\n{}\n{}\n{}\n\nstderr:\n{}\n{}\n{}\n\n", command, code, *CLI_LINE_SEPARATOR, stdout, *CLI_LINE_SEPARATOR, *CLI_LINE_SEPARATOR, stderr,*CLI_LINE_SEPARATOR),
             BuildError::ImageBuildLaunchError{ command, err }          => write!(f, "Could not run command '{}' to build the package image: {}", command, err),
             BuildError::ImageBuildError{ command, code }               => write!(f, "Command '{}' to build the package image returned exit code {}", command, code),
+            BuildError::IllegalPlatform{ platform, reason }            => write!(f, "Illegal platform '{}': {}", platform, reason),
+            BuildError::MultiPlatformWithoutRegistry{ platforms }      => write!(f, "Cannot build for multiple platforms ({}) without a registry to push the image to: buildx can only load an image for a single platform into Docker (or into the package's image.tar). Pass '--push <registry>' to push the multi-platform image there, or build for a single platform", platforms.join(", ")),
+            BuildError::MultiPlatformTar{ platforms }                  => write!(f, "Cannot write an image for multiple platforms ({}) to image.tar, as buildx can only do that for a single platform", platforms.join(", ")),
+            BuildError::RuntimeUnsupported{ runtime, feature }         => write!(f, "Cannot {} with {} without buildx; install the Docker buildx plugin, or pass '--container-runtime docker' if this is not Podman", feature, runtime),
+            BuildError::IllegalSourceDateEpoch{ value }                => write!(f, "Illegal SOURCE_DATE_EPOCH '{}': expected a number of seconds since 1 Jan 1970", value),
+
+            BuildError::BuildStepDuplicate{ step }                     => write!(f, "Build step '{}' is defined more than once", step),
+            BuildError::BuildStepUnknownDependency{ step, dependency } => write!(f, "Build step '{}' depends on unknown build step '{}'", step, dependency),
+            BuildError::BuildStepCycle{ steps }                        => write!(f, "Build steps {} depend on each other in a cycle", steps.iter().map(|s| format!("'{}'", s)).collect::<Vec<String>>().join(", ")),
+            BuildError::BuildStepPanicked{ step, err }                 => write!(f, "Build step '{}' did not complete: {}", step, err),
+            BuildError::BuildStepSpawnError{ step, err }               => write!(f, "Could not run build step '{}' in the background: {}", step, err),
 
             BuildError::DigestError{ err }            => write!(f, "Could not get Docker image digest: {}", err),
             BuildError::PackageFileCreateError{ err } => write!(f, "Could not write package info to build directory: {}", err),
+            BuildError::TestError{ err }              => write!(f, "Package was built, but did not pass its tests: {}", err),
+            BuildError::InputHashError{ path, err }   => write!(f, "Could not hash build input '{}': {}", path.display(), err),
+            BuildError::ImageTarMoveError{ source, target, err } => write!(f, "Could not move '{}' to '{}': {}", source.display(), target.display(), err),
+            BuildError::NotReproducible{ difference } => write!(f, "Package image is not reproducible: building it again gave a different image: {}", difference),
 
             // BuildError::DockerCleanupError{ image, err } => write!(f, "Could not remove existing image '{}' from docker daemon: {}", image, err),
             BuildError::FileCleanupError{ path, err } => write!(f, "Could not clean file '{}' from build directory: {}", path.display(), err),
@@ -286,6 +377,14 @@ pub enum ImportError {
     TempDirCanonicalizeError{ path: PathBuf, err: std::io::Error },
     /// Error for when we failed to clone a repository
     RepoCloneError{ repo: String, target: PathBuf, err: git2::Error },
+    /// Could not reach the remote repository through the configured proxy
+    RepoProxyError{ repo: String, proxy: String, err: git2::Error },
+    /// Could not authenticate with the remote repository
+    RepoAuthError{ repo: String, err: git2::Error },
+    /// The branch, tag or commit to check out does not exist in the repository
+    RepoRefNotFoundError{ repo: String, reference: String, err: git2::Error },
+    /// Could not check out the branch, tag or commit in the repository
+    RepoCheckoutError{ repo: String, reference: String, err: git2::Error },
 
     /// Error for when a path supposed to refer inside the repository escaped out of it
     RepoEscapeError{ path: PathBuf },
@@ -297,6 +396,10 @@ impl Display for ImportError {
             ImportError::TempDirError{ err }                   => write!(f, "Could not create temporary repository directory: {}", err),
             ImportError::TempDirCanonicalizeError{ path, err } => write!(f, "Could not resolve temporary directory path '{}': {}", path.display(), err),
             ImportError::RepoCloneError{ repo, target, err }   => write!(f, "Could not clone repository at '{}' to directory '{}': {}", repo, target.display(), err),
+            ImportError::RepoProxyError{ repo, proxy, err }    => write!(f, "Could not reach repository at '{}' through proxy '{}' (check --proxy, --no-proxy or the HTTPS_PROXY, HTTP_PROXY and NO_PROXY environment variables): {}", repo, proxy, err),
+            ImportError::RepoAuthError{ repo, err }            => write!(f, "Could not authenticate with repository at '{}' (for SSH, make sure your key is loaded into the SSH agent): {}", repo, err),
+            ImportError::RepoRefNotFoundError{ repo, reference, err } => write!(f, "Could not find {} in repository at '{}': {}", reference, repo, err),
+            ImportError::RepoCheckoutError{ repo, reference, err }    => write!(f, "Could not check out {} in repository at '{}': {}", reference, repo, err),
 
             ImportError::RepoEscapeError{ path } => write!(f, "Path '{}' points outside of repository folder", path.display()),
         }
@@ -307,6 +410,84 @@ impl Error for ImportError {}
 
 
 
+/// Collects errors that occur when connecting to the driver of a remote Brane instance
+#[derive(Debug)]
+pub enum RemoteError {
+    /// Could not read the CA certificate to verify the remote with
+    CaCertReadError{ path: PathBuf, err: std::io::Error },
+    /// The token cannot be sent to the remote
+    TokenError{ err: brane_drv::errors::AuthError },
+    /// Could not set up the connection
+    ConnectError{ err: tonic::transport::Error },
+}
+
+impl Display for RemoteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            RemoteError::CaCertReadError{ path, err } => write!(f, "Could not read CA certificate '{}': {}", path.display(), err),
+            RemoteError::TokenError{ err }            => write!(f, "{}", err),
+            RemoteError::ConnectError{ err }          => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for RemoteError {}
+
+
+
+/// Collects errors when parsing the sandbox options of local package executions
+#[derive(Debug)]
+pub enum SandboxError {
+    /// The network is not one we know
+    UnknownNetwork{ raw: String },
+    /// The memory limit is not a (positive) size
+    IllegalMemory{ raw: String },
+    /// The memory limit has a unit we don't know
+    UnknownMemoryUnit{ raw: String, unit: String },
+    /// The CPU limit is not a (positive) number
+    IllegalCpus{ raw: String },
+}
+
+impl Display for SandboxError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            SandboxError::UnknownNetwork{ raw }          => write!(f, "Unknown network '{}' (expected 'none' or 'bridge')", raw),
+            SandboxError::IllegalMemory{ raw }           => write!(f, "Illegal memory limit '{}': expected a positive size (e.g., '512m' or '2g')", raw),
+            SandboxError::UnknownMemoryUnit{ raw, unit } => write!(f, "Unknown unit '{}' in memory limit '{}' (expected 'b', 'k', 'm' or 'g')", unit, raw),
+            SandboxError::IllegalCpus{ raw }             => write!(f, "Illegal CPU limit '{}': expected a positive number (e.g., '1.5')", raw),
+        }
+    }
+}
+
+impl Error for SandboxError {}
+
+
+
+/// Collects errors during the logs subcommand
+#[derive(Debug)]
+pub enum LogsError {
+    /// Could not connect to the given address
+    ClientConnectError{ address: String, err: RemoteError },
+    /// The request for the job's output failed
+    RequestError{ address: String, job_id: String, err: tonic::Status },
+    /// Could not serialize the job's output as JSON
+    JsonSerializeError{ err: serde_json::Error },
+}
+
+impl Display for LogsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            LogsError::ClientConnectError{ address, err }   => write!(f, "Could not connect to remote Brane instance '{}': {}", address, err),
+            LogsError::RequestError{ address, job_id, err } => write!(f, "Could not get output of job '{}' from remote Brane instance '{}': {}", job_id, address, err.message()),
+            LogsError::JsonSerializeError{ err }            => write!(f, "Could not serialize job output as JSON: {}", err),
+        }
+    }
+}
+
+impl Error for LogsError {}
+
+
+
 /// Collects errors during the repl subcommand
 #[derive(Debug)]
 pub enum ReplError {
@@ -316,16 +497,37 @@ pub enum ReplError {
     HistoryFileError{ err: UtilError },
 
     /// Could not connect to the given address
-    ClientConnectError{ address: String, err: tonic::transport::Error },
+    ClientConnectError{ address: String, err: RemoteError },
     /// Could not create a new session on the given address
     SessionCreateError{ address: String, err: tonic::Status },
+    /// The driver on the given address has a version that is incompatible with ours
+    VersionMismatch{ address: String, client: String, driver: String, upgrade: &'static str },
     /// Requesting a command failed
     CommandRequestError{ address: String, err: tonic::Status },
+    /// Requesting the globals of the session failed
+    GlobalsRequestError{ address: String, err: tonic::Status },
+    /// Could not reconnect to the given address after losing the connection
+    ReconnectError{ address: String, session: String, attempts: u32 },
+    /// The session on the given address expired after being idle for too long
+    SessionExpired{ address: String, session: String },
+    /// The remote on the given address does not know the session to attach to
+    UnknownSession{ address: String, session: String },
 
     /// Failed to 'read' the local package index
     PackageIndexError{ err: PackageError },
     /// Failed to create the local VM
     VmCreateError{ err: VmError },
+    /// Failed to pass the script arguments to the local VM
+    VmArgsError{ err: VmError },
+
+    /// Failed to serialize the state of the local VM
+    StateSerializeError{ err: serde_json::Error },
+    /// Failed to write the state of the local VM to the given file
+    StateWriteError{ path: PathBuf, err: std::io::Error },
+    /// Failed to read a VM state from the given file
+    StateReadError{ path: PathBuf, err: std::io::Error },
+    /// The given file did not contain a valid VM state
+    StateParseError{ path: PathBuf, err: serde_json::Error },
 }
 
 impl Display for ReplError {
@@ -335,11 +537,22 @@ impl Display for ReplError {
             ReplError::HistoryFileError{ err }     => write!(f, "Could not get REPL history file location: {}", err),
 
             ReplError::ClientConnectError{ address, err }  => write!(f, "Could not connect to remote Brane instance '{}': {}", address, err),
-            ReplError::SessionCreateError{ address, err }  => write!(f, "Could not create new session with remote Brane instance '{}': remote returned status: {}", address, err),
+            ReplError::SessionCreateError{ address, err }  => write!(f, "Could not create new session with remote Brane instance '{}': remote returned status: {}{}", address, err, if err.code() == tonic::Code::Unauthenticated { " (log in with 'brane login' or pass a token with '--token')" } else { "" }),
+            ReplError::VersionMismatch{ address, client, driver, upgrade } => write!(f, "This CLI (version {}) is incompatible with the driver of remote Brane instance '{}' (version {}); upgrade the {}, or use '--skip-version-check' to connect anyway", client, address, driver, upgrade),
             ReplError::CommandRequestError{ address, err } => write!(f, "Could not run command on remote Brane instance '{}': request failed: remote returned status: {}", address, err),
+            ReplError::GlobalsRequestError{ address, err } => write!(f, "Could not get the globals of the session on remote Brane instance '{}': remote returned status: {}", address, err),
+            ReplError::ReconnectError{ address, session, attempts } => write!(f, "Could not reconnect to remote Brane instance '{}' after {} attempts; use '--attach {}' to continue the session later", address, attempts, session),
+            ReplError::SessionExpired{ address, session }  => write!(f, "Session '{}' on remote Brane instance '{}' has expired after being idle for too long; start a new one", session, address),
+            ReplError::UnknownSession{ address, session }  => write!(f, "Remote Brane instance '{}' does not know session '{}' (it may have been closed, or the remote restarted without keeping its sessions); start a new one", address, session),
 
             ReplError::PackageIndexError{ err } => write!(f, "Could not read local package index: {}", err),
             ReplError::VmCreateError{ err }     => write!(f, "Could not create local VM: {}", err),
+            ReplError::VmArgsError{ err }       => write!(f, "Could not pass arguments to local VM: {}", err),
+
+            ReplError::StateSerializeError{ err }    => write!(f, "Could not serialize the state of the local VM: {}", err),
+            ReplError::StateWriteError{ path, err }  => write!(f, "Could not write session state to '{}': {}", path.display(), err),
+            ReplError::StateReadError{ path, err }   => write!(f, "Could not read session state from '{}': {}", path.display(), err),
+            ReplError::StateParseError{ path, err }  => write!(f, "Could not parse session state in '{}': {}", path.display(), err),
         }
     }
 }
@@ -348,18 +561,88 @@ impl Error for ReplError {}
 
 
 
+/// Collects errors during the run subcommand
+#[derive(Debug)]
+pub enum RunError {
+    /// Could not read the script file
+    ScriptReadError{ path: PathBuf, err: std::io::Error },
+    /// Failed to 'read' the local package index
+    PackageIndexError{ err: PackageError },
+    /// The script could not be compiled
+    CompileError{ err: anyhow::Error },
+    /// Failed to create the VM
+    VmCreateError{ err: VmError },
+    /// Failed to pass the script arguments to the VM
+    VmArgsError{ err: VmError },
+    /// Failed to disassemble the compiled script
+    DisassembleError{ err: VmError },
+    /// The script failed while running
+    ExecutionError{ err: VmError },
+
+    /// Could not serialize the result report
+    ReportSerializeError{ err: serde_json::Error },
+    /// Could not write the result report
+    ReportWriteError{ path: PathBuf, err: std::io::Error },
+}
+
+impl Display for RunError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            RunError::ScriptReadError{ path, err }  => write!(f, "Could not read script '{}': {}", path.display(), err),
+            RunError::PackageIndexError{ err }      => write!(f, "Could not read local package index: {}", err),
+            RunError::CompileError{ err }           => write!(f, "{:?}", err),
+            RunError::VmCreateError{ err }          => write!(f, "Could not create VM: {}", err),
+            RunError::VmArgsError{ err }            => write!(f, "Could not pass arguments to the script: {}", err),
+            RunError::DisassembleError{ err }       => write!(f, "{}", err),
+            RunError::ExecutionError{ err }         => write!(f, "{}", err),
+
+            RunError::ReportSerializeError{ err }   => write!(f, "Could not serialize result report: {}", err),
+            RunError::ReportWriteError{ path, err } => write!(f, "Could not write result report to '{}': {}", path.display(), err),
+        }
+    }
+}
+
+impl Error for RunError {}
+
+
+
+/// Collects the resources that we cannot get because we are running in offline mode (`--offline`).
+#[derive(Debug)]
+pub enum OfflineError {
+    /// A package is not available locally. The version may also be a requirement, for dependencies.
+    MissingPackage{ name: String, version: String },
+    /// A Docker image is not in the local Docker daemon, and there is no image file to import it from
+    MissingImage{ image: String },
+    /// A registry endpoint would have to be contacted
+    RegistryEndpoint{ endpoint: String },
+}
+
+impl Display for OfflineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            OfflineError::MissingPackage{ name, version } => write!(f, "Package '{}' (version {}) is not available locally, and cannot be pulled in offline mode", name, version),
+            OfflineError::MissingImage{ image }           => write!(f, "Image '{}' is not available in the local Docker daemon, and cannot be pulled in offline mode", image),
+            OfflineError::RegistryEndpoint{ endpoint }    => write!(f, "Cannot contact registry endpoint '{}' in offline mode", endpoint),
+        }
+    }
+}
+
+impl Error for OfflineError {}
+
+
+
 /// Collects errors relating to the version command.
 #[derive(Debug)]
 pub enum VersionError {
     /// Could not parse a Version number.
     VersionParseError{ raw: String, err: specifications::version::ParseError },
 
-    /// Could not get the configuration directory
-    ConfigDirError{ err: UtilError },
-    /// Could not open the registry file
-    RegistryFileError{ err: specifications::registry::RegistryConfigError },
+    /// Could not read the registry we're logged into
+    CredentialError{ err: crate::credentials::CredentialError },
     /// Could not perform the request
     RequestError{ url: String, err: reqwest::Error },
+    /// Could not reach the registry through the proxy
+    ProxyError{ err: ProxyError },
     /// The request returned a non-200 exit code
     RequestFailure{ url: String, status: reqwest::StatusCode },
     /// The request's body could not be get.
@@ -373,9 +656,9 @@ impl Display for VersionError {
         match self {
             VersionParseError{ raw, err } => write!(f, "Could parse '{}' as Version: {}", raw, err),
 
-            ConfigDirError{ err }         => write!(f, "Could not get the Brane configuration directory: {}", err),
-            RegistryFileError{ err }      => write!(f, "{}", err),
+            CredentialError{ err }        => write!(f, "{}", err),
             RequestError{ url, err }      => write!(f, "Could not perform request to '{}': {}", url, err),
+            ProxyError{ err }             => write!(f, "{}", err),
             RequestFailure{ url, status } => write!(f, "Request to '{}' returned non-zero exit code {} ({})", url, status.as_u16(), status.canonical_reason().unwrap_or("<???>")),
             RequestBodyError{ url, err }  => write!(f, "Could not get body from response from '{}': {}", url, err),
         }
@@ -401,10 +684,6 @@ pub enum UtilError {
     BuildxLaunchError{ command: String, err: std::io::Error },
     /// The Buildx version in the buildx command does not have at least two parts, separated by spaces
     BuildxVersionNoParts{ version: String },
-    /// The Buildx version is not prepended with a 'v'
-    BuildxVersionNoV{ version: String },
-    /// The Buildx version in the buildx command is not a version split by something else
-    BuildxVersionNoDash{ version: String },
     /// The version reported by Buildx is not a valid version
     IllegalBuildxVersion{ version: String, err: VersionParseError },
 
@@ -412,6 +691,10 @@ pub enum UtilError {
     DirectoryReadError{ dir: PathBuf, err: std::io::Error },
     /// Could not automatically determine package file inside a directory.
     UndeterminedPackageFile{ dir: PathBuf },
+    /// Found more than one file in a directory that could be the package file.
+    AmbiguousPackageFile{ dir: PathBuf, candidates: Vec<PathBuf> },
+    /// The `brane.toml` manifest of a package could not be used.
+    ManifestError{ err: ManifestError },
 
     /// Could not open the main package file of the package to build.
     PackageFileOpenError{ file: PathBuf, err: std::io::Error },
@@ -419,6 +702,8 @@ pub enum UtilError {
     PackageFileReadError{ file: PathBuf, err: std::io::Error },
     /// Could not automatically determine package kind based on the file.
     UndeterminedPackageKind{ file: PathBuf },
+    /// The package file looks like more than one kind of package.
+    AmbiguousPackageKind{ file: PathBuf, kinds: Vec<PackageKind> },
 
     /// Could not find the user config folder
     UserConfigDirNotFound,
@@ -427,6 +712,10 @@ pub enum UtilError {
     /// Could not find brane's folder in the config folder
     BraneConfigDirNotFound{ path: PathBuf },
 
+    /// Could not find the user's home folder
+    UserHomeDirNotFound,
+    /// Could not create the directory of Brane's history file
+    HistoryDirCreateError{ path: PathBuf, err: std::io::Error },
     /// Could not create Brane's history file
     HistoryFileCreateError{ path: PathBuf, err: std::io::Error },
     /// Could not find Brane's history file
@@ -477,21 +766,24 @@ impl Display for UtilError {
             UtilError::IllegalDockerVersion{ version, err } => write!(f, "Local Docker instance reports unparseable version '{}': {}", version, err),
             UtilError::BuildxLaunchError{ command, err }    => write!(f, "Could not run command '{}' to get Buildx version information: {}", command, err),
             UtilError::BuildxVersionNoParts{ version }      => write!(f, "Illegal Buildx version '{}': did not find second part (separted by spaces) with version number", version),
-            UtilError::BuildxVersionNoV{ version }          => write!(f, "Illegal Buildx version '{}': did not find 'v' prepending version number", version),
-            UtilError::BuildxVersionNoDash{ version }       => write!(f, "Illegal Buildx version '{}': did not find dash separating version number", version),
             UtilError::IllegalBuildxVersion{ version, err } => write!(f, "Buildx reports unparseable version '{}': {}", version, err),
 
             UtilError::DirectoryReadError{ dir, err } => write!(f, "Could not read from directory '{}': {}", dir.display(), err),
             UtilError::UndeterminedPackageFile{ dir } => write!(f, "Could not determine package file in directory '{}'; specify it manually with '--file'", dir.display()),
+            UtilError::AmbiguousPackageFile{ dir, candidates } => write!(f, "Found multiple package files in directory '{}': {}; specify the one to use manually, or declare it in a '{}'", dir.display(), candidates.iter().map(|file| format!("'{}'", file.display())).collect::<Vec<_>>().join(", "), crate::manifest::MANIFEST_FILE),
+            UtilError::ManifestError{ err }           => write!(f, "{}", err),
 
             UtilError::PackageFileOpenError{ file, err } => write!(f, "Could not open package file '{}': {}", file.display(), err),
             UtilError::PackageFileReadError{ file, err } => write!(f, "Could not read from package file '{}': {}", file.display(), err),
             UtilError::UndeterminedPackageKind{ file }   => write!(f, "Could not determine package from package file '{}'; specify it manually with '--kind'", file.display()),
+            UtilError::AmbiguousPackageKind{ file, kinds } => write!(f, "Package file '{}' could be a package of kind {}; specify it manually with '--kind', or declare it in a '{}'", file.display(), kinds.iter().map(|kind| format!("'{}'", kind)).collect::<Vec<_>>().join(" or "), crate::manifest::MANIFEST_FILE),
     
             UtilError::UserConfigDirNotFound                        => write!(f, "Could not find the user's config directory for your OS (reported as {})", std::env::consts::OS),
             UtilError::BraneConfigDirCreateError{ path, err }       => write!(f, "Could not create Brane config directory '{}': {}", path.display(), err),
             UtilError::BraneConfigDirNotFound{ path }               => write!(f, "Brane config directory '{}' not found", path.display()),
 
+            UtilError::UserHomeDirNotFound                 => write!(f, "Could not find the user's home directory for your OS (reported as {})", std::env::consts::OS),
+            UtilError::HistoryDirCreateError{ path, err }  => write!(f, "Could not create directory '{}' for the REPL history: {}", path.display(), err),
             UtilError::HistoryFileCreateError{ path, err } => write!(f, "Could not create history file '{}' for the REPL: {}", path.display(), err),
             UtilError::HistoryFileNotFound{ path }         => write!(f, "History file '{}' for the REPL does not exist", path.display()),
 
@@ -519,3 +811,34 @@ impl Display for UtilError {
 }
 
 impl Error for UtilError {}
+
+
+
+/// Collects errors when reading the `brane.toml` manifest of a package.
+#[derive(Debug)]
+pub enum ManifestError {
+    /// Could not read the manifest file
+    ReadError{ path: PathBuf, err: std::io::Error },
+    /// The manifest file is not valid TOML, or not a valid manifest
+    ParseError{ path: PathBuf, err: toml::de::Error },
+    /// The manifest declares a kind of package that doesn't exist
+    IllegalKind{ path: PathBuf, err: PackageKindError },
+    /// A path in the manifest is absolute or escapes the manifest's directory
+    IllegalPath{ path: PathBuf, field: &'static str, value: PathBuf },
+    /// The package file that the manifest declares does not exist
+    MissingFile{ path: PathBuf, file: PathBuf },
+}
+
+impl Display for ManifestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            ManifestError::ReadError{ path, err }            => write!(f, "Could not read manifest '{}': {}", path.display(), err),
+            ManifestError::ParseError{ path, err }           => write!(f, "Could not parse manifest '{}': {}", path.display(), err),
+            ManifestError::IllegalKind{ path, err }          => write!(f, "Illegal package kind in manifest '{}': {}", path.display(), err),
+            ManifestError::IllegalPath{ path, field, value } => write!(f, "Illegal {} '{}' in manifest '{}': it must be a relative path that stays inside the manifest's directory", field, value.display(), path.display()),
+            ManifestError::MissingFile{ path, file }         => write!(f, "Package file '{}' declared in manifest '{}' does not exist", file.display(), path.display()),
+        }
+    }
+}
+
+impl Error for ManifestError {}
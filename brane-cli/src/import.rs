@@ -0,0 +1,212 @@
+/* IMPORT.rs
+ *   by Lut99
+ *
+ * Created:
+ *   14 Oct 2026, 20:42:18
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements fetching the repository for the `import` subcommand, i.e.,
+ *   resolving the repository URL, cloning it (over HTTPS or SSH) and
+ *   checking out the requested branch, tag or commit.
+**/
+
+use std::cell::Cell;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::path::Path;
+
+use git2::{Config, Cred, CredentialType, ErrorClass, ErrorCode, FetchOptions, Object, RemoteCallbacks, Repository};
+use git2::build::{CheckoutBuilder, RepoBuilder};
+
+use crate::errors::ImportError;
+use crate::proxy;
+
+
+/***** CONSTANTS *****/
+/// The message of the error with which we stop libgit2 from asking for credentials once we tried them all.
+const NO_CREDENTIALS: &str = "no valid credentials found";
+
+/// The HTTP status codes with which servers refuse to let us in.
+const AUTH_STATUS_CODES: [u16; 2] = [ 401, 403 ];
+
+
+/***** LIBRARY STRUCTS *****/
+/// Defines what to check out after cloning a repository.
+#[derive(Clone, Debug)]
+pub enum GitRef {
+    /// Use whatever the default branch of the repository is.
+    Default,
+    /// Check out the given branch.
+    Branch(String),
+    /// Check out the given tag.
+    Tag(String),
+    /// Check out the given commit (or anything else `git rev-parse` understands).
+    Commit(String),
+}
+
+impl GitRef {
+    /// Constructor for the GitRef that selects it from the (mutually exclusive) command-line options.
+    /// 
+    /// **Arguments**
+    ///  * `branch`: The branch to check out, if any.
+    ///  * `tag`: The tag to check out, if any.
+    ///  * `commit`: The commit to check out, if any.
+    /// 
+    /// **Returns**  
+    /// The GitRef for the first of the options that is given, or GitRef::Default if none is.
+    pub fn new(branch: Option<String>, tag: Option<String>, commit: Option<String>) -> Self {
+        match (branch, tag, commit) {
+            (Some(branch), _, _) => GitRef::Branch(branch),
+            (_, Some(tag), _)    => GitRef::Tag(tag),
+            (_, _, Some(commit)) => GitRef::Commit(commit),
+            _                    => GitRef::Default,
+        }
+    }
+}
+
+impl Display for GitRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            GitRef::Default        => write!(f, "default branch"),
+            GitRef::Branch(branch) => write!(f, "branch '{}'", branch),
+            GitRef::Tag(tag)       => write!(f, "tag '{}'", tag),
+            GitRef::Commit(commit) => write!(f, "commit '{}'", commit),
+        }
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Resolves the repository given on the command line to a URL git can clone.
+/// 
+/// Full `https://`, `http://` and `ssh://` URLs are used as-is, while anything else is taken to be a GitHub `owner/repo` short-hand.
+/// 
+/// **Arguments**
+///  * `repo`: The repository as given by the user.
+/// 
+/// **Returns**  
+/// The URL of the repository.
+pub fn resolve_url(repo: &str) -> String {
+    if repo.starts_with("https://") || repo.starts_with("http://") || repo.starts_with("ssh://") {
+        repo.to_string()
+    } else {
+        format!("https://github.com/{}", repo)
+    }
+}
+
+
+
+/// Returns whether the given error (of cloning a repository) means that we could not authenticate with the repository.
+/// 
+/// That is the case if libgit2 says so itself, if the server refused us with a 401 or 403 status code, if the SSH server did not accept our key, or if we ran out of credentials to try. Other errors (like network errors) are not authentication errors, even if they happen after we sent credentials.
+/// 
+/// **Arguments**
+///  * `err`: The error to check.
+/// 
+/// **Returns**  
+/// Whether the error is an authentication error.
+pub fn is_auth_error(err: &git2::Error) -> bool {
+    if err.code() == ErrorCode::Auth || err.message().contains(NO_CREDENTIALS) { return true; }
+    match err.class() {
+        ErrorClass::Http => {
+            // libgit2 reports these as 'unexpected http status code: <code>'
+            let code = err.message().split("status code: ").nth(1).and_then(|rest| rest.split(|c: char| !c.is_ascii_digit()).next());
+            code.and_then(|code| code.parse::<u16>().ok()).map(|code| AUTH_STATUS_CODES.contains(&code)).unwrap_or(false)
+        },
+        ErrorClass::Ssh  => err.message().contains("Failed to authenticate SSH session") || err.message().contains("Username/PublicKey combination invalid"),
+        _                => false,
+    }
+}
+
+
+
+/// Clones the given repository and checks out the given reference.
+/// 
+/// Repositories cloned over SSH are authenticated using the keys in the local SSH agent; for HTTPS, git's credential helpers are used (if any are configured). HTTP(S) traffic goes through the configured proxy (see `proxy::git_proxy_options()`).
+/// 
+/// **Arguments**
+///  * `url`: The URL of the repository to clone (see `resolve_url()`).
+///  * `target`: The (empty) directory to clone the repository to.
+///  * `git_ref`: The branch, tag or commit to check out.
+/// 
+/// **Returns**  
+/// The cloned Repository on success, or an ImportError otherwise. Failing authentication and references that do not exist have their own errors.
+pub fn clone_repo(url: &str, target: &Path, git_ref: &GitRef) -> Result<Repository, ImportError> {
+    // Prepare the credential callback. libgit2 keeps asking for credentials as long as we give some, so only try every method once.
+    let auth_attempted = Cell::new(false);
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(|url, username_from_url, allowed| {
+        if auth_attempted.replace(true) { return Err(git2::Error::new(ErrorCode::Auth, ErrorClass::Callback, NO_CREDENTIALS)); }
+
+        if allowed.contains(CredentialType::SSH_KEY) {
+            Cred::ssh_key_from_agent(username_from_url.unwrap_or("git"))
+        } else if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            let config = Config::open_default()?;
+            Cred::credential_helper(&config, url, username_from_url)
+        } else {
+            Cred::default()
+        }
+    });
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(callbacks);
+    fetch_options.proxy_options(proxy::git_proxy_options(url));
+
+    // Clone the repository, immediately selecting the branch if one is given
+    debug!("Cloning repository '{}' ({}) to '{}'...", url, git_ref, target.display());
+    let mut builder = RepoBuilder::new();
+    builder.fetch_options(fetch_options);
+    if let GitRef::Branch(branch) = git_ref { builder.branch(branch); }
+    let repo = match builder.clone(url, target) {
+        Ok(repo) => repo,
+        Err(err) => {
+            if is_auth_error(&err) {
+                return Err(ImportError::RepoAuthError{ repo: url.to_string(), err });
+            }
+            if err.class() == ErrorClass::Net {
+                let settings = proxy::settings();
+                if let Some(proxy) = url::Url::parse(url).ok().as_ref().and_then(|url| settings.proxy_for(url)) {
+                    return Err(ImportError::RepoProxyError{ repo: url.to_string(), proxy: proxy::redact(proxy), err });
+                }
+            }
+            if let GitRef::Branch(_) = git_ref {
+                if err.code() == ErrorCode::NotFound {
+                    return Err(ImportError::RepoRefNotFoundError{ repo: url.to_string(), reference: git_ref.to_string(), err });
+                }
+            }
+            return Err(ImportError::RepoCloneError{ repo: url.to_string(), target: target.to_path_buf(), err });
+        }
+    };
+
+    // Check out the tag or commit, if any
+    let spec = match git_ref {
+        GitRef::Tag(tag)       => format!("refs/tags/{}", tag),
+        GitRef::Commit(commit) => commit.clone(),
+        _                      => { return Ok(repo); }
+    };
+    checkout(&repo, &spec).map_err(|err| match err.code() {
+        ErrorCode::NotFound | ErrorCode::InvalidSpec | ErrorCode::Ambiguous => ImportError::RepoRefNotFoundError{ repo: url.to_string(), reference: git_ref.to_string(), err },
+        _                                                                   => ImportError::RepoCheckoutError{ repo: url.to_string(), reference: git_ref.to_string(), err },
+    })?;
+
+    Ok(repo)
+}
+
+/// Checks out the commit that the given revision refers to in a detached HEAD.
+/// 
+/// **Arguments**
+///  * `repo`: The Repository to check out in.
+///  * `spec`: The revision to check out (e.g., a tag reference or a (short) commit hash).
+/// 
+/// **Returns**  
+/// Nothing on success, or the git2::Error if the revision does not exist or could not be checked out.
+fn checkout(repo: &Repository, spec: &str) -> Result<(), git2::Error> {
+    let commit: Object = repo.revparse_single(spec)?.peel_to_commit()?.into_object();
+    repo.checkout_tree(&commit, Some(CheckoutBuilder::new().force()))?;
+    repo.set_head_detached(commit.id())
+}
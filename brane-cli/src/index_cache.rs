@@ -0,0 +1,270 @@
+/* INDEX CACHE.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 00:58:12
+ * Last edited:
+ *   15 Oct 2026, 00:58:12
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements an on-disk cache of the parsed package.yml files in the local
+ *   package directory, such that building the local PackageIndex only has to
+ *   parse the packages that changed since the last time.
+**/
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use specifications::package::PackageInfo;
+use specifications::version::Version;
+
+use crate::utils::get_index_cache_file;
+
+
+/***** CONSTANTS *****/
+/// The version of the cache format. Caches with another version are ignored (and overwritten).
+pub const INDEX_CACHE_FORMAT: u32 = 1;
+
+
+
+
+
+/***** ERRORS *****/
+/// Collects errors that relate to the index cache.
+#[derive(Debug)]
+pub enum IndexCacheError {
+    /// Could not create the directory of the cache file
+    DirCreateError{ path: PathBuf, err: std::io::Error },
+    /// Could not serialize the cache
+    SerializeError{ err: serde_json::Error },
+    /// Could not write the (temporary) cache file
+    FileWriteError{ path: PathBuf, err: std::io::Error },
+    /// Could not move the new cache file in place
+    FileRenameError{ from: PathBuf, to: PathBuf, err: std::io::Error },
+}
+
+impl Display for IndexCacheError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            IndexCacheError::DirCreateError{ path, err }      => write!(f, "Could not create index cache directory '{}': {}", path.display(), err),
+            IndexCacheError::SerializeError{ err }            => write!(f, "Could not serialize index cache: {}", err),
+            IndexCacheError::FileWriteError{ path, err }      => write!(f, "Could not write index cache file '{}': {}", path.display(), err),
+            IndexCacheError::FileRenameError{ from, to, err } => write!(f, "Could not move index cache file '{}' to '{}': {}", from.display(), to.display(), err),
+        }
+    }
+}
+
+impl Error for IndexCacheError {}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Describes the state of a package version directory at the time its package.yml was parsed. If any of it changes, the cached PackageInfo is stale.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Fingerprint {
+    /// The modification time of the version directory itself (changes when files are added or removed).
+    pub dir_mtime : SystemTime,
+    /// The modification time of the package.yml.
+    pub yml_mtime : SystemTime,
+    /// The size of the package.yml (in bytes).
+    pub yml_size  : u64,
+    /// The (sorted) names of the files in the version directory.
+    pub files     : Vec<String>,
+}
+
+impl Fingerprint {
+    /// Constructor for the Fingerprint, which takes it from the given version directory.
+    /// 
+    /// **Arguments**
+    ///  * `version_dir`: The directory of the package version (i.e., the one with the package.yml in it).
+    /// 
+    /// **Returns**  
+    /// The new Fingerprint, or an IO error if we could not read the directory or its package.yml.
+    pub fn of(version_dir: &Path) -> Result<Self, std::io::Error> {
+        let dir_mtime = fs::metadata(version_dir)?.modified()?;
+        let yml = fs::metadata(version_dir.join("package.yml"))?;
+        let mut files = Vec::new();
+        for entry in fs::read_dir(version_dir)? {
+            files.push(entry?.file_name().to_string_lossy().to_string());
+        }
+        files.sort();
+
+        Ok(Self {
+            dir_mtime,
+            yml_mtime : yml.modified()?,
+            yml_size  : yml.len(),
+            files,
+        })
+    }
+}
+
+
+
+/// A single package version in the IndexCache.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CacheEntry {
+    /// The state of the version directory when we parsed its package.yml.
+    pub fingerprint : Fingerprint,
+    /// The parsed package.yml.
+    pub info        : PackageInfo,
+}
+
+
+
+/// The parsed package.yml files of the local packages, keyed by `<name>/<version>`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct IndexCache {
+    /// The version of the cache format (see INDEX_CACHE_FORMAT).
+    pub format  : u32,
+    /// The cached package versions.
+    pub entries : HashMap<String, CacheEntry>,
+}
+
+impl Default for IndexCache {
+    fn default() -> Self {
+        Self {
+            format  : INDEX_CACHE_FORMAT,
+            entries : HashMap::new(),
+        }
+    }
+}
+
+impl IndexCache {
+    /// Loads the cache from the given file.
+    /// 
+    /// As the cache can always be rebuilt, a missing, unreadable or outdated file simply results in an empty cache.
+    /// 
+    /// **Arguments**
+    ///  * `path`: The path of the cache file.
+    /// 
+    /// **Returns**  
+    /// The IndexCache in the file, or an empty one if there is no (usable) cache.
+    pub fn load(path: &Path) -> Self {
+        let handle = match File::open(path) {
+            Ok(handle) => handle,
+            Err(err)   => {
+                if err.kind() != std::io::ErrorKind::NotFound { warn!("Could not open index cache file '{}': {}; rebuilding the package index", path.display(), err); }
+                return Self::default();
+            }
+        };
+        match serde_json::from_reader::<_, Self>(BufReader::new(handle)) {
+            Ok(cache) if cache.format == INDEX_CACHE_FORMAT => cache,
+            Ok(cache) => {
+                debug!("Ignoring index cache file '{}' with format {} (expected {})", path.display(), cache.format, INDEX_CACHE_FORMAT);
+                Self::default()
+            },
+            Err(err) => {
+                warn!("Could not parse index cache file '{}': {}; rebuilding the package index", path.display(), err);
+                Self::default()
+            },
+        }
+    }
+
+    /// Writes the cache to the given file.
+    /// 
+    /// The cache is written to a temporary file first, so concurrent `brane` invocations never see half a cache.
+    /// 
+    /// **Arguments**
+    ///  * `path`: The path of the cache file.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or an IndexCacheError otherwise.
+    pub fn store(&self, path: &Path) -> Result<(), IndexCacheError> {
+        if let Some(dir) = path.parent() {
+            if let Err(err) = fs::create_dir_all(dir) { return Err(IndexCacheError::DirCreateError{ path: dir.to_path_buf(), err }); }
+        }
+
+        let raw = match serde_json::to_vec(self) {
+            Ok(raw)  => raw,
+            Err(err) => { return Err(IndexCacheError::SerializeError{ err }); }
+        };
+        let temp_path = path.with_extension(format!("cache.{}", std::process::id()));
+        if let Err(err) = fs::write(&temp_path, raw) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(IndexCacheError::FileWriteError{ path: temp_path, err });
+        }
+        if let Err(err) = fs::rename(&temp_path, path) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(IndexCacheError::FileRenameError{ from: temp_path, to: path.to_path_buf(), err });
+        }
+        Ok(())
+    }
+
+
+
+    /// Returns the cached PackageInfo of the given package version, if it's still up-to-date.
+    /// 
+    /// **Arguments**
+    ///  * `key`: The key of the package version (see `key()`).
+    ///  * `fingerprint`: The current Fingerprint of its directory.
+    pub fn get(&self, key: &str, fingerprint: &Fingerprint) -> Option<&PackageInfo> {
+        self.entries.get(key).filter(|entry| entry.fingerprint == *fingerprint).map(|entry| &entry.info)
+    }
+
+    /// Removes the given package (version) from the cache.
+    /// 
+    /// **Arguments**
+    ///  * `name`: The name of the package.
+    ///  * `version`: The version to remove, or None to remove all versions of the package.
+    /// 
+    /// **Returns**  
+    /// Whether anything was removed.
+    pub fn invalidate(&mut self, name: &str, version: Option<&Version>) -> bool {
+        let before = self.entries.len();
+        match version {
+            Some(version) => { self.entries.remove(&key(name, version)); },
+            None          => {
+                let prefix = format!("{}/", name);
+                self.entries.retain(|key, _| !key.starts_with(&prefix));
+            },
+        }
+        self.entries.len() < before
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Returns the key of the given package version in the IndexCache.
+/// 
+/// **Arguments**
+///  * `name`: The name of the package.
+///  * `version`: The version of the package.
+#[inline]
+pub fn key(name: &str, version: &Version) -> String {
+    format!("{}/{}", name, version)
+}
+
+/// Removes the given package (version) from the on-disk cache, such that it will be parsed again the next time the package index is built.
+/// 
+/// Should be called whenever a package version is built, pulled or removed. Failures are only logged, since the fingerprints catch most changes anyway.
+/// 
+/// **Arguments**
+///  * `name`: The name of the package.
+///  * `version`: The version to invalidate, or None to invalidate all versions of the package.
+pub fn invalidate(name: &str, version: Option<&Version>) {
+    let path = match get_index_cache_file() {
+        Ok(path) => path,
+        Err(err) => { debug!("Not invalidating index cache: {}", err); return; }
+    };
+    if !path.exists() { return; }
+
+    let mut cache = IndexCache::load(&path);
+    if cache.invalidate(name, version) {
+        debug!("Invalidated package '{}' ({}) in the index cache", name, version.map(|v| v.to_string()).unwrap_or_else(|| String::from("all versions")));
+        if let Err(err) = cache.store(&path) { warn!("{}", err); }
+    }
+}
@@ -0,0 +1,292 @@
+/* INIT BINARY.rs
+ *   by Lut99
+ *
+ * Created:
+ *   16 Oct 2026, 00:00:21
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Selects the init binary (branelet) that goes into a package image
+ *   for every architecture the image is built for. Binaries are taken
+ *   from the one given with `--init`, from those that ship with the CLI
+ *   or from the cache in ~/.brane/bin, or else downloaded from the
+ *   release (and checked against the digest built into the CLI) and
+ *   cached there.
+**/
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use specifications::package::InitBinary;
+
+use crate::build_common::BRANELET_RELEASE_URL;
+use crate::errors::BuildError;
+use crate::proxy;
+use crate::utils::get_bin_dir;
+
+
+/***** CONSTANTS *****/
+/// The ELF machine types of the architectures that we know the init binary of, as Docker calls them.
+const ELF_MACHINES: [(u16, &str); 7] = [
+    (0x03, "386"),
+    (0x14, "ppc"),
+    (0x15, "ppc64le"),
+    (0x16, "s390x"),
+    (0x28, "arm"),
+    (0x3E, "amd64"),
+    (0xB7, "arm64"),
+];
+
+/// The SHA-256 digests of the released init binaries, by architecture, as embedded when the CLI was built (see `build.rs`).
+const BRANELET_DIGESTS: &[(&str, &str)] = include!(concat!(env!("OUT_DIR"), "/branelet_digests.rs"));
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// An init binary that was selected for one of the architectures of a package image.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SelectedInit {
+    /// Where the binary is.
+    pub path   : PathBuf,
+    /// The architecture it was selected for and its hash, as recorded in the package info.
+    pub binary : InitBinary,
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Returns the architecture of a Docker platform (e.g., 'arm64' for 'linux/arm64/v8'), which is what BuildKit calls the TARGETARCH.
+/// 
+/// **Arguments**
+///  * `platform`: The platform, as 'os/arch' or 'os/arch/variant'.
+/// 
+/// **Returns**  
+/// The architecture part of the platform, or the platform itself if it has no '/'.
+pub fn platform_arch(platform: &str) -> &str {
+    platform.split('/').nth(1).unwrap_or(platform)
+}
+
+/// Returns the name of the init binary for the given architecture, both in the CLI distribution and in the build context of a package.
+#[inline]
+pub fn binary_name(arch: &str) -> String {
+    format!("branelet-{}", arch)
+}
+
+/// Determines the architecture that an executable was built for from its ELF header.
+/// 
+/// **Arguments**
+///  * `header`: The first bytes of the executable (at least 20).
+/// 
+/// **Returns**  
+/// The architecture as Docker calls it (e.g., 'amd64'), or None if the executable is not an ELF executable or for an architecture we don't know.
+pub fn elf_arch(header: &[u8]) -> Option<&'static str> {
+    if header.len() < 20 || &header[..4] != b"\x7fELF" { return None; }
+    let machine = match header[5] {
+        1 => u16::from_le_bytes([ header[18], header[19] ]),
+        2 => u16::from_be_bytes([ header[18], header[19] ]),
+        _ => { return None; }
+    };
+    ELF_MACHINES.iter().find(|(m, _)| *m == machine).map(|(_, arch)| *arch)
+}
+
+/// Returns the digest of the released init binary for the given architecture, as embedded when the CLI was built.
+/// 
+/// **Arguments**
+///  * `arch`: The architecture (e.g., 'amd64') to return the digest for.
+/// 
+/// **Returns**  
+/// The digest as lowercase hex, or None if the CLI was built without one for the architecture.
+pub fn release_digest(arch: &str) -> Option<&'static str> {
+    BRANELET_DIGESTS.iter().find(|(a, _)| *a == arch).map(|(_, digest)| *digest)
+}
+
+/// Checks a downloaded init binary against the digest that it should have.
+/// 
+/// **Arguments**
+///  * `url`: The URL that the binary was downloaded from.
+///  * `bytes`: The downloaded binary.
+///  * `expected`: The digest it should have as hex (see `release_digest()`).
+/// 
+/// **Returns**  
+/// Nothing if the binary matches, or a BuildError::InitBinaryDigestMismatch otherwise.
+pub fn check_digest(url: &str, bytes: &[u8], expected: &str) -> Result<(), BuildError> {
+    let got = format!("{:x}", Sha256::digest(bytes));
+    if !expected.eq_ignore_ascii_case(&got) { return Err(BuildError::InitBinaryDigestMismatch{ url: url.to_string(), expected: expected.to_string(), got }); }
+    Ok(())
+}
+
+/// Returns the directories where we look for prebuilt init binaries, in order: those that ship with the CLI (next to the executable, or in `../lib/brane` relative to it), then the cache of downloaded ones.
+/// 
+/// **Returns**  
+/// The directories, which may not exist.
+pub fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    if let Some(exe_dir) = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) {
+        dirs.push(exe_dir.join("..").join("lib").join("brane"));
+        dirs.insert(0, exe_dir);
+    }
+    if let Ok(bin_dir) = get_bin_dir() { dirs.push(bin_dir.join(env!("CARGO_PKG_VERSION"))); }
+    dirs
+}
+
+/// Selects the init binary for the given architecture without downloading anything.
+/// 
+/// **Arguments**
+///  * `arch`: The architecture (e.g., 'amd64') to select the binary for.
+///  * `custom`: The binary given with `--init`, if any: either the binary itself, or a directory with a `branelet-<arch>` for every architecture.
+///  * `dirs`: The directories with prebuilt binaries to look in (see `search_dirs()`), if no binary was given.
+/// 
+/// **Returns**  
+/// The path of the binary, or None if none was given and none of the directories has one (so it should be downloaded). If the given binary is built for another architecture or the given directory has none for it, a BuildError is returned instead.
+pub fn select(arch: &str, custom: Option<&Path>, dirs: &[PathBuf]) -> Result<Option<PathBuf>, BuildError> {
+    match custom {
+        Some(custom) if custom.is_dir() => match find(arch, &[ custom.to_path_buf() ]) {
+            Some(path) => Ok(Some(path)),
+            None       => Err(BuildError::MissingInitBinary{ arch: arch.to_string(), searched: vec![ custom.to_path_buf() ], err: None }),
+        },
+        Some(custom) => {
+            let path = fs::canonicalize(custom).map_err(|err| BuildError::BraneletCanonicalizeError{ path: custom.to_path_buf(), err })?;
+            let mut header = Vec::with_capacity(20);
+            let mut handle = File::open(&path).map_err(|err| BuildError::InitBinaryReadError{ path: path.clone(), err })?;
+            handle.by_ref().take(20).read_to_end(&mut header).map_err(|err| BuildError::InitBinaryReadError{ path: path.clone(), err })?;
+            match elf_arch(&header) {
+                Some(got) if got != arch => Err(BuildError::InitBinaryArchMismatch{ path, expected: arch.to_string(), got: got.to_string() }),
+                Some(_)                  => Ok(Some(path)),
+                None                     => {
+                    warn!("Cannot tell which architecture init binary '{}' is built for; using it for '{}' anyway", path.display(), arch);
+                    Ok(Some(path))
+                },
+            }
+        },
+        None => Ok(find(arch, dirs)),
+    }
+}
+
+/// Selects the init binary for every architecture of the given platforms, downloading (and caching) those that we don't have yet.
+/// 
+/// **Arguments**
+///  * `custom`: The binary given with `--init`, if any (see `select()`).
+///  * `platforms`: The platforms that the package image is built for.
+/// 
+/// **Returns**  
+/// The selected init binary for every architecture, in the order of the platforms, or a BuildError (e.g., BuildError::MissingInitBinary) if there is an architecture we have no binary for.
+pub async fn select_all(custom: Option<PathBuf>, platforms: &[String]) -> Result<Vec<SelectedInit>, BuildError> {
+    let dirs = search_dirs();
+    let mut selected: Vec<SelectedInit> = vec![];
+    for platform in platforms {
+        let arch = platform_arch(platform);
+        if selected.iter().any(|init| init.binary.arch == arch) { continue; }
+
+        let path = match select(arch, custom.as_deref(), &dirs)? {
+            Some(path) => path,
+            None       => match download(arch).await {
+                Ok(path) => path,
+                Err(err) => { return Err(BuildError::MissingInitBinary{ arch: arch.to_string(), searched: dirs, err: Some(err.to_string()) }); }
+            },
+        };
+        debug!("Using init binary '{}' for architecture '{}'", path.display(), arch);
+        let sha256 = hash_file(&path)?;
+        selected.push(SelectedInit{ path, binary: InitBinary{ arch: arch.to_string(), sha256 } });
+    }
+    Ok(selected)
+}
+
+/// Copies the selected init binaries to the build context of a package, as `branelet-<arch>` (which the Dockerfile picks from by the TARGETARCH).
+/// 
+/// **Arguments**
+///  * `selected`: The selected init binaries.
+///  * `container_dir`: The container directory in the package directory.
+/// 
+/// **Returns**  
+/// Nothing if the binaries were copied successfully, or a BuildError otherwise.
+pub fn copy_all(selected: &[SelectedInit], container_dir: &Path) -> Result<(), BuildError> {
+    for init in selected {
+        let target = container_dir.join(binary_name(&init.binary.arch));
+        if let Err(err) = fs::copy(&init.path, &target) {
+            return Err(BuildError::BraneletCopyError{ source: init.path.clone(), target, err });
+        }
+    }
+    Ok(())
+}
+
+
+
+/// Finds the prebuilt init binary for the given architecture in the given directories.
+/// 
+/// **Arguments**
+///  * `arch`: The architecture to find the binary for.
+///  * `dirs`: The directories to look in, in order.
+/// 
+/// **Returns**  
+/// The path of the first `branelet-<arch>` found, or None if there is none.
+fn find(arch: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let name = binary_name(arch);
+    dirs.iter().map(|dir| dir.join(&name)).find(|path| path.is_file())
+}
+
+/// Computes the SHA-256 hash of the given file.
+/// 
+/// **Arguments**
+///  * `path`: The file to hash.
+/// 
+/// **Returns**  
+/// The hash as `sha256:<hex>`, or a BuildError::InitBinaryReadError if the file could not be read.
+fn hash_file(path: &Path) -> Result<String, BuildError> {
+    let mut handle = File::open(path).map_err(|err| BuildError::InitBinaryReadError{ path: path.to_path_buf(), err })?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut handle, &mut hasher).map_err(|err| BuildError::InitBinaryReadError{ path: path.to_path_buf(), err })?;
+    Ok(format!("sha256:{:x}", hasher.finalize()))
+}
+
+/// Downloads the file at the given URL, through the configured proxies.
+/// 
+/// **Arguments**
+///  * `url`: The URL to download.
+/// 
+/// **Returns**  
+/// The contents of the file, or a BuildError::BraneletDownloadError if it could not be downloaded.
+async fn fetch(url: &str) -> Result<Vec<u8>, BuildError> {
+    let client = proxy::client_builder().build().map_err(|err| BuildError::BraneletDownloadError{ url: url.to_string(), err })?;
+    let response = match client.get(url).send().await.and_then(|response| response.error_for_status()) {
+        Ok(response) => response,
+        Err(err)     => { return Err(BuildError::BraneletDownloadError{ url: url.to_string(), err }); }
+    };
+    match response.bytes().await {
+        Ok(bytes) => Ok(bytes.to_vec()),
+        Err(err)  => Err(BuildError::BraneletDownloadError{ url: url.to_string(), err }),
+    }
+}
+
+/// Downloads the prebuilt init binary for the given architecture from the release, checks it against the digest built into the CLI and caches it in ~/.brane/bin.
+/// 
+/// **Arguments**
+///  * `arch`: The architecture to download the binary for.
+/// 
+/// **Returns**  
+/// The path of the cached binary, or a BuildError if the CLI has no digest to check it against, or if it could not be downloaded, does not match its digest or could not be cached.
+async fn download(arch: &str) -> Result<PathBuf, BuildError> {
+    // Without a digest we can't tell a tampered download apart, so we don't even try
+    let expected = release_digest(arch).ok_or_else(|| BuildError::InitBinaryUnknownDigest{ arch: arch.to_string() })?;
+    let url = format!("{}/{}", BRANELET_RELEASE_URL, binary_name(arch));
+    debug!("Downloading init binary from '{}'...", url);
+    let bytes = fetch(&url).await?;
+    check_digest(&url, &bytes, expected)?;
+
+    // Cache it (under a temporary name first, so that a half-written binary is never picked up)
+    let cache_dir = get_bin_dir().map_err(|err| BuildError::InitCacheDirError{ err })?.join(env!("CARGO_PKG_VERSION"));
+    if let Err(err) = fs::create_dir_all(&cache_dir) { return Err(BuildError::InitCacheCreateError{ path: cache_dir, err }); }
+    let target = cache_dir.join(binary_name(arch));
+    let partial = cache_dir.join(format!("{}.partial", binary_name(arch)));
+    if let Err(err) = fs::write(&partial, &bytes) { return Err(BuildError::BraneletWriteError{ path: partial, err }); }
+    if let Err(err) = fs::rename(&partial, &target) { return Err(BuildError::BraneletWriteError{ path: target, err }); }
+    Ok(target)
+}
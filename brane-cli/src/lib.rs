@@ -7,16 +7,34 @@ extern crate prettytable;
 #[macro_use]
 extern crate lazy_static;
 
+pub mod archive;
 #[macro_use]
 pub mod build_common;
+pub mod build_dag;
 pub mod build_ecu;
 pub mod build_oas;
+pub mod completion;
+pub mod credentials;
 pub mod docker;
 pub mod errors;
+pub mod import;
+pub mod index_cache;
+pub mod init_binary;
+pub mod lock;
+pub mod logs;
+pub mod manifest;
+pub mod oci;
+pub mod oidc;
 pub mod packages;
+pub mod prompt;
+pub mod proxy;
 pub mod registry;
+pub mod remote;
 pub mod repl;
 pub mod run;
+pub mod runtime;
+pub mod sandbox;
+pub mod signing;
 pub mod test;
 pub mod utils;
 pub mod version;
@@ -27,5 +45,8 @@ pub mod version;
 /// The minimum Docker version required by the Brane CLI command-line tool
 pub const MIN_DOCKER_VERSION: specifications::version::Version = specifications::version::Version::new(19, 0, 0);
 
+/// The minimum Podman version required by the Brane CLI command-line tool (when talking to Podman's Docker-compatible socket)
+pub const MIN_PODMAN_VERSION: specifications::version::Version = specifications::version::Version::new(3, 0, 0);
+
 /// The minimum Buildx version required by the Brane CLI command-line tool
 pub const MIN_BUILDX_VERSION: specifications::version::Version = specifications::version::Version::new(0, 7, 0);
@@ -0,0 +1,287 @@
+/* LOCK.rs
+ *   by Lut99
+ *
+ * Created:
+ *   14 Oct 2026, 19:02:41
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements per-package locks on the local package directory, so that
+ *   concurrent `brane` invocations (e.g., a build and a remove) do not
+ *   trample over each other's files.
+**/
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use fs2::FileExt;
+
+use crate::errors::UtilError;
+use crate::utils::ensure_packages_dir;
+
+
+/***** CONSTANTS *****/
+/// The default number of seconds we wait for a package lock before giving up.
+pub const DEFAULT_LOCK_TIMEOUT: u64 = 30;
+
+/// The time between two attempts to acquire a lock that someone else holds.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+
+
+
+
+/***** GLOBALS *****/
+/// The number of seconds PackageLock::acquire() (and acquire_async()) waits for a lock. Set once from the command line.
+static LOCK_TIMEOUT: AtomicU64 = AtomicU64::new(DEFAULT_LOCK_TIMEOUT);
+
+
+
+
+
+/***** ERRORS *****/
+/// Collects errors that relate to locking packages.
+#[derive(Debug)]
+pub enum LockError {
+    /// Could not find or create the packages directory to put the lock in
+    PackagesDirError{ err: UtilError },
+    /// Could not open (or create) the lock file
+    LockFileOpenError{ path: PathBuf, err: std::io::Error },
+    /// Could not lock the lock file for other reasons than that it is locked already
+    LockFileLockError{ path: PathBuf, err: std::io::Error },
+    /// Another operation held the lock for longer than we were willing to wait
+    LockTimeout{ name: String, operation: String, holder: Option<String>, timeout: Duration },
+}
+
+impl Display for LockError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            LockError::PackagesDirError{ err }        => write!(f, "Could not prepare the packages directory for locking: {}", err),
+            LockError::LockFileOpenError{ path, err } => write!(f, "Could not open lock file '{}': {}", path.display(), err),
+            LockError::LockFileLockError{ path, err } => write!(f, "Could not lock lock file '{}': {}", path.display(), err),
+            LockError::LockTimeout{ name, operation, holder, timeout } => match holder {
+                Some(holder) => write!(f, "Could not {} package '{}': package is busy with {} (waited {} seconds; try again later, or use --lock-timeout to wait longer)", operation, name, holder, timeout.as_secs()),
+                None         => write!(f, "Could not {} package '{}': package is busy with another operation (waited {} seconds; try again later, or use --lock-timeout to wait longer)", operation, name, timeout.as_secs()),
+            },
+        }
+    }
+}
+
+impl Error for LockError {}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Sets the time that PackageLock::acquire() waits for a lock held by someone else.
+/// 
+/// **Arguments**
+///  * `timeout`: The time to wait before giving up.
+#[inline]
+pub fn set_lock_timeout(timeout: Duration) {
+    LOCK_TIMEOUT.store(timeout.as_secs(), Ordering::Relaxed);
+}
+
+/// Returns the time that PackageLock::acquire() waits for a lock held by someone else.
+#[inline]
+pub fn lock_timeout() -> Duration {
+    Duration::from_secs(LOCK_TIMEOUT.load(Ordering::Relaxed))
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// An exclusive, advisory lock on all versions of a package in the local package directory.
+/// 
+/// The lock is implemented as an OS-level file lock on `.<name>.lock` in the packages directory, so it is released automatically if the process dies. It is released when the PackageLock is dropped.
+#[derive(Debug)]
+pub struct PackageLock {
+    /// The locked file.
+    file : File,
+    /// The path of the locked file.
+    path : PathBuf,
+}
+
+impl PackageLock {
+    /// Acquires the lock for the given package in the default packages directory, waiting at most `lock_timeout()` for other operations to finish.
+    /// 
+    /// **Arguments**
+    ///  * `name`: The name of the package to lock.
+    ///  * `operation`: A short description of what we're going to do with the package (e.g., "build"). Shown to others waiting for the lock.
+    /// 
+    /// **Returns**  
+    /// The PackageLock, which holds the lock until it is dropped, or a LockError if we could not get it in time.
+    pub fn acquire(name: &str, operation: &str) -> Result<Self, LockError> {
+        let packages_dir = match ensure_packages_dir(true) {
+            Ok(packages_dir) => packages_dir,
+            Err(err)         => { return Err(LockError::PackagesDirError{ err }); }
+        };
+        Self::acquire_in(&packages_dir, name, operation, lock_timeout())
+    }
+
+    /// Acquires the lock for the given package in the default packages directory like `PackageLock::acquire()`, but waits for other operations without blocking the async runtime.
+    /// 
+    /// **Arguments**
+    ///  * `name`: The name of the package to lock.
+    ///  * `operation`: A short description of what we're going to do with the package (e.g., "build"). Shown to others waiting for the lock.
+    /// 
+    /// **Returns**  
+    /// The PackageLock, which holds the lock until it is dropped, or a LockError if we could not get it in time.
+    pub async fn acquire_async(name: &str, operation: &str) -> Result<Self, LockError> {
+        let packages_dir = match ensure_packages_dir(true) {
+            Ok(packages_dir) => packages_dir,
+            Err(err)         => { return Err(LockError::PackagesDirError{ err }); }
+        };
+        Self::acquire_in_async(&packages_dir, name, operation, lock_timeout()).await
+    }
+
+    /// Acquires the lock for the given package in the given directory.
+    /// 
+    /// **Arguments**
+    ///  * `dir`: The (existing) directory to put the lock file in.
+    ///  * `name`: The name of the package to lock.
+    ///  * `operation`: A short description of what we're going to do with the package (e.g., "build"). Shown to others waiting for the lock.
+    ///  * `timeout`: The time to wait for the lock if someone else holds it.
+    /// 
+    /// **Returns**  
+    /// The PackageLock, which holds the lock until it is dropped, or a LockError if we could not get it in time.
+    pub fn acquire_in(dir: &Path, name: &str, operation: &str, timeout: Duration) -> Result<Self, LockError> {
+        let (mut file, path) = open_lock(dir, name)?;
+
+        // Keep trying until we get it or run out of time
+        let start = Instant::now();
+        let mut warned = false;
+        while !try_lock(&mut file, &path, name, operation, start, timeout, &mut warned)? {
+            std::thread::sleep(LOCK_POLL_INTERVAL);
+        }
+        Ok(Self::locked(file, path, name, operation))
+    }
+
+    /// Acquires the lock for the given package in the given directory like `PackageLock::acquire_in()`, but waits for other operations without blocking the async runtime.
+    /// 
+    /// **Arguments**
+    ///  * `dir`: The (existing) directory to put the lock file in.
+    ///  * `name`: The name of the package to lock.
+    ///  * `operation`: A short description of what we're going to do with the package (e.g., "build"). Shown to others waiting for the lock.
+    ///  * `timeout`: The time to wait for the lock if someone else holds it.
+    /// 
+    /// **Returns**  
+    /// The PackageLock, which holds the lock until it is dropped, or a LockError if we could not get it in time.
+    pub async fn acquire_in_async(dir: &Path, name: &str, operation: &str, timeout: Duration) -> Result<Self, LockError> {
+        let (mut file, path) = open_lock(dir, name)?;
+
+        // Keep trying until we get it or run out of time
+        let start = Instant::now();
+        let mut warned = false;
+        while !try_lock(&mut file, &path, name, operation, start, timeout, &mut warned)? {
+            tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+        }
+        Ok(Self::locked(file, path, name, operation))
+    }
+
+    /// Wraps a lock file that we just locked in a PackageLock, telling others who's holding it.
+    /// 
+    /// **Arguments**
+    ///  * `file`: The locked file.
+    ///  * `path`: The path of the locked file.
+    ///  * `name`: The name of the locked package.
+    ///  * `operation`: What we're going to do with the package.
+    /// 
+    /// **Returns**  
+    /// The PackageLock, which releases the lock when dropped.
+    fn locked(mut file: File, path: PathBuf, name: &str, operation: &str) -> Self {
+        // Tell others who's holding it (this is only informative, so failures aren't fatal)
+        debug!("Acquired lock '{}' to {} package '{}'", path.display(), operation, name);
+        let holder = format!("'{}' (process {})", operation, std::process::id());
+        if let Err(err) = file.set_len(0).and_then(|_| file.seek(SeekFrom::Start(0))).and_then(|_| file.write_all(holder.as_bytes())) {
+            warn!("Could not write lock holder to '{}': {}", path.display(), err);
+        }
+
+        Self{ file, path }
+    }
+}
+
+impl Drop for PackageLock {
+    fn drop(&mut self) {
+        debug!("Releasing lock '{}'", self.path.display());
+        if let Err(err) = self.file.set_len(0) { warn!("Could not clear lock holder from '{}': {}", self.path.display(), err); }
+        if let Err(err) = self.file.unlock() { warn!("Could not release lock '{}': {}", self.path.display(), err); }
+    }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Opens (or creates) the lock file of the given package.
+/// 
+/// **Arguments**
+///  * `dir`: The (existing) directory to put the lock file in.
+///  * `name`: The name of the package to lock.
+/// 
+/// **Returns**  
+/// The opened lock file and its path, or a LockError if we could not open it.
+fn open_lock(dir: &Path, name: &str) -> Result<(File, PathBuf), LockError> {
+    let path = dir.join(format!(".{}.lock", name));
+    match OpenOptions::new().read(true).write(true).create(true).open(&path) {
+        Ok(file) => Ok((file, path)),
+        Err(err) => Err(LockError::LockFileOpenError{ path, err }),
+    }
+}
+
+/// Tries to lock the given lock file once, telling the user (once) that we're waiting if someone else holds it.
+/// 
+/// **Arguments**
+///  * `file`: The lock file to lock.
+///  * `path`: The path of the lock file.
+///  * `name`: The name of the package to lock.
+///  * `operation`: What we're going to do with the package.
+///  * `start`: When we started waiting for the lock.
+///  * `timeout`: The time to wait for the lock if someone else holds it.
+///  * `warned`: Whether we already told the user we're waiting. Updated when we do.
+/// 
+/// **Returns**  
+/// Whether we got the lock, or a LockError if we could not lock the file or waited for longer than `timeout`.
+fn try_lock(file: &mut File, path: &Path, name: &str, operation: &str, start: Instant, timeout: Duration, warned: &mut bool) -> Result<bool, LockError> {
+    match file.try_lock_exclusive() {
+        Ok(_) => { return Ok(true); },
+        Err(err) if err.kind() == fs2::lock_contended_error().kind() => {},
+        Err(err) => { return Err(LockError::LockFileLockError{ path: path.to_path_buf(), err }); }
+    }
+
+    let holder = read_holder(file);
+    if start.elapsed() >= timeout {
+        return Err(LockError::LockTimeout{ name: name.to_string(), operation: operation.to_string(), holder, timeout });
+    }
+    if !*warned {
+        println!("Waiting for package '{}' to become available (busy with {})...", name, holder.as_deref().unwrap_or("another operation"));
+        *warned = true;
+    }
+    Ok(false)
+}
+
+/// Reads who is currently holding the lock from the given lock file.
+/// 
+/// **Arguments**
+///  * `file`: The lock file to read.
+/// 
+/// **Returns**  
+/// The description of the holder, or None if it couldn't be read (or there is none).
+fn read_holder(file: &mut File) -> Option<String> {
+    let mut holder = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut holder).ok()?;
+    if holder.is_empty() { None } else { Some(holder) }
+}
@@ -0,0 +1,103 @@
+/* LOGS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   14 Oct 2026, 10:31:09
+ * Last edited:
+ *   14 Oct 2026, 10:31:09
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements the `logs` subcommand, which retrieves the output of a job
+ *   that ran on a remote Brane instance.
+**/
+
+use serde::Serialize;
+use serde_json::Value as JValue;
+
+use brane_drv::grpc::GetJobOutputRequest;
+
+use crate::errors::LogsError;
+use crate::remote::RemoteOptions;
+
+
+/***** HELPER STRUCTS *****/
+/// Defines the output of a job as we print it in JSON mode.
+#[derive(Debug, Serialize)]
+struct JobOutput {
+    /// The ID of the job.
+    job_id : String,
+    /// Whether the job failed or not.
+    failed : bool,
+    /// The exit code of the job, if it failed.
+    code   : Option<i32>,
+    /// Whatever the job wrote to stdout, if it failed.
+    stdout : String,
+    /// Whatever the job wrote to stderr, if it failed.
+    stderr : String,
+    /// The value that the job returned, if it finished.
+    value  : Option<JValue>,
+}
+
+
+
+
+
+/***** SUBCOMMANDS *****/
+/// Fetches the output of the given job from the remote driver and prints it.
+/// 
+/// **Arguments**
+///  * `remote`: The address of the driver of the remote Brane instance.
+///  * `options`: The RemoteOptions to connect to the driver with.
+///  * `job_id`: The ID of the job to fetch the output of.
+///  * `json`: If true, prints the output as a JSON object instead of in a human-readable way.
+/// 
+/// **Returns**  
+/// Nothing on success, or a LogsError otherwise.
+pub async fn handle(remote: String, options: RemoteOptions, job_id: String, json: bool) -> Result<(), LogsError> {
+    // Connect to the driver
+    let mut client = match options.connect(&remote).await {
+        Ok(client) => client,
+        Err(err)   => { return Err(LogsError::ClientConnectError{ address: remote, err }); }
+    };
+
+    // Ask it for the job's output
+    let request = GetJobOutputRequest { job_id: job_id.clone() };
+    let reply = match client.get_job_output(request).await {
+        Ok(reply) => reply.into_inner(),
+        Err(err)  => { return Err(LogsError::RequestError{ address: remote, job_id, err }); }
+    };
+
+    // Print it in the requested format
+    if json {
+        let output = JobOutput {
+            job_id,
+            failed : reply.failed,
+            code   : reply.code,
+            stdout : reply.stdout,
+            stderr : reply.stderr,
+            value  : reply.value.map(|value| serde_json::from_str(&value).unwrap_or(JValue::String(value))),
+        };
+        match serde_json::to_string_pretty(&output) {
+            Ok(output) => println!("{}", output),
+            Err(err)   => { return Err(LogsError::JsonSerializeError{ err }); }
+        }
+    } else {
+        let separator = (0..80).map(|_| '-').collect::<String>();
+        if reply.failed {
+            match reply.code {
+                Some(code) => println!("Job '{}' failed with exit code {}.", job_id, code),
+                None       => println!("Job '{}' failed with an unknown exit code (its output could not be parsed, so it's shown raw below).", job_id),
+            }
+            println!("\nstdout:\n{}\n{}\n{}", separator, reply.stdout, separator);
+            println!("\nstderr:\n{}\n{}\n{}", separator, reply.stderr, separator);
+        } else {
+            println!("Job '{}' finished.", job_id);
+            println!("\nresult:\n{}\n{}\n{}", separator, reply.value.unwrap_or_default(), separator);
+        }
+    }
+
+    // Done
+    Ok(())
+}
@@ -0,0 +1,205 @@
+/* MANIFEST.rs
+ *   by Lut99
+ *
+ * Created:
+ *   16 Oct 2026, 00:00:16
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Reads the `brane.toml` manifest, with which a repository declares
+ *   explicitly which file describes its package, what kind of package
+ *   it is and which directory to use as working directory. Both
+ *   `brane build` and `brane import` prefer it over guessing.
+**/
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+use specifications::package::PackageKind;
+
+use crate::errors::ManifestError;
+
+
+/***** CONSTANTS *****/
+/// The name of the manifest file, which lives in the root of a package's repository.
+pub const MANIFEST_FILE: &str = "brane.toml";
+/// The name of the directory (or file, for worktrees and submodules) that marks the root of a git repository.
+const REPOSITORY_MARKER: &str = ".git";
+
+
+
+
+
+/***** HELPER STRUCTS *****/
+/// The manifest as it is written in the file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawManifest {
+    /// Describes the package itself.
+    package      : RawPackage,
+    /// The packages this package depends on, by name, with the versions it needs.
+    #[serde(default)]
+    dependencies : BTreeMap<String, String>,
+}
+
+/// The `[package]` section of the manifest as it is written in the file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPackage {
+    /// The package file, relative to the manifest.
+    file    : PathBuf,
+    /// The kind of package, if given.
+    kind    : Option<String>,
+    /// The working directory, relative to the manifest, if given.
+    workdir : Option<PathBuf>,
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// A `brane.toml` manifest, which declares the package in its directory.
+/// 
+/// For example:
+/// ```toml
+/// [package]
+/// file = "container.yml"
+/// kind = "ecu"
+/// workdir = "src"
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Manifest {
+    /// The directory that the manifest lives in, which all its paths are relative to.
+    pub root         : PathBuf,
+    /// The package file, relative to the root.
+    pub file         : PathBuf,
+    /// The kind of package, if the manifest declares it.
+    pub kind         : Option<PackageKind>,
+    /// The working directory, relative to the root, if the manifest declares it.
+    pub workdir      : Option<PathBuf>,
+    /// The packages that this package depends on, with the versions it needs. Not used yet; declare them as `packageDependencies` in `container.yml` for now.
+    pub dependencies : BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Reads the manifest in the given directory, if it has one.
+    /// 
+    /// **Arguments**
+    ///  * `dir`: The directory to look for a `brane.toml` in.
+    /// 
+    /// **Returns**  
+    /// The Manifest if there is one, None if there isn't, or a ManifestError if it could not be read or is invalid.
+    pub fn load(dir: &Path) -> Result<Option<Self>, ManifestError> {
+        let path = dir.join(MANIFEST_FILE);
+        if !path.is_file() { return Ok(None); }
+
+        // Read and parse it
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err)     => { return Err(ManifestError::ReadError{ path, err }); }
+        };
+        let raw: RawManifest = match toml::from_str(&contents) {
+            Ok(raw)  => raw,
+            Err(err) => { return Err(ManifestError::ParseError{ path, err }); }
+        };
+
+        // Validate what it says
+        check_path(&path, dir, "file", &raw.package.file)?;
+        if let Some(workdir) = &raw.package.workdir { check_path(&path, dir, "workdir", workdir)?; }
+        let kind = match raw.package.kind.as_deref().map(PackageKind::from_str).transpose() {
+            Ok(kind) => kind,
+            Err(err) => { return Err(ManifestError::IllegalKind{ path, err }); }
+        };
+        if !dir.join(&raw.package.file).is_file() { return Err(ManifestError::MissingFile{ path, file: raw.package.file }); }
+        if !raw.dependencies.is_empty() { warn!("Ignoring the dependencies in '{}'; declare them as 'packageDependencies' in the package file instead", path.display()); }
+
+        debug!("Using package file '{}' from manifest '{}'", raw.package.file.display(), path.display());
+        Ok(Some(Self {
+            root         : dir.to_path_buf(),
+            file         : raw.package.file,
+            kind,
+            workdir      : raw.package.workdir,
+            dependencies : raw.dependencies,
+        }))
+    }
+
+    /// Finds the manifest that declares the given package file, by looking in its directory and the directories above it up to the root of the git repository that it is in. If it is not in a repository, only its own directory is searched, so that unrelated manifests (e.g., one in the home directory) are never used.
+    /// 
+    /// **Arguments**
+    ///  * `file`: The package file to find the manifest of.
+    /// 
+    /// **Returns**  
+    /// The nearest Manifest if it declares the given file, None if it declares another file or there is none, or a ManifestError if it could not be read or is invalid.
+    pub fn for_file(file: &Path) -> Result<Option<Self>, ManifestError> {
+        let file = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+        let in_repository = file.ancestors().skip(1).any(is_repository_root);
+        for dir in file.ancestors().skip(1) {
+            if let Some(manifest) = Self::load(dir)? {
+                return Ok(if manifest.declares(&file) { Some(manifest) } else { None });
+            }
+            if !in_repository || is_repository_root(dir) { break; }
+        }
+        Ok(None)
+    }
+
+
+
+    /// Returns whether the manifest declares the given package file.
+    /// 
+    /// **Arguments**
+    ///  * `file`: The file to check.
+    pub fn declares(&self, file: &Path) -> bool {
+        let ours = self.file_path();
+        match (fs::canonicalize(&ours), fs::canonicalize(file)) {
+            (Ok(ours), Ok(file)) => ours == file,
+            _                    => ours == file,
+        }
+    }
+
+    /// Returns the path of the package file.
+    #[inline]
+    pub fn file_path(&self) -> PathBuf { self.root.join(&self.file) }
+
+    /// Returns the path of the working directory, if the manifest declares it.
+    #[inline]
+    pub fn workdir_path(&self) -> Option<PathBuf> { self.workdir.as_ref().map(|workdir| self.root.join(workdir)) }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Makes sure that a path in the manifest is relative and stays inside the manifest's directory, also when following symlinks.
+/// 
+/// **Arguments**
+///  * `path`: The path of the manifest.
+///  * `dir`: The directory of the manifest.
+///  * `field`: The field that the path is given in.
+///  * `value`: The path to check.
+/// 
+/// **Returns**  
+/// Nothing if the path is fine, or a ManifestError::IllegalPath otherwise. A path that does not exist is fine as far as this check goes.
+fn check_path(path: &Path, dir: &Path, field: &'static str, value: &Path) -> Result<(), ManifestError> {
+    let illegal = || ManifestError::IllegalPath{ path: path.to_path_buf(), field, value: value.to_path_buf() };
+    if value.as_os_str().is_empty() || value.is_absolute() || value.components().any(|component| component == Component::ParentDir) {
+        return Err(illegal());
+    }
+
+    // Resolve symlinks on both sides, since the directory itself may be reached through one
+    if let (Ok(root), Ok(target)) = (fs::canonicalize(dir), fs::canonicalize(dir.join(value))) {
+        if !target.starts_with(&root) { return Err(illegal()); }
+    }
+    Ok(())
+}
+
+/// Returns whether the given directory is the root of a git repository.
+#[inline]
+fn is_repository_root(dir: &Path) -> bool { dir.join(REPOSITORY_MARKER).exists() }
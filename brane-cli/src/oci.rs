@@ -0,0 +1,804 @@
+/* OCI.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 10:02:11
+ * Last edited:
+ *   15 Oct 2026, 10:02:11
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Pushes and pulls packages to and from standard OCI registries (e.g.,
+ *   Harbor, GHCR or a plain `registry:2`) instead of a Brane registry.
+ *
+ *   A package version is kept as two artifacts in the repository named
+ *   after the package: the Docker image itself, tagged with the version,
+ *   and a metadata artifact tagged with the version plus `.brane`. The
+ *   latter has the PackageInfo (as JSON) as its config and the package
+ *   directory (without the image) as its only layer.
+**/
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use reqwest::{Body, Client, Method, RequestBuilder, Response, StatusCode};
+use reqwest::header::{HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, LOCATION, WWW_AUTHENTICATE};
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use tokio::fs::File as TokioFile;
+use tokio_util::codec::{BytesCodec, FramedRead};
+use url::Url;
+
+use specifications::package::PackageInfo;
+use specifications::version::Version;
+
+use crate::credentials::Credentials;
+use crate::proxy::{self, ProxyError};
+
+
+/***** CONSTANTS *****/
+/// The media type of OCI image manifests.
+pub const OCI_MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+/// The media type of OCI image indices.
+pub const OCI_INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+/// The media type of Docker image manifests, which registries accept next to OCI ones.
+pub const DOCKER_MANIFEST_MEDIA_TYPE: &str = "application/vnd.docker.distribution.manifest.v2+json";
+/// The media type of the config of a package metadata artifact (the PackageInfo as JSON).
+pub const PACKAGE_CONFIG_MEDIA_TYPE: &str = "application/vnd.brane.package.config.v1+json";
+/// The media type of the layer of a package metadata artifact (the package directory without its image, as a gzipped tarball).
+pub const PACKAGE_LAYER_MEDIA_TYPE: &str = "application/vnd.brane.package.layer.v1.tar+gzip";
+/// Appended to the version to get the tag of the metadata artifact of that version.
+pub const PACKAGE_TAG_SUFFIX: &str = ".brane";
+
+/// The name of the image file in a package directory.
+const IMAGE_FILE: &str = "image.tar";
+
+
+
+
+
+/***** ERRORS *****/
+/// Collects errors that relate to talking to OCI registries.
+#[derive(Debug)]
+pub enum OciError {
+    /// The registry URL is not a valid URL
+    IllegalUrl{ raw: String, err: url::ParseError },
+    /// The registry URL has no host
+    MissingHost{ url: String },
+    /// Could not create the HTTP client
+    ClientError{ err: reqwest::Error },
+    /// Could not connect through the proxy
+    ProxyError{ err: ProxyError },
+    /// Could not send a request to the registry
+    RequestError{ url: String, err: reqwest::Error },
+    /// The registry rejected a request
+    RequestFailure{ url: String, status: StatusCode, body: String },
+    /// The registry asked us to authenticate in a way we don't know
+    UnsupportedChallenge{ url: String, challenge: String },
+    /// The token service did not give us a token
+    TokenError{ url: String, err: reqwest::Error },
+    /// The registry did not tell us where to upload a blob
+    MissingUploadLocation{ url: String },
+
+    /// A digest uses an algorithm other than sha256
+    UnsupportedDigest{ digest: String },
+    /// Something we downloaded does not match its digest
+    DigestMismatch{ what: String, expected: String, got: String },
+    /// The image of a package is not the one its metadata says it is
+    ImageDigestMismatch{ name: String, version: Version, expected: String, got: String },
+    /// A manifest (or index) could not be parsed
+    ManifestParseError{ what: String, err: serde_json::Error },
+    /// A manifest could not be serialized
+    ManifestEncodeError{ what: String, err: serde_json::Error },
+    /// The image.tar does not describe exactly one image
+    IllegalImageIndex{ path: PathBuf, got: usize },
+    /// The image in the image.tar (or registry) is not a single-platform image
+    UnsupportedManifest{ what: String, media_type: String },
+    /// The tag we pulled is not a Brane package
+    NotAPackage{ repository: String, reference: String },
+
+    /// Could not create a temporary directory
+    TempDirError{ err: std::io::Error },
+    /// Could not read a file
+    FileReadError{ path: PathBuf, err: std::io::Error },
+    /// Could not write a file
+    FileWriteError{ path: PathBuf, err: std::io::Error },
+    /// Could not pack or unpack an archive
+    ArchiveError{ path: PathBuf, err: std::io::Error },
+}
+
+impl Display for OciError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        use OciError::*;
+        match self {
+            IllegalUrl{ raw, err }               => write!(f, "Registry URL '{}' is not a valid URL: {}", raw, err),
+            MissingHost{ url }                   => write!(f, "Registry URL '{}' does not have a (valid) host", url),
+            ClientError{ err }                   => write!(f, "Could not create HTTP client: {}", err),
+            OciError::ProxyError{ err }          => write!(f, "{}", err),
+            RequestError{ url, err }             => write!(f, "Could not send request to '{}': {}", url, err),
+            RequestFailure{ url, status, body }  => write!(f, "Request to '{}' failed with status {}{}", url, status, if body.is_empty() { String::new() } else { format!(": {}", body) }),
+            UnsupportedChallenge{ url, challenge } => write!(f, "Registry '{}' asks for an unsupported kind of authentication: '{}'", url, challenge),
+            TokenError{ url, err }               => write!(f, "Could not get a token from '{}': {}", url, err),
+            MissingUploadLocation{ url }         => write!(f, "Registry did not return an upload location for '{}'", url),
+
+            UnsupportedDigest{ digest }                          => write!(f, "Digest '{}' does not use sha256", digest),
+            DigestMismatch{ what, expected, got }                => write!(f, "Digest of {} does not match: expected '{}', got '{}'", what, expected, got),
+            ImageDigestMismatch{ name, version, expected, got }  => write!(f, "Image of package '{}' (version {}) has digest '{}', but its package info says it should be '{}'", name, version, got, expected),
+            ManifestParseError{ what, err }                      => write!(f, "Could not parse {}: {}", what, err),
+            ManifestEncodeError{ what, err }                     => write!(f, "Could not serialize {}: {}", what, err),
+            IllegalImageIndex{ path, got }                       => write!(f, "Image file '{}' should contain exactly one image, but it contains {} (rebuild the package)", path.display(), got),
+            UnsupportedManifest{ what, media_type }              => write!(f, "{} has unsupported media type '{}' (only single-platform images are supported)", what, media_type),
+            NotAPackage{ repository, reference }                 => write!(f, "'{}:{}' in the registry is not a Brane package", repository, reference),
+
+            TempDirError{ err }         => write!(f, "Could not create temporary directory: {}", err),
+            FileReadError{ path, err }  => write!(f, "Could not read file '{}': {}", path.display(), err),
+            FileWriteError{ path, err } => write!(f, "Could not write file '{}': {}", path.display(), err),
+            ArchiveError{ path, err }   => write!(f, "Could not archive '{}': {}", path.display(), err),
+        }
+    }
+}
+
+impl Error for OciError {}
+
+
+
+
+
+/***** HELPER STRUCTS *****/
+/// Refers to a blob or manifest in a registry.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    /// The media type of the referenced content.
+    media_type  : String,
+    /// The digest of the referenced content.
+    digest      : String,
+    /// The size of the referenced content, in bytes.
+    size        : u64,
+    /// Any annotations on the reference.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    annotations : Option<HashMap<String, String>>,
+}
+
+impl Descriptor {
+    /// Constructor for the Descriptor that describes the given bytes.
+    fn of(media_type: &str, bytes: &[u8]) -> Self {
+        Self {
+            media_type  : media_type.to_string(),
+            digest      : sha256(bytes),
+            size        : bytes.len() as u64,
+            annotations : None,
+        }
+    }
+}
+
+/// An image manifest, which is also used for the metadata artifacts.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    /// Always 2.
+    schema_version : u32,
+    /// The media type of the manifest itself, if given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    media_type     : Option<String>,
+    /// The config blob.
+    config         : Descriptor,
+    /// The layer blobs.
+    layers         : Vec<Descriptor>,
+}
+
+/// An image index, as found in the index.json of an image.tar.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ImageIndex {
+    /// Always 2.
+    schema_version : u32,
+    /// The media type of the index itself, if given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    media_type     : Option<String>,
+    /// The manifests in the index.
+    manifests      : Vec<Descriptor>,
+}
+
+/// An entry in the manifest.json that Docker reads when loading an image.tar.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct DockerManifestEntry {
+    /// The path of the config blob.
+    config    : String,
+    /// The tags to give the image.
+    repo_tags : Vec<String>,
+    /// The paths of the layer blobs.
+    layers    : Vec<String>,
+}
+
+/// The list of tags of a repository.
+#[derive(Debug, Deserialize)]
+struct TagList {
+    /// The tags, if any.
+    tags : Option<Vec<String>>,
+}
+
+/// The answer of a token service.
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    /// The token, as most registries call it.
+    token        : Option<String>,
+    /// The token, as OAuth2 calls it.
+    access_token : Option<String>,
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// The ways in which a registry may ask us to authenticate.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Challenge {
+    /// Send the username and token as HTTP basic credentials.
+    Basic,
+    /// Get a bearer token from the given token service first.
+    Bearer{ realm: String, service: Option<String> },
+}
+
+impl Challenge {
+    /// Parses the WWW-Authenticate header of a registry.
+    ///
+    /// **Arguments**
+    ///  * `header`: The value of the header, e.g. `Bearer realm="https://ghcr.io/token",service="ghcr.io"`.
+    ///
+    /// **Returns**
+    /// The Challenge, or None if it's not one we know.
+    pub fn parse(header: &str) -> Option<Self> {
+        let header = header.trim();
+        let (scheme, params) = header.split_once(' ').unwrap_or((header, ""));
+        if scheme.eq_ignore_ascii_case("basic") { return Some(Challenge::Basic); }
+        if !scheme.eq_ignore_ascii_case("bearer") { return None; }
+
+        // Split the parameters on commas outside of quotes
+        let mut values: HashMap<String, String> = HashMap::new();
+        let mut key = String::new();
+        let mut value = String::new();
+        let (mut in_value, mut quoted) = (false, false);
+        for c in params.chars().chain(std::iter::once(',')) {
+            match c {
+                '"'                 => { quoted = !quoted; },
+                '=' if !in_value    => { in_value = true; },
+                ',' if !quoted      => {
+                    values.insert(key.trim().to_lowercase(), value.clone());
+                    key.clear();
+                    value.clear();
+                    in_value = false;
+                },
+                c if in_value       => { value.push(c); },
+                c                   => { key.push(c); },
+            }
+        }
+
+        Some(Challenge::Bearer{ realm: values.remove("realm")?, service: values.remove("service") })
+    }
+}
+
+
+
+/// Talks to the OCI registry that we're logged into.
+#[derive(Debug)]
+pub struct OciClient {
+    /// The HTTP client to send requests with.
+    client      : Client,
+    /// The base URL of the registry (without namespace).
+    base        : Url,
+    /// The namespace in which the packages live (e.g., the organisation), if any.
+    namespace   : Option<String>,
+    /// The credentials to authenticate with, if any.
+    credentials : Option<Credentials>,
+}
+
+impl OciClient {
+    /// Constructor for the OciClient.
+    ///
+    /// **Arguments**
+    ///  * `url`: The URL of the registry, optionally followed by the namespace to keep packages in (e.g., `https://ghcr.io/my-org`).
+    ///  * `credentials`: The credentials to authenticate with, if any.
+    ///
+    /// **Returns**
+    /// A new OciClient, or an OciError if the URL is invalid.
+    pub fn new(url: &str, credentials: Option<Credentials>) -> Result<Self, OciError> {
+        let parsed = Url::parse(url).map_err(|err| OciError::IllegalUrl{ raw: url.to_string(), err })?;
+        let host = match parsed.host_str() {
+            Some(host) => host,
+            None       => { return Err(OciError::MissingHost{ url: url.to_string() }); }
+        };
+        let port = parsed.port().map(|port| format!(":{}", port)).unwrap_or_default();
+        let raw_base = format!("{}://{}{}/", parsed.scheme(), host, port);
+        let base = Url::parse(&raw_base).map_err(|err| OciError::IllegalUrl{ raw: raw_base, err })?;
+        let namespace = parsed.path().trim_matches('/');
+
+        let client = proxy::client_builder().build().map_err(|err| OciError::ClientError{ err })?;
+        Ok(Self {
+            client,
+            base,
+            namespace : if namespace.is_empty() { None } else { Some(namespace.to_string()) },
+            credentials,
+        })
+    }
+
+    /// Returns the base URL of the registry, without the namespace.
+    #[inline]
+    pub fn base(&self) -> &Url { &self.base }
+
+    /// Returns the repository in which the given package lives.
+    ///
+    /// **Arguments**
+    ///  * `name`: The name of the package.
+    pub fn repository(&self, name: &str) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{}/{}", namespace, name),
+            None            => name.to_string(),
+        }
+    }
+
+    /// Starts talking about the repository of the given package, authenticating if the registry asks for it.
+    ///
+    /// **Arguments**
+    ///  * `name`: The name of the package.
+    ///  * `push`: If true, asks for permission to push as well as pull.
+    ///
+    /// **Returns**
+    /// A new OciSession, or an OciError if we could not authenticate.
+    pub async fn session(&self, name: &str, push: bool) -> Result<OciSession<'_>, OciError> {
+        let repository = self.repository(name);
+        let auth = self.authorize(&repository, push).await?;
+        Ok(OciSession{ client: self, repository, auth })
+    }
+
+    /// Finds out how to authenticate to the registry, and does so for the given repository.
+    async fn authorize(&self, repository: &str, push: bool) -> Result<Option<HeaderValue>, OciError> {
+        let url = self.base.join("v2/").map_err(|err| OciError::IllegalUrl{ raw: self.base.to_string(), err })?;
+        let response = send(self.client.get(url.clone()), &url).await?;
+        if response.status() != StatusCode::UNAUTHORIZED { return Ok(self.basic_auth()); }
+
+        let raw = response.headers().get(WWW_AUTHENTICATE).and_then(|value| value.to_str().ok()).unwrap_or_default().to_string();
+        let (realm, service) = match Challenge::parse(&raw) {
+            Some(Challenge::Basic)                   => { return Ok(self.basic_auth()); },
+            Some(Challenge::Bearer{ realm, service }) => (realm, service),
+            None                                     => { return Err(OciError::UnsupportedChallenge{ url: url.to_string(), challenge: raw }); }
+        };
+
+        // Ask the token service for a token for this repository
+        let mut token_url = Url::parse(&realm).map_err(|err| OciError::IllegalUrl{ raw: realm.clone(), err })?;
+        {
+            let mut query = token_url.query_pairs_mut();
+            if let Some(service) = &service { query.append_pair("service", service); }
+            query.append_pair("scope", &format!("repository:{}:{}", repository, if push { "pull,push" } else { "pull" }));
+        }
+        let mut request = self.client.get(token_url.clone());
+        if let Some(auth) = self.basic_auth() { request = request.header(AUTHORIZATION, auth); }
+        let response = check(send(request, &token_url).await?, &token_url).await?;
+        let token: TokenResponse = response.json().await.map_err(|err| OciError::TokenError{ url: token_url.to_string(), err })?;
+        let token = token.token.or(token.access_token).unwrap_or_default();
+
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token)).map_err(|_| OciError::UnsupportedChallenge{ url: url.to_string(), challenge: raw })?;
+        value.set_sensitive(true);
+        Ok(Some(value))
+    }
+
+    /// Returns our credentials as a basic Authorization header, if we have any.
+    fn basic_auth(&self) -> Option<HeaderValue> {
+        let credentials = self.credentials.as_ref()?;
+        let raw = format!("{}:{}", credentials.username, credentials.token.as_deref().unwrap_or_default());
+        let mut value = HeaderValue::from_str(&format!("Basic {}", base64::encode(raw))).ok()?;
+        value.set_sensitive(true);
+        Some(value)
+    }
+}
+
+
+
+/// Talks to a single repository in an OCI registry, with the authorization for it.
+#[derive(Debug)]
+pub struct OciSession<'a> {
+    /// The client that opened this session.
+    client     : &'a OciClient,
+    /// The repository we talk to.
+    repository : String,
+    /// The Authorization header to send along, if any.
+    auth       : Option<HeaderValue>,
+}
+
+impl<'a> OciSession<'a> {
+    /// Returns the URL of the given path in our repository.
+    fn url(&self, path: &str) -> Result<Url, OciError> {
+        let raw = format!("v2/{}/{}", self.repository, path);
+        self.client.base.join(&raw).map_err(|err| OciError::IllegalUrl{ raw, err })
+    }
+
+    /// Prepares a request with our authorization.
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        let request = self.client.client.request(method, url);
+        match &self.auth {
+            Some(auth) => request.header(AUTHORIZATION, auth.clone()),
+            None       => request,
+        }
+    }
+
+    /// Returns the tags in our repository (or none if the repository doesn't exist yet).
+    pub async fn tags(&self) -> Result<Vec<String>, OciError> {
+        let url = self.url("tags/list")?;
+        let response = send(self.request(Method::GET, url.clone()), &url).await?;
+        if response.status() == StatusCode::NOT_FOUND { return Ok(vec![]); }
+        let response = check(response, &url).await?;
+        let tags: TagList = parse_json(&read_body(response, &url).await?, &format!("tag list of '{}'", self.repository))?;
+        Ok(tags.tags.unwrap_or_default())
+    }
+
+    /// Returns whether the registry already has the blob with the given digest.
+    async fn has_blob(&self, digest: &str) -> Result<bool, OciError> {
+        let url = self.url(&format!("blobs/{}", digest))?;
+        let response = send(self.request(Method::HEAD, url.clone()), &url).await?;
+        if response.status() == StatusCode::NOT_FOUND { return Ok(false); }
+        check(response, &url).await?;
+        Ok(true)
+    }
+
+    /// Uploads a blob in one go.
+    async fn upload(&self, descriptor: &Descriptor, body: Body) -> Result<(), OciError> {
+        let url = self.url("blobs/uploads/")?;
+        let response = check(send(self.request(Method::POST, url.clone()).header(CONTENT_LENGTH, 0), &url).await?, &url).await?;
+        let location = match response.headers().get(LOCATION).and_then(|value| value.to_str().ok()) {
+            Some(location) => location.to_string(),
+            None           => { return Err(OciError::MissingUploadLocation{ url: url.to_string() }); }
+        };
+
+        let mut upload = url.join(&location).map_err(|err| OciError::IllegalUrl{ raw: location, err })?;
+        upload.query_pairs_mut().append_pair("digest", &descriptor.digest);
+        let request = self.request(Method::PUT, upload.clone())
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .header(CONTENT_LENGTH, descriptor.size)
+            .body(body);
+        check(send(request, &upload).await?, &upload).await?;
+        Ok(())
+    }
+
+    /// Uploads the given bytes as a blob, unless the registry already has it.
+    async fn push_blob_bytes(&self, descriptor: &Descriptor, bytes: Vec<u8>) -> Result<(), OciError> {
+        if self.has_blob(&descriptor.digest).await? { return Ok(()); }
+        self.upload(descriptor, Body::from(bytes)).await
+    }
+
+    /// Uploads the given file as a blob, unless the registry already has it.
+    async fn push_blob_file(&self, descriptor: &Descriptor, path: &Path) -> Result<(), OciError> {
+        if self.has_blob(&descriptor.digest).await? {
+            debug!("Blob '{}' already exists in repository '{}'", descriptor.digest, self.repository);
+            return Ok(());
+        }
+        let handle = TokioFile::open(path).await.map_err(|err| OciError::FileReadError{ path: path.to_path_buf(), err })?;
+        self.upload(descriptor, Body::wrap_stream(FramedRead::new(handle, BytesCodec::new()))).await
+    }
+
+    /// Uploads a manifest under the given tag.
+    async fn push_manifest(&self, tag: &str, media_type: &str, bytes: Vec<u8>) -> Result<(), OciError> {
+        let url = self.url(&format!("manifests/{}", tag))?;
+        let request = self.request(Method::PUT, url.clone()).header(CONTENT_TYPE, media_type).body(bytes);
+        check(send(request, &url).await?, &url).await?;
+        Ok(())
+    }
+
+    /// Downloads the manifest with the given tag (or digest).
+    ///
+    /// **Returns**
+    /// The media type and the raw manifest, or an OciError if we could not get it (or it does not match the digest the registry gives for it).
+    async fn pull_manifest(&self, reference: &str) -> Result<(String, Vec<u8>), OciError> {
+        let url = self.url(&format!("manifests/{}", reference))?;
+        let request = self.request(Method::GET, url.clone()).header(ACCEPT, format!("{}, {}", OCI_MANIFEST_MEDIA_TYPE, DOCKER_MANIFEST_MEDIA_TYPE));
+        let response = check(send(request, &url).await?, &url).await?;
+        let header = |name: &str| response.headers().get(name).and_then(|value| value.to_str().ok()).map(|value| value.to_string());
+        let media_type = header(CONTENT_TYPE.as_str()).unwrap_or_else(|| OCI_MANIFEST_MEDIA_TYPE.to_string());
+        let expected = header("docker-content-digest");
+
+        let bytes = read_body(response, &url).await?;
+        if let Some(expected) = expected { verify(&format!("manifest '{}:{}'", self.repository, reference), &expected, &sha256(&bytes))?; }
+        Ok((media_type, bytes))
+    }
+
+    /// Downloads a (small) blob into memory, checking its digest.
+    async fn pull_blob_bytes(&self, descriptor: &Descriptor) -> Result<Vec<u8>, OciError> {
+        let url = self.url(&format!("blobs/{}", descriptor.digest))?;
+        let response = check(send(self.request(Method::GET, url.clone()), &url).await?, &url).await?;
+        let bytes = read_body(response, &url).await?;
+        verify(&format!("blob '{}'", descriptor.digest), &descriptor.digest, &sha256(&bytes))?;
+        Ok(bytes)
+    }
+
+    /// Downloads a blob into the given file, checking its digest.
+    async fn pull_blob_file(&self, descriptor: &Descriptor, path: &Path) -> Result<(), OciError> {
+        let url = self.url(&format!("blobs/{}", descriptor.digest))?;
+        let mut response = check(send(self.request(Method::GET, url.clone()), &url).await?, &url).await?;
+
+        let mut handle = File::create(path).map_err(|err| OciError::FileWriteError{ path: path.to_path_buf(), err })?;
+        let mut hasher = Sha256::new();
+        while let Some(chunk) = response.chunk().await.map_err(|err| OciError::RequestError{ url: url.to_string(), err })? {
+            hasher.update(&chunk);
+            handle.write_all(&chunk).map_err(|err| OciError::FileWriteError{ path: path.to_path_buf(), err })?;
+        }
+        verify(&format!("blob '{}'", descriptor.digest), &descriptor.digest, &format!("sha256:{:x}", hasher.finalize()))
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Pushes a package to the OCI registry: first its image (tagged with the version), then its metadata artifact (tagged with the version plus `.brane`).
+///
+/// **Arguments**
+///  * `client`: The OciClient for the registry to push to.
+///  * `package_dir`: The local directory of the package version, with its package.yml and image.tar.
+///  * `info`: The PackageInfo of the package.
+///
+/// **Returns**
+/// Nothing on success, or an OciError otherwise.
+pub async fn push_package(client: &OciClient, package_dir: &Path, info: &PackageInfo) -> Result<(), OciError> {
+    // Check the image before we bother the registry
+    let image_file = package_dir.join(IMAGE_FILE);
+    let layout = tempfile::tempdir().map_err(|err| OciError::TempDirError{ err })?;
+    let handle = File::open(&image_file).map_err(|err| OciError::FileReadError{ path: image_file.clone(), err })?;
+    tar::Archive::new(handle).unpack(layout.path()).map_err(|err| OciError::ArchiveError{ path: image_file.clone(), err })?;
+
+    let index_file = layout.path().join("index.json");
+    let index: ImageIndex = parse_json(&read_file(&index_file)?, &format!("index.json in '{}'", image_file.display()))?;
+    let descriptor = match index.manifests.as_slice() {
+        [ descriptor ] => descriptor,
+        manifests      => { return Err(OciError::IllegalImageIndex{ path: image_file, got: manifests.len() }); }
+    };
+    if descriptor.media_type != OCI_MANIFEST_MEDIA_TYPE && descriptor.media_type != DOCKER_MANIFEST_MEDIA_TYPE {
+        return Err(OciError::UnsupportedManifest{ what: format!("Image in '{}'", image_file.display()), media_type: descriptor.media_type.clone() });
+    }
+    let raw_manifest = read_file(&blob_path(layout.path(), &descriptor.digest)?)?;
+    let manifest: Manifest = parse_json(&raw_manifest, &format!("image manifest in '{}'", image_file.display()))?;
+    if let Some(digest) = &info.digest {
+        if &manifest.config.digest != digest {
+            return Err(OciError::ImageDigestMismatch{ name: info.name.clone(), version: info.version.clone(), expected: digest.clone(), got: manifest.config.digest });
+        }
+    }
+
+    // Push the image first, so the metadata never refers to an image that isn't there
+    let session = client.session(&info.name, true).await?;
+    let tag = info.version.to_string();
+    for blob in std::iter::once(&manifest.config).chain(manifest.layers.iter()) {
+        session.push_blob_file(blob, &blob_path(layout.path(), &blob.digest)?).await?;
+    }
+    session.push_manifest(&tag, &descriptor.media_type, raw_manifest).await?;
+
+    // Then the metadata artifact
+    let config = serde_json::to_vec(info).map_err(|err| OciError::ManifestEncodeError{ what: String::from("package info"), err })?;
+    let layer = metadata_archive(package_dir)?;
+    let manifest = Manifest {
+        schema_version : 2,
+        media_type     : Some(OCI_MANIFEST_MEDIA_TYPE.to_string()),
+        config         : Descriptor::of(PACKAGE_CONFIG_MEDIA_TYPE, &config),
+        layers         : vec![ Descriptor::of(PACKAGE_LAYER_MEDIA_TYPE, &layer) ],
+    };
+    session.push_blob_bytes(&manifest.config, config).await?;
+    session.push_blob_bytes(&manifest.layers[0], layer).await?;
+    let raw_manifest = serde_json::to_vec(&manifest).map_err(|err| OciError::ManifestEncodeError{ what: String::from("metadata manifest"), err })?;
+    session.push_manifest(&format!("{}{}", tag, PACKAGE_TAG_SUFFIX), OCI_MANIFEST_MEDIA_TYPE, raw_manifest).await
+}
+
+/// Pulls a package from the OCI registry, and reconstructs its package directory (including the image.tar that `brane build` would have written) in the given directory.
+///
+/// Every blob is checked against its digest, and the image against the digest in the package's metadata.
+///
+/// **Arguments**
+///  * `client`: The OciClient for the registry to pull from.
+///  * `name`: The name of the package.
+///  * `version`: The version of the package.
+///  * `dest`: The (existing) directory to write the package to.
+///
+/// **Returns**
+/// The PackageInfo of the pulled package, or an OciError otherwise.
+pub async fn pull_package(client: &OciClient, name: &str, version: &Version, dest: &Path) -> Result<PackageInfo, OciError> {
+    let session = client.session(name, false).await?;
+    let tag = version.to_string();
+
+    // Get the metadata first, since it tells us which image to expect
+    let (manifest, info) = pull_metadata(&session, &tag).await?;
+    let layer = session.pull_blob_bytes(&manifest.layers[0]).await?;
+    tar::Archive::new(GzDecoder::new(layer.as_slice())).unpack(dest).map_err(|err| OciError::ArchiveError{ path: dest.to_path_buf(), err })?;
+
+    // Then the image, which we write as an image layout
+    let (media_type, raw_manifest) = session.pull_manifest(&tag).await?;
+    if media_type != OCI_MANIFEST_MEDIA_TYPE && media_type != DOCKER_MANIFEST_MEDIA_TYPE {
+        return Err(OciError::UnsupportedManifest{ what: format!("Image '{}:{}'", session.repository, tag), media_type });
+    }
+    let manifest: Manifest = parse_json(&raw_manifest, &format!("manifest '{}:{}'", session.repository, tag))?;
+    if let Some(digest) = &info.digest {
+        if &manifest.config.digest != digest {
+            return Err(OciError::ImageDigestMismatch{ name: info.name.clone(), version: info.version.clone(), expected: digest.clone(), got: manifest.config.digest });
+        }
+    }
+
+    let layout = tempfile::tempdir().map_err(|err| OciError::TempDirError{ err })?;
+    let blobs_dir = layout.path().join("blobs").join("sha256");
+    fs::create_dir_all(&blobs_dir).map_err(|err| OciError::FileWriteError{ path: blobs_dir.clone(), err })?;
+    for blob in std::iter::once(&manifest.config).chain(manifest.layers.iter()) {
+        session.pull_blob_file(blob, &blob_path(layout.path(), &blob.digest)?).await?;
+    }
+    let mut descriptor = Descriptor::of(&media_type, &raw_manifest);
+    write_file(&blob_path(layout.path(), &descriptor.digest)?, &raw_manifest)?;
+
+    let image_name = format!("{}:{}", info.name, info.version);
+    descriptor.annotations = Some(vec![
+        (String::from("io.containerd.image.name"), image_name.clone()),
+        (String::from("org.opencontainers.image.ref.name"), tag),
+    ].into_iter().collect());
+    let index = ImageIndex{ schema_version: 2, media_type: Some(OCI_INDEX_MEDIA_TYPE.to_string()), manifests: vec![ descriptor ] };
+    let docker_manifest = vec![ DockerManifestEntry {
+        config    : format!("blobs/sha256/{}", hex_digest(&manifest.config.digest)?),
+        repo_tags : vec![ image_name ],
+        layers    : manifest.layers.iter().map(|layer| Ok(format!("blobs/sha256/{}", hex_digest(&layer.digest)?))).collect::<Result<_, OciError>>()?,
+    } ];
+    write_json(&layout.path().join("index.json"), &index)?;
+    write_json(&layout.path().join("manifest.json"), &docker_manifest)?;
+    write_file(&layout.path().join("oci-layout"), b"{\"imageLayoutVersion\":\"1.0.0\"}")?;
+
+    let image_file = dest.join(IMAGE_FILE);
+    let handle = File::create(&image_file).map_err(|err| OciError::FileWriteError{ path: image_file.clone(), err })?;
+    let mut archive = tar::Builder::new(handle);
+    for file in [ "oci-layout", "index.json", "manifest.json" ] {
+        archive.append_path_with_name(layout.path().join(file), file).map_err(|err| OciError::ArchiveError{ path: image_file.clone(), err })?;
+    }
+    archive.append_dir_all("blobs", layout.path().join("blobs")).map_err(|err| OciError::ArchiveError{ path: image_file.clone(), err })?;
+    archive.into_inner().map_err(|err| OciError::ArchiveError{ path: image_file.clone(), err })?;
+
+    Ok(info)
+}
+
+/// Returns the metadata of a package in the OCI registry, without pulling the rest of it.
+///
+/// **Arguments**
+///  * `client`: The OciClient for the registry to look in.
+///  * `name`: The name of the package.
+///  * `version`: The version of the package.
+///
+/// **Returns**
+/// The PackageInfo of the package, or an OciError if it could not be retrieved.
+pub async fn package_info(client: &OciClient, name: &str, version: &Version) -> Result<PackageInfo, OciError> {
+    let session = client.session(name, false).await?;
+    Ok(pull_metadata(&session, &version.to_string()).await?.1)
+}
+
+/// Returns the versions of the given package in the OCI registry.
+///
+/// **Arguments**
+///  * `client`: The OciClient for the registry to look in.
+///  * `name`: The name of the package.
+///
+/// **Returns**
+/// The versions that have a metadata artifact (in no particular order), or an OciError if we could not list them.
+pub async fn package_versions(client: &OciClient, name: &str) -> Result<Vec<Version>, OciError> {
+    let session = client.session(name, false).await?;
+    Ok(session.tags().await?.into_iter().filter_map(|tag| {
+        tag.strip_suffix(PACKAGE_TAG_SUFFIX).and_then(|version| Version::from_str(version).ok())
+    }).collect())
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Pulls the manifest and the PackageInfo of the metadata artifact of the given version of a package.
+async fn pull_metadata(session: &OciSession<'_>, tag: &str) -> Result<(Manifest, PackageInfo), OciError> {
+    let reference = format!("{}{}", tag, PACKAGE_TAG_SUFFIX);
+    let (_, raw_manifest) = session.pull_manifest(&reference).await?;
+    let manifest: Manifest = parse_json(&raw_manifest, &format!("manifest '{}:{}'", session.repository, reference))?;
+    if manifest.config.media_type != PACKAGE_CONFIG_MEDIA_TYPE || manifest.layers.len() != 1 {
+        return Err(OciError::NotAPackage{ repository: session.repository.clone(), reference });
+    }
+    let info: PackageInfo = parse_json(&session.pull_blob_bytes(&manifest.config).await?, &format!("package info of '{}:{}'", session.repository, reference))?;
+    Ok((manifest, info))
+}
+
+/// Sends a request, turning connection failures into OciErrors.
+async fn send(request: RequestBuilder, url: &Url) -> Result<Response, OciError> {
+    match request.send().await {
+        Ok(response) => Ok(response),
+        Err(err)     => match proxy::as_proxy_error(err) {
+            Ok(err)  => Err(OciError::ProxyError{ err }),
+            Err(err) => Err(OciError::RequestError{ url: url.to_string(), err }),
+        },
+    }
+}
+
+/// Turns an unsuccessful response into an OciError.
+async fn check(response: Response, url: &Url) -> Result<Response, OciError> {
+    if response.status().is_success() { return Ok(response); }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(OciError::RequestFailure{ url: url.to_string(), status, body: body.trim().to_string() })
+}
+
+/// Reads the whole body of a response.
+async fn read_body(response: Response, url: &Url) -> Result<Vec<u8>, OciError> {
+    match response.bytes().await {
+        Ok(bytes) => Ok(bytes.to_vec()),
+        Err(err)  => Err(OciError::RequestError{ url: url.to_string(), err }),
+    }
+}
+
+/// Parses the given JSON, naming it `what` if it's broken.
+fn parse_json<T: DeserializeOwned>(bytes: &[u8], what: &str) -> Result<T, OciError> {
+    serde_json::from_slice(bytes).map_err(|err| OciError::ManifestParseError{ what: what.to_string(), err })
+}
+
+/// Returns the sha256 digest of the given bytes, as `sha256:<hex>`.
+fn sha256(bytes: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(bytes))
+}
+
+/// Checks that a digest is the one we expected.
+fn verify(what: &str, expected: &str, got: &str) -> Result<(), OciError> {
+    if expected == got { return Ok(()); }
+    Err(OciError::DigestMismatch{ what: what.to_string(), expected: expected.to_string(), got: got.to_string() })
+}
+
+/// Returns the hex part of a sha256 digest.
+fn hex_digest(digest: &str) -> Result<&str, OciError> {
+    match digest.strip_prefix("sha256:") {
+        Some(hex) if !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) => Ok(hex),
+        _ => Err(OciError::UnsupportedDigest{ digest: digest.to_string() }),
+    }
+}
+
+/// Returns the path of the blob with the given digest in an image layout.
+fn blob_path(layout: &Path, digest: &str) -> Result<PathBuf, OciError> {
+    Ok(layout.join("blobs").join("sha256").join(hex_digest(digest)?))
+}
+
+/// Reads a whole file.
+fn read_file(path: &Path) -> Result<Vec<u8>, OciError> {
+    fs::read(path).map_err(|err| OciError::FileReadError{ path: path.to_path_buf(), err })
+}
+
+/// Writes a whole file.
+fn write_file(path: &Path, contents: &[u8]) -> Result<(), OciError> {
+    fs::write(path, contents).map_err(|err| OciError::FileWriteError{ path: path.to_path_buf(), err })
+}
+
+/// Writes the given value to a file as JSON.
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), OciError> {
+    let contents = serde_json::to_vec(value).map_err(|err| OciError::ManifestEncodeError{ what: path.display().to_string(), err })?;
+    write_file(path, &contents)
+}
+
+/// Packs the package directory, without its image, into a gzipped tarball.
+fn metadata_archive(package_dir: &Path) -> Result<Vec<u8>, OciError> {
+    let archive_err = |err| OciError::ArchiveError{ path: package_dir.to_path_buf(), err };
+    let mut entries: Vec<_> = fs::read_dir(package_dir).map_err(archive_err)?.collect::<Result<_, _>>().map_err(archive_err)?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut archive = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+    for entry in entries {
+        let name = entry.file_name();
+        if name == IMAGE_FILE { continue; }
+        if entry.path().is_dir() {
+            archive.append_dir_all(&name, entry.path()).map_err(archive_err)?;
+        } else {
+            archive.append_path_with_name(entry.path(), &name).map_err(archive_err)?;
+        }
+    }
+    archive.into_inner().and_then(|gz| gz.finish()).map_err(archive_err)
+}
@@ -0,0 +1,392 @@
+/* OIDC.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 21:52:40
+ * Last edited:
+ *   15 Oct 2026, 21:52:40
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Logs into registries that are fronted by an OpenID Connect provider
+ *   (e.g., Keycloak) with the OAuth 2.0 device authorization grant, and
+ *   refreshes the access token we got that way when it expires.
+**/
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::time::Duration;
+
+use chrono::Utc;
+use clap::Args;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use url::Url;
+
+use specifications::registry::OidcSession;
+
+use crate::credentials::{CredentialError, CredentialManager, Credentials};
+use crate::proxy;
+
+
+/***** CONSTANTS *****/
+/// The client ID we log in with if the user doesn't give one.
+pub const DEFAULT_CLIENT_ID: &str = "brane-cli";
+/// The scopes we ask for; `offline_access` gets us a refresh token.
+const SCOPES: &str = "openid offline_access";
+/// The grant type with which we poll for the token of a device authorization.
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+/// The number of seconds between polls if the provider doesn't say.
+const DEFAULT_INTERVAL: u64 = 5;
+/// The number of seconds that the provider asks us to add to the interval when we poll too fast.
+const SLOW_DOWN_INCREMENT: u64 = 5;
+/// The number of seconds before its actual expiry that we consider an access token expired, to account for clock skew and slow requests.
+const EXPIRY_MARGIN: i64 = 30;
+
+
+
+
+
+/***** ERRORS *****/
+/// Collects errors that relate to logging in with OpenID Connect.
+#[derive(Debug)]
+pub enum OidcError {
+    /// A URL is not a valid URL
+    IllegalUrl{ raw: String, err: url::ParseError },
+    /// Could not create the HTTP client
+    ClientError{ err: reqwest::Error },
+    /// Could not send a request to the provider
+    RequestError{ url: String, err: reqwest::Error },
+    /// The provider rejected a request without telling us why in OAuth terms
+    RequestFailure{ url: String, status: StatusCode, body: String },
+    /// The provider's answer could not be parsed
+    ResponseParseError{ url: String, err: serde_json::Error },
+    /// The provider does not support the device authorization grant
+    NoDeviceFlow{ issuer: String },
+    /// The provider returned an OAuth error
+    TokenError{ url: String, error: String, description: Option<String> },
+    /// The user denied the login
+    AccessDenied,
+    /// The user didn't log in before the device code expired
+    DeviceCodeExpired,
+
+    /// The access token expired and we can't get a new one
+    LoginExpired{ url: String },
+    /// Could not read or store the credentials
+    CredentialError{ err: CredentialError },
+}
+
+impl Display for OidcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        use OidcError::*;
+        match self {
+            IllegalUrl{ raw, err }                 => write!(f, "'{}' is not a valid URL: {}", raw, err),
+            ClientError{ err }                     => write!(f, "Could not create HTTP client: {}", err),
+            RequestError{ url, err }               => write!(f, "Could not send request to '{}': {}", url, err),
+            RequestFailure{ url, status, body }    => write!(f, "Request to '{}' failed with status {}{}", url, status, if body.is_empty() { String::new() } else { format!(": {}", body) }),
+            ResponseParseError{ url, err }         => write!(f, "Could not parse the answer of '{}': {}", url, err),
+            NoDeviceFlow{ issuer }                 => write!(f, "OpenID Connect provider '{}' does not support logging in with a device code", issuer),
+            TokenError{ url, error, description }  => write!(f, "OpenID Connect provider at '{}' returned error '{}'{}", url, error, description.as_ref().map(|description| format!(": {}", description)).unwrap_or_default()),
+            AccessDenied                           => write!(f, "The login was denied"),
+            DeviceCodeExpired                      => write!(f, "The login code expired before it was used; please run 'brane login' again"),
+
+            LoginExpired{ url }    => write!(f, "Your login to registry '{}' has expired; please run 'brane login' again", url),
+            CredentialError{ err } => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for OidcError {}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// The options with which `brane login` logs in through OpenID Connect.
+#[derive(Args, Clone, Debug, Default)]
+pub struct OidcOptions {
+    /// Whether to log in through OpenID Connect at all
+    #[clap(long, conflicts_with = "token", help = "Log in through the OpenID Connect provider (e.g., Keycloak) in front of the registry, with a code to enter in the browser")]
+    pub oidc      : bool,
+    /// The issuer to log in with, if not the registry itself
+    #[clap(long, requires = "oidc", help = "URL of the OpenID Connect provider (e.g., 'https://keycloak.example.com/realms/brane'), if its metadata is not served by the registry itself")]
+    pub issuer    : Option<String>,
+    /// The client ID to log in with
+    #[clap(long, requires = "oidc", default_value = DEFAULT_CLIENT_ID, help = "Client ID to log in to the OpenID Connect provider with")]
+    pub client_id : String,
+}
+
+
+
+/// The parts of the metadata of an OpenID Connect provider (as served at `/.well-known/openid-configuration`) that we use.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ProviderMetadata {
+    /// The URL that identifies the provider.
+    pub issuer                        : String,
+    /// The endpoint at which we start a device authorization, if the provider supports it.
+    pub device_authorization_endpoint : Option<String>,
+    /// The endpoint at which we get tokens.
+    pub token_endpoint                : String,
+}
+
+/// The answer of the provider when we start a device authorization.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct DeviceAuthorization {
+    /// The code with which we poll for the token.
+    pub device_code               : String,
+    /// The code that the user has to enter.
+    pub user_code                 : String,
+    /// The page where the user has to enter it.
+    #[serde(alias = "verification_url")]
+    pub verification_uri          : String,
+    /// The same page with the code already filled in, if the provider has one.
+    pub verification_uri_complete : Option<String>,
+    /// The number of seconds before the codes expire.
+    pub expires_in                : u64,
+    /// The number of seconds to wait between polls.
+    pub interval                  : Option<u64>,
+}
+
+/// The tokens that the provider gives us.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct TokenSet {
+    /// The token to send along with requests to the registry.
+    pub access_token  : String,
+    /// The token with which we get a new access token, if any.
+    pub refresh_token : Option<String>,
+    /// The number of seconds before the access token expires, if the provider says.
+    pub expires_in    : Option<u64>,
+}
+
+impl TokenSet {
+    /// Turns the tokens into the credentials we store.
+    /// 
+    /// **Arguments**
+    ///  * `username`: The username with which we sign packages.
+    ///  * `token_endpoint`: The endpoint at which to refresh the tokens.
+    ///  * `client_id`: The client ID with which we logged in.
+    ///  * `previous`: The refresh token we had before, which we keep if the provider didn't give us a new one.
+    ///  * `now`: The current time, as seconds since the Unix epoch.
+    pub fn into_credentials(self, username: String, token_endpoint: String, client_id: String, previous: Option<String>, now: i64) -> Credentials {
+        Credentials {
+            username,
+            token : Some(self.access_token),
+            oidc  : Some(OidcSession {
+                token_endpoint,
+                client_id,
+                refresh_token : self.refresh_token.or(previous),
+                expires_at    : self.expires_in.map(|expires_in| now + expires_in as i64),
+            }),
+        }
+    }
+}
+
+/// What the provider said when we polled for the token of a device authorization.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenPoll {
+    /// The user logged in, and these are the tokens.
+    Token(TokenSet),
+    /// The user didn't log in yet.
+    Pending,
+    /// The user didn't log in yet, and we're polling too fast.
+    SlowDown,
+}
+
+/// An OAuth error answer.
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    /// The error code (e.g., 'authorization_pending').
+    error             : String,
+    /// A human-readable description, if any.
+    error_description : Option<String>,
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Returns the URL at which the given issuer serves its metadata.
+/// 
+/// **Arguments**
+///  * `issuer`: The URL of the issuer (e.g., 'https://keycloak.example.com/realms/brane').
+/// 
+/// **Returns**  
+/// The URL of its `/.well-known/openid-configuration`, or an OidcError if the issuer is not a valid URL.
+pub fn discovery_url(issuer: &str) -> Result<Url, OidcError> {
+    let raw = format!("{}/.well-known/openid-configuration", issuer.trim_end_matches('/'));
+    Url::parse(&raw).map_err(|err| OidcError::IllegalUrl{ raw, err })
+}
+
+/// Fetches the metadata of the given OpenID Connect provider.
+/// 
+/// **Arguments**
+///  * `client`: The HTTP client to send the request with.
+///  * `issuer`: The URL of the issuer.
+/// 
+/// **Returns**  
+/// The ProviderMetadata, or an OidcError if we could not get it.
+pub async fn discover(client: &Client, issuer: &str) -> Result<ProviderMetadata, OidcError> {
+    let url = discovery_url(issuer)?;
+    let response = client.get(url.clone()).send().await.map_err(|err| OidcError::RequestError{ url: url.to_string(), err })?;
+    let status = response.status();
+    let body = response.bytes().await.map_err(|err| OidcError::RequestError{ url: url.to_string(), err })?;
+    if !status.is_success() { return Err(OidcError::RequestFailure{ url: url.to_string(), status, body: String::from_utf8_lossy(&body).to_string() }); }
+    serde_json::from_slice(&body).map_err(|err| OidcError::ResponseParseError{ url: url.to_string(), err })
+}
+
+/// Starts a device authorization at the given provider.
+/// 
+/// **Arguments**
+///  * `client`: The HTTP client to send the request with.
+///  * `metadata`: The metadata of the provider.
+///  * `client_id`: The client ID to log in with.
+/// 
+/// **Returns**  
+/// The codes to show the user and to poll with, or an OidcError if the provider refused.
+pub async fn authorize_device(client: &Client, metadata: &ProviderMetadata, client_id: &str) -> Result<DeviceAuthorization, OidcError> {
+    let url = match &metadata.device_authorization_endpoint {
+        Some(url) => url,
+        None      => { return Err(OidcError::NoDeviceFlow{ issuer: metadata.issuer.clone() }); }
+    };
+    let response = client.post(url).form(&[ ("client_id", client_id), ("scope", SCOPES) ]).send().await.map_err(|err| OidcError::RequestError{ url: url.clone(), err })?;
+    let status = response.status();
+    let body = response.bytes().await.map_err(|err| OidcError::RequestError{ url: url.clone(), err })?;
+    if !status.is_success() {
+        return Err(match serde_json::from_slice::<ErrorResponse>(&body) {
+            Ok(error) => OidcError::TokenError{ url: url.clone(), error: error.error, description: error.error_description },
+            Err(_)    => OidcError::RequestFailure{ url: url.clone(), status, body: String::from_utf8_lossy(&body).to_string() },
+        });
+    }
+    serde_json::from_slice(&body).map_err(|err| OidcError::ResponseParseError{ url: url.clone(), err })
+}
+
+/// Interprets the answer of a token endpoint.
+/// 
+/// **Arguments**
+///  * `url`: The URL of the token endpoint (for errors).
+///  * `status`: The status code of the answer.
+///  * `body`: The body of the answer.
+/// 
+/// **Returns**  
+/// Whether we got the tokens or should keep polling, or an OidcError if the login failed.
+pub fn parse_token_response(url: &str, status: StatusCode, body: &[u8]) -> Result<TokenPoll, OidcError> {
+    if status.is_success() {
+        return serde_json::from_slice(body).map(TokenPoll::Token).map_err(|err| OidcError::ResponseParseError{ url: url.to_string(), err });
+    }
+
+    let error: ErrorResponse = match serde_json::from_slice(body) {
+        Ok(error) => error,
+        Err(_)    => { return Err(OidcError::RequestFailure{ url: url.to_string(), status, body: String::from_utf8_lossy(body).to_string() }); }
+    };
+    match error.error.as_str() {
+        "authorization_pending" => Ok(TokenPoll::Pending),
+        "slow_down"             => Ok(TokenPoll::SlowDown),
+        "access_denied"         => Err(OidcError::AccessDenied),
+        "expired_token"         => Err(OidcError::DeviceCodeExpired),
+        _                       => Err(OidcError::TokenError{ url: url.to_string(), error: error.error, description: error.error_description }),
+    }
+}
+
+/// Polls the token endpoint of the provider until the user has logged in with the given device authorization.
+/// 
+/// **Arguments**
+///  * `client`: The HTTP client to send the requests with.
+///  * `token_endpoint`: The token endpoint of the provider.
+///  * `client_id`: The client ID to log in with.
+///  * `authorization`: The device authorization that the user is logging in with.
+/// 
+/// **Returns**  
+/// The tokens, or an OidcError if the login failed or the codes expired.
+pub async fn poll_token(client: &Client, token_endpoint: &str, client_id: &str, authorization: &DeviceAuthorization) -> Result<TokenSet, OidcError> {
+    let deadline = Utc::now().timestamp() + authorization.expires_in as i64;
+    let mut interval = authorization.interval.unwrap_or(DEFAULT_INTERVAL);
+    loop {
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        if Utc::now().timestamp() > deadline { return Err(OidcError::DeviceCodeExpired); }
+
+        let form = [ ("grant_type", DEVICE_CODE_GRANT), ("device_code", authorization.device_code.as_str()), ("client_id", client_id) ];
+        let response = client.post(token_endpoint).form(&form).send().await.map_err(|err| OidcError::RequestError{ url: token_endpoint.to_string(), err })?;
+        let status = response.status();
+        let body = response.bytes().await.map_err(|err| OidcError::RequestError{ url: token_endpoint.to_string(), err })?;
+        match parse_token_response(token_endpoint, status, &body)? {
+            TokenPoll::Token(tokens) => { return Ok(tokens); },
+            TokenPoll::Pending       => { debug!("Waiting for the user to log in..."); },
+            TokenPoll::SlowDown      => { interval += SLOW_DOWN_INCREMENT; },
+        }
+    }
+}
+
+/// Returns whether the access token of the given session has expired (or is about to).
+/// 
+/// **Arguments**
+///  * `session`: The session to check.
+///  * `now`: The current time, as seconds since the Unix epoch.
+#[inline]
+pub fn is_expired(session: &OidcSession, now: i64) -> bool {
+    session.expires_at.map(|expires_at| now >= expires_at - EXPIRY_MARGIN).unwrap_or(false)
+}
+
+/// Logs in with the device authorization grant: starts a device authorization, tells the user where to enter the code and waits until they did.
+/// 
+/// **Arguments**
+///  * `issuer`: The URL of the issuer.
+///  * `client_id`: The client ID to log in with.
+///  * `username`: The username with which we sign packages.
+/// 
+/// **Returns**  
+/// The credentials to store, or an OidcError if the login failed.
+pub async fn login(issuer: &str, client_id: &str, username: String) -> Result<Credentials, OidcError> {
+    let client = proxy::client_builder().build().map_err(|err| OidcError::ClientError{ err })?;
+    let metadata = discover(&client, issuer).await?;
+    let authorization = authorize_device(&client, &metadata, client_id).await?;
+
+    match &authorization.verification_uri_complete {
+        Some(url) => println!("To log in, visit {} (or visit {} and enter code {}).", url, authorization.verification_uri, authorization.user_code),
+        None      => println!("To log in, visit {} and enter code {}.", authorization.verification_uri, authorization.user_code),
+    }
+    println!("Waiting for you to log in...");
+
+    let tokens = poll_token(&client, &metadata.token_endpoint, client_id, &authorization).await?;
+    if tokens.refresh_token.is_none() { warn!("The OpenID Connect provider did not give us a refresh token; you will have to log in again when the access token expires"); }
+    Ok(tokens.into_credentials(username, metadata.token_endpoint, client_id.to_string(), None, Utc::now().timestamp()))
+}
+
+/// Gets a new access token for the registry we're logged into with its refresh token, and stores it.
+/// 
+/// **Arguments**
+///  * `manager`: The CredentialManager that holds the credentials.
+///  * `url`: The URL of the registry (for errors).
+///  * `credentials`: The current credentials, which must have come from OpenID Connect.
+/// 
+/// **Returns**  
+/// The new credentials, or an OidcError (i.e., LoginExpired if we have no refresh token or the provider doesn't accept it anymore).
+pub async fn refresh(manager: &CredentialManager, url: &str, credentials: Credentials) -> Result<Credentials, OidcError> {
+    let session = match credentials.oidc {
+        Some(session) => session,
+        None          => { return Err(OidcError::LoginExpired{ url: url.to_string() }); }
+    };
+    let refresh_token = match &session.refresh_token {
+        Some(refresh_token) => refresh_token,
+        None                => { return Err(OidcError::LoginExpired{ url: url.to_string() }); }
+    };
+    debug!("Refreshing the access token for registry '{}'...", url);
+
+    let client = proxy::client_builder().build().map_err(|err| OidcError::ClientError{ err })?;
+    let form = [ ("grant_type", "refresh_token"), ("refresh_token", refresh_token.as_str()), ("client_id", session.client_id.as_str()) ];
+    let response = client.post(&session.token_endpoint).form(&form).send().await.map_err(|err| OidcError::RequestError{ url: session.token_endpoint.clone(), err })?;
+    let status = response.status();
+    let body = response.bytes().await.map_err(|err| OidcError::RequestError{ url: session.token_endpoint.clone(), err })?;
+    let tokens = match parse_token_response(&session.token_endpoint, status, &body) {
+        Ok(TokenPoll::Token(tokens)) => tokens,
+        // The provider no longer accepts the refresh token (e.g., because the session ended)
+        Ok(_) | Err(OidcError::TokenError{ .. }) => { return Err(OidcError::LoginExpired{ url: url.to_string() }); },
+        Err(err)                     => { return Err(err); },
+    };
+
+    let credentials = tokens.into_credentials(credentials.username, session.token_endpoint.clone(), session.client_id.clone(), session.refresh_token.clone(), Utc::now().timestamp());
+    manager.update(&credentials).map_err(|err| OidcError::CredentialError{ err })?;
+    Ok(credentials)
+}
@@ -1,16 +1,17 @@
+use std::collections::HashSet;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;use anyhow::Result;
 
 use bollard::errors::Error;
 use bollard::image::ImportImageOptions;
 use bollard::image::TagImageOptions;
 use bollard::models::BuildInfo;
-use bollard::Docker;
 use chrono::Utc;
 use console::{pad_str, Alignment};
 use dialoguer::Confirm;
 use fs_extra::dir;
+use futures::future::{FutureExt, LocalBoxFuture};
 use futures_util::stream::TryStreamExt;
 use hyper::Body;
 use indicatif::{DecimalBytes, HumanDuration};
@@ -21,12 +22,17 @@ use tokio::fs::File as TFile;
 use tokio_stream::StreamExt;
 use tokio_util::codec::{BytesCodec, FramedRead};
 
+use specifications::common::{Function, Type};
 use specifications::package::{PackageIndex, PackageInfo, PackageInfoError, PackageIndexError};
 use specifications::version::Version;
 
 use crate::docker;
-use crate::errors::UtilError;
-use crate::utils::{ensure_packages_dir, ensure_package_dir, get_package_versions};
+use crate::errors::{OfflineError, UtilError};
+use crate::index_cache::{self, CacheEntry, Fingerprint, IndexCache};
+use crate::lock::PackageLock;
+use crate::registry;
+use crate::runtime;
+use crate::utils::{ensure_packages_dir, ensure_package_dir, get_index_cache_file, get_package_dir, get_package_versions};
 
 
 /* TIM */
@@ -63,6 +69,20 @@ impl std::error::Error for PackageError {}
 
 
 
+/***** LIBRARY STRUCTS *****/
+/// Reports how the package index was built.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IndexStats {
+    /// The number of package versions taken from the index cache.
+    pub cached : usize,
+    /// The number of package versions whose package.yml was parsed.
+    pub parsed : usize,
+}
+
+
+
+
+
 /***** HELPER FUNCTIONS *****/
 /// Inserts a PackageInfo in a list of PackageInfos such that it tries to only have the latest version of each package.
 /// 
@@ -91,30 +111,146 @@ fn insert_package_in_list(infos: &mut Vec<PackageInfo>, info: PackageInfo) {
 }
 /*******/
 
+/// Prints the given PackageInfo, including the descriptions of its functions, parameters and types.
+/// 
+/// **Arguments**
+///  * `info`: The PackageInfo to print.
+///  * `json`: Whether to print it as JSON instead of as tables.
+/// 
+/// **Returns**  
+/// Nothing on success, or an anyhow error if the PackageInfo could not be serialized.
+fn print_package_info(info: &PackageInfo, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(info)?);
+        return Ok(());
+    }
+
+    println!("Name:        {}", info.name);
+    println!("Version:     {}", info.version);
+    println!("Kind:        {}", info.kind);
+    println!("Owners:      {}", info.owners.join(", "));
+    println!("Description: {}", info.description);
+    println!("Digest:      {}", info.digest.as_deref().unwrap_or("<none>"));
+
+    let format = FormatBuilder::new()
+        .column_separator('\0')
+        .borders('\0')
+        .padding(1, 1)
+        .build();
+
+    // Show the functions, with a row for every parameter below them
+    let mut functions: Vec<(&String, &Function)> = info.functions.iter().collect();
+    functions.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+    let mut table = Table::new();
+    table.set_format(format);
+    table.add_row(row!["FUNCTION", "TYPE", "DESCRIPTION"]);
+    for (name, function) in functions {
+        table.add_row(row![name, function.return_type, function.description.as_deref().unwrap_or_default()]);
+        for parameter in &function.parameters {
+            table.add_row(row![format!("  {}", parameter.name), parameter.data_type, parameter.description.as_deref().unwrap_or_default()]);
+        }
+    }
+    println!();
+    table.printstd();
+
+    // Show the types, with a row for every property below them
+    if !info.types.is_empty() {
+        let mut types: Vec<(&String, &Type)> = info.types.iter().collect();
+        types.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+        let mut table = Table::new();
+        table.set_format(format);
+        table.add_row(row!["TYPE", "", "DESCRIPTION"]);
+        for (name, data_type) in types {
+            table.add_row(row![name, "", data_type.description.as_deref().unwrap_or_default()]);
+            for property in &data_type.properties {
+                table.add_row(row![format!("  {}", property.name), property.data_type, property.description.as_deref().unwrap_or_default()]);
+            }
+        }
+        println!();
+        table.printstd();
+    }
+
+    Ok(())
+}
+
+/// Compares the digest of a local package with that of the same version in the registry.
+/// 
+/// **Arguments**
+///  * `version`: The version of the package.
+///  * `local`: The digest of the local package, if any.
+///  * `remote`: The digest of the package in the registry, if any.
+/// 
+/// **Returns**  
+/// A note telling the user that the local package differs from the one in the registry, or None if they are the same.
+pub fn digest_mismatch(version: &Version, local: Option<&str>, remote: Option<&str>) -> Option<String> {
+    if local == remote { return None; }
+    Some(format!("Note: local version {} has digest {}, but the registry has {}.", version, local.unwrap_or("<none>"), remote.unwrap_or("<none>")))
+}
+
 /* TIM */
-/// **Edited: Changed to return PackageErrors.**
+/// **Edited: Changed to return PackageErrors. Now using the index cache.**
 ///
 /// Returns the an index of available packages and their versions.
 /// 
 /// **Returns**  
 /// A PackageIndex if we could retrieve it, or a PackageError if we failed.
+#[inline]
 pub fn get_package_index() -> Result<PackageIndex, PackageError> {
+    read_package_index(false)
+}
+/*******/
+
+/// Returns the index of available packages and their versions, optionally ignoring the index cache.
+/// 
+/// **Arguments**
+///  * `rebuild`: If true, parses every package.yml again (and replaces the cache with the result).
+/// 
+/// **Returns**  
+/// A PackageIndex if we could retrieve it, or a PackageError if we failed.
+fn read_package_index(rebuild: bool) -> Result<PackageIndex, PackageError> {
     // Try to get the generic packages dir (which is guaranteed to exist)
     let packages_dir = match ensure_packages_dir(false) {
         Ok(packages_dir) => packages_dir,
         Err(err)         => { return Err(PackageError::UtilError{ err }); }
     };
+    // Without a home directory, we simply do without the cache
+    let cache_file = match get_index_cache_file() {
+        Ok(cache_file) => Some(cache_file),
+        Err(err)       => { debug!("Not using the index cache: {}", err); None },
+    };
+
+    let (index, stats) = load_package_index(&packages_dir, cache_file.as_deref(), rebuild)?;
+    debug!("Loaded package index ({} package version(s) from cache, {} parsed)", stats.cached, stats.parsed);
+    Ok(index)
+}
+
+/// Builds the index of the packages in the given directory, taking the package.yml files that didn't change from the given index cache.
+/// 
+/// **Arguments**
+///  * `packages_dir`: The directory with the packages (see `ensure_packages_dir()`).
+///  * `cache_file`: The index cache to use (and update), if any.
+///  * `rebuild`: If true, ignores what is in the cache and parses every package.yml again.
+/// 
+/// **Returns**  
+/// The PackageIndex and how many of its packages came from the cache, or a PackageError if we failed.
+pub fn load_package_index(packages_dir: &Path, cache_file: Option<&Path>, rebuild: bool) -> Result<(PackageIndex, IndexStats), PackageError> {
+    let old_cache = match (cache_file, rebuild) {
+        (Some(cache_file), false) => IndexCache::load(cache_file),
+        _                         => IndexCache::default(),
+    };
+    let mut new_cache = IndexCache::default();
+    let mut stats = IndexStats::default();
 
     // Open an iterator to the list of files
     let package_dirs = match fs::read_dir(&packages_dir) {
         Ok(dir)  => dir,
-        Err(err) => { return Err(PackageError::PackagesDirReadError{ path: packages_dir, err }); }
+        Err(err) => { return Err(PackageError::PackagesDirReadError{ path: packages_dir.to_path_buf(), err }); }
     };
 
     // Start iterating through all the packages
     let mut packages = vec![];
     for package in package_dirs {
-        if let Err(reason) = package { return Err(PackageError::PackagesDirReadError{ path: packages_dir, err: reason }); }
+        if let Err(reason) = package { return Err(PackageError::PackagesDirReadError{ path: packages_dir.to_path_buf(), err: reason }); }
         let package = package.unwrap();
 
         // Make sure it's a directory
@@ -130,41 +266,89 @@ pub fn get_package_index() -> Result<PackageIndex, PackageError> {
         for version in versions {
             // Get the path of this version
             let version_path = package_path.join(version.to_string());
+            let key = index_cache::key(&package_name, &version);
+
+            // Use the cached package info if the directory didn't change since
+            let fingerprint = match Fingerprint::of(&version_path) {
+                Ok(fingerprint) => Some(fingerprint),
+                Err(err)        => { debug!("Could not fingerprint '{}' (not caching it): {}", version_path.display(), err); None },
+            };
+            if let Some(info) = fingerprint.as_ref().and_then(|fingerprint| old_cache.get(&key, fingerprint)) {
+                stats.cached += 1;
+                new_cache.entries.insert(key, CacheEntry{ fingerprint: fingerprint.unwrap(), info: info.clone() });
+                packages.push(info.clone());
+                continue;
+            }
 
             // Try to read the propery package info
             let package_file = version_path.join("package.yml");
             match PackageInfo::from_path(package_file.clone()) {
-                Ok(package_info) => { packages.push(package_info); }
+                Ok(package_info) => {
+                    stats.parsed += 1;
+                    if let Some(fingerprint) = fingerprint { new_cache.entries.insert(key, CacheEntry{ fingerprint, info: package_info.clone() }); }
+                    packages.push(package_info);
+                }
                 Err(err)         => { return Err(PackageError::InvalidPackageYml{ package: package_name.to_string(), path: package_file, err }); }
             }
         }
     }
 
+    // Only write the cache back if anything changed (including packages that have disappeared)
+    if let Some(cache_file) = cache_file {
+        if stats.parsed > 0 || new_cache.entries.len() != old_cache.entries.len() || !cache_file.exists() {
+            if let Err(err) = new_cache.store(cache_file) { warn!("{}", err); }
+        }
+    }
+
     // Generate the package index from the collected list of packages
     match PackageIndex::from_value(json!(packages)) {
-        Ok(index) => Ok(index),
+        Ok(index) => Ok((index, stats)),
         Err(err)  => Err(PackageError::PackageIndexError{ err }),
     }
 }
-/*******/
 
 
 
 
 
 /***** SUBCOMMANDS *****/
-///
-///
-///
-pub fn inspect(
+/// **Edited: can now also inspect packages in the registry, and print them as JSON.**
+/// 
+/// Prints the information of a local package, or of a package in the registry without pulling it.
+/// 
+/// **Arguments**
+///  * `name`: The name of the package.
+///  * `version`: The version of the package. If it's 'latest', the latest local (or remote) version is used.
+///  * `remote`: If true, asks the registry for the package's information instead of reading it locally.
+///  * `json`: If true, prints the package's information as JSON instead of as tables.
+/// 
+/// **Returns**  
+/// Nothing other than prints on stdout if successfull, or an anyhow error otherwise.
+pub async fn inspect(
     name: String,
     version: Version,
+    remote: bool,
+    json: bool,
 ) -> Result<()> {
+    if remote {
+        let package_info = registry::remote_package_info(&name, &version).await?;
+        if json { return print_package_info(&package_info, true); }
+        println!("Remote package (from the registry, not pulled):");
+        print_package_info(&package_info, false)?;
+
+        // Point out if what we have locally is something else
+        let local_info = get_package_dir(&name, Some(&package_info.version)).ok().and_then(|dir| PackageInfo::from_path(dir.join("package.yml")).ok());
+        if let Some(note) = local_info.and_then(|local_info| digest_mismatch(&package_info.version, local_info.digest.as_deref(), package_info.digest.as_deref())) {
+            println!("\n{}", note);
+        }
+        return Ok(());
+    }
+
     let package_dir = ensure_package_dir(&name, Some(&version), false)?;
     let package_file = package_dir.join("package.yml");
 
     if let Ok(package_info) = PackageInfo::from_path(package_file) {
-        println!("{:#?}", package_info);
+        print_package_info(&package_info, json)?;
     } else {
         return Err(anyhow!("Failed to read package information."));
     }
@@ -175,17 +359,19 @@ pub fn inspect(
 
 
 /* TIM */
-/// **Edited: updated to deal with get_packages_dir() returning ExecutorErrors. Also added option to only show latest packages and also standard packages.**
+/// **Edited: updated to deal with get_packages_dir() returning ExecutorErrors. Also added option to only show latest packages and also standard packages. Also added option to rebuild the index cache.**
 ///
 /// Lists the packages locally build and available.
 /// 
 /// **Arguments**
 ///  * `latest`: If set to true, only shows latest version of each package.
+///  * `rebuild_index`: If set to true, ignores the index cache and parses every package again.
 /// 
 /// **Returns**  
 /// Nothing other than prints on stdout if successfull, or an ExecutorError otherwise.
 pub fn list(
-    latest: bool
+    latest: bool,
+    rebuild_index: bool,
 ) -> Result<(), PackageError> {
     // Get the directory with the packages
     let packages_dir = match ensure_packages_dir(false) {
@@ -204,7 +390,7 @@ pub fn list(
     table.add_row(row!["ID", "NAME", "VERSION", "KIND", "CREATED", "SIZE"]);
 
     // Get the local PackageIndex
-    let index = match get_package_index() {
+    let index = match read_package_index(rebuild_index) {
         Ok(idx) => idx,
         Err(reason) => { return Err(reason); }
     };
@@ -254,24 +440,102 @@ pub fn list(
 
 
 
-/// **Edited: now working with new versions.**
+/// **Edited: now working with new versions, and loading dependencies too.**
 /// 
-/// Loads the given package to the local Docker daemon.
+/// Loads the given package to the local Docker daemon, together with the packages it (transitively) depends on.
 /// 
 /// **Arguments**
 ///  * `name`: The name of the package to load.
 ///  * `version`: The Version of the package to load. Might be an unresolved 'latest'.
+///  * `no_deps`: If true, does not load the dependencies of the package.
+///  * `offline`: If true, a package (or dependency) that is not available locally results in an OfflineError.
 /// 
 /// **Returns**  
-/// Nothing on success, or else an error.
+/// Nothing on success, or else an error (including when a dependency is not available locally or the dependencies are circular).
 pub async fn load(
     name: String,
     version: Version,
+    no_deps: bool,
+    offline: bool,
 ) -> Result<()> {
-    debug!("Loading package '{}' (version {})", name, &version);
+    // Load the package itself
+    let package_info = load_package(&name, &version, offline).await?;
+
+    // Load its dependencies, if told to do so
+    if !no_deps {
+        let index = get_package_index()?;
+        let mut path = vec![ package_info.name.clone() ];
+        let mut done = HashSet::new();
+        load_dependencies(&index, &package_info, &mut path, &mut done, offline).await?;
+    }
 
-    let package_dir = ensure_package_dir(&name, Some(&version), false)?;
+    Ok(())
+}
+
+/// Loads the dependencies of the given package (and their dependencies, etc) to the local Docker daemon.
+/// 
+/// **Arguments**
+///  * `index`: The index of local packages to resolve the dependencies with.
+///  * `package_info`: The package to load the dependencies of.
+///  * `path`: The names of the packages we're currently resolving the dependencies of, used to detect cycles. Should start with the package itself.
+///  * `done`: The names of the packages of which we already loaded all dependencies.
+///  * `offline`: If true, a dependency that is not available locally results in an OfflineError.
+/// 
+/// **Returns**  
+/// Nothing on success, or else an error.
+fn load_dependencies<'a>(
+    index: &'a PackageIndex,
+    package_info: &'a PackageInfo,
+    path: &'a mut Vec<String>,
+    done: &'a mut HashSet<String>,
+    offline: bool,
+) -> LocalBoxFuture<'a, Result<()>> {
+    async move {
+        for dependency in &package_info.dependencies {
+            // Refuse to go round in circles
+            if path.contains(&dependency.name) { bail!("Circular dependency detected: {} -> {}", path.join(" -> "), dependency.name); }
+            if done.contains(&dependency.name) { continue; }
+
+            // We can only load what we have
+            let dependency_info = match index.get_matching(dependency) {
+                Some(dependency_info) => dependency_info,
+                None if offline       => { return Err(OfflineError::MissingPackage{ name: dependency.name.clone(), version: dependency.version_req.to_string() }.into()); }
+                None                  => { bail!("Dependency {} of package '{}' is not available locally; use `brane pull {}` first", dependency, package_info.name, dependency.name); }
+            };
+            println!("Loading dependency {} (version {}) of package {}...", dependency.name, dependency_info.version, package_info.name);
+            load_package(&dependency_info.name, &dependency_info.version, offline).await?;
+
+            // Recurse into its own dependencies
+            path.push(dependency.name.clone());
+            load_dependencies(index, dependency_info, path, done, offline).await?;
+            path.pop();
+            done.insert(dependency.name.clone());
+        }
+
+        Ok(())
+    }.boxed_local()
+}
+
+/// Loads a single package to the local Docker daemon.
+/// 
+/// **Arguments**
+///  * `name`: The name of the package to load.
+///  * `version`: The Version of the package to load. Might be an unresolved 'latest'.
+///  * `offline`: If true, a package that is not available locally results in an OfflineError.
+/// 
+/// **Returns**  
+/// The PackageInfo of the loaded package on success, or else an error.
+async fn load_package(
+    name: &str,
+    version: &Version,
+    offline: bool,
+) -> Result<PackageInfo> {
+    debug!("Loading package '{}' (version {})", name, version);
+
+    let _lock = PackageLock::acquire_async(name, "load").await?;
+    let package_dir = ensure_package_dir(name, Some(version), false)?;
     if !package_dir.exists() {
+        if offline { return Err(OfflineError::MissingPackage{ name: name.to_string(), version: version.to_string() }.into()); }
         return Err(anyhow!("Package not found."));
     }
 
@@ -279,12 +543,12 @@ pub async fn load(
     let image = format!("{}:{}", package_info.name, package_info.version);
     let image_file = package_dir.join("image.tar");
 
-    let docker = Docker::connect_with_local_defaults()?;
+    let docker = runtime::connect()?;
 
     // Abort, if image is already loaded
     if docker.inspect_image(&image).await.is_ok() {
         println!("Image already exists in local Docker deamon.");
-        return Ok(());
+        return Ok(package_info);
     }
 
     println!("Image doesn't exist in Docker deamon: importing...");
@@ -328,7 +592,7 @@ pub async fn load(
         }
     }
 
-    Ok(())
+    Ok(package_info)
 }
 
 
@@ -349,12 +613,16 @@ pub async fn remove(
     version: Option<Version>,
     force: bool,
 ) -> Result<()> {
+    let _lock = PackageLock::acquire_async(&name, "remove").await?;
+
     // Remove without confirmation if explicity stated package version.
     if let Some(version) = version {
         let package_dir = ensure_package_dir(&name, Some(&version), false)?;
         if fs::remove_dir_all(&package_dir).is_err() {
             println!("No package with name '{}' and version '{}' exists!", name, version);
         }
+        // (If it was 'latest', we don't know anymore which one that was)
+        index_cache::invalidate(&name, if version.is_latest() { None } else { Some(&version) });
 
         return Ok(());
     }
@@ -395,6 +663,7 @@ pub async fn remove(
     }
 
     fs::remove_dir_all(&package_dir)?;
+    index_cache::invalidate(&name, None);
 
     Ok(())
 }
@@ -0,0 +1,299 @@
+/* PROMPT.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 23:44:02
+ * Last edited:
+ *   15 Oct 2026, 23:44:02
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Asks the user for the arguments of a function (e.g., for
+ *   `brane test`), walking through the type of every parameter:
+ *    - Arrays are filled in element by element, asking whether to add
+ *      another one each time.
+ *    - Classes are filled in property by property.
+ *    - Everything else is typed in directly, and asked again (for just
+ *      that value) if it isn't valid.
+ *   At any level, the answer '@file.json' loads that (part of the) value
+ *   from a JSON file instead.
+**/
+
+use std::collections::HashMap;
+use std::fs;
+
+use anyhow::Result;
+use console::style;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Confirm, Input, Password};
+use serde_json::Value as JValue;
+
+use specifications::common::{Parameter, Type, Value};
+use specifications::pretty;
+
+use crate::test::typed_value;
+
+
+/***** CONSTANTS *****/
+/// The prefix of an answer that loads the value from a JSON file. Strings that start with it can be typed by doubling it.
+pub const FILE_PREFIX: char = '@';
+
+
+
+
+
+/***** LIBRARY TRAITS *****/
+/// Asks the user questions, so that the prompts can be answered by something else than a terminal (e.g., in tests).
+pub trait Questions {
+    /// Asks for a line of text.
+    /// 
+    /// **Arguments**
+    ///  * `question`: The question to ask.
+    ///  * `default`: The answer that is filled in if the user gives none, if any.
+    ///  * `allow_empty`: Whether an empty answer is allowed.
+    ///  * `secret`: Whether the answer should be hidden while it's typed (e.g., for passwords).
+    /// 
+    /// **Returns**  
+    /// The answer.
+    fn text(&mut self, question: &str, default: Option<String>, allow_empty: bool, secret: bool) -> Result<String>;
+
+    /// Asks a yes/no question.
+    /// 
+    /// **Arguments**
+    ///  * `question`: The question to ask.
+    ///  * `default`: The answer if the user gives none.
+    /// 
+    /// **Returns**  
+    /// The answer.
+    fn confirm(&mut self, question: &str, default: bool) -> Result<bool>;
+
+    /// Tells the user why an answer was rejected (right before asking the question again).
+    fn invalid(&mut self, message: &str);
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Asks questions on the terminal.
+#[derive(Debug, Default)]
+pub struct Terminal;
+
+impl Questions for Terminal {
+    fn text(&mut self, question: &str, default: Option<String>, allow_empty: bool, secret: bool) -> Result<String> {
+        let theme = ColorfulTheme::default();
+        if secret {
+            let mut input = Password::with_theme(&theme);
+            input.with_prompt(question).allow_empty_password(allow_empty);
+            return Ok(input.interact()?);
+        }
+
+        let mut input = Input::<String>::with_theme(&theme);
+        input.with_prompt(question).allow_empty(allow_empty);
+        if let Some(default) = default { input.default(default); }
+        Ok(input.interact()?)
+    }
+
+    fn confirm(&mut self, question: &str, default: bool) -> Result<bool> {
+        Ok(Confirm::with_theme(&ColorfulTheme::default()).with_prompt(question).default(default).interact()?)
+    }
+
+    fn invalid(&mut self, message: &str) {
+        eprintln!("{}", style(message).red());
+    }
+}
+
+
+
+/// Asks for the values of parameters, walking through their types.
+pub struct Prompter<'a, Q> {
+    /// Where we ask our questions
+    questions : &'a mut Q,
+    /// The types declared by the package
+    types     : &'a HashMap<String, Type>,
+}
+
+impl<'a, Q: Questions> Prompter<'a, Q> {
+    /// Constructor for the Prompter.
+    /// 
+    /// **Arguments**
+    ///  * `questions`: Where to ask the questions.
+    ///  * `types`: The types declared by the package, which are filled in property by property.
+    pub fn new(questions: &'a mut Q, types: &'a HashMap<String, Type>) -> Self {
+        Self {
+            questions,
+            types,
+        }
+    }
+
+
+
+    /// Asks for the value of the given parameter.
+    /// 
+    /// **Arguments**
+    ///  * `parameter`: The Parameter to ask the value of.
+    /// 
+    /// **Returns**  
+    /// The Value (which is unit if the parameter is optional and left empty), or an error if we could not ask.
+    pub fn parameter(&mut self, parameter: &Parameter) -> Result<Value> {
+        let secret = parameter.secret.is_some() || parameter.name.to_lowercase().contains("password");
+        self.value(&parameter.name, &parameter.data_type, parameter.default.as_ref(), parameter.optional.unwrap_or_default(), secret)
+    }
+
+
+
+    /// Asks for a value of the given type, which is known to the user as `path`.
+    fn value(&mut self, path: &str, data_type: &str, default: Option<&Value>, optional: bool, secret: bool) -> Result<Value> {
+        let element_type = data_type.strip_suffix("[]");
+        if element_type.is_none() && !self.types.contains_key(data_type) { return self.scalar(path, data_type, default, optional, secret); }
+        let label = format!("{} ({})", path, data_type);
+
+        // Offer the default first, and then whether to give it at all
+        if let Some(default) = default {
+            if self.questions.confirm(&format!("Use the default for {}: {}?", label, pretty::compact(default)), true)? { return Ok(default.clone()); }
+        }
+        if optional && !self.questions.confirm(&format!("Give a value for {}?", label), true)? { return Ok(Value::Unit); }
+
+        // See if the user wants to load it from a file
+        loop {
+            let answer = self.questions.text(&format!("{}: press enter to fill it in, or type {}file.json to load it", label, FILE_PREFIX), None, true, false)?;
+            let answer = answer.trim();
+            if answer.is_empty() { break; }
+            match answer.strip_prefix(FILE_PREFIX) {
+                Some(file) => match self.load(path, data_type, file) {
+                    Ok(value) => { return Ok(value); },
+                    Err(err)  => self.questions.invalid(&err),
+                },
+                None => self.questions.invalid(&format!("Press enter to fill in {} or type {}file.json to load it", path, FILE_PREFIX)),
+            }
+        }
+
+        // Fill it in
+        if let Some(element_type) = element_type {
+            let mut entries = vec![];
+            while self.questions.confirm(&format!("Add {} element to {}?", if entries.is_empty() { "an" } else { "another" }, path), entries.is_empty())? {
+                entries.push(self.value(&format!("{}[{}]", path, entries.len()), element_type, None, false, secret)?);
+            }
+            return Ok(Value::Array{ data_type: data_type.to_string(), entries });
+        }
+
+        let types = self.types;
+        let class = &types[data_type];
+        let mut properties = HashMap::new();
+        for property in &class.properties {
+            let value = self.value(&format!("{}.{}", path, property.name), &property.data_type, property.default.as_ref(), property.optional.unwrap_or_default(), property.secret.unwrap_or_default())?;
+            if !matches!(value, Value::Unit) { properties.insert(property.name.clone(), value); }
+        }
+        Ok(Value::Struct{ data_type: class.name.clone(), properties })
+    }
+
+    /// Asks for a value that is typed in directly, asking again until it is valid.
+    fn scalar(&mut self, path: &str, data_type: &str, default: Option<&Value>, optional: bool, secret: bool) -> Result<Value> {
+        let label = format!("{} ({})", path, data_type);
+        let default = default.map(|default| match default {
+            Value::Unicode(text) => text.clone(),
+            default              => default.to_string(),
+        });
+
+        loop {
+            let answer = self.questions.text(&label, default.clone(), optional, secret)?;
+            if optional && answer.is_empty() { return Ok(Value::Unit); }
+
+            let value = match answer.strip_prefix(FILE_PREFIX) {
+                Some(file) if !file.starts_with(FILE_PREFIX) => self.load(path, data_type, file),
+                Some(text)                                   => parse(text, data_type).map_err(|err| format!("{}: {}", path, err)),
+                None                                         => parse(&answer, data_type).map_err(|err| format!("{}: {}", path, err)),
+            };
+            match value {
+                Ok(value) => { return Ok(value); },
+                Err(err)  => self.questions.invalid(&err),
+            }
+        }
+    }
+
+    /// Loads a value of the given type from a JSON file.
+    fn load(&self, path: &str, data_type: &str, file: &str) -> Result<Value, String> {
+        let text = fs::read_to_string(file).map_err(|err| format!("Could not read '{}': {}", file, err))?;
+        let json: JValue = serde_json::from_str(&text).map_err(|err| format!("Could not parse '{}' as JSON: {}", file, err))?;
+        check(&json, data_type, self.types, path).map_err(|err| format!("Could not load '{}': {}", file, err))?;
+        Ok(typed_value(&json, Some(data_type), self.types))
+    }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Parses a typed-in answer as a value of the given (non-class) type.
+fn parse(answer: &str, data_type: &str) -> Result<Value, String> {
+    match data_type {
+        "boolean" => match answer.trim().to_lowercase().as_str() {
+            "true" | "yes" | "y"  => Ok(Value::Boolean(true)),
+            "false" | "no" | "n" => Ok(Value::Boolean(false)),
+            _                     => Err(format!("'{}' is not a boolean; expected 'true' or 'false'", answer)),
+        },
+        "integer" => answer.trim().parse().map(Value::Integer).map_err(|err| format!("'{}' is not an integer ({})", answer, err)),
+        "real"    => answer.trim().parse().map(Value::Real).map_err(|err| format!("'{}' is not a real ({})", answer, err)),
+        "string"  => Ok(Value::Unicode(answer.to_string())),
+        "Directory" | "File" => {
+            let mut properties = HashMap::new();
+            properties.insert(String::from("url"), Value::Unicode(format!("file:///{}", answer)));
+            Ok(Value::Struct{ data_type: data_type.to_string(), properties })
+        },
+        // We don't know what it is, so let the user write it out
+        _ => serde_json::from_str(answer).map(|json| Value::from_json(&json)).map_err(|err| format!("'{}' is not valid JSON ({})", answer, err)),
+    }
+}
+
+/// Checks that a JSON value (e.g., loaded from a file) is a value of the given type.
+/// 
+/// **Arguments**
+///  * `json`: The JSON value to check.
+///  * `data_type`: The type it should have.
+///  * `types`: The types declared by the package.
+///  * `path`: Where the value is in the parameter, for the error message.
+/// 
+/// **Returns**  
+/// Nothing if it's valid, or a message saying where it's not otherwise.
+fn check(json: &JValue, data_type: &str, types: &HashMap<String, Type>, path: &str) -> Result<(), String> {
+    let valid = if let Some(element_type) = data_type.strip_suffix("[]") {
+        match json.as_array() {
+            Some(entries) => {
+                for (i, entry) in entries.iter().enumerate() { check(entry, element_type, types, &format!("{}[{}]", path, i))?; }
+                true
+            },
+            None => false,
+        }
+    } else {
+        match data_type {
+            "boolean"            => json.is_boolean(),
+            "integer"            => json.is_i64(),
+            "real"               => json.is_number(),
+            "string"             => json.is_string(),
+            "Directory" | "File" => json.get("url").map(JValue::is_string).unwrap_or(false),
+            data_type            => match (types.get(data_type), json.as_object()) {
+                (Some(class), Some(fields)) => {
+                    for property in &class.properties {
+                        match fields.get(&property.name) {
+                            Some(field)                                    => check(field, &property.data_type, types, &format!("{}.{}", path, property.name))?,
+                            None if property.optional.unwrap_or_default() => {},
+                            None                                           => { return Err(format!("{}.{} is missing", path, property.name)); },
+                        }
+                    }
+                    if let Some(name) = fields.keys().find(|name| !class.properties.iter().any(|property| &property.name == *name)) {
+                        return Err(format!("{}.{} is not a property of {}", path, name, data_type));
+                    }
+                    true
+                },
+                (Some(_), None) => false,
+                // We don't know what it is, so anything goes
+                (None, _)       => true,
+            },
+        }
+    };
+
+    if valid { Ok(()) } else { Err(format!("{} should be a {}, not {}", path, data_type, json)) }
+}
@@ -1,5 +1,7 @@
+use std::collections::HashSet;
 use std::fs::{self, File};
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use anyhow::{Context, Result};
@@ -10,31 +12,58 @@ use console::{pad_str, Alignment};
 use dialoguer::Confirm;
 use flate2::write::GzEncoder;
 use flate2::Compression;
+use fs_extra::dir::CopyOptions;
+use futures::future::{FutureExt, LocalBoxFuture};
 use graphql_client::{GraphQLQuery, Response};
 use indicatif::{ProgressBar, ProgressStyle};
 use prettytable::format::FormatBuilder;
 use prettytable::Table;
-use reqwest::{self, Body, Client};
+use reqwest::{self, Body, Client, RequestBuilder, StatusCode};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use serde::Serialize;
 use tokio::fs::File as TokioFile;
 use tokio_util::codec::{BytesCodec, FramedRead};
 use url::Url;
 use uuid::Uuid;
 
-use specifications::package::{PackageKind, PackageInfo};
-use specifications::registry::RegistryConfig;
+use specifications::package::{PackageDependency, PackageKind, PackageInfo};
+use specifications::signing::{sign_package, PackageSignature};
 use specifications::version::Version;
 
-use crate::utils::{get_config_dir, get_package_dir, ensure_package_dir, get_package_versions, ensure_packages_dir, ensure_config_dir};
+use crate::credentials::{CredentialManager, Credentials};
+use crate::index_cache;
+use crate::lock::PackageLock;
+use crate::oci::{self, OciClient};
+use crate::oidc::{self, OidcOptions};
+use crate::packages;
+use crate::proxy;
+use crate::signing;
+use crate::utils::{get_package_dir, ensure_package_dir, get_package_versions, ensure_packages_dir};
 
 
 type DateTimeUtc = DateTime<Utc>;
 
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/api_schema.json",
+    query_path = "src/graphql/get_package.graphql",
+    response_derives = "Debug"
+)]
+pub struct GetPackage;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/api_schema.json",
+    query_path = "src/graphql/get_package_versions.graphql",
+    response_derives = "Debug"
+)]
+pub struct GetPackageVersions;
+
+
 /// Get the GraphQL endpoint of the Brane API.
 pub fn get_graphql_endpoint() -> Result<String> {
-    // Get the configuration directory
-    let config_file = get_config_dir().unwrap().join("registry.yml");
-    let config = RegistryConfig::from_path(&config_file)
+    let config = CredentialManager::new()?.registry()
         .with_context(|| "No registry configuration found, please use `brane login` first.")?;
 
     Ok(format!("{}/graphql", config.url))
@@ -42,82 +71,265 @@ pub fn get_graphql_endpoint() -> Result<String> {
 
 /// Get the package endpoint of the Brane API.
 pub fn get_packages_endpoint() -> Result<String> {
-    let config_file = get_config_dir().unwrap().join("registry.yml");
-    let config = RegistryConfig::from_path(&config_file)
+    let config = CredentialManager::new()?.registry()
         .with_context(|| "No registry configuration found, please use `brane login` first.")?;
 
     Ok(format!("{}/packages", config.url))
 }
 
-///
-///
-///
-pub fn login(
+/// Creates an HTTP client that sends the token in the given credentials (if any) along with every request.
+fn client_with(credentials: Option<&Credentials>) -> Result<Client> {
+    let mut headers = HeaderMap::new();
+    if let Some(token) = credentials.and_then(|c| c.token.as_ref()) {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token)).with_context(|| "Registry token contains illegal characters; please use `brane login` again.")?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+    Ok(proxy::client_builder().default_headers(headers).build()?)
+}
+
+/// Returns the credentials for the registry we're logged into, refreshing the token first if we got it through OpenID Connect and it has expired (or is about to).
+/// 
+/// **Returns**  
+/// The URL of the registry and its credentials (if any), or an anyhow error if we're not logged in or the login has expired for good.
+pub async fn fresh_credentials() -> Result<(String, Option<Credentials>)> {
+    let manager = CredentialManager::new()?;
+    let (url, credentials) = manager.credentials()
+        .with_context(|| "No registry configuration found, please use `brane login` first.")?;
+    match credentials {
+        Some(credentials) if credentials.oidc.as_ref().map(|session| oidc::is_expired(session, Utc::now().timestamp())).unwrap_or(false) => {
+            let credentials = oidc::refresh(&manager, &url, credentials).await?;
+            Ok((url, Some(credentials)))
+        },
+        credentials => Ok((url, credentials)),
+    }
+}
+
+/// Sends a request to the registry we're logged into with our token (if any).
+/// 
+/// If we got the token through OpenID Connect, it is refreshed when it has expired or when the registry says it's no good (in which case the request is sent again).
+/// 
+/// **Arguments**
+///  * `build`: Builds the request with the given client. May be called twice.
+/// 
+/// **Returns**  
+/// The registry's response, or an anyhow error if we could not send the request (or our login has expired for good).
+pub async fn send_registry<F>(build: F) -> Result<reqwest::Response>
+where
+    F: Fn(&Client) -> RequestBuilder,
+{
+    let (url, credentials) = fresh_credentials().await?;
+    let response = build(&client_with(credentials.as_ref())?).send().await.map_err(proxy::check_send_error)?;
+    if response.status() != StatusCode::UNAUTHORIZED { return Ok(response); }
+
+    // Tokens from OpenID Connect may have been revoked early; try a new one
+    let credentials = match credentials {
+        Some(credentials) if credentials.oidc.is_some() => credentials,
+        _                                               => { return Ok(response); }
+    };
+    let credentials = oidc::refresh(&CredentialManager::new()?, &url, credentials).await?;
+    let response = build(&client_with(Some(&credentials))?).send().await.map_err(proxy::check_send_error)?;
+    if response.status() == StatusCode::UNAUTHORIZED { return Err(oidc::OidcError::LoginExpired{ url }.into()); }
+    Ok(response)
+}
+
+/// Creates a client for the registry we're logged into if that is an OCI registry (see `brane login --oci`).
+/// 
+/// Like `send_registry()`, this refreshes the token first if we got it through OpenID Connect and it is about to expire.
+/// 
+/// **Returns**  
+/// The new OciClient, None if the registry is a Brane registry, or an anyhow error if we could not read the credentials.
+pub async fn oci_client() -> Result<Option<OciClient>> {
+    let config = CredentialManager::new()?.registry()
+        .with_context(|| "No registry configuration found, please use `brane login` first.")?;
+    if !config.oci { return Ok(None); }
+
+    let (url, credentials) = fresh_credentials().await?;
+    Ok(Some(OciClient::new(&url, credentials)?))
+}
+
+/// **Edited: now stores the credentials in the OS keyring if possible, and can log in through OpenID Connect.**
+/// 
+/// Logs into the given registry.
+/// 
+/// **Arguments**
+///  * `url`: The URL of the registry.
+///  * `username`: The username with which we sign packages.
+///  * `token`: The token to authenticate to the registry with, if any.
+///  * `insecure_store`: If true, keeps the credentials in the plaintext registry file even if there is a keyring.
+///  * `oci`: If true, the registry is a standard OCI registry instead of a Brane registry. Implied by an `oci://` URL.
+///  * `platform`: The platform that the registry's default cluster runs packages on, if not the default one.
+///  * `oidc`: How to log in through OpenID Connect instead of with the given token, if at all.
+/// 
+/// **Returns**  
+/// Nothing on success, or an anyhow error otherwise.
+pub async fn login(
     url: String,
     username: String,
+    token: Option<String>,
+    insecure_store: bool,
+    oci: bool,
+    platform: Option<String>,
+    oidc: OidcOptions,
 ) -> Result<()> {
-    let url = Url::parse(&url).with_context(|| format!("Not a valid absolute URL: {}", url))?;
+    // OCI registries are addressed like images, so the scheme is optional (and HTTPS by default)
+    let (url, oci) = match url.strip_prefix("oci://") {
+        Some(rest) => (format!("https://{}", rest), true),
+        None if oci && !url.contains("://") => (format!("https://{}", url), true),
+        None => (url, oci),
+    };
+    let parsed = Url::parse(&url).with_context(|| format!("Not a valid absolute URL: {}", url))?;
 
-    let host = url
+    let host = parsed
         .host_str()
         .with_context(|| format!("URL does not have a (valid) host: {}", url))?;
 
-    /* TIM */
-    // Added quick error handling
-    let config_file = match get_config_dir() {
-        Ok(dir)  => dir.join("registry.yml"),
-        Err(err) => { panic!("{}", err); }
-    };
-    /*******/
-    let mut config = if config_file.exists() {
-        RegistryConfig::from_path(&config_file)?
+    let url = if oci {
+        // Keep the namespace (if any), but not the default port
+        let port = parsed.port().map(|port| format!(":{}", port)).unwrap_or_default();
+        format!("{}://{}{}{}", parsed.scheme(), host, port, parsed.path().trim_end_matches('/'))
     } else {
-        RegistryConfig::default()
+        format!("{}://{}:{}", parsed.scheme(), host, parsed.port().unwrap_or(50051))
     };
 
-    config.username = username;
-    config.url = format!("{}://{}:{}", url.scheme(), host, url.port().unwrap_or(50051));
-
-    // Write registry.yml to config directory
-    fs::create_dir_all(&config_file.parent().unwrap())?;
-    let mut buffer = File::create(config_file)?;
-    write!(buffer, "{}", serde_yaml::to_string(&config)?)?;
+    // Unless told otherwise, the registry (at the address we'll talk to it on) serves the metadata of its OpenID Connect provider
+    let credentials = if oidc.oidc {
+        let issuer = oidc.issuer.clone().unwrap_or_else(|| url.clone());
+        oidc::login(&issuer, &oidc.client_id, username).await?
+    } else {
+        Credentials{ username, token, oidc: None }
+    };
+    let manager = CredentialManager::new()?;
+    let store = manager.login(&url, &credentials, insecure_store, oci)?;
+    if platform.is_some() { manager.set_platform(platform)?; }
+    println!("Logged in to '{}'; credentials are stored in {}.", url, store);
 
     Ok(())
 }
 
-///
-///
-///
+/// **Edited: now removes the credentials from whichever store holds them.**
+/// 
+/// Logs out of the registry we're logged into, if any.
+/// 
+/// **Returns**  
+/// Nothing on success, or an anyhow error otherwise.
 pub fn logout() -> Result<()> {
-    let config_file = ensure_config_dir(false).unwrap().join("registry.yml");
-    if config_file.exists() {
-        fs::remove_file(config_file)?;
-    }
-
+    CredentialManager::new()?.logout()?;
     Ok(())
 }
 
-///
-///
-///
+/// Pulls the given package from the registry we're currently logged into, together with the packages it (transitively) depends on.
+/// 
+/// **Arguments**
+///  * `name`: The name/ID of the package to pull.
+///  * `version`: The version of the package to pull.
+///  * `no_deps`: If true, does not pull the dependencies of the package.
+///  * `require_signed`: If true, refuses packages (including dependencies) that are not validly signed by a key in the trust store; otherwise, only warns about them.
+/// 
+/// **Returns**  
+/// Nothing on success, or an anyhow error on failure (including when the dependencies are circular).
 pub async fn pull(
     name: String,
     version: Version,
+    no_deps: bool,
+    require_signed: bool,
 ) -> Result<()> {
-    #[derive(GraphQLQuery)]
-    #[graphql(
-        schema_path = "src/graphql/api_schema.json",
-        query_path = "src/graphql/get_package.graphql",
-        response_derives = "Debug"
-    )]
-    pub struct GetPackage;
+    // Pull the package itself
+    let package_info = pull_package(&name, &version, require_signed).await?;
+
+    // Pull its dependencies, if told to do so
+    if !no_deps {
+        let mut path = vec![ package_info.name.clone() ];
+        let mut done = HashSet::new();
+        pull_dependencies(&package_info, &mut path, &mut done, require_signed).await?;
+    }
+
+    Ok(())
+}
+
+/// Pulls the dependencies of the given package (and their dependencies, etc) that we don't have locally yet.
+/// 
+/// **Arguments**
+///  * `package_info`: The package to pull the dependencies of.
+///  * `path`: The names of the packages we're currently resolving the dependencies of, used to detect cycles. Should start with the package itself.
+///  * `done`: The names of the packages of which we already resolved all dependencies.
+///  * `require_signed`: If true, refuses dependencies that are not validly signed by a key in the trust store.
+/// 
+/// **Returns**  
+/// Nothing on success, or an anyhow error on failure.
+fn pull_dependencies<'a>(
+    package_info: &'a PackageInfo,
+    path: &'a mut Vec<String>,
+    done: &'a mut HashSet<String>,
+    require_signed: bool,
+) -> LocalBoxFuture<'a, Result<()>> {
+    async move {
+        for dependency in &package_info.dependencies {
+            // Refuse to go round in circles
+            if path.contains(&dependency.name) { bail!("Circular dependency detected: {} -> {}", path.join(" -> "), dependency.name); }
+            if done.contains(&dependency.name) { continue; }
+
+            // Use a matching local version if we have one; otherwise, pull the latest matching one
+            let local_info = packages::get_package_index()?.get_matching(dependency).cloned();
+            let dependency_info = match local_info {
+                Some(dependency_info) => {
+                    debug!("Dependency {} of package '{}' is satisfied by local version {}", dependency, package_info.name, dependency_info.version);
+                    dependency_info
+                },
+                None => {
+                    let version = resolve_dependency(dependency).await?;
+                    println!("Pulling dependency {} of package {}...", style(dependency).bold().cyan(), style(&package_info.name).bold().cyan());
+                    pull_package(&dependency.name, &version, require_signed).await?
+                },
+            };
+
+            // Recurse into its own dependencies
+            path.push(dependency.name.clone());
+            pull_dependencies(&dependency_info, path, done, require_signed).await?;
+            path.pop();
+            done.insert(dependency.name.clone());
+        }
+
+        Ok(())
+    }.boxed_local()
+}
+
+/// Finds the latest version of a package in the registry that satisfies the given dependency.
+/// 
+/// **Arguments**
+///  * `dependency`: The dependency to resolve.
+/// 
+/// **Returns**  
+/// The version to pull on success, or an anyhow error if no version matches (or we couldn't reach the registry).
+async fn resolve_dependency(dependency: &PackageDependency) -> Result<Version> {
+    // Select the latest of the matching versions
+    match remote_versions(&dependency.name).await?.into_iter().filter(|version| dependency.matches(version)).max() {
+        Some(version) => Ok(version),
+        None          => Err(anyhow!("No version of package '{}' in the registry satisfies requirement '{}'", dependency.name, dependency.version_req)),
+    }
+}
+
+/// Pulls a single package from the registry we're currently logged into.
+/// 
+/// **Arguments**
+///  * `name`: The name/ID of the package to pull.
+///  * `version`: The version of the package to pull.
+///  * `require_signed`: If true, refuses the package if it is not validly signed by a key in the trust store.
+/// 
+/// **Returns**  
+/// The PackageInfo of the pulled package on success, or an anyhow error on failure.
+async fn pull_package(
+    name: &str,
+    version: &Version,
+    require_signed: bool,
+) -> Result<PackageInfo> {
+    let package_dir = get_package_dir(name, Some(version))?;
+    if let Some(client) = oci_client().await? { return pull_oci_package(&client, name, version, &package_dir, require_signed).await; }
 
-    let package_dir = get_package_dir(&name, Some(&version))?;
     let mut temp_file = tempfile::NamedTempFile::new().expect("Failed to create temporary file.");
 
     let url = format!("{}/{}/{}", get_packages_endpoint()?, name, version);
-    let mut package_archive = reqwest::get(&url).await?;
+    let mut package_archive = send_registry(|client| client.get(&url)).await?;
     let content_length = package_archive
         .headers()
         .get("content-length")
@@ -140,68 +352,256 @@ pub async fn pull(
 
     progress.finish();
 
-    // Copy package to package directory.
+    // Retreive package information from API, and check that it's what its author published (and that the image is the one it describes) before installing anything
+    let package_info = graphql_package_info(name, version).await?;
+    let signature = package_signature(name, version).await?;
+    signing::check_pulled(&package_info, signature.as_ref(), require_signed)?;
+    signing::check_image(&package_info, temp_file.path())?;
+
+    // Copy package to package directory (making sure nobody else is working on it).
+    let _lock = PackageLock::acquire_async(name, "pull").await?;
     fs::create_dir_all(&package_dir)?;
     fs::copy(temp_file.path(), package_dir.join("image.tar"))?;
 
-    // Retreive package information from API.
-    let client = reqwest::Client::new();
+    // Write package.yml to package directory
+    let mut buffer = File::create(package_dir.join("package.yml"))?;
+    write!(buffer, "{}", serde_yaml::to_string(&package_info)?)?;
+    index_cache::invalidate(&package_info.name, Some(&package_info.version));
+
+    println!(
+        "\nSuccessfully pulled version {} of package {}.",
+        style(&version).bold().cyan(),
+        style(&name).bold().cyan(),
+    );
+
+    Ok(package_info)
+}
+
+/// Pulls a single package from an OCI registry.
+/// 
+/// **Arguments**
+///  * `client`: The OciClient for the registry.
+///  * `name`: The name/ID of the package to pull.
+///  * `version`: The version of the package to pull.
+///  * `package_dir`: The local directory to put the package in.
+///  * `require_signed`: If true, refuses the package, as OCI registries do not store signatures (yet).
+/// 
+/// **Returns**  
+/// The PackageInfo of the pulled package on success, or an anyhow error on failure.
+async fn pull_oci_package(
+    client: &OciClient,
+    name: &str,
+    version: &Version,
+    package_dir: &Path,
+    require_signed: bool,
+) -> Result<PackageInfo> {
+    let progress = ProgressBar::new(0);
+    progress.set_style(ProgressStyle::default_bar().template("Downloading... [{elapsed_precise}]"));
+    progress.enable_steady_tick(250);
+
+    // Download into a temporary directory first, so a failed pull leaves nothing behind
+    let staging = tempfile::tempdir()?;
+    let package_info = oci::pull_package(client, name, version, staging.path()).await?;
+    progress.finish();
+    signing::check_pulled(&package_info, None, require_signed)?;
+
+    // Copy package to package directory (making sure nobody else is working on it).
+    let _lock = PackageLock::acquire_async(name, "pull").await?;
+    fs::create_dir_all(package_dir)?;
+    fs_extra::dir::copy(staging.path(), package_dir, &CopyOptions{ overwrite: true, content_only: true, ..CopyOptions::new() })?;
+    index_cache::invalidate(&package_info.name, Some(&package_info.version));
+
+    println!(
+        "\nSuccessfully pulled version {} of package {}.",
+        style(&version).bold().cyan(),
+        style(&name).bold().cyan(),
+    );
+
+    Ok(package_info)
+}
+
+/// Returns every version of the given package in the registry we're currently logged into.
+/// 
+/// **Arguments**
+///  * `name`: The name of the package.
+/// 
+/// **Returns**  
+/// The versions (in no particular order) on success, or an anyhow error if we couldn't reach the registry.
+async fn remote_versions(name: &str) -> Result<Vec<Version>> {
+    // OCI registries know the versions from the tags
+    if let Some(client) = oci_client().await? { return Ok(oci::package_versions(&client, name).await?); }
+
+    let graphql_endpoint = get_graphql_endpoint()?;
+
+    // Prepare GraphQL query.
+    let variables = get_package_versions::Variables { name: name.to_string() };
+    let graphql_query = GetPackageVersions::build_query(variables);
+
+    // Request/response for GraphQL query.
+    let graphql_response = send_registry(|client| client.post(&graphql_endpoint).json(&graphql_query)).await?;
+    let graphql_response: Response<get_package_versions::ResponseData> = graphql_response.json().await?;
+    let data = match graphql_response.data {
+        Some(data) => data,
+        None       => { bail!("Failed to get versions of package '{}' from API: {:?}", name, graphql_response.errors); }
+    };
+
+    let mut versions: Vec<Version> = Vec::with_capacity(data.packages.len());
+    for package in data.packages {
+        versions.push(Version::from_str(&package.version)?);
+    }
+    Ok(versions)
+}
+
+/// Retrieves the metadata of a package from the GraphQL API of the (Brane) registry we're currently logged into.
+/// 
+/// **Arguments**
+///  * `name`: The name of the package.
+///  * `version`: The (resolved) version of the package.
+/// 
+/// **Returns**  
+/// The PackageInfo of the package on success, or an anyhow error if the registry doesn't know it (or doesn't offer package metadata at all).
+async fn graphql_package_info(
+    name: &str,
+    version: &Version,
+) -> Result<PackageInfo> {
     let graphql_endpoint = get_graphql_endpoint()?;
 
     // Prepare GraphQL query.
     let variables = get_package::Variables {
-        name: name.clone(),
+        name: name.to_string(),
         version: version.to_string(),
     };
     let graphql_query = GetPackage::build_query(variables);
 
-    // Request/response for GraphQL query.
-    let graphql_response = client.post(graphql_endpoint).json(&graphql_query).send().await?;
-    let graphql_response: Response<get_package::ResponseData> = graphql_response.json().await?;
+    // Request/response for GraphQL query
+    let graphql_response = send_registry(|client| client.post(&graphql_endpoint).json(&graphql_query)).await?;
+    let status = graphql_response.status();
+    check_metadata_status(&graphql_endpoint, status)?;
+    let graphql_response: Response<get_package::ResponseData> = graphql_response.json().await
+        .with_context(|| format!("The registry did not return package metadata at '{}' ({})", graphql_endpoint, status))?;
+    let data = match graphql_response.data {
+        Some(data) => data,
+        None       => { bail!("Failed to get package information from API: {:?}", graphql_response.errors); }
+    };
+    let package = match data.packages.first() {
+        Some(package) => package,
+        None          => { bail!("Version {} of package '{}' is not in the registry", version, name); }
+    };
 
-    if let Some(data) = graphql_response.data {
-        let package = data.packages.first().expect("No package information available");
-        let functions = package
-            .functions_as_json
-            .as_ref()
-            .map(|f| serde_json::from_str(f).unwrap());
-
-        let types = package.types_as_json.as_ref().map(|t| serde_json::from_str(t).unwrap());
-        /* TIM */
-        // TODO: Fix error handling
-        let kind = PackageKind::from_str(&package.kind).unwrap();
-        /*******/
-
-        let package_info = PackageInfo {
-            created: package.created,
-            description: package.description.clone().unwrap_or_default(),
-            detached: package.detached,
-            digest: package.digest.clone(),
-            functions: functions.unwrap_or_default(),
-            id: package.id,
-            kind,
-            name: package.name.clone(),
-            owners: package.owners.clone(),
-            types: types.unwrap_or_default(),
-            version: Version::from_str(&package.version)?,
-        };
+    // Parse the nested JSON
+    let functions = match &package.functions_as_json {
+        Some(functions) => serde_json::from_str(functions).with_context(|| format!("Registry returned illegal functions for package '{}'", name))?,
+        None            => Default::default(),
+    };
+    let types = match &package.types_as_json {
+        Some(types) => serde_json::from_str(types).with_context(|| format!("Registry returned illegal types for package '{}'", name))?,
+        None        => Default::default(),
+    };
+    let dependencies = match &package.dependencies_as_json {
+        Some(dependencies) => serde_json::from_str(dependencies).with_context(|| format!("Registry returned illegal dependencies for package '{}'", name))?,
+        None               => Default::default(),
+    };
+    let requirements = match &package.requirements_as_json {
+        Some(requirements) => serde_json::from_str(requirements).with_context(|| format!("Registry returned illegal requirements for package '{}'", name))?,
+        None               => Default::default(),
+    };
+    let environment = match &package.environment_as_json {
+        Some(environment) => serde_json::from_str(environment).with_context(|| format!("Registry returned illegal environment for package '{}'", name))?,
+        None              => Default::default(),
+    };
+    let kind = PackageKind::from_str(&package.kind).map_err(|err| anyhow!("Registry returned illegal kind for package '{}': {}", name, err))?;
+
+    Ok(PackageInfo {
+        created: package.created,
+        description: package.description.clone().unwrap_or_default(),
+        detached: package.detached,
+        digest: package.digest.clone(),
+        functions,
+        id: package.id,
+        kind,
+        name: package.name.clone(),
+        owners: package.owners.clone(),
+        types,
+        version: Version::from_str(&package.version)?,
+        dependencies,
+        platforms: vec![],
+        requirements,
+        reproducible: None,
+        init: vec![],
+        environment,
+    })
+}
 
-        // Write package.yml to package directory
-        let mut buffer = File::create(package_dir.join("package.yml"))?;
-        write!(buffer, "{}", serde_yaml::to_string(&package_info)?)?;
-    } else {
-        bail!("Failed to get package information from API.");
+/// Retrieves the metadata of a package in the registry we're currently logged into, without pulling it.
+/// 
+/// **Arguments**
+///  * `name`: The name of the package.
+///  * `version`: The version of the package. If it's 'latest', the latest version in the registry is used.
+/// 
+/// **Returns**  
+/// The PackageInfo of the package on success, or an anyhow error otherwise.
+pub async fn remote_package_info(
+    name: &str,
+    version: &Version,
+) -> Result<PackageInfo> {
+    // Resolve the latest version ourselves, since the API only looks up exact versions
+    let version = if version.is_latest() { latest_version(name, remote_versions(name).await?)? } else { version.clone() };
+
+    match oci_client().await? {
+        Some(client) => Ok(oci::package_info(&client, name, &version).await?),
+        None         => graphql_package_info(name, &version).await,
     }
+}
 
-    println!(
-        "\nSuccessfully pulled version {} of package {}.",
-        style(&version).bold().cyan(),
-        style(&name).bold().cyan(),
-    );
+/// Chooses the latest of the versions of a package in the registry.
+/// 
+/// **Arguments**
+///  * `name`: The name of the package (used for debugging).
+///  * `versions`: The versions of the package in the registry, in no particular order.
+/// 
+/// **Returns**  
+/// The greatest version by semver precedence, or an anyhow error if there are none (i.e., the package is not in the registry).
+pub fn latest_version(name: &str, versions: Vec<Version>) -> Result<Version> {
+    match versions.into_iter().filter(|version| !version.is_latest()).max() {
+        Some(version) => Ok(version),
+        None          => { bail!("Package '{}' is not in the registry", name); }
+    }
+}
 
+/// Checks whether the status code of the response to a package metadata query means that the registry supports such queries at all.
+/// 
+/// **Arguments**
+///  * `graphql_endpoint`: The endpoint that we asked (used for debugging).
+///  * `status`: The status code of the response.
+/// 
+/// **Returns**  
+/// Nothing if the registry seems to know the query, or an anyhow error explaining that it doesn't offer package metadata otherwise. Registries without the API tend to say so with one of these codes.
+pub fn check_metadata_status(graphql_endpoint: &str, status: StatusCode) -> Result<()> {
+    if status == StatusCode::NOT_FOUND || status == StatusCode::METHOD_NOT_ALLOWED || status == StatusCode::NOT_IMPLEMENTED {
+        bail!("The registry does not offer package metadata at '{}' ({}); use 'brane pull' to inspect the package locally instead", graphql_endpoint, status);
+    }
     Ok(())
 }
 
+/// Retrieves the (detached) signature of a package from the registry we're currently logged into.
+/// 
+/// **Arguments**
+///  * `name`: The name of the package.
+///  * `version`: The (resolved) version of the package.
+/// 
+/// **Returns**  
+/// The PackageSignature, None if the package is not signed, or an anyhow error if the registry returned something else.
+async fn package_signature(
+    name: &str,
+    version: &Version,
+) -> Result<Option<PackageSignature>> {
+    let url = format!("{}/{}/{}/signature", get_packages_endpoint()?, name, version);
+    let response = send_registry(|client| client.get(&url)).await?;
+    if response.status() == StatusCode::NOT_FOUND { return Ok(None); }
+    if !response.status().is_success() { bail!("Failed to get signature of package '{}' (version {}) from registry: {}", name, version, response.status()); }
+    Ok(Some(response.json().await.with_context(|| format!("Registry returned an illegal signature for package '{}' (version {})", name, version))?))
+}
+
 /* TIM */
 /// **Edited: the version is now optional.**
 /// 
@@ -210,12 +610,14 @@ pub async fn pull(
 /// **Arguments**
 ///  * `name`: The name/ID of the package to push.
 ///  * `version`: Optional package version to push. Will resolve it if it's the latest version.
+///  * `sign`: If given, the key file to sign the package's metadata with. The signature is uploaded after the package.
 /// 
 /// **Returns**  
 /// Nothing on success, or an anyhow error on failure.
 pub async fn push(
     name: String,
     version: Version,
+    sign: Option<PathBuf>,
 ) -> Result<()> {
     // Try to get the general package directory
     let packages_dir = ensure_packages_dir(false)?;
@@ -239,6 +641,37 @@ pub async fn push(
 
     // Construct the full package directory with version
     let package_dir = ensure_package_dir(&name, Some(&version), false)?;
+
+    // Don't push what the registry's cluster cannot run
+    let package_info = PackageInfo::from_path(package_dir.join("package.yml"))?;
+    let registry = CredentialManager::new()?.registry()
+        .with_context(|| "No registry configuration found, please use `brane login` first.")?;
+    check_platform(&package_info, registry.platform())?;
+
+    // Sign before uploading anything, so a bad key doesn't leave an unsigned package behind
+    let signature = match sign {
+        Some(key) => Some(sign_package(&package_info, &signing::read_signing_key(&key)?)?),
+        None      => None,
+    };
+
+    // OCI registries get the image and the metadata as separate artifacts
+    if let Some(client) = oci_client().await? {
+        if signature.is_some() { bail!("Signing packages is not supported for OCI registries"); }
+
+        let progress = ProgressBar::new(0);
+        progress.set_style(ProgressStyle::default_bar().template("Uploading...   [{elapsed_precise}]"));
+        progress.enable_steady_tick(250);
+        oci::push_package(&client, &package_dir, &package_info).await?;
+        progress.finish();
+
+        println!(
+            "\nSuccessfully pushed version {} of package {}.",
+            style(&version).bold().cyan(),
+            style(&name).bold().cyan(),
+        );
+        return Ok(());
+    }
+
     let temp_file = tempfile::NamedTempFile::new().expect("Failed to create temporary file.");
 
     let progress = ProgressBar::new(0);
@@ -255,7 +688,8 @@ pub async fn push(
 
     // Upload file
     let url = get_packages_endpoint()?;
-    let request = Client::new().post(&url);
+    let (_, credentials) = fresh_credentials().await?;
+    let request = client_with(credentials.as_ref())?.post(&url);
 
     let progress = ProgressBar::new(0);
     progress.set_style(ProgressStyle::default_bar().template("Uploading...   [{elapsed_precise}]"));
@@ -279,7 +713,7 @@ pub async fn push(
         .header("Content-Type", "application/gzip")
         .header("Content-Length", content_length);
 
-    let response = request.send().await?;
+    let response = request.send().await.map_err(proxy::check_send_error)?;
     let response_status = response.status();
 
     progress.finish();
@@ -290,6 +724,14 @@ pub async fn push(
             style(&version).bold().cyan(),
             style(&name).bold().cyan(),
         );
+
+        // Upload the signature next to it
+        if let Some(signature) = signature {
+            let url = format!("{}/{}/{}/signature", get_packages_endpoint()?, name, version);
+            let response = send_registry(|client| client.post(&url).json(&signature)).await?;
+            if !response.status().is_success() { bail!("Failed to upload signature of package '{}' (version {}): {}", name, version, response.text().await?); }
+            println!("Signed version {} of package {}.", style(&version).bold().cyan(), style(&name).bold().cyan());
+        }
     } else {
         let response_text = response.text().await?;
         println!("\nFailed to push package: {}", response_text)
@@ -297,12 +739,80 @@ pub async fn push(
 
     Ok(())
 }
+
+/// Checks whether the given package can run on the platform of the registry's cluster.
+/// 
+/// Packages built before multi-platform support (i.e., without a list of platforms) are assumed to match.
+/// 
+/// **Arguments**
+///  * `info`: The PackageInfo of the package to check.
+///  * `required`: The platform that the registry requires (e.g., `linux/amd64`).
+/// 
+/// **Returns**  
+/// Nothing if the package matches, or an anyhow error explaining how to fix it otherwise.
+pub fn check_platform(info: &PackageInfo, required: &str) -> Result<()> {
+    if !info.platforms.is_empty() && !info.platforms.iter().any(|platform| platform == required) {
+        bail!(
+            "Package '{}' (version {}) is built for {}, but the registry runs packages on {}; rebuild it with `brane build --platform {}`",
+            info.name, info.version, info.platforms.join(", "), required, required,
+        );
+    }
+    Ok(())
+}
 /*******/
 
-///
-///
-///
-pub async fn search(term: Option<String>) -> Result<()> {
+/// A package as shown in the results of `brane search`.
+#[derive(Debug, Serialize)]
+pub struct SearchedPackage {
+    /// The name of the package.
+    pub name        : String,
+    /// The latest version of the package.
+    pub version     : String,
+    /// The kind of the package.
+    pub kind        : String,
+    /// The owners of the package.
+    pub owners      : Vec<String>,
+    /// The description of the package, if any.
+    pub description : Option<String>,
+    /// The time the latest version of the package was pushed.
+    pub created     : DateTimeUtc,
+}
+
+/// The results of `brane search`, as printed with `--json`.
+#[derive(Debug, Serialize)]
+pub struct SearchResults {
+    /// The total number of packages that match the search.
+    pub total    : usize,
+    /// The page that is shown (1-indexed).
+    pub page     : usize,
+    /// The maximum number of packages per page.
+    pub limit    : usize,
+    /// The packages on this page.
+    pub packages : Vec<SearchedPackage>,
+}
+
+/// Searches the registry for packages.
+/// 
+/// If the registry does not support filtering and pagination yet, all matching packages are fetched and filtered locally instead (with a warning).
+/// 
+/// **Arguments**
+///  * `term`: The term that the names of the packages should contain, if any.
+///  * `kind`: The kind of the packages to search for, if any.
+///  * `author`: The owner of the packages to search for, if any.
+///  * `limit`: The maximum number of packages to show per page.
+///  * `page`: The page of results to show (1-indexed).
+///  * `json`: Whether to print the results as JSON instead of as a table.
+/// 
+/// **Returns**  
+/// Nothing on success, or an anyhow::Error otherwise.
+pub async fn search(
+    term: Option<String>,
+    kind: Option<PackageKind>,
+    author: Option<String>,
+    limit: usize,
+    page: usize,
+    json: bool,
+) -> Result<()> {
     #[derive(GraphQLQuery)]
     #[graphql(
         schema_path = "src/graphql/api_schema.json",
@@ -311,49 +821,173 @@ pub async fn search(term: Option<String>) -> Result<()> {
     )]
     pub struct SearchPackages;
 
-    let client = reqwest::Client::new();
+    if oci_client().await?.is_some() { bail!("Searching is not supported for OCI registries; browse the registry itself instead."); }
+
     let graphql_endpoint = get_graphql_endpoint()?;
+    let page = page.max(1);
+    let kind = kind.map(|kind| kind.to_string());
 
     // Prepare GraphQL query.
-    let variables = search_packages::Variables { term };
+    let variables = search_packages::Variables {
+        term: term.clone(),
+        kind: kind.clone(),
+        author: author.clone(),
+        limit: Some(limit as i64),
+        offset: Some(((page - 1) * limit) as i64),
+    };
     let graphql_query = SearchPackages::build_query(variables);
 
     // Request/response for GraphQL query.
-    let graphql_response = client.post(graphql_endpoint).json(&graphql_query).send().await?;
+    let graphql_response = send_registry(|client| client.post(&graphql_endpoint).json(&graphql_query)).await?;
     let graphql_response: Response<search_packages::ResponseData> = graphql_response.json().await?;
 
-    if let Some(data) = graphql_response.data {
-        let packages = data.packages;
-
-        // Present results in a table.
-        let format = FormatBuilder::new()
-            .column_separator('\0')
-            .borders('\0')
-            .padding(1, 1)
-            .build();
-
-        let mut table = Table::new();
-        table.set_format(format);
-        table.add_row(row!["NAME", "VERSION", "KIND", "DESCRIPTION"]);
-
-        for package in packages {
-            let name = pad_str(&package.name, 20, Alignment::Left, Some(".."));
-            let version = pad_str(&package.version, 10, Alignment::Left, Some(".."));
-            let kind = pad_str(&package.kind, 10, Alignment::Left, Some(".."));
-            let description = package.description.clone().unwrap_or_default();
-            let description = pad_str(&description, 50, Alignment::Left, Some(".."));
-
-            table.add_row(row![name, version, kind, description]);
-        }
+    let results = match (graphql_response.data, graphql_response.errors) {
+        (Some(data), _) => {
+            let packages = data.search_packages.packages.into_iter().map(|package| SearchedPackage {
+                name        : package.name,
+                version     : package.version,
+                kind        : package.kind,
+                owners      : package.owners,
+                description : package.description,
+                created     : package.created,
+            }).collect();
+            SearchResults{ total: data.search_packages.total as usize, page, limit, packages }
+        },
+        (None, Some(errors)) if errors.iter().any(|err| err.message.contains("searchPackages")) => {
+            // The registry predates searchPackages, so do the filtering ourselves
+            warn!("Registry does not support searchPackages: {:?}", errors);
+            eprintln!("{}", style("WARNING: The registry does not support filtering or pagination; falling back to searching all packages locally.").yellow());
+            search_fallback(&graphql_endpoint, term, kind, author, limit, page).await?
+        },
+        (None, errors) => { return Err(anyhow!("Could not search registry: {:?}", errors.unwrap_or_default())); },
+    };
+
+    // Show the results
+    if json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+        return Ok(());
+    }
+
+    let format = FormatBuilder::new()
+        .column_separator('\0')
+        .borders('\0')
+        .padding(1, 1)
+        .build();
+
+    let mut table = Table::new();
+    table.set_format(format);
+    table.add_row(row!["NAME", "LATEST VERSION", "KIND", "OWNERS", "DESCRIPTION"]);
+
+    for package in &results.packages {
+        let name = pad_str(&package.name, 20, Alignment::Left, Some(".."));
+        let version = pad_str(&package.version, 14, Alignment::Left, Some(".."));
+        let kind = pad_str(&package.kind, 10, Alignment::Left, Some(".."));
+        let owners = pad_str(&package.owners.join(", "), 20, Alignment::Left, Some(".."));
+        let description = package.description.clone().unwrap_or_default();
+        let description = pad_str(&description, 50, Alignment::Left, Some(".."));
+
+        table.add_row(row![name, version, kind, owners, description]);
+    }
+
+    table.printstd();
 
-        table.printstd();
+    // Tell the user where they are
+    let shown_until = (page - 1) * limit + results.packages.len();
+    if results.packages.is_empty() {
+        println!("\nNo packages found on page {} ({} package(s) in total).", page, results.total);
     } else {
-        eprintln!("{:?}", graphql_response.errors);
-    };
+        println!("\nShowing package(s) {}-{} of {}.", (page - 1) * limit + 1, shown_until, results.total);
+    }
+    if shown_until < results.total {
+        println!("More results are available; use '--page {}' to see the next page.", page + 1);
+    }
 
     Ok(())
 }
 
+/// Searches the registry for packages using the plain `packages` query, which is supported by older registries as well. Filtering, taking the latest versions and pagination are all done locally.
+/// 
+/// **Arguments**
+///  * `graphql_endpoint`: The GraphQL endpoint of the registry.
+///  * `term`: The term that the names of the packages should contain, if any.
+///  * `kind`: The kind of the packages to search for, if any.
+///  * `author`: The owner of the packages to search for, if any.
+///  * `limit`: The maximum number of packages per page.
+///  * `page`: The page of results to return (1-indexed).
+/// 
+/// **Returns**  
+/// The requested page of SearchResults on success, or an anyhow::Error otherwise.
+async fn search_fallback(
+    graphql_endpoint: &str,
+    term: Option<String>,
+    kind: Option<String>,
+    author: Option<String>,
+    limit: usize,
+    page: usize,
+) -> Result<SearchResults> {
+    #[derive(GraphQLQuery)]
+    #[graphql(
+        schema_path = "src/graphql/api_schema.json",
+        query_path = "src/graphql/list_packages.graphql",
+        response_derives = "Debug"
+    )]
+    pub struct ListPackages;
+
+    // Request/response for GraphQL query.
+    let graphql_query = ListPackages::build_query(list_packages::Variables { term });
+    let graphql_response = send_registry(|client| client.post(graphql_endpoint).json(&graphql_query)).await?;
+    let graphql_response: Response<list_packages::ResponseData> = graphql_response.json().await?;
+    let data = match graphql_response.data {
+        Some(data) => data,
+        None       => { return Err(anyhow!("Could not search registry: {:?}", graphql_response.errors.unwrap_or_default())); }
+    };
+
+    let packages = data.packages.into_iter().map(|package| SearchedPackage {
+        name        : package.name,
+        version     : package.version,
+        kind        : package.kind,
+        owners      : package.owners,
+        description : package.description,
+        created     : package.created,
+    }).collect();
+    Ok(select_search_page(packages, kind.as_deref(), author.as_deref(), limit, page))
+}
+
+/// Filters all versions of all packages in the registry down to the requested page of search results, for registries that cannot do so themselves.
+/// 
+/// **Arguments**
+///  * `packages`: Every version of every package with a matching name.
+///  * `kind`: The kind of the packages to search for, if any.
+///  * `author`: The owner of the packages to search for, if any.
+///  * `limit`: The maximum number of packages per page.
+///  * `page`: The page of results to return (1-indexed).
+/// 
+/// **Returns**  
+/// The requested page of the latest versions of the matching packages, sorted by name. Versions that cannot be parsed are skipped with a warning.
+pub fn select_search_page(packages: Vec<SearchedPackage>, kind: Option<&str>, author: Option<&str>, limit: usize, page: usize) -> SearchResults {
+    // Only keep the latest version of every package that matches the filters
+    let mut latest: Vec<(Version, SearchedPackage)> = vec![];
+    for package in packages {
+        if kind.map(|kind| kind != package.kind).unwrap_or(false) { continue; }
+        if author.map(|author| !package.owners.iter().any(|owner| owner == author)).unwrap_or(false) { continue; }
+        let version = match Version::from_str(&package.version) {
+            Ok(version) => version,
+            Err(err)    => { warn!("Ignoring package '{}' with illegal version '{}': {}", package.name, package.version, err); continue; }
+        };
+
+        match latest.iter_mut().find(|(_, other)| other.name == package.name) {
+            Some(entry) => { if entry.0 < version { *entry = (version, package); } },
+            None        => { latest.push((version, package)); },
+        }
+    }
+    latest.sort_by(|(_, lhs), (_, rhs)| lhs.name.cmp(&rhs.name));
+
+    // Select the page
+    let total = latest.len();
+    let packages = latest.into_iter().map(|(_, package)| package).skip(page.saturating_sub(1) * limit).take(limit).collect();
+    SearchResults{ total, page, limit, packages }
+}
+
 ///
 ///
 ///
@@ -370,7 +1004,8 @@ pub async fn unpublish(
     )]
     pub struct UnpublishPackage;
 
-    let client = reqwest::Client::new();
+    if oci_client().await?.is_some() { bail!("Unpublishing is not supported for OCI registries; delete the package's tags in the registry itself instead."); }
+
     let graphql_endpoint = get_graphql_endpoint()?;
 
     // Ask for permission, if --force is not provided
@@ -392,7 +1027,7 @@ pub async fn unpublish(
     let graphql_query = UnpublishPackage::build_query(variables);
 
     // Request/response for GraphQL query.
-    let graphql_response = client.post(graphql_endpoint).json(&graphql_query).send().await?;
+    let graphql_response = send_registry(|client| client.post(&graphql_endpoint).json(&graphql_query)).await?;
     let graphql_response: Response<unpublish_package::ResponseData> = graphql_response.json().await?;
 
     if let Some(data) = graphql_response.data {
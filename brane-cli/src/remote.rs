@@ -0,0 +1,82 @@
+/* REMOTE.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 20:58:19
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Connects to the driver of a remote Brane instance, over TLS if asked
+ *   to and authenticating with the token that `brane login` stored (or
+ *   the one given on the command line).
+**/
+
+use std::fs;
+use std::path::PathBuf;
+
+use brane_drv::auth::{self, BearerToken, DriverClient};
+use clap::Args;
+
+use crate::errors::RemoteError;
+use crate::registry;
+
+
+/***** LIBRARY STRUCTS *****/
+/// The options with which we connect to the driver of a remote Brane instance.
+#[derive(Args, Clone, Debug, Default)]
+pub struct RemoteOptions {
+    /// The CA certificate to verify the remote's TLS certificate with
+    #[clap(long, value_names = &["file"], env = "BRANE_CA_CERT", help = "CA certificate (as PEM) to verify the remote's TLS certificate with, if it is not signed by one of the system's roots")]
+    pub ca_cert : Option<PathBuf>,
+    /// The token to authenticate with, instead of the one stored by `brane login`
+    #[clap(long, env = "BRANE_DRIVER_TOKEN", hide_env_values = true, help = "Token to authenticate to the remote with (defaults to the one stored by 'brane login')")]
+    pub token   : Option<String>,
+}
+
+impl RemoteOptions {
+    /// Returns the token to authenticate with: the one given explicitly, or else the one of the registry we're logged into (refreshed first if we got it through OpenID Connect and it is about to expire).
+    /// 
+    /// **Returns**  
+    /// The token, or None if we have none (in which case we connect unauthenticated).
+    pub async fn token(&self) -> Option<String> {
+        if let Some(token) = &self.token { return Some(token.clone()); }
+
+        match registry::fresh_credentials().await {
+            Ok((_, Some(credentials))) => credentials.token,
+            Ok((_, None))              => None,
+            Err(err)                   => {
+                debug!("Not authenticating to the remote, as we have no (valid) stored credentials: {}", err);
+                None
+            },
+        }
+    }
+
+    /// Connects to the driver on the given address with these options.
+    /// 
+    /// **Arguments**
+    ///  * `address`: The address of the driver (use 'https://' to connect over TLS).
+    /// 
+    /// **Returns**  
+    /// The client on success, or a RemoteError otherwise.
+    pub async fn connect(&self, address: &str) -> Result<DriverClient, RemoteError> {
+        let ca_cert = match &self.ca_cert {
+            Some(path) => match fs::read(path) {
+                Ok(ca_cert) => Some(ca_cert),
+                Err(err)    => { return Err(RemoteError::CaCertReadError{ path: path.clone(), err }); }
+            },
+            None => None,
+        };
+        let token = match BearerToken::new(self.token().await.as_deref()) {
+            Ok(token) => token,
+            Err(err)  => { return Err(RemoteError::TokenError{ err }); }
+        };
+
+        match auth::connect(address, ca_cert, token).await {
+            Ok(client) => Ok(client),
+            Err(err)   => Err(RemoteError::ConnectError{ err }),
+        }
+    }
+}
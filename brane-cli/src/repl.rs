@@ -1,10 +1,19 @@
 use std::borrow::Cow::{self, Borrowed, Owned};
-use std::fs;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::Result;
-use brane_bvm::vm::{Vm, VmOptions};
-use brane_drv::grpc::{CreateSessionRequest, DriverServiceClient, ExecuteRequest};
+use brane_bvm::args::args_to_json;
+use brane_bvm::diff::StateDiff;
+use brane_bvm::trace::TraceEntry;
+use brane_bvm::vm::{Vm, VmOptions, VmState};
+use brane_drv::auth::DriverClient;
+use brane_drv::grpc::{CancelRequest, CloseSessionRequest, Compatibility, CreateSessionReply, CreateSessionRequest, ExecuteRequest, FollowRequest, GetGlobalsRequest};
+use brane_drv::multiplex::is_busy_status;
+use brane_drv::sessions::is_expired_status;
 use brane_dsl::{Compiler, CompilerOptions, Lang};
 use log::warn;
 use rustyline::completion::{Completer, FilenameCompleter, Pair};
@@ -12,14 +21,49 @@ use rustyline::config::OutputStreamType;
 use rustyline::error::ReadlineError;
 use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
 use rustyline::hint::{Hinter, HistoryHinter};
-use rustyline::validate::{self, MatchingBracketValidator, Validator};
+use rustyline::validate::Validator;
 use rustyline::{CompletionType, Config, Context, EditMode, Editor};
 use rustyline_derive::Helper;
+use specifications::common::Value;
+use tonic::{Code, Status};
+use uuid::Uuid;
 
 use crate::docker::DockerExecutor;
 use crate::errors::ReplError;
 use crate::packages;
-use crate::utils::{ensure_config_dir, get_history_file};
+use crate::remote::RemoteOptions;
+use crate::run::print_trace;
+use crate::utils::ensure_history_file;
+
+
+/***** CONSTANTS *****/
+/// The prompt shown while the user is still typing a statement that spans multiple lines.
+const CONTINUATION_PROMPT: &str = "... ";
+/// The command that switches the REPL to paste mode.
+const PASTE_COMMAND: &str = ":paste";
+/// The number of times we try to reconnect to a remote after losing the connection, before giving up.
+const RECONNECT_ATTEMPTS: u32 = 8;
+/// The time we wait after the first failed attempt to reconnect; it doubles after every next attempt.
+const RECONNECT_MIN_DELAY: Duration = Duration::from_secs(1);
+/// The maximum time we wait in between two attempts to reconnect.
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+/// The maximum number of instructions a single statement may execute in the local REPL, so an accidental infinite loop doesn't hang it forever (as Ctrl+C cannot interrupt it).
+const REPL_MAX_INSTRUCTIONS: u64 = 1_000_000_000;
+/// The overview of the meta-commands, which is shown for ':help' and for any meta-command we don't know.
+const META_COMMANDS_HELP: &str = "Available commands:
+  :vars                List the variables that are defined, with their types
+  :funcs               List the functions that can be called, with their signatures
+  :packages            List the imported packages, with their versions
+  :doc <name>          Show the signature and description of the given function
+  :state save <file>   Save the state of the session to the given file (local sessions only)
+  :state load <file>   Replace the state of the session with the one in the given file (local sessions only)
+  :unimport <package>  Remove the functions and types of an imported package again (local sessions only)
+  :trace               Toggle printing the external function calls of every statement after it ran
+  :dryrun              Toggle only checking external function calls instead of running them (they return default values)
+  :paste               Enter a block of statements, finished by an empty line";
+
+
+
 
 
 /***** REPL HELPER *****/
@@ -30,8 +74,6 @@ struct ReplHelper {
     completer      : FilenameCompleter,
     /// Highlighter: we highlight matching brackets
     highlighter    : MatchingBracketHighlighter,
-    /// We even validate for matching brackets
-    validator      : MatchingBracketValidator,
     /// We hint based on the user's history
     hinter         : HistoryHinter,
     /// Does something with being a coloured prompt(?)
@@ -103,16 +145,640 @@ impl Highlighter for ReplHelper {
     }
 }
 
-impl Validator for ReplHelper {
-    fn validate(
-        &self,
-        ctx: &mut validate::ValidationContext,
-    ) -> rustyline::Result<validate::ValidationResult> {
-        self.validator.validate(ctx)
+/// We accept every line as-is; whether a statement is complete is decided by read_statement(), so that continuation lines get their own prompt.
+impl Validator for ReplHelper {}
+
+
+
+
+
+/***** META COMMANDS *****/
+/// Defines the commands that the REPL handles itself instead of passing them to the compiler.
+#[derive(Debug, PartialEq)]
+enum MetaCommand<'a> {
+    /// Lists the variables that are currently defined.
+    Vars,
+    /// Lists the functions that can currently be called.
+    Funcs,
+    /// Lists the packages that are currently imported.
+    Packages,
+    /// Shows the documentation of the given function.
+    Doc(&'a str),
+    /// Saves the state of the session to the given file.
+    StateSave(&'a str),
+    /// Replaces the state of the session with the one in the given file.
+    StateLoad(&'a str),
+    /// Removes the functions and types of the given package again.
+    Unimport(&'a str),
+    /// Turns printing the execution trace of every statement on or off.
+    Trace,
+    /// Turns simulating the external function calls of every statement on or off.
+    DryRun,
+    /// Shows the available meta-commands (also used for unknown or malformed ones).
+    Help,
+}
+
+/// Parses the given statement as a meta-command, i.e., a line that starts with a colon.
+/// 
+/// **Arguments**
+///  * `statement`: The statement as typed by the user.
+/// 
+/// **Returns**  
+/// The MetaCommand to run, or None if this is a normal statement that should be compiled.
+fn parse_meta_command(statement: &str) -> Option<MetaCommand<'_>> {
+    let statement = statement.trim();
+    if !statement.starts_with(':') { return None; }
+
+    let words: Vec<&str> = statement.split_whitespace().collect();
+    Some(match words[..] {
+        [":vars"]                => MetaCommand::Vars,
+        [":funcs"]               => MetaCommand::Funcs,
+        [":packages"]            => MetaCommand::Packages,
+        [":doc", name]           => MetaCommand::Doc(name),
+        [":state", "save", file] => MetaCommand::StateSave(file),
+        [":state", "load", file] => MetaCommand::StateLoad(file),
+        [":unimport", package]   => MetaCommand::Unimport(package),
+        [":trace"]               => MetaCommand::Trace,
+        [":dryrun"]              => MetaCommand::DryRun,
+        _                        => MetaCommand::Help,
+    })
+}
+
+
+
+/// Prints the given variables, one per line.
+/// 
+/// **Arguments**
+///  * `variables`: The names of the variables with their data types.
+fn print_variables(variables: Vec<(String, String)>) {
+    if variables.is_empty() { println!("No variables defined."); }
+    for (name, data_type) in variables { println!("  {}: {}", name, data_type); }
+}
+
+/// Prints the given functions, one per line.
+/// 
+/// **Arguments**
+///  * `functions`: The names of the functions with their signatures.
+fn print_functions(functions: Vec<(String, String)>) {
+    if functions.is_empty() { println!("No functions defined."); }
+    for (_, signature) in functions { println!("  {}", signature); }
+}
+
+/// Prints the documentation of the given function.
+/// 
+/// **Arguments**
+///  * `name`: The name of the function.
+///  * `doc`: Its documentation, or None if there is no function with that name.
+fn print_doc(name: &str, doc: Option<String>) {
+    match doc {
+        Some(doc) => println!("{}", doc),
+        None      => println!("No function '{}' defined.", name),
+    }
+}
+
+/// Prints which variables a statement defined, removed or changed on a single line (or nothing, if it did not change any).
+/// 
+/// **Arguments**
+///  * `diff`: The StateDiff from before to after the statement.
+fn print_diff(diff: &StateDiff) {
+    if !diff.is_empty() { println!("({})", diff); }
+}
+
+/// Prints the given packages, one per line.
+/// 
+/// **Arguments**
+///  * `packages`: The names of the packages with their versions.
+fn print_packages(packages: Vec<(String, String)>) {
+    if packages.is_empty() { println!("No packages imported."); }
+    for (name, version) in packages { println!("  {} {}", name, version); }
+}
+
+
+
+/// Runs the given meta-command on the local VM.
+/// 
+/// Any errors are written to stderr, so the user may simply try again.
+/// 
+/// **Arguments**
+///  * `command`: The MetaCommand to run.
+///  * `vm`: The VM of the session, which is replaced when loading a state.
+///  * `compiler`: The Compiler of the session, which gets a fresh package index on unimports.
+///  * `executor`: The executor to give a VM created from a loaded state.
+///  * `trace`: Whether the session prints execution traces, which is toggled by `:trace`.
+///  * `dry_run`: Whether the session simulates external function calls, which is toggled by `:dryrun`.
+fn local_meta_command(
+    command: MetaCommand,
+    vm: &mut Vm<DockerExecutor>,
+    compiler: &mut Compiler,
+    executor: &DockerExecutor,
+    trace: &mut bool,
+    dry_run: &mut bool,
+) {
+    match command {
+        MetaCommand::Vars     => print_variables(vm.capture_state().variables()),
+        MetaCommand::Funcs    => print_functions(vm.capture_state().functions()),
+        MetaCommand::Packages => print_packages(vm.capture_state().packages().into_iter().map(|(name, version)| (name, version.to_string())).collect()),
+        MetaCommand::Doc(name) => print_doc(name, vm.capture_state().doc(name)),
+
+        MetaCommand::StateSave(file) => {
+            let state = match serde_json::to_string_pretty(&vm.capture_state()) {
+                Ok(state) => state,
+                Err(err)  => { eprintln!("{}", ReplError::StateSerializeError{ err }); return; }
+            };
+            match fs::write(file, state) {
+                Ok(_)    => println!("Saved the session state to '{}'", file),
+                Err(err) => eprintln!("{}", ReplError::StateWriteError{ path: PathBuf::from(file), err }),
+            }
+        },
+        MetaCommand::StateLoad(file) => {
+            let state = match fs::read_to_string(file) {
+                Ok(state) => state,
+                Err(err)  => { eprintln!("{}", ReplError::StateReadError{ path: PathBuf::from(file), err }); return; }
+            };
+            let state: VmState = match serde_json::from_str(&state) {
+                Ok(state) => state,
+                Err(err)  => { eprintln!("{}", ReplError::StateParseError{ path: PathBuf::from(file), err }); return; }
+            };
+            match Vm::new_with_state(executor.clone(), Some(compiler.package_index.clone()), state) {
+                Ok(new_vm) => { *vm = new_vm; vm.set_trace(*trace); println!("Loaded the session state from '{}'", file); },
+                Err(err)   => eprintln!("{}", ReplError::VmCreateError{ err }),
+            }
+        },
+
+        MetaCommand::Unimport(package) => {
+            match vm.drop_package(package) {
+                Ok(globals) => {
+                    // Reload the packages, so that importing it again picks up any version pulled in the meantime
+                    match packages::get_package_index() {
+                        Ok(index) => { compiler.package_index = index.clone(); vm.set_package_index(index); },
+                        Err(err)  => { eprintln!("Could not reload the package index: {}", err); },
+                    }
+                    println!("Unimported package '{}' (removed {})", package, globals.join(", "));
+                },
+                Err(err) => eprintln!("{}", err),
+            }
+        },
+
+        MetaCommand::Trace => {
+            *trace = !*trace;
+            vm.set_trace(*trace);
+            println!("Execution trace {}", if *trace { "enabled" } else { "disabled" });
+        },
+        MetaCommand::DryRun => {
+            // Statements are simulated on a copy of the session (see `local_repl()`), so the VM itself never simulates anything
+            *dry_run = !*dry_run;
+            println!("Dry run {}", if *dry_run { "enabled: external functions are only checked, not run" } else { "disabled" });
+        },
+
+        MetaCommand::Help => println!("{}", META_COMMANDS_HELP),
+    }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Checks whether the given input forms a complete statement, i.e., whether all braces, parentheses and brackets have been closed.
+/// 
+/// Delimiters inside string literals and comments are ignored. Closing delimiters without a matching opening one are not considered here, but left to the compiler to complain about.
+/// 
+/// **Arguments**
+///  * `input`: The (possibly multi-line) input that the user has typed so far.
+/// 
+/// **Returns**  
+/// True if the statement is complete and may be executed, or false if we should keep reading lines.
+pub fn is_complete_statement(input: &str) -> bool {
+    let mut depth: usize = 0;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // Skip over strings, taking escapes into account
+            '"' => {
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    if c == '\\' { chars.next(); }
+                    else if c == '"' { closed = true; break; }
+                }
+                if !closed { return false; }
+            },
+
+            // Skip over comments
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() { if c == '\n' { break; } }
+            },
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    if c == '*' && chars.peek() == Some(&'/') { chars.next(); closed = true; break; }
+                }
+                if !closed { return false; }
+            },
+
+            // Keep track of the delimiters
+            '{' | '(' | '[' => { depth += 1; },
+            '}' | ')' | ']' => { depth = depth.saturating_sub(1); },
+            _               => {},
+        }
+    }
+
+    // We're done if everything has been closed
+    depth == 0
+}
+
+
+
+/// Reads a single, complete statement from the user.
+/// 
+/// If the first line leaves any braces, parentheses or brackets open, then we keep reading lines with a secondary prompt until they are all closed. If the user enters the paste command, then we read lines until an empty line is given instead.
+/// 
+/// **Arguments**
+///  * `rl`: The RustyLine editor that we use to get user input.
+///  * `count`: The number of the current statement, which is shown in the prompt.
+/// 
+/// **Returns**  
+/// The statement typed by the user, which is already added to the history. If anything went wrong (including Ctrl+C and Ctrl+D), then the ReadlineError is returned and any partial input is discarded.
+fn read_statement(
+    rl: &mut Editor<ReplHelper>,
+    count: u32,
+) -> Result<String, ReadlineError> {
+    // Prepare the prompt with the current iteration number
+    let p = format!("{}> ", count);
+
+    // Write the prompt in a coloured way
+    rl.helper_mut().expect("No helper").colored_prompt = format!("\x1b[1;32m{}\x1b[0m", p);
+
+    // Read the first line
+    let mut statement = rl.readline(&p)?;
+
+    // Also colour the secondary prompt for any upcoming lines
+    rl.helper_mut().expect("No helper").colored_prompt = format!("\x1b[1;32m{}\x1b[0m", CONTINUATION_PROMPT);
+    if statement.trim() == PASTE_COMMAND {
+        // Read everything up to the first empty line
+        println!("(paste mode; finish with an empty line)");
+        statement = read_paste_block(|| rl.readline(CONTINUATION_PROMPT))?;
+    } else {
+        // Keep on reading until all delimiters are closed
+        while !is_complete_statement(&statement) {
+            let line = rl.readline(CONTINUATION_PROMPT)?;
+            statement.push('\n');
+            statement.push_str(&line);
+        }
+    }
+
+    // The statement checked out, so add it to the history as a whole
+    rl.add_history_entry(statement.as_str());
+    Ok(statement)
+}
+
+
+
+/// Reads the block of statements that follows the paste command.
+/// 
+/// **Arguments**
+///  * `next_line`: Closure that reads the next line from the user.
+/// 
+/// **Returns**  
+/// All lines up to (but not including) the first empty one, joined by newlines. If reading a line fails, then that ReadlineError is returned instead.
+pub fn read_paste_block<F>(mut next_line: F) -> Result<String, ReadlineError>
+where
+    F: FnMut() -> Result<String, ReadlineError>,
+{
+    let mut block = String::new();
+    loop {
+        let line = next_line()?;
+        if line.trim().is_empty() { break; }
+        if !block.is_empty() { block.push('\n'); }
+        block.push_str(&line);
+    }
+    Ok(block)
+}
+
+
+
+/// Loads the REPL history from the given file.
+/// 
+/// **Arguments**
+///  * `rl`: The RustyLine editor to load the history into.
+///  * `path`: The path of the history file.
+/// 
+/// **Returns**  
+/// Nothing on success, or an std::io::Error if we failed to read the file.
+fn load_history(rl: &mut Editor<ReplHelper>, path: &Path) -> Result<(), std::io::Error> {
+    for entry in read_history(path)? {
+        rl.add_history_entry(entry);
+    }
+    Ok(())
+}
+
+/// Saves the REPL history to the given file.
+/// 
+/// **Arguments**
+///  * `rl`: The RustyLine editor with the history to write.
+///  * `path`: The path of the history file.
+/// 
+/// **Returns**  
+/// Nothing on success, or an std::io::Error if we failed to write the file.
+fn save_history(rl: &Editor<ReplHelper>, path: &Path) -> Result<(), std::io::Error> {
+    write_history(path, rl.history().iter())
+}
+
+/// Reads the history entries from the given file.
+/// 
+/// Entries are stored one per line, with newlines (and backslashes) escaped so that multi-line statements survive a round-trip.
+/// 
+/// **Arguments**
+///  * `path`: The path of the history file.
+/// 
+/// **Returns**  
+/// The entries in the file, oldest first, or an std::io::Error if we failed to read the file.
+pub fn read_history(path: &Path) -> Result<Vec<String>, std::io::Error> {
+    let handle = File::open(path)?;
+    let mut entries = vec![];
+    for line in BufReader::new(handle).lines() {
+        let line = line?;
+
+        // Undo the escaping
+        let mut entry = String::with_capacity(line.len());
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                match chars.next() {
+                    Some('n') => entry.push('\n'),
+                    Some(c)   => entry.push(c),
+                    None      => entry.push('\\'),
+                }
+            } else {
+                entry.push(c);
+            }
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Writes the given history entries to the given file, in the format that `read_history()` reads.
+/// 
+/// **Arguments**
+///  * `path`: The path of the history file.
+///  * `entries`: The entries to write, oldest first.
+/// 
+/// **Returns**  
+/// Nothing on success, or an std::io::Error if we failed to write the file.
+pub fn write_history<I, S>(path: &Path, entries: I) -> Result<(), std::io::Error>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut handle = BufWriter::new(File::create(path)?);
+    for entry in entries {
+        writeln!(handle, "{}", entry.as_ref().replace('\\', "\\\\").replace('\n', "\\n"))?;
+    }
+    handle.flush()
+}
+
+
+
+
+
+/// Checks the driver's verdict on whether its version is compatible with ours.
+/// 
+/// **Arguments**
+///  * `address`: The address of the driver (used for debugging).
+///  * `reply`: The driver's reply to our CreateSessionRequest.
+///  * `skip_version_check`: If true, incompatible versions result in a warning instead of an error.
+/// 
+/// **Returns**  
+/// Nothing if we may continue, or a ReplError::VersionMismatch telling the user which side to upgrade.
+fn check_driver_version(address: &str, reply: &CreateSessionReply, skip_version_check: bool) -> Result<(), ReplError> {
+    let driver_version = if reply.driver_version.is_empty() { "unknown".to_string() } else { reply.driver_version.clone() };
+    let upgrade = match reply.compatibility() {
+        Compatibility::Compatible    => { return Ok(()); },
+        Compatibility::Unknown       => {
+            warn!("Could not check if the driver at '{}' (version {}) is compatible with this CLI (version {})", address, driver_version, env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        },
+        Compatibility::UpgradeClient => "CLI",
+        Compatibility::UpgradeDriver => "driver",
+    };
+
+    let err = ReplError::VersionMismatch{ address: address.to_string(), client: env!("CARGO_PKG_VERSION").to_string(), driver: driver_version, upgrade };
+    if skip_version_check {
+        warn!("{} (continuing anyway, as the version check is skipped)", err);
+        return Ok(());
+    }
+    Err(err)
+}
+
+
+
+
+
+/// Why running a statement on a remote did not end with its closing reply.
+#[derive(Debug)]
+enum StatementError {
+    /// The request to run the statement failed.
+    Request(Status),
+    /// The stream of replies failed.
+    Stream(Status),
+    /// The stream of replies ended without a closing reply.
+    Closed,
+}
+
+impl StatementError {
+    /// Returns whether the error means that we lost the connection to the remote (instead of, say, the statement not compiling or being refused).
+    /// 
+    /// Only statuses that come from the transport count: the remote never attaches a source error to the statuses it sends, while tonic does for the ones it makes of a failing connection.
+    fn is_connection_lost(&self) -> bool {
+        match self {
+            StatementError::Request(status) | StatementError::Stream(status) => std::error::Error::source(status).is_some(),
+            StatementError::Closed                                            => true,
+        }
+    }
+}
+
+
+
+/// Connects to the given remote and creates a new session or attaches to an existing one, checking if the driver speaks our version while at it.
+/// 
+/// **Arguments**
+///  * `remote`: The address of the remote.
+///  * `options`: The RemoteOptions to connect with (i.e., how to verify the remote and authenticate to it).
+///  * `attach`: If not None, the session to attach to.
+///  * `client_id`: The ID that identifies us to the remote, so it knows we use the session until we close it.
+///  * `skip_version_check`: Whether to continue even if the remote's version is incompatible with ours.
+/// 
+/// **Returns**  
+/// The client and the UUID of the session on success, or a ReplError otherwise (ReplError::SessionExpired if the session to attach to has expired, or ReplError::UnknownSession if the remote does not know it).
+async fn connect(remote: &str, options: &RemoteOptions, attach: Option<String>, client_id: &str, skip_version_check: bool) -> Result<(DriverClient, String), ReplError> {
+    let mut client = match options.connect(remote).await {
+        Ok(client) => client,
+        Err(err)   => { return Err(ReplError::ClientConnectError{ address: remote.to_string(), err }); }
+    };
+
+    let request = CreateSessionRequest {
+        client_version : Some(env!("CARGO_PKG_VERSION").to_string()),
+        attach         : attach.clone(),
+        client         : Some(client_id.to_string()),
+    };
+    let reply = match client.create_session(request).await {
+        Ok(reply) => reply.into_inner(),
+        Err(err)  => {
+            return Err(match attach {
+                Some(session) if is_expired_status(&err)      => ReplError::SessionExpired{ address: remote.to_string(), session },
+                Some(session) if err.code() == Code::NotFound => ReplError::UnknownSession{ address: remote.to_string(), session },
+                _                                             => ReplError::SessionCreateError{ address: remote.to_string(), err },
+            });
+        }
+    };
+    check_driver_version(remote, &reply, skip_version_check)?;
+
+    // Drivers that predate attaching through CreateSession hand out a new session instead, so use the given UUID regardless
+    Ok((client, attach.unwrap_or(reply.uuid)))
+}
+
+/// Reconnects to the given remote after we lost the connection, and reattaches to the given session. Retries with an exponential backoff, telling the user how it goes.
+/// 
+/// **Arguments**
+///  * `remote`: The address of the remote.
+///  * `options`: The RemoteOptions to connect with.
+///  * `session`: The session to reattach to.
+///  * `client_id`: The ID that identifies us to the remote.
+///  * `skip_version_check`: Whether to continue even if the remote's version is incompatible with ours.
+/// 
+/// **Returns**  
+/// The new client on success, or a ReplError if we could not reconnect in time (or the remote's version changed to an incompatible one, or it lost the session).
+async fn reconnect(remote: &str, options: &RemoteOptions, session: &str, client_id: &str, skip_version_check: bool) -> Result<DriverClient, ReplError> {
+    let mut delay = RECONNECT_MIN_DELAY;
+    for attempt in 1..=RECONNECT_ATTEMPTS {
+        println!("Reconnecting to '{}' (attempt {}/{})...", remote, attempt, RECONNECT_ATTEMPTS);
+        match connect(remote, options, Some(session.to_string()), client_id, skip_version_check).await {
+            Ok((client, _)) => {
+                println!("Reconnected to session '{}'.", session);
+                return Ok(client);
+            },
+            Err(err @ ReplError::VersionMismatch{ .. }) => { return Err(err); },
+            // Nor will it bring back a session that expired
+            Err(err @ ReplError::SessionExpired{ .. })  => { return Err(err); },
+            Err(err @ ReplError::UnknownSession{ .. })  => { return Err(err); },
+            // Trying again won't fix our credentials
+            Err(ReplError::SessionCreateError{ address, err }) if err.code() == Code::Unauthenticated => { return Err(ReplError::SessionCreateError{ address, err }); },
+            Err(err)                                    => { eprintln!("Could not reconnect: {}", err); },
+        }
+
+        if attempt < RECONNECT_ATTEMPTS {
+            tokio::time::sleep(delay).await;
+            delay = std::cmp::min(2 * delay, RECONNECT_MAX_DELAY);
+        }
+    }
+    Err(ReplError::ReconnectError{ address: remote.to_string(), session: session.to_string(), attempts: RECONNECT_ATTEMPTS })
+}
+
+/// Tells the remote that we're done with the given session, so it may forget the session's state right away instead of waiting for it to expire (unless other clients still use it).
+/// 
+/// **Arguments**
+///  * `client`: The client to the remote.
+///  * `session`: The session to close.
+///  * `client_id`: The ID that identifies us to the remote.
+async fn close_session(client: &mut DriverClient, session: &str, client_id: &str) {
+    match client.close_session(CloseSessionRequest{ uuid: session.to_string(), client: Some(client_id.to_string()) }).await {
+        Ok(reply) if reply.get_ref().clients > 0 => { debug!("Left session '{}', which {} other client(s) still use.", session, reply.get_ref().clients); },
+        Ok(_)       => { debug!("Closed session '{}'.", session); },
+        // Drivers that predate closing sessions let it expire instead
+        Err(status) => { debug!("Could not close session '{}': {}", session, status.message()); },
     }
+}
+
+/// Runs a single statement on the remote, printing its output as it comes in.
+/// 
+/// **Arguments**
+///  * `client`: The client to the remote.
+///  * `session`: The session to run the statement in.
+///  * `request`: The request with the statement.
+///  * `resent`: Whether we send the statement again after losing the connection (which makes us tell the user what happened to the earlier one).
+///  * `verbose`: Whether to print which variables the statement defined, removed or changed.
+/// 
+/// **Returns**  
+/// Nothing if the remote sent its closing reply, or a StatementError otherwise.
+async fn execute_statement(client: &mut DriverClient, session: &str, request: ExecuteRequest, mut resent: bool, verbose: bool) -> Result<(), StatementError> {
+    // Run it
+    let response = match client.execute(request).await {
+        Ok(response) => response,
+        Err(err)     => { return Err(StatementError::Request(err)); }
+    };
+    let mut stream = response.into_inner();
+
+    // Switch on the type of message that the remote returned
+    loop {
+        // Wait for the next message, but don't let Ctrl+C kill the whole client while the statement runs
+        let message = tokio::select! {
+            message = stream.message() => message,
+            _ = tokio::signal::ctrl_c() => {
+                // Ask the remote to cancel whatever this session is running instead; its reply will come in over the stream
+                match client.cancel(CancelRequest{ uuid: session.to_string() }).await {
+                    Ok(reply) => {
+                        let job_ids = reply.into_inner().job_ids;
+                        if job_ids.is_empty() { println!("\nNo running jobs to cancel."); }
+                        else { println!("\nCancelled job(s): {}", job_ids.join(", ")); }
+                    },
+                    Err(status) => { eprintln!("\nCould not cancel running jobs: {}", status.message()); },
+                };
+                continue;
+            },
+        };
 
-    fn validate_while_typing(&self) -> bool {
-        self.validator.validate_while_typing()
+        match message {
+            // The message itself went alright
+            Ok(Some(reply)) => {
+                // Tell the user whether the remote ran the statement again or already knew it
+                if resent {
+                    if reply.cached.unwrap_or(false) { println!("The statement already ran before the connection was lost; showing its result."); }
+                    else { println!("The statement never reached the remote; it runs now."); }
+                    resent = false;
+                }
+
+                // The remote send us some debug message
+                if let Some(debug) = reply.debug {
+                    debug!("Remote: {}", debug);
+                }
+
+                // The remote send us a normal text message
+                if let Some(stdout) = reply.stdout {
+                    debug!("Remote returned stdout");
+                    println!("{}", stdout);
+                }
+
+                // The remote send us an error
+                if let Some(stderr) = reply.stderr {
+                    debug!("Remote returned error");
+                    eprintln!("{}", stderr);
+                }
+
+                // The remote send us the trace of the statement
+                if let Some(entries) = reply.trace {
+                    match serde_json::from_str::<Vec<TraceEntry>>(&entries) {
+                        Ok(entries) => print_trace(&entries),
+                        Err(err)    => { eprintln!("Could not parse execution trace from remote: {}", err); },
+                    }
+                }
+
+                // The remote send us what the statement changed
+                if let Some(diff) = reply.diff.filter(|_| verbose) {
+                    match serde_json::from_str::<StateDiff>(&diff) {
+                        Ok(diff) => print_diff(&diff),
+                        Err(err) => { eprintln!("Could not parse state diff from remote: {}", err); },
+                    }
+                }
+
+                // The remote is done with this
+                if reply.close { return Ok(()); }
+            }
+            // Did not receive the message properly
+            Err(status) => { return Err(StatementError::Stream(status)); },
+            // The stream closed before the statement was done
+            Ok(None) => { return Err(StatementError::Closed); },
+        }
     }
 }
 
@@ -127,17 +793,30 @@ impl Validator for ReplHelper {
 ///  * `bakery`: Whether to use BraneScript (false) or Bakery (true).
 ///  * `clear`: Whether or not to clear the history of the REPL before beginning.
 ///  * `remote`: Whether or not to connect to a remote Brane Instance (address is given if Some).
+///  * `remote_options`: The RemoteOptions to connect to the remote with, if any.
 ///  * `attach`: If not None, defines the session ID of an existing session to connect to.
+///  * `follow`: Whether to also show the statements that other clients run in the remote session.
 ///  * `data`: Whether or not to mount a particular folder for the data directory.
+///  * `offline`: If true, fails instead of pulling images that are not available locally (only for the local REPL).
+///  * `args`: The script arguments to expose as the global `args` to every statement.
+///  * `skip_version_check`: Whether to connect to a remote even if its version is incompatible with ours.
+///  * `verbose`: Whether to print which variables every statement defined, removed or changed.
 /// 
 /// **Returns**  
 /// Nothing on success, or else a ReplError.
+#[allow(clippy::too_many_arguments)]
 pub async fn start(
     bakery: bool,
     clear: bool,
     remote: Option<String>,
+    remote_options: RemoteOptions,
     attach: Option<String>,
+    follow: bool,
     data: Option<PathBuf>,
+    offline: bool,
+    args: HashMap<String, Value>,
+    skip_version_check: bool,
+    verbose: bool,
 ) -> Result<(), ReplError> {
     // Build the config for the rustyline REPL.
     let config = Config::builder()
@@ -153,17 +832,15 @@ pub async fn start(
         highlighter: MatchingBracketHighlighter::new(),
         hinter: HistoryHinter {},
         colored_prompt: "".to_owned(),
-        validator: MatchingBracketValidator::new(),
     };
 
-    // Get the history file, clearing it if necessary
-    if let Err(err) = ensure_config_dir(true) { return Err(ReplError::ConfigDirCreateError{ err }); };
-    let history_file = match get_history_file() {
+    // Get the history file (making sure it exists), clearing it if necessary
+    let history_file = match ensure_history_file(true) {
         Ok(file) => file,
         Err(err) => { return Err(ReplError::HistoryFileError{ err }); }
     };
-    if clear && history_file.exists() {
-        if let Err(err) = fs::remove_file(&history_file) {
+    if clear {
+        if let Err(err) = fs::write(&history_file, "") {
             warn!("Could not clear REPL history: {}", err);
         };
     }
@@ -171,18 +848,19 @@ pub async fn start(
     // Create the REPL
     let mut rl = Editor::with_config(config);
     rl.set_helper(Some(repl_helper));
-    if let Err(err) = rl.load_history(&history_file) { warn!("Could not load REPL history from '{}': {}", history_file.display(), err); }
+    if let Err(err) = load_history(&mut rl, &history_file) { warn!("Could not load REPL history from '{}': {}", history_file.display(), err); }
 
     // Initialization done; run the REPL
-    println!("Welcome to the Brane REPL, press Ctrl+D to exit.\n");
+    println!("Welcome to the Brane REPL, press Ctrl+D to exit.");
+    println!("Use Ctrl+R to search the history, type '{}' to enter a block of statements or ':help' for more commands.\n", PASTE_COMMAND);
     if let Some(remote) = remote {
-        remote_repl(&mut rl, bakery, remote, attach).await?;
+        remote_repl(&mut rl, bakery, remote, remote_options, attach, follow, args, skip_version_check, verbose).await?;
     } else {
-        local_repl(&mut rl, bakery, data).await?;
+        local_repl(&mut rl, bakery, data, offline, args, verbose).await?;
     }
 
     // Try to save the history if we exited cleanly
-    if let Err(reason) = rl.save_history(&history_file) {
+    if let Err(reason) = save_history(&rl, &history_file) {
         warn!("Could not save session history to '{}': {}", history_file.display(), reason);
     }
 
@@ -198,103 +876,116 @@ pub async fn start(
 ///  * `rl`: The RustyLine editor that we use to get user input.
 ///  * `bakery`: Whether to use BraneScript (false) or Bakery (true).
 ///  * `remote`: The remote address to connect to.
+///  * `options`: The RemoteOptions to connect to the remote with.
 ///  * `attach`: If not None, defines the session ID of an existing session to connect to.
+///  * `follow`: Whether to also show the statements that other clients run in the session.
+///  * `args`: The script arguments that the remote exposes as `args`; sent along with every statement.
+///  * `skip_version_check`: Whether to continue even if the remote's version is incompatible with ours.
+///  * `verbose`: Whether to print which variables every statement defined, removed or changed.
 /// 
 /// **Returns**  
 /// Nothing on success, or else a ReplError.
+#[allow(clippy::too_many_arguments)]
 async fn remote_repl(
     rl: &mut Editor<ReplHelper>,
     _bakery: bool,
     remote: String,
+    options: RemoteOptions,
     attach: Option<String>,
+    follow: bool,
+    args: HashMap<String, Value>,
+    skip_version_check: bool,
+    verbose: bool,
 ) -> Result<(), ReplError> {
-    // Connect to the server with gRPC
-    let mut client = match DriverServiceClient::connect(remote.clone()).await {
-        Ok(client) => client,
-        Err(err)   => { return Err(ReplError::ClientConnectError{ address: remote, err }); }
-    };
+    // Only send arguments if there are any, so attaching to a session does not reset the ones it has
+    let args = if args.is_empty() { None } else { Some(args_to_json(&args)) };
 
-    // Either use the given Session UUID or create a new one (with matching session)
-    let session = if let Some(attach) = attach {
-        attach.clone()
-    } else {
-        // Setup a new session
-        let request = CreateSessionRequest {};
-        let reply = match client.create_session(request).await {
-            Ok(reply) => reply,
-            Err(err)  => { return Err(ReplError::SessionCreateError{ address: remote, err }); }
-        };
+    // Identify ourselves to the remote and with every statement, so it knows when we're done with the session and we can tell our statements apart from those of other clients in it
+    let client_id = Uuid::new_v4().to_string();
 
-        // Return the UUID of this session
-        reply.into_inner().uuid.clone()
-    };
+    // Connect to the server with gRPC, either attaching to the given session or creating a new one
+    let (mut client, session) = connect(&remote, &options, attach, &client_id, skip_version_check).await?;
+    let follower = if follow { Some(tokio::spawn(follow_session(client.clone(), session.clone(), client_id.clone()))) } else { None };
 
     // With the status setup, enter the L in the REPL
     let mut count: u32 = 1;
+    let mut trace = false;
+    let mut dry_run = false;
     loop {
-        // Prepare the prompt with the current iteration number
-        let p = format!("{}> ", count);
-
-        // Write the prompt in a coloured way
-        rl.helper_mut().expect("No helper").colored_prompt = format!("\x1b[1;32m{}\x1b[0m", p);
-
-        // Wait until the user provided us with some command
-        let readline = rl.readline(&p);
-        match readline {
+        // Wait until the user provided us with some (complete) statement
+        match read_statement(rl, count) {
+            Ok(line) if parse_meta_command(&line).is_some() => {
+                match parse_meta_command(&line).unwrap() {
+                    command @ (MetaCommand::Vars | MetaCommand::Funcs | MetaCommand::Packages | MetaCommand::Doc(_)) => {
+                        // Ask the remote what the session has defined so far
+                        match client.get_globals(GetGlobalsRequest{ uuid: session.clone() }).await {
+                            Ok(reply) => {
+                                let reply = reply.into_inner();
+                                match command {
+                                    MetaCommand::Vars  => print_variables(reply.variables.into_iter().map(|v| (v.name, v.data_type)).collect()),
+                                    MetaCommand::Funcs => print_functions(reply.functions.into_iter().map(|f| (f.name, f.signature)).collect()),
+                                    MetaCommand::Doc(name) => print_doc(name, reply.functions.into_iter().find(|f| f.name == name).map(|f| f.doc.unwrap_or(f.signature))),
+                                    _                  => print_packages(reply.packages.into_iter().map(|p| (p.name, p.version)).collect()),
+                                }
+                            },
+                            Err(err) if is_expired_status(&err) => { return Err(ReplError::SessionExpired{ address: remote, session }); },
+                            Err(err) => { eprintln!("{}", ReplError::GlobalsRequestError{ address: remote.clone(), err }); },
+                        }
+                    },
+                    MetaCommand::StateSave(_) | MetaCommand::StateLoad(_) => { eprintln!(":state is not supported in remote sessions"); },
+                    MetaCommand::Unimport(_)                             => { eprintln!(":unimport is not supported in remote sessions"); },
+                    MetaCommand::Trace => {
+                        // The remote traces each statement that we ask it to
+                        trace = !trace;
+                        println!("Execution trace {}", if trace { "enabled" } else { "disabled" });
+                    },
+                    MetaCommand::DryRun => {
+                        // The remote simulates each statement that we ask it to
+                        dry_run = !dry_run;
+                        println!("Dry run {}", if dry_run { "enabled: external functions are only checked, not run" } else { "disabled" });
+                    },
+                    MetaCommand::Help                                    => { println!("{}", META_COMMANDS_HELP); },
+                }
+            },
             Ok(line) => {
-                // The command checked out, so add it to the history
-                rl.add_history_entry(line.as_str());
-
-                // Prepare the request to execute this command
+                // Prepare the request to execute this command, with a token so we can safely send it again if the connection drops
                 let request = ExecuteRequest {
                     uuid: session.clone(),
                     input: line.clone(),
+                    args: args.clone(),
+                    trace: Some(trace),
+                    token: Some(Uuid::new_v4().to_string()),
+                    dry_run: Some(dry_run),
+                    client: Some(client_id.clone()),
+                    diff: Some(verbose),
                 };
 
-                // Run it
-                let response = match client.execute(request).await {
-                    Ok(response) => response,
-                    Err(err)     => { return Err(ReplError::CommandRequestError{ address: remote, err }); }
-                };
-                let mut stream = response.into_inner();
-
-                // Switch on the type of message that the remote returned
-                #[allow(irrefutable_let_patterns)]
-                while let message = stream.message().await {
-                    match message {
-                        // The message itself went alright
-                        Ok(Some(reply)) => {
-                            // The remote send us some debug message
-                            if let Some(debug) = reply.debug {
-                                debug!("Remote: {}", debug);
-                            }
-
-                            // The remote send us a normal text message
-                            if let Some(stdout) = reply.stdout {
-                                debug!("Remote returned stdout");
-                                println!("{}", stdout);
-                            }
-
-                            // The remote send us an error
-                            if let Some(stderr) = reply.stderr {
-                                debug!("Remote returned error");
-                                eprintln!("{}", stderr);
+                // Run it, reconnecting (and sending it again) as long as we lose the connection
+                let mut resent = false;
+                loop {
+                    match execute_statement(&mut client, &session, request.clone(), resent, verbose).await {
+                        Ok(()) => { break; },
+                        Err(err) if err.is_connection_lost() => {
+                            match &err {
+                                StatementError::Request(status) | StatementError::Stream(status) => { eprintln!("\nLost the connection to '{}': {}", remote, status.message()); },
+                                StatementError::Closed                                            => { eprintln!("\nLost the connection to '{}'", remote); },
                             }
-
-                            // The remote is done with this
-                            if reply.close {
-                                break;
-                            }
-                        }
-                        Err(status) => {
+                            client = reconnect(&remote, &options, &session, &client_id, skip_version_check).await?;
+                            resent = true;
+                        },
+                        Err(StatementError::Request(err)) if is_expired_status(&err) => { return Err(ReplError::SessionExpired{ address: remote, session }); },
+                        Err(StatementError::Request(status)) if is_busy_status(&status) || status.code() == Code::Unavailable => {
+                            // The remote refused the statement for now (e.g., because another client's statement is running); the user may try again
+                            eprintln!("\nStatement refused: {}", status.message());
+                            break;
+                        },
+                        Err(StatementError::Request(err)) => { return Err(ReplError::CommandRequestError{ address: remote, err }); },
+                        Err(StatementError::Stream(status)) => {
                             // Did not receive the message properly
                             eprintln!("\nStatus error: {}", status.message());
                             break;
-                        }
-                        Ok(None) => {
-                            // Stream closed(?)
-                            break;
-                        }
+                        },
+                        Err(StatementError::Closed) => { unreachable!("A stream that closes early always means the connection was lost"); },
                     }
                 }
             }
@@ -315,18 +1006,54 @@ async fn remote_repl(
         count += 1;
     }
 
-    // Exit cleanly
+    // Exit cleanly, closing the session (which the remote only does once no other client uses it)
+    if let Some(follower) = follower { follower.abort(); }
+    close_session(&mut client, &session, &client_id).await;
     Ok(())
 }
 
 
 
+/// Prints the statements that other clients run in the given session, and their output, as they come in.
+/// 
+/// **Arguments**
+///  * `client`: The client to the remote.
+///  * `session`: The session to follow.
+///  * `client_id`: The ID that we send our own statements with, which we leave out (as `execute_statement()` prints those).
+async fn follow_session(mut client: DriverClient, session: String, client_id: String) {
+    let mut stream = match client.follow(FollowRequest{ uuid: session.clone() }).await {
+        Ok(response) => response.into_inner(),
+        Err(status)  => { eprintln!("Could not follow session '{}': {}", session, status.message()); return; }
+    };
+
+    loop {
+        let reply = match stream.message().await {
+            Ok(Some(reply)) => reply,
+            Ok(None)        => { eprintln!("\nNo longer following session '{}'.", session); return; },
+            Err(status)     => { eprintln!("\nNo longer following session '{}': {}", session, status.message()); return; },
+        };
+        if reply.client.as_deref() == Some(client_id.as_str()) { continue; }
+
+        // Name the statement by its number and (the start of) the ID of the client that sent it
+        let who = reply.client.as_deref().map(|client| client.chars().take(8).collect::<String>()).unwrap_or_else(|| String::from("unknown"));
+        let prefix = format!("[#{} {}]", reply.sequence.unwrap_or_default(), who);
+        if let Some(input) = reply.input { println!("\n{} > {}", prefix, input.trim_end()); }
+        if let Some(stdout) = reply.stdout { println!("{} {}", prefix, stdout); }
+        if let Some(stderr) = reply.stderr { eprintln!("{} {}", prefix, stderr); }
+    }
+}
+
+
+
 /// Implements a REPL that runs stuff on the local Docker daemon.
 /// 
 /// *Arguments**
 ///  * `rl`: The RustyLine editor that we use to get user input.
 ///  * `bakery`: Whether to use BraneScript (false) or Bakery (true).
 ///  * `data`: Whether or not to mount a particular folder for the data directory.
+///  * `offline`: If true, fails instead of pulling images that are not available locally.
+///  * `args`: The script arguments to expose as the global `args`.
+///  * `verbose`: Whether to print which variables every statement defined, removed or changed.
 /// 
 /// **Returns**  
 /// Nothing on success, or else a ReplError.
@@ -334,6 +1061,9 @@ async fn local_repl(
     rl: &mut Editor<ReplHelper>,
     bakery: bool,
     data: Option<PathBuf>,
+    offline: bool,
+    args: HashMap<String, Value>,
+    verbose: bool,
 ) -> Result<(), ReplError> {
     // Setup the compiler options for the appropriate language
     let compiler_options = if bakery {
@@ -352,39 +1082,53 @@ async fn local_repl(
     let mut compiler = Compiler::new(compiler_options, package_index.clone());
 
     // Initialize the local executor
-    let executor = DockerExecutor::new(data);
+    let executor = DockerExecutor::new(data, offline);
     let options = VmOptions {
         clear_after_main: true,
+        max_instructions: Some(REPL_MAX_INSTRUCTIONS),
+        compact_print: true,
         ..Default::default()
     };
-    let mut vm = match Vm::new_with(executor, Some(package_index), Some(options)) {
+    let mut vm = match Vm::new_with(executor.clone(), Some(package_index), Some(options)) {
         Ok(vm)   => vm,
         Err(err) => { return Err(ReplError::VmCreateError{ err }); }
     };
+    if let Err(err) = vm.set_args(args) { return Err(ReplError::VmArgsError{ err }); }
 
     // With the VM setup, enter the L in the REPL
     let mut count: u32 = 1;
+    let mut trace = false;
+    let mut dry_run = false;
     loop {
-        // Prepare the prompt with the current iteration number
-        let p = format!("{}> ", count);
-
-        // Write the prompt in a coloured way
-        rl.helper_mut().expect("No helper").colored_prompt = format!("\x1b[1;32m{}\x1b[0m", p);
-
-        // Wait until the user provided us with some command
-        let readline = rl.readline(&p);
-        match readline {
+        // Wait until the user provided us with some (complete) statement
+        match read_statement(rl, count) {
+            Ok(line) if parse_meta_command(&line).is_some() => {
+                local_meta_command(parse_meta_command(&line).unwrap(), &mut vm, &mut compiler, &executor, &mut trace, &mut dry_run);
+            },
             Ok(line) => {
-                // The command checked out, so add it to the history
-                rl.add_history_entry(line.as_str());
-
                 // Compile it
                 match compiler.compile(line) {
                     Ok(function) => {
-                        // Call the virtual machine to execute the instructions
-                        if let Err(reason) = vm.main(function).await {
-                            // Do not throw an error, but simply write what went wrong and allow the user to try again
-                            eprintln!("{}", reason);
+                        // A dry run happens on a copy of the session that is thrown away afterwards, so the values it simulates don't end up in the session
+                        let mut simulation = None;
+                        if dry_run {
+                            match Vm::new_with_state(executor.clone(), Some(compiler.package_index.clone()), vm.capture_state()) {
+                                Ok(mut copy) => { copy.set_dry_run(true); simulation = Some(copy); },
+                                Err(err)     => eprintln!("{}", ReplError::VmCreateError{ err }),
+                            }
+                        }
+
+                        if !dry_run || simulation.is_some() {
+                            let vm = simulation.as_mut().unwrap_or(&mut vm);
+
+                            // Call the virtual machine to execute the instructions
+                            let before = if verbose { Some(vm.capture_state()) } else { None };
+                            if let Err(reason) = vm.main(function).await {
+                                // Do not throw an error, but simply write what went wrong and allow the user to try again
+                                eprintln!("{}", reason);
+                            }
+                            if trace { print_trace(&vm.take_trace()); }
+                            if let Some(before) = before { print_diff(&before.diff(&vm.capture_state())); }
                         }
                     },
                     Err(error) => eprintln!("{:?}", error),
@@ -1,40 +1,366 @@
 use crate::{docker::DockerExecutor, packages};
-use anyhow::Result;
-use brane_bvm::vm::Vm;
+use crate::errors::{OfflineError, RunError};
+use crate::sandbox::SandboxOptions;
+use anyhow::{Context, Result};
+use brane_bvm::args::{args_from_json, parse_args};
+use brane_bvm::executor::{ExecutorError, VmExecutor};
+use brane_bvm::trace::TraceEntry;
+use brane_bvm::vm::{Vm, VmError, VmOptions};
 use brane_dsl::{Compiler, CompilerOptions, Lang};
+use console::{pad_str, Alignment};
+use prettytable::format::FormatBuilder;
+use prettytable::Table;
+use serde::Serialize;
+use specifications::common::Value;
+use specifications::package::PackageIndex;
+use specifications::pretty;
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-///
-///
-///
+/// Collects the arguments for a script from an optional JSON file and a list of 'key=value' pairs, where the latter take precedence.
+/// 
+/// **Arguments**
+///  * `args`: The 'key=value' pairs given on the command line.
+///  * `args_json`: The path to a file with a JSON object of arguments, if any.
+/// 
+/// **Returns**  
+/// The arguments to expose to the script, or an error if any of them could not be parsed.
+pub fn collect_args(
+    args: Vec<String>,
+    args_json: Option<PathBuf>,
+) -> Result<HashMap<String, Value>> {
+    let mut result = match args_json {
+        Some(path) => {
+            let json = fs::read_to_string(&path).with_context(|| format!("Could not read script arguments from '{}'", path.display()))?;
+            args_from_json(&json).with_context(|| format!("Could not parse script arguments in '{}'", path.display()))?
+        },
+        None => HashMap::new(),
+    };
+    result.extend(parse_args(&args)?);
+    Ok(result)
+}
+
+
+
+/// Categorizes why `brane run` failed, which decides its exit code.
+/// 
+/// The exit codes are: 0 on success, 1 for anything that is not the script's fault (e.g., an unreadable file), 2 for compile and VM errors, 3 if an external call failed and 4 if the infrastructure could not be reached.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// Something went wrong before the script got to run (reading it, loading the package index, writing the report, ...)
+    Other,
+    /// The script could not be compiled
+    Compile,
+    /// The script failed in the VM (e.g., a type error)
+    Runtime,
+    /// An external function was called but failed (e.g., it returned a non-zero exit code)
+    ExternalCall,
+    /// An external function could not be called because we could not reach the infrastructure (e.g., Docker)
+    Infrastructure,
+}
+
+impl ErrorCategory {
+    /// Returns the exit code of `brane run` for this category.
+    #[inline]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorCategory::Other          => 1,
+            ErrorCategory::Compile        => 2,
+            ErrorCategory::Runtime        => 2,
+            ErrorCategory::ExternalCall   => 3,
+            ErrorCategory::Infrastructure => 4,
+        }
+    }
+}
+
+
+
+/// Whether a script ran successfully, as written to the result report.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    /// The script ran to completion
+    Success,
+    /// The script (or running it) failed
+    Failure,
+}
+
+/// The JSON report that `brane run --result-out` writes.
+#[derive(Clone, Debug, Serialize)]
+pub struct RunReport {
+    /// Whether the script succeeded.
+    pub status    : RunStatus,
+    /// The exit code of `brane run`.
+    pub exit_code : i32,
+    /// What kind of error made the script fail, if it did.
+    pub category  : Option<ErrorCategory>,
+    /// The error message, if the script failed.
+    pub message   : Option<String>,
+    /// The line in the script where it failed, if known.
+    pub line      : Option<usize>,
+    /// The value that the script returned with a top-level `return`, if any.
+    pub value     : Option<serde_json::Value>,
+}
+
+impl RunReport {
+    /// Constructor for the RunReport, which describes the given result of running a script.
+    /// 
+    /// **Arguments**
+    ///  * `result`: The result of `run_script()`.
+    pub fn new(result: &Result<Option<Value>, RunError>) -> Self {
+        match result {
+            Ok(value) => Self {
+                status    : RunStatus::Success,
+                exit_code : 0,
+                category  : None,
+                message   : None,
+                line      : None,
+                value     : value.as_ref().map(|value| value.as_json()),
+            },
+            Err(err) => {
+                let category = error_category(err);
+                Self {
+                    status    : RunStatus::Failure,
+                    exit_code : category.exit_code(),
+                    category  : Some(category),
+                    message   : Some(format!("{}", err)),
+                    line      : match err { RunError::ExecutionError{ err } => err.line(), _ => None },
+                    value     : None,
+                }
+            },
+        }
+    }
+
+    /// Writes the report as JSON to the given file.
+    /// 
+    /// **Arguments**
+    ///  * `path`: The file to write to. Is overwritten if it already exists.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or a RunError otherwise.
+    pub fn write(&self, path: &Path) -> Result<(), RunError> {
+        let json = serde_json::to_string_pretty(self).map_err(|err| RunError::ReportSerializeError{ err })?;
+        fs::write(path, json).map_err(|err| RunError::ReportWriteError{ path: path.to_path_buf(), err })
+    }
+}
+
+
+
+/// Decides which category the given error falls in.
+/// 
+/// **Arguments**
+///  * `err`: The RunError to categorize.
+/// 
+/// **Returns**  
+/// The ErrorCategory of the error.
+pub fn error_category(err: &RunError) -> ErrorCategory {
+    match err {
+        RunError::CompileError{ .. } => ErrorCategory::Compile,
+
+        RunError::VmCreateError{ err }    |
+        RunError::VmArgsError{ err }      |
+        RunError::DisassembleError{ err } |
+        RunError::ExecutionError{ err }   => vm_error_category(err),
+
+        RunError::ScriptReadError{ .. }      |
+        RunError::PackageIndexError{ .. }    |
+        RunError::ReportSerializeError{ .. } |
+        RunError::ReportWriteError{ .. }     => ErrorCategory::Other,
+    }
+}
+
+/// Decides which category the given VM error falls in.
+fn vm_error_category(err: &VmError) -> ErrorCategory {
+    match err.inner() {
+        VmError::ExternalCallError{ err, .. } => executor_error_category(err),
+        VmError::ClientTxError{ .. }          => ErrorCategory::Infrastructure,
+        _                                     => ErrorCategory::Runtime,
+    }
+}
+
+/// Decides which category the given executor error falls in.
+fn executor_error_category(err: &ExecutorError) -> ErrorCategory {
+    use ExecutorError::*;
+    match err {
+        // The script asked for something impossible
+        UnsupportedError{ .. } | IllegalArguments{ .. } => ErrorCategory::Runtime,
+
+        // The call itself went wrong
+        ExternalCallError{ .. } | ExternalCallFailed{ .. } | ExternalCallFailedRaw{ .. } | OutputDecodeError{ .. } | ServiceFailed{ .. } => ErrorCategory::ExternalCall,
+
+        // Everything else means the environment of the call is broken (Docker, Kafka, the data directory, the local package, ...)
+        IllegalDataDir{ .. } | DataDirDoesntExist{ .. } | UnreadableDataDir{ .. } | IllegalDataDirColon{ .. } |
+        PackageDirError{ .. } | PackageInfoError{ .. } |
+        ImageReadError{ .. } | DockerConnectionFailed{ .. } | DockerImportError{ .. } | DockerCreateImageError{ .. } | OfflineImageError{ .. } |
+        DockerCreateContainerError{ .. } | DockerStartError{ .. } | DockerSandboxError{ .. } | DockerWaitError{ .. } | DockerLogsError{ .. } |
+        DockerInspectContainerError{ .. } | DockerRemoveContainerError{ .. } | DockerRemoveImageError{ .. } |
+        DockerContainerNoState{ .. } | DockerContainerNoExitCode{ .. } | DockerContainerNoNetwork{ .. } |
+        InvalidSessionIdError{ .. } | UnknownLocationError{ .. } | LocationSelectionError{ .. } |
+        CommandScheduleError{ .. } | ClientTxError{ .. } | ClientBackpressure{ .. } => ErrorCategory::Infrastructure,
+    }
+}
+
+
+
+/// Returns what we could not get because we ran in offline mode, if that is why the given error occurred.
+/// 
+/// **Arguments**
+///  * `err`: The RunError to examine.
+/// 
+/// **Returns**  
+/// The matching OfflineError if the script failed because an image was not available offline, or None otherwise.
+pub fn offline_error(err: &RunError) -> Option<OfflineError> {
+    if let RunError::ExecutionError{ err } = err {
+        if let VmError::ExternalCallError{ err: ExecutorError::OfflineImageError{ image }, .. } = err.inner() {
+            return Some(OfflineError::MissingImage{ image: image.clone() });
+        }
+    }
+    None
+}
+
+
+
+/// **Edited: now returning RunErrors (and thus failing with the proper exit code), and writing a result report if asked.**
+/// 
+/// Runs the given script locally, on the local Docker daemon, and prints the value that it returns with a top-level `return` (if any).
+/// 
+/// **Arguments**
+///  * `file`: The script to run.
+///  * `data`: The directory to mount as /data in the packages, if any.
+///  * `show_bytecode`: Whether to print the compiled script before running it.
+///  * `args`: The arguments to pass to the script.
+///  * `result_out`: If given, writes a JSON report of how the script went (see RunReport) to this file.
+///  * `offline`: If true, external calls fail instead of pulling images that are not available locally.
+///  * `trace`: If true, prints a table with the external function calls that the script made once it's done.
+///  * `dry_run`: If true, only checks the external function calls instead of running them, continuing with default values for what they return.
+///  * `max_instructions`: If given, aborts the script once it has executed this many instructions.
+///  * `sandbox`: The options that restrict the containers of the packages.
+/// 
+/// **Returns**  
+/// Nothing if the script ran successfully, or a RunError otherwise (see `error_category()` for the exit code it implies).
+#[allow(clippy::too_many_arguments)]
 pub async fn handle(
     file: PathBuf,
     data: Option<PathBuf>,
-) -> Result<()> {
-    let source_code = fs::read_to_string(&file)?;
+    show_bytecode: bool,
+    args: HashMap<String, Value>,
+    result_out: Option<PathBuf>,
+    offline: bool,
+    trace: bool,
+    dry_run: bool,
+    max_instructions: Option<u64>,
+    sandbox: SandboxOptions,
+) -> Result<(), RunError> {
+    let result = run_file(&file, data, show_bytecode, args, offline, trace, dry_run, max_instructions, sandbox).await;
+    if let Ok(Some(value)) = &result { println!("{}", pretty::pretty(value)); }
 
+    if let Some(result_out) = result_out {
+        if let Err(err) = RunReport::new(&result).write(&result_out) {
+            // Don't hide why the script failed, if it did
+            if let Err(reason) = result { eprintln!("{}", reason); }
+            return Err(err);
+        }
+    }
+    result.map(|_| ())
+}
+
+/// Runs the given script file with the DockerExecutor.
+#[allow(clippy::too_many_arguments)]
+async fn run_file(
+    file: &Path,
+    data: Option<PathBuf>,
+    show_bytecode: bool,
+    args: HashMap<String, Value>,
+    offline: bool,
+    trace: bool,
+    dry_run: bool,
+    max_instructions: Option<u64>,
+    sandbox: SandboxOptions,
+) -> Result<Option<Value>, RunError> {
+    let source_code = fs::read_to_string(file).map_err(|err| RunError::ScriptReadError{ path: file.to_path_buf(), err })?;
+    let package_index = packages::get_package_index().map_err(|err| RunError::PackageIndexError{ err })?;
+    run_script(&source_code, DockerExecutor::new(data, offline).with_sandbox(sandbox), package_index, args, show_bytecode, trace, dry_run, max_instructions).await
+}
+
+/// Compiles and runs the given script with the given executor.
+/// 
+/// The script may end with a top-level `return`, whose value is returned.
+/// 
+/// **Arguments**
+///  * `source_code`: The BraneScript to run.
+///  * `executor`: The executor that performs the external calls.
+///  * `package_index`: The packages that the script may import.
+///  * `args`: The arguments to pass to the script.
+///  * `show_bytecode`: Whether to print the compiled script before running it.
+///  * `trace`: Whether to print the external function calls that the script made after running it (also if it failed).
+///  * `dry_run`: Whether to simulate the external function calls instead of performing them (see `VmOptions::dry_run`).
+///  * `max_instructions`: The maximum number of instructions the script may execute, if any.
+/// 
+/// **Returns**  
+/// The value returned by the script (if any), or a RunError if it failed.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_script<E>(
+    source_code: &str,
+    executor: E,
+    package_index: PackageIndex,
+    args: HashMap<String, Value>,
+    show_bytecode: bool,
+    trace: bool,
+    dry_run: bool,
+    max_instructions: Option<u64>,
+) -> Result<Option<Value>, RunError>
+where
+    E: 'static + VmExecutor + Clone + Send + Sync,
+{
     let compiler_options = CompilerOptions::new(Lang::BraneScript);
-    let package_index = packages::get_package_index()?;
     let mut compiler = Compiler::new(compiler_options, package_index.clone());
+    let function = compiler.compile(source_code).map_err(|err| RunError::CompileError{ err })?;
 
-    let executor = DockerExecutor::new(data);
-    let mut vm = match Vm::new_with(executor, Some(package_index), None) {
-        Ok(vm)      => vm,
-        Err(reason) => { eprintln!("Could not create VM: {}", reason); return Ok(()); }
-    };
+    let options = VmOptions{ global_return_halts: true, trace, max_instructions, dry_run, ..Default::default() };
+    let mut vm = Vm::new_with(executor, Some(package_index), Some(options)).map_err(|err| RunError::VmCreateError{ err })?;
+    vm.set_args(args).map_err(|err| RunError::VmArgsError{ err })?;
 
-    match compiler.compile(source_code) {
-        /* TIM */
-        // Ok(function) => vm.main(function).await,
-        Ok(function) => {
-            if let Err(reason) = vm.main(function).await {
-                eprintln!("{}", reason);
-            }
-        }
-        /*******/
-        Err(error) => eprintln!("{:?}", error),
+    if show_bytecode {
+        let bytecode = vm.disassemble_main(&function).map_err(|err| RunError::DisassembleError{ err })?;
+        println!("{}", bytecode);
+    }
+
+    let result = vm.main(function).await;
+    if trace { print_trace(&vm.take_trace()); }
+    result.map_err(|err| RunError::ExecutionError{ err })?;
+    Ok(vm.take_main_result().filter(|value| !matches!(value, Value::Unit)))
+}
+
+/// Prints the given execution trace as a table, one external function call per row.
+/// 
+/// **Arguments**
+///  * `trace`: The calls recorded by the VM.
+pub fn print_trace(trace: &[TraceEntry]) {
+    if trace.is_empty() { println!("No external functions were called."); return; }
+
+    let format = FormatBuilder::new()
+        .column_separator('\0')
+        .borders('\0')
+        .padding(1, 1)
+        .build();
+    let mut table = Table::new();
+    table.set_format(format);
+    table.add_row(row!["FUNCTION", "PACKAGE", "LOCATION", "ARGUMENTS", "DURATION", "OUTCOME"]);
+
+    for entry in trace {
+        let package = format!("{} {}", entry.package, entry.version);
+        let location = entry.location.as_deref().unwrap_or("-");
+        let arguments = pad_str(&entry.arguments, 50, Alignment::Left, Some(".."));
+        let duration = format!("{:.3}s", entry.duration().as_secs_f64());
+        // Errors of external calls span many lines, which wouldn't fit a row
+        let outcome = format!("{}", entry.outcome);
+        let outcome = outcome.lines().next().unwrap_or_default();
+        let outcome = if entry.simulated { format!("{} (simulated)", outcome) } else { outcome.to_string() };
+        table.add_row(row![entry.function, package, location, arguments, duration, outcome]);
     }
 
-    Ok(())
+    println!();
+    table.printstd();
 }
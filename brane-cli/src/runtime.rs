@@ -0,0 +1,362 @@
+/* RUNTIME.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 09:58:20
+ * Last edited:
+ *   15 Oct 2026, 09:58:20
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Finds and identifies the container runtime that the CLI talks to:
+ *   Docker (rootful or rootless) or Podman through its Docker-compatible
+ *   socket. Also contains the few places where the two differ in ways
+ *   that matter to us, like how images are built without buildx.
+**/
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use bollard::{Docker, API_DEFAULT_VERSION};
+use tokio::process::Command;
+
+use specifications::version::{ParseError as VersionParseError, Version};
+
+use crate::{MIN_DOCKER_VERSION, MIN_PODMAN_VERSION};
+
+
+/***** CONSTANTS *****/
+/// The socket that a rootful Docker daemon listens on.
+pub const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+
+/// The timeout (in seconds) of requests to the container runtime when we connect to a socket ourselves (the same as bollard's default).
+const SOCKET_TIMEOUT: u64 = 120;
+
+
+
+
+
+/***** GLOBALS *****/
+lazy_static! {
+    /// The container runtime that the user asked for (`--container-runtime`). Set once from the command line.
+    static ref RUNTIME_CHOICE: RwLock<RuntimeChoice> = RwLock::new(RuntimeChoice::Auto);
+    /// The container runtime that we found, once we looked for it.
+    static ref RUNTIME_INFO: RwLock<Option<RuntimeInfo>> = RwLock::new(None);
+}
+
+
+
+
+
+/***** ERRORS *****/
+/// Collects errors that relate to choosing the container runtime.
+#[derive(Debug)]
+pub enum RuntimeError {
+    /// The given runtime is not one we know
+    IllegalChoice{ raw: String },
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            RuntimeError::IllegalChoice{ raw } => write!(f, "Unknown container runtime '{}' (expected 'docker', 'podman' or 'auto')", raw),
+        }
+    }
+}
+
+impl Error for RuntimeError {}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Defines which container runtime the user wants us to use.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RuntimeChoice {
+    /// Find out from the runtime behind the socket
+    Auto,
+    /// Always treat the runtime as Docker
+    Docker,
+    /// Always treat the runtime as Podman
+    Podman,
+}
+
+impl Display for RuntimeChoice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            RuntimeChoice::Auto   => write!(f, "auto"),
+            RuntimeChoice::Docker => write!(f, "docker"),
+            RuntimeChoice::Podman => write!(f, "podman"),
+        }
+    }
+}
+
+impl FromStr for RuntimeChoice {
+    type Err = RuntimeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto"   => Ok(RuntimeChoice::Auto),
+            "docker" => Ok(RuntimeChoice::Docker),
+            "podman" => Ok(RuntimeChoice::Podman),
+            _        => Err(RuntimeError::IllegalChoice{ raw: s.to_string() }),
+        }
+    }
+}
+
+
+
+/// Defines the container runtimes that we know how to work with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ContainerRuntime {
+    /// Docker, rootful or rootless
+    Docker,
+    /// Podman, through its Docker-compatible API
+    Podman,
+}
+
+impl ContainerRuntime {
+    /// Returns the command-line tool of this runtime.
+    #[inline]
+    pub fn command(&self) -> &'static str {
+        match self {
+            ContainerRuntime::Docker => "docker",
+            ContainerRuntime::Podman => "podman",
+        }
+    }
+
+    /// Returns the minimum version of this runtime that Brane works with.
+    #[inline]
+    pub fn min_version(&self) -> Version {
+        match self {
+            ContainerRuntime::Docker => MIN_DOCKER_VERSION,
+            ContainerRuntime::Podman => MIN_PODMAN_VERSION,
+        }
+    }
+}
+
+impl Display for ContainerRuntime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            ContainerRuntime::Docker => write!(f, "Docker"),
+            ContainerRuntime::Podman => write!(f, "Podman"),
+        }
+    }
+}
+
+
+
+/// Defines how we build package images.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BuildBackend {
+    /// With `docker buildx build`
+    Buildx,
+    /// With `podman build` (and `podman save` to write the image.tar)
+    PodmanBuild,
+}
+
+
+
+/// Describes the container runtime that we found, and what it can do.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RuntimeInfo {
+    /// The kind of runtime
+    pub runtime : ContainerRuntime,
+    /// The version of the runtime, if we could get it
+    pub version : Option<Version>,
+    /// Whether `docker buildx` is available
+    pub buildx  : bool,
+}
+
+impl RuntimeInfo {
+    /// Returns how we build images with this runtime: with buildx if we can, or else with the runtime's own build command.
+    #[inline]
+    pub fn build_backend(&self) -> BuildBackend {
+        if self.buildx || self.runtime == ContainerRuntime::Docker { BuildBackend::Buildx } else { BuildBackend::PodmanBuild }
+    }
+
+    /// Returns the features of Brane that are not available with this runtime.
+    pub fn unavailable_features(&self) -> Vec<&'static str> {
+        let mut features = vec![];
+        if self.build_backend() == BuildBackend::PodmanBuild {
+            features.push("building for multiple platforms and pushing images while building ('--platform' with more than one platform, '--push'), which require buildx");
+        }
+        features
+    }
+}
+
+impl Display for RuntimeInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match &self.version {
+            Some(version) => write!(f, "{} {}", self.runtime, version),
+            None          => write!(f, "{} (unknown version)", self.runtime),
+        }
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Sets the container runtime that the user asked for.
+/// 
+/// **Arguments**
+///  * `choice`: The runtime to use, or RuntimeChoice::Auto to find out.
+pub fn set_runtime_choice(choice: RuntimeChoice) {
+    *RUNTIME_CHOICE.write().unwrap() = choice;
+}
+
+/// Returns the container runtime that the user asked for.
+#[inline]
+pub fn runtime_choice() -> RuntimeChoice {
+    *RUNTIME_CHOICE.read().unwrap()
+}
+
+
+
+/// Returns the sockets where we look for a container runtime, in order of preference.
+/// 
+/// **Arguments**
+///  * `docker_host`: The value of `DOCKER_HOST`, if set.
+///  * `xdg_runtime_dir`: The value of `XDG_RUNTIME_DIR`, if set (where rootless Docker and Podman put their sockets).
+/// 
+/// **Returns**  
+/// The paths of the sockets. If `DOCKER_HOST` is set, only the socket it names is returned (which is none if it's not a unix socket).
+pub fn socket_candidates(docker_host: Option<&str>, xdg_runtime_dir: Option<&str>) -> Vec<PathBuf> {
+    if let Some(docker_host) = docker_host {
+        return docker_host.strip_prefix("unix://").map(PathBuf::from).into_iter().collect();
+    }
+
+    let mut candidates = vec![ PathBuf::from(DEFAULT_SOCKET) ];
+    if let Some(dir) = xdg_runtime_dir {
+        candidates.push(Path::new(dir).join("docker.sock"));
+        candidates.push(Path::new(dir).join("podman").join("podman.sock"));
+    }
+    candidates.push(PathBuf::from("/run/podman/podman.sock"));
+    candidates
+}
+
+/// Returns the socket of the container runtime that we connect to.
+/// 
+/// **Returns**  
+/// The path of the first socket of socket_candidates() that exists, or None if there is none (or `DOCKER_HOST` is not a unix socket).
+pub fn socket_path() -> Option<PathBuf> {
+    let docker_host = std::env::var("DOCKER_HOST").ok();
+    let xdg_runtime_dir = std::env::var("XDG_RUNTIME_DIR").ok();
+    socket_candidates(docker_host.as_deref(), xdg_runtime_dir.as_deref()).into_iter().find(|socket| socket.exists())
+}
+
+/// Connects to the local container runtime.
+/// 
+/// Like Docker's own tools, this honours `DOCKER_HOST`. Without it, we also look for the sockets of rootless Docker and Podman if there is no rootful Docker.
+/// 
+/// **Returns**  
+/// The connection, or bollard's error if we could not set it up.
+pub fn connect() -> Result<Docker, bollard::errors::Error> {
+    if std::env::var("DOCKER_HOST").is_err() {
+        if let Some(socket) = socket_path() {
+            debug!("Connecting to container runtime at '{}'", socket.display());
+            return Docker::connect_with_unix(&socket.to_string_lossy(), SOCKET_TIMEOUT, API_DEFAULT_VERSION);
+        }
+    }
+    Docker::connect_with_local_defaults()
+}
+
+
+
+/// Parses the version that a container runtime reports, with or without a 'v' in front of it and ignoring any suffix (e.g., '4.9.4-rhel' or '20.10.17+dfsg1'), since distributions use those for their own packaging rather than for prereleases.
+/// 
+/// **Arguments**
+///  * `raw`: The version as reported.
+/// 
+/// **Returns**  
+/// The parsed Version, or a VersionParseError if it isn't one.
+pub fn parse_version(raw: &str) -> Result<Version, VersionParseError> {
+    let raw = raw.trim();
+    let end = raw.find(|c| c == '-' || c == '+').unwrap_or(raw.len());
+    Version::from_str(&raw[..end])
+}
+
+/// Determines which container runtime we talk to.
+/// 
+/// **Arguments**
+///  * `choice`: The runtime that the user asked for.
+///  * `components`: The names of the components that the runtime reports in its version (Podman reports a 'Podman Engine').
+///  * `socket`: The socket we connect to, if any.
+/// 
+/// **Returns**  
+/// The runtime that the user asked for, or the one we think it is if they left it to us.
+pub fn detect_runtime(choice: RuntimeChoice, components: &[String], socket: Option<&Path>) -> ContainerRuntime {
+    match choice {
+        RuntimeChoice::Docker => ContainerRuntime::Docker,
+        RuntimeChoice::Podman => ContainerRuntime::Podman,
+        RuntimeChoice::Auto   => {
+            let podman_component = components.iter().any(|name| name.to_lowercase().contains("podman"));
+            let podman_socket = socket.map(|socket| socket.to_string_lossy().contains("podman")).unwrap_or(false);
+            if podman_component || podman_socket { ContainerRuntime::Podman } else { ContainerRuntime::Docker }
+        },
+    }
+}
+
+/// Checks whether `docker buildx` is available.
+pub async fn has_buildx() -> bool {
+    match Command::new("docker").args(&[ "buildx", "version" ]).output().await {
+        Ok(output) => output.status.success(),
+        Err(_)     => false,
+    }
+}
+
+/// Remembers the runtime that we found, and tells the user about it (and what it cannot do).
+/// 
+/// **Arguments**
+///  * `info`: The RuntimeInfo describing the runtime.
+pub fn set_runtime_info(info: RuntimeInfo) {
+    if info.runtime == ContainerRuntime::Docker { debug!("Using container runtime {}", info); }
+    else { info!("Using container runtime {}", info); }
+    let unavailable = info.unavailable_features();
+    if !unavailable.is_empty() { warn!("Not available with {}{}: {}", info, if info.buildx { "" } else { " without buildx" }, unavailable.join("; ")); }
+    *RUNTIME_INFO.write().unwrap() = Some(info);
+}
+
+/// Returns the container runtime that we talk to, looking for it first if the dependency check didn't already.
+/// 
+/// **Returns**  
+/// The RuntimeInfo of the runtime. If we cannot reach it, we assume it's Docker (so the command fails later with the usual error).
+pub async fn runtime_info() -> RuntimeInfo {
+    let cached = RUNTIME_INFO.read().unwrap().clone();
+    if let Some(info) = cached { return info; }
+
+    let choice = runtime_choice();
+    let version = match connect() {
+        Ok(docker) => docker.version().await.ok(),
+        Err(_)     => None,
+    };
+    let (components, version) = match version {
+        Some(version) => (
+            version.components.unwrap_or_default().into_iter().map(|component| component.name).collect::<Vec<String>>(),
+            version.version.and_then(|version| parse_version(&version).ok()),
+        ),
+        None => (vec![], None),
+    };
+    let info = RuntimeInfo {
+        runtime : detect_runtime(choice, &components, socket_path().as_deref()),
+        version,
+        buildx  : has_buildx().await,
+    };
+    set_runtime_info(info.clone());
+    info
+}
+
+/// Returns the ID of an image as Docker reports it ('sha256:<hex>'), which is what package digests are compared against. Podman leaves out the algorithm.
+/// 
+/// **Arguments**
+///  * `id`: The ID as the runtime reported it.
+pub fn normalize_image_id(id: &str) -> String {
+    if id.contains(':') { id.to_string() } else { format!("sha256:{}", id) }
+}
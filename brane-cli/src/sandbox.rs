@@ -0,0 +1,193 @@
+/* SANDBOX.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 23:59:40
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Defines the options that restrict the containers of packages that
+ *   are run locally (by `brane test` and `brane run`), such as which
+ *   network they get and how much memory and CPU they may use.
+**/
+
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::str::FromStr;
+
+use bollard::models::HostConfig;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::SandboxError;
+use crate::runtime::DEFAULT_SOCKET;
+
+
+/***** CONSTANTS *****/
+/// The path at which the data directory is mounted in package containers.
+pub const DATA_MOUNT: &str = "/data";
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// The network that a sandboxed container is attached to.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SandboxNetwork {
+    /// The container has no network at all (except for loopback).
+    None,
+    /// The container is attached to Docker's default bridge network, instead of the host network.
+    Bridge,
+}
+
+impl SandboxNetwork {
+    /// Returns the Docker network mode of this network.
+    #[inline]
+    pub fn network_mode(&self) -> &'static str {
+        match self {
+            SandboxNetwork::None   => "none",
+            SandboxNetwork::Bridge => "bridge",
+        }
+    }
+}
+
+impl Display for SandboxNetwork {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        write!(f, "{}", self.network_mode())
+    }
+}
+
+impl FromStr for SandboxNetwork {
+    type Err = SandboxError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none"   => Ok(SandboxNetwork::None),
+            "bridge" => Ok(SandboxNetwork::Bridge),
+            _        => Err(SandboxError::UnknownNetwork{ raw: s.to_string() }),
+        }
+    }
+}
+
+
+
+/// The options that restrict the containers of packages that are run locally.
+#[derive(Args, Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SandboxOptions {
+    /// The network to attach the container to, if not the default one (see `DOCKER_NETWORK`)
+    #[clap(long, value_names = &["none|bridge"], help = "The network to run packages in: 'none' cuts them off from the network entirely, 'bridge' isolates them from the host's (defaults to the host network)")]
+    pub network        : Option<SandboxNetwork>,
+    /// The maximum amount of memory the container may use, in bytes
+    #[clap(long, value_names = &["size"], parse(try_from_str = parse_memory), help = "The maximum amount of memory that packages may use (e.g., '512m' or '2g')")]
+    pub memory         : Option<i64>,
+    /// The maximum number of CPUs the container may use
+    #[clap(long, value_names = &["n"], parse(try_from_str = parse_cpus), help = "The maximum number of CPUs that packages may use (e.g., '1.5')")]
+    pub cpus           : Option<f64>,
+    /// Whether to mount the data directory read-only
+    #[clap(long, help = "Mount the data directory read-only in packages")]
+    pub read_only_data : bool,
+}
+
+impl SandboxOptions {
+    /// Returns whether these options don't restrict the container in any way.
+    #[inline]
+    pub fn is_default(&self) -> bool { self.network.is_none() && self.memory.is_none() && self.cpus.is_none() && !self.read_only_data }
+
+    /// Restricts the given HostConfig of a container according to these options.
+    /// 
+    /// Any restriction also takes away the Docker socket and privileged mode, since either would let the container escape the others.
+    /// 
+    /// **Arguments**
+    ///  * `host_config`: The HostConfig as it would be without a sandbox.
+    /// 
+    /// **Returns**  
+    /// The HostConfig with the network, resource limits and data mount adapted to these options.
+    pub fn apply(&self, mut host_config: HostConfig) -> HostConfig {
+        if self.is_default() { return host_config; }
+
+        let socket_suffix = format!(":{}", DEFAULT_SOCKET);
+        host_config.binds = host_config.binds.map(|binds| binds.into_iter().filter(|bind| !bind.ends_with(&socket_suffix)).collect());
+        host_config.privileged = Some(false);
+        if let Some(network) = self.network { host_config.network_mode = Some(network.network_mode().to_string()); }
+        if let Some(memory) = self.memory { host_config.memory = Some(memory); }
+        if let Some(cpus) = self.cpus { host_config.nano_cpus = Some((cpus * 1_000_000_000.0).round() as i64); }
+        if self.read_only_data {
+            let data_suffix = format!(":{}", DATA_MOUNT);
+            host_config.binds = host_config.binds.map(|binds| binds.into_iter().map(|bind| if bind.ends_with(&data_suffix) { format!("{}:ro", bind) } else { bind }).collect());
+        }
+        host_config
+    }
+}
+
+impl Display for SandboxOptions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        if self.is_default() { return write!(f, "no restrictions"); }
+
+        let mut parts: Vec<String> = vec![ String::from("no Docker socket"), String::from("unprivileged") ];
+        if let Some(network) = self.network { parts.push(format!("network {}", network)); }
+        if let Some(memory) = self.memory { parts.push(format!("memory {} bytes", memory)); }
+        if let Some(cpus) = self.cpus { parts.push(format!("{} CPUs", cpus)); }
+        if self.read_only_data { parts.push(format!("read-only {}", DATA_MOUNT)); }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Returns the Docker bind that mounts the given directory as the data directory of a package container.
+/// 
+/// **Arguments**
+///  * `host_path`: The (canonical) path of the data directory on the host.
+/// 
+/// **Returns**  
+/// The bind as `<host_path>:/data`.
+#[inline]
+pub fn data_bind(host_path: &str) -> String { format!("{}:{}", host_path, DATA_MOUNT) }
+
+/// Parses a memory limit the way Docker does: a number of bytes, optionally followed by the unit 'b', 'k', 'm' or 'g' (which are powers of 1024).
+/// 
+/// **Arguments**
+///  * `raw`: The memory limit to parse.
+/// 
+/// **Returns**  
+/// The limit in bytes, or a SandboxError if it is not a positive size.
+pub fn parse_memory(raw: &str) -> Result<i64, SandboxError> {
+    let trimmed = raw.trim();
+    let split = trimmed.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(trimmed.len());
+    let (number, unit) = (&trimmed[..split], trimmed[split..].trim());
+    if number.is_empty() { return Err(SandboxError::IllegalMemory{ raw: raw.to_string() }); }
+
+    let multiplier: f64 = match unit.to_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k"      => 1024.0,
+        "m"      => 1024.0 * 1024.0,
+        "g"      => 1024.0 * 1024.0 * 1024.0,
+        _        => { return Err(SandboxError::UnknownMemoryUnit{ raw: raw.to_string(), unit: unit.to_string() }); }
+    };
+    match number.parse::<f64>() {
+        Ok(number) if number > 0.0 && number * multiplier < i64::MAX as f64 => Ok((number * multiplier).round() as i64),
+        _                                                                    => Err(SandboxError::IllegalMemory{ raw: raw.to_string() }),
+    }
+}
+
+/// Parses a CPU limit, which is a (fractional) number of CPUs.
+/// 
+/// **Arguments**
+///  * `raw`: The CPU limit to parse.
+/// 
+/// **Returns**  
+/// The number of CPUs, or a SandboxError if it is not a positive number.
+pub fn parse_cpus(raw: &str) -> Result<f64, SandboxError> {
+    match raw.trim().parse::<f64>() {
+        Ok(cpus) if cpus > 0.0 && cpus.is_finite() => Ok(cpus),
+        _                                          => Err(SandboxError::IllegalCpus{ raw: raw.to_string() }),
+    }
+}
@@ -0,0 +1,205 @@
+/* SIGNING.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 23:59:56
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Handles the keys for signing packages (`brane keygen`, `brane push
+ *   --sign`) and checks the signatures of pulled packages against the
+ *   trust store in `~/.brane/trusted_keys/`. The signing itself is done
+ *   by `specifications::signing`.
+**/
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use console::style;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+use tar::Archive;
+
+use specifications::errors::SigningError;
+use specifications::package::PackageInfo;
+use specifications::signing::{verify_package, PackageSignature, SigningKey, TrustedKey, SEED_LENGTH};
+
+use crate::utils::{get_keys_dir, get_trusted_keys_dir};
+
+
+/***** CONSTANTS *****/
+/// The extension of files with a (private) signing key.
+pub const SIGNING_KEY_EXTENSION: &str = "key";
+/// The extension of files with a public key, which is what the trust store consists of.
+pub const PUBLIC_KEY_EXTENSION: &str = "pub";
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Generates a new key to sign packages with, and writes it (and its public half) to the given directory.
+/// 
+/// **Arguments**
+///  * `name`: The name of the key, which becomes the name of its files.
+///  * `output`: The directory to write the key files to. Defaults to `~/.brane/keys`.
+///  * `force`: Whether to overwrite an existing key with the same name.
+/// 
+/// **Returns**  
+/// The path of the public key file on success, or an anyhow error otherwise.
+pub fn keygen(name: String, output: Option<PathBuf>, force: bool) -> Result<PathBuf> {
+    let dir = match output {
+        Some(output) => output,
+        None         => get_keys_dir()?,
+    };
+    let key_path = dir.join(format!("{}.{}", name, SIGNING_KEY_EXTENSION));
+    let public_path = dir.join(format!("{}.{}", name, PUBLIC_KEY_EXTENSION));
+    if !force && (key_path.exists() || public_path.exists()) { bail!("Key '{}' already exists in '{}' (use --force to overwrite it)", name, dir.display()); }
+
+    // Generate the key from fresh randomness
+    let mut seed = [ 0u8; SEED_LENGTH ];
+    OsRng.fill_bytes(&mut seed);
+    let key = SigningKey::from_seed(&seed)?;
+
+    // Write it, making sure only the user can read the private half
+    fs::create_dir_all(&dir).with_context(|| format!("Could not create key directory '{}'", dir.display()))?;
+    fs::write(&key_path, format!("{}\n", key.to_base64())).with_context(|| format!("Could not write signing key '{}'", key_path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Err(err) = fs::set_permissions(&key_path, fs::Permissions::from_mode(0o600)) {
+            warn!("Could not restrict permissions of signing key '{}': {}", key_path.display(), err);
+        }
+    }
+    fs::write(&public_path, format!("{}\n", key.trusted(&name).to_base64())).with_context(|| format!("Could not write public key '{}'", public_path.display()))?;
+
+    println!("Generated signing key {} in '{}'.", style(&name).bold().cyan(), key_path.display());
+    println!("Sign packages with `brane push --sign --key {}`; sites that should trust them copy '{}' to their '~/.brane/trusted_keys/'.", key_path.display(), public_path.display());
+    Ok(public_path)
+}
+
+/// Returns the key file that `brane push --sign` uses if no key is given, i.e., the one that `brane keygen` generates by default.
+/// 
+/// **Returns**  
+/// The path of the key file, or an anyhow error if we could not find the user's home directory.
+#[inline]
+pub fn default_key_path() -> Result<PathBuf> {
+    Ok(get_keys_dir()?.join(format!("brane.{}", SIGNING_KEY_EXTENSION)))
+}
+
+/// Reads a signing key from the given key file (as written by `keygen()`).
+/// 
+/// **Arguments**
+///  * `path`: The path of the key file.
+/// 
+/// **Returns**  
+/// The SigningKey on success, or an anyhow error if the file could not be read or has no valid key.
+pub fn read_signing_key(path: &Path) -> Result<SigningKey> {
+    let raw = fs::read_to_string(path).with_context(|| format!("Could not read signing key '{}'", path.display()))?;
+    SigningKey::from_base64(&raw).with_context(|| format!("Signing key '{}' is not valid", path.display()))
+}
+
+/// Reads the trusted public keys from the given directory. Every file with the `PUBLIC_KEY_EXTENSION` is one key, which is named after its file.
+/// 
+/// **Arguments**
+///  * `dir`: The directory to read the keys from. If it does not exist, no keys are trusted.
+/// 
+/// **Returns**  
+/// The TrustedKeys on success, or an anyhow error if the directory could not be read or one of the keys is not valid.
+pub fn read_trusted_keys(dir: &Path) -> Result<Vec<TrustedKey>> {
+    if !dir.exists() { return Ok(vec![]); }
+
+    let mut keys: Vec<TrustedKey> = vec![];
+    for entry in fs::read_dir(dir).with_context(|| format!("Could not read trust store '{}'", dir.display()))? {
+        let path = entry.with_context(|| format!("Could not read trust store '{}'", dir.display()))?.path();
+        if path.extension().map(|extension| extension != PUBLIC_KEY_EXTENSION).unwrap_or(true) { continue; }
+
+        let name = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+        let raw = fs::read_to_string(&path).with_context(|| format!("Could not read trusted key '{}'", path.display()))?;
+        keys.push(TrustedKey::from_base64(&name, &raw).with_context(|| format!("Trusted key '{}' is not valid", path.display()))?);
+    }
+    keys.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+    Ok(keys)
+}
+
+/// Checks the signature (if any) of a pulled package against the given trusted keys.
+/// 
+/// **Arguments**
+///  * `info`: The PackageInfo of the package, as it was pulled.
+///  * `signature`: The signature that the registry has for the package, if any.
+///  * `trusted`: The keys to trust signatures from.
+/// 
+/// **Returns**  
+/// The name of the key that signed the package, or a SigningError if it is not signed (by a trusted key) or its signature does not match.
+pub fn check_signature(info: &PackageInfo, signature: Option<&PackageSignature>, trusted: &[TrustedKey]) -> Result<String, SigningError> {
+    match signature {
+        Some(signature) => verify_package(info, signature, trusted).map(|key| key.name.clone()),
+        None            => Err(SigningError::Unsigned{ name: info.name.clone(), version: info.version.to_string() }),
+    }
+}
+
+/// Checks that the image.tar of a pulled package holds the image that its (signed) package info describes, i.e., that the config of the image hashes to the package's digest. Docker checks the layers of the image against that config when it loads the image.
+/// 
+/// **Arguments**
+///  * `info`: The PackageInfo of the package, as it was pulled.
+///  * `image`: The path of the pulled image.tar.
+/// 
+/// **Returns**  
+/// Nothing if the image matches the digest, or an anyhow error if it doesn't (or could not be read).
+pub fn check_image(info: &PackageInfo, image: &Path) -> Result<()> {
+    let expected = match &info.digest {
+        Some(digest) => digest,
+        None         => bail!("Package '{}' (version {}) has no digest to check its image against", info.name, info.version),
+    };
+
+    // Find the config that the image claims to have...
+    let mut claimed = info.clone();
+    claimed.resolve_digest(image).with_context(|| format!("Could not read the image of package '{}' (version {})", info.name, info.version))?;
+    let claimed = claimed.digest.unwrap_or_default();
+    if &claimed != expected { bail!("Image of package '{}' (version {}) has digest '{}', but its package info says it should be '{}'", info.name, info.version, claimed, expected); }
+
+    // ...and check that it really is that config
+    let config_path = PathBuf::from(format!("blobs/sha256/{}", claimed.trim_start_matches("sha256:")));
+    let mut archive = Archive::new(File::open(image).with_context(|| format!("Could not open image '{}'", image.display()))?);
+    for entry in archive.entries().with_context(|| format!("Could not read image '{}'", image.display()))? {
+        let mut entry = entry.with_context(|| format!("Could not read image '{}'", image.display()))?;
+        if entry.path()? != config_path { continue; }
+
+        let mut hasher = Sha256::new();
+        io::copy(&mut entry, &mut hasher).with_context(|| format!("Could not read image '{}'", image.display()))?;
+        let got = format!("sha256:{:x}", hasher.finalize());
+        if &got != expected { bail!("Image of package '{}' (version {}) has a config with digest '{}', but its package info says it should be '{}'", info.name, info.version, got, expected); }
+        return Ok(());
+    }
+    bail!("Image of package '{}' (version {}) has no config '{}'", info.name, info.version, config_path.display())
+}
+
+/// Checks a pulled package against the trust store in `~/.brane/trusted_keys/` (see `check_signature()`), before it is installed.
+/// 
+/// **Arguments**
+///  * `info`: The PackageInfo of the package, as it was pulled.
+///  * `signature`: The signature that the registry has for the package, if any.
+///  * `require_signed`: If true, a package that is not validly signed by a trusted key is refused; otherwise, we only warn about it.
+/// 
+/// **Returns**  
+/// Nothing if the package may be installed, or an anyhow error otherwise.
+pub fn check_pulled(info: &PackageInfo, signature: Option<&PackageSignature>, require_signed: bool) -> Result<()> {
+    let trusted = read_trusted_keys(&get_trusted_keys_dir()?)?;
+    match check_signature(info, signature, &trusted) {
+        Ok(key) => {
+            println!("Package {} is signed by trusted key {}.", style(&info.name).bold().cyan(), style(&key).bold());
+            Ok(())
+        },
+        Err(err) if require_signed => Err(anyhow!("Refusing to install package '{}' (version {}): {}", info.name, info.version, err)),
+        Err(err)                   => {
+            warn!("{} (pass --require-signed to refuse such packages)", err);
+            Ok(())
+        },
+    }
+}
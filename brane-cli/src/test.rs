@@ -1,22 +1,23 @@
 use std::fs;
-use std::path::PathBuf;
-use std::{
-    fmt::{Debug, Display},
-    str::FromStr,
-};
+use std::path::{Path, PathBuf};
+use std::fmt::{Display, Formatter, Result as FResult};
 
 use anyhow::{Context, Result};
 use console::style;
 use dialoguer::theme::ColorfulTheme;
-use dialoguer::{Confirm, Password};
-use dialoguer::{Input as Prompt, Select};
+use dialoguer::Select;
 use serde::de::DeserializeOwned;
+use serde_json::Value as JValue;
 
-use specifications::common::{Function, Parameter, Type, Value};
+use specifications::common::{Function, Type, Value};
+use specifications::compare::{self, Difference};
+use specifications::container::{ContainerInfo, TestCase};
 use specifications::package::{PackageKind, PackageInfo};
 use specifications::version::Version;
 
 use crate::docker::{self, ExecuteInfo};
+use crate::prompt::{Prompter, Terminal};
+use crate::sandbox::{data_bind, SandboxOptions, DATA_MOUNT};
 use crate::utils::ensure_package_dir;
 
 
@@ -24,6 +25,42 @@ type Map<T> = std::collections::HashMap<String, T>;
 
 const PACKAGE_NOT_FOUND: &str = "Package not found.";
 // const UNSUPPORTED_PACKAGE_KIND: &str = "Package kind not supported.";
+/// The number of lines of stderr to show when a test case fails with an unexpected exit code.
+const STDERR_TAIL: usize = 10;
+
+
+/// The ways in which a test case from a container file can fail.
+#[derive(Debug)]
+pub enum TestFailure {
+    /// The call exited with a different code than expected
+    ExitCode{ expected: i32, actual: i32, stderr: String },
+    /// The call succeeded, but what it returned could not be decoded
+    IllegalOutput{ err: String },
+    /// The call returned something else than expected
+    Output{ differences: Vec<Difference> },
+}
+
+impl Display for TestFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            TestFailure::ExitCode{ expected, actual, stderr } => {
+                write!(f, "expected exit code {}, got {}", expected, actual)?;
+                let lines: Vec<&str> = stderr.lines().collect();
+                if !lines.is_empty() {
+                    write!(f, "\nstderr (last {} line(s)):", lines.len().min(STDERR_TAIL))?;
+                    for line in &lines[lines.len().saturating_sub(STDERR_TAIL)..] { write!(f, "\n  {}", line)?; }
+                }
+                Ok(())
+            },
+            TestFailure::IllegalOutput{ err } => write!(f, "could not decode the output: {}", err),
+            TestFailure::Output{ differences } => {
+                write!(f, "output differs from what was expected (- expected, + actual):")?;
+                for difference in differences { write!(f, "\n{}", difference)?; }
+                Ok(())
+            },
+        }
+    }
+}
 
 ///
 ///
@@ -32,6 +69,8 @@ pub async fn handle(
     name: String,
     version: Version,
     data: Option<PathBuf>,
+    sandbox: SandboxOptions,
+    offline: bool,
 ) -> Result<()> {
     let package_dir = ensure_package_dir(&name, Some(&version), false)?;
     if !package_dir.exists() {
@@ -48,7 +87,7 @@ pub async fn handle(
     //     }
     // };
     // TODO: Fix error handling
-    let output = test_generic(package_info.kind, package_dir, package_info, data).await?;
+    let output = test_generic(package_info.kind, package_dir, package_info, data, sandbox, offline).await?;
     /*******/
 
     print_output(&output);
@@ -67,13 +106,188 @@ pub async fn test_generic(
     package_dir: PathBuf,
     package_info: PackageInfo,
     data: Option<PathBuf>,
+    sandbox: SandboxOptions,
+    offline: bool,
 ) -> Result<Value> {
     let (function, arguments) = prompt_for_input(&package_info.functions, &package_info.types)?;
 
     let image = format!("{}:{}", package_info.name, package_info.version);
+    let mounts = data_mounts(data)?;
+    let (code, stdout, stderr) = run_function(package_kind, &package_dir, image, function, &arguments, mounts, &sandbox, offline).await?;
+    debug!("return code: {}", code);
+    debug!("stderr:\n{}\n{}{}\n", (0..80).map(|_| '-').collect::<String>(), stderr, (0..80).map(|_| '-').collect::<String>());
+    debug!("stdout:\n{}\n{}{}\n", (0..80).map(|_| '-').collect::<String>(), stdout, (0..80).map(|_| '-').collect::<String>());
+
+    match decode_output(&stdout) {
+        Ok(value) => Ok(value),
+        Err(err) => {
+            println!("{:?}", err);
+            Ok(Value::Unit)
+        }
+    }
+}
+
+
+
+/// Runs the test cases in the given container file against the locally built package it describes.
+/// 
+/// **Arguments**
+///  * `file`: The container file with the test cases.
+///  * `data`: An optional directory to mount as /data while running them.
+///  * `sandbox`: The options that restrict the package's container.
+///  * `offline`: If true, fails instead of pulling the package's image if it is not available locally.
+/// 
+/// **Returns**  
+/// Nothing if all test cases pass, or an error saying how many failed otherwise (after printing a report of each failure).
+pub async fn handle_spec(
+    file: PathBuf,
+    data: Option<PathBuf>,
+    sandbox: SandboxOptions,
+    offline: bool,
+) -> Result<()> {
+    let container_info = ContainerInfo::from_path(&file)?;
+    let tests = container_info.tests.clone().unwrap_or_default();
+    if tests.is_empty() {
+        println!("No test cases in '{}'.", file.display());
+        return Ok(());
+    }
+
+    let package_dir = ensure_package_dir(&container_info.name, Some(&container_info.version), false)?;
+    if !package_dir.exists() {
+        return Err(anyhow!(PACKAGE_NOT_FOUND));
+    }
+    let image = format!("{}:{}", container_info.name, container_info.version);
+    let mounts = data_mounts(data)?;
+    let types = container_info.types.clone().unwrap_or_default();
+
+    println!("\nrunning {} test case(s) of {}", tests.len(), style(&image).bold().cyan());
+    let mut failures: Vec<(String, TestFailure)> = vec![];
+    for (i, case) in tests.iter().enumerate() {
+        let label = match &case.name {
+            Some(name) => format!("{} ({})", name, case.function),
+            None       => format!("#{} ({})", i + 1, case.function),
+        };
+
+        // Type the arguments according to the action's parameters
+        let action = container_info.actions.get(&case.function).ok_or_else(|| anyhow!("Test case {} calls unknown function '{}'", label, case.function))?;
+        let mut arguments = Map::<Value>::new();
+        for (name, value) in case.input.iter().flatten() {
+            let data_type = action.input.iter().flatten().find(|p| &p.name == name).map(|p| p.data_type.as_str());
+            arguments.insert(name.clone(), typed_value(value, data_type, &types));
+        }
+
+        let (code, stdout, stderr) = run_function(container_info.kind, &package_dir, image.clone(), case.function.clone(), &arguments, mounts.clone(), &sandbox, offline).await?;
+        match check_case(case, code, &stdout, &stderr) {
+            None          => println!("test {} ... {}", label, style("ok").green()),
+            Some(failure) => {
+                println!("test {} ... {}", label, style("FAILED").red());
+                failures.push((label, failure));
+            },
+        }
+    }
+
+    // Report
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for (label, failure) in &failures {
+            println!("\n---- {} ----\n{}", style(label).bold(), failure);
+        }
+    }
+    println!("\ntest result: {}. {} passed; {} failed\n", if failures.is_empty() { style("ok").green() } else { style("FAILED").red() }, tests.len() - failures.len(), failures.len());
+    if !failures.is_empty() { return Err(anyhow!("{} of {} test case(s) failed", failures.len(), tests.len())); }
+    Ok(())
+}
+
+/// Checks the result of running a test case against what it expects.
+/// 
+/// **Arguments**
+///  * `case`: The test case that was run.
+///  * `code`: The exit code of the container.
+///  * `stdout`: The stdout of the container, which ends with the encoded output of the call.
+///  * `stderr`: The stderr of the container.
+/// 
+/// **Returns**  
+/// Nothing if the test case passed, or a TestFailure describing why it didn't.
+pub fn check_case(case: &TestCase, code: i32, stdout: &str, stderr: &str) -> Option<TestFailure> {
+    let expected_code = case.expected_exit_code();
+    if code != expected_code { return Some(TestFailure::ExitCode{ expected: expected_code, actual: code, stderr: stderr.to_string() }); }
+
+    // Only successful calls have an output to check
+    let expected = match &case.output {
+        Some(output) if expected_code == 0 => Value::from_json(output),
+        _                                  => { return None; }
+    };
+    let actual = match decode_output(stdout) {
+        Ok(actual) => actual,
+        Err(err)   => { return Some(TestFailure::IllegalOutput{ err: format!("{:#}", err) }); }
+    };
+
+    let differences = compare::compare(&expected, &actual);
+    if differences.is_empty() { None } else { Some(TestFailure::Output{ differences }) }
+}
+
+/// Converts a JSON value from a test case to the Value for a parameter of the given type, so that structs and arrays carry the type names the package expects.
+/// 
+/// **Arguments**
+///  * `value`: The JSON value to convert.
+///  * `data_type`: The declared type of the parameter (or property), if it is declared.
+///  * `types`: The types declared by the package.
+/// 
+/// **Returns**  
+/// The converted Value.
+pub fn typed_value(value: &JValue, data_type: Option<&str>, types: &Map<Type>) -> Value {
+    let data_type = match data_type {
+        Some(data_type) => data_type,
+        None            => { return Value::from_json(value); }
+    };
+
+    if let (JValue::Array(entries), Some(element_type)) = (value, data_type.strip_suffix("[]")) {
+        return Value::Array{ data_type: data_type.to_string(), entries: entries.iter().map(|entry| typed_value(entry, Some(element_type), types)).collect() };
+    }
+    match value {
+        JValue::Number(number) if data_type == "real" => Value::Real(number.as_f64().unwrap_or_default()),
+        JValue::Object(properties) if data_type == "Directory" || data_type == "File" || types.contains_key(data_type) => {
+            let declared = types.get(data_type).map(|t| &t.properties[..]).unwrap_or(&[]);
+            Value::Struct {
+                data_type: data_type.to_string(),
+                properties: properties.iter().map(|(name, value)| {
+                    let property_type = declared.iter().find(|p| &p.name == name).map(|p| p.data_type.as_str());
+                    (name.clone(), typed_value(value, property_type, types))
+                }).collect(),
+            }
+        },
+        value => Value::from_json(value),
+    }
+}
+
+/// Runs a function of a locally built package in a container, the same way it is run when testing it interactively.
+/// 
+/// **Arguments**
+///  * `package_kind`: The kind of the package.
+///  * `package_dir`: The directory of the package, which contains its image.
+///  * `image`: The name of the package's image.
+///  * `function`: The function to call.
+///  * `arguments`: The arguments to call it with.
+///  * `mounts`: Any volumes to mount in the container.
+///  * `sandbox`: The options that restrict the container.
+///  * `offline`: If true, fails instead of pulling the image if it is not available locally.
+/// 
+/// **Returns**  
+/// The exit code, stdout and stderr of the container.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_function(
+    package_kind: PackageKind,
+    package_dir: &Path,
+    image: String,
+    function: String,
+    arguments: &Map<Value>,
+    mounts: Option<Vec<String>>,
+    sandbox: &SandboxOptions,
+    offline: bool,
+) -> Result<(i32, String, String)> {
     let image_file = Some(package_dir.join("image.tar"));
 
-    let command = vec![
+    let mut command = vec![
         String::from("-d"),
         String::from("--application-id"),
         String::from("test"),
@@ -81,15 +295,24 @@ pub async fn test_generic(
         String::from("localhost"),
         String::from("--job-id"),
         String::from("1"),
-        package_kind.to_string(),
-        function,
-        base64::encode(serde_json::to_string(&arguments)?),
     ];
+    // Functions with outputs store them in the data directory, like they do in `brane run`
+    if mounts.is_some() {
+        command.push(String::from("--artifact-dir"));
+        command.push(format!("{}/artifacts", DATA_MOUNT));
+    }
+    command.extend([ package_kind.to_string(), function, base64::encode(serde_json::to_string(arguments)?) ]);
 
+    let exec = ExecuteInfo::new(image, image_file, mounts, Some(command)).with_sandbox(sandbox.clone());
+    Ok(docker::run_and_wait(exec, offline).await?)
+}
+
+/// Returns the volume to mount for the given data directory, if any.
+fn data_mounts(data: Option<PathBuf>) -> Result<Option<Vec<String>>> {
     let mounts = if let Some(data) = data {
         let data = fs::canonicalize(data)?;
         if data.exists() {
-            Some(vec![format!("{}:/data", data.into_os_string().into_string().unwrap())])
+            Some(vec![data_bind(&data.into_os_string().into_string().unwrap())])
         } else {
             None
         }
@@ -97,21 +320,12 @@ pub async fn test_generic(
         None
     };
 
-    let exec = ExecuteInfo::new(image, image_file, mounts, Some(command));
-
-    let (code, stdout, stderr) = docker::run_and_wait(exec).await?;
-    debug!("return code: {}", code);
-    debug!("stderr:\n{}\n{}{}\n", (0..80).map(|_| '-').collect::<String>(), stderr, (0..80).map(|_| '-').collect::<String>());
-    debug!("stdout:\n{}\n{}{}\n", (0..80).map(|_| '-').collect::<String>(), stdout, (0..80).map(|_| '-').collect::<String>());
+    Ok(mounts)
+}
 
-    let output = stdout.lines().last().unwrap_or_default().to_string();
-    match decode_b64(output) {
-        Ok(value) => Ok(value),
-        Err(err) => {
-            println!("{:?}", err);
-            Ok(Value::Unit)
-        }
-    }
+/// Decodes the output of a call, which is the last line of the container's stdout.
+fn decode_output(stdout: &str) -> Result<Value> {
+    decode_b64(stdout.lines().last().unwrap_or_default().to_string())
 }
 
 ///
@@ -134,31 +348,11 @@ fn prompt_for_input(
 
     println!("\nPlease provide input for the chosen function:\n");
 
+    let mut terminal = Terminal;
+    let mut prompter = Prompter::new(&mut terminal, types);
     let mut arguments = Map::<Value>::new();
     for p in &function.parameters {
-        let data_type = p.data_type.as_str();
-
-        debug!("{:?}", types);
-        let value = if let Some(input_type) = types.get(data_type) {
-            let mut properties = Map::<Value>::new();
-
-            for p in &input_type.properties {
-                let p = p.clone().into_parameter();
-                let data_type = p.data_type.as_str();
-
-                let value = prompt_for_value(data_type, &p)?;
-                properties.insert(p.name.clone(), value);
-            }
-
-            Value::Struct {
-                data_type: input_type.name.clone(),
-                properties,
-            }
-        } else {
-            prompt_for_value(data_type, p)?
-        };
-
-        arguments.insert(p.name.clone(), value);
+        arguments.insert(p.name.clone(), prompter.parameter(p)?);
     }
 
     debug!("Arguments: {:#?}", arguments);
@@ -168,122 +362,6 @@ fn prompt_for_input(
     Ok((function_name.clone(), arguments))
 }
 
-///
-///
-///
-fn prompt_for_value(
-    data_type: &str,
-    p: &Parameter,
-) -> Result<Value> {
-    let value = if let Some(element_data_type) = data_type.strip_suffix("[]") {
-        let mut elements = vec![];
-
-        loop {
-            let mut p = p.clone();
-            p.data_type = format!("{}[{}]", element_data_type, elements.len());
-            elements.push(prompt_for_value(element_data_type, &p)?);
-
-            if !Confirm::new()
-                .with_prompt(format!(
-                    "Do you want to more items to the {} array?",
-                    style(p.name).bold().cyan()
-                ))
-                .interact()?
-            {
-                break;
-            }
-        }
-
-        Value::Array {
-            data_type: data_type.to_string(),
-            entries: elements,
-        }
-    } else {
-        match data_type {
-            "boolean" => {
-                let default = p.clone().default.map(|d| d.as_bool().unwrap());
-                Value::Boolean(prompt(p, default)?)
-            }
-            "Directory" | "File" => {
-                let default = p.clone().default.map(|d| d.as_string().unwrap());
-                let url = Value::Unicode(format!("file:///{}", prompt(p, default)?));
-
-                let mut properties = Map::<Value>::default();
-                properties.insert(String::from("url"), url);
-
-                Value::Struct {
-                    data_type: String::from(data_type),
-                    properties,
-                }
-            }
-            "integer" => {
-                let default = p.clone().default.map(|d| d.as_i64().unwrap());
-                Value::Integer(prompt(p, default)?)
-            }
-            "real" => {
-                let default = p.clone().default.map(|d| d.as_f64().unwrap());
-                Value::Real(prompt(p, default)?)
-            }
-            "string" => {
-                let default = p.clone().default.map(|d| d.as_string().unwrap());
-                let value = if p.name.to_lowercase().contains("password") {
-                    prompt_password(p, default)?
-                } else {
-                    prompt(p, default)?
-                };
-
-                Value::Unicode(value)
-            }
-            _ => {
-                error!("Unreachable, because data type is '{}'", data_type);
-                unreachable!()
-            }
-        }
-    };
-
-    Ok(value)
-}
-
-///
-///
-///
-fn prompt<T>(
-    parameter: &Parameter,
-    default: Option<T>,
-) -> std::io::Result<T>
-where
-    T: Clone + FromStr + Display,
-    T::Err: Display + Debug,
-{
-    let colorful = ColorfulTheme::default();
-    let mut prompt = Prompt::with_theme(&colorful);
-    prompt
-        .with_prompt(&format!("{} ({})", parameter.name, parameter.data_type))
-        .allow_empty(parameter.optional.unwrap_or_default());
-
-    if let Some(default) = default {
-        prompt.default(default);
-    }
-
-    prompt.interact()
-}
-
-///
-///
-///
-fn prompt_password(
-    parameter: &Parameter,
-    _default: Option<String>,
-) -> std::io::Result<String> {
-    let colorful = ColorfulTheme::default();
-    let mut prompt = Password::with_theme(&colorful);
-    prompt
-        .with_prompt(&format!("{} ({})", parameter.name, parameter.data_type))
-        .allow_empty_password(parameter.optional.unwrap_or_default());
-
-    prompt.interact()
-}
-
 ///
 ///
 ///
@@ -302,7 +380,7 @@ fn print_output(value: &Value) {
         Value::Unicode(unicode) => println!("{}", style(unicode).bold().cyan()),
         Value::Unit => println!("_ (unit)"),
         Value::Pointer { .. } => unreachable!(),
-        Value::Struct { properties, .. } => {
+        Value::Struct { properties, .. } | Value::Map(properties) => {
             for (name, value) in properties.iter() {
                 println!("{}:", style(name).bold().cyan());
                 println!("{}\n", style(value).cyan());
@@ -20,13 +20,13 @@ use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::str::FromStr;
 
-use bollard::Docker;
-
 use specifications::package::PackageKind;
-use specifications::version::Version;
+use specifications::version::{Version, VersionConstraint};
 
-use crate::{MIN_DOCKER_VERSION, MIN_BUILDX_VERSION};
+use crate::MIN_BUILDX_VERSION;
 use crate::errors::UtilError;
+use crate::manifest::Manifest;
+use crate::runtime::{self, ContainerRuntime, RuntimeInfo};
 
 
 /***** HELPER ENUMS *****/
@@ -37,6 +37,8 @@ pub enum DependencyError {
     DockerNotInstalled,
     /// Docker has a too low version
     DockerMinNotMet{ got: Version, expected: Version },
+    /// Podman has a too low version
+    PodmanMinNotMet{ got: Version, expected: Version },
 
     /// The Buildkit plugin is not installed for Docker
     BuildkitNotInstalled,
@@ -47,8 +49,9 @@ pub enum DependencyError {
 impl Display for DependencyError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
         match self {
-            DependencyError::DockerNotInstalled               => write!(f, "Local Docker instance cannot be reached (is Docker installed and running?)"),
+            DependencyError::DockerNotInstalled               => write!(f, "Local Docker instance cannot be reached (is Docker installed and running? For Podman, enable its Docker-compatible socket with 'systemctl --user enable --now podman.socket' or point DOCKER_HOST to it)"),
             DependencyError::DockerMinNotMet{ got, expected } => write!(f, "Docker version is {}, but Brane requires version {} or later", got, expected),
+            DependencyError::PodmanMinNotMet{ got, expected } => write!(f, "Podman version is {}, but Brane requires version {} or later (or pass '--container-runtime docker' if this is not Podman)", got, expected),
 
             DependencyError::BuildkitNotInstalled               => write!(f, "Local Docker instance does not have the Buildkit plugin installed"),
             DependencyError::BuildKitMinNotMet{ got, expected } => write!(f, "Buildkit plugin for Docker version is {}, but Brane requires version {} or later", got, expected),
@@ -65,84 +68,60 @@ impl Error for DependencyError {}
 /***** UTILITIES *****/
 /// **Edited: Now returning UtilErrors.**
 /// 
-/// Checks the runtime dependencies of brane-cli (Docker + BuildKit)
+/// Checks the runtime dependencies of brane-cli (Docker or Podman + BuildKit)
 /// 
 /// **Returns**  
 /// Nothing if the dependencies are met, a DependencyError if it wasn't, or a UtilError if we couldn't determine.
 pub async fn check_dependencies() -> Result<Result<(), DependencyError>, UtilError> {
     /* Docker */
     // Connect to the local instance using bollard
-    let docker = match Docker::connect_with_local_defaults() {
+    let docker = match runtime::connect() {
         Ok(docker) => docker,
         Err(_)     => { return Ok(Err(DependencyError::DockerNotInstalled)); }
     };
 
     // Get the version of information of the docker container
-    let docker_version = match docker.version().await {
+    let (docker_version, components) = match docker.version().await {
         Ok(docker_version) => match docker_version.version {
-            Some(docker_version) => docker_version,
-            None                 => { return Err(UtilError::DockerNoVersion); }
+            Some(version) => (version, docker_version.components.unwrap_or_default().into_iter().map(|component| component.name).collect::<Vec<String>>()),
+            None          => { return Err(UtilError::DockerNoVersion); }
         },
         Err(err)           => { return Err(UtilError::DockerVersionError{ err }); }
     };
 
     // Try to convert the version number to a semver
-    let docker_version = match Version::from_str(&docker_version) {
+    let docker_version = match runtime::parse_version(&docker_version) {
         Ok(docker_version) => docker_version,
         Err(err)           => { return Err(UtilError::IllegalDockerVersion{ version: docker_version, err }); }
     };
 
-    // Compare it with the required instance
-    if docker_version < MIN_DOCKER_VERSION {
-        return Ok(Err(DependencyError::DockerMinNotMet{ got: docker_version, expected: MIN_DOCKER_VERSION }));
+    // Podman reports its own version, so find out which of the two we're talking to before we compare it with the required one
+    let container_runtime = runtime::detect_runtime(runtime::runtime_choice(), &components, runtime::socket_path().as_deref());
+    let expected = container_runtime.min_version();
+    if docker_version < expected {
+        return Ok(Err(match container_runtime {
+            ContainerRuntime::Docker => DependencyError::DockerMinNotMet{ got: docker_version, expected },
+            ContainerRuntime::Podman => DependencyError::PodmanMinNotMet{ got: docker_version, expected },
+        }));
     }
+    runtime::set_runtime_info(RuntimeInfo{ runtime: container_runtime, version: Some(docker_version), buildx: runtime::has_buildx().await });
 
 
 
     /* Buildx */
-    // // Run a command to get the buildx version
-    // let mut command = Command::new("docker");
-    // command.arg("buildx");
-    // command.arg("version");
-    // command.stdout(Stdio::piped());
-    // let output = match command.output() {
-    //     Ok(output) => output,
-    //     Err(err)   => { return Err(UtilError::BuildxLaunchError{ command: format!("{:?}", command), err }); }
-    // };
-    // if !output.status.success() {
-    //     return Ok(Err(DependencyError::BuildkitNotInstalled));
-    // }
-    // let buildx_version = String::from_utf8_lossy(&output.stdout).to_string();
-
-    // // Get the second when splitting on spaces
-    // let buildx_version = match buildx_version.split(' ').nth(1) {
-    //     Some(buildx_version) => buildx_version,
-    //     None                 => { return Err(UtilError::BuildxVersionNoParts{ version: buildx_version }); }
-    // };
-
-    // // Remove the first v
-    // let buildx_version = if !buildx_version.is_empty() && buildx_version.starts_with('v') {
-    //     &buildx_version[1..]
-    // } else {
-    //     return Err(UtilError::BuildxVersionNoV{ version: buildx_version.to_string() });
-    // };
-
-    // // Parse the first part up to a dash
-    // let buildx_version = match buildx_version.find('-') {
-    //     Some(dash_pos) => buildx_version[..dash_pos].to_string(),
-    //     None           => { return Err(UtilError::BuildxVersionNoDash{ version: buildx_version.to_string() }); }
-    // };
-
-    // // Finally, try to convert into a semantic version number
-    // let buildx_version = match Version::from_str(&buildx_version) {
-    //     Ok(buildx_version) => buildx_version,
-    //     Err(err)           => { return Err(UtilError::IllegalBuildxVersion{ version: buildx_version, err }); }
-    // };
-
-    // // With that all done, compare it with the required
-    // if buildx_version < MIN_BUILDX_VERSION {
-    //     return Ok(Err(DependencyError::BuildKitMinNotMet{ got: docker_version, expected: MIN_BUILDX_VERSION }));
-    // }
+    // Buildx is optional (we build without it if it's missing), but if it's there, it has to be recent enough
+    let mut command = Command::new("docker");
+    command.args(&[ "buildx", "version" ]);
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::null());
+    if let Ok(output) = command.output() {
+        if output.status.success() {
+            let buildx_version = parse_buildx_version(&String::from_utf8_lossy(&output.stdout))?;
+            if buildx_version < MIN_BUILDX_VERSION {
+                return Ok(Err(DependencyError::BuildKitMinNotMet{ got: buildx_version, expected: MIN_BUILDX_VERSION }));
+            }
+        }
+    }
 
 
 
@@ -152,45 +131,68 @@ pub async fn check_dependencies() -> Result<Result<(), DependencyError>, UtilErr
 
 
 
+/// Parses the version from the output of `docker buildx version` (e.g., 'github.com/docker/buildx v0.10.0-rc1 a3d8a0d').
+/// 
+/// The version is the second part of the output, which is taken with or without a 'v' in front of it and with any prerelease or build metadata (so 'v0.10.0-rc1' comes just before 'v0.10.0').
+/// 
+/// **Arguments**
+///  * `output`: The output of the command.
+/// 
+/// **Returns**  
+/// The parsed Version, or a UtilError if the output doesn't contain one.
+pub fn parse_buildx_version(output: &str) -> Result<Version, UtilError> {
+    let raw = match output.split_whitespace().nth(1) {
+        Some(raw) => raw,
+        None      => { return Err(UtilError::BuildxVersionNoParts{ version: output.trim().to_string() }); }
+    };
+    Version::from_str(raw).map_err(|err| UtilError::IllegalBuildxVersion{ version: raw.to_string(), err })
+}
+
+
+
 /// **Edited: now returning CliErrors.**
 /// 
 /// Tries to determine the package file in the pulled repository.
 /// 
+/// If the directory has a `brane.toml` manifest, the package file it declares is used. Otherwise, the directory should contain exactly one file that looks like a package file (a `container.yml`, a `.bk` or `.cwl` file, or a YAML or JSON document that is an OpenAPI or CWL document); if there are more, we refuse to guess.
+/// 
 /// **Arguments**
 ///  * `dir`: The directory the is the root of a package.
 /// 
 /// **Returns**  
-/// A PathBuf pointing to what we think is the package file, or else a CliError if we could not determine it or something went wrong.
+/// A PathBuf pointing to what we think is the package file (relative to `dir`), or else a UtilError if we could not determine it, found more than one candidate or something went wrong.
 pub fn determine_file(
     dir: &Path,
 ) -> Result<PathBuf, UtilError> {
+    // Prefer what the manifest says
+    if let Some(manifest) = Manifest::load(dir).map_err(|err| UtilError::ManifestError{ err })? {
+        return Ok(manifest.file);
+    }
+
     // Open an iterator over the directory's files
     let files = match fs::read_dir(dir) {
         Ok(files) => files,
         Err(err)  => { return Err(UtilError::DirectoryReadError{ dir: dir.to_path_buf(), err }); }
     };
 
-    // Iterate through them
+    // Collect every file that looks like a package file
+    let mut candidates: Vec<PathBuf> = Vec::new();
     for file in files {
         // Make sure this file is valid
         let file = match file {
             Ok(file) => file,
             Err(err) => { return Err(UtilError::DirectoryReadError{ dir: dir.to_path_buf(), err }); }
         };
-
-        // Compare the filename with anything we know
-        let file_name = String::from(file.file_name().to_string_lossy()).to_lowercase();
-        if file.path().is_file() &&
-            (file_name.eq("container.yml") ||
-             file_name.eq("container.yaml") ||
-             file_name.ends_with(".bk") ||
-             file_name.ends_with(".cwl"))
-        {
-            return Ok(PathBuf::from(file_name));
-        }
+        if file.path().is_file() && is_package_file(&file.path()) { candidates.push(PathBuf::from(file.file_name())); }
     }
 
-    Err(UtilError::UndeterminedPackageFile{ dir: dir.to_path_buf() })
+    // Only use it if there's exactly one
+    candidates.sort();
+    match candidates.len() {
+        0 => Err(UtilError::UndeterminedPackageFile{ dir: dir.to_path_buf() }),
+        1 => Ok(candidates.remove(0)),
+        _ => Err(UtilError::AmbiguousPackageFile{ dir: dir.to_path_buf(), candidates }),
+    }
 }
 
 
@@ -199,31 +201,24 @@ pub fn determine_file(
 /// 
 /// Tries to deduce the package kind from the given file.
 /// 
+/// If a `brane.toml` manifest declares the file and its kind, that kind is used. Otherwise, the kind is deduced from the name of the file (`container.yml`, `.bk` or `.cwl`) or from the top-level fields of the document (`openapi` or `cwlVersion`); if it looks like more than one kind, we refuse to guess.
+/// 
 /// **Arguments**
 ///  * `path`: Path to file from which we'd like to deduce the kind.
 /// 
 /// **Returns**  
-/// The PackageKind if we could deduce it, or some sort of CliError if we could not or something went wrong.
+/// The PackageKind if we could deduce it, or some sort of UtilError if we could not, it's ambiguous or something went wrong.
 pub fn determine_kind(
     path: &Path,
 ) -> Result<PackageKind, UtilError> {
-    // See if the filename convention allows us to choose a package kind
-    if let Some(file) = path.file_name() {
-        let filename = String::from(file.to_string_lossy()).to_lowercase();
-        if filename.eq("container.yml") || filename.eq("container.yaml") {
-            // It's a code package, likely
-            return Ok(PackageKind::Ecu);
-        }
-    }
-    // See if the extension allows us to choose a package kind
-    if let Some(extension) = path.extension() {
-        let extension = String::from(extension.to_string_lossy()).to_lowercase();
-        if extension.eq("bk") {
-            // It's a Bakery / DSL package
-            return Ok(PackageKind::Dsl);
-        }
+    // Prefer what the manifest says
+    if let Some(kind) = Manifest::for_file(path).map_err(|err| UtilError::ManifestError{ err })?.and_then(|manifest| manifest.kind) {
+        return Ok(kind);
     }
 
+    // See if the filename or extension allows us to choose a package kind
+    if let Some(kind) = kind_from_name(path) { return Ok(kind); }
+
     // For CWL and OAS we need to look inside the file
     let mut file_content = String::new();
     {
@@ -239,16 +234,64 @@ pub fn determine_kind(
         };
     }
 
-    // Check if the content contains a keywords that allow us to say which package it is
-    if file_content.contains("cwlVersion") {
-        return Ok(PackageKind::Cwl);
+    // Check which top-level fields the document has
+    let mut kinds = kinds_from_content(&file_content);
+    match kinds.len() {
+        0 => Err(UtilError::UndeterminedPackageKind{ file: path.to_path_buf() }),
+        1 => Ok(kinds.remove(0)),
+        _ => Err(UtilError::AmbiguousPackageKind{ file: path.to_path_buf(), kinds }),
+    }
+}
+
+/// Returns the package kind that the name of the given file implies, if any.
+/// 
+/// **Arguments**
+///  * `path`: The path of the file.
+fn kind_from_name(path: &Path) -> Option<PackageKind> {
+    let filename = path.file_name().map(|file| file.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if filename == "container.yml" || filename == "container.yaml" {
+        // It's a code package, likely
+        return Some(PackageKind::Ecu);
     }
-    if file_content.contains("openapi") {
-        return Ok(PackageKind::Oas);
+    match path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).as_deref() {
+        // It's a Bakery / DSL package
+        Some("bk")  => Some(PackageKind::Dsl),
+        Some("cwl") => Some(PackageKind::Cwl),
+        _           => None,
     }
+}
 
-    // Could not determine the package
-    Err(UtilError::UndeterminedPackageKind{ file: path.to_path_buf() })
+/// Returns the package kinds that the given document could describe, judging by its top-level fields (`openapi` for OAS, `cwlVersion` for CWL).
+/// 
+/// Documents that are not YAML (or JSON) are searched for the field names instead.
+/// 
+/// **Arguments**
+///  * `content`: The contents of the document.
+fn kinds_from_content(content: &str) -> Vec<PackageKind> {
+    let has_field = |field: &str| match serde_yaml::from_str::<serde_yaml::Value>(content) {
+        Ok(serde_yaml::Value::Mapping(fields)) => fields.contains_key(&serde_yaml::Value::String(field.to_string())),
+        Ok(_)                                  => false,
+        Err(_)                                 => content.contains(field),
+    };
+
+    let mut kinds = Vec::new();
+    if has_field("cwlVersion") { kinds.push(PackageKind::Cwl); }
+    if has_field("openapi") { kinds.push(PackageKind::Oas); }
+    kinds
+}
+
+/// Returns whether the given file looks like a package file (see `determine_file()`).
+/// 
+/// **Arguments**
+///  * `path`: The path of the file.
+fn is_package_file(path: &Path) -> bool {
+    if kind_from_name(path).is_some() { return true; }
+
+    // YAML and JSON documents are only package files if they are OpenAPI or CWL documents
+    match path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).as_deref() {
+        Some("yml") | Some("yaml") | Some("json") => fs::read_to_string(path).map(|content| !kinds_from_content(&content).is_empty()).unwrap_or(false),
+        _                                         => false,
+    }
 }
 
 
@@ -292,24 +335,87 @@ pub fn ensure_config_dir(create: bool) -> Result<PathBuf, UtilError> {
     Ok(config_dir)
 }
 
-/// **Edited: Now returns UtilErrors.**
+/// **Edited: Now returns UtilErrors + lives in ~/.brane so it is shared across sessions.**
 ///
 /// Returns the location of the history file for Brane.
 /// 
 /// **Returns**  
 /// The path of the HistoryFile or a UtilError otherwise.
 pub fn get_history_file() -> Result<PathBuf, UtilError> {
-    // Get the config dir
-    let config_dir = get_config_dir()?;
+    // Get the user's home directory
+    let home = match dirs_2::home_dir() {
+        Some(home) => home,
+        None       => { return Err(UtilError::UserHomeDirNotFound); }
+    };
+
+    // Add the path and return
+    Ok(home.join(".brane").join("history"))
+}
+
+/// Returns the location of the cache of the local package index (see `index_cache`).
+/// 
+/// **Returns**  
+/// The path of the cache file (which may not exist yet) or a UtilError otherwise.
+pub fn get_index_cache_file() -> Result<PathBuf, UtilError> {
+    // Get the user's home directory
+    let home = match dirs_2::home_dir() {
+        Some(home) => home,
+        None       => { return Err(UtilError::UserHomeDirNotFound); }
+    };
+
+    // Add the path and return
+    Ok(home.join(".brane").join("index.cache"))
+}
+
+/// Returns the location of the cache of downloaded init binaries (see `init_binary`).
+/// 
+/// **Returns**  
+/// The path of the cache directory (which may not exist yet) or a UtilError otherwise.
+pub fn get_bin_dir() -> Result<PathBuf, UtilError> {
+    // Get the user's home directory
+    let home = match dirs_2::home_dir() {
+        Some(home) => home,
+        None       => { return Err(UtilError::UserHomeDirNotFound); }
+    };
 
     // Add the path and return
-    Ok(config_dir.join("repl_history.txt"))
+    Ok(home.join(".brane").join("bin"))
+}
+
+/// Returns the location of the directory with the public keys that package signatures are trusted from (see `signing`).
+/// 
+/// **Returns**  
+/// The path of the trust store (which may not exist yet) or a UtilError otherwise.
+pub fn get_trusted_keys_dir() -> Result<PathBuf, UtilError> {
+    // Get the user's home directory
+    let home = match dirs_2::home_dir() {
+        Some(home) => home,
+        None       => { return Err(UtilError::UserHomeDirNotFound); }
+    };
+
+    // Add the path and return
+    Ok(home.join(".brane").join("trusted_keys"))
+}
+
+/// Returns the location of the directory where `brane keygen` puts new keys by default.
+/// 
+/// **Returns**  
+/// The path of the key directory (which may not exist yet) or a UtilError otherwise.
+pub fn get_keys_dir() -> Result<PathBuf, UtilError> {
+    // Get the user's home directory
+    let home = match dirs_2::home_dir() {
+        Some(home) => home,
+        None       => { return Err(UtilError::UserHomeDirNotFound); }
+    };
+
+    // Add the path and return
+    Ok(home.join(".brane").join("keys"))
 }
 
 /// Makes sure that the history file exists and then returns its path.
 /// 
 /// **Arguments**
-///  * `create`: If true, creates the directory if it does not exist; if false, throws an error.
+///  * `create`: If true, creates the file (and its directory) if it does not exist; if false, throws an error.
 /// 
 /// **Returns**  
 /// The path of the HistoryFile or a UtilError otherwise.
@@ -321,8 +427,12 @@ pub fn ensure_history_file(create: bool) -> Result<PathBuf, UtilError> {
     if !history_file.exists() {
         // Either create it if told to do so, or error
         if create {
-            // Make sure the config directory exists
-            ensure_config_dir(create)?;
+            // Make sure the directory of the history file exists
+            if let Some(history_dir) = history_file.parent() {
+                if !history_dir.exists() {
+                    if let Err(err) = fs::create_dir_all(history_dir) { return Err(UtilError::HistoryDirCreateError{ path: history_dir.to_path_buf(), err }); }
+                }
+            }
 
             // Now create the file
             if let Err(err) = File::create(&history_file) { return Err(UtilError::HistoryFileCreateError{ path: history_file, err }); }
@@ -450,12 +560,12 @@ pub fn get_package_dir(
     // Otherwise, resolve the version number if its 'latest'
     let version = version.unwrap();
     let version = if version.is_latest() {
-        // Get the list of versions
-        let mut versions = get_package_versions(name, &package_dir)?;
-
-        // Sort the versions and return the last one
-        versions.sort();
-        versions[versions.len() - 1].clone()
+        // Resolve it the same way the VM resolves imports
+        let versions = get_package_versions(name, &package_dir)?;
+        match VersionConstraint::Latest.best_match(&versions) {
+            Some(version) => version.clone(),
+            None          => { return Err(UtilError::NoVersions{ package: name.to_string() }); }
+        }
     } else {
         // Simply use the given version
         version.clone()
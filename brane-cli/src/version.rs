@@ -4,7 +4,7 @@
  * Created:
  *   08 May 2022, 13:31:16
  * Last edited:
- *   08 May 2022, 14:37:46
+ *   16 Oct 2026, 00:00:23
  * Auto updated?
  *   Yes
  *
@@ -21,8 +21,9 @@ use reqwest::{Response, StatusCode};
 use specifications::registry::RegistryConfig;
 use specifications::version::Version;
 
+use crate::credentials::CredentialManager;
 use crate::errors::VersionError;
-use crate::utils::get_config_dir;
+use crate::proxy;
 
 
 /***** HELPER STRUCTS *****/
@@ -81,18 +82,9 @@ impl RemoteVersion {
     async fn new() -> Result<Self, VersionError> {
         debug!("Retrieving remote version number");
 
-        // Try to get the registry file path
+        // Load the registry file
         debug!(" > Reading registy.yml...");
-        let config_file = match get_config_dir() {
-            Ok(dir)  => dir.join("registry.yml"),
-            Err(err) => { return Err(VersionError::ConfigDirError{ err }); }
-        };
-
-        // We are, so load the registry file
-        let registry = match RegistryConfig::from_path(&config_file) {
-            Ok(registry) => registry,
-            Err(err)     => { return Err(VersionError::RegistryFileError{ err }); }
-        };
+        let registry = read_registry_file()?;
 
         // Pass to the other constructor
         Self::from_registry_file(registry).await
@@ -108,10 +100,17 @@ impl RemoteVersion {
     async fn from_registry_file(registry: RegistryConfig) -> Result<Self, VersionError> {
         // Use reqwest for the API call
         debug!(" > Querying...");
-        let mut url: String = registry.url.clone(); url.push_str("/version");
-        let response: Response = match reqwest::get(&url).await {
+        let url = version_url(&registry);
+        let client = match proxy::client_builder().build() {
+            Ok(client) => client,
+            Err(err)   => { return Err(VersionError::RequestError{ url, err }); }
+        };
+        let response: Response = match client.get(&url).send().await {
             Ok(version) => version,
-            Err(err)    => { return Err(VersionError::RequestError{ url, err }); }
+            Err(err)    => match proxy::as_proxy_error(err) {
+                Ok(err)  => { return Err(VersionError::ProxyError{ err }); },
+                Err(err) => { return Err(VersionError::RequestError{ url, err }); },
+            },
         };
         if response.status() != StatusCode::OK {
             return Err(VersionError::RequestFailure{ url, status: response.status() });
@@ -147,6 +146,40 @@ impl Display for RemoteVersion {
 
 
 
+/***** HELPER FUNCTIONS *****/
+/// Reads the Brane registry login file (get_config_dir()/registry.yml).
+/// 
+/// # Returns
+/// The RegistryConfig in the file on success, or else a VersionError.
+fn read_registry_file() -> Result<RegistryConfig, VersionError> {
+    let manager = match CredentialManager::new() {
+        Ok(manager) => manager,
+        Err(err)    => { return Err(VersionError::CredentialError{ err }); }
+    };
+    match manager.registry() {
+        Ok(registry) => Ok(registry),
+        Err(err)     => Err(VersionError::CredentialError{ err }),
+    }
+}
+
+/// Returns the endpoint of the given registry that reports its version.
+#[inline]
+fn version_url(registry: &RegistryConfig) -> String {
+    format!("{}/version", registry.url)
+}
+
+/// Returns the endpoint that `handle_remote()` queries, without querying it.
+/// 
+/// # Returns
+/// The URL of the endpoint on success, or else a VersionError if the registry login file could not be read.
+pub fn remote_endpoint() -> Result<String, VersionError> {
+    Ok(version_url(&read_registry_file()?))
+}
+
+
+
+
+
 /***** HANDLERS *****/
 /// Returns the local version (without any extra text).
 pub fn handle_local() -> Result<(), VersionError> {
@@ -171,7 +204,10 @@ pub async fn handle_remote() -> Result<(), VersionError> {
 
 
 /// Returns both the local and possible remote version numbers with some pretty formatting.
-pub async fn handle() -> Result<(), VersionError> {
+/// 
+/// # Arguments
+/// - `offline`: If true, only prints where the remote instance is instead of querying its version.
+pub async fn handle(offline: bool) -> Result<(), VersionError> {
     // Get the local version first and immediately print
     println!();
     println!("Brane CLI client");
@@ -179,19 +215,24 @@ pub async fn handle() -> Result<(), VersionError> {
     println!();
 
     // If the registry file exists, then also do the remote
-    let config_file = match get_config_dir() {
-        Ok(dir)  => dir.join("registry.yml"),
-        Err(err) => { return Err(VersionError::ConfigDirError{ err }); }
+    let manager = match CredentialManager::new() {
+        Ok(manager) => manager,
+        Err(err)    => { return Err(VersionError::CredentialError{ err }); }
     };
-    if config_file.exists() {
+    if manager.path().exists() {
         // Get the registry file from it
-        let registry = match RegistryConfig::from_path(&config_file) {
+        let registry = match manager.registry() {
             Ok(registry) => registry,
-            Err(err)     => { return Err(VersionError::RegistryFileError{ err }); }
+            Err(err)     => { return Err(VersionError::CredentialError{ err }); }
         };
 
         // Print the URL
         println!("Remote Brane instance at '{}'", &registry.url);
+        if offline {
+            println!(" - Version: <not checked in offline mode>");
+            println!();
+            return Ok(());
+        }
         
         // Get the version
         let version = RemoteVersion::from_registry_file(registry).await?;
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use brane_cli::archive::{self, ArchiveError, IMAGE_FILE, MANIFEST_FILE};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use specifications::package::{PackageInfo, PackageKind};
+use specifications::version::Version;
+
+/// Writes a package directory with a package.yml, a container.yml and a file in a subdirectory.
+fn package(dir: &Path, name: &str, digest: Option<String>) -> PackageInfo {
+    let mut info = PackageInfo::new(name.to_string(), Version::from_str("1.0.0").unwrap(), PackageKind::Ecu, vec![], String::from("A test package"), false, HashMap::new(), HashMap::new(), vec![]);
+    info.digest = digest;
+    fs::create_dir_all(dir.join("wd")).unwrap();
+    info.to_path(dir.join("package.yml")).unwrap();
+    fs::write(dir.join("container.yml"), "name: test\n").unwrap();
+    fs::write(dir.join("wd").join("run.sh"), "#!/bin/sh\necho hello\n").unwrap();
+    info
+}
+
+/// Packs a package with a stand-in image file into an archive in the given directory.
+fn archive(dir: &Path) -> std::path::PathBuf {
+    let package_dir = dir.join("source");
+    fs::create_dir_all(&package_dir).unwrap();
+    package(&package_dir, "hello", Some(String::from("sha256:0123")));
+    fs::write(package_dir.join(IMAGE_FILE), "the package's own image, which is left out").unwrap();
+    fs::write(dir.join("saved.tar"), "not really an image").unwrap();
+
+    let output = dir.join("hello.tar.gz");
+    archive::pack(&package_dir, &dir.join("saved.tar"), &output).unwrap();
+    output
+}
+
+fn extract(archive: &Path, dir: &Path) {
+    tar::Archive::new(GzDecoder::new(fs::File::open(archive).unwrap())).unpack(dir).unwrap();
+}
+
+fn repack(dir: &Path, output: &Path) {
+    let mut archive = tar::Builder::new(GzEncoder::new(fs::File::create(output).unwrap(), Compression::default()));
+    archive.append_dir_all(".", dir).unwrap();
+    archive.into_inner().unwrap().finish().unwrap();
+}
+
+#[test]
+fn pack_and_unpack_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = archive(dir.path());
+
+    let staging = tempfile::tempdir().unwrap();
+    let (manifest, info) = archive::unpack(&output, staging.path()).unwrap();
+    assert_eq!(manifest.name, "hello");
+    assert_eq!(manifest.digest.as_deref(), Some("sha256:0123"));
+    assert_eq!(manifest.files.keys().map(String::as_str).collect::<Vec<_>>(), vec![ "image.tar", "package/container.yml", "package/package.yml", "package/wd/run.sh" ]);
+    assert_eq!(info.version, Version::from_str("1.0.0").unwrap());
+    assert_eq!(fs::read_to_string(staging.path().join(IMAGE_FILE)).unwrap(), "not really an image");
+    assert_eq!(fs::read_to_string(staging.path().join("package").join("wd").join("run.sh")).unwrap(), "#!/bin/sh\necho hello\n");
+}
+
+#[test]
+fn unpack_rejects_corrupted_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = archive(dir.path());
+    let raw = tempfile::tempdir().unwrap();
+    extract(&output, raw.path());
+    fs::write(raw.path().join("package").join("container.yml"), "name: evil\n").unwrap();
+    repack(raw.path(), &output);
+
+    let err = archive::unpack(&output, tempfile::tempdir().unwrap().path()).unwrap_err();
+    assert!(matches!(err, ArchiveError::DigestMismatch{ ref file, .. } if file == "package/container.yml"), "Expected a digest mismatch, got {:?}", err);
+}
+
+#[test]
+fn unpack_rejects_unlisted_and_missing_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = archive(dir.path());
+    let raw = tempfile::tempdir().unwrap();
+    extract(&output, raw.path());
+    fs::write(raw.path().join("package").join("extra.sh"), "rm -rf /\n").unwrap();
+    repack(raw.path(), &output);
+    let err = archive::unpack(&output, tempfile::tempdir().unwrap().path()).unwrap_err();
+    assert!(matches!(err, ArchiveError::UnlistedFile{ ref file, .. } if file == "package/extra.sh"), "Expected an unlisted file, got {:?}", err);
+
+    fs::remove_file(raw.path().join("package").join("extra.sh")).unwrap();
+    fs::remove_file(raw.path().join(IMAGE_FILE)).unwrap();
+    repack(raw.path(), &output);
+    let err = archive::unpack(&output, tempfile::tempdir().unwrap().path()).unwrap_err();
+    assert!(matches!(err, ArchiveError::MissingFile{ ref file, .. } if file == IMAGE_FILE), "Expected a missing file, got {:?}", err);
+}
+
+#[test]
+fn unpack_rejects_mismatched_manifest() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = archive(dir.path());
+    let raw = tempfile::tempdir().unwrap();
+    extract(&output, raw.path());
+    let manifest = fs::read_to_string(raw.path().join(MANIFEST_FILE)).unwrap();
+    fs::write(raw.path().join(MANIFEST_FILE), manifest.replace("\"hello\"", "\"goodbye\"")).unwrap();
+    repack(raw.path(), &output);
+
+    let err = archive::unpack(&output, tempfile::tempdir().unwrap().path()).unwrap_err();
+    assert!(matches!(err, ArchiveError::ManifestMismatch{ what: "package", .. }), "Expected a manifest mismatch, got {:?}", err);
+}
+
+#[test]
+fn install_replaces_existing_version() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = archive(dir.path());
+    let staging = tempfile::tempdir().unwrap();
+    let (_, info) = archive::unpack(&output, staging.path()).unwrap();
+
+    // An older import of the same version, and what a killed import left behind
+    let packages = tempfile::tempdir().unwrap();
+    let target = packages.path().join("hello").join("1.0.0");
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("stale.txt"), "old").unwrap();
+    fs::create_dir_all(packages.path().join("hello").join(".1.0.0.import")).unwrap();
+
+    archive::install(staging.path(), packages.path(), &info).unwrap();
+    assert_eq!(fs::read_to_string(target.join(IMAGE_FILE)).unwrap(), "not really an image");
+    assert!(target.join("package.yml").exists());
+    assert!(target.join("wd").join("run.sh").exists());
+    assert!(!target.join("stale.txt").exists());
+    assert!(!target.join(".lock").exists());
+    let mut entries: Vec<_> = fs::read_dir(packages.path().join("hello")).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    entries.sort();
+    assert_eq!(entries, vec![ "1.0.0" ]);
+}
+
+
+
+#[cfg(feature = "docker-archive-tests")]
+mod docker {
+    use super::*;
+
+    use bollard::Docker;
+    use bollard::image::{CreateImageOptions, TagImageOptions};
+    use futures_util::stream::TryStreamExt;
+
+    /// Tags busybox (pulling it if needed) as version 1.0.0 of a new package, and returns that package's name and image digest.
+    async fn busybox(docker: &Docker) -> (String, String) {
+        if docker.inspect_image("busybox:latest").await.is_err() {
+            docker.create_image(Some(CreateImageOptions{ from_image: "busybox", tag: "latest", ..Default::default() }), None, None).try_collect::<Vec<_>>().await.unwrap();
+        }
+        let name = format!("archive-test-{}", uuid::Uuid::new_v4());
+        docker.tag_image("busybox:latest", Some(TagImageOptions{ repo: name.as_str(), tag: "1.0.0" })).await.unwrap();
+        let digest = docker.inspect_image(&format!("{}:1.0.0", name)).await.unwrap().id;
+        (name, digest)
+    }
+
+    #[tokio::test]
+    async fn export_and_import_round_trip() {
+        let docker = Docker::connect_with_local_defaults().unwrap();
+        let (name, digest) = busybox(&docker).await;
+        let image = format!("{}:1.0.0", name);
+        let source = tempfile::tempdir().unwrap();
+        package(source.path(), &name, Some(digest.clone()));
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("busybox.tar.gz");
+        archive::export_package(&docker, source.path(), &output).await.unwrap();
+
+        // Import it as if on another machine
+        docker.remove_image(&image, None, None).await.unwrap();
+        let packages = tempfile::tempdir().unwrap();
+        let info = archive::import_archive(&docker, &output, packages.path(), |_| panic!("There is nothing to overwrite")).await.unwrap();
+        assert_eq!(info.digest.as_deref(), Some(digest.as_str()));
+        assert_eq!(docker.inspect_image(&image).await.unwrap().id, digest);
+        assert!(packages.path().join(&name).join("1.0.0").join(IMAGE_FILE).exists());
+
+        // Importing it again asks first
+        let err = archive::import_archive(&docker, &output, packages.path(), |_| Ok(false)).await.unwrap_err();
+        assert!(matches!(err, ArchiveError::PackageConflict{ .. }), "Expected a conflict, got {:?}", err);
+        archive::import_archive(&docker, &output, packages.path(), |_| Ok(true)).await.unwrap();
+
+        docker.remove_image(&image, None, None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_import_cleans_up() {
+        let docker = Docker::connect_with_local_defaults().unwrap();
+        let (name, digest) = busybox(&docker).await;
+        let image = format!("{}:1.0.0", name);
+        let source = tempfile::tempdir().unwrap();
+        package(source.path(), &name, Some(digest));
+        let dir = tempfile::tempdir().unwrap();
+        archive::export_package(&docker, source.path(), &dir.path().join("good.tar.gz")).await.unwrap();
+
+        // Pack the same image with a package.yml that claims a different one
+        let raw = tempfile::tempdir().unwrap();
+        extract(&dir.path().join("good.tar.gz"), raw.path());
+        let bad_source = tempfile::tempdir().unwrap();
+        package(bad_source.path(), &name, Some(String::from("sha256:0000000000000000000000000000000000000000000000000000000000000000")));
+        let output = dir.path().join("bad.tar.gz");
+        archive::pack(bad_source.path(), &raw.path().join(IMAGE_FILE), &output).unwrap();
+
+        docker.remove_image(&image, None, None).await.unwrap();
+        let packages = tempfile::tempdir().unwrap();
+        let err = archive::import_archive(&docker, &output, packages.path(), |_| Ok(true)).await.unwrap_err();
+        assert!(matches!(err, ArchiveError::ImageDigestMismatch{ .. }), "Expected a digest mismatch, got {:?}", err);
+
+        // Neither the image nor the package stuck around
+        assert!(docker.inspect_image(&image).await.is_err());
+        assert!(!packages.path().join(&name).exists());
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+
+use brane_cli::build_common::{build_commands, buildx_args, check_platforms, compare_images, hash_inputs, host_platform, podman_build_args, BuildFlags, ImageDifference, ImageManifest, ImageOptions, ImageOutput};
+use brane_cli::build_ecu::build_context;
+use brane_cli::errors::BuildError;
+use brane_cli::registry::check_platform;
+use brane_cli::runtime::BuildBackend;
+use flate2::read::GzDecoder;
+use specifications::container::ContainerInfo;
+use specifications::package::{PackageInfo, PackageKind, ReproducibleBuild};
+use specifications::version::Version;
+
+const CONTAINER: &str = r#"
+name: hello
+version: 1.0.0
+kind: ecu
+
+entrypoint:
+  kind: task
+  exec: run.sh
+
+environment:
+  GREETING: hello
+  AUDIENCE: world
+  PUNCTUATION: "!"
+
+files:
+  - run.sh
+  - data
+
+actions:
+  'hello':
+    output:
+      - type: string
+        name: output
+  'goodbye':
+    output:
+      - type: string
+        name: output
+  'wave':
+    input:
+      - type: integer
+        name: times
+"#;
+
+fn platforms(platforms: &[&str]) -> Vec<String> {
+    platforms.iter().map(|platform| platform.to_string()).collect()
+}
+
+fn package(built_for: &[&str]) -> PackageInfo {
+    let mut info = PackageInfo::new(String::from("hello"), Version::from_str("1.0.0").unwrap(), PackageKind::Ecu, vec![], String::new(), false, HashMap::new(), HashMap::new(), vec![]);
+    info.platforms = platforms(built_for);
+    info
+}
+
+#[test]
+fn buildx_args_for_the_host_platform() {
+    let args = buildx_args("hello:1.0.0", ImageOutput::Tar, &[], BuildFlags::default()).unwrap();
+    assert_eq!(args, vec![ "buildx", "build", "--output", "type=docker,dest=image.tar", "--tag", "hello:1.0.0", "." ]);
+
+    let args = buildx_args("hello:1.0.0", ImageOutput::Cache("deps"), &platforms(&[ "linux/arm64" ]), BuildFlags::default()).unwrap();
+    assert_eq!(args, vec![ "buildx", "build", "--platform", "linux/arm64", "--target", "deps", "." ]);
+}
+
+#[test]
+fn buildx_args_for_multiple_platforms() {
+    let both = platforms(&[ "linux/amd64", "linux/arm64" ]);
+    let args = buildx_args("hello:1.0.0", ImageOutput::Push("ghcr.io/org/hello:1.0.0"), &both, BuildFlags::default()).unwrap();
+    assert_eq!(args, vec![ "buildx", "build", "--platform", "linux/amd64,linux/arm64", "--push", "--tag", "ghcr.io/org/hello:1.0.0", "." ]);
+
+    // buildx cannot put those in a single image.tar
+    assert!(matches!(buildx_args("hello:1.0.0", ImageOutput::Tar, &both, BuildFlags::default()), Err(BuildError::MultiPlatformTar{ .. })));
+}
+
+#[test]
+fn podman_builds_without_buildx() {
+    let commands = podman_build_args("hello:1.0.0", ImageOutput::Tar, &[], BuildFlags::default()).unwrap();
+    assert_eq!(commands, vec![
+        vec![ "build", "--tag", "hello:1.0.0", "." ],
+        vec![ "save", "--format", "docker-archive", "--output", "image.tar", "hello:1.0.0" ],
+    ]);
+    let commands = podman_build_args("hello:1.0.0", ImageOutput::Cache("deps"), &platforms(&[ "linux/arm64" ]), BuildFlags::default()).unwrap();
+    assert_eq!(commands, vec![ vec![ "build", "--platform", "linux/arm64", "--target", "deps", "." ] ]);
+
+    // Only buildx can push or build for multiple platforms
+    assert!(matches!(podman_build_args("hello:1.0.0", ImageOutput::Push("ghcr.io/org/hello:1.0.0"), &[], BuildFlags::default()), Err(BuildError::RuntimeUnsupported{ .. })));
+    assert!(matches!(podman_build_args("hello:1.0.0", ImageOutput::Cache("deps"), &platforms(&[ "linux/amd64", "linux/arm64" ]), BuildFlags::default()), Err(BuildError::RuntimeUnsupported{ .. })));
+}
+
+#[test]
+fn build_commands_follow_the_backend() {
+    let commands = build_commands(BuildBackend::Buildx, "hello:1.0.0", ImageOutput::Tar, &[], BuildFlags::default()).unwrap();
+    assert_eq!(commands, vec![ ("docker", buildx_args("hello:1.0.0", ImageOutput::Tar, &[], BuildFlags::default()).unwrap()) ]);
+    let commands = build_commands(BuildBackend::PodmanBuild, "hello:1.0.0", ImageOutput::Tar, &[], BuildFlags::default()).unwrap();
+    assert_eq!(commands.iter().map(|(program, _)| *program).collect::<Vec<_>>(), vec![ "podman", "podman" ]);
+}
+
+#[test]
+fn platforms_are_checked() {
+    check_platforms(&platforms(&[ "linux/amd64", "linux/arm/v7" ])).unwrap();
+    assert!(matches!(check_platforms(&platforms(&[ "amd64" ])), Err(BuildError::IllegalPlatform{ .. })));
+    assert!(matches!(check_platforms(&platforms(&[ "linux/" ])), Err(BuildError::IllegalPlatform{ .. })));
+    assert!(matches!(check_platforms(&platforms(&[ "linux/amd64", "linux/amd64" ])), Err(BuildError::IllegalPlatform{ .. })));
+}
+
+#[test]
+fn image_tar_gets_a_single_platform() {
+    let options = ImageOptions::default();
+    assert_eq!(options.tar_platform(), None);
+    assert_eq!(options.built_platforms(), vec![ host_platform() ]);
+
+    // The host platform is preferred, so the image.tar can be run locally
+    let options = ImageOptions{ platforms: platforms(&[ "linux/s390x", &host_platform() ]), push: Some(String::from("ghcr.io/org")), ..Default::default() };
+    assert_eq!(options.tar_platform(), Some(host_platform()));
+    assert_eq!(options.built_platforms().len(), 2);
+
+    let options = ImageOptions{ platforms: platforms(&[ "linux/s390x", "linux/ppc64le" ]), push: Some(String::from("ghcr.io/org")), ..Default::default() };
+    assert_eq!(options.tar_platform().as_deref(), Some("linux/s390x"));
+}
+
+#[test]
+fn push_checks_the_registry_platform() {
+    check_platform(&package(&[ "linux/amd64", "linux/arm64" ]), "linux/arm64").unwrap();
+    assert!(check_platform(&package(&[ "linux/arm64" ]), "linux/amd64").unwrap_err().to_string().contains("--platform linux/amd64"));
+
+    // Packages from before multi-platform builds don't say, so they are let through
+    check_platform(&package(&[]), "linux/amd64").unwrap();
+}
+
+#[test]
+fn package_info_platforms_are_optional() {
+    let info = package(&[ "linux/amd64", "linux/arm64" ]);
+    let yaml = serde_yaml::to_string(&info).unwrap();
+    assert_eq!(PackageInfo::from_string(yaml).unwrap().platforms, info.platforms);
+
+    // Older package.yml files don't have the field at all
+    let yaml = serde_yaml::to_string(&package(&[])).unwrap();
+    assert!(!yaml.contains("platforms"));
+    assert!(PackageInfo::from_string(yaml).unwrap().platforms.is_empty());
+}
+
+#[test]
+fn reproducible_builds_pass_source_date_epoch() {
+    let flags = BuildFlags{ source_date_epoch: Some(1700000000), no_cache: false };
+    let args = buildx_args("hello:1.0.0", ImageOutput::Tar, &[], flags).unwrap();
+    assert_eq!(args, vec![ "buildx", "build", "--build-arg", "SOURCE_DATE_EPOCH=1700000000", "--provenance=false", "--output", "type=docker,dest=image.tar,rewrite-timestamp=true", "--tag", "hello:1.0.0", "." ]);
+    let args = buildx_args("hello:1.0.0", ImageOutput::Push("ghcr.io/org/hello:1.0.0"), &[], BuildFlags{ no_cache: true, ..flags }).unwrap();
+    assert_eq!(args, vec![ "buildx", "build", "--build-arg", "SOURCE_DATE_EPOCH=1700000000", "--provenance=false", "--no-cache", "--output", "type=registry,rewrite-timestamp=true", "--tag", "ghcr.io/org/hello:1.0.0", "." ]);
+
+    let commands = podman_build_args("hello:1.0.0", ImageOutput::Cache("deps"), &[], flags).unwrap();
+    assert_eq!(commands, vec![ vec![ "build", "--timestamp", "1700000000", "--build-arg", "SOURCE_DATE_EPOCH=1700000000", "--target", "deps", "." ] ]);
+}
+
+#[tokio::test]
+async fn reproducible_build_context_is_byte_identical() {
+    let context = tempfile::tempdir().unwrap();
+    fs::write(context.path().join("container.yml"), CONTAINER).unwrap();
+    fs::write(context.path().join("run.sh"), "#!/bin/bash\necho \"output: $GREETING, $AUDIENCE$PUNCTUATION\"\n").unwrap();
+    fs::create_dir(context.path().join("data")).unwrap();
+    for name in [ "b.txt", "a.txt", "c.txt" ] { fs::write(context.path().join("data").join(name), name).unwrap(); }
+    // Not an ELF executable, so it's used for any architecture
+    let branelet = context.path().join("branelet");
+    fs::write(&branelet, "not really a branelet").unwrap();
+    let platforms = vec![ String::from("linux/amd64") ];
+    let document = ContainerInfo::from_string(CONTAINER.to_string()).unwrap();
+
+    // Build the context twice, in different directories and at different times
+    let first = tempfile::tempdir().unwrap();
+    build_context(&document, context.path().to_path_buf(), first.path(), Some(branelet.clone()), &platforms, Some(1700000000)).await.unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    let second = tempfile::tempdir().unwrap();
+    build_context(&document, context.path().to_path_buf(), second.path(), Some(branelet), &platforms, Some(1700000000)).await.unwrap();
+
+    for input in [ "Dockerfile", "container/branelet-amd64", "container/wd.tar.gz" ] {
+        assert_eq!(fs::read(first.path().join(input)).unwrap(), fs::read(second.path().join(input)).unwrap(), "'{}' differs between builds", input);
+    }
+    let inputs = [ "Dockerfile", "container/branelet-amd64", "container/wd.tar.gz" ];
+    assert_eq!(hash_inputs(first.path(), &inputs).unwrap(), hash_inputs(second.path(), &inputs).unwrap());
+    assert_eq!(hash_inputs(first.path(), &inputs).unwrap().len(), 3);
+
+    // The environment is sorted, and the archive is sorted and normalized
+    let dockerfile = fs::read_to_string(first.path().join("Dockerfile")).unwrap();
+    let env: Vec<&str> = dockerfile.lines().filter(|line| line.starts_with("ENV ")).collect();
+    assert_eq!(env, vec![ "ENV AUDIENCE=world", "ENV GREETING=hello", "ENV PUNCTUATION=!" ]);
+    assert!(dockerfile.contains("ARG TARGETARCH\nADD ./container/branelet-${TARGETARCH} /branelet\n"));
+    let mut archive = tar::Archive::new(GzDecoder::new(fs::File::open(first.path().join("container/wd.tar.gz")).unwrap()));
+    let mut names = vec![];
+    for entry in archive.entries().unwrap() {
+        let entry = entry.unwrap();
+        let header = entry.header();
+        assert_eq!((header.mtime().unwrap(), header.uid().unwrap(), header.gid().unwrap()), (1700000000, 0, 0));
+        names.push(entry.path().unwrap().display().to_string().trim_end_matches('/').to_string());
+    }
+    assert_eq!(names, vec![ "wd", "wd/data", "wd/data/a.txt", "wd/data/b.txt", "wd/data/c.txt", "wd/local_container.yml", "wd/run.sh" ]);
+}
+
+#[test]
+fn rebuilds_are_compared_layer_by_layer() {
+    let manifest = |config: &str, layers: &[&str]| ImageManifest{ config: config.to_string(), layers: layers.iter().map(|layer| layer.to_string()).collect() };
+    let first = manifest("blobs/sha256/aaa", &[ "blobs/sha256/1", "blobs/sha256/2" ]);
+    assert_eq!(compare_images(&first, &first.clone()), None);
+
+    let second = manifest("blobs/sha256/bbb", &[ "blobs/sha256/1", "blobs/sha256/3" ]);
+    assert_eq!(compare_images(&first, &second), Some(ImageDifference::Layer{ index: 1, first: Some(String::from("blobs/sha256/2")), second: Some(String::from("blobs/sha256/3")) }));
+    let second = manifest("blobs/sha256/bbb", &[ "blobs/sha256/1", "blobs/sha256/2", "blobs/sha256/4" ]);
+    assert_eq!(compare_images(&first, &second), Some(ImageDifference::Layer{ index: 2, first: None, second: Some(String::from("blobs/sha256/4")) }));
+    let second = manifest("blobs/sha256/bbb", &[ "blobs/sha256/1", "blobs/sha256/2" ]);
+    assert!(compare_images(&first, &second).unwrap().to_string().contains("image config differs"));
+
+    // Docker's manifest.json is read as-is
+    let parsed: Vec<ImageManifest> = serde_json::from_str(r#"[{"Config":"blobs/sha256/aaa","RepoTags":["hello:1.0.0"],"Layers":["blobs/sha256/1","blobs/sha256/2"]}]"#).unwrap();
+    assert_eq!(parsed[0], first);
+}
+
+#[test]
+fn package_info_records_reproducible_builds() {
+    let mut info = package(&[]);
+    assert!(!serde_yaml::to_string(&info).unwrap().contains("reproducible"));
+
+    let mut inputs = std::collections::BTreeMap::new();
+    inputs.insert(String::from("Dockerfile"), String::from("sha256:abc"));
+    info.reproducible = Some(ReproducibleBuild{ source_date_epoch: 1700000000, inputs });
+    let yaml = serde_yaml::to_string(&info).unwrap();
+    assert!(yaml.contains("sourceDateEpoch: 1700000000"), "Unexpected package info:\n{}", yaml);
+    assert_eq!(PackageInfo::from_string(yaml).unwrap().reproducible, info.reproducible);
+}
@@ -0,0 +1,128 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use brane_cli::build_dag::{lock_tag, BuildDag};
+use brane_cli::errors::BuildError;
+
+const STEP_TIME: Duration = Duration::from_millis(200);
+
+/// When every (mocked) build step started and finished.
+#[derive(Clone, Default)]
+struct Timeline {
+    spans: Arc<Mutex<Vec<(&'static str, Instant, Instant)>>>,
+}
+
+impl Timeline {
+    /// Stands in for a step that runs a command for STEP_TIME.
+    fn step(&self, name: &'static str) -> impl std::future::Future<Output = Result<(), BuildError>> + Send + 'static {
+        let spans = self.spans.clone();
+        async move {
+            let start = Instant::now();
+            tokio::time::sleep(STEP_TIME).await;
+            spans.lock().unwrap().push((name, start, Instant::now()));
+            Ok(())
+        }
+    }
+
+    /// Stands in for a step that runs Docker for the given tag.
+    fn docker_step(&self, name: &'static str, tag: &'static str) -> impl std::future::Future<Output = Result<(), BuildError>> + Send + 'static {
+        let step = self.step(name);
+        async move {
+            let _lock = lock_tag(tag).await;
+            step.await
+        }
+    }
+
+    fn span(&self, name: &str) -> (Instant, Instant) {
+        let spans = self.spans.lock().unwrap();
+        let (_, start, end) = spans.iter().find(|(n, _, _)| *n == name).unwrap_or_else(|| panic!("Step '{}' did not run", name));
+        (*start, *end)
+    }
+
+    fn overlap(&self, a: &str, b: &str) -> bool {
+        let (a_start, a_end) = self.span(a);
+        let (b_start, b_end) = self.span(b);
+        a_start < b_end && b_start < a_end
+    }
+}
+
+#[tokio::test]
+async fn independent_steps_overlap() {
+    let timeline = Timeline::default();
+    let mut dag = BuildDag::new();
+    dag.add("deps", &[], timeline.step("deps"));
+    dag.add("workdir", &[], timeline.step("workdir"));
+    dag.add("image", &["deps", "workdir"], timeline.step("image"));
+
+    let start = Instant::now();
+    dag.run(4).await.unwrap();
+    assert!(timeline.overlap("deps", "workdir"));
+    assert!(!timeline.overlap("deps", "image"));
+    assert!(!timeline.overlap("workdir", "image"));
+    assert!(timeline.span("image").0 >= timeline.span("deps").1);
+    // Two rounds of steps, not three
+    assert!(start.elapsed() < STEP_TIME * 3);
+}
+
+#[tokio::test]
+async fn jobs_bound_concurrency() {
+    let timeline = Timeline::default();
+    let mut dag = BuildDag::new();
+    dag.add("a", &[], timeline.step("a"));
+    dag.add("b", &[], timeline.step("b"));
+    dag.add("c", &[], timeline.step("c"));
+
+    dag.run(1).await.unwrap();
+    assert!(!timeline.overlap("a", "b"));
+    assert!(!timeline.overlap("a", "c"));
+    assert!(!timeline.overlap("b", "c"));
+}
+
+#[tokio::test]
+async fn same_tag_serializes() {
+    let timeline = Timeline::default();
+    let mut dag = BuildDag::new();
+    dag.add("first", &[], timeline.docker_step("first", "hello:1.0.0"));
+    dag.add("second", &[], timeline.docker_step("second", "hello:1.0.0"));
+    dag.add("other", &[], timeline.docker_step("other", "world:1.0.0"));
+
+    dag.run(4).await.unwrap();
+    assert!(!timeline.overlap("first", "second"));
+    assert!(timeline.overlap("first", "other") || timeline.overlap("second", "other"));
+}
+
+#[tokio::test]
+async fn failure_stops_dependents() {
+    let timeline = Timeline::default();
+    let mut dag = BuildDag::new();
+    dag.add("workdir", &[], async { Err(BuildError::UnsafePath{ path: String::from("../escape") }) });
+    dag.add("deps", &[], timeline.step("deps"));
+    dag.add("image", &["deps", "workdir"], timeline.step("image"));
+
+    let err = dag.run(4).await.unwrap_err();
+    assert!(matches!(err, BuildError::UnsafePath{ .. }));
+    // Already running steps are allowed to finish, but nothing new is started
+    timeline.span("deps");
+    assert!(timeline.spans.lock().unwrap().iter().all(|(name, _, _)| *name != "image"));
+}
+
+#[tokio::test]
+async fn invalid_dags_are_rejected() {
+    let mut dag = BuildDag::new();
+    dag.add("a", &["b"], async { Ok(()) });
+    dag.add("b", &["a"], async { Ok(()) });
+    dag.add("c", &[], async { Ok(()) });
+    match dag.run(1).await {
+        Err(BuildError::BuildStepCycle{ steps }) => assert_eq!(steps, vec!["a", "b"]),
+        other => panic!("Expected a cycle, got {:?}", other),
+    }
+
+    let mut dag = BuildDag::new();
+    dag.add("a", &["missing"], async { Ok(()) });
+    assert!(matches!(dag.run(1).await, Err(BuildError::BuildStepUnknownDependency{ .. })));
+
+    let mut dag = BuildDag::new();
+    dag.add("a", &[], async { Ok(()) });
+    dag.add("a", &[], async { Ok(()) });
+    assert!(matches!(dag.run(1).await, Err(BuildError::BuildStepDuplicate{ .. })));
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use brane_cli::completion::{candidates, output, script, strip_zsh_dispatch};
+use clap::{Arg, Command};
+use clap_complete::Shell;
+use specifications::package::{PackageIndex, PackageInfo, PackageKind};
+use specifications::version::Version;
+
+/// Returns an index with the given (name, version) pairs.
+fn index(packages: &[(&str, &str)]) -> PackageIndex {
+    PackageIndex::new(packages.iter().map(|(name, version)| {
+        let info = PackageInfo::new(name.to_string(), Version::from_str(version).unwrap(), PackageKind::Ecu, vec![], String::new(), false, HashMap::new(), HashMap::new(), vec![]);
+        (format!("{}-{}", name, version), info)
+    }).collect())
+}
+
+fn request(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+#[test]
+fn packages_are_listed_once_and_sorted() {
+    let index = index(&[ ("hello", "1.0.0"), ("arrays", "0.1.0"), ("hello", "2.0.0") ]);
+    assert_eq!(candidates(&index, &request(&[ "packages" ])), vec![ "arrays", "hello" ]);
+    assert_eq!(output(&index, &request(&[ "packages" ])), "arrays\nhello\n");
+}
+
+#[test]
+fn versions_are_listed_from_old_to_new() {
+    let index = index(&[ ("hello", "1.10.0"), ("hello", "1.2.0"), ("arrays", "0.1.0"), ("hello", "2.0.0") ]);
+    assert_eq!(output(&index, &request(&[ "versions", "hello" ])), "1.2.0\n1.10.0\n2.0.0\n");
+    assert_eq!(output(&index, &request(&[ "versions", "arrays" ])), "0.1.0\n");
+}
+
+#[test]
+fn unknown_requests_complete_nothing() {
+    let empty = index(&[]);
+    assert_eq!(output(&empty, &request(&[ "packages" ])), "");
+
+    let index = index(&[ ("hello", "1.0.0") ]);
+    for words in vec![ vec![], vec![ "versions" ], vec![ "versions", "goodbye" ], vec![ "packages", "hello" ], vec![ "functions" ] ] {
+        assert_eq!(output(&index, &request(&words)), "", "Request {:?} completed something", words);
+    }
+}
+
+#[test]
+fn scripts_call_the_helper() {
+    let command = || Command::new("brane").subcommand(Command::new("inspect").arg(Arg::new("NAME")).arg(Arg::new("VERSION")));
+    for shell in [ Shell::Bash, Shell::Zsh, Shell::Fish ] {
+        let text = script(shell, &mut command());
+        assert!(text.contains("brane __complete packages") && text.contains("brane __complete versions"), "{} script does not complete packages", shell);
+        assert!(text.contains("export inspect load push remove test unpublish"));
+    }
+
+    // Clap's own zsh completion is only called through ours
+    let zsh = script(Shell::Zsh, &mut command());
+    assert!(!zsh.lines().any(|line| line == "_brane \"$@\"" || line.trim() == "compdef _brane brane"));
+    assert!(zsh.contains("compdef _brane_dynamic brane"));
+
+    // Other shells get what clap generates
+    assert!(!script(Shell::PowerShell, &mut command()).contains("__complete"));
+}
+
+#[test]
+fn zsh_dispatch_is_recognized() {
+    // Older versions of clap simply call the function...
+    let old = "#compdef brane\n\n_brane() {\n    _arguments\n}\n\n_brane \"$@\"\n";
+    assert_eq!(strip_zsh_dispatch(old), Some("#compdef brane\n\n_brane() {\n    _arguments\n}\n"));
+
+    // ...newer ones only do so if the script is autoloaded
+    let new = "#compdef brane\n\n_brane() {\n    _brane \"$@\"\n}\n\nif [ \"$funcstack[1]\" = \"_brane\" ]; then\n    _brane \"$@\"\nelse\n    compdef _brane brane\nfi\n";
+    assert_eq!(strip_zsh_dispatch(new), Some("#compdef brane\n\n_brane() {\n    _brane \"$@\"\n}\n"));
+
+    // Anything else is left alone
+    assert_eq!(strip_zsh_dispatch("#compdef brane\n\n_brane() {\n}\n"), None);
+    assert_eq!(strip_zsh_dispatch("if [ \"$funcstack[1]\" = \"_brane\" ]; then\n    _brane \"$@\"\nfi\n_other"), None);
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+use brane_cli::credentials::{CredentialError, CredentialManager, CredentialStore, Credentials};
+use specifications::registry::RegistryConfig;
+
+const URL: &str = "http://registry.example.com:50051";
+
+/// Stands in for the OS keyring, which isn't available where the tests run.
+#[derive(Clone, Default)]
+struct MemoryStore {
+    entries: Arc<Mutex<HashMap<String, Credentials>>>,
+}
+
+impl CredentialStore for MemoryStore {
+    fn name(&self) -> &'static str { "memory" }
+
+    fn load(&self, url: &str) -> Result<Option<Credentials>, CredentialError> {
+        Ok(self.entries.lock().unwrap().get(url).cloned())
+    }
+
+    fn store(&self, url: &str, credentials: &Credentials) -> Result<(), CredentialError> {
+        self.entries.lock().unwrap().insert(url.to_string(), credentials.clone());
+        Ok(())
+    }
+
+    fn delete(&self, url: &str) -> Result<bool, CredentialError> {
+        Ok(self.entries.lock().unwrap().remove(url).is_some())
+    }
+}
+
+fn credentials() -> Credentials {
+    Credentials{ username: String::from("alice"), token: Some(String::from("s3cr3t")), oidc: None }
+}
+
+#[test]
+fn falls_back_to_plaintext_without_keyring() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("registry.yml");
+    let manager = CredentialManager::with_stores(&path, None);
+
+    manager.login(URL, &credentials(), false, false).unwrap();
+    let config = RegistryConfig::from_path(&path).unwrap();
+    assert_eq!(config.url, URL);
+    assert_eq!(config.username.as_deref(), Some("alice"));
+    assert_eq!(config.token.as_deref(), Some("s3cr3t"));
+    assert_eq!(manager.credentials().unwrap(), (URL.to_string(), Some(credentials())));
+
+    manager.logout().unwrap();
+    assert!(!path.exists());
+    assert!(manager.credentials().is_err());
+}
+
+#[test]
+fn keyring_keeps_secrets_out_of_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("registry.yml");
+    let keyring = MemoryStore::default();
+    let manager = CredentialManager::with_stores(&path, Some(Box::new(keyring.clone())));
+
+    assert_eq!(manager.login(URL, &credentials(), false, false).unwrap(), "memory");
+    let contents = fs::read_to_string(&path).unwrap();
+    assert!(!contents.contains("alice"));
+    assert!(!contents.contains("s3cr3t"));
+    assert_eq!(manager.credentials().unwrap().1, Some(credentials()));
+
+    manager.logout().unwrap();
+    assert!(keyring.entries.lock().unwrap().is_empty());
+}
+
+#[test]
+fn insecure_store_is_respected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("registry.yml");
+    let keyring = MemoryStore::default();
+    let manager = CredentialManager::with_stores(&path, Some(Box::new(keyring.clone())));
+
+    manager.login(URL, &credentials(), true, false).unwrap();
+    assert!(keyring.entries.lock().unwrap().is_empty());
+    // Not migrated on use either
+    assert_eq!(manager.credentials().unwrap().1, Some(credentials()));
+    assert!(keyring.entries.lock().unwrap().is_empty());
+    assert!(RegistryConfig::from_path(&path).unwrap().insecure_store);
+    assert!(!RegistryConfig::from_path(&path).unwrap().oci);
+
+    manager.logout().unwrap();
+    assert!(!path.exists());
+}
+
+#[test]
+fn legacy_plaintext_credentials_are_migrated() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("registry.yml");
+    // What older versions of brane login wrote
+    fs::write(&path, format!("url: {}\nusername: alice\n", URL)).unwrap();
+
+    let keyring = MemoryStore::default();
+    let manager = CredentialManager::with_stores(&path, Some(Box::new(keyring.clone())));
+    let expected = Credentials{ username: String::from("alice"), token: None, oidc: None };
+    assert_eq!(manager.credentials().unwrap(), (URL.to_string(), Some(expected.clone())));
+
+    // Moved to the keyring, and gone from the file
+    assert_eq!(keyring.entries.lock().unwrap().get(URL), Some(&expected));
+    let config = RegistryConfig::from_path(&path).unwrap();
+    assert_eq!(config.url, URL);
+    assert!(config.username.is_none());
+    assert_eq!(manager.credentials().unwrap().1, Some(expected));
+
+    // Logging out removes them from the keyring
+    manager.logout().unwrap();
+    assert!(keyring.entries.lock().unwrap().is_empty());
+}
+
+#[test]
+fn logging_in_elsewhere_forgets_old_registry() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("registry.yml");
+    let keyring = MemoryStore::default();
+    let manager = CredentialManager::with_stores(&path, Some(Box::new(keyring.clone())));
+
+    manager.login(URL, &credentials(), false, false).unwrap();
+    manager.login("http://other.example.com:50051", &credentials(), false, false).unwrap();
+    let entries = keyring.entries.lock().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert!(entries.contains_key("http://other.example.com:50051"));
+}
+
+#[test]
+fn oci_registries_are_remembered() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("registry.yml");
+    let manager = CredentialManager::with_stores(&path, None);
+
+    manager.login("https://ghcr.io/example", &credentials(), false, true).unwrap();
+    let config = RegistryConfig::from_path(&path).unwrap();
+    assert!(config.oci);
+    assert_eq!(config.url, "https://ghcr.io/example");
+    assert_eq!(manager.credentials().unwrap().1, Some(credentials()));
+}
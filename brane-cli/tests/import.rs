@@ -0,0 +1,27 @@
+use brane_cli::import::{is_auth_error, resolve_url};
+use git2::{Error, ErrorClass, ErrorCode};
+
+#[test]
+fn short_hands_resolve_to_github() {
+    assert_eq!(resolve_url("onnovalkering/brane"), "https://github.com/onnovalkering/brane");
+    assert_eq!(resolve_url("ssh://git@example.com/brane.git"), "ssh://git@example.com/brane.git");
+}
+
+#[test]
+fn refused_credentials_are_auth_errors() {
+    assert!(is_auth_error(&Error::new(ErrorCode::Auth, ErrorClass::Http, "authentication required but no callback set")));
+    assert!(is_auth_error(&Error::new(ErrorCode::GenericError, ErrorClass::Http, "unexpected http status code: 401")));
+    assert!(is_auth_error(&Error::new(ErrorCode::GenericError, ErrorClass::Http, "unexpected http status code: 403")));
+    assert!(is_auth_error(&Error::new(ErrorCode::GenericError, ErrorClass::Ssh, "Failed to authenticate SSH session: Unable to open public key file")));
+    assert!(is_auth_error(&Error::new(ErrorCode::User, ErrorClass::Callback, "no valid credentials found")));
+}
+
+#[test]
+fn other_errors_are_not_auth_errors() {
+    // Not even if they happen after we sent credentials
+    assert!(!is_auth_error(&Error::new(ErrorCode::GenericError, ErrorClass::Http, "unexpected http status code: 500")));
+    assert!(!is_auth_error(&Error::new(ErrorCode::GenericError, ErrorClass::Http, "unexpected http status code: 4031")));
+    assert!(!is_auth_error(&Error::new(ErrorCode::GenericError, ErrorClass::Net, "failed to connect to github.com: Connection refused")));
+    assert!(!is_auth_error(&Error::new(ErrorCode::NotFound, ErrorClass::Reference, "reference 'refs/heads/nope' not found")));
+    assert!(!is_auth_error(&Error::new(ErrorCode::GenericError, ErrorClass::Ssh, "Failed to retrieve list of SSH authentication methods: Failed getting banner")));
+}
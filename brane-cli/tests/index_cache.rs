@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use brane_cli::index_cache::IndexCache;
+use brane_cli::packages::load_package_index;
+use specifications::package::{PackageInfo, PackageKind};
+use specifications::version::Version;
+
+/// Writes a package.yml for the given package version in the given packages directory.
+fn add_package(packages_dir: &Path, name: &str, version: &str, description: &str) {
+    let info = PackageInfo::new(name.to_string(), Version::from_str(version).unwrap(), PackageKind::Ecu, vec![], description.to_string(), false, HashMap::new(), HashMap::new(), vec![]);
+    let dir = packages_dir.join(name).join(version);
+    fs::create_dir_all(&dir).unwrap();
+    info.to_path(dir.join("package.yml")).unwrap();
+}
+
+fn description(packages_dir: &Path, cache_file: &Path, name: &str) -> String {
+    let (index, _) = load_package_index(packages_dir, Some(cache_file), false).unwrap();
+    index.get(name, None).unwrap().description.clone()
+}
+
+#[test]
+fn warm_cache_skips_parsing() {
+    let dir = tempfile::tempdir().unwrap();
+    let packages_dir = dir.path().join("packages");
+    let cache_file = dir.path().join("index.cache");
+    for i in 0..50 { add_package(&packages_dir, &format!("package{}", i), "1.0.0", "A package"); }
+    add_package(&packages_dir, "package0", "2.0.0", "A newer package");
+
+    let (cold, stats) = load_package_index(&packages_dir, Some(&cache_file), false).unwrap();
+    assert_eq!((stats.cached, stats.parsed), (0, 51));
+    assert!(cache_file.exists());
+
+    let (warm, stats) = load_package_index(&packages_dir, Some(&cache_file), false).unwrap();
+    assert_eq!((stats.cached, stats.parsed), (51, 0));
+    assert_eq!(warm.packages.len(), cold.packages.len());
+    assert_eq!(warm.get("package0", None).unwrap().version, Version::new(2, 0, 0));
+
+    // Rebuilding ignores the cache
+    let (_, stats) = load_package_index(&packages_dir, Some(&cache_file), true).unwrap();
+    assert_eq!((stats.cached, stats.parsed), (0, 51));
+}
+
+#[test]
+fn changes_invalidate_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let packages_dir = dir.path().join("packages");
+    let cache_file = dir.path().join("index.cache");
+    add_package(&packages_dir, "hello", "1.0.0", "Hello");
+    add_package(&packages_dir, "world", "1.0.0", "World");
+    assert_eq!(description(&packages_dir, &cache_file, "hello"), "Hello");
+
+    // A rewritten package.yml is read again, even if it happens to keep the same size and modification time
+    add_package(&packages_dir, "hello", "1.0.0", "Howdy");
+    let yml = packages_dir.join("hello").join("1.0.0").join("package.yml");
+    filetime::set_file_mtime(&yml, filetime::FileTime::from_system_time(SystemTime::now() + Duration::from_secs(10))).unwrap();
+    let (_, stats) = load_package_index(&packages_dir, Some(&cache_file), false).unwrap();
+    assert_eq!((stats.cached, stats.parsed), (1, 1));
+    assert_eq!(description(&packages_dir, &cache_file, "hello"), "Howdy");
+
+    // So is one that got new files next to it
+    fs::write(packages_dir.join("world").join("1.0.0").join("image.tar"), b"").unwrap();
+    let (_, stats) = load_package_index(&packages_dir, Some(&cache_file), false).unwrap();
+    assert_eq!((stats.cached, stats.parsed), (1, 1));
+
+    // Removed packages disappear from the cache
+    fs::remove_dir_all(packages_dir.join("world")).unwrap();
+    let (index, _) = load_package_index(&packages_dir, Some(&cache_file), false).unwrap();
+    assert!(index.get("world", None).is_none());
+    assert_eq!(IndexCache::load(&cache_file).entries.len(), 1);
+
+    // Explicit invalidation (as done by build, pull and remove)
+    let mut cache = IndexCache::load(&cache_file);
+    assert!(cache.invalidate("hello", None));
+    cache.store(&cache_file).unwrap();
+    let (_, stats) = load_package_index(&packages_dir, Some(&cache_file), false).unwrap();
+    assert_eq!((stats.cached, stats.parsed), (0, 1));
+}
+
+#[test]
+fn broken_cache_is_rebuilt() {
+    let dir = tempfile::tempdir().unwrap();
+    let packages_dir = dir.path().join("packages");
+    let cache_file = dir.path().join("index.cache");
+    add_package(&packages_dir, "hello", "1.0.0", "Hello");
+
+    fs::write(&cache_file, b"{ not json").unwrap();
+    let (_, stats) = load_package_index(&packages_dir, Some(&cache_file), false).unwrap();
+    assert_eq!((stats.cached, stats.parsed), (0, 1));
+    let (_, stats) = load_package_index(&packages_dir, Some(&cache_file), false).unwrap();
+    assert_eq!((stats.cached, stats.parsed), (1, 0));
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use brane_cli::errors::BuildError;
+use brane_cli::init_binary::{binary_name, check_digest, elf_arch, platform_arch, release_digest, select};
+use specifications::package::{InitBinary, PackageInfo, PackageKind};
+use specifications::version::Version;
+
+/// Returns the start of a little-endian, 64-bit ELF executable for the given machine type.
+fn elf(machine: u16) -> Vec<u8> {
+    let mut header = vec![ 0x7f, b'E', b'L', b'F', 2, 1, 1 ];
+    header.resize(16, 0);
+    header.extend_from_slice(&2u16.to_le_bytes());
+    header.extend_from_slice(&machine.to_le_bytes());
+    header.resize(64, 0);
+    header
+}
+
+#[test]
+fn platforms_map_to_their_architecture() {
+    assert_eq!(platform_arch("linux/amd64"), "amd64");
+    assert_eq!(platform_arch("linux/arm64/v8"), "arm64");
+    assert_eq!(platform_arch("amd64"), "amd64");
+    assert_eq!(binary_name("arm64"), "branelet-arm64");
+}
+
+#[test]
+fn elf_headers_tell_the_architecture() {
+    assert_eq!(elf_arch(&elf(0x3E)), Some("amd64"));
+    assert_eq!(elf_arch(&elf(0xB7)), Some("arm64"));
+    assert_eq!(elf_arch(&elf(0x1234)), None);
+    assert_eq!(elf_arch(b"#!/bin/bash\necho hello\n"), None);
+    assert_eq!(elf_arch(b"\x7fELF"), None);
+
+    // Big-endian executables store the machine type the other way around
+    let mut header = elf(0);
+    header[5] = 2;
+    header[18..20].copy_from_slice(&0x16u16.to_be_bytes());
+    assert_eq!(elf_arch(&header), Some("s390x"));
+}
+
+#[test]
+fn bundled_binaries_are_selected_per_architecture() {
+    let first = tempfile::tempdir().unwrap();
+    let second = tempfile::tempdir().unwrap();
+    fs::write(first.path().join("branelet-amd64"), elf(0x3E)).unwrap();
+    fs::write(second.path().join("branelet-amd64"), elf(0x3E)).unwrap();
+    fs::write(second.path().join("branelet-arm64"), elf(0xB7)).unwrap();
+    let dirs = vec![ first.path().to_path_buf(), second.path().to_path_buf() ];
+
+    // The first directory that has one wins, and architectures nobody has are left to download
+    assert_eq!(select("amd64", None, &dirs).unwrap(), Some(first.path().join("branelet-amd64")));
+    assert_eq!(select("arm64", None, &dirs).unwrap(), Some(second.path().join("branelet-arm64")));
+    assert_eq!(select("s390x", None, &dirs).unwrap(), None);
+}
+
+#[test]
+fn custom_binaries_must_match_the_architecture() {
+    let dir = tempfile::tempdir().unwrap();
+    let branelet = dir.path().join("branelet");
+    fs::write(&branelet, elf(0xB7)).unwrap();
+
+    assert_eq!(select("arm64", Some(&branelet), &[]).unwrap(), Some(fs::canonicalize(&branelet).unwrap()));
+    match select("amd64", Some(&branelet), &[]) {
+        Err(BuildError::InitBinaryArchMismatch{ expected, got, .. }) => assert_eq!((expected.as_str(), got.as_str()), ("amd64", "arm64")),
+        result => panic!("Expected an InitBinaryArchMismatch, got {:?}", result),
+    }
+
+    // We can't tell for anything that isn't ELF, so those are trusted
+    let script = dir.path().join("branelet.sh");
+    fs::write(&script, "#!/bin/sh\n").unwrap();
+    assert!(select("amd64", Some(&script), &[]).unwrap().is_some());
+}
+
+#[test]
+fn custom_directories_must_have_every_architecture() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("branelet-amd64"), elf(0x3E)).unwrap();
+
+    assert_eq!(select("amd64", Some(dir.path()), &[]).unwrap(), Some(dir.path().join("branelet-amd64")));
+    let err = select("arm64", Some(dir.path()), &[ Path::new("/unused").to_path_buf() ]).unwrap_err();
+    match &err {
+        BuildError::MissingInitBinary{ arch, searched, err: None } => {
+            assert_eq!(arch, "arm64");
+            assert_eq!(searched, &vec![ dir.path().to_path_buf() ]);
+        },
+        err => panic!("Expected a MissingInitBinary, got {:?}", err),
+    }
+    assert!(err.to_string().contains("No init binary for architecture 'arm64'"));
+    assert!(err.to_string().contains("--init"));
+}
+
+#[test]
+fn selected_binaries_are_recorded_in_the_package_info() {
+    let mut info = PackageInfo::new(String::from("hello"), Version::from_str("1.0.0").unwrap(), PackageKind::Ecu, vec![], String::new(), false, HashMap::new(), HashMap::new(), vec![]);
+    assert!(info.init.is_empty());
+    assert!(!serde_yaml::to_string(&info).unwrap().contains("init:"));
+
+    info.init = vec![ InitBinary{ arch: String::from("amd64"), sha256: String::from("sha256:ab12") } ];
+    let info = PackageInfo::from_string(serde_yaml::to_string(&info).unwrap()).unwrap();
+    assert_eq!(info.init, vec![ InitBinary{ arch: String::from("amd64"), sha256: String::from("sha256:ab12") } ]);
+}
+
+#[test]
+fn downloads_must_match_the_release_digest() {
+    let digest = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+    assert!(check_digest("https://example.com/branelet-amd64", b"hello world", digest).is_ok());
+    assert!(check_digest("https://example.com/branelet-amd64", b"hello world", &digest.to_uppercase()).is_ok());
+    assert!(matches!(check_digest("https://example.com/branelet-amd64", b"hello world!", digest), Err(BuildError::InitBinaryDigestMismatch{ got, .. }) if got != digest));
+
+    // Architectures we don't release for never have a digest
+    assert_eq!(release_digest("s390x"), None);
+}
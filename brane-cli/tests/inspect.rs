@@ -0,0 +1,52 @@
+use std::str::FromStr;
+
+use brane_cli::packages::digest_mismatch;
+use brane_cli::registry::{check_metadata_status, latest_version};
+use reqwest::StatusCode;
+use specifications::version::Version;
+
+fn versions(versions: &[&str]) -> Vec<Version> {
+    versions.iter().map(|version| Version::from_str(version).unwrap()).collect()
+}
+
+#[test]
+fn the_latest_version_is_chosen_by_precedence() {
+    // Not by the order of the registry, nor as strings
+    let latest = latest_version("hello-world", versions(&[ "1.2.0", "1.10.0", "1.9.3" ])).unwrap();
+    assert_eq!(latest, Version::from_str("1.10.0").unwrap());
+
+    let latest = latest_version("hello-world", versions(&[ "2.0.0-rc.1", "1.10.0" ])).unwrap();
+    assert_eq!(latest, Version::from_str("2.0.0-rc.1").unwrap());
+}
+
+#[test]
+fn packages_without_versions_are_not_in_the_registry() {
+    let err = latest_version("hello-world", vec![]).unwrap_err();
+    assert!(format!("{}", err).contains("Package 'hello-world' is not in the registry"));
+}
+
+#[test]
+fn only_different_digests_are_noted() {
+    let version = Version::from_str("1.0.0").unwrap();
+    assert_eq!(digest_mismatch(&version, Some("sha256:abc"), Some("sha256:abc")), None);
+    assert_eq!(digest_mismatch(&version, None, None), None);
+
+    let note = digest_mismatch(&version, Some("sha256:abc"), Some("sha256:def")).unwrap();
+    assert_eq!(note, "Note: local version 1.0.0 has digest sha256:abc, but the registry has sha256:def.");
+    let note = digest_mismatch(&version, None, Some("sha256:def")).unwrap();
+    assert!(note.contains("has digest <none>"));
+}
+
+#[test]
+fn registries_without_metadata_are_explained() {
+    for status in [ StatusCode::NOT_FOUND, StatusCode::METHOD_NOT_ALLOWED, StatusCode::NOT_IMPLEMENTED ] {
+        let err = check_metadata_status("https://registry.example.com/graphql", status).unwrap_err();
+        let message = format!("{}", err);
+        assert!(message.contains("does not offer package metadata at 'https://registry.example.com/graphql'"), "{}", message);
+        assert!(message.contains(&status.as_u16().to_string()));
+    }
+
+    // Other failures are left to the rest of the query
+    assert!(check_metadata_status("https://registry.example.com/graphql", StatusCode::OK).is_ok());
+    assert!(check_metadata_status("https://registry.example.com/graphql", StatusCode::BAD_REQUEST).is_ok());
+}
@@ -0,0 +1,86 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use brane_cli::lock::{LockError, PackageLock};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[test]
+fn contending_threads_are_serialized() {
+    let dir = tempfile::tempdir().unwrap();
+    let log: Arc<Mutex<Vec<(usize, &'static str)>>> = Arc::new(Mutex::new(Vec::new()));
+
+    let handles: Vec<_> = (0..2).map(|i| {
+        let path = dir.path().to_path_buf();
+        let log = log.clone();
+        thread::spawn(move || {
+            let _lock = PackageLock::acquire_in(&path, "hello-world", "build", TIMEOUT).unwrap();
+            log.lock().unwrap().push((i, "start"));
+            thread::sleep(Duration::from_millis(300));
+            log.lock().unwrap().push((i, "end"));
+        })
+    }).collect();
+    for handle in handles { handle.join().unwrap(); }
+
+    // Whoever got the lock first must have finished before the other started
+    let log = log.lock().unwrap();
+    assert_eq!(log.len(), 4);
+    assert_eq!(log[0].0, log[1].0);
+    assert_eq!(log[1].1, "end");
+    assert_eq!(log[2].0, log[3].0);
+    assert_eq!(log[2].1, "start");
+}
+
+#[test]
+fn different_packages_do_not_contend() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let _first = PackageLock::acquire_in(dir.path(), "hello-world", "build", TIMEOUT).unwrap();
+    let start = Instant::now();
+    let _second = PackageLock::acquire_in(dir.path(), "goodbye-world", "build", TIMEOUT).unwrap();
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn timeout_names_the_holder() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let path = dir.path().to_path_buf();
+    let holder = thread::spawn(move || {
+        let _lock = PackageLock::acquire_in(&path, "hello-world", "remove", TIMEOUT).unwrap();
+        thread::sleep(Duration::from_secs(1));
+    });
+    thread::sleep(Duration::from_millis(200));
+
+    match PackageLock::acquire_in(dir.path(), "hello-world", "build", Duration::from_millis(300)) {
+        Err(err @ LockError::LockTimeout{ .. }) => {
+            let message = format!("{}", err);
+            assert!(message.contains("Could not build package 'hello-world'"));
+            assert!(message.contains("'remove'"));
+        },
+        res => panic!("Expected a LockTimeout, got {:?}", res.map(|_| ())),
+    }
+    holder.join().unwrap();
+
+    // Once released, it can be acquired again
+    assert!(PackageLock::acquire_in(dir.path(), "hello-world", "build", TIMEOUT).is_ok());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn waiting_does_not_block_the_runtime() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let first = PackageLock::acquire_in_async(dir.path(), "hello-world", "pull", TIMEOUT).await.unwrap();
+    let path = dir.path().to_path_buf();
+    let waiter = tokio::spawn(async move {
+        PackageLock::acquire_in_async(&path, "hello-world", "load", TIMEOUT).await.map(|_| ())
+    });
+
+    // With a single thread, this only wakes up in time if the waiter yields while it waits
+    let start = Instant::now();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(start.elapsed() < Duration::from_secs(2));
+    drop(first);
+    assert!(waiter.await.unwrap().is_ok());
+}
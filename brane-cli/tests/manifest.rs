@@ -0,0 +1,146 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use brane_cli::errors::{ManifestError, UtilError};
+use brane_cli::manifest::Manifest;
+use brane_cli::utils::{determine_file, determine_kind};
+use specifications::package::PackageKind;
+
+const CONTAINER: &str = "name: hello\nversion: 1.0.0\nkind: ecu\n\nentrypoint:\n  kind: task\n  exec: run.sh\n";
+const OPENAPI: &str = "openapi: 3.0.0\ninfo:\n  title: hello\n  version: 1.0.0\npaths: {}\n";
+const CWL: &str = "cwlVersion: v1.0\nclass: CommandLineTool\nbaseCommand: echo\n";
+
+/// Creates a repository with the given files.
+fn repo(files: &[(&str, &str)]) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join(".git")).unwrap();
+    for (name, contents) in files {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    dir
+}
+
+fn manifest_error(dir: &Path) -> ManifestError {
+    match determine_file(dir) {
+        Err(UtilError::ManifestError{ err }) => err,
+        res => panic!("Expected a manifest error, got {:?}", res),
+    }
+}
+
+#[test]
+fn manifest_decides_in_ambiguous_repo() {
+    let dir = repo(&[
+        ("container.yml", CONTAINER),
+        ("openapi.yaml", OPENAPI),
+        ("brane.toml", "[package]\nfile = \"openapi.yaml\"\nkind = \"oas\"\nworkdir = \"src\"\n"),
+    ]);
+    assert_eq!(determine_file(dir.path()).unwrap(), PathBuf::from("openapi.yaml"));
+    assert_eq!(determine_kind(&dir.path().join("openapi.yaml")).unwrap(), PackageKind::Oas);
+
+    let manifest = Manifest::for_file(&dir.path().join("openapi.yaml")).unwrap().unwrap();
+    assert_eq!(manifest.workdir_path(), Some(fs::canonicalize(dir.path()).unwrap().join("src")));
+    // The manifest says nothing about the other file
+    assert!(Manifest::for_file(&dir.path().join("container.yml")).unwrap().is_none());
+    assert_eq!(determine_kind(&dir.path().join("container.yml")).unwrap(), PackageKind::Ecu);
+}
+
+#[test]
+fn manifest_in_repo_root_declares_nested_file() {
+    let dir = repo(&[
+        ("package/container.yml", CONTAINER),
+        ("brane.toml", "[package]\nfile = \"package/container.yml\"\n"),
+    ]);
+    assert_eq!(determine_file(dir.path()).unwrap(), PathBuf::from("package/container.yml"));
+    let manifest = Manifest::for_file(&dir.path().join("package").join("container.yml")).unwrap().unwrap();
+    assert_eq!(manifest.kind, None);
+    assert_eq!(manifest.workdir_path(), None);
+}
+
+#[test]
+fn single_candidate_is_used() {
+    let dir = repo(&[ ("container.yml", CONTAINER), ("run.sh", "echo hello"), ("docker-compose.yml", "services: {}\n") ]);
+    assert_eq!(determine_file(dir.path()).unwrap(), PathBuf::from("container.yml"));
+
+    let dir = repo(&[ ("api.json", "{\n  \"openapi\": \"3.0.0\",\n  \"paths\": {}\n}\n"), ("README.md", "openapi") ]);
+    assert_eq!(determine_file(dir.path()).unwrap(), PathBuf::from("api.json"));
+    assert_eq!(determine_kind(&dir.path().join("api.json")).unwrap(), PackageKind::Oas);
+
+    let dir = repo(&[ ("tool.yml", CWL) ]);
+    assert_eq!(determine_kind(&dir.path().join(determine_file(dir.path()).unwrap())).unwrap(), PackageKind::Cwl);
+}
+
+#[test]
+fn ambiguous_repo_lists_candidates() {
+    let dir = repo(&[ ("openapi.yaml", OPENAPI), ("container.yml", CONTAINER), ("tool.cwl", CWL) ]);
+    let err = determine_file(dir.path()).unwrap_err();
+    match &err {
+        UtilError::AmbiguousPackageFile{ candidates, .. } => assert_eq!(candidates, &vec![ PathBuf::from("container.yml"), PathBuf::from("openapi.yaml"), PathBuf::from("tool.cwl") ]),
+        err => panic!("Expected an ambiguous package file, got {:?}", err),
+    }
+    let message = err.to_string();
+    assert!(message.contains("'container.yml', 'openapi.yaml', 'tool.cwl'"), "Unexpected message: {}", message);
+    assert!(message.contains("brane.toml"), "Unexpected message: {}", message);
+
+    let dir = repo(&[ ("README.md", "nothing to see here") ]);
+    assert!(matches!(determine_file(dir.path()), Err(UtilError::UndeterminedPackageFile{ .. })));
+}
+
+#[test]
+fn ambiguous_kind_is_an_error() {
+    let dir = repo(&[ ("both.yml", "openapi: 3.0.0\ncwlVersion: v1.0\n") ]);
+    match determine_kind(&dir.path().join("both.yml")) {
+        Err(UtilError::AmbiguousPackageKind{ kinds, .. }) => assert_eq!(kinds, vec![ PackageKind::Cwl, PackageKind::Oas ]),
+        res => panic!("Expected an ambiguous package kind, got {:?}", res),
+    }
+
+    // Mentioning a field somewhere in the document is not enough
+    let dir = repo(&[ ("api.yml", "openapi: 3.0.0\ninfo:\n  description: Not a cwlVersion document\n") ]);
+    assert_eq!(determine_kind(&dir.path().join("api.yml")).unwrap(), PackageKind::Oas);
+}
+
+#[test]
+fn invalid_manifests_are_rejected() {
+    let dir = repo(&[ ("container.yml", CONTAINER), ("brane.toml", "[package]\nfile = \"../container.yml\"\n") ]);
+    assert!(matches!(manifest_error(dir.path()), ManifestError::IllegalPath{ field: "file", .. }));
+
+    let dir = repo(&[ ("container.yml", CONTAINER), ("brane.toml", "[package]\nfile = \"container.yml\"\nkind = \"docker\"\n") ]);
+    assert!(matches!(manifest_error(dir.path()), ManifestError::IllegalKind{ .. }));
+
+    let dir = repo(&[ ("brane.toml", "[package]\nfile = \"container.yml\"\n") ]);
+    assert!(matches!(manifest_error(dir.path()), ManifestError::MissingFile{ .. }));
+
+    let dir = repo(&[ ("container.yml", CONTAINER), ("brane.toml", "[package]\nfile = \"container.yml\"\nimage = \"hello\"\n") ]);
+    assert!(matches!(manifest_error(dir.path()), ManifestError::ParseError{ .. }));
+}
+
+#[test]
+fn symlinks_may_not_escape_the_repo() {
+    let outside = repo(&[ ("container.yml", CONTAINER), ("src/main.py", "print('hello')") ]);
+    let dir = repo(&[ ("container.yml", CONTAINER), ("brane.toml", "[package]\nfile = \"linked.yml\"\n") ]);
+    std::os::unix::fs::symlink(outside.path().join("container.yml"), dir.path().join("linked.yml")).unwrap();
+    assert!(matches!(manifest_error(dir.path()), ManifestError::IllegalPath{ field: "file", .. }));
+
+    let dir = repo(&[ ("container.yml", CONTAINER), ("brane.toml", "[package]\nfile = \"container.yml\"\nworkdir = \"src\"\n") ]);
+    std::os::unix::fs::symlink(outside.path().join("src"), dir.path().join("src")).unwrap();
+    assert!(matches!(manifest_error(dir.path()), ManifestError::IllegalPath{ field: "workdir", .. }));
+}
+
+#[test]
+fn manifests_above_the_repo_are_ignored() {
+    // A broken manifest above the repository (e.g., in the home directory) is never read
+    let home = tempfile::tempdir().unwrap();
+    fs::write(home.path().join("brane.toml"), "this is not a manifest").unwrap();
+    let package = home.path().join("package");
+    fs::create_dir_all(package.join(".git")).unwrap();
+    fs::create_dir_all(package.join("nested")).unwrap();
+    fs::write(package.join("nested").join("container.yml"), CONTAINER).unwrap();
+    assert!(Manifest::for_file(&package.join("nested").join("container.yml")).unwrap().is_none());
+
+    // Outside of a repository, only the package's own directory is searched
+    let loose = home.path().join("loose");
+    fs::create_dir_all(&loose).unwrap();
+    fs::write(loose.join("container.yml"), CONTAINER).unwrap();
+    assert!(Manifest::for_file(&loose.join("container.yml")).unwrap().is_none());
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use brane_cli::oci::{self, Challenge, OciClient, OciError};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use specifications::package::{PackageInfo, PackageKind};
+use specifications::version::Version;
+
+fn sha256(bytes: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(bytes))
+}
+
+/// Writes a package directory with a package.yml, an extra file and a tiny image.tar as `docker buildx build --output type=docker` writes it.
+fn package(dir: &Path, name: &str) -> PackageInfo {
+    let layout = tempfile::tempdir().unwrap();
+    let blobs = layout.path().join("blobs").join("sha256");
+    fs::create_dir_all(&blobs).unwrap();
+    let blob = |bytes: &[u8]| -> serde_json::Value {
+        let digest = sha256(bytes);
+        fs::write(blobs.join(&digest[7..]), bytes).unwrap();
+        json!({ "digest": digest, "size": bytes.len() })
+    };
+
+    let mut layer = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(5);
+    header.set_mode(0o644);
+    header.set_cksum();
+    layer.append_data(&mut header, "hello", &b"hello"[..]).unwrap();
+    let layer = blob(&layer.into_inner().unwrap().finish().unwrap());
+    let config = blob(br#"{"architecture":"amd64","os":"linux","rootfs":{"type":"layers","diff_ids":[]}}"#);
+    let manifest = json!({
+        "schemaVersion": 2,
+        "mediaType": oci::OCI_MANIFEST_MEDIA_TYPE,
+        "config": { "mediaType": "application/vnd.oci.image.config.v1+json", "digest": config["digest"], "size": config["size"] },
+        "layers": [ { "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip", "digest": layer["digest"], "size": layer["size"] } ],
+    });
+    let manifest = blob(&serde_json::to_vec(&manifest).unwrap());
+    let index = json!({ "schemaVersion": 2, "manifests": [ { "mediaType": oci::OCI_MANIFEST_MEDIA_TYPE, "digest": manifest["digest"], "size": manifest["size"] } ] });
+    fs::write(layout.path().join("index.json"), serde_json::to_vec(&index).unwrap()).unwrap();
+    fs::write(layout.path().join("manifest.json"), serde_json::to_vec(&json!([ { "Config": format!("blobs/sha256/{}", &config["digest"].as_str().unwrap()[7..]) } ])).unwrap()).unwrap();
+
+    let mut image = tar::Builder::new(fs::File::create(dir.join("image.tar")).unwrap());
+    image.append_dir_all(".", layout.path()).unwrap();
+    image.into_inner().unwrap();
+
+    let mut info = PackageInfo::new(name.to_string(), Version::from_str("1.0.0").unwrap(), PackageKind::Ecu, vec![], String::from("A test package"), false, HashMap::new(), HashMap::new(), vec![]);
+    info.resolve_digest(dir.join("image.tar")).unwrap();
+    assert_eq!(info.digest.as_deref(), config["digest"].as_str());
+    info.to_path(dir.join("package.yml")).unwrap();
+    fs::write(dir.join("container.yml"), "name: test\n").unwrap();
+    info
+}
+
+#[test]
+fn parses_challenges() {
+    assert_eq!(Challenge::parse("Basic realm=\"Registry\""), Some(Challenge::Basic));
+    assert_eq!(
+        Challenge::parse("Bearer realm=\"https://ghcr.io/token\",service=\"ghcr.io\",scope=\"repository:org/pkg:pull,push\""),
+        Some(Challenge::Bearer{ realm: String::from("https://ghcr.io/token"), service: Some(String::from("ghcr.io")) }),
+    );
+    assert_eq!(
+        Challenge::parse("Bearer realm=\"https://auth.example.com/token\""),
+        Some(Challenge::Bearer{ realm: String::from("https://auth.example.com/token"), service: None }),
+    );
+    assert_eq!(Challenge::parse("Negotiate"), None);
+    assert_eq!(Challenge::parse("Bearer service=\"no-realm\""), None);
+}
+
+#[test]
+fn namespaces_repositories() {
+    let client = OciClient::new("https://ghcr.io/my-org/brane", None).unwrap();
+    assert_eq!(client.base().as_str(), "https://ghcr.io/");
+    assert_eq!(client.repository("hello"), "my-org/brane/hello");
+
+    let client = OciClient::new("http://localhost:5000", None).unwrap();
+    assert_eq!(client.base().as_str(), "http://localhost:5000/");
+    assert_eq!(client.repository("hello"), "hello");
+}
+
+#[tokio::test]
+async fn refuses_to_push_mismatched_image() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut info = package(dir.path(), "mismatch");
+    info.digest = Some(sha256(b"something else"));
+
+    // Checked before the registry is contacted, so this one doesn't need to exist
+    let client = OciClient::new("http://127.0.0.1:9", None).unwrap();
+    let err = oci::push_package(&client, dir.path(), &info).await.unwrap_err();
+    assert!(matches!(err, OciError::ImageDigestMismatch{ .. }));
+}
+
+/// The registry to run the round-trip tests against, e.g. `docker run -d -p 5000:5000 registry:2`.
+#[cfg(feature = "oci-registry-tests")]
+fn registry() -> OciClient {
+    let url = std::env::var("BRANE_TEST_OCI_REGISTRY").unwrap_or_else(|_| String::from("http://localhost:5000/brane-tests"));
+    OciClient::new(&url, None).unwrap()
+}
+
+#[cfg(feature = "oci-registry-tests")]
+#[tokio::test]
+async fn push_and_pull_round_trip() {
+    let name = format!("oci-test-{}", uuid::Uuid::new_v4());
+    let source = tempfile::tempdir().unwrap();
+    let info = package(source.path(), &name);
+    let client = registry();
+    oci::push_package(&client, source.path(), &info).await.unwrap();
+    // Pushing again only re-uploads the manifests
+    oci::push_package(&client, source.path(), &info).await.unwrap();
+    assert_eq!(oci::package_versions(&client, &name).await.unwrap(), vec![ info.version.clone() ]);
+
+    let dest = tempfile::tempdir().unwrap();
+    let pulled = oci::pull_package(&client, &name, &info.version, dest.path()).await.unwrap();
+    assert_eq!(pulled.name, name);
+    assert_eq!(pulled.digest, info.digest);
+    assert_eq!(PackageInfo::from_path(dest.path().join("package.yml")).unwrap().id, info.id);
+    assert_eq!(fs::read_to_string(dest.path().join("container.yml")).unwrap(), "name: test\n");
+
+    // The rebuilt image.tar has the same image in it
+    let mut rebuilt = pulled.clone();
+    rebuilt.digest = None;
+    rebuilt.resolve_digest(dest.path().join("image.tar")).unwrap();
+    assert_eq!(rebuilt.digest, info.digest);
+}
+
+#[cfg(feature = "oci-registry-tests")]
+#[tokio::test]
+async fn pull_reports_missing_packages() {
+    let name = format!("oci-test-{}", uuid::Uuid::new_v4());
+    let client = registry();
+    let err = oci::pull_package(&client, &name, &Version::from_str("1.0.0").unwrap(), tempfile::tempdir().unwrap().path()).await.unwrap_err();
+    assert!(matches!(err, OciError::RequestFailure{ status, .. } if status == reqwest::StatusCode::NOT_FOUND));
+}
@@ -0,0 +1,94 @@
+use brane_cli::credentials::{CredentialManager, Credentials};
+use brane_cli::oidc::{self, DeviceAuthorization, OidcError, TokenPoll, TokenSet};
+use reqwest::StatusCode;
+use specifications::registry::{OidcSession, RegistryConfig};
+
+const URL: &str = "https://registry.example.com:50051";
+const TOKEN_ENDPOINT: &str = "https://keycloak.example.com/realms/brane/protocol/openid-connect/token";
+
+fn session(refresh_token: Option<&str>, expires_at: Option<i64>) -> OidcSession {
+    OidcSession{ token_endpoint: TOKEN_ENDPOINT.to_string(), client_id: String::from("brane-cli"), refresh_token: refresh_token.map(String::from), expires_at }
+}
+
+#[test]
+fn discovery_url_is_under_the_issuer() {
+    assert_eq!(oidc::discovery_url("https://keycloak.example.com/realms/brane").unwrap().as_str(), "https://keycloak.example.com/realms/brane/.well-known/openid-configuration");
+    assert_eq!(oidc::discovery_url("https://registry.example.com/").unwrap().as_str(), "https://registry.example.com/.well-known/openid-configuration");
+    assert!(matches!(oidc::discovery_url("registry.example.com"), Err(OidcError::IllegalUrl{ .. })));
+}
+
+#[test]
+fn device_authorization_is_parsed() {
+    let authorization: DeviceAuthorization = serde_json::from_str(r#"{"device_code":"dev","user_code":"ABCD-EFGH","verification_uri":"https://kc/device","expires_in":600}"#).unwrap();
+    assert_eq!(authorization.user_code, "ABCD-EFGH");
+    assert_eq!(authorization.interval, None);
+    assert_eq!(authorization.verification_uri_complete, None);
+
+    // Some providers still call it a URL
+    let authorization: DeviceAuthorization = serde_json::from_str(r#"{"device_code":"dev","user_code":"ABCD","verification_url":"https://kc/device","expires_in":600,"interval":10}"#).unwrap();
+    assert_eq!(authorization.verification_uri, "https://kc/device");
+    assert_eq!(authorization.interval, Some(10));
+}
+
+#[test]
+fn token_responses_are_interpreted() {
+    let poll = |status: StatusCode, body: &str| oidc::parse_token_response(TOKEN_ENDPOINT, status, body.as_bytes());
+
+    assert_eq!(poll(StatusCode::OK, r#"{"access_token":"at","refresh_token":"rt","expires_in":300,"token_type":"Bearer"}"#).unwrap(), TokenPoll::Token(TokenSet{ access_token: String::from("at"), refresh_token: Some(String::from("rt")), expires_in: Some(300) }));
+    assert_eq!(poll(StatusCode::BAD_REQUEST, r#"{"error":"authorization_pending"}"#).unwrap(), TokenPoll::Pending);
+    assert_eq!(poll(StatusCode::BAD_REQUEST, r#"{"error":"slow_down"}"#).unwrap(), TokenPoll::SlowDown);
+    assert!(matches!(poll(StatusCode::BAD_REQUEST, r#"{"error":"access_denied"}"#), Err(OidcError::AccessDenied)));
+    assert!(matches!(poll(StatusCode::BAD_REQUEST, r#"{"error":"expired_token"}"#), Err(OidcError::DeviceCodeExpired)));
+    assert!(matches!(poll(StatusCode::BAD_REQUEST, r#"{"error":"invalid_client","error_description":"Unknown client"}"#), Err(OidcError::TokenError{ error, description: Some(_), .. }) if error == "invalid_client"));
+    assert!(matches!(poll(StatusCode::BAD_GATEWAY, "<html>Bad gateway</html>"), Err(OidcError::RequestFailure{ status: StatusCode::BAD_GATEWAY, .. })));
+    assert!(matches!(poll(StatusCode::OK, "{}"), Err(OidcError::ResponseParseError{ .. })));
+}
+
+#[test]
+fn tokens_become_credentials() {
+    let tokens = TokenSet{ access_token: String::from("at"), refresh_token: None, expires_in: Some(300) };
+    let credentials = tokens.into_credentials(String::from("alice"), TOKEN_ENDPOINT.to_string(), String::from("brane-cli"), Some(String::from("old")), 1000);
+    assert_eq!(credentials.username, "alice");
+    assert_eq!(credentials.token.as_deref(), Some("at"));
+    // The provider didn't rotate the refresh token, so we keep the old one
+    assert_eq!(credentials.oidc, Some(session(Some("old"), Some(1300))));
+}
+
+#[test]
+fn expiry_has_a_margin() {
+    assert!(!oidc::is_expired(&session(None, None), i64::MAX));
+    assert!(!oidc::is_expired(&session(None, Some(1000)), 900));
+    assert!(oidc::is_expired(&session(None, Some(1000)), 990));
+    assert!(oidc::is_expired(&session(None, Some(1000)), 2000));
+}
+
+#[test]
+fn sessions_are_kept_with_the_credentials() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("registry.yml");
+    let manager = CredentialManager::with_stores(&path, None);
+
+    let credentials = Credentials{ username: String::from("alice"), token: Some(String::from("at")), oidc: Some(session(Some("rt"), Some(1300))) };
+    manager.login(URL, &credentials, false, false).unwrap();
+    assert_eq!(RegistryConfig::from_path(&path).unwrap().oidc, Some(session(Some("rt"), Some(1300))));
+    assert_eq!(manager.credentials().unwrap().1, Some(credentials.clone()));
+
+    // Refreshed tokens replace the old ones in place
+    let refreshed = Credentials{ token: Some(String::from("at2")), oidc: Some(session(Some("rt2"), Some(2000))), ..credentials };
+    manager.update(&refreshed).unwrap();
+    assert_eq!(manager.credentials().unwrap(), (URL.to_string(), Some(refreshed)));
+
+    manager.logout().unwrap();
+    assert!(!path.exists());
+}
+
+#[test]
+fn expired_login_without_refresh_token_asks_to_log_in_again() {
+    let dir = tempfile::tempdir().unwrap();
+    let manager = CredentialManager::with_stores(dir.path().join("registry.yml"), None);
+
+    let credentials = Credentials{ username: String::from("alice"), token: Some(String::from("at")), oidc: Some(session(None, Some(0))) };
+    let err = futures::executor::block_on(oidc::refresh(&manager, URL, credentials)).unwrap_err();
+    assert!(matches!(&err, OidcError::LoginExpired{ url } if url == URL));
+    assert!(err.to_string().contains("please run 'brane login' again"), "Unexpected message: {}", err);
+}
@@ -0,0 +1,184 @@
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::Result;
+use brane_cli::prompt::{Prompter, Questions};
+use specifications::common::{Parameter, Property, Type, Value};
+
+/// An answer to one question.
+enum Answer {
+    Text(String),
+    Yes,
+    No,
+}
+
+/// Answers questions from a script, remembering what was asked and rejected.
+#[derive(Default)]
+struct Script {
+    answers  : VecDeque<Answer>,
+    asked    : Vec<String>,
+    defaults : Vec<Option<String>>,
+    rejected : Vec<String>,
+}
+
+impl Script {
+    fn new(answers: Vec<Answer>) -> Self {
+        Self{ answers: answers.into_iter().collect(), ..Default::default() }
+    }
+}
+
+impl Questions for Script {
+    fn text(&mut self, question: &str, default: Option<String>, _allow_empty: bool, _secret: bool) -> Result<String> {
+        self.asked.push(question.to_string());
+        self.defaults.push(default.clone());
+        match (self.answers.pop_front(), default) {
+            (Some(Answer::Text(text)), Some(default)) if text.is_empty() => Ok(default),
+            (Some(Answer::Text(text)), _)                                => Ok(text),
+            _                                                            => panic!("Expected a yes/no question, got '{}'", question),
+        }
+    }
+
+    fn confirm(&mut self, question: &str, _default: bool) -> Result<bool> {
+        self.asked.push(question.to_string());
+        match self.answers.pop_front() {
+            Some(Answer::Yes) => Ok(true),
+            Some(Answer::No)  => Ok(false),
+            _                 => panic!("Expected a text question, got '{}'", question),
+        }
+    }
+
+    fn invalid(&mut self, message: &str) {
+        self.rejected.push(message.to_string());
+    }
+}
+
+/// Returns an answer with the given text.
+fn text(answer: impl Into<String>) -> Answer { Answer::Text(answer.into()) }
+
+/// Returns a property with the given name and type.
+fn property(name: &str, data_type: &str, optional: bool) -> Property {
+    Property{ data_type: data_type.to_string(), default: None, name: name.to_string(), optional: Some(optional), properties: None, secret: None, description: None }
+}
+
+/// Returns the types of the test package: a Point and a Line between two of them.
+fn types() -> HashMap<String, Type> {
+    let point = Type{ name: String::from("Point"), properties: vec![ property("x", "real", false), property("y", "real", false) ], description: None };
+    let line = Type{ name: String::from("Line"), properties: vec![ property("from", "Point", false), property("to", "Point", false), property("label", "string", true) ], description: None };
+    vec![ (String::from("Point"), point), (String::from("Line"), line) ].into_iter().collect()
+}
+
+/// Asks for the given parameter with the given answers, checking that all of them were used.
+fn ask(parameter: Parameter, answers: Vec<Answer>) -> (Value, Script) {
+    let types = types();
+    let mut script = Script::new(answers);
+    let value = Prompter::new(&mut script, &types).parameter(&parameter).unwrap();
+    assert!(script.answers.is_empty(), "Not all answers were used; asked: {:?}", script.asked);
+    (value, script)
+}
+
+/// Returns a Point struct.
+fn point(x: f64, y: f64) -> Value {
+    Value::Struct{ data_type: String::from("Point"), properties: vec![ (String::from("x"), Value::Real(x)), (String::from("y"), Value::Real(y)) ].into_iter().collect() }
+}
+
+/// Returns a required parameter without a default.
+fn parameter(name: &str, data_type: &str) -> Parameter {
+    Parameter::new(name.to_string(), data_type.to_string(), None, None, None)
+}
+
+#[test]
+fn arrays_of_structs_are_filled_in_element_by_element() {
+    use Answer::*;
+    let (value, script) = ask(parameter("points", "Point[]"), vec![
+        text(""), Yes, text(""), text("1"), text("2"),
+        Yes, text(""), text("3"), text("four"), text("4"),
+        No,
+    ]);
+    assert!(matches!(&value, Value::Array{ data_type, entries } if data_type == "Point[]" && entries.len() == 2));
+    if let Value::Array{ entries, .. } = &value {
+        assert!(compare(&entries[0], &point(1.0, 2.0)) && compare(&entries[1], &point(3.0, 4.0)), "Unexpected points: {:?}", entries);
+    }
+
+    // Only the offending field was asked again
+    assert_eq!(script.rejected.len(), 1);
+    assert!(script.rejected[0].starts_with("points[1].y: 'four' is not a real"), "Unexpected message: {}", script.rejected[0]);
+    assert_eq!(script.asked.iter().filter(|question| question.starts_with("points[1].y (real)")).count(), 2);
+    assert_eq!(script.asked.iter().filter(|question| question.starts_with("points[1].x (real)")).count(), 1);
+    assert!(script.asked.contains(&String::from("Add another element to points?")));
+}
+
+#[test]
+fn nested_classes_are_filled_in_per_property() {
+    let (value, script) = ask(parameter("line", "Line"), vec![
+        text(""),
+        text(""), text("0"), text("0"),
+        text(""), text("1"), text("1"),
+        text(""),
+    ]);
+    assert!(script.asked.contains(&String::from("line.to.y (real)")));
+    match &value {
+        Value::Struct{ data_type, properties } => {
+            assert_eq!(data_type, "Line");
+            assert!(compare(&properties["from"], &point(0.0, 0.0)) && compare(&properties["to"], &point(1.0, 1.0)));
+            // Optional properties that are left empty are left out
+            assert!(!properties.contains_key("label"));
+        },
+        value => panic!("Expected a Line, got {:?}", value),
+    }
+}
+
+#[test]
+fn values_can_be_loaded_from_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let good = dir.path().join("points.json");
+    let bad = dir.path().join("bad.json");
+    std::fs::write(&good, r#"[ { "x": 1, "y": 2 }, { "x": 3.5, "y": 4 } ]"#).unwrap();
+    std::fs::write(&bad, r#"[ { "x": 1, "y": "two" } ]"#).unwrap();
+
+    // A file that doesn't fit is rejected, after which the same question is asked again
+    let (value, script) = ask(parameter("points", "Point[]"), vec![ text(format!("@{}", bad.display())), text(format!("@{}", good.display())) ]);
+    assert_eq!(script.rejected.len(), 1);
+    assert!(script.rejected[0].ends_with("points[0].y should be a real, not \"two\""), "Unexpected message: {}", script.rejected[0]);
+    if let Value::Array{ entries, .. } = &value {
+        assert!(compare(&entries[0], &point(1.0, 2.0)) && compare(&entries[1], &point(3.5, 4.0)), "Unexpected points: {:?}", entries);
+    } else {
+        panic!("Expected an array, got {:?}", value);
+    }
+
+    // Subtrees can be loaded too
+    let point_file = dir.path().join("point.json");
+    std::fs::write(&point_file, r#"{ "x": 5, "y": 6 }"#).unwrap();
+    let point_answer = format!("@{}", point_file.display());
+    let (value, _) = ask(parameter("line", "Line"), vec![ text(""), text(&point_answer), text(&point_answer), text("diagonal") ]);
+    if let Value::Struct{ properties, .. } = &value {
+        assert!(compare(&properties["from"], &point(5.0, 6.0)));
+        assert!(matches!(&properties["label"], Value::Unicode(label) if label == "diagonal"));
+    }
+
+    // And strings can still start with the prefix
+    let (value, _) = ask(parameter("handle", "string"), vec![ text("@@brane") ]);
+    assert!(matches!(value, Value::Unicode(handle) if handle == "@brane"));
+}
+
+#[test]
+fn defaults_are_offered() {
+    use Answer::*;
+    let with_default = Parameter::new(String::from("count"), String::from("integer"), None, Some(Value::Integer(3)), None);
+    let (value, script) = ask(with_default, vec![ text("") ]);
+    assert!(matches!(value, Value::Integer(3)));
+    assert_eq!(script.defaults, vec![ Some(String::from("3")) ]);
+
+    let with_default = Parameter::new(String::from("origin"), String::from("Point"), None, Some(point(0.0, 0.0)), None);
+    let (value, script) = ask(with_default, vec![ Yes ]);
+    assert!(compare(&value, &point(0.0, 0.0)));
+    assert_eq!(script.asked, vec![ String::from("Use the default for origin (Point): Point {x: 0, y: 0}?") ]);
+
+    // Optional values may be left out entirely
+    let optional = Parameter::new(String::from("origin"), String::from("Point"), Some(true), None, None);
+    let (value, _) = ask(optional, vec![ No ]);
+    assert!(matches!(value, Value::Unit));
+}
+
+/// Compares two Values, since they don't implement PartialEq.
+fn compare(lhs: &Value, rhs: &Value) -> bool {
+    specifications::compare::compare(lhs, rhs).is_empty()
+}
@@ -0,0 +1,70 @@
+use brane_cli::repl::{is_complete_statement, read_history, read_paste_block, write_history};
+use rustyline::error::ReadlineError;
+
+#[test]
+fn unbalanced_delimiters_are_incomplete() {
+    assert!(is_complete_statement("let x := 1;"));
+    assert!(is_complete_statement("func f(a) { return [a, (a + 1)]; }"));
+
+    assert!(!is_complete_statement("func f(a) {"));
+    assert!(!is_complete_statement("func f(a) {\n  if (a > 1) {\n    return a;\n  }"));
+    assert!(!is_complete_statement("print(f(1)"));
+    assert!(!is_complete_statement("let xs := [1, 2,"));
+
+    // Stray closing delimiters are left to the compiler
+    assert!(is_complete_statement("}"));
+}
+
+#[test]
+fn delimiters_in_strings_are_ignored() {
+    assert!(is_complete_statement("print(\"{ ( [\");"));
+    assert!(is_complete_statement("print(\"an \\\" escaped quote {\");"));
+    assert!(!is_complete_statement("print(\"} ) ]\""));
+
+    // An unclosed string continues on the next line
+    assert!(!is_complete_statement("print(\"hello"));
+}
+
+#[test]
+fn delimiters_in_comments_are_ignored() {
+    assert!(is_complete_statement("let x := 1; // {"));
+    assert!(is_complete_statement("let x := 1; /* ( [ { */"));
+    assert!(!is_complete_statement("func f() { // }"));
+    assert!(is_complete_statement("func f() { // }\n}"));
+
+    // An unclosed block comment continues on the next line
+    assert!(!is_complete_statement("let x := 1; /* still"));
+}
+
+#[test]
+fn history_entries_survive_a_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("history");
+    let entries = vec![
+        "let x := 1;".to_string(),
+        "func f(a) {\n  return a;\n}".to_string(),
+        "print(\"C:\\\\Users\\\\brane\");".to_string(),
+        "print(\"a literal \\\\n, not a newline\");".to_string(),
+        "\\".to_string(),
+    ];
+
+    write_history(&path, &entries).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), entries.len());
+    assert_eq!(read_history(&path).unwrap(), entries);
+}
+
+#[test]
+fn paste_mode_stops_at_the_first_empty_line() {
+    let mut lines = vec!["let x := 1;", "func f() {", "  return x;", "}", "  ", "print(f());"].into_iter();
+    let block = read_paste_block(|| Ok(lines.next().unwrap().to_string())).unwrap();
+    assert_eq!(block, "let x := 1;\nfunc f() {\n  return x;\n}");
+
+    // The rest is left for the next statement
+    assert_eq!(lines.next(), Some("print(f());"));
+}
+
+#[test]
+fn paste_mode_is_aborted_by_interrupts() {
+    let mut lines = vec![Ok("let x := 1;"), Err(ReadlineError::Interrupted)].into_iter();
+    assert!(matches!(read_paste_block(|| lines.next().unwrap().map(String::from)), Err(ReadlineError::Interrupted)));
+}
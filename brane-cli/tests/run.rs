@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use brane_bvm::executor::{ExecutorError, ServiceState, VmExecutor};
+use brane_cli::docker::{self, ExecuteInfo};
+use brane_cli::errors::{OfflineError, RunError};
+use brane_cli::run::{error_category, offline_error, run_script, ErrorCategory, RunReport};
+use serde_json::json;
+use specifications::common::{Function, FunctionExt, Value};
+use specifications::package::{PackageIndex, PackageInfo, PackageKind};
+use specifications::version::Version;
+
+/// An executor whose external calls fail in the way named by the called function.
+#[derive(Clone, Default)]
+struct FailingExecutor;
+
+#[async_trait]
+impl VmExecutor for FailingExecutor {
+    async fn call(&self, function: FunctionExt, _: HashMap<String, Value>, _: Option<String>) -> Result<Value, ExecutorError> {
+        let version = function.version.clone();
+        match function.name.as_str() {
+            "crash"    => Err(ExecutorError::ExternalCallFailed{ name: function.name, package: function.package, version, code: 1, stdout: String::new(), stderr: String::from("Segmentation fault") }),
+            "offline"  => Err(ExecutorError::CommandScheduleError{ topic: String::from("commands"), err: String::from("Connection refused") }),
+            "unpulled" => Err(ExecutorError::OfflineImageError{ image: String::from("jobs:1.0.0") }),
+            _          => Ok(Value::Integer(42)),
+        }
+    }
+
+    async fn debug(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stderr(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn stdout(&self, _: String) -> Result<(), ExecutorError> { Ok(()) }
+
+    async fn wait_until(&self, _: String, _: ServiceState) -> Result<(), ExecutorError> { Ok(()) }
+}
+
+/// A package 'jobs' with the nullary functions answer(), crash(), offline() and unpulled().
+fn index() -> PackageIndex {
+    let mut functions = HashMap::new();
+    functions.insert(String::from("answer"), Function::new(vec![], None, String::from("integer")));
+    functions.insert(String::from("crash"), Function::new(vec![], None, String::from("integer")));
+    functions.insert(String::from("offline"), Function::new(vec![], None, String::from("integer")));
+    functions.insert(String::from("unpulled"), Function::new(vec![], None, String::from("integer")));
+
+    let mut package = PackageInfo::new(String::from("jobs"), Version::from_str("1.0.0").unwrap(), PackageKind::Ecu, vec![], String::new(), false, functions, HashMap::new(), vec![]);
+    package.digest = Some(String::from("sha256:1.0.0"));
+    PackageIndex::new(vec![ (String::from("jobs-1.0.0"), package) ].into_iter().collect())
+}
+
+async fn run(code: &str) -> (Result<Option<Value>, RunError>, serde_json::Value) {
+    let result = run_script(code, FailingExecutor, index(), HashMap::new(), false, false, false, None).await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("result.json");
+    RunReport::new(&result).write(&path).unwrap();
+    let report = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    (result, report)
+}
+
+#[tokio::test]
+async fn success_reports_value() {
+    let (result, report) = run("import jobs;\nlet a := answer();\nreturn a + 1;\n").await;
+    assert!(result.is_ok());
+    assert_eq!(report["status"], "success");
+    assert_eq!(report["exit_code"], 0);
+    assert_eq!(report["category"], serde_json::Value::Null);
+    assert_eq!(report["value"], json!(43));
+
+    // Scripts without a top-level return have no value
+    let (_, report) = run("let a := 1;\n").await;
+    assert_eq!(report["status"], "success");
+    assert_eq!(report["value"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn compile_error() {
+    let (result, report) = run("let = ;\n").await;
+    let err = result.unwrap_err();
+    assert_eq!(error_category(&err), ErrorCategory::Compile);
+    assert_eq!(error_category(&err).exit_code(), 2);
+    assert_eq!(report["status"], "failure");
+    assert_eq!(report["exit_code"], 2);
+    assert_eq!(report["category"], "compile");
+    assert!(report["message"].is_string());
+}
+
+#[tokio::test]
+async fn runtime_error() {
+    let (result, report) = run("let a := 1;\nlet c := a + unit;\n").await;
+    let err = result.unwrap_err();
+    assert_eq!(error_category(&err), ErrorCategory::Runtime);
+    assert_eq!(report["exit_code"], 2);
+    assert_eq!(report["category"], "runtime");
+    assert_eq!(report["line"], 2);
+}
+
+#[tokio::test]
+async fn external_call_error() {
+    let (result, report) = run("import jobs;\nlet a := crash();\n").await;
+    let err = result.unwrap_err();
+    assert_eq!(error_category(&err), ErrorCategory::ExternalCall);
+    assert_eq!(report["exit_code"], 3);
+    assert_eq!(report["category"], "external_call");
+    assert!(report["message"].as_str().unwrap().contains("crash"));
+}
+
+#[tokio::test]
+async fn infrastructure_error() {
+    let (result, report) = run("import jobs;\nlet a := offline();\n").await;
+    let err = result.unwrap_err();
+    assert_eq!(error_category(&err), ErrorCategory::Infrastructure);
+    assert_eq!(report["exit_code"], 4);
+    assert_eq!(report["category"], "infrastructure");
+    assert!(report["message"].as_str().unwrap().contains("Connection refused"));
+}
+
+#[tokio::test]
+async fn offline_image_error() {
+    let (result, report) = run("import jobs;\nlet a := unpulled();\n").await;
+    let err = result.unwrap_err();
+    assert_eq!(error_category(&err), ErrorCategory::Infrastructure);
+    assert_eq!(report["exit_code"], 4);
+    assert!(matches!(offline_error(&err), Some(OfflineError::MissingImage{ image }) if image == "jobs:1.0.0"));
+
+    // Other errors are not offline errors
+    let (result, _) = run("import jobs;\nlet a := offline();\n").await;
+    assert!(offline_error(&result.unwrap_err()).is_none());
+}
+
+#[tokio::test]
+async fn offline_mode_does_not_pull() {
+    let image = String::from("brane-offline-test:0.0.0");
+    for image_file in vec![ None, Some(PathBuf::from("/nonexistent/image.tar")) ] {
+        let err = docker::run_and_wait(ExecuteInfo::new(image.clone(), image_file, None, None), true).await.unwrap_err();
+        assert!(matches!(err, ExecutorError::OfflineImageError{ image: ref name } if name == &image));
+    }
+}
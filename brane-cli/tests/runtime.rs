@@ -0,0 +1,104 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use brane_cli::runtime::{detect_runtime, normalize_image_id, parse_version, socket_candidates, BuildBackend, ContainerRuntime, RuntimeChoice, RuntimeInfo};
+use brane_cli::errors::UtilError;
+use brane_cli::utils::parse_buildx_version;
+use brane_cli::{MIN_BUILDX_VERSION, MIN_DOCKER_VERSION, MIN_PODMAN_VERSION};
+use specifications::version::Version;
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+#[test]
+fn runtime_choice_parses() {
+    assert_eq!(RuntimeChoice::from_str("auto").unwrap(), RuntimeChoice::Auto);
+    assert_eq!(RuntimeChoice::from_str("Docker").unwrap(), RuntimeChoice::Docker);
+    assert_eq!(RuntimeChoice::from_str("podman").unwrap(), RuntimeChoice::Podman);
+    let err = RuntimeChoice::from_str("containerd").unwrap_err();
+    assert!(err.to_string().contains("'containerd'"), "Unexpected error: {}", err);
+}
+
+#[test]
+fn detects_podman() {
+    // Docker reports its engine and containerd, Podman its own engine
+    assert_eq!(detect_runtime(RuntimeChoice::Auto, &names(&[ "Engine", "containerd", "runc" ]), Some(Path::new("/var/run/docker.sock"))), ContainerRuntime::Docker);
+    assert_eq!(detect_runtime(RuntimeChoice::Auto, &names(&[ "Podman Engine" ]), Some(Path::new("/var/run/docker.sock"))), ContainerRuntime::Podman);
+    assert_eq!(detect_runtime(RuntimeChoice::Auto, &[], Some(Path::new("/run/user/1000/podman/podman.sock"))), ContainerRuntime::Podman);
+    assert_eq!(detect_runtime(RuntimeChoice::Auto, &[], None), ContainerRuntime::Docker);
+
+    // The user has the last word
+    assert_eq!(detect_runtime(RuntimeChoice::Docker, &names(&[ "Podman Engine" ]), None), ContainerRuntime::Docker);
+    assert_eq!(detect_runtime(RuntimeChoice::Podman, &names(&[ "Engine" ]), None), ContainerRuntime::Podman);
+}
+
+#[test]
+fn version_gate_depends_on_runtime() {
+    let podman = parse_version("4.3.1").unwrap();
+    assert!(podman < ContainerRuntime::Docker.min_version());
+    assert!(podman >= ContainerRuntime::Podman.min_version());
+    assert_eq!(ContainerRuntime::Docker.min_version(), MIN_DOCKER_VERSION);
+    assert_eq!(ContainerRuntime::Podman.min_version(), MIN_PODMAN_VERSION);
+
+    // Distributions like to add suffixes
+    assert_eq!(parse_version("4.9.4-rhel").unwrap(), Version::new(4, 9, 4));
+    assert_eq!(parse_version("20.10.17+dfsg1").unwrap(), Version::new(20, 10, 17));
+    assert_eq!(parse_version("v20.10.7").unwrap(), Version::new(20, 10, 7));
+    assert_eq!(parse_version(" v24.0.0-rc.2 ").unwrap(), Version::new(24, 0, 0));
+    assert!(parse_version("latest").is_err());
+}
+
+#[test]
+fn buildx_versions_may_be_prereleases() {
+    let version = parse_buildx_version("github.com/docker/buildx v0.10.0-rc1 a3d8a0d\n").unwrap();
+    assert_eq!(version, Version::from_str("0.10.0-rc1").unwrap());
+    assert!(version < Version::new(0, 10, 0));
+    assert!(version >= MIN_BUILDX_VERSION);
+
+    // With or without a 'v', and with whatever distributions add
+    assert_eq!(parse_buildx_version("github.com/docker/buildx 0.9.1 0.9.1").unwrap(), Version::new(0, 9, 1));
+    assert_eq!(parse_buildx_version("github.com/docker/buildx v0.10.4+unknown").unwrap().to_string(), "0.10.4+unknown");
+    assert!(parse_buildx_version("github.com/docker/buildx v0.6.3-docker").unwrap() < MIN_BUILDX_VERSION);
+
+    assert!(matches!(parse_buildx_version("buildx"), Err(UtilError::BuildxVersionNoParts{ .. })));
+    assert!(matches!(parse_buildx_version("github.com/docker/buildx dev"), Err(UtilError::IllegalBuildxVersion{ version, .. }) if version == "dev"));
+}
+
+#[test]
+fn looks_for_rootless_sockets() {
+    assert_eq!(socket_candidates(None, Some("/run/user/1000")), vec![
+        PathBuf::from("/var/run/docker.sock"),
+        PathBuf::from("/run/user/1000/docker.sock"),
+        PathBuf::from("/run/user/1000/podman/podman.sock"),
+        PathBuf::from("/run/podman/podman.sock"),
+    ]);
+    assert_eq!(socket_candidates(None, None), vec![ PathBuf::from("/var/run/docker.sock"), PathBuf::from("/run/podman/podman.sock") ]);
+
+    // DOCKER_HOST wins, also if it's not a socket
+    assert_eq!(socket_candidates(Some("unix:///run/user/1000/podman/podman.sock"), Some("/run/user/1000")), vec![ PathBuf::from("/run/user/1000/podman/podman.sock") ]);
+    assert!(socket_candidates(Some("tcp://10.0.0.1:2376"), Some("/run/user/1000")).is_empty());
+}
+
+#[test]
+fn podman_without_buildx_lacks_features() {
+    let podman = RuntimeInfo{ runtime: ContainerRuntime::Podman, version: Some(Version::new(4, 3, 1)), buildx: false };
+    assert_eq!(podman.build_backend(), BuildBackend::PodmanBuild);
+    assert_eq!(podman.unavailable_features().len(), 1);
+    assert!(podman.unavailable_features()[0].contains("--push"));
+    assert_eq!(podman.to_string(), "Podman 4.3.1");
+
+    // With buildx (or on Docker) everything is there
+    let podman = RuntimeInfo{ buildx: true, ..podman };
+    assert_eq!(podman.build_backend(), BuildBackend::Buildx);
+    assert!(podman.unavailable_features().is_empty());
+    let docker = RuntimeInfo{ runtime: ContainerRuntime::Docker, version: None, buildx: false };
+    assert_eq!(docker.build_backend(), BuildBackend::Buildx);
+    assert_eq!(docker.to_string(), "Docker (unknown version)");
+}
+
+#[test]
+fn image_ids_get_their_algorithm() {
+    assert_eq!(normalize_image_id("sha256:abcdef"), "sha256:abcdef");
+    assert_eq!(normalize_image_id("abcdef"), "sha256:abcdef");
+}
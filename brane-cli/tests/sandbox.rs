@@ -0,0 +1,69 @@
+use bollard::models::HostConfig;
+use brane_cli::errors::SandboxError;
+use brane_cli::sandbox::{data_bind, parse_cpus, parse_memory, SandboxNetwork, SandboxOptions, DATA_MOUNT};
+
+/// The HostConfig of a container with a data directory, as the local executor creates it without a sandbox.
+fn host_config() -> HostConfig {
+    HostConfig {
+        binds: Some(vec![ String::from("/home/user/data:/data"), String::from("/var/run/docker.sock:/var/run/docker.sock") ]),
+        network_mode: Some(String::from("host")),
+        privileged: Some(true),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn no_sandbox_changes_nothing() {
+    let sandbox = SandboxOptions::default();
+    assert!(sandbox.is_default());
+    assert_eq!(sandbox.apply(host_config()), host_config());
+    assert_eq!(sandbox.to_string(), "no restrictions");
+}
+
+#[test]
+fn sandbox_maps_onto_the_host_config() {
+    let sandbox = SandboxOptions{ network: Some(SandboxNetwork::None), memory: Some(512 * 1024 * 1024), cpus: Some(1.5), read_only_data: true };
+    let config = sandbox.apply(host_config());
+    assert_eq!(config.network_mode.as_deref(), Some("none"));
+    assert_eq!(config.memory, Some(512 * 1024 * 1024));
+    assert_eq!(config.nano_cpus, Some(1_500_000_000));
+    // Only the data directory becomes read-only, and the Docker socket and privileged mode are taken away
+    assert_eq!(config.binds, Some(vec![ String::from("/home/user/data:/data:ro") ]));
+    assert_eq!(config.privileged, Some(false));
+    assert_eq!(sandbox.to_string(), "no Docker socket, unprivileged, network none, memory 536870912 bytes, 1.5 CPUs, read-only /data");
+
+    let sandbox = SandboxOptions{ network: Some(SandboxNetwork::Bridge), ..Default::default() };
+    let config = sandbox.apply(host_config());
+    assert_eq!(config.network_mode.as_deref(), Some("bridge"));
+    assert_eq!((config.memory, config.nano_cpus), (None, None));
+    assert_eq!(config.binds, Some(vec![ String::from("/home/user/data:/data") ]));
+    assert_eq!(config.privileged, Some(false));
+}
+
+#[test]
+fn data_binds_mount_on_data() {
+    assert_eq!(data_bind("/home/user/data"), "/home/user/data:/data");
+    assert_eq!(data_bind("/home/user/data"), format!("/home/user/data:{}", DATA_MOUNT));
+}
+
+#[test]
+fn parses_networks() {
+    assert_eq!("none".parse::<SandboxNetwork>().unwrap(), SandboxNetwork::None);
+    assert_eq!("Bridge".parse::<SandboxNetwork>().unwrap(), SandboxNetwork::Bridge);
+    assert!(matches!("host".parse::<SandboxNetwork>(), Err(SandboxError::UnknownNetwork{ .. })));
+}
+
+#[test]
+fn parses_limits() {
+    assert_eq!(parse_memory("1048576").unwrap(), 1048576);
+    assert_eq!(parse_memory("512m").unwrap(), 512 * 1024 * 1024);
+    assert_eq!(parse_memory("2G").unwrap(), 2 * 1024 * 1024 * 1024);
+    assert_eq!(parse_memory("1.5k").unwrap(), 1536);
+    assert!(matches!(parse_memory("0"), Err(SandboxError::IllegalMemory{ .. })));
+    assert!(matches!(parse_memory("lots"), Err(SandboxError::IllegalMemory{ .. })));
+    assert!(matches!(parse_memory("2 gallons"), Err(SandboxError::UnknownMemoryUnit{ .. })));
+
+    assert_eq!(parse_cpus("0.5").unwrap(), 0.5);
+    assert!(matches!(parse_cpus("0"), Err(SandboxError::IllegalCpus{ .. })));
+    assert!(matches!(parse_cpus("many"), Err(SandboxError::IllegalCpus{ .. })));
+}
@@ -0,0 +1,70 @@
+use brane_cli::registry::{select_search_page, SearchedPackage};
+use chrono::Utc;
+
+fn package(name: &str, version: &str, kind: &str, owners: &[&str]) -> SearchedPackage {
+    SearchedPackage {
+        name        : name.to_string(),
+        version     : version.to_string(),
+        kind        : kind.to_string(),
+        owners      : owners.iter().map(|owner| owner.to_string()).collect(),
+        description : None,
+        created     : Utc::now(),
+    }
+}
+
+fn registry() -> Vec<SearchedPackage> {
+    vec![
+        package("hello-world", "1.0.0", "ecu", &["alice"]),
+        package("hello-world", "1.2.0", "ecu", &["alice"]),
+        package("hello-world", "1.10.0", "ecu", &["alice"]),
+        package("base64", "2.0.0", "ecu", &["bob"]),
+        package("petstore", "0.1.0", "oas", &["alice", "bob"]),
+        package("cowsay", "not-a-version", "ecu", &["carol"]),
+        package("github", "1.0.0", "oas", &["carol"]),
+    ]
+}
+
+fn names_and_versions(results: &brane_cli::registry::SearchResults) -> Vec<(&str, &str)> {
+    results.packages.iter().map(|package| (package.name.as_str(), package.version.as_str())).collect()
+}
+
+#[test]
+fn the_first_page_has_the_latest_versions_by_name() {
+    let results = select_search_page(registry(), None, None, 2, 1);
+    assert_eq!(results.total, 4);
+    assert_eq!(names_and_versions(&results), vec![("base64", "2.0.0"), ("github", "1.0.0")]);
+
+    let results = select_search_page(registry(), None, None, 2, 2);
+    assert_eq!(names_and_versions(&results), vec![("hello-world", "1.10.0"), ("petstore", "0.1.0")]);
+}
+
+#[test]
+fn a_page_past_the_end_is_empty() {
+    let results = select_search_page(registry(), None, None, 2, 3);
+    assert_eq!(results.total, 4);
+    assert_eq!(results.page, 3);
+    assert!(results.packages.is_empty());
+}
+
+#[test]
+fn packages_are_filtered_by_kind() {
+    let results = select_search_page(registry(), Some("oas"), None, 10, 1);
+    assert_eq!(results.total, 2);
+    assert_eq!(names_and_versions(&results), vec![("github", "1.0.0"), ("petstore", "0.1.0")]);
+}
+
+#[test]
+fn packages_are_filtered_by_author() {
+    let results = select_search_page(registry(), None, Some("bob"), 10, 1);
+    assert_eq!(names_and_versions(&results), vec![("base64", "2.0.0"), ("petstore", "0.1.0")]);
+
+    let results = select_search_page(registry(), Some("ecu"), Some("alice"), 10, 1);
+    assert_eq!(names_and_versions(&results), vec![("hello-world", "1.10.0")]);
+}
+
+#[test]
+fn illegal_versions_are_skipped() {
+    let results = select_search_page(registry(), None, Some("carol"), 10, 1);
+    assert_eq!(results.total, 1);
+    assert_eq!(names_and_versions(&results), vec![("github", "1.0.0")]);
+}
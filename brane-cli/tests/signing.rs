@@ -0,0 +1,105 @@
+use brane_cli::signing::{check_image, check_signature, keygen, read_signing_key, read_trusted_keys};
+use specifications::errors::SigningError;
+use specifications::package::{PackageInfo, PackageKind};
+use specifications::signing::sign_package;
+use specifications::version::Version;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+fn package() -> PackageInfo {
+    let mut info = PackageInfo::new(String::from("arith"), Version::new(1, 0, 0), PackageKind::Ecu, vec![], String::from("Arithmetic"), false, HashMap::new(), HashMap::new(), vec![]);
+    info.digest = Some(String::from("sha256:0123456789abcdef"));
+    info
+}
+
+#[test]
+fn keygen_writes_a_usable_key_pair() {
+    let keys = tempfile::tempdir().unwrap();
+    let public = keygen(String::from("alice"), Some(keys.path().to_path_buf()), false).unwrap();
+    assert_eq!(public, keys.path().join("alice.pub"));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(keys.path().join("alice.key")).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    // Existing keys are only overwritten when forced to
+    assert!(keygen(String::from("alice"), Some(keys.path().to_path_buf()), false).is_err());
+    keygen(String::from("alice"), Some(keys.path().to_path_buf()), true).unwrap();
+
+    // The public half is what ends up in the trust store, and checks what the private half signed
+    let trust_store = tempfile::tempdir().unwrap();
+    fs::copy(&public, trust_store.path().join("alice.pub")).unwrap();
+    fs::write(trust_store.path().join("README"), "not a key").unwrap();
+    let trusted = read_trusted_keys(trust_store.path()).unwrap();
+    assert_eq!(trusted.len(), 1);
+
+    let info = package();
+    let signature = sign_package(&info, &read_signing_key(&keys.path().join("alice.key")).unwrap()).unwrap();
+    assert_eq!(check_signature(&info, Some(&signature), &trusted).unwrap(), "alice");
+}
+
+/// Writes an image.tar with the given config to the given path, returning the digest of that config.
+fn write_image(path: &Path, config: &[u8]) -> String {
+    let hex = format!("{:x}", Sha256::digest(config));
+    let manifest = format!(r#"[{{"Config": "blobs/sha256/{}", "RepoTags": [], "Layers": []}}]"#, hex);
+
+    let mut builder = tar::Builder::new(fs::File::create(path).unwrap());
+    for (name, contents) in [ ("manifest.json".to_string(), manifest.as_bytes()), (format!("blobs/sha256/{}", hex), config) ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, contents).unwrap();
+    }
+    builder.finish().unwrap();
+    format!("sha256:{}", hex)
+}
+
+#[test]
+fn pulled_images_must_match_the_digest() {
+    let dir = tempfile::tempdir().unwrap();
+    let image = dir.path().join("image.tar");
+    let mut info = package();
+    info.digest = Some(write_image(&image, br#"{"rootfs": {"diff_ids": []}}"#));
+    check_image(&info, &image).unwrap();
+
+    // Another image is refused, even if it claims to have the right config
+    write_image(&image, br#"{"rootfs": {"diff_ids": ["sha256:evil"]}}"#);
+    assert!(check_image(&info, &image).is_err());
+    let hex = info.digest.as_deref().unwrap().trim_start_matches("sha256:").to_string();
+    let mut builder = tar::Builder::new(fs::File::create(&image).unwrap());
+    for (name, contents) in [ ("manifest.json".to_string(), format!(r#"[{{"Config": "blobs/sha256/{}"}}]"#, hex).into_bytes()), (format!("blobs/sha256/{}", hex), b"tampered".to_vec()) ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_cksum();
+        builder.append_data(&mut header, name, contents.as_slice()).unwrap();
+    }
+    builder.finish().unwrap();
+    assert!(check_image(&info, &image).is_err());
+
+    // So are packages without a digest
+    info.digest = None;
+    assert!(check_image(&info, &image).is_err());
+}
+
+#[test]
+fn unsigned_and_untrusted_packages_are_rejected() {
+    let keys = tempfile::tempdir().unwrap();
+    keygen(String::from("mallory"), Some(keys.path().to_path_buf()), false).unwrap();
+    let info = package();
+    let signature = sign_package(&info, &read_signing_key(&keys.path().join("mallory.key")).unwrap()).unwrap();
+
+    // A missing trust store trusts nobody
+    let trusted = read_trusted_keys(&keys.path().join("does-not-exist")).unwrap();
+    assert!(trusted.is_empty());
+    assert!(matches!(check_signature(&info, Some(&signature), &trusted), Err(SigningError::UnknownKey{ .. })));
+    assert!(matches!(check_signature(&info, None, &trusted), Err(SigningError::Unsigned{ .. })));
+
+    // Broken keys in the trust store are reported rather than skipped
+    let trust_store = tempfile::tempdir().unwrap();
+    fs::write(trust_store.path().join("broken.pub"), "not base64!").unwrap();
+    assert!(read_trusted_keys(trust_store.path()).is_err());
+}
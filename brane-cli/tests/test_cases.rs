@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use brane_cli::test::{self, TestFailure};
+use serde_json::json;
+use specifications::common::{Type, Value};
+use specifications::container::{ContainerInfo, TestCase};
+
+const CONTAINER: &str = r#"
+name: geometry
+version: 1.0.0
+kind: ecu
+
+entrypoint:
+  kind: task
+  exec: run.sh
+
+actions:
+  'centre':
+    input:
+      - type: Point[]
+        name: points
+      - type: real
+        name: weight
+    output:
+      - type: Point
+        name: output
+
+types:
+  'Point':
+    name: Point
+    properties:
+      - type: real
+        name: x
+      - type: real
+        name: y
+
+tests:
+  - name: square
+    function: centre
+    input:
+      points: [ { x: 0, y: 0 }, { x: 2, y: 2 } ]
+      weight: 1
+    output: { x: 1, y: 1 }
+  - function: centre
+    input:
+      points: []
+      weight: 1
+    exitCode: 1
+"#;
+
+/// Returns the test cases and the declared types of the test container.
+fn container() -> (Vec<TestCase>, HashMap<String, Type>) {
+    let container = ContainerInfo::from_string(CONTAINER.to_string()).unwrap();
+    (container.tests.unwrap(), container.types.unwrap())
+}
+
+/// Encodes the given Value like branelet does as the last line of its stdout.
+fn stdout(value: &Value) -> String {
+    format!("some logging\n{}\n", base64::encode(serde_json::to_string(value).unwrap()))
+}
+
+/// Returns a Point struct.
+fn point(x: f64, y: f64) -> Value {
+    Value::Struct{ data_type: String::from("Point"), properties: vec![ (String::from("x"), Value::Real(x)), (String::from("y"), Value::Real(y)) ].into_iter().collect() }
+}
+
+#[test]
+fn matching_output_passes() {
+    let (tests, _) = container();
+    assert!(test::check_case(&tests[0], 0, &stdout(&point(1.0, 1.0)), "").is_none());
+}
+
+#[test]
+fn different_output_fails_with_a_diff() {
+    let (tests, _) = container();
+    let failure = test::check_case(&tests[0], 0, &stdout(&point(1.0, 1.5)), "").unwrap();
+    match &failure {
+        TestFailure::Output{ differences } => assert_eq!(differences.iter().map(|d| d.path.as_str()).collect::<Vec<&str>>(), vec![ ".y" ]),
+        failure                            => panic!("Expected an Output failure, got {:?}", failure),
+    }
+    assert_eq!(failure.to_string(), "output differs from what was expected (- expected, + actual):\nat '.y':\n  - 1\n  + 1.5");
+}
+
+#[test]
+fn unexpected_exit_code_fails_with_stderr() {
+    let (tests, _) = container();
+    let failure = test::check_case(&tests[0], 1, "", "Traceback:\nZeroDivisionError").unwrap();
+    assert!(matches!(&failure, TestFailure::ExitCode{ expected: 0, actual: 1, .. }), "Unexpected failure: {:?}", failure);
+    assert!(failure.to_string().ends_with("stderr (last 2 line(s)):\n  Traceback:\n  ZeroDivisionError"), "Unexpected report: {}", failure);
+
+    assert!(matches!(test::check_case(&tests[0], 0, "not base64", ""), Some(TestFailure::IllegalOutput{ .. })));
+}
+
+#[test]
+fn expected_failure_passes_only_with_that_code() {
+    let (tests, _) = container();
+    assert!(test::check_case(&tests[1], 1, "", "no points").is_none());
+    assert!(matches!(test::check_case(&tests[1], 0, &stdout(&point(0.0, 0.0)), ""), Some(TestFailure::ExitCode{ expected: 1, actual: 0, .. })));
+    assert!(matches!(test::check_case(&tests[1], 2, "", ""), Some(TestFailure::ExitCode{ expected: 1, actual: 2, .. })));
+}
+
+#[test]
+fn arguments_get_their_declared_types() {
+    let (tests, types) = container();
+    let input = tests[0].input.as_ref().unwrap();
+
+    let points = test::typed_value(&input["points"], Some("Point[]"), &types);
+    assert!(matches!(&points, Value::Array{ data_type, entries } if data_type == "Point[]" && entries.len() == 2));
+    if let Value::Array{ entries, .. } = &points {
+        assert!(matches!(&entries[1], Value::Struct{ data_type, properties } if data_type == "Point" && matches!(properties["x"], Value::Real(x) if x == 2.0)));
+    }
+    assert!(matches!(test::typed_value(&input["weight"], Some("real"), &types), Value::Real(w) if w == 1.0));
+
+    // Undeclared arguments are taken as-is
+    assert!(matches!(test::typed_value(&json!({ "x": 1 }), None, &types), Value::Struct{ data_type, .. } if data_type == "anonymous"));
+    assert!(matches!(test::typed_value(&json!(1), Some("integer"), &types), Value::Integer(1)));
+}
@@ -0,0 +1,186 @@
+/* AUTH.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 20:37:45
+ * Last edited:
+ *   15 Oct 2026, 20:37:45
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements the bearer-token authentication of the driver's gRPC API.
+ *   The server checks the token in the metadata of every request against
+ *   the ones it was configured with, and clients (like brane-cli) add
+ *   theirs to every request they send.
+**/
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint};
+use tonic::{Request, Status};
+
+use crate::errors::AuthError;
+use crate::grpc::DriverServiceClient;
+
+
+/***** CONSTANTS *****/
+/// The metadata key in which clients send their token.
+pub const AUTHORIZATION_KEY: &str = "authorization";
+/// The scheme that precedes the token in the metadata value.
+const BEARER_PREFIX: &str = "Bearer ";
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Compares two byte strings in a time that only depends on their lengths, so that the time it takes to reject a token says nothing about how much of it was right.
+fn constant_time_eq(lhs: &[u8], rhs: &[u8]) -> bool {
+    if lhs.len() != rhs.len() { return false; }
+    lhs.iter().zip(rhs).fold(0u8, |acc, (l, r)| acc | (l ^ r)) == 0
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Collects the tokens that clients may authenticate with.
+/// 
+/// **Arguments**
+///  * `file`: If given, a file with one token per line (empty lines and lines starting with '#' are skipped).
+///  * `list`: If given, a comma-separated list of tokens.
+/// 
+/// **Returns**  
+/// The tokens from both, or an AuthError if we could not read the file. If the list is empty, clients are not authenticated at all.
+pub fn read_tokens(file: Option<&Path>, list: Option<&str>) -> Result<Vec<String>, AuthError> {
+    let mut tokens: Vec<String> = Vec::new();
+    if let Some(file) = file {
+        let contents = match fs::read_to_string(file) {
+            Ok(contents) => contents,
+            Err(err)     => { return Err(AuthError::TokenFileError{ path: file.to_path_buf(), err }); }
+        };
+        tokens.extend(contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).map(String::from));
+    }
+    if let Some(list) = list {
+        tokens.extend(list.split(',').map(str::trim).filter(|token| !token.is_empty()).map(String::from));
+    }
+    Ok(tokens)
+}
+
+
+
+/// Connects to the driver on the given address, adding the given token to every request.
+/// 
+/// **Arguments**
+///  * `address`: The address of the driver (e.g., 'https://brane.example.com:50053').
+///  * `ca_cert`: If given, the CA certificate (as PEM) to verify the driver's TLS certificate with. Without it, the system's roots are used for 'https://' addresses.
+///  * `token`: The BearerToken to authenticate with.
+/// 
+/// **Returns**  
+/// The client on success, or a tonic transport error if the address is invalid or we could not connect.
+pub async fn connect(address: &str, ca_cert: Option<Vec<u8>>, token: BearerToken) -> Result<DriverClient, tonic::transport::Error> {
+    let mut endpoint = Endpoint::from_shared(address.to_string())?;
+    if let Some(ca_cert) = ca_cert {
+        endpoint = endpoint.tls_config(ClientTlsConfig::new().ca_certificate(Certificate::from_pem(ca_cert)))?;
+    }
+    let channel = endpoint.connect().await?;
+    Ok(DriverServiceClient::with_interceptor(channel, token))
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Checks the bearer token that clients send along with every request against the tokens that the driver knows.
+/// 
+/// If the driver knows no tokens at all, every request is let through.
+#[derive(Clone, Debug, Default)]
+pub struct TokenInterceptor {
+    /// The tokens that clients may authenticate with
+    tokens : Arc<Vec<String>>,
+}
+
+impl TokenInterceptor {
+    /// Constructor for the TokenInterceptor.
+    /// 
+    /// **Arguments**
+    ///  * `tokens`: The tokens that clients may authenticate with. If empty, clients are not authenticated.
+    #[inline]
+    pub fn new(tokens: Vec<String>) -> Self {
+        Self{ tokens: Arc::new(tokens) }
+    }
+
+
+
+    /// Returns whether this interceptor authenticates clients at all.
+    #[inline]
+    pub fn is_enabled(&self) -> bool { !self.tokens.is_empty() }
+}
+
+impl Interceptor for TokenInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        if !self.is_enabled() { return Ok(request); }
+
+        let value = match request.metadata().get(AUTHORIZATION_KEY) {
+            Some(value) => value,
+            None        => { return Err(Status::unauthenticated("Missing token")); }
+        };
+        let valid = match value.to_str().ok().and_then(|value| value.strip_prefix(BEARER_PREFIX)) {
+            Some(token) => self.tokens.iter().any(|known| constant_time_eq(known.as_bytes(), token.as_bytes())),
+            None        => false,
+        };
+
+        if valid { Ok(request) } else { Err(Status::unauthenticated("Invalid token")) }
+    }
+}
+
+
+
+/// Adds a bearer token to every request that a client sends.
+#[derive(Clone, Debug, Default)]
+pub struct BearerToken {
+    /// The value of the authorization metadata, or None to send requests without one
+    value : Option<MetadataValue<Ascii>>,
+}
+
+impl BearerToken {
+    /// Constructor for the BearerToken.
+    /// 
+    /// **Arguments**
+    ///  * `token`: The token to send, or None to not authenticate at all.
+    /// 
+    /// **Returns**  
+    /// The new BearerToken, or an AuthError if the token cannot be sent as gRPC metadata.
+    pub fn new(token: Option<&str>) -> Result<Self, AuthError> {
+        let value = match token {
+            Some(token) => match format!("{}{}", BEARER_PREFIX, token).parse() {
+                Ok(value) => Some(value),
+                Err(_)    => { return Err(AuthError::IllegalToken); }
+            },
+            None => None,
+        };
+        Ok(Self{ value })
+    }
+}
+
+impl Interceptor for BearerToken {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(value) = &self.value {
+            request.metadata_mut().insert(AUTHORIZATION_KEY, value.clone());
+        }
+        Ok(request)
+    }
+}
+
+
+
+/// A client to the driver that authenticates every request with a BearerToken.
+pub type DriverClient = DriverServiceClient<InterceptedService<Channel, BearerToken>>;
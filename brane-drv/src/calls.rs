@@ -0,0 +1,425 @@
+/* CALLS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 23:59:59
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Remembers the results of calls to functions that their package marks
+ *   as pure, so that running a workflow again does not repeat the calls
+ *   it already made with the same arguments. Results are kept in memory
+ *   and, optionally, in a directory so that they survive a restart.
+**/
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use brane_bvm::executor::ExecutorError;
+use specifications::common::{FunctionExt, Value};
+
+use crate::errors::CallCacheError;
+use crate::metrics;
+
+
+/***** LIBRARY STRUCTS *****/
+/// Identifies a call to a pure function by the image it runs in, the function and its arguments.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct CallKey {
+    /// The package that provides the function.
+    pub package : String,
+    /// The version of the package.
+    pub version : String,
+    /// The digest of the package's image. Results computed by another image are never reused.
+    pub digest  : String,
+    /// The function and the (canonicalized) arguments it is called with.
+    pub call    : String,
+}
+
+impl CallKey {
+    /// Constructor for the CallKey.
+    /// 
+    /// **Arguments**
+    ///  * `function`: The function that is called.
+    ///  * `arguments`: The arguments it is called with.
+    /// 
+    /// **Returns**  
+    /// The key of the call, or None if the function is not pure (or detached, since a service is never the same twice), or if it is given files (see `refers_to_data()`).
+    pub fn new(function: &FunctionExt, arguments: &HashMap<String, Value>) -> Option<Self> {
+        if !function.pure || function.detached { return None; }
+        if arguments.values().any(refers_to_data) { return None; }
+
+        // Going through a JSON value sorts the arguments (also nested maps), as the order of a HashMap is not stable
+        let arguments = serde_json::to_value(arguments).ok()?.to_string();
+        // The package environment that the script configured may change the result too
+        let env = if function.env.is_empty() { String::new() } else { format!("+env{}", serde_json::to_value(&function.env).ok()?) };
+        Some(Self {
+            package : function.package.clone(),
+            version : function.version.to_string(),
+            digest  : function.digest.clone(),
+            call    : format!("{}{}{}", function.name, arguments, env),
+        })
+    }
+}
+
+impl Display for CallKey {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        write!(f, "{}:{}@{}/{}", self.package, self.version, self.digest, self.call)
+    }
+}
+
+
+
+/// Keeps the results of the most recently used calls in memory. If there are more than the given number, the least recently used is evicted first.
+#[derive(Debug)]
+pub struct MemoryStore {
+    /// The maximum number of results that we keep around.
+    capacity : usize,
+    /// The results by key, together with the order in which they were last used.
+    inner    : Mutex<(HashMap<CallKey, Value>, VecDeque<CallKey>)>,
+}
+
+impl MemoryStore {
+    /// Constructor for the MemoryStore.
+    /// 
+    /// **Arguments**
+    ///  * `capacity`: The maximum number of results to remember.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner : Mutex::new((HashMap::new(), VecDeque::new())),
+        }
+    }
+
+    /// Returns the number of results in the store.
+    #[inline]
+    pub fn len(&self) -> usize { self.inner.lock().unwrap().0.len() }
+
+    /// Returns whether the store is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.inner.lock().unwrap().0.is_empty() }
+}
+
+impl CallStore for MemoryStore {
+    fn get(&self, key: &CallKey) -> Result<Option<Value>, CallCacheError> {
+        let mut inner = self.inner.lock().unwrap();
+        let (values, order) = &mut *inner;
+        let value = match values.get(key) {
+            Some(value) => value.clone(),
+            None        => { return Ok(None); }
+        };
+
+        // Mark it as the most recently used
+        if let Some(index) = order.iter().position(|k| k == key) { order.remove(index); }
+        order.push_back(key.clone());
+        Ok(Some(value))
+    }
+
+    fn put(&self, key: &CallKey, value: &Value) -> Result<(), CallCacheError> {
+        if self.capacity == 0 { return Ok(()); }
+        let mut inner = self.inner.lock().unwrap();
+        let (values, order) = &mut *inner;
+
+        if values.insert(key.clone(), value.clone()).is_some() {
+            if let Some(index) = order.iter().position(|k| k == key) { order.remove(index); }
+        }
+        order.push_back(key.clone());
+        while order.len() > self.capacity {
+            if let Some(oldest) = order.pop_front() { values.remove(&oldest); }
+        }
+        Ok(())
+    }
+
+    fn invalidate(&self, package: &str, version: &str, digest: &str) -> Result<usize, CallCacheError> {
+        let mut inner = self.inner.lock().unwrap();
+        let (values, order) = &mut *inner;
+        let before = values.len();
+        let keep = |key: &CallKey| key.package != package || key.version != version || key.digest == digest;
+        values.retain(|key, _| keep(key));
+        order.retain(|key| keep(key));
+        Ok(before - values.len())
+    }
+}
+
+
+
+/// Keeps results in a directory, as one JSON file per package image (`<dir>/<package>/<version>/<digest>.json`), so that they survive a restart of the driver.
+#[derive(Debug)]
+pub struct FileStore {
+    /// The directory to keep the results in.
+    dir   : PathBuf,
+    /// Makes sure that only one thread at a time rewrites a file.
+    write : Mutex<()>,
+}
+
+impl FileStore {
+    /// Constructor for the FileStore, which creates the given directory if it does not exist yet.
+    /// 
+    /// **Arguments**
+    ///  * `dir`: The directory to keep the results in.
+    /// 
+    /// **Returns**  
+    /// A new FileStore, or a CallCacheError if the directory could not be created.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, CallCacheError> {
+        let dir = dir.into();
+        if let Err(err) = fs::create_dir_all(&dir) { return Err(CallCacheError::DirCreateError{ path: dir, err }); }
+        Ok(Self{ dir, write: Mutex::new(()) })
+    }
+
+    /// Returns the directory with the results of the given package version.
+    fn version_dir(&self, package: &str, version: &str) -> PathBuf {
+        self.dir.join(package).join(file_stem(version))
+    }
+
+    /// Returns the path of the file with the results of the given package image.
+    fn path(&self, key: &CallKey) -> PathBuf {
+        self.version_dir(&key.package, &key.version).join(format!("{}.json", file_stem(&key.digest)))
+    }
+}
+
+impl CallStore for FileStore {
+    fn get(&self, key: &CallKey) -> Result<Option<Value>, CallCacheError> {
+        let mut values = read_results(&self.path(key))?;
+        Ok(values.remove(&key.call))
+    }
+
+    fn put(&self, key: &CallKey, value: &Value) -> Result<(), CallCacheError> {
+        let _lock = self.write.lock().unwrap();
+        let path = self.path(key);
+        let mut values = read_results(&path)?;
+        values.insert(key.call.clone(), value.clone());
+        let contents = match serde_json::to_string(&values) {
+            Ok(contents) => contents,
+            Err(err)     => { return Err(CallCacheError::SerializeError{ package: key.package.clone(), digest: key.digest.clone(), err }); }
+        };
+
+        // Write to a temporary file first, so a crash never leaves a half-written file behind
+        let dir = self.version_dir(&key.package, &key.version);
+        if let Err(err) = fs::create_dir_all(&dir) { return Err(CallCacheError::DirCreateError{ path: dir, err }); }
+        let temp = dir.join(format!(".{}.json.tmp", file_stem(&key.digest)));
+        if let Err(err) = fs::write(&temp, contents) { return Err(CallCacheError::FileWriteError{ path: temp, err }); }
+        if let Err(err) = fs::rename(&temp, &path) { return Err(CallCacheError::FileWriteError{ path, err }); }
+        Ok(())
+    }
+
+    fn invalidate(&self, package: &str, version: &str, digest: &str) -> Result<usize, CallCacheError> {
+        let _lock = self.write.lock().unwrap();
+        let dir = self.version_dir(package, version);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => { return Ok(0); }
+            Err(err) => { return Err(CallCacheError::FileReadError{ path: dir, err }); }
+        };
+
+        // Remove the files of every other image of the package version
+        let keep = format!("{}.json", file_stem(digest));
+        let mut removed = 0;
+        for entry in entries {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(err)  => { return Err(CallCacheError::FileReadError{ path: dir, err }); }
+            };
+            if path.file_name().and_then(|name| name.to_str()) == Some(keep.as_str()) || path.extension().and_then(|ext| ext.to_str()) != Some("json") { continue; }
+            removed += read_results(&path)?.len();
+            if let Err(err) = fs::remove_file(&path) { return Err(CallCacheError::FileWriteError{ path, err }); }
+        }
+        Ok(removed)
+    }
+}
+
+
+
+/// The cache that the driver consults before scheduling a call to a pure function. It looks in each of its stores in turn (the in-memory store first), and stores results in all of them.
+/// 
+/// Since stores may read and write files, they are used from a blocking thread rather than from the async runtime.
+pub struct CallCache {
+    /// The stores, fastest first.
+    stores  : Vec<Arc<dyn CallStore>>,
+    /// The digest we last saw for every package version, so we know when to invalidate the results of its previous image.
+    digests : Mutex<HashMap<(String, String), String>>,
+}
+
+impl CallCache {
+    /// Constructor for the CallCache, which keeps results in memory only.
+    /// 
+    /// **Arguments**
+    ///  * `capacity`: The maximum number of results to keep in memory.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            stores  : vec![ Arc::new(MemoryStore::new(capacity)) ],
+            digests : Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Adds another store, which is consulted after the existing ones.
+    /// 
+    /// **Arguments**
+    ///  * `store`: The CallStore to add (e.g., a FileStore).
+    /// 
+    /// **Returns**  
+    /// The same CallCache, with the store added.
+    #[inline]
+    pub fn with_store(mut self, store: Box<dyn CallStore>) -> Self {
+        self.stores.push(Arc::from(store));
+        self
+    }
+
+
+
+    /// Looks up the result of the given call. If we see the package version with a different digest than before, the results of its previous image are invalidated first.
+    /// 
+    /// **Arguments**
+    ///  * `key`: The key of the call.
+    /// 
+    /// **Returns**  
+    /// The result of an earlier call with the same key, if any store has it. Stores that fail to read are treated as not having it.
+    pub async fn get(&self, key: &CallKey) -> Option<Value> {
+        self.invalidate_stale(key).await;
+
+        for (i, store) in self.stores.iter().enumerate() {
+            let lookup = key.clone();
+            match blocking(store, move |store| store.get(&lookup)).await {
+                Ok(Some(value)) => {
+                    // Copy it to the faster stores, so the next lookup is quicker
+                    for faster in &self.stores[..i] {
+                        let (stored, value) = (key.clone(), value.clone());
+                        if let Err(err) = blocking(faster, move |faster| faster.put(&stored, &value)).await { warn!("Could not store result of call '{}': {}", key, err); }
+                    }
+                    metrics::CALL_CACHE_LOOKUPS.with_label_values(&["hit"]).inc();
+                    return Some(value);
+                },
+                Ok(None) => {},
+                Err(err) => { warn!("Could not look up result of call '{}': {}", key, err); }
+            }
+        }
+        metrics::CALL_CACHE_LOOKUPS.with_label_values(&["miss"]).inc();
+        None
+    }
+
+    /// Stores the result of the given call, but only if it succeeded: a failed call is always tried again. Results with files are never stored either, as those live in the data directory of the session that made the call (see `refers_to_data()`).
+    /// 
+    /// **Arguments**
+    ///  * `key`: The key of the call.
+    ///  * `result`: What the call returned.
+    pub async fn record(&self, key: &CallKey, result: &Result<Value, ExecutorError>) {
+        let value = match result {
+            Ok(value) if !refers_to_data(value) => value,
+            _                                   => { return; }
+        };
+        self.invalidate_stale(key).await;
+        for store in &self.stores {
+            let (stored, value) = (key.clone(), value.clone());
+            if let Err(err) = blocking(store, move |store| store.put(&stored, &value)).await { warn!("Could not store result of call '{}': {}", key, err); }
+        }
+    }
+
+    /// Invalidates the results of the given package version that another image computed, if we did not see this digest for the package version last time.
+    async fn invalidate_stale(&self, key: &CallKey) {
+        {
+            let mut digests = self.digests.lock().unwrap();
+            let version = (key.package.clone(), key.version.clone());
+            if digests.get(&version).map(|known| known == &key.digest).unwrap_or(false) { return; }
+            digests.insert(version, key.digest.clone());
+        }
+
+        for store in &self.stores {
+            let (package, version, digest) = (key.package.clone(), key.version.clone(), key.digest.clone());
+            match blocking(store, move |store| store.invalidate(&package, &version, &digest)).await {
+                Ok(0)       => {},
+                Ok(removed) => { info!("Invalidated {} cached result(s) of package '{}' version {}, which now has digest '{}'", removed, key.package, key.version, key.digest); },
+                Err(err)    => { warn!("Could not invalidate cached results of package '{}' version {}: {}", key.package, key.version, err); }
+            }
+        }
+    }
+}
+
+
+
+
+
+/***** LIBRARY TRAITS *****/
+/// A place to keep the results of calls in.
+pub trait CallStore: Send + Sync {
+    /// Returns the result of the given call, if the store has it.
+    /// 
+    /// **Arguments**
+    ///  * `key`: The key of the call.
+    /// 
+    /// **Returns**  
+    /// The result if we have it, None if we don't, or a CallCacheError if the store could not be read.
+    fn get(&self, key: &CallKey) -> Result<Option<Value>, CallCacheError>;
+
+    /// Stores the result of the given call.
+    /// 
+    /// **Arguments**
+    ///  * `key`: The key of the call.
+    ///  * `value`: The value it returned.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or a CallCacheError if the store could not be written.
+    fn put(&self, key: &CallKey, value: &Value) -> Result<(), CallCacheError>;
+
+    /// Removes the results of the given package version that were computed by an image with another digest. Results of other versions are kept.
+    /// 
+    /// **Arguments**
+    ///  * `package`: The package to invalidate the results of.
+    ///  * `version`: The version of the package.
+    ///  * `digest`: The digest of the package version's current image, of which the results are kept.
+    /// 
+    /// **Returns**  
+    /// The number of results removed, or a CallCacheError if the store could not be written.
+    fn invalidate(&self, package: &str, version: &str, digest: &str) -> Result<usize, CallCacheError>;
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Runs the given operation on a store in a blocking thread, so that a store that reads or writes files does not hold up the async runtime.
+async fn blocking<T, F>(store: &Arc<dyn CallStore>, op: F) -> Result<T, CallCacheError>
+where
+    T: Send + 'static,
+    F: FnOnce(&dyn CallStore) -> Result<T, CallCacheError> + Send + 'static,
+{
+    let store = store.clone();
+    match tokio::task::spawn_blocking(move || op(store.as_ref())).await {
+        Ok(res)  => res,
+        Err(err) => Err(CallCacheError::TaskError{ err }),
+    }
+}
+
+/// Returns whether the given value refers to a file or directory (also nested). Those live in the data directory of a session, so a call that takes or returns one cannot be reused by another session.
+fn refers_to_data(value: &Value) -> bool {
+    match value {
+        Value::Struct{ data_type, properties } => data_type == "File" || data_type == "Directory" || properties.values().any(refers_to_data),
+        Value::Array{ entries, .. }            => entries.iter().any(refers_to_data),
+        Value::Map(entries)                    => entries.values().any(refers_to_data),
+        _                                      => false,
+    }
+}
+
+/// Turns the given image digest into something that may be used as a filename (e.g., 'sha256:abc' into 'sha256-abc').
+fn file_stem(digest: &str) -> String {
+    digest.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' }).collect()
+}
+
+/// Reads the results stored in the given file, which are none if it does not exist.
+fn read_results(path: &Path) -> Result<HashMap<String, Value>, CallCacheError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => { return Ok(HashMap::new()); }
+        Err(err) => { return Err(CallCacheError::FileReadError{ path: path.to_path_buf(), err }); }
+    };
+    match serde_json::from_str(&contents) {
+        Ok(values) => Ok(values),
+        Err(err)   => Err(CallCacheError::FileParseError{ path: path.to_path_buf(), err }),
+    }
+}
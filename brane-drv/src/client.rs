@@ -0,0 +1,531 @@
+/* CLIENT.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 23:59:58
+ * Last edited:
+ *   15 Oct 2026, 23:59:58
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements the bounded channel that carries the ExecuteReplies of a
+ *   statement to its client. Instead of blocking (or failing) as soon as
+ *   a slow client lets the channel fill up, every class of reply has its
+ *   own overflow policy: debug messages make room by dropping the oldest
+ *   one, output is merged into the output that is already waiting and
+ *   the closing reply is always accepted. Only output that cannot be
+ *   queued in time makes sending fail. Senders may be tagged with the
+ *   statement they send for, in which case every reply is numbered and
+ *   copied to the clients that follow the session.
+**/
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter, Result as FResult};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use futures_core::Stream;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use tonic::Status;
+
+use crate::grpc;
+
+
+/***** CONSTANTS *****/
+/// The number of replies that may wait for the client before the overflow policies kick in.
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// How long output may wait for room in the channel before we give up on the client.
+pub const DEFAULT_SEND_TIMEOUT: Duration = Duration::from_secs(60);
+
+
+
+
+
+/***** ERRORS *****/
+/// Defines the reasons why a reply could not be sent to the client.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The client is gone (i.e., the receiving end of the channel has been dropped).
+    Disconnected,
+    /// The client does not read its replies, so there was no room for output (that we may not drop) in time.
+    Backpressure{ class: ReplyClass, pending: usize, timeout: Duration },
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            ClientError::Disconnected                         => write!(f, "Client has disconnected"),
+            ClientError::Backpressure{ class, pending, timeout } => write!(f, "Client does not read its replies: {} could not be sent within {:?}, as {} replies are still waiting", class, timeout, pending),
+        }
+    }
+}
+
+impl Error for ClientError {}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// A reply for the client, as it goes over the gRPC stream.
+pub type ClientReply = Result<grpc::ExecuteReply, Status>;
+
+
+
+/// The classes of replies, which are treated differently once the channel is full.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReplyClass {
+    /// Debug messages, which are dropped (oldest first) if the client can't keep up.
+    Debug,
+    /// Output on stdout, which is merged with the stdout that is already waiting if the client can't keep up.
+    Stdout,
+    /// Output on stderr, which is merged with the stderr that is already waiting if the client can't keep up.
+    Stderr,
+    /// The closing reply (or the status a statement is refused with), which is never dropped.
+    Final,
+}
+
+impl ReplyClass {
+    /// Returns the class of the given reply.
+    pub fn of(reply: &ClientReply) -> Self {
+        match reply {
+            Ok(reply) => Self::of_reply(reply),
+            Err(_)    => ReplyClass::Final,
+        }
+    }
+
+    /// Returns the class of the given (successful) reply.
+    pub fn of_reply(reply: &grpc::ExecuteReply) -> Self {
+        if reply.close { ReplyClass::Final }
+        else if reply.stdout.is_some() { ReplyClass::Stdout }
+        else if reply.stderr.is_some() { ReplyClass::Stderr }
+        else { ReplyClass::Debug }
+    }
+}
+
+impl Display for ReplyClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            ReplyClass::Debug  => write!(f, "debug message"),
+            ReplyClass::Stdout => write!(f, "stdout"),
+            ReplyClass::Stderr => write!(f, "stderr"),
+            ReplyClass::Final  => write!(f, "closing reply"),
+        }
+    }
+}
+
+
+
+/// The state that both ends of the channel share.
+struct Shared {
+    /// The number of replies that may wait before the overflow policies kick in.
+    capacity : usize,
+    /// How long output may wait for room.
+    timeout  : Duration,
+    /// The state of the channel itself.
+    state    : Mutex<State>,
+    /// Notifies the senders that are waiting for room whenever a reply is taken out (or the receiver is dropped).
+    space    : Notify,
+}
+
+/// The state of a channel.
+struct State {
+    /// The replies that wait for the client.
+    queue     : VecDeque<ClientReply>,
+    /// The number of senders that are alive.
+    senders   : usize,
+    /// Whether the receiver is alive.
+    receiver  : bool,
+    /// Wakes the receiver if it waits for a reply.
+    waker     : Option<Waker>,
+    /// The number of debug messages that have been dropped so far.
+    dropped   : usize,
+    /// The number of outputs that have been merged with output that was already waiting so far.
+    coalesced : usize,
+}
+
+/// What happened to a reply that was offered to the channel.
+enum Offer {
+    /// It has been queued (or merged, or dropped according to its policy).
+    Queued,
+    /// There is no room for it.
+    Full(ClientReply),
+    /// The receiver is gone.
+    Disconnected,
+}
+
+impl Shared {
+    /// Tries to queue the given reply according to the overflow policy of its class.
+    fn offer(&self, reply: ClientReply, class: ReplyClass) -> Offer {
+        let mut state = self.state.lock().unwrap();
+        if !state.receiver { return Offer::Disconnected; }
+
+        if class == ReplyClass::Final || state.queue.len() < self.capacity {
+            state.queue.push_back(reply);
+        } else {
+            match class {
+                ReplyClass::Debug => {
+                    // Make room by dropping the oldest debug message, or drop this one if there is none
+                    if let Some(index) = state.queue.iter().position(|queued| ReplyClass::of(queued) == ReplyClass::Debug) {
+                        state.queue.remove(index);
+                        state.queue.push_back(reply);
+                    }
+                    state.dropped += 1;
+                    debug!("Dropped a debug message for a client that doesn't keep up ({} so far)", state.dropped);
+                },
+                ReplyClass::Stdout | ReplyClass::Stderr => {
+                    // Merge it with the last reply if that's output of the same kind; the client prints every reply on its own line
+                    match state.queue.back_mut() {
+                        Some(Ok(last)) if ReplyClass::of_reply(last) == class => {
+                            let (text, target) = match (reply, class) {
+                                (Ok(reply), ReplyClass::Stdout) => (reply.stdout, &mut last.stdout),
+                                (Ok(reply), _)                  => (reply.stderr, &mut last.stderr),
+                                (Err(_), _)                     => unreachable!(),
+                            };
+                            if let (Some(text), Some(target)) = (text, target.as_mut()) {
+                                target.push('\n');
+                                target.push_str(&text);
+                            }
+                            state.coalesced += 1;
+                        },
+                        _ => { return Offer::Full(reply); },
+                    }
+                },
+                ReplyClass::Final => unreachable!(),
+            }
+        }
+
+        // Wake the receiver, if it's waiting
+        let waker = state.waker.take();
+        drop(state);
+        if let Some(waker) = waker { waker.wake(); }
+        Offer::Queued
+    }
+}
+
+
+
+/// The statement that a tagged ClientSender sends replies for (see `ClientSender::tagged()`).
+#[derive(Debug)]
+struct Tag {
+    /// The number of the statement within its session.
+    sequence  : u64,
+    /// The client that sent the statement, if it identified itself.
+    client    : Option<String>,
+    /// The clients that follow the session.
+    followers : Arc<Followers>,
+}
+
+
+
+/// The sending end of a channel to a client (see `channel()`). It may be cloned, and the receiver sees the end of the stream once all clones are dropped.
+pub struct ClientSender {
+    /// The state shared with the receiver.
+    shared : Arc<Shared>,
+    /// The statement that we send replies for, if we are tagged.
+    tag    : Option<Arc<Tag>>,
+}
+
+impl ClientSender {
+    /// Returns a clone of this sender that numbers every reply with the given statement and copies it to the given followers.
+    /// 
+    /// **Arguments**
+    ///  * `sequence`: The number of the statement within its session.
+    ///  * `client`: The client that sent the statement, if it identified itself.
+    ///  * `followers`: The clients that follow the session of the statement.
+    /// 
+    /// **Returns**  
+    /// The tagged sender, which shares the channel with this one.
+    pub fn tagged(&self, sequence: u64, client: Option<String>, followers: Arc<Followers>) -> Self {
+        let mut sender = self.clone();
+        sender.tag = Some(Arc::new(Tag{ sequence, client, followers }));
+        sender
+    }
+
+    /// Numbers the given reply with the statement we send for, if we are tagged. Doing so twice is harmless.
+    /// 
+    /// **Arguments**
+    ///  * `reply`: The reply to number.
+    /// 
+    /// **Returns**  
+    /// The numbered reply, or the reply as-is if we aren't tagged (or it's an error).
+    pub fn stamp(&self, reply: ClientReply) -> ClientReply {
+        match (&self.tag, reply) {
+            (Some(tag), Ok(reply)) => Ok(grpc::ExecuteReply{ sequence: Some(tag.sequence), client: tag.client.clone(), ..reply }),
+            (_, reply)             => reply,
+        }
+    }
+
+    /// Numbers the given reply and copies it to the followers, if we are tagged.
+    fn publish(&self, reply: ClientReply) -> ClientReply {
+        let reply = self.stamp(reply);
+        if let Some(tag) = &self.tag {
+            tag.followers.publish(&reply, tag.sequence, &tag.client);
+        }
+        reply
+    }
+
+    /// Sends a reply to the client, waiting for room if it is output that the channel has no room for.
+    /// 
+    /// Debug messages and the closing reply never wait (see ReplyClass for how each class is treated once the channel is full).
+    /// 
+    /// **Arguments**
+    ///  * `reply`: The reply to send.
+    /// 
+    /// **Returns**  
+    /// Nothing if the reply has been queued, or a ClientError if the client is gone or there was no room for the output in time.
+    pub async fn send(&self, reply: ClientReply) -> Result<(), ClientError> {
+        let mut reply = self.publish(reply);
+        let class = ReplyClass::of(&reply);
+        let deadline = Instant::now() + self.shared.timeout;
+        loop {
+            // Register for room before we check, so we don't miss it being made in between
+            let space = self.shared.space.notified();
+            reply = match self.shared.offer(reply, class) {
+                Offer::Queued       => { return Ok(()); },
+                Offer::Disconnected => { return Err(ClientError::Disconnected); },
+                Offer::Full(reply)  => reply,
+            };
+            if tokio::time::timeout_at(deadline, space).await.is_err() {
+                return Err(ClientError::Backpressure{ class, pending: self.pending(), timeout: self.shared.timeout });
+            }
+        }
+    }
+
+    /// Sends a reply to the client without waiting (see `ClientSender::send()`), for replies that are not worth holding anything up for.
+    /// 
+    /// **Arguments**
+    ///  * `reply`: The reply to send.
+    /// 
+    /// **Returns**  
+    /// Nothing if the reply has been queued, or a ClientError if the client is gone or there is no room for the output.
+    pub fn try_send(&self, reply: ClientReply) -> Result<(), ClientError> {
+        let reply = self.publish(reply);
+        let class = ReplyClass::of(&reply);
+        match self.shared.offer(reply, class) {
+            Offer::Queued       => Ok(()),
+            Offer::Full(_)      => Err(ClientError::Backpressure{ class, pending: self.pending(), timeout: Duration::ZERO }),
+            Offer::Disconnected => Err(ClientError::Disconnected),
+        }
+    }
+
+
+
+    /// Returns the number of replies that wait for the client.
+    #[inline]
+    pub fn pending(&self) -> usize { self.shared.state.lock().unwrap().queue.len() }
+}
+
+impl Clone for ClientSender {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+        Self { shared: self.shared.clone(), tag: self.tag.clone() }
+    }
+}
+
+impl Drop for ClientSender {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.senders -= 1;
+        // The receiver sees the end of the stream once the last sender is gone
+        let waker = if state.senders == 0 { state.waker.take() } else { None };
+        drop(state);
+        if let Some(waker) = waker { waker.wake(); }
+    }
+}
+
+impl Debug for ClientSender {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        f.debug_struct("ClientSender").field("capacity", &self.shared.capacity).field("pending", &self.pending()).field("sequence", &self.tag.as_ref().map(|tag| tag.sequence)).finish()
+    }
+}
+
+
+
+/// The clients that follow a session, which get a copy of the replies of every statement that runs in it (see `ClientSender::tagged()`).
+/// 
+/// Followers never hold up a statement: if one doesn't keep up, it misses the output that there's no room for, and followers that are gone are forgotten.
+#[derive(Debug, Default)]
+pub struct Followers {
+    /// The channels to the followers.
+    senders : Mutex<Vec<ClientSender>>,
+}
+
+impl Followers {
+    /// Constructor for the Followers, which starts without any.
+    #[inline]
+    pub fn new() -> Self { Self::default() }
+
+
+
+    /// Adds a follower.
+    /// 
+    /// **Arguments**
+    ///  * `capacity`: The number of replies that may wait for the follower (see `channel()`).
+    /// 
+    /// **Returns**  
+    /// The stream of replies for the follower, which ends once the session is forgotten.
+    pub fn follow(&self, capacity: usize) -> ClientReceiver {
+        let (tx, rx) = channel(capacity);
+        self.senders.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// Copies the given reply to every follower. Errors (which only the client that sent the statement gets as a Status) are copied as a closing reply with the error on stderr.
+    /// 
+    /// **Arguments**
+    ///  * `reply`: The reply to copy.
+    ///  * `sequence`: The number of the statement that the reply is for.
+    ///  * `client`: The client that sent the statement, if it identified itself.
+    pub fn publish(&self, reply: &ClientReply, sequence: u64, client: &Option<String>) {
+        let mut senders = self.senders.lock().unwrap();
+        if senders.is_empty() { return; }
+        let reply = match reply {
+            Ok(reply)   => reply.clone(),
+            Err(status) => grpc::ExecuteReply {
+                close: true,
+                debug: None,
+                stderr: Some(status.message().to_string()),
+                stdout: None,
+                trace: None,
+                cached: None,
+                diff: None,
+                sequence: Some(sequence),
+                client: client.clone(),
+                input: None,
+            },
+        };
+        senders.retain(|tx| match tx.try_send(Ok(reply.clone())) {
+            Ok(_)                                => true,
+            Err(ClientError::Disconnected)       => { debug!("Forgetting follower that has disconnected"); false },
+            Err(ClientError::Backpressure{ .. }) => { debug!("Follower does not keep up; it misses a reply of statement #{}", sequence); true },
+        });
+    }
+
+    /// Returns the number of followers.
+    #[inline]
+    pub fn len(&self) -> usize { self.senders.lock().unwrap().len() }
+
+    /// Returns whether nobody follows the session.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.senders.lock().unwrap().is_empty() }
+}
+
+
+
+/// The receiving end of a channel to a client (see `channel()`), which is the stream of replies that tonic sends.
+pub struct ClientReceiver {
+    /// The state shared with the senders.
+    shared : Arc<Shared>,
+}
+
+impl ClientReceiver {
+    /// Waits for the next reply.
+    /// 
+    /// **Returns**  
+    /// The reply, or None once all senders are gone and every reply has been received.
+    pub async fn recv(&mut self) -> Option<ClientReply> {
+        futures::future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// Returns the next reply if there is one, without waiting.
+    pub fn try_recv(&mut self) -> Option<ClientReply> {
+        let reply = self.shared.state.lock().unwrap().queue.pop_front();
+        if reply.is_some() { self.shared.space.notify_waiters(); }
+        reply
+    }
+
+    /// Polls for the next reply.
+    /// 
+    /// **Arguments**
+    ///  * `cx`: The Context of the task that polls.
+    /// 
+    /// **Returns**  
+    /// The reply if there is one, None if all senders are gone and every reply has been received, or Pending otherwise.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<ClientReply>> {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(reply) = state.queue.pop_front() {
+            drop(state);
+            self.shared.space.notify_waiters();
+            return Poll::Ready(Some(reply));
+        }
+        if state.senders == 0 { return Poll::Ready(None); }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+
+
+    /// Returns the number of debug messages that have been dropped because the client didn't keep up.
+    #[inline]
+    pub fn dropped(&self) -> usize { self.shared.state.lock().unwrap().dropped }
+
+    /// Returns the number of outputs that have been merged with output that was already waiting because the client didn't keep up.
+    #[inline]
+    pub fn coalesced(&self) -> usize { self.shared.state.lock().unwrap().coalesced }
+}
+
+impl Stream for ClientReceiver {
+    type Item = ClientReply;
+
+    #[inline]
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_recv(cx)
+    }
+}
+
+impl Drop for ClientReceiver {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().receiver = false;
+        // Wake the senders that wait for room, so they find out
+        self.shared.space.notify_waiters();
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Creates a channel to a client that holds `capacity` replies before the overflow policies kick in, and on which output waits for room for `DEFAULT_SEND_TIMEOUT` at most.
+/// 
+/// **Arguments**
+///  * `capacity`: The number of replies that may wait for the client. Is at least 1.
+/// 
+/// **Returns**  
+/// The sending and the receiving end of the channel.
+#[inline]
+pub fn channel(capacity: usize) -> (ClientSender, ClientReceiver) {
+    channel_with_timeout(capacity, DEFAULT_SEND_TIMEOUT)
+}
+
+/// Creates a channel to a client like `channel()`, but with the given time that output may wait for room.
+/// 
+/// **Arguments**
+///  * `capacity`: The number of replies that may wait for the client. Is at least 1.
+///  * `timeout`: How long output may wait for room before sending it fails.
+/// 
+/// **Returns**  
+/// The sending and the receiving end of the channel.
+pub fn channel_with_timeout(capacity: usize, timeout: Duration) -> (ClientSender, ClientReceiver) {
+    let shared = Arc::new(Shared {
+        capacity : capacity.max(1),
+        timeout,
+        state    : Mutex::new(State {
+            queue     : VecDeque::new(),
+            senders   : 1,
+            receiver  : true,
+            waker     : None,
+            dropped   : 0,
+            coalesced : 0,
+        }),
+        space    : Notify::new(),
+    });
+    (ClientSender{ shared: shared.clone(), tag: None }, ClientReceiver{ shared })
+}
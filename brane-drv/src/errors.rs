@@ -14,32 +14,29 @@
 
 use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FResult};
+use std::path::PathBuf;
 use rdkafka::error::KafkaError;
-use rdkafka::error::RDKafkaErrorCode;
+use brane_job::interface::SchemaVersion;
+use brane_shr::kafka::KafkaAssignError;
+use specifications::package::PackageRequirements;
 
 
 /***** ERRORS *****/
 /// Errors that occur during the main phase of the brane-drv package
 #[derive(Debug)]
 pub enum DriverError {
-    /// Could not create a Kafka client
-    KafkaClientError{ servers: String, err: KafkaError },
-    /// Could not get the Kafka client to try to add more topics
-    KafkaTopicsError{ topics: String, err: KafkaError },
-    /// Could not add the given topic (with a duplicate error already filtered out)
-    KafkaTopicError{ topic: String, err: RDKafkaErrorCode },
     /// Could not create a Kafka consumer
     KafkaConsumerError{ servers: String, id: String, err: KafkaError },
 
-    /// Could not get the Kafka commit offsets
-    KafkaGetOffsetError{ topic: String, err: KafkaError },
-    /// Could not update the Kafka commit offsets
-    KafkaSetOffsetError{ topic: String, err: KafkaError },
-    /// Could not commit the update to the Kafka commit offsets
-    KafkaSetOffsetsError{ topic: String, err: KafkaError },
+    /// Could not assign the partitions of the event topic to the consumer
+    KafkaAssignError{ err: KafkaAssignError },
+    /// Could not commit the offset of a processed event
+    KafkaCommitError{ topic: String, err: KafkaError },
 
     /// Error for when we failed to monitor events
     EventMonitorError{ err: KafkaError },
+    /// An event was encoded with a schema major version we cannot interpret
+    EventSchemaMismatch{ topic: String, partition: i32, offset: i64, key: String, version: SchemaVersion },
 }
 
 impl DriverError {
@@ -69,18 +66,184 @@ impl DriverError {
 impl Display for DriverError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
         match self {
-            DriverError::KafkaClientError{ servers, err }       => write!(f, "Could not create Kafka client with bootstrap servers '{}': {}", servers, err),
-            DriverError::KafkaTopicsError{ topics, err }        => write!(f, "Could not create new Kafka topics '{}': {}", topics, err),
-            DriverError::KafkaTopicError{ topic, err }          => write!(f, "Could not create Kafka topic '{}': {}", topic, err),
             DriverError::KafkaConsumerError{ servers, id, err } => write!(f, "Could not create Kafka consumer for ID '{}' with bootstrap servers '{}': {}", id, servers, err),
 
-            DriverError::KafkaGetOffsetError{ topic, err }  => write!(f, "Could not get offsets for topic '{}': {}", topic, err),
-            DriverError::KafkaSetOffsetError{ topic, err }  => write!(f, "Could not set offsets for topic '{}': {}", topic, err),
-            DriverError::KafkaSetOffsetsError{ topic, err } => write!(f, "Could not commit offsets for topic '{}': {}", topic, err),
+            DriverError::KafkaAssignError{ err }            => write!(f, "{}", err),
+            DriverError::KafkaCommitError{ topic, err }     => write!(f, "Could not commit offset of processed event in topic '{}': {}", topic, err),
 
             DriverError::EventMonitorError{ err } => write!(f, "Failed to monitor Kafka events: {}", err),
+            DriverError::EventSchemaMismatch{ topic, partition, offset, key, version } => write!(f, "Event in topic '{}' (partition: {}, offset: {}, key: {}) has schema version {}, but this brane-drv speaks version {}; dropping event ({})", topic, partition, offset, key, version, SchemaVersion::current(), version.migration_advice()),
         }
     }
 }
 
 impl Error for DriverError {}
+
+
+
+/// Errors that occur when persisting the state of sessions
+#[derive(Debug)]
+pub enum SessionError {
+    /// Could not create the directory that we persist sessions in
+    DirCreateError{ path: PathBuf, err: std::io::Error },
+    /// Could not read the directory that we persist sessions in
+    DirReadError{ path: PathBuf, err: std::io::Error },
+    /// Could not read a persisted session
+    FileReadError{ path: PathBuf, err: std::io::Error },
+    /// Could not parse a persisted session
+    FileParseError{ path: PathBuf, err: serde_json::Error },
+    /// Could not serialize a session
+    SerializeError{ uuid: String, err: serde_json::Error },
+    /// Could not write a persisted session
+    FileWriteError{ path: PathBuf, err: std::io::Error },
+    /// Could not remove the persisted file of a closed or expired session
+    FileRemoveError{ path: PathBuf, err: std::io::Error },
+}
+
+impl Display for SessionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            SessionError::DirCreateError{ path, err } => write!(f, "Could not create session directory '{}': {}", path.display(), err),
+            SessionError::DirReadError{ path, err }   => write!(f, "Could not read session directory '{}': {}", path.display(), err),
+            SessionError::FileReadError{ path, err }  => write!(f, "Could not read session file '{}': {}", path.display(), err),
+            SessionError::FileParseError{ path, err } => write!(f, "Could not parse session file '{}': {}", path.display(), err),
+            SessionError::SerializeError{ uuid, err } => write!(f, "Could not serialize session '{}': {}", uuid, err),
+            SessionError::FileWriteError{ path, err } => write!(f, "Could not write session file '{}': {}", path.display(), err),
+            SessionError::FileRemoveError{ path, err } => write!(f, "Could not remove session file '{}': {}", path.display(), err),
+        }
+    }
+}
+
+impl Error for SessionError {}
+
+
+
+/// Errors that occur when reading or writing the file-backed store of the call cache
+#[derive(Debug)]
+pub enum CallCacheError {
+    /// Could not create the directory that we store results in
+    DirCreateError{ path: PathBuf, err: std::io::Error },
+    /// Could not read a file with stored results
+    FileReadError{ path: PathBuf, err: std::io::Error },
+    /// Could not parse a file with stored results
+    FileParseError{ path: PathBuf, err: serde_json::Error },
+    /// Could not serialize the stored results of a package
+    SerializeError{ package: String, digest: String, err: serde_json::Error },
+    /// Could not write a file with stored results
+    FileWriteError{ path: PathBuf, err: std::io::Error },
+    /// The blocking task that used a store failed
+    TaskError{ err: tokio::task::JoinError },
+}
+
+impl Display for CallCacheError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            CallCacheError::DirCreateError{ path, err }             => write!(f, "Could not create call cache directory '{}': {}", path.display(), err),
+            CallCacheError::FileReadError{ path, err }              => write!(f, "Could not read call cache file '{}': {}", path.display(), err),
+            CallCacheError::FileParseError{ path, err }             => write!(f, "Could not parse call cache file '{}': {}", path.display(), err),
+            CallCacheError::SerializeError{ package, digest, err }  => write!(f, "Could not serialize cached results of package '{}' ({}): {}", package, digest, err),
+            CallCacheError::FileWriteError{ path, err }             => write!(f, "Could not write call cache file '{}': {}", path.display(), err),
+            CallCacheError::TaskError{ err }                        => write!(f, "Could not use call cache store: {}", err),
+        }
+    }
+}
+
+impl Error for CallCacheError {}
+
+
+
+/// Errors that occur when reporting the lineage of jobs to the API
+#[derive(Debug)]
+pub enum LineageError {
+    /// Could not send the record to the API (or read its response)
+    RequestError{ url: String, correlation_id: String, err: reqwest::Error },
+    /// The API responded with a non-success status code
+    RequestFailure{ url: String, correlation_id: String, status: reqwest::StatusCode },
+    /// The API refused the mutation
+    MutationError{ url: String, correlation_id: String, errors: String },
+}
+
+impl Display for LineageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            LineageError::RequestError{ url, correlation_id, err }      => write!(f, "Could not send lineage of job '{}' to '{}': {}", correlation_id, url, err),
+            LineageError::RequestFailure{ url, correlation_id, status } => write!(f, "Could not send lineage of job '{}' to '{}': API responded with {}", correlation_id, url, status),
+            LineageError::MutationError{ url, correlation_id, errors }  => write!(f, "API at '{}' refused lineage of job '{}': {}", url, correlation_id, errors),
+        }
+    }
+}
+
+impl Error for LineageError {}
+
+
+
+/// Errors that occur when setting up the authentication of the gRPC API
+#[derive(Debug)]
+pub enum AuthError {
+    /// Could not read the file with the tokens of clients
+    TokenFileError{ path: PathBuf, err: std::io::Error },
+    /// A token contains characters that cannot be sent as gRPC metadata
+    IllegalToken,
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            AuthError::TokenFileError{ path, err } => write!(f, "Could not read token file '{}': {}", path.display(), err),
+            AuthError::IllegalToken                => write!(f, "Token contains characters that cannot be sent to the driver (only visible ASCII characters are allowed)"),
+        }
+    }
+}
+
+impl Error for AuthError {}
+
+
+
+/// Errors that occur when picking a location for a call that doesn't name one
+#[derive(Debug)]
+pub enum PlannerError {
+    /// Could not read the locations from the infrastructure file
+    InfrastructureError{ err: brane_cfg::infrastructure::InfrastructureError },
+    /// The infrastructure file has no locations to pick from
+    NoLocations,
+    /// No location satisfies the requirements of the package; lists what every location lacks
+    NoMatchingLocation{ requirements: PackageRequirements, unmet: Vec<(String, Vec<String>)> },
+}
+
+impl Display for PlannerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            PlannerError::InfrastructureError{ err }               => write!(f, "Could not read the locations in the infrastructure file: {}", err),
+            PlannerError::NoLocations                              => write!(f, "There are no locations in the infrastructure file"),
+            PlannerError::NoMatchingLocation{ requirements, unmet } => {
+                write!(f, "No location satisfies the requirements of the package ({}):", requirements)?;
+                for (location, unmet) in unmet { write!(f, "\n - '{}' lacks {}", location, unmet.join(", "))?; }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl Error for PlannerError {}
+
+
+
+/// Errors that occur when a client sends a statement to a session that other clients share
+#[derive(Debug)]
+pub enum MultiplexError {
+    /// The session is still running another statement, and the driver rejects statements instead of queueing them
+    SessionBusy{ uuid: String, running: u64 },
+    /// The policy for concurrent statements is not one we know
+    UnknownPolicy{ raw: String },
+}
+
+impl Display for MultiplexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            MultiplexError::SessionBusy{ uuid, running } => write!(f, "Session '{}' is still running statement #{}; try again once it has finished", uuid, running),
+            MultiplexError::UnknownPolicy{ raw }         => write!(f, "Unknown policy '{}' for concurrent statements (expected 'queue' or 'reject')", raw),
+        }
+    }
+}
+
+impl Error for MultiplexError {}
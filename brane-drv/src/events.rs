@@ -0,0 +1,463 @@
+/* EVENTS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   14 Oct 2026, 20:11:37
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Processes the events that brane-job sends to the driver, updating the
+ *   maps that the executor uses to follow its jobs. Kept separate from the
+ *   Kafka consumer so that replaying events after a restart goes through
+ *   exactly the same code. Log, CreateRetrying and Pulling events are not
+ *   state changes; they are forwarded to the client of the session that
+ *   waits for the job. Payloads that were too large for Kafka arrive as a
+ *   reference to a file that brane-job shares with us, which is read (and
+ *   removed) before the event is processed.
+**/
+
+use brane_job::interface::{CreateRetryInfo, Event, EventKind, FailureResult, PayloadRef, PullProgress};
+use brane_job::logs::LOG_CATEGORY_STDERR;
+use brane_shr::jobs::JobStatus;
+use dashmap::DashMap;
+use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::executor::ActiveJob;
+use crate::grpc;
+use crate::metrics;
+use crate::outputs::{JobOutput, JobOutputs};
+
+
+/***** CONSTANTS *****/
+/// The number of finished jobs that the event monitor remembers, so that it can ignore the events that arrive for them late.
+pub const FINISHED_JOBS_CAPACITY: usize = 10000;
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Remembers the jobs that most recently reached a final state. Only the given number of jobs is kept; if there are more, the oldest are forgotten first.
+#[derive(Debug)]
+pub struct FinishedJobs {
+    /// The maximum number of jobs that we remember.
+    capacity : usize,
+    /// The jobs, together with the order in which they finished.
+    inner    : Mutex<(HashSet<String>, VecDeque<String>)>,
+}
+
+impl FinishedJobs {
+    /// Constructor for the FinishedJobs.
+    /// 
+    /// **Arguments**
+    ///  * `capacity`: The maximum number of jobs to remember.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner : Mutex::new((HashSet::new(), VecDeque::with_capacity(capacity))),
+        }
+    }
+
+
+
+    /// Notes that the given job has finished, forgetting the oldest finished job if we're at capacity.
+    /// 
+    /// **Arguments**
+    ///  * `correlation_id`: The ID of the job that finished.
+    pub fn insert(&self, correlation_id: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        let (jobs, order) = &mut *inner;
+        if !jobs.insert(correlation_id.to_string()) { return; }
+        order.push_back(correlation_id.to_string());
+
+        while order.len() > self.capacity {
+            if let Some(oldest) = order.pop_front() { jobs.remove(&oldest); }
+        }
+    }
+
+    /// Returns whether the given job has finished (as far as we remember).
+    /// 
+    /// **Arguments**
+    ///  * `correlation_id`: The ID of the job to check.
+    #[inline]
+    pub fn contains(&self, correlation_id: &str) -> bool {
+        self.inner.lock().unwrap().0.contains(correlation_id)
+    }
+}
+
+
+
+/// Collects the state that the event monitor updates for every incoming event.
+#[derive(Clone, Debug)]
+pub struct EventMonitor {
+    /// The list of states we use to keep track at what state what running job is.
+    pub states      : Arc<DashMap<String, JobStatus>>,
+    /// The list of times we last saw a heartbeat for a given job.
+    pub heartbeats  : Arc<DashMap<String, SystemTime>>,
+    /// The list of locations where our jobs are running.
+    pub locations   : Arc<DashMap<String, String>>,
+    /// The (bounded) list of outputs of failed and finished jobs, which clients may query later.
+    pub outputs     : Arc<JobOutputs>,
+    /// The list of jobs that sessions are currently waiting for, which we use to route the output of jobs to their clients.
+    pub active      : Arc<DashMap<String, ActiveJob>>,
+    /// The order of the latest state change we processed for every job, used to drop events that arrive (or are replayed) out of order.
+    pub orders      : Arc<DashMap<String, u32>>,
+    /// The jobs that reached a final state, whose late events we ignore so they don't bring back the state that the executor already cleaned up.
+    pub finished    : Arc<FinishedJobs>,
+    /// The directory that brane-job writes payloads that are too large for an event to. If None, such payloads are not read at all.
+    pub payload_dir : Option<PathBuf>,
+}
+
+impl EventMonitor {
+    /// Constructor for the EventMonitor.
+    /// 
+    /// **Arguments**
+    ///  * `states`: The list of states we use to keep track at what state what running job is.
+    ///  * `heartbeats`: The list of times we last saw a heartbeat for a given job.
+    ///  * `locations`: The list of locations where our jobs are running.
+    ///  * `outputs`: The (bounded) list of outputs of failed and finished jobs.
+    ///  * `active`: The list of jobs that sessions are currently waiting for.
+    ///  * `orders`: The order of the latest state change we processed for every job.
+    pub fn new(
+        states: Arc<DashMap<String, JobStatus>>,
+        heartbeats: Arc<DashMap<String, SystemTime>>,
+        locations: Arc<DashMap<String, String>>,
+        outputs: Arc<JobOutputs>,
+        active: Arc<DashMap<String, ActiveJob>>,
+        orders: Arc<DashMap<String, u32>>,
+    ) -> Self {
+        Self {
+            states,
+            heartbeats,
+            locations,
+            outputs,
+            active,
+            orders,
+            finished    : Arc::new(FinishedJobs::new(FINISHED_JOBS_CAPACITY)),
+            payload_dir : None,
+        }
+    }
+
+    /// Sets the directory that payloads which are too large for an event are read from. References to files outside of it are refused.
+    /// 
+    /// **Arguments**
+    ///  * `payload_dir`: The directory that brane-job shares with us.
+    /// 
+    /// **Returns**  
+    /// The same EventMonitor, for chaining.
+    pub fn with_payload_dir(mut self, payload_dir: PathBuf) -> Self {
+        self.payload_dir = Some(payload_dir);
+        self
+    }
+
+
+
+    /// Processes a single event, updating the state of the job it belongs to.
+    /// 
+    /// State changes that are older than the last one we processed for the same job (as per their `order` field) are dropped, so that e.g. a delayed Started event cannot undo a Completed one. Heartbeats are not state changes, and are never dropped while the job runs. Once a job has reached a final state, any state changes and heartbeats that arrive for it later are dropped as well, since the executor forgets the state of the job once it has its result. Processing the same event twice is harmless, which is what allows uncommitted events to be replayed after a restart.
+    /// 
+    /// **Arguments**
+    ///  * `event`: The Event to process.
+    /// 
+    /// **Returns**  
+    /// Whether the event has been applied (true) or dropped (false).
+    pub fn handle(&self, event: &Event) -> bool {
+        let kind = match EventKind::from_i32(event.kind) {
+            Some(kind) => kind,
+            None       => { warn!("Ignoring event '{}' with unknown kind {}", event.identifier, event.kind); return false; }
+        };
+        let correlation_id = event.identifier.split('-').next().unwrap_or_default().to_string();
+
+        // Output does not change the state of the job, so it's not subject to the ordering below either
+        if kind == EventKind::Log { return self.forward_log(&correlation_id, event); }
+        // Neither does brane-job trying to create the job again
+        if kind == EventKind::CreateRetrying { return self.forward_create_retry(&correlation_id, event); }
+        // Or pulling the image of the job
+        if kind == EventKind::Pulling { return self.forward_pull_progress(&correlation_id, event); }
+        // Whatever arrives after the job is done would only bring back the state that the executor cleaned up
+        if self.finished.contains(&correlation_id) {
+            debug!("Dropping late {} event for finished job '{}'", kind, correlation_id);
+            return false;
+        }
+        // Heartbeats only say the job is alive, whatever state it's in, and may come from a different sender than the state changes
+        if kind == EventKind::Heartbeat {
+            self.heartbeats.insert(correlation_id, SystemTime::now());
+            return true;
+        }
+
+        // Drop the event if we've already seen a later one for this job
+        {
+            let mut last_order = self.orders.entry(correlation_id.clone()).or_insert(event.order);
+            if event.order < *last_order {
+                debug!("Dropping stale {} event for job '{}' (order {} < {})", kind, correlation_id, event.order, *last_order);
+                return false;
+            }
+            *last_order = event.order;
+        }
+
+        // Read the payload from its file if brane-job had to write it there
+        let payload = match PayloadRef::from_payload(&event.payload) {
+            Some(reference) => match self.read_payload(&reference) {
+                Ok(payload) => Cow::Owned(payload),
+                Err(err)    => {
+                    warn!("Could not read the payload of {} event for job '{}' from '{}': {}", kind, correlation_id, reference.path, err);
+                    let err = format!("Could not read the result of the job from '{}' (is it shared with brane-job?): {}", reference.path, err);
+                    self.states.insert(correlation_id.clone(), JobStatus::CompleteFailed{ err });
+                    self.finished.insert(&correlation_id);
+                    metrics::JOB_STATES.with_label_values(&[&format!("{:?}", EventKind::CompleteFailed)]).inc();
+                    return true;
+                },
+            },
+            None => Cow::Borrowed(event.payload.as_slice()),
+        };
+
+        // Remember the jobs that are done once we've noted their final state
+        if matches!(kind, EventKind::CreateFailed | EventKind::InitializeFailed | EventKind::StartFailed | EventKind::CompleteFailed | EventKind::DecodeFailed | EventKind::StoreFailed | EventKind::Failed | EventKind::Stopped | EventKind::Finished) {
+            self.finished.insert(&correlation_id);
+        }
+
+        // Just collect everything we see; don't reason about it yet
+        match kind {
+            EventKind::CreateFailed => {
+                // Decode the payload as error
+                let err = String::from_utf8_lossy(&payload).to_string();
+                // Note the state with what went wrong
+                self.states.insert(correlation_id, JobStatus::CreateFailed{ err });
+            }
+            EventKind::Created => {
+                // The container has been created, so note it
+                self.states.insert(correlation_id.clone(), JobStatus::Created);
+                self.locations.insert(correlation_id, event.location.clone());
+            }
+
+            EventKind::Ready => {
+                // Update the state
+                self.states.insert(correlation_id, JobStatus::Ready);
+            }
+
+            EventKind::InitializeFailed => {
+                // Decode the payload as error
+                let err = String::from_utf8_lossy(&payload).to_string();
+                // Update the state
+                self.states.insert(correlation_id, JobStatus::InitializeFailed{ err });
+            }
+            EventKind::Initialized => {
+                // Update the state
+                self.states.insert(correlation_id, JobStatus::Initialized);
+            }
+
+            EventKind::StartFailed => {
+                // Decode the payload as error
+                let err = String::from_utf8_lossy(&payload).to_string();
+                // Update the state
+                self.states.insert(correlation_id, JobStatus::StartFailed{ err });
+            }
+            EventKind::Started => {
+                // Update the state
+                self.states.insert(correlation_id, JobStatus::Started);
+            }
+
+            EventKind::CompleteFailed => {
+                // Decode the payload as error
+                let err = String::from_utf8_lossy(&payload).to_string();
+                // Update the state
+                self.states.insert(correlation_id, JobStatus::CompleteFailed{ err });
+            }
+            EventKind::Completed => {
+                // Update the state
+                self.states.insert(correlation_id, JobStatus::Completed);
+            }
+
+            EventKind::DecodeFailed => {
+                // Decode the payload as error
+                let err = String::from_utf8_lossy(&payload).to_string();
+                // Update the state
+                self.states.insert(correlation_id, JobStatus::DecodeFailed{ err });
+            }
+            EventKind::StoreFailed => {
+                // Decode the payload as error
+                let err = String::from_utf8_lossy(&payload).to_string();
+                // Update the state
+                self.states.insert(correlation_id, JobStatus::StoreFailed{ err });
+            }
+            EventKind::Failed => {
+                // Decode the result as a JSON code/stdout/stderr pair
+                let payload = String::from_utf8_lossy(&payload).to_string();
+                // Only check whether it parses, so that a mangled payload still reaches the user as raw text instead of as a deserialization error
+                self.outputs.insert(correlation_id.clone(), JobOutput::Failed{ res: payload.clone() });
+                if serde_json::from_str::<FailureResult>(&payload).is_ok() {
+                    self.states.insert(correlation_id, JobStatus::Failed{ res: payload });
+                } else {
+                    warn!("Output of failed job '{}' is not a valid code/stdout/stderr triplet; passing it on as-is", correlation_id);
+                    self.states.insert(correlation_id, JobStatus::FailedRaw{ res: payload });
+                }
+            }
+            EventKind::Stopped => {
+                // Decode the payload as a signal name
+                let signal = String::from_utf8_lossy(&payload).to_string();
+                // Update the state
+                self.states.insert(correlation_id, JobStatus::Stopped{ signal });
+            }
+            EventKind::Finished => {
+                // Decode the payload as JSON value description
+                let payload = String::from_utf8_lossy(&payload).to_string();
+                // Do not parse the JSON, as this is error-prone and we want to treat errors in the executor
+                self.outputs.insert(correlation_id.clone(), JobOutput::Finished{ res: payload.clone() });
+                self.states.insert(correlation_id, JobStatus::Finished{ res: payload });
+            }
+
+            EventKind::Log | EventKind::CreateRetrying | EventKind::Pulling | EventKind::Heartbeat => unreachable!(),
+            EventKind::Unknown | EventKind::Connected | EventKind::Disconnected => {
+                warn!("Ignoring {} event for job '{}'", kind, correlation_id);
+                return false;
+            }
+        }
+        metrics::JOB_STATES.with_label_values(&[&format!("{:?}", kind)]).inc();
+
+        true
+    }
+
+    /// Reads the payload that brane-job wrote to a file in our payload directory, removing the file once it has been read.
+    /// 
+    /// **Arguments**
+    ///  * `reference`: The PayloadRef that the event carried instead of its payload.
+    /// 
+    /// **Returns**  
+    /// The payload, or an std::io::Error if it could not be read (which includes the case where we have no payload directory at all).
+    fn read_payload(&self, reference: &PayloadRef) -> Result<Vec<u8>, std::io::Error> {
+        match &self.payload_dir {
+            Some(dir) => reference.take(dir),
+            None      => Err(std::io::Error::new(std::io::ErrorKind::NotFound, "the driver has no payload directory (see '--payload-dir')")),
+        }
+    }
+
+    /// Forwards the output in a Log event to the client of the session that is waiting for the job.
+    /// 
+    /// The output is dropped if no session waits for the job (anymore), or if the client cannot keep up; it's still part of the job's result once it finishes. brane-job sends all output of a job with the same key, so the chunks arrive (and are forwarded) in order.
+    /// 
+    /// **Arguments**
+    ///  * `correlation_id`: The ID of the job that sent the output.
+    ///  * `event`: The Log event with the output.
+    /// 
+    /// **Returns**  
+    /// Whether the output has been forwarded.
+    fn forward_log(&self, correlation_id: &str, event: &Event) -> bool {
+        let client_tx = match self.active.get(correlation_id) {
+            Some(job) => job.client_tx.clone(),
+            None      => { debug!("Dropping output of job '{}', as no session is waiting for it", correlation_id); return false; }
+        };
+
+        // brane-job only splits output between characters (see `logs::LogBuffer`), so every chunk decodes on its own. The client prints every reply on its own line.
+        let text = String::from_utf8_lossy(&event.payload);
+        let text = text.strip_suffix('\n').unwrap_or(&text).to_string();
+        let stderr = event.category == LOG_CATEGORY_STDERR;
+        let reply = grpc::ExecuteReply {
+            close: false,
+            debug: None,
+            stderr: if stderr { Some(text.clone()) } else { None },
+            stdout: if stderr { None } else { Some(text) },
+            trace: None,
+            cached: None,
+            diff: None,
+            sequence: None,
+            client: None,
+            input: None,
+        };
+
+        // Don't wait on slow clients, as that would hold up the events of all other jobs
+        if let Err(err) = client_tx.try_send(Ok(reply)) {
+            debug!("Dropping output of job '{}': {}", correlation_id, err);
+            return false;
+        }
+        true
+    }
+
+    /// Tells the client of the session that is waiting for the job that brane-job will try to create it again, so the user knows why it takes a while.
+    /// 
+    /// **Arguments**
+    ///  * `correlation_id`: The ID of the job that is being retried.
+    ///  * `event`: The CreateRetrying event with the CreateRetryInfo.
+    /// 
+    /// **Returns**  
+    /// Whether the notice has been forwarded.
+    fn forward_create_retry(&self, correlation_id: &str, event: &Event) -> bool {
+        let info: CreateRetryInfo = match serde_json::from_slice(&event.payload) {
+            Ok(info) => info,
+            Err(err) => { warn!("Ignoring CreateRetrying event for job '{}' with invalid payload: {}", correlation_id, err); return false; }
+        };
+        info!("Job '{}' could not be created at location '{}' ({}); retrying ({}/{})", correlation_id, event.location, info.reason, info.attempt, info.max_retries);
+
+        let client_tx = match self.active.get(correlation_id) {
+            Some(job) => job.client_tx.clone(),
+            None      => { debug!("Not forwarding retry of job '{}', as no session is waiting for it", correlation_id); return false; }
+        };
+        let reply = grpc::ExecuteReply {
+            close: false,
+            debug: None,
+            stderr: Some(format!("Could not create job '{}' at location '{}' ({}); retrying ({}/{})...", correlation_id, event.location, info.reason, info.attempt, info.max_retries)),
+            stdout: None,
+            trace: None,
+            cached: None,
+            diff: None,
+            sequence: None,
+            client: None,
+            input: None,
+        };
+
+        // Like output, this is not worth waiting on slow clients for
+        if let Err(err) = client_tx.try_send(Ok(reply)) {
+            debug!("Not forwarding retry of job '{}': {}", correlation_id, err);
+            return false;
+        }
+        true
+    }
+
+    /// Tells the client of the session that is waiting for the job how far the location is with pulling its image.
+    /// 
+    /// Because the job is clearly still being worked on, this also counts as a heartbeat, so that big images don't make the job time out before it is created.
+    /// 
+    /// **Arguments**
+    ///  * `correlation_id`: The ID of the job whose image is being pulled.
+    ///  * `event`: The Pulling event with the PullProgress.
+    /// 
+    /// **Returns**  
+    /// Whether the progress has been forwarded.
+    fn forward_pull_progress(&self, correlation_id: &str, event: &Event) -> bool {
+        let progress: PullProgress = match serde_json::from_slice(&event.payload) {
+            Ok(progress) => progress,
+            Err(err)     => { warn!("Ignoring Pulling event for job '{}' with invalid payload: {}", correlation_id, err); return false; }
+        };
+        self.heartbeats.insert(correlation_id.to_string(), SystemTime::now());
+
+        let client_tx = match self.active.get(correlation_id) {
+            Some(job) => job.client_tx.clone(),
+            None      => { debug!("Not forwarding pull progress of job '{}', as no session is waiting for it", correlation_id); return false; }
+        };
+        let reply = grpc::ExecuteReply {
+            close: false,
+            debug: Some(format!("Job '{}' at location '{}' is {}", correlation_id, event.location, progress)),
+            stderr: None,
+            stdout: None,
+            trace: None,
+            cached: None,
+            diff: None,
+            sequence: None,
+            client: None,
+            input: None,
+        };
+
+        // Like output, this is not worth waiting on slow clients for
+        if let Err(err) = client_tx.try_send(Ok(reply)) {
+            debug!("Not forwarding pull progress of job '{}': {}", correlation_id, err);
+            return false;
+        }
+        true
+    }
+}
@@ -1,12 +1,23 @@
+use crate::calls::{CallCache, CallKey};
+use crate::client::{ClientError, ClientSender};
 use crate::grpc;
+use crate::limits::JobLimits;
+use crate::lineage::{LineageOutcome, LineageRecord, LineageReporter};
+use crate::metrics;
+use crate::planner;
+use crate::sessions::{call_key, PendingJob, SessionStore};
 use anyhow::Result;
 use async_trait::async_trait;
-use brane_bvm::executor::{VmExecutor, ExecutorError};
+use brane_bvm::executor::{call_in_batches, ExecutorError, ServiceState, VmExecutor, DEFAULT_MAP_CONCURRENCY};
 use brane_cfg::Infrastructure;
-use brane_job::interface::{Command, CommandKind, FailureResult};
+use brane_cfg::infrastructure::{Location, LocationTimeouts};
+use brane_bvm::builtins::BuiltinClass;
+use brane_job::naming::array_element_id;
+use brane_job::interface::{session_data_dir, Artifact, CallStats, Command, CommandKind, FailureResult, SESSION_DATA_ENV};
 use brane_shr::jobs::JobStatus;
 use bytes::BytesMut;
 use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
 use prost::Message as _;
 use rand::distributions::Alphanumeric;
 use rand::{self, Rng};
@@ -16,14 +27,14 @@ use rdkafka::{
     util::Timeout,
 };
 use specifications::common::{FunctionExt, Value};
+use specifications::version::Version;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::SystemTime;
 use std::{collections::HashMap, time::Duration};
-use tokio::sync::mpsc::Sender;
-use tonic::Status;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
 
@@ -41,6 +52,11 @@ const DEFAULT_HEARTBEAT_TIMEOUT   : u128 = 10 * 1000;
 /// Determines the timeout (in milliseconds) we give the job between completing and returning a result
 const DEFAULT_RESULT_TIMEOUT      : u128 = 30 * 1000;
 
+/// The most bytes that the encoded arguments of a job array may take, as they end up in a single argument of the command line (which Linux caps at 128 KiB) or of the pod spec
+pub const MAX_ARRAY_ARGUMENTS : usize = 64 * 1024;
+/// How often the elements of a job array that are still queued get the latest heartbeat of the others (see `share_heartbeats()`)
+const ARRAY_HEARTBEAT_INTERVAL : Duration = Duration::from_secs(1);
+
 
 
 
@@ -50,64 +66,96 @@ const DEFAULT_RESULT_TIMEOUT      : u128 = 30 * 1000;
 #[derive(Debug)]
 enum ScheduleError {
     /// The Job node did not report 'created' or 'created failed' within time
-    JobCreatedTimeout{ correlation_id: String },
+    JobCreatedTimeout{ correlation_id: String, timeout_ms: u128 },
     /// The Job node returned a CreateFailed event
     JobCreateFailed{ correlation_id: String, err: String },
 
     /// The Job with the given correlation ID failed to emit a 'Ready' within the timeout
-    JobReadyTimeout{ correlation_id: String },
+    JobReadyTimeout{ correlation_id: String, timeout_ms: u128 },
     /// The Job with the given correlation ID failed to emit an 'Initialized' within the timeout
-    JobInitializedTimeout{ correlation_id: String },
+    JobInitializedTimeout{ correlation_id: String, timeout_ms: u128 },
     /// The Job node returned an InitializeFailed event
     JobInitializeFailed{ correlation_id: String, err: String },
     /// The Job with the given correlation ID failed to emit a 'Started' within the timeout
-    JobStartedTimeout{ correlation_id: String },
+    JobStartedTimeout{ correlation_id: String, timeout_ms: u128 },
     /// The Job node returned a StartFailed event
     JobStartFailed{ correlation_id: String, err: String },
     /// The Job with the given correlation ID failed to emit a 'Heartbeat' within the timeout
-    JobHeartbeatTimeout{ correlation_id: String },
+    JobHeartbeatTimeout{ correlation_id: String, timeout_ms: u128 },
     /// The Job node returned a CompleteFailed event
     JobCompleteFailed{ correlation_id: String, err: String },
 
     /// The job didn't respond stopped, failed or finished in time
-    JobResultTimeout{ correlation_id: String },
+    JobResultTimeout{ correlation_id: String, timeout_ms: u128 },
     /// Could not decode the output of the job
     JobDecodeFailed{ correlation_id: String, err: String },
+    /// Could not store the outputs of the job as artifacts
+    JobStoreFailed{ correlation_id: String, err: String },
     /// The job was stopped
     JobStopped{ correlation_id: String, signal: String },
     /// The job failed by itself
     JobFailed{ correlation_id: String, code: i32, stdout: String, stderr: String },
+    /// The job failed by itself, but its output could not be parsed (so we don't know its exit code either)
+    JobFailedRaw{ correlation_id: String, output: String },
 
-    /// Could not deserialize the output from a failed job
-    FailedDeserializeError{ output: String, err: serde_json::Error },
     /// Could not deserialize the output from a finished job
     FinishedDeserializeError{ output: String, err: serde_json::Error },
+
+    /// The job was cancelled by the client
+    Cancelled{ correlation_id: String },
+
+    /// The job was scheduled before the driver restarted, longer ago than the horizon, and nothing is known about it anymore
+    JobLost{ correlation_id: String, age: u64, horizon: u64 },
+}
+
+impl ScheduleError {
+    /// Returns the name of the error if it's a timeout, which is used as label in the timeout metric.
+    fn timeout_name(&self) -> Option<&'static str> {
+        match self {
+            ScheduleError::JobCreatedTimeout{ .. }     => Some("JobCreatedTimeout"),
+            ScheduleError::JobReadyTimeout{ .. }       => Some("JobReadyTimeout"),
+            ScheduleError::JobInitializedTimeout{ .. } => Some("JobInitializedTimeout"),
+            ScheduleError::JobStartedTimeout{ .. }     => Some("JobStartedTimeout"),
+            ScheduleError::JobHeartbeatTimeout{ .. }   => Some("JobHeartbeatTimeout"),
+            ScheduleError::JobResultTimeout{ .. }      => Some("JobResultTimeout"),
+            _                                          => None,
+        }
+    }
 }
 
 impl std::fmt::Display for ScheduleError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ScheduleError::JobCreatedTimeout{ correlation_id }    => write!(f, "Job node failed to create job '{}' within {} seconds (is the Job node online?)", correlation_id, DEFAULT_CREATED_TIMEOUT / 1000),
-            ScheduleError::JobCreateFailed{ correlation_id, err } => write!(f, "Could not create job '{}': {}", correlation_id, err),
-
-            ScheduleError::JobReadyTimeout{ correlation_id }          => write!(f, "Job '{}' failed to report alive within {} seconds", correlation_id, DEFAULT_READY_TIMEOUT / 1000),
-            ScheduleError::JobInitializedTimeout{ correlation_id }    => write!(f, "Job '{}' failed to prepare running within {} seconds", correlation_id, DEFAULT_INITIALIZED_TIMEOUT / 1000),
-            ScheduleError::JobInitializeFailed{ correlation_id, err } => write!(f, "Could not initialize job '{}': {}", correlation_id, err),
-            ScheduleError::JobStartedTimeout{ correlation_id }        => write!(f, "Job '{}' failed to start running within {} seconds", correlation_id, DEFAULT_STARTED_TIMEOUT / 1000),
-            ScheduleError::JobStartFailed{ correlation_id, err }      => write!(f, "Could not start job '{}': {}", correlation_id, err),
-            ScheduleError::JobHeartbeatTimeout{ correlation_id }      => write!(f, "Job '{}' didn't send a heartbeat for {} seconds; considering it dead", correlation_id, DEFAULT_HEARTBEAT_TIMEOUT / 1000),
-            ScheduleError::JobCompleteFailed{ correlation_id, err }   => write!(f, "Could not complete job '{}': {}", correlation_id, err),
-
-            ScheduleError::JobResultTimeout{ correlation_id }                => write!(f, "Job '{}' didn't send result within {} seconds", correlation_id, DEFAULT_RESULT_TIMEOUT / 1000),
+            ScheduleError::JobCreatedTimeout{ correlation_id, timeout_ms } => write!(f, "Job node failed to create job '{}' within {} seconds (is the Job node online?)", correlation_id, timeout_ms / 1000),
+            ScheduleError::JobCreateFailed{ correlation_id, err }          => write!(f, "Could not create job '{}': {}", correlation_id, err),
+
+            ScheduleError::JobReadyTimeout{ correlation_id, timeout_ms }       => write!(f, "Job '{}' failed to report alive within {} seconds", correlation_id, timeout_ms / 1000),
+            ScheduleError::JobInitializedTimeout{ correlation_id, timeout_ms } => write!(f, "Job '{}' failed to prepare running within {} seconds", correlation_id, timeout_ms / 1000),
+            ScheduleError::JobInitializeFailed{ correlation_id, err }          => write!(f, "Could not initialize job '{}': {}", correlation_id, err),
+            ScheduleError::JobStartedTimeout{ correlation_id, timeout_ms }     => write!(f, "Job '{}' failed to start running within {} seconds", correlation_id, timeout_ms / 1000),
+            ScheduleError::JobStartFailed{ correlation_id, err }               => write!(f, "Could not start job '{}': {}", correlation_id, err),
+            ScheduleError::JobHeartbeatTimeout{ correlation_id, timeout_ms }   => write!(f, "Job '{}' didn't send a heartbeat for {} seconds; considering it dead", correlation_id, timeout_ms / 1000),
+            ScheduleError::JobCompleteFailed{ correlation_id, err }            => write!(f, "Could not complete job '{}': {}", correlation_id, err),
+
+            ScheduleError::JobResultTimeout{ correlation_id, timeout_ms }    => write!(f, "Job '{}' didn't send result within {} seconds", correlation_id, timeout_ms / 1000),
             ScheduleError::JobDecodeFailed{ correlation_id, err }            => write!(f, "Could not decode output of job '{}': {}", correlation_id, err),
+            ScheduleError::JobStoreFailed{ correlation_id, err }             => write!(f, "Could not store artifacts of job '{}': {}", correlation_id, err),
             ScheduleError::JobStopped{ correlation_id, signal }              => write!(f, "Job '{}' failed because it was stopped externally (signal {})", correlation_id, signal),
             ScheduleError::JobFailed{ correlation_id, code, stdout, stderr } => {
                 let separator = (0..80).map(|_| '-').collect::<String>();
                 write!(f, "Job '{}' failed by returning non-zero exit code {}:\nstdout:\n{}\n{}\n{}\n\nstderr:\n{}\n{}\n{}\n\n", correlation_id, code, separator, stdout, separator, separator, stderr, separator)
             },
 
-            ScheduleError::FailedDeserializeError{ output, err }   => write!(f, "Could not deserialize '{}' as a valid code/stdout/stderr triplet: {}", output, err),
+            ScheduleError::JobFailedRaw{ correlation_id, output }            => {
+                let separator = (0..80).map(|_| '-').collect::<String>();
+                write!(f, "Job '{}' failed with an unknown exit code; its raw output is:\n{}\n{}\n{}\n\n", correlation_id, separator, output, separator)
+            },
+
             ScheduleError::FinishedDeserializeError{ output, err } => write!(f, "Could not deserialize '{}' as a valid Value: {}", output, err),
+
+            ScheduleError::Cancelled{ correlation_id } => write!(f, "Job '{}' was cancelled", correlation_id),
+
+            ScheduleError::JobLost{ correlation_id, age, horizon } => write!(f, "Job '{}' was scheduled {} seconds ago, before the driver restarted, and no events of it are known; as that is longer than the horizon of {} seconds, it is considered lost", correlation_id, age, horizon),
         }
     }
 }
@@ -118,6 +166,174 @@ impl std::error::Error for ScheduleError {}
 
 
 
+/***** LIBRARY STRUCTS *****/
+/// Keeps track of a job that a session is currently waiting for.
+#[derive(Clone, Debug)]
+pub struct ActiveJob {
+    /// The session that scheduled the job
+    pub session_uuid : String,
+    /// Whether the job has been cancelled (and a Stop command has been emitted for it)
+    pub cancelled    : bool,
+    /// The channel to the session's client, where we forward the output the job streams while it runs
+    pub client_tx    : ClientSender,
+}
+
+
+
+/// A job that a session was waiting for when the driver restarted, and that we wait for again since the session reattached.
+#[derive(Debug)]
+pub struct ResumedJob {
+    /// The job as the session persisted it
+    pub job        : PendingJob,
+    /// The session that scheduled the job
+    session_uuid   : String,
+    /// The resumed wait for the job to finish
+    handle         : JoinHandle<Result<Value, ScheduleError>>,
+}
+
+impl ResumedJob {
+    /// Waits until the job has finished (or failed, or is lost).
+    /// 
+    /// **Returns**  
+    /// The job's return value on success, or an ExecutorError describing why the call failed otherwise.
+    pub async fn wait(self) -> Result<Value, ExecutorError> {
+        let job = self.job;
+        match self.handle.await {
+            Ok(res)  => res.map_err(|err| call_error(job.function, job.package, job.version, err)),
+            Err(err) => Err(ExecutorError::ExternalCallError{ name: job.function, package: job.package, version: job.version, err: format!("Could not wait for resumed job '{}': {}", job.correlation_id, err) }),
+        }
+    }
+}
+
+
+
+/// The timeouts (in milliseconds) that we give a job for every stage of its lifetime.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct JobTimeouts {
+    /// How long we wait until we hear the job has been created
+    pub created     : u128,
+    /// How long we wait for the job's first event
+    pub ready       : u128,
+    /// How long we wait for the job to initialize its directories
+    pub initialized : u128,
+    /// How long we wait for the job to actually start running
+    pub started     : u128,
+    /// How long we wait at most in between heartbeats
+    pub heartbeat   : u128,
+    /// How long we wait between the job completing and returning a result
+    pub result      : u128,
+}
+
+impl Default for JobTimeouts {
+    fn default() -> Self {
+        Self {
+            created     : DEFAULT_CREATED_TIMEOUT,
+            ready       : DEFAULT_READY_TIMEOUT,
+            initialized : DEFAULT_INITIALIZED_TIMEOUT,
+            started     : DEFAULT_STARTED_TIMEOUT,
+            heartbeat   : DEFAULT_HEARTBEAT_TIMEOUT,
+            result      : DEFAULT_RESULT_TIMEOUT,
+        }
+    }
+}
+
+impl JobTimeouts {
+    /// Returns the default timeouts, overridden by those that the given location sets in the infrastructure file.
+    /// 
+    /// **Arguments**
+    ///  * `overrides`: The location's timeouts (in seconds). Those that are not given keep their default.
+    pub fn with_overrides(overrides: &LocationTimeouts) -> Self {
+        let defaults = Self::default();
+        let ms = |value: Option<u64>, default: u128| value.map(|secs| secs as u128 * 1000).unwrap_or(default);
+        Self {
+            created     : ms(overrides.created, defaults.created),
+            ready       : ms(overrides.ready, defaults.ready),
+            initialized : defaults.initialized,
+            started     : ms(overrides.started, defaults.started),
+            heartbeat   : ms(overrides.heartbeat, defaults.heartbeat),
+            result      : ms(overrides.result, defaults.result),
+        }
+    }
+
+    /// Returns the timeout for a job that was last seen in the given state.
+    /// 
+    /// **Arguments**
+    ///  * `state`: The last state we saw the job in. Must be one in which we are still waiting for the job.
+    /// 
+    /// **Returns**  
+    /// The time (in milliseconds) that the job gets to leave this state.
+    pub fn for_state(&self, state: &JobStatus) -> u128 {
+        match state {
+            JobStatus::Unknown     => self.created,
+            JobStatus::Created     => self.ready,
+            JobStatus::Ready       => self.initialized,
+            JobStatus::Initialized => self.started,
+            JobStatus::Started     => self.heartbeat,
+            JobStatus::Completed   => self.result,
+            _                      => { unreachable!(); }
+        }
+    }
+}
+
+
+
+/// Decides the timeouts of jobs, based on the location they run on.
+#[derive(Clone, Default)]
+pub struct TimeoutPolicy {
+    /// The infrastructure file with the per-location timeouts, if any
+    infra     : Option<Infrastructure>,
+    /// The locations that the jobs run on (maintained by the event monitor)
+    locations : Arc<DashMap<String, String>>,
+}
+
+impl TimeoutPolicy {
+    /// Constructor for the TimeoutPolicy.
+    /// 
+    /// **Arguments**
+    ///  * `infra`: The infrastructure file with the per-location timeouts.
+    ///  * `locations`: The locations that the jobs run on (maintained by the event monitor).
+    pub fn new(infra: Infrastructure, locations: Arc<DashMap<String, String>>) -> Self {
+        Self { infra: Some(infra), locations }
+    }
+
+    /// Returns the timeouts for the given job, if we know where it runs.
+    /// 
+    /// **Arguments**
+    ///  * `correlation_id`: The ID of the job.
+    ///  * `requested`: The location that the call asked for, which is used until the event monitor knows where the job was created.
+    /// 
+    /// **Returns**  
+    /// The job's timeouts, or None if its location is not known (yet).
+    pub fn for_job(&self, correlation_id: &str, requested: Option<&str>) -> Option<JobTimeouts> {
+        match self.locations.get(correlation_id) {
+            Some(location) => Some(self.for_location(location.value())),
+            None           => requested.map(|location| self.for_location(location)),
+        }
+    }
+
+    /// Returns the timeouts for jobs on the given location, falling back to the defaults where it sets none.
+    /// 
+    /// **Arguments**
+    ///  * `location`: The name of the location.
+    pub fn for_location(&self, location: &str) -> JobTimeouts {
+        let infra = match &self.infra {
+            Some(infra) => infra,
+            None        => { return JobTimeouts::default(); }
+        };
+        match infra.get_location_metadata(location) {
+            Ok(metadata) => JobTimeouts::with_overrides(&metadata.get_timeouts()),
+            Err(err)     => {
+                warn!("Could not read the timeouts of location '{}': {}; using the defaults", location, err);
+                JobTimeouts::default()
+            },
+        }
+    }
+}
+
+
+
+
+
 /***** FUTURES *****/
 /// Waits until the given job reaches Completed before it timeouts by missing heartbeats
 struct WaitUntilNewState {
@@ -126,10 +342,12 @@ struct WaitUntilNewState {
     /// The current state, which we use to check if a new state arrived
     current_state  : JobStatus,
 
-    /// The event-monitor updated list of last heartbeat times we use to check the job's alive status. If None, then not accepting heartbeats.
+    /// The event-monitor updated list of last heartbeat times we use to check the job's alive status. If None, then not accepting heartbeats. Heartbeats from before `timeout_start` don't count.
     heartbeats     : Option<Arc<DashMap<String, SystemTime>>>,
     /// The event-monitor updated list of states we use to check the job's status
     states         : Arc<DashMap<String, JobStatus>>,
+    /// The list of jobs currently waited for, which we use to see if the job has been cancelled
+    active         : Arc<DashMap<String, ActiveJob>>,
 
     /// The timeout before we call it a day
     timeout          : u128,
@@ -138,7 +356,7 @@ struct WaitUntilNewState {
 }
 
 impl Future for WaitUntilNewState {
-    type Output = Option<(JobStatus, SystemTime)>;
+    type Output = Result<Option<(JobStatus, SystemTime)>, ScheduleError>;
 
     /// Polls the WaitUntilCompleted to see if the remote job has been completed (or failed to do so).
     /// 
@@ -146,22 +364,27 @@ impl Future for WaitUntilNewState {
     ///  * `cx`: The context with which to check if we need to wait for something.
     /// 
     /// **Returns**  
-    /// A Poll::Ready with the JobStatus we found and the time we found it at, or a Poll::Ready with None if we timed out.
+    /// A Poll::Ready with the JobStatus we found and the time we found it at, or a Poll::Ready with None if we timed out. If the job has been cancelled, resolves to a ScheduleError::Cancelled instead.
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Stop waiting if the job has been cancelled
+        if let Some(job) = self.active.get(&self.correlation_id) {
+            if job.cancelled { return Poll::Ready(Err(ScheduleError::Cancelled{ correlation_id: self.correlation_id.clone() })); }
+        }
+
         // Try to match the current state of the job
         let state = self.states.get(&self.correlation_id);
         if let Some(state) = state {
             let state = state.value();
             if std::mem::discriminant(state) != std::mem::discriminant(&self.current_state) {
                 // It has changed
-                return Poll::Ready(Some((state.clone(), SystemTime::now())));
+                return Poll::Ready(Ok(Some((state.clone(), SystemTime::now()))));
             }
         }
 
         // Get the time since the last update
         let last_update: SystemTime = match &self.heartbeats {
             Some(heartbeats) => match heartbeats.get(&self.correlation_id) {
-                Some(last_update) => *last_update.value(),
+                Some(last_update) => (*last_update.value()).max(self.timeout_start),
                 None              => self.timeout_start,
             },
             None => self.timeout_start,
@@ -174,7 +397,7 @@ impl Future for WaitUntilNewState {
         };
 
         // If we haven't seen the event on time, report a timeout (a None)
-        if elapsed.as_millis() >= self.timeout { Poll::Ready(None) }
+        if elapsed.as_millis() >= self.timeout { Poll::Ready(Ok(None)) }
         else {
             // Keep trying
             cx.waker().wake_by_ref();
@@ -192,22 +415,29 @@ impl Future for WaitUntilNewState {
 /// 
 /// **Arguments**
 ///  * `correlation_id`: The ID of the job to wait for.
+///  * `location`: The location that the job was scheduled on, if the call named one.
+///  * `policy`: The TimeoutPolicy that decides how long we wait.
+///  * `heartbeats`: The list of heartbeats to use for checking whether the location is still pulling the job's image (maintained by the event monitor).
 ///  * `states`: The list of states to use for checking the job's progress (maintained by the event monitor).
+///  * `active`: The list of jobs currently waited for, which tells us if the job has been cancelled.
 /// 
 /// **Returns**  
 /// Nothing on success, or a ScheduleError if the job didn't make creation.
-async fn job_wait_created(correlation_id: &str, states: Arc<DashMap<String, JobStatus>>) -> Result<(), ScheduleError> {
+async fn job_wait_created(correlation_id: &str, location: Option<&str>, policy: &TimeoutPolicy, heartbeats: Arc<DashMap<String, SystemTime>>, states: Arc<DashMap<String, JobStatus>>, active: Arc<DashMap<String, ActiveJob>>) -> Result<(), ScheduleError> {
+    let timeouts = policy.for_job(correlation_id, location).unwrap_or_default();
+
     // Wait for a change in state
     let new_state = WaitUntilNewState {
         correlation_id : correlation_id.to_string(),
         current_state  : JobStatus::Unknown,
 
-        heartbeats : None,
+        heartbeats : Some(heartbeats),
         states     : states.clone(),
+        active,
 
-        timeout          : DEFAULT_CREATED_TIMEOUT,
+        timeout          : timeouts.created,
         timeout_start    : SystemTime::now(),
-    }.await;
+    }.await?;
 
     // Now match the new state
     match new_state {
@@ -218,7 +448,125 @@ async fn job_wait_created(correlation_id: &str, states: Arc<DashMap<String, JobS
         Some(_) => Ok(()),
 
         // If we see 'None', then a timeout occurred
-        None => Err(ScheduleError::JobCreatedTimeout{ correlation_id: correlation_id.to_string() }),
+        None => Err(timeout_error(correlation_id, &JobStatus::Unknown, &timeouts)),
+    }
+}
+
+/// Returns whether heartbeats keep a job in the given state alive.
+/// 
+/// That is the case while the job runs, but also before it is ready, where the event monitor takes Pulling events as heartbeats so that pulling a big image doesn't time out.
+/// 
+/// **Arguments**
+///  * `state`: The state that the job is in.
+/// 
+/// **Returns**  
+/// Whether to accept heartbeats in that state.
+fn takes_heartbeats(state: &JobStatus) -> bool {
+    matches!(state, JobStatus::Unknown | JobStatus::Created | JobStatus::Started)
+}
+
+/// Returns the error that corresponds to the given state, if it's a state that means the job failed (or was stopped).
+/// 
+/// **Arguments**
+///  * `correlation_id`: The ID of the job that reached the state.
+///  * `state`: The state that the job reached.
+/// 
+/// **Returns**  
+/// The ScheduleError describing the failure, or None if the state doesn't mean the job failed.
+fn state_error(correlation_id: &str, state: JobStatus) -> Option<ScheduleError> {
+    let correlation_id = correlation_id.to_string();
+    match state {
+        JobStatus::Failed{ res } => {
+            // Try to parse as a FailureResult
+            match serde_json::from_str::<FailureResult>(&res) {
+                Ok(result) => Some(ScheduleError::JobFailed{ correlation_id, code: result.code, stdout: result.stdout, stderr: result.stderr }),
+                Err(_)     => Some(ScheduleError::JobFailedRaw{ correlation_id, output: res }),
+            }
+        },
+        JobStatus::FailedRaw{ res }    => Some(ScheduleError::JobFailedRaw{ correlation_id, output: res }),
+        JobStatus::Stopped{ signal }   => Some(ScheduleError::JobStopped{ correlation_id, signal }),
+        JobStatus::DecodeFailed{ err } => Some(ScheduleError::JobDecodeFailed{ correlation_id, err }),
+        JobStatus::StoreFailed{ err }  => Some(ScheduleError::JobStoreFailed{ correlation_id, err }),
+
+        JobStatus::CompleteFailed{ err }   => Some(ScheduleError::JobCompleteFailed{ correlation_id, err }),
+        JobStatus::StartFailed{ err }      => Some(ScheduleError::JobStartFailed{ correlation_id, err }),
+        JobStatus::InitializeFailed{ err } => Some(ScheduleError::JobInitializeFailed{ correlation_id, err }),
+        JobStatus::CreateFailed{ err }     => Some(ScheduleError::JobCreateFailed{ correlation_id, err }),
+
+        _ => None,
+    }
+}
+
+/// Returns the timeout error for a job that did not leave the given state in time, and counts it in the timeout metric.
+/// 
+/// **Arguments**
+///  * `correlation_id`: The ID of the job that timed out.
+///  * `last_state`: The last state we saw the job in.
+///  * `timeouts`: The timeouts that the job had.
+/// 
+/// **Returns**  
+/// The ScheduleError describing the timeout.
+fn timeout_error(correlation_id: &str, last_state: &JobStatus, timeouts: &JobTimeouts) -> ScheduleError {
+    // Depending on the order of the last state, do different timeout error
+    let correlation_id = correlation_id.to_string();
+    let timeout_ms = timeouts.for_state(last_state);
+    let err = if last_state.order() == JobStatus::Unknown.order()     { ScheduleError::JobCreatedTimeout{ correlation_id, timeout_ms } }
+         else if last_state.order() == JobStatus::Created.order()     { ScheduleError::JobReadyTimeout{ correlation_id, timeout_ms } }
+         else if last_state.order() == JobStatus::Ready.order()       { ScheduleError::JobInitializedTimeout{ correlation_id, timeout_ms } }
+         else if last_state.order() == JobStatus::Initialized.order() { ScheduleError::JobStartedTimeout{ correlation_id, timeout_ms } }
+         else if last_state.order() == JobStatus::Started.order()     { ScheduleError::JobHeartbeatTimeout{ correlation_id, timeout_ms } }
+         else if last_state.order() == JobStatus::Completed.order()   { ScheduleError::JobResultTimeout{ correlation_id, timeout_ms } }
+         else { unreachable!(); };
+    if let Some(name) = err.timeout_name() { metrics::JOB_TIMEOUTS.with_label_values(&[name]).inc(); }
+    err
+}
+
+/// Waits until the job with the given correlation ID has started (or has already gotten further than that).
+/// 
+/// **Arguments**
+///  * `correlation_id`: The ID of the job to wait for.
+///  * `policy`: The TimeoutPolicy that decides how long we wait.
+///  * `heartbeats`: The list of heartbeats to use for checking whether the location is still pulling the job's image (maintained by the event monitor).
+///  * `states`: The list of states to use for checking the job's progress (maintained by the event monitor).
+///  * `active`: The list of jobs currently waited for, which tells us if the job has been cancelled.
+/// 
+/// **Returns**  
+/// Nothing on success, or a ScheduleError if the job failed before it started or took too long to do so.
+async fn job_wait_started(correlation_id: &str, policy: &TimeoutPolicy, heartbeats: Arc<DashMap<String, SystemTime>>, states: Arc<DashMap<String, JobStatus>>, active: Arc<DashMap<String, ActiveJob>>) -> Result<(), ScheduleError> {
+    let mut last_state       = JobStatus::Unknown;
+    let mut last_time_update = SystemTime::now();
+    let mut location_timeouts: Option<JobTimeouts> = None;
+    loop {
+        // Determine the timeout based on the state, using those of the job's location as soon as we know where it runs
+        if location_timeouts.is_none() { location_timeouts = policy.for_job(correlation_id, None); }
+        let timeouts = location_timeouts.unwrap_or_default();
+        let timeout  = timeouts.for_state(&last_state);
+
+        // Wait for a change in state
+        let new_state = WaitUntilNewState {
+            correlation_id : correlation_id.to_string(),
+            current_state  : last_state.clone(),
+
+            heartbeats : if takes_heartbeats(&last_state) { Some(heartbeats.clone()) } else { None },
+            states     : states.clone(),
+            active     : active.clone(),
+
+            timeout,
+            timeout_start    : last_time_update,
+        }.await?;
+
+        match new_state {
+            // Anything at or beyond Started that isn't an error means the job has started
+            Some((new_state, time_update)) => {
+                if let Some(err) = state_error(correlation_id, new_state.clone()) { return Err(err); }
+                if new_state.order() >= JobStatus::Started.order() { return Ok(()); }
+                last_state = new_state;
+                last_time_update = time_update;
+            },
+
+            // If we see 'None', then a timeout occurred
+            None => { return Err(timeout_error(correlation_id, &last_state, &timeouts)); },
+        }
     }
 }
 
@@ -226,79 +574,59 @@ async fn job_wait_created(correlation_id: &str, states: Arc<DashMap<String, JobS
 /// 
 /// **Arguments**
 ///  * `correlation_id`: The ID of the job to wait for.
+///  * `location`: The location that the job was scheduled on, if the call named one.
+///  * `resumed`: Whether we wait for the job again after the driver restarted. Because we may have missed its earlier events, any heartbeat is then taken as a sign of life.
+///  * `policy`: The TimeoutPolicy that decides how long we wait for every stage of the job.
 ///  * `heartbeats`: The list of heartbeats to use for checking the job's alive status (maintained by the event monitor).
 ///  * `states`: The list of states to use for checking the job's progress (maintained by the event monitor).
+///  * `active`: The list of jobs currently waited for, which tells us if the job has been cancelled.
 /// 
 /// **Returns**  
-/// The job's return value on success, or a ScheduleError if the job didn't make creation.
-async fn job_wait_finished(correlation_id: &str, heartbeats: Arc<DashMap<String, SystemTime>>, states: Arc<DashMap<String, JobStatus>>) -> Result<Value, ScheduleError> {
+/// The job's return value and the resources it used (if the branelet reported them) on success, or a ScheduleError if the job didn't make creation.
+async fn job_wait_finished(correlation_id: &str, location: Option<&str>, resumed: bool, policy: &TimeoutPolicy, heartbeats: Arc<DashMap<String, SystemTime>>, states: Arc<DashMap<String, JobStatus>>, active: Arc<DashMap<String, ActiveJob>>) -> Result<(Value, Option<CallStats>), ScheduleError> {
     // Jeep iterating until, inevitably, we timeout, see an error or see a finished state
     let mut last_state       = JobStatus::Unknown;
     let mut last_time_update = SystemTime::now();
+    let mut location_timeouts: Option<JobTimeouts> = None;
     loop {
-        // Determine the timeout based on the state
-        let timeout = match last_state {
-            JobStatus::Unknown     => DEFAULT_CREATED_TIMEOUT,
-            JobStatus::Created     => DEFAULT_READY_TIMEOUT,
-            JobStatus::Ready       => DEFAULT_INITIALIZED_TIMEOUT,
-            JobStatus::Initialized => DEFAULT_STARTED_TIMEOUT,
-            JobStatus::Started     => DEFAULT_HEARTBEAT_TIMEOUT,
-            JobStatus::Completed   => DEFAULT_RESULT_TIMEOUT,
-            _                      => { unreachable!(); }
-        };
+        // Determine the timeout based on the state, using those of the job's location as soon as we know where it runs
+        if location_timeouts.is_none() { location_timeouts = policy.for_job(correlation_id, location); }
+        let timeouts = location_timeouts.unwrap_or_default();
+        let timeout  = timeouts.for_state(&last_state);
 
         // Wait for a change in state
         let new_state = WaitUntilNewState {
             correlation_id : correlation_id.to_string(),
             current_state  : last_state.clone(),
 
-            heartbeats : if std::mem::discriminant(&last_state) == std::mem::discriminant(&JobStatus::Started) { Some(heartbeats.clone()) } else { None },
+            heartbeats : if resumed || takes_heartbeats(&last_state) { Some(heartbeats.clone()) } else { None },
             states     : states.clone(),
+            active     : active.clone(),
 
             timeout,
             timeout_start    : last_time_update,
-        }.await;
+        }.await?;
 
         // Now match the new state
         match new_state {
-            // If it's any of the final states, then we can quit
+            // If it's the final state, then we can quit
             Some((JobStatus::Finished{ res }, _)) => {
-                // Try to parse as a Value
-                match serde_json::from_str::<Value>(&res) {
-                    Ok(value) => { return Ok(value); },
-                    Err(err)  => { return Err(ScheduleError::FinishedDeserializeError{ output: res, err }); },
-                }
-            },
-            Some((JobStatus::Failed{ res }, _)) => {
-                // Try to parse as a FailureResult
-                match serde_json::from_str::<FailureResult>(&res) {
-                    Ok(result) => { return Err(ScheduleError::JobFailed{ correlation_id: correlation_id.to_string(), code: result.code, stdout: result.stdout, stderr: result.stderr }); },
-                    Err(err)   => { return Err(ScheduleError::FailedDeserializeError{ output: res, err }); },
+                // Try to parse as a Value (which skips the stats, if any)
+                match decode_finished(&res) {
+                    Ok(result) => { return Ok(result); },
+                    Err(err)   => { return Err(ScheduleError::FinishedDeserializeError{ output: res, err }); },
                 }
             },
-            Some((JobStatus::Stopped{ signal }, _))   => { return Err(ScheduleError::JobStopped{ correlation_id: correlation_id.to_string(), signal }); },
-            Some((JobStatus::DecodeFailed{ err }, _)) => { return Err(ScheduleError::JobDecodeFailed{ correlation_id: correlation_id.to_string(), err }); },
 
-            // Otherwise, for any other error, quit as well
-            Some((JobStatus::CompleteFailed{ err }, _))   => { return Err(ScheduleError::JobCompleteFailed{ correlation_id: correlation_id.to_string(), err }) },
-            Some((JobStatus::StartFailed{ err }, _))      => { return Err(ScheduleError::JobStartFailed{ correlation_id: correlation_id.to_string(), err }) },
-            Some((JobStatus::InitializeFailed{ err }, _)) => { return Err(ScheduleError::JobInitializeFailed{ correlation_id: correlation_id.to_string(), err }) },
-            Some((JobStatus::CreateFailed{ err }, _))     => { return Err(ScheduleError::JobCreateFailed{ correlation_id: correlation_id.to_string(), err }) },
-
-            // For any other state, set it as the last state and see if we need to match again
-            Some((new_state, time_update)) => { last_state = new_state; last_time_update = time_update; }
+            // For any other state, quit if it's an error or set it as the last state and see if we need to match again
+            Some((new_state, time_update)) => {
+                if let Some(err) = state_error(correlation_id, new_state.clone()) { return Err(err); }
+                last_state = new_state;
+                last_time_update = time_update;
+            },
 
             // If we see 'None', then a timeout occurred
-            None => {
-                // Depending on the order of the last state, do different timeout error
-                if      last_state.order() == JobStatus::Unknown.order()     { return Err(ScheduleError::JobCreatedTimeout{ correlation_id: correlation_id.to_string() }); }
-                else if last_state.order() == JobStatus::Created.order()     { return Err(ScheduleError::JobReadyTimeout{ correlation_id: correlation_id.to_string() }); }
-                else if last_state.order() == JobStatus::Ready.order()       { return Err(ScheduleError::JobInitializedTimeout{ correlation_id: correlation_id.to_string() }); }
-                else if last_state.order() == JobStatus::Initialized.order() { return Err(ScheduleError::JobStartedTimeout{ correlation_id: correlation_id.to_string() }); }
-                else if last_state.order() == JobStatus::Started.order()     { return Err(ScheduleError::JobHeartbeatTimeout{ correlation_id: correlation_id.to_string() }); }
-                else if last_state.order() == JobStatus::Completed.order()   { return Err(ScheduleError::JobResultTimeout{ correlation_id: correlation_id.to_string() }); }
-                else { unreachable!(); }
-            },
+            None => { return Err(timeout_error(correlation_id, &last_state, &timeouts)); },
         }
 
         // Do a nice debug print
@@ -310,19 +638,368 @@ async fn job_wait_finished(correlation_id: &str, heartbeats: Arc<DashMap<String,
 
 
 
+/// Converts the error of a job into the error of the external call that scheduled it.
+/// 
+/// **Arguments**
+///  * `name`: The name of the function that was called.
+///  * `package`: The package that provides the function.
+///  * `version`: The version of that package.
+///  * `err`: The ScheduleError to convert.
+/// 
+/// **Returns**  
+/// An ExecutorError::ExternalCallFailed (or ExternalCallFailedRaw, if its output was mangled) if the job itself failed, or an ExecutorError::ExternalCallError otherwise.
+fn call_error(name: String, package: String, version: Version, err: ScheduleError) -> ExecutorError {
+    match err {
+        ScheduleError::JobFailed{ code, stdout, stderr, .. } => ExecutorError::ExternalCallFailed{ name, package, version, code, stdout, stderr },
+        ScheduleError::JobFailedRaw{ output, .. }            => ExecutorError::ExternalCallFailedRaw{ name, package, version, output },
+        err                                                  => ExecutorError::ExternalCallError{ name, package, version, err: format!("{}", err) },
+    }
+}
+
+/// Converts the error of sending a reply to the client into the ExecutorError that the VM sees.
+/// 
+/// **Arguments**
+///  * `err`: The ClientError to convert.
+/// 
+/// **Returns**  
+/// An ExecutorError::ClientBackpressure if the client did not read its replies, or an ExecutorError::ClientTxError if it is gone.
+fn client_error(err: ClientError) -> ExecutorError {
+    match err {
+        ClientError::Backpressure{ class, pending, timeout } => ExecutorError::ClientBackpressure{ what: format!("{}", class), pending, timeout },
+        err                                                  => ExecutorError::ClientTxError{ err: format!("{}", err) },
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Marks the given job as waited for, so that it may be cancelled while we wait.
+/// 
+/// A job that is already waited for keeps its entry, and with it whether it has been cancelled.
+/// 
+/// **Arguments**
+///  * `active`: The list of jobs currently waited for.
+///  * `correlation_id`: The ID of the job to mark.
+///  * `job`: The ActiveJob to mark it with if it isn't marked yet.
+/// 
+/// **Returns**  
+/// Whether we marked the job. Only then should the caller unmark it when it is done waiting.
+pub fn mark_active(active: &DashMap<String, ActiveJob>, correlation_id: &str, job: ActiveJob) -> bool {
+    match active.entry(correlation_id.to_string()) {
+        Entry::Occupied(_)   => false,
+        Entry::Vacant(entry) => { entry.insert(job); true },
+    }
+}
+
+/// Cancels the jobs that the given session is currently waiting for, one at a time: a job is claimed by marking it as cancelled (which makes its pending call return with an error), and then stopped with the given function.
+/// 
+/// Jobs that have already been cancelled, or that complete concurrently, are skipped, so no job is ever stopped twice. If stopping a job fails, its claim is undone and the jobs after it are left alone, so that cancelling again picks them all up.
+/// 
+/// **Arguments**
+///  * `active`: The list of jobs currently waited for.
+///  * `session_uuid`: The session to cancel the jobs of.
+///  * `stop`: Stops the job with the given correlation ID (e.g., by publishing a Stop command for it).
+/// 
+/// **Returns**  
+/// The correlation IDs of the jobs that we cancelled, or the ID of the job that we could not stop together with the error that `stop` failed with.
+pub async fn cancel_jobs<S, F, E>(active: &DashMap<String, ActiveJob>, session_uuid: &str, mut stop: S) -> Result<Vec<String>, (String, E)>
+where
+    S: FnMut(String) -> F,
+    F: Future<Output = Result<(), E>>,
+{
+    let candidates: Vec<String> = active.iter().filter(|job| job.session_uuid == session_uuid).map(|job| job.key().clone()).collect();
+
+    let mut job_ids: Vec<String> = Vec::new();
+    for correlation_id in candidates {
+        // Flipping the flag under the entry's lock makes sure only one party ever claims a job
+        match active.get_mut(&correlation_id) {
+            Some(mut job) if !job.cancelled => { job.cancelled = true; },
+            _                               => { continue; }
+        }
+
+        if let Err(err) = stop(correlation_id.clone()).await {
+            if let Some(mut job) = active.get_mut(&correlation_id) { job.cancelled = false; }
+            return Err((correlation_id, err));
+        }
+        job_ids.push(correlation_id);
+    }
+    Ok(job_ids)
+}
+
+/// Waits until the job of a detached service has reached the given state, as reported by the event monitor.
+/// 
+/// **Arguments**
+///  * `service`: The identifier of the service (i.e., the correlation ID of its job).
+///  * `state`: The state to wait for. ServiceState::Done waits for the job to finish successfully.
+///  * `policy`: The TimeoutPolicy that decides how long we wait for every stage of the job.
+///  * `heartbeats`: The list of heartbeats to use for checking the job's alive status (maintained by the event monitor).
+///  * `states`: The list of states to use for checking the job's progress (maintained by the event monitor).
+///  * `active`: The list of jobs currently waited for, which tells us if the job has been cancelled.
+/// 
+/// **Returns**  
+/// Nothing if the state was reached, or an ExecutorError::ServiceFailed if the job failed, was stopped or timed out before that.
+pub async fn wait_until_service(service: &str, state: ServiceState, policy: &TimeoutPolicy, heartbeats: Arc<DashMap<String, SystemTime>>, states: Arc<DashMap<String, JobStatus>>, active: Arc<DashMap<String, ActiveJob>>) -> Result<(), ExecutorError> {
+    let res = match state {
+        ServiceState::Created => job_wait_created(service, None, policy, heartbeats, states, active).await,
+        ServiceState::Started => job_wait_started(service, policy, heartbeats, states, active).await,
+        ServiceState::Done    => job_wait_finished(service, None, false, policy, heartbeats, states, active).await.map(|_| ()),
+    };
+    res.map_err(|err| ExecutorError::ServiceFailed{ service: service.to_string(), err: format!("{}", err) })
+}
+
+/// Resumes waiting for the jobs that the given session was still waiting for when the driver went down, which is done when the session reattaches.
+/// 
+/// Jobs that a running statement still waits for, and jobs that have been resumed already, are skipped. Jobs that were scheduled longer ago than the horizon and of which we have seen no events are considered lost.
+/// 
+/// **Arguments**
+///  * `session_uuid`: The UUID of the session that reattached.
+///  * `sessions`: The store with the pending jobs of every session.
+///  * `resumed`: The list of resumed jobs, which the executor takes from when the session retries the statement that scheduled them.
+///  * `horizon`: How long after scheduling a job we may still expect to see its events.
+///  * `policy`: The TimeoutPolicy that decides how long we wait for every stage of the jobs.
+///  * `heartbeats`: The list of heartbeats to use for checking the job's alive status (maintained by the event monitor).
+///  * `states`: The list of states to use for checking the job's progress (maintained by the event monitor).
+///  * `active`: The list of jobs currently waited for.
+/// 
+/// **Returns**  
+/// The number of jobs that we resumed waiting for (including the lost ones).
+pub fn resume_session(session_uuid: &str, sessions: &SessionStore, resumed: &DashMap<String, ResumedJob>, horizon: Duration, policy: &TimeoutPolicy, heartbeats: Arc<DashMap<String, SystemTime>>, states: Arc<DashMap<String, JobStatus>>, active: Arc<DashMap<String, ActiveJob>>) -> usize {
+    let mut count = 0;
+    for job in sessions.pending(session_uuid) {
+        if active.contains_key(&job.correlation_id) || resumed.contains_key(&job.correlation_id) { continue; }
+
+        // Without events and past the horizon, there's no point in waiting
+        let correlation_id = job.correlation_id.clone();
+        let age = job.age();
+        let handle = if age > horizon.as_secs() && !states.contains_key(&correlation_id) {
+            warn!("Considering job '{}' of session '{}' lost ({} seconds old)", correlation_id, session_uuid, age);
+            let err = ScheduleError::JobLost{ correlation_id: correlation_id.clone(), age, horizon: horizon.as_secs() };
+            tokio::spawn(async move { Err(err) })
+        } else {
+            info!("Resuming wait for job '{}' of session '{}'", correlation_id, session_uuid);
+            let (id, policy, heartbeats, states, active) = (correlation_id.clone(), policy.clone(), heartbeats.clone(), states.clone(), active.clone());
+            tokio::spawn(async move { job_wait_finished(&id, None, true, &policy, heartbeats, states, active).await.map(|(value, _)| value) })
+        };
+
+        resumed.insert(correlation_id, ResumedJob{ job, session_uuid: session_uuid.to_string(), handle });
+        count += 1;
+    }
+    count
+}
+
+/// Parses the UUID of the session that makes a call.
+/// 
+/// **Arguments**
+///  * `session_uuid`: The session ID as sent by the client.
+/// 
+/// **Returns**  
+/// The parsed Uuid, or an ExecutorError::InvalidSessionIdError if it isn't one.
+pub fn parse_session_uuid(session_uuid: &str) -> Result<Uuid, ExecutorError> {
+    Uuid::parse_str(session_uuid).map_err(|err| ExecutorError::InvalidSessionIdError{ session_uuid: session_uuid.to_string(), err: format!("{}", err) })
+}
+
+/// Resolves the metadata of the location that the given job was created on, once per job.
+/// 
+/// The first resolution is cached, so that later lookups for the same job neither read the infrastructure file again nor see a location that the event monitor updated in the meantime.
+/// 
+/// **Arguments**
+///  * `correlation_id`: The ID of the job.
+///  * `requested`: The location that the call asked for, which is used if the event monitor does not know where the job was created.
+///  * `locations`: The locations that the jobs run on (maintained by the event monitor).
+///  * `infra`: The infrastructure file with the metadata of every location.
+///  * `cache`: The locations resolved so far, per job.
+/// 
+/// **Returns**  
+/// The Location of the job, or an ExecutorError::UnknownLocationError if it could not be resolved.
+pub fn resolve_location(correlation_id: &str, requested: Option<&str>, locations: &DashMap<String, String>, infra: &Infrastructure, cache: &DashMap<String, Location>) -> Result<Location, ExecutorError> {
+    if let Some(location) = cache.get(correlation_id) { return Ok(location.clone()); }
+
+    let location = match locations.get(correlation_id) {
+        Some(location) => location.clone(),
+        None           => requested.map(String::from).unwrap_or_default(),
+    };
+    let metadata = infra.get_location_metadata(&location)
+        .map_err(|err| ExecutorError::UnknownLocationError{ correlation_id: correlation_id.to_string(), location, err: format!("{}", err) })?;
+    Ok(cache.entry(correlation_id.to_string()).or_insert(metadata).clone())
+}
+
+/// Takes the resumed job that was scheduled by the given call of the given session, if any.
+/// 
+/// **Arguments**
+///  * `resumed`: The list of resumed jobs.
+///  * `session_uuid`: The UUID of the session that makes the call.
+///  * `call_key`: The key of the call (see `sessions::call_key()`).
+/// 
+/// **Returns**  
+/// The ResumedJob to wait for instead of scheduling a new job, or None if the call did not schedule a job before the driver restarted.
+pub fn take_resumed(resumed: &DashMap<String, ResumedJob>, session_uuid: &str, call_key: &str) -> Option<ResumedJob> {
+    let correlation_id = resumed.iter()
+        .find(|job| job.session_uuid == session_uuid && job.job.call_key == call_key)
+        .map(|job| job.key().clone())?;
+    resumed.remove(&correlation_id).map(|(_, job)| job)
+}
+
+/// Releases the resumed jobs of the given session that it never took (e.g., because it did not retry the statement that scheduled them), which is done once the session is closed or has expired.
+/// 
+/// Waits that are still running are stopped; the jobs themselves keep running.
+/// 
+/// **Arguments**
+///  * `resumed`: The list of resumed jobs.
+///  * `session_uuid`: The UUID of the session that is gone.
+/// 
+/// **Returns**  
+/// The correlation IDs of the released jobs.
+pub fn release_resumed(resumed: &DashMap<String, ResumedJob>, session_uuid: &str) -> Vec<String> {
+    let mut released = vec![];
+    resumed.retain(|correlation_id, job| {
+        if job.session_uuid != session_uuid { return true; }
+        job.handle.abort();
+        released.push(correlation_id.clone());
+        false
+    });
+    released
+}
+
+/// Decodes the payload of a Finished event into the Value that the call returned and the resources it used.
+/// 
+/// If the call stored artifacts, the Value is wrapped in an `Output` struct with the original Value as `value` and the artifacts as an array of `Artifact` structs in `artifacts`, so that scripts can pass them to later calls.
+/// 
+/// **Arguments**
+///  * `res`: The JSON-encoded payload of the Finished event.
+/// 
+/// **Returns**  
+/// The returned Value and the CallStats (if the branelet sent any), or a serde_json::Error if the payload isn't a valid Value.
+pub fn decode_finished(res: &str) -> Result<(Value, Option<CallStats>), serde_json::Error> {
+    let value = Value::from_payload(res)?;
+    let value = match Artifact::from_payload(res) {
+        Some(artifacts) => {
+            let artifact_type = format!("{}", BuiltinClass::Artifact);
+            let entries = artifacts.into_iter().map(|artifact| {
+                let mut properties = HashMap::new();
+                properties.insert(String::from("name"), Value::Unicode(artifact.name));
+                properties.insert(String::from("size"), Value::Integer(artifact.size as i64));
+                properties.insert(String::from("path"), Value::Unicode(artifact.path));
+                properties.insert(String::from("sha256"), Value::Unicode(artifact.sha256));
+                Value::Struct{ data_type: artifact_type.clone(), properties }
+            }).collect();
+
+            let mut properties = HashMap::new();
+            properties.insert(String::from("value"), value);
+            properties.insert(String::from("artifacts"), Value::Array{ data_type: format!("{}[]", artifact_type), entries });
+            Value::Struct{ data_type: format!("{}", BuiltinClass::Output), properties }
+        },
+        None => value,
+    };
+    Ok((value, CallStats::from_payload(res)))
+}
+
+/// Decides whether the calls of a `map_call()` are scheduled as a single job array instead of one job per call.
+/// 
+/// **Arguments**
+///  * `function`: The function that is called.
+///  * `inputs`: The number of calls.
+///  * `location`: The location that the calls run on.
+/// 
+/// **Returns**  
+/// Whether to use a job array, which is only worth it for more than one call of a function that isn't detached, and only possible if the location supports them (see `Location::supports_arrays()`).
+pub fn uses_job_array(function: &FunctionExt, inputs: usize, location: &Location) -> bool {
+    !function.detached && inputs > 1 && location.supports_arrays()
+}
+
+/// Splits the calls of a `map_call()` that still have to run into job arrays that the session may have in flight at once, and whose arguments fit in `MAX_ARRAY_ARGUMENTS` once encoded (see `array_arguments()`).
+/// 
+/// **Arguments**
+///  * `arguments`: The arguments of every call, as key/value pairs.
+///  * `pending`: The indices (in `arguments`) of the calls that still have to run, in order.
+///  * `max_elements`: The most elements that a single job array may have.
+/// 
+/// **Returns**  
+/// The indices of the calls of every job array, in order. Calls that end up on their own (e.g., because their arguments are too large to share an array) run as single jobs instead.
+pub fn plan_arrays(arguments: &[HashMap<String, Value>], pending: &[usize], max_elements: usize) -> Vec<Vec<usize>> {
+    let mut arrays: Vec<Vec<usize>> = vec![];
+    let mut current: Vec<usize> = vec![];
+    // The size of the JSON array so far, including its brackets and separators
+    let mut size: usize = 2;
+    for &index in pending {
+        let element = serde_json::to_string(&arguments[index]).unwrap().len() + 1;
+        if !current.is_empty() && (current.len() >= max_elements.max(1) || 4 * ((size + element + 2) / 3) > MAX_ARRAY_ARGUMENTS) {
+            arrays.push(std::mem::take(&mut current));
+            size = 2;
+        }
+        current.push(index);
+        size += element;
+    }
+    if !current.is_empty() { arrays.push(current); }
+    arrays
+}
+
+/// Keeps the elements of a job array that the location still holds back (because of the array's parallelism) from running into their created or ready timeout, as long as the elements that do run show signs of life. It does so by giving the queued elements the latest heartbeat of the others, which they accept while waiting for those states.
+/// 
+/// **Arguments**
+///  * `elements`: The IDs of the elements of the job array.
+///  * `heartbeats`: The list of heartbeats to update (maintained by the event monitor).
+///  * `states`: The list of states that tells us which elements are still queued (maintained by the event monitor).
+pub fn share_heartbeats(elements: &[String], heartbeats: &DashMap<String, SystemTime>, states: &DashMap<String, JobStatus>) {
+    let queued = |element: &String| states.get(element).map(|state| state.order() <= JobStatus::Created.order()).unwrap_or(true);
+
+    // Find the latest sign of life of the elements that left the queue
+    let latest = elements.iter()
+        .filter(|element| !queued(element))
+        .filter_map(|element| heartbeats.get(element).map(|time| *time.value()))
+        .max();
+
+    // Pass it on to those that are still in it
+    if let Some(latest) = latest {
+        for element in elements.iter().filter(|element| queued(element)) {
+            let mut time = heartbeats.entry(element.clone()).or_insert(latest);
+            if *time < latest { *time = latest; }
+        }
+    }
+}
+
+/// Encodes the arguments of the elements of a job array like the arguments of a single job: as Base64-encoded JSON, but of an array with the arguments of every element (the branelet of each element picks its own).
+/// 
+/// **Arguments**
+///  * `arguments`: The arguments of every element, as key/value pairs.
+/// 
+/// **Returns**  
+/// The encoded arguments.
+pub fn array_arguments(arguments: &[HashMap<String, Value>]) -> String {
+    base64::encode(serde_json::to_string(arguments).unwrap())
+}
+
+
+
+
+
 /***** DRIVER EXECUTOR *****/
 ///
 ///
 ///
 #[derive(Clone)]
 pub struct JobExecutor {
-    pub client_tx: Sender<Result<grpc::ExecuteReply, Status>>,
+    pub client_tx: ClientSender,
     pub command_topic: String,
     pub producer: FutureProducer,
     pub session_uuid: String,
     pub states: Arc<DashMap<String, JobStatus>>,
     pub heartbeats: Arc<DashMap<String, SystemTime>>,
     pub locations: Arc<DashMap<String, String>>,
+    pub active: Arc<DashMap<String, ActiveJob>>,
+    /// The order of the latest state change that the event monitor processed for every job, which we forget together with the job's state.
+    pub orders: Arc<DashMap<String, u32>>,
+    pub sessions: Arc<SessionStore>,
+    pub resumed: Arc<DashMap<String, ResumedJob>>,
+    /// The location metadata of the detached jobs that this executor started, resolved once per job.
+    pub services: Arc<DashMap<String, Location>>,
+    /// Reports the lineage of every job to the API, unless disabled.
+    pub lineage: Option<LineageReporter>,
+    /// Limits the number of jobs in flight, per session and in total.
+    pub limits: Arc<JobLimits>,
+    /// The results of earlier calls to pure functions, unless caching is disabled.
+    pub calls: Option<Arc<CallCache>>,
     pub infra: Infrastructure,
 }
 
@@ -341,52 +1018,116 @@ impl JobExecutor {
 
         identifier.to_lowercase()
     }
-}
 
-#[async_trait]
-impl VmExecutor for JobExecutor {
-    /* TIM */
-    /// **Edited: Synced Call up with the VmExecutor trait. This also means we implemented proper error handling in this function.**
-    ///
-    /// Calls an external function on the given Brane infrastructure site.
+    /// Picks a location for a call that doesn't name one (see `planner::plan_location()`), and tells the client which one it picked.
     /// 
-    /// **Arguments**  
+    /// **Arguments**
+    ///  * `function`: The function to pick a location for, which carries the requirements of its package.
+    /// 
+    /// **Returns**  
+    /// The name of the location, or an ExecutorError::LocationSelectionError (listing the unmet requirements) if no location fits.
+    async fn pick_location(&self, function: &FunctionExt) -> Result<String, ExecutorError> {
+        let choice = match planner::plan_location(&function.requirements, &self.infra) {
+            Ok(choice) => choice,
+            Err(err)   => { return Err(ExecutorError::LocationSelectionError{ name: function.name.clone(), package: function.package.clone(), version: function.version.clone(), err: format!("{}", err) }); }
+        };
+
+        let text = format!("No location given for function '{}'; picked '{}' (cost {}), the cheapest of {} location(s) that satisfy the requirements of package '{}' ({})", function.name, choice.location, choice.cost, choice.candidates, function.package, function.requirements);
+        debug!("{}", text);
+        if let Err(err) = self.debug(text).await {
+            warn!("Could not notify client of the location of function '{}': {}", function.name, err);
+        }
+        Ok(choice.location)
+    }
+
+    /// Waits for a job that this session scheduled before the driver restarted, as if the call just scheduled it.
+    /// 
+    /// **Arguments**
+    ///  * `job`: The ResumedJob to wait for.
+    /// 
+    /// **Returns**  
+    /// The job's return value on success, or an ExecutorError otherwise.
+    async fn resume_call(&self, job: ResumedJob) -> Result<Value, ExecutorError> {
+        let correlation_id = job.job.correlation_id.clone();
+        if let Err(err) = self.debug(format!("Resuming job '{}' for function '{}', which was scheduled before the driver restarted", correlation_id, job.job.function)).await {
+            warn!("Could not notify client of job '{}': {}", correlation_id, err);
+        }
+
+        // Mark it as active again, so it may be cancelled and its output reaches this client
+        self.active.insert(correlation_id.clone(), ActiveJob{ session_uuid: self.session_uuid.clone(), cancelled: false, client_tx: self.client_tx.clone() });
+        info!("Waiting until resumed job '{}' is finished...", correlation_id);
+        let res = job.wait().await;
+        self.active.remove(&correlation_id);
+        self.states.remove(&correlation_id);
+        self.orders.remove(&correlation_id);
+        if let Err(err) = self.sessions.remove_pending(&self.session_uuid, &correlation_id) {
+            warn!("Could not persist that job '{}' is no longer pending: {}", correlation_id, err);
+        }
+        res
+    }
+
+    /// Schedules a job for the given external call and waits for it (or for the job that the call scheduled before the driver restarted).
+    /// 
+    /// **Arguments**
     ///  * `function`: The function to execute remotely.
     ///  * `arguments`: A map of key/value pairs that are passed to the function to be executed.
-    ///  * `location`: The location/site where the function will be executed.
+    ///  * `location`: The location/site where the function will be executed. If omitted, one is picked based on the requirements of the function's package.
+    ///  * `job_id`: Is set to the correlation ID of the job as soon as it is known.
     /// 
     /// **Returns**  
     /// The value of the external call if successful, or an ExecutorError otherwise.
-    async fn call(
+    async fn schedule_call(
         &self,
         function: FunctionExt,
         arguments: HashMap<String, Value>,
         location: Option<String>,
+        job_id: &mut Option<String>,
     ) -> Result<Value, ExecutorError> {
         debug!("Processing external call for function '{}'...", function.name);
         let image = format!("{}:{}@{}", function.package, function.version, function.digest);
         debug!(" > associated image: {}...", image);
+
+        // If the driver restarted while this call's job ran, wait for that job instead of scheduling it again
+        let key = call_key(&function, &arguments, &location);
+        if !function.detached {
+            if let Some(job) = take_resumed(&self.resumed, &self.session_uuid, &key) {
+                *job_id = Some(job.job.correlation_id.clone());
+                return self.resume_call(job).await;
+            }
+        }
+
+        // Pick a location ourselves if the call doesn't name one
+        let location = match location {
+            Some(location) => location,
+            None           => self.pick_location(&function).await?,
+        };
+
         let command = vec![
             function.kind.to_string(),
             function.name.to_string(),
             base64::encode(serde_json::to_string(&arguments).unwrap()),
         ];
 
-        let session_uuid = Uuid::parse_str(&self.session_uuid).unwrap();
+        let session_uuid = parse_session_uuid(&self.session_uuid)?;
         let session_uuid_simple = session_uuid.to_simple().to_string();
 
         let random_id = self.get_random_identifier();
         let correlation_id = format!("A{}R{}", &session_uuid_simple[..8], random_id);
+        *job_id = Some(correlation_id.clone());
+        let requested = Some(location.clone());
+        let policy = TimeoutPolicy::new(self.infra.clone(), self.locations.clone());
 
+        // Every job of the session works in the same subdirectory of the data directory (unless the location shares it between sessions), with the package environment that the script configured
         let command = Command::new(
             CommandKind::Create,
             Some(correlation_id.clone()),
             Some(self.session_uuid.clone()),
-            location,
+            Some(location),
             Some(image),
             command,
             None,
-        );
+        ).with_environment(SESSION_DATA_ENV, session_data_dir(&self.session_uuid));
+        let command = function.env.iter().fold(command, |command, (key, value)| command.with_environment(key.clone(), value.clone()));
 
         let mut payload = BytesMut::with_capacity(64);
         command.encode(&mut payload).unwrap();
@@ -396,30 +1137,34 @@ impl VmExecutor for JobExecutor {
             .key(&correlation_id)
             .payload(payload.to_bytes());
 
+        // Mark the job as active before it's scheduled, so it may be cancelled from the get-go
+        self.active.insert(correlation_id.clone(), ActiveJob{ session_uuid: self.session_uuid.clone(), cancelled: false, client_tx: self.client_tx.clone() });
+
         let timeout = Timeout::After(Duration::from_secs(5));
         if let Err(err) = self.producer.send(message, timeout).await {
+            self.active.remove(&correlation_id);
             return Err(ExecutorError::CommandScheduleError{ topic: self.command_topic.clone(), err: format!("{:?}", err) });
         }
 
+        // Let the client know the job ID, so it may retrieve its output later
+        if let Err(err) = self.debug(format!("Scheduled job '{}' for function '{}'", correlation_id, function.name)).await {
+            warn!("Could not notify client of job '{}': {}", correlation_id, err);
+        }
+
         if function.detached {
             // It's a detached, so we only wait until it's underway
-            let created = job_wait_created(&correlation_id, self.states.clone());
+            let created = job_wait_created(&correlation_id, requested.as_deref(), &policy, self.heartbeats.clone(), self.states.clone(), self.active.clone());
 
             info!("Waiting until (detached) job '{}' is created...", correlation_id);
             let res = created.await;
+            self.active.remove(&correlation_id);
             if let Err(err) = res {
                 return Err(ExecutorError::ExternalCallError{ name: function.name, package: function.package, version: function.version, err: format!("{}", err) });
             }
             info!("OK, job '{}' has been created", correlation_id);
 
             // Return a Service that represents the running call
-            let location = self
-                .locations
-                .get(&correlation_id)
-                .map(|s| s.clone())
-                .unwrap_or_default();
-
-            let location = self.infra.get_location_metadata(location).unwrap();
+            let location = resolve_location(&correlation_id, requested.as_deref(), &self.locations, &self.infra, &self.services)?;
 
             let mut properties = HashMap::default();
             properties.insert(String::from("identifier"), Value::Unicode(correlation_id));
@@ -430,27 +1175,332 @@ impl VmExecutor for JobExecutor {
                 properties,
             })
         } else {
+            // Remember that we wait for the job, so we may pick it up again if the driver restarts
+            if let Err(err) = self.sessions.add_pending(&self.session_uuid, PendingJob::new(correlation_id.clone(), key, &function)) {
+                warn!("Could not persist pending job '{}': {}", correlation_id, err);
+            }
+
             // Wait until the job is completed
-            let finished = job_wait_finished(&correlation_id, self.heartbeats.clone(), self.states.clone());
+            let finished = job_wait_finished(&correlation_id, requested.as_deref(), false, &policy, self.heartbeats.clone(), self.states.clone(), self.active.clone());
 
             info!("Waiting until job '{}' is finished...", correlation_id);
-            let value = match finished.await {
-                Ok(value) => value,
-                Err(ScheduleError::JobFailed{ code, stdout, stderr, .. }) => { return Err(ExecutorError::ExternalCallFailed{ name: function.name, package: function.package, version: function.version, code, stdout, stderr }); }
-                Err(err) => { return Err(ExecutorError::ExternalCallError{ name: function.name, package: function.package, version: function.version, err: format!("{}", err) }); }
+            let res = finished.await;
+            self.active.remove(&correlation_id);
+            if let Err(err) = self.sessions.remove_pending(&self.session_uuid, &correlation_id) {
+                warn!("Could not persist that job '{}' is no longer pending: {}", correlation_id, err);
+            }
+            let (value, stats) = match res {
+                Ok(result) => result,
+                Err(err)   => { return Err(call_error(function.name, function.package, function.version, err)); }
             };
             info!("OK, job '{}' is finished", correlation_id);
+            if let Some(stats) = stats {
+                debug!("Job '{}' used {}", correlation_id, stats);
+                if let Err(err) = self.debug(format!("Job '{}' for function '{}' used {}", correlation_id, function.name, stats)).await {
+                    warn!("Could not notify client of job '{}': {}", correlation_id, err);
+                }
+            }
 
             // Remove the job
             self.states.remove(&correlation_id);
+            self.orders.remove(&correlation_id);
 
             // Return the result
             debug!("RESULT: {:?}", value);
             Ok(value)
         }
     }
+
+    /// Schedules a single job array for the calls of a `map_call()` and waits for all of its elements, which report to us as jobs of their own (see `naming::array_element_id()`).
+    /// 
+    /// **Arguments**
+    ///  * `function`: The function to execute remotely.
+    ///  * `arguments`: The arguments of every element, as key/value pairs.
+    ///  * `location`: The location/site where the job array will be created, which has to support them.
+    ///  * `parallelism`: The most elements that the location runs at the same time.
+    /// 
+    /// **Returns**  
+    /// The value of every element (or the ExecutorError it failed with) in the order of `arguments`, or an ExecutorError if the job array could not be scheduled at all.
+    async fn schedule_array(
+        &self,
+        function: &FunctionExt,
+        arguments: &[HashMap<String, Value>],
+        location: String,
+        parallelism: usize,
+    ) -> Result<Vec<Result<Value, ExecutorError>>, ExecutorError> {
+        debug!("Processing {} external calls for function '{}' as a job array...", arguments.len(), function.name);
+        let image = format!("{}:{}@{}", function.package, function.version, function.digest);
+
+        let command = vec![
+            function.kind.to_string(),
+            function.name.to_string(),
+            array_arguments(arguments),
+        ];
+
+        let session_uuid = parse_session_uuid(&self.session_uuid)?;
+        let session_uuid_simple = session_uuid.to_simple().to_string();
+
+        let random_id = self.get_random_identifier();
+        let correlation_id = format!("A{}R{}", &session_uuid_simple[..8], random_id);
+        let elements: Vec<String> = (0..arguments.len() as u32).map(|i| array_element_id(&correlation_id, i)).collect();
+        let requested = Some(location.clone());
+        let policy = TimeoutPolicy::new(self.infra.clone(), self.locations.clone());
+
+        let command = Command::new(
+            CommandKind::Create,
+            Some(correlation_id.clone()),
+            Some(self.session_uuid.clone()),
+            Some(location),
+            Some(image),
+            command,
+            None,
+        ).with_environment(SESSION_DATA_ENV, session_data_dir(&self.session_uuid));
+        let command = function.env.iter()
+            .fold(command, |command, (key, value)| command.with_environment(key.clone(), value.clone()))
+            .with_array(arguments.len() as u32, parallelism as u32);
+
+        let mut payload = BytesMut::with_capacity(64);
+        command.encode(&mut payload).unwrap();
+        debug!("Sending command: \"{:?}\" (encoded: \"{:?}\").", command, payload);
+
+        let message = FutureRecord::to(&self.command_topic)
+            .key(&correlation_id)
+            .payload(payload.to_bytes());
+
+        // Mark every element as active before the array is scheduled, so they may be cancelled from the get-go
+        for element in &elements {
+            self.active.insert(element.clone(), ActiveJob{ session_uuid: self.session_uuid.clone(), cancelled: false, client_tx: self.client_tx.clone() });
+        }
+
+        let timeout = Timeout::After(Duration::from_secs(5));
+        if let Err(err) = self.producer.send(message, timeout).await {
+            for element in &elements { self.active.remove(element); }
+            return Err(ExecutorError::CommandScheduleError{ topic: self.command_topic.clone(), err: format!("{:?}", err) });
+        }
+
+        if let Err(err) = self.debug(format!("Scheduled job array '{}' with {} elements ('{}' to '{}') for function '{}'", correlation_id, elements.len(), elements[0], elements[elements.len() - 1], function.name)).await {
+            warn!("Could not notify client of job array '{}': {}", correlation_id, err);
+        }
+
+        // Wait until every element is completed, keeping those that wait for a turn alive while the others run
+        info!("Waiting until the {} elements of job array '{}' are finished...", elements.len(), correlation_id);
+        let finished = futures::future::join_all(elements.iter().map(|element| {
+            job_wait_finished(element, requested.as_deref(), false, &policy, self.heartbeats.clone(), self.states.clone(), self.active.clone())
+        }));
+        tokio::pin!(finished);
+        let results = loop {
+            tokio::select! {
+                results = &mut finished => { break results; },
+                _ = tokio::time::sleep(ARRAY_HEARTBEAT_INTERVAL) => { share_heartbeats(&elements, &self.heartbeats, &self.states); },
+            }
+        };
+
+        // Remove the elements
+        let mut values = Vec::with_capacity(results.len());
+        for (element, res) in elements.iter().zip(results) {
+            self.active.remove(element);
+            self.heartbeats.remove(element);
+            self.states.remove(element);
+            self.orders.remove(element);
+            values.push(match res {
+                Ok((value, _)) => Ok(value),
+                Err(err)       => Err(call_error(function.name.clone(), function.package.clone(), function.version.clone(), err)),
+            });
+        }
+        info!("OK, job array '{}' is finished", correlation_id);
+        Ok(values)
+    }
+
+    /// Runs some of the calls of a `map_call()` as a single job array, holding a slot of the session's limits for every element until the array is done. A lone call runs as a single job instead.
+    /// 
+    /// **Arguments**
+    ///  * `function`: The function to execute remotely.
+    ///  * `arguments`: The arguments of every call, as key/value pairs.
+    ///  * `keys`: The CallKey of every call, if its result may be cached.
+    ///  * `location`: The location/site where the calls will be executed, which has to support job arrays.
+    ///  * `concurrency`: The most calls that run at the same time.
+    /// 
+    /// **Returns**  
+    /// The value of every call (or the ExecutorError it failed with) in the order of `arguments`, or an ExecutorError if the job array could not be scheduled at all.
+    async fn map_array(
+        &self,
+        function: &FunctionExt,
+        arguments: Vec<HashMap<String, Value>>,
+        keys: Vec<Option<CallKey>>,
+        location: String,
+        concurrency: usize,
+    ) -> Result<Vec<Result<Value, ExecutorError>>, ExecutorError> {
+        if arguments.len() < 2 { return Ok(call_in_batches(self, function, arguments, Some(location), concurrency).await); }
+
+        let _permit = self.limits.acquire_many(&self.session_uuid, arguments.len()).await;
+        let results = self.schedule_array(function, &arguments, location, concurrency).await?;
+
+        // Remember the results for the next time (if they're successful ones)
+        if let Some(calls) = &self.calls {
+            for (key, res) in keys.iter().zip(&results) {
+                if let Some(key) = key { calls.record(key, res).await; }
+            }
+        }
+        Ok(results)
+    }
+}
+
+#[async_trait]
+impl VmExecutor for JobExecutor {
+    /* TIM */
+    /// **Edited: Synced Call up with the VmExecutor trait. This also means we implemented proper error handling in this function. Also waiting for a free slot if the session (or the driver) has too many jobs in flight, and reusing the results of pure functions.**
+    ///
+    /// Calls an external function on the given Brane infrastructure site.
+    /// 
+    /// **Arguments**  
+    ///  * `function`: The function to execute remotely.
+    ///  * `arguments`: A map of key/value pairs that are passed to the function to be executed.
+    ///  * `location`: The location/site where the function will be executed.
+    /// 
+    /// **Returns**  
+    /// The value of the external call if successful, or an ExecutorError otherwise.
+    async fn call(
+        &self,
+        function: FunctionExt,
+        arguments: HashMap<String, Value>,
+        location: Option<String>,
+    ) -> Result<Value, ExecutorError> {
+        // Reuse the result of an earlier call with the same arguments if the function is pure (and its package hasn't changed since)
+        let cached = self.calls.as_ref().and_then(|calls| CallKey::new(&function, &arguments).map(|key| (calls, key)));
+        if let Some((calls, key)) = &cached {
+            if let Some(value) = calls.get(key).await {
+                debug!("Reusing cached result of call '{}'", key);
+                if let Err(err) = self.debug(format!("Reusing the result of an earlier call to pure function '{}' with the same arguments", function.name)).await {
+                    warn!("Could not notify client of cached call to '{}': {}", function.name, err);
+                }
+                return Ok(value);
+            }
+        }
+
+        // Wait until the session may have another job in flight, so that it cannot flood the command topic
+        let _permit = self.limits.acquire(&self.session_uuid).await;
+
+        let lineage = self.lineage.as_ref().map(|_| (function.package.clone(), function.version.clone(), function.digest.clone(), location.clone(), SystemTime::now()));
+
+        let mut correlation_id = None;
+        let res = self.schedule_call(function, arguments, location, &mut correlation_id).await;
+
+        // Report what ran where (if the job got as far as being identified)
+        if let (Some(reporter), Some((package, version, digest, location, started)), Some(correlation_id)) = (&self.lineage, lineage, correlation_id) {
+            reporter.report(LineageRecord {
+                session  : self.session_uuid.clone(),
+                location : self.locations.get(&correlation_id).map(|l| l.clone()).or(location),
+                correlation_id,
+                package,
+                version,
+                digest,
+                started,
+                finished : SystemTime::now(),
+                outcome  : match &res { Ok(_) => LineageOutcome::Success, Err(err) => LineageOutcome::Failure{ error: format!("{}", err) } },
+            });
+        }
+
+        // Remember the result for the next time (if it's a successful one)
+        if let Some((calls, key)) = cached { calls.record(&key, &res).await; }
+        res
+    }
     /*******/
 
+    /// Checks an external function call in a dry run, without scheduling a job for it.
+    /// 
+    /// **Arguments**  
+    ///  * `function`: The function that would be executed remotely.
+    ///  * `arguments`: A map of key/value pairs that would be passed to the function.
+    ///  * `location`: The location/site where the function would be executed, which has to be in the infrastructure file (if given). If omitted, a location has to satisfy the requirements of the function's package.
+    ///  * `default`: The value to return, as synthesized by the VM.
+    /// 
+    /// **Returns**  
+    /// The given default if the call would be possible, or an ExecutorError::UnknownLocationError or ExecutorError::LocationSelectionError otherwise.
+    async fn dry_call(
+        &self,
+        function: FunctionExt,
+        _arguments: HashMap<String, Value>,
+        location: Option<String>,
+        default: Value,
+    ) -> Result<Value, ExecutorError> {
+        let location = match location {
+            Some(location) => {
+                if let Err(err) = self.infra.get_location_metadata(&location) {
+                    return Err(ExecutorError::UnknownLocationError{ correlation_id: format!("dry run of '{}'", function.name), location, err: format!("{}", err) });
+                }
+                location
+            },
+            None => self.pick_location(&function).await?,
+        };
+
+        if let Err(err) = self.debug(format!("Dry run: not scheduling function '{}' (package '{}', version {}) on '{}'", function.name, function.package, function.version, location)).await {
+            warn!("Could not notify client of dry run: {}", err);
+        }
+        Ok(default)
+    }
+
+    /// Calls an external function once for every set of arguments, as job arrays if the location supports them (see `uses_job_array()`) or one job per call otherwise.
+    /// 
+    /// Calls to pure functions reuse the results of earlier calls with the same arguments. The others are split over job arrays (see `plan_arrays()`), of which every element counts as a job in flight for the limits of the session.
+    /// 
+    /// **Arguments**  
+    ///  * `function`: The function to execute remotely.
+    ///  * `arguments`: The arguments of every call, as key/value pairs.
+    ///  * `location`: The location/site where the calls will be executed. If omitted, one is picked based on the requirements of the function's package.
+    /// 
+    /// **Returns**  
+    /// The value of every call (or the ExecutorError it failed with) in the order of `arguments`, or an ExecutorError if the calls could not be scheduled at all.
+    async fn map_call(
+        &self,
+        function: FunctionExt,
+        arguments: Vec<HashMap<String, Value>>,
+        location: Option<String>,
+    ) -> Result<Vec<Result<Value, ExecutorError>>, ExecutorError> {
+        let concurrency = function.concurrency.map(|concurrency| concurrency as usize).unwrap_or(DEFAULT_MAP_CONCURRENCY);
+        if function.detached || arguments.len() < 2 {
+            return Ok(call_in_batches(self, &function, arguments, location, concurrency).await);
+        }
+
+        // Pick the location once, so that the calls don't end up on different ones
+        let location = match location {
+            Some(location) => location,
+            None           => self.pick_location(&function).await?,
+        };
+        let metadata = self.infra.get_location_metadata(&location)
+            .map_err(|err| ExecutorError::UnknownLocationError{ correlation_id: format!("map_call of '{}'", function.name), location: location.clone(), err: format!("{}", err) })?;
+        if !uses_job_array(&function, arguments.len(), &metadata) {
+            return Ok(call_in_batches(self, &function, arguments, Some(location), concurrency).await);
+        }
+
+        // Reuse the results of earlier calls with the same arguments if the function is pure (and its package hasn't changed since)
+        let keys: Vec<Option<CallKey>> = arguments.iter().map(|arguments| self.calls.as_ref().and_then(|_| CallKey::new(&function, arguments))).collect();
+        let mut values: Vec<Option<Result<Value, ExecutorError>>> = Vec::with_capacity(arguments.len());
+        for key in &keys {
+            values.push(match (&self.calls, key) {
+                (Some(calls), Some(key)) => calls.get(key).await.map(Ok),
+                _                        => None,
+            });
+        }
+        let pending: Vec<usize> = (0..arguments.len()).filter(|&index| values[index].is_none()).collect();
+        if pending.len() < arguments.len() {
+            if let Err(err) = self.debug(format!("Reusing the results of {} earlier call(s) to pure function '{}' with the same arguments", arguments.len() - pending.len(), function.name)).await {
+                warn!("Could not notify client of cached calls to '{}': {}", function.name, err);
+            }
+        }
+
+        // Run the others as job arrays that fit in the limits of the session
+        let arrays = plan_arrays(&arguments, &pending, self.limits.max_jobs());
+        let results = futures::future::join_all(arrays.iter().map(|array| {
+            let arguments = array.iter().map(|&index| arguments[index].clone()).collect();
+            let keys = array.iter().map(|&index| keys[index].clone()).collect();
+            self.map_array(&function, arguments, keys, location.clone(), concurrency)
+        })).await;
+        for (array, results) in arrays.iter().zip(results) {
+            for (&index, res) in array.iter().zip(results?) { values[index] = Some(res); }
+        }
+        Ok(values.into_iter().map(|value| value.expect("Every call is either cached or run")).collect())
+    }
+
     /* TIM */
     /// **Edited: Synced Call up with the VmExecutor trait.**
     ///
@@ -470,13 +1520,16 @@ impl VmExecutor for JobExecutor {
             debug: Some(text),
             stderr: None,
             stdout: None,
+            trace: None,
+            cached: None,
+            diff: None,
+            sequence: None,
+            client: None,
+            input: None,
         };
 
-        // use try_send instead, since we don't _really_ care if the debug message doesn't go to the other side
-        if let Err(reason) = self.client_tx.try_send(Ok(reply)) {
-            return Err(ExecutorError::ClientTxError{ err: format!("{}", reason) });
-        }
-        Ok(())
+        // Debug messages never wait for the client (the oldest are dropped instead), so this only fails if it's gone
+        self.client_tx.send(Ok(reply)).await.map_err(client_error)
     }
     /*******/
 
@@ -499,13 +1552,16 @@ impl VmExecutor for JobExecutor {
             debug: None,
             stderr: Some(text),
             stdout: None,
+            trace: None,
+            cached: None,
+            diff: None,
+            sequence: None,
+            client: None,
+            input: None,
         };
 
-        // Use a timeout of say a minute
-        if let Err(reason) = tokio::time::timeout(std::time::Duration::from_secs(60), self.client_tx.send(Ok(reply))).await {
-            return Err(ExecutorError::ClientTxError{ err: format!("{}", reason) });
-        }
-        Ok(())
+        // Waits for room (up to the channel's timeout) only if there's no stderr waiting to merge it with
+        self.client_tx.send(Ok(reply)).await.map_err(client_error)
     }
     /*******/
 
@@ -528,33 +1584,41 @@ impl VmExecutor for JobExecutor {
             debug: None,
             stderr: None,
             stdout: Some(text),
+            trace: None,
+            cached: None,
+            diff: None,
+            sequence: None,
+            client: None,
+            input: None,
         };
 
-        // Use a timeout of say a minute
-        if let Err(reason) = tokio::time::timeout(std::time::Duration::from_secs(60), self.client_tx.send(Ok(reply))).await {
-            return Err(ExecutorError::ClientTxError{ err: format!("{}", reason) });
-        }
-        Ok(())
+        // Waits for room (up to the channel's timeout) only if there's no stdout waiting to merge it with
+        self.client_tx.send(Ok(reply)).await.map_err(client_error)
     }
     /*******/
 
     /* TIM */
-    // TODO????
-    /// **Edited: Synced Call up with the VmExecutor trait.**
+    /// **Edited: Synced Call up with the VmExecutor trait. Now actually waiting for the service.**
     ///
-    /// Launches a new job and waits until it has reached the target ServiceState.
+    /// Waits until the job behind the given (detached) service has reached the target ServiceState.
     /// 
     /// **Arguments**  
-    ///  * `text`: The message to send.
+    ///  * `service`: The identifier of the service (i.e., the correlation ID of its job).
+    ///  * `state`: The state to wait for.
     /// 
     /// **Returns**  
-    /// Nothing if successfull, or an ExecutorError otherwise.
+    /// Nothing if the state was reached, or an ExecutorError if the service failed or timed out before that.
     async fn wait_until(
         &self,
-        _service: String,
-        _state: brane_bvm::executor::ServiceState,
+        service: String,
+        state: ServiceState,
     ) -> Result<(), ExecutorError> {
-        Ok(())
+        // Mark the job as waited for, so that we may cancel it while we wait (without forgetting that it has been cancelled already)
+        let marked = mark_active(&self.active, &service, ActiveJob{ session_uuid: self.session_uuid.clone(), cancelled: false, client_tx: self.client_tx.clone() });
+        let policy = TimeoutPolicy::new(self.infra.clone(), self.locations.clone());
+        let res = wait_until_service(&service, state, &policy, self.heartbeats.clone(), self.states.clone(), self.active.clone()).await;
+        if marked { self.active.remove(&service); }
+        res
     }
     /*******/
 }
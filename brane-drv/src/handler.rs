@@ -1,61 +1,214 @@
-use crate::executor::JobExecutor;
-use crate::{grpc, packages};
+use crate::calls::CallCache;
+use crate::client::{self, ClientReceiver, ClientSender};
+use crate::executor::{cancel_jobs, release_resumed, resume_session, ActiveJob, JobExecutor, ResumedJob, TimeoutPolicy};
+use crate::limits::JobLimits;
+use crate::lineage::LineageReporter;
+use crate::multiplex::{busy_status, Multiplexer};
+use crate::outputs::{JobOutput, JobOutputs};
+use crate::sessions::{expired_status, SessionStore};
+use crate::statements::{StatementCache, StatementGuard, StatementStatus};
+use crate::{grpc, metrics, packages};
 use anyhow::Result;
-use brane_bvm::vm::{Vm, VmOptions, VmState, VmError};
+use brane_bvm::args::args_from_json;
+use brane_bvm::diff::StateDiff;
+use brane_bvm::trace::TraceEntry;
+use brane_bvm::vm::{CancelToken, Vm, VmOptions, VmError};
 use brane_cfg::Infrastructure;
 use brane_dsl::{Compiler, CompilerOptions, Lang};
+use brane_job::interface::{Command, CommandKind, FailureResult};
 use brane_shr::jobs::JobStatus;
+use bytes::BytesMut;
 use dashmap::DashMap;
-use rdkafka::producer::FutureProducer;
+use prost::Message as _;
+use rdkafka::message::ToBytes;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::util::Timeout;
+use specifications::version::{Compatibility, Version};
+use std::str::FromStr;
 use std::sync::Arc;
-use std::time::SystemTime;
-use tokio::sync::mpsc;
-use tokio_stream::wrappers::ReceiverStream;
-use tonic::{Request, Response, Status};
+use std::time::{Duration, SystemTime};
+use tonic::{Code, Request, Response, Status};
 use uuid::Uuid;
 
+/// The time between two checks whether a statement that was sent again has finished.
+const STATEMENT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// The longest time between two checks for sessions that have been idle for longer than the session TTL.
+pub const SESSION_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
 #[derive(Clone)]
 pub struct DriverHandler {
     pub command_topic: String,
     pub graphql_url: String,
     pub producer: FutureProducer,
-    pub sessions: Arc<DashMap<String, VmState>>,
+    pub sessions: Arc<SessionStore>,
     pub states: Arc<DashMap<String, JobStatus>>,
     pub heartbeats: Arc<DashMap<String, SystemTime>>,
     pub locations: Arc<DashMap<String, String>>,
+    pub outputs: Arc<JobOutputs>,
+    pub active: Arc<DashMap<String, ActiveJob>>,
+    /// The order of the latest state change that the event monitor processed for every job.
+    pub orders: Arc<DashMap<String, u32>>,
+    pub resumed: Arc<DashMap<String, ResumedJob>>,
+    /// The token to cancel the statement that each session is currently running with.
+    pub running: Arc<DashMap<String, CancelToken>>,
+    pub orphan_horizon: Duration,
+    /// The status of every statement that was sent with a token, so that sending it again does not run it twice.
+    pub statements: Arc<StatementCache>,
+    /// Reports the lineage of every job to the API, unless disabled.
+    pub lineage: Option<LineageReporter>,
+    /// Limits the number of jobs in flight, per session (by UUID) and in total.
+    pub limits: Arc<JobLimits>,
+    /// The results of earlier calls to pure functions, unless caching is disabled.
+    pub calls: Option<Arc<CallCache>>,
+    /// Lets the statements of clients that share a session run one at a time.
+    pub multiplexer: Arc<Multiplexer>,
+    /// The maximum number of instructions that a single statement may execute, if limited.
+    pub max_instructions: Option<u64>,
     pub infra: Infrastructure,
 }
 
+impl DriverHandler {
+    /// Expires the sessions that have been idle for at least the given time (see `SessionStore::expire_idle()`). Sessions that are running a statement are not idle.
+    /// 
+    /// **Arguments**
+    ///  * `ttl`: The time after which idle sessions expire.
+    /// 
+    /// **Returns**  
+    /// The number of sessions that expired.
+    pub fn expire_idle_sessions(&self, ttl: Duration) -> usize {
+        for running in self.running.iter() { self.sessions.touch(running.key()); }
+        let expired = self.sessions.expire_idle(ttl);
+        for uuid in &expired {
+            self.multiplexer.remove(uuid);
+            self.release_resumed(uuid);
+            info!("Session '{}' expired after being idle for at least {}s.", uuid, ttl.as_secs());
+        }
+        metrics::EXPIRED_SESSIONS.inc_by(expired.len() as u64);
+        metrics::ACTIVE_SESSIONS.set(self.sessions.active() as i64);
+        expired.len()
+    }
+
+    /// Forgets the jobs that the given session resumed waiting for after a restart but never took, together with what the event monitor knows about them.
+    /// 
+    /// **Arguments**
+    ///  * `uuid`: The UUID of the session that was closed or expired.
+    fn release_resumed(&self, uuid: &str) {
+        for correlation_id in release_resumed(&self.resumed, uuid) {
+            debug!("Releasing resumed job '{}' of session '{}'", correlation_id, uuid);
+            self.states.remove(&correlation_id);
+            self.orders.remove(&correlation_id);
+        }
+    }
+}
+
 #[tonic::async_trait]
 impl grpc::DriverService for DriverHandler {
-    type ExecuteStream = ReceiverStream<Result<grpc::ExecuteReply, Status>>;
+    type ExecuteStream = ClientReceiver;
+    type FollowStream = ClientReceiver;
 
-    ///
-    ///
-    ///
+    /// Creates a new session, or attaches to an existing one, and tells the client whether its version is compatible with ours.
+    /// 
+    /// We only report the verdict; it's up to the client to refuse to continue, so that the check may be skipped during development.
+    /// 
+    /// When attaching to an existing session, we resume waiting for the jobs it was still waiting for when the driver restarted (see `executor::resume_session()`). Clients that identify themselves are counted as users of the session until they close it.
+    /// 
+    /// **Arguments**
+    ///  * `request`: The request with the version of the client, the client itself and the session to attach to, if any.
+    /// 
+    /// **Returns**  
+    /// The UUID of the session, our version and the verdict, a 'failed precondition' Status if the session to attach to has expired or a 'not found' Status if we do not know it at all.
     async fn create_session(
         &self,
-        _request: Request<grpc::CreateSessionRequest>,
+        request: Request<grpc::CreateSessionRequest>,
     ) -> Result<Response<grpc::CreateSessionReply>, Status> {
-        let uuid = Uuid::new_v4().to_string();
+        let request = request.into_inner();
+        let driver_version = match Version::from_str(env!("CARGO_PKG_VERSION")) {
+            Ok(version) => version,
+            Err(err)    => { return Err(Status::internal(format!("Could not parse driver version '{}': {}", env!("CARGO_PKG_VERSION"), err))); }
+        };
+        let compatibility = check_client_version(&driver_version, request.client_version.as_deref());
+        if compatibility != grpc::Compatibility::Compatible {
+            warn!("Client with version '{}' may not be compatible with this driver (version {}): {:?}", request.client_version.as_deref().unwrap_or("unknown"), driver_version, compatibility);
+        }
 
-        let reply = grpc::CreateSessionReply { uuid };
+        let uuid = match request.attach {
+            Some(uuid) => {
+                if self.sessions.is_expired(&uuid) { return Err(expired_status(&uuid)); }
+                if !self.sessions.is_known(&uuid) { return Err(Status::not_found(format!("Unknown session '{}'", uuid))); }
+                let policy = TimeoutPolicy::new(self.infra.clone(), self.locations.clone());
+                let resumed = resume_session(&uuid, &self.sessions, &self.resumed, self.orphan_horizon, &policy, self.heartbeats.clone(), self.states.clone(), self.active.clone());
+                if resumed > 0 { info!("Session '{}' reattached with {} pending job(s).", uuid, resumed); }
+                uuid
+            },
+            None => Uuid::new_v4().to_string(),
+        };
+        self.sessions.touch(&uuid);
+        if let Some(client) = &request.client { self.sessions.attach(&uuid, client); }
+        metrics::ACTIVE_SESSIONS.set(self.sessions.active() as i64);
+        let reply = grpc::CreateSessionReply {
+            uuid,
+            driver_version: driver_version.to_string(),
+            compatibility: compatibility as i32,
+        };
         Ok(Response::new(reply))
     }
 
-    ///
-    ///
-    ///
+    /// Runs a statement in the given session, streaming its output back to the client.
+    /// 
+    /// If the statement comes with a token that we have seen before (because the client lost its connection and sends it again), it is not run again; instead, the stream returns the status of the earlier run, waiting for it to finish if need be.
+    /// 
+    /// Statements in the same session run one at a time, even if they come from different clients: a statement that arrives while another one is busy waits for its turn, or is refused with a 'resource exhausted' status if the driver rejects such statements (see `Multiplexer` and `busy_status()`).
+    /// 
+    /// **Arguments**
+    ///  * `request`: The request with the session, the statement and its options.
+    /// 
+    /// **Returns**  
+    /// The stream of replies for the statement, numbered with its place in the session, or a 'failed precondition' Status if the session has expired.
     async fn execute(
         &self,
         request: Request<grpc::ExecuteRequest>,
     ) -> Result<Response<Self::ExecuteStream>, Status> {
         let request = request.into_inner();
-        let package_index = packages::get_package_index(&self.graphql_url).await.unwrap();
+        if self.sessions.is_expired(&request.uuid) { return Err(expired_status(&request.uuid)); }
+        self.sessions.touch(&request.uuid);
+        if let Some(token) = &request.token {
+            if let Some(status) = self.statements.begin(&request.uuid, token) {
+                info!("Session '{}' sent statement '{}' again; returning its status instead of running it twice.", request.uuid, token);
+                let (tx, rx) = client::channel(client::DEFAULT_CAPACITY);
+                tokio::spawn(replay_statement(self.statements.clone(), request.uuid, token.clone(), status, tx));
+                return Ok(Response::new(rx));
+            }
+        }
+
+        // Take a ticket, so the statement runs after the ones that other clients sent to the session before it
+        let ticket = match self.multiplexer.admit(&request.uuid, request.client.clone()) {
+            Ok(ticket) => ticket,
+            Err(err)   => {
+                // Forget the token, so the client may send the statement again once the session is free
+                if let Some(token) = &request.token { self.statements.forget(&request.uuid, token); }
+                info!("Refusing statement: {}", err);
+                return Err(busy_status(&err));
+            }
+        };
+
+        let package_index = match packages::get_package_index(&self.graphql_url).await {
+            Ok(package_index) => package_index,
+            Err(err)          => {
+                // Forget the token, so the client may send the statement again once the index can be fetched
+                if let Some(token) = &request.token { self.statements.forget(&request.uuid, token); }
+                error!("Could not fetch the package index: {}", err);
+                return Err(Status::unavailable(format!("Could not fetch the package index: {}", err)));
+            }
+        };
         let sessions = self.sessions.clone();
+        let running = self.running.clone();
+        // Makes sure the statement is marked as done, even if running it panics
+        let mut guard = StatementGuard::new(self.statements.clone(), request.uuid.clone(), request.token.clone());
+        let max_instructions = self.max_instructions;
 
-        // Prepare gRPC stream between client and (this) driver.
-        let (tx, rx) = mpsc::channel::<Result<grpc::ExecuteReply, Status>>(10);
+        // Prepare gRPC stream between client and (this) driver. It's bounded, but a slow client only makes us drop debug messages and merge output (see `client`).
+        let (tx, rx) = client::channel(client::DEFAULT_CAPACITY);
+        let tx = ticket.tag(&tx);
 
         let executor = JobExecutor {
             client_tx: tx.clone(),
@@ -65,12 +218,40 @@ impl grpc::DriverService for DriverHandler {
             states: self.states.clone(),
             heartbeats: self.heartbeats.clone(),
             locations: self.locations.clone(),
+            active: self.active.clone(),
+            orders: self.orders.clone(),
+            sessions: self.sessions.clone(),
+            resumed: self.resumed.clone(),
+            services: Arc::new(DashMap::new()),
+            lineage: self.lineage.clone(),
+            limits: self.limits.clone(),
+            calls: self.calls.clone(),
             infra: self.infra.clone(),
         };
 
         /* TIM */
-        let vm_state = sessions.get(&request.uuid).as_deref().cloned();
         tokio::spawn(async move {
+            // Wait for the statements before this one to finish; only then is the session's state final
+            let ahead = ticket.ahead();
+            if ahead > 0 {
+                let reply = grpc::ExecuteReply {
+                    close: false,
+                    debug: Some(format!("Statement #{} waits for {} statement(s) of the session to finish.", ticket.sequence(), ahead)),
+                    stderr: None,
+                    stdout: None,
+                    trace: None,
+                    cached: None,
+                    diff: None,
+                    sequence: None,
+                    client: None,
+                    input: None,
+                };
+                let _ = tx.try_send(Ok(reply));
+                ticket.turn().await;
+            }
+            ticket.announce(&request.input);
+            let vm_state = sessions.state(&request.uuid);
+
             let options = CompilerOptions::new(Lang::BraneScript);
             let mut compiler = Compiler::new(options, package_index.clone());
 
@@ -79,16 +260,32 @@ impl grpc::DriverService for DriverHandler {
                 Ok(function) => function,
                 Err(error) => {
                     let status = Status::invalid_argument(error.to_string());
-                    tx.send(Err(status)).await.unwrap();
+                    close_statement(&tx, &mut guard, Err(status)).await;
+                    return;
+                }
+            };
+
+            // Parse the script arguments, if the client sent new ones
+            let args = match request.args.as_deref().map(args_from_json).transpose() {
+                Ok(args) => args,
+                Err(err) => {
+                    let status = Status::invalid_argument(err.to_string());
+                    close_statement(&tx, &mut guard, Err(status)).await;
                     return;
                 }
             };
 
             // Restore VM state corresponding to the session, if any.
             // We do this in a block to make sure vm doesn't exist anymore when we .await on tx.send
-            let res: Result<(), VmError> = {
+            let trace = request.trace.unwrap_or(false);
+            let dry_run = request.dry_run.unwrap_or(false);
+            let want_diff = request.diff.unwrap_or(false);
+            let (res, trace, diff): (Result<(), VmError>, Option<Vec<TraceEntry>>, Option<StateDiff>) = {
+                // Remember the state from before the statement, to tell the client what it changed (if it wants to know)
+                let old_state = if want_diff { Some(vm_state.clone().unwrap_or_default()) } else { None };
+
                 // Create the VM with state if we have one, or otherwise without
-                let mut vm = if let Some(vm_state) = vm_state {
+                let vm = if let Some(vm_state) = vm_state {
                     debug!("Restore VM with state:\n{:?}", vm_state);
                     match Vm::new_with_state(executor, Some(package_index), vm_state) {
                         Ok(vm)      => Ok(vm),
@@ -98,6 +295,7 @@ impl grpc::DriverService for DriverHandler {
                     debug!("No VM state to restore, creating new VM.");
                     let options = VmOptions {
                         clear_after_main: true,
+                        compact_print: true,
                         ..Default::default()
                     };
                     match Vm::new_with(executor, Some(package_index), Some(options)) {
@@ -106,66 +304,356 @@ impl grpc::DriverService for DriverHandler {
                     }
                 };
 
+                // Expose the new arguments, if any
+                let mut vm = match (vm, args) {
+                    (Ok(mut vm), Some(args)) => vm.set_args(args).map(|_| vm),
+                    (vm, _)                  => vm,
+                };
+
                 // Switch on the creation state of the VM
                 match vm {
                     Ok(ref mut vm) => {
-                        // We can continue to run it
+                        // We can continue to run it, tracing and simulating only this statement if asked (restored sessions get our current limit too)
+                        vm.set_trace(trace);
+                        vm.set_dry_run(dry_run);
+                        vm.set_max_instructions(max_instructions);
+
+                        // Allow the Cancel RPC to abort the statement, even if it never makes an external call
+                        let cancel = CancelToken::new();
+                        running.insert(request.uuid.clone(), cancel.clone());
+                        vm.set_cancel_token(cancel.clone());
 
                         // TEMP: needed because the VM is not completely `send`.
                         // futures::executor::block_on(vm.main(function));
                         let res = futures::executor::block_on(vm.main(function));
+                        running.remove_if(&request.uuid, |_, token| token.ptr_eq(&cancel));
+                        let entries = vm.take_trace();
+                        vm.set_trace(false);
+                        vm.set_dry_run(false);
 
-                        // Already store the state of the VM before erroring to let Tokio allow the .await on tx.send
+                        // Already store the state of the VM before erroring to let Tokio allow the .await on tx.send. A dry run only worked on a copy of the session, which we discard (so the values it simulated don't end up in the session).
                         let vm_state = vm.capture_state();
-                        sessions.insert(request.uuid, vm_state);
+                        let diff = old_state.map(|old_state| old_state.diff(&vm_state));
+                        if dry_run {
+                            sessions.touch(&request.uuid);
+                        } else if let Err(err) = sessions.set_state(&request.uuid, vm_state) {
+                            warn!("Could not persist session: {}", err);
+                        }
+                        metrics::ACTIVE_SESSIONS.set(sessions.active() as i64);
 
                         // Done
-                        res
+                        (res, if trace { Some(entries) } else { None }, diff)
                     },
                     // We couldn't create it
-                    Err(reason) => Err(reason),
+                    Err(reason) => (Err(reason), None, None),
                 }
             };
 
+            // The trace goes along with the closing reply, whichever it is
+            let trace = match trace.map(|trace| serde_json::to_string(&trace)).transpose() {
+                Ok(trace) => trace,
+                Err(err)  => { error!("Could not serialize execution trace: {}", err); None },
+            };
+            let diff = match diff.map(|diff| serde_json::to_string(&diff)).transpose() {
+                Ok(diff) => diff,
+                Err(err) => { error!("Could not serialize state diff: {}", err); None },
+            };
+
             // Make vm a non-muteable reference so it allows the await
-            match res {
+            let reply = match res {
                 Ok(()) => {
                     // Send a debug message to client saying it all worked out
                     debug!("Completed execution.");
-
-                    // Create the reply text
-                    let msg = String::from("Driver completed execution.");
-                    let reply = grpc::ExecuteReply {
+                    grpc::ExecuteReply {
                         close: true,
-                        debug: Some(msg.clone()),
+                        debug: Some(String::from("Driver completed execution.")),
                         stderr: None,
                         stdout: None,
-                    };
-
-                    // Send it to the client
-                    if let Err(err) = tx.send(Ok(reply)).await {
-                        error!("Could not send debug message '{}' to client: {}", msg, err);
+                        trace,
+                        cached: None,
+                        diff,
+                        sequence: None,
+                        client: None,
+                        input: None,
                     }
                 },
-                Err(err) => {
-                    // Create the reply text
-                    let msg = format!("{}", err);
-                    let reply = grpc::ExecuteReply {
-                        close: true,
-                        debug: None,
-                        stderr: Some(msg.clone()),
-                        stdout: None,
-                    };
+                Err(err) => grpc::ExecuteReply {
+                    close: true,
+                    debug: None,
+                    stderr: Some(format!("{}", err)),
+                    stdout: None,
+                    trace,
+                    cached: None,
+                    diff,
+                    sequence: None,
+                    client: None,
+                    input: None,
+                },
+            };
+            close_statement(&tx, &mut guard, Ok(reply)).await;
 
-                    // Send it to the client
-                    if let Err(err) = tx.send(Ok(reply)).await {
-                        error!("Could not send VM error '{}' to client: {}", msg, err);
-                    }
-                }
-            }
+            // Only now may the next statement run, so followers see the replies of every statement in order
+            drop(ticket);
         });
         /*******/
 
-        Ok(Response::new(ReceiverStream::new(rx)))
+        Ok(Response::new(rx))
     }
+
+    /// Returns the output (stdout, stderr, exit code or result value) of a job that has recently failed or finished.
+    /// 
+    /// **Arguments**
+    ///  * `request`: The request with the correlation ID of the job to get the output of.
+    /// 
+    /// **Returns**  
+    /// The output of the job, or a 'not found' Status if we don't know (or don't remember) the job.
+    async fn get_job_output(
+        &self,
+        request: Request<grpc::GetJobOutputRequest>,
+    ) -> Result<Response<grpc::GetJobOutputReply>, Status> {
+        let request = request.into_inner();
+
+        // Try to find the output of the job
+        let reply = match self.outputs.get(&request.job_id) {
+            Some(JobOutput::Finished{ res }) => grpc::GetJobOutputReply {
+                failed : false,
+                code   : None,
+                stdout : String::new(),
+                stderr : String::new(),
+                value  : Some(res),
+            },
+            Some(JobOutput::Failed{ res }) => {
+                // Parse the code/stdout/stderr triplet, falling back to the raw output (with an unknown code) if it's mangled
+                match serde_json::from_str::<FailureResult>(&res) {
+                    Ok(result) => grpc::GetJobOutputReply {
+                        failed : true,
+                        code   : Some(result.code),
+                        stdout : result.stdout,
+                        stderr : result.stderr,
+                        value  : None,
+                    },
+                    Err(_) => grpc::GetJobOutputReply {
+                        failed : true,
+                        code   : None,
+                        stdout : String::new(),
+                        stderr : res,
+                        value  : None,
+                    },
+                }
+            },
+            None => { return Err(Status::not_found(format!("No output known for job '{}' (it may still be running, or its output has been evicted)", request.job_id))); },
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    /// Cancels the jobs that the given session is currently waiting for.
+    /// 
+    /// Every such job is marked as cancelled (which makes its pending call return with an error) and a Stop command is published for it (see `executor::cancel_jobs()`). Jobs that have already been cancelled, or that complete concurrently, are skipped, so no job is ever stopped twice.
+    /// 
+    /// **Arguments**
+    ///  * `request`: The request with the UUID of the session to cancel the jobs of.
+    /// 
+    /// **Returns**  
+    /// The correlation IDs of the jobs that we cancelled, an 'internal' Status if we could not publish a Stop command (after which cancelling again picks up the jobs that were not stopped yet), or a 'failed precondition' Status if the session has expired.
+    async fn cancel(
+        &self,
+        request: Request<grpc::CancelRequest>,
+    ) -> Result<Response<grpc::CancelReply>, Status> {
+        let request = request.into_inner();
+        if self.sessions.is_expired(&request.uuid) { return Err(expired_status(&request.uuid)); }
+
+        // Abort the statement itself, in case it's busy computing rather than waiting for a job
+        if let Some(token) = self.running.get(&request.uuid) { token.cancel(); }
+
+        // Mark the session's jobs as cancelled and publish a Stop for each of them
+        let uuid = request.uuid.clone();
+        let res = cancel_jobs(&self.active, &request.uuid, |correlation_id| {
+            let location = self.locations.get(&correlation_id).map(|l| l.clone());
+            let command = Command::new(
+                CommandKind::Stop,
+                Some(correlation_id.clone()),
+                Some(uuid.clone()),
+                location,
+                None,
+                vec![],
+                None,
+            );
+
+            let mut payload = BytesMut::with_capacity(64);
+            command.encode(&mut payload).unwrap();
+            debug!("Sending command: \"{:?}\" (encoded: \"{:?}\").", command, payload);
+
+            async move {
+                let message = FutureRecord::to(&self.command_topic)
+                    .key(&correlation_id)
+                    .payload(payload.to_bytes());
+
+                let timeout = Timeout::After(Duration::from_secs(5));
+                self.producer.send(message, timeout).await.map(|_| ()).map_err(|(err, _)| err)
+            }
+        }).await;
+        let job_ids = match res {
+            Ok(job_ids)                => job_ids,
+            Err((correlation_id, err)) => { return Err(Status::internal(format!("Could not send Stop command for job '{}' on Kafka topic '{}': {:?}", correlation_id, self.command_topic, err))); }
+        };
+        info!("Cancelled {} job(s) for session '{}'.", job_ids.len(), request.uuid);
+
+        Ok(Response::new(grpc::CancelReply { job_ids }))
+    }
+
+    /// Returns a summary of the globals (variables, functions and imported packages) that the given session has defined so far.
+    /// 
+    /// **Arguments**
+    ///  * `request`: The request with the UUID of the session to summarize.
+    /// 
+    /// **Returns**  
+    /// The globals of the session, which are empty if the session has not run anything yet, or a 'failed precondition' Status if the session has expired.
+    async fn get_globals(
+        &self,
+        request: Request<grpc::GetGlobalsRequest>,
+    ) -> Result<Response<grpc::GetGlobalsReply>, Status> {
+        let request = request.into_inner();
+        if self.sessions.is_expired(&request.uuid) { return Err(expired_status(&request.uuid)); }
+        let state = self.sessions.state(&request.uuid).unwrap_or_default();
+
+        let reply = grpc::GetGlobalsReply {
+            variables : state.variables().into_iter().map(|(name, data_type)| grpc::GlobalVariable{ name, data_type }).collect(),
+            functions : state.functions().into_iter().map(|(name, signature)| grpc::GlobalFunction{ doc: state.doc(&name), name, signature }).collect(),
+            packages  : state.packages().into_iter().map(|(name, version)| grpc::ImportedPackage{ name, version: version.to_string() }).collect(),
+        };
+        Ok(Response::new(reply))
+    }
+
+    /// Follows the given session: streams the replies of every statement that runs in it from now on, whichever client sent it.
+    /// 
+    /// Every statement starts with a reply that carries the statement itself, and all replies are numbered with the statement and name the client that sent it (if it identified itself), so that a client can leave out its own statements.
+    /// 
+    /// **Arguments**
+    ///  * `request`: The request with the UUID of the session to follow.
+    /// 
+    /// **Returns**  
+    /// The stream of replies, which ends once the session is closed or expires, a 'failed precondition' Status if the session has expired already or a 'not found' Status if we do not know it at all.
+    async fn follow(
+        &self,
+        request: Request<grpc::FollowRequest>,
+    ) -> Result<Response<Self::FollowStream>, Status> {
+        let request = request.into_inner();
+        if self.sessions.is_expired(&request.uuid) { return Err(expired_status(&request.uuid)); }
+        if !self.sessions.is_known(&request.uuid) { return Err(Status::not_found(format!("Unknown session '{}'", request.uuid))); }
+        let rx = self.multiplexer.follow(&request.uuid, client::DEFAULT_CAPACITY);
+        info!("Session '{}' has {} follower(s).", request.uuid, self.multiplexer.followers(&request.uuid));
+        Ok(Response::new(rx))
+    }
+
+    /// Closes the given session: aborts the statement it is running (if any) and forgets its state, instead of waiting for it to expire. As long as other clients that created or attached to the session use it, the client is only counted out instead.
+    /// 
+    /// Jobs that the session still waits for keep running; use Cancel first to stop them.
+    /// 
+    /// **Arguments**
+    ///  * `request`: The request with the UUID of the session to close and the client that is done with it.
+    /// 
+    /// **Returns**  
+    /// Whether we closed the session and how many other clients still use it.
+    async fn close_session(
+        &self,
+        request: Request<grpc::CloseSessionRequest>,
+    ) -> Result<Response<grpc::CloseSessionReply>, Status> {
+        let request = request.into_inner();
+        let clients = self.sessions.detach(&request.uuid, request.client.as_deref());
+        if clients > 0 {
+            info!("Session '{}' stays open for {} other client(s).", request.uuid, clients);
+            return Ok(Response::new(grpc::CloseSessionReply { closed: false, clients: clients as u32 }));
+        }
+        if let Some(token) = self.running.get(&request.uuid) { token.cancel(); }
+
+        let closed = match self.sessions.close(&request.uuid) {
+            Ok(closed) => closed,
+            Err(err)   => { warn!("Could not remove closed session: {}", err); true },
+        };
+        self.multiplexer.remove(&request.uuid);
+        self.release_resumed(&request.uuid);
+        metrics::ACTIVE_SESSIONS.set(self.sessions.active() as i64);
+        if closed { info!("Session '{}' closed.", request.uuid); }
+
+        Ok(Response::new(grpc::CloseSessionReply { closed, clients: 0 }))
+    }
+}
+
+
+
+/// Checks whether a client with the given version can work with a driver of the given version.
+/// 
+/// **Arguments**
+///  * `driver_version`: The version of the driver.
+///  * `client_version`: The version the client sent, if any.
+/// 
+/// **Returns**  
+/// The verdict to send back to the client. This is Unknown if the client did not send a (valid) version.
+pub fn check_client_version(driver_version: &Version, client_version: Option<&str>) -> grpc::Compatibility {
+    let client_version = match client_version.map(Version::from_str) {
+        Some(Ok(version)) if !version.is_latest() => version,
+        _                                         => { return grpc::Compatibility::Unknown; }
+    };
+    match client_version.compatibility(driver_version) {
+        Compatibility::Compatible => grpc::Compatibility::Compatible,
+        Compatibility::Older      => grpc::Compatibility::UpgradeClient,
+        Compatibility::Newer      => grpc::Compatibility::UpgradeDriver,
+    }
+}
+
+
+
+/// Sends the status of a statement that was sent again to the client, instead of running it twice. If the statement is still running, waits for it to finish first.
+/// 
+/// **Arguments**
+///  * `statements`: The cache with the status of every statement that was sent with a token.
+///  * `uuid`: The session that sent the statement.
+///  * `token`: The token that identifies the statement in the session.
+///  * `status`: The status of the statement when it was sent again.
+///  * `tx`: The stream to the client.
+pub async fn replay_statement(statements: Arc<StatementCache>, uuid: String, token: String, mut status: StatementStatus, tx: ClientSender) {
+    if !status.is_done() {
+        let reply = grpc::ExecuteReply {
+            close: false,
+            debug: Some(String::from("Statement is still running; waiting for it to finish.")),
+            stderr: None,
+            stdout: None,
+            trace: None,
+            cached: Some(true),
+            diff: None,
+            sequence: None,
+            client: None,
+            input: None,
+        };
+        if tx.send(Ok(reply)).await.is_err() { return; }
+    }
+    while !status.is_done() {
+        tokio::time::sleep(STATEMENT_POLL_INTERVAL).await;
+        status = match statements.status(&uuid, &token) {
+            Some(status) => status,
+            // It ran but got evicted in the meantime, so we don't know how it went
+            None => StatementStatus::Done(Err((Code::NotFound, format!("Statement '{}' has finished, but its status has been evicted", token)))),
+        };
+    }
+
+    let result = match status {
+        StatementStatus::Done(Ok(reply))             => Ok(grpc::ExecuteReply{ cached: Some(true), ..reply }),
+        StatementStatus::Done(Err((code, message))) => Err(Status::new(code, message)),
+        StatementStatus::Running                     => unreachable!(),
+    };
+    if let Err(err) = tx.send(result).await { error!("Could not send cached status of statement '{}' to client: {}", token, err); }
+}
+
+/// Sends the closing reply of a statement (or the status it was refused with) to the client, remembering it in case the client sends the statement again.
+/// 
+/// **Arguments**
+///  * `tx`: The stream to the client.
+///  * `guard`: The guard that marks the statement as done in the cache with the status of every statement that was sent with a token.
+///  * `result`: The closing reply or status to send.
+async fn close_statement(tx: &ClientSender, guard: &mut StatementGuard, result: Result<grpc::ExecuteReply, Status>) {
+    // Number it first, so the client that sends the statement again learns its number as well
+    let result = tx.stamp(result);
+    guard.finish(&result);
+    if let Err(err) = tx.send(result).await { error!("Could not send the closing reply of a statement to client: {}", err); }
 }
@@ -1,12 +1,26 @@
 #[macro_use]
 extern crate anyhow;
 #[macro_use]
+extern crate lazy_static;
+#[macro_use]
 extern crate log;
 
+pub mod auth;
+pub mod calls;
+pub mod client;
 pub mod errors;
+pub mod events;
 pub mod executor;
 pub mod handler;
+pub mod limits;
+pub mod lineage;
+pub mod metrics;
+pub mod multiplex;
+pub mod outputs;
 pub mod packages;
+pub mod planner;
+pub mod sessions;
+pub mod statements;
 
 pub mod grpc {
     tonic::include_proto!("driver");
@@ -0,0 +1,148 @@
+/* LIMITS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 23:59:48
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Limits the number of jobs that are in flight, both per session and
+ *   across all sessions, so that one session cannot flood the command
+ *   topic and starve the others.
+**/
+
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::metrics;
+
+
+/***** LIBRARY STRUCTS *****/
+/// Limits the number of jobs in flight per session and in total. Calls beyond a limit wait for a slot rather than fail; since the underlying semaphores are fair, waiting calls get a slot in the order they asked for one.
+#[derive(Debug)]
+pub struct JobLimits {
+    /// The maximum number of jobs in flight for a single session.
+    per_session : usize,
+    /// The maximum number of jobs in flight across all sessions.
+    total       : usize,
+    /// The slots of every session that has (or waits for) a job in flight.
+    sessions    : Arc<DashMap<String, Arc<Semaphore>>>,
+    /// The slots shared by all sessions.
+    global      : Arc<Semaphore>,
+}
+
+impl JobLimits {
+    /// Constructor for the JobLimits.
+    /// 
+    /// **Arguments**
+    ///  * `per_session`: The maximum number of jobs in flight for a single session. Is at least 1.
+    ///  * `total`: The maximum number of jobs in flight across all sessions. Is at least 1.
+    pub fn new(per_session: usize, total: usize) -> Self {
+        let (per_session, total) = (per_session.max(1), total.max(1));
+        Self {
+            per_session,
+            total,
+            sessions : Arc::new(DashMap::new()),
+            global   : Arc::new(Semaphore::new(total)),
+        }
+    }
+
+
+
+    /// Waits for a slot for a job of the given session. The session first waits for a slot of its own, so it never holds on to a global slot while its own limit keeps it waiting.
+    /// 
+    /// **Arguments**
+    ///  * `session_uuid`: The session that wants to schedule a job.
+    /// 
+    /// **Returns**  
+    /// The JobPermit that holds the slot until it is dropped.
+    #[inline]
+    pub async fn acquire(&self, session_uuid: &str) -> JobPermit { self.acquire_many(session_uuid, 1).await }
+
+    /// Waits for the slots for several jobs of the given session at once (e.g., the elements of a job array), which it gets all together or not at all.
+    /// 
+    /// **Arguments**
+    ///  * `session_uuid`: The session that wants to schedule the jobs.
+    ///  * `jobs`: The number of jobs, which is clamped to `max_jobs()` so it can't wait forever.
+    /// 
+    /// **Returns**  
+    /// The JobPermit that holds the slots until it is dropped.
+    pub async fn acquire_many(&self, session_uuid: &str, jobs: usize) -> JobPermit {
+        let jobs = jobs.max(1).min(self.max_jobs());
+        let semaphore = self.sessions.entry(session_uuid.to_string()).or_insert_with(|| Arc::new(Semaphore::new(self.per_session))).clone();
+        if semaphore.available_permits() < jobs { debug!("Session '{}' has {} job(s) in flight; waiting for {} to finish", session_uuid, self.per_session - semaphore.available_permits(), jobs - semaphore.available_permits()); }
+        let session = semaphore.acquire_many_owned(jobs as u32).await.expect("Session semaphore is never closed");
+        if self.global.available_permits() < jobs { debug!("{} job(s) in flight; session '{}' waits for {} to finish", self.total - self.global.available_permits(), session_uuid, jobs - self.global.available_permits()); }
+        let global = self.global.clone().acquire_many_owned(jobs as u32).await.expect("Global semaphore is never closed");
+
+        metrics::JOBS_IN_FLIGHT.add(jobs as i64);
+        metrics::SESSION_JOBS_IN_FLIGHT.with_label_values(&[session_uuid]).add(jobs as i64);
+        JobPermit {
+            session_uuid : session_uuid.to_string(),
+            jobs,
+            sessions     : self.sessions.clone(),
+            session      : Some(session),
+            global       : Some(global),
+        }
+    }
+
+
+
+    /// Returns the number of jobs that the given session has in flight.
+    /// 
+    /// **Arguments**
+    ///  * `session_uuid`: The session to count the jobs of.
+    #[inline]
+    pub fn in_flight(&self, session_uuid: &str) -> usize {
+        self.sessions.get(session_uuid).map(|semaphore| self.per_session - semaphore.available_permits()).unwrap_or(0)
+    }
+
+    /// Returns the number of jobs in flight across all sessions.
+    #[inline]
+    pub fn total_in_flight(&self) -> usize { self.total - self.global.available_permits() }
+
+    /// Returns the number of sessions that have (or wait for) a job in flight.
+    #[inline]
+    pub fn sessions(&self) -> usize { self.sessions.len() }
+
+    /// Returns the most jobs that a single session may have in flight at the same time, which is what `acquire_many()` hands out at most.
+    #[inline]
+    pub fn max_jobs(&self) -> usize { self.per_session.min(self.total) }
+}
+
+
+
+/// The slots for one or more jobs, which count towards the limits of its session and the global limit until it is dropped.
+#[derive(Debug)]
+pub struct JobPermit {
+    /// The session that holds the slots.
+    session_uuid : String,
+    /// The number of jobs that the permit holds slots for.
+    jobs         : usize,
+    /// The slots of every session, so we can forget the session's once it's not used anymore.
+    sessions     : Arc<DashMap<String, Arc<Semaphore>>>,
+    /// The slot of the session.
+    session      : Option<OwnedSemaphorePermit>,
+    /// The global slot.
+    global       : Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for JobPermit {
+    fn drop(&mut self) {
+        self.global.take();
+        self.session.take();
+        metrics::JOBS_IN_FLIGHT.sub(self.jobs as i64);
+
+        // Forget the session's slots once no other job holds or waits for one (as they all keep a reference to the semaphore)
+        if self.sessions.remove_if(&self.session_uuid, |_, semaphore| Arc::strong_count(semaphore) == 1).is_some() {
+            if let Err(err) = metrics::SESSION_JOBS_IN_FLIGHT.remove_label_values(&[&self.session_uuid]) { debug!("Could not remove in-flight metric of session '{}': {}", self.session_uuid, err); }
+        } else {
+            metrics::SESSION_JOBS_IN_FLIGHT.with_label_values(&[&self.session_uuid]).sub(self.jobs as i64);
+        }
+    }
+}
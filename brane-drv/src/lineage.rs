@@ -0,0 +1,292 @@
+/* LINEAGE.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 18:21:07
+ * Last edited:
+ *   15 Oct 2026, 18:21:07
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Reports the lineage of every job (which session ran which package
+ *   version where, and how that went) to the GraphQL API. Records are
+ *   queued and sent in the background, so that the API being down never
+ *   holds up execution; if it stays down for too long, the oldest
+ *   records are dropped.
+**/
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value as JValue};
+use specifications::version::Version;
+use tokio::sync::Notify;
+
+use crate::errors::LineageError;
+
+
+/***** CONSTANTS *****/
+/// The mutation that records a job's lineage in the API.
+pub const RECORD_LINEAGE_MUTATION: &str = "mutation RecordLineage($record: LineageInput!) { recordLineage(record: $record) }";
+
+/// The time we wait before retrying a record after the API failed for the first time.
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// The maximum time we wait before retrying a record.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// How a job in a lineage record ended.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LineageOutcome {
+    /// The job returned a value.
+    Success,
+    /// The job (or scheduling it) failed with the given error.
+    Failure{ error: String },
+}
+
+
+
+/// Describes a single job that was run on behalf of a session.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineageRecord {
+    /// The session that ran the job.
+    pub session        : String,
+    /// The correlation ID of the job.
+    pub correlation_id : String,
+    /// The package that provides the function that the job ran.
+    pub package        : String,
+    /// The version of that package.
+    pub version        : Version,
+    /// The digest of that package's image.
+    pub digest         : String,
+    /// The location that the job ran on, if it got that far.
+    pub location       : Option<String>,
+    /// When the call for the job was made.
+    pub started        : SystemTime,
+    /// When the call for the job returned.
+    pub finished       : SystemTime,
+    /// How the job ended.
+    pub outcome        : LineageOutcome,
+}
+
+impl LineageRecord {
+    /// Returns the GraphQL request that records this record in the API.
+    pub fn mutation_payload(&self) -> JValue {
+        let (outcome, error) = match &self.outcome {
+            LineageOutcome::Success          => ("success", None),
+            LineageOutcome::Failure{ error } => ("failure", Some(error)),
+        };
+        json!({
+            "query": RECORD_LINEAGE_MUTATION,
+            "variables": {
+                "record": {
+                    "session"       : self.session,
+                    "correlationId" : self.correlation_id,
+                    "package"       : self.package,
+                    "version"       : self.version.to_string(),
+                    "digest"        : self.digest,
+                    "location"      : self.location,
+                    "started"       : timestamp(self.started),
+                    "finished"      : timestamp(self.finished),
+                    "outcome"       : outcome,
+                    "error"         : error,
+                },
+            },
+        })
+    }
+}
+
+
+
+/// A bounded queue of records that still have to be sent. If it is full, the oldest records are dropped first.
+#[derive(Debug)]
+pub struct LineageQueue {
+    /// The maximum number of records in the queue.
+    capacity : usize,
+    /// The records themselves, oldest first.
+    records  : VecDeque<LineageRecord>,
+    /// The number of records that we dropped so far.
+    dropped  : u64,
+}
+
+impl LineageQueue {
+    /// Constructor for the LineageQueue.
+    /// 
+    /// **Arguments**
+    ///  * `capacity`: The maximum number of records to keep. If 0, every record is dropped.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records : VecDeque::with_capacity(capacity),
+            dropped : 0,
+        }
+    }
+
+
+
+    /// Adds a new record to the back of the queue, dropping the oldest record if the queue is full.
+    /// 
+    /// **Arguments**
+    ///  * `record`: The record to add.
+    /// 
+    /// **Returns**  
+    /// The record that was dropped to make room, if any.
+    pub fn push(&mut self, record: LineageRecord) -> Option<LineageRecord> {
+        if self.capacity == 0 { self.dropped += 1; return Some(record); }
+        let dropped = if self.records.len() >= self.capacity { self.dropped += 1; self.records.pop_front() } else { None };
+        self.records.push_back(record);
+        dropped
+    }
+
+    /// Puts a record that could not be sent back at the front of the queue, unless newer records filled the queue in the meantime (in which case it is the oldest, and thus dropped).
+    /// 
+    /// **Arguments**
+    ///  * `record`: The record to put back.
+    /// 
+    /// **Returns**  
+    /// Whether the record was dropped.
+    pub fn requeue(&mut self, record: LineageRecord) -> bool {
+        if self.records.len() >= self.capacity { self.dropped += 1; return true; }
+        self.records.push_front(record);
+        false
+    }
+
+    /// Takes the oldest record from the queue, if any.
+    #[inline]
+    pub fn pop(&mut self) -> Option<LineageRecord> { self.records.pop_front() }
+
+    /// Returns the records in the queue, oldest first.
+    #[inline]
+    pub fn records(&self) -> impl Iterator<Item = &LineageRecord> { self.records.iter() }
+
+    /// Returns the number of records in the queue.
+    #[inline]
+    pub fn len(&self) -> usize { self.records.len() }
+
+    /// Returns whether the queue is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.records.is_empty() }
+
+    /// Returns the number of records that were dropped so far.
+    #[inline]
+    pub fn dropped(&self) -> u64 { self.dropped }
+}
+
+
+
+/// Reports lineage records to the GraphQL API in the background. May be cloned cheaply to report from multiple sessions.
+#[derive(Clone, Debug)]
+pub struct LineageReporter {
+    /// The records that still have to be sent.
+    queue  : Arc<Mutex<LineageQueue>>,
+    /// Wakes the background task when a record is reported.
+    notify : Arc<Notify>,
+}
+
+impl LineageReporter {
+    /// Constructor for the LineageReporter.
+    /// 
+    /// Note that nothing is sent until `run()` is spawned.
+    /// 
+    /// **Arguments**
+    ///  * `capacity`: The maximum number of records to keep while the API is unreachable.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            queue  : Arc::new(Mutex::new(LineageQueue::new(capacity))),
+            notify : Arc::new(Notify::new()),
+        }
+    }
+
+
+
+    /// Queues the given record to be sent. Never blocks on the API.
+    /// 
+    /// **Arguments**
+    ///  * `record`: The record to send.
+    pub fn report(&self, record: LineageRecord) {
+        let dropped = self.queue.lock().unwrap().push(record);
+        if let Some(dropped) = dropped { warn!("Lineage queue is full; dropping the record of job '{}'", dropped.correlation_id); }
+        self.notify.notify_one();
+    }
+
+    /// Returns a copy of the records that still have to be sent, oldest first.
+    pub fn queued(&self) -> Vec<LineageRecord> { self.queue.lock().unwrap().records().cloned().collect() }
+
+    /// Returns the number of records that were dropped so far.
+    pub fn dropped(&self) -> u64 { self.queue.lock().unwrap().dropped() }
+
+
+
+    /// Sends the queued records to the given GraphQL endpoint, forever. If the endpoint fails, the record is retried with an exponential backoff.
+    /// 
+    /// **Arguments**
+    ///  * `graphql_url`: The GraphQL endpoint of the API.
+    pub async fn run(self, graphql_url: String) {
+        let client = reqwest::Client::new();
+        let mut delay = MIN_RETRY_DELAY;
+        loop {
+            // Wait until there is something to send
+            let record = self.queue.lock().unwrap().pop();
+            let record = match record {
+                Some(record) => record,
+                None         => { self.notify.notified().await; continue; }
+            };
+
+            match post(&client, &graphql_url, &record).await {
+                Ok(_) => {
+                    debug!("Recorded lineage of job '{}'", record.correlation_id);
+                    delay = MIN_RETRY_DELAY;
+                },
+                Err(err) => {
+                    warn!("{} (retrying in {} seconds)", err, delay.as_secs());
+                    if self.queue.lock().unwrap().requeue(record.clone()) { warn!("Lineage queue is full; dropping the record of job '{}'", record.correlation_id); }
+                    tokio::time::sleep(delay).await;
+                    delay = std::cmp::min(2 * delay, MAX_RETRY_DELAY);
+                },
+            }
+        }
+    }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Formats the given time as an RFC 3339 timestamp in UTC.
+fn timestamp(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Sends the given record to the given GraphQL endpoint.
+/// 
+/// **Arguments**
+///  * `client`: The client to send the request with.
+///  * `graphql_url`: The GraphQL endpoint of the API.
+///  * `record`: The record to send.
+/// 
+/// **Returns**  
+/// Nothing on success, or a LineageError if the endpoint could not be reached or refused the record.
+async fn post(client: &reqwest::Client, graphql_url: &str, record: &LineageRecord) -> Result<(), LineageError> {
+    let response = match client.post(graphql_url).json(&record.mutation_payload()).send().await {
+        Ok(response) => response,
+        Err(err)     => { return Err(LineageError::RequestError{ url: graphql_url.to_string(), correlation_id: record.correlation_id.clone(), err }); }
+    };
+    let status = response.status();
+    if !status.is_success() { return Err(LineageError::RequestFailure{ url: graphql_url.to_string(), correlation_id: record.correlation_id.clone(), status }); }
+
+    // GraphQL reports errors in the body
+    let body: JValue = match response.json().await {
+        Ok(body) => body,
+        Err(err) => { return Err(LineageError::RequestError{ url: graphql_url.to_string(), correlation_id: record.correlation_id.clone(), err }); }
+    };
+    if let Some(errors) = body.get("errors") { return Err(LineageError::MutationError{ url: graphql_url.to_string(), correlation_id: record.correlation_id.clone(), errors: errors.to_string() }); }
+    Ok(())
+}
@@ -1,29 +1,41 @@
 use anyhow::{Context, Result};
-use brane_bvm::vm::VmState;
 use brane_cfg::Infrastructure;
+use brane_drv::auth::{self, TokenInterceptor};
+use brane_drv::calls::{CallCache, FileStore};
 use brane_drv::errors::DriverError;
+use brane_drv::events::EventMonitor;
 use brane_drv::grpc::DriverServiceServer;
-use brane_drv::handler::DriverHandler;
-use brane_job::interface::{Event, EventKind};
+use brane_drv::executor::{ActiveJob, ResumedJob};
+use brane_drv::handler::{DriverHandler, SESSION_SWEEP_INTERVAL};
+use brane_drv::limits::JobLimits;
+use brane_drv::metrics::DEFAULT_METRICS_ADDRESS;
+use brane_drv::lineage::LineageReporter;
+use brane_drv::multiplex::{Multiplexer, StatementPolicy};
+use brane_drv::outputs::JobOutputs;
+use brane_drv::sessions::SessionStore;
+use brane_drv::statements::StatementCache;
+use brane_job::interface::Event;
 use brane_shr::jobs::JobStatus;
+use brane_shr::kafka::{self, KafkaSecurity, TopicOptions};
+use brane_shr::metrics as shr_metrics;
 use clap::Parser;
 use dashmap::DashMap;
 use dotenv::dotenv;
-use futures::TryStreamExt;
-use log::info;
+use futures::StreamExt;
+use log::{error, info, warn};
 use log::LevelFilter;
 use prost::Message as _;
 use rdkafka::{
-    admin::{AdminClient, AdminOptions, NewTopic, TopicReplication},
-    consumer::{Consumer, StreamConsumer},
-    error::RDKafkaErrorCode,
+    consumer::{CommitMode, Consumer, StreamConsumer},
     producer::FutureProducer,
-    util::Timeout,
-    ClientConfig, Message as _, Offset, TopicPartitionList
+    Message as _,
 };
+use std::net::SocketAddr;
+use std::fs;
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::SystemTime;
-use tonic::transport::Server;
+use std::time::{Duration, SystemTime};
+use tonic::transport::{Identity, Server, ServerTlsConfig};
 
 
 /***** ARGUMENTS *****/
@@ -39,6 +51,10 @@ struct Opts {
     /// Kafka brokers
     #[clap(short, long, default_value = "localhost:9092", env = "BROKERS")]
     brokers: String,
+    #[clap(flatten)]
+    kafka: KafkaSecurity,
+    #[clap(flatten)]
+    topics: TopicOptions,
     /// Topic to send commands to
     #[clap(short, long = "cmd-topic", default_value = "drv-cmd", env = "COMMAND_TOPIC")]
     command_topic: String,
@@ -54,6 +70,66 @@ struct Opts {
     /// Infra metadata store
     #[clap(short, long, default_value = "./infra.yml", env = "INFRA")]
     infra: String,
+    /// Maximum total size (in bytes) of the job outputs (of failed or finished jobs) to remember for `brane logs`
+    #[clap(long, default_value = "67108864", env = "MAX_JOB_OUTPUT_BYTES")]
+    max_job_output_bytes: usize,
+    /// Address to serve the Prometheus metrics on (at '/metrics')
+    #[clap(long, default_value = DEFAULT_METRICS_ADDRESS, env = "METRICS_ADDRESS")]
+    metrics_address: SocketAddr,
+    /// Directory to persist sessions (and the jobs they wait for) in, so they survive a restart. If omitted, sessions are kept in memory only.
+    #[clap(long, env = "SESSION_DIR")]
+    session_dir: Option<PathBuf>,
+    /// Seconds after which a session that has not been used (and does not wait for any jobs) expires and its state is forgotten. 0 keeps sessions forever.
+    #[clap(long, default_value = "86400", env = "SESSION_TTL")]
+    session_ttl: u64,
+    /// Seconds after which a job that was pending when the driver restarted, and of which no events are known, is considered lost
+    #[clap(long, default_value = "3600", env = "ORPHAN_HORIZON")]
+    orphan_horizon: u64,
+    /// Number of finished statements to remember the status of, so clients that lost their connection may send them again without running them twice
+    #[clap(long, default_value = "1000", env = "MAX_STATEMENTS")]
+    max_statements: usize,
+    /// Do not report the lineage of jobs (which session ran which package where, and how that went) to the GraphQL API
+    #[clap(long, env = "NO_LINEAGE", takes_value = false)]
+    no_lineage: bool,
+    /// Number of lineage records to keep while the GraphQL API is unreachable, after which the oldest are dropped
+    #[clap(long, default_value = "1024", env = "LINEAGE_QUEUE_SIZE")]
+    lineage_queue_size: usize,
+    /// File with the tokens that clients may authenticate with, one per line. If neither this nor '--tokens' is given, clients are not authenticated.
+    #[clap(long, env = "TOKEN_FILE")]
+    token_file: Option<PathBuf>,
+    /// Comma-separated list of tokens that clients may authenticate with
+    #[clap(long, env = "TOKENS", hide_env_values = true)]
+    tokens: Option<String>,
+    /// Number of jobs that a single session may have in flight; further calls wait until one finishes
+    #[clap(long, default_value = "16", env = "MAX_SESSION_JOBS")]
+    max_session_jobs: usize,
+    /// Number of jobs that all sessions together may have in flight; further calls wait until one finishes
+    #[clap(long, default_value = "256", env = "MAX_JOBS")]
+    max_jobs: usize,
+    /// Certificate (as PEM) to serve the gRPC API over TLS with. If omitted, the API is served in plaintext.
+    #[clap(long, env = "TLS_CERT", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// Private key (as PEM) that belongs to '--tls-cert'
+    #[clap(long, env = "TLS_KEY", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// Directory to keep the results of pure functions in, so they are reused after a restart. If omitted, results are kept in memory only.
+    #[clap(long, env = "CALL_CACHE", conflicts_with = "no_cache")]
+    call_cache: Option<PathBuf>,
+    /// Number of results of pure functions to keep in memory
+    #[clap(long, default_value = "1024", env = "CALL_CACHE_SIZE")]
+    call_cache_size: usize,
+    /// Do not reuse the results of pure functions; always run them again
+    #[clap(long, env = "NO_CACHE", takes_value = false)]
+    no_cache: bool,
+    /// What to do with a statement that a client sends to a session that is still running another one: 'queue' it or 'reject' it
+    #[clap(long, default_value = "queue", env = "CONCURRENT_STATEMENTS")]
+    concurrent_statements: StatementPolicy,
+    /// Number of instructions that a single statement may execute before it is aborted (so it can't keep the driver busy forever). 0 means unlimited
+    #[clap(long, default_value = "1000000000", env = "MAX_INSTRUCTIONS")]
+    max_instructions: u64,
+    /// Directory that brane-job writes the results that are too large for an event to (it has to contain the 'payload_dir' of every location). Results that refer to files outside of it are refused; if omitted, such results fail their job.
+    #[clap(long, env = "PAYLOAD_DIR")]
+    payload_dir: Option<PathBuf>,
 }
 /*******/
 
@@ -77,9 +153,19 @@ async fn main() -> Result<()> {
         logger.filter_level(LevelFilter::Info).init();
     }
 
+    // Refuse Kafka options that don't make sense before connecting with them
+    if let Err(reason) = opts.kafka.validate() {
+        log::error!("{}", reason);
+        std::process::exit(-1);
+    }
+    if let Err(reason) = opts.topics.validate() {
+        log::error!("{}", reason);
+        std::process::exit(-1);
+    }
+
     // Ensure that the input/output topics exists.
     let command_topic = opts.command_topic.clone();
-    if let Err(reason) = ensure_topics(vec![&command_topic, &opts.event_topic], &opts.brokers).await {
+    if let Err(reason) = kafka::ensure_topics(&[ &command_topic, &opts.event_topic ], &opts.brokers, &opts.kafka, &opts.topics).await {
         log::error!("{}", reason);
         std::process::exit(-1);
     };
@@ -87,8 +173,7 @@ async fn main() -> Result<()> {
     let infra = Infrastructure::new(opts.infra.clone())?;
     infra.validate()?;
 
-    let producer: FutureProducer = ClientConfig::new()
-        .set("bootstrap.servers", &opts.brokers)
+    let producer: FutureProducer = opts.kafka.client_config(&opts.brokers)
         .set("message.timeout.ms", "5000")
         .create()
         .context("Failed to create Kafka producer.")?;
@@ -97,18 +182,58 @@ async fn main() -> Result<()> {
     let states: Arc<DashMap<String, JobStatus>> = Arc::new(DashMap::new());
     let heartbeats: Arc<DashMap<String, SystemTime>> = Arc::new(DashMap::new());
     let locations: Arc<DashMap<String, String>> = Arc::new(DashMap::new());
+    let outputs: Arc<JobOutputs> = Arc::new(JobOutputs::new(opts.max_job_output_bytes));
+    let active: Arc<DashMap<String, ActiveJob>> = Arc::new(DashMap::new());
+    let orders: Arc<DashMap<String, u32>> = Arc::new(DashMap::new());
 
     tokio::spawn(start_event_monitor(
         opts.brokers.clone(),
+        opts.kafka.clone(),
         opts.group_id.clone(),
         opts.event_topic.clone(),
         states.clone(),
         heartbeats.clone(),
         locations.clone(),
+        outputs.clone(),
+        active.clone(),
+        orders.clone(),
+        opts.payload_dir.clone(),
     ));
 
+    // Expose the metrics
+    let metrics_address = opts.metrics_address;
+    tokio::spawn(async move {
+        info!("Serving metrics on 'http://{}/metrics'.", metrics_address);
+        if let Err(err) = shr_metrics::serve(metrics_address).await { log::error!("Could not serve metrics on '{}': {}", metrics_address, err); }
+    });
+
     let graphql_url = opts.graphql_url.clone();
-    let sessions: Arc<DashMap<String, VmState>> = Arc::new(DashMap::new());
+
+    // Report lineage in the background, so the API being down never holds up execution
+    let lineage = if opts.no_lineage {
+        info!("Not reporting the lineage of jobs.");
+        None
+    } else {
+        let reporter = LineageReporter::new(opts.lineage_queue_size);
+        tokio::spawn(reporter.clone().run(graphql_url.clone()));
+        Some(reporter)
+    };
+
+    let sessions: Arc<SessionStore> = Arc::new(SessionStore::new(opts.session_dir.clone())?);
+
+    // Reuse the results of pure functions, unless told not to
+    let calls = if opts.no_cache {
+        info!("Not reusing the results of pure functions.");
+        None
+    } else {
+        let mut cache = CallCache::new(opts.call_cache_size);
+        if let Some(dir) = &opts.call_cache {
+            info!("Keeping the results of pure functions in '{}'.", dir.display());
+            cache = cache.with_store(Box::new(FileStore::new(dir)?));
+        }
+        Some(Arc::new(cache))
+    };
+    let resumed: Arc<DashMap<String, ResumedJob>> = Arc::new(DashMap::new());
     let handler = DriverHandler {
         command_topic,
         graphql_url,
@@ -117,235 +242,142 @@ async fn main() -> Result<()> {
         states,
         heartbeats,
         locations,
+        outputs,
+        active,
+        orders,
+        resumed,
+        running: Arc::new(DashMap::new()),
+        orphan_horizon: Duration::from_secs(opts.orphan_horizon),
+        statements: Arc::new(StatementCache::new(opts.max_statements)),
+        lineage,
+        limits: Arc::new(JobLimits::new(opts.max_session_jobs, opts.max_jobs)),
+        calls,
+        multiplexer: Arc::new(Multiplexer::new(opts.concurrent_statements)),
+        max_instructions: if opts.max_instructions > 0 { Some(opts.max_instructions) } else { None },
         infra,
     };
 
-    // Start gRPC server with callback service.
-    Server::builder()
-        .add_service(DriverServiceServer::new(handler))
-        .serve(opts.address.parse()?)
-        .await
-        .context("Failed to start callback gRPC server.")
-}
-
-/* TIM */
-/// **Edited: now returning DriverErrors.**
-///
-/// Makes sure the required topics are present and watched in the local Kafka server.
-/// 
-/// **Arguments**
-///  * `topics`: The list of topics to make sure they exist of.
-///  * `brokers`: The string list of Kafka servers that act as the brokers.
-/// 
-/// **Returns**  
-/// Nothing on success, or a DriverError otherwise.
-async fn ensure_topics(
-    topics: Vec<&str>,
-    brokers: &str,
-) -> Result<(), DriverError> {
-    // Connect with an admin client
-    let admin_client: AdminClient<_> = match ClientConfig::new().set("bootstrap.servers", brokers) .create() {
-        Ok(client)  => client,
-        Err(reason) => { return Err(DriverError::KafkaClientError{ servers: brokers.to_string(), err: reason }); }
-    };
-
-    // Collect the topics to create and then create them
-    let ktopics: Vec<NewTopic> = topics
-        .iter()
-        .map(|t| NewTopic::new(t, 1, TopicReplication::Fixed(1)))
-        .collect();
-    let results = match admin_client.create_topics(ktopics.iter(), &AdminOptions::new()).await {
-        Ok(results) => results,
-        Err(reason) => { return Err(DriverError::KafkaTopicsError{ topics: DriverError::serialize_vec(&topics), err: reason }); }
-    };
+    // Expire idle sessions in the background, so clients that never close theirs don't pile up state
+    if opts.session_ttl > 0 {
+        tokio::spawn(expire_sessions(handler.clone(), Duration::from_secs(opts.session_ttl)));
+    } else {
+        info!("Not expiring idle sessions.");
+    }
 
-    // Report on the results. Don't consider 'TopicAlreadyExists' an error.
-    for result in results {
-        match result {
-            Ok(topic) => info!("Kafka topic '{}' created.", topic),
-            Err((topic, error)) => match error {
-                RDKafkaErrorCode::TopicAlreadyExists => {
-                    info!("Kafka topic '{}' already exists", topic);
-                }
-                _ => { return Err(DriverError::KafkaTopicError{ topic, err: error }); }
-            },
-        }
+    // Only let clients with a known token in (if we know any)
+    let tokens = auth::read_tokens(opts.token_file.as_deref(), opts.tokens.as_deref())?;
+    let interceptor = TokenInterceptor::new(tokens);
+    if !interceptor.is_enabled() { warn!("No client tokens given; anyone who can reach '{}' can run workflows in any session.", opts.address); }
+
+    // Start gRPC server with callback service, over TLS if we have a certificate.
+    let mut server = Server::builder();
+    if let (Some(cert), Some(key)) = (&opts.tls_cert, &opts.tls_key) {
+        let cert = fs::read(cert).with_context(|| format!("Failed to read TLS certificate '{}'.", cert.display()))?;
+        let key = fs::read(key).with_context(|| format!("Failed to read TLS key '{}'.", key.display()))?;
+        server = server.tls_config(ServerTlsConfig::new().identity(Identity::from_pem(cert, key))).context("Failed to configure TLS.")?;
+    } else {
+        warn!("No TLS certificate given; serving the gRPC API in plaintext.");
     }
 
-    Ok(())
+    server
+        .add_service(DriverServiceServer::with_interceptor(handler, interceptor))
+        .serve(opts.address.parse()?)
+        .await
+        .context("Failed to start callback gRPC server.")
 }
-/*******/
 
 /* TIM */
-/// **Edited: taking into account new events. To do so, now accepting 'heartbeats' list.**
+/// **Edited: taking into account new events. To do so, now accepting 'heartbeats' list. Also committing offsets manually, only after an event has been processed, and forwarding the output of running jobs to their sessions.**
 /// 
 /// Monitors the Kafka events for interesting stuff for us.
 /// 
 /// **Arguments**
 ///  * `brokers`: The list of Kafka servers to listen to.
+///  * `security`: The options to connect to the brokers with.
 ///  * `group_id`: The group_id for the brane-drv.
 ///  * `topic`: The topic to listen on.
 ///  * `states`: The list of states we use to keep track at what state what running job is.
 ///  * `heartbeats`: The list of times we last saw a heartbeat for a given job.
-///  * `results`: A list to put the results in we accumulated from each job.
 ///  * `locations`: The list of locations where our jobs are running.
+///  * `outputs`: The (bounded) list of outputs of failed and finished jobs, which clients may query later.
+///  * `active`: The list of jobs that sessions are currently waiting for, to whose clients we forward the output of those jobs.
+///  * `orders`: The order of the latest state change we processed for every job, so that we can drop events that arrive out of order.
+///  * `payload_dir`: The directory that brane-job writes results that are too large for an event to, if any.
 /// 
 /// **Returns**  
 /// Nothing on success, or a DriverError upon failure.
+#[allow(clippy::too_many_arguments)]
 async fn start_event_monitor(
     brokers: String,
+    security: KafkaSecurity,
     group_id: String,
     topic: String,
     states: Arc<DashMap<String, JobStatus>>,
     heartbeats: Arc<DashMap<String, SystemTime>>,
     locations: Arc<DashMap<String, String>>,
+    outputs: Arc<JobOutputs>,
+    active: Arc<DashMap<String, ActiveJob>>,
+    orders: Arc<DashMap<String, u32>>,
+    payload_dir: Option<PathBuf>,
 ) -> Result<(), DriverError> {
-    let consumer: StreamConsumer = match ClientConfig::new()
+    let consumer: StreamConsumer = match security.client_config(&brokers)
         .set("group.id", group_id.clone())
-        .set("bootstrap.servers", brokers.clone())
         .set("enable.partition.eof", "false")
         .set("session.timeout.ms", "6000")
-        .set("enable.auto.commit", "true")
+        .set("enable.auto.commit", "false")
         .create()
     {
         Ok(consumer) => consumer,
         Err(err)     => { return Err(DriverError::KafkaConsumerError{ servers: brokers, id: group_id, err }); }
     };
 
-    // Restore previous topic/partition offset.
-    let mut tpl = TopicPartitionList::new();
-    tpl.add_partition(&topic, 0);
+    // Read every partition of the topic, restoring the previous offsets.
+    match kafka::assign_partitions(&consumer, &[ &topic ]) {
+        Ok(tpl)  => info!("Restored commited offsets: {:?}", &tpl),
+        Err(err) => { return Err(DriverError::KafkaAssignError{ err }); }
+    }
 
-    let committed_offsets = match consumer.committed_offsets(tpl.clone(), Timeout::Never) {
-        Ok(commited_offsets) => commited_offsets.to_topic_map(),
-        Err(err)             => { return Err(DriverError::KafkaGetOffsetError{ topic, err }); }
-    };
-    if let Some(offset) = committed_offsets.get(&(topic.clone(), 0)) {
-        let res = match offset {
-            Offset::Invalid => tpl.set_partition_offset(&topic, 0, Offset::Beginning),
-            offset => tpl.set_partition_offset(&topic, 0, *offset),
+    // Run the consumer. Offsets are only committed once an event has been processed, so that any event we did not get to before a crash is replayed on the next start.
+    let mut monitor = EventMonitor::new(states, heartbeats, locations, outputs, active, orders);
+    if let Some(payload_dir) = payload_dir { monitor = monitor.with_payload_dir(payload_dir); }
+    let mut stream = consumer.stream();
+    while let Some(message) = stream.next().await {
+        let message = match message {
+            Ok(message) => message,
+            Err(err)    => { return Err(DriverError::EventMonitorError{ err }); }
         };
-        if let Err(err) = res {
-            return Err(DriverError::KafkaSetOffsetError{ topic, err });
+
+        if let Some(payload) = message.payload() {
+            // Decode payload into a Event message.
+            match Event::decode(payload) {
+                Ok(event) if !event.schema_version().is_compatible() => {
+                    let key = message.key().map(|key| String::from_utf8_lossy(key).to_string()).unwrap_or_default();
+                    error!("{}", DriverError::EventSchemaMismatch{ topic: topic.clone(), partition: message.partition(), offset: message.offset(), key, version: event.schema_version() });
+                },
+                Ok(event)   => { monitor.handle(&event); },
+                Err(reason) => { warn!("Ignoring event at offset {} that could not be decoded: {}", message.offset(), reason); },
+            }
         }
-    }
 
-    info!("Restoring commited offsets: {:?}", &tpl);
-    if let Err(err) = consumer.assign(&tpl) {
-        return Err(DriverError::KafkaSetOffsetsError{ topic, err });
+        if let Err(err) = consumer.commit_message(&message, CommitMode::Async) {
+            return Err(DriverError::KafkaCommitError{ topic, err });
+        }
     }
 
-    // Run the consumer
-    match consumer
-        .stream()
-        .try_for_each(|borrowed_message| {
-            let owned_message = borrowed_message.detach();
-            let owned_states = states.clone();
-            let owned_heartbeats = heartbeats.clone();
-            let owned_locations = locations.clone();
-
-            async move {
-                if let Some(payload) = owned_message.payload() {
-                    // Decode payload into a Event message.
-                    let event = Event::decode(payload).unwrap();
-                    let kind = EventKind::from_i32(event.kind).unwrap();
-
-                    let event_id: Vec<_> = event.identifier.split('-').collect();
-                    let correlation_id = event_id.first().unwrap().to_string();
-
-                    // Just collect everything we see; don't reason about it yet
-                    match kind {
-                        EventKind::CreateFailed => {
-                            // Decode the payload as error
-                            let err = String::from_utf8_lossy(&event.payload).to_string();
-                            // Note the state with what went wrong
-                            owned_states.insert(correlation_id, JobStatus::CreateFailed{ err });
-                        }
-                        EventKind::Created => {
-                            // The container has been created, so note it
-                            owned_states.insert(correlation_id.clone(), JobStatus::Created);
-                            owned_locations.insert(correlation_id, event.location.clone());
-                        }
-
-                        EventKind::Ready => {
-                            // Update the state
-                            owned_states.insert(correlation_id, JobStatus::Ready);
-                        }
-
-                        EventKind::InitializeFailed => {
-                            // Decode the payload as error
-                            let err = String::from_utf8_lossy(&event.payload).to_string();
-                            // Update the state
-                            owned_states.insert(correlation_id, JobStatus::InitializeFailed{ err });
-                        }
-                        EventKind::Initialized => {
-                            // Update the state
-                            owned_states.insert(correlation_id, JobStatus::Initialized);
-                        }
-
-                        EventKind::StartFailed => {
-                            // Decode the payload as error
-                            let err = String::from_utf8_lossy(&event.payload).to_string();
-                            // Update the state
-                            owned_states.insert(correlation_id, JobStatus::StartFailed{ err });
-                        }
-                        EventKind::Started => {
-                            // Update the state
-                            owned_states.insert(correlation_id, JobStatus::Started);
-                        }
-
-                        EventKind::Heartbeat => {
-                            // Note the time that we received the heartbeat only
-                            owned_heartbeats.insert(correlation_id, SystemTime::now());
-                        }
-                        EventKind::CompleteFailed => {
-                            // Decode the payload as error
-                            let err = String::from_utf8_lossy(&event.payload).to_string();
-                            // Update the state
-                            owned_states.insert(correlation_id, JobStatus::CompleteFailed{ err });
-                        }
-                        EventKind::Completed => {
-                            // Update the state
-                            owned_states.insert(correlation_id, JobStatus::Completed);
-                        }
-
-                        EventKind::DecodeFailed => {
-                            // Decode the payload as error
-                            let err = String::from_utf8_lossy(&event.payload).to_string();
-                            // Update the state
-                            owned_states.insert(correlation_id, JobStatus::DecodeFailed{ err });
-                        }
-                        EventKind::Failed => {
-                            // Decode the result as a JSON code/stdout/stderr pair
-                            let payload = String::from_utf8_lossy(&event.payload).to_string();
-                            // Do not parse the JSON, as this is error-prone and we want to treat errors in the executor
-                            owned_states.insert(correlation_id, JobStatus::Failed{ res: payload });
-                        }
-                        EventKind::Stopped => {
-                            // Decode the payload as a signal name
-                            let signal = String::from_utf8_lossy(&event.payload).to_string();
-                            // Update the state
-                            owned_states.insert(correlation_id, JobStatus::Stopped{ signal });
-                        }
-                        EventKind::Finished => {
-                            // Decode the payload as JSON value description
-                            let payload = String::from_utf8_lossy(&event.payload).to_string();
-                            // Do not parse the JSON, as this is error-prone and we want to treat errors in the executor
-                            owned_states.insert(correlation_id, JobStatus::Finished{ res: payload });
-                        }
-                        _ => {
-                            unreachable!();
-                        }
-                    }
-                }
+    Ok(())
+}
+/*******/
 
-                Ok(())
-            }
-        })
-        .await
-    {
-        Ok(_)    => Ok(()),
-        Err(err) => Err(DriverError::EventMonitorError{ err }),
+/// Expires the sessions that have been idle for longer than the given TTL, checking every `SESSION_SWEEP_INTERVAL` (or every TTL, if that's shorter).
+/// 
+/// **Arguments**
+///  * `handler`: The DriverHandler with the sessions to expire.
+///  * `ttl`: The time after which idle sessions expire.
+async fn expire_sessions(handler: DriverHandler, ttl: Duration) {
+    info!("Expiring sessions that are idle for {}s or longer.", ttl.as_secs());
+    let mut interval = tokio::time::interval(std::cmp::min(ttl, SESSION_SWEEP_INTERVAL));
+    loop {
+        interval.tick().await;
+        handler.expire_idle_sessions(ttl);
     }
 }
-/*******/
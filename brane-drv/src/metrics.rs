@@ -0,0 +1,75 @@
+/* METRICS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   14 Oct 2026, 21:14:08
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Defines the Prometheus metrics that brane-drv keeps track of. They are
+ *   exposed by `brane_shr::metrics::serve()`.
+**/
+
+use prometheus::{register_int_counter, register_int_counter_vec, register_int_gauge, register_int_gauge_vec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec};
+
+
+/***** CONSTANTS *****/
+/// The address that the driver serves its metrics on by default. Differs from the one of brane-job, so both can run on the same host.
+pub const DEFAULT_METRICS_ADDRESS: &str = "127.0.0.1:9090";
+
+
+
+
+
+/***** METRICS *****/
+lazy_static! {
+    /// The number of sessions the driver keeps state for.
+    pub static ref ACTIVE_SESSIONS: IntGauge = register_int_gauge!(
+        "brane_drv_active_sessions",
+        "Number of sessions the driver keeps state for"
+    ).expect("Could not register metric");
+
+    /// The number of sessions that expired because they were idle for longer than the session TTL.
+    pub static ref EXPIRED_SESSIONS: IntCounter = register_int_counter!(
+        "brane_drv_expired_sessions_total",
+        "Number of sessions that expired after being idle for longer than the session TTL"
+    ).expect("Could not register metric");
+
+    /// The number of jobs that reached a certain state, per JobStatus.
+    pub static ref JOB_STATES: IntCounterVec = register_int_counter_vec!(
+        "brane_drv_job_states_total",
+        "Number of jobs that reached a state, by state",
+        &["status"]
+    ).expect("Could not register metric");
+
+    /// The number of jobs that timed out, per (timeout) ScheduleError variant.
+    pub static ref JOB_TIMEOUTS: IntCounterVec = register_int_counter_vec!(
+        "brane_drv_job_timeouts_total",
+        "Number of jobs that timed out, by the state they timed out in",
+        &["error"]
+    ).expect("Could not register metric");
+
+    /// The number of jobs in flight across all sessions (see `JobLimits`).
+    pub static ref JOBS_IN_FLIGHT: IntGauge = register_int_gauge!(
+        "brane_drv_jobs_in_flight",
+        "Number of jobs in flight across all sessions"
+    ).expect("Could not register metric");
+
+    /// The number of lookups in the call cache, by whether they found a result (see `CallCache`).
+    pub static ref CALL_CACHE_LOOKUPS: IntCounterVec = register_int_counter_vec!(
+        "brane_drv_call_cache_lookups_total",
+        "Number of lookups in the cache of results of pure functions, by result",
+        &["result"]
+    ).expect("Could not register metric");
+
+    /// The number of jobs in flight, per session that has any.
+    pub static ref SESSION_JOBS_IN_FLIGHT: IntGaugeVec = register_int_gauge_vec!(
+        "brane_drv_session_jobs_in_flight",
+        "Number of jobs in flight, by session",
+        &["session"]
+    ).expect("Could not register metric");
+}
+
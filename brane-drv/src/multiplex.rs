@@ -0,0 +1,278 @@
+/* MULTIPLEX.rs
+ *   by Lut99
+ *
+ * Created:
+ *   16 Oct 2026, 00:00:15
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Lets multiple clients share a session safely. Every statement that
+ *   is sent to a session gets a ticket with the next number in that
+ *   session, and statements only run once it's their turn, so that they
+ *   never see (or overwrite) each other's half-finished VM state. If the
+ *   driver is configured to reject instead of queue, a statement that
+ *   arrives while another one is busy is refused. Clients may also
+ *   follow a session to see the statements of the other clients.
+**/
+
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use dashmap::DashMap;
+use tokio::sync::Notify;
+use tonic::{Code, Status};
+
+use crate::client::{ClientReceiver, ClientSender, Followers};
+use crate::errors::MultiplexError;
+use crate::grpc;
+
+
+/***** LIBRARY STRUCTS *****/
+/// What to do with a statement that arrives while its session is still running another one.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StatementPolicy {
+    /// Wait until the statements before it have finished.
+    Queue,
+    /// Refuse it (with a 'resource exhausted' status, see `busy_status()`).
+    Reject,
+}
+
+impl Default for StatementPolicy {
+    #[inline]
+    fn default() -> Self { StatementPolicy::Queue }
+}
+
+impl Display for StatementPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            StatementPolicy::Queue  => write!(f, "queue"),
+            StatementPolicy::Reject => write!(f, "reject"),
+        }
+    }
+}
+
+impl FromStr for StatementPolicy {
+    type Err = MultiplexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "queue"  => Ok(StatementPolicy::Queue),
+            "reject" => Ok(StatementPolicy::Reject),
+            _        => Err(MultiplexError::UnknownPolicy{ raw: s.to_string() }),
+        }
+    }
+}
+
+
+
+/// The numbers of the statements of a session.
+#[derive(Debug)]
+struct Turns {
+    /// The number that the next statement gets.
+    next     : u64,
+    /// The number of the statement whose turn it is (which equals `next` if no statement is running or waiting).
+    serving  : u64,
+    /// The statements after `serving` that have already finished (or were given up on before their turn).
+    finished : BTreeSet<u64>,
+}
+
+/// Lets the statements of a single session run one at a time.
+#[derive(Debug)]
+struct SessionGate {
+    /// The numbers of the statements of the session.
+    turns     : Mutex<Turns>,
+    /// Notifies the statements that wait for their turn whenever a statement finishes.
+    done      : Notify,
+    /// The clients that follow the session.
+    followers : Arc<Followers>,
+}
+
+impl SessionGate {
+    /// Notes that the statement with the given number has finished, which makes it the turn of the next statement that hasn't.
+    fn finish(&self, sequence: u64) {
+        let mut guard = self.turns.lock().unwrap();
+        let turns = &mut *guard;
+        turns.finished.insert(sequence);
+        while turns.finished.remove(&turns.serving) { turns.serving += 1; }
+        drop(guard);
+        self.done.notify_waiters();
+    }
+}
+
+impl Default for SessionGate {
+    fn default() -> Self {
+        Self {
+            turns     : Mutex::new(Turns{ next: 1, serving: 1, finished: BTreeSet::new() }),
+            done      : Notify::new(),
+            followers : Arc::new(Followers::new()),
+        }
+    }
+}
+
+
+
+/// The right of a statement to run in its session, once it's its turn (see `Ticket::turn()`). The next statement may run once the ticket is dropped.
+#[derive(Debug)]
+pub struct Ticket {
+    /// The number of the statement within its session.
+    sequence : u64,
+    /// The client that sent the statement, if it identified itself.
+    client   : Option<String>,
+    /// The gate of the session.
+    gate     : Arc<SessionGate>,
+}
+
+impl Ticket {
+    /// Returns the number of the statement within its session.
+    #[inline]
+    pub fn sequence(&self) -> u64 { self.sequence }
+
+    /// Returns the number of statements that run or wait before this one.
+    pub fn ahead(&self) -> u64 {
+        let turns = self.gate.turns.lock().unwrap();
+        (turns.serving..self.sequence).filter(|sequence| !turns.finished.contains(sequence)).count() as u64
+    }
+
+    /// Returns a clone of the given sender that numbers the replies of this statement and copies them to the session's followers.
+    #[inline]
+    pub fn tag(&self, tx: &ClientSender) -> ClientSender { tx.tagged(self.sequence, self.client.clone(), self.gate.followers.clone()) }
+
+    /// Waits until the statements before this one have finished.
+    pub async fn turn(&self) {
+        loop {
+            // Register for the next finish before we check, so we don't miss it happening in between
+            let done = self.gate.done.notified();
+            if self.gate.turns.lock().unwrap().serving == self.sequence { return; }
+            done.await;
+        }
+    }
+
+    /// Tells the session's followers that the statement starts running.
+    /// 
+    /// **Arguments**
+    ///  * `input`: The statement itself.
+    pub fn announce(&self, input: &str) {
+        let reply = grpc::ExecuteReply {
+            close: false,
+            debug: None,
+            stderr: None,
+            stdout: None,
+            trace: None,
+            cached: None,
+            diff: None,
+            sequence: Some(self.sequence),
+            client: self.client.clone(),
+            input: Some(input.to_string()),
+        };
+        self.gate.followers.publish(&Ok(reply), self.sequence, &self.client);
+    }
+}
+
+impl Drop for Ticket {
+    #[inline]
+    fn drop(&mut self) { self.gate.finish(self.sequence); }
+}
+
+
+
+/// Hands out tickets to the statements of every session, so that clients may share a session.
+#[derive(Debug, Default)]
+pub struct Multiplexer {
+    /// What to do with a statement that arrives while its session is busy.
+    policy : StatementPolicy,
+    /// The gate of every session that ran (or is following) anything.
+    gates  : DashMap<String, Arc<SessionGate>>,
+}
+
+impl Multiplexer {
+    /// Constructor for the Multiplexer.
+    /// 
+    /// **Arguments**
+    ///  * `policy`: What to do with a statement that arrives while its session is still running another one.
+    pub fn new(policy: StatementPolicy) -> Self {
+        Self {
+            policy,
+            gates : DashMap::new(),
+        }
+    }
+
+
+
+    /// Gives a new statement of the given session a ticket, which is the next number in the session.
+    /// 
+    /// **Arguments**
+    ///  * `uuid`: The session that the statement is sent to.
+    ///  * `client`: The client that sent the statement, if it identified itself.
+    /// 
+    /// **Returns**  
+    /// The Ticket of the statement, or a MultiplexError if the session is busy and we reject statements instead of queueing them.
+    pub fn admit(&self, uuid: &str, client: Option<String>) -> Result<Ticket, MultiplexError> {
+        let gate = self.gate(uuid);
+        let sequence = {
+            let mut turns = gate.turns.lock().unwrap();
+            if self.policy == StatementPolicy::Reject && turns.serving != turns.next {
+                return Err(MultiplexError::SessionBusy{ uuid: uuid.to_string(), running: turns.serving });
+            }
+            turns.next += 1;
+            turns.next - 1
+        };
+        Ok(Ticket{ sequence, client, gate })
+    }
+
+    /// Starts following the given session.
+    /// 
+    /// **Arguments**
+    ///  * `uuid`: The session to follow.
+    ///  * `capacity`: The number of replies that may wait for the follower.
+    /// 
+    /// **Returns**  
+    /// The stream with the replies of every statement that runs in the session from now on.
+    #[inline]
+    pub fn follow(&self, uuid: &str, capacity: usize) -> ClientReceiver { self.gate(uuid).followers.follow(capacity) }
+
+    /// Returns the number of clients that follow the given session.
+    #[inline]
+    pub fn followers(&self, uuid: &str) -> usize { self.gates.get(uuid).map(|gate| gate.followers.len()).unwrap_or(0) }
+
+    /// Returns what we do with a statement that arrives while its session is busy.
+    #[inline]
+    pub fn policy(&self) -> StatementPolicy { self.policy }
+
+    /// Forgets the given session (because it was closed or expired). Statements that already have a ticket still run in order, but its followers stop receiving replies once those are done.
+    /// 
+    /// **Arguments**
+    ///  * `uuid`: The session to forget.
+    #[inline]
+    pub fn remove(&self, uuid: &str) { self.gates.remove(uuid); }
+
+
+
+    /// Returns the gate of the given session, creating it if it doesn't exist yet.
+    fn gate(&self, uuid: &str) -> Arc<SessionGate> {
+        self.gates.entry(uuid.to_string()).or_default().clone()
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Returns the status with which a statement is refused because its session is busy (see `StatementPolicy::Reject`).
+/// 
+/// **Arguments**
+///  * `err`: The MultiplexError that `Multiplexer::admit()` refused the statement with.
+/// 
+/// **Returns**  
+/// A 'resource exhausted' Status, which the driver uses for nothing else (see `is_busy_status()`).
+#[inline]
+pub fn busy_status(err: &MultiplexError) -> Status { Status::resource_exhausted(err.to_string()) }
+
+/// Returns whether the given status tells that a statement was refused because its session is busy (see `busy_status()`). The client may send it again once the session is free.
+#[inline]
+pub fn is_busy_status(status: &Status) -> bool { status.code() == Code::ResourceExhausted }
@@ -0,0 +1,134 @@
+/* OUTPUTS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   14 Oct 2026, 10:12:41
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Keeps track of the results of recently finished jobs, so that clients
+ *   can fetch their output after the fact.
+**/
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+
+/***** LIBRARY STRUCTS *****/
+/// Defines the (raw) output of a job that has stopped running.
+#[derive(Clone, Debug)]
+pub enum JobOutput {
+    /// The job finished successfully; contains the JSON-encoded Value it returned.
+    Finished{ res: String },
+    /// The job failed; contains the JSON-encoded FailureResult with its exit code, stdout and stderr.
+    Failed{ res: String },
+}
+
+
+
+impl JobOutput {
+    /// Returns the number of bytes that this output takes up in the store.
+    #[inline]
+    fn size(&self) -> usize {
+        match self {
+            JobOutput::Finished{ res } | JobOutput::Failed{ res } => res.len(),
+        }
+    }
+}
+
+
+
+/// The outputs in a JobOutputs, together with the order in which they are inserted and their total size.
+#[derive(Debug, Default)]
+struct Outputs {
+    /// The outputs themselves.
+    outputs : HashMap<String, JobOutput>,
+    /// The order in which the outputs were inserted (oldest first).
+    order   : VecDeque<String>,
+    /// The total size of the stored outputs (including their correlation IDs), in bytes.
+    size    : usize,
+}
+
+impl Outputs {
+    /// Removes the output of the given job, if any.
+    fn remove(&mut self, correlation_id: &str) {
+        if let Some(output) = self.outputs.remove(correlation_id) {
+            self.size -= correlation_id.len() + output.size();
+        }
+    }
+}
+
+
+
+/// A bounded store of job outputs, indexed by correlation ID. If the store is full, the oldest entries are evicted first.
+#[derive(Debug)]
+pub struct JobOutputs {
+    /// The maximum number of bytes of outputs we keep around.
+    capacity : usize,
+    /// The outputs themselves.
+    inner    : Mutex<Outputs>,
+}
+
+impl JobOutputs {
+    /// Constructor for the JobOutputs.
+    /// 
+    /// **Arguments**
+    ///  * `capacity`: The maximum total size (in bytes) of the outputs to store. If 0, nothing is stored at all.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner : Mutex::new(Outputs::default()),
+        }
+    }
+
+
+
+    /// Stores the output of the given job, evicting the oldest outputs until it fits.
+    /// 
+    /// Outputs that are larger than the store as a whole are not stored at all.
+    /// 
+    /// **Arguments**
+    ///  * `correlation_id`: The ID of the job that produced the output.
+    ///  * `output`: The output to store. Overwrites any previous output of the same job.
+    pub fn insert(&self, correlation_id: String, output: JobOutput) {
+        let size = correlation_id.len() + output.size();
+        let mut inner = self.inner.lock().expect("Could not lock job outputs");
+
+        // If we already know the job, then forget its old output (also if the new one doesn't fit)
+        if inner.outputs.contains_key(&correlation_id) {
+            inner.remove(&correlation_id);
+            inner.order.retain(|id| id != &correlation_id);
+        }
+        if size > self.capacity { return; }
+
+        // Evict the oldest results until the new one fits
+        while inner.size + size > self.capacity {
+            match inner.order.pop_front() {
+                Some(oldest) => { inner.remove(&oldest); },
+                None         => { break; },
+            }
+        }
+        inner.outputs.insert(correlation_id.clone(), output);
+        inner.order.push_back(correlation_id);
+        inner.size += size;
+    }
+
+    /// Returns the output of the given job, if we (still) know it.
+    /// 
+    /// **Arguments**
+    ///  * `correlation_id`: The ID of the job to get the output of.
+    /// 
+    /// **Returns**  
+    /// A copy of the job's output, or None if we never saw it or already evicted it.
+    pub fn get(&self, correlation_id: &str) -> Option<JobOutput> {
+        let inner = self.inner.lock().expect("Could not lock job outputs");
+        inner.outputs.get(correlation_id).cloned()
+    }
+
+    /// Returns the total size of the outputs that are currently stored, in bytes.
+    #[inline]
+    pub fn size(&self) -> usize { self.inner.lock().expect("Could not lock job outputs").size }
+}
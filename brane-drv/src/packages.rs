@@ -43,6 +43,9 @@ pub async fn get_package_index(graphql_endpoint: &str) -> Result<PackageIndex> {
         .map(|p| {
             let functions = p.functions_as_json.map(|f| serde_json::from_str(&f).unwrap());
             let types = p.types_as_json.map(|t| serde_json::from_str(&t).unwrap());
+            let dependencies = p.dependencies_as_json.map(|d| serde_json::from_str(&d).unwrap());
+            let requirements = p.requirements_as_json.map(|r| serde_json::from_str(&r).unwrap());
+            let environment = p.environment_as_json.map(|e| serde_json::from_str(&e).unwrap());
             // TODO: Return properly
             let kind = PackageKind::from_str(&p.kind).unwrap();
 
@@ -58,6 +61,12 @@ pub async fn get_package_index(graphql_endpoint: &str) -> Result<PackageIndex> {
                 name: p.name,
                 owners: p.owners,
                 types: types.unwrap_or_default(),
+                dependencies: dependencies.unwrap_or_default(),
+                platforms: vec![],
+                requirements: requirements.unwrap_or_default(),
+                reproducible: None,
+                init: vec![],
+                environment: environment.unwrap_or_default(),
                 version: Version::from_str(&version).unwrap_or_else(|err| panic!("Could not parse GraphQL-obtained package version '{}': {}", &version, err)),
             }
         })
@@ -0,0 +1,119 @@
+/* PLANNER.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 23:59:44
+ * Last edited:
+ *   15 Oct 2026, 23:59:44
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Picks a location for external calls that don't name one, by matching
+ *   the requirements of their package against the capabilities that the
+ *   locations declare in the infrastructure file.
+**/
+
+use brane_cfg::Infrastructure;
+use brane_cfg::infrastructure::{LocationCapabilities, MemoryLimit};
+use specifications::package::PackageRequirements;
+
+use crate::errors::PlannerError;
+
+
+/***** LIBRARY STRUCTS *****/
+/// The location that the planner picked for a call.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LocationChoice {
+    /// The name of the location.
+    pub location   : String,
+    /// Its cost, as declared in its capabilities.
+    pub cost       : u64,
+    /// The number of locations that satisfied the requirements (including this one).
+    pub candidates : usize,
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Returns which of the given requirements a location with the given capabilities does not satisfy.
+/// 
+/// Capabilities that the location does not declare are not offered, and operating systems and architectures are compared case-insensitively.
+/// 
+/// **Arguments**
+///  * `requirements`: The requirements of the package.
+///  * `capabilities`: The capabilities of the location.
+/// 
+/// **Returns**  
+/// A description of every unmet requirement, which is empty if the location satisfies them all.
+pub fn unmet_requirements(requirements: &PackageRequirements, capabilities: &LocationCapabilities) -> Vec<String> {
+    let mut unmet: Vec<String> = vec![];
+    if requirements.gpu && !capabilities.gpu { unmet.push(String::from("gpu")); }
+    if let Some(min_memory) = &requirements.min_memory {
+        match min_memory.parse::<MemoryLimit>() {
+            Ok(MemoryLimit(needed)) => match capabilities.memory {
+                Some(MemoryLimit(offered)) if offered >= needed => {},
+                Some(MemoryLimit(offered))                      => unmet.push(format!("min_memory {} (offers {} bytes)", min_memory, offered)),
+                None                                            => unmet.push(format!("min_memory {} (offers no memory)", min_memory)),
+            },
+            Err(err) => unmet.push(format!("min_memory {} ({})", min_memory, err)),
+        }
+    }
+    for (requirement, needed, offered) in [ ("os", &requirements.os, &capabilities.os), ("arch", &requirements.arch, &capabilities.arch) ] {
+        if let Some(needed) = needed {
+            match offered {
+                Some(offered) if offered.eq_ignore_ascii_case(needed) => {},
+                Some(offered)                                         => unmet.push(format!("{} {} (offers {})", requirement, needed, offered)),
+                None                                                  => unmet.push(format!("{} {} (offers none)", requirement, needed)),
+            }
+        }
+    }
+    unmet
+}
+
+/// Picks the cheapest of the given locations that satisfies the given requirements. Locations that are equally cheap are picked in alphabetical order, so the choice is deterministic.
+/// 
+/// **Arguments**
+///  * `requirements`: The requirements of the package.
+///  * `locations`: The names of the locations to pick from, together with their capabilities.
+/// 
+/// **Returns**  
+/// The LocationChoice on success, or a PlannerError listing what every location lacks if none satisfies the requirements.
+pub fn select_location(requirements: &PackageRequirements, locations: &[(String, LocationCapabilities)]) -> Result<LocationChoice, PlannerError> {
+    if locations.is_empty() { return Err(PlannerError::NoLocations); }
+
+    let mut matching: Vec<(&str, u64)> = vec![];
+    let mut unmet: Vec<(String, Vec<String>)> = vec![];
+    for (name, capabilities) in locations {
+        let lacks = unmet_requirements(requirements, capabilities);
+        if lacks.is_empty() { matching.push((name, capabilities.cost)); } else { unmet.push((name.clone(), lacks)); }
+    }
+
+    match matching.iter().min_by(|(lhs_name, lhs_cost), (rhs_name, rhs_cost)| lhs_cost.cmp(rhs_cost).then_with(|| lhs_name.cmp(rhs_name))) {
+        Some((location, cost)) => Ok(LocationChoice{ location: location.to_string(), cost: *cost, candidates: matching.len() }),
+        None                   => {
+            unmet.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+            Err(PlannerError::NoMatchingLocation{ requirements: requirements.clone(), unmet })
+        },
+    }
+}
+
+/// Picks the cheapest location in the given infrastructure file that satisfies the given requirements (see `select_location()`).
+/// 
+/// **Arguments**
+///  * `requirements`: The requirements of the package.
+///  * `infra`: The infrastructure file with the locations and their capabilities.
+/// 
+/// **Returns**  
+/// The LocationChoice on success, or a PlannerError if the file could not be read or no location satisfies the requirements.
+pub fn plan_location(requirements: &PackageRequirements, infra: &Infrastructure) -> Result<LocationChoice, PlannerError> {
+    let names = infra.get_locations().map_err(|err| PlannerError::InfrastructureError{ err })?;
+    let mut locations: Vec<(String, LocationCapabilities)> = Vec::with_capacity(names.len());
+    for name in names {
+        let location = infra.get_location_metadata(&name).map_err(|err| PlannerError::InfrastructureError{ err })?;
+        locations.push((name, location.get_capabilities().clone()));
+    }
+    select_location(requirements, &locations)
+}
@@ -0,0 +1,409 @@
+/* SESSIONS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 16:02:18
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Keeps track of the state of every session: the VmState in between
+ *   statements, and the jobs that the session is still waiting for. If
+ *   given a directory, both are written to disk on every change so that
+ *   a restarted driver can pick up where it left off. Sessions that are
+ *   idle for longer than the session TTL expire, and clients that come
+ *   back to them are told so. Sessions that several clients use are
+ *   only closed once the last of them is done with it.
+**/
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use brane_bvm::vm::VmState;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use specifications::common::{FunctionExt, Value};
+use specifications::version::Version;
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::{Code, Status};
+
+use crate::errors::SessionError;
+
+
+/***** CONSTANTS *****/
+/// The metadata key that marks the statuses of calls for expired sessions (see `expired_status()`).
+pub const EXPIRED_METADATA: &str = "brane-session-expired";
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// A job that a session has scheduled, but that it has not seen finish yet.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PendingJob {
+    /// The correlation ID of the job.
+    pub correlation_id : String,
+    /// Identifies the call that scheduled the job (see `call_key()`), so that the job is picked up again if the same call is made again.
+    pub call_key       : String,
+    /// The name of the function that the job runs.
+    pub function       : String,
+    /// The package that provides the function.
+    pub package        : String,
+    /// The version of that package.
+    pub version        : Version,
+    /// When the job was scheduled, in seconds since the Unix epoch.
+    pub scheduled_at   : u64,
+}
+
+impl PendingJob {
+    /// Constructor for the PendingJob, which is scheduled right now.
+    /// 
+    /// **Arguments**
+    ///  * `correlation_id`: The correlation ID of the job.
+    ///  * `call_key`: The key of the call that scheduled the job (see `call_key()`).
+    ///  * `function`: The function that the job runs.
+    pub fn new(correlation_id: String, call_key: String, function: &FunctionExt) -> Self {
+        Self {
+            correlation_id,
+            call_key,
+            function : function.name.clone(),
+            package  : function.package.clone(),
+            version  : function.version.clone(),
+            scheduled_at : now(),
+        }
+    }
+
+    /// Returns how many seconds ago the job was scheduled.
+    #[inline]
+    pub fn age(&self) -> u64 { now().saturating_sub(self.scheduled_at) }
+}
+
+
+
+/// The part of a session that is written to disk.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct PersistedSession {
+    /// The state of the session's VM after its last statement, if it ran any.
+    state   : Option<VmState>,
+    /// The jobs the session is still waiting for.
+    pending : Vec<PendingJob>,
+    /// When the session was last used, in seconds since the Unix epoch. Not set by drivers that predate session expiry.
+    #[serde(default)]
+    last_active : Option<u64>,
+}
+
+
+
+/// Keeps the VmState and the pending jobs of every session, optionally persisting them to a directory (one JSON file per session).
+#[derive(Debug, Default)]
+pub struct SessionStore {
+    /// The directory to persist sessions in, if any.
+    dir     : Option<PathBuf>,
+    /// The state of every session's VM.
+    states  : DashMap<String, VmState>,
+    /// The jobs every session is still waiting for.
+    pending : DashMap<String, Vec<PendingJob>>,
+    /// When every session was last used.
+    last_active : DashMap<String, SystemTime>,
+    /// The sessions that expired, with when they did, so that clients that come back to them can be told so.
+    expired : DashMap<String, SystemTime>,
+    /// The clients that created or attached to every session and are not done with it yet (if they identified themselves).
+    clients : DashMap<String, HashSet<String>>,
+}
+
+impl SessionStore {
+    /// Constructor for the SessionStore, which restores any sessions persisted in the given directory.
+    /// 
+    /// **Arguments**
+    ///  * `dir`: The directory to persist sessions in. If None, sessions live in memory only (and are lost when the driver restarts).
+    /// 
+    /// **Returns**  
+    /// A new SessionStore, or a SessionError if the directory could not be created or read.
+    pub fn new(dir: Option<PathBuf>) -> Result<Self, SessionError> {
+        let store = Self{ dir, ..Default::default() };
+        let dir = match &store.dir {
+            Some(dir) => dir,
+            None      => { return Ok(store); }
+        };
+        if let Err(err) = fs::create_dir_all(dir) { return Err(SessionError::DirCreateError{ path: dir.clone(), err }); }
+
+        // Restore every session in it
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err)    => { return Err(SessionError::DirReadError{ path: dir.clone(), err }); }
+        };
+        for entry in entries {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(err)  => { return Err(SessionError::DirReadError{ path: dir.clone(), err }); }
+            };
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") { continue; }
+            let uuid = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(uuid) => uuid.to_string(),
+                None       => { continue; }
+            };
+
+            let session = read_session(&path)?;
+            // Sessions persisted before we kept track of their activity start counting now
+            store.last_active.insert(uuid.clone(), session.last_active.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)).unwrap_or_else(SystemTime::now));
+            if let Some(state) = session.state { store.states.insert(uuid.clone(), state); }
+            if !session.pending.is_empty() { store.pending.insert(uuid, session.pending); }
+        }
+        if !store.states.is_empty() || !store.pending.is_empty() {
+            info!("Restored {} session(s) from '{}' ({} with pending jobs).", store.states.len(), dir.display(), store.pending.len());
+        }
+
+        Ok(store)
+    }
+
+
+
+    /// Returns the state of the given session's VM, if it ran anything yet.
+    #[inline]
+    pub fn state(&self, uuid: &str) -> Option<VmState> { self.states.get(uuid).map(|state| state.clone()) }
+
+    /// Updates the state of the given session's VM.
+    /// 
+    /// **Arguments**
+    ///  * `uuid`: The UUID of the session.
+    ///  * `state`: The state of its VM after the last statement.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or a SessionError if we could not persist the session. Even then, the new state is kept in memory.
+    pub fn set_state(&self, uuid: &str, state: VmState) -> Result<(), SessionError> {
+        self.states.insert(uuid.to_string(), state);
+        self.touch(uuid);
+        self.persist(uuid)
+    }
+
+    /// Returns the number of sessions that ran anything.
+    #[inline]
+    pub fn len(&self) -> usize { self.states.len() }
+
+    /// Returns whether no session ran anything yet.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.states.is_empty() }
+
+
+
+    /// Notes that the given session is used right now, which postpones its expiry.
+    #[inline]
+    pub fn touch(&self, uuid: &str) { self.last_active.insert(uuid.to_string(), SystemTime::now()); }
+
+    /// Returns the number of sessions that have been used and have not expired or been closed since.
+    #[inline]
+    pub fn active(&self) -> usize { self.last_active.len() }
+
+    /// Returns whether the given session is known, i.e., has been created (or restored) and has not expired or been closed since.
+    #[inline]
+    pub fn is_known(&self, uuid: &str) -> bool { self.last_active.contains_key(uuid) }
+
+    /// Returns whether the given session has expired (see `expire_idle()`).
+    #[inline]
+    pub fn is_expired(&self, uuid: &str) -> bool { self.expired.contains_key(uuid) }
+
+    /// Notes that the given client uses the given session, because it created or attached to it. A client that attaches again (e.g., after reconnecting) is counted once.
+    /// 
+    /// **Arguments**
+    ///  * `uuid`: The UUID of the session.
+    ///  * `client`: The ID of the client.
+    #[inline]
+    pub fn attach(&self, uuid: &str, client: &str) { self.clients.entry(uuid.to_string()).or_default().insert(client.to_string()); }
+
+    /// Notes that the given client is done with the given session.
+    /// 
+    /// **Arguments**
+    ///  * `uuid`: The UUID of the session.
+    ///  * `client`: The ID of the client, if it identified itself.
+    /// 
+    /// **Returns**  
+    /// The number of other clients that still use the session, which should only be closed if there are none.
+    pub fn detach(&self, uuid: &str, client: Option<&str>) -> usize {
+        let remaining = match self.clients.get_mut(uuid) {
+            Some(mut clients) => {
+                if let Some(client) = client { clients.remove(client); }
+                clients.len()
+            },
+            None => 0,
+        };
+        self.clients.remove_if(uuid, |_, clients| clients.is_empty());
+        remaining
+    }
+
+    /// Forgets the given session: its state, the jobs it waits for, the clients that use it and its persisted file.
+    /// 
+    /// **Arguments**
+    ///  * `uuid`: The UUID of the session.
+    /// 
+    /// **Returns**  
+    /// Whether we knew the session, or a SessionError if we could not remove its persisted file. Even then, the session is forgotten in memory.
+    pub fn close(&self, uuid: &str) -> Result<bool, SessionError> {
+        let known = self.last_active.remove(uuid).is_some() | self.states.remove(uuid).is_some() | self.pending.remove(uuid).is_some();
+        self.clients.remove(uuid);
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None      => { return Ok(known); }
+        };
+        let path = dir.join(format!("{}.json", uuid));
+        match fs::remove_file(&path) {
+            Ok(_)                                                   => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(known),
+            Err(err)                                                => Err(SessionError::FileRemoveError{ path, err }),
+        }
+    }
+
+    /// Expires the sessions that have not been used for at least the given time, forgetting them like `close()` does. Sessions that still wait for jobs are never idle.
+    /// 
+    /// The expired sessions are remembered for as long again, so that clients that come back to them are told that they expired.
+    /// 
+    /// **Arguments**
+    ///  * `ttl`: The time after which idle sessions expire.
+    /// 
+    /// **Returns**  
+    /// The UUIDs of the sessions that expired.
+    pub fn expire_idle(&self, ttl: Duration) -> Vec<String> {
+        let now = SystemTime::now();
+        let idle = |since: &SystemTime| now.duration_since(*since).map(|idle| idle >= ttl).unwrap_or(false);
+        self.expired.retain(|_, since| !idle(since));
+
+        let expired: Vec<String> = self.last_active.iter()
+            .filter(|entry| idle(entry.value()) && !self.pending.contains_key(entry.key()))
+            .map(|entry| entry.key().clone())
+            .collect();
+        for uuid in &expired {
+            if let Err(err) = self.close(uuid) { warn!("Could not remove expired session: {}", err); }
+            self.expired.insert(uuid.clone(), now);
+        }
+        expired
+    }
+
+
+
+    /// Returns the jobs that the given session is still waiting for.
+    #[inline]
+    pub fn pending(&self, uuid: &str) -> Vec<PendingJob> { self.pending.get(uuid).map(|pending| pending.clone()).unwrap_or_default() }
+
+    /// Notes that the given session waits for the given job.
+    /// 
+    /// **Arguments**
+    ///  * `uuid`: The UUID of the session.
+    ///  * `job`: The job it waits for.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or a SessionError if we could not persist the session.
+    pub fn add_pending(&self, uuid: &str, job: PendingJob) -> Result<(), SessionError> {
+        self.pending.entry(uuid.to_string()).or_default().push(job);
+        self.touch(uuid);
+        self.persist(uuid)
+    }
+
+    /// Notes that the given session no longer waits for the given job (because it finished, failed or is considered lost).
+    /// 
+    /// **Arguments**
+    ///  * `uuid`: The UUID of the session.
+    ///  * `correlation_id`: The ID of the job.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or a SessionError if we could not persist the session.
+    pub fn remove_pending(&self, uuid: &str, correlation_id: &str) -> Result<(), SessionError> {
+        let removed = match self.pending.get_mut(uuid) {
+            Some(mut pending) => {
+                let before = pending.len();
+                pending.retain(|job| job.correlation_id != correlation_id);
+                pending.len() != before
+            },
+            None => false,
+        };
+        self.pending.remove_if(uuid, |_, pending| pending.is_empty());
+        if removed { self.persist(uuid) } else { Ok(()) }
+    }
+
+
+
+    /// Writes the given session to the session directory (if any).
+    fn persist(&self, uuid: &str) -> Result<(), SessionError> {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None      => { return Ok(()); }
+        };
+        let session = PersistedSession {
+            state   : self.state(uuid),
+            pending : self.pending(uuid),
+            last_active : self.last_active.get(uuid).and_then(|since| since.duration_since(UNIX_EPOCH).ok()).map(|since| since.as_secs()),
+        };
+        let contents = match serde_json::to_string(&session) {
+            Ok(contents) => contents,
+            Err(err)     => { return Err(SessionError::SerializeError{ uuid: uuid.to_string(), err }); }
+        };
+
+        // Write to a temporary file first, so a crash never leaves a half-written session behind
+        let path = dir.join(format!("{}.json", uuid));
+        let temp = dir.join(format!(".{}.json.tmp", uuid));
+        if let Err(err) = fs::write(&temp, contents) { return Err(SessionError::FileWriteError{ path: temp, err }); }
+        if let Err(err) = fs::rename(&temp, &path) { return Err(SessionError::FileWriteError{ path, err }); }
+        Ok(())
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Computes a key that identifies an external call by its function, arguments, location and package environment (see `with_env()`), which is used to recognize a statement that is retried after the driver restarted.
+/// 
+/// **Arguments**
+///  * `function`: The function that is called.
+///  * `arguments`: The arguments it is called with.
+///  * `location`: The location it is called on, if any.
+/// 
+/// **Returns**  
+/// The key as a string. Calls with the same function, arguments, location and package environment have the same key.
+pub fn call_key(function: &FunctionExt, arguments: &HashMap<String, Value>, location: &Option<String>) -> String {
+    // Going through a JSON value sorts the arguments (also nested maps), as the order of a HashMap is not stable
+    let arguments = serde_json::to_value(arguments).map(|arguments| arguments.to_string()).unwrap_or_default();
+    // Calls without a package environment keep the key they had before it existed
+    let env = if function.env.is_empty() { String::new() } else { serde_json::to_value(&function.env).map(|env| format!("+env{}", env)).unwrap_or_default() };
+    format!("{}:{}/{}@{}{}{}", function.package, function.version, function.name, location.as_deref().unwrap_or("*"), arguments, env)
+}
+
+
+
+/// Returns the status with which calls for the given (expired) session fail.
+/// 
+/// **Arguments**
+///  * `uuid`: The UUID of the session.
+/// 
+/// **Returns**  
+/// A 'failed precondition' Status, marked with `EXPIRED_METADATA` so that it can be told apart from other failed preconditions (see `is_expired_status()`).
+pub fn expired_status(uuid: &str) -> Status {
+    let mut metadata = MetadataMap::new();
+    metadata.insert(EXPIRED_METADATA, MetadataValue::from_static("true"));
+    Status::with_metadata(Code::FailedPrecondition, format!("Session '{}' has expired after being idle for too long; start a new one", uuid), metadata)
+}
+
+/// Returns whether the given status tells that the session of the call has expired (see `expired_status()`).
+#[inline]
+pub fn is_expired_status(status: &Status) -> bool { status.code() == Code::FailedPrecondition && status.metadata().contains_key(EXPIRED_METADATA) }
+
+
+
+/// Returns the current time in seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or_default()
+}
+
+/// Reads a persisted session from the given file.
+fn read_session(path: &Path) -> Result<PersistedSession, SessionError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err)     => { return Err(SessionError::FileReadError{ path: path.to_path_buf(), err }); }
+    };
+    match serde_json::from_str(&contents) {
+        Ok(session) => Ok(session),
+        Err(err)    => Err(SessionError::FileParseError{ path: path.to_path_buf(), err }),
+    }
+}
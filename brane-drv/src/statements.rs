@@ -0,0 +1,167 @@
+/* STATEMENTS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 19:40:12
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Remembers the status of statements by the token that the client sent
+ *   along with them, so that a client that lost its connection may send
+ *   a statement again without it being run twice.
+**/
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use tonic::{Code, Status};
+
+use crate::grpc;
+
+
+/***** LIBRARY STRUCTS *****/
+/// The status of a statement that was sent with a token.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StatementStatus {
+    /// The statement is still running.
+    Running,
+    /// The statement has finished; contains the closing reply, or the code and message of the error the statement was refused with.
+    Done(Result<grpc::ExecuteReply, (Code, String)>),
+}
+
+impl StatementStatus {
+    /// Returns whether the statement has finished.
+    #[inline]
+    pub fn is_done(&self) -> bool { matches!(self, StatementStatus::Done(_)) }
+}
+
+
+
+/// Keeps the status of statements by session and token. Only the given number of finished statements is kept; if there are more, the oldest are evicted first. Running statements are never evicted.
+#[derive(Debug)]
+pub struct StatementCache {
+    /// The maximum number of finished statements that we keep around.
+    capacity : usize,
+    /// The statuses, indexed by session and token, together with the order in which the statements finished.
+    inner    : Mutex<(HashMap<(String, String), StatementStatus>, VecDeque<(String, String)>)>,
+}
+
+impl StatementCache {
+    /// Constructor for the StatementCache.
+    /// 
+    /// **Arguments**
+    ///  * `capacity`: The maximum number of finished statements to remember.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner : Mutex::new((HashMap::new(), VecDeque::with_capacity(capacity))),
+        }
+    }
+
+
+
+    /// Marks the statement with the given token as running, unless we have seen the token before.
+    /// 
+    /// **Arguments**
+    ///  * `uuid`: The session that sent the statement.
+    ///  * `token`: The token that identifies the statement in the session.
+    /// 
+    /// **Returns**  
+    /// None if the statement is new (and should thus be run), or its current status if we have seen it before.
+    pub fn begin(&self, uuid: &str, token: &str) -> Option<StatementStatus> {
+        let mut inner = self.inner.lock().unwrap();
+        let key = (uuid.to_string(), token.to_string());
+        if let Some(status) = inner.0.get(&key) { return Some(status.clone()); }
+        inner.0.insert(key, StatementStatus::Running);
+        None
+    }
+
+    /// Marks the statement with the given token as done, evicting the oldest finished statement if we're at capacity.
+    /// 
+    /// **Arguments**
+    ///  * `uuid`: The session that sent the statement.
+    ///  * `token`: The token that identifies the statement in the session.
+    ///  * `result`: The closing reply of the statement, or the status it was refused with.
+    pub fn finish(&self, uuid: &str, token: &str, result: &Result<grpc::ExecuteReply, Status>) {
+        let mut inner = self.inner.lock().unwrap();
+        let (statuses, order) = &mut *inner;
+        let key = (uuid.to_string(), token.to_string());
+
+        let result = match result {
+            Ok(reply)   => Ok(reply.clone()),
+            Err(status) => Err((status.code(), status.message().to_string())),
+        };
+        if !statuses.get(&key).map(StatementStatus::is_done).unwrap_or(false) { order.push_back(key.clone()); }
+        statuses.insert(key, StatementStatus::Done(result));
+
+        while order.len() > self.capacity {
+            if let Some(oldest) = order.pop_front() { statuses.remove(&oldest); }
+        }
+    }
+
+    /// Forgets a statement that was marked as running but will not run after all, so that it may be sent again.
+    /// 
+    /// **Arguments**
+    ///  * `uuid`: The session that sent the statement.
+    ///  * `token`: The token that identifies the statement in the session.
+    pub fn forget(&self, uuid: &str, token: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        let key = (uuid.to_string(), token.to_string());
+        if inner.0.get(&key) == Some(&StatementStatus::Running) { inner.0.remove(&key); }
+    }
+
+    /// Returns the status of the statement with the given token, if we know it.
+    /// 
+    /// **Arguments**
+    ///  * `uuid`: The session that sent the statement.
+    ///  * `token`: The token that identifies the statement in the session.
+    #[inline]
+    pub fn status(&self, uuid: &str, token: &str) -> Option<StatementStatus> {
+        self.inner.lock().unwrap().0.get(&(uuid.to_string(), token.to_string())).cloned()
+    }
+}
+
+
+
+/// Marks a statement that was marked as running as done once it goes out of scope, so that a statement whose run stops early (e.g., because it panicked) does not keep the clients that send it again waiting forever.
+#[derive(Debug)]
+pub struct StatementGuard {
+    /// The cache with the status of the statement.
+    statements : Arc<StatementCache>,
+    /// The session that sent the statement.
+    uuid       : String,
+    /// The token that identifies the statement in the session, or None if the client sent none (or the statement is done).
+    token      : Option<String>,
+}
+
+impl StatementGuard {
+    /// Constructor for the StatementGuard.
+    /// 
+    /// **Arguments**
+    ///  * `statements`: The cache with the status of the statement.
+    ///  * `uuid`: The session that sent the statement.
+    ///  * `token`: The token that identifies the statement in the session, if the client sent one.
+    #[inline]
+    pub fn new(statements: Arc<StatementCache>, uuid: String, token: Option<String>) -> Self {
+        Self { statements, uuid, token }
+    }
+
+
+
+    /// Marks the statement as done with the given result, after which dropping the guard does nothing anymore.
+    /// 
+    /// **Arguments**
+    ///  * `result`: The closing reply of the statement, or the status it was refused with.
+    pub fn finish(&mut self, result: &Result<grpc::ExecuteReply, Status>) {
+        if let Some(token) = self.token.take() { self.statements.finish(&self.uuid, &token, result); }
+    }
+}
+
+impl Drop for StatementGuard {
+    fn drop(&mut self) {
+        self.finish(&Err(Status::aborted("The driver stopped running the statement before it finished")));
+    }
+}
@@ -0,0 +1,42 @@
+use brane_drv::executor::decode_finished;
+use specifications::common::Value;
+
+#[test]
+fn results_without_artifacts_are_untouched() {
+    let (value, stats) = decode_finished("{\"v\":\"integer\",\"c\":42,\"stats\":{\"wall_time\":1.5}}").unwrap();
+    assert_eq!(value, Value::Integer(42));
+    assert_eq!(stats.unwrap().wall_time, 1.5);
+
+    assert!(decode_finished("not json").is_err());
+}
+
+#[test]
+fn artifacts_are_wrapped_with_the_result() {
+    let payload = "{\"v\":\"integer\",\"c\":42,\"artifacts\":[{\"name\":\"out/plot.png\",\"size\":3,\"path\":\"/data/artifacts/job-1/out/plot.png\",\"sha256\":\"ab12\"}]}";
+    let (value, stats) = decode_finished(payload).unwrap();
+    assert!(stats.is_none());
+
+    let properties = match value {
+        Value::Struct{ data_type, properties } if data_type == "Output" => properties,
+        value => panic!("Expected an Output, got {:?}", value),
+    };
+    assert_eq!(properties["value"], Value::Integer(42));
+    let entries = match &properties["artifacts"] {
+        Value::Array{ data_type, entries } if data_type == "Artifact[]" => entries,
+        value => panic!("Expected an array of Artifacts, got {:?}", value),
+    };
+    assert_eq!(entries.len(), 1);
+    match &entries[0] {
+        Value::Struct{ data_type, properties } if data_type == "Artifact" => {
+            assert_eq!(properties["name"], Value::Unicode(String::from("out/plot.png")));
+            assert_eq!(properties["size"], Value::Integer(3));
+            assert_eq!(properties["path"], Value::Unicode(String::from("/data/artifacts/job-1/out/plot.png")));
+            assert_eq!(properties["sha256"], Value::Unicode(String::from("ab12")));
+        },
+        value => panic!("Expected an Artifact, got {:?}", value),
+    }
+
+    // A function that declares outputs that matched nothing still returns an Output, so scripts don't have to guess
+    let (value, _) = decode_finished("{\"v\":\"unit\",\"artifacts\":[]}").unwrap();
+    assert!(value.conforms_to("Output"));
+}
@@ -0,0 +1,64 @@
+use std::fs;
+
+use brane_drv::auth::{self, BearerToken, TokenInterceptor, AUTHORIZATION_KEY};
+use brane_drv::errors::AuthError;
+use tonic::service::Interceptor;
+use tonic::{Code, Request};
+
+/// Runs a request with the given authorization metadata (if any) through an interceptor that knows the given tokens.
+fn check(tokens: &[&str], authorization: Option<&str>) -> Result<(), Code> {
+    let mut request = Request::new(());
+    if let Some(authorization) = authorization {
+        request.metadata_mut().insert(AUTHORIZATION_KEY, authorization.parse().unwrap());
+    }
+    let mut interceptor = TokenInterceptor::new(tokens.iter().map(|token| token.to_string()).collect());
+    interceptor.call(request).map(|_| ()).map_err(|status| status.code())
+}
+
+#[test]
+fn known_tokens_are_accepted() {
+    assert_eq!(check(&["secret"], Some("Bearer secret")), Ok(()));
+    assert_eq!(check(&["first", "second"], Some("Bearer second")), Ok(()));
+}
+
+#[test]
+fn missing_or_invalid_tokens_are_unauthenticated() {
+    assert_eq!(check(&["secret"], None), Err(Code::Unauthenticated));
+    assert_eq!(check(&["secret"], Some("Bearer wrong")), Err(Code::Unauthenticated));
+    assert_eq!(check(&["secret"], Some("Bearer secre")), Err(Code::Unauthenticated));
+    assert_eq!(check(&["secret"], Some("Bearer ")), Err(Code::Unauthenticated));
+    // Only bearer tokens count
+    assert_eq!(check(&["secret"], Some("secret")), Err(Code::Unauthenticated));
+    assert_eq!(check(&["secret"], Some("Basic secret")), Err(Code::Unauthenticated));
+}
+
+#[test]
+fn no_tokens_disables_authentication() {
+    assert!(!TokenInterceptor::new(vec![]).is_enabled());
+    assert_eq!(check(&[], None), Ok(()));
+    assert_eq!(check(&[], Some("Bearer anything")), Ok(()));
+}
+
+#[test]
+fn bearer_token_is_accepted_by_interceptor() {
+    let request = BearerToken::new(Some("secret")).unwrap().call(Request::new(())).unwrap();
+    assert_eq!(request.metadata().get(AUTHORIZATION_KEY).unwrap(), "Bearer secret");
+    assert!(TokenInterceptor::new(vec![ String::from("secret") ]).call(request).is_ok());
+
+    // Without a token, nothing is added
+    let request = BearerToken::new(None).unwrap().call(Request::new(())).unwrap();
+    assert!(request.metadata().get(AUTHORIZATION_KEY).is_none());
+
+    assert!(matches!(BearerToken::new(Some("multi\nline")), Err(AuthError::IllegalToken)));
+}
+
+#[test]
+fn tokens_are_read_from_file_and_list() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tokens");
+    fs::write(&path, "# The CI pipeline\nci-token\n\n  alice-token  \n").unwrap();
+
+    assert_eq!(auth::read_tokens(Some(&path), Some("bob-token, ,carol-token")).unwrap(), vec![ "ci-token", "alice-token", "bob-token", "carol-token" ]);
+    assert!(auth::read_tokens(None, None).unwrap().is_empty());
+    assert!(matches!(auth::read_tokens(Some(&dir.path().join("missing")), None), Err(AuthError::TokenFileError{ .. })));
+}
@@ -0,0 +1,150 @@
+use brane_bvm::executor::ExecutorError;
+use brane_drv::calls::{CallCache, CallKey, CallStore, FileStore, MemoryStore};
+use specifications::common::{FunctionExt, Value};
+use specifications::package::PackageKind;
+use specifications::version::Version;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Returns the function 'square' of package 'maths' in the image with the given digest.
+fn square(digest: &str, pure: bool) -> FunctionExt {
+    FunctionExt {
+        detached     : false,
+        digest       : digest.to_string(),
+        kind         : PackageKind::Ecu,
+        name         : String::from("square"),
+        package      : String::from("maths"),
+        parameters   : vec![],
+        version      : Version::from_str("1.0.0").unwrap(),
+        return_type  : Some(String::from("integer")),
+        description  : None,
+        requirements : Default::default(),
+        environment  : Default::default(),
+        env          : Default::default(),
+        pure,
+        concurrency  : None,
+    }
+}
+
+fn args(n: i64) -> HashMap<String, Value> {
+    vec![ (String::from("n"), Value::Integer(n)) ].into_iter().collect()
+}
+
+fn key(digest: &str, n: i64) -> CallKey {
+    CallKey::new(&square(digest, true), &args(n)).unwrap()
+}
+
+#[test]
+fn only_pure_functions_have_keys() {
+    assert!(CallKey::new(&square("sha256:a", false), &args(2)).is_none());
+
+    let mut service = square("sha256:a", true);
+    service.detached = true;
+    assert!(CallKey::new(&service, &args(2)).is_none());
+
+    // The same call gives the same key, regardless of the order of the arguments
+    let mut many: HashMap<String, Value> = (0..16).map(|i| (format!("arg{}", i), Value::Integer(i))).collect();
+    let first = CallKey::new(&square("sha256:a", true), &many).unwrap();
+    many = many.into_iter().rev().collect();
+    assert_eq!(CallKey::new(&square("sha256:a", true), &many).unwrap(), first);
+
+    // A configured environment makes it another call
+    let mut configured = square("sha256:a", true);
+    configured.env.insert(String::from("THREADS"), String::from("4"));
+    assert_ne!(CallKey::new(&configured, &args(2)).unwrap(), key("sha256:a", 2));
+}
+
+#[tokio::test]
+async fn hits_and_misses() {
+    let cache = CallCache::new(16);
+    assert!(cache.get(&key("sha256:a", 2)).await.is_none());
+
+    cache.record(&key("sha256:a", 2), &Ok(Value::Integer(4))).await;
+    assert_eq!(cache.get(&key("sha256:a", 2)).await, Some(Value::Integer(4)));
+    // Other arguments are another call
+    assert!(cache.get(&key("sha256:a", 3)).await.is_none());
+}
+
+#[tokio::test]
+async fn failures_are_never_cached() {
+    let cache = CallCache::new(16);
+    let failed = Err(ExecutorError::ExternalCallFailed{ name: String::from("square"), package: String::from("maths"), version: Version::from_str("1.0.0").unwrap(), code: 1, stdout: String::new(), stderr: String::from("out of memory") });
+    cache.record(&key("sha256:a", 2), &failed).await;
+    assert!(cache.get(&key("sha256:a", 2)).await.is_none());
+}
+
+#[tokio::test]
+async fn new_digest_invalidates_old_results() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = CallCache::new(16).with_store(Box::new(FileStore::new(dir.path()).unwrap()));
+    cache.record(&key("sha256:a", 2), &Ok(Value::Integer(4))).await;
+    cache.record(&key("sha256:a", 3), &Ok(Value::Integer(9))).await;
+    let file = dir.path().join("maths").join("1-0-0").join("sha256-a.json");
+    assert!(file.exists());
+
+    // The package was rebuilt; its old results are gone, from memory and from disk
+    assert!(cache.get(&key("sha256:b", 2)).await.is_none());
+    assert!(cache.get(&key("sha256:a", 2)).await.is_none());
+    assert!(!file.exists());
+
+    cache.record(&key("sha256:b", 2), &Ok(Value::Integer(4))).await;
+    assert_eq!(cache.get(&key("sha256:b", 2)).await, Some(Value::Integer(4)));
+}
+
+#[tokio::test]
+async fn versions_are_cached_separately() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = CallCache::new(16).with_store(Box::new(FileStore::new(dir.path()).unwrap()));
+    let mut newer = square("sha256:b", true);
+    newer.version = Version::from_str("2.0.0").unwrap();
+    let newer = CallKey::new(&newer, &args(2)).unwrap();
+
+    // Alternating between the versions of a package keeps the results of both
+    cache.record(&key("sha256:a", 2), &Ok(Value::Integer(4))).await;
+    cache.record(&newer, &Ok(Value::Integer(5))).await;
+    assert_eq!(cache.get(&key("sha256:a", 2)).await, Some(Value::Integer(4)));
+    assert_eq!(cache.get(&newer).await, Some(Value::Integer(5)));
+    assert!(dir.path().join("maths").join("1-0-0").join("sha256-a.json").exists());
+}
+
+#[tokio::test]
+async fn files_are_never_shared() {
+    let cache = CallCache::new(16);
+    let file = Value::Struct{ data_type: String::from("File"), properties: vec![ (String::from("url"), Value::Unicode(String::from("file:///data/session-a/out.txt"))) ].into_iter().collect() };
+
+    // Files live in the data directory of a session, so calls that are given one have no key...
+    let given: HashMap<String, Value> = vec![ (String::from("n"), file.clone()) ].into_iter().collect();
+    assert!(CallKey::new(&square("sha256:a", true), &given).is_none());
+
+    // ...and results that contain one are not stored
+    let nested = Value::Array{ data_type: String::from("File[]"), entries: vec![ file ] };
+    cache.record(&key("sha256:a", 2), &Ok(nested)).await;
+    assert!(cache.get(&key("sha256:a", 2)).await.is_none());
+}
+
+#[tokio::test]
+async fn file_store_survives_restarts() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let cache = CallCache::new(16).with_store(Box::new(FileStore::new(dir.path()).unwrap()));
+        cache.record(&key("sha256:a", 2), &Ok(Value::Unicode(String::from("four")))).await;
+    }
+
+    let cache = CallCache::new(16).with_store(Box::new(FileStore::new(dir.path()).unwrap()));
+    assert_eq!(cache.get(&key("sha256:a", 2)).await, Some(Value::Unicode(String::from("four"))));
+}
+
+#[test]
+fn memory_store_evicts_least_recently_used() {
+    let store = MemoryStore::new(2);
+    store.put(&key("sha256:a", 1), &Value::Integer(1)).unwrap();
+    store.put(&key("sha256:a", 2), &Value::Integer(4)).unwrap();
+    // Using the first makes the second the least recently used
+    assert!(store.get(&key("sha256:a", 1)).unwrap().is_some());
+    store.put(&key("sha256:a", 3), &Value::Integer(9)).unwrap();
+
+    assert_eq!(store.len(), 2);
+    assert!(store.get(&key("sha256:a", 1)).unwrap().is_some());
+    assert!(store.get(&key("sha256:a", 2)).unwrap().is_none());
+    assert!(store.get(&key("sha256:a", 3)).unwrap().is_some());
+}
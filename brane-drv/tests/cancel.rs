@@ -0,0 +1,82 @@
+use brane_drv::client;
+use brane_drv::executor::{cancel_jobs, ActiveJob};
+use dashmap::DashMap;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+const SESSION: &str = "8c9d5a2e-0000-4000-8000-000000000006";
+const OTHER: &str = "8c9d5a2e-0000-4000-8000-000000000007";
+
+fn active(jobs: &[(&str, &str)]) -> Arc<DashMap<String, ActiveJob>> {
+    let (client_tx, _) = client::channel(client::DEFAULT_CAPACITY);
+    let active = Arc::new(DashMap::new());
+    for (correlation_id, session_uuid) in jobs {
+        active.insert(correlation_id.to_string(), ActiveJob{ session_uuid: session_uuid.to_string(), cancelled: false, client_tx: client_tx.clone() });
+    }
+    active
+}
+
+/// Counts the Stop commands that were sent for every job, yielding first so that other cancels (and completing jobs) get a chance to interfere.
+async fn stop(stops: Arc<Mutex<HashMap<String, usize>>>, correlation_id: String) -> Result<(), String> {
+    tokio::task::yield_now().await;
+    *stops.lock().unwrap().entry(correlation_id).or_default() += 1;
+    Ok(())
+}
+
+#[tokio::test]
+async fn the_jobs_of_the_session_are_cancelled() {
+    let active = active(&[ ("A1", SESSION), ("A2", SESSION), ("B1", OTHER) ]);
+    let stops = Arc::new(Mutex::new(HashMap::new()));
+
+    let mut job_ids = cancel_jobs(&active, SESSION, |id| stop(stops.clone(), id)).await.unwrap();
+    job_ids.sort();
+    assert_eq!(job_ids, vec![ "A1", "A2" ]);
+    assert!(active.get("A1").unwrap().cancelled);
+    assert!(!active.get("B1").unwrap().cancelled);
+
+    // Cancelling again finds nothing left to cancel
+    assert!(cancel_jobs(&active, SESSION, |id| stop(stops.clone(), id)).await.unwrap().is_empty());
+    assert_eq!(stops.lock().unwrap().len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn every_job_is_stopped_once() {
+    let jobs: Vec<String> = (0..50).map(|i| format!("A{}", i)).collect();
+    let active = active(&jobs.iter().map(|id| (id.as_str(), SESSION)).collect::<Vec<_>>());
+    let stops = Arc::new(Mutex::new(HashMap::new()));
+
+    // Two cancels race each other, while some of the jobs complete
+    let first = tokio::spawn({ let (active, stops) = (active.clone(), stops.clone()); async move { cancel_jobs(&active, SESSION, |id| stop(stops.clone(), id)).await.unwrap() } });
+    let second = tokio::spawn({ let (active, stops) = (active.clone(), stops.clone()); async move { cancel_jobs(&active, SESSION, |id| stop(stops.clone(), id)).await.unwrap() } });
+    for id in jobs.iter().step_by(3) { active.remove(id); tokio::task::yield_now().await; }
+    let (first, second) = (first.await.unwrap(), second.await.unwrap());
+
+    let stops = stops.lock().unwrap();
+    assert!(stops.values().all(|count| *count == 1));
+    assert_eq!(first.len() + second.len(), stops.len());
+    assert!(first.iter().all(|id| !second.contains(id)));
+}
+
+#[tokio::test]
+async fn jobs_that_could_not_be_stopped_are_cancelled_again() {
+    let active = active(&[ ("A1", SESSION), ("A2", SESSION), ("A3", SESSION) ]);
+    let stops = Arc::new(Mutex::new(HashMap::new()));
+
+    // The second Stop fails, so that job and the ones after it are left alone
+    let mut sent = 0;
+    let (failed, _) = cancel_jobs(&active, SESSION, |id| {
+        sent += 1;
+        let fail = sent == 2;
+        let stops = stops.clone();
+        async move { if fail { Err(String::from("broker down")) } else { stop(stops, id).await } }
+    }).await.unwrap_err();
+    assert!(!active.get(&failed).unwrap().cancelled);
+    assert_eq!(active.iter().filter(|job| job.cancelled).count(), 1);
+
+    // Trying again picks up the rest
+    let job_ids = cancel_jobs(&active, SESSION, |id| stop(stops.clone(), id)).await.unwrap();
+    assert_eq!(job_ids.len(), 2);
+    assert!(job_ids.contains(&failed));
+    assert!(active.iter().all(|job| job.cancelled));
+    assert!(stops.lock().unwrap().values().all(|count| *count == 1));
+}
@@ -0,0 +1,114 @@
+use brane_drv::client::{self, ClientError, ClientReceiver, ClientReply, ReplyClass};
+use brane_drv::grpc::ExecuteReply;
+use std::time::Duration;
+use tonic::{Code, Status};
+
+fn reply(debug: Option<&str>, stdout: Option<&str>, close: bool) -> ClientReply {
+    Ok(ExecuteReply{ close, debug: debug.map(String::from), stderr: None, stdout: stdout.map(String::from), trace: None, cached: None, diff: None, sequence: None, client: None, input: None })
+}
+
+fn debug(text: &str) -> ClientReply { reply(Some(text), None, false) }
+fn stdout(text: &str) -> ClientReply { reply(None, Some(text), false) }
+fn closing(text: &str) -> ClientReply { reply(None, Some(text), true) }
+
+/// Reads the stream the way a client that takes its time with every reply would.
+async fn slow_consumer(mut rx: ClientReceiver, delay: Duration) -> (Vec<ExecuteReply>, usize, usize) {
+    let mut replies = vec![];
+    while let Some(reply) = rx.recv().await {
+        replies.push(reply.unwrap());
+        tokio::time::sleep(delay).await;
+    }
+    (replies, rx.dropped(), rx.coalesced())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn slow_consumer_gets_all_output_and_final_reply() {
+    let (tx, rx) = client::channel(4);
+    let consumer = tokio::spawn(slow_consumer(rx, Duration::from_millis(2)));
+
+    // A statement that is chatty on both debug and stdout
+    let producer = tokio::spawn(async move {
+        for i in 0..200 {
+            tx.send(debug(&format!("debug {}", i))).await.unwrap();
+            tx.send(stdout(&format!("line {}", i))).await.unwrap();
+        }
+        tx.send(closing("done")).await.unwrap();
+    });
+
+    let (replies, dropped, coalesced) = tokio::time::timeout(Duration::from_secs(30), async {
+        producer.await.unwrap();
+        consumer.await.unwrap()
+    }).await.expect("Sending to a slow consumer deadlocked");
+
+    // The final reply arrives, and it arrives last
+    let last = replies.last().unwrap();
+    assert!(last.close);
+    assert_eq!(last.stdout.as_deref(), Some("done"));
+    assert_eq!(replies.iter().filter(|reply| reply.close).count(), 1);
+
+    // No line of output is lost or reordered, even if some of them got merged
+    let lines: Vec<String> = replies.iter().filter(|reply| !reply.close).filter_map(|reply| reply.stdout.clone()).flat_map(|text| text.lines().map(String::from).collect::<Vec<_>>()).collect();
+    assert_eq!(lines, (0..200).map(|i| format!("line {}", i)).collect::<Vec<_>>());
+
+    // Only debug messages are dropped
+    let debugs = replies.iter().filter(|reply| reply.debug.is_some()).count();
+    assert_eq!(debugs + dropped, 200);
+    assert!(dropped > 0 || coalesced > 0, "Consumer was not slow enough to test anything");
+}
+
+#[tokio::test]
+async fn stalled_consumer_only_fails_output() {
+    let (tx, mut rx) = client::channel_with_timeout(2, Duration::from_millis(50));
+
+    // Fill the channel with something that output can't be merged with
+    tx.send(debug("first")).await.unwrap();
+    tx.send(debug("second")).await.unwrap();
+
+    // Debug messages make room for themselves, but output has to wait for a client that never reads
+    tx.send(debug("third")).await.unwrap();
+    match tx.send(stdout("lost")).await {
+        Err(ClientError::Backpressure{ class: ReplyClass::Stdout, pending: 2, .. }) => {},
+        other => panic!("Expected backpressure on stdout, got {:?}", other),
+    }
+
+    // The final reply is never refused
+    tx.send(Err(Status::new(Code::Internal, "failed"))).await.unwrap();
+    drop(tx);
+
+    let replies: Vec<ClientReply> = std::iter::from_fn(|| rx.try_recv()).collect();
+    assert_eq!(replies.len(), 3);
+    assert_eq!(replies[0].as_ref().unwrap().debug.as_deref(), Some("second"));
+    assert_eq!(replies[1].as_ref().unwrap().debug.as_deref(), Some("third"));
+    assert_eq!(replies[2].as_ref().unwrap_err().code(), Code::Internal);
+    assert_eq!(rx.dropped(), 1);
+    assert!(rx.recv().await.is_none());
+}
+
+#[tokio::test]
+async fn output_is_merged_when_full() {
+    let (tx, mut rx) = client::channel_with_timeout(1, Duration::from_millis(50));
+    tx.send(stdout("a")).await.unwrap();
+    tx.send(stdout("b")).await.unwrap();
+    tx.send(stdout("c")).await.unwrap();
+    tx.send(closing("done")).await.unwrap();
+
+    assert_eq!(rx.recv().await.unwrap().unwrap().stdout.as_deref(), Some("a\nb\nc"));
+    assert!(rx.recv().await.unwrap().unwrap().close);
+    assert_eq!(rx.coalesced(), 2);
+}
+
+#[tokio::test]
+async fn sending_fails_once_client_is_gone() {
+    let (tx, rx) = client::channel(4);
+    drop(rx);
+    assert!(matches!(tx.send(stdout("nobody")).await, Err(ClientError::Disconnected)));
+    assert!(matches!(tx.try_send(debug("nobody")), Err(ClientError::Disconnected)));
+}
+
+#[test]
+fn classifies_replies() {
+    assert_eq!(ReplyClass::of(&debug("x")), ReplyClass::Debug);
+    assert_eq!(ReplyClass::of(&stdout("x")), ReplyClass::Stdout);
+    assert_eq!(ReplyClass::of(&closing("x")), ReplyClass::Final);
+    assert_eq!(ReplyClass::of(&Err(Status::new(Code::NotFound, "gone"))), ReplyClass::Final);
+}
@@ -0,0 +1,326 @@
+use brane_drv::client;
+use brane_drv::events::EventMonitor;
+use brane_drv::executor::ActiveJob;
+use brane_drv::outputs::JobOutputs;
+use brane_job::interface::{CreateRetryInfo, Event, EventKind, PayloadRef, PullProgress};
+use brane_shr::jobs::JobStatus;
+use dashmap::DashMap;
+use std::sync::Arc;
+
+/// Creates a fresh EventMonitor, as the driver does when it (re)starts.
+fn new_monitor() -> EventMonitor {
+    EventMonitor::new(
+        Arc::new(DashMap::new()),
+        Arc::new(DashMap::new()),
+        Arc::new(DashMap::new()),
+        Arc::new(JobOutputs::new(1024 * 1024)),
+        Arc::new(DashMap::new()),
+        Arc::new(DashMap::new()),
+    )
+}
+
+fn event(
+    kind: EventKind,
+    job: &str,
+    order: u32,
+) -> Event {
+    Event::new(kind, format!("{}-abcd", job), String::from("app"), String::from("loc1"), String::from("job"), order, None, None)
+}
+
+/// Feeds the monitor the events in the log, starting at the committed offset. If `crash_at` is given, we 'crash' after handling that event but before committing it.
+fn run(
+    monitor: &EventMonitor,
+    log: &[Event],
+    committed: &mut usize,
+    crash_at: Option<usize>,
+) {
+    for (i, event) in log.iter().enumerate().skip(*committed) {
+        monitor.handle(event);
+        if crash_at == Some(i) {
+            return;
+        }
+        *committed = i + 1;
+    }
+}
+
+#[test]
+fn replays_uncommitted_events_after_crash() {
+    let log = vec![
+        event(EventKind::Created, "job1", 0),
+        event(EventKind::Ready, "job1", 1),
+        event(EventKind::Initialized, "job1", 2),
+        event(EventKind::Started, "job1", 3),
+    ];
+    let mut committed = 0;
+
+    // The first run comes as far as Initialized, but crashes before committing it
+    let first = new_monitor();
+    run(&first, &log, &mut committed, Some(2));
+    assert_eq!(committed, 2);
+    assert!(matches!(*first.states.get("job1").unwrap(), JobStatus::Initialized));
+
+    // The second run starts without any state, and replays from the committed offset
+    let second = new_monitor();
+    run(&second, &log, &mut committed, None);
+    assert_eq!(committed, log.len());
+    assert!(matches!(*second.states.get("job1").unwrap(), JobStatus::Started));
+}
+
+#[test]
+fn replays_into_existing_state() {
+    let log = vec![
+        event(EventKind::Created, "job1", 0),
+        event(EventKind::Ready, "job1", 1),
+        event(EventKind::Heartbeat, "job1", 2),
+    ];
+    let mut committed = 0;
+
+    // Handling already seen events again is harmless
+    let monitor = new_monitor();
+    run(&monitor, &log, &mut committed, Some(1));
+    run(&monitor, &log, &mut committed, None);
+    assert_eq!(committed, log.len());
+    assert!(matches!(*monitor.states.get("job1").unwrap(), JobStatus::Ready));
+    assert_eq!(monitor.locations.get("job1").unwrap().as_str(), "loc1");
+    assert!(monitor.heartbeats.contains_key("job1"));
+}
+
+#[test]
+fn drops_stale_events() {
+    let monitor = new_monitor();
+    assert!(monitor.handle(&event(EventKind::Created, "job1", 0)));
+    assert!(monitor.handle(&event(EventKind::Started, "job1", 3)));
+
+    // A late-arriving Ready event must not move the job back
+    assert!(!monitor.handle(&event(EventKind::Ready, "job1", 1)));
+    assert!(matches!(*monitor.states.get("job1").unwrap(), JobStatus::Started));
+
+    // Events of other jobs are unaffected
+    assert!(monitor.handle(&event(EventKind::Created, "job2", 0)));
+    assert!(matches!(*monitor.states.get("job2").unwrap(), JobStatus::Created));
+}
+
+#[test]
+fn delayed_started_does_not_undo_completion() {
+    let monitor = new_monitor();
+    for event in [ event(EventKind::Created, "job1", 0), event(EventKind::Ready, "job1", 1), event(EventKind::Initialized, "job1", 2), event(EventKind::Completed, "job1", 4) ] {
+        assert!(monitor.handle(&event));
+    }
+
+    // The Started event got held up, and arrives after the job completed
+    assert!(!monitor.handle(&event(EventKind::Started, "job1", 3)));
+    assert!(matches!(*monitor.states.get("job1").unwrap(), JobStatus::Completed));
+    assert_eq!(*monitor.orders.get("job1").unwrap(), 4);
+}
+
+#[test]
+fn shuffled_events_end_in_terminal_state() {
+    let finished = Event::new(EventKind::Finished, String::from("job1-abcd"), String::from("app"), String::from("loc1"), String::from("job"), 6, Some(b"{\"v\":\"unit\"}".to_vec()), None);
+    let log = vec![
+        event(EventKind::Created, "job1", 0),
+        event(EventKind::Completed, "job1", 5),
+        event(EventKind::Ready, "job1", 1),
+        finished,
+        event(EventKind::Started, "job1", 3),
+        event(EventKind::Initialized, "job1", 2),
+    ];
+
+    let monitor = new_monitor();
+    let applied: Vec<bool> = log.iter().map(|event| monitor.handle(event)).collect();
+    assert_eq!(applied, vec![ true, true, false, true, false, false ]);
+    assert!(matches!(&*monitor.states.get("job1").unwrap(), JobStatus::Finished{ res } if res == "{\"v\":\"unit\"}"));
+}
+
+#[test]
+fn heartbeats_are_not_ordered() {
+    let monitor = new_monitor();
+    assert!(monitor.handle(&event(EventKind::Started, "job1", 3)));
+
+    // A heartbeat with any order is noted, but doesn't hold up later state changes
+    assert!(monitor.handle(&event(EventKind::Heartbeat, "job1", 10)));
+    assert!(monitor.heartbeats.contains_key("job1"));
+    assert_eq!(*monitor.orders.get("job1").unwrap(), 3);
+    assert!(monitor.handle(&event(EventKind::Completed, "job1", 4)));
+    assert!(matches!(*monitor.states.get("job1").unwrap(), JobStatus::Completed));
+
+    // An old heartbeat still says the job is alive
+    monitor.heartbeats.clear();
+    assert!(monitor.handle(&event(EventKind::Heartbeat, "job1", 0)));
+    assert!(monitor.heartbeats.contains_key("job1"));
+
+    // Heartbeats of jobs without state changes don't start tracking their order
+    assert!(monitor.handle(&event(EventKind::Heartbeat, "job2", 7)));
+    assert!(!monitor.orders.contains_key("job2"));
+}
+
+fn log_event(
+    job: &str,
+    stream: &str,
+    text: &str,
+) -> Event {
+    Event::new(EventKind::Log, job.to_string(), String::from("app"), String::from("loc1"), stream.to_string(), 0, Some(text.as_bytes().to_vec()), None)
+}
+
+#[test]
+fn forwards_logs_to_waiting_session() {
+    let monitor = new_monitor();
+    let (client_tx, mut client_rx) = client::channel(8);
+    monitor.active.insert(String::from("job1"), ActiveJob{ session_uuid: String::from("session"), cancelled: false, client_tx });
+    assert!(monitor.handle(&event(EventKind::Started, "job1", 3)));
+
+    assert!(monitor.handle(&log_event("job1", "stdout", "epoch 1: loss 0.5\nepoch 2: loss 0.3\n")));
+    assert!(monitor.handle(&log_event("job1", "stderr", "warning: slow\n")));
+    let reply = client_rx.try_recv().unwrap().unwrap();
+    assert_eq!(reply.stdout.as_deref(), Some("epoch 1: loss 0.5\nepoch 2: loss 0.3"));
+    assert!(reply.stderr.is_none());
+    let reply = client_rx.try_recv().unwrap().unwrap();
+    assert_eq!(reply.stderr.as_deref(), Some("warning: slow"));
+
+    // Logs are not state changes, and have their own ordering
+    assert!(matches!(*monitor.states.get("job1").unwrap(), JobStatus::Started));
+    assert!(monitor.handle(&event(EventKind::Heartbeat, "job1", 4)));
+
+    // Without a waiting session, there is nobody to forward to
+    assert!(!monitor.handle(&log_event("job2", "stdout", "hello")));
+}
+
+#[test]
+fn forwards_create_retries_to_waiting_session() {
+    let monitor = new_monitor();
+    let (client_tx, mut client_rx) = client::channel(8);
+    monitor.active.insert(String::from("job1"), ActiveJob{ session_uuid: String::from("session"), cancelled: false, client_tx });
+
+    let info = CreateRetryInfo{ attempt: 2, max_retries: 3, reason: String::from("pull timed out") };
+    let retry = Event::new(EventKind::CreateRetrying, String::from("job1-abcd"), String::from("app"), String::from("loc1"), String::from("job"), 0, Some(serde_json::to_vec(&info).unwrap()), None);
+    assert!(monitor.handle(&retry));
+    let reply = client_rx.try_recv().unwrap().unwrap();
+    assert_eq!(reply.stderr.as_deref(), Some("Could not create job 'job1' at location 'loc1' (pull timed out); retrying (2/3)..."));
+
+    // Retrying is not a state change, and the job may still be created afterwards
+    assert!(monitor.states.get("job1").is_none());
+    assert!(monitor.handle(&event(EventKind::Created, "job1", 0)));
+    assert!(matches!(*monitor.states.get("job1").unwrap(), JobStatus::Created));
+}
+
+#[test]
+fn forwards_pull_progress_as_heartbeat() {
+    let monitor = new_monitor();
+    let (client_tx, mut client_rx) = client::channel(8);
+    monitor.active.insert(String::from("job1"), ActiveJob{ session_uuid: String::from("session"), cancelled: false, client_tx });
+
+    let pulling = |progress: &PullProgress| Event::new(EventKind::Pulling, String::from("job1-abcd"), String::from("app"), String::from("loc1"), String::from("job"), 0, Some(serde_json::to_vec(progress).unwrap()), None);
+    let progress = PullProgress{ image: String::from("hello:1.0.0"), current: 45 * 1024 * 1024, total: Some(100 * 1024 * 1024) };
+    assert!(monitor.handle(&pulling(&progress)));
+    let reply = client_rx.try_recv().unwrap().unwrap();
+    assert_eq!(reply.debug.as_deref(), Some("Job 'job1' at location 'loc1' is pulling image 'hello:1.0.0'... 45% (45.0 of 100.0 MiB)"));
+    assert!(reply.stdout.is_none() && reply.stderr.is_none());
+
+    // Pulling keeps the job alive, but is not a state change
+    assert!(monitor.heartbeats.get("job1").is_some());
+    assert!(monitor.states.get("job1").is_none());
+
+    // Locations that can't tell the size still let the user know
+    assert!(monitor.handle(&pulling(&PullProgress{ image: String::from("hello:1.0.0"), current: 0, total: None })));
+    let reply = client_rx.try_recv().unwrap().unwrap();
+    assert_eq!(reply.debug.as_deref(), Some("Job 'job1' at location 'loc1' is pulling image 'hello:1.0.0'..."));
+    assert!(monitor.handle(&event(EventKind::Created, "job1", 0)));
+    assert!(matches!(*monitor.states.get("job1").unwrap(), JobStatus::Created));
+}
+
+#[test]
+fn keeps_unparseable_failures_raw() {
+    let monitor = new_monitor();
+    let failed = |job: &str, payload: &str| Event::new(EventKind::Failed, format!("{}-abcd", job), String::from("app"), String::from("loc1"), String::from("job"), 8, Some(payload.as_bytes().to_vec()), None);
+
+    // A valid triplet is a normal failure
+    assert!(monitor.handle(&failed("job1", "{\"code\":3,\"stdout\":\"\",\"stderr\":\"oops\"}")));
+    assert!(matches!(*monitor.states.get("job1").unwrap(), JobStatus::Failed{ .. }));
+
+    // A mangled one (e.g., cut off) is kept as-is
+    assert!(monitor.handle(&failed("job2", "{\"code\":3,\"stdout\":\"Traceback (most")));
+    match &*monitor.states.get("job2").unwrap() {
+        JobStatus::FailedRaw{ res } => assert_eq!(res, "{\"code\":3,\"stdout\":\"Traceback (most"),
+        state                       => panic!("Expected FailedRaw, got {:?}", state),
+    }
+}
+
+#[test]
+fn artifact_failures_are_noted() {
+    let monitor = new_monitor();
+    let store_failed = Event::new(EventKind::StoreFailed, String::from("job1-abcd"), String::from("app"), String::from("loc1"), String::from("job"), 7, Some(b"Outputs are too large".to_vec()), None);
+
+    assert!(monitor.handle(&store_failed));
+    match &*monitor.states.get("job1").unwrap() {
+        JobStatus::StoreFailed{ err } => assert_eq!(err, "Outputs are too large"),
+        state                         => panic!("Expected StoreFailed, got {:?}", state),
+    }
+}
+
+#[test]
+fn payload_references_are_read() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("job1-abcd_6.payload");
+    std::fs::write(&path, "{\"v\":\"integer\",\"c\":42}").unwrap();
+
+    let monitor = new_monitor().with_payload_dir(dir.path().to_path_buf());
+    let reference = PayloadRef{ path: path.to_string_lossy().to_string(), size: 22 };
+    let finished = Event{ payload: reference.to_payload(), ..event(EventKind::Finished, "job1", 6) };
+    assert!(monitor.handle(&finished));
+    assert!(matches!(&*monitor.states.get("job1").unwrap(), JobStatus::Finished{ res } if res == "{\"v\":\"integer\",\"c\":42}"));
+    // The file is only needed once
+    assert!(!path.exists());
+
+    // A payload we cannot read fails the job instead of leaving it waiting
+    let reference = PayloadRef{ path: dir.path().join("missing.payload").to_string_lossy().to_string(), size: 22 };
+    let finished = Event{ payload: reference.to_payload(), ..event(EventKind::Finished, "job2", 6) };
+    assert!(monitor.handle(&finished));
+    assert!(matches!(&*monitor.states.get("job2").unwrap(), JobStatus::CompleteFailed{ err } if err.contains("missing.payload")));
+}
+
+#[test]
+fn payload_references_outside_the_payload_dir_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    let path = outside.path().join("secret");
+    std::fs::write(&path, "{\"v\":\"unit\"}").unwrap();
+
+    // Neither directly, nor by climbing out of the payload directory
+    let monitor = new_monitor().with_payload_dir(dir.path().to_path_buf());
+    let escaping = dir.path().join("..").join(outside.path().file_name().unwrap()).join("secret");
+    for (job, path) in [ ("job1", path.clone()), ("job2", escaping) ] {
+        let reference = PayloadRef{ path: path.to_string_lossy().to_string(), size: 12 };
+        assert!(monitor.handle(&Event{ payload: reference.to_payload(), ..event(EventKind::Finished, job, 6) }));
+        assert!(matches!(&*monitor.states.get(job).unwrap(), JobStatus::CompleteFailed{ err } if err.contains("not in the payload directory")));
+    }
+    assert!(path.exists());
+
+    // Without a payload directory, no references are read at all
+    let monitor = new_monitor();
+    let reference = PayloadRef{ path: path.to_string_lossy().to_string(), size: 12 };
+    assert!(monitor.handle(&Event{ payload: reference.to_payload(), ..event(EventKind::Finished, "job3", 6) }));
+    assert!(matches!(&*monitor.states.get("job3").unwrap(), JobStatus::CompleteFailed{ err } if err.contains("--payload-dir")));
+    assert!(path.exists());
+}
+
+#[test]
+fn late_events_of_finished_jobs_are_ignored() {
+    let monitor = new_monitor();
+    assert!(monitor.handle(&event(EventKind::Started, "job1", 3)));
+    assert!(monitor.handle(&event(EventKind::Finished, "job1", 5)));
+
+    // The executor forgets the job once it has its result
+    monitor.states.remove("job1");
+    monitor.orders.remove("job1");
+    monitor.heartbeats.remove("job1");
+
+    // Whatever arrives late must not bring the job back
+    assert!(!monitor.handle(&event(EventKind::Completed, "job1", 4)));
+    assert!(!monitor.handle(&event(EventKind::Heartbeat, "job1", 6)));
+    assert!(monitor.states.get("job1").is_none());
+    assert!(monitor.orders.get("job1").is_none());
+    assert!(monitor.heartbeats.get("job1").is_none());
+
+    // Other jobs are unaffected
+    assert!(monitor.handle(&event(EventKind::Started, "job2", 3)));
+    assert!(matches!(*monitor.states.get("job2").unwrap(), JobStatus::Started));
+}
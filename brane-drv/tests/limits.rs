@@ -0,0 +1,138 @@
+use brane_drv::limits::JobLimits;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+
+/// Stands in for the Kafka producer: remembers which correlation IDs every session has sent but not seen acknowledged yet, and the most it ever had at once.
+#[derive(Default)]
+struct MockProducer {
+    unacked: Mutex<HashMap<String, HashSet<String>>>,
+    max_unacked: Mutex<HashMap<String, usize>>,
+    max_total: Mutex<usize>,
+    sent: Mutex<Vec<String>>,
+}
+
+impl MockProducer {
+    fn send(&self, session: &str, correlation_id: String) {
+        let mut unacked = self.unacked.lock().unwrap();
+        let pending = unacked.entry(session.to_string()).or_default();
+        pending.insert(correlation_id.clone());
+        let count = pending.len();
+        let total: usize = unacked.values().map(HashSet::len).sum();
+
+        let mut max_unacked = self.max_unacked.lock().unwrap();
+        let max = max_unacked.entry(session.to_string()).or_default();
+        *max = (*max).max(count);
+        let mut max_total = self.max_total.lock().unwrap();
+        *max_total = (*max_total).max(total);
+        self.sent.lock().unwrap().push(correlation_id);
+    }
+
+    fn ack(&self, session: &str, correlation_id: &str) {
+        self.unacked.lock().unwrap().get_mut(session).unwrap().remove(correlation_id);
+    }
+
+    fn max_unacked(&self, session: &str) -> usize { self.max_unacked.lock().unwrap().get(session).copied().unwrap_or(0) }
+}
+
+/// Schedules a job the way `JobExecutor::call()` does: waits for a slot, sends the command and holds the slot until the job is done, which is once `done` hands out a permit for it.
+async fn run_job(limits: Arc<JobLimits>, producer: Arc<MockProducer>, session: &'static str, i: usize, done: Arc<Semaphore>) {
+    let _permit = limits.acquire(session).await;
+    let correlation_id = format!("{}-{}", session, i);
+    producer.send(session, correlation_id.clone());
+    done.acquire().await.unwrap().forget();
+    producer.ack(session, &correlation_id);
+}
+
+/// Yields until the given condition holds, so spawned jobs get to run as far as they can.
+async fn wait_for(condition: impl Fn() -> bool) {
+    while !condition() { tokio::task::yield_now().await; }
+}
+
+#[tokio::test]
+async fn sessions_never_exceed_their_limit() {
+    let limits = Arc::new(JobLimits::new(3, 5));
+    let producer = Arc::new(MockProducer::default());
+    let done = Arc::new(Semaphore::new(0));
+
+    // Three sessions issue a big `parallel` each
+    let mut handles = vec![];
+    for session in [ "flood", "also-flood", "modest" ] {
+        let jobs = if session == "modest" { 4 } else { 20 };
+        for i in 0..jobs { handles.push(tokio::spawn(run_job(limits.clone(), producer.clone(), session, i, done.clone()))); }
+    }
+
+    // Only as many jobs as the global limit allows get sent, until jobs finish one at a time
+    wait_for(|| limits.total_in_flight() == 5).await;
+    assert_eq!(producer.sent.lock().unwrap().len(), 5);
+    for finished in 1..=39 {
+        done.add_permits(1);
+        wait_for(|| producer.sent.lock().unwrap().len() == 5 + finished).await;
+        assert!(limits.total_in_flight() <= 5);
+    }
+    done.add_permits(5);
+    for handle in handles { handle.await.unwrap(); }
+
+    assert_eq!(producer.sent.lock().unwrap().len(), 44);
+    for session in [ "flood", "also-flood", "modest" ] {
+        assert!(producer.max_unacked(session) <= 3, "Session '{}' had {} unacknowledged jobs", session, producer.max_unacked(session));
+        assert_eq!(limits.in_flight(session), 0);
+    }
+    assert!(*producer.max_total.lock().unwrap() <= 5);
+
+    // Sessions are forgotten once they have nothing in flight
+    assert_eq!(limits.total_in_flight(), 0);
+    assert_eq!(limits.sessions(), 0);
+}
+
+#[tokio::test]
+async fn flooding_session_does_not_starve_others() {
+    let limits = Arc::new(JobLimits::new(2, 4));
+    let producer = Arc::new(MockProducer::default());
+    let done = Arc::new(Semaphore::new(0));
+
+    // One session floods the driver with jobs that don't finish yet...
+    let mut handles = vec![];
+    for i in 0..50 { handles.push(tokio::spawn(run_job(limits.clone(), producer.clone(), "flood", i, done.clone()))); }
+    wait_for(|| limits.in_flight("flood") == 2).await;
+    assert_eq!(producer.sent.lock().unwrap().len(), 2);
+
+    // ...but another session still gets its job through right away
+    run_job(limits.clone(), producer.clone(), "other", 0, Arc::new(Semaphore::new(1))).await;
+    assert_eq!(*producer.sent.lock().unwrap(), vec![ "flood-0", "flood-1", "other-0" ]);
+
+    done.add_permits(50);
+    for handle in handles { handle.await.unwrap(); }
+    assert_eq!(producer.sent.lock().unwrap().len(), 51);
+    assert!(producer.max_unacked("flood") <= 2);
+}
+
+#[test]
+fn limits_are_at_least_one() {
+    let limits = JobLimits::new(0, 0);
+    assert_eq!(limits.total_in_flight(), 0);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let permit = limits.acquire("session").await;
+        assert_eq!((limits.in_flight("session"), limits.total_in_flight()), (1, 1));
+        drop(permit);
+        assert_eq!((limits.in_flight("session"), limits.sessions()), (0, 0));
+    });
+}
+
+#[tokio::test]
+async fn arrays_hold_a_slot_for_every_element() {
+    let limits = JobLimits::new(4, 6);
+    assert_eq!(limits.max_jobs(), 4);
+
+    let permit = limits.acquire_many("session", 3).await;
+    assert_eq!((limits.in_flight("session"), limits.total_in_flight()), (3, 3));
+    drop(permit);
+    assert_eq!((limits.in_flight("session"), limits.total_in_flight()), (0, 0));
+
+    // More than the session may have in flight is clamped, rather than waiting forever
+    let permit = limits.acquire_many("session", 10).await;
+    assert_eq!((limits.in_flight("session"), limits.total_in_flight()), (4, 4));
+    drop(permit);
+    assert_eq!((limits.total_in_flight(), limits.sessions()), (0, 0));
+}
@@ -0,0 +1,94 @@
+use brane_drv::lineage::{LineageOutcome, LineageQueue, LineageRecord, LineageReporter, RECORD_LINEAGE_MUTATION};
+use specifications::version::Version;
+use std::str::FromStr;
+use std::time::{Duration, UNIX_EPOCH};
+
+const SESSION: &str = "8c9d5a2e-0000-4000-8000-000000000001";
+
+fn record(job: &str, outcome: LineageOutcome) -> LineageRecord {
+    LineageRecord {
+        session        : SESSION.to_string(),
+        correlation_id : job.to_string(),
+        package        : String::from("hello"),
+        version        : Version::from_str("1.0.0").unwrap(),
+        digest         : String::from("sha256:abc"),
+        location       : Some(String::from("loc1")),
+        started        : UNIX_EPOCH + Duration::from_millis(1_600_000_000_000),
+        finished       : UNIX_EPOCH + Duration::from_millis(1_600_000_001_500),
+        outcome,
+    }
+}
+
+fn jobs(records: &[LineageRecord]) -> Vec<&str> {
+    records.iter().map(|record| record.correlation_id.as_str()).collect()
+}
+
+#[test]
+fn payload_describes_job() {
+    let payload = record("A1", LineageOutcome::Success).mutation_payload();
+    assert_eq!(payload["query"], RECORD_LINEAGE_MUTATION);
+
+    let record = &payload["variables"]["record"];
+    assert_eq!(record["session"], SESSION);
+    assert_eq!(record["correlationId"], "A1");
+    assert_eq!(record["package"], "hello");
+    assert_eq!(record["version"], "1.0.0");
+    assert_eq!(record["digest"], "sha256:abc");
+    assert_eq!(record["location"], "loc1");
+    assert_eq!(record["started"], "2020-09-13T12:26:40.000Z");
+    assert_eq!(record["finished"], "2020-09-13T12:26:41.500Z");
+    assert_eq!(record["outcome"], "success");
+    assert!(record["error"].is_null());
+}
+
+#[test]
+fn payload_describes_failure() {
+    let mut failed = record("A1", LineageOutcome::Failure{ error: String::from("boom") });
+    failed.location = None;
+
+    let payload = failed.mutation_payload();
+    let record = &payload["variables"]["record"];
+    assert_eq!(record["outcome"], "failure");
+    assert_eq!(record["error"], "boom");
+    assert!(record["location"].is_null());
+}
+
+#[test]
+fn queue_drops_oldest() {
+    let mut queue = LineageQueue::new(2);
+    assert!(queue.push(record("A1", LineageOutcome::Success)).is_none());
+    assert!(queue.push(record("A2", LineageOutcome::Success)).is_none());
+    assert_eq!(queue.push(record("A3", LineageOutcome::Success)).unwrap().correlation_id, "A1");
+    assert_eq!(queue.dropped(), 1);
+
+    // A record that failed to send goes back to the front...
+    let retried = queue.pop().unwrap();
+    assert!(!queue.requeue(retried));
+    assert_eq!(queue.records().map(|record| record.correlation_id.as_str()).collect::<Vec<&str>>(), vec![ "A2", "A3" ]);
+
+    // ...unless newer records took its place
+    let retried = queue.pop().unwrap();
+    queue.push(record("A4", LineageOutcome::Success));
+    assert!(queue.requeue(retried));
+    assert_eq!(queue.records().map(|record| record.correlation_id.as_str()).collect::<Vec<&str>>(), vec![ "A3", "A4" ]);
+    assert_eq!(queue.dropped(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reporter_keeps_newest_while_endpoint_is_down() {
+    let reporter = LineageReporter::new(3);
+    // Nothing listens on this port
+    tokio::spawn(reporter.clone().run(String::from("http://127.0.0.1:1/graphql")));
+
+    for i in 0..5 {
+        reporter.report(record(&format!("A{}", i), LineageOutcome::Success));
+    }
+
+    // Wait until the first attempt failed (the next one is only after a second)
+    for _ in 0..50 {
+        if reporter.dropped() == 2 && reporter.queued().len() == 3 { break; }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(jobs(&reporter.queued()), vec![ "A2", "A3", "A4" ]);
+    assert_eq!(reporter.dropped(), 2);
+}
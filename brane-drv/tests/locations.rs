@@ -0,0 +1,95 @@
+use brane_bvm::executor::ExecutorError;
+use brane_cfg::Infrastructure;
+use brane_cfg::infrastructure::Location;
+use brane_drv::executor::{parse_session_uuid, resolve_location};
+use dashmap::DashMap;
+use std::fs;
+
+const JOB: &str = "AabcdefghRxyz123";
+
+const INFRA: &str = "locations:
+  site1:
+    kind: local
+    network: brane
+    address: \"10.0.0.1\"
+    registry: \"localhost:5000\"
+    callback_to: \"http://brane-clb:50052\"
+  site2:
+    kind: local
+    network: brane
+    address: \"10.0.0.2\"
+    registry: \"localhost:5000\"
+    callback_to: \"http://brane-clb:50052\"
+";
+
+fn infra(dir: &tempfile::TempDir) -> Infrastructure {
+    let path = dir.path().join("infra.yml");
+    fs::write(&path, INFRA).unwrap();
+    Infrastructure::new(path.to_string_lossy().to_string()).unwrap()
+}
+
+#[test]
+fn unknown_location_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra(&dir);
+    let locations = DashMap::new();
+    let cache: DashMap<String, Location> = DashMap::new();
+
+    locations.insert(JOB.to_string(), String::from("site3"));
+    match resolve_location(JOB, None, &locations, &infra, &cache) {
+        Err(ExecutorError::UnknownLocationError{ correlation_id, location, .. }) => {
+            assert_eq!(correlation_id, JOB);
+            assert_eq!(location, "site3");
+        },
+        res => panic!("Expected an UnknownLocationError, got {:?}", res.map(|l| l.get_address())),
+    }
+    assert!(cache.is_empty());
+
+    // Neither the event monitor nor the call knowing the location is an error too
+    locations.clear();
+    assert!(matches!(resolve_location(JOB, None, &locations, &infra, &cache), Err(ExecutorError::UnknownLocationError{ .. })));
+}
+
+#[test]
+fn falls_back_to_requested_location() {
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra(&dir);
+    let locations = DashMap::new();
+    let cache = DashMap::new();
+
+    let location = resolve_location(JOB, Some("site2"), &locations, &infra, &cache).unwrap();
+    assert_eq!(location.get_address(), "10.0.0.2");
+
+    // What the event monitor reports takes precedence
+    let locations = DashMap::new();
+    let cache = DashMap::new();
+    locations.insert(JOB.to_string(), String::from("site1"));
+    let location = resolve_location(JOB, Some("site2"), &locations, &infra, &cache).unwrap();
+    assert_eq!(location.get_address(), "10.0.0.1");
+}
+
+#[test]
+fn resolves_once_per_job() {
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra(&dir);
+    let locations = DashMap::new();
+    let cache = DashMap::new();
+
+    locations.insert(JOB.to_string(), String::from("site1"));
+    assert_eq!(resolve_location(JOB, None, &locations, &infra, &cache).unwrap().get_address(), "10.0.0.1");
+
+    // Later updates by the event monitor (or removals from the infrastructure file) don't change the answer
+    locations.insert(JOB.to_string(), String::from("site2"));
+    fs::write(dir.path().join("infra.yml"), "locations: {}\n").unwrap();
+    assert_eq!(resolve_location(JOB, None, &locations, &infra, &cache).unwrap().get_address(), "10.0.0.1");
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+fn malformed_session_id_is_an_error() {
+    assert!(parse_session_uuid("8c9d5a2e-0000-4000-8000-000000000001").is_ok());
+    match parse_session_uuid("not-a-session") {
+        Err(ExecutorError::InvalidSessionIdError{ session_uuid, .. }) => assert_eq!(session_uuid, "not-a-session"),
+        res => panic!("Expected an InvalidSessionIdError, got {:?}", res),
+    }
+}
@@ -0,0 +1,195 @@
+use brane_bvm::executor::{ExecutorError, VmExecutor};
+use brane_cfg::Infrastructure;
+use brane_drv::calls::{CallCache, CallKey};
+use brane_drv::client;
+use brane_drv::executor::{array_arguments, plan_arrays, share_heartbeats, uses_job_array, JobExecutor, MAX_ARRAY_ARGUMENTS};
+use brane_drv::limits::JobLimits;
+use brane_drv::sessions::SessionStore;
+use brane_shr::jobs::JobStatus;
+use dashmap::DashMap;
+use rdkafka::config::ClientConfig;
+use specifications::common::{FunctionExt, Value};
+use specifications::package::PackageKind;
+use specifications::version::Version;
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+const INFRA: &str = "locations:
+  arrays:
+    kind: kube
+    address: \"https://kube.example.com:6443\"
+    namespace: brane
+    registry: \"registry.example.com:5000\"
+    callback_to: \"http://brane-clb:50052\"
+    credentials:
+      mechanism: config
+      file: s$kubeconfig
+    supports_arrays: true
+  plain:
+    kind: local
+    network: brane
+    address: \"10.0.0.1\"
+    registry: \"localhost:5000\"
+    callback_to: \"http://brane-clb:50052\"
+";
+
+fn infra(dir: &tempfile::TempDir) -> Infrastructure {
+    let path = dir.path().join("infra.yml");
+    fs::write(&path, INFRA).unwrap();
+    Infrastructure::new(path.to_string_lossy().to_string()).unwrap()
+}
+
+fn square(detached: bool) -> FunctionExt {
+    FunctionExt {
+        detached,
+        digest       : String::from("sha256:a"),
+        kind         : PackageKind::Ecu,
+        name         : String::from("square"),
+        package      : String::from("maths"),
+        parameters   : vec![],
+        version      : Version::from_str("1.0.0").unwrap(),
+        return_type  : Some(String::from("integer")),
+        description  : None,
+        requirements : Default::default(),
+        environment  : Default::default(),
+        env          : Default::default(),
+        pure         : false,
+        concurrency  : Some(2),
+    }
+}
+
+fn inputs(count: i64) -> Vec<HashMap<String, Value>> {
+    (1..=count).map(|n| vec![ (String::from("n"), Value::Integer(n)) ].into_iter().collect()).collect()
+}
+
+/// Returns an executor whose jobs never get scheduled, as its producer talks to a broker that isn't there.
+fn executor(infra: Infrastructure, calls: Option<Arc<CallCache>>) -> JobExecutor {
+    let producer = ClientConfig::new()
+        .set("bootstrap.servers", "127.0.0.1:1")
+        .set("message.timeout.ms", "100")
+        .create()
+        .unwrap();
+    JobExecutor {
+        client_tx     : client::channel(client::DEFAULT_CAPACITY).0,
+        command_topic : String::from("drv-cmd"),
+        producer,
+        session_uuid  : String::from("8c9d5a2e-0000-4000-8000-000000000005"),
+        states        : Arc::new(DashMap::new()),
+        heartbeats    : Arc::new(DashMap::new()),
+        locations     : Arc::new(DashMap::new()),
+        active        : Arc::new(DashMap::new()),
+        orders        : Arc::new(DashMap::new()),
+        sessions      : Arc::new(SessionStore::new(None).unwrap()),
+        resumed       : Arc::new(DashMap::new()),
+        services      : Arc::new(DashMap::new()),
+        lineage       : None,
+        limits        : Arc::new(JobLimits::new(4, 8)),
+        calls,
+        infra,
+    }
+}
+
+#[test]
+fn only_locations_with_arrays_get_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let infra = infra(&dir);
+    let arrays = infra.get_location_metadata("arrays").unwrap();
+    let plain = infra.get_location_metadata("plain").unwrap();
+
+    assert!(uses_job_array(&square(false), 3, &arrays));
+    assert!(!uses_job_array(&square(false), 3, &plain));
+    // A single call is just a job, and detached calls are waited for one by one
+    assert!(!uses_job_array(&square(false), 1, &arrays));
+    assert!(!uses_job_array(&square(true), 3, &arrays));
+}
+
+#[test]
+fn array_arguments_keep_their_order() {
+    let arguments: Vec<HashMap<String, Value>> = (1..=3).map(|n| vec![ (String::from("n"), Value::Integer(n)) ].into_iter().collect()).collect();
+    let encoded = array_arguments(&arguments);
+
+    let decoded: Vec<HashMap<String, Value>> = serde_json::from_slice(&base64::decode(encoded).unwrap()).unwrap();
+    assert_eq!(decoded, arguments);
+}
+
+#[test]
+fn arrays_fit_in_the_limits() {
+    // No more elements than the session may have in flight
+    let arguments = inputs(10);
+    let pending: Vec<usize> = (0..10).filter(|index| index % 3 != 0).collect();
+    assert_eq!(plan_arrays(&arguments, &pending, 4), vec![ vec![ 1, 2, 4, 5 ], vec![ 7, 8 ] ]);
+
+    // No more arguments than fit on a command line, so large ones end up on their own
+    let large: HashMap<String, Value> = vec![ (String::from("s"), Value::Unicode("x".repeat(MAX_ARRAY_ARGUMENTS / 2))) ].into_iter().collect();
+    let arguments = vec![ large.clone(), large.clone(), large ];
+    let arrays = plan_arrays(&arguments, &[ 0, 1, 2 ], 16);
+    assert_eq!(arrays, vec![ vec![ 0 ], vec![ 1 ], vec![ 2 ] ]);
+    let arguments = inputs(100);
+    for array in plan_arrays(&arguments, &(0..100).collect::<Vec<_>>(), 100) {
+        let array: Vec<HashMap<String, Value>> = array.iter().map(|&index| arguments[index].clone()).collect();
+        assert!(array_arguments(&array).len() <= MAX_ARRAY_ARGUMENTS);
+    }
+}
+
+#[test]
+fn queued_elements_share_the_heartbeats_of_running_ones() {
+    let elements: Vec<String> = (0..3).map(|index| format!("Aabc-{}", index)).collect();
+    let heartbeats = DashMap::new();
+    let states = DashMap::new();
+    let (earlier, now) = (SystemTime::now() - Duration::from_secs(30), SystemTime::now());
+
+    // Nothing runs, so nothing is kept alive
+    states.insert(elements[0].clone(), JobStatus::Created);
+    heartbeats.insert(elements[0].clone(), earlier);
+    share_heartbeats(&elements, &heartbeats, &states);
+    assert_eq!(heartbeats.len(), 1);
+
+    // The first element runs, and the others are still waiting for a turn
+    states.insert(elements[0].clone(), JobStatus::Started);
+    heartbeats.insert(elements[0].clone(), now);
+    states.insert(elements[1].clone(), JobStatus::Created);
+    share_heartbeats(&elements, &heartbeats, &states);
+    assert_eq!(*heartbeats.get(&elements[1]).unwrap(), now);
+    assert_eq!(*heartbeats.get(&elements[2]).unwrap(), now);
+}
+
+#[tokio::test]
+async fn map_call_falls_back_to_separate_jobs() {
+    let dir = tempfile::tempdir().unwrap();
+    let executor = executor(infra(&dir), None);
+
+    // Every call runs (and fails to be scheduled) on its own, instead of failing the map as a whole like a job array would
+    let results = executor.map_call(square(false), inputs(3), Some(String::from("plain"))).await.unwrap();
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|res| matches!(res, Err(ExecutorError::CommandScheduleError{ .. }))));
+    assert!(executor.map_call(square(false), inputs(3), Some(String::from("arrays"))).await.is_err());
+
+    // The same goes for arguments that are too large for a job array
+    let large: HashMap<String, Value> = vec![ (String::from("s"), Value::Unicode("x".repeat(MAX_ARRAY_ARGUMENTS))) ].into_iter().collect();
+    let results = executor.map_call(square(false), vec![ large.clone(), large ], Some(String::from("arrays"))).await.unwrap();
+    assert!(results.iter().all(|res| matches!(res, Err(ExecutorError::CommandScheduleError{ .. }))));
+    assert_eq!(executor.limits.in_flight(&executor.session_uuid), 0);
+}
+
+#[tokio::test]
+async fn map_call_reuses_cached_results() {
+    let dir = tempfile::tempdir().unwrap();
+    let calls = Arc::new(CallCache::new(16));
+    let mut function = square(false);
+    function.pure = true;
+    let arguments = inputs(3);
+    for arguments in &arguments[..2] {
+        let n = match arguments["n"] { Value::Integer(n) => n, _ => unreachable!() };
+        calls.record(&CallKey::new(&function, arguments).unwrap(), &Ok::<Value, ExecutorError>(Value::Integer(n * n))).await;
+    }
+    let executor = executor(infra(&dir), Some(calls));
+
+    // Only the call that isn't cached is scheduled, which is a job of its own
+    let results = executor.map_call(function, arguments, Some(String::from("arrays"))).await.unwrap();
+    assert!(matches!(results[0], Ok(Value::Integer(1))));
+    assert!(matches!(results[1], Ok(Value::Integer(4))));
+    assert!(matches!(results[2], Err(ExecutorError::CommandScheduleError{ .. })));
+}
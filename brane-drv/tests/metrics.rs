@@ -0,0 +1,9 @@
+use std::net::SocketAddr;
+
+#[test]
+fn default_metrics_addresses_do_not_collide() {
+    let driver: SocketAddr = brane_drv::metrics::DEFAULT_METRICS_ADDRESS.parse().unwrap();
+    let job: SocketAddr = brane_job::metrics::DEFAULT_METRICS_ADDRESS.parse().unwrap();
+    assert_ne!(driver, job);
+    assert!(driver.ip().is_loopback() && job.ip().is_loopback());
+}
@@ -0,0 +1,144 @@
+use brane_drv::client::{self, ClientReceiver};
+use brane_drv::errors::MultiplexError;
+use brane_drv::grpc::ExecuteReply;
+use brane_drv::multiplex::{busy_status, is_busy_status, Multiplexer, StatementPolicy};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const SESSION: &str = "8c9d5a2e-0000-4000-8000-000000000002";
+
+fn reply(stdout: &str, close: bool) -> ExecuteReply {
+    ExecuteReply{ close, debug: None, stderr: None, stdout: Some(stdout.to_string()), trace: None, cached: None, diff: None, sequence: None, client: None, input: None }
+}
+
+/// Runs a statement the way the handler does: it waits for its turn, then reads the state of the session, takes its time and writes it back, which loses updates if two statements run at once.
+fn statement(multiplexer: &Multiplexer, state: Arc<Mutex<Vec<String>>>, client: &str, input: &str) -> ClientReceiver {
+    let ticket = multiplexer.admit(SESSION, Some(client.to_string())).unwrap();
+    let (tx, rx) = client::channel(client::DEFAULT_CAPACITY);
+    let tx = ticket.tag(&tx);
+    let input = input.to_string();
+    tokio::spawn(async move {
+        ticket.turn().await;
+        ticket.announce(&input);
+
+        let mut defined = state.lock().unwrap().clone();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        defined.push(input.clone());
+        *state.lock().unwrap() = defined;
+
+        tx.send(Ok(reply(&format!("ran {}", input), false))).await.unwrap();
+        tx.send(Ok(reply("done", true))).await.unwrap();
+        drop(ticket);
+    });
+    rx
+}
+
+async fn collect(mut rx: ClientReceiver) -> Vec<ExecuteReply> {
+    let mut replies = vec![];
+    while let Some(reply) = rx.recv().await { replies.push(reply.unwrap()); }
+    replies
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn queued_statements_of_two_clients_do_not_clobber_the_state() {
+    let multiplexer = Multiplexer::new(StatementPolicy::Queue);
+    let state = Arc::new(Mutex::new(vec![]));
+
+    // Both clients send two statements without waiting for each other
+    let streams = vec![
+        statement(&multiplexer, state.clone(), "alice", "let a := 1;"),
+        statement(&multiplexer, state.clone(), "bob", "let b := 2;"),
+        statement(&multiplexer, state.clone(), "alice", "let c := 3;"),
+        statement(&multiplexer, state.clone(), "bob", "let d := 4;"),
+    ];
+    let replies = tokio::time::timeout(Duration::from_secs(10), futures::future::join_all(streams.into_iter().map(collect))).await.expect("Statements deadlocked");
+
+    // No statement lost the update of another, and they ran in the order they were sent
+    assert_eq!(*state.lock().unwrap(), vec![ "let a := 1;", "let b := 2;", "let c := 3;", "let d := 4;" ]);
+
+    // Every client only gets the replies of its own statements, numbered with the statement
+    for (i, (replies, client)) in replies.iter().zip([ "alice", "bob", "alice", "bob" ]).enumerate() {
+        assert_eq!(replies.len(), 2);
+        assert!(replies.iter().all(|reply| reply.sequence == Some(i as u64 + 1) && reply.client.as_deref() == Some(client)));
+        assert!(replies[1].close);
+    }
+}
+
+#[tokio::test]
+async fn rejecting_refuses_statements_while_the_session_is_busy() {
+    let multiplexer = Multiplexer::new(StatementPolicy::Reject);
+    let first = multiplexer.admit(SESSION, Some(String::from("alice"))).unwrap();
+    first.turn().await;
+
+    match multiplexer.admit(SESSION, Some(String::from("bob"))) {
+        Err(err @ MultiplexError::SessionBusy{ .. }) => {
+            assert!(matches!(&err, MultiplexError::SessionBusy{ uuid, running } if uuid == SESSION && *running == 1));
+            // Clients can tell a refused statement from a lost connection or an expired session
+            let status = busy_status(&err);
+            assert!(is_busy_status(&status));
+            assert!(std::error::Error::source(&status).is_none());
+            assert!(!is_busy_status(&tonic::Status::unavailable("Could not fetch the package index")));
+        },
+        res => panic!("Expected the session to be busy, got {:?}", res),
+    }
+    // Other sessions are not affected
+    multiplexer.admit("other", None).unwrap();
+
+    // Once the first statement is done, the next one gets the next number
+    drop(first);
+    assert_eq!(multiplexer.admit(SESSION, Some(String::from("bob"))).unwrap().sequence(), 2);
+}
+
+#[tokio::test]
+async fn abandoned_tickets_do_not_block_the_session() {
+    let multiplexer = Multiplexer::new(StatementPolicy::Queue);
+    let first = multiplexer.admit(SESSION, None).unwrap();
+    let second = multiplexer.admit(SESSION, None).unwrap();
+    let third = multiplexer.admit(SESSION, None).unwrap();
+    assert_eq!(third.ahead(), 2);
+
+    // The second statement is given up on before its turn (e.g., because it did not compile)
+    drop(second);
+    assert_eq!(third.ahead(), 1);
+    drop(first);
+    tokio::time::timeout(Duration::from_secs(1), third.turn()).await.expect("Abandoned ticket blocks the session");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn followers_see_the_statements_of_every_client() {
+    let multiplexer = Multiplexer::new(StatementPolicy::Queue);
+    let state = Arc::new(Mutex::new(vec![]));
+    let mut follower = multiplexer.follow(SESSION, client::DEFAULT_CAPACITY);
+    assert_eq!(multiplexer.followers(SESSION), 1);
+
+    let alice = statement(&multiplexer, state.clone(), "alice", "let a := 1;");
+    let bob = statement(&multiplexer, state.clone(), "bob", "let b := 2;");
+    futures::future::join(collect(alice), collect(bob)).await;
+
+    // The follower gets the statement itself and then its replies, for both clients in order
+    let mut seen = vec![];
+    while let Some(reply) = follower.try_recv() { seen.push(reply.unwrap()); }
+    let summary: Vec<(Option<u64>, Option<&str>, Option<&str>, Option<&str>)> = seen.iter().map(|reply| (reply.sequence, reply.client.as_deref(), reply.input.as_deref(), reply.stdout.as_deref())).collect();
+    assert_eq!(summary, vec![
+        (Some(1), Some("alice"), Some("let a := 1;"), None),
+        (Some(1), Some("alice"), None, Some("ran let a := 1;")),
+        (Some(1), Some("alice"), None, Some("done")),
+        (Some(2), Some("bob"), Some("let b := 2;"), None),
+        (Some(2), Some("bob"), None, Some("ran let b := 2;")),
+        (Some(2), Some("bob"), None, Some("done")),
+    ]);
+
+    // Followers that are gone are forgotten
+    drop(follower);
+    collect(statement(&multiplexer, state, "alice", "let c := 3;")).await;
+    assert_eq!(multiplexer.followers(SESSION), 0);
+}
+
+#[test]
+fn policies_parse() {
+    assert_eq!(StatementPolicy::from_str("queue").unwrap(), StatementPolicy::Queue);
+    assert_eq!(StatementPolicy::from_str("Reject").unwrap(), StatementPolicy::Reject);
+    assert!(StatementPolicy::from_str("drop").is_err());
+    assert_eq!(StatementPolicy::default().to_string(), "queue");
+}
@@ -0,0 +1,57 @@
+use brane_drv::outputs::{JobOutput, JobOutputs};
+
+fn finished(size: usize) -> JobOutput { JobOutput::Finished{ res: "x".repeat(size) } }
+
+#[test]
+fn oldest_outputs_are_evicted_first() {
+    // Room for three outputs of 30 bytes (including their 2-byte IDs)
+    let outputs = JobOutputs::new(100);
+    outputs.insert(String::from("j1"), finished(28));
+    outputs.insert(String::from("j2"), finished(28));
+    outputs.insert(String::from("j3"), finished(28));
+    assert_eq!(outputs.size(), 90);
+
+    // The fourth one pushes out the first
+    outputs.insert(String::from("j4"), finished(28));
+    assert!(outputs.get("j1").is_none());
+    assert!(outputs.get("j2").is_some());
+    assert_eq!(outputs.size(), 90);
+
+    // A large one pushes out as many as needed
+    outputs.insert(String::from("j5"), finished(68));
+    assert!(outputs.get("j2").is_none());
+    assert!(outputs.get("j3").is_none());
+    assert!(outputs.get("j4").is_some());
+    assert_eq!(outputs.size(), 100);
+}
+
+#[test]
+fn outputs_are_capped_by_size() {
+    let outputs = JobOutputs::new(100);
+    outputs.insert(String::from("j1"), finished(28));
+
+    // Outputs that don't fit at all are not stored, and don't evict anything either
+    outputs.insert(String::from("j2"), JobOutput::Failed{ res: "x".repeat(99) });
+    assert!(outputs.get("j2").is_none());
+    assert!(outputs.get("j1").is_some());
+    assert_eq!(outputs.size(), 30);
+
+    // Nothing fits in an empty store
+    let outputs = JobOutputs::new(0);
+    outputs.insert(String::from("j1"), finished(0));
+    assert!(outputs.get("j1").is_none());
+}
+
+#[test]
+fn replaced_outputs_are_counted_once() {
+    let outputs = JobOutputs::new(100);
+    outputs.insert(String::from("j1"), finished(28));
+    outputs.insert(String::from("j2"), finished(28));
+    outputs.insert(String::from("j1"), finished(8));
+    assert_eq!(outputs.size(), 40);
+
+    // Replacing moves the job to the back of the queue
+    outputs.insert(String::from("j3"), finished(59));
+    assert!(outputs.get("j2").is_none());
+    assert!(matches!(outputs.get("j1"), Some(JobOutput::Finished{ res }) if res.len() == 8));
+}
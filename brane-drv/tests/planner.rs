@@ -0,0 +1,88 @@
+use brane_cfg::infrastructure::{LocationCapabilities, MemoryLimit};
+use brane_drv::errors::PlannerError;
+use brane_drv::planner::{select_location, unmet_requirements, LocationChoice};
+use specifications::package::PackageRequirements;
+
+const GIB: i64 = 1024 * 1024 * 1024;
+
+/// A location with the given capabilities.
+fn location(name: &str, gpu: bool, memory_gib: Option<i64>, platform: Option<(&str, &str)>, cost: u64) -> (String, LocationCapabilities) {
+    (name.to_string(), LocationCapabilities {
+        gpu,
+        memory : memory_gib.map(|memory| MemoryLimit(memory * GIB)),
+        os     : platform.map(|(os, _)| os.to_string()),
+        arch   : platform.map(|(_, arch)| arch.to_string()),
+        cost,
+    })
+}
+
+/// The locations that most tests pick from: a cheap small one, an expensive GPU one and an ARM one in between.
+fn locations() -> Vec<(String, LocationCapabilities)> {
+    vec![
+        location("small", false, Some(4), Some(("linux", "amd64")), 1),
+        location("gpu", true, Some(64), Some(("linux", "amd64")), 10),
+        location("arm", false, Some(16), Some(("linux", "arm64")), 5),
+    ]
+}
+
+fn requirements(gpu: bool, min_memory: Option<&str>, os: Option<&str>, arch: Option<&str>) -> PackageRequirements {
+    PackageRequirements{ gpu, min_memory: min_memory.map(String::from), os: os.map(String::from), arch: arch.map(String::from) }
+}
+
+fn picked(requirements: &PackageRequirements, locations: &[(String, LocationCapabilities)]) -> String {
+    match select_location(requirements, locations) {
+        Ok(LocationChoice{ location, .. }) => location,
+        Err(err)                           => panic!("Expected a location, got: {}", err),
+    }
+}
+
+#[test]
+fn picks_the_cheapest_matching_location() {
+    let locations = locations();
+    assert_eq!(select_location(&PackageRequirements::default(), &locations).unwrap(), LocationChoice{ location: String::from("small"), cost: 1, candidates: 3 });
+    assert_eq!(picked(&requirements(true, None, None, None), &locations), "gpu");
+    assert_eq!(picked(&requirements(false, Some("8GiB"), None, None), &locations), "arm");
+    assert_eq!(picked(&requirements(false, Some("8GiB"), Some("linux"), Some("amd64")), &locations), "gpu");
+    assert_eq!(picked(&requirements(false, Some("4GiB"), None, Some("AMD64")), &locations), "small");
+}
+
+#[test]
+fn equally_cheap_locations_are_picked_alphabetically() {
+    let locations = vec![ location("site-b", false, None, None, 0), location("site-c", false, None, None, 0), location("site-a", false, None, None, 0) ];
+    assert_eq!(picked(&PackageRequirements::default(), &locations), "site-a");
+
+    let mut reversed = locations.clone();
+    reversed.reverse();
+    assert_eq!(picked(&PackageRequirements::default(), &reversed), "site-a");
+}
+
+#[test]
+fn undeclared_capabilities_are_not_offered() {
+    let bare = location("bare", false, None, None, 0).1;
+    assert!(unmet_requirements(&PackageRequirements::default(), &bare).is_empty());
+    assert_eq!(unmet_requirements(&requirements(true, Some("1GiB"), Some("linux"), Some("amd64")), &bare), vec![
+        "gpu", "min_memory 1GiB (offers no memory)", "os linux (offers none)", "arch amd64 (offers none)",
+    ]);
+}
+
+#[test]
+fn mismatches_list_the_unmet_requirements() {
+    let err = select_location(&requirements(true, Some("32GiB"), None, Some("arm64")), &locations()).unwrap_err();
+    match &err {
+        PlannerError::NoMatchingLocation{ unmet, .. } => {
+            let names: Vec<&str> = unmet.iter().map(|(name, _)| name.as_str()).collect();
+            assert_eq!(names, vec![ "arm", "gpu", "small" ]);
+            assert_eq!(unmet[0].1, vec![ "gpu", "min_memory 32GiB (offers 17179869184 bytes)" ]);
+            assert_eq!(unmet[1].1, vec![ "arch arm64 (offers amd64)" ]);
+            assert_eq!(unmet[2].1.len(), 3);
+        },
+        err => panic!("Expected a NoMatchingLocation error, got: {}", err),
+    }
+    assert!(format!("{}", err).contains("'arm' lacks gpu, min_memory 32GiB"), "Unexpected message: {}", err);
+
+    // Sizes that cannot be parsed are never satisfied
+    let err = select_location(&requirements(false, Some("lots"), None, None), &locations()).unwrap_err();
+    assert!(matches!(err, PlannerError::NoMatchingLocation{ ref unmet, .. } if unmet.len() == 3));
+
+    assert!(matches!(select_location(&PackageRequirements::default(), &[]), Err(PlannerError::NoLocations)));
+}
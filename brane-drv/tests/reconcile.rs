@@ -0,0 +1,165 @@
+use brane_bvm::executor::ExecutorError;
+use brane_drv::events::EventMonitor;
+use brane_drv::executor::{release_resumed, resume_session, take_resumed, TimeoutPolicy};
+use brane_drv::outputs::JobOutputs;
+use brane_drv::sessions::{PendingJob, SessionStore};
+use brane_job::interface::{Event, EventKind};
+use dashmap::DashMap;
+use specifications::common::Value;
+use specifications::version::Version;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SESSION: &str = "8c9d5a2e-0000-4000-8000-000000000001";
+const JOB: &str = "A8c9d5a2eRabc123";
+const CALL: &str = "hello:1.0.0/hello@*{}";
+const HORIZON: Duration = Duration::from_secs(3600);
+
+/// Creates a fresh EventMonitor, as the driver does when it (re)starts.
+fn new_monitor() -> EventMonitor {
+    EventMonitor::new(
+        Arc::new(DashMap::new()),
+        Arc::new(DashMap::new()),
+        Arc::new(DashMap::new()),
+        Arc::new(JobOutputs::new(1024 * 1024)),
+        Arc::new(DashMap::new()),
+        Arc::new(DashMap::new()),
+    )
+}
+
+fn event(
+    kind: EventKind,
+    order: u32,
+    payload: Option<&str>,
+) -> Event {
+    Event::new(kind, format!("{}-abcd", JOB), String::from("app"), String::from("loc1"), String::from("job"), order, payload.map(|payload| payload.as_bytes().to_vec()), None)
+}
+
+/// A job for the 'hello' function, scheduled the given number of seconds ago.
+fn pending(age: u64) -> PendingJob {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    PendingJob {
+        correlation_id : JOB.to_string(),
+        call_key       : CALL.to_string(),
+        function       : String::from("hello"),
+        package        : String::from("hello"),
+        version        : Version::from_str("1.0.0").unwrap(),
+        scheduled_at   : now - age,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn resumes_job_after_restart_between_created_and_finished() {
+    let dir = tempfile::tempdir().unwrap();
+
+    // Before the restart, the session scheduled the job and saw it being created
+    {
+        let sessions = SessionStore::new(Some(dir.path().to_path_buf())).unwrap();
+        sessions.add_pending(SESSION, pending(0)).unwrap();
+        assert!(new_monitor().handle(&event(EventKind::Created, 0, None)));
+    }
+
+    // After the restart, the Created event has been committed and is not replayed, but the session is restored
+    let sessions = SessionStore::new(Some(dir.path().to_path_buf())).unwrap();
+    assert_eq!(sessions.pending(SESSION).len(), 1);
+    assert_eq!(sessions.pending(SESSION)[0].correlation_id, JOB);
+    let monitor = new_monitor();
+    let resumed = DashMap::new();
+    assert_eq!(resume_session(SESSION, &sessions, &resumed, HORIZON, &TimeoutPolicy::default(), monitor.heartbeats.clone(), monitor.states.clone(), monitor.active.clone()), 1);
+    // Reattaching twice does not resume the job twice
+    assert_eq!(resume_session(SESSION, &sessions, &resumed, HORIZON, &TimeoutPolicy::default(), monitor.heartbeats.clone(), monitor.states.clone(), monitor.active.clone()), 0);
+
+    // The job keeps running and finishes
+    let late = monitor.clone();
+    tokio::spawn(async move {
+        for (i, kind) in vec![ EventKind::Heartbeat, EventKind::Completed ].into_iter().enumerate() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            late.handle(&event(kind, 5 + i as u32, None));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        late.handle(&event(EventKind::Finished, 7, Some("{\"v\":\"integer\",\"c\":42}")));
+    });
+
+    // Only the same call of the same session gets the result
+    assert!(take_resumed(&resumed, SESSION, "hello:1.0.0/hello@*{\"name\":\"x\"}").is_none());
+    assert!(take_resumed(&resumed, "some-other-session", CALL).is_none());
+    let job = take_resumed(&resumed, SESSION, CALL).unwrap();
+    assert!(matches!(job.wait().await, Ok(Value::Integer(42))));
+    assert!(resumed.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn resumed_job_delivers_failures() {
+    let sessions = SessionStore::new(None).unwrap();
+    sessions.add_pending(SESSION, pending(0)).unwrap();
+
+    // The job failed while the driver was down, so the event is replayed
+    let monitor = new_monitor();
+    monitor.handle(&event(EventKind::Failed, 8, Some("{\"code\":3,\"stdout\":\"\",\"stderr\":\"oops\"}")));
+
+    let resumed = DashMap::new();
+    resume_session(SESSION, &sessions, &resumed, HORIZON, &TimeoutPolicy::default(), monitor.heartbeats.clone(), monitor.states.clone(), monitor.active.clone());
+    let res = take_resumed(&resumed, SESSION, CALL).unwrap().wait().await;
+    assert!(matches!(res, Err(ExecutorError::ExternalCallFailed{ code: 3, .. })));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn resumed_job_delivers_raw_failures() {
+    let sessions = SessionStore::new(None).unwrap();
+    sessions.add_pending(SESSION, pending(0)).unwrap();
+
+    let monitor = new_monitor();
+    monitor.handle(&event(EventKind::Failed, 8, Some("Segmentation fault (core dumped)")));
+
+    let resumed = DashMap::new();
+    resume_session(SESSION, &sessions, &resumed, HORIZON, &TimeoutPolicy::default(), monitor.heartbeats.clone(), monitor.states.clone(), monitor.active.clone());
+    match take_resumed(&resumed, SESSION, CALL).unwrap().wait().await {
+        Err(ExecutorError::ExternalCallFailedRaw{ output, .. }) => assert_eq!(output, "Segmentation fault (core dumped)"),
+        res                                                     => panic!("Expected a raw failure, got {:?}", res),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn old_jobs_without_events_are_lost() {
+    let sessions = SessionStore::new(None).unwrap();
+    sessions.add_pending(SESSION, pending(2 * HORIZON.as_secs())).unwrap();
+
+    let monitor = new_monitor();
+    let resumed = DashMap::new();
+    resume_session(SESSION, &sessions, &resumed, HORIZON, &TimeoutPolicy::default(), monitor.heartbeats.clone(), monitor.states.clone(), monitor.active.clone());
+    match take_resumed(&resumed, SESSION, CALL).unwrap().wait().await {
+        Err(ExecutorError::ExternalCallError{ err, .. }) => assert!(err.contains("considered lost")),
+        res                                              => panic!("Expected the job to be lost, got {:?}", res),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn untaken_jobs_are_released_with_their_session() {
+    let sessions = SessionStore::new(None).unwrap();
+    sessions.add_pending(SESSION, pending(0)).unwrap();
+    sessions.add_pending("some-other-session", PendingJob{ correlation_id: String::from("A00000000Rdef456"), ..pending(0) }).unwrap();
+
+    let monitor = new_monitor();
+    let resumed = DashMap::new();
+    resume_session(SESSION, &sessions, &resumed, HORIZON, &TimeoutPolicy::default(), monitor.heartbeats.clone(), monitor.states.clone(), monitor.active.clone());
+    resume_session("some-other-session", &sessions, &resumed, HORIZON, &TimeoutPolicy::default(), monitor.heartbeats.clone(), monitor.states.clone(), monitor.active.clone());
+    assert_eq!(resumed.len(), 2);
+
+    // The session never retries its statement before it goes away
+    assert_eq!(release_resumed(&resumed, SESSION), vec![ JOB.to_string() ]);
+    assert!(take_resumed(&resumed, SESSION, CALL).is_none());
+    assert!(take_resumed(&resumed, "some-other-session", CALL).is_some());
+    assert!(release_resumed(&resumed, SESSION).is_empty());
+}
+
+#[test]
+fn pending_jobs_are_persisted() {
+    let dir = tempfile::tempdir().unwrap();
+    let sessions = SessionStore::new(Some(dir.path().to_path_buf())).unwrap();
+    sessions.add_pending(SESSION, pending(0)).unwrap();
+    sessions.remove_pending(SESSION, JOB).unwrap();
+
+    let sessions = SessionStore::new(Some(dir.path().to_path_buf())).unwrap();
+    assert!(sessions.pending(SESSION).is_empty());
+}
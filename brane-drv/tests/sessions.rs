@@ -0,0 +1,131 @@
+use brane_bvm::vm::VmState;
+use brane_drv::sessions::{expired_status, is_expired_status, PendingJob, SessionStore};
+use specifications::version::Version;
+use std::str::FromStr;
+use std::time::Duration;
+use tonic::Status;
+
+const IDLE: &str = "8c9d5a2e-0000-4000-8000-000000000003";
+const BUSY: &str = "8c9d5a2e-0000-4000-8000-000000000004";
+const TTL: Duration = Duration::from_millis(50);
+
+fn pending() -> PendingJob {
+    PendingJob {
+        correlation_id : String::from("A8c9d5a2eRabc123"),
+        call_key       : String::from("hello:1.0.0/hello@*{}"),
+        function       : String::from("hello"),
+        package        : String::from("hello"),
+        version        : Version::from_str("1.0.0").unwrap(),
+        scheduled_at   : 0,
+    }
+}
+
+#[test]
+fn idle_sessions_expire_after_the_ttl() {
+    let sessions = SessionStore::new(None).unwrap();
+    sessions.touch(IDLE);
+    sessions.set_state(BUSY, VmState::default()).unwrap();
+    assert_eq!(sessions.active(), 2);
+
+    // Nothing is idle for long enough yet
+    assert!(sessions.expire_idle(TTL).is_empty());
+
+    // Using a session postpones its expiry
+    std::thread::sleep(TTL * 2);
+    sessions.touch(BUSY);
+    assert_eq!(sessions.expire_idle(TTL), vec![ IDLE ]);
+    assert!(sessions.is_expired(IDLE));
+    assert!(!sessions.is_expired(BUSY));
+    assert_eq!(sessions.active(), 1);
+    assert!(sessions.state(BUSY).is_some());
+}
+
+#[test]
+fn sessions_waiting_for_jobs_are_not_idle() {
+    let sessions = SessionStore::new(None).unwrap();
+    sessions.add_pending(BUSY, pending()).unwrap();
+
+    std::thread::sleep(TTL * 2);
+    assert!(sessions.expire_idle(TTL).is_empty());
+    assert_eq!(sessions.pending(BUSY).len(), 1);
+}
+
+#[test]
+fn expired_sessions_are_removed_from_disk() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let sessions = SessionStore::new(Some(dir.path().to_path_buf())).unwrap();
+        sessions.set_state(IDLE, VmState::default()).unwrap();
+    }
+    assert!(dir.path().join(format!("{}.json", IDLE)).exists());
+
+    // The restored session keeps the time it was last used
+    std::thread::sleep(Duration::from_millis(1100));
+    let sessions = SessionStore::new(Some(dir.path().to_path_buf())).unwrap();
+    assert_eq!(sessions.expire_idle(Duration::from_secs(1)), vec![ IDLE ]);
+    assert!(!dir.path().join(format!("{}.json", IDLE)).exists());
+    assert!(SessionStore::new(Some(dir.path().to_path_buf())).unwrap().state(IDLE).is_none());
+}
+
+#[test]
+fn closed_sessions_are_forgotten() {
+    let dir = tempfile::tempdir().unwrap();
+    let sessions = SessionStore::new(Some(dir.path().to_path_buf())).unwrap();
+    sessions.set_state(IDLE, VmState::default()).unwrap();
+
+    assert!(sessions.is_known(IDLE));
+    assert!(!sessions.is_known(BUSY));
+    assert!(sessions.close(IDLE).unwrap());
+    assert!(!sessions.is_known(IDLE));
+    assert!(sessions.state(IDLE).is_none());
+    assert_eq!(sessions.active(), 0);
+    assert!(!dir.path().join(format!("{}.json", IDLE)).exists());
+
+    // Closing is not expiring, and closing twice is fine
+    assert!(!sessions.is_expired(IDLE));
+    assert!(!sessions.close(IDLE).unwrap());
+}
+
+#[test]
+fn expired_sessions_are_reported_until_forgotten() {
+    let sessions = SessionStore::new(None).unwrap();
+    sessions.touch(IDLE);
+    std::thread::sleep(TTL * 2);
+    assert_eq!(sessions.expire_idle(TTL), vec![ IDLE ]);
+
+    // Clients that come back get a status that the CLI recognizes
+    assert!(sessions.is_expired(IDLE));
+    let status = expired_status(IDLE);
+    assert!(is_expired_status(&status));
+    assert!(status.message().contains(IDLE));
+    assert!(!is_expired_status(&Status::not_found("No output known for job")));
+    assert!(!is_expired_status(&Status::unavailable("Session is busy")));
+    // Other failed preconditions don't carry the marker
+    assert!(!is_expired_status(&Status::failed_precondition("Job is not finished yet")));
+
+    // After another TTL, the session is forgotten altogether
+    std::thread::sleep(TTL * 2);
+    assert!(sessions.expire_idle(TTL).is_empty());
+    assert!(!sessions.is_expired(IDLE));
+}
+
+#[test]
+fn shared_sessions_stay_open_until_the_last_client_is_done() {
+    let sessions = SessionStore::new(None).unwrap();
+    sessions.touch(IDLE);
+    sessions.attach(IDLE, "creator");
+    sessions.attach(IDLE, "follower");
+    // Reattaching after a reconnect does not count twice
+    sessions.attach(IDLE, "follower");
+
+    assert_eq!(sessions.detach(IDLE, Some("creator")), 1);
+    // Clients that don't identify themselves don't close it from under the others either
+    assert_eq!(sessions.detach(IDLE, None), 1);
+    assert_eq!(sessions.detach(IDLE, Some("follower")), 0);
+    assert_eq!(sessions.detach(IDLE, None), 0);
+
+    // Closing forgets the clients too
+    sessions.attach(BUSY, "creator");
+    assert!(!sessions.close(BUSY).unwrap());
+    assert_eq!(sessions.detach(BUSY, None), 0);
+}
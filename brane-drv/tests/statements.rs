@@ -0,0 +1,120 @@
+use brane_drv::client;
+use brane_drv::grpc::ExecuteReply;
+use brane_drv::handler::replay_statement;
+use brane_drv::statements::{StatementCache, StatementGuard, StatementStatus};
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::{Code, Status};
+
+const SESSION: &str = "8c9d5a2e-0000-4000-8000-000000000001";
+
+fn closing(stdout: &str) -> ExecuteReply {
+    ExecuteReply {
+        close    : true,
+        debug    : None,
+        stderr   : None,
+        stdout   : Some(stdout.to_string()),
+        trace    : None,
+        cached   : None,
+        diff     : None,
+        sequence : None,
+        client   : None,
+        input    : None,
+    }
+}
+
+#[test]
+fn duplicate_tokens_are_recognized() {
+    let statements = StatementCache::new(16);
+    assert!(statements.begin(SESSION, "t1").is_none());
+    assert_eq!(statements.begin(SESSION, "t1"), Some(StatementStatus::Running));
+    // The same token in another session is another statement
+    assert!(statements.begin("some-other-session", "t1").is_none());
+
+    statements.finish(SESSION, "t1", &Ok(closing("42")));
+    assert_eq!(statements.begin(SESSION, "t1"), Some(StatementStatus::Done(Ok(closing("42")))));
+
+    // Refused statements are remembered as well
+    assert!(statements.begin(SESSION, "t2").is_none());
+    statements.finish(SESSION, "t2", &Err(Status::invalid_argument("oops")));
+    assert_eq!(statements.status(SESSION, "t2"), Some(StatementStatus::Done(Err((Code::InvalidArgument, String::from("oops"))))));
+}
+
+#[test]
+fn oldest_finished_statements_are_evicted() {
+    let statements = StatementCache::new(2);
+    for token in &[ "t1", "t2", "t3" ] { statements.begin(SESSION, token); }
+    for token in &[ "t1", "t2" ] { statements.finish(SESSION, token, &Ok(closing(token))); }
+
+    // Running statements don't count
+    assert_eq!(statements.status(SESSION, "t3"), Some(StatementStatus::Running));
+    statements.finish(SESSION, "t3", &Ok(closing("t3")));
+    assert!(statements.status(SESSION, "t1").is_none());
+    assert!(statements.status(SESSION, "t2").is_some());
+    assert!(statements.status(SESSION, "t3").is_some());
+}
+
+#[tokio::test]
+async fn replays_finished_statement() {
+    let statements = Arc::new(StatementCache::new(16));
+    statements.begin(SESSION, "t1");
+    statements.finish(SESSION, "t1", &Ok(closing("42")));
+
+    let (tx, mut rx) = client::channel(10);
+    let status = statements.begin(SESSION, "t1").unwrap();
+    replay_statement(statements.clone(), SESSION.to_string(), String::from("t1"), status, tx).await;
+
+    let reply = rx.recv().await.unwrap().unwrap();
+    assert!(reply.close);
+    assert_eq!(reply.stdout.as_deref(), Some("42"));
+    assert_eq!(reply.cached, Some(true));
+    assert!(rx.recv().await.is_none());
+}
+
+#[tokio::test]
+async fn replay_waits_for_running_statement() {
+    let statements = Arc::new(StatementCache::new(16));
+    statements.begin(SESSION, "t1");
+
+    let (tx, mut rx) = client::channel(10);
+    let status = statements.begin(SESSION, "t1").unwrap();
+    tokio::spawn(replay_statement(statements.clone(), SESSION.to_string(), String::from("t1"), status, tx));
+
+    // First we're told to wait...
+    let reply = rx.recv().await.unwrap().unwrap();
+    assert!(!reply.close);
+    assert_eq!(reply.cached, Some(true));
+
+    // ...and once the original run is refused, we get that too
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    statements.finish(SESSION, "t1", &Err(Status::invalid_argument("oops")));
+    let status = rx.recv().await.unwrap().unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(status.message(), "oops");
+}
+
+#[test]
+fn guard_finishes_statements_that_stop_early() {
+    let statements = Arc::new(StatementCache::new(16));
+    statements.begin(SESSION, "t1");
+    statements.begin(SESSION, "t2");
+
+    // A run that finishes normally keeps its reply
+    let mut guard = StatementGuard::new(statements.clone(), SESSION.to_string(), Some(String::from("t1")));
+    guard.finish(&Ok(closing("42")));
+    drop(guard);
+    assert_eq!(statements.status(SESSION, "t1"), Some(StatementStatus::Done(Ok(closing("42")))));
+
+    // A run that panics is marked as aborted, so the clients that send it again don't wait forever
+    let guard = StatementGuard::new(statements.clone(), SESSION.to_string(), Some(String::from("t2")));
+    let res = std::thread::spawn(move || {
+        let _guard = guard;
+        panic!("The VM panicked");
+    }).join();
+    assert!(res.is_err());
+    assert!(matches!(statements.status(SESSION, "t2"), Some(StatementStatus::Done(Err((Code::Aborted, _))))));
+
+    // Statements without a token are not remembered at all
+    drop(StatementGuard::new(statements.clone(), SESSION.to_string(), None));
+    assert!(statements.status(SESSION, "t3").is_none());
+}
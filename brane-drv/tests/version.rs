@@ -0,0 +1,31 @@
+use brane_drv::grpc::Compatibility;
+use brane_drv::handler::check_client_version;
+use specifications::version::Version;
+
+#[test]
+fn equal_and_patch_different_versions_are_compatible() {
+    let driver = Version::new(1, 4, 2);
+    assert_eq!(check_client_version(&driver, Some("1.4.2")), Compatibility::Compatible);
+    assert_eq!(check_client_version(&driver, Some("1.4.0")), Compatibility::Compatible);
+    assert_eq!(check_client_version(&driver, Some("1.4.7")), Compatibility::Compatible);
+}
+
+#[test]
+fn major_different_versions_say_which_side_to_upgrade() {
+    let driver = Version::new(2, 0, 0);
+    assert_eq!(check_client_version(&driver, Some("1.9.3")), Compatibility::UpgradeClient);
+    assert_eq!(check_client_version(&driver, Some("3.0.0")), Compatibility::UpgradeDriver);
+
+    // Before 1.0.0, a different minor version is a breaking change too
+    let driver = Version::new(0, 6, 0);
+    assert_eq!(check_client_version(&driver, Some("0.6.3")), Compatibility::Compatible);
+    assert_eq!(check_client_version(&driver, Some("0.5.1")), Compatibility::UpgradeClient);
+}
+
+#[test]
+fn missing_or_garbled_versions_are_unknown() {
+    let driver = Version::new(1, 0, 0);
+    assert_eq!(check_client_version(&driver, None), Compatibility::Unknown);
+    assert_eq!(check_client_version(&driver, Some("one")), Compatibility::Unknown);
+    assert_eq!(check_client_version(&driver, Some("latest")), Compatibility::Unknown);
+}
@@ -0,0 +1,230 @@
+use brane_bvm::executor::{ExecutorError, ServiceState};
+use brane_cfg::Infrastructure;
+use brane_drv::executor::{mark_active, wait_until_service, ActiveJob, JobTimeouts, TimeoutPolicy};
+use brane_shr::jobs::JobStatus;
+use dashmap::DashMap;
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+const SERVICE: &str = "AabcdefghRxyz123";
+
+const INFRA: &str = "locations:
+  impatient:
+    kind: local
+    network: brane
+    registry: \"localhost:5000\"
+    callback_to: \"http://brane-clb:50052\"
+    timeouts:
+      ready: 1
+      heartbeat: 1
+      result: 5
+  patient:
+    kind: local
+    network: brane
+    registry: \"localhost:5000\"
+    callback_to: \"http://brane-clb:50052\"
+";
+
+struct Maps {
+    heartbeats: Arc<DashMap<String, SystemTime>>,
+    states: Arc<DashMap<String, JobStatus>>,
+    active: Arc<DashMap<String, ActiveJob>>,
+    policy: TimeoutPolicy,
+}
+
+impl Maps {
+    fn new() -> Self {
+        Maps {
+            heartbeats: Arc::new(DashMap::new()),
+            states: Arc::new(DashMap::new()),
+            active: Arc::new(DashMap::new()),
+            policy: TimeoutPolicy::default(),
+        }
+    }
+
+    /// Like Maps::new(), but with the timeouts of the given infra.yml, where the service runs on the given location.
+    fn with_location(dir: &tempfile::TempDir, location: &str) -> Self {
+        let path = dir.path().join("infra.yml");
+        fs::write(&path, INFRA).unwrap();
+        let infra = Infrastructure::new(path.to_string_lossy().to_string()).unwrap();
+
+        let locations = Arc::new(DashMap::new());
+        locations.insert(SERVICE.to_string(), location.to_string());
+        Maps { policy: TimeoutPolicy::new(infra, locations), ..Maps::new() }
+    }
+
+    async fn wait(&self, state: ServiceState) -> Result<(), ExecutorError> {
+        wait_until_service(SERVICE, state, &self.policy, self.heartbeats.clone(), self.states.clone(), self.active.clone()).await
+    }
+}
+
+/// Moves the service through the given states, waiting the given number of milliseconds before each one (as the event monitor would).
+fn flip_states(
+    states: Arc<DashMap<String, JobStatus>>,
+    flips: Vec<(u64, JobStatus)>,
+) {
+    tokio::spawn(async move {
+        for (delay, state) in flips {
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            states.insert(SERVICE.to_string(), state);
+        }
+    });
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn waits_until_started() {
+    let maps = Maps::new();
+    maps.states.insert(SERVICE.to_string(), JobStatus::Created);
+    flip_states(maps.states.clone(), vec![(100, JobStatus::Ready), (100, JobStatus::Initialized), (100, JobStatus::Started)]);
+
+    let start = Instant::now();
+    maps.wait(ServiceState::Started).await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    assert!(matches!(*maps.states.get(SERVICE).unwrap(), JobStatus::Started));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn started_returns_immediately_when_already_further() {
+    let maps = Maps::new();
+    maps.states.insert(SERVICE.to_string(), JobStatus::Completed);
+
+    let start = Instant::now();
+    maps.wait(ServiceState::Started).await.unwrap();
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn waits_until_done() {
+    let maps = Maps::new();
+    maps.states.insert(SERVICE.to_string(), JobStatus::Started);
+    maps.heartbeats.insert(SERVICE.to_string(), SystemTime::now());
+    flip_states(maps.states.clone(), vec![(100, JobStatus::Completed), (100, JobStatus::Finished{ res: String::from("{\"v\":\"unit\"}") })]);
+
+    let start = Instant::now();
+    maps.wait(ServiceState::Done).await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fails_if_service_fails() {
+    let maps = Maps::new();
+    maps.states.insert(SERVICE.to_string(), JobStatus::Created);
+    flip_states(maps.states.clone(), vec![(100, JobStatus::StartFailed{ err: String::from("no such binary") })]);
+
+    match maps.wait(ServiceState::Started).await {
+        Err(ExecutorError::ServiceFailed{ service, err }) => {
+            assert_eq!(service, SERVICE);
+            assert!(err.contains("no such binary"));
+        },
+        res => panic!("Expected ServiceFailed, got {:?}", res),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fails_if_service_is_stopped_before_done() {
+    let maps = Maps::new();
+    maps.states.insert(SERVICE.to_string(), JobStatus::Started);
+    maps.heartbeats.insert(SERVICE.to_string(), SystemTime::now());
+    flip_states(maps.states.clone(), vec![(100, JobStatus::Stopped{ signal: String::from("SIGKILL") })]);
+
+    assert!(matches!(maps.wait(ServiceState::Done).await, Err(ExecutorError::ServiceFailed{ .. })));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stops_waiting_when_cancelled() {
+    let maps = Maps::new();
+    maps.states.insert(SERVICE.to_string(), JobStatus::Created);
+    let (client_tx, _client_rx) = brane_drv::client::channel(1);
+    maps.active.insert(SERVICE.to_string(), ActiveJob{ session_uuid: String::from("session"), cancelled: true, client_tx });
+
+    assert!(matches!(maps.wait(ServiceState::Started).await, Err(ExecutorError::ServiceFailed{ .. })));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn waiting_again_keeps_the_job_cancelled() {
+    let maps = Maps::new();
+    maps.states.insert(SERVICE.to_string(), JobStatus::Created);
+    let (client_tx, _client_rx) = brane_drv::client::channel(1);
+    maps.active.insert(SERVICE.to_string(), ActiveJob{ session_uuid: String::from("session"), cancelled: true, client_tx: client_tx.clone() });
+
+    // Another wait for the same job does not mark it as running again
+    assert!(!mark_active(&maps.active, SERVICE, ActiveJob{ session_uuid: String::from("session"), cancelled: false, client_tx: client_tx.clone() }));
+    assert!(maps.active.get(SERVICE).unwrap().cancelled);
+    assert!(matches!(maps.wait(ServiceState::Started).await, Err(ExecutorError::ServiceFailed{ .. })));
+
+    // But jobs nobody waits for yet are marked
+    maps.active.clear();
+    assert!(mark_active(&maps.active, SERVICE, ActiveJob{ session_uuid: String::from("session"), cancelled: false, client_tx }));
+    assert!(!maps.active.get(SERVICE).unwrap().cancelled);
+}
+
+#[test]
+fn location_timeouts_override_defaults() {
+    let dir = tempfile::tempdir().unwrap();
+    let maps = Maps::with_location(&dir, "impatient");
+
+    let timeouts = maps.policy.for_job(SERVICE, None).unwrap();
+    assert_eq!(timeouts, JobTimeouts{ ready: 1000, heartbeat: 1000, result: 5000, ..JobTimeouts::default() });
+    assert_ne!(timeouts.heartbeat, JobTimeouts::default().heartbeat);
+
+    // Locations without timeouts, unknown locations and unknown jobs get the defaults
+    assert_eq!(maps.policy.for_location("patient"), JobTimeouts::default());
+    assert_eq!(maps.policy.for_location("nowhere"), JobTimeouts::default());
+    assert_eq!(maps.policy.for_job("unknown", None), None);
+    assert_eq!(maps.policy.for_job("unknown", Some("impatient")), Some(timeouts));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn uses_heartbeat_timeout_of_location() {
+    let dir = tempfile::tempdir().unwrap();
+    let maps = Maps::with_location(&dir, "impatient");
+    maps.states.insert(SERVICE.to_string(), JobStatus::Started);
+    maps.heartbeats.insert(SERVICE.to_string(), SystemTime::now());
+
+    // The default heartbeat timeout is 10 seconds, but this location only allows one
+    let start = Instant::now();
+    match maps.wait(ServiceState::Done).await {
+        Err(ExecutorError::ServiceFailed{ err, .. }) => assert!(err.contains("1 seconds"), "Unexpected error: {}", err),
+        res => panic!("Expected ServiceFailed, got {:?}", res),
+    }
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pull_progress_keeps_created_job_alive() {
+    let dir = tempfile::tempdir().unwrap();
+    let maps = Maps::with_location(&dir, "impatient");
+    maps.states.insert(SERVICE.to_string(), JobStatus::Created);
+
+    // The location only gives one second to get ready, but keeps reporting that it is pulling the image (as the event monitor records it)
+    let heartbeats = maps.heartbeats.clone();
+    tokio::spawn(async move {
+        for _ in 0..5 {
+            tokio::time::sleep(Duration::from_millis(400)).await;
+            heartbeats.insert(SERVICE.to_string(), SystemTime::now());
+        }
+    });
+    flip_states(maps.states.clone(), vec![(2000, JobStatus::Ready), (100, JobStatus::Initialized), (100, JobStatus::Started)]);
+
+    let start = Instant::now();
+    maps.wait(ServiceState::Started).await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(2200));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn created_job_times_out_without_pull_progress() {
+    let dir = tempfile::tempdir().unwrap();
+    let maps = Maps::with_location(&dir, "impatient");
+    maps.states.insert(SERVICE.to_string(), JobStatus::Created);
+
+    // Heartbeats from before we started waiting don't count
+    maps.heartbeats.insert(SERVICE.to_string(), SystemTime::now() - Duration::from_secs(60));
+    let start = Instant::now();
+    match maps.wait(ServiceState::Started).await {
+        Err(ExecutorError::ServiceFailed{ err, .. }) => assert!(err.contains("1 seconds"), "Unexpected error: {}", err),
+        res => panic!("Expected ServiceFailed, got {:?}", res),
+    }
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
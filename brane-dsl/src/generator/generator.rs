@@ -71,10 +71,16 @@ pub fn stmt_to_opcodes(
 ) {
     match stmt {
         Stmt::Import {
-            package: Ident(ident), ..
+            package: Ident(ident),
+            version,
         } => {
             let import = chunk.add_constant(ident.into());
+            let constraint = match version {
+                Some(constraint) => chunk.add_constant(Value::Unicode(constraint.to_string())),
+                None             => chunk.add_constant(Value::Unit),
+            };
             chunk.write_pair(Opcode::IMPORT, import);
+            chunk.write(constraint);
         }
         Stmt::DeclareClass {
             ident: Ident(ident),
@@ -120,6 +126,20 @@ pub fn stmt_to_opcodes(
                 chunk.write_pair(Opcode::SET_GLOBAL, ident);
             }
         }
+        Stmt::IndexAssign { ident: Ident(ident), index, value } => {
+            // Heap objects are immutable, so INDEX_SET results in an updated copy that we store in the variable again.
+            expr_to_opcodes(Expr::Ident(Ident(ident.clone())), chunk, locals, scope);
+            expr_to_opcodes(index, chunk, locals, scope);
+            expr_to_opcodes(value, chunk, locals, scope);
+            chunk.write(Opcode::INDEX_SET);
+
+            if let Some(index) = locals.iter().position(|l| l.name == ident) {
+                chunk.write_pair(Opcode::SET_LOCAL, index as u8);
+            } else {
+                let ident = chunk.add_constant(ident.into());
+                chunk.write_pair(Opcode::SET_GLOBAL, ident);
+            }
+        }
         Stmt::LetAssign(Ident(ident), expr) => {
             expr_to_opcodes(expr, chunk, locals, scope);
 
@@ -274,6 +294,11 @@ pub fn stmt_to_opcodes(
             expr_to_opcodes(expr, chunk, locals, scope);
             chunk.write(Opcode::POP);
         }
+        Stmt::Located { line, stmt } => {
+            // Everything emitted for this statement originates from its line
+            chunk.set_line(line);
+            stmt_to_opcodes(*stmt, chunk, locals, scope);
+        }
         Stmt::Property { .. } => {
             unreachable!()
         }
@@ -415,6 +440,23 @@ pub fn expr_to_opcodes(
                 }
             }
 
+            if let BinOp::Coalesce = operator {
+                // Only evaluate the fallback if the lefthandside is unit
+                chunk.write(Opcode::COALESCE);
+                // Placeholders, we'll backpatch this later.
+                let plh_pos = chunk.code.len();
+                chunk.write_pair(0x00, 0x00);
+
+                expr_to_opcodes(rhs_operand, chunk, locals, scope);
+
+                // How much to jump if the lefthandside is kept?
+                let jump = (chunk.code.len() - plh_pos - 2) as u16;
+                let [first, second, ..] = jump.to_be_bytes();
+                chunk.code[plh_pos] = first;
+                chunk.code[plh_pos + 1] = second;
+                return;
+            }
+
             expr_to_opcodes(rhs_operand, chunk, locals, scope);
             match operator {
                 // Arithmetic
@@ -426,21 +468,9 @@ pub fn expr_to_opcodes(
                 BinOp::Eq => chunk.write(Opcode::EQUAL),
                 BinOp::Lt => chunk.write(Opcode::LESS),
                 BinOp::Gt => chunk.write(Opcode::GREATER),
-                BinOp::Le => {
-                    // !(lhs > rhs)
-                    chunk.write(Opcode::GREATER);
-                    chunk.write(Opcode::NOT);
-                }
-                BinOp::Ge => {
-                    // !(lhs < rhs)
-                    chunk.write(Opcode::LESS);
-                    chunk.write(Opcode::NOT);
-                }
-                BinOp::Ne => {
-                    // !(lhs == rhs)
-                    chunk.write(Opcode::EQUAL);
-                    chunk.write(Opcode::NOT);
-                }
+                BinOp::Le => chunk.write(Opcode::LESS_EQUAL),
+                BinOp::Ge => chunk.write(Opcode::GREATER_EQUAL),
+                BinOp::Ne => chunk.write(Opcode::NOT_EQUAL),
 
                 // Logical
                 BinOp::And => chunk.write(Opcode::AND),
@@ -1,4 +1,4 @@
-use semver::Version;
+use specifications::version::VersionConstraint;
 use std::collections::HashMap;
 
 pub type Program = Block;
@@ -30,11 +30,23 @@ pub enum Stmt {
         consequent: Block,
         alternative: Option<Block>,
     },
+    /// Imports a package, optionally constrained to the versions that satisfy the given VersionConstraint (e.g., `import foo[1.2.0];` or `import foo["^1.2"];`).
     Import {
         package: Ident,
-        version: Option<Version>,
+        version: Option<VersionConstraint>,
+    },
+    /// Assigns a new value to an element of an array or map, e.g. `ident[index] := value;`.
+    IndexAssign {
+        ident: Ident,
+        index: Expr,
+        value: Expr,
     },
     LetAssign(Ident, Expr),
+    /// A statement together with the line in the source where it starts.
+    Located {
+        line: usize,
+        stmt: Box<Stmt>,
+    },
     On {
         location: Expr,
         block: Block,
@@ -142,6 +154,8 @@ pub enum BinOp {
     Ge,
     /// The `>` operator (greater than)
     Gt,
+    /// The `??` operator (the left operand, unless it is unit)
+    Coalesce,
 }
 
 impl BinOp {
@@ -150,13 +164,14 @@ impl BinOp {
     ///
     pub fn binding_power(&self) -> (u8, u8) {
         match &self {
-            BinOp::And | BinOp::Or => (1, 2),   // Conditional
-            BinOp::Eq | BinOp::Ne => (3, 4),    // Equality
-            BinOp::Lt | BinOp::Gt => (5, 6),    // Comparison
-            BinOp::Le | BinOp::Ge => (5, 6),    // Comparison
-            BinOp::Add | BinOp::Sub => (7, 8),  // Terms
-            BinOp::Mul | BinOp::Div => (9, 10), // Factors
-            BinOp::Dot => (13, 14),             // Nesting
+            BinOp::And | BinOp::Or => (1, 2),    // Conditional
+            BinOp::Eq | BinOp::Ne => (3, 4),     // Equality
+            BinOp::Lt | BinOp::Gt => (5, 6),     // Comparison
+            BinOp::Le | BinOp::Ge => (5, 6),     // Comparison
+            BinOp::Coalesce => (7, 8),           // Null-coalescing
+            BinOp::Add | BinOp::Sub => (9, 10),  // Terms
+            BinOp::Mul | BinOp::Div => (11, 12), // Factors
+            BinOp::Dot => (15, 16),              // Nesting
         }
     }
 }
@@ -180,9 +195,9 @@ impl UnOp {
     ///
     pub fn binding_power(&self) -> (u8, u8) {
         match &self {
-            UnOp::Not => (0, 11),
-            UnOp::Neg => (0, 11),
-            UnOp::Idx => (11, 0),
+            UnOp::Not => (0, 13),
+            UnOp::Neg => (0, 13),
+            UnOp::Idx => (13, 0),
             UnOp::Prio => (0, 0), // Handled seperatly by pratt parser.
         }
     }
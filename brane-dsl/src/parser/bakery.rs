@@ -38,7 +38,12 @@ pub fn parse_stmt<'a, E: ParseError<Tokens<'a>> + ContextError<Tokens<'a>>>(
         return Err(nom::Err::Error(nom::error_position!(input, ErrorKind::Tag)));
     }
 
-    branch::alt((import_stmt, assign_stmt, return_stmt, expr_stmt)).parse(input)
+    let line = input.tok[0].inner().location_line() as usize;
+    comb::map(
+        branch::alt((import_stmt, assign_stmt, return_stmt, expr_stmt)),
+        move |stmt| Stmt::Located { line, stmt: Box::new(stmt) },
+    )
+    .parse(input)
 }
 
 ///
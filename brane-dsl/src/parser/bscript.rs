@@ -5,8 +5,8 @@ use crate::tag_token;
 use nom::error::{ContextError, ErrorKind, ParseError, VerboseError};
 use nom::{branch, combinator as comb, multi, sequence as seq};
 use nom::{IResult, Parser};
-use semver::Version;
-use std::{collections::HashMap, num::NonZeroUsize};
+use specifications::version::{Version, VersionConstraint};
+use std::{collections::HashMap, num::NonZeroUsize, str::FromStr};
 
 ///
 ///
@@ -25,21 +25,26 @@ pub fn parse_stmt<'a, E: ParseError<Tokens<'a>> + ContextError<Tokens<'a>>>(
         return Err(nom::Err::Error(nom::error_position!(input, ErrorKind::Tag)));
     }
 
-    branch::alt((
-        for_stmt,
-        assign_stmt,
-        on_stmt,
-        block_stmt,
-        parallel_stmt,
-        declare_class_stmt,
-        declare_func_stmt,
-        expr_stmt,
-        if_stmt,
-        import_stmt,
-        let_assign_stmt,
-        return_stmt,
-        while_stmt,
-    ))
+    let line = input.tok[0].inner().location_line() as usize;
+    comb::map(
+        branch::alt((
+            for_stmt,
+            assign_stmt,
+            index_assign_stmt,
+            on_stmt,
+            block_stmt,
+            parallel_stmt,
+            declare_class_stmt,
+            declare_func_stmt,
+            expr_stmt,
+            if_stmt,
+            import_stmt,
+            let_assign_stmt,
+            return_stmt,
+            while_stmt,
+        )),
+        move |stmt| Stmt::Located { line, stmt: Box::new(stmt) },
+    )
     .parse(input)
 }
 
@@ -78,6 +83,29 @@ pub fn assign_stmt<'a, E: ParseError<Tokens<'a>> + ContextError<Tokens<'a>>>(
     .parse(input)
 }
 
+///
+///
+///
+pub fn index_assign_stmt<'a, E: ParseError<Tokens<'a>> + ContextError<Tokens<'a>>>(
+    input: Tokens<'a>
+) -> IResult<Tokens, Stmt, E> {
+    comb::map(
+        seq::terminated(
+            seq::separated_pair(
+                seq::pair(
+                    identifier::parse,
+                    seq::delimited(tag_token!(Token::LeftBracket), expression::parse, tag_token!(Token::RightBracket)),
+                ),
+                tag_token!(Token::Assign),
+                expression::parse,
+            ),
+            comb::cut(tag_token!(Token::Semicolon)),
+        ),
+        |((ident, index), value)| Stmt::IndexAssign { ident, index, value },
+    )
+    .parse(input)
+}
+
 ///
 ///
 ///
@@ -312,9 +340,20 @@ pub fn import_stmt<'a, E: ParseError<Tokens<'a>> + ContextError<Tokens<'a>>>(
                         identifier::parse,
                         comb::opt(seq::delimited(
                             tag_token!(Token::LeftBracket),
-                            comb::map(tag_token!(Token::SemVer), |x| {
-                                Version::parse(&x.tok[0].as_string()).unwrap()
-                            }),
+                            // Once there's a bracket, anything but a valid constraint is an error (instead of an import without one)
+                            comb::cut(nom::error::context(
+                                "version constraint (e.g., 1.2.0 or \"^1.2\")",
+                                branch::alt((
+                                    // A plain version pins the import to exactly that version
+                                    comb::map_opt(tag_token!(Token::SemVer), |x| {
+                                        Version::from_str(&x.tok[0].as_string()).ok().map(VersionConstraint::Exact)
+                                    }),
+                                    // A string may be any constraint (e.g., "^1.2" or ">=1.2, <2")
+                                    comb::map_opt(tag_token!(Token::String), |x| {
+                                        VersionConstraint::from_str(&x.tok[0].as_string()).ok()
+                                    }),
+                                )),
+                            )),
                             tag_token!(Token::RightBracket),
                         )),
                     ),
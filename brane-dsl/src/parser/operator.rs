@@ -27,6 +27,7 @@ pub fn binary_operator<'a, E: ParseError<Tokens<'a>> + ContextError<Tokens<'a>>>
 ) -> IResult<Tokens, BinOp, E> {
     branch::alt((
         comb::map(tag_token!(Token::And), |_| BinOp::And),
+        comb::map(tag_token!(Token::Coalesce), |_| BinOp::Coalesce),
         comb::map(tag_token!(Token::Equal), |_| BinOp::Eq),
         comb::map(tag_token!(Token::Greater), |_| BinOp::Gt),
         comb::map(tag_token!(Token::GreaterOrEqual), |_| BinOp::Ge),
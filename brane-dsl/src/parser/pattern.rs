@@ -27,20 +27,32 @@ pub fn resolve_patterns(
 
     let mut statements = vec![];
     for stmt in program {
-        let stmt = match stmt {
-            Stmt::Expr(Expr::Pattern(pattern)) => {
-                let call = pattern_to_call(pattern, &function_patterns)?;
-                Stmt::Expr(call)
-            }
-            stmt => stmt,
-        };
-
-        statements.push(stmt);
+        statements.push(resolve_stmt(stmt, &function_patterns)?);
     }
 
     Ok(statements)
 }
 
+///
+///
+///
+fn resolve_stmt(
+    stmt: Stmt,
+    patterns: &[FunctionPattern],
+) -> Result<Stmt> {
+    match stmt {
+        Stmt::Expr(Expr::Pattern(pattern)) => {
+            let call = pattern_to_call(pattern, patterns)?;
+            Ok(Stmt::Expr(call))
+        }
+        Stmt::Located { line, stmt } => Ok(Stmt::Located {
+            line,
+            stmt: Box::new(resolve_stmt(*stmt, patterns)?),
+        }),
+        stmt => Ok(stmt),
+    }
+}
+
 ///
 ///
 ///
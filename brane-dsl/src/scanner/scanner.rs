@@ -73,6 +73,7 @@ fn operator<'a, E: ParseError<Span<'a>> + ContextError<Span<'a>>>(input: Span<'a
         comb::map(bc::tag(">="), Token::GreaterOrEqual),
         comb::map(bc::tag("<="), Token::LessOrEqual),
         comb::map(bc::tag("!="), Token::NotEqual),
+        comb::map(bc::tag("??"), Token::Coalesce),
         // One character token
         comb::map(bc::tag("!"), Token::Not),
         comb::map(bc::tag("&"), Token::And),
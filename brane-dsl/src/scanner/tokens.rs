@@ -16,6 +16,9 @@ pub enum Token<'a> {
     /// `class`
     Class(Span<'a>),
 
+    /// `??`
+    Coalesce(Span<'a>),
+
     /// `continue`
     Continue(Span<'a>),
 
@@ -186,7 +189,7 @@ impl<'a> Token<'a> {
         use Token::*;
 
         match self {
-            And(span) | Break(span) | Class(span) | Continue(span) | Else(span) | For(span) | Function(span)
+            And(span) | Break(span) | Class(span) | Coalesce(span) | Continue(span) | Else(span) | For(span) | Function(span)
             | If(span) | Import(span) | Let(span) | On(span) | Or(span) | Return(span) | Unit(span) | While(span)
             | Dot(span) | Colon(span) | Comma(span) | LeftBrace(span) | LeftBracket(span) | LeftParen(span)
             | Parallel(span) | RightBrace(span) | RightBracket(span) | RightParen(span) | Semicolon(span)
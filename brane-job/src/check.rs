@@ -0,0 +1,344 @@
+/* CHECK.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 23:59:57
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements `brane-job check`, which tries to reach every location in
+ *   the infrastructure file the way `cmd_create` would (and checks that
+ *   the secrets they refer to exist), so that misconfigured locations show
+ *   up before the first job on them fails.
+**/
+
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::time::Duration;
+
+use bollard::Docker;
+use brane_cfg::infrastructure::{InfrastructureError, Location};
+use brane_cfg::{Infrastructure, Secrets};
+use k8s_openapi::api::core::v1::Namespace;
+use kube::api::{Api, ListParams};
+use serde::Serialize;
+
+use crate::cmd_create;
+use crate::errors::JobError;
+use crate::networks;
+use crate::schedulers::{SchedulerSpec, Xenon, XenonSchedulers};
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(location: &str, kind: &str, required: bool, error: Option<&str>) -> LocationCheck {
+        LocationCheck{ location: location.to_string(), kind: Some(kind.to_string()), required, error: error.map(String::from) }
+    }
+
+    /// Writes the given secrets file to a temporary directory, returning the directory (which removes the file when dropped) and the Secrets.
+    fn secrets(contents: &str) -> (tempfile::TempDir, Secrets) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secrets.yml");
+        std::fs::write(&path, contents).unwrap();
+        let secrets = Secrets::new(path.to_string_lossy().to_string()).unwrap();
+        (dir, secrets)
+    }
+
+    #[test]
+    fn test_missing_secrets() {
+        let (_dir, secrets) = secrets("docker-cert: PEM\n");
+
+        let location: Location = serde_yaml::from_str("kind: docker
+address: \"tcp://docker.example.com:2376\"
+network: brane
+registry: \"localhost:5000\"
+callback_to: \"http://brane-clb:50052\"
+tls:
+  ca: /etc/brane/docker/ca.pem
+  cert: s$docker-cert
+  key: s$docker-key
+").unwrap();
+        assert_eq!(missing_secrets(&location, &secrets), vec![ "docker-key" ]);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_locations_fail() {
+        let (_dir, secrets) = secrets("{}\n");
+        let location: Location = serde_yaml::from_str("kind: docker
+address: \"tcp://docker.example.com:2376\"
+network: brane
+registry: \"localhost:5000\"
+callback_to: \"http://brane-clb:50052\"
+").unwrap();
+        let result = check_location("remote", location, &secrets, "http://localhost:50051", &XenonSchedulers::new(Xenon), DEFAULT_CHECK_TIMEOUT).await;
+        assert!(matches!(result, Err(JobError::InfrastructureError{ err: InfrastructureError::MissingDockerTls{ .. } })), "Unexpected result: {:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_probes_time_out() {
+        // A "daemon" that accepts connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut connections = vec![];
+            while let Ok((connection, _)) = listener.accept().await { connections.push(connection); }
+        });
+
+        let (_dir, secrets) = secrets("{}\n");
+        let location: Location = serde_yaml::from_str(&format!("kind: docker
+address: \"tcp://127.0.0.1:{}\"
+network: brane
+registry: \"localhost:5000\"
+callback_to: \"http://brane-clb:50052\"
+", port)).unwrap();
+        let result = check_location("silent", location, &secrets, "http://localhost:50051", &XenonSchedulers::new(Xenon), Duration::from_millis(100)).await;
+        assert!(matches!(result, Err(JobError::CheckTimeout{ ref location_id, .. }) if location_id == "silent"), "Unexpected result: {:?}", result);
+    }
+
+    #[test]
+    fn test_report() {
+        let report = CheckReport{ locations: vec![
+            check("local", "local", true, None),
+            check("hpc", "slurm", false, Some("Xenon is down")),
+        ] };
+        assert!(report.is_healthy());
+        let text = format!("{}", report);
+        assert!(text.contains("local"), "Unexpected report:\n{}", text);
+        assert!(text.lines().any(|line| line.starts_with("hpc") && line.contains("FAIL (optional)")), "Unexpected report:\n{}", text);
+        assert!(text.contains("hpc: Xenon is down"), "Unexpected report:\n{}", text);
+
+        // Any required location that fails makes the check fail
+        let report = CheckReport{ locations: vec![ check("local", "local", true, None), check("kube", "kube", true, Some("unreachable")) ] };
+        assert!(!report.is_healthy());
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+        assert_eq!(json["locations"][1]["error"], "unreachable");
+        assert_eq!(json["locations"][1]["kind"], "kube");
+    }
+}
+
+
+
+
+
+/***** CONSTANTS *****/
+/// How long the probe of a single location may take by default.
+pub const DEFAULT_CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// The result of checking a single location.
+#[derive(Clone, Debug, Serialize)]
+pub struct LocationCheck {
+    /// The ID of the location
+    pub location : String,
+    /// The kind of the location, or None if we could not read it
+    pub kind     : Option<String>,
+    /// Whether the location failing fails the check as a whole
+    pub required : bool,
+    /// Why the location could not be reached, if it couldn't
+    pub error    : Option<String>,
+}
+
+/// What `brane-job check` found on every location.
+#[derive(Clone, Debug, Serialize)]
+pub struct CheckReport {
+    /// The result per location
+    pub locations : Vec<LocationCheck>,
+}
+
+impl CheckReport {
+    /// Returns whether every required location passed the check.
+    pub fn is_healthy(&self) -> bool {
+        self.locations.iter().all(|location| !location.required || location.error.is_none())
+    }
+}
+
+impl Display for CheckReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        let width = self.locations.iter().map(|location| location.location.len()).chain([ "LOCATION".len() ]).max().unwrap_or_default();
+        writeln!(f, "{:<width$}  {:<6}  RESULT", "LOCATION", "KIND", width = width)?;
+        for location in &self.locations {
+            let result = match (&location.error, location.required) {
+                (None, _)        => "PASS",
+                (Some(_), true)  => "FAIL",
+                (Some(_), false) => "FAIL (optional)",
+            };
+            writeln!(f, "{:<width$}  {:<6}  {}", location.location, location.kind.as_deref().unwrap_or("?"), result, width = width)?;
+        }
+
+        // List the reasons below the table, as they tend to be long
+        for location in &self.locations {
+            if let Some(err) = &location.error { writeln!(f, "\n{}: {}", location.location, err)?; }
+        }
+        Ok(())
+    }
+}
+
+
+
+/// Determines what `brane-job check` checks.
+#[derive(Clone, Debug)]
+pub struct CheckOptions {
+    /// Only check these locations; all of them if empty
+    pub locations : Vec<String>,
+    /// Locations that may fail without failing the check
+    pub optional  : Vec<String>,
+    /// How long the probe of a single location may take
+    pub timeout   : Duration,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        Self {
+            locations : vec![],
+            optional  : vec![],
+            timeout   : DEFAULT_CHECK_TIMEOUT,
+        }
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Checks the locations in the infrastructure file (see `check_location()`).
+/// 
+/// Failures of one location do not keep the others from being checked; they end up in the report instead.
+/// 
+/// **Arguments**
+///  * `infra`: The Infrastructure with the locations.
+///  * `secrets`: The Secrets to resolve the credentials of the locations with.
+///  * `xenon_endpoint`: The Xenon endpoint to reach Slurm and Vm locations with.
+///  * `options`: The CheckOptions that determine which locations are checked.
+/// 
+/// **Returns**  
+/// A CheckReport with the result of every location, or a JobError if we could not read the locations (or one of the given ones does not exist).
+pub async fn run(infra: &Infrastructure, secrets: &Secrets, xenon_endpoint: &str, options: &CheckOptions) -> Result<CheckReport, JobError> {
+    let mut location_ids = infra.get_locations().map_err(|err| JobError::InfrastructureError{ err })?;
+    location_ids.sort();
+    if !options.locations.is_empty() {
+        if let Some(unknown) = options.locations.iter().find(|location_id| !location_ids.contains(*location_id)) {
+            return Err(JobError::InfrastructureError{ err: InfrastructureError::UnknownLocation{ location: unknown.clone() } });
+        }
+        location_ids.retain(|location_id| options.locations.contains(location_id));
+    }
+
+    let xenon_schedulers = XenonSchedulers::new(Xenon);
+    let mut locations = Vec::with_capacity(location_ids.len());
+    for location_id in location_ids {
+        debug!("Checking location '{}'...", location_id);
+        let required = !options.optional.contains(&location_id);
+        let (kind, result) = match infra.get_location_metadata(&location_id) {
+            Ok(location) => (Some(kind(&location).to_string()), check_location(&location_id, location, secrets, xenon_endpoint, &xenon_schedulers, options.timeout).await),
+            Err(err)     => (None, Err(JobError::InfrastructureError{ err })),
+        };
+        locations.push(LocationCheck{ location: location_id, kind, required, error: result.err().map(|err| format!("{}", err)) });
+    }
+    xenon_schedulers.close_all().await;
+
+    Ok(CheckReport{ locations })
+}
+
+/// Checks that a single location can be reached, using the same clients that `cmd_create` creates jobs with:
+///  * Kube: creates the client from the location's config file and lists the namespaces of the cluster.
+///  * Local and Docker: connects to the Docker daemon, pings it and checks that the network exists (unless brane-job may create it).
+///  * Slurm and Vm: creates a Xenon scheduler and checks that it is open.
+/// 
+/// Before any of that, the location must be valid (see `Location::validate()`) and every secret that it refers to must be in the secrets file.
+/// 
+/// **Arguments**
+///  * `location_id`: The ID of the location.
+///  * `location`: The Location to check.
+///  * `secrets`: The Secrets to resolve the credentials of the location with.
+///  * `xenon_endpoint`: The Xenon endpoint to reach Slurm and Vm locations with.
+///  * `xenon_schedulers`: The cache to create Xenon schedulers in.
+///  * `timeout`: How long reaching the location may take.
+/// 
+/// **Returns**  
+/// Nothing if the location can be reached, or the JobError that a job on it would run into otherwise.
+pub async fn check_location(location_id: &str, location: Location, secrets: &Secrets, xenon_endpoint: &str, xenon_schedulers: &XenonSchedulers, timeout: Duration) -> Result<(), JobError> {
+    location.validate(location_id).map_err(|err| JobError::InfrastructureError{ err })?;
+    let missing = missing_secrets(&location, secrets);
+    if !missing.is_empty() { return Err(JobError::MissingSecrets{ location_id: location_id.to_string(), secrets: missing }); }
+
+    match tokio::time::timeout(timeout, probe(location_id, location, secrets, xenon_endpoint, xenon_schedulers)).await {
+        Ok(result) => result,
+        Err(_)     => Err(JobError::CheckTimeout{ location_id: location_id.to_string(), timeout }),
+    }
+}
+
+/// Returns the secrets that the given location refers to but that are not in the secrets file (or cannot be read from it).
+/// 
+/// **Arguments**
+///  * `location`: The Location to check the references of.
+///  * `secrets`: The Secrets to look them up in.
+pub fn missing_secrets(location: &Location, secrets: &Secrets) -> Vec<String> {
+    location.secret_references().into_iter().filter(|name| secrets.get(name.as_str()).is_err()).collect()
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Tries to reach a location (see `check_location()`), without any timeout.
+async fn probe(location_id: &str, location: Location, secrets: &Secrets, xenon_endpoint: &str, xenon_schedulers: &XenonSchedulers) -> Result<(), JobError> {
+    match location {
+        Location::Kube { credentials, .. } => {
+            let client = cmd_create::k8s_client(location_id, credentials.resolve_secrets(secrets)).await?;
+            let namespaces: Api<Namespace> = Api::all(client);
+            namespaces.list(&ListParams::default().limit(1)).await.map_err(|err| JobError::K8sListNamespacesError{ location_id: location_id.to_string(), err })?;
+            Ok(())
+        },
+        Location::Local { network, create_network, .. } => {
+            let docker = Docker::connect_with_local_defaults().map_err(|err| JobError::DockerConnectionFailed{ err })?;
+            check_docker(location_id, &docker, &network, create_network).await
+        },
+        Location::Docker { address, tls, network, create_network, .. } => {
+            let docker = cmd_create::remote_docker(location_id, &address, tls.map(|tls| tls.resolve_secrets(secrets)))?;
+            check_docker(location_id, &docker, &network, create_network).await
+        },
+        Location::Slurm { address, credentials, .. } => {
+            let spec = cmd_create::slurm_spec(location_id, address, credentials.resolve_secrets(secrets), xenon_endpoint.to_string())?;
+            check_xenon(location_id, xenon_schedulers, spec).await
+        },
+        Location::Vm { address, credentials, .. } => {
+            let spec = cmd_create::vm_spec(address, credentials.resolve_secrets(secrets), xenon_endpoint.to_string());
+            check_xenon(location_id, xenon_schedulers, spec).await
+        },
+    }
+}
+
+/// Returns the kind of the given location, as it is written in the infrastructure file.
+fn kind(location: &Location) -> &'static str {
+    match location {
+        Location::Kube { .. }   => "kube",
+        Location::Local { .. }  => "local",
+        Location::Docker { .. } => "docker",
+        Location::Slurm { .. }  => "slurm",
+        Location::Vm { .. }     => "vm",
+    }
+}
+
+/// Pings a Docker daemon and checks that the network of its location exists (unless we may create it).
+async fn check_docker(location_id: &str, docker: &Docker, network: &str, create_network: bool) -> Result<(), JobError> {
+    docker.ping().await.map_err(|err| JobError::DockerPingError{ location_id: location_id.to_string(), err })?;
+    if !create_network { networks::ensure_network(docker, network, false).await?; }
+    Ok(())
+}
+
+/// Creates the Xenon scheduler of a location and checks that it is open.
+async fn check_xenon(location_id: &str, xenon_schedulers: &XenonSchedulers, spec: SchedulerSpec) -> Result<(), JobError> {
+    let scheduler = xenon_schedulers.get_or_create(location_id, spec).await?;
+    if xenon_schedulers.is_stale(&scheduler).await { return Err(JobError::XenonSchedulerClosed{ location_id: location_id.to_string() }); }
+    Ok(())
+}
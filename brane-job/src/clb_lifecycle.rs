@@ -34,9 +34,14 @@ pub fn handle(callback: Callback) -> Result<Vec<(String, Event)>, JobError> {
         CallbackKind::CompleteFailed => EventKind::CompleteFailed,
         CallbackKind::Completed => EventKind::Completed,
         CallbackKind::DecodeFailed => EventKind::DecodeFailed,
+        CallbackKind::StoreFailed => EventKind::StoreFailed,
         CallbackKind::Stopped => EventKind::Stopped,
         CallbackKind::Failed => EventKind::Failed,
         CallbackKind::Finished => EventKind::Finished,
+        CallbackKind::Batch => {
+            warn!("Received nested callback batch; ignoring it");
+            return Ok(vec![]);
+        }
     };
 
     // Construct the new event
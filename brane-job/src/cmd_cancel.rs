@@ -1,6 +1,89 @@
-use crate::interface::{Command, Event};
-use anyhow::Result;
+use crate::errors::JobError;
+use crate::interface::{Command, CommandKind, Event};
+use bollard::container::KillContainerOptions;
+use bollard::Docker;
+use brane_cfg::infrastructure::Location;
+use brane_cfg::Infrastructure;
+
+/* TIM */
+/// Handles an incoming STOP command.
+/// 
+/// Only jobs running on local Docker locations can actually be stopped for now; for any other location kind, the command is logged and ignored.
+/// Note that the driver does not wait for a confirmation of the stop, so no events are returned.
+/// 
+/// **Arguments**
+///  * `key`: The key of the message that brought us the command.
+///  * `command`: The Command struct that contains the message payload, already parsed.
+///  * `infra`: The Infrastructure handle to the infra.yml.
+/// 
+/// **Returns**  
+/// A list of events to fire on success (always empty), or else a JobError listing what went wrong.
+pub async fn handle(
+    key: &str,
+    command: Command,
+    infra: Infrastructure,
+) -> Result<Vec<(String, Event)>, JobError> {
+    // Get the identifier and the location of the job to stop
+    debug!("Validating STOP command...");
+    let correlation_id = match command.identifier {
+        Some(correlation_id) => correlation_id,
+        None                 => { return Err(JobError::IllegalCommandError{ key: key.to_string(), kind: format!("{}", CommandKind::Stop), field: "identifier".to_string() }); }
+    };
+    let location_id = match command.location {
+        Some(location_id) => location_id,
+        None              => {
+            // The job hasn't been created yet as far as the driver knows, so there is nothing to stop
+            debug!("STOP command for job '{}' has no location; nothing to stop.", correlation_id);
+            return Ok(vec![]);
+        }
+    };
+
+    // Retreive location metadata
+    let location = match infra.get_location_metadata(&location_id) {
+        Ok(location) => location,
+        Err(reason)  => { return Err(JobError::InfrastructureError{ err: reason }); }
+    };
+
+    // Stop the job based on the location kind
+    match location {
+        Location::Local { .. } => {
+            debug!("Stopping local job '{}'...", correlation_id);
+            stop_local(&correlation_id).await?;
+            info!("Stopped job '{}' at location '{}'.", correlation_id, location_id);
+        },
+        _ => {
+            warn!("Stopping jobs is not supported for location '{}'; job '{}' will run to completion.", location_id, correlation_id);
+        },
+    }
 
-pub fn handle(_command: Command) -> Result<Vec<Event>> {
     Ok(vec![])
 }
+/*******/
+
+/* TIM */
+/// Stops the local Docker container that runs the given job.
+/// 
+/// **Arguments**
+///  * `name`: The name of the container (i.e., the correlation ID of the job).
+/// 
+/// **Returns**  
+/// Nothing on success (including when the container is already gone), or else a JobError describing what went wrong.
+async fn stop_local(name: &str) -> Result<(), JobError> {
+    let docker = match Docker::connect_with_local_defaults() {
+        Ok(docker)  => docker,
+        Err(reason) => { return Err(JobError::DockerConnectionFailed{ err: reason }); }
+    };
+
+    // If the container no longer exists, the job has already completed (and been cleaned up)
+    if docker.inspect_container(name, None).await.is_err() {
+        debug!("Container '{}' does not exist (anymore); nothing to stop.", name);
+        return Ok(());
+    }
+
+    // Otherwise, kill it
+    match docker.kill_container(name, None::<KillContainerOptions<String>>).await {
+        Ok(_)    => Ok(()),
+        Err(err) => Err(JobError::DockerKillContainerError{ name: name.to_string(), err }),
+    }
+}
+/*******/
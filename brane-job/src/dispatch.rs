@@ -0,0 +1,272 @@
+/* DISPATCH.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 11:14:27
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Runs the handlers of incoming messages concurrently, up to a maximum
+ *   number at a time, while messages with the same key (i.e., for the
+ *   same job) are still handled in the order they arrived. Also keeps
+ *   track of which offsets can be committed, such that a message is only
+ *   committed once it (and everything before it) has been handled.
+**/
+
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tokio::sync::{oneshot, Semaphore};
+use tokio::task::JoinHandle;
+
+
+/***** CONSTANTS *****/
+/// The default maximum number of messages that a worker handles at the same time.
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 8;
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    use tokio::time::{sleep, Instant};
+
+    use super::*;
+
+    /// Dispatches a handler that records when it started and stopped, and sleeps for the given time in between.
+    async fn dispatch_sleeper(dispatcher: &Dispatcher, key: &str, id: usize, millis: u64, log: &Arc<Mutex<Vec<(usize, Instant, Instant)>>>) -> JoinHandle<()> {
+        let log = log.clone();
+        dispatcher.dispatch(key, async move {
+            let start = Instant::now();
+            sleep(Duration::from_millis(millis)).await;
+            log.lock().unwrap().push((id, start, Instant::now()));
+        }).await
+    }
+
+    #[tokio::test]
+    async fn different_keys_overlap() {
+        let dispatcher = Dispatcher::new(DEFAULT_MAX_IN_FLIGHT);
+        let log = Arc::new(Mutex::new(vec![]));
+        let slow = dispatch_sleeper(&dispatcher, "job-1", 1, 200, &log).await;
+        let fast = dispatch_sleeper(&dispatcher, "job-2", 2, 10, &log).await;
+        fast.await.unwrap();
+        slow.await.unwrap();
+
+        // The second job did not have to wait for the first one
+        let log = log.lock().unwrap();
+        assert_eq!(log.iter().map(|(id, _, _)| *id).collect::<Vec<_>>(), vec![ 2, 1 ]);
+        assert!(log[0].1 < log[1].2);
+    }
+
+    #[tokio::test]
+    async fn same_key_serializes() {
+        let dispatcher = Dispatcher::new(DEFAULT_MAX_IN_FLIGHT);
+        let log = Arc::new(Mutex::new(vec![]));
+        let handles = vec![
+            dispatch_sleeper(&dispatcher, "job-1", 1, 100, &log).await,
+            dispatch_sleeper(&dispatcher, "job-1", 2, 10, &log).await,
+            dispatch_sleeper(&dispatcher, "job-1", 3, 50, &log).await,
+        ];
+        for handle in handles { handle.await.unwrap(); }
+
+        // In order, and never at the same time
+        let log = log.lock().unwrap();
+        assert_eq!(log.iter().map(|(id, _, _)| *id).collect::<Vec<_>>(), vec![ 1, 2, 3 ]);
+        assert!(log[0].2 <= log[1].1);
+        assert!(log[1].2 <= log[2].1);
+        assert!(dispatcher.chains.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn waiting_handlers_take_no_room() {
+        let dispatcher = Dispatcher::new(2);
+        let log = Arc::new(Mutex::new(vec![]));
+        let slow = dispatch_sleeper(&dispatcher, "job-1", 1, 200, &log).await;
+        let queued = dispatch_sleeper(&dispatcher, "job-1", 2, 10, &log).await;
+        let other = dispatch_sleeper(&dispatcher, "job-2", 3, 10, &log).await;
+        for handle in [ slow, queued, other ] { handle.await.unwrap(); }
+
+        // The handler waiting behind the slow one did not keep the other job from running
+        let log = log.lock().unwrap();
+        assert_eq!(log.iter().map(|(id, _, _)| *id).collect::<Vec<_>>(), vec![ 3, 1, 2 ]);
+        assert!(log[0].2 < log[1].2);
+    }
+
+    #[tokio::test]
+    async fn in_flight_is_bounded() {
+        let dispatcher = Dispatcher::new(2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let mut handles = vec![];
+        for i in 0..6 {
+            let (running, most) = (running.clone(), most.clone());
+            handles.push(dispatcher.dispatch(&format!("job-{}", i), async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                sleep(Duration::from_millis(20)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+            }).await);
+        }
+        for handle in handles { handle.await.unwrap(); }
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn offsets_are_committed_in_order() {
+        let mut tracker = OffsetTracker::default();
+        tracker.start("cmd", 0, 10);
+        tracker.start("cmd", 0, 11);
+        tracker.start("cmd", 0, 12);
+        tracker.start("clb", 0, 5);
+
+        // 11 is done, but 10 isn't, so nothing can be committed yet
+        assert_eq!(tracker.complete("cmd", 0, 11), None);
+        assert_eq!(tracker.complete("cmd", 0, 10), Some(12));
+        assert_eq!(tracker.complete("clb", 0, 5), Some(6));
+        assert_eq!(tracker.complete("cmd", 0, 12), Some(13));
+        assert_eq!(tracker.complete("cmd", 0, 12), None);
+    }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Runs message handlers as separate tasks, at most a fixed number at a time, and such that the handlers for messages with the same key run one after the other (in the order they were dispatched).
+#[derive(Debug)]
+pub struct Dispatcher {
+    /// Limits the number of handlers that are in flight.
+    permits : Arc<Semaphore>,
+    /// For every key with handlers in flight, the ID of the last dispatched handler and a receiver that is closed once it is done.
+    chains  : Arc<Mutex<HashMap<String, (u64, oneshot::Receiver<()>)>>>,
+    /// The ID of the next dispatched handler.
+    next_id : AtomicU64,
+}
+
+impl Dispatcher {
+    /// Constructor for the Dispatcher.
+    ///
+    /// **Arguments**
+    ///  * `max_in_flight`: The maximum number of handlers that may be in flight at the same time (at least 1).
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            permits : Arc::new(Semaphore::new(max_in_flight.max(1))),
+            chains  : Arc::new(Mutex::new(HashMap::new())),
+            next_id : AtomicU64::new(0),
+        }
+    }
+
+    /// Runs the given handler in a new task once there is room for it, and once all handlers dispatched earlier with the same key are done.
+    ///
+    /// Waits until there is room (which is what pushes back on the message stream), but not for the handler itself. Handlers that are still waiting for an earlier handler with the same key don't take up room, so they can't starve handlers for other keys.
+    ///
+    /// **Arguments**
+    ///  * `key`: The key of the message (e.g., the correlation ID of the job).
+    ///  * `handler`: The future that handles the message.
+    ///
+    /// **Returns**
+    /// The JoinHandle of the task that runs the handler.
+    pub async fn dispatch<F>(&self, key: &str, handler: F) -> JoinHandle<F::Output>
+    where
+        F: 'static + Future + Send,
+        F::Output: 'static + Send,
+    {
+        // Wait for room before we take the message off the stream, but don't hold on to it yet; the semaphore is never closed
+        drop(self.permits.acquire().await.expect("Dispatcher semaphore closed"));
+
+        // Queue behind the previous handler with this key (if any) before spawning, so the order is the dispatch order
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (done, done_rx) = oneshot::channel::<()>();
+        let previous = self.chains.lock().unwrap().insert(key.to_string(), (id, done_rx)).map(|(_, previous)| previous);
+
+        let permits = self.permits.clone();
+        let chains = self.chains.clone();
+        let key = key.to_string();
+        tokio::spawn(async move {
+            // The sender is dropped (not used) when the previous handler is done, which is all we need to know
+            if let Some(previous) = previous { let _ = previous.await; }
+            let permit = permits.acquire_owned().await.expect("Dispatcher semaphore closed");
+            let result = handler.await;
+
+            // Forget about the key if nobody queued behind us
+            {
+                let mut chains = chains.lock().unwrap();
+                if chains.get(&key).map(|(last, _)| *last == id).unwrap_or(false) { chains.remove(&key); }
+            }
+            drop(done);
+            drop(permit);
+            result
+        })
+    }
+}
+
+
+
+/// Keeps track of the messages that are being handled per topic and partition, to find the offsets that are safe to commit.
+///
+/// An offset is safe to commit if every message before it has been handled; committing it earlier would lose those messages if brane-job stopped before handling them.
+#[derive(Debug, Default)]
+pub struct OffsetTracker {
+    /// The offsets per topic and partition.
+    partitions : HashMap<(String, i32), PartitionOffsets>,
+}
+
+/// The offsets of a single partition, as kept by the OffsetTracker.
+#[derive(Debug, Default)]
+struct PartitionOffsets {
+    /// The offsets of messages that are still being handled.
+    in_flight : BTreeSet<i64>,
+    /// The highest offset that has been handled.
+    done      : Option<i64>,
+    /// The last offset we said to commit.
+    committed : Option<i64>,
+}
+
+impl OffsetTracker {
+    /// Notes that the message with the given offset is being handled.
+    ///
+    /// **Arguments**
+    ///  * `topic`: The topic of the message.
+    ///  * `partition`: The partition of the message.
+    ///  * `offset`: The offset of the message.
+    pub fn start(&mut self, topic: &str, partition: i32, offset: i64) {
+        let offsets = self.partitions.entry((topic.to_string(), partition)).or_default();
+        // We start consuming from the first message we see, so there's no point in committing that
+        offsets.committed.get_or_insert(offset);
+        offsets.in_flight.insert(offset);
+    }
+
+    /// Notes that the message with the given offset has been handled.
+    ///
+    /// **Arguments**
+    ///  * `topic`: The topic of the message.
+    ///  * `partition`: The partition of the message.
+    ///  * `offset`: The offset of the message.
+    ///
+    /// **Returns**
+    /// The offset to commit (i.e., of the next message to consume), or None if it's the same as before.
+    pub fn complete(&mut self, topic: &str, partition: i32, offset: i64) -> Option<i64> {
+        let offsets = self.partitions.entry((topic.to_string(), partition)).or_default();
+        offsets.in_flight.remove(&offset);
+        offsets.done = Some(offsets.done.map(|done| done.max(offset)).unwrap_or(offset));
+
+        let next = match offsets.in_flight.iter().next() {
+            Some(first) => *first,
+            None        => offsets.done? + 1,
+        };
+        if offsets.committed.map(|committed| next <= committed).unwrap_or(false) { return None; }
+        offsets.committed = Some(next);
+        Some(next)
+    }
+}
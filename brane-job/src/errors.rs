@@ -15,40 +15,44 @@
 use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FResult};
 use std::path::PathBuf;
+use std::time::Duration;
 
 use brane_cfg::infrastructure::{LocationCredentials, InfrastructureError};
+use brane_shr::kafka::KafkaAssignError;
 use prost::{EncodeError, DecodeError};
-use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::error::KafkaError;
+
+use crate::interface::SchemaVersion;
 
 
 /***** ERRORS *****/
 /// Lists the top-most errors in the brane-job service.
 #[derive(Debug)]
 pub enum JobError {
-    /// Could not create a Kafka client
-    KafkaClientError{ servers: String, err: KafkaError },
-    /// Could not get the Kafka client to try to add more topics
-    KafkaTopicsError{ topics: String, err: KafkaError },
-    /// Could not add the given topic (with a duplicate error already filtered out)
-    KafkaTopicError{ topic: String, err: RDKafkaErrorCode },
     /// Could not create a Kafka producer
     KafkaProducerError{ servers: String, err: KafkaError },
     /// Could not create a Kafka consumer
     KafkaConsumerError{ servers: String, id: String, err: KafkaError },
 
-    /// Could not get the Kafka commit offsets
-    KafkaGetOffsetError{ clb: String, cmd: String, err: KafkaError },
-    /// Could not update the Kafka commit offsets
-    KafkaSetOffsetError{ topic: String, kind: String, err: KafkaError },
-    /// Could not commit the update to the Kafka commit offsets
-    KafkaSetOffsetsError{ clb: String, cmd: String, err: KafkaError },
+    /// Could not assign the partitions of the callback and command topics to the consumer
+    KafkaAssignError{ err: KafkaAssignError },
+    /// Could not receive the next message from Kafka
+    KafkaReceiveError{ err: KafkaError },
 
     /// Could not encode an event for sending
     EventEncodeError{ key: String, err: EncodeError },
+    /// Could not send an event to Kafka
+    EventSendError{ key: String, err: KafkaError },
+    /// An event is larger than the maximum event size, and cannot be made smaller
+    EventTooLarge{ key: String, kind: String, size: usize, max: usize },
+    /// Could not write the payload of an event that is too large to the payload directory of its location
+    PayloadWriteError{ key: String, path: PathBuf, err: std::io::Error },
     /// Could not decode a message into a Callback struct
     CallbackDecodeError{ key: String, err: DecodeError },
     /// Could not decode a message into a Command struct
     CommandDecodeError{ key: String, err: DecodeError },
+    /// A command was encoded with a schema major version we cannot interpret
+    CommandSchemaMismatch{ key: String, version: SchemaVersion },
     /// Given integer is not a valid CallbackKind
     IllegalCallbackKind{ kind: i32 },
     /// Given integer is not a valid CommandKind
@@ -75,11 +79,33 @@ pub enum JobError {
     K8sNamespaceError{ location_id: String, namespace: String, err: serde_json::Error },
     /// Could not launch a Kubernetes job
     K8sCreateJobError{ job_id: String, location_id: String, err: kube::Error },
+    /// Could not inspect an existing Kubernetes job with the same name as the one we tried to create
+    K8sInspectJobError{ job_id: String, location_id: String, err: kube::Error },
+    /// Could not delete an existing Kubernetes job with the same name as the one we tried to create
+    K8sDeleteJobError{ job_id: String, location_id: String, err: kube::Error },
+    /// Could not remove a Kubernetes job (e.g., while cleaning up)
+    K8sRemoveJobError{ name: String, location_id: String, err: kube::Error },
+    /// Could not list the jobs with the given label in a namespace
+    K8sListJobsError{ label: String, namespace: String, location_id: String, err: kube::Error },
+    /// Could not create the Secret description for the registry credentials
+    K8sSecretDescriptionError{ name: String, location_id: String, err: serde_json::Error },
+    /// Could not create the image pull Secret in the namespace
+    K8sCreateSecretError{ name: String, namespace: String, location_id: String, err: kube::Error },
+    /// The namespace of a location does not exist, and we may not create it
+    K8sMissingNamespace{ namespace: String, location_id: String },
+    /// Could not create the missing namespace of a location
+    K8sCreateNamespaceError{ namespace: String, location_id: String, err: kube::Error },
+    /// Could not list the namespaces of a cluster (e.g., while checking that we can reach it)
+    K8sListNamespacesError{ location_id: String, err: kube::Error },
 
     /// The given image file could not be read
     ImageReadError{ path: PathBuf, err: tokio::io::Error },
     /// Could not connect to the local Docker instance
     DockerConnectionFailed{ err: bollard::errors::Error },
+    /// Could not connect to the remote Docker daemon at the given address
+    DockerRemoteConnectionFailed{ address: String, err: bollard::errors::Error },
+    /// Could not write the TLS material of a remote Docker daemon to a file
+    DockerTlsWriteError{ path: PathBuf, err: std::io::Error },
     /// Could not import the image at the given path
     DockerImportError{ path: PathBuf, err: bollard::errors::Error },
     /// Could not create the given image
@@ -96,8 +122,22 @@ pub enum JobError {
     DockerInspectContainerError{ name: String, err: bollard::errors::Error },
     /// Could not remove the given container
     DockerRemoveContainerError{ name: String, err: bollard::errors::Error },
+    /// Could not list the containers with the given label
+    DockerListContainersError{ label: String, err: bollard::errors::Error },
+    /// Could not kill the given container
+    DockerKillContainerError{ name: String, err: bollard::errors::Error },
     /// Could not remove the given image
     DockerRemoveImageError{ name: String, id: String, err: bollard::errors::Error },
+    /// Could not check if the given network exists
+    DockerNetworkInspectError{ network: String, err: bollard::errors::Error },
+    /// The given network does not exist, and we may not create it
+    DockerNetworkMissing{ network: String },
+    /// Could not create the given network
+    DockerNetworkCreateError{ network: String, err: bollard::errors::Error },
+    /// Could not remove the given network
+    DockerNetworkRemoveError{ network: String, err: bollard::errors::Error },
+    /// The Docker daemon of a location did not answer a ping
+    DockerPingError{ location_id: String, err: bollard::errors::Error },
 
     /// A Docker container had no runningstate once it was finished
     DockerContainerNoState{ name: String },
@@ -111,6 +151,10 @@ pub enum JobError {
 
     /// Could not check if the given xenon scheduler is still open for writing
     XenonIsOpenError{ location_id: String, err: anyhow::Error },
+    /// Tried to recreate a Xenon scheduler that was never created
+    XenonUnknownScheduler{ location_id: String },
+    /// Illegal credential type for a Xenon scheduler
+    XenonIllegalCredentials{ location_id: String, adaptor: String, cred_type: String },
     /// Could not decode a certificate as Base64
     XenonCertBase64Error{ location_id: String, err: base64::DecodeError },
     /// Could not create a local filesystem on the Xenon endpoint
@@ -123,6 +167,23 @@ pub enum JobError {
     XenonUnknownRuntime{ runtime: String, location_id: String },
     /// Could not submit a Xenon job
     XenonSubmitError{ job_id: String, adaptor: String, location_id: String, err: anyhow::Error },
+    /// Could not list the job outputs to clean up on a Xenon location
+    XenonOutputListError{ dir: String, location_id: String, err: anyhow::Error },
+    /// Could not remove a job output on a Xenon location
+    XenonOutputRemoveError{ path: String, location_id: String, err: anyhow::Error },
+    /// Could not create the directory for the job outputs on a Xenon location
+    XenonOutputDirError{ dir: String, location_id: String, err: anyhow::Error },
+    /// A freshly created Xenon scheduler is not open
+    XenonSchedulerClosed{ location_id: String },
+
+    /// The location refers to secrets that are not in the secrets file
+    MissingSecrets{ location_id: String, secrets: Vec<String> },
+    /// The location could not be reached in time by `brane-job check`
+    CheckTimeout{ location_id: String, timeout: Duration },
+    /// The command tries to set an environment variable that Brane reserves for itself
+    ReservedEnvironmentVariable{ name: String },
+    /// The command asks for a job array on a location that doesn't (say it does) support them
+    ArraysNotSupported{ location_id: String },
 
     /// Could not properly get information from the infrastructure file
     InfrastructureError{ err: InfrastructureError },
@@ -150,41 +211,84 @@ impl JobError {
         }
         res
     }
+
+    /// Returns whether this error may well go away by itself, such that it makes sense to try again (e.g., a timeout while pulling an image, a Docker daemon that briefly refuses connections or a Kubernetes API that returns a 5xx).
+    /// 
+    /// **Returns**  
+    /// True if the error is transient, or false if trying again will just fail the same way.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            JobError::DockerConnectionFailed{ .. }       |
+            JobError::DockerRemoteConnectionFailed{ .. } => true,
+            JobError::DockerImportError{ err, .. }          |
+            JobError::DockerCreateImageError{ err, .. }     |
+            JobError::DockerCreateContainerError{ err, .. } |
+            JobError::DockerStartError{ err, .. }           |
+            JobError::DockerNetworkInspectError{ err, .. }  |
+            JobError::DockerNetworkCreateError{ err, .. }   => is_transient_docker_error(err),
+
+            JobError::K8sClientError{ err, .. }          |
+            JobError::K8sCreateJobError{ err, .. }       |
+            JobError::K8sInspectJobError{ err, .. }      |
+            JobError::K8sDeleteJobError{ err, .. }       |
+            JobError::K8sCreateSecretError{ err, .. }    |
+            JobError::K8sCreateNamespaceError{ err, .. } => is_transient_kube_error(err),
+
+            JobError::XenonIsOpenError{ err, .. }     |
+            JobError::XenonFilesystemError{ err, .. } |
+            JobError::XenonFileWriteError{ err, .. }  |
+            JobError::XenonSchedulerError{ err, .. }  |
+            JobError::XenonSubmitError{ err, .. }     => is_transient_message(&err.to_string()),
+
+            _ => false,
+        }
+    }
 }
 
 impl Display for JobError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
         match self {
-            JobError::KafkaClientError{ servers, err }       => write!(f, "Could not create Kafka client with bootstrap servers '{}': {}", servers, err),
-            JobError::KafkaTopicsError{ topics, err }        => write!(f, "Could not create new Kafka topics '{}': {}", topics, err),
-            JobError::KafkaTopicError{ topic, err }          => write!(f, "Coult not create Kafka topic '{}': {}", topic, err),
             JobError::KafkaProducerError{ servers, err }     => write!(f, "Could not create Kafka producer with bootstrap servers '{}': {}", servers, err),
             JobError::KafkaConsumerError{ servers, id, err } => write!(f, "Could not create Kafka consumer for ID '{}' with bootstrap servers '{}': {}", id, servers, err),
 
-            JobError::KafkaGetOffsetError{ clb, cmd, err }    => write!(f, "Could not get offsets for topics '{}' (callback) and '{}' (command): {}", clb, cmd, err),
-            JobError::KafkaSetOffsetError{ topic, kind, err } => write!(f, "Could not set offsets for topic '{}' ({}): {}", topic, kind, err),
-            JobError::KafkaSetOffsetsError{ clb, cmd, err }   => write!(f, "Could not commit offsets for topics '{}' (callback) and '{}' (command): {}", clb, cmd, err),
+            JobError::KafkaAssignError{ err }                 => write!(f, "{}", err),
+            JobError::KafkaReceiveError{ err }                => write!(f, "Could not receive message from Kafka: {}", err),
 
             JobError::EventEncodeError{ key, err }    => write!(f, "Could not encode event message (key: {}) for sending: {}", key, err),
+            JobError::EventSendError{ key, err }      => write!(f, "Could not send event message (key: {}): {}", key, err),
+            JobError::EventTooLarge{ key, kind, size, max } => write!(f, "{} event message (key: {}) is {} bytes, which is more than the maximum event size of {} bytes", kind, key, size, max),
+            JobError::PayloadWriteError{ key, path, err }  => write!(f, "Could not write payload of event message (key: {}) to '{}': {}", key, path.display(), err),
             JobError::CallbackDecodeError{ key, err } => write!(f, "Could not decode message (key: {}) as a callback message: {}", key, err),
             JobError::CommandDecodeError{ key, err }  => write!(f, "Could not decode message (key: {}) as a command message: {}", key, err),
+            JobError::CommandSchemaMismatch{ key, version } => write!(f, "Command message (key: {}) has schema version {}, but this brane-job speaks version {}; dropping message ({})", key, version, SchemaVersion::current(), version.migration_advice()),
             JobError::IllegalCallbackKind{ kind }     => write!(f, "Unknown callback kind '{}'", kind),
             JobError::IllegalCommandKind{ kind }      => write!(f, "Unknown command kind '{}'", kind),
 
             JobError::IllegalCommandError{ key, kind, field } => write!(f, "Incoming {} command message (key: {}) has field '{}' unset", kind, key, field),
 
-            JobError::K8sIllegalCredentials{ location_id, cred_type }    => write!(f, "Cannot use {} credentials for Kubernetes site '{}': expected {}", cred_type, location_id, LocationCredentials::Config{ file: String::new() }.cred_type()),
-            JobError::K8sBase64Error{ location_id, err }                 => write!(f, "Cannot decode Kubernetes config file for site '{}' as Base64: {}", location_id, err),
-            JobError::K8sUTF8Error{ location_id, err }                   => write!(f, "Cannot decode Kubernetes config file for site '{}' as UTF-8: {}", location_id, err),
-            JobError::K8sYAMLError{ location_id, err }                   => write!(f, "Cannot parse Kubernetes config file for site '{}' as YAML: {}", location_id, err),
-            JobError::K8sConfigError{ location_id, err }                 => write!(f, "Cannot parse Kubernetes config file for site '{}': {}", location_id, err),
-            JobError::K8sClientError{ location_id, err }                 => write!(f, "Cannot create client from the Kubernetes config file of site '{}': {}", location_id, err),
-            JobError::K8sJobDescriptionError{ job_id, location_id, err } => write!(f, "Creating job description for job '{}' on site '{}' failed: {}", job_id, location_id, err),
-            JobError::K8sNamespaceError{ location_id, namespace, err }   => write!(f, "Creating namespace '{}' on site '{}' failed: {}", namespace, location_id, err),
-            JobError::K8sCreateJobError{ job_id, location_id, err }      => write!(f, "Could not create job '{}' on site '{}': {}", job_id, location_id, err),
+            JobError::K8sIllegalCredentials{ location_id, cred_type }           => write!(f, "Cannot use {} credentials for Kubernetes site '{}': expected {}", cred_type, location_id, LocationCredentials::Config{ file: String::new() }.cred_type()),
+            JobError::K8sBase64Error{ location_id, err }                        => write!(f, "Cannot decode Kubernetes config file for site '{}' as Base64: {}", location_id, err),
+            JobError::K8sUTF8Error{ location_id, err }                          => write!(f, "Cannot decode Kubernetes config file for site '{}' as UTF-8: {}", location_id, err),
+            JobError::K8sYAMLError{ location_id, err }                          => write!(f, "Cannot parse Kubernetes config file for site '{}' as YAML: {}", location_id, err),
+            JobError::K8sConfigError{ location_id, err }                        => write!(f, "Cannot parse Kubernetes config file for site '{}': {}", location_id, err),
+            JobError::K8sClientError{ location_id, err }                        => write!(f, "Cannot create client from the Kubernetes config file of site '{}': {}", location_id, err),
+            JobError::K8sJobDescriptionError{ job_id, location_id, err }        => write!(f, "Creating job description for job '{}' on site '{}' failed: {}", job_id, location_id, err),
+            JobError::K8sNamespaceError{ location_id, namespace, err }          => write!(f, "Creating namespace '{}' on site '{}' failed: {}", namespace, location_id, err),
+            JobError::K8sCreateJobError{ job_id, location_id, err }             => write!(f, "Could not create job '{}' on site '{}': {}", job_id, location_id, err),
+            JobError::K8sInspectJobError{ job_id, location_id, err }            => write!(f, "Could not inspect existing job '{}' on site '{}': {}", job_id, location_id, err),
+            JobError::K8sDeleteJobError{ job_id, location_id, err }             => write!(f, "Could not delete existing job '{}' on site '{}' to replace it: {}", job_id, location_id, err),
+            JobError::K8sRemoveJobError{ name, location_id, err }               => write!(f, "Could not remove job '{}' from site '{}': {}", name, location_id, err),
+            JobError::K8sListJobsError{ label, namespace, location_id, err }    => write!(f, "Could not list jobs with label '{}' in namespace '{}' on site '{}': {}", label, namespace, location_id, err),
+            JobError::K8sSecretDescriptionError{ name, location_id, err }       => write!(f, "Creating description of image pull secret '{}' for site '{}' failed: {}", name, location_id, err),
+            JobError::K8sCreateSecretError{ name, namespace, location_id, err } => write!(f, "Could not create image pull secret '{}' in namespace '{}' on site '{}': {}", name, namespace, location_id, err),
+            JobError::K8sMissingNamespace{ namespace, location_id }             => write!(f, "Namespace '{}' does not exist on site '{}'; create it first, or set 'create_namespace: true' for the site in the infrastructure file to let brane-job create it", namespace, location_id),
+            JobError::K8sCreateNamespaceError{ namespace, location_id, err }    => write!(f, "Could not create namespace '{}' on site '{}': {}", namespace, location_id, err),
+            JobError::K8sListNamespacesError{ location_id, err }                => write!(f, "Could not list namespaces on site '{}': {}", location_id, err),
 
             JobError::ImageReadError{ path, err }                    => write!(f, "Cannot read image '{}' for import: {}", path.display(), err),
             JobError::DockerConnectionFailed{ err }                  => write!(f, "Could not connect to local Docker instance: {}", err),
+            JobError::DockerRemoteConnectionFailed{ address, err }   => write!(f, "Could not connect to Docker daemon at '{}': {}", address, err),
+            JobError::DockerTlsWriteError{ path, err }               => write!(f, "Could not write Docker TLS material to '{}': {}", path.display(), err),
             JobError::DockerImportError{ path, err }                 => write!(f, "Cannot import Docker image '{}': {}", path.display(), err),
             JobError::DockerCreateImageError{ image, err }           => write!(f, "Cannot create Docker image '{}': {}", image, err),
             JobError::DockerCreateContainerError{ name, image, err } => write!(f, "Could not create Docker container '{}' from image '{}': {}", name, image, err),
@@ -193,7 +297,14 @@ impl Display for JobError {
             JobError::DockerLogsError{ name, image, err }            => write!(f, "Could not retrieve logs from Docker container '{}' (from image '{}'): {}", name, image, err),
             JobError::DockerInspectContainerError{ name, err }       => write!(f, "Could not inspect Docker container '{}': {}", name, err),
             JobError::DockerRemoveContainerError{ name, err }        => write!(f, "Could not remove Docker container '{}': {}", name, err),
+            JobError::DockerListContainersError{ label, err }        => write!(f, "Could not list Docker containers with label '{}': {}", label, err),
+            JobError::DockerKillContainerError{ name, err }          => write!(f, "Could not kill Docker container '{}': {}", name, err),
             JobError::DockerRemoveImageError{ name, id, err }        => write!(f, "Could not remove Docker image '{}' (id: {}): {}", name, id, err),
+            JobError::DockerNetworkInspectError{ network, err }      => write!(f, "Could not check if Docker network '{}' exists: {}{}", network, err, network_hint(network, err)),
+            JobError::DockerNetworkMissing{ network }                => write!(f, "Docker network '{}' does not exist; create it with '{}', or set 'create_network: true' for the location in infra.yml", network, network_command(network)),
+            JobError::DockerNetworkCreateError{ network, err }       => write!(f, "Could not create Docker network '{}': {}{}", network, err, network_hint(network, err)),
+            JobError::DockerNetworkRemoveError{ network, err }       => write!(f, "Could not remove Docker network '{}': {}", network, err),
+            JobError::DockerPingError{ location_id, err }            => write!(f, "Docker daemon of site '{}' did not answer a ping: {}", location_id, err),
 
             JobError::DockerContainerNoState{ name }    => write!(f, "Docker container '{}' has no state after running", name),
             JobError::DockerContainerNoExitCode{ name } => write!(f, "Docker container '{}' has no exit code after running", name),
@@ -202,12 +313,23 @@ impl Display for JobError {
             JobError::SlurmIllegalCredentials{ location_id, cred_type } => write!(f, "Cannot use {} credentials for Slurm site '{}': expected {} or {}", cred_type, location_id, LocationCredentials::SshCertificate{ username: String::new(), certificate: String::new(), passphrase: None }.cred_type(), LocationCredentials::SshPassword{ username: String::new(), password: String::new() }.cred_type()),
 
             JobError::XenonIsOpenError{ location_id, err }                        => write!(f, "Cannot check if the Xenon scheduler for site '{}' is open: {}", location_id, err),
+            JobError::XenonUnknownScheduler{ location_id }                        => write!(f, "There is no Xenon scheduler for site '{}' to recreate", location_id),
+            JobError::XenonIllegalCredentials{ location_id, adaptor, cred_type }  => write!(f, "Cannot use {} credentials for a Xenon scheduler with {} adaptor for site '{}'", cred_type, adaptor, location_id),
             JobError::XenonCertBase64Error{ location_id, err }                    => write!(f, "Could not decode the certificate for site '{}' as Base64: {}", location_id, err),
             JobError::XenonFilesystemError{ endpoint, location_id, err }          => write!(f, "Could not create a local filesystem on Xenon endpoint '{}' for site '{}': {}", endpoint, location_id, err),
             JobError::XenonFileWriteError{ filename, endpoint, location_id, err } => write!(f, "Could not write local file '{}' on Xenon endpoint '{}' for site '{}': {}", filename, endpoint, location_id, err),
             JobError::XenonSchedulerError{ adaptor, endpoint, location_id, err }  => write!(f, "Could not create a Xenon scheduler with {} adaptor on endpoint '{}' for site '{}': {}", adaptor, endpoint, location_id, err),
             JobError::XenonUnknownRuntime{ runtime, location_id }                 => write!(f, "Unknown runtime '{}' for site '{}'; expected 'docker' or 'singularity'", runtime, location_id),
             JobError::XenonSubmitError{ job_id, adaptor, location_id, err }       => write!(f, "Could not submit job '{}' on a Xenon scheduler with {} adaptor on site '{}': {}", job_id, adaptor, location_id, err),
+            JobError::XenonOutputListError{ dir, location_id, err }               => write!(f, "Could not list job outputs in directory '{}' on site '{}': {}", dir, location_id, err),
+            JobError::XenonOutputRemoveError{ path, location_id, err }            => write!(f, "Could not remove job output '{}' on site '{}': {}", path, location_id, err),
+            JobError::XenonOutputDirError{ dir, location_id, err }                => write!(f, "Could not create job output directory '{}' on site '{}': {}", dir, location_id, err),
+            JobError::XenonSchedulerClosed{ location_id }                         => write!(f, "Xenon scheduler for site '{}' is not open right after creating it", location_id),
+
+            JobError::MissingSecrets{ location_id, secrets } => write!(f, "Site '{}' refers to secret(s) that are not in the secrets file: {}", location_id, secrets.iter().map(|secret| format!("'{}'", secret)).collect::<Vec<String>>().join(", ")),
+            JobError::CheckTimeout{ location_id, timeout }  => write!(f, "Could not reach site '{}' within {} second(s)", location_id, timeout.as_secs_f64()),
+            JobError::ReservedEnvironmentVariable{ name }    => write!(f, "Command tries to set environment variable '{}' for the package, but variables starting with '{}' are reserved", name, specifications::package::RESERVED_ENVIRONMENT_PREFIX),
+            JobError::ArraysNotSupported{ location_id }      => write!(f, "Site '{}' does not support job arrays (set 'supports_arrays' in the infrastructure file if it does)", location_id),
 
             JobError::InfrastructureError{ err } => write!(f, "Could not read infrastructure data: {}", err),
         }
@@ -215,3 +337,71 @@ impl Display for JobError {
 }
 
 impl Error for JobError {}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Returns whether the given error message hints at a problem that may go away by itself (a timeout or a refused connection).
+fn is_transient_message(message: &str) -> bool {
+    let message = message.to_lowercase();
+    ["timeout", "timed out", "connection refused", "connection reset", "temporarily unavailable"].iter().any(|hint| message.contains(hint))
+}
+
+/// Returns whether the given Docker error may go away by itself: server errors, timeouts and failing connections to the daemon.
+fn is_transient_docker_error(err: &bollard::errors::Error) -> bool {
+    match err {
+        bollard::errors::Error::DockerResponseServerError{ status_code, .. } if *status_code >= 500 => true,
+        bollard::errors::Error::RequestTimeoutError         => true,
+        bollard::errors::Error::IOError{ .. }               => true,
+        bollard::errors::Error::HyperResponseError{ .. }    => true,
+        err                                                 => is_transient_message(&err.to_string()),
+    }
+}
+
+/// Returns whether the given Kubernetes error may go away by itself: server errors and rate limiting of the API, or failing connections to it.
+fn is_transient_kube_error(err: &kube::Error) -> bool {
+    match err {
+        kube::Error::Api(response) => response.code >= 500 || response.code == 429,
+        err                        => is_transient_message(&err.to_string()),
+    }
+}
+
+/// Returns whether the given Docker error means that a container with the same name already exists.
+pub(crate) fn is_docker_conflict(err: &bollard::errors::Error) -> bool {
+    match err {
+        bollard::errors::Error::DockerResponseServerError{ status_code, .. } if *status_code == 409 => true,
+        err                                                                                          => err.to_string().to_lowercase().contains("already in use"),
+    }
+}
+
+/// Returns whether the given Kubernetes error means that an object with the same name already exists.
+pub(crate) fn is_kube_conflict(err: &kube::Error) -> bool {
+    match err {
+        kube::Error::Api(response) => response.code == 409 || response.reason == "AlreadyExists",
+        _                          => false,
+    }
+}
+
+/// Returns whether the given Kubernetes error means that the namespace we tried to create something in does not exist.
+pub(crate) fn is_kube_missing_namespace(err: &kube::Error) -> bool {
+    match err {
+        kube::Error::Api(response) => response.code == 404 && response.message.starts_with("namespaces"),
+        _                          => false,
+    }
+}
+
+/// Returns the command with which to create the given network manually, the same way we would.
+fn network_command(network: &str) -> String {
+    format!("docker network create --driver {} --label {}=true {}", crate::networks::NETWORK_DRIVER, crate::networks::NETWORK_LABEL, network)
+}
+
+/// Returns a hint on what to do if we were not allowed to manage the given network (e.g., with rootless Docker), or an empty string if the error is about something else.
+fn network_hint(network: &str, err: &bollard::errors::Error) -> String {
+    if format!("{}", err).to_lowercase().contains("permission denied") {
+        format!(" (brane-job may not manage Docker networks; create it manually with '{}')", network_command(network))
+    } else {
+        String::new()
+    }
+}
@@ -0,0 +1,544 @@
+/* GC.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 23:58:04
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements `brane-job gc`, which removes what failed or abandoned
+ *   jobs leave behind on the locations: stopped containers on Docker
+ *   locations, finished Kubernetes Jobs and the stdout/stderr files of
+ *   jobs on Xenon locations. Only resources with a Brane label (or, for
+ *   files, with the name of a job output) are ever touched.
+ *
+ *   The functions that remove a single resource are exposed, so that
+ *   other handlers (e.g., STOP) may use them too.
+**/
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bollard::container::{ListContainersOptions, RemoveContainerOptions};
+use bollard::Docker;
+use brane_cfg::infrastructure::Location;
+use brane_cfg::{Infrastructure, Secrets};
+use k8s_openapi::api::batch::v1::Job;
+use kube::api::{Api, ListParams};
+use kube::Client as KubeClient;
+use serde::Serialize;
+
+use crate::cmd_create::{self, K8sApi, KubeApi};
+use crate::errors::JobError;
+use crate::naming;
+use crate::schedulers::{self, SchedulerSpec, Xenon, XenonSchedulers};
+
+
+/***** CONSTANTS *****/
+/// The units that an age may be given in (see `parse_age()`), with their length in seconds.
+const AGE_UNITS: &[(char, u64)] = &[ ('s', 1), ('m', 60), ('h', 3600), ('d', 24 * 3600) ];
+/// The Docker states of containers that no longer (or never did) run.
+const STOPPED_STATES: &[&str] = &[ "created", "exited", "dead" ];
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a candidate of the given kind, created the given number of hours before `now()`.
+    fn candidate(kind: ResourceKind, name: &str, labels: HashMap<String, String>, hours: u64, running: bool) -> Candidate {
+        Candidate{ kind, name: name.to_string(), labels, created: now() - Duration::from_secs(hours * 3600), running }
+    }
+
+    fn now() -> SystemTime { UNIX_EPOCH + Duration::from_secs(1_000_000) }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("24h"), Ok(Duration::from_secs(24 * 3600)));
+        assert_eq!(parse_age("90m"), Ok(Duration::from_secs(90 * 60)));
+        assert_eq!(parse_age("7d"), Ok(Duration::from_secs(7 * 24 * 3600)));
+        assert_eq!(parse_age(" 30s "), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_age("3600"), Ok(Duration::from_secs(3600)));
+
+        for age in [ "", "h", "-1h", "1.5h", "24x", "24 h" ] {
+            assert!(parse_age(age).is_err(), "'{}' was parsed as an age", age);
+        }
+
+        // Ages that don't fit are refused instead of overflowing
+        assert_eq!(parse_age(&format!("{}s", u64::MAX)), Ok(Duration::from_secs(u64::MAX)));
+        assert_eq!(parse_age(&format!("{}d", u64::MAX / 86400 + 1)), Err(format!("Age '{}d' is too large", u64::MAX / 86400 + 1)));
+        assert!(parse_age(&format!("{}m", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_garbage_is_old_stopped_and_ours() {
+        let ours = naming::job_labels("abc123", "app");
+        let candidates = vec![
+            candidate(ResourceKind::Container, "old", ours.clone(), 25, false),
+            candidate(ResourceKind::Container, "new", ours.clone(), 1, false),
+            candidate(ResourceKind::Container, "running", ours.clone(), 48, true),
+            candidate(ResourceKind::Container, "unlabelled", HashMap::new(), 48, false),
+            candidate(ResourceKind::KubeJob, "old-job", ours, 48, false),
+            candidate(ResourceKind::JobOutput, "/outputs/stdout-abc123.txt", HashMap::new(), 48, false),
+            candidate(ResourceKind::JobOutput, "/outputs/results.csv", HashMap::new(), 48, false),
+        ];
+
+        let garbage = garbage(candidates, now(), Duration::from_secs(24 * 3600));
+        let names: Vec<&str> = garbage.iter().map(|resource| resource.name.as_str()).collect();
+        assert_eq!(names, vec![ "old", "old-job", "/outputs/stdout-abc123.txt" ]);
+        assert_eq!(garbage[0], Resource{ kind: ResourceKind::Container, name: String::from("old"), age_secs: 25 * 3600 });
+
+        // Resources from the future (clock skew) are never old enough
+        let future = Candidate{ created: now() + Duration::from_secs(60), ..candidate(ResourceKind::Container, "future", naming::job_labels("abc123", "app"), 0, false) };
+        assert!(garbage(vec![ future ], now(), Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn test_report() {
+        let resource = |name: &str| Resource{ kind: ResourceKind::Container, name: name.to_string(), age_secs: 2 * 24 * 3600 };
+        let mut local = LocationReport::new("local");
+        local.record(resource("abc123-0-0123abcd"), Ok(()));
+        local.record(resource("def456-0-4567cdef"), Err(JobError::XenonUnknownScheduler{ location_id: String::from("local") }));
+        let mut slurm = LocationReport::new("slurm");
+        slurm.skipped = Some(String::from("it has no output_dir"));
+        let mut kube = LocationReport::new("kube");
+        kube.error = Some(String::from("cluster unreachable"));
+        let report = GcReport{ dry_run: false, older_than_secs: 3600, locations: vec![ local, slurm, kube ] };
+
+        assert!(!report.is_complete());
+        let text = format!("{}", report);
+        assert!(text.contains("local: removed 1 resource(s), failed to remove 1"), "Unexpected report:\n{}", text);
+        assert!(text.contains("  container abc123-0-0123abcd (48h old)"));
+        assert!(text.contains("slurm: skipped, as it has no output_dir"));
+        assert!(text.contains("kube: failed: cluster unreachable"));
+
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+        assert_eq!(json["locations"][0]["removed"][0]["kind"], "container");
+        assert_eq!(json["locations"][0]["failed"][0]["name"], "def456-0-4567cdef");
+        assert_eq!(json["locations"][1]["skipped"], "it has no output_dir");
+
+        // A dry run says what it would do instead
+        let mut local = LocationReport::new("local");
+        local.record(resource("abc123-0-0123abcd"), Ok(()));
+        let report = GcReport{ dry_run: true, older_than_secs: 3600, locations: vec![ local ] };
+        assert!(report.is_complete());
+        assert!(format!("{}", report).contains("local: would remove 1 resource(s)"));
+    }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// The kinds of resources that jobs leave behind.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceKind {
+    /// A Docker container (on a Local or Docker location)
+    Container,
+    /// A Kubernetes Job (on a Kube location)
+    KubeJob,
+    /// A stdout/stderr file of a job (on a Slurm or Vm location)
+    JobOutput,
+}
+
+impl Display for ResourceKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            ResourceKind::Container => write!(f, "container"),
+            ResourceKind::KubeJob   => write!(f, "job"),
+            ResourceKind::JobOutput => write!(f, "job output"),
+        }
+    }
+}
+
+
+
+/// A resource on a location as it is listed, before we decide whether it is garbage.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    /// What kind of resource this is
+    pub kind    : ResourceKind,
+    /// The name of the resource (or the path, for job outputs)
+    pub name    : String,
+    /// The labels of the resource (always empty for job outputs)
+    pub labels  : HashMap<String, String>,
+    /// When the resource was created (or last modified, for job outputs)
+    pub created : SystemTime,
+    /// Whether the resource is still in use (i.e., the container or job is running)
+    pub running : bool,
+}
+
+/// A resource that is (or would be, in a dry run) removed.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Resource {
+    /// What kind of resource this is
+    pub kind     : ResourceKind,
+    /// The name of the resource (or the path, for job outputs)
+    pub name     : String,
+    /// How old the resource is, in seconds
+    pub age_secs : u64,
+}
+
+/// A resource that we failed to remove.
+#[derive(Clone, Debug, Serialize)]
+pub struct FailedResource {
+    /// The resource itself
+    #[serde(flatten)]
+    pub resource : Resource,
+    /// Why we could not remove it
+    pub error    : String,
+}
+
+
+
+/// What happened on a single location.
+#[derive(Clone, Debug, Serialize)]
+pub struct LocationReport {
+    /// The ID of the location
+    pub location : String,
+    /// The resources that were removed (or would be, in a dry run)
+    pub removed  : Vec<Resource>,
+    /// The resources that could not be removed
+    pub failed   : Vec<FailedResource>,
+    /// Why the location was not cleaned up, if there is nothing to clean up on it
+    pub skipped  : Option<String>,
+    /// Why the location could not be cleaned up, if we could not list its resources at all
+    pub error    : Option<String>,
+}
+
+impl LocationReport {
+    /// Constructor for the LocationReport, which has nothing to report yet.
+    /// 
+    /// **Arguments**
+    ///  * `location`: The ID of the location.
+    pub fn new(location: impl Into<String>) -> Self {
+        Self {
+            location : location.into(),
+            removed  : vec![],
+            failed   : vec![],
+            skipped  : None,
+            error    : None,
+        }
+    }
+
+    /// Records the result of removing the given resource.
+    /// 
+    /// **Arguments**
+    ///  * `resource`: The Resource that we tried to remove.
+    ///  * `result`: Whether that worked.
+    pub fn record(&mut self, resource: Resource, result: Result<(), JobError>) {
+        match result {
+            Ok(_)    => { self.removed.push(resource); },
+            Err(err) => { self.failed.push(FailedResource{ resource, error: format!("{}", err) }); },
+        }
+    }
+}
+
+/// What `brane-job gc` did (or would do) on every location.
+#[derive(Clone, Debug, Serialize)]
+pub struct GcReport {
+    /// Whether nothing was actually removed
+    pub dry_run         : bool,
+    /// The age from which resources were removed, in seconds
+    pub older_than_secs : u64,
+    /// What happened per location
+    pub locations       : Vec<LocationReport>,
+}
+
+impl GcReport {
+    /// Returns whether every location was cleaned up without errors.
+    pub fn is_complete(&self) -> bool {
+        self.locations.iter().all(|location| location.error.is_none() && location.failed.is_empty())
+    }
+}
+
+impl Display for GcReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        for location in &self.locations {
+            if let Some(reason) = &location.skipped { writeln!(f, "{}: skipped, as {}", location.location, reason)?; continue; }
+            if let Some(err) = &location.error { writeln!(f, "{}: failed: {}", location.location, err)?; continue; }
+
+            write!(f, "{}: {} {} resource(s)", location.location, if self.dry_run { "would remove" } else { "removed" }, location.removed.len())?;
+            if !location.failed.is_empty() { write!(f, ", failed to remove {}", location.failed.len())?; }
+            writeln!(f)?;
+            for resource in &location.removed {
+                writeln!(f, "  {} {} ({}h old)", resource.kind, resource.name, resource.age_secs / 3600)?;
+            }
+            for failed in &location.failed {
+                writeln!(f, "  {} {} ({}h old): {}", failed.resource.kind, failed.resource.name, failed.resource.age_secs / 3600, failed.error)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+
+/// Determines what `brane-job gc` removes.
+#[derive(Clone, Copy, Debug)]
+pub struct GcOptions {
+    /// Only resources older than this are removed
+    pub older_than : Duration,
+    /// If true, nothing is removed, only reported
+    pub dry_run    : bool,
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Parses an age such as '90m', '24h' or '7d' (or a plain number of seconds).
+/// 
+/// **Arguments**
+///  * `age`: The age to parse, with an optional unit of 's', 'm', 'h' or 'd'.
+/// 
+/// **Returns**  
+/// The age as a Duration, or a message explaining why it isn't one.
+pub fn parse_age(age: &str) -> Result<Duration, String> {
+    let age = age.trim();
+    let (number, unit) = match age.chars().last() {
+        Some(last) if last.is_ascii_alphabetic() => (&age[..age.len() - 1], last),
+        _                                        => (age, 's'),
+    };
+    let seconds = match AGE_UNITS.iter().find(|(u, _)| *u == unit) {
+        Some((_, seconds)) => *seconds,
+        None               => { return Err(format!("Unknown unit '{}' in age '{}' (expected one of 's', 'm', 'h' or 'd')", unit, age)); }
+    };
+    let number = match number.parse::<u64>() {
+        Ok(number) => number,
+        Err(_)     => { return Err(format!("Illegal age '{}' (expected a whole number with an optional unit, e.g., '24h')", age)); }
+    };
+    match number.checked_mul(seconds) {
+        Some(seconds) => Ok(Duration::from_secs(seconds)),
+        None          => Err(format!("Age '{}' is too large", age)),
+    }
+}
+
+/// Selects the candidates that may be removed: those that are ours, no longer in use and older than the given age.
+/// 
+/// Containers and Kubernetes Jobs are ours if they have a Brane label (see `naming::has_brane_label()`), job outputs if their file name is that of a job output.
+/// 
+/// **Arguments**
+///  * `candidates`: The resources found on a location.
+///  * `now`: The current time.
+///  * `older_than`: How old resources must be to be removed.
+/// 
+/// **Returns**  
+/// The resources to remove.
+pub fn garbage(candidates: impl IntoIterator<Item = Candidate>, now: SystemTime, older_than: Duration) -> Vec<Resource> {
+    candidates
+        .into_iter()
+        .filter(|candidate| match candidate.kind {
+            ResourceKind::JobOutput => Path::new(&candidate.name).file_name().and_then(|name| name.to_str()).map(schedulers::is_job_output).unwrap_or(false),
+            _                       => naming::has_brane_label(&candidate.labels),
+        })
+        .filter(|candidate| !candidate.running)
+        .filter_map(|candidate| {
+            let age = now.duration_since(candidate.created).ok()?;
+            if age <= older_than { return None; }
+            Some(Resource{ kind: candidate.kind, name: candidate.name, age_secs: age.as_secs() })
+        })
+        .collect()
+}
+
+
+
+/// Removes the leftovers of jobs on every location in the infrastructure file.
+/// 
+/// Errors on one location do not keep the others from being cleaned up; they end up in the report instead.
+/// 
+/// **Arguments**
+///  * `infra`: The Infrastructure with the locations.
+///  * `secrets`: The Secrets to resolve the credentials of the locations with.
+///  * `xenon_endpoint`: The Xenon endpoint to reach Slurm and Vm locations with.
+///  * `options`: The GcOptions that determine what is removed.
+/// 
+/// **Returns**  
+/// A GcReport with what happened on every location, or a JobError if we could not read the locations.
+pub async fn run(infra: &Infrastructure, secrets: &Secrets, xenon_endpoint: &str, options: GcOptions) -> Result<GcReport, JobError> {
+    let location_ids = infra.get_locations().map_err(|err| JobError::InfrastructureError{ err })?;
+
+    let xenon_schedulers = XenonSchedulers::new(Xenon);
+    let mut locations = Vec::with_capacity(location_ids.len());
+    for location_id in location_ids {
+        let mut report = LocationReport::new(&location_id);
+        match infra.get_location_metadata(&location_id) {
+            Ok(location) => {
+                if let Err(err) = clean_location(&location_id, location, secrets, xenon_endpoint, &xenon_schedulers, options, &mut report).await {
+                    report.error = Some(format!("{}", err));
+                }
+            },
+            Err(err) => { report.error = Some(format!("{}", JobError::InfrastructureError{ err })); },
+        }
+        locations.push(report);
+    }
+    xenon_schedulers.close_all().await;
+
+    Ok(GcReport{ dry_run: options.dry_run, older_than_secs: options.older_than.as_secs(), locations })
+}
+
+
+
+/// Removes (forcefully) the Docker container with the given name.
+/// 
+/// **Arguments**
+///  * `docker`: The Docker daemon that runs the container.
+///  * `name`: The name (or ID) of the container.
+/// 
+/// **Returns**  
+/// Nothing on success (including when the container is already gone), or a JobError::DockerRemoveContainerError otherwise.
+pub async fn remove_container(docker: &Docker, name: &str) -> Result<(), JobError> {
+    let options = RemoveContainerOptions{ force: true, ..Default::default() };
+    match docker.remove_container(name, Some(options)).await {
+        Ok(_)                                                                              => Ok(()),
+        Err(bollard::errors::Error::DockerResponseServerError{ status_code: 404, .. }) => Ok(()),
+        Err(err)                                                                           => Err(JobError::DockerRemoveContainerError{ name: name.to_string(), err }),
+    }
+}
+
+/// Removes the Kubernetes Job with the given name (and its pods).
+/// 
+/// **Arguments**
+///  * `api`: The K8sApi of the namespace that the Job is in.
+///  * `location_id`: The ID of the location. Only used for debugging purposes.
+///  * `name`: The name of the Job.
+/// 
+/// **Returns**  
+/// Nothing on success (including when the Job is already gone), or a JobError::K8sRemoveJobError otherwise.
+pub(crate) async fn remove_k8s_job<A: K8sApi + Sync>(api: &A, location_id: &str, name: &str) -> Result<(), JobError> {
+    api.delete_job(name).await.map_err(|err| JobError::K8sRemoveJobError{ name: name.to_string(), location_id: location_id.to_string(), err })
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Removes the leftovers of jobs on a single location, recording what happened in the given report.
+/// 
+/// **Returns**  
+/// Nothing if we could list the resources of the location (even if some could not be removed), or a JobError otherwise.
+async fn clean_location(
+    location_id: &str,
+    location: Location,
+    secrets: &Secrets,
+    xenon_endpoint: &str,
+    xenon_schedulers: &XenonSchedulers,
+    options: GcOptions,
+    report: &mut LocationReport,
+) -> Result<(), JobError> {
+    let outputs = location.get_job_outputs();
+    let adaptor = if matches!(location, Location::Slurm { .. }) { "slurm" } else { "ssh" };
+    match location {
+        Location::Local { .. } => {
+            let docker = Docker::connect_with_local_defaults().map_err(|err| JobError::DockerConnectionFailed{ err })?;
+            clean_docker(&docker, options, report).await
+        },
+        Location::Docker { address, tls, .. } => {
+            let docker = cmd_create::remote_docker(location_id, &address, tls.map(|tls| tls.resolve_secrets(secrets)))?;
+            clean_docker(&docker, options, report).await
+        },
+        Location::Kube { namespace, credentials, .. } => {
+            let client = cmd_create::k8s_client(location_id, credentials.resolve_secrets(secrets)).await?;
+            clean_k8s(client, location_id, &namespace, options, report).await
+        },
+        Location::Slurm { address, credentials, .. } | Location::Vm { address, credentials, .. } => {
+            // Only files in a dedicated output directory can be told apart from the rest, and some locations keep them on purpose
+            let dir = match (outputs.dir, outputs.keep) {
+                (_, true)        => { report.skipped = Some(String::from("it keeps job outputs ('keep_job_output')")); return Ok(()); },
+                (None, _)        => { report.skipped = Some(String::from("it has no 'output_dir'")); return Ok(()); },
+                (Some(dir), _)   => dir,
+            };
+
+            let spec = SchedulerSpec {
+                adaptor     : String::from(adaptor),
+                location    : address,
+                credentials : credentials.resolve_secrets(secrets),
+                endpoint    : xenon_endpoint.to_string(),
+            };
+            xenon_schedulers.get_or_create(location_id, spec).await?;
+            clean_outputs(xenon_schedulers, location_id, &dir, options, report).await
+        },
+    }
+}
+
+/// Removes the stopped containers of jobs on a Docker daemon.
+async fn clean_docker(docker: &Docker, options: GcOptions, report: &mut LocationReport) -> Result<(), JobError> {
+    // Only list containers with our labels in the first place
+    let filters: HashMap<&str, Vec<&str>> = hashmap!{ "label" => vec![ naming::CORRELATION_LABEL ] };
+    let containers = docker.list_containers(Some(ListContainersOptions{ all: true, filters, ..Default::default() })).await
+        .map_err(|err| JobError::DockerListContainersError{ label: naming::CORRELATION_LABEL.to_string(), err })?;
+
+    let candidates = containers.into_iter().filter_map(|container| {
+        // Docker prefixes the names of containers with a slash
+        let name = container.names.and_then(|names| names.into_iter().next()).map(|name| name.trim_start_matches('/').to_string()).or(container.id)?;
+        Some(Candidate {
+            kind    : ResourceKind::Container,
+            name,
+            labels  : container.labels.unwrap_or_default(),
+            created : UNIX_EPOCH + Duration::from_secs(u64::try_from(container.created?).ok()?),
+            running : !container.state.map(|state| STOPPED_STATES.contains(&state.as_str())).unwrap_or(false),
+        })
+    });
+
+    for resource in garbage(candidates, SystemTime::now(), options.older_than) {
+        let result = if options.dry_run { Ok(()) } else { remove_container(docker, &resource.name).await };
+        report.record(resource, result);
+    }
+    Ok(())
+}
+
+/// Removes the finished Kubernetes Jobs of jobs in a namespace.
+async fn clean_k8s(client: KubeClient, location_id: &str, namespace: &str, options: GcOptions, report: &mut LocationReport) -> Result<(), JobError> {
+    let jobs: Api<Job> = Api::namespaced(client.clone(), namespace);
+    let jobs = jobs.list(&ListParams::default().labels(naming::CORRELATION_LABEL)).await
+        .map_err(|err| JobError::K8sListJobsError{ label: naming::CORRELATION_LABEL.to_string(), namespace: namespace.to_string(), location_id: location_id.to_string(), err })?;
+
+    let candidates = jobs.items.into_iter().filter_map(|job| {
+        Some(Candidate {
+            kind    : ResourceKind::KubeJob,
+            name    : job.metadata.name?,
+            labels  : job.metadata.labels.unwrap_or_default().into_iter().collect(),
+            created : SystemTime::from(job.metadata.creation_timestamp?.0),
+            running : job.status.and_then(|status| status.active).unwrap_or(0) > 0,
+        })
+    });
+
+    let api = KubeApi::new(client, namespace);
+    for resource in garbage(candidates, SystemTime::now(), options.older_than) {
+        let result = if options.dry_run { Ok(()) } else { remove_k8s_job(&api, location_id, &resource.name).await };
+        report.record(resource, result);
+    }
+    Ok(())
+}
+
+/// Removes the old job outputs in the output directory of a Xenon location, whose scheduler must be in the given cache.
+async fn clean_outputs(xenon_schedulers: &XenonSchedulers, location_id: &str, dir: &str, options: GcOptions, report: &mut LocationReport) -> Result<(), JobError> {
+    let files = xenon_schedulers.list_outputs(location_id, dir).await?;
+    let candidates = files.into_iter().map(|(path, modified)| Candidate {
+        kind    : ResourceKind::JobOutput,
+        name    : path,
+        labels  : HashMap::new(),
+        created : modified,
+        running : false,
+    });
+
+    for resource in garbage(candidates, SystemTime::now(), options.older_than) {
+        let result = if options.dry_run { Ok(()) } else { xenon_schedulers.remove_output(location_id, &resource.name).await };
+        report.record(resource, result);
+    }
+    Ok(())
+}
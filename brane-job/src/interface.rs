@@ -1,8 +1,99 @@
 use prost::{Enumeration, Message};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt;
+use std::path::Path;
 use time::OffsetDateTime;
 
+
+/// The major version of the Command and Event schemas. Receivers reject messages with a different major version.
+pub const SCHEMA_VERSION_MAJOR: u16 = 1;
+/// The minor version of the Command and Event schemas. Only bumped for additive (i.e., backwards compatible) changes.
+pub const SCHEMA_VERSION_MINOR: u16 = 5;
+/// The schema version as it is put on the wire: the major version in the upper 16 bits, the minor version in the lower 16.
+pub const SCHEMA_VERSION: u32 = ((SCHEMA_VERSION_MAJOR as u32) << 16) | SCHEMA_VERSION_MINOR as u32;
+
+/// The environment variable that tells the branelet which subdirectory of the data directory belongs to the session of its job.
+pub const SESSION_DATA_ENV: &str = "BRANE_SESSION_DATA";
+/// The environment variable that tells the branelet where to store the artifacts of its job, instead of the mounted DFS.
+pub const ARTIFACT_DIR_ENV: &str = "BRANE_ARTIFACT_DIR";
+/// The environment variable that tells the branelet how large (in bytes) a single artifact may be.
+pub const ARTIFACT_MAX_SIZE_ENV: &str = "BRANE_ARTIFACT_MAX_SIZE";
+/// The environment variable that tells the branelet how large (in bytes) all artifacts of its job may be together.
+pub const ARTIFACT_MAX_TOTAL_ENV: &str = "BRANE_ARTIFACT_MAX_TOTAL";
+/// The environment variable that tells the branelet that its job is an element of a job array of the given size, so that it picks its own arguments from the array in the command.
+pub const ARRAY_SIZE_ENV: &str = "BRANE_ARRAY_SIZE";
+/// The environment variable that tells the branelet which element of a job array it runs, on locations that don't set an index of their own.
+pub const ARRAY_INDEX_ENV: &str = "BRANE_ARRAY_INDEX";
+
+
+
+/// Returns the name of the subdirectory of the data directory that the jobs of the given session use, which is unique for every session UUID.
+/// 
+/// **Arguments**
+///  * `session`: The UUID of the session (i.e., the application ID of its jobs).
+/// 
+/// **Returns**  
+/// The name of the subdirectory, which is a single, safe path component.
+pub fn session_data_dir(session: &str) -> String {
+    let name: String = session.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-').collect();
+    format!("session-{}", name)
+}
+
+
+
+/// A decoded schema version, as found in the `version` field of a Command or Event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SchemaVersion {
+    /// The major version; messages with a different one cannot be interpreted.
+    pub major : u16,
+    /// The minor version; newer minor versions only add fields, which we ignore.
+    pub minor : u16,
+}
+
+impl SchemaVersion {
+    /// Splits a version as it is put on the wire.
+    /// 
+    /// **Arguments**
+    ///  * `version`: The raw version number from a message.
+    #[inline]
+    pub fn from_u32(version: u32) -> Self {
+        Self {
+            major : (version >> 16) as u16,
+            minor : (version & 0xFFFF) as u16,
+        }
+    }
+
+    /// Returns the version of the schema we speak ourselves.
+    #[inline]
+    pub fn current() -> Self {
+        Self::from_u32(SCHEMA_VERSION)
+    }
+
+    /// Returns whether messages with this version can be interpreted by us (i.e., whether the major versions match).
+    #[inline]
+    pub fn is_compatible(&self) -> bool {
+        self.major == SCHEMA_VERSION_MAJOR
+    }
+
+    /// Returns a short explanation of how to reconcile a sender with this version and ourselves, to put in error messages.
+    pub fn migration_advice(&self) -> &'static str {
+        if self.major == 0 && self.minor == 0 {
+            "the sender predates schema versioning; upgrade brane-drv, brane-job, brane-plr and brane-net together to the same release"
+        } else if self.major < SCHEMA_VERSION_MAJOR {
+            "the sender is older than this service; upgrade it to a release with the same schema major version, or drain the topic before upgrading"
+        } else {
+            "the sender is newer than this service; upgrade this service to a release with the same schema major version"
+        }
+    }
+}
+
+impl fmt::Display for SchemaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
 // #[derive(Clone, PartialEq, Message)]
 // pub struct Callback {
 //     #[prost(tag = "1", enumeration = "CallbackKind")]
@@ -83,6 +174,21 @@ pub struct Command {
     pub command: Vec<String>,
     #[prost(tag = "7", repeated, message)]
     pub mounts: Vec<Mount>,
+    /// Resource limits for the job's container, which take precedence over those of the location.
+    #[prost(tag = "8", optional, message)]
+    pub resources: Option<Resources>,
+    /// Environment variables to set for the job, in addition to the ones the location sets itself (which take precedence): the session's data directory (SESSION_DATA_ENV) and the package environment that the script configured for the call. Other variables may not start with `RESERVED_ENVIRONMENT_PREFIX`.
+    #[prost(tag = "9", map = "string, string")]
+    pub environment: HashMap<String, String>,
+    /// The number of elements of the job array to create, or 0 for a single job. For an array, the arguments in `command` are a JSON array with the arguments of every element.
+    #[prost(tag = "11", uint32)]
+    pub array_size: u32,
+    /// How many elements of the job array may run at the same time, or 0 to leave it to the location.
+    #[prost(tag = "12", uint32)]
+    pub array_parallelism: u32,
+    /// The schema version this command was encoded with (see SCHEMA_VERSION).
+    #[prost(tag = "15", uint32)]
+    pub version: u32,
 }
 
 impl Command {
@@ -103,8 +209,45 @@ impl Command {
             image: image.map(S::into),
             command: command.iter().map(S::clone).map(S::into).collect(),
             mounts: mounts.unwrap_or_default(),
+            resources: None,
+            environment: HashMap::new(),
+            array_size: 0,
+            array_parallelism: 0,
+            version: SCHEMA_VERSION,
         }
     }
+
+    /// Sets an environment variable for the job's container.
+    #[inline]
+    pub fn with_environment<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.environment.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets the resource limits of the job's container, overriding those of the location.
+    #[inline]
+    pub fn with_resources(mut self, resources: Resources) -> Self {
+        self.resources = Some(resources);
+        self
+    }
+
+    /// Turns the command into one that creates a job array with the given number of elements, of which at most `parallelism` run at the same time (0 for no limit).
+    #[inline]
+    pub fn with_array(mut self, size: u32, parallelism: u32) -> Self {
+        self.array_size = size;
+        self.array_parallelism = parallelism;
+        self
+    }
+
+    /// Returns whether the command creates a job array instead of a single job.
+    #[inline]
+    pub fn is_array(&self) -> bool { self.array_size > 0 }
+
+    /// Returns the schema version this command was encoded with.
+    #[inline]
+    pub fn schema_version(&self) -> SchemaVersion {
+        SchemaVersion::from_u32(self.version)
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration)]
@@ -141,6 +284,9 @@ pub struct Event {
     pub payload: Vec<u8>,
     #[prost(tag = "8", int64)]
     pub timestamp: i64,
+    /// The schema version this event was encoded with (see SCHEMA_VERSION).
+    #[prost(tag = "15", uint32)]
+    pub version: u32,
 }
 
 impl Event {
@@ -169,8 +315,15 @@ impl Event {
             order,
             payload: payload.unwrap_or_default(),
             timestamp,
+            version: SCHEMA_VERSION,
         }
     }
+
+    /// Returns the schema version this event was encoded with.
+    #[inline]
+    pub fn schema_version(&self) -> SchemaVersion {
+        SchemaVersion::from_u32(self.version)
+    }
 }
 
 /* TIM */
@@ -188,6 +341,10 @@ pub enum EventKind {
     Created      =  1,
     /// We could not create the container to run the call
     CreateFailed = -1,
+    /// We could not create the container to run the call yet, but will try again (the payload is a CreateRetryInfo)
+    CreateRetrying = 14,
+    /// The location is still pulling the image of the job before it can create the container (the payload is a PullProgress)
+    Pulling        = 15,
 
     // Initialization events
     /// The container is ready with setting up the branelet executable (first opportunity for branelet to send events)
@@ -212,6 +369,8 @@ pub enum EventKind {
     // Finish events
     /// brane-let could not decode the output from the package call
     DecodeFailed =  -8,
+    /// brane-let could not store the files that the package call declares as outputs as artifacts
+    StoreFailed  = -11,
     /// The container has exited with a non-zero status code
     Failed       = -10,
     /// The container was interrupted by the Job node
@@ -224,6 +383,10 @@ pub enum EventKind {
     Connected    = 11,
     /// Something has disconnected (?)
     Disconnected = 12,
+
+    // Output events
+    /// A chunk of the output of a running job; the category says whether it's stdout or stderr
+    Log = 13,
 }
 
 impl fmt::Display for EventKind {
@@ -248,6 +411,236 @@ pub struct FailureResult {
 
 
 
+/// The key under which the CallStats are nested in the payload of a Finished event.
+pub const CALL_STATS_KEY: &str = "stats";
+
+/// Defines the resources that a package call used, which the branelet sends along with the result of the call.
+/// 
+/// It is nested under `CALL_STATS_KEY` in the JSON-encoded result Value of a Finished event. Decoding the Value ignores it, so drivers that don't know about it keep working.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CallStats {
+    /// The wall-clock time of the call, in seconds
+    pub wall_time: f64,
+    /// The CPU time that the package spent in user mode, in seconds (code packages only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_time: Option<f64>,
+    /// The CPU time that the package spent in kernel mode, in seconds (code packages only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_time: Option<f64>,
+    /// The peak resident set size of the package, in kilobytes (code packages only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rss: Option<u64>,
+    /// The size of the response, in bytes (web API packages only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_size: Option<u64>,
+}
+
+impl CallStats {
+    /// Reads the CallStats from the payload of a Finished event.
+    /// 
+    /// **Arguments**
+    ///  * `payload`: The JSON-encoded result of the call.
+    /// 
+    /// **Returns**  
+    /// The CallStats, or None if the payload has none (e.g., because it was sent by an older branelet) or they are invalid.
+    pub fn from_payload(payload: &str) -> Option<Self> {
+        let mut payload: serde_json::Value = serde_json::from_str(payload).ok()?;
+        serde_json::from_value(payload.get_mut(CALL_STATS_KEY)?.take()).ok()
+    }
+}
+
+impl fmt::Display for CallStats {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        write!(f, "{:.3}s wall time", self.wall_time)?;
+        if let Some(user_time) = self.user_time { write!(f, ", {:.3}s user CPU", user_time)?; }
+        if let Some(system_time) = self.system_time { write!(f, ", {:.3}s system CPU", system_time)?; }
+        if let Some(max_rss) = self.max_rss { write!(f, ", {:.1} MiB peak memory", max_rss as f64 / 1024.0)?; }
+        if let Some(response_size) = self.response_size { write!(f, ", {} byte response", response_size)?; }
+        Ok(())
+    }
+}
+
+
+
+/// The key under which the Artifacts are nested in the payload of a Finished event.
+pub const ARTIFACTS_KEY: &str = "artifacts";
+
+/// Defines a file that a package call produced (as declared by the `outputs` of its function), which the branelet copied to shared storage.
+/// 
+/// The list of them is nested under `ARTIFACTS_KEY` in the JSON-encoded result Value of a Finished event, next to the CallStats.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Artifact {
+    /// The path of the file relative to the directory the package ran in
+    pub name: String,
+    /// The size of the file, in bytes
+    pub size: u64,
+    /// Where the file was stored, as the jobs on the same location see it
+    pub path: String,
+    /// The SHA-256 checksum of the file, hex-encoded
+    pub sha256: String,
+}
+
+impl Artifact {
+    /// Reads the Artifacts from the payload of a Finished event.
+    /// 
+    /// **Arguments**
+    ///  * `payload`: The JSON-encoded result of the call.
+    /// 
+    /// **Returns**  
+    /// The Artifacts, or None if the payload has none (e.g., because the function doesn't declare any outputs) or they are invalid.
+    pub fn from_payload(payload: &str) -> Option<Vec<Self>> {
+        let mut payload: serde_json::Value = serde_json::from_str(payload).ok()?;
+        serde_json::from_value(payload.get_mut(ARTIFACTS_KEY)?.take()).ok()
+    }
+}
+
+
+
+/// The key of the only field in the payload of an event that refers to its actual payload (see PayloadRef).
+pub const PAYLOAD_REF_KEY: &str = "$payload_ref";
+
+/// Refers to the payload of an event that was too large to send over Kafka, which brane-job wrote to a file in the `payload_dir` of the location instead. That directory has to be shared with the driver, which reads the payload from there.
+/// 
+/// It replaces the payload of the event as a JSON object with PAYLOAD_REF_KEY as its only field.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PayloadRef {
+    /// The file with the payload
+    pub path: String,
+    /// The size of the payload, in bytes
+    pub size: u64,
+}
+
+impl PayloadRef {
+    /// Encodes the PayloadRef as the payload of an event.
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut envelope = HashMap::new();
+        envelope.insert(PAYLOAD_REF_KEY, self);
+        serde_json::to_vec(&envelope).expect("Could not encode PayloadRef")
+    }
+
+    /// Reads the PayloadRef from the payload of an event.
+    /// 
+    /// **Arguments**
+    ///  * `payload`: The payload of the event.
+    /// 
+    /// **Returns**  
+    /// The PayloadRef, or None if the payload is not a reference (i.e., it is the actual payload).
+    pub fn from_payload(payload: &[u8]) -> Option<Self> {
+        // Don't bother parsing payloads that aren't JSON objects in the first place
+        if payload.first() != Some(&b'{') { return None; }
+        let mut envelope: HashMap<String, PayloadRef> = serde_json::from_slice(payload).ok()?;
+        if envelope.len() != 1 { return None; }
+        envelope.remove(PAYLOAD_REF_KEY)
+    }
+
+    /// Reads the payload that this PayloadRef refers to.
+    /// 
+    /// **Arguments**
+    ///  * `root`: The directory that the payload must be in. References to files outside of it (e.g., with `..` or through symlinks) are refused, since the reference comes from an event that anyone who can write to Kafka could have sent.
+    /// 
+    /// **Returns**  
+    /// The payload, or an std::io::Error if it could not be read (or is outside of the `root`, or has a different size than it should).
+    pub fn read(&self, root: &Path) -> Result<Vec<u8>, std::io::Error> {
+        let path = std::fs::canonicalize(&self.path)?;
+        if !path.starts_with(std::fs::canonicalize(root)?) {
+            return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, format!("'{}' is not in the payload directory '{}'", self.path, root.display())));
+        }
+
+        let payload = std::fs::read(&path)?;
+        if payload.len() as u64 != self.size {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("expected {} bytes, found {}", self.size, payload.len())));
+        }
+        Ok(payload)
+    }
+
+    /// Reads the payload that this PayloadRef refers to, and removes its file once it has been read successfully (the payload is only needed once).
+    /// 
+    /// **Arguments**
+    ///  * `root`: The directory that the payload must be in (see `PayloadRef::read()`).
+    /// 
+    /// **Returns**  
+    /// The payload, or an std::io::Error if it could not be read. Failing to remove the file is only logged, since we have the payload anyway.
+    pub fn take(&self, root: &Path) -> Result<Vec<u8>, std::io::Error> {
+        let payload = self.read(root)?;
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            log::warn!("Could not remove payload file '{}': {}", self.path, err);
+        }
+        Ok(payload)
+    }
+}
+
+
+
+/// Defines the struct that will be used to tell the Driver that we will try to create a job again
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CreateRetryInfo {
+    /// The number of the retry that we will do next (starting at 1)
+    pub attempt: u32,
+    /// The number of retries that we will do at most
+    pub max_retries: u32,
+    /// Why creating the job failed this time
+    pub reason: String,
+}
+
+
+
+/// Defines the struct that will be used to tell the Driver how far a location is with pulling the image of a job
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PullProgress {
+    /// The image that is being pulled
+    pub image: String,
+    /// The number of bytes pulled so far (zero if the location cannot tell)
+    pub current: u64,
+    /// The number of bytes to pull in total, if known. Note that layers only report their size once they start downloading, so this may grow during the pull.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+}
+
+impl PullProgress {
+    /// Returns how far the pull is, in percent.
+    /// 
+    /// **Returns**  
+    /// The percentage (capped to 100), or None if the total size of the image is unknown.
+    pub fn percentage(&self) -> Option<u8> {
+        match self.total {
+            Some(total) if total > 0 => Some((self.current.min(total) * 100 / total) as u8),
+            _                        => None,
+        }
+    }
+}
+
+impl fmt::Display for PullProgress {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        write!(f, "pulling image '{}'...", self.image)?;
+        match (self.percentage(), self.total) {
+            (Some(percentage), Some(total)) => write!(f, " {}% ({:.1} of {:.1} MiB)", percentage, self.current as f64 / (1024.0 * 1024.0), total as f64 / (1024.0 * 1024.0)),
+            _                               => Ok(()),
+        }
+    }
+}
+
+
+
+/// Resource limits for the container of a job. Limits that are not given fall back to those of the location.
+#[derive(Clone, PartialEq, Message)]
+pub struct Resources {
+    /// The maximum amount of memory, in bytes.
+    #[prost(tag = "1", optional, int64)]
+    pub memory_limit: Option<i64>,
+    /// The maximum number of CPUs, in nano-CPUs (10^-9 CPUs).
+    #[prost(tag = "2", optional, int64)]
+    pub cpu_limit: Option<i64>,
+    /// The maximum number of processes.
+    #[prost(tag = "3", optional, int64)]
+    pub pids_limit: Option<i64>,
+}
+
 #[derive(Clone, PartialEq, Message)]
 pub struct Mount {
     #[prost(tag = "1", string)]
@@ -2,11 +2,23 @@ extern crate anyhow;
 #[macro_use]
 extern crate log;
 #[macro_use]
+extern crate lazy_static;
+#[macro_use]
 extern crate maplit;
 
 pub mod clb_heartbeat;
 pub mod clb_lifecycle;
 pub mod cmd_cancel;
+pub mod check;
 pub mod cmd_create;
+pub mod dispatch;
 pub mod errors;
+pub mod gc;
 pub mod interface;
+pub mod logs;
+pub mod naming;
+pub mod metrics;
+pub mod networks;
+pub mod producer;
+pub mod pulls;
+pub mod schedulers;
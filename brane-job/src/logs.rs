@@ -0,0 +1,444 @@
+/* LOGS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   14 Oct 2026, 23:05:41
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Streams the output of running containers to the event topic as Log
+ *   events, for locations that have `stream_logs` enabled. The output is
+ *   collected in chunks of bounded size and rate-limited before it hits
+ *   Kafka.
+**/
+
+use std::time::{Duration, Instant};
+
+use bollard::container::{LogOutput, LogsOptions};
+use bollard::Docker;
+use futures_util::stream::StreamExt;
+use tokio::sync::mpsc::Sender;
+
+use crate::interface::{Event, EventKind};
+use crate::naming;
+
+
+/***** CONSTANTS *****/
+/// The maximum size (in bytes) of the text in a single Log event.
+pub const LOG_CHUNK_SIZE: usize = 16 * 1024;
+/// The number of bytes of output per second we stream for a single job. Output beyond that is dropped (and the number of dropped bytes reported).
+pub const LOG_RATE_LIMIT: usize = 64 * 1024;
+/// The time after which pending output is sent, even if it doesn't fill a chunk.
+pub const LOG_FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+/// The number of Log events that may be waiting to be sent to Kafka before the log streams have to wait.
+pub const LOG_CHANNEL_CAPACITY: usize = 64;
+
+/// The Event category of Log events with stdout output.
+pub const LOG_CATEGORY_STDOUT: &str = "stdout";
+/// The Event category of Log events with stderr output.
+pub const LOG_CATEGORY_STDERR: &str = "stderr";
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_output_waits_for_flush() {
+        let start = Instant::now();
+        let mut buffer = LogBuffer::new(start);
+        assert!(buffer.push(LogStream::Stdout, "hello\n", start).is_empty());
+        assert!(buffer.push(LogStream::Stderr, "oops\n", start).is_empty());
+        assert!(buffer.flush(start + LOG_FLUSH_INTERVAL / 2).is_empty());
+
+        let chunks = buffer.flush(start + LOG_FLUSH_INTERVAL);
+        assert_eq!(chunks, vec![
+            LogChunk{ stream: LogStream::Stdout, text: "hello\n".to_string() },
+            LogChunk{ stream: LogStream::Stderr, text: "oops\n".to_string() },
+        ]);
+        assert!(buffer.finish().is_empty());
+    }
+
+    #[test]
+    fn chunks_are_capped_and_split_on_lines() {
+        let start = Instant::now();
+        let mut buffer = LogBuffer::new(start);
+        let line = format!("{}\n", "x".repeat(999));
+        let chunks = buffer.push(LogStream::Stdout, &line.repeat(40), start);
+
+        assert_eq!(chunks.len(), 2);
+        for chunk in &chunks {
+            assert!(chunk.text.len() <= LOG_CHUNK_SIZE);
+            assert!(chunk.text.ends_with('\n'));
+        }
+        let rest = buffer.finish();
+        assert_eq!(chunks.iter().chain(rest.iter()).map(|c| c.text.len()).sum::<usize>(), 40_000);
+    }
+
+    #[test]
+    fn excess_output_is_dropped_and_reported() {
+        let start = Instant::now();
+        let mut buffer = LogBuffer::new(start);
+        let chunks = buffer.push(LogStream::Stdout, &"y".repeat(LOG_RATE_LIMIT + 100), start);
+        assert_eq!(chunks.iter().map(|c| c.text.len()).sum::<usize>(), LOG_RATE_LIMIT);
+
+        // Nothing else gets through until the budget is refilled
+        assert!(buffer.push(LogStream::Stdout, "more", start).is_empty());
+        let chunks = buffer.flush(start + Duration::from_secs(1));
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].stream, LogStream::Stderr);
+        assert!(chunks[0].text.contains("104 bytes"));
+    }
+
+    #[test]
+    fn characters_split_over_frames_are_kept_whole() {
+        let start = Instant::now();
+        let mut buffer = LogBuffer::new(start);
+        let text = "héllo wörld ✓\n".as_bytes();
+        // Every split of the text, including those halfway through a character
+        for split in 0..text.len() {
+            assert!(buffer.push_bytes(LogStream::Stdout, &text[..split], start).is_empty());
+            assert!(buffer.push_bytes(LogStream::Stdout, &text[split..], start).is_empty());
+            assert_eq!(buffer.finish(), vec![ LogChunk{ stream: LogStream::Stdout, text: "héllo wörld ✓\n".to_string() } ]);
+        }
+
+        // Bytes that aren't UTF-8 at all are replaced, and a character that never completes is too
+        assert!(buffer.push_bytes(LogStream::Stderr, b"a\xffb\xe2\x9c", start).is_empty());
+        assert_eq!(buffer.finish(), vec![ LogChunk{ stream: LogStream::Stderr, text: "a\u{FFFD}b\u{FFFD}".to_string() } ]);
+    }
+
+    #[test]
+    fn chunks_of_a_job_share_a_key() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        let chunks = vec![
+            LogChunk{ stream: LogStream::Stdout, text: "one\n".to_string() },
+            LogChunk{ stream: LogStream::Stderr, text: "two\n".to_string() },
+            LogChunk{ stream: LogStream::Stdout, text: "three\n".to_string() },
+        ];
+        let mut order = 0;
+        assert!(futures::executor::block_on(send_chunks(&tx, chunks, "abc123-0-0123456789", "app", "loc1", &mut order)));
+
+        // So they end up on the same partition, in order
+        let sent: Vec<(String, u32)> = (0..3).map(|_| rx.try_recv().map(|(key, event)| (key, event.order)).unwrap()).collect();
+        assert_eq!(sent, vec![ (String::from("abc123"), 0), (String::from("abc123"), 1), (String::from("abc123"), 2) ]);
+        assert_eq!(order, 3);
+    }
+
+    #[test]
+    fn multibyte_characters_are_not_split() {
+        let start = Instant::now();
+        let mut buffer = LogBuffer::new(start);
+        let chunks = buffer.push(LogStream::Stdout, &"é".repeat(LOG_CHUNK_SIZE), start);
+        assert!(!chunks.is_empty());
+        for chunk in chunks.iter().chain(buffer.finish().iter()) {
+            assert!(chunk.text.len() <= LOG_CHUNK_SIZE);
+            assert!(chunk.text.chars().all(|c| c == 'é'));
+        }
+    }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// The output stream that a piece of log output came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogStream {
+    /// The container's stdout
+    Stdout,
+    /// The container's stderr
+    Stderr,
+}
+
+impl LogStream {
+    /// Returns the Event category for Log events of this stream.
+    #[inline]
+    pub fn category(&self) -> &'static str {
+        match self {
+            LogStream::Stdout => LOG_CATEGORY_STDOUT,
+            LogStream::Stderr => LOG_CATEGORY_STDERR,
+        }
+    }
+}
+
+
+
+/// A piece of log output that is ready to be sent as a single Log event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogChunk {
+    /// The stream the output came from
+    pub stream : LogStream,
+    /// The output itself (at most LOG_CHUNK_SIZE bytes)
+    pub text   : String,
+}
+
+
+
+/// Collects the output of a container into chunks, enforcing the rate limit.
+#[derive(Debug)]
+pub struct LogBuffer {
+    /// The pending stdout output
+    stdout : String,
+    /// The pending stderr output
+    stderr : String,
+    /// The bytes of the character that the last stdout output ended halfway through, if any
+    stdout_partial : Vec<u8>,
+    /// The bytes of the character that the last stderr output ended halfway through, if any
+    stderr_partial : Vec<u8>,
+
+    /// The number of bytes we may still accept before we start dropping output
+    budget      : usize,
+    /// The moment we last refilled the budget
+    last_refill : Instant,
+    /// The number of bytes we dropped since we last reported it
+    dropped     : usize,
+    /// The moment we last sent the pending output
+    last_flush  : Instant,
+}
+
+impl LogBuffer {
+    /// Constructor for the LogBuffer.
+    /// 
+    /// **Arguments**
+    ///  * `now`: The current time.
+    pub fn new(now: Instant) -> Self {
+        Self {
+            stdout : String::new(),
+            stderr : String::new(),
+            stdout_partial : vec![],
+            stderr_partial : vec![],
+
+            budget      : LOG_RATE_LIMIT,
+            last_refill : now,
+            dropped     : 0,
+            last_flush  : now,
+        }
+    }
+
+
+
+    /// Adds new output to the buffer.
+    /// 
+    /// **Arguments**
+    ///  * `stream`: The stream the output came from.
+    ///  * `text`: The output itself.
+    ///  * `now`: The current time.
+    /// 
+    /// **Returns**  
+    /// The chunks that have filled up and should be sent right away.
+    pub fn push(&mut self, stream: LogStream, text: &str, now: Instant) -> Vec<LogChunk> {
+        self.refill(now);
+
+        // Only accept what fits in the budget
+        let accepted = floor_char_boundary(text, self.budget);
+        self.budget -= accepted;
+        self.dropped += text.len() - accepted;
+
+        let pending = match stream {
+            LogStream::Stdout => &mut self.stdout,
+            LogStream::Stderr => &mut self.stderr,
+        };
+        pending.push_str(&text[..accepted]);
+
+        // Split off any chunks that are full
+        let mut chunks = Vec::new();
+        while pending.len() >= LOG_CHUNK_SIZE {
+            chunks.push(LogChunk{ stream, text: split_chunk(pending) });
+        }
+        chunks
+    }
+
+    /// Adds new raw output to the buffer. Output may end halfway through a character (as Docker frames do), in which case the rest of that character is expected at the start of the next output of the same stream.
+    /// 
+    /// **Arguments**
+    ///  * `stream`: The stream the output came from.
+    ///  * `bytes`: The output itself.
+    ///  * `now`: The current time.
+    /// 
+    /// **Returns**  
+    /// The chunks that have filled up and should be sent right away.
+    pub fn push_bytes(&mut self, stream: LogStream, bytes: &[u8], now: Instant) -> Vec<LogChunk> {
+        let partial = match stream {
+            LogStream::Stdout => &mut self.stdout_partial,
+            LogStream::Stderr => &mut self.stderr_partial,
+        };
+        let text = decode_utf8(partial, bytes);
+        self.push(stream, &text, now)
+    }
+
+    /// Sends the pending output if it has been waiting for at least LOG_FLUSH_INTERVAL.
+    /// 
+    /// **Arguments**
+    ///  * `now`: The current time.
+    /// 
+    /// **Returns**  
+    /// The chunks to send, if any.
+    pub fn flush(&mut self, now: Instant) -> Vec<LogChunk> {
+        self.refill(now);
+        if now.duration_since(self.last_flush) < LOG_FLUSH_INTERVAL { return vec![]; }
+        self.last_flush = now;
+        self.take_pending()
+    }
+
+    /// Sends whatever is still pending, e.g., because the container has exited. A character that the output ended halfway through will never be completed, so it is replaced by U+FFFD.
+    /// 
+    /// **Returns**  
+    /// The chunks to send, if any.
+    pub fn finish(&mut self) -> Vec<LogChunk> {
+        if !self.stdout_partial.is_empty() { self.stdout_partial.clear(); self.stdout.push(char::REPLACEMENT_CHARACTER); }
+        if !self.stderr_partial.is_empty() { self.stderr_partial.clear(); self.stderr.push(char::REPLACEMENT_CHARACTER); }
+        self.take_pending()
+    }
+
+
+
+    /// Refills the budget by LOG_RATE_LIMIT bytes per second, up to a maximum of LOG_RATE_LIMIT.
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        let refill = (elapsed * LOG_RATE_LIMIT as f64) as usize;
+        if refill > 0 {
+            self.budget = (self.budget + refill).min(LOG_RATE_LIMIT);
+            self.last_refill = now;
+        }
+    }
+
+    /// Empties the buffer, including a note on the output that we dropped.
+    fn take_pending(&mut self) -> Vec<LogChunk> {
+        let mut chunks = Vec::with_capacity(3);
+        if !self.stdout.is_empty() { chunks.push(LogChunk{ stream: LogStream::Stdout, text: std::mem::take(&mut self.stdout) }); }
+        if !self.stderr.is_empty() { chunks.push(LogChunk{ stream: LogStream::Stderr, text: std::mem::take(&mut self.stderr) }); }
+        if self.dropped > 0 {
+            chunks.push(LogChunk{ stream: LogStream::Stderr, text: format!("[brane: dropped {} bytes of output that exceeded the log rate limit of {} bytes per second]\n", self.dropped, LOG_RATE_LIMIT) });
+            self.dropped = 0;
+        }
+        chunks
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Streams the output of the given Docker container as Log events, until the container exits.
+/// 
+/// This runs in the background; errors are logged but otherwise do not affect the job.
+/// 
+/// **Arguments**
+///  * `docker`: The Docker daemon the container runs on.
+///  * `container`: The name of the container, which is also used as the identifier of the events.
+///  * `application`: The application (session) the job belongs to.
+///  * `location`: The location where the job runs.
+///  * `events`: The channel to send the Log events (and their keys) on.
+pub fn spawn_docker_log_stream(docker: Docker, container: String, application: String, location: String, events: Sender<(String, Event)>) {
+    tokio::spawn(async move {
+        let options = LogsOptions::<String> {
+            follow: true,
+            stdout: true,
+            stderr: true,
+            ..Default::default()
+        };
+        let mut logs = Box::pin(docker.logs(&container, Some(options)));
+
+        let mut buffer = LogBuffer::new(Instant::now());
+        let mut interval = tokio::time::interval(LOG_FLUSH_INTERVAL);
+        let mut order: u32 = 0;
+        loop {
+            let chunks = tokio::select! {
+                output = logs.next() => match output {
+                    Some(Ok(LogOutput::StdOut{ message })) => buffer.push_bytes(LogStream::Stdout, &message, Instant::now()),
+                    Some(Ok(LogOutput::StdErr{ message })) => buffer.push_bytes(LogStream::Stderr, &message, Instant::now()),
+                    Some(Ok(_))  => vec![],
+                    Some(Err(err)) => { warn!("Could not read the logs of container '{}': {}", container, err); break; },
+                    None           => { break; },
+                },
+                _ = interval.tick() => buffer.flush(Instant::now()),
+            };
+            if !send_chunks(&events, chunks, &container, &application, &location, &mut order).await { return; }
+        }
+
+        debug!("Log stream of container '{}' ended", container);
+        send_chunks(&events, buffer.finish(), &container, &application, &location, &mut order).await;
+    });
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Sends the given chunks as Log events.
+/// 
+/// All chunks of a job have the same key (its correlation ID), so that they end up on the same partition and reach the driver in the order they were sent.
+/// 
+/// **Returns**  
+/// Whether the events could be sent (false means the receiving end is gone).
+async fn send_chunks(events: &Sender<(String, Event)>, chunks: Vec<LogChunk>, container: &str, application: &str, location: &str, order: &mut u32) -> bool {
+    for chunk in chunks {
+        let key = naming::correlation_id(container).to_string();
+        let event = Event::new(EventKind::Log, container, application, location, chunk.stream.category(), *order, Some(chunk.text.into_bytes()), None);
+        *order += 1;
+        if events.send((key, event)).await.is_err() {
+            warn!("Could not forward the logs of container '{}': event channel closed", container);
+            return false;
+        }
+    }
+    true
+}
+
+/// Decodes the given output as UTF-8, after the bytes of the character that the previous output ended halfway through.
+/// 
+/// **Arguments**
+///  * `partial`: The bytes of the unfinished character of the previous output, which are replaced by those of this output (if it ends halfway through a character too).
+///  * `bytes`: The output to decode.
+/// 
+/// **Returns**  
+/// The complete characters in the output. Bytes that are not UTF-8 at all are replaced by U+FFFD.
+fn decode_utf8(partial: &mut Vec<u8>, bytes: &[u8]) -> String {
+    partial.extend_from_slice(bytes);
+    let mut text = String::with_capacity(partial.len());
+    let mut start = 0;
+    loop {
+        match std::str::from_utf8(&partial[start..]) {
+            Ok(valid) => { text.push_str(valid); start = partial.len(); break; },
+            Err(err)  => {
+                let end = start + err.valid_up_to();
+                text.push_str(std::str::from_utf8(&partial[start..end]).unwrap());
+                match err.error_len() {
+                    Some(len) => { text.push(char::REPLACEMENT_CHARACTER); start = end + len; },
+                    // The output ends halfway through a character
+                    None      => { start = end; break; },
+                }
+            },
+        }
+    }
+    partial.drain(..start);
+    text
+}
+
+/// Splits the first chunk off the given pending output, preferably just after a newline.
+fn split_chunk(pending: &mut String) -> String {
+    let end = floor_char_boundary(pending, LOG_CHUNK_SIZE);
+    let end = match pending[..end].rfind('\n') {
+        Some(newline) => newline + 1,
+        None          => end,
+    };
+    let rest = pending.split_off(end);
+    std::mem::replace(pending, rest)
+}
+
+/// Returns the largest index of at most `max` that lies on a character boundary in `text`.
+fn floor_char_boundary(text: &str, max: usize) -> usize {
+    if max >= text.len() { return text.len(); }
+    let mut index = max;
+    while !text.is_char_boundary(index) { index -= 1; }
+    index
+}
@@ -1,36 +1,41 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
 use brane_cfg::{Infrastructure, Secrets};
-use brane_clb::interface::{Callback, CallbackKind};
+use brane_cfg::secrets::SecretsKey;
+use brane_clb::interface::{self as clb_interface, Callback, CallbackKind};
 use brane_job::{
     clb_lifecycle,
     interface::{Command, CommandKind, Event},
 };
-use brane_job::{cmd_create};
-use brane_shr::utilities;
-use bytes::BytesMut;
+use brane_job::{check, cmd_cancel, cmd_create, gc, metrics, networks};
+use brane_job::dispatch::{Dispatcher, OffsetTracker};
+use brane_job::logs::LOG_CHANNEL_CAPACITY;
+use brane_job::producer::{self, EventSender, KafkaSink};
+use brane_job::schedulers::{Xenon, XenonSchedulers};
+use brane_shr::{metrics as shr_metrics, utilities};
+use brane_shr::kafka::{self, KafkaSecurity, TopicOptions};
+use bollard::Docker;
 use brane_job::errors::JobError;
-use clap::Parser;
-use dashmap::{lock::RwLock, DashMap};
+use clap::{Parser, Subcommand};
 use dotenv::dotenv;
 use futures::stream::FuturesUnordered;
-use futures::{StreamExt, TryStreamExt};
+use futures::StreamExt;
 use log::LevelFilter;
 use log::{debug, error, info, warn};
 use prost::Message;
 use rdkafka::{
-    admin::{AdminClient, AdminOptions, NewTopic, TopicReplication},
-    config::ClientConfig,
     consumer::{stream_consumer::StreamConsumer, CommitMode, Consumer},
-    error::RDKafkaErrorCode,
-    message::ToBytes,
-    producer::{FutureProducer, FutureRecord},
-    util::Timeout,
+    producer::FutureProducer,
     Message as KafkaMesage, Offset, TopicPartitionList,
 };
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
-use xenon::compute::Scheduler;
 
 
 #[derive(Parser)]
@@ -45,6 +50,10 @@ struct Opts {
     /// Kafka brokers
     #[clap(short, long, default_value = "127.0.0.1:9092", env = "BROKERS")]
     brokers: String,
+    #[clap(flatten)]
+    kafka: KafkaSecurity,
+    #[clap(flatten)]
+    topics: TopicOptions,
     /// Print debug info
     #[clap(short, long, env = "DEBUG", takes_value = false)]
     debug: bool,
@@ -63,9 +72,64 @@ struct Opts {
     /// Secrets store
     #[clap(short, long, default_value = "./secrets.yml", env = "SECRETS")]
     secrets: String,
+    /// File with the key to decrypt an encrypted secrets store with (defaults to the key in BRANE_SECRETS_KEY, if any)
+    #[clap(long, env = "SECRETS_KEY_FILE")]
+    secrets_key_file: Option<PathBuf>,
     /// Xenon gRPC endpoint
     #[clap(short, long, default_value = "http://127.0.0.1:50051", env = "XENON")]
     xenon: String,
+    /// Interval (in seconds) between health checks of the cached Xenon schedulers
+    #[clap(long, default_value = "30", env = "XENON_HEALTH_INTERVAL")]
+    xenon_health_interval: u64,
+    /// Interval (in seconds) between clean-ups of the old job outputs on Xenon locations with an 'output_retention'
+    #[clap(long, default_value = "3600", env = "XENON_CLEANUP_INTERVAL")]
+    xenon_cleanup_interval: u64,
+    /// Address to serve the Prometheus metrics on (at '/metrics')
+    #[clap(long, default_value = metrics::DEFAULT_METRICS_ADDRESS, env = "METRICS_ADDRESS")]
+    metrics_address: SocketAddr,
+    /// Maximum number of messages a worker handles at the same time (messages for the same job are always handled one after the other)
+    #[clap(long, default_value = "8", env = "MAX_IN_FLIGHT")]
+    max_in_flight: usize,
+    /// Remove the Docker networks brane-job created when it shuts down
+    #[clap(long, env = "CLEANUP_NETWORKS", takes_value = false)]
+    cleanup_networks: bool,
+    /// Maximum size (in bytes) of an event; larger results are written to the 'payload_dir' of their location, or fail the job if it has none (should stay below Kafka's 'message.max.bytes')
+    #[clap(long, default_value_t = producer::DEFAULT_MAX_EVENT_SIZE, env = "MAX_EVENT_SIZE")]
+    max_event_size: usize,
+    /// Run a one-off command instead of the service
+    #[clap(subcommand)]
+    command: Option<SubCommand>,
+}
+
+#[derive(Subcommand)]
+enum SubCommand {
+    /// Check that every location can be reached and that the secrets it refers to exist
+    Check {
+        /// Only check this location (may be given multiple times)
+        #[clap(long = "location")]
+        locations: Vec<String>,
+        /// Location that may fail without failing the check (may be given multiple times)
+        #[clap(long)]
+        optional: Vec<String>,
+        /// How long reaching a single location may take, in seconds
+        #[clap(long, default_value_t = check::DEFAULT_CHECK_TIMEOUT.as_secs())]
+        timeout: u64,
+        /// Print the report as JSON
+        #[clap(long, takes_value = false)]
+        json: bool,
+    },
+    /// Remove the containers, Kubernetes jobs and job outputs that jobs left behind on the locations
+    Gc {
+        /// Only remove resources older than this (e.g., '90m', '24h' or '7d')
+        #[clap(long, parse(try_from_str = gc::parse_age))]
+        older_than: Duration,
+        /// Only report what would be removed
+        #[clap(long, takes_value = false)]
+        dry_run: bool,
+        /// Print the report as JSON
+        #[clap(long, takes_value = false)]
+        json: bool,
+    },
 }
 
 /* TIM */
@@ -86,29 +150,62 @@ async fn main() -> Result<()> {
     }
     debug!("Initializing brane-job...");
 
+    // Run one-off commands without touching Kafka
+    if let Some(SubCommand::Check{ locations, optional, timeout, json }) = opts.command.as_ref() {
+        // Invalid locations end up in the report rather than stopping the check
+        let (infra, secrets) = load_config(&opts, false);
+        let xenon_endpoint = utilities::ensure_http_schema(&opts.xenon, !opts.debug)?;
+        let options = check::CheckOptions{ locations: locations.clone(), optional: optional.clone(), timeout: Duration::from_secs(*timeout) };
+        let report = match check::run(&infra, &secrets, &xenon_endpoint, &options).await {
+            Ok(report)  => report,
+            Err(reason) => { error!("{}", reason); std::process::exit(-1); }
+        };
+        if *json { println!("{}", serde_json::to_string_pretty(&report)?); } else { print!("{}", report); }
+        if !report.is_healthy() { std::process::exit(1); }
+        return Ok(());
+    }
+    if let Some(&SubCommand::Gc{ older_than, dry_run, json }) = opts.command.as_ref() {
+        let (infra, secrets) = load_config(&opts, true);
+        let xenon_endpoint = utilities::ensure_http_schema(&opts.xenon, !opts.debug)?;
+        let report = match gc::run(&infra, &secrets, &xenon_endpoint, gc::GcOptions{ older_than, dry_run }).await {
+            Ok(report)  => report,
+            Err(reason) => { error!("{}", reason); std::process::exit(-1); }
+        };
+        if json { println!("{}", serde_json::to_string_pretty(&report)?); } else { print!("{}", report); }
+        if !report.is_complete() { std::process::exit(1); }
+        return Ok(());
+    }
+
+    // Refuse Kafka options that don't make sense before connecting with them
+    if let Err(reason) = opts.kafka.validate() { error!("{}", reason); std::process::exit(-1); }
+    if let Err(reason) = opts.topics.validate() { error!("{}", reason); std::process::exit(-1); }
+
     // Ensure that the input/output topics exists.
-    if let Err(reason) = ensure_topics(
-        vec![&opts.callback_topic, &opts.command_topic, &opts.event_topic],
+    if let Err(reason) = kafka::ensure_topics(
+        &[ &opts.callback_topic, &opts.command_topic, &opts.event_topic ],
         &opts.brokers,
+        &opts.kafka,
+        &opts.topics,
     ).await { error!("{}", reason); std::process::exit(-1); }
 
-    debug!("Loading infrastructure file...");
-    let infra = match Infrastructure::new(opts.infra.clone()) {
-        Ok(infra)   => infra,
-        Err(reason) => { error!("{}", reason); std::process::exit(-1); }
-    };
-    if let Err(reason) = infra.validate() { error!("{}", reason); std::process::exit(-1); }
-
-    debug!("Loading secrets file...");
-    let secrets = match Secrets::new(opts.secrets.clone()) {
-        Ok(secrets) => secrets,
-        Err(reason) => { error!("{}", reason); std::process::exit(-1); }
+    let (infra, secrets) = load_config(&opts, true);
+    let payload_dirs = match producer::payload_dirs(&infra) {
+        Ok(payload_dirs) => payload_dirs,
+        Err(reason)      => { error!("{}", reason); std::process::exit(-1); }
     };
-    if let Err(reason) = secrets.validate() { error!("{}", reason); std::process::exit(-1); }
 
     debug!("Initializing Xenon...");
-    let xenon_schedulers = Arc::new(DashMap::<String, Arc<RwLock<Scheduler>>>::new());
+    let xenon_schedulers = Arc::new(XenonSchedulers::new(Xenon));
     let xenon_endpoint = utilities::ensure_http_schema(&opts.xenon, !opts.debug)?;
+    XenonSchedulers::start_health_checks(xenon_schedulers.clone(), Duration::from_secs(opts.xenon_health_interval));
+    XenonSchedulers::start_output_cleanup(xenon_schedulers.clone(), Duration::from_secs(opts.xenon_cleanup_interval));
+
+    // Expose the metrics
+    let metrics_address = opts.metrics_address;
+    tokio::spawn(async move {
+        info!("Serving metrics on 'http://{}/metrics'.", metrics_address);
+        if let Err(err) = shr_metrics::serve(metrics_address).await { error!("Could not serve metrics on '{}': {}", metrics_address, err); }
+    });
 
     // Spawn workers, using Tokio tasks and thread pool.
     debug!("Launching workers...");
@@ -117,6 +214,7 @@ async fn main() -> Result<()> {
             let handle = tokio::spawn(start_worker(
                 opts.debug,
                 opts.brokers.clone(),
+                opts.kafka.clone(),
                 opts.group_id.clone(),
                 opts.callback_topic.clone(),
                 opts.command_topic.clone(),
@@ -125,6 +223,9 @@ async fn main() -> Result<()> {
                 secrets.clone(),
                 xenon_endpoint.clone(),
                 xenon_schedulers.clone(),
+                opts.max_in_flight,
+                opts.max_event_size,
+                payload_dirs.clone(),
             ));
 
             info!("Spawned asynchronous worker #{}.", i + 1);
@@ -132,76 +233,92 @@ async fn main() -> Result<()> {
         })
         .collect::<FuturesUnordered<JoinHandle<_>>>();
 
-    // Wait for workers to finish, print any errors.
-    workers
+    // Wait for workers to finish (or for us to be stopped), print any errors.
+    let workers = workers
         .map(|r| r.unwrap())
         .for_each(|r| async {
             if let Err(error) = r {
                 error!("{}", error);
             };
-        })
-        .await;
+        });
+    tokio::select! {
+        _ = workers           => {},
+        _ = shutdown_signal() => { info!("Shutting down brane-job..."); },
+    }
+
+    // Remove the networks we created for local jobs, if told to
+    if opts.cleanup_networks {
+        match Docker::connect_with_local_defaults() {
+            Ok(docker) => { networks::cleanup_networks(&docker).await; },
+            Err(err)   => { warn!("Could not clean up Docker networks: {}", JobError::DockerConnectionFailed{ err }); },
+        }
+    }
 
     Ok(())
 }
 /*******/
 
-/* TIM */
-/// **Edited: now returns JobErrors.**
-/// 
-/// Makes sure the required topics are present and watched in the local Kafka server.
+/// Loads (and validates) the infrastructure and secrets files, exiting if either of them is broken.
 /// 
 /// **Arguments**
-///  * `topics`: The list of topics to make sure they exist of.
-///  * `brokers`: The string list of Kafka servers that act as the brokers.
+///  * `opts`: The Opts with the paths to the files (and the key to decrypt the secrets with, if any).
+///  * `validate_infra`: Whether to validate every location in the infrastructure file up front. If not, invalid locations only show up once they are used.
 /// 
 /// **Returns**  
-/// Nothing on success, or an ExecutorError otherwise.
-async fn ensure_topics(
-    topics: Vec<&str>,
-    brokers: &str,
-) -> Result<(), JobError> {
-    // Connect with an admin client
-    let admin_client: AdminClient<_> = match ClientConfig::new().set("bootstrap.servers", brokers) .create() {
-        Ok(client)  => client,
-        Err(reason) => { return Err(JobError::KafkaClientError{ servers: brokers.to_string(), err: reason }); }
+/// The Infrastructure and the Secrets.
+fn load_config(opts: &Opts, validate_infra: bool) -> (Infrastructure, Secrets) {
+    debug!("Loading infrastructure file...");
+    let infra = match Infrastructure::new(opts.infra.clone()) {
+        Ok(infra)   => infra,
+        Err(reason) => { error!("{}", reason); std::process::exit(-1); }
     };
+    if validate_infra {
+        if let Err(reason) = infra.validate() { error!("{}", reason); std::process::exit(-1); }
+    }
 
-    // Collect the topics to create and then create them
-    let ktopics: Vec<NewTopic> = topics
-        .iter()
-        .map(|t| NewTopic::new(t, 1, TopicReplication::Fixed(1)))
-        .collect();
-    let results = match admin_client.create_topics(ktopics.iter(), &AdminOptions::new()).await {
-        Ok(results) => results,
-        Err(reason) => { return Err(JobError::KafkaTopicsError{ topics: JobError::serialize_vec(&topics), err: reason }); }
+    debug!("Loading secrets file...");
+    let secrets_key = match SecretsKey::resolve(opts.secrets_key_file.as_deref()) {
+        Ok(key)     => key,
+        Err(reason) => { error!("{}", reason); std::process::exit(-1); }
+    };
+    let secrets = match secrets_key {
+        Some(key) => Secrets::new_encrypted(opts.secrets.clone(), key),
+        None      => Secrets::new(opts.secrets.clone()),
+    };
+    let secrets = match secrets {
+        Ok(secrets) => secrets,
+        Err(reason) => { error!("{}", reason); std::process::exit(-1); }
     };
+    if let Err(reason) = secrets.validate() { error!("{}", reason); std::process::exit(-1); }
 
-    // Report on the results. Don't consider 'TopicAlreadyExists' an error.
-    for result in results {
-        match result {
-            Ok(topic) => info!("Kafka topic '{}' created.", topic),
-            Err((topic, error)) => match error {
-                RDKafkaErrorCode::TopicAlreadyExists => {
-                    info!("Kafka topic '{}' already exists", topic);
-                }
-                _ => { return Err(JobError::KafkaTopicError{ topic, err: error }); }
-            },
+    (infra, secrets)
+}
+
+/// Waits until brane-job is asked to stop, either by an interrupt (Ctrl+C) or by a SIGTERM (e.g., `docker stop`).
+async fn shutdown_signal() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(err)      => {
+            warn!("Could not listen for SIGTERM: {}", err);
+            if let Err(err) = tokio::signal::ctrl_c().await { warn!("Could not listen for interrupts: {}", err); futures::future::pending::<()>().await; }
+            return;
         }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = terminate.recv()        => {},
     }
-
-    Ok(())
 }
-/*******/
 
 /* TIM */
-/// **Edited: Now working with the various errors.**
+/// **Edited: Now working with the various errors. Also forwarding the Log events of jobs that stream their output.**
 /// 
 /// One of the workers in the brane-job service.
 /// 
 /// **Arguments**
 ///  * `debug`: Whether or not to enable debug mode (i.e., more prints and things like not destroying containers)
 ///  * `brokers`: The list of Kafka brokers we're using.
+///  * `security`: The options to connect to the brokers with.
 ///  * `group_id`: The Kafka group ID for the brane-job service.
 ///  * `clb_topic`: The Kafka callback topic for job results.
 ///  * `cmd_topic`: The Kafka command topic for incoming commands.
@@ -210,6 +327,9 @@ async fn ensure_topics(
 ///  * `secrets`: The Secrets handle to the infra.yml.
 ///  * `xenon_endpoint`: The Xenon endpoint to connect to and schedule jobs on.
 ///  * `xenon_schedulers`: A list of Xenon schedulers we use to determine where to run what.
+///  * `max_in_flight`: The maximum number of messages that are handled at the same time.
+///  * `max_event_size`: The maximum size of an encoded event, in bytes.
+///  * `payload_dirs`: The directories where payloads that are too large for an event are written to, per location.
 /// 
 /// **Returns**  
 /// Nothing if the worker exited cleanly, or a JobError if it didn't.
@@ -217,6 +337,7 @@ async fn ensure_topics(
 async fn start_worker(
     debug: bool,
     brokers: String,
+    security: KafkaSecurity,
     group_id: String,
     clb_topic: String,
     cmd_topic: String,
@@ -224,24 +345,24 @@ async fn start_worker(
     infra: Infrastructure,
     secrets: Secrets,
     xenon_endpoint: String,
-    xenon_schedulers: Arc<DashMap<String, Arc<RwLock<Scheduler>>>>,
+    xenon_schedulers: Arc<XenonSchedulers>,
+    max_in_flight: usize,
+    max_event_size: usize,
+    payload_dirs: HashMap<String, PathBuf>,
 ) -> Result<(), JobError> {
-    let output_topic = evt_topic.as_ref();
-
     debug!("Creating Kafka producer...");
-    let producer: FutureProducer = match ClientConfig::new()
-        .set("bootstrap.servers", &brokers)
+    let producer: FutureProducer = match security.client_config(&brokers)
         .set("message.timeout.ms", "5000")
         .create()
     {
         Ok(producer) => producer,
         Err(reason)  => { return Err(JobError::KafkaProducerError{ servers: brokers, err: reason }); }
     };
+    let sender = Arc::new(EventSender::new(KafkaSink::new(producer, evt_topic), max_event_size, payload_dirs));
 
     debug!("Creating Kafka consumer...");
-    let consumer: StreamConsumer = match ClientConfig::new()
+    let consumer: StreamConsumer = match security.client_config(&brokers)
         .set("group.id", &group_id)
-        .set("bootstrap.servers", &brokers)
         .set("enable.partition.eof", "false")
         .set("session.timeout.ms", "6000")
         .set("enable.auto.commit", "false")
@@ -251,155 +372,207 @@ async fn start_worker(
         Err(reason)  => { return Err(JobError::KafkaConsumerError{ servers: brokers, id: group_id, err: reason }); }
     };
 
-    // TODO: make use of transactions / exactly-once semantics (EOS)
-
-    // Restore previous topic/partition offset.
-    let mut tpl = TopicPartitionList::new();
-    tpl.add_partition(&clb_topic, 0);
-    tpl.add_partition(&cmd_topic, 0);
-
-    let committed_offsets = match consumer.committed_offsets(tpl.clone(), Timeout::Never) {
-        Ok(commited_offsets) => commited_offsets.to_topic_map(),
-        Err(reason)          => { return Err(JobError::KafkaGetOffsetError{ clb: clb_topic, cmd: cmd_topic, err: reason }); }
-    };
-    if let Some(offset) = committed_offsets.get(&(clb_topic.clone(), 0)) {
-        let res = match offset {
-            Offset::Invalid => tpl.set_partition_offset(&clb_topic, 0, Offset::Beginning),
-            offset => tpl.set_partition_offset(&clb_topic, 0, *offset),
-        };
-        if let Err(reason) = res {
-            return Err(JobError::KafkaSetOffsetError{ topic: clb_topic, kind: "callback".to_string(), err: reason });
-        }
-    }
-    if let Some(offset) = committed_offsets.get(&(cmd_topic.clone(), 0)) {
-        let res = match offset {
-            Offset::Invalid => tpl.set_partition_offset(&cmd_topic, 0, Offset::Beginning),
-            offset => tpl.set_partition_offset(&cmd_topic, 0, *offset),
-        };
-        if let Err(reason) = res {
-            return Err(JobError::KafkaSetOffsetError{ topic: cmd_topic, kind: "command".to_string(), err: reason });
-        }
+    // Log (and CreateRetrying) events are produced while commands are still being handled, so they get their own forwarder
+    let (log_tx, mut log_rx) = mpsc::channel::<(String, Event)>(LOG_CHANNEL_CAPACITY);
+    {
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            while let Some((evt_key, event)) = log_rx.recv().await {
+                send_event(&sender, evt_key, event).await;
+            }
+        });
     }
 
-    info!("Restoring commited offsets: {:?}", &tpl);
-    if let Err(reason) = consumer.assign(&tpl) {
-        return Err(JobError::KafkaSetOffsetsError{ clb: clb_topic, cmd: cmd_topic, err: reason });
+    // TODO: make use of transactions / exactly-once semantics (EOS)
+
+    // Read every partition of both topics, restoring the previous offsets.
+    match kafka::assign_partitions(&consumer, &[ &clb_topic, &cmd_topic ]) {
+        Ok(tpl)     => info!("Restored commited offsets: {:?}", &tpl),
+        Err(reason) => { return Err(JobError::KafkaAssignError{ err: reason }); }
     }
 
-    // Create the outer pipeline on the message stream.
+    // Handle the messages as they come in, committing each one only once it (and everything before it) has been handled
     debug!("Waiting for messages...");
-    let stream_processor = consumer.stream().try_for_each(|borrowed_message| {
-        // Copy the message into owned space
-        consumer.commit_message(&borrowed_message, CommitMode::Sync).unwrap();
+    let dispatcher = Dispatcher::new(max_in_flight);
+    let mut offsets = OffsetTracker::default();
+    let (done_tx, mut done_rx) = mpsc::unbounded_channel::<(String, i32, i64)>();
+    let mut stream = consumer.stream();
+    loop {
+        let borrowed_message = tokio::select! {
+            Some((topic, partition, offset)) = done_rx.recv() => {
+                if let Some(next) = offsets.complete(&topic, partition, offset) { commit_offset(&consumer, &topic, partition, next); }
+                continue;
+            },
+            message = stream.next() => match message {
+                Some(Ok(message)) => message,
+                Some(Err(err))    => { return Err(JobError::KafkaReceiveError{ err }); },
+                None              => { return Ok(()); },
+            },
+        };
 
+        // Copy the message into owned space
         let owned_message = borrowed_message.detach();
-        let owned_producer = producer.clone();
+        let (topic, partition, offset) = (owned_message.topic().to_string(), owned_message.partition(), owned_message.offset());
+        offsets.start(&topic, partition, offset);
+
+        // Get the message key
+        let msg_key = match owned_message
+            .key()
+            .map(String::from_utf8_lossy)
+            .map(String::from)
+        {
+            Some(msg_key) => msg_key,
+            None          => {
+                warn!("Received message without a key; ignoring message");
+                if let Some(next) = offsets.complete(&topic, partition, offset) { commit_offset(&consumer, &topic, partition, next); }
+                continue;
+            }
+        };
+
+        let sender = sender.clone();
         let owned_infra = infra.clone();
         let owned_secrets = secrets.clone();
         let owned_xenon_endpoint = xenon_endpoint.clone();
         let owned_xenon_schedulers = xenon_schedulers.clone();
         let clb_topic = clb_topic.clone();
         let cmd_topic = cmd_topic.clone();
+        let log_tx = log_tx.clone();
+        let done_tx = done_tx.clone();
 
-        async move {
-            // Get the message key
-            let msg_key = match owned_message
-                .key()
-                .map(String::from_utf8_lossy)
-                .map(String::from)
-            {
-                Some(msg_key) => msg_key,
-                None          => {
-                    warn!("Received message without a key; ignoring message");
-                    return Ok(());
-                }
-            };
-
+        // Handle it in its own task, after any earlier messages for the same job
+        let dispatch_key = msg_key.clone();
+        dispatcher.dispatch(&dispatch_key, async move {
             // Get the payload
-            let msg_payload = match owned_message.payload() {
-                Some(msg_payload) => msg_payload,
-                None              => {
+            let events = match owned_message.payload() {
+                // Depending on the message's topic, handle it differently
+                Some(msg_payload) => if topic == clb_topic {
+                    Some(handle_clb_message(msg_key, msg_payload))
+                } else if topic == cmd_topic {
+                    Some(handle_cmd_message(
+                        debug,
+                        msg_key,
+                        msg_payload,
+                        owned_infra,
+                        owned_secrets,
+                        owned_xenon_endpoint,
+                        owned_xenon_schedulers,
+                        log_tx,
+                    )
+                    .await)
+                } else {
+                    warn!("Received message (key: {}) with unknown topic '{}'; ignoring message", msg_key, topic);
+                    None
+                },
+                None => {
                     warn!("Received message (key: {}) without a payload; ignoring message", msg_key);
-                    return Ok(());
+                    None
                 }
             };
 
-            // Depending on the message's topic, handle it differently
-            let topic = owned_message.topic();
-            let events = if topic == clb_topic {
-                handle_clb_message(msg_key, msg_payload)
-            } else if topic == cmd_topic {
-                handle_cmd_message(
-                    debug,
-                    msg_key,
-                    msg_payload,
-                    owned_infra,
-                    owned_secrets,
-                    owned_xenon_endpoint,
-                    owned_xenon_schedulers,
-                )
-                .await
-            } else {
-                warn!("Received message (key: {}) with unknown topic '{}'; ignoring message", msg_key, topic);
-                return Ok(());
-            };
-
             // Match the events to return
             match events {
-                Ok(events) => {
+                Some(Ok(events)) => {
                     for (evt_key, event) in events {
-                        // Encode event message into a payload (bytes)
-                        let mut payload = BytesMut::with_capacity(64);
-                        match event.encode(&mut payload) {
-                            Ok(_) => {
-                                // Send event on output topic
-                                let message = FutureRecord::to(output_topic).key(&evt_key).payload(payload.to_bytes());
-                                if let Err(error) = owned_producer.send(message, Timeout::Never).await {
-                                    error!("Failed to send event (key: {}): {:?}", evt_key, error);
-                                }
-                            },
-                            Err(reason) => { error!("Failed to send event (key: {}): {}", evt_key.clone(), JobError::EventEncodeError{ key: evt_key, err: reason }); }
-                        }
+                        send_event(&sender, evt_key, event).await;
                     }
                 }
-                Err(err) => {
+                Some(Err(err)) => {
                     // Log the error but continue listening
                     error!("{}", &err);
                 }
+                None => {},
             };
 
-            Ok(())
-        }
-    });
-
-    match stream_processor.await {
-        Ok(_)  => Ok(()),
-        Err(_) => panic!("The Stream Processor shouldn't return an error, but it does; this should never happen!"),
+            // The worker is only gone if it failed, in which case the offset doesn't matter anymore
+            let _ = done_tx.send((topic, partition, offset));
+        }).await;
     }
 }
 /*******/
 
+/// Commits the given offset for the given topic and partition. Failures are logged, not returned, as the message will simply be handled again after a restart.
+/// 
+/// **Arguments**
+///  * `consumer`: The Kafka consumer to commit the offset for.
+///  * `topic`: The topic to commit the offset for.
+///  * `partition`: The partition to commit the offset for.
+///  * `offset`: The offset of the next message to consume.
+fn commit_offset(
+    consumer: &StreamConsumer,
+    topic: &str,
+    partition: i32,
+    offset: i64,
+) {
+    let mut tpl = TopicPartitionList::new();
+    let res = tpl.add_partition_offset(topic, partition, Offset::Offset(offset))
+        .and_then(|_| consumer.commit(&tpl, CommitMode::Sync));
+    if let Err(err) = res { error!("Could not commit offset {} for topic '{}': {}", offset, topic, err); }
+}
+
+/// Sends the given event to the driver. Failures are logged, not returned, as there is nobody to return them to.
+/// 
+/// **Arguments**
+///  * `sender`: The EventSender to send the event with.
+///  * `evt_key`: The key of the event message.
+///  * `event`: The Event to send.
+async fn send_event(
+    sender: &EventSender<KafkaSink>,
+    evt_key: String,
+    event: Event,
+) {
+    if let Err(err) = sender.send(evt_key, event).await { error!("Failed to send event: {}", err); }
+}
+
 /* TIM */
 /// **Edited: now returning JobErrors.**
 /// 
-/// Handles a given callback message by calling the appropriate handler.
+/// Handles a given callback message by calling the appropriate handler for every callback in it (which is more than one if it's a batch).
 /// 
 /// **Arguments**
 ///  * `key`: The key of the message we received.
 ///  * `payload`: The raw, binary payload of the message.
 /// 
 /// **Returns**  
-/// A list of events that should be fired on success, or a JobError if that somehow failed.
+/// A list of events that should be fired on success (in the order of the callbacks), or a JobError if that somehow failed.
 fn handle_clb_message(
     key: String,
     payload: &[u8],
 ) -> Result<Vec<(String, Event)>, JobError> {
-    // Decode payload into a callback message.
+    // Decode payload into callback messages.
     debug!("Decoding clb message...");
-    let callback = match Callback::decode(payload) {
-        Ok(callback) => callback,
-        Err(reason)  => { return Err(JobError::CallbackDecodeError{ key, err: reason }); }
+    let callbacks = match clb_interface::decode_callbacks(payload) {
+        Ok(callbacks) => callbacks,
+        Err(reason)   => {
+            metrics::DECODE_FAILURES.with_label_values(&["callback"]).inc();
+            return Err(JobError::CallbackDecodeError{ key, err: reason });
+        }
     };
+    if callbacks.len() == 1 { return handle_callback(&key, callbacks.into_iter().next().unwrap()); }
+
+    // Handle every callback in the batch, so that one bad callback doesn't lose the others
+    debug!("Unpacked batch of {} callbacks (key: {})", callbacks.len(), key);
+    let mut events = Vec::with_capacity(callbacks.len());
+    for callback in callbacks {
+        match handle_callback(&key, callback) {
+            Ok(callback_events) => events.extend(callback_events),
+            Err(err)            => error!("{}", err),
+        }
+    }
+    Ok(events)
+}
+
+/// Handles a single callback by calling the appropriate handler.
+/// 
+/// **Arguments**
+///  * `key`: The key of the message that carried the callback.
+///  * `callback`: The decoded callback.
+/// 
+/// **Returns**  
+/// A list of events that should be fired on success, or a JobError if that somehow failed.
+fn handle_callback(
+    key: &str,
+    callback: Callback,
+) -> Result<Vec<(String, Event)>, JobError> {
     let kind = match CallbackKind::from_i32(callback.kind) {
         Some(kind) => kind,
         None       => { return Err(JobError::IllegalCallbackKind{ kind: callback.kind }); }
@@ -436,9 +609,11 @@ fn handle_clb_message(
 ///  * `secrets`: The Secrets handle to the infra.yml.
 ///  * `xenon_endpoint`: The Xenon endpoint to connect to and schedule jobs on.
 ///  * `xenon_schedulers`: A list of Xenon schedulers we use to determine where to run what.
+///  * `log_events`: The channel for events that are sent before a command has been handled (CreateRetrying events, and Log events of jobs that stream their output).
 /// 
 /// **Returns**  
 /// A list of events that should be fired on success, or a JobError if that somehow failed.
+#[allow(clippy::too_many_arguments)]
 async fn handle_cmd_message(
     debug: bool,
     key: String,
@@ -446,14 +621,23 @@ async fn handle_cmd_message(
     infra: Infrastructure,
     secrets: Secrets,
     xenon_endpoint: String,
-    xenon_schedulers: Arc<DashMap<String, Arc<RwLock<Scheduler>>>>,
+    xenon_schedulers: Arc<XenonSchedulers>,
+    log_events: mpsc::Sender<(String, Event)>,
 ) -> Result<Vec<(String, Event)>, JobError> {
     // Decode payload into a command message.
     debug!("Decoding cmd message...");
     let command = match Command::decode(payload) {
         Ok(callback) => callback,
-        Err(reason)  => { return Err(JobError::CommandDecodeError{ key, err: reason }); }
+        Err(reason)  => {
+            metrics::DECODE_FAILURES.with_label_values(&["command"]).inc();
+            return Err(JobError::CommandDecodeError{ key, err: reason });
+        }
     };
+    let version = command.schema_version();
+    if !version.is_compatible() {
+        metrics::SCHEMA_MISMATCHES.with_label_values(&[&version.to_string()]).inc();
+        return Err(JobError::CommandSchemaMismatch{ key, version });
+    }
     let kind = match CommandKind::from_i32(command.kind) {
         Some(kind) => kind,
         None       => { return Err(JobError::IllegalCommandKind{ kind: command.kind }); }
@@ -467,14 +651,18 @@ async fn handle_cmd_message(
 
     info!("Received {} command (key: {}).", kind, key);
     debug!("{:?}", command);
+    metrics::COMMANDS_HANDLED.with_label_values(&[&kind.to_string()]).inc();
 
     // Dispatch command message to appropriate handlers.
     match kind {
         CommandKind::Create => {
             debug!("Handling CREATE command...");
-            cmd_create::handle(debug, &key, command, infra, secrets, xenon_endpoint, xenon_schedulers).await
+            cmd_create::handle(debug, &key, command, infra, secrets, xenon_endpoint, xenon_schedulers, log_events).await
+        }
+        CommandKind::Stop => {
+            debug!("Handling STOP command...");
+            cmd_cancel::handle(&key, command, infra).await
         }
-        CommandKind::Stop => unimplemented!(),
         CommandKind::Unknown => unreachable!(),
     }
 }
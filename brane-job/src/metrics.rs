@@ -0,0 +1,63 @@
+/* METRICS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   14 Oct 2026, 21:10:26
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Defines the Prometheus metrics that brane-job keeps track of. They are
+ *   exposed by `brane_shr::metrics::serve()`.
+**/
+
+use prometheus::{register_int_counter_vec, IntCounterVec};
+
+
+/***** CONSTANTS *****/
+/// The address that brane-job serves its metrics on by default. Differs from the one of the driver, so both can run on the same host.
+pub const DEFAULT_METRICS_ADDRESS: &str = "127.0.0.1:9091";
+
+
+
+
+
+/***** METRICS *****/
+lazy_static! {
+    /// The number of commands handled, per CommandKind.
+    pub static ref COMMANDS_HANDLED: IntCounterVec = register_int_counter_vec!(
+        "brane_job_commands_handled_total",
+        "Number of commands handled, by command kind",
+        &["kind"]
+    ).expect("Could not register metric");
+
+    /// The number of events emitted, per EventKind.
+    pub static ref EVENTS_EMITTED: IntCounterVec = register_int_counter_vec!(
+        "brane_job_events_emitted_total",
+        "Number of events emitted to the driver, by event kind",
+        &["kind"]
+    ).expect("Could not register metric");
+
+    /// The number of events that were larger than the maximum event size, per outcome ('spilled' to the payload directory or 'rejected').
+    pub static ref OVERSIZED_EVENTS: IntCounterVec = register_int_counter_vec!(
+        "brane_job_oversized_events_total",
+        "Number of events larger than the maximum event size, by what happened to them",
+        &["outcome"]
+    ).expect("Could not register metric");
+
+    /// The number of Kafka messages that could not be decoded, per topic kind ('callback' or 'command').
+    pub static ref DECODE_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "brane_job_decode_failures_total",
+        "Number of Kafka messages that could not be decoded, by message kind",
+        &["topic"]
+    ).expect("Could not register metric");
+
+    /// The number of commands dropped because their schema major version differs from ours, per received version.
+    pub static ref SCHEMA_MISMATCHES: IntCounterVec = register_int_counter_vec!(
+        "brane_job_schema_mismatches_total",
+        "Number of commands dropped because of an incompatible schema version, by received version",
+        &["version"]
+    ).expect("Could not register metric");
+}
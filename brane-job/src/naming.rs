@@ -0,0 +1,201 @@
+/* NAMING.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 21:24:06
+ * Last edited:
+ *   15 Oct 2026, 21:24:06
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Names and labels the containers and Kubernetes Jobs that brane-job
+ *   creates. Names are deterministic per attempt to create a job, so a
+ *   command that is handled twice ends up with the same name, and the
+ *   labels tell whether an existing resource with that name is ours.
+**/
+
+use std::collections::HashMap;
+
+use sha2::{Digest, Sha256};
+
+
+/***** CONSTANTS *****/
+/// The label with the correlation ID of the job that a container or Kubernetes Job runs.
+pub const CORRELATION_LABEL: &str = "brane.correlation-id";
+/// The label with the ID of the application that a container or Kubernetes Job is part of.
+pub const APPLICATION_LABEL: &str = "brane.application-id";
+/// The prefix of all labels that we put on containers and Kubernetes Jobs; resources without any such label are never ours.
+pub const LABEL_PREFIX: &str = "brane.";
+/// The number of hexadecimal characters of the hash in a job name.
+const HASH_LENGTH: usize = 8;
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_job_name_is_deterministic() {
+        let name = job_name("abc123", "app", "site", 0);
+        assert_eq!(name, job_name("abc123", "app", "site", 0));
+        assert!(name.starts_with("abc123-0-"), "Unexpected name '{}'", name);
+        assert_eq!(name.len(), "abc123-0-".len() + HASH_LENGTH);
+        assert!(name["abc123-0-".len()..].chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()));
+
+        // Every attempt, application and location gets its own name
+        assert!(job_name("abc123", "app", "site", 1).starts_with("abc123-1-"));
+        assert_ne!(job_name("abc123", "app", "site", 1)[9..], name[9..]);
+        assert_ne!(job_name("abc123", "other", "site", 0), name);
+        assert_ne!(job_name("abc123", "app", "other", 0), name);
+        // Without the separators, these would hash the same
+        assert_ne!(job_name("abc123", "ap", "psite", 0), name);
+    }
+
+    #[test]
+    fn test_correlation_id_of_job_name() {
+        assert_eq!(correlation_id(&job_name("abc123", "app", "site", 2)), "abc123");
+        assert_eq!(correlation_id("abc123"), "abc123");
+    }
+
+    #[test]
+    fn test_array_element_id() {
+        assert_eq!(array_element_id("abc123", 0), "abc123x0");
+        assert_eq!(array_element_id("abc123", 41), "abc123x41");
+
+        // Names of jobs keep their attempt and hash, and every element is a job of its own to the driver
+        let name = array_element_id(&job_name("abc123", "app", "site", 1), 7);
+        assert!(name.starts_with("abc123x7-1-"), "Unexpected name '{}'", name);
+        assert_eq!(correlation_id(&name), array_element_id("abc123", 7));
+        assert_ne!(array_element_id("abc123", 1), array_element_id("abc12", 31));
+    }
+
+    #[test]
+    fn test_is_ours() {
+        let labels = job_labels("abc123", "app");
+        assert!(is_ours(&labels, "abc123", "app"));
+        assert!(!is_ours(&labels, "abc123", "other"));
+        assert!(!is_ours(&labels, "def456", "app"));
+        assert!(!is_ours(&HashMap::new(), "abc123", "app"));
+
+        // Kubernetes keeps its labels in a BTreeMap, and may add some of its own
+        let mut labels: BTreeMap<String, String> = labels.into_iter().collect();
+        labels.insert(String::from("job-name"), String::from("abc123-0-0123abcd"));
+        assert!(is_ours(&labels, "abc123", "app"));
+    }
+
+    #[test]
+    fn test_has_brane_label() {
+        assert!(has_brane_label(&job_labels("abc123", "app")));
+        let labels: HashMap<String, String> = hashmap!{ String::from("brane.something-else") => String::new() };
+        assert!(has_brane_label(&labels));
+
+        let labels: HashMap<String, String> = hashmap!{ String::from("brane") => String::from("true"), String::from("app.brane.io") => String::new() };
+        assert!(!has_brane_label(&labels));
+        assert!(!has_brane_label(&HashMap::new()));
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Returns the name of the container or Kubernetes Job for the given attempt to create a job.
+/// 
+/// The name is '<correlation_id>-<attempt>-<hash>', where the hash covers the correlation ID, application, location and attempt. Since the driver takes the part before the first dash as the correlation ID, the name can be used as the job ID as-is.
+/// 
+/// **Arguments**
+///  * `correlation_id`: The driver-assigned correlation ID of the job.
+///  * `application_id`: The ID of the application that the job is part of.
+///  * `location_id`: The ID of the location where the job is created.
+///  * `attempt`: The number of the attempt to create the job (starting at 0).
+/// 
+/// **Returns**  
+/// The name of the job for this attempt.
+pub fn job_name(correlation_id: &str, application_id: &str, location_id: &str, attempt: u32) -> String {
+    let mut hasher = Sha256::new();
+    for part in [ correlation_id, application_id, location_id ] {
+        hasher.update(part.as_bytes());
+        hasher.update([ 0 ]);
+    }
+    hasher.update(attempt.to_be_bytes());
+    let hash: String = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    format!("{}-{}-{}", correlation_id, attempt, &hash[..HASH_LENGTH])
+}
+
+/// Returns the correlation ID of the job with the given name (i.e., everything before the first dash).
+#[inline]
+pub fn correlation_id(job_name: &str) -> &str {
+    job_name.split('-').next().unwrap_or_default()
+}
+
+/// Returns the ID of an element of a job array, which is the given ID with the index of the element appended to its correlation ID.
+/// 
+/// Since the driver takes the part before the first dash as the correlation ID, every element of the array reports its events as a job of its own.
+/// 
+/// **Arguments**
+///  * `id`: The correlation ID or name of the job array.
+///  * `index`: The index of the element in the array.
+/// 
+/// **Returns**  
+/// The ID of the element, as '<correlation_id>x<index>' followed by the rest of the given ID (if any).
+pub fn array_element_id(id: &str, index: u32) -> String {
+    match id.find('-') {
+        Some(pos) => format!("{}x{}{}", &id[..pos], index, &id[pos..]),
+        None      => format!("{}x{}", id, index),
+    }
+}
+
+/// Returns the labels that we put on every container and Kubernetes Job we create.
+/// 
+/// **Arguments**
+///  * `correlation_id`: The correlation ID of the job.
+///  * `application_id`: The ID of the application that the job is part of.
+pub fn job_labels(correlation_id: &str, application_id: &str) -> HashMap<String, String> {
+    hashmap! {
+        CORRELATION_LABEL.to_string() => correlation_id.to_string(),
+        APPLICATION_LABEL.to_string() => application_id.to_string(),
+    }
+}
+
+/// Checks whether a container or Kubernetes Job with the given labels was created by us for the given job, so that we may adopt it instead of creating it again.
+/// 
+/// **Arguments**
+///  * `labels`: The labels of the existing container or Job.
+///  * `correlation_id`: The correlation ID of the job we were going to create.
+///  * `application_id`: The ID of the application that the job is part of.
+/// 
+/// **Returns**  
+/// true if both labels are there and match, or false otherwise.
+pub fn is_ours<'a, I>(labels: I, correlation_id: &str, application_id: &str) -> bool
+where
+    I: IntoIterator<Item = (&'a String, &'a String)>,
+{
+    let (mut correlation, mut application) = (false, false);
+    for (key, value) in labels {
+        if key == CORRELATION_LABEL { correlation = value == correlation_id; }
+        else if key == APPLICATION_LABEL { application = value == application_id; }
+    }
+    correlation && application
+}
+
+/// Checks whether a container or Kubernetes Job with the given labels was created by brane-job at all, i.e., whether it has any label that starts with `LABEL_PREFIX`.
+/// 
+/// **Arguments**
+///  * `labels`: The labels of the container or Job.
+/// 
+/// **Returns**  
+/// true if at least one of the labels is a Brane label, or false otherwise.
+pub fn has_brane_label<'a, I>(labels: I) -> bool
+where
+    I: IntoIterator<Item = (&'a String, &'a String)>,
+{
+    labels.into_iter().any(|(key, _)| key.starts_with(LABEL_PREFIX))
+}
@@ -0,0 +1,209 @@
+/* NETWORKS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   14 Oct 2026, 23:41:19
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Makes sure the Docker networks that local jobs are attached to exist,
+ *   creating them if the location allows it, and removes the networks we
+ *   created again when the service shuts down.
+**/
+
+use std::collections::{HashMap, HashSet};
+
+use bollard::network::{CreateNetworkOptions, InspectNetworkOptions, ListNetworksOptions};
+use bollard::Docker;
+use tokio::sync::Mutex;
+
+use crate::errors::JobError;
+
+
+/***** CONSTANTS *****/
+/// The label that we put on the networks we create ourselves.
+pub const NETWORK_LABEL: &str = "brane";
+/// The Docker driver of the networks we create.
+pub const NETWORK_DRIVER: &str = "bridge";
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn only_unused_networks_we_created_are_removed() {
+        let created = names(&[ "brane-a", "brane-b", "brane-c" ]);
+        let attached: HashMap<String, usize> = hashmap!{
+            String::from("brane-a") => 0,
+            String::from("brane-b") => 2,
+            // Someone else's network, which we never touch
+            String::from("bridge") => 0,
+        };
+
+        // 'brane-c' could not be inspected, so we don't know if it's in use
+        assert_eq!(removable_networks(&created, &attached), vec![ String::from("brane-a") ]);
+        assert!(removable_networks(&HashSet::new(), &attached).is_empty());
+    }
+
+    #[test]
+    fn permission_errors_tell_how_to_create_the_network() {
+        let err = JobError::DockerNetworkCreateError{
+            network: String::from("brane-net"),
+            err: bollard::errors::Error::DockerResponseServerError{ status_code: 403, message: String::from("permission denied while trying to connect to the Docker daemon socket") },
+        };
+        let message = format!("{}", err);
+        assert!(message.contains("'brane-net'"));
+        assert!(message.contains("docker network create --driver bridge --label brane=true brane-net"));
+
+        // Other errors don't get the hint
+        let err = JobError::DockerNetworkCreateError{
+            network: String::from("brane-net"),
+            err: bollard::errors::Error::DockerResponseServerError{ status_code: 500, message: String::from("oops") },
+        };
+        assert!(!format!("{}", err).contains("docker network create"));
+    }
+}
+
+
+
+
+
+/***** GLOBALS *****/
+/// Keeps track of the networks we've seen (so we only check them once) and the ones we've created (so we may clean them up).
+#[derive(Debug, Default)]
+struct Networks {
+    /// The networks that we know exist
+    known   : HashSet<String>,
+    /// The networks that we created ourselves
+    created : HashSet<String>,
+}
+
+lazy_static! {
+    /// The networks used by this instance of brane-job. Locked for the whole check, so that concurrent jobs don't try to create the same network at once.
+    static ref NETWORKS: Mutex<Networks> = Mutex::new(Networks::default());
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Makes sure the given Docker network exists, creating it if allowed.
+/// 
+/// Only the first call for a network actually talks to Docker; afterwards, we assume it still exists.
+/// 
+/// **Arguments**
+///  * `docker`: The Docker daemon to check the network on.
+///  * `network`: The name of the network.
+///  * `create`: Whether to create the network if it's missing (Location::Local's `create_network`).
+/// 
+/// **Returns**  
+/// Nothing if the network exists (now), or a JobError if it doesn't and we could not or were not allowed to create it.
+pub async fn ensure_network(docker: &Docker, network: &str, create: bool) -> Result<(), JobError> {
+    let mut networks = NETWORKS.lock().await;
+    if networks.known.contains(network) { return Ok(()); }
+
+    // Ask Docker whether it exists
+    debug!("Checking if Docker network '{}' exists...", network);
+    let filters: HashMap<&str, Vec<&str>> = hashmap!{ "name" => vec![ network ] };
+    let existing = match docker.list_networks(Some(ListNetworksOptions{ filters })).await {
+        Ok(existing) => existing,
+        Err(err)     => { return Err(JobError::DockerNetworkInspectError{ network: network.to_string(), err }); }
+    };
+    // The filter also matches on parts of the name, so check for the exact one
+    if existing.iter().any(|n| n.name.as_deref() == Some(network)) {
+        networks.known.insert(network.to_string());
+        return Ok(());
+    }
+    if !create { return Err(JobError::DockerNetworkMissing{ network: network.to_string() }); }
+
+    // Create it
+    info!("Creating Docker network '{}'...", network);
+    let options = CreateNetworkOptions {
+        name            : network,
+        check_duplicate : true,
+        driver          : NETWORK_DRIVER,
+        labels          : hashmap!{ NETWORK_LABEL => "true" },
+        ..Default::default()
+    };
+    if let Err(err) = docker.create_network(options).await {
+        return Err(JobError::DockerNetworkCreateError{ network: network.to_string(), err });
+    }
+    networks.known.insert(network.to_string());
+    networks.created.insert(network.to_string());
+    Ok(())
+}
+
+
+
+/// Removes the networks that we created, as long as no containers are attached to them anymore.
+/// 
+/// Failures are logged instead of returned, as this is done while shutting down anyway.
+/// 
+/// **Arguments**
+///  * `docker`: The Docker daemon to remove the networks from.
+/// 
+/// **Returns**  
+/// The names of the networks that we removed.
+pub async fn cleanup_networks(docker: &Docker) -> Vec<String> {
+    let mut networks = NETWORKS.lock().await;
+
+    // Find out which of our networks are still in use
+    let mut attached = HashMap::with_capacity(networks.created.len());
+    for network in networks.created.iter() {
+        match docker.inspect_network(network, None::<InspectNetworkOptions<String>>).await {
+            Ok(info) => { attached.insert(network.clone(), info.containers.map(|c| c.len()).unwrap_or(0)); },
+            Err(err) => { warn!("{}", JobError::DockerNetworkInspectError{ network: network.clone(), err }); }
+        }
+    }
+
+    let mut removed = Vec::with_capacity(networks.created.len());
+    for network in removable_networks(&networks.created, &attached) {
+        debug!("Removing Docker network '{}'...", network);
+        match docker.remove_network(network).await {
+            Ok(_)    => { info!("Removed Docker network '{}'", network); removed.push(network); },
+            Err(err) => { warn!("{}", JobError::DockerNetworkRemoveError{ network, err }); },
+        }
+    }
+
+    for network in &removed {
+        networks.created.remove(network);
+        networks.known.remove(network);
+    }
+    removed
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Decides which networks may be removed when shutting down.
+/// 
+/// **Arguments**
+///  * `created`: The networks that we created ourselves.
+///  * `attached`: The number of containers attached to every network that we could inspect.
+/// 
+/// **Returns**  
+/// The networks that we created and that have no containers attached to them anymore, sorted by name. Networks we could not inspect are left alone.
+fn removable_networks(created: &HashSet<String>, attached: &HashMap<String, usize>) -> Vec<String> {
+    let mut removable: Vec<String> = created.iter().filter(|network| match attached.get(*network) {
+        Some(0)     => true,
+        Some(count) => { info!("Not removing Docker network '{}', as {} container(s) are still attached to it", network, count); false },
+        None        => false,
+    }).cloned().collect();
+    removable.sort();
+    removable
+}
@@ -0,0 +1,314 @@
+/* PRODUCER.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 23:41:09
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Sends events to the driver, keeping them within the size that Kafka
+ *   accepts for a message. Events carry the result of a job as their
+ *   payload, which can easily be larger than that. Such payloads are
+ *   written to the `payload_dir` of the job's location instead, and the
+ *   event only carries a reference to the file (see PayloadRef). If the
+ *   location has no such directory, the job fails with a CompleteFailed
+ *   event that says why, instead of the driver waiting for a result that
+ *   never comes.
+**/
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use brane_cfg::Infrastructure;
+use brane_cfg::infrastructure::InfrastructureError;
+use prost::Message;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::util::Timeout;
+
+use crate::errors::JobError;
+use crate::interface::{Event, EventKind, PayloadRef};
+use crate::metrics;
+
+
+/***** CONSTANTS *****/
+/// The default maximum size of an encoded event, in bytes (as used by brane-job's `--max-event-size`). Stays a bit below Kafka's default limit of 1MiB on messages, which also counts the key and headers.
+pub const DEFAULT_MAX_EVENT_SIZE: usize = 1_000_000;
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// An EventSink that remembers every event instead of sending it.
+    #[derive(Default)]
+    struct RecordingSink {
+        sent: Mutex<Vec<(String, Event)>>,
+    }
+
+    #[async_trait]
+    impl EventSink for RecordingSink {
+        async fn send(&self, key: &str, payload: Vec<u8>) -> Result<(), JobError> {
+            self.sent.lock().unwrap().push((key.to_string(), Event::decode(payload.as_slice()).unwrap()));
+            Ok(())
+        }
+    }
+
+    /// Creates a fresh payload directory to test with.
+    fn payload_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("brane-job-producer-test-{}-{}", std::process::id(), name));
+        if dir.exists() { fs::remove_dir_all(&dir).unwrap(); }
+        dir
+    }
+
+    /// Returns an event with a payload of the given size.
+    fn event(kind: EventKind, size: usize) -> Event {
+        Event::new(kind, "job1-abcd", "app", "loc1", "job", 6, Some(vec![ b'1'; size ]), Some(42))
+    }
+
+    /// Sends the given event with a maximum event size of 1KiB and returns what arrived at the sink.
+    fn send(payload_dirs: HashMap<String, PathBuf>, event: Event) -> (Result<(), JobError>, Vec<(String, Event)>) {
+        let sender = EventSender::new(RecordingSink::default(), 1024, payload_dirs);
+        let res = futures::executor::block_on(sender.send(String::from("job1-abcd#6"), event));
+        let sent = sender.sink.sent.into_inner().unwrap();
+        (res, sent)
+    }
+
+    #[test]
+    fn small_events_are_sent_as_is() {
+        let (res, sent) = send(HashMap::new(), event(EventKind::Finished, 512));
+        res.unwrap();
+        assert_eq!(sent, vec![ (String::from("job1-abcd#6"), event(EventKind::Finished, 512)) ]);
+    }
+
+    #[test]
+    fn large_payloads_are_written_to_the_payload_dir() {
+        let dir = payload_dir("spill");
+        let (res, sent) = send(vec![ (String::from("loc1"), dir.clone()) ].into_iter().collect(), event(EventKind::Finished, 4096));
+        res.unwrap();
+        assert_eq!(sent.len(), 1);
+
+        // The event itself is the same, except that it refers to its payload
+        let (key, sent) = &sent[0];
+        assert_eq!(key, "job1-abcd#6");
+        assert_eq!(Event{ payload: vec![], ..sent.clone() }, Event{ payload: vec![], ..event(EventKind::Finished, 4096) });
+        let reference = PayloadRef::from_payload(&sent.payload).unwrap();
+        assert!(Path::new(&reference.path).starts_with(&dir));
+        assert_eq!(reference.read(&dir).unwrap(), event(EventKind::Finished, 4096).payload);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn large_results_fail_the_job_without_payload_dir() {
+        let (res, sent) = send(HashMap::new(), event(EventKind::Finished, 4096));
+        res.unwrap();
+        assert_eq!(sent.len(), 1);
+
+        let (_, sent) = &sent[0];
+        assert_eq!(sent.kind, EventKind::CompleteFailed as i32);
+        assert_eq!((sent.identifier.as_str(), sent.order), ("job1-abcd", 6));
+        let message = String::from_utf8(sent.payload.clone()).unwrap();
+        assert!(message.contains("4096 bytes") && message.contains("payload_dir") && message.contains("'loc1'"), "Unexpected message: {}", message);
+    }
+
+    #[test]
+    fn other_large_events_are_not_sent() {
+        let (res, sent) = send(HashMap::new(), event(EventKind::Log, 4096));
+        assert!(matches!(res, Err(JobError::EventTooLarge{ max: 1024, .. })));
+        assert!(sent.is_empty());
+    }
+}
+
+
+
+
+
+/***** LIBRARY TRAITS *****/
+/// Abstracts over where encoded events are sent to, so that the EventSender can be tested without Kafka.
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    /// Sends a single, encoded event.
+    /// 
+    /// **Arguments**
+    ///  * `key`: The key of the event message.
+    ///  * `payload`: The encoded Event.
+    /// 
+    /// **Returns**  
+    /// Nothing if the event was sent, or a JobError otherwise.
+    async fn send(&self, key: &str, payload: Vec<u8>) -> Result<(), JobError>;
+}
+
+
+
+/// The EventSink that sends events on a Kafka topic.
+pub struct KafkaSink {
+    /// The producer to send the events with.
+    producer : FutureProducer,
+    /// The topic to send the events on.
+    topic    : String,
+}
+
+impl KafkaSink {
+    /// Constructor for the KafkaSink.
+    /// 
+    /// **Arguments**
+    ///  * `producer`: The producer to send the events with.
+    ///  * `topic`: The topic to send the events on.
+    #[inline]
+    pub fn new(producer: FutureProducer, topic: String) -> Self {
+        Self { producer, topic }
+    }
+}
+
+#[async_trait]
+impl EventSink for KafkaSink {
+    async fn send(&self, key: &str, payload: Vec<u8>) -> Result<(), JobError> {
+        let message = FutureRecord::to(&self.topic).key(key).payload(&payload);
+        match self.producer.send(message, Timeout::Never).await {
+            Ok(_)         => Ok(()),
+            Err((err, _)) => Err(JobError::EventSendError{ key: key.to_string(), err }),
+        }
+    }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Encodes events and sends them to an EventSink, making sure that they are not larger than the maximum event size.
+pub struct EventSender<S> {
+    /// Where the encoded events go.
+    sink         : S,
+    /// The maximum size of an encoded event, in bytes.
+    max_size     : usize,
+    /// The directories where payloads that are too large are written to, per location.
+    payload_dirs : HashMap<String, PathBuf>,
+}
+
+impl<S: EventSink> EventSender<S> {
+    /// Constructor for the EventSender.
+    /// 
+    /// **Arguments**
+    ///  * `sink`: Where the encoded events go.
+    ///  * `max_size`: The maximum size of an encoded event, in bytes.
+    ///  * `payload_dirs`: The directories where payloads that are too large are written to, per location (see `payload_dirs()`).
+    #[inline]
+    pub fn new(sink: S, max_size: usize, payload_dirs: HashMap<String, PathBuf>) -> Self {
+        Self { sink, max_size, payload_dirs }
+    }
+
+
+
+    /// Encodes the given event and sends it. If it is larger than the maximum event size, its payload is written to the payload directory of its location instead; if that's not possible, a Finished or Failed event is replaced by a CompleteFailed one that explains why.
+    /// 
+    /// **Arguments**
+    ///  * `key`: The key of the event message.
+    ///  * `event`: The Event to send.
+    /// 
+    /// **Returns**  
+    /// Nothing if the event (or what replaced it) was sent, or a JobError otherwise.
+    pub async fn send(&self, key: String, event: Event) -> Result<(), JobError> {
+        let size = event.encoded_len();
+        let event = if size > self.max_size { self.shrink(&key, event, size)? } else { event };
+
+        let mut payload = Vec::with_capacity(event.encoded_len());
+        if let Err(err) = event.encode(&mut payload) { return Err(JobError::EventEncodeError{ key, err }); }
+        self.sink.send(&key, payload).await?;
+
+        let kind = EventKind::from_i32(event.kind).unwrap_or(EventKind::Unknown);
+        metrics::EVENTS_EMITTED.with_label_values(&[&kind.to_string()]).inc();
+        Ok(())
+    }
+
+    /// Replaces an event that is too large by one that isn't.
+    /// 
+    /// **Arguments**
+    ///  * `key`: The key of the event message.
+    ///  * `event`: The Event that is too large.
+    ///  * `size`: The size of the encoded Event.
+    /// 
+    /// **Returns**  
+    /// The Event with a reference to its payload or, for Finished and Failed events that could not be written, a CompleteFailed event. Other events are an EventTooLarge error instead.
+    fn shrink(&self, key: &str, event: Event, size: usize) -> Result<Event, JobError> {
+        let kind = EventKind::from_i32(event.kind).unwrap_or(EventKind::Unknown);
+
+        // Write the payload to the location's payload directory, if it has one
+        let reason = match self.payload_dirs.get(&event.location) {
+            Some(dir) => match write_payload(dir, key, &event.payload) {
+                Ok(reference) => {
+                    let shrunk = Event{ payload: reference.to_payload(), ..event.clone() };
+                    if shrunk.encoded_len() <= self.max_size {
+                        info!("{} event (key: {}) is {} bytes; wrote its payload to '{}'", kind, key, size, reference.path);
+                        metrics::OVERSIZED_EVENTS.with_label_values(&["spilled"]).inc();
+                        return Ok(shrunk);
+                    }
+                    format!("even a reference to it does not fit in the maximum event size of {} bytes", self.max_size)
+                },
+                Err(err) => {
+                    warn!("{}", err);
+                    format!("it could not be written to the payload directory '{}' of location '{}'", dir.display(), event.location)
+                },
+            },
+            None => format!("location '{}' has no `payload_dir` (shared with the driver) to write it to", event.location),
+        };
+        metrics::OVERSIZED_EVENTS.with_label_values(&["rejected"]).inc();
+
+        // Results that don't arrive should at least fail the job, instead of leaving the driver waiting for them
+        if kind != EventKind::Finished && kind != EventKind::Failed { return Err(JobError::EventTooLarge{ key: key.to_string(), kind: kind.to_string(), size, max: self.max_size }); }
+        let message = format!("The result of the job is {} bytes, which is more than the maximum event size of {} bytes, and {}", event.payload.len(), self.max_size, reason);
+        warn!("{} event (key: {}) is too large; sending a {} event instead: {}", kind, key, EventKind::CompleteFailed, message);
+        Ok(Event{ kind: EventKind::CompleteFailed as i32, payload: message.into_bytes(), ..event })
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Collects the payload directories of the locations in the given infrastructure.
+/// 
+/// **Arguments**
+///  * `infra`: The Infrastructure to read the locations from.
+/// 
+/// **Returns**  
+/// The payload directory of every location that has one, or an InfrastructureError if the locations could not be read.
+pub fn payload_dirs(infra: &Infrastructure) -> Result<HashMap<String, PathBuf>, InfrastructureError> {
+    let mut dirs = HashMap::new();
+    for location in infra.get_locations()? {
+        if let Some(dir) = infra.get_location_metadata(&location)?.get_payload_dir() {
+            dirs.insert(location, PathBuf::from(dir));
+        }
+    }
+    Ok(dirs)
+}
+
+
+
+/// Writes the payload of an event to the given directory.
+/// 
+/// **Arguments**
+///  * `dir`: The directory to write the payload to (created if it doesn't exist yet).
+///  * `key`: The key of the event message, which names the file.
+///  * `payload`: The payload to write.
+/// 
+/// **Returns**  
+/// A PayloadRef to the written payload, or a JobError::PayloadWriteError if it could not be written.
+fn write_payload(dir: &Path, key: &str, payload: &[u8]) -> Result<PayloadRef, JobError> {
+    let name: String = key.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+    let path = dir.join(format!("{}.payload", name));
+    if let Err(err) = fs::create_dir_all(dir).and_then(|_| fs::write(&path, payload)) {
+        return Err(JobError::PayloadWriteError{ key: key.to_string(), path, err });
+    }
+    Ok(PayloadRef{ path: path.to_string_lossy().to_string(), size: payload.len() as u64 })
+}
@@ -0,0 +1,337 @@
+/* PULLS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 09:12:48
+ * Last edited:
+ *   15 Oct 2026, 09:12:48
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Reports the progress of pulling the image of a job as Pulling events,
+ *   so the user (and the driver's timeouts) know that a location is still
+ *   busy with a big image before it creates the container. Progress is
+ *   throttled to at most one event every few seconds.
+**/
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use bollard::models::CreateImageInfo;
+use k8s_openapi::api::core::v1::{Event as K8sEvent, Pod};
+use kube::api::{Api, ListParams};
+use kube::Client as KubeClient;
+use tokio::sync::mpsc::Sender;
+
+use crate::interface::{Event, EventKind, PullProgress};
+
+
+/***** CONSTANTS *****/
+/// The minimum time in between two Pulling events of the same job.
+pub const PULL_PROGRESS_INTERVAL: Duration = Duration::from_secs(3);
+/// How long we follow the image pull of a Kubernetes pod at most.
+pub const K8S_PULL_WATCH_LIMIT: Duration = Duration::from_secs(60 * 60);
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::models::ProgressDetail;
+
+    fn downloading(layer: &str, current: i64, total: i64) -> CreateImageInfo {
+        CreateImageInfo {
+            id: Some(layer.to_string()),
+            status: Some(String::from("Downloading")),
+            progress_detail: Some(ProgressDetail{ current: Some(current), total: Some(total) }),
+            ..Default::default()
+        }
+    }
+
+    fn status(layer: &str, status: &str) -> CreateImageInfo {
+        CreateImageInfo {
+            id: Some(layer.to_string()),
+            status: Some(status.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn progress_is_summed_over_layers() {
+        let start = Instant::now();
+        let mut tracker = PullTracker::new("hello:1.0.0", PULL_PROGRESS_INTERVAL);
+        tracker.observe(&status("layer1", "Pulling fs layer"), start);
+        tracker.observe(&downloading("layer1", 10, 100), start);
+        tracker.observe(&downloading("layer2", 40, 300), start);
+        assert_eq!(tracker.progress(), PullProgress{ image: String::from("hello:1.0.0"), current: 50, total: Some(400) });
+
+        // Finished layers count as fully downloaded, even without a last Downloading update
+        tracker.observe(&status("layer1", "Download complete"), start);
+        tracker.observe(&downloading("layer2", 100, 300), start);
+        let progress = tracker.progress();
+        assert_eq!((progress.current, progress.total), (200, Some(400)));
+        assert_eq!(progress.percentage(), Some(50));
+
+        // Extracting is not downloading
+        tracker.observe(&CreateImageInfo{ status: Some(String::from("Extracting")), ..downloading("layer1", 1, 100) }, start);
+        assert_eq!(tracker.progress().current, 200);
+    }
+
+    #[test]
+    fn unknown_sizes_have_no_percentage() {
+        let tracker = PullTracker::new("hello:1.0.0", PULL_PROGRESS_INTERVAL);
+        let progress = tracker.progress();
+        assert_eq!(progress.total, None);
+        assert_eq!(progress.percentage(), None);
+        assert_eq!(format!("{}", progress), "pulling image 'hello:1.0.0'...");
+    }
+
+    #[test]
+    fn progress_is_throttled() {
+        // Simulate a pull that sends an update every 100 milliseconds for ten seconds
+        let start = Instant::now();
+        let mut tracker = PullTracker::new("hello:1.0.0", PULL_PROGRESS_INTERVAL);
+        let mut reports: Vec<(Instant, PullProgress)> = vec![];
+        for i in 0..100 {
+            let now = start + Duration::from_millis(100 * i);
+            if let Some(progress) = tracker.observe(&downloading("layer1", (i + 1) as i64 * 10, 1000), now) {
+                reports.push((now, progress));
+            }
+        }
+
+        // The first update is reported right away, the others at most once per interval
+        assert_eq!(reports.len(), 4);
+        assert_eq!(reports[0].0, start);
+        for pair in reports.windows(2) {
+            assert!(pair[1].0.duration_since(pair[0].0) >= PULL_PROGRESS_INTERVAL);
+            assert!(pair[1].1.current > pair[0].1.current);
+        }
+        assert_eq!(reports.last().unwrap().1.percentage(), Some(91));
+    }
+
+    #[test]
+    fn k8s_pull_state_follows_pod_events() {
+        let reasons = |reasons: &[&str]| -> Vec<String> { reasons.iter().map(|r| r.to_string()).collect() };
+        assert_eq!(k8s_pull_state(&reasons(&[])), K8sPullState::Waiting);
+        assert_eq!(k8s_pull_state(&reasons(&[ "Scheduled" ])), K8sPullState::Waiting);
+        assert_eq!(k8s_pull_state(&reasons(&[ "Scheduled", "Pulling" ])), K8sPullState::Pulling);
+        assert_eq!(k8s_pull_state(&reasons(&[ "Scheduled", "Pulling", "Pulled" ])), K8sPullState::Done);
+        assert_eq!(k8s_pull_state(&reasons(&[ "Scheduled", "Pulling", "Failed" ])), K8sPullState::Done);
+        assert_eq!(k8s_pull_state(&reasons(&[ "Scheduled", "Started" ])), K8sPullState::Done);
+    }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Keeps track of how far Docker is with pulling an image, and decides when that is worth reporting.
+#[derive(Clone, Debug)]
+pub struct PullTracker {
+    /// The image that is being pulled
+    image    : String,
+    /// The bytes downloaded and the total size of every layer that has started downloading so far
+    layers   : HashMap<String, (u64, u64)>,
+    /// The minimum time in between two reports
+    interval : Duration,
+    /// The time of the last report, if any
+    last_report : Option<Instant>,
+}
+
+impl PullTracker {
+    /// Constructor for the PullTracker.
+    /// 
+    /// **Arguments**
+    ///  * `image`: The image that is being pulled.
+    ///  * `interval`: The minimum time in between two reports.
+    pub fn new(image: &str, interval: Duration) -> Self {
+        PullTracker {
+            image    : image.to_string(),
+            layers   : HashMap::new(),
+            interval,
+            last_report : None,
+        }
+    }
+
+    /// Processes the next update of Docker's pull stream.
+    /// 
+    /// **Arguments**
+    ///  * `info`: The update to process.
+    ///  * `now`: The current time.
+    /// 
+    /// **Returns**  
+    /// The progress so far if it's time to report it, or None if the last report is too recent.
+    pub fn observe(&mut self, info: &CreateImageInfo, now: Instant) -> Option<PullProgress> {
+        if let Some(layer) = &info.id {
+            match info.status.as_deref() {
+                Some("Downloading") => {
+                    if let Some(detail) = &info.progress_detail {
+                        if let (Some(current), Some(total)) = (detail.current, detail.total) {
+                            self.layers.insert(layer.clone(), (current.max(0) as u64, total.max(0) as u64));
+                        }
+                    }
+                },
+                Some("Download complete") | Some("Pull complete") => {
+                    if let Some((current, total)) = self.layers.get_mut(layer) { *current = *total; }
+                },
+                _ => {},
+            }
+        }
+
+        // Only report once the interval has passed since the last report
+        if let Some(last_report) = self.last_report {
+            if now.saturating_duration_since(last_report) < self.interval { return None; }
+        }
+        self.last_report = Some(now);
+        Some(self.progress())
+    }
+
+    /// Returns the progress of the pull so far.
+    pub fn progress(&self) -> PullProgress {
+        let (current, total) = self.layers.values().fold((0, 0), |(current, total), layer| (current + layer.0, total + layer.1));
+        PullProgress {
+            image : self.image.clone(),
+            current,
+            total : if self.layers.is_empty() { None } else { Some(total) },
+        }
+    }
+}
+
+
+
+/// Sends the Pulling events of a single job.
+#[derive(Clone)]
+pub struct PullReporter {
+    /// The channel to send the events (and their keys) on
+    events         : Sender<(String, Event)>,
+    /// The ID of the job that pulls the image
+    job_id         : String,
+    /// The application (session) the job belongs to
+    application_id : String,
+    /// The location that pulls the image
+    location_id    : String,
+}
+
+impl PullReporter {
+    /// Constructor for the PullReporter.
+    /// 
+    /// **Arguments**
+    ///  * `events`: The channel to send the Pulling events (and their keys) on.
+    ///  * `job_id`: The ID of the job that pulls the image.
+    ///  * `application_id`: The application (session) the job belongs to.
+    ///  * `location_id`: The location that pulls the image.
+    pub fn new(events: Sender<(String, Event)>, job_id: &str, application_id: &str, location_id: &str) -> Self {
+        PullReporter {
+            events,
+            job_id         : job_id.to_string(),
+            application_id : application_id.to_string(),
+            location_id    : location_id.to_string(),
+        }
+    }
+
+    /// Sends a Pulling event with the given progress.
+    /// 
+    /// **Arguments**
+    ///  * `progress`: The progress to report.
+    /// 
+    /// **Returns**  
+    /// Whether the event could be sent (false means the receiving end is gone).
+    pub async fn report(&self, progress: &PullProgress) -> bool {
+        debug!("Job '{}' is {}", self.job_id, progress);
+        let payload = serde_json::to_string(progress).unwrap().into_bytes();
+        let order = 0; // Like CreateRetrying, this event is part of creating the job.
+        let event = Event::new(EventKind::Pulling, &self.job_id, &self.application_id, &self.location_id, "job", order, Some(payload), None);
+        if let Err(err) = self.events.send((format!("{}#{}", self.job_id, order), event)).await {
+            warn!("Could not send Pulling event for job '{}': {}", self.job_id, err);
+            return false;
+        }
+        true
+    }
+}
+
+
+
+/// What the events of a Kubernetes pod say about pulling its image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum K8sPullState {
+    /// The pod has not started pulling yet
+    Waiting,
+    /// The pod is pulling its image
+    Pulling,
+    /// The pod is done pulling its image (or failed to do so)
+    Done,
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Follows the image pull of the pod of the given Kubernetes job, and reports it as Pulling events for as long as it lasts.
+/// 
+/// Kubernetes does not tell how many bytes it pulled, so these events only say that the pull is still going. This runs in the background; errors are logged but otherwise do not affect the job.
+/// 
+/// **Arguments**
+///  * `client`: The client of the cluster that runs the job.
+///  * `namespace`: The namespace of the job.
+///  * `job_name`: The name of the Kubernetes job (which labels its pods).
+///  * `image`: The image of the job.
+///  * `reporter`: The PullReporter to send the events with.
+pub fn spawn_k8s_pull_watch(client: KubeClient, namespace: String, job_name: String, image: String, reporter: PullReporter) {
+    tokio::spawn(async move {
+        let pods: Api<Pod> = Api::namespaced(client.clone(), &namespace);
+        let pod_events: Api<K8sEvent> = Api::namespaced(client, &namespace);
+        let pod_params = ListParams::default().labels(&format!("job-name={}", job_name));
+
+        let start = Instant::now();
+        let mut interval = tokio::time::interval(PULL_PROGRESS_INTERVAL);
+        while start.elapsed() < K8S_PULL_WATCH_LIMIT {
+            interval.tick().await;
+
+            // The pod may not have been created yet
+            let pod = match pods.list(&pod_params).await {
+                Ok(pods) => pods.items.into_iter().find_map(|pod| pod.metadata.name),
+                Err(err) => { warn!("Could not find the pod of job '{}': {}", job_name, err); return; }
+            };
+            let pod = match pod {
+                Some(pod) => pod,
+                None      => { continue; }
+            };
+
+            let event_params = ListParams::default().fields(&format!("involvedObject.name={}", pod));
+            let reasons: Vec<String> = match pod_events.list(&event_params).await {
+                Ok(events) => events.items.into_iter().filter_map(|event| event.reason).collect(),
+                Err(err)   => { warn!("Could not get the events of pod '{}': {}", pod, err); return; }
+            };
+            match k8s_pull_state(&reasons) {
+                K8sPullState::Waiting => {},
+                K8sPullState::Pulling => {
+                    if !reporter.report(&PullProgress{ image: image.clone(), current: 0, total: None }).await { return; }
+                },
+                K8sPullState::Done => { break; },
+            }
+        }
+        debug!("Stopped following the image pull of job '{}'", job_name);
+    });
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Determines whether a Kubernetes pod is pulling its image, based on the reasons of its events.
+fn k8s_pull_state(reasons: &[String]) -> K8sPullState {
+    if reasons.iter().any(|reason| matches!(reason.as_str(), "Pulled" | "Failed" | "ErrImagePull" | "BackOff" | "Created" | "Started")) {
+        K8sPullState::Done
+    } else if reasons.iter().any(|reason| reason == "Pulling") {
+        K8sPullState::Pulling
+    } else {
+        K8sPullState::Waiting
+    }
+}
@@ -0,0 +1,181 @@
+use brane_job::errors::JobError;
+use brane_job::interface::{
+    session_data_dir, Artifact, Command, CommandKind, Event, EventKind, Mount, PayloadRef, Resources, SchemaVersion, SCHEMA_VERSION, SCHEMA_VERSION_MAJOR, SCHEMA_VERSION_MINOR, SESSION_DATA_ENV,
+};
+use prost::Message;
+
+/// What a Command looks like to a sender one minor version ahead of us: same fields, plus one we don't know about.
+#[derive(Clone, PartialEq, Message)]
+struct NextMinorCommand {
+    #[prost(tag = "1", enumeration = "CommandKind")]
+    pub kind: i32,
+    #[prost(tag = "2", optional, string)]
+    pub identifier: Option<String>,
+    #[prost(tag = "3", optional, string)]
+    pub application: Option<String>,
+    #[prost(tag = "4", optional, string)]
+    pub location: Option<String>,
+    #[prost(tag = "5", optional, string)]
+    pub image: Option<String>,
+    #[prost(tag = "6", repeated, string)]
+    pub command: Vec<String>,
+    #[prost(tag = "7", repeated, message)]
+    pub mounts: Vec<Mount>,
+    #[prost(tag = "15", uint32)]
+    pub version: u32,
+    #[prost(tag = "16", string)]
+    pub priority: String,
+}
+
+fn command() -> Command {
+    Command::new(
+        CommandKind::Create,
+        Some("job-1"),
+        Some("app-1"),
+        Some("local"),
+        Some("hello-world:1.0.0"),
+        vec!["-c", "hello"],
+        None,
+    )
+}
+
+fn event() -> Event {
+    Event::new(EventKind::Created, "job-1", "app-1", "local", "job", 0, None, Some(42))
+}
+
+fn roundtrip_command(command: &Command) -> Command {
+    Command::decode(command.encode_to_vec().as_slice()).unwrap()
+}
+
+#[test]
+fn encode_sets_current_version() {
+    assert_eq!(command().version, SCHEMA_VERSION);
+    assert_eq!(event().version, SCHEMA_VERSION);
+    assert_eq!(SchemaVersion::current(), SchemaVersion{ major: SCHEMA_VERSION_MAJOR, minor: SCHEMA_VERSION_MINOR });
+}
+
+#[test]
+fn same_version_is_accepted() {
+    let decoded = roundtrip_command(&command());
+    assert_eq!(decoded, command());
+    assert!(decoded.schema_version().is_compatible());
+
+    let decoded = Event::decode(event().encode_to_vec().as_slice()).unwrap();
+    assert_eq!(decoded, event());
+    assert!(decoded.schema_version().is_compatible());
+}
+
+#[test]
+fn resources_are_optional() {
+    assert_eq!(command().resources, None);
+
+    let limited = command().with_resources(Resources{ memory_limit: Some(1 << 30), cpu_limit: None, pids_limit: Some(64) });
+    let decoded = roundtrip_command(&limited);
+    assert_eq!(decoded, limited);
+    assert_eq!(decoded.resources.unwrap().cpu_limit, None);
+}
+
+#[test]
+fn environment_is_kept() {
+    assert!(command().environment.is_empty());
+
+    let session = "2c1b8e2e-7f0a-4f3e-9a51-3f0c2b9d6e11";
+    let scoped = command().with_environment(SESSION_DATA_ENV, &session_data_dir(session));
+    let decoded = roundtrip_command(&scoped);
+    assert_eq!(decoded, scoped);
+    assert_eq!(decoded.environment[SESSION_DATA_ENV], format!("session-{}", session));
+
+    // Whatever the session is called, it stays within the data directory
+    assert_eq!(session_data_dir("../../etc"), "session-etc");
+}
+
+#[test]
+fn package_env_is_kept() {
+    assert!(command().environment.is_empty());
+
+    let configured = command().with_environment("OMP_NUM_THREADS", "8");
+    let decoded = roundtrip_command(&configured);
+    assert_eq!(decoded, configured);
+    assert_eq!(decoded.environment["OMP_NUM_THREADS"], "8");
+}
+
+#[test]
+fn arrays_are_kept() {
+    assert!(!command().is_array());
+
+    let array = command().with_array(500, 16);
+    let decoded = roundtrip_command(&array);
+    assert_eq!(decoded, array);
+    assert!(decoded.is_array());
+    assert_eq!((decoded.array_size, decoded.array_parallelism), (500, 16));
+
+    // Older senders never set the fields, which reads as a single job
+    let mut single = command();
+    single.version = SCHEMA_VERSION - 1;
+    assert!(!roundtrip_command(&single).is_array());
+}
+
+#[test]
+fn newer_minor_version_is_accepted() {
+    let original = command();
+    let next = NextMinorCommand {
+        kind: original.kind,
+        identifier: original.identifier.clone(),
+        application: original.application.clone(),
+        location: original.location.clone(),
+        image: original.image.clone(),
+        command: original.command.clone(),
+        mounts: original.mounts.clone(),
+        version: SCHEMA_VERSION + 1,
+        priority: String::from("high"),
+    };
+
+    let decoded = Command::decode(next.encode_to_vec().as_slice()).unwrap();
+    assert_eq!(decoded.schema_version(), SchemaVersion{ major: SCHEMA_VERSION_MAJOR, minor: SCHEMA_VERSION_MINOR + 1 });
+    assert!(decoded.schema_version().is_compatible());
+    assert_eq!(decoded.identifier, original.identifier);
+    assert_eq!(decoded.command, original.command);
+}
+
+#[test]
+fn other_major_version_is_rejected() {
+    let mut newer = command();
+    newer.version = ((SCHEMA_VERSION_MAJOR as u32 + 1) << 16) | 3;
+    let decoded = roundtrip_command(&newer);
+    assert!(!decoded.schema_version().is_compatible());
+
+    let message = format!("{}", JobError::CommandSchemaMismatch{ key: String::from("job-1"), version: decoded.schema_version() });
+    assert!(message.contains(&format!("{}.3", SCHEMA_VERSION_MAJOR + 1)));
+    assert!(message.contains("upgrade this service"));
+}
+
+#[test]
+fn unversioned_message_is_rejected() {
+    let mut old = event();
+    old.version = 0;
+    let decoded = Event::decode(old.encode_to_vec().as_slice()).unwrap();
+    assert_eq!(decoded.schema_version(), SchemaVersion{ major: 0, minor: 0 });
+    assert!(!decoded.schema_version().is_compatible());
+    assert!(decoded.schema_version().migration_advice().contains("predates schema versioning"));
+}
+
+#[test]
+fn payload_refs_are_recognised() {
+    let reference = PayloadRef{ path: String::from("/brane/payloads/job-1_6.payload"), size: 4096 };
+    assert_eq!(PayloadRef::from_payload(&reference.to_payload()), Some(reference));
+
+    // Actual payloads are not references, even if they look a bit like one
+    assert_eq!(PayloadRef::from_payload(b"{\"v\":\"integer\",\"c\":42}"), None);
+    assert_eq!(PayloadRef::from_payload(b"{\"$payload_ref\":{\"path\":\"/x\",\"size\":1},\"v\":\"unit\"}"), None);
+    assert_eq!(PayloadRef::from_payload(b"Traceback (most recent call last)"), None);
+}
+
+#[test]
+fn artifacts_are_read_from_finished_payloads() {
+    let payload = "{\"v\":\"unit\",\"stats\":{\"wall_time\":1.5},\"artifacts\":[{\"name\":\"out/plot.png\",\"size\":42,\"path\":\"/data/artifacts/job-1/out/plot.png\",\"sha256\":\"ab12\"}]}";
+    assert_eq!(Artifact::from_payload(payload), Some(vec![ Artifact{ name: String::from("out/plot.png"), size: 42, path: String::from("/data/artifacts/job-1/out/plot.png"), sha256: String::from("ab12") } ]));
+
+    // Payloads of older branelets (or of functions without outputs) have none
+    assert_eq!(Artifact::from_payload("{\"v\":\"unit\",\"stats\":{\"wall_time\":1.5}}"), None);
+    assert_eq!(Artifact::from_payload("{\"v\":\"unit\",\"artifacts\":[{\"name\":\"plot.png\"}]}"), None);
+}
@@ -0,0 +1,118 @@
+/* ARRAY.rs
+ *   by Lut99
+ *
+ * Created:
+ *   16 Oct 2026, 00:00:19
+ * Last edited:
+ *   16 Oct 2026, 00:00:19
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Lets the branelet run one element of a job array. All elements get
+ *   the same command, with the arguments of every element in it; each
+ *   element picks its own arguments by the index that the location gives
+ *   it, and reports to the driver as a job of its own.
+**/
+
+use brane_job::interface::ARRAY_INDEX_ENV;
+
+use crate::errors::LetError;
+
+
+/***** CONSTANTS *****/
+/// The environment variables that may tell an element of a job array which one it is, in order of preference: the one we set ourselves, then the one of Kubernetes Indexed Jobs, then the one of Slurm array jobs.
+pub const INDEX_ENVS: [&str; 3] = [ ARRAY_INDEX_ENV, "JOB_COMPLETION_INDEX", "SLURM_ARRAY_TASK_ID" ];
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Returns a lookup function for the given environment variables.
+    fn env(vars: &[ (&str, &str) ]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn index_comes_from_the_location() {
+        assert_eq!(element_index(4, env(&[ ("JOB_COMPLETION_INDEX", "2") ])).unwrap(), 2);
+        assert_eq!(element_index(4, env(&[ ("SLURM_ARRAY_TASK_ID", "3") ])).unwrap(), 3);
+        // Our own variable wins
+        assert_eq!(element_index(4, env(&[ (ARRAY_INDEX_ENV, "0"), ("SLURM_ARRAY_TASK_ID", "3") ])).unwrap(), 0);
+
+        assert!(matches!(element_index(4, env(&[])), Err(LetError::MissingArrayIndex{ size: 4 })));
+        for raw in [ "4", "-1", "one", "" ] {
+            let err = element_index(4, env(&[ ("JOB_COMPLETION_INDEX", raw) ])).unwrap_err();
+            assert!(matches!(&err, LetError::IllegalArrayIndex{ name, .. } if name == "JOB_COMPLETION_INDEX"), "Unexpected error for '{}': {}", raw, err);
+        }
+    }
+
+    #[test]
+    fn element_gets_its_own_arguments() {
+        let arguments = base64::encode("[{\"n\":1},{\"n\":2}]");
+        assert_eq!(base64::decode(element_arguments(arguments.clone(), Some(1)).unwrap()).unwrap(), b"{\"n\":2}");
+        // Jobs that aren't part of an array get theirs as-is
+        assert_eq!(element_arguments(arguments.clone(), None).unwrap(), arguments);
+
+        assert!(matches!(element_arguments(arguments, Some(2)), Err(LetError::MissingArrayArguments{ index: 2, len: 2 })));
+        assert!(matches!(element_arguments(base64::encode("{\"n\":1}"), Some(0)), Err(LetError::ArgumentsJSONError{ .. })));
+        assert!(matches!(element_arguments(String::from("not base64!"), Some(0)), Err(LetError::ArgumentsBase64Error{ .. })));
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Finds out which element of a job array we are.
+/// 
+/// **Arguments**
+///  * `size`: The number of elements in the array.
+///  * `lookup`: Looks up the value of an environment variable (i.e., `std::env::var()`, or a map in tests).
+/// 
+/// **Returns**  
+/// The index of our element, taken from the first of `INDEX_ENVS` that is set. If none of them is, or the index is not a number below `size`, a LetError is returned instead.
+pub fn element_index<F: Fn(&str) -> Option<String>>(size: u32, lookup: F) -> Result<u32, LetError> {
+    for name in INDEX_ENVS {
+        if let Some(raw) = lookup(name) {
+            return match raw.trim().parse::<u32>() {
+                Ok(index) if index < size => Ok(index),
+                _                         => Err(LetError::IllegalArrayIndex{ name: name.to_string(), raw, size }),
+            };
+        }
+    }
+    Err(LetError::MissingArrayIndex{ size })
+}
+
+/// Picks the arguments of our element from the arguments of a job array.
+/// 
+/// **Arguments**
+///  * `arguments`: The input arguments as given on the command line (i.e., Base64-encoded JSON).
+///  * `index`: The index of our element in the job array, or None if the job is not part of one.
+/// 
+/// **Returns**  
+/// The input arguments of our element, encoded like `arguments`, or the arguments as-is if the job is not part of an array. If the arguments are not a JSON array with an entry for our element, a LetError is returned instead.
+pub fn element_arguments(arguments: String, index: Option<u32>) -> Result<String, LetError> {
+    let index = match index {
+        Some(index) => index,
+        None        => { return Ok(arguments); }
+    };
+
+    // Decode the arguments of all elements
+    let raw = base64::decode(arguments).map_err(|err| LetError::ArgumentsBase64Error{ err })?;
+    let raw = String::from_utf8(raw).map_err(|err| LetError::ArgumentsUTF8Error{ err })?;
+    let mut elements: Vec<serde_json::Value> = serde_json::from_str(&raw).map_err(|err| LetError::ArgumentsJSONError{ err })?;
+
+    // Encode ours like the arguments of any other job
+    let len = elements.len();
+    if index as usize >= len { return Err(LetError::MissingArrayArguments{ index, len }); }
+    Ok(base64::encode(elements.swap_remove(index as usize).to_string()))
+}
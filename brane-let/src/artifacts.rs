@@ -0,0 +1,286 @@
+/* ARTIFACTS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   16 Oct 2026, 00:00:17
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Collects the files that a function declares as its outputs once the
+ *   package is done, and copies them to shared storage (the mounted DFS
+ *   or a directory configured by the location) so that later calls can
+ *   use them.
+**/
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use brane_job::interface::Artifact;
+use glob::{MatchOptions, Pattern};
+use sha2::{Digest, Sha256};
+
+use crate::errors::LetError;
+
+
+/***** CONSTANTS *****/
+/// The directory in the artifact store that holds the artifacts of every job, each in a subdirectory named after the job.
+pub const ARTIFACTS_DIR: &str = "artifacts";
+
+/// The default maximum size (in bytes) of a single artifact, if BRANE_ARTIFACT_MAX_SIZE is not given.
+pub const MAX_ARTIFACT_SIZE: u64 = 1024 * 1024 * 1024;
+/// The default maximum size (in bytes) of all artifacts of a job together, if BRANE_ARTIFACT_MAX_TOTAL is not given.
+pub const MAX_ARTIFACTS_TOTAL: u64 = 4 * 1024 * 1024 * 1024;
+
+/// The size of the chunks in which artifacts are copied (and hashed).
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the given files (relative to the given directory).
+    fn write_files(dir: &Path, files: &[(&str, &str)]) {
+        for (name, contents) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> { patterns.iter().map(|p| p.to_string()).collect() }
+
+    #[test]
+    fn matching_files_are_copied_and_hashed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let (run_dir, root) = (dir.join("wd"), dir.join("store"));
+        write_files(&run_dir, &[ ("results/a.csv", "1,2,3"), ("results/b.csv", ""), ("results/nested/c.csv", "x"), ("plot.png", "png"), ("notes.txt", "no") ]);
+
+        let store = ArtifactStore::new(&root, "job-1", None, None).unwrap();
+        let artifacts = store.collect(&run_dir, &patterns(&[ "results/*.csv", "./plot.png" ])).unwrap();
+        let names: Vec<&str> = artifacts.iter().map(|a| a.name.as_str()).collect();
+        // '*' does not cross directories
+        assert_eq!(names, vec![ "plot.png", "results/a.csv", "results/b.csv" ]);
+
+        let csv = &artifacts[1];
+        assert_eq!(csv.size, 5);
+        assert_eq!(csv.sha256, format!("{:x}", Sha256::digest(b"1,2,3")));
+        assert_eq!(PathBuf::from(&csv.path), root.join(ARTIFACTS_DIR).join("job-1").join("results").join("a.csv"));
+        assert_eq!(fs::read_to_string(&csv.path).unwrap(), "1,2,3");
+        // Empty files are artifacts too
+        assert_eq!(artifacts[2].sha256, format!("{:x}", Sha256::digest(b"")));
+
+        // Patterns that match nothing are no error
+        assert!(store.collect(&run_dir, &patterns(&[ "*.pdf" ])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn limits_are_enforced_before_copying() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let (run_dir, root) = (dir.join("wd"), dir.join("store"));
+        write_files(&run_dir, &[ ("small.bin", "1234"), ("large.bin", "1234567890") ]);
+
+        let store = ArtifactStore::new(&root, "job-1", Some(8), None).unwrap();
+        match store.collect(&run_dir, &patterns(&[ "*.bin" ])) {
+            Err(LetError::ArtifactTooLarge{ name, size, max }) => { assert_eq!(name, "large.bin"); assert_eq!(size, 10); assert_eq!(max, 8); },
+            res => panic!("Expected an artifact that is too large, got {:?}", res.map(|_| ())),
+        }
+
+        let store = ArtifactStore::new(&root, "job-1", None, Some(12)).unwrap();
+        match store.collect(&run_dir, &patterns(&[ "*.bin" ])) {
+            Err(LetError::ArtifactsTooLarge{ total, max }) => { assert_eq!(total, 14); assert_eq!(max, 12); },
+            res => panic!("Expected artifacts that are too large together, got {:?}", res.map(|_| ())),
+        }
+
+        // Nothing was copied at all
+        assert!(!root.join(ARTIFACTS_DIR).exists());
+        assert_eq!(store.collect(&run_dir, &patterns(&[ "small.bin" ])).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn store_is_not_collected_from_itself() {
+        // Without a session mount, the package runs in the same directory that the artifacts are stored in
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        write_files(&dir, &[ ("out.txt", "hello") ]);
+
+        let store = ArtifactStore::new(&dir, "job-1", None, None).unwrap();
+        assert_eq!(store.collect(&dir, &patterns(&[ "**/*.txt" ])).unwrap().len(), 1);
+        let store = ArtifactStore::new(&dir, "job-2", None, None).unwrap();
+        let artifacts = store.collect(&dir, &patterns(&[ "**/*.txt" ])).unwrap();
+        assert_eq!(artifacts.iter().map(|a| a.name.as_str()).collect::<Vec<&str>>(), vec![ "out.txt" ]);
+    }
+
+    #[test]
+    fn illegal_input_is_rejected() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        for job_id in [ "", "..", "a/b", "/job" ] {
+            assert!(matches!(ArtifactStore::new(&dir, job_id, None, None), Err(LetError::IllegalArtifactJobId{ .. })), "Job ID '{}' was accepted", job_id);
+        }
+
+        let store = ArtifactStore::new(&dir, "job-1", None, None).unwrap();
+        assert!(matches!(store.collect(&dir, &patterns(&[ "[unclosed" ])), Err(LetError::IllegalArtifactPattern{ .. })));
+    }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Stores the artifacts of a job in shared storage, under `<root>/artifacts/<job ID>/`.
+#[derive(Clone, Debug)]
+pub struct ArtifactStore {
+    /// The directory (on shared storage) to store the artifacts under
+    root      : PathBuf,
+    /// The ID of the job whose artifacts we store
+    job_id    : String,
+    /// The maximum size of a single artifact, in bytes
+    max_size  : u64,
+    /// The maximum size of all artifacts together, in bytes
+    max_total : u64,
+}
+
+impl ArtifactStore {
+    /// Constructor for the ArtifactStore.
+    /// 
+    /// **Arguments**
+    ///  * `root`: The directory (on shared storage) to store the artifacts under.
+    ///  * `job_id`: The ID of the job whose artifacts we store.
+    ///  * `max_size`: The maximum size of a single artifact, in bytes, if not MAX_ARTIFACT_SIZE.
+    ///  * `max_total`: The maximum size of all artifacts together, in bytes, if not MAX_ARTIFACTS_TOTAL.
+    /// 
+    /// **Returns**  
+    /// The new ArtifactStore, or a LetError if the job ID is not a single directory name.
+    pub fn new(root: impl Into<PathBuf>, job_id: &str, max_size: Option<u64>, max_total: Option<u64>) -> Result<Self, LetError> {
+        let mut components = Path::new(job_id).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => {},
+            _                                  => { return Err(LetError::IllegalArtifactJobId{ job_id: job_id.to_string() }); }
+        }
+
+        Ok(Self {
+            root      : root.into(),
+            job_id    : job_id.to_string(),
+            max_size  : max_size.unwrap_or(MAX_ARTIFACT_SIZE),
+            max_total : max_total.unwrap_or(MAX_ARTIFACTS_TOTAL),
+        })
+    }
+
+
+
+    /// Collects the files in the given directory that match any of the given patterns, and copies them to the store.
+    /// 
+    /// The limits are checked before anything is copied, so a job that produces too much leaves nothing behind.
+    /// 
+    /// **Arguments**
+    ///  * `run_dir`: The directory that the package ran in, which the patterns are relative to.
+    ///  * `patterns`: The glob patterns of the files to collect (as declared in the `outputs` of the function).
+    /// 
+    /// **Returns**  
+    /// The collected Artifacts, sorted by name, or a LetError if a pattern is invalid, the files are too large or they could not be copied.
+    pub fn collect(&self, run_dir: &Path, patterns: &[String]) -> Result<Vec<Artifact>, LetError> {
+        let patterns: Vec<Pattern> = patterns.iter()
+            .map(|pattern| Pattern::new(pattern.trim_start_matches("./")).map_err(|err| LetError::IllegalArtifactPattern{ pattern: pattern.clone(), err }))
+            .collect::<Result<_, _>>()?;
+        let options = MatchOptions{ case_sensitive: true, require_literal_separator: true, require_literal_leading_dot: false };
+
+        // Find what matches, without descending into the artifacts we (or earlier jobs) stored already
+        let mut files: Vec<(String, PathBuf, u64)> = vec![];
+        find_files(run_dir, "", &self.root.join(ARTIFACTS_DIR), &mut files)?;
+        files.retain(|(name, _, _)| patterns.iter().any(|pattern| pattern.matches_with(name, options)));
+        files.sort_by(|(lhs, _, _), (rhs, _, _)| lhs.cmp(rhs));
+
+        // Check the limits before we copy anything
+        let mut total: u64 = 0;
+        for (name, _, size) in &files {
+            if *size > self.max_size { return Err(LetError::ArtifactTooLarge{ name: name.clone(), size: *size, max: self.max_size }); }
+            total += size;
+        }
+        if total > self.max_total { return Err(LetError::ArtifactsTooLarge{ total, max: self.max_total }); }
+
+        // Copy them over
+        let target_dir = self.dir();
+        let mut artifacts = Vec::with_capacity(files.len());
+        for (name, source, _) in files {
+            let target = target_dir.join(&name);
+            let (size, sha256) = copy_hashed(&source, &target).map_err(|err| LetError::ArtifactCopyError{ name: name.clone(), path: target.clone(), err })?;
+            debug!("Stored artifact '{}' ({} bytes) as '{}'", name, size, target.display());
+            artifacts.push(Artifact{ name, size, path: target.to_string_lossy().to_string(), sha256 });
+        }
+        Ok(artifacts)
+    }
+
+
+
+    /// Returns the directory that the artifacts of the job are stored in.
+    #[inline]
+    pub fn dir(&self) -> PathBuf { self.root.join(ARTIFACTS_DIR).join(&self.job_id) }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Recursively lists the regular files in the given directory, skipping symbolic links (which may point anywhere) and the given directory.
+/// 
+/// **Arguments**
+///  * `dir`: The directory to list.
+///  * `prefix`: The path of `dir` relative to the directory we started in, with a trailing slash (or empty for that directory itself).
+///  * `skip`: A directory not to descend into.
+///  * `files`: The list to add the (relative path, path, size) of every file to.
+fn find_files(dir: &Path, prefix: &str, skip: &Path, files: &mut Vec<(String, PathBuf, u64)>) -> Result<(), LetError> {
+    let entries = fs::read_dir(dir).map_err(|err| LetError::ArtifactReadError{ path: dir.to_path_buf(), err })?;
+    for entry in entries {
+        let entry = entry.map_err(|err| LetError::ArtifactReadError{ path: dir.to_path_buf(), err })?;
+        let path = entry.path();
+        let metadata = fs::symlink_metadata(&path).map_err(|err| LetError::ArtifactReadError{ path: path.clone(), err })?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+
+        if metadata.is_dir() {
+            if path != skip { find_files(&path, &format!("{}/", name), skip, files)?; }
+        } else if metadata.is_file() {
+            files.push((name, path, metadata.len()));
+        }
+    }
+    Ok(())
+}
+
+/// Copies the given file, computing its SHA-256 checksum along the way.
+/// 
+/// **Arguments**
+///  * `source`: The file to copy.
+///  * `target`: The path to copy it to. Its parent directories are created if they don't exist.
+/// 
+/// **Returns**  
+/// The number of bytes copied and the hex-encoded checksum, or an std::io::Error if the file could not be copied.
+fn copy_hashed(source: &Path, target: &Path) -> std::io::Result<(u64, String)> {
+    if let Some(parent) = target.parent() { fs::create_dir_all(parent)?; }
+    let mut input = File::open(source)?;
+    let mut output = File::create(target)?;
+
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; COPY_CHUNK_SIZE];
+    let mut size: u64 = 0;
+    loop {
+        let n = input.read(&mut buffer)?;
+        if n == 0 { break; }
+        hasher.update(&buffer[..n]);
+        output.write_all(&buffer[..n])?;
+        size += n as u64;
+    }
+    output.flush()?;
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
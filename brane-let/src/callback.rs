@@ -1,19 +1,276 @@
-use anyhow::Result;
-use brane_clb::grpc::{CallbackKind, CallbackRequest, CallbackServiceClient};
+use async_trait::async_trait;
+use brane_clb::grpc::{CallbackBatchRequest, CallbackKind, CallbackRequest, CallbackServiceClient};
 use brane_job::interface::FailureResult;
 use libc::{strsignal, c_int, c_char};
-use log::debug;
+use log::{debug, warn};
+use std::collections::VecDeque;
 use std::error::Error;
 use std::ffi::CStr;
 use std::fmt::{Display, Formatter, Result as FResult};
-use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Mutex};
+use tokio::task::JoinHandle;
 use tonic::transport::Channel;
 
+use crate::common::HEARTBEAT_DELAY;
+
 
 /***** CONSTANTS *****/
 /// The default name of a signal in case strsignal fails.
 const UNKNOWN_SIGNAL_NAME: &str = "UNKNOWN";
 
+/// The default number of callbacks we buffer while the connection is down.
+const DEFAULT_MAX_PENDING: usize = 64;
+/// The default time we wait before the first reconnection attempt.
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+/// The default maximum time we wait between reconnection attempts.
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+/// The default time we keep trying to deliver the final (Finished or Failed) callback.
+const DEFAULT_FINAL_DEADLINE: Duration = Duration::from_secs(120);
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Keeps track of what the MockTransport has done, shared with the test.
+    #[derive(Default)]
+    struct MockState {
+        /// The (kind, order) pairs of the callbacks delivered so far
+        sent       : Mutex<Vec<(i32, i32)>>,
+        /// The orders of the callbacks delivered per batch
+        batches    : Mutex<Vec<Vec<i32>>>,
+        /// If true, every send fails
+        broken     : AtomicBool,
+        /// The number of sends that should fail before it starts working (again)
+        fail_sends : AtomicUsize,
+        /// The number of times we've been asked to reconnect
+        reconnects : AtomicUsize,
+    }
+
+    /// A transport that delivers callbacks to a list instead of to a remote.
+    struct MockTransport(Arc<MockState>);
+
+    #[async_trait]
+    impl CallbackTransport for MockTransport {
+        async fn send(&mut self, request: CallbackRequest) -> Result<(), CallbackError> {
+            let fail_now = self.0.fail_sends.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| if n > 0 { Some(n - 1) } else { None }).is_ok();
+            if fail_now || self.0.broken.load(Ordering::SeqCst) {
+                return Err(CallbackError::SendError{ kind: format!("{:?}", CallbackKind::from_i32(request.kind)), err: tonic::Status::unavailable("connection reset") });
+            }
+            self.0.sent.lock().unwrap().push((request.kind, request.order));
+            Ok(())
+        }
+
+        async fn send_batch(&mut self, requests: Vec<CallbackRequest>) -> Result<(), CallbackError> {
+            if self.0.broken.load(Ordering::SeqCst) {
+                return Err(CallbackError::SendError{ kind: format!("batch of {}", requests.len()), err: tonic::Status::unavailable("connection reset") });
+            }
+            self.0.batches.lock().unwrap().push(requests.iter().map(|request| request.order).collect());
+            self.0.sent.lock().unwrap().extend(requests.iter().map(|request| (request.kind, request.order)));
+            Ok(())
+        }
+
+        async fn reconnect(&mut self) -> Result<(), CallbackError> {
+            self.0.reconnects.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn callback(options: CallbackOptions) -> (Callback, Arc<MockState>) {
+        let state = Arc::new(MockState::default());
+        (Callback::with_transport("app", "loc", "job", Box::new(MockTransport(state.clone())), options), state)
+    }
+
+    fn fast_options() -> CallbackOptions {
+        CallbackOptions {
+            max_pending     : 4,
+            initial_backoff : Duration::from_millis(0),
+            max_backoff     : Duration::from_millis(5),
+            final_deadline  : Duration::from_millis(200),
+            batch_window    : Duration::from_millis(0),
+        }
+    }
+
+    #[tokio::test]
+    async fn buffered_callbacks_are_flushed_in_order() {
+        let (mut callback, state) = callback(fast_options());
+
+        // While the connection is down, nothing is sent but nothing is lost either
+        state.broken.store(true, Ordering::SeqCst);
+        callback.ready().await.unwrap();
+        callback.initialized().await.unwrap();
+        callback.started().await.unwrap();
+        assert!(state.sent.lock().unwrap().is_empty());
+        assert_eq!(callback.pending().await, 3);
+
+        // Once it's back, the next callback flushes the buffer first
+        state.broken.store(false, Ordering::SeqCst);
+        callback.heartbeat().await.unwrap();
+        assert_eq!(callback.pending().await, 0);
+        assert_eq!(*state.sent.lock().unwrap(), vec![
+            (CallbackKind::Ready as i32, 1),
+            (CallbackKind::Initialized as i32, 2),
+            (CallbackKind::Started as i32, 3),
+            (CallbackKind::Heartbeat as i32, 4),
+        ]);
+        assert!(state.reconnects.load(Ordering::SeqCst) >= 1);
+    }
+
+    #[tokio::test]
+    async fn full_buffer_drops_heartbeats_first() {
+        let (mut callback, state) = callback(fast_options());
+        state.broken.store(true, Ordering::SeqCst);
+
+        callback.ready().await.unwrap();
+        callback.heartbeat().await.unwrap();
+        callback.initialized().await.unwrap();
+        callback.started().await.unwrap();
+        // The buffer is full, so the heartbeat has to make way
+        callback.completed().await.unwrap();
+        assert_eq!(callback.pending().await, 4);
+
+        // Without heartbeats left, new heartbeats are dropped instead (a later one tells the same)...
+        callback.heartbeat().await.unwrap();
+        assert_eq!(callback.pending().await, 4);
+        // ...but lifecycle callbacks are never dropped
+        callback.complete_failed(String::from("too bad")).await.unwrap_err();
+        assert_eq!(callback.pending().await, 5);
+
+        state.broken.store(false, Ordering::SeqCst);
+        callback.finished(String::from("{}")).await.unwrap();
+        let kinds: Vec<i32> = state.sent.lock().unwrap().iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(kinds, vec![CallbackKind::Ready as i32, CallbackKind::Initialized as i32, CallbackKind::Started as i32, CallbackKind::Completed as i32, CallbackKind::CompleteFailed as i32, CallbackKind::Finished as i32]);
+    }
+
+    #[tokio::test]
+    async fn buffered_callbacks_are_retried_in_the_background() {
+        let (mut callback, state) = callback(fast_options());
+        state.broken.store(true, Ordering::SeqCst);
+        callback.ready().await.unwrap();
+        callback.started().await.unwrap();
+        assert_eq!(callback.pending().await, 2);
+
+        // No other callback has to come along to deliver them once the connection is back
+        state.broken.store(false, Ordering::SeqCst);
+        let start = Instant::now();
+        while callback.pending().await > 0 {
+            assert!(start.elapsed() < Duration::from_millis(100), "Buffered callbacks were not retried");
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(*state.sent.lock().unwrap(), vec![(CallbackKind::Ready as i32, 1), (CallbackKind::Started as i32, 2)]);
+    }
+
+    #[tokio::test]
+    async fn failure_callbacks_block_until_delivered() {
+        let (mut callback, state) = callback(fast_options());
+
+        // These are the last thing a branelet sends before it exits, so they can't wait for the next one
+        state.fail_sends.store(3, Ordering::SeqCst);
+        callback.stopped(9).await.unwrap();
+        state.fail_sends.store(3, Ordering::SeqCst);
+        callback.decode_failed(String::from("not JSON")).await.unwrap();
+        assert_eq!(callback.pending().await, 0);
+        assert_eq!(*state.sent.lock().unwrap(), vec![(CallbackKind::Stopped as i32, 1), (CallbackKind::DecodeFailed as i32, 2)]);
+
+        state.broken.store(true, Ordering::SeqCst);
+        assert!(matches!(callback.initialize_failed(String::from("no such file")).await, Err(CallbackError::DeliveryTimeout{ .. })));
+    }
+
+    #[tokio::test]
+    async fn final_callback_blocks_until_delivered() {
+        let (mut callback, state) = callback(fast_options());
+        state.fail_sends.store(3, Ordering::SeqCst);
+
+        callback.ready().await.unwrap();
+        callback.finished(String::from("{}")).await.unwrap();
+        assert_eq!(callback.pending().await, 0);
+        assert_eq!(*state.sent.lock().unwrap(), vec![(CallbackKind::Ready as i32, 1), (CallbackKind::Finished as i32, 2)]);
+    }
+
+    #[tokio::test]
+    async fn final_callback_gives_up_after_deadline() {
+        let (mut callback, state) = callback(fast_options());
+        state.broken.store(true, Ordering::SeqCst);
+
+        let start = Instant::now();
+        assert!(matches!(callback.failed(1, String::new(), String::new()).await, Err(CallbackError::DeliveryTimeout{ .. })));
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert!(state.sent.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn heartbeats_stop_when_told() {
+        let (mut callback, state) = callback(fast_options());
+        let heartbeats = || state.sent.lock().unwrap().iter().filter(|(kind, _)| *kind == CallbackKind::Heartbeat as i32).count();
+
+        let heartbeat = Heartbeat::start(callback.clone(), Duration::from_millis(10));
+        tokio::time::sleep(Duration::from_millis(55)).await;
+        heartbeat.stop().await;
+        let sent = heartbeats();
+        assert!(sent >= 2);
+
+        // Nothing is sent afterwards, and the task has let go of the connection
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(heartbeats(), sent);
+        assert_eq!(Arc::strong_count(&callback.state), 1);
+
+        // The final callback still comes last
+        callback.finished(String::from("{}")).await.unwrap();
+        let sent = state.sent.lock().unwrap();
+        assert_eq!(sent.last().unwrap().0, CallbackKind::Finished as i32);
+        assert!(sent.windows(2).all(|w| w[0].1 < w[1].1));
+    }
+
+    #[test]
+    fn batch_window_follows_heartbeat_interval() {
+        assert_eq!(max_batch_window(Duration::from_millis(5000)), Duration::from_millis(2500));
+        assert_eq!(CallbackOptions::default().batch_window, max_batch_window(Duration::from_millis(HEARTBEAT_DELAY)));
+        assert!(max_batch_window(Duration::from_millis(1)) < Duration::from_millis(1));
+    }
+
+    #[tokio::test]
+    async fn heartbeats_are_batched_in_order() {
+        let (mut callback, state) = callback(CallbackOptions{ batch_window: Duration::from_millis(50), ..fast_options() });
+
+        // Heartbeats wait for the window to pass...
+        callback.ready().await.unwrap();
+        callback.heartbeat().await.unwrap();
+        callback.heartbeat().await.unwrap();
+        assert_eq!(*state.sent.lock().unwrap(), vec![(CallbackKind::Ready as i32, 1)]);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(callback.pending().await, 0);
+
+        // ...unless a lifecycle callback comes along, which takes them with it
+        callback.heartbeat().await.unwrap();
+        callback.finished(String::from("{}")).await.unwrap();
+        assert_eq!(*state.batches.lock().unwrap(), vec![ vec![2, 3], vec![4, 5] ]);
+        let sent = state.sent.lock().unwrap();
+        assert_eq!(sent.iter().map(|(_, order)| *order).collect::<Vec<i32>>(), vec![1, 2, 3, 4, 5]);
+        assert_eq!(sent.last().unwrap().0, CallbackKind::Finished as i32);
+    }
+
+    #[tokio::test]
+    async fn heartbeats_stop_when_dropped() {
+        let (callback, _state) = callback(fast_options());
+        drop(Heartbeat::start(callback.clone(), Duration::from_millis(10)));
+
+        // The task notices on its next poll
+        let start = Instant::now();
+        while Arc::strong_count(&callback.state) > 1 {
+            assert!(start.elapsed() < Duration::from_millis(100), "Heartbeat task outlived its Heartbeat");
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+}
+
 
 
 
@@ -26,6 +283,8 @@ pub enum CallbackError {
     ConnectError{ address: String, err: tonic::transport::Error },
     /// Could not send a callback
     SendError{ kind: String, err: tonic::Status },
+    /// The final callback could not be delivered before the deadline passed
+    DeliveryTimeout{ kind: String, pending: usize, deadline: Duration, err: Option<Box<CallbackError>> },
 
     /// Could not serialize a given struct of code, stdout & stderr
     FailureSerializeError{ err: serde_json::Error },
@@ -36,6 +295,10 @@ impl Display for CallbackError {
         match self {
             CallbackError::ConnectError{ address, err } => write!(f, "Could not connect to remote gRPC callback server at '{}': {}", address, err),
             CallbackError::SendError{ kind, err }       => write!(f, "Could not send {} callback:  status {}", kind, err),
+            CallbackError::DeliveryTimeout{ kind, pending, deadline, err } => match err {
+                Some(err) => write!(f, "Could not deliver {} callback within {} seconds ({} callback(s) undelivered): {}", kind, deadline.as_secs(), pending, err),
+                None      => write!(f, "Could not deliver {} callback within {} seconds ({} callback(s) undelivered)", kind, deadline.as_secs(), pending),
+            },
 
             CallbackError::FailureSerializeError{ err } => write!(f, "Could not serialize output from failed job: {}", err),
         }
@@ -48,18 +311,234 @@ impl Error for CallbackError {}
 
 
 
+/***** TRANSPORTS *****/
+/// Abstracts over the connection to the remote callback node, so that a Callback can be tested without one.
+#[async_trait]
+pub trait CallbackTransport: Send {
+    /// Sends a single callback.
+    /// 
+    /// **Arguments**
+    ///  * `request`: The callback to send.
+    /// 
+    /// **Returns**  
+    /// Nothing if the callback was delivered, or a CallbackError otherwise.
+    async fn send(&mut self, request: CallbackRequest) -> Result<(), CallbackError>;
+
+    /// Sends a number of callbacks as a single message.
+    /// 
+    /// **Arguments**
+    ///  * `requests`: The callbacks to send, in order.
+    /// 
+    /// **Returns**  
+    /// Nothing if all callbacks were delivered, or a CallbackError if none were.
+    async fn send_batch(&mut self, requests: Vec<CallbackRequest>) -> Result<(), CallbackError>;
+
+    /// Re-establishes the connection after sending failed.
+    /// 
+    /// **Returns**  
+    /// Nothing if we're connected again, or a CallbackError otherwise.
+    async fn reconnect(&mut self) -> Result<(), CallbackError>;
+}
+
+
+
+/// The CallbackTransport that sends callbacks to a brane-clb service over gRPC.
+pub struct GrpcTransport {
+    /// The address of the callback service.
+    address : String,
+    /// The client connected to the callback service.
+    client  : CallbackServiceClient<Channel>,
+}
+
+impl GrpcTransport {
+    /// Constructor for the GrpcTransport, which immediately connects to the given address.
+    /// 
+    /// **Arguments**
+    ///  * `address`: The address of the callback service.
+    /// 
+    /// **Returns**  
+    /// The new GrpcTransport on success, or a CallbackError::ConnectError if we could not connect.
+    pub async fn connect(address: String) -> Result<Self, CallbackError> {
+        debug!("Setting up a callback channel to: {}.", address);
+        match CallbackServiceClient::connect(address.clone()).await {
+            Ok(client) => Ok(Self{ address, client }),
+            Err(err)   => Err(CallbackError::ConnectError{ address, err }),
+        }
+    }
+}
+
+#[async_trait]
+impl CallbackTransport for GrpcTransport {
+    async fn send(&mut self, request: CallbackRequest) -> Result<(), CallbackError> {
+        let kind = format!("{:?}", CallbackKind::from_i32(request.kind).unwrap_or(CallbackKind::Unknown));
+        match self.client.callback(request).await {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(CallbackError::SendError{ kind, err }),
+        }
+    }
+
+    async fn send_batch(&mut self, requests: Vec<CallbackRequest>) -> Result<(), CallbackError> {
+        let kind = format!("batch of {}", requests.len());
+        match self.client.callback_batch(CallbackBatchRequest{ callbacks: requests }).await {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(CallbackError::SendError{ kind, err }),
+        }
+    }
+
+    async fn reconnect(&mut self) -> Result<(), CallbackError> {
+        debug!("Reconnecting callback channel to: {}.", self.address);
+        match CallbackServiceClient::connect(self.address.clone()).await {
+            Ok(client) => { self.client = client; Ok(()) },
+            Err(err)   => Err(CallbackError::ConnectError{ address: self.address.clone(), err }),
+        }
+    }
+}
+
+
+
+
+
 /***** CALLBACK *****/
+/// Configures how a Callback deals with a broken connection.
+#[derive(Clone, Debug)]
+pub struct CallbackOptions {
+    /// The maximum number of callbacks we buffer while the connection is down. If it's full, the oldest heartbeat is dropped to make room. Other callbacks are never dropped, so they may exceed it (there are only a few of them per job).
+    pub max_pending     : usize,
+    /// The time we wait before the first reconnection attempt. Doubles after every failed attempt.
+    pub initial_backoff : Duration,
+    /// The maximum time we wait between reconnection attempts.
+    pub max_backoff     : Duration,
+    /// The time we keep trying to deliver the final (Finished or Failed) callback before giving up.
+    pub final_deadline  : Duration,
+    /// The time that heartbeats wait for other callbacks, so that they are sent together as one batch. Zero sends every callback on its own.
+    pub batch_window    : Duration,
+}
+
+impl Default for CallbackOptions {
+    fn default() -> Self {
+        Self {
+            max_pending     : DEFAULT_MAX_PENDING,
+            initial_backoff : DEFAULT_INITIAL_BACKOFF,
+            max_backoff     : DEFAULT_MAX_BACKOFF,
+            final_deadline  : DEFAULT_FINAL_DEADLINE,
+            batch_window    : max_batch_window(Duration::from_millis(HEARTBEAT_DELAY)),
+        }
+    }
+}
+
+
+
+/// Returns the longest time that heartbeats may wait for other callbacks, given the time between two heartbeats.
+/// 
+/// That's half the interval: the driver gives up on a job that it hasn't heard from for a while (by default, two intervals), so a heartbeat may not be delayed until the next one is due. This also means that heartbeats are never batched with each other, only with the other callbacks that come along while they wait.
+/// 
+/// **Arguments**
+///  * `heartbeat_interval`: The time between two heartbeats.
+/// 
+/// **Returns**  
+/// The maximum batch window, which is also the default one.
+pub fn max_batch_window(heartbeat_interval: Duration) -> Duration {
+    heartbeat_interval / 2
+}
+
+
+
+/// The part of a Callback that is shared between its clones (e.g., with the heartbeat task): the connection and the buffer.
+struct CallbackState {
+    event_counter: i32,
+    transport: Box<dyn CallbackTransport>,
+
+    /// Configures the buffering and reconnection behaviour.
+    options      : CallbackOptions,
+    /// The callbacks that still have to be sent, oldest first.
+    pending      : VecDeque<CallbackRequest>,
+    /// Whether the last attempt to send failed, meaning we have to reconnect first.
+    broken       : bool,
+    /// The time we currently wait between reconnection attempts.
+    backoff      : Duration,
+    /// The earliest time at which we may try again.
+    next_attempt : Instant,
+    /// Whether a flush of the waiting callbacks has been scheduled already.
+    flush_scheduled : bool,
+}
+
+impl CallbackState {
+    /// Adds a callback to the back of the buffer, making room by dropping the oldest heartbeat if it's full.
+    /// 
+    /// Only heartbeats are ever dropped, since a later heartbeat tells the driver the same. If there are none in a full buffer, a new heartbeat is dropped instead, while other callbacks are added anyway.
+    /// 
+    /// **Arguments**
+    ///  * `request`: The callback to buffer.
+    fn enqueue(&mut self, request: CallbackRequest) {
+        let heartbeat = CallbackKind::Heartbeat as i32;
+        if self.pending.len() >= self.options.max_pending {
+            match self.pending.iter().position(|pending| pending.kind == heartbeat) {
+                Some(index)                     => { self.pending.remove(index); },
+                None if request.kind == heartbeat => {
+                    debug!("Dropping heartbeat: already {} callbacks waiting for the connection to come back", self.pending.len());
+                    return;
+                },
+                None                            => {},
+            }
+        }
+        self.pending.push_back(request);
+    }
+
+    /// Tries once to send all buffered callbacks, in order, reconnecting first if the previous attempt failed. On failure, the next attempt is scheduled according to the backoff.
+    /// 
+    /// **Returns**  
+    /// Nothing if the buffer is empty now, or the CallbackError that made this attempt fail.
+    async fn flush(&mut self) -> Result<(), CallbackError> {
+        let res = self.try_flush().await;
+        match res {
+            Ok(_) => {
+                self.broken = false;
+                self.backoff = self.options.initial_backoff;
+            },
+            Err(_) => {
+                self.broken = true;
+                self.next_attempt = Instant::now() + self.backoff;
+                self.backoff = std::cmp::min(self.backoff * 2, self.options.max_backoff);
+            },
+        }
+        res
+    }
+
+    /// Implements the actual work of `flush()`, without dealing with the backoff.
+    async fn try_flush(&mut self) -> Result<(), CallbackError> {
+        if self.broken { self.transport.reconnect().await?; }
+        if self.pending.len() > 1 && !self.options.batch_window.is_zero() {
+            self.transport.send_batch(self.pending.iter().cloned().collect()).await?;
+            self.pending.clear();
+            return Ok(());
+        }
+        while let Some(request) = self.pending.front() {
+            self.transport.send(request.clone()).await?;
+            self.pending.pop_front();
+        }
+        Ok(())
+    }
+}
+
+
+
 /// An instance that represents a connection to a remote callback node.
+/// 
+/// If sending a callback fails, it is buffered (in order) and the connection is re-established with an exponential backoff. Buffered callbacks are sent before any new ones.
+/// 
+/// Heartbeats are not sent right away, but wait for the batch window to pass (or for another callback to come along) so that they are sent together with whatever else is waiting as one batch.
+/// 
+/// Clones share the same connection, buffer and order counter.
+#[derive(Clone)]
 pub struct Callback {
     application_id: String,
     location_id: String,
     job_id: String,
-    event_counter: AtomicI32,
-    client: CallbackServiceClient<Channel>,
+    state: Arc<Mutex<CallbackState>>,
 }
 
 impl Callback {
-    /// **Edited: now returning CallbackErrors.**
+    /// **Edited: now returning CallbackErrors. Now also buffering callbacks while the connection is down.**
     /// 
     /// Constructor for the Callback.
     /// 
@@ -68,6 +547,7 @@ impl Callback {
     ///  * `location_id`: The ID of the location where we are currently running.
     ///  * `job_id`: The ID of the job that we're executing.
     ///  * `callback_to`: The address where this instance will report callbacks to.
+    ///  * `options`: The CallbackOptions that determine how we deal with a broken connection and how we batch callbacks.
     /// 
     /// **Returns**  
     /// The new Callback instance on success, or a CallbackError on failure.
@@ -76,44 +556,76 @@ impl Callback {
         location_id: S,
         job_id: S,
         callback_to: S,
+        options: CallbackOptions,
     ) -> Result<Self, CallbackError> {
-        // Conver the string-like callback_to to a string
-        let callback_to = callback_to.into();
-
         // Create the gRPC channel
-        debug!("Setting up a callback channel to: {}.", callback_to);
-        let client = match CallbackServiceClient::connect(callback_to.to_string()).await {
-            Ok(client) => client,
-            Err(err)   => { return Err(CallbackError::ConnectError{ address: callback_to, err }); }
-        };
+        let transport = GrpcTransport::connect(callback_to.into()).await?;
 
         // Create the instance
-        Ok(Callback {
+        Ok(Self::with_transport(application_id, location_id, job_id, Box::new(transport), options))
+    }
+
+    /// Constructor for the Callback that uses the given transport instead of connecting to a remote.
+    /// 
+    /// **Arguments**
+    ///  * `application_id`: The ID of the application that this branelet is working for.
+    ///  * `location_id`: The ID of the location where we are currently running.
+    ///  * `job_id`: The ID of the job that we're executing.
+    ///  * `transport`: The CallbackTransport to send the callbacks with.
+    ///  * `options`: The CallbackOptions that determine how we deal with a broken connection.
+    /// 
+    /// **Returns**  
+    /// The new Callback instance.
+    pub fn with_transport<S: Into<String>>(
+        application_id: S,
+        location_id: S,
+        job_id: S,
+        transport: Box<dyn CallbackTransport>,
+        options: CallbackOptions,
+    ) -> Self {
+        Callback {
             application_id: application_id.into(),
             location_id: location_id.into(),
             job_id: job_id.into(),
-            event_counter: AtomicI32::new(1),
-            client,
-        })
+            state: Arc::new(Mutex::new(CallbackState {
+                event_counter: 1,
+                transport,
+
+                backoff      : options.initial_backoff,
+                options,
+                pending      : VecDeque::new(),
+                broken       : false,
+                next_attempt : Instant::now(),
+                flush_scheduled : false,
+            })),
+        }
     }
 
-    /// **Edited: now returning CallbackErrors.**
+
+
+    /// **Edited: now returning CallbackErrors. Now also buffering callbacks while the connection is down.**
     /// 
     /// Performs a callback call to the remote callback.
     /// 
+    /// Normally, a callback that cannot be sent right away is left in the buffer, to be retried in the background or sent along with the next one. If `blocking` is true, we instead keep trying until it has been delivered or the final deadline passes.
+    /// 
     /// **Arguments**
     ///  * `kind`: The kind of the callback as a number of any sort.
     ///  * `payload`: Optional payload to send along with the callback.
+    ///  * `blocking`: Whether to wait until the callback has been delivered.
     /// 
     /// **Returns**  
-    /// Nothing when the call was sent successfully, or a CallbackError otherwise.
+    /// Nothing when the call was sent (or buffered) successfully, or a CallbackError otherwise.
     async fn call(
         &mut self,
         kind: CallbackKind,
         payload: Option<Vec<u8>>,
+        blocking: bool,
     ) -> Result<(), CallbackError> {
-        // Get this message's order ID
-        let order = self.event_counter.fetch_add(1, Ordering::Release);
+        // Get this message's order ID (under the lock, so the buffer stays in order)
+        let mut state = self.state.lock().await;
+        let order = state.event_counter;
+        state.event_counter += 1;
 
         // Create the request
         let request = CallbackRequest {
@@ -125,14 +637,94 @@ impl Callback {
             payload: payload.unwrap_or_default(),
         };
 
-        // Send the client on its way
+        // Send the client on its way (after the ones still waiting)
         debug!("Reached target: {:?}", kind);
-        match self.client.callback(request).await {
-            Ok(_)    => Ok(()),
-            Err(err) => Err(CallbackError::SendError{ kind: format!("{:?}", kind), err }),
+        state.enqueue(request);
+        if kind == CallbackKind::Heartbeat && !state.options.batch_window.is_zero() {
+            // Let it wait for company instead
+            if !state.flush_scheduled {
+                state.flush_scheduled = true;
+                self.schedule_flush(state.options.batch_window);
+            }
+            return Ok(());
+        }
+        let deadline = Instant::now() + state.options.final_deadline;
+        let mut last_err: Option<CallbackError> = None;
+        loop {
+            if Instant::now() >= state.next_attempt {
+                match state.flush().await {
+                    Ok(_)    => { return Ok(()); },
+                    Err(err) => {
+                        warn!("{} ({} callback(s) waiting; retrying in {:?})", err, state.pending.len(), state.next_attempt.saturating_duration_since(Instant::now()));
+                        last_err = Some(err);
+                    },
+                }
+            }
+            if !blocking {
+                // Make sure that what's left is retried, even if no other callback comes along
+                if !state.pending.is_empty() && !state.flush_scheduled {
+                    state.flush_scheduled = true;
+                    self.schedule_flush(state.next_attempt.saturating_duration_since(Instant::now()));
+                }
+                return Ok(());
+            }
+
+            // Wait until we may try again (or until we have to give up)
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(CallbackError::DeliveryTimeout{ kind: format!("{:?}", kind), pending: state.pending.len(), deadline: state.options.final_deadline, err: last_err.map(Box::new) });
+            }
+            tokio::time::sleep(std::cmp::min(state.next_attempt, deadline).saturating_duration_since(now)).await;
         }
     }
 
+    /// Flushes the waiting callbacks in the background once the given delay has passed, unless another callback has taken them along already.
+    /// 
+    /// If the flush fails, it is retried (with the usual backoff) until the buffer is empty or the Callback is dropped.
+    /// 
+    /// **Arguments**
+    ///  * `delay`: The time to wait before flushing.
+    fn schedule_flush(&self, delay: Duration) {
+        // Don't keep the connection alive just for this
+        let weak: Weak<Mutex<CallbackState>> = Arc::downgrade(&self.state);
+        tokio::spawn(async move {
+            let mut delay = delay;
+            loop {
+                tokio::time::sleep(delay).await;
+                let state = match weak.upgrade() {
+                    Some(state) => state,
+                    None        => { return; }
+                };
+                let mut state = state.lock().await;
+                if state.pending.is_empty() {
+                    state.flush_scheduled = false;
+                    return;
+                }
+
+                // Wait some more if we're still backing off, and try again if we fail
+                let now = Instant::now();
+                if now < state.next_attempt {
+                    delay = state.next_attempt - now;
+                    continue;
+                }
+                match state.flush().await {
+                    Ok(_)    => {
+                        state.flush_scheduled = false;
+                        return;
+                    },
+                    Err(err) => {
+                        delay = state.next_attempt.saturating_duration_since(Instant::now());
+                        warn!("{} ({} callback(s) waiting; retrying in {:?})", err, state.pending.len(), delay);
+                    },
+                }
+            }
+        });
+    }
+
+    /// Returns the number of callbacks that are still waiting to be sent.
+    #[inline]
+    pub async fn pending(&self) -> usize { self.state.lock().await.pending.len() }
+
     /// **Edited: now returning CallbackErrors.**
     /// 
     /// Sends a Ready callback to the remote callback node.
@@ -141,7 +733,7 @@ impl Callback {
     /// Nothing when the call was sent successfully, or a CallbackError otherwise.
     #[inline]
     pub async fn ready(&mut self) -> Result<(), CallbackError> {
-        self.call(CallbackKind::Ready, None).await
+        self.call(CallbackKind::Ready, None, false).await
     }
 
     /// Sends an InitializeFail callback to the remote callback node.
@@ -153,7 +745,7 @@ impl Callback {
     /// Nothing when the call was sent successfully, or a CallbackError otherwise.\
     #[inline]
     pub async fn initialize_failed(&mut self, err: String) -> Result<(), CallbackError> {
-        self.call(CallbackKind::InitializeFailed, Some(err.as_bytes().to_vec())).await
+        self.call(CallbackKind::InitializeFailed, Some(err.as_bytes().to_vec()), true).await
     }
     /// **Edited: now returning CallbackErrors.**
     /// 
@@ -163,7 +755,7 @@ impl Callback {
     /// Nothing when the call was sent successfully, or a CallbackError otherwise.
     #[inline]
     pub async fn initialized(&mut self) -> Result<(), CallbackError> {
-        self.call(CallbackKind::Initialized, None).await
+        self.call(CallbackKind::Initialized, None, false).await
     }
 
     /// Sends an StartFailed callback to the remote callback node.
@@ -175,7 +767,7 @@ impl Callback {
     /// Nothing when the call was sent successfully, or a CallbackError otherwise.\
     #[inline]
     pub async fn start_failed(&mut self, err: String) -> Result<(), CallbackError> {
-        self.call(CallbackKind::StartFailed, Some(err.as_bytes().to_vec())).await
+        self.call(CallbackKind::StartFailed, Some(err.as_bytes().to_vec()), true).await
     }
     /// **Edited: now returning CallbackErrors.**
     /// 
@@ -185,7 +777,7 @@ impl Callback {
     /// Nothing when the call was sent successfully, or a CallbackError otherwise.
     #[inline]
     pub async fn started(&mut self) -> Result<(), CallbackError> {
-        self.call(CallbackKind::Started, None).await
+        self.call(CallbackKind::Started, None, false).await
     }
 
     /// **Edited: now returning CallbackErrors.**
@@ -196,7 +788,7 @@ impl Callback {
     /// Nothing when the call was sent successfully, or a CallbackError otherwise.
     #[inline]
     pub async fn heartbeat(&mut self) -> Result<(), CallbackError> {
-        self.call(CallbackKind::Heartbeat, None).await
+        self.call(CallbackKind::Heartbeat, None, false).await
     }
     /// Sends a CompleteFailed callback to the remote callback node.
     /// 
@@ -207,7 +799,7 @@ impl Callback {
     /// Nothing when the call was sent successfully, or a CallbackError otherwise.
     #[inline]
     pub async fn complete_failed(&mut self, err: String) -> Result<(), CallbackError> {
-        self.call(CallbackKind::CompleteFailed, Some(err.as_bytes().to_vec())).await
+        self.call(CallbackKind::CompleteFailed, Some(err.as_bytes().to_vec()), true).await
     }
     /// Sends a Completed callback to the remote callback node.
     /// 
@@ -215,7 +807,7 @@ impl Callback {
     /// Nothing when the call was sent successfully, or a CallbackError otherwise.
     #[inline]
     pub async fn completed(&mut self) -> Result<(), CallbackError> {
-        self.call(CallbackKind::Completed, None).await
+        self.call(CallbackKind::Completed, None, false).await
     }
 
     /// Sends a DecodeFailed to te remote callback node.
@@ -227,7 +819,19 @@ impl Callback {
     /// Nothing when the call was sent successfully, or a CallbackError otherwise.
     #[inline]
     pub async fn decode_failed(&mut self, err: String) -> Result<(), CallbackError> {
-        self.call(CallbackKind::DecodeFailed, Some(err.as_bytes().to_vec())).await
+        self.call(CallbackKind::DecodeFailed, Some(err.as_bytes().to_vec()), true).await
+    }
+
+    /// Sends a StoreFailed to the remote callback node.
+    /// 
+    /// **Arguments**
+    ///  * `err`: The reason why storing the outputs of the package as artifacts failed.
+    /// 
+    /// **Returns**  
+    /// Nothing when the call was sent successfully, or a CallbackError otherwise.
+    #[inline]
+    pub async fn store_failed(&mut self, err: String) -> Result<(), CallbackError> {
+        self.call(CallbackKind::StoreFailed, Some(err.as_bytes().to_vec()), true).await
     }
     /// **Edited: now returning CallbackErrors.**
     /// 
@@ -257,7 +861,7 @@ impl Callback {
         }
 
         // Write the string version of the signal
-        self.call(CallbackKind::Stopped, Some(signal_name.as_bytes().to_vec())).await
+        self.call(CallbackKind::Stopped, Some(signal_name.as_bytes().to_vec()), true).await
     }
     /// **Edited: now returning CallbackErrors.**
     /// 
@@ -280,7 +884,7 @@ impl Callback {
         let payload = payload_text.as_bytes().to_vec();
 
         // Perform the call
-        self.call(CallbackKind::Failed, Some(payload)).await
+        self.call(CallbackKind::Failed, Some(payload), true).await
     }
     /// **Edited: now returning CallbackErrors.**
     /// 
@@ -293,6 +897,55 @@ impl Callback {
     /// Nothing when the call was sent successfully, or a CallbackError otherwise.
     #[inline]
     pub async fn finished(&mut self, raw_result: String) -> Result<(), CallbackError> {
-        self.call(CallbackKind::Finished, Some(raw_result.as_bytes().to_vec())).await
+        self.call(CallbackKind::Finished, Some(raw_result.as_bytes().to_vec()), true).await
+    }
+}
+
+
+
+
+
+/***** HEARTBEAT *****/
+/// Sends Heartbeat callbacks in the background at a fixed interval, until it is stopped or dropped.
+pub struct Heartbeat {
+    /// Tells the background task to stop (dropping it has the same effect).
+    stop   : Option<oneshot::Sender<()>>,
+    /// The background task itself.
+    handle : JoinHandle<()>,
+}
+
+impl Heartbeat {
+    /// Constructor for the Heartbeat, which immediately starts the background task. The first heartbeat is sent after one interval.
+    /// 
+    /// **Arguments**
+    ///  * `callback`: The Callback to send the heartbeats with. It shares its connection with the original, so heartbeats are ordered (and buffered) along with the other callbacks.
+    ///  * `interval`: The time between two heartbeats.
+    /// 
+    /// **Returns**  
+    /// The new Heartbeat, which keeps sending heartbeats until `stop()` is called or it is dropped.
+    pub fn start(callback: Callback, interval: Duration) -> Self {
+        let (stop, mut stopped) = oneshot::channel::<()>();
+        let handle = tokio::spawn(async move {
+            let mut callback = callback;
+            loop {
+                tokio::select! {
+                    // Resolves both if we're told to stop and if the Heartbeat was dropped
+                    _ = &mut stopped => { break; },
+                    _ = tokio::time::sleep(interval) => {
+                        if let Err(err) = callback.heartbeat().await { warn!("Could not update driver on Heartbeat: {}", err); }
+                        else { debug!("Sent Heartbeat to driver."); }
+                    },
+                }
+            }
+        });
+        Self{ stop: Some(stop), handle }
+    }
+
+    /// Stops sending heartbeats.
+    /// 
+    /// Waits until the background task is gone, so a heartbeat that is being sent right now is never sent after whatever callback comes next.
+    pub async fn stop(mut self) {
+        if let Some(stop) = self.stop.take() { let _ = stop.send(()); }
+        if let Err(err) = self.handle.await { warn!("Heartbeat task failed: {}", err); }
     }
 }
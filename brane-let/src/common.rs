@@ -14,16 +14,62 @@
 
 use crate::errors::LetError;
 
+use brane_job::interface::{Artifact, CallStats};
 use specifications::common::{Parameter, Value};
 use specifications::package::PackageKind;
 
 
 /***** CONSTANTS *****/
-/// The time between each heartbeat update (in ms)
+/// The default time between each heartbeat update (in ms), if BRANE_HEARTBEAT_INTERVAL is not given
 /// 
 /// Shouldn't be longer than the timeout of heartbeats defined in brane-drv (10 seconds at the time of writing), as brane-drv considers the branelet dead if it didn't send a heartbeat in that time.
 pub const HEARTBEAT_DELAY: u64 = 5000;
 
+/// The default maximum size (in bytes) of the stdout and the stderr each that we embed in a Failed callback, if BRANE_MAX_OUTPUT_SIZE is not given
+/// 
+/// Keeps the payload well below the message size limits of Kafka, which would otherwise drop the event (and leave the driver waiting for a result that never comes).
+pub const MAX_OUTPUT_SIZE: usize = 64 * 1024;
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_output_is_untouched() {
+        assert_eq!(cap_output(String::from("Hello there!"), 12), "Hello there!");
+        assert_eq!(cap_output(String::new(), 0), "");
+    }
+
+    #[test]
+    fn long_output_keeps_head_and_tail() {
+        let output = format!("{}{}{}", "a".repeat(100), "b".repeat(1000), "c".repeat(100));
+        let capped = cap_output(output, 200);
+        assert!(capped.starts_with(&"a".repeat(100)));
+        assert!(capped.ends_with(&"c".repeat(100)));
+        assert!(capped.contains("[... 1000 bytes truncated ...]"));
+        assert!(!capped.contains('b'));
+    }
+
+    #[test]
+    fn truncation_respects_char_boundaries() {
+        // Every character is three bytes, so neither half falls on a boundary
+        let capped = cap_output("€".repeat(100), 10);
+        assert!(capped.starts_with("€"));
+        assert!(capped.ends_with("€"));
+        assert!(capped.contains("[... 294 bytes truncated ...]"));
+    }
+}
+
+
+
+
+
+
 
 
 
@@ -43,8 +89,8 @@ pub enum PackageReturnState {
     Stopped{ signal: i32 },
     /// The package failed to execute on its own
     Failed{ code: i32, stdout: String, stderr: String },
-    /// The package completed successfully
-    Finished{ stdout: String },
+    /// The package completed successfully, using the given resources (if known)
+    Finished{ stdout: String, stats: Option<CallStats> },
 }
 
 
@@ -55,8 +101,33 @@ pub enum PackageResult {
     Stopped{ signal: i32 },
     /// The package failed to execute on its own
     Failed{ code: i32, stdout: String, stderr: String },
-    /// The package completed successfully
-    Finished{ result: Value },
+    /// The package completed successfully, using the given resources (if known) and leaving the given artifacts (if its function declares outputs)
+    Finished{ result: Value, stats: Option<CallStats>, artifacts: Option<Vec<Artifact>> },
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Caps the given output of a package to the given size, keeping its head and its tail (which usually hold the most interesting bits) and replacing the middle with a note on how much was cut.
+/// 
+/// **Arguments**
+///  * `output`: The output (stdout or stderr) to cap.
+///  * `max_size`: The maximum number of bytes of the output to keep. The note is added on top of that.
+/// 
+/// **Returns**  
+/// The output as-is if it fits, or its head and tail with a truncation note otherwise.
+pub fn cap_output(output: String, max_size: usize) -> String {
+    if output.len() <= max_size { return output; }
+
+    // Find the halves, rounded down to the nearest character
+    let mut head = max_size / 2;
+    while !output.is_char_boundary(head) { head -= 1; }
+    let mut tail = output.len() - (max_size - max_size / 2);
+    while !output.is_char_boundary(tail) { tail += 1; }
+
+    format!("{}\n[... {} bytes truncated ...]\n{}", &output[..head], tail - head, &output[tail..])
 }
 
 
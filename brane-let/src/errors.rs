@@ -29,11 +29,23 @@ pub enum LetError {
     JuiceFSLaunchError{ command: String, err: std::io::Error },
     /// The JuiceFS executable didn't complete successfully
     JuiceFSError{ command: String, code: i32, stdout: String, stderr: String },
+    /// The data directory of the job's session is not a single directory name
+    IllegalSessionData{ session: String },
+    /// Could not create the data directory of the job's session
+    SessionDataCreateError{ path: PathBuf, err: std::io::Error },
+    /// Could not create the working directory, nor the directory to fall back to
+    WorkdirCreateError{ path: PathBuf, fallback: PathBuf, err: std::io::Error },
+    /// Could not list the contents of the working directory
+    WorkdirReadError{ path: PathBuf, err: std::io::Error },
+    /// Could not remove what the call left behind in the working directory
+    WorkdirCleanupError{ path: PathBuf, err: std::io::Error },
 
     /// Could not start the proxy redirector in the background
     RedirectorError{ address: String, err: String },
     /// Failed to connect to a remote callback while asked
     CallbackConnectError{ address: String, err: CallbackError },
+    /// The heartbeat interval is zero
+    IllegalHeartbeatInterval,
 
     /// Could not decode input arguments with Base64
     ArgumentsBase64Error{ err: base64::DecodeError },
@@ -41,6 +53,12 @@ pub enum LetError {
     ArgumentsUTF8Error{ err: std::string::FromUtf8Error },
     /// Could not decode input arguments with JSON
     ArgumentsJSONError{ err: serde_json::Error },
+    /// The job is an element of a job array, but the location did not say which one
+    MissingArrayIndex{ size: u32 },
+    /// The index of the element of the job array is not a number below the size of the array
+    IllegalArrayIndex{ name: String, raw: String, size: u32 },
+    /// The input arguments of a job array have no entry for our element
+    MissingArrayArguments{ index: u32, len: usize },
 
     /// Could not load a ContainerInfo file.
     LocalContainerInfoError{ path: PathBuf, err: LocalContainerInfoError },
@@ -86,6 +104,10 @@ pub enum LetError {
 
     /// The given Open API Standard file does not parse as OAS
     IllegalOasDocument{ path: PathBuf, err: anyhow::Error },
+    /// A (nested) argument for an Open API call has a class type that the package doesn't define
+    UnknownArgumentClass{ function: String, path: String, class_name: String },
+    /// A (nested) argument for an Open API call misses a property that is required by its class
+    MissingArgumentProperty{ function: String, path: String, class_name: String, property_name: String },
 
     /// Somehow, we got an error while waiting for the subprocess
     PackageRunError{ err: std::io::Error },
@@ -103,6 +125,21 @@ pub enum LetError {
     /// Encountered more than one output from the function
     UnsupportedMultipleOutputs{ n: usize },
 
+    /// The function declares outputs, but there is nowhere to store them
+    NoArtifactStore,
+    /// The job ID is not a single directory name, so we cannot store its artifacts under it
+    IllegalArtifactJobId{ job_id: String },
+    /// One of the output patterns of the function is not a valid glob pattern
+    IllegalArtifactPattern{ pattern: String, err: glob::PatternError },
+    /// Could not list the files that the package left behind
+    ArtifactReadError{ path: PathBuf, err: std::io::Error },
+    /// A single artifact is larger than allowed
+    ArtifactTooLarge{ name: String, size: u64, max: u64 },
+    /// The artifacts of the job are larger than allowed together
+    ArtifactsTooLarge{ total: u64, max: u64 },
+    /// Could not copy an artifact to the artifact store
+    ArtifactCopyError{ name: String, path: PathBuf, err: std::io::Error },
+
     /// Could not write the resulting value to JSON
     ResultJSONError{ value: String, err: serde_json::Error },
 }
@@ -112,13 +149,22 @@ impl Display for LetError {
         match self {
             LetError::JuiceFSLaunchError{ command, err }            => write!(f, "Could not run JuiceFS command '{}': {}", command, err),
             LetError::JuiceFSError{ command, code, stdout, stderr } => write!(f, "JuiceFS command '{}' returned exit code {}:\n\nstdout:\n{}\n{}\n{}\n\nstderr:\n{}\n{}\n{}\n\n", command, code, (0..80).map(|_| '-').collect::<String>(), stdout, (0..80).map(|_| '-').collect::<String>(), (0..80).map(|_| '-').collect::<String>(), stderr,(0..80).map(|_| '-').collect::<String>()),
+            LetError::IllegalSessionData{ session }                 => write!(f, "Session data directory '{}' (BRANE_SESSION_DATA) is not a single directory name", session),
+            LetError::SessionDataCreateError{ path, err }           => write!(f, "Could not create session data directory '{}': {}", path.display(), err),
+            LetError::WorkdirCreateError{ path, fallback, err }     => write!(f, "Could not create working directory '{}', nor fallback working directory '{}': {}", path.display(), fallback.display(), err),
+            LetError::WorkdirReadError{ path, err }                 => write!(f, "Could not read working directory '{}': {}", path.display(), err),
+            LetError::WorkdirCleanupError{ path, err }              => write!(f, "Could not clean up '{}' in the working directory: {}", path.display(), err),
 
             LetError::RedirectorError{ address, err }      => write!(f, "Could not start redirector to '{}' in the background: {}", address, err),
             LetError::CallbackConnectError{ address, err } => write!(f, "Could not connect to remote callback node at '{}': {}", address, err),
+            LetError::IllegalHeartbeatInterval             => write!(f, "The heartbeat interval (BRANE_HEARTBEAT_INTERVAL) must be at least 1 millisecond"),
 
             LetError::ArgumentsBase64Error{ err } => write!(f, "Could not decode input arguments as Base64: {}", err),
             LetError::ArgumentsUTF8Error{ err }   => write!(f, "Could not decode input arguments as UTF-8: {}", err),
             LetError::ArgumentsJSONError{ err }   => write!(f, "Could not parse input arguments as JSON: {}", err),
+            LetError::MissingArrayIndex{ size }                => write!(f, "Job is an element of a job array of {} elements, but none of {} tells which one", size, crate::array::INDEX_ENVS.iter().map(|name| format!("'{}'", name)).collect::<Vec<String>>().join(", ")),
+            LetError::IllegalArrayIndex{ name, raw, size }     => write!(f, "Index '{}' of the job array element (from {}) is not a number below the size of the array ({})", raw, name, size),
+            LetError::MissingArrayArguments{ index, len }      => write!(f, "Input arguments of the job array have no entry for element {} (only {} given)", index, len),
 
             LetError::LocalContainerInfoError{ path, err }                              => write!(f, "Could not load local container information file '{}': {}", path.display(), err),
             LetError::PackageInfoError{ err }                                           => write!(f, "Could not parse package information file from Open-API document: {}", err),
@@ -142,7 +188,9 @@ impl Display for LetError {
             LetError::IllegalNestedURL{ name, field }                  => write!(f, "Field '{}' of struct '{}' is a Directory or a File struct, but misses the 'URL' field", field, name),
             LetError::PackageLaunchError{ command, err }               => write!(f, "Could not run nested package call '{}': {}", command, err),
 
-            LetError::IllegalOasDocument{ path, err } => write!(f, "Could not parse OpenAPI specification '{}': {}", path.display(), err),
+            LetError::IllegalOasDocument{ path, err }                                      => write!(f, "Could not parse OpenAPI specification '{}': {}", path.display(), err),
+            LetError::UnknownArgumentClass{ function, path, class_name }                   => write!(f, "Argument '{}' of function '{}' has class type '{}', but that class is undefined", path, function, class_name),
+            LetError::MissingArgumentProperty{ function, path, class_name, property_name } => write!(f, "Argument '{}' of function '{}' has class type '{}', but is missing required property '{}'", path, function, class_name, property_name),
 
             LetError::ClosedStdout           => write!(f, "Could not open subprocess stdout"),
             LetError::ClosedStderr           => write!(f, "Could not open subprocess stdout"),
@@ -153,6 +201,14 @@ impl Display for LetError {
             LetError::DecodeError{ stdout, err }      => write!(f, "Could not parse package stdout: {}\n\nstdout:\n{}\n{}\n{}\n\n", err, (0..80).map(|_| '-').collect::<String>(), stdout, (0..80).map(|_| '-').collect::<String>()),
             LetError::UnsupportedMultipleOutputs{ n } => write!(f, "Function return {} outputs; this is not (yet) supported, please return only one", n),
 
+            LetError::NoArtifactStore                        => write!(f, "Function declares outputs, but there is no DFS mounted nor an artifact directory (BRANE_ARTIFACT_DIR) configured to store them in"),
+            LetError::IllegalArtifactJobId{ job_id }         => write!(f, "Cannot store artifacts for job '{}' (BRANE_JOB_ID), as it is not a single directory name", job_id),
+            LetError::IllegalArtifactPattern{ pattern, err } => write!(f, "Output pattern '{}' is not a valid glob pattern: {}", pattern, err),
+            LetError::ArtifactReadError{ path, err }         => write!(f, "Could not list outputs in '{}': {}", path.display(), err),
+            LetError::ArtifactTooLarge{ name, size, max }    => write!(f, "Output '{}' is {} bytes, which is more than the {} bytes that a single artifact may be (BRANE_ARTIFACT_MAX_SIZE)", name, size, max),
+            LetError::ArtifactsTooLarge{ total, max }        => write!(f, "Outputs are {} bytes together, which is more than the {} bytes that the artifacts of a job may be (BRANE_ARTIFACT_MAX_TOTAL)", total, max),
+            LetError::ArtifactCopyError{ name, path, err }   => write!(f, "Could not store output '{}' as artifact '{}': {}", name, path.display(), err),
+
             LetError::ResultJSONError{ value, err } => write!(f, "Could not serialize value '{}' to JSON: {}", value, err),
         }
     }
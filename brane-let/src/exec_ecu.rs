@@ -1,17 +1,82 @@
+use crate::artifacts::ArtifactStore;
 use crate::callback::Callback;
-use crate::common::{assert_input, HEARTBEAT_DELAY, Map, PackageResult, PackageReturnState};
+use crate::common::{assert_input, Map, PackageResult, PackageReturnState};
 use crate::errors::{DecodeError, LetError};
+use crate::stats::wait_with_usage;
+use brane_job::interface::Artifact;
 use specifications::common::{Parameter, Type, Value};
 use specifications::container::{Action, ActionCommand, LocalContainerInfo};
+use specifications::package::EnvironmentVariable;
 use std::os::unix::process::ExitStatusExt;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::time::Instant;
 use tokio::io::AsyncReadExt;
 use tokio::process::{Command as TokioCommand, Child as TokioChild};
-use tokio::time::{self, Duration};
 use yaml_rust::{Yaml, YamlLoader};
 
 
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specifications::common::Property;
+
+    /// Decodes the given stdout as the output of a function returning a 'Person' called 'output'.
+    fn decode_person(stdout: &str) -> Result<PackageResult, LetError> {
+        let parameters = vec![ Parameter::new(String::from("output"), String::from("Person"), None, None, None) ];
+        let mut types = Map::<Type>::new();
+        types.insert(String::from("Person"), Type::new(String::from("Person"), vec![ Property::new_quick("name", "string"), Property::new_quick("age", "integer") ]));
+        decode(PackageReturnState::Finished{ stdout: stdout.to_string(), stats: None }, &None, &parameters, &types)
+    }
+
+    #[test]
+    fn null_outputs_decode_to_unit() {
+        let properties = match decode_person("output:\n  name: Alice\n  age: null\n") {
+            Ok(PackageResult::Finished{ result: Value::Struct{ properties, .. }, .. }) => properties,
+            Ok(_)    => panic!("Expected a finished struct"),
+            Err(err) => panic!("Could not decode: {}", err),
+        };
+        assert_eq!(properties["name"], Value::Unicode(String::from("Alice")));
+        assert_eq!(properties["age"], Value::Unit);
+
+        assert!(matches!(decode_person("output: ~\n"), Ok(PackageResult::Finished{ result: Value::Unit, .. })));
+        // Missing properties are still an error
+        assert!(matches!(decode_person("output:\n  name: Alice\n"), Err(LetError::DecodeError{ err: DecodeError::MissingStructProperty{ .. }, .. })));
+    }
+
+    #[test]
+    fn package_envs_fall_back_to_defaults() {
+        let mut declared = Map::<EnvironmentVariable>::new();
+        declared.insert(String::from("MODE"), EnvironmentVariable::Fixed(String::from("batch")));
+        declared.insert(String::from("THREADS"), EnvironmentVariable::Declared{ default: Some(String::from("1")), configurable: true });
+        declared.insert(String::from("TOKEN"), EnvironmentVariable::Declared{ default: None, configurable: true });
+
+        // Nothing set by the job: only the defaults
+        let envs = construct_package_envs(&declared, |_| None);
+        assert_eq!(envs.len(), 2);
+        assert_eq!(envs["MODE"], "batch");
+        assert_eq!(envs["THREADS"], "1");
+
+        // Whatever the job set wins
+        let envs = construct_package_envs(&declared, |name| if name == "THREADS" || name == "TOKEN" { Some(format!("{}-value", name)) } else { None });
+        assert_eq!(envs["MODE"], "batch");
+        assert_eq!(envs["THREADS"], "THREADS-value");
+        assert_eq!(envs["TOKEN"], "TOKEN-value");
+    }
+
+    #[test]
+    fn outputs_need_an_artifact_store() {
+        let run_dir = tempfile::tempdir().unwrap();
+        assert!(matches!(collect_artifacts(None, run_dir.path(), &[]), Ok(None)));
+        assert!(matches!(collect_artifacts(None, run_dir.path(), &[ String::from("*.csv") ]), Err(LetError::NoArtifactStore)));
+    }
+}
+
+
+
+
+
 /***** CONSTANTS *****/
 /// Initial capacity for the buffers for stdout and stderr
 const DEFAULT_STD_BUFFER_SIZE: usize = 2048;
@@ -35,6 +100,8 @@ const PREFIX: &str = "~~>";
 ///  * `function`: The function name to execute in the package.
 ///  * `arguments`: The arguments, as a map of argument name / value pairs.
 ///  * `working_dir`: The wokring directory for this package.
+///  * `package_dir`: The directory to run the package in, if not its working directory.
+///  * `artifacts`: The ArtifactStore to store the outputs of the function in, if there is shared storage to put them.
 ///  * `callback`: The callback object we use to keep in touch with the driver.
 /// 
 /// **Returns**  
@@ -43,6 +110,8 @@ pub async fn handle(
     function: String,
     arguments: Map<Value>,
     working_dir: PathBuf,
+    package_dir: Option<PathBuf>,
+    artifacts: Option<&ArtifactStore>,
     callback: &mut Option<&mut Callback>,
 ) -> Result<PackageResult, LetError> {
     debug!("Executing '{}' (ecu) using arguments:\n{:#?}", function, arguments);
@@ -66,7 +135,8 @@ pub async fn handle(
     };
 
     // Launch the job
-    let (command, process) = match start(&container_info, &function, &arguments, &working_dir) {
+    let started = Instant::now();
+    let (command, process) = match start(&container_info, &function, &arguments, &working_dir, package_dir.as_deref()) {
         Ok(result) => {
             if let Some(callback) = callback {
                 if let Err(err) = callback.started().await { warn!("Could not update driver on Started: {}", err); }
@@ -84,7 +154,7 @@ pub async fn handle(
     };
 
     // Wait until the job is completed
-    let result = match complete(process, callback).await {
+    let result = match complete(process, started).await {
         Ok(result) => {
             if let Some(callback) = callback {
                 if let Err(err) = callback.completed().await { warn!("Could not update driver on Completed: {}", err); }
@@ -113,6 +183,23 @@ pub async fn handle(
     };
     info!("Reached target 'Decode'");
 
+    // Store the files that the function declares as its outputs, now that we know it succeeded
+    let result = match result {
+        PackageResult::Finished{ result, stats, .. } => {
+            let run_dir = package_dir.as_deref().unwrap_or(&working_dir);
+            match collect_artifacts(artifacts, run_dir, function.outputs.as_deref().unwrap_or_default()) {
+                Ok(artifacts) => PackageResult::Finished{ result, stats, artifacts },
+                Err(err)      => {
+                    if let Some(callback) = callback {
+                        if let Err(err) = callback.store_failed(format!("{}", &err)).await { warn!("Could not update driver on StoreFailed: {}", err); }
+                    }
+                    return Err(err);
+                },
+            }
+        },
+        result => result,
+    };
+
     // Return the package call result!
     Ok(result)
 }
@@ -200,6 +287,7 @@ fn initialize(
 ///  * `function`: The function to call.
 ///  * `arguments`: The arguments to pass to the function.
 ///  * `working_dir`: The working directory for the function.
+///  * `package_dir`: The directory to run the function in, if not the directory that the container runs in.
 /// 
 /// **Returns**  
 /// The ActionCommand used + a process handle on success, or a LetError on failure.
@@ -208,6 +296,7 @@ fn start(
     function: &Action,
     arguments: &Map<Value>,
     working_dir: &Path,
+    package_dir: Option<&Path>,
 ) -> Result<(ActionCommand, TokioChild), LetError> {
     // Determine entrypoint and, optionally, command and arguments
     let entrypoint = &container_info.entrypoint.exec;
@@ -233,14 +322,16 @@ fn start(
     // };
     let mut exec_command = TokioCommand::new(entrypoint_path);
 
-    // Construct the environment variables
-    let envs = construct_envs(arguments)?;
+    // Construct the environment variables; the arguments go last, so they win over the package's own variables
+    let mut envs = construct_package_envs(&container_info.environment, |name| std::env::var(name).ok());
+    envs.extend(construct_envs(arguments)?);
     debug!("Using environment variables:\n{:#?}", envs);
     let envs: Vec<_> = envs.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
 
     // Finally, prepare the subprocess
     exec_command.args(&command.args);
     exec_command.envs(envs);
+    if let Some(package_dir) = package_dir { exec_command.current_dir(package_dir); }
     exec_command.stdout(Stdio::piped());
     exec_command.stderr(Stdio::piped());
     let process = match exec_command.spawn() {
@@ -252,6 +343,27 @@ fn start(
     Ok((command, process))
 }
 
+/// Creates a map with the environment variables that the package declares in its container.yml.
+/// 
+/// **Arguments**
+///  * `declared`: The environment variables declared by the package.
+///  * `lookup`: Returns the value that the job set for the given variable, if any (i.e., the value in our own environment).
+/// 
+/// **Returns**  
+/// A new map with the value of every declared variable that is set by the job or that has a default.
+fn construct_package_envs(
+    declared: &Map<EnvironmentVariable>,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Map<String> {
+    let mut envs = Map::<String>::new();
+    for (name, variable) in declared.iter() {
+        if let Some(value) = lookup(name).or_else(|| variable.default_value().map(String::from)) {
+            envs.insert(name.clone(), value);
+        }
+    }
+    envs
+}
+
 /// **Edited: now returning LetErrors.**
 /// 
 /// Creates a map with enviroment variables for the nested package based on the given arguments.
@@ -392,55 +504,25 @@ fn construct_struct_envs(
 
 
 /***** WAITING FOR RESULT *****/
+/// **Edited: heartbeats are now sent in the background for the whole run (see `Heartbeat`).**
+/// 
 /// Waits for the given process to complete, then returns its result.
 /// 
 /// **Arguments**
 ///  * `process`: The handle to the asynchronous tokio process.
-///  * `callback`: A Callback object to send heartbeats with.
 /// 
 /// **Returns**  
 /// The PackageReturnState describing how the call went on success, or a LetError on failure.
 async fn complete(
     process: TokioChild,
-    callback: &mut Option<&mut Callback>,
+    started: Instant,
 ) -> Result<PackageReturnState, LetError> {
-    let mut process = process;
-
-    // Handle waiting for the subprocess and doing heartbeats in a neat way, using select
-    let status = loop {
-        // Prepare the timer
-        let sleep = time::sleep(Duration::from_millis(HEARTBEAT_DELAY));
-        tokio::pin!(sleep);
-
-        // Wait for either the timer or the process
-        let status = loop {
-            tokio::select! {
-                status = process.wait() => {
-                    // Process is finished!
-                    break Some(status);
-                },
-                _ = &mut sleep => {
-                    // Timeout occurred; send the heartbeat and continue
-                    if let Some(callback) = callback {
-                        if let Err(err) = callback.heartbeat().await { warn!("Could not update driver on Heartbeat: {}", err); }
-                        else { debug!("Sent Heartbeat to driver."); }
-                    }
-
-                    // Stop without result
-                    break None;
-                },
-            }
-        };
-
-        // If we have a result, break from the main loop; otherwise, try again
-        if let Some(status) = status { break status; }
-    };
-
-    // Match the status result
-    let status = match status {
-        Ok(status) => status,
+    // Wait for the process, keeping track of what it used
+    let (status, stats) = match wait_with_usage(&process, started).await {
+        Ok(result) => result,
         Err(err)   => { return Err(LetError::PackageRunError{ err }); }
     };
+    debug!("Package call used {}", stats);
 
     // Try to get stdout and stderr readers
     let mut stdout = match process.stdout {
@@ -474,7 +556,7 @@ async fn complete(
     }
 
     // Otherwise, it was a success, so return it as such!
-    Ok(PackageReturnState::Finished{ stdout })
+    Ok(PackageReturnState::Finished{ stdout, stats: Some(stats) })
 }
 
 /// **Edited: returns LetErrors + changed to accept string instead of split stuff.**
@@ -554,7 +636,7 @@ fn decode(
 ) -> Result<PackageResult, LetError> {
     // Match on the result
     match result {
-        PackageReturnState::Finished{ stdout } => {
+        PackageReturnState::Finished{ stdout, stats } => {
             // First, preprocess the stdout
             let stdout = preprocess_stdout(stdout, mode);
 
@@ -579,7 +661,7 @@ fn decode(
             };
 
             // Done
-            Ok(PackageResult::Finished{ result: value })
+            Ok(PackageResult::Finished{ result: value, stats, artifacts: None })
         },
 
         PackageReturnState::Failed{ code, stdout, stderr } => {
@@ -714,6 +796,9 @@ fn unwrap_yaml_value(
 ) -> Result<Value, DecodeError> {
     debug!("Unwrapping as {}: {:?} ", data_type, value);
 
+    // A null is always Unit, whatever type was declared, so workflows can test for it with 'is_unit()' or '??'
+    if let Yaml::Null = value { return Ok(Value::Unit); }
+
     // Match on the data type
     let value = match data_type {
         "boolean" => {
@@ -785,3 +870,29 @@ fn unwrap_yaml_value(
 
     Ok(value)
 }
+
+
+
+
+
+/***** ARTIFACTS *****/
+/// Collects the files that the function declares as its outputs as artifacts.
+/// 
+/// **Arguments**
+///  * `store`: The ArtifactStore to store them in, if there is one.
+///  * `run_dir`: The directory that the function ran in.
+///  * `outputs`: The glob patterns of the files to collect.
+/// 
+/// **Returns**  
+/// The Artifacts, None if the function declares no outputs, or a LetError if there is no place to store them or they could not be stored.
+fn collect_artifacts(store: Option<&ArtifactStore>, run_dir: &Path, outputs: &[String]) -> Result<Option<Vec<Artifact>>, LetError> {
+    if outputs.is_empty() { return Ok(None); }
+    let store = match store {
+        Some(store) => store,
+        None        => { return Err(LetError::NoArtifactStore); },
+    };
+
+    let artifacts = store.collect(run_dir, outputs)?;
+    info!("Stored {} artifact(s) in '{}'", artifacts.len(), store.dir().display());
+    Ok(Some(artifacts))
+}
@@ -52,5 +52,5 @@ pub async fn handle(
     info!("Reached target 'Completed'");
 
     // Done, return the empty result
-    Ok(PackageResult::Finished{ result: Value::Unit })
+    Ok(PackageResult::Finished{ result: Value::Unit, stats: None, artifacts: None })
 }
@@ -1,18 +1,103 @@
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Instant;
 
-use tokio::time::{self, Duration};
-
+use brane_job::interface::CallStats;
 use brane_oas::OpenAPI;
+use serde_json::Value as JValue;
 use specifications::common::{Function, Type, Value};
 use specifications::package::{PackageInfo, PackageKind};
 use specifications::version::Version;
 
 use crate::callback::Callback;
-use crate::common::{assert_input, HEARTBEAT_DELAY, Map, PackageResult, PackageReturnState};
+use crate::common::{assert_input, Map, PackageResult, PackageReturnState};
 use crate::errors::{DecodeError, LetError};
 
 
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specifications::common::Property;
+
+    /// The types of a 'listPets' operation that returns a page of 'Pet's, of which the 'tag' (and the page's 'next') is optional.
+    fn pet_types() -> Map<Type> {
+        let mut types = Map::<Type>::new();
+        types.insert(String::from("Pet"), Type::new(String::from("Pet"), vec![
+            Property::new_quick("id", "integer"),
+            Property::new_quick("name", "string"),
+            Property::new(String::from("tag"), String::from("string"), None, None, Some(true), None),
+        ]));
+        types.insert(String::from("ListPetsOutput"), Type::new(String::from("ListPetsOutput"), vec![
+            Property::new_quick("pets", "Pet[]"),
+            Property::new(String::from("next"), String::from("integer"), None, None, Some(true), None),
+        ]));
+        types
+    }
+
+    /// Decodes the given stdout as the response of a function returning the given type.
+    fn decode_response(stdout: &str, return_type: &str) -> Result<Value, LetError> {
+        match decode(PackageReturnState::Finished{ stdout: stdout.to_string(), stats: None }, return_type, &pet_types())? {
+            PackageResult::Finished{ result, .. } => Ok(result),
+            _                                     => panic!("Expected a finished result"),
+        }
+    }
+
+    fn pet(id: i64, name: &str, tag: Option<&str>) -> Value {
+        let mut properties = Map::<Value>::new();
+        properties.insert(String::from("id"), Value::Integer(id));
+        properties.insert(String::from("name"), Value::Unicode(name.to_string()));
+        properties.insert(String::from("tag"), tag.map(|tag| Value::Unicode(tag.to_string())).unwrap_or(Value::Unit));
+        Value::Struct{ data_type: String::from("Pet"), properties }
+    }
+
+    #[test]
+    fn test_arrays_of_structs() {
+        let pets = decode_response(r#"[{"id": 1, "name": "Rex", "tag": "dog", "owner": "Alice"}, {"id": 2, "name": "Tom"}]"#, "Pet[]").unwrap();
+        assert_eq!(pets, Value::Array{ data_type: String::from("Pet[]"), entries: vec![ pet(1, "Rex", Some("dog")), pet(2, "Tom", None) ] });
+
+        let page = decode_response(r#"{"pets": [{"id": 3, "name": "Kit"}], "next": 4}"#, "ListPetsOutput").unwrap();
+        match page {
+            Value::Struct{ data_type, properties } => {
+                assert_eq!(data_type, "ListPetsOutput");
+                assert_eq!(properties["pets"], Value::Array{ data_type: String::from("Pet[]"), entries: vec![ pet(3, "Kit", None) ] });
+                assert_eq!(properties["next"], Value::Integer(4));
+            },
+            value => panic!("Expected a struct, got: {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_nullable_fields() {
+        assert_eq!(decode_response(r#"[{"id": 1, "name": "Rex", "tag": null}]"#, "Pet[]").unwrap(), Value::Array{ data_type: String::from("Pet[]"), entries: vec![ pet(1, "Rex", None) ] });
+        let page = decode_response(r#"{"pets": [], "next": null}"#, "ListPetsOutput").unwrap();
+        assert!(matches!(page, Value::Struct{ ref properties, .. } if properties["next"] == Value::Unit));
+        assert_eq!(decode_response("null", "ListPetsOutput").unwrap(), Value::Unit);
+
+        // Missing properties that are not optional are still an error, and so are nulls where they aren't optional
+        assert!(matches!(decode_response(r#"[{"id": 1}]"#, "Pet[]"), Err(LetError::DecodeError{ err: DecodeError::MissingStructProperty{ ref name, .. }, .. }) if name == "$[0]"));
+        assert!(matches!(decode_response(r#"[{"id": 1, "name": null}]"#, "Pet[]"), Err(LetError::DecodeError{ err: DecodeError::OutputTypeMismatch{ ref name, ref got, .. }, .. }) if name == "$[0].name" && got == "unit"));
+        assert!(matches!(decode_response(r#"{"pets": null}"#, "ListPetsOutput"), Err(LetError::DecodeError{ err: DecodeError::OutputTypeMismatch{ ref name, .. }, .. }) if name == "$.pets"));
+        assert!(matches!(decode_response(r#"[null]"#, "Pet[]"), Err(LetError::DecodeError{ err: DecodeError::OutputTypeMismatch{ ref name, .. }, .. }) if name == "$[0]"));
+    }
+
+    #[test]
+    fn test_mismatches_name_the_path() {
+        match decode_response(r#"{"pets": [{"id": 1, "name": "Rex"}, {"id": "two", "name": "Tom"}], "next": 2}"#, "ListPetsOutput") {
+            Err(LetError::DecodeError{ err: DecodeError::OutputTypeMismatch{ name, expected, got }, .. }) => {
+                assert_eq!((name.as_str(), expected.as_str(), got.as_str()), ("$.pets[1].id", "integer", "string"));
+            },
+            result => panic!("Expected a type mismatch, got: {:?}", result),
+        }
+        assert!(matches!(decode_response(r#"{"id": 1, "name": "Rex"}"#, "Pet[]"), Err(LetError::DecodeError{ err: DecodeError::OutputTypeMismatch{ ref name, .. }, .. }) if name == "$"));
+        assert!(matches!(decode_response(r#"{"id": 1}"#, "Owner"), Err(LetError::DecodeError{ err: DecodeError::UnknownClassType{ .. }, .. })));
+    }
+}
+
+
+
+
+
 /***** ENTRYPOINT *****/
 /// **Edited: working with new callback interface + events.**
 /// 
@@ -35,7 +120,7 @@ pub async fn handle(
     debug!("Executing '{}' (oas) using arguments:\n{:#?}", function, arguments);
 
     // Initialize the package
-    let (oas_document, package_info, function_info) = match initialize(&function, &arguments, &working_dir) {
+    let (oas_document, package_info, function_info, arguments) = match initialize(&function, &arguments, &working_dir) {
         Ok(results) => {
             if let Some(callback) = callback {
                 if let Err(err) = callback.initialized().await { warn!("Could not update driver on Initialized: {}", err); }
@@ -55,7 +140,7 @@ pub async fn handle(
     };
 
     // Do the API call, sending heartbeat updates while at it
-    let result = match complete(&function, &arguments, &oas_document, callback).await {
+    let result = match complete(&function, &arguments, &oas_document).await {
         Ok(result) => {
             if let Some(callback) = callback {
                 if let Err(err) = callback.completed().await { warn!("Could not update driver on Completed: {}", err); }
@@ -92,7 +177,7 @@ pub async fn handle(
 
 
 /***** INITIALIZATION *****/
-/// **Edited: returning LetErrors + now also doing the steps before the specific working dir initialization.**
+/// **Edited: returning LetErrors + now also doing the steps before the specific working dir initialization + encoding the arguments.**
 /// 
 /// Initializes the environment for the nested package by reading the package.yml and preparing the working directory (though that's not needed yet).
 /// 
@@ -105,13 +190,14 @@ pub async fn handle(
 ///  * On success, a tuple with (in order):
 ///    * The PackageInfo struct representing the package.yml in this package
 ///    * The function represented as an Action that we should execute
+///    * The arguments, rebuilt such that they can be serialized as the request (see `encode()`)
 ///  * On failure:
 ///    * A LetError describing what went wrong.
 fn initialize(
     function: &str,
     arguments: &Map<Value>,
     working_dir: &Path,
-) -> Result<(OpenAPI, PackageInfo, Function), LetError> {
+) -> Result<(OpenAPI, PackageInfo, Function, Map<Value>), LetError> {
     // Get the OasDocument from path
     let oas_file = working_dir.join("document.yml");
     let oas_document = match brane_oas::parse_oas_file(&oas_file) {
@@ -134,8 +220,11 @@ fn initialize(
     // Make sure the input matches what we expect
     assert_input(&function_info.parameters, arguments, function, &package_info.name, package_info.kind)?;
 
+    // Rebuild the arguments based on the package's types, so nested instances are serialized properly
+    let arguments = encode(function, arguments, &function_info, &package_info.types)?;
+
     // Done!
-    Ok((oas_document, package_info, function_info))
+    Ok((oas_document, package_info, function_info, arguments))
 }
 
 
@@ -170,6 +259,7 @@ fn create_package_info(
         false,
         functions,
         types,
+        vec![],
     ))
 }
 
@@ -177,14 +267,120 @@ fn create_package_info(
 
 
 
+/***** ENCODE *****/
+/// Rebuilds the arguments of an OpenAPI call such that they serialize to the JSON the API expects.
+/// 
+/// Nested instances (including those in arrays) are rebuilt recursively according to their class in the package, dropping any optional properties that aren't set.
+/// 
+/// **Arguments**
+///  * `function`: The name of the function we're calling. Used for writing sensible errors only.
+///  * `arguments`: The arguments, as a map of argument name / value pairs.
+///  * `function_info`: The function that we're calling.
+///  * `c_types`: The types defined in the package.
+/// 
+/// **Returns**  
+/// The rebuilt arguments on success, or a LetError otherwise.
+fn encode(
+    function: &str,
+    arguments: &Map<Value>,
+    function_info: &Function,
+    c_types: &Map<Type>,
+) -> Result<Map<Value>, LetError> {
+    let mut encoded = Map::<Value>::new();
+    for (name, value) in arguments {
+        // Unset optional arguments are simply omitted
+        if let Value::Unit = value { continue; }
+
+        // Rebuild the argument if we know its type
+        let value = match function_info.parameters.iter().find(|p| &p.name == name) {
+            Some(parameter) => encode_value(function, value, &parameter.data_type, c_types, name)?,
+            None            => value.clone(),
+        };
+        encoded.insert(name.clone(), value);
+    }
+
+    Ok(encoded)
+}
+
+/// Rebuilds a single (possibly nested) argument value according to the given type.
+/// 
+/// **Arguments**
+///  * `function`: The name of the function we're calling. Used for writing sensible errors only.
+///  * `value`: The value to rebuild.
+///  * `c_type`: The type we expect the value to have.
+///  * `c_types`: The types defined in the package.
+///  * `path`: The path to the value in the arguments (e.g., `input.tags[0]`). Used for writing sensible errors only.
+/// 
+/// **Returns**  
+/// The rebuilt Value on success, or a LetError otherwise.
+fn encode_value(
+    function: &str,
+    value: &Value,
+    c_type: &str,
+    c_types: &Map<Type>,
+    path: &str,
+) -> Result<Value, LetError> {
+    match value {
+        Value::Struct { data_type, properties } => {
+            // Files and directories have their own serialization
+            if data_type == "File" || data_type == "Directory" { return Ok(value.clone()); }
+
+            // Find the class that we expect
+            let class = match c_types.get(c_type) {
+                Some(class) => class,
+                None        => { return Err(LetError::UnknownArgumentClass{ function: function.to_string(), path: path.to_string(), class_name: c_type.to_string() }); }
+            };
+
+            // Rebuild the properties that the class defines
+            let mut filtered = Map::<Value>::new();
+            for p in &class.properties {
+                match properties.get(&p.name) {
+                    Some(Value::Unit) | None => {
+                        if p.optional.unwrap_or(false) { continue; }
+                        return Err(LetError::MissingArgumentProperty{ function: function.to_string(), path: path.to_string(), class_name: class.name.clone(), property_name: p.name.clone() });
+                    },
+                    Some(property) => {
+                        let property = encode_value(function, property, &p.data_type, c_types, &format!("{}.{}", path, p.name))?;
+                        filtered.insert(p.name.clone(), property);
+                    },
+                }
+            }
+
+            Ok(Value::Struct {
+                data_type: class.name.clone(),
+                properties: filtered,
+            })
+        },
+        Value::Array { data_type, entries } => {
+            // Rebuild each of the elements with the array's element type
+            let element_type = c_type.strip_suffix("[]").unwrap_or(c_type);
+            let mut rebuilt = Vec::with_capacity(entries.len());
+            for (i, entry) in entries.iter().enumerate() {
+                rebuilt.push(encode_value(function, entry, element_type, c_types, &format!("{}[{}]", path, i))?);
+            }
+
+            Ok(Value::Array {
+                data_type: data_type.clone(),
+                entries: rebuilt,
+            })
+        },
+        _ => Ok(value.clone()),
+    }
+}
+
+
+
+
+
 /***** WAITING FOR RESULT *****/
+/// **Edited: heartbeats are now sent in the background for the whole run (see `Heartbeat`).**
+/// 
 /// Waits for the given process to complete, then returns its result.
 /// 
 /// **Arguments**
 ///  * `function`: The OpenAPI function to run.
 ///  * `arguments`: The Arguments to pass to the OpenAPI call.
 ///  * `oas_doc`: The parsed document with the call to execute.
-///  * `callback`: A Callback object to send heartbeats with.
 /// 
 /// **Returns**  
 /// The PackageReturnState describing how the call went on success, or a LetError on failure.
@@ -192,41 +388,17 @@ async fn complete(
     function: &str,
     arguments: &Map<Value>,
     oas_doc: &OpenAPI,
-    callback: &mut Option<&mut Callback>,
 ) -> Result<PackageReturnState, LetError> {
-    // Handle waiting for the subprocess and doing heartbeats in a neat way, using select
-    let result = loop {
-        // Prepare the timer
-        let sleep = time::sleep(Duration::from_millis(HEARTBEAT_DELAY));
-        tokio::pin!(sleep);
-
-        // Wait for either the timer or the process
-        let status = loop {
-            tokio::select! {
-                result = brane_oas::execute(function, arguments, oas_doc) => {
-                    // Process is finished!
-                    break Some(result);
-                },
-                _ = &mut sleep => {
-                    // Timeout occurred; send the heartbeat and continue
-                    if let Some(callback) = callback {
-                        if let Err(err) = callback.heartbeat().await { warn!("Could not update driver on Heartbeat: {}", err); }
-                        else { debug!("Sent Heartbeat to driver."); }
-                    }
-
-                    // Stop without result
-                    break None;
-                },
-            }
-        };
-
-        // If we have a result, break from the main loop; otherwise, try again
-        if let Some(status) = status { break status; }
-    };
+    let started = Instant::now();
+    let result = brane_oas::execute(function, arguments, oas_doc).await;
 
     // Match the status
     match result {
-        Ok(stdout) => Ok(PackageReturnState::Finished{ stdout }),
+        Ok(stdout) => {
+            let stats = CallStats{ wall_time: started.elapsed().as_secs_f64(), response_size: Some(stdout.len() as u64), ..Default::default() };
+            debug!("Package call used {}", stats);
+            Ok(PackageReturnState::Finished{ stdout, stats: Some(stats) })
+        },
         Err(err)   => Ok(PackageReturnState::Failed{ code: -1, stdout: String::new(), stderr: format!("Could not perform external OpenAPI call: {}", err) }),
     }
 }
@@ -242,7 +414,6 @@ async fn complete(
 ///  * `result`: The result from the call that we (possibly) want to decode.
 ///  * `return_type`: The one general object / type that is returned by the call.
 ///  * `c_types`: The output types to capture in the resulting output.
-///  * `p_name`: The name of the output argument we're currently parsing. Used for writing sensible errors only.
 /// 
 /// **Returns**  
 /// The decoded return state as a PackageResult, or a LetError otherwise.
@@ -253,30 +424,26 @@ fn decode(
 ) -> Result<PackageResult, LetError> {
     // Match on the result
     match result {
-        PackageReturnState::Finished{ stdout } => {
+        PackageReturnState::Finished{ stdout, stats } => {
             // First, convert the input to JSON
-            let stdout_json = match serde_json::from_str(&stdout) {
+            let stdout_json: JValue = match serde_json::from_str(&stdout) {
                 Ok(stdout_json) => stdout_json,
                 Err(err)        => { return Err(LetError::DecodeError{ stdout, err: DecodeError::InvalidJSON{ err } }); }
             };
 
-            // Try to parse it into a value (which always succeeds, as its already valid JSON and we accept any object / value)
-            let output = Value::from_json(&stdout_json);
             debug!("Received JSON response:\n{}", serde_json::to_string_pretty(&stdout_json).unwrap_or_else(|_| String::from("<could not serialize>")));
-            debug!("Parsed response:\n{:#?}", output);
-            debug!("Trying to construct '{}' from parsed response.", return_type);
-
-            // If the nested type is an Array or a Struct, verify its type; otherwise, just parse
-            let output = match &output {
-                Value::Array { .. } | Value::Struct { .. } => match as_type(&output, return_type, c_types, "OAS output") {
-                    Ok(value) => value,
-                    Err(err)  => { return Err(LetError::DecodeError{ stdout, err }); }
-                },
-                _ => output,
+            debug!("Trying to construct '{}' from response.", return_type);
+
+            // Map the JSON onto the declared return type (where an empty response is unit, whatever the type)
+            let output = if stdout_json.is_null() { Ok(Value::Unit) } else { json_to_value(&stdout_json, return_type, c_types, "$") };
+            let output = match output {
+                Ok(output) => output,
+                Err(err)   => { return Err(LetError::DecodeError{ stdout, err }); }
             };
+            debug!("Parsed response:\n{:#?}", output);
 
             // Done
-            Ok(PackageResult::Finished{ result: output })
+            Ok(PackageResult::Finished{ result: output, stats, artifacts: None })
         },
 
         PackageReturnState::Failed{ code, stdout, stderr } => {
@@ -291,75 +458,108 @@ fn decode(
     }
 }
 
-/// **Edited: Now returning DecodeErrors.**
+/// **Edited: Now mapping the JSON itself, guided by the declared type.**
 /// 
-/// Tries to build the given object or array with proper typing.  
-/// Simply clones the value if it isn't an object or an array.
+/// Maps the given JSON onto a Value of the given type. Arrays are mapped element-wise, objects become a Struct of the declared class (where missing or null optional properties become Unit and undeclared fields are ignored). Null is refused for anything else than optional properties and the unit type.
 /// 
 /// **Arguments**
-///  * `object`: The object or array (or other) to rebuild.
-///  * `c_type`: The type we want the object to be.
+///  * `json`: The JSON to map.
+///  * `c_type`: The type we want the value to be.
 ///  * `c_types`: A list of known Class type definitions.
-///  * `p_name`: The name of the output argument we're currently parsing. Used for writing sensible errors only.
+///  * `path`: The path of the JSON we're currently mapping (e.g., `$.items[2].name`). Used for writing sensible errors only.
 /// 
 /// **Returns**  
-/// The rebuilt Value on success, or a DecodeError otherwise.
-fn as_type(
-    object: &Value,
+/// The mapped Value on success, or a DecodeError naming the path of the offending JSON otherwise.
+fn json_to_value(
+    json: &JValue,
     c_type: &str,
     c_types: &Map<Type>,
-    p_name: &str,
+    path: &str,
 ) -> Result<Value, DecodeError> {
-    // Switch on the object type
-    let mut filtered = Map::<Value>::new();
-    match object {
-        Value::Struct { properties, .. } => {
-            // It's a struct, so check if the type exists in the form it is now
-            match c_types.get(c_type) {
-                Some(c_type) => {
-                    // Rebuild all properties
-                    for p in &c_type.properties {
-                        // Try to get one
-                        let property = match properties.get(&p.name) {
-                            Some(property) => property,
-                            None           => { return Err(DecodeError::MissingStructProperty{ name: p_name.to_string(), class_name: c_type.name.clone(), property_name: p.name.clone() }); }
-                        };
-
-                        // Rebuild it with a recursive call
-                        let property = as_type(property, &p.data_type, c_types, p_name)?;
-                        filtered.insert(p.name.to_string(), property.clone());
-                    }
-
-                    // Return the rebuild struct
-                    Ok(Value::Struct {
-                        data_type: c_type.name.clone(),
-                        properties: filtered,
-                    })
-                },
-                None => Err(DecodeError::UnknownClassType{ name: p_name.to_string(), class_name: c_type.to_string() }),
-            }
+    // Arrays are mapped element-wise
+    if let Some(element_type) = c_type.strip_suffix("[]") {
+        let elements = match json.as_array() {
+            Some(elements) => elements,
+            None           => { return Err(DecodeError::OutputTypeMismatch{ name: path.to_string(), expected: c_type.to_string(), got: json_type(json).to_string() }); }
+        };
+
+        let mut entries = Vec::with_capacity(elements.len());
+        for (i, element) in elements.iter().enumerate() {
+            entries.push(json_to_value(element, element_type, c_types, &format!("{}[{}]", path, i))?);
         }
-        Value::Array { entries: elements, .. } => {
-            // Get the array's base type name
-            let n = match c_type.find('[') {
-                Some(n) => n,
-                None    => { return Err(DecodeError::OutputTypeMismatch{ name: p_name.to_string(), expected: c_type.to_string(), got: "Array".to_string() }); }
+        return Ok(Value::Array {
+            data_type: c_type.to_string(),
+            entries,
+        });
+    }
+
+    // Match the remaining builtin types
+    let value = match c_type {
+        "boolean" => json.as_bool().map(Value::Boolean),
+        "integer" => json.as_i64().map(Value::Integer),
+        "real"    => json.as_f64().map(Value::Real),
+        "string"  => json.as_str().map(|string| Value::Unicode(string.to_string())),
+        "unit"    => {
+            debug!("Ignoring response at '{}', as no response is declared", path);
+            Some(Value::Unit)
+        },
+
+        // Otherwise, it must be an object of a known class
+        class_name => {
+            let class = match c_types.get(class_name) {
+                Some(class) => class,
+                None        => { return Err(DecodeError::UnknownClassType{ name: path.to_string(), class_name: class_name.to_string() }); }
+            };
+            let object = match json.as_object() {
+                Some(object) => object,
+                None         => { return Err(DecodeError::OutputTypeMismatch{ name: path.to_string(), expected: class_name.to_string(), got: json_type(json).to_string() }); }
             };
-            let element_type: String = c_type.chars().take(n).collect();
 
-            // Go through each of the elements, recursing to rebuild those
-            let mut entries = vec![];
-            for element in elements.iter() {
-                let variable = as_type(element, &element_type, c_types, p_name)?;
-                entries.push(variable);
+            // Map all declared properties
+            let mut properties = Map::<Value>::new();
+            for p in &class.properties {
+                let property = match object.get(&p.name).filter(|property| !property.is_null() || !p.optional.unwrap_or(false)) {
+                    Some(property)                      => json_to_value(property, &p.data_type, c_types, &format!("{}.{}", path, p.name))?,
+                    None if p.optional.unwrap_or(false) => Value::Unit,
+                    None                                => { return Err(DecodeError::MissingStructProperty{ name: path.to_string(), class_name: class.name.clone(), property_name: p.name.clone() }); }
+                };
+                properties.insert(p.name.clone(), property);
             }
 
-            // Finally, return the rebuild array
-            Ok(Value::Array {
-                entries,
-                data_type: c_type.to_string(),
-            })
-        }
-        _ => Ok(object.clone()),
+            // Any other fields are not part of the class
+            for name in object.keys().filter(|name| !class.properties.iter().any(|p| &p.name == *name)) {
+                debug!("Ignoring field '{}.{}', as class '{}' does not declare it", path, name, class.name);
+            }
+
+            return Ok(Value::Struct {
+                data_type: class.name.clone(),
+                properties,
+            });
+        },
+    };
+
+    // Fail if the scalar did not match
+    match value {
+        Some(value) => Ok(value),
+        None        => Err(DecodeError::OutputTypeMismatch{ name: path.to_string(), expected: c_type.to_string(), got: json_type(json).to_string() }),
+    }
+}
+
+/// Returns the name of the (Brane) type of the given JSON, for use in errors.
+/// 
+/// **Arguments**
+///  * `json`: The JSON to return the type of.
+/// 
+/// **Returns**  
+/// The name of the type.
+fn json_type(json: &JValue) -> &'static str {
+    match json {
+        JValue::Null                      => "unit",
+        JValue::Bool(_)                   => "boolean",
+        JValue::Number(n) if n.is_i64()   => "integer",
+        JValue::Number(_)                 => "real",
+        JValue::String(_)                 => "string",
+        JValue::Array(_)                  => "array",
+        JValue::Object(_)                 => "object",
     }
 }
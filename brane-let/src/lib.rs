@@ -2,6 +2,8 @@ extern crate anyhow;
 #[macro_use]
 extern crate log;
 
+pub mod array;
+pub mod artifacts;
 pub mod callback;
 pub mod common;
 pub mod errors;
@@ -9,3 +11,6 @@ pub mod exec_ecu;
 pub mod exec_nop;
 pub mod exec_oas;
 pub mod redirector;
+pub mod session;
+pub mod stats;
+pub mod workdir;
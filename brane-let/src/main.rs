@@ -1,18 +1,25 @@
-use brane_let::callback::Callback;
-use brane_let::common::PackageResult;
+use brane_job::naming::array_element_id;
+use brane_let::array;
+use brane_let::artifacts::ArtifactStore;
+use brane_let::callback::{max_batch_window, Callback, CallbackOptions, Heartbeat};
+use brane_let::common::{cap_output, HEARTBEAT_DELAY, MAX_OUTPUT_SIZE, PackageResult};
 use brane_let::errors::LetError;
 use brane_let::exec_ecu;
 use brane_let::exec_nop;
 use brane_let::exec_oas;
 use brane_let::redirector;
+use brane_let::session::{self, SessionData, DATA_ROOT};
+use brane_let::stats::finished_payload;
+use brane_let::workdir::{self, KEEP_WORKDIR_ENV};
 use clap::Parser;
 use dotenv::dotenv;
 use log::{debug, LevelFilter};
 use serde::de::DeserializeOwned;
 use socksx::socks6::options::MetadataOption;
 use socksx::socks6::options::SocksOption;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{self, Command, Stdio};
+use std::time::Duration;
 
 #[derive(Parser)]
 #[clap(version = env!("CARGO_PKG_VERSION"))]
@@ -29,6 +36,36 @@ struct Opts {
     proxy_address: Option<String>,
     #[clap(short, long, env = "BRANE_MOUNT_DFS")]
     mount_dfs: Option<String>,
+    /// The subdirectory of the data directory that belongs to the session of this job; if given, the package only gets to see that subdirectory
+    #[clap(long, env = "BRANE_SESSION_DATA")]
+    session_data: Option<String>,
+    /// The time between two heartbeats sent to the driver while the package runs (in milliseconds, default 5000)
+    #[clap(long, env = "BRANE_HEARTBEAT_INTERVAL")]
+    heartbeat_interval: Option<u64>,
+    /// The time that heartbeats wait for other callbacks, so that they are sent to the driver together (in milliseconds; 0 sends every callback on its own). Defaults to, and is capped at, half the heartbeat interval
+    #[clap(long, env = "BRANE_CALLBACK_BATCH_WINDOW")]
+    callback_batch_window: Option<u64>,
+    /// The maximum number of bytes of the stdout and the stderr each that are sent to the driver if the package fails (default 65536)
+    #[clap(long, env = "BRANE_MAX_OUTPUT_SIZE")]
+    max_output_size: Option<usize>,
+    /// The directory to store the outputs of the function in (default: the data directory, if a DFS is mounted there)
+    #[clap(long, env = "BRANE_ARTIFACT_DIR")]
+    artifact_dir: Option<PathBuf>,
+    /// The maximum size (in bytes) of a single output of the function (default 1 GiB)
+    #[clap(long, env = "BRANE_ARTIFACT_MAX_SIZE")]
+    artifact_max_size: Option<u64>,
+    /// The maximum size (in bytes) of all outputs of the function together (default 4 GiB)
+    #[clap(long, env = "BRANE_ARTIFACT_MAX_TOTAL")]
+    artifact_max_total: Option<u64>,
+    /// Keeps whatever the call leaves behind in the working directory for debugging, if set to '1' or 'true'
+    #[clap(long, env = "BRANE_KEEP_WORKDIR")]
+    keep_workdir: Option<String>,
+    /// The directory to use if the working directory cannot be created (default: a directory in the system's temporary directory)
+    #[clap(long, env = "BRANE_WORKDIR_FALLBACK")]
+    workdir_fallback: Option<PathBuf>,
+    /// If given, the job is an element of a job array of this size, which runs with the arguments of its own element (see the `array` module)
+    #[clap(long, env = "BRANE_ARRAY_SIZE")]
+    array_size: Option<u32>,
     /// Prints debug info
     #[clap(short, long, env = "DEBUG", takes_value = false)]
     debug: bool,
@@ -70,7 +107,6 @@ async fn main() {
 
     let application_id = opts.application_id.clone();
     let location_id = opts.location_id.clone();
-    let job_id = opts.job_id.clone();
     let callback_to = opts.callback_to.clone();
     let proxy_address = opts.proxy_address.clone();
 
@@ -86,6 +122,22 @@ async fn main() {
     debug!("BRANELET v{}", env!("CARGO_PKG_VERSION"));
     debug!("Initializing...");
 
+    // An element of a job array reports as a job of its own
+    let element: Option<u32> = match opts.array_size {
+        Some(size) => match array::element_index(size, |name| std::env::var(name).ok()) {
+            Ok(index) => Some(index),
+            Err(err)  => { log::error!("{}", err); std::process::exit(-1); }
+        },
+        None => None,
+    };
+    let job_id = match element {
+        Some(index) => {
+            debug!("Running element {} of job array '{}'", index, opts.job_id);
+            array_element_id(&opts.job_id, index)
+        },
+        None => opts.job_id.clone(),
+    };
+
     // Mount DFS via JuiceFS.
     if let Some(ref mount_dfs) = opts.mount_dfs {
         debug!("Initializing JuiceFS...");
@@ -108,6 +160,30 @@ async fn main() {
         }
     }
 
+    // Scope the data directory (including whatever JuiceFS mounted there) to the session of this job.
+    let session_data: Option<SessionData> = match &opts.session_data {
+        Some(session_data) => {
+            debug!("Scoping data directory to session...");
+            match session::enter(Path::new(DATA_ROOT), session_data) {
+                Ok(session_data) => session_data,
+                Err(err)         => { log::error!("{}", err); std::process::exit(-1); }
+            }
+        },
+        None => None,
+    };
+    let package_dir = session_data.as_ref().and_then(SessionData::package_dir).map(Path::to_path_buf);
+
+    // Store the outputs of the function in the directory the location told us to, or else next to the (session's) data on the DFS
+    let artifact_root = match (&opts.artifact_dir, &opts.mount_dfs) {
+        (Some(artifact_dir), _) => Some(artifact_dir.clone()),
+        (None, Some(_))         => Some(package_dir.clone().unwrap_or_else(|| PathBuf::from(DATA_ROOT))),
+        (None, None)            => None,
+    };
+    let artifacts: Option<ArtifactStore> = match artifact_root.map(|root| ArtifactStore::new(root, &job_id, opts.artifact_max_size, opts.artifact_max_total)).transpose() {
+        Ok(artifacts) => artifacts,
+        Err(err)      => { log::error!("{}", err); std::process::exit(-1); }
+    };
+
     // Start redirector in the background, if proxy address is set.
     if let Some(proxy_address) = proxy_address {
         debug!("Initializing proxy...");
@@ -124,10 +200,21 @@ async fn main() {
         };
     }
 
+    let heartbeat_interval = opts.heartbeat_interval.unwrap_or(HEARTBEAT_DELAY);
+    if heartbeat_interval == 0 { log::error!("{}", LetError::IllegalHeartbeatInterval); std::process::exit(-1); }
+    let heartbeat_interval = Duration::from_millis(heartbeat_interval);
+
     // Callbacks may be called at any time of the execution.
     debug!("Initializing callback...");
+    let max_window = max_batch_window(heartbeat_interval);
+    let mut callback_options = CallbackOptions{ batch_window: max_window, ..Default::default() };
+    if let Some(window) = opts.callback_batch_window {
+        let window = Duration::from_millis(window);
+        if window > max_window { log::warn!("Callback batch window of {}ms is longer than half the heartbeat interval; using {}ms instead", window.as_millis(), max_window.as_millis()); }
+        callback_options.batch_window = std::cmp::min(window, max_window);
+    }
     let callback: Option<Callback> = match callback_to {
-        Some(callback_to) => match Callback::new(application_id, location_id, job_id, callback_to).await {
+        Some(callback_to) => match Callback::new(application_id, location_id, job_id, callback_to, callback_options).await {
             Ok(callback) => Some(callback),
             Err(err)     => { log::error!("Could not setup callback connection: {}", err); std::process::exit(-1); }
         },
@@ -135,7 +222,10 @@ async fn main() {
     };
 
     // Wrap actual execution, so we can always log errors.
-    match run(opts.sub_command, callback).await {
+    let max_output_size = opts.max_output_size.unwrap_or(MAX_OUTPUT_SIZE);
+    let keep_workdir = workdir::is_enabled(opts.keep_workdir.as_deref());
+    if keep_workdir { debug!("Keeping the working directory after the call ({} is set)", KEEP_WORKDIR_ENV); }
+    match run(opts.sub_command, element, callback, heartbeat_interval, max_output_size, package_dir, artifacts, opts.workdir_fallback, keep_workdir).await {
         Ok(code) => process::exit(code),
         Err(err) => {
             log::error!("{}", err);
@@ -144,19 +234,34 @@ async fn main() {
     }
 }
 
-/// **Edited: instantiating callback earlier, updated callback policy (new callback interface + new events). Also returning LetErrors.**
+/// **Edited: instantiating callback earlier, updated callback policy (new callback interface + new events). Also returning LetErrors. Now sending heartbeats in the background, preparing (and cleaning up) the working directory, storing artifacts and running elements of job arrays.**
 /// 
 /// Runs the job that this branelet is in charge of.
 /// 
 /// **Arguments**
 ///  * `sub_command`: The subcommand to execute (is it code, oas or nop?)
+///  * `element`: The index of our element if the job is part of a job array, which picks the arguments to run with.
 ///  * `callback`: The Callback future that asynchronously constructs a Callback instance.
+///  * `heartbeat_interval`: The time between two heartbeats while the package runs.
+///  * `max_output_size`: The maximum number of bytes of the stdout and the stderr each that we send to the driver if the package fails.
+///  * `package_dir`: The directory to run a code package in, if not its working directory (i.e., its session's data directory, if that could not be mounted).
+///  * `artifacts`: The ArtifactStore to store the outputs of a code package in, if there is shared storage to put them.
+///  * `workdir_fallback`: The directory to use if the working directory cannot be created, if not one in the system's temporary directory.
+///  * `keep_workdir`: Whether to keep whatever the call leaves behind in the working directory.
 /// 
 /// **Returns**  
 /// The exit code of the nested application on success, or a LetError otherwise.
+#[allow(clippy::too_many_arguments)]
 async fn run(
     sub_command: SubCommand,
+    element: Option<u32>,
     callback: Option<Callback>,
+    heartbeat_interval: Duration,
+    max_output_size: usize,
+    package_dir: Option<PathBuf>,
+    artifacts: Option<ArtifactStore>,
+    workdir_fallback: Option<PathBuf>,
+    keep_workdir: bool,
 ) -> Result<i32, LetError> {
     let mut callback = callback;
 
@@ -165,27 +270,62 @@ async fn run(
         if let Err(err) = callback.ready().await { log::error!("Could not update driver on Ready: {}", err); }
     }
 
+    // Make sure there is a working directory to run in, even if the image doesn't have one (which is cleaned up when dropped, should we return early)
+    let workdir = match &sub_command {
+        SubCommand::Code{ working_dir, .. } | SubCommand::WebApi{ working_dir, .. } => Some(workdir::prepare(working_dir, workdir_fallback.as_deref(), keep_workdir)?),
+        SubCommand::NoOp => None,
+    };
+    let working_dir = workdir.as_ref().map(|workdir| workdir.path().to_path_buf()).unwrap_or_default();
+
+    // Keep the driver posted until we know how the package went, however quiet the package itself is
+    let heartbeat = callback.as_ref().map(|callback| Heartbeat::start(callback.clone(), heartbeat_interval));
+
     // Switch on the sub_command to do the actual work
     let output = match sub_command {
         SubCommand::Code {
             function,
             arguments,
-            working_dir,
-        } => exec_ecu::handle(function, decode_b64(arguments)?, working_dir, &mut callback.as_mut()).await,
+            ..
+        } => exec_ecu::handle(function, decode_b64(array::element_arguments(arguments, element)?)?, working_dir, package_dir, artifacts.as_ref(), &mut callback.as_mut()).await,
         SubCommand::WebApi {
             function,
             arguments,
-            working_dir,
-        } => exec_oas::handle(function, decode_b64(arguments)?, working_dir, &mut callback.as_mut()).await,
+            ..
+        } => exec_oas::handle(function, decode_b64(array::element_arguments(arguments, element)?)?, working_dir, &mut callback.as_mut()).await,
         SubCommand::NoOp {
         } => exec_nop::handle(&mut callback.as_mut()).await,
     };
+    if let Some(heartbeat) = heartbeat { heartbeat.stop().await; }
+
+    // Report how the package went, and only then clean up after it
+    let res = report(output, callback, max_output_size).await;
+    if let Some(workdir) = workdir {
+        if let Err(err) = workdir.cleanup() { log::warn!("{}", err); }
+    }
+    res
+}
+
+/// Performs the final callback, which tells the driver how the package went.
+/// 
+/// **Arguments**
+///  * `output`: The result of the package call.
+///  * `callback`: The Callback to report with. If omitted, the result is written to stdout (or the failure to stderr).
+///  * `max_output_size`: The maximum number of bytes of the stdout and the stderr each that we send to the driver if the package fails.
+/// 
+/// **Returns**  
+/// The exit code of the nested application on success, or a LetError otherwise.
+async fn report(
+    output: Result<PackageResult, LetError>,
+    callback: Option<Callback>,
+    max_output_size: usize,
+) -> Result<i32, LetError> {
+    let mut callback = callback;
 
     // Perform final FINISHED callback.
     match output {
-        Ok(PackageResult::Finished{ result }) => {
-            // Convert the output to a string
-            let output = match serde_json::to_string(&result) {
+        Ok(PackageResult::Finished{ result, stats, artifacts }) => {
+            // Convert the output to a string, along with the resources the call used and the artifacts it left
+            let output = match finished_payload(&result, stats.as_ref(), artifacts.as_deref()) {
                 Ok(output) => output,
                 Err(err)   => {
                     let err = LetError::ResultJSONError{ value: format!("{:?}", result), err };
@@ -197,10 +337,15 @@ async fn run(
             };
 
             // If that went successfull, output the result in some way
-            if let Some(ref mut callback) = callback {
-                // Use the callback to report it
-                if let Err(err) = callback.finished(output).await { log::error!("Could not update driver on Finished: {}", err); }
-            } else {
+            // (If the callback could not be delivered, we fall back to stdout as well)
+            let undelivered = match callback {
+                Some(ref mut callback) => match callback.finished(output.clone()).await {
+                    Ok(_)    => false,
+                    Err(err) => { log::error!("Could not update driver on Finished: {}", err); true },
+                },
+                None => true,
+            };
+            if undelivered {
                 // Print to stdout as (base64-encoded) JSON
                 println!("{}", base64::encode(output));
             }
@@ -209,11 +354,15 @@ async fn run(
         },
 
         Ok(PackageResult::Failed{ code, stdout, stderr }) => {
-            // Back it up to the user
-            if let Some(ref mut callback) = callback {
-                // Use the callback to report it
-                if let Err(err) = callback.failed(code, stdout, stderr).await { log::error!("Could not update driver on Failed: {}", err); }
-            } else {
+            // Back it up to the user, cutting overly long output so the event is never too large to deliver
+            let undelivered = match callback {
+                Some(ref mut callback) => match callback.failed(code, cap_output(stdout.clone(), max_output_size), cap_output(stderr.clone(), max_output_size)).await {
+                    Ok(_)    => false,
+                    Err(err) => { log::error!("Could not update driver on Failed: {}", err); true },
+                },
+                None => true,
+            };
+            if undelivered {
                 // Gnerate the line divider
                 let lines = (0..80).map(|_| '-').collect::<String>();
                 // Print to stderr
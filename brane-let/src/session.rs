@@ -0,0 +1,178 @@
+/* SESSION.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 22:58:04
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Scopes the data directory to the session of the job, so that the
+ *   jobs of different sessions cannot see (or clobber) each other's
+ *   files.
+**/
+
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::errors::LetError;
+
+
+/***** CONSTANTS *****/
+/// The directory with the data of jobs, be it a volume of the infrastructure or the JuiceFS mount.
+pub const DATA_ROOT: &str = "/data";
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brane_job::interface::session_data_dir;
+
+    #[test]
+    fn session_directory_is_created_under_the_root() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        let dir = prepare(&root, "session-abc").unwrap();
+        assert_eq!(dir, root.join("session-abc"));
+        assert!(dir.is_dir());
+
+        // Preparing it again keeps what's in there
+        fs::write(dir.join("result.csv"), "1,2,3").unwrap();
+        assert_eq!(prepare(&root, "session-abc").unwrap(), dir);
+        assert_eq!(fs::read_to_string(dir.join("result.csv")).unwrap(), "1,2,3");
+    }
+
+    #[test]
+    fn illegal_session_directories_are_rejected() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        for session in [ "", ".", "..", "../other", "a/b", "/data" ] {
+            assert!(matches!(prepare(&root, session), Err(LetError::IllegalSessionData{ .. })), "Session '{}' was accepted", session);
+        }
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn sessions_get_disjoint_directories() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        let first = prepare(&root, &session_data_dir("0b6c7c1e-4f1d-4b8e-a3c5-0c1d2e3f4a5b")).unwrap();
+        let second = prepare(&root, &session_data_dir("9d0e1f2a-3b4c-4d5e-8f6a-7b8c9d0e1f2a")).unwrap();
+        assert!(!first.starts_with(&second) && !second.starts_with(&first), "'{}' and '{}' overlap", first.display(), second.display());
+
+        // What one session writes, the other doesn't see
+        fs::write(first.join("output.txt"), "mine").unwrap();
+        assert!(!second.join("output.txt").exists());
+    }
+
+    #[test]
+    fn missing_data_directory_is_left_alone() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("data");
+        assert_eq!(enter(&root, "session-abc").unwrap(), None);
+        assert!(!root.exists());
+    }
+
+    #[test]
+    fn package_runs_in_session_directory_only_without_mount() {
+        let dir = PathBuf::from("/data/session-abc");
+        assert_eq!(SessionData::Mounted{ dir: dir.clone() }.package_dir(), None);
+        assert_eq!(SessionData::Workdir{ dir: dir.clone() }.package_dir(), Some(dir.as_path()));
+    }
+}
+
+
+
+
+
+/***** LIBRARY ENUMS *****/
+/// Describes how the data directory of the job's session is exposed to the package.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionData {
+    /// The session's directory is bind-mounted over the data directory, so the package only sees the data of its own session there.
+    Mounted{ dir: PathBuf },
+    /// The session's directory could not be mounted, so the package runs in it instead (which only scopes relative paths).
+    Workdir{ dir: PathBuf },
+}
+
+impl SessionData {
+    /// Returns the directory to run the package in, if it should not run where it normally does.
+    #[inline]
+    pub fn package_dir(&self) -> Option<&Path> {
+        match self {
+            SessionData::Mounted{ .. } => None,
+            SessionData::Workdir{ dir } => Some(dir.as_path()),
+        }
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Creates the data directory of the job's session (if it doesn't exist yet).
+/// 
+/// **Arguments**
+///  * `root`: The data directory that is shared by all sessions.
+///  * `session`: The name of the session's subdirectory (as given by the driver in BRANE_SESSION_DATA).
+/// 
+/// **Returns**  
+/// The path of the session's data directory on success, or a LetError otherwise (also if the name isn't a single directory name).
+pub fn prepare(root: &Path, session: &str) -> Result<PathBuf, LetError> {
+    // Make sure the session cannot escape its own subdirectory
+    let mut components = Path::new(session).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => {},
+        _                                  => { return Err(LetError::IllegalSessionData{ session: session.to_string() }); }
+    }
+
+    let dir = root.join(session);
+    if let Err(err) = fs::create_dir_all(&dir) { return Err(LetError::SessionDataCreateError{ path: dir, err }); }
+    Ok(dir)
+}
+
+/// Scopes the data directory to the job's session, by bind-mounting the session's subdirectory over it. If that's not allowed (e.g., because the container is not privileged), the package is run in the session's subdirectory instead.
+/// 
+/// **Arguments**
+///  * `root`: The data directory that is shared by all sessions.
+///  * `session`: The name of the session's subdirectory (as given by the driver in BRANE_SESSION_DATA).
+/// 
+/// **Returns**  
+/// How the session's data directory is exposed, None if there is no data directory to scope, or a LetError if the session's directory could not be created.
+pub fn enter(root: &Path, session: &str) -> Result<Option<SessionData>, LetError> {
+    if !root.is_dir() {
+        debug!("No data directory '{}' to scope to session '{}'", root.display(), session);
+        return Ok(None);
+    }
+
+    let dir = prepare(root, session)?;
+    match bind_mount(&dir, root) {
+        Ok(())   => {
+            debug!("Mounted session data directory '{}' over '{}'", dir.display(), root.display());
+            Ok(Some(SessionData::Mounted{ dir }))
+        },
+        Err(err) => {
+            warn!("Could not mount session data directory '{}' over '{}' ({}); running the package in it instead", dir.display(), root.display(), err);
+            Ok(Some(SessionData::Workdir{ dir }))
+        },
+    }
+}
+
+
+
+/// Bind-mounts the given directory over another.
+fn bind_mount(source: &Path, target: &Path) -> std::io::Result<()> {
+    let source = CString::new(source.as_os_str().as_bytes())?;
+    let target = CString::new(target.as_os_str().as_bytes())?;
+    let res = unsafe { libc::mount(source.as_ptr(), target.as_ptr(), std::ptr::null(), libc::MS_BIND, std::ptr::null()) };
+    if res == 0 { Ok(()) } else { Err(std::io::Error::last_os_error()) }
+}
@@ -0,0 +1,162 @@
+/* STATS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 20:12:44
+ * Last edited:
+ *   15 Oct 2026, 20:12:44
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Collects the resources used by a package call, and embeds them in
+ *   the result that is sent back to the driver.
+**/
+
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::time::Instant;
+
+use brane_job::interface::{Artifact, CallStats, ARTIFACTS_KEY, CALL_STATS_KEY};
+use specifications::common::Value;
+use tokio::process::Child as TokioChild;
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::process::Command as TokioCommand;
+
+    async fn run(script: &str) -> (ExitStatus, CallStats) {
+        let started = Instant::now();
+        let process = TokioCommand::new("sh").arg("-c").arg(script).spawn().unwrap();
+        wait_with_usage(&process, started).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn busy_child_uses_cpu_and_memory() {
+        let (status, stats) = run("i=0; while [ $i -lt 200000 ]; do i=$((i + 1)); done").await;
+        assert!(status.success());
+        assert!(stats.user_time.unwrap() > 0.0, "Expected some user time, got {:?}", stats);
+        assert!(stats.system_time.is_some());
+        assert!(stats.max_rss.unwrap() > 0, "Expected some memory usage, got {:?}", stats);
+        assert!(stats.wall_time >= stats.user_time.unwrap() * 0.5);
+        assert_eq!(stats.response_size, None);
+    }
+
+    #[tokio::test]
+    async fn exit_code_is_preserved() {
+        let (status, stats) = run("exit 3").await;
+        assert_eq!(status.code(), Some(3));
+        assert!(stats.max_rss.is_some());
+    }
+
+    #[tokio::test]
+    async fn wall_time_includes_sleeping() {
+        let (status, stats) = run("sleep 0.2").await;
+        assert!(status.success());
+        assert!(stats.wall_time >= 0.2, "Expected at least 0.2s wall time, got {:?}", stats);
+    }
+
+    #[test]
+    fn payload_stays_a_value() {
+        let result = Value::Integer(42);
+        let stats = CallStats{ wall_time: 1.5, max_rss: Some(2048), ..Default::default() };
+        let artifacts = vec![ Artifact{ name: String::from("plot.png"), size: 3, path: String::from("/data/artifacts/job-1/plot.png"), sha256: String::from("ab12") } ];
+        let payload = finished_payload(&result, Some(&stats), Some(&artifacts[..])).unwrap();
+
+        // Older drivers simply skip the stats and the artifacts
+        assert_eq!(serde_json::from_str::<Value>(&payload).unwrap(), result);
+        assert_eq!(CallStats::from_payload(&payload), Some(stats));
+        assert_eq!(Artifact::from_payload(&payload), Some(artifacts));
+
+        let payload = finished_payload(&result, None, None).unwrap();
+        assert_eq!(payload, serde_json::to_string(&result).unwrap());
+        assert_eq!(CallStats::from_payload(&payload), None);
+        assert_eq!(Artifact::from_payload(&payload), None);
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Waits for the given process to complete, and collects the resources it used while doing so.
+/// 
+/// Note that this reaps the process itself, so it should not be waited for through tokio anymore (but its stdout and stderr may still be read).
+/// 
+/// **Arguments**
+///  * `process`: The process to wait for.
+///  * `started`: The moment at which the process was started, for computing the wall time.
+/// 
+/// **Returns**  
+/// The exit status of the process and the resources it used, or an io::Error if we could not wait for it.
+pub async fn wait_with_usage(process: &TokioChild, started: Instant) -> Result<(ExitStatus, CallStats), io::Error> {
+    let pid = match process.id() {
+        Some(pid) => pid as libc::pid_t,
+        None      => { return Err(io::Error::new(io::ErrorKind::Other, "process has already been waited for")); }
+    };
+
+    // wait4() blocks, so do it off the runtime
+    let (status, usage) = match tokio::task::spawn_blocking(move || wait4(pid)).await {
+        Ok(result) => result?,
+        Err(err)   => { return Err(io::Error::new(io::ErrorKind::Other, err)); }
+    };
+    let stats = CallStats {
+        wall_time     : started.elapsed().as_secs_f64(),
+        user_time     : Some(seconds(usage.ru_utime)),
+        system_time   : Some(seconds(usage.ru_stime)),
+        // Linux reports the maximum resident set size in kilobytes already
+        max_rss       : Some(usage.ru_maxrss as u64),
+        response_size : None,
+    };
+    Ok((ExitStatus::from_raw(status), stats))
+}
+
+
+
+/// Serializes the given result of a package call to the payload of a Finished callback, with the given CallStats and Artifacts nested under their own keys.
+/// 
+/// **Arguments**
+///  * `result`: The value that the package call returned.
+///  * `stats`: The resources that the package call used, if known.
+///  * `artifacts`: The files that the package call left behind, if its function declares outputs.
+/// 
+/// **Returns**  
+/// The JSON-encoded payload, or a serde_json::Error if the result could not be serialized.
+pub fn finished_payload(result: &Value, stats: Option<&CallStats>, artifacts: Option<&[Artifact]>) -> Result<String, serde_json::Error> {
+    let mut payload = serde_json::to_value(result)?;
+    if let (Some(stats), Some(object)) = (stats, payload.as_object_mut()) {
+        object.insert(CALL_STATS_KEY.to_string(), serde_json::to_value(stats)?);
+    }
+    if let (Some(artifacts), Some(object)) = (artifacts, payload.as_object_mut()) {
+        object.insert(ARTIFACTS_KEY.to_string(), serde_json::to_value(artifacts)?);
+    }
+    serde_json::to_string(&payload)
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Blocks until the process with the given PID terminates, and returns its raw wait status and resource usage.
+fn wait4(pid: libc::pid_t) -> Result<(i32, libc::rusage), io::Error> {
+    let mut status: libc::c_int = 0;
+    // SAFETY: rusage is plain old data, which wait4() fills in before we read it
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        // SAFETY: both pointers point to live, properly sized locals
+        let res = unsafe { libc::wait4(pid, &mut status, 0, &mut usage) };
+        if res == pid { return Ok((status, usage)); }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted { return Err(err); }
+    }
+}
+
+/// Converts the given timeval to (fractional) seconds.
+#[inline]
+fn seconds(time: libc::timeval) -> f64 { time.tv_sec as f64 + time.tv_usec as f64 / 1_000_000.0 }
@@ -0,0 +1,285 @@
+/* WORKDIR.rs
+ *   by Lut99
+ *
+ * Created:
+ *   16 Oct 2026, 00:00:14
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Prepares the working directory of the package: it is created if the
+ *   image doesn't have it (falling back to a temporary directory if that
+ *   is impossible), and whatever a call leaves behind in it is removed
+ *   after the result has been reported, so that a container that is
+ *   reused doesn't pile up garbage.
+**/
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::errors::LetError;
+
+
+/***** CONSTANTS *****/
+/// The environment variable that, if set to '1' (or 'true'), keeps whatever a call leaves in the working directory for debugging.
+pub const KEEP_WORKDIR_ENV: &str = "BRANE_KEEP_WORKDIR";
+/// The environment variable with the directory to use if the working directory cannot be created (instead of one in the system's temporary directory).
+pub const WORKDIR_FALLBACK_ENV: &str = "BRANE_WORKDIR_FALLBACK";
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_workdir_is_created_and_removed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        let requested = root.join("opt").join("wd");
+        let workdir = prepare(&requested, None, false).unwrap();
+        assert_eq!(workdir.path(), requested.as_path());
+        assert_eq!(workdir.kind(), WorkdirKind::Created);
+        assert!(requested.is_dir());
+
+        fs::write(requested.join("output.txt"), "garbage").unwrap();
+        workdir.cleanup().unwrap();
+        assert!(!requested.exists());
+    }
+
+    #[test]
+    fn existing_workdir_only_loses_new_contents() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        fs::write(root.join("local_container.yml"), "name: test").unwrap();
+        fs::create_dir(root.join("src")).unwrap();
+
+        let workdir = prepare(&root, None, false).unwrap();
+        assert_eq!(workdir.kind(), WorkdirKind::Existing);
+        fs::write(root.join("scratch.bin"), "garbage").unwrap();
+        fs::create_dir_all(root.join("cache").join("nested")).unwrap();
+        fs::write(root.join("src").join("generated.py"), "# kept, as src/ was there before").unwrap();
+        workdir.cleanup().unwrap();
+
+        let mut left: Vec<String> = fs::read_dir(&root).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().to_string()).collect();
+        left.sort();
+        assert_eq!(left, vec![ "local_container.yml", "src" ]);
+        assert!(root.join("src").join("generated.py").exists());
+    }
+
+    #[test]
+    fn uncreatable_workdir_falls_back() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        // A path below a file can never be created
+        fs::write(root.join("file"), "").unwrap();
+        let requested = root.join("file").join("wd");
+        let fallback = root.join("fallback");
+
+        let workdir = prepare(&requested, Some(&fallback), false).unwrap();
+        assert_eq!(workdir.path(), fallback.as_path());
+        assert_eq!(workdir.kind(), WorkdirKind::Fallback);
+        assert!(fallback.is_dir());
+        workdir.cleanup().unwrap();
+        assert!(!fallback.exists());
+
+        // If the fallback cannot be created either, we give up
+        let err = prepare(&requested, Some(&root.join("file").join("fallback")), false).unwrap_err();
+        assert!(matches!(err, LetError::WorkdirCreateError{ .. }), "Unexpected error: {}", err);
+    }
+
+    #[test]
+    fn dropped_workdir_is_cleaned_up() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        let requested = root.join("wd");
+        {
+            let _workdir = prepare(&requested, None, false).unwrap();
+            fs::write(requested.join("output.txt"), "garbage").unwrap();
+        }
+        assert!(!requested.exists());
+    }
+
+    #[test]
+    fn kept_workdir_is_left_alone() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        let requested = root.join("wd");
+        let workdir = prepare(&requested, None, true).unwrap();
+        fs::write(requested.join("output.txt"), "evidence").unwrap();
+        workdir.cleanup().unwrap();
+        assert_eq!(fs::read_to_string(requested.join("output.txt")).unwrap(), "evidence");
+    }
+
+    #[test]
+    fn keep_flag_values() {
+        for value in [ "1", "true", "TRUE", "yes" ] { assert!(is_enabled(Some(value)), "'{}' is not enabled", value); }
+        for value in [ "", "0", "false", "no" ] { assert!(!is_enabled(Some(value)), "'{}' is enabled", value); }
+        assert!(!is_enabled(None));
+    }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Where the working directory came from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WorkdirKind {
+    /// The working directory already existed (e.g., because the package was built into it).
+    Existing,
+    /// The working directory did not exist, so we created it.
+    Created,
+    /// The working directory could not be created, so we use the fallback directory instead.
+    Fallback,
+}
+
+
+
+/// The working directory of a call, which remembers what was in it beforehand so that it can be cleaned up afterwards.
+/// 
+/// It also acts as a guard: if it is dropped before `cleanup()` is called (e.g., because the call returned early with an error), it cleans up anyway.
+#[derive(Debug)]
+pub struct Workdir {
+    /// The path of the working directory.
+    path    : PathBuf,
+    /// Where the working directory came from.
+    kind    : WorkdirKind,
+    /// Whether we created the directory itself (and thus remove it when cleaning up).
+    created : bool,
+    /// The names of the entries that were in the directory before the call.
+    before  : HashSet<OsString>,
+    /// Whether to leave everything in place when cleaning up.
+    keep    : bool,
+    /// Whether we cleaned up already.
+    cleaned : bool,
+}
+
+impl Workdir {
+    /// Returns the path of the working directory.
+    #[inline]
+    pub fn path(&self) -> &Path { &self.path }
+
+    /// Returns where the working directory came from.
+    #[inline]
+    pub fn kind(&self) -> WorkdirKind { self.kind }
+
+    /// Removes whatever the call left behind in the working directory (and the directory itself, if we created it), unless we were asked to keep it.
+    /// 
+    /// **Returns**  
+    /// Nothing on success, or a LetError if something could not be removed.
+    #[inline]
+    pub fn cleanup(mut self) -> Result<(), LetError> { self.clean() }
+
+    /// Does the actual work for `cleanup()`, at most once.
+    fn clean(&mut self) -> Result<(), LetError> {
+        if self.cleaned { return Ok(()); }
+        self.cleaned = true;
+        if self.keep {
+            info!("Keeping working directory '{}' for debugging ({} is set)", self.path.display(), KEEP_WORKDIR_ENV);
+            return Ok(());
+        }
+        if self.created {
+            debug!("Removing working directory '{}'...", self.path.display());
+            return match fs::remove_dir_all(&self.path) {
+                Ok(_)    => Ok(()),
+                Err(err) => Err(LetError::WorkdirCleanupError{ path: self.path.clone(), err }),
+            };
+        }
+
+        // Only remove what wasn't there before
+        for path in entries(&self.path)?.into_iter().filter(|name| !self.before.contains(name)).map(|name| self.path.join(name)) {
+            debug!("Removing '{}' from working directory...", path.display());
+            let is_dir = fs::symlink_metadata(&path).map(|metadata| metadata.is_dir()).unwrap_or(false);
+            let res = if is_dir { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+            if let Err(err) = res { return Err(LetError::WorkdirCleanupError{ path, err }); }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Workdir {
+    fn drop(&mut self) {
+        if let Err(err) = self.clean() { warn!("{}", err); }
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Prepares the working directory of a call. If it doesn't exist, it is created (with its parents); if that fails, we warn and use the fallback directory instead.
+/// 
+/// **Arguments**
+///  * `requested`: The working directory to use (as given by BRANE_WORKDIR).
+///  * `fallback`: The directory to use if the working directory cannot be created. If omitted, a directory in the system's temporary directory is used (see `default_fallback()`).
+///  * `keep`: Whether to leave whatever the call leaves behind in place when cleaning up.
+/// 
+/// **Returns**  
+/// The Workdir on success, or a LetError if neither directory could be created.
+pub fn prepare(requested: &Path, fallback: Option<&Path>, keep: bool) -> Result<Workdir, LetError> {
+    if requested.is_dir() { return open(requested, WorkdirKind::Existing, false, keep); }
+
+    let err = match fs::create_dir_all(requested) {
+        Ok(_)    => { return open(requested, WorkdirKind::Created, true, keep); },
+        Err(err) => err,
+    };
+    let fallback = fallback.map(Path::to_path_buf).unwrap_or_else(default_fallback);
+    warn!("Could not create working directory '{}' ({}); falling back to '{}' (set {} to use another directory)", requested.display(), err, fallback.display(), WORKDIR_FALLBACK_ENV);
+
+    let created = !fallback.is_dir();
+    if let Err(err) = fs::create_dir_all(&fallback) { return Err(LetError::WorkdirCreateError{ path: requested.to_path_buf(), fallback, err }); }
+    open(&fallback, WorkdirKind::Fallback, created, keep)
+}
+
+/// Returns the directory that `prepare()` falls back to by default, which is unique for this process.
+#[inline]
+pub fn default_fallback() -> PathBuf {
+    std::env::temp_dir().join(format!("branelet-wd-{}", std::process::id()))
+}
+
+/// Returns whether a flag given as environment variable (such as BRANE_KEEP_WORKDIR) is enabled.
+/// 
+/// **Arguments**
+///  * `value`: The value of the variable, if it is set.
+#[inline]
+pub fn is_enabled(value: Option<&str>) -> bool {
+    matches!(value.map(|value| value.to_lowercase()).as_deref(), Some("1") | Some("true") | Some("yes"))
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Creates a Workdir for the given (existing) directory, remembering what's in it.
+fn open(path: &Path, kind: WorkdirKind, created: bool, keep: bool) -> Result<Workdir, LetError> {
+    let before = if created { HashSet::new() } else { entries(path)? };
+    debug!("Using working directory '{}' ({:?})", path.display(), kind);
+    Ok(Workdir{ path: path.to_path_buf(), kind, created, before, keep, cleaned: false })
+}
+
+/// Returns the names of the entries in the given directory.
+fn entries(path: &Path) -> Result<HashSet<OsString>, LetError> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(err)    => { return Err(LetError::WorkdirReadError{ path: path.to_path_buf(), err }); }
+    };
+    let mut names = HashSet::new();
+    for entry in entries {
+        match entry {
+            Ok(entry) => { names.insert(entry.file_name()); },
+            Err(err)  => { return Err(LetError::WorkdirReadError{ path: path.to_path_buf(), err }); }
+        }
+    }
+    Ok(names)
+}
@@ -1,15 +1,15 @@
 use crate::interface::{Event, EventKind};
 use crate::schema;
 use anyhow::{Context as AContext, Result};
+use brane_shr::kafka;
 use futures::stream::StreamExt;
 use log::info;
 use prost::Message;
 use rdkafka::{
     config::ClientConfig,
-    consumer::{stream_consumer::StreamConsumer, Consumer},
+    consumer::stream_consumer::StreamConsumer,
     message::OwnedMessage,
-    util::Timeout,
-    Message as KafkaMesage, Offset, TopicPartitionList,
+    Message as KafkaMesage,
 };
 use schema::KeyValuePair;
 use scylla::Session;
@@ -80,27 +80,11 @@ pub async fn start_worker(
         .create()
         .context("Failed to create Kafka consumer.")?;
 
-    // Restore previous topic/partition offset.
-    let mut tpl = TopicPartitionList::new();
-    for topic in event_topics.iter() {
-        tpl.add_partition(topic, 0);
-    }
-
-    let committed_offsets = consumer.committed_offsets(tpl.clone(), Timeout::Never)?;
-    let committed_offsets = committed_offsets.to_topic_map();
-    for topic in event_topics.iter() {
-        if let Some(offset) = committed_offsets.get(&(topic.clone(), 0)) {
-            match offset {
-                Offset::Invalid => tpl.set_partition_offset(topic, 0, Offset::Beginning)?,
-                offset => tpl.set_partition_offset(topic, 0, *offset)?,
-            };
-        }
-    }
-
-    info!("Restoring commited offsets: {:?}", &tpl);
-    consumer
-        .assign(&tpl)
+    // Read every partition of the topics, restoring the previous offsets.
+    let topics: Vec<&str> = event_topics.iter().map(String::as_str).collect();
+    let tpl = kafka::assign_partitions(&consumer, &topics)
         .context("Failed to manually assign topic, partition, and/or offset to consumer.")?;
+    info!("Restored commited offsets: {:?}", &tpl);
 
     let mut message_stream = consumer.stream();
 
@@ -1,6 +1,9 @@
 use prost::{Enumeration, Message};
 use time::OffsetDateTime;
 
+/// The schema version put on the events we send; brane-drv rejects events with a different major version.
+pub use brane_job::interface::SCHEMA_VERSION;
+
 #[derive(Clone, PartialEq, Message)]
 pub struct Event {
     #[prost(tag = "1", enumeration = "EventKind")]
@@ -19,6 +22,8 @@ pub struct Event {
     pub payload: Vec<u8>,
     #[prost(tag = "8", int64)]
     pub timestamp: i64,
+    #[prost(tag = "15", uint32)]
+    pub version: u32,
 }
 
 impl Event {
@@ -47,6 +52,7 @@ impl Event {
             order,
             payload: payload.unwrap_or_default(),
             timestamp,
+            version: SCHEMA_VERSION,
         }
     }
 }
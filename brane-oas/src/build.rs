@@ -4,9 +4,7 @@ use anyhow::Result;
 use openapiv3::{Components, Parameter as OParameter, Type as OType};
 use openapiv3::{OpenAPI, ReferenceOr, SecurityScheme};
 use openapiv3::{Operation, ParameterSchemaOrContent, Schema, SchemaKind};
-use rand::distributions::Alphanumeric;
-use rand::{self, Rng};
-use specifications::common::{CallPattern, Function, Parameter, Property, Type};
+use specifications::common::{CallPattern, Function, Parameter, Property, Type, Value};
 
 type Map<T> = std::collections::HashMap<String, T>;
 type FunctionsAndTypes = (Map<Function>, Map<Type>);
@@ -14,7 +12,11 @@ type FunctionsAndTypes = (Map<Function>, Map<Type>);
 const OAS_ADD_OPERATION_ID: &str = "Please add an operation ID (operationId) to each operation.";
 const OAS_CONTENT_NOT_SUPPORTED: &str = "OpenAPI parameter content mapping is not supported.";
 const OAS_JSON_MEDIA_NOT_FOUND: &str = "JSON media type not found (application/json).";
-const OAS_NESTED_OBJECTS_NOT_SUPPORTED: &str = "Nested objects are not supported.";
+const OAS_FREE_FORM_OBJECTS_NOT_SUPPORTED: &str = "Nested objects without properties are not supported.";
+const OAS_NESTED_ARRAYS_NOT_SUPPORTED: &str = "Nested arrays are not supported.";
+const OAS_ONE_OF_NOT_SUPPORTED: &str = "Schemas using 'oneOf' are not supported.";
+const OAS_ANY_OF_NOT_SUPPORTED: &str = "Schemas using 'anyOf' are not supported.";
+const OAS_COMPOSITION_NOT_SUPPORTED: &str = "Composed schemas (e.g., 'allOf') are not supported.";
 
 /// Traverses a valid OpenAPI document and builds a function
 /// for every operation it finds. Corresponding input/output
@@ -166,8 +168,10 @@ pub fn build_oas_function(
     // Build function
     let name = operation_id.to_lowercase();
     let call_pattern = CallPattern::new(Some(name.clone()), None, None);
+    let mut function = Function::new(input, Some(call_pattern), output);
+    function.description = operation.description.clone().or_else(|| operation.summary.clone());
     let functions = hashmap! {
-        name => Function::new(input, Some(call_pattern), output)
+        name => function
     };
 
     // Combine input and output types
@@ -193,7 +197,7 @@ fn build_oas_function_input(
     // Determine input from paramaters.
     for parameter in &operation.parameters {
         let parameter = resolver::resolve_parameter(parameter, components)?;
-        let mut properties = parameter_to_properties(&parameter, components, &mut input_types)?;
+        let mut properties = parameter_to_properties(operation_id, &parameter, components, &mut input_types)?;
 
         input_properties.append(&mut properties);
     }
@@ -208,7 +212,8 @@ fn build_oas_function_input(
                 let (ref_name, schema) = resolver::resolve_schema(schema, components)?;
 
                 let required = true; // At the top-level, the request body is required, if present.
-                let properties = schema_to_properties(None, &schema, required, components, &mut input_types, ref_name)?;
+                let path = format!("{}.body", operation_id);
+                let properties = schema_to_properties(None, &schema, required, components, &mut input_types, ref_name, &path)?;
 
                 input_properties.extend(properties);
             }
@@ -254,6 +259,7 @@ fn build_oas_function_input(
         let input_type = Type {
             name: input_data_type.clone(),
             properties: input_properties,
+            description: None,
         };
 
         input_types.insert(input_data_type.clone(), input_type);
@@ -302,7 +308,8 @@ fn build_oas_function_output(
         if let Some(schema) = &content.schema {
             let (ref_name, schema) = resolver::resolve_schema(schema, components)?;
             let required = true; // check if is in required list
-            let properties = schema_to_properties(None, &schema, required, components, &mut output_types, ref_name)?;
+            let path = format!("{}.output", operation_id);
+            let properties = schema_to_properties(None, &schema, required, components, &mut output_types, ref_name, &path)?;
 
             output_properties.extend(properties);
         }
@@ -327,6 +334,7 @@ fn build_oas_function_output(
         let output_type = Type {
             name: output_data_type.clone(),
             properties: output_properties,
+            description: None,
         };
 
         output_types.insert(output_data_type.clone(), output_type);
@@ -342,6 +350,7 @@ fn build_oas_function_output(
 ///
 ///
 fn parameter_to_properties(
+    operation_id: &str,
     parameter: &OParameter,
     components: &Option<Components>,
     types: &mut Map<Type>,
@@ -359,15 +368,24 @@ fn parameter_to_properties(
     match &parameter_data.format {
         ParameterSchemaOrContent::Schema(schema) => {
             let (ref_name, schema) = resolver::resolve_schema(schema, components)?;
-            schema_to_properties(name, &schema, required, components, types, ref_name)
+            let path = format!("{}.{}", operation_id, parameter_data.name);
+            let mut properties = schema_to_properties(name, &schema, required, components, types, ref_name, &path)?;
+
+            // The description of the parameter itself takes precedence over that of its schema
+            if parameter_data.description.is_some() {
+                for property in &mut properties { property.description = parameter_data.description.clone(); }
+            }
+            Ok(properties)
         }
         ParameterSchemaOrContent::Content(_) => Err(anyhow!(OAS_CONTENT_NOT_SUPPORTED)),
     }
 }
 
+/// Converts a schema to properties. Nested objects (and array items that are objects) are
+/// turned into types of their own, which are added to `types`.
 ///
-///
-///
+/// The `path` identifies the schema in the document (e.g., `addPet.body.tags[]`), and is used
+/// both in errors and to name nested types that aren't defined as a component.
 pub fn schema_to_properties(
     name: Option<String>,
     schema: &Schema,
@@ -375,12 +393,27 @@ pub fn schema_to_properties(
     components: &Option<Components>,
     types: &mut Map<Type>,
     ref_name: Option<String>,
+    path: &str,
 ) -> Result<Vec<Property>> {
-    match schema.schema_kind {
-        SchemaKind::Any(_) => any_schema_to_properties(name, schema, components, types, ref_name),
-        SchemaKind::Type(_) => type_schema_to_properties(name, schema, required, components, types, ref_name),
-        _ => todo!(),
+    let named = name.is_some();
+    let mut properties = match schema.schema_kind {
+        SchemaKind::Any(_) => any_schema_to_properties(name, schema, required, components, types, ref_name, path)?,
+        SchemaKind::Type(_) => type_schema_to_properties(name, schema, required, components, types, ref_name, path)?,
+        SchemaKind::OneOf { .. } => bail!("{} (at '{}')", OAS_ONE_OF_NOT_SUPPORTED, path),
+        SchemaKind::AnyOf { .. } => bail!("{} (at '{}')", OAS_ANY_OF_NOT_SUPPORTED, path),
+        _ => bail!("{} (at '{}')", OAS_COMPOSITION_NOT_SUPPORTED, path),
+    };
+
+    // A named schema becomes a single property, which is documented by the schema's description
+    if named {
+        for property in &mut properties {
+            if property.description.is_none() {
+                property.description = schema.schema_data.description.clone();
+            }
+        }
     }
+
+    Ok(properties)
 }
 
 ///
@@ -389,9 +422,11 @@ pub fn schema_to_properties(
 fn any_schema_to_properties(
     name: Option<String>,
     schema: &Schema,
+    required: bool,
     components: &Option<Components>,
     types: &mut Map<Type>,
     ref_name: Option<String>,
+    path: &str,
 ) -> Result<Vec<Property>> {
     let any_schema = if let SchemaKind::Any(any_schema) = &schema.schema_kind {
         any_schema
@@ -408,38 +443,23 @@ fn any_schema_to_properties(
     let mut properties = vec![];
     for (p_name, property) in any_schema.properties.iter() {
         let property = property.clone().unbox();
-        let required = any_schema.required.contains(p_name);
+        let p_required = any_schema.required.contains(p_name);
 
         let (p_ref_name, p_schema) = resolve_schema(&property, components)?;
-        let props = schema_to_properties(Some(p_name.clone()), &p_schema, required, components, types, p_ref_name)?;
-
-        // Group subproperties
-        if let Some(name) = &name {
-            let type_name = if let Some(ref_name) = &ref_name {
-                ref_name.clone()
-            } else {
-                let random_id = get_random_identifier();
-                info!(
-                    "Couldn't determine name for {}'s type, using a random one: {}",
-                    name, random_id
-                );
-
-                random_id
-            };
+        let p_path = format!("{}.{}", path, p_name);
+        let props = schema_to_properties(Some(p_name.clone()), &p_schema, p_required, components, types, p_ref_name, &p_path)?;
 
-            let item_type = Type {
-                name: type_name.clone(),
-                properties: props,
-            };
+        properties.extend(props);
+    }
 
-            properties.push(Property::new_quick(name, &type_name));
-            types.insert(type_name, item_type);
-        } else {
-            properties.extend(props);
+    // Group subproperties
+    match name {
+        Some(name) => {
+            let type_name = add_type(properties, types, ref_name, schema.schema_data.description.clone(), path)?;
+            Ok(vec![Property::new(name, type_name, None, None, Some(!required), None)])
         }
+        None => Ok(properties),
     }
-
-    Ok(properties)
 }
 
 ///
@@ -451,7 +471,8 @@ fn type_schema_to_properties(
     required: bool,
     components: &Option<Components>,
     types: &mut Map<Type>,
-    _ref_name: Option<String>,
+    ref_name: Option<String>,
+    path: &str,
 ) -> Result<Vec<Property>> {
     let data_type = if let SchemaKind::Type(data_type) = &schema.schema_kind {
         data_type
@@ -468,44 +489,34 @@ fn type_schema_to_properties(
     let properties = match data_type {
         OType::Array(array) => {
             let items = array.items.clone().unbox();
-            let (ref_name, items_schema) = resolver::resolve_schema(&items, components)?;
-
-            let data_type = match &items_schema.schema_kind {
-                SchemaKind::Type(OType::String(_)) => String::from("string[]"),
-                SchemaKind::Type(OType::Number(_)) => String::from("real[]"),
-                SchemaKind::Type(OType::Integer(_)) => String::from("integer[]"),
-                SchemaKind::Type(OType::Boolean {}) => String::from("boolean[]"),
-                SchemaKind::Any(_) => {
-                    let item_type_properties =
-                        any_schema_to_properties(None, &items_schema, components, types, ref_name.clone())?;
-
-                    let item_type_name = if let Some(ref_name) = ref_name {
-                        ref_name
-                    } else {
-                        let random_id = get_random_identifier();
-                        info!(
-                            "Couldn't properly determine array item type name (AnySchema), using a random one: {}",
-                            random_id
-                        );
-
-                        random_id
-                    };
-
-                    let item_type = Type {
-                        name: item_type_name.clone(),
-                        properties: item_type_properties,
-                    };
-
-                    types.insert(item_type_name.clone(), item_type);
-
-                    format!("{}[]", item_type_name)
-                }
-                _ => todo!(),
+            let (items_ref_name, items_schema) = resolver::resolve_schema(&items, components)?;
+
+            // Convert the items as if they were a (required) property of their own, so nested objects become types.
+            let items_path = format!("{}[]", path);
+            let items_properties = schema_to_properties(
+                Some(String::new()),
+                &items_schema,
+                true,
+                components,
+                types,
+                items_ref_name,
+                &items_path,
+            )?;
+
+            let item_type = match items_properties.first() {
+                Some(Property { data_type, .. }) => data_type.clone(),
+                None => unreachable!(),
             };
+            ensure!(
+                !item_type.ends_with("[]"),
+                "{} (at '{}')",
+                OAS_NESTED_ARRAYS_NOT_SUPPORTED,
+                items_path
+            );
 
             vec![Property::new(
                 name.unwrap_or_default(),
-                data_type,
+                format!("{}[]", item_type),
                 None,
                 None,
                 Some(!required),
@@ -513,20 +524,34 @@ fn type_schema_to_properties(
             )]
         }
         OType::Object(object) => {
-            ensure!(name.is_none(), OAS_NESTED_OBJECTS_NOT_SUPPORTED);
-
             let mut properties = Vec::<Property>::new();
-            for (name, p_schema) in object.properties.iter() {
+            for (p_name, p_schema) in object.properties.iter() {
                 let p_schema = p_schema.clone().unbox();
-                let (ref_name, p_schema) = resolver::resolve_schema(&p_schema, components)?;
-
-                let required = object.required.contains(name);
-                let props = schema_to_properties(Some(name.clone()), &p_schema, required, components, types, ref_name)?;
+                let (p_ref_name, p_schema) = resolver::resolve_schema(&p_schema, components)?;
+
+                let p_required = object.required.contains(p_name);
+                let p_path = format!("{}.{}", path, p_name);
+                let props = schema_to_properties(
+                    Some(p_name.clone()),
+                    &p_schema,
+                    p_required,
+                    components,
+                    types,
+                    p_ref_name,
+                    &p_path,
+                )?;
 
                 properties.extend(props);
             }
 
-            properties
+            // Top-level objects are flattened, nested objects become a type of their own.
+            match name {
+                Some(name) => {
+                    let type_name = add_type(properties, types, ref_name, schema.schema_data.description.clone(), path)?;
+                    vec![Property::new(name, type_name, None, None, Some(!required), None)]
+                }
+                None => properties,
+            }
         }
         _ => {
             let data_type = match data_type {
@@ -537,11 +562,12 @@ fn type_schema_to_properties(
                 _ => unreachable!(),
             };
 
+            let default = schema.schema_data.default.as_ref().map(Value::from_json);
             vec![Property::new(
                 name.unwrap_or_default(),
                 data_type,
                 None,
-                None,
+                default,
                 Some(!required),
                 None,
             )]
@@ -560,15 +586,43 @@ fn uppercase_first_letter(s: &str) -> String {
     }
 }
 
-// Utility to generate a random identifier.
-fn get_random_identifier() -> String {
-    let mut rng = rand::thread_rng();
+/// Adds a type with the given properties (and the description of its schema) for a nested object,
+/// and returns its name. The name of the schema component is used if there is one, otherwise the
+/// name is derived from the path.
+fn add_type(
+    properties: Vec<Property>,
+    types: &mut Map<Type>,
+    ref_name: Option<String>,
+    description: Option<String>,
+    path: &str,
+) -> Result<String> {
+    ensure!(
+        !properties.is_empty(),
+        "{} (at '{}')",
+        OAS_FREE_FORM_OBJECTS_NOT_SUPPORTED,
+        path
+    );
+
+    let type_name = ref_name.unwrap_or_else(|| path_to_type_name(path));
+    debug!("Adding type '{}' for nested object at '{}'.", type_name, path);
 
-    let identifier: String = std::iter::repeat(())
-        .map(|()| rng.sample(Alphanumeric))
-        .map(char::from)
-        .take(6)
-        .collect();
+    let nested_type = Type {
+        name: type_name.clone(),
+        properties,
+        description,
+    };
+
+    types.insert(type_name.clone(), nested_type);
+    Ok(type_name)
+}
 
-    identifier.to_lowercase()
+/// Utility to derive a type name from a schema path, e.g., `addPet.body.tags[]` becomes `AddPetBodyTagsItem`.
+fn path_to_type_name(path: &str) -> String {
+    path.replace("[]", ".item")
+        .split('.')
+        .map(|segment| {
+            let segment: String = segment.chars().filter(|c| c.is_alphanumeric()).collect();
+            uppercase_first_letter(&segment)
+        })
+        .collect()
 }
@@ -161,11 +161,16 @@ pub async fn execute(
             if let Some(schema) = &content.schema {
                 let (ref_name, schema) = resolver::resolve_schema(schema, &components)?;
                 let mut _types = HashMap::new();
-                let properties = build::schema_to_properties(None, &schema, true, &components, &mut _types, ref_name)?;
+                let path = format!("{}.body", operation_id);
+                let properties = build::schema_to_properties(None, &schema, true, &components, &mut _types, ref_name, &path)?;
 
+                // Omitted (optional) properties are left out of the body altogether; nested values are serialized recursively.
                 for property in properties {
-                    if let Some(value) = arguments.get(&property.name) {
-                        json.insert(property.name.clone(), value.as_json());
+                    match arguments.get(&property.name) {
+                        Some(Value::Unit) | None => continue,
+                        Some(value) => {
+                            json.insert(property.name.clone(), value.as_json());
+                        }
                     }
                 }
             }
@@ -1,6 +1,7 @@
 mod common;
 
 use anyhow::Result;
+use specifications::common::Value;
 
 #[test]
 fn param_count_matches_function_param_count() -> Result<()> {
@@ -55,6 +56,33 @@ fn param_required_is_preserved() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn param_default_is_preserved() -> Result<()> {
+    let (function, _) = common::build_oas_function_param("/param-default", "onlyPathParameters")?;
+    assert_eq!(function.parameters.len(), 2);
+
+    let first = function.parameters.iter().find(|p| p.name == "1").unwrap();
+    assert!(first.default.is_none());
+
+    let second = function.parameters.iter().find(|p| p.name == "2").unwrap();
+    assert!(matches!(second.default, Some(Value::Integer(10))));
+
+    Ok(())
+}
+
+#[test]
+fn param_description_is_preserved() -> Result<()> {
+    let (function, _) = common::build_oas_function_param("/param-description", "onlyPathParameters")?;
+    assert_eq!(function.description.as_deref(), Some("Echoes the parameters back, as a JSON object."));
+
+    let description = |name: &str| function.parameters.iter().find(|p| p.name == name).unwrap().description.clone();
+    assert_eq!(description("1").as_deref(), Some("The first parameter."));
+    assert_eq!(description("2").as_deref(), Some("The second parameter."));
+    assert_eq!(description("3"), None);
+
+    Ok(())
+}
+
 #[test]
 fn body_none_ignored() -> Result<()> {
     let (function, _) = common::build_oas_function_body("/body-none", "onlyPathParameters")?;
@@ -123,3 +151,73 @@ fn servers_operation_0param() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn body_nested_refs_become_types() -> Result<()> {
+    let (function, types) = common::build_oas_function_petstore("/pet", "post", "addPet")?;
+    assert_eq!(function.parameters.len(), 1);
+    assert_eq!(function.parameters[0].data_type, String::from("AddPetInput"));
+
+    // Referenced nested schemas keep their component name.
+    assert_eq!(types.len(), 4);
+    assert_eq!(types.get("Category").unwrap().properties.len(), 2);
+    assert_eq!(types.get("Tag").unwrap().properties.len(), 2);
+
+    let input_type = types.get("AddPetInput").unwrap();
+    assert_eq!(input_type.properties.len(), 6);
+
+    let category = input_type.properties.iter().find(|p| p.name == "category").unwrap();
+    assert_eq!(category.data_type, String::from("Category"));
+    assert_eq!(category.optional, Some(true));
+
+    let tags = input_type.properties.iter().find(|p| p.name == "tags").unwrap();
+    assert_eq!(tags.data_type, String::from("Tag[]"));
+    assert_eq!(tags.optional, Some(true));
+
+    let photo_urls = input_type.properties.iter().find(|p| p.name == "photoUrls").unwrap();
+    assert_eq!(photo_urls.data_type, String::from("string[]"));
+    assert_eq!(photo_urls.optional, Some(false));
+
+    Ok(())
+}
+
+#[test]
+fn body_nested_inline_objects_become_types() -> Result<()> {
+    let (function, types) = common::build_oas_function_petstore("/pet", "put", "updatePet")?;
+    assert_eq!(function.parameters.len(), 2);
+
+    let owner = function.parameters.iter().find(|p| p.name == "owner").unwrap();
+    assert_eq!(owner.data_type, String::from("UpdatePetBodyOwner"));
+    assert_eq!(owner.optional, Some(true));
+
+    // Inline schemas are named after their path.
+    assert_eq!(types.len(), 2);
+    let owner_type = types.get("UpdatePetBodyOwner").unwrap();
+    let addresses = owner_type.properties.iter().find(|p| p.name == "addresses").unwrap();
+    assert_eq!(addresses.data_type, String::from("UpdatePetBodyOwnerAddressesItem[]"));
+
+    let address_type = types.get("UpdatePetBodyOwnerAddressesItem").unwrap();
+    assert_eq!(address_type.properties.len(), 2);
+    assert!(address_type.properties.iter().all(|p| p.optional == Some(true)));
+
+    Ok(())
+}
+
+#[test]
+fn body_oneof_err() -> Result<()> {
+    let result = common::build_oas_function_petstore("/pet/choose", "post", "choosePet");
+    let err = result.err().unwrap().to_string();
+    assert!(err.contains("oneOf"));
+    assert!(err.contains("choosePet.body.pet"));
+
+    Ok(())
+}
+
+#[test]
+fn body_nested_arrays_err() -> Result<()> {
+    let result = common::build_oas_function_petstore("/pet/nested-arrays", "post", "nestedArrays");
+    let err = result.err().unwrap().to_string();
+    assert!(err.contains("nestedArrays.body.matrix[]"));
+
+    Ok(())
+}
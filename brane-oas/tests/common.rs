@@ -29,6 +29,15 @@ pub fn build_oas_function_resp(
     build_oas_function(path, operation_id, "resp.yml")
 }
 
+#[allow(dead_code)]
+pub fn build_oas_function_petstore(
+    path: &str,
+    method: &str,
+    operation_id: &str,
+) -> Result<FunctionAndTypes> {
+    build_oas_operation(path, method, operation_id, "petstore.yml")
+}
+
 ///
 ///
 ///
@@ -36,13 +45,31 @@ pub fn build_oas_function(
     path: &str,
     operation_id: &str,
     file: &str,
+) -> Result<FunctionAndTypes> {
+    build_oas_operation(path, "get", operation_id, file)
+}
+
+///
+///
+///
+pub fn build_oas_operation(
+    path: &str,
+    method: &str,
+    operation_id: &str,
+    file: &str,
 ) -> Result<FunctionAndTypes> {
     let oas = parse_oas_file(format!("tests/resources/{}", file))?;
     let path_item = resolver::resolve_path_item(oas.paths.get(path).unwrap())?;
     let server_known = !oas.servers.is_empty() || !path_item.servers.is_empty();
+    let operation = match method {
+        "get" => path_item.get,
+        "post" => path_item.post,
+        "put" => path_item.put,
+        method => anyhow::bail!("Method '{}' is not supported by the test helpers", method),
+    };
     let (functions, types) = build::build_oas_function(
         operation_id.to_string(),
-        &path_item.get.unwrap(),
+        &operation.unwrap(),
         &oas.components,
         server_known,
     )?;
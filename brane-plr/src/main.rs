@@ -299,6 +299,7 @@ async fn process_cmd_message(
 
         match metadata {
             Location::Kube { registry, .. }
+            | Location::Docker { registry, .. }
             | Location::Slurm { registry, .. }
             | Location::Vm { registry, .. }
             | Location::Local { registry, .. } => {
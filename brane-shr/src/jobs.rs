@@ -56,6 +56,11 @@ pub enum JobStatus {
     /// **Carries**
     ///  * `res`: A JSON-formatted string (hopefully) containing a code/stdout/stderr triplet of results of the failed job.
     Failed{ res: String },
+    /// The container has exited with a non-zero status code, but its result is not a valid code/stdout/stderr triplet (e.g., because it was cut off)
+    /// 
+    /// **Carries**
+    ///  * `res`: The raw result as sent by the branelet, which is all we know of the job's output.
+    FailedRaw{ res: String },
     /// The container was interrupted by the Job node
     Stopped{ signal: String },
     /// We could not decode the output from the package
@@ -63,6 +68,11 @@ pub enum JobStatus {
     /// **Carries**
     ///  * `err`: A string describing why we failed to decode the job output.
     DecodeFailed{ err: String },
+    /// We could not store the outputs of the package as artifacts
+    /// 
+    /// **Carries**
+    ///  * `err`: A string describing why we failed to store the artifacts.
+    StoreFailed{ err: String },
 }
 
 impl JobStatus {
@@ -85,8 +95,10 @@ impl JobStatus {
             JobStatus::CompleteFailed{ .. }   => 5,
             JobStatus::Finished{ .. }         => 6,
             JobStatus::Failed{ .. }           => 6,
+            JobStatus::FailedRaw{ .. }        => 6,
             JobStatus::Stopped{ .. }          => 6,
             JobStatus::DecodeFailed{ .. }     => 6,
+            JobStatus::StoreFailed{ .. }      => 6,
         }
     }
 
@@ -0,0 +1,685 @@
+/* KAFKA.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 20:41:09
+ * Last edited:
+ *   16 Oct 2026, 00:00:23
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Defines the options with which the services authenticate to Kafka
+ *   (TLS and/or SASL), and builds the ClientConfig of every producer,
+ *   consumer and admin client from them so they can't drift apart. Also
+ *   makes sure the topics the services talk over exist, with the requested
+ *   number of partitions and replication factor.
+**/
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FResult};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::Args;
+use log::{info, warn};
+use rdkafka::{ClientConfig, Offset, TopicPartitionList};
+use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, TopicReplication};
+use rdkafka::client::DefaultClientContext;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::util::Timeout;
+
+
+/***** CONSTANTS *****/
+/// The time we wait for the brokers to describe an existing topic.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sasl(protocol: SecurityProtocol, mechanism: SaslMechanism) -> KafkaSecurity {
+        KafkaSecurity {
+            protocol,
+            sasl_mechanism : Some(mechanism),
+            sasl_username  : Some(String::from("brane")),
+            sasl_password  : Some(String::from("hunter2")),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn plaintext_only_sets_brokers() {
+        let security = KafkaSecurity::default();
+        security.validate().unwrap();
+        let config = security.client_config("localhost:9092");
+        assert_eq!(config.get("bootstrap.servers"), Some("localhost:9092"));
+        assert_eq!(config.get("security.protocol"), Some("plaintext"));
+        assert_eq!(config.get("sasl.mechanism"), None);
+        assert_eq!(config.get("ssl.ca.location"), None);
+    }
+
+    #[test]
+    fn sasl_ssl_sets_everything() {
+        let ca = tempfile::NamedTempFile::new().unwrap();
+        let security = KafkaSecurity{ ssl_ca: Some(ca.path().to_path_buf()), ..sasl(SecurityProtocol::SaslSsl, SaslMechanism::ScramSha512) };
+        security.validate().unwrap();
+
+        let config = security.client_config("broker:9093");
+        assert_eq!(config.get("security.protocol"), Some("sasl_ssl"));
+        assert_eq!(config.get("sasl.mechanism"), Some("SCRAM-SHA-512"));
+        assert_eq!(config.get("sasl.username"), Some("brane"));
+        assert_eq!(config.get("sasl.password"), Some("hunter2"));
+        assert_eq!(config.get("ssl.ca.location"), Some(ca.path().to_str().unwrap()));
+    }
+
+    #[test]
+    fn sasl_needs_credentials() {
+        let security = KafkaSecurity{ sasl_password: None, ..sasl(SecurityProtocol::SaslSsl, SaslMechanism::Plain) };
+        assert!(matches!(security.validate(), Err(KafkaSecurityError::MissingOption{ option: "--kafka-sasl-password", .. })));
+        let security = KafkaSecurity{ sasl_mechanism: None, ..sasl(SecurityProtocol::SaslSsl, SaslMechanism::Plain) };
+        assert!(matches!(security.validate(), Err(KafkaSecurityError::MissingOption{ option: "--kafka-sasl-mechanism", .. })));
+    }
+
+    #[test]
+    fn plain_sasl_needs_tls() {
+        let security = sasl(SecurityProtocol::SaslPlaintext, SaslMechanism::Plain);
+        assert!(matches!(security.validate(), Err(KafkaSecurityError::CleartextPassword)));
+        sasl(SecurityProtocol::SaslPlaintext, SaslMechanism::ScramSha256).validate().unwrap();
+    }
+
+    #[test]
+    fn unused_options_are_rejected() {
+        let security = sasl(SecurityProtocol::Ssl, SaslMechanism::Plain);
+        assert!(matches!(security.validate(), Err(KafkaSecurityError::UnusedOption{ option: "--kafka-sasl-mechanism", .. })));
+        let security = KafkaSecurity{ ssl_ca: Some(PathBuf::from("/ca.pem")), ..Default::default() };
+        assert!(matches!(security.validate(), Err(KafkaSecurityError::UnusedOption{ option: "--kafka-ssl-ca", .. })));
+    }
+
+    #[test]
+    fn tls_files_must_exist_and_pair() {
+        let security = KafkaSecurity{ protocol: SecurityProtocol::Ssl, ssl_ca: Some(PathBuf::from("/does/not/exist.pem")), ..Default::default() };
+        assert!(matches!(security.validate(), Err(KafkaSecurityError::FileNotFound{ option: "--kafka-ssl-ca", .. })));
+
+        let cert = tempfile::NamedTempFile::new().unwrap();
+        let security = KafkaSecurity{ protocol: SecurityProtocol::Ssl, ssl_cert: Some(cert.path().to_path_buf()), ..Default::default() };
+        assert!(matches!(security.validate(), Err(KafkaSecurityError::MissingOption{ option: "--kafka-ssl-key", .. })));
+
+        let key = tempfile::NamedTempFile::new().unwrap();
+        let security = KafkaSecurity{ ssl_key: Some(key.path().to_path_buf()), ..security };
+        security.validate().unwrap();
+        let config = security.client_config("broker:9093");
+        assert_eq!(config.get("ssl.certificate.location"), Some(cert.path().to_str().unwrap()));
+        assert_eq!(config.get("ssl.key.location"), Some(key.path().to_str().unwrap()));
+    }
+
+    #[test]
+    fn topic_options_must_be_positive() {
+        TopicOptions::default().validate().unwrap();
+        TopicOptions{ partitions: 6, replication: 3 }.validate().unwrap();
+        assert!(matches!(TopicOptions{ partitions: 0, replication: 1 }.validate(), Err(KafkaTopicError::IllegalOption{ option: "--topic-partitions", value: 0 })));
+        assert!(matches!(TopicOptions{ partitions: 1, replication: -1 }.validate(), Err(KafkaTopicError::IllegalOption{ option: "--topic-replication", value: -1 })));
+    }
+
+    #[test]
+    fn new_topics_use_the_options() {
+        for (partitions, replication) in [ (1, 1), (6, 1), (1, 3), (12, 3) ] {
+            let options = TopicOptions{ partitions, replication };
+            let topics = options.new_topics(&[ "drv-cmd", "job-evt" ]);
+            assert_eq!(topics.iter().map(|t| t.name).collect::<Vec<_>>(), vec![ "drv-cmd", "job-evt" ]);
+            for topic in topics {
+                assert_eq!(topic.num_partitions, partitions);
+                assert!(matches!(topic.replication, TopicReplication::Fixed(r) if r == replication));
+            }
+        }
+    }
+
+    #[test]
+    fn mismatches_are_reported_per_setting() {
+        let options = TopicOptions{ partitions: 6, replication: 3 };
+        assert!(options.mismatches(6, 3).is_empty());
+        // Having more or fewer than requested are both mismatches; we don't change existing topics either way
+        assert_eq!(options.mismatches(1, 3), vec![ TopicMismatch{ option: "--topic-partitions", requested: 6, actual: 1 } ]);
+        assert_eq!(options.mismatches(6, 5), vec![ TopicMismatch{ option: "--topic-replication", requested: 3, actual: 5 } ]);
+        assert_eq!(options.mismatches(12, 1), vec![
+            TopicMismatch{ option: "--topic-partitions", requested: 6, actual: 12 },
+            TopicMismatch{ option: "--topic-replication", requested: 3, actual: 1 },
+        ]);
+        assert!(TopicOptions::default().mismatches(1, 1).is_empty());
+    }
+
+    #[test]
+    fn admin_errors_name_topic_and_option() {
+        let options = TopicOptions{ partitions: 6, replication: 3 };
+        match options.creation_error(String::from("job-evt"), RDKafkaErrorCode::InvalidReplicationFactor) {
+            err @ KafkaTopicError::RejectedOption{ .. } => {
+                let message = err.to_string();
+                assert!(message.contains("'job-evt'") && message.contains("--topic-replication") && message.contains('3'), "Unexpected message: {}", message);
+            },
+            err => panic!("Expected a rejected option, got {:?}", err),
+        }
+        assert!(matches!(options.creation_error(String::from("drv-cmd"), RDKafkaErrorCode::InvalidPartitions), KafkaTopicError::RejectedOption{ option: "--topic-partitions", value: 6, .. }));
+        assert!(matches!(options.creation_error(String::from("drv-cmd"), RDKafkaErrorCode::TopicAuthorizationFailed), KafkaTopicError::TopicError{ .. }));
+    }
+
+    #[test]
+    fn names_parse_case_insensitively() {
+        assert_eq!(SecurityProtocol::from_str("SASL_SSL").unwrap(), SecurityProtocol::SaslSsl);
+        assert_eq!(SaslMechanism::from_str("scram-sha-256").unwrap(), SaslMechanism::ScramSha256);
+        assert!(SecurityProtocol::from_str("tls").is_err());
+    }
+}
+
+
+
+
+
+/***** ERRORS *****/
+/// Errors for when the Kafka security options don't make sense.
+#[derive(Debug)]
+pub enum KafkaSecurityError {
+    /// The given security protocol is unknown
+    UnknownProtocol{ raw: String },
+    /// The given SASL mechanism is unknown
+    UnknownMechanism{ raw: String },
+
+    /// The protocol needs an option that wasn't given
+    MissingOption{ option: &'static str, protocol: SecurityProtocol },
+    /// An option was given that the protocol doesn't use (and which would thus be silently ignored)
+    UnusedOption{ option: &'static str, protocol: SecurityProtocol },
+    /// SASL/PLAIN was used without TLS, which would send the password in cleartext
+    CleartextPassword,
+    /// A TLS file does not exist
+    FileNotFound{ option: &'static str, path: PathBuf },
+}
+
+impl Display for KafkaSecurityError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            KafkaSecurityError::UnknownProtocol{ raw }  => write!(f, "Unknown Kafka security protocol '{}' (expected plaintext, ssl, sasl_plaintext or sasl_ssl)", raw),
+            KafkaSecurityError::UnknownMechanism{ raw } => write!(f, "Unknown Kafka SASL mechanism '{}' (expected PLAIN, SCRAM-SHA-256 or SCRAM-SHA-512)", raw),
+
+            KafkaSecurityError::MissingOption{ option, protocol } => write!(f, "Kafka security protocol '{}' requires {}", protocol, option),
+            KafkaSecurityError::UnusedOption{ option, protocol }  => write!(f, "{} is not used by Kafka security protocol '{}'", option, protocol),
+            KafkaSecurityError::CleartextPassword                 => write!(f, "The PLAIN SASL mechanism sends the password in cleartext; use security protocol 'sasl_ssl' or a SCRAM mechanism instead"),
+            KafkaSecurityError::FileNotFound{ option, path }      => write!(f, "File '{}' given to {} does not exist", path.display(), option),
+        }
+    }
+}
+
+impl Error for KafkaSecurityError {}
+
+
+
+/// Errors for when the topics the services talk over could not be made to exist.
+#[derive(Debug)]
+pub enum KafkaTopicError {
+    /// A topic option has a value that no topic can have
+    IllegalOption{ option: &'static str, value: i32 },
+
+    /// Could not create the admin client
+    ClientError{ servers: String, err: KafkaError },
+    /// Could not ask the brokers to create the topics at all
+    CreateError{ topics: Vec<String>, err: KafkaError },
+    /// The brokers refused to create a topic with the value of the given option (e.g., a replication factor larger than the number of brokers)
+    RejectedOption{ topic: String, option: &'static str, value: i32, err: RDKafkaErrorCode },
+    /// The brokers refused to create a topic for another reason
+    TopicError{ topic: String, err: RDKafkaErrorCode },
+}
+
+impl Display for KafkaTopicError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            KafkaTopicError::IllegalOption{ option, value } => write!(f, "{} must be at least 1, not {}", option, value),
+
+            KafkaTopicError::ClientError{ servers, err }                  => write!(f, "Could not create Kafka admin client with bootstrap servers '{}': {}", servers, err),
+            KafkaTopicError::CreateError{ topics, err }                   => write!(f, "Could not create Kafka topics '{}': {}", topics.join("', '"), err),
+            KafkaTopicError::RejectedOption{ topic, option, value, err }  => write!(f, "Could not create Kafka topic '{}' with {} {}: {}", topic, option, value, err),
+            KafkaTopicError::TopicError{ topic, err }                     => write!(f, "Could not create Kafka topic '{}': {}", topic, err),
+        }
+    }
+}
+
+impl Error for KafkaTopicError {}
+
+
+
+/// Errors for when a consumer could not be assigned the partitions of the topics it reads.
+#[derive(Debug)]
+pub enum KafkaAssignError {
+    /// Could not ask the brokers which partitions a topic has
+    MetadataError{ topic: String, err: KafkaError },
+    /// The brokers don't know the topic (or say it has no partitions)
+    UnknownTopic{ topic: String },
+    /// Could not get the offsets that the group of the consumer committed
+    GetOffsetError{ topics: Vec<String>, err: KafkaError },
+    /// Could not set the offset to start reading a partition from
+    SetOffsetError{ topic: String, partition: i32, err: KafkaError },
+    /// Could not assign the partitions to the consumer
+    AssignError{ topics: Vec<String>, err: KafkaError },
+}
+
+impl Display for KafkaAssignError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            KafkaAssignError::MetadataError{ topic, err }             => write!(f, "Could not get the partitions of Kafka topic '{}': {}", topic, err),
+            KafkaAssignError::UnknownTopic{ topic }                   => write!(f, "Kafka topic '{}' does not exist or has no partitions", topic),
+            KafkaAssignError::GetOffsetError{ topics, err }           => write!(f, "Could not get offsets for Kafka topics '{}': {}", topics.join("', '"), err),
+            KafkaAssignError::SetOffsetError{ topic, partition, err } => write!(f, "Could not set offset for partition {} of Kafka topic '{}': {}", partition, topic, err),
+            KafkaAssignError::AssignError{ topics, err }              => write!(f, "Could not assign the partitions of Kafka topics '{}': {}", topics.join("', '"), err),
+        }
+    }
+}
+
+impl Error for KafkaAssignError {}
+
+
+
+
+
+/***** LIBRARY ENUMS *****/
+/// The protocol used to talk to the Kafka brokers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SecurityProtocol {
+    /// No TLS and no authentication
+    Plaintext,
+    /// TLS (optionally with a client certificate)
+    Ssl,
+    /// SASL authentication without TLS
+    SaslPlaintext,
+    /// SASL authentication over TLS
+    SaslSsl,
+}
+
+impl SecurityProtocol {
+    /// Returns whether this protocol authenticates with SASL.
+    #[inline]
+    pub fn uses_sasl(&self) -> bool { matches!(self, SecurityProtocol::SaslPlaintext | SecurityProtocol::SaslSsl) }
+
+    /// Returns whether this protocol uses TLS.
+    #[inline]
+    pub fn uses_tls(&self) -> bool { matches!(self, SecurityProtocol::Ssl | SecurityProtocol::SaslSsl) }
+}
+
+impl Default for SecurityProtocol {
+    #[inline]
+    fn default() -> Self { SecurityProtocol::Plaintext }
+}
+
+impl Display for SecurityProtocol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            SecurityProtocol::Plaintext     => write!(f, "plaintext"),
+            SecurityProtocol::Ssl           => write!(f, "ssl"),
+            SecurityProtocol::SaslPlaintext => write!(f, "sasl_plaintext"),
+            SecurityProtocol::SaslSsl       => write!(f, "sasl_ssl"),
+        }
+    }
+}
+
+impl FromStr for SecurityProtocol {
+    type Err = KafkaSecurityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "plaintext"      => Ok(SecurityProtocol::Plaintext),
+            "ssl"            => Ok(SecurityProtocol::Ssl),
+            "sasl_plaintext" => Ok(SecurityProtocol::SaslPlaintext),
+            "sasl_ssl"       => Ok(SecurityProtocol::SaslSsl),
+            _                => Err(KafkaSecurityError::UnknownProtocol{ raw: s.to_string() }),
+        }
+    }
+}
+
+
+
+/// The mechanism used to authenticate with SASL.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SaslMechanism {
+    /// Username and password, as-is
+    Plain,
+    /// Salted challenge/response with SHA-256
+    ScramSha256,
+    /// Salted challenge/response with SHA-512
+    ScramSha512,
+}
+
+impl Display for SaslMechanism {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            SaslMechanism::Plain       => write!(f, "PLAIN"),
+            SaslMechanism::ScramSha256 => write!(f, "SCRAM-SHA-256"),
+            SaslMechanism::ScramSha512 => write!(f, "SCRAM-SHA-512"),
+        }
+    }
+}
+
+impl FromStr for SaslMechanism {
+    type Err = KafkaSecurityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "PLAIN"         => Ok(SaslMechanism::Plain),
+            "SCRAM-SHA-256" => Ok(SaslMechanism::ScramSha256),
+            "SCRAM-SHA-512" => Ok(SaslMechanism::ScramSha512),
+            _               => Err(KafkaSecurityError::UnknownMechanism{ raw: s.to_string() }),
+        }
+    }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// The options with which a service connects to Kafka. Flatten these into the service's own options to get the `--kafka-*` flags.
+#[derive(Args, Clone, Default)]
+pub struct KafkaSecurity {
+    /// Protocol to connect to the Kafka brokers with (plaintext, ssl, sasl_plaintext or sasl_ssl)
+    #[clap(long = "kafka-security-protocol", default_value = "plaintext", env = "KAFKA_SECURITY_PROTOCOL")]
+    pub protocol       : SecurityProtocol,
+    /// SASL mechanism to authenticate to Kafka with (PLAIN, SCRAM-SHA-256 or SCRAM-SHA-512)
+    #[clap(long = "kafka-sasl-mechanism", env = "KAFKA_SASL_MECHANISM")]
+    pub sasl_mechanism : Option<SaslMechanism>,
+    /// Username to authenticate to Kafka with
+    #[clap(long = "kafka-sasl-username", env = "KAFKA_SASL_USERNAME")]
+    pub sasl_username  : Option<String>,
+    /// Password to authenticate to Kafka with
+    #[clap(long = "kafka-sasl-password", env = "KAFKA_SASL_PASSWORD", hide_env_values = true)]
+    pub sasl_password  : Option<String>,
+    /// CA certificate (PEM) to verify the Kafka brokers with, instead of the system's
+    #[clap(long = "kafka-ssl-ca", env = "KAFKA_SSL_CA")]
+    pub ssl_ca         : Option<PathBuf>,
+    /// Client certificate (PEM) to authenticate to Kafka with
+    #[clap(long = "kafka-ssl-cert", env = "KAFKA_SSL_CERT")]
+    pub ssl_cert       : Option<PathBuf>,
+    /// Private key (PEM) of the client certificate
+    #[clap(long = "kafka-ssl-key", env = "KAFKA_SSL_KEY")]
+    pub ssl_key        : Option<PathBuf>,
+}
+
+impl KafkaSecurity {
+    /// Checks that the options make sense together, so that a misconfiguration is caught at startup instead of when the first client fails to connect.
+    /// 
+    /// **Returns**  
+    /// Nothing if the options are valid, or a KafkaSecurityError describing the first problem otherwise.
+    pub fn validate(&self) -> Result<(), KafkaSecurityError> {
+        let protocol = self.protocol;
+
+        // SASL needs all of its options, and nothing else may use them
+        let sasl = [
+            ("--kafka-sasl-mechanism", self.sasl_mechanism.is_some()),
+            ("--kafka-sasl-username", self.sasl_username.is_some()),
+            ("--kafka-sasl-password", self.sasl_password.is_some()),
+        ];
+        for (option, given) in sasl {
+            if protocol.uses_sasl() && !given { return Err(KafkaSecurityError::MissingOption{ option, protocol }); }
+            if !protocol.uses_sasl() && given { return Err(KafkaSecurityError::UnusedOption{ option, protocol }); }
+        }
+        if protocol == SecurityProtocol::SaslPlaintext && self.sasl_mechanism == Some(SaslMechanism::Plain) { return Err(KafkaSecurityError::CleartextPassword); }
+
+        // The TLS files are only used with TLS, and must exist
+        let tls = [
+            ("--kafka-ssl-ca", &self.ssl_ca),
+            ("--kafka-ssl-cert", &self.ssl_cert),
+            ("--kafka-ssl-key", &self.ssl_key),
+        ];
+        for (option, path) in tls {
+            if let Some(path) = path {
+                if !protocol.uses_tls() { return Err(KafkaSecurityError::UnusedOption{ option, protocol }); }
+                if !path.exists() { return Err(KafkaSecurityError::FileNotFound{ option, path: path.clone() }); }
+            }
+        }
+        match (&self.ssl_cert, &self.ssl_key) {
+            (Some(_), None) => Err(KafkaSecurityError::MissingOption{ option: "--kafka-ssl-key", protocol }),
+            (None, Some(_)) => Err(KafkaSecurityError::MissingOption{ option: "--kafka-ssl-cert", protocol }),
+            _               => Ok(()),
+        }
+    }
+
+
+
+    /// Returns a new ClientConfig that connects to the given brokers with these options. Every Kafka client of the services should start from this.
+    /// 
+    /// Assumes the options have been validated.
+    /// 
+    /// **Arguments**
+    ///  * `brokers`: The comma-separated list of Kafka brokers to connect to.
+    /// 
+    /// **Returns**  
+    /// The ClientConfig, to which the client-specific settings may be added.
+    pub fn client_config(&self, brokers: &str) -> ClientConfig {
+        let mut config = ClientConfig::new();
+        config.set("bootstrap.servers", brokers);
+        config.set("security.protocol", self.protocol.to_string());
+
+        if let Some(mechanism) = self.sasl_mechanism { config.set("sasl.mechanism", mechanism.to_string()); }
+        if let Some(username) = &self.sasl_username { config.set("sasl.username", username); }
+        if let Some(password) = &self.sasl_password { config.set("sasl.password", password); }
+        if let Some(ca) = &self.ssl_ca { config.set("ssl.ca.location", ca.to_string_lossy()); }
+        if let Some(cert) = &self.ssl_cert { config.set("ssl.certificate.location", cert.to_string_lossy()); }
+        if let Some(key) = &self.ssl_key { config.set("ssl.key.location", key.to_string_lossy()); }
+        config
+    }
+}
+
+
+
+/// A setting of an existing topic that differs from what was requested.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TopicMismatch {
+    /// The option that requested the setting
+    pub option    : &'static str,
+    /// The value that was requested
+    pub requested : i32,
+    /// The value the topic actually has
+    pub actual    : i32,
+}
+
+
+
+/// The options with which the services create the topics they talk over. Flatten these into the service's own options to get the `--topic-*` flags.
+#[derive(Args, Clone, Copy, Debug)]
+pub struct TopicOptions {
+    /// Number of partitions to create missing Kafka topics with
+    #[clap(long = "topic-partitions", default_value = "1", env = "TOPIC_PARTITIONS")]
+    pub partitions  : i32,
+    /// Replication factor to create missing Kafka topics with (may not exceed the number of brokers)
+    #[clap(long = "topic-replication", default_value = "1", env = "TOPIC_REPLICATION")]
+    pub replication : i32,
+}
+
+impl TopicOptions {
+    /// Checks that the options are something a topic can have.
+    /// 
+    /// **Returns**  
+    /// Nothing if the options are valid, or a KafkaTopicError describing the first problem otherwise.
+    pub fn validate(&self) -> Result<(), KafkaTopicError> {
+        if self.partitions < 1 { return Err(KafkaTopicError::IllegalOption{ option: "--topic-partitions", value: self.partitions }); }
+        if self.replication < 1 { return Err(KafkaTopicError::IllegalOption{ option: "--topic-replication", value: self.replication }); }
+        Ok(())
+    }
+
+
+
+    /// Returns the NewTopics with which the given topics are created.
+    /// 
+    /// **Arguments**
+    ///  * `topics`: The names of the topics to create.
+    /// 
+    /// **Returns**  
+    /// One NewTopic per name, in the same order.
+    pub fn new_topics<'a>(&self, topics: &[&'a str]) -> Vec<NewTopic<'a>> {
+        topics.iter().map(|t| NewTopic::new(t, self.partitions, TopicReplication::Fixed(self.replication))).collect()
+    }
+
+    /// Compares the settings of an existing topic with these options.
+    /// 
+    /// **Arguments**
+    ///  * `partitions`: The number of partitions the topic has.
+    ///  * `replication`: The replication factor of the topic.
+    /// 
+    /// **Returns**  
+    /// The settings that differ, which is empty if the topic is as requested.
+    pub fn mismatches(&self, partitions: i32, replication: i32) -> Vec<TopicMismatch> {
+        let mut mismatches = Vec::new();
+        if partitions != self.partitions { mismatches.push(TopicMismatch{ option: "--topic-partitions", requested: self.partitions, actual: partitions }); }
+        if replication != self.replication { mismatches.push(TopicMismatch{ option: "--topic-replication", requested: self.replication, actual: replication }); }
+        mismatches
+    }
+
+    /// Turns the error with which the brokers refused to create a topic into a KafkaTopicError, naming the option that was refused if there is one.
+    /// 
+    /// **Arguments**
+    ///  * `topic`: The topic that could not be created.
+    ///  * `err`: The error the brokers returned for it.
+    /// 
+    /// **Returns**  
+    /// The KafkaTopicError to report.
+    pub fn creation_error(&self, topic: String, err: RDKafkaErrorCode) -> KafkaTopicError {
+        match err {
+            RDKafkaErrorCode::InvalidPartitions        => KafkaTopicError::RejectedOption{ topic, option: "--topic-partitions", value: self.partitions, err },
+            RDKafkaErrorCode::InvalidReplicationFactor |
+            RDKafkaErrorCode::InvalidReplicaAssignment => KafkaTopicError::RejectedOption{ topic, option: "--topic-replication", value: self.replication, err },
+            err                                        => KafkaTopicError::TopicError{ topic, err },
+        }
+    }
+}
+
+impl Default for TopicOptions {
+    #[inline]
+    fn default() -> Self { TopicOptions{ partitions: 1, replication: 1 } }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Makes sure the given topics exist on the Kafka brokers, creating the missing ones with the given options.
+/// 
+/// Topics that already exist are left as they are, but if their number of partitions or replication factor differs from the options, a warning is logged.
+/// 
+/// **Arguments**
+///  * `topics`: The names of the topics that should exist.
+///  * `brokers`: The comma-separated list of Kafka brokers to connect to.
+///  * `security`: The options to connect to the brokers with.
+///  * `options`: The options to create the topics with.
+/// 
+/// **Returns**  
+/// Nothing on success, or a KafkaTopicError otherwise.
+pub async fn ensure_topics(
+    topics: &[&str],
+    brokers: &str,
+    security: &KafkaSecurity,
+    options: &TopicOptions,
+) -> Result<(), KafkaTopicError> {
+    options.validate()?;
+
+    // Connect with an admin client
+    let admin_client: AdminClient<DefaultClientContext> = match security.client_config(brokers).create() {
+        Ok(client)  => client,
+        Err(err)    => { return Err(KafkaTopicError::ClientError{ servers: brokers.to_string(), err }); }
+    };
+
+    // Try to create all of the topics
+    let results = match admin_client.create_topics(options.new_topics(topics).iter(), &AdminOptions::new()).await {
+        Ok(results) => results,
+        Err(err)    => { return Err(KafkaTopicError::CreateError{ topics: topics.iter().map(|t| t.to_string()).collect(), err }); }
+    };
+
+    // Report on the results. Don't consider 'TopicAlreadyExists' an error, but do check if it looks like what we would have created.
+    let mut existing = Vec::new();
+    for result in results {
+        match result {
+            Ok(topic) => info!("Kafka topic '{}' created with {} partition(s) and replication factor {}.", topic, options.partitions, options.replication),
+            Err((topic, RDKafkaErrorCode::TopicAlreadyExists)) => {
+                info!("Kafka topic '{}' already exists", topic);
+                existing.push(topic);
+            },
+            Err((topic, err)) => { return Err(options.creation_error(topic, err)); }
+        }
+    }
+
+    // Asking the brokers about the existing topics blocks, so do it off the runtime
+    if !existing.is_empty() {
+        let options = *options;
+        let check = tokio::task::spawn_blocking(move || {
+            for topic in existing { check_existing_topic(&admin_client, &topic, &options); }
+        });
+        if let Err(err) = check.await { warn!("Could not check the partitions and replication factor of existing Kafka topics: {}", err); }
+    }
+
+    Ok(())
+}
+
+/// Assigns every partition of the given topics to the consumer, each starting at the offset that the group of the consumer committed for it (or at the beginning of the partition if it never committed one).
+/// 
+/// The services assign the partitions themselves instead of subscribing to the topics, so that every instance reads all messages regardless of its group. Partitions that are added to a topic later are only read after a restart.
+/// 
+/// **Arguments**
+///  * `consumer`: The consumer to assign the partitions to.
+///  * `topics`: The names of the topics to read.
+/// 
+/// **Returns**  
+/// The partitions that were assigned (with the offsets to start from), or a KafkaAssignError if we could not find out which partitions there are or where to start reading them.
+pub fn assign_partitions(consumer: &StreamConsumer, topics: &[&str]) -> Result<TopicPartitionList, KafkaAssignError> {
+    let names = || topics.iter().map(|t| t.to_string()).collect::<Vec<String>>();
+
+    // Ask the brokers which partitions there are
+    let mut tpl = TopicPartitionList::new();
+    for topic in topics {
+        let metadata = match consumer.fetch_metadata(Some(topic), METADATA_TIMEOUT) {
+            Ok(metadata) => metadata,
+            Err(err)     => { return Err(KafkaAssignError::MetadataError{ topic: topic.to_string(), err }); }
+        };
+        let partitions = metadata.topics().iter().find(|t| t.name() == *topic).map(|t| t.partitions()).unwrap_or_default();
+        if partitions.is_empty() { return Err(KafkaAssignError::UnknownTopic{ topic: topic.to_string() }); }
+        for partition in partitions { tpl.add_partition(topic, partition.id()); }
+    }
+
+    // Continue each of them where the group left off
+    let committed = match consumer.committed_offsets(tpl.clone(), Timeout::Never) {
+        Ok(committed) => committed,
+        Err(err)      => { return Err(KafkaAssignError::GetOffsetError{ topics: names(), err }); }
+    };
+    for element in committed.elements() {
+        let offset = match element.offset() {
+            Offset::Invalid => Offset::Beginning,
+            offset          => offset,
+        };
+        if let Err(err) = tpl.set_partition_offset(element.topic(), element.partition(), offset) {
+            return Err(KafkaAssignError::SetOffsetError{ topic: element.topic().to_string(), partition: element.partition(), err });
+        }
+    }
+
+    if let Err(err) = consumer.assign(&tpl) { return Err(KafkaAssignError::AssignError{ topics: names(), err }); }
+    Ok(tpl)
+}
+
+/// Warns if an existing topic has another number of partitions or replication factor than requested. We don't change the topic, since that may reorder or lose messages.
+/// 
+/// **Arguments**
+///  * `admin_client`: The client to ask the brokers about the topic with.
+///  * `topic`: The name of the topic.
+///  * `options`: The options the topic was requested with.
+fn check_existing_topic(admin_client: &AdminClient<DefaultClientContext>, topic: &str, options: &TopicOptions) {
+    let metadata = match admin_client.inner().fetch_metadata(Some(topic), METADATA_TIMEOUT) {
+        Ok(metadata) => metadata,
+        Err(err)     => { warn!("Could not check the partitions and replication factor of existing Kafka topic '{}': {}", topic, err); return; }
+    };
+    let partitions = match metadata.topics().iter().find(|t| t.name() == topic) {
+        Some(metadata) => metadata.partitions(),
+        None           => { warn!("Could not check the partitions and replication factor of existing Kafka topic '{}': the brokers did not describe it", topic); return; }
+    };
+
+    // The partitions of a topic normally all have the same number of replicas; go by the largest if they don't
+    let replication = partitions.iter().map(|p| p.replicas().len() as i32).max().unwrap_or(0);
+    for mismatch in options.mismatches(partitions.len() as i32, replication) {
+        warn!("Existing Kafka topic '{}' does not match {} (requested {}, but it has {}); leaving it as it is", topic, mismatch.option, mismatch.requested, mismatch.actual);
+    }
+}
@@ -1,2 +1,4 @@
 pub mod jobs;
+pub mod kafka;
+pub mod metrics;
 pub mod utilities;
@@ -0,0 +1,71 @@
+/* METRICS.rs
+ *   by Lut99
+ *
+ * Created:
+ *   14 Oct 2026, 21:03:52
+ * Last edited:
+ *   14 Oct 2026, 21:03:52
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Implements a small HTTP server that exposes the metrics in the default
+ *   Prometheus registry on `/metrics`. The services themselves define
+ *   (and register) the metrics they keep track of.
+**/
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use hyper::{Body, Request, Response, Server, StatusCode};
+use hyper::service::{make_service_fn, service_fn};
+use prometheus::{Encoder, TextEncoder};
+
+
+/***** HELPER FUNCTIONS *****/
+/// Handles a single request to the metrics server.
+/// 
+/// **Arguments**
+///  * `request`: The incoming request.
+/// 
+/// **Returns**  
+/// The metrics in the Prometheus text format if the request was for `/metrics`, or a 404 otherwise.
+async fn handle(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.uri().path() != "/metrics" {
+        let mut response = Response::new(Body::from("Not found"));
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(response);
+    }
+
+    // Encode all registered metrics
+    let encoder = TextEncoder::new();
+    let mut buffer: Vec<u8> = Vec::new();
+    if let Err(err) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        let mut response = Response::new(Body::from(format!("Could not encode metrics: {}", err)));
+        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        return Ok(response);
+    }
+
+    let mut response = Response::new(Body::from(buffer));
+    response.headers_mut().insert(hyper::header::CONTENT_TYPE, hyper::header::HeaderValue::from_static("text/plain; version=0.0.4"));
+    Ok(response)
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Serves the metrics in the default Prometheus registry on `http://<address>/metrics`.
+/// 
+/// Must be run in a Tokio runtime, and only returns if the server fails.
+/// 
+/// **Arguments**
+///  * `address`: The address to listen on.
+/// 
+/// **Returns**  
+/// Nothing if the server stopped, or a hyper::Error if it could not be started or crashed.
+pub async fn serve(address: SocketAddr) -> Result<(), hyper::Error> {
+    let service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+    Server::try_bind(&address)?.serve(service).await
+}
@@ -8,10 +8,122 @@ use serde::{Deserialize, Serialize};
 use serde_json::{json, Value as JValue};
 use serde_with::skip_serializing_none;
 
-use crate::package::PackageKind;
+use crate::package::{EnvironmentVariable, PackageKind, PackageRequirements};
 use crate::version::Version;
 
 
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_maps_round_trip_through_json() {
+        let mut inner = Map::<Value>::new();
+        inner.insert(String::from("x"), Value::Integer(1));
+        inner.insert(String::from("y"), Value::Unicode(String::from("why")));
+        let mut outer = Map::<Value>::new();
+        outer.insert(String::from("inner"), Value::Map(inner));
+        outer.insert(String::from("flag"), Value::Boolean(true));
+        let value = Value::Map(outer);
+
+        let json = serde_json::to_string(&value).unwrap();
+        let parsed: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, value);
+        assert_eq!(parsed.data_type(), "map");
+
+        // Plain JSON drops the type tags
+        assert_eq!(value.as_json(), json!({ "inner": { "x": 1, "y": "why" }, "flag": true }));
+    }
+
+    #[test]
+    fn map_display_is_sorted() {
+        let mut map = Map::<Value>::new();
+        map.insert(String::from("b"), Value::Integer(2));
+        map.insert(String::from("a"), Value::Integer(1));
+        assert_eq!(Value::Map(map).to_string(), "{\"a\": 1, \"b\": 2}");
+    }
+
+    #[test]
+    fn nested_values_compare_by_contents() {
+        let array = |entries: Vec<Value>| Value::Array { data_type: String::from("integer[]"), entries };
+        let point = |x: i64| {
+            let mut properties = Map::<Value>::new();
+            properties.insert(String::from("x"), Value::Integer(x));
+            Value::Struct { data_type: String::from("Point"), properties }
+        };
+        assert_eq!(array(vec![Value::Integer(1), Value::Integer(2)]), array(vec![Value::Integer(1), Value::Integer(2)]));
+        assert_ne!(array(vec![Value::Integer(1)]), array(vec![Value::Integer(2)]));
+        assert_eq!(point(1), point(1));
+        assert_ne!(point(1), point(2));
+
+        // Maps compare their values, whatever they are
+        let mut lhs = Map::<Value>::new();
+        lhs.insert(String::from("points"), array(vec![point(1)]));
+        let mut rhs = lhs.clone();
+        assert_eq!(Value::Map(lhs.clone()), Value::Map(rhs.clone()));
+        rhs.insert(String::from("points"), array(vec![point(2)]));
+        assert_ne!(Value::Map(lhs), Value::Map(rhs));
+    }
+
+    #[test]
+    fn values_conform_to_declared_types() {
+        assert!(Value::Integer(1).conforms_to("integer"));
+        assert!(!Value::Integer(1).conforms_to("real"));
+        assert!(Value::Unicode(String::from("hi")).conforms_to("any"));
+
+        let strings = Value::Array{ data_type: String::from("string"), entries: vec![ Value::Unicode(String::from("a")) ] };
+        assert!(strings.conforms_to("string[]"));
+        assert!(!strings.conforms_to("integer[]"));
+        assert!(!strings.conforms_to("string"));
+        assert!(Value::Array{ data_type: String::from("unit"), entries: vec![] }.conforms_to("integer[][]"));
+
+        let file = Value::Struct{ data_type: String::from("File"), properties: Map::new() };
+        assert!(file.conforms_to("File"));
+        assert!(!file.conforms_to("Directory"));
+    }
+
+    #[test]
+    fn json_nulls_are_unit() {
+        let json = json!({ "name": "x", "parent": null, "tags": [], "sizes": [ null, 1 ] });
+        let value = Value::from_json(&json);
+        let properties = match &value {
+            Value::Struct{ properties, .. } => properties,
+            value                           => panic!("Expected a struct, got {:?}", value),
+        };
+        assert_eq!(properties["parent"], Value::Unit);
+        assert!(matches!(&properties["tags"], Value::Array{ data_type, entries } if data_type == "unit[]" && entries.is_empty()));
+        assert!(matches!(&properties["sizes"], Value::Array{ entries, .. } if entries[0] == Value::Unit && entries[1] == Value::Integer(1)));
+
+        // Units survive the round trip through a payload, and untagged nulls in one are read as Unit too
+        let payload = serde_json::to_string(&value).unwrap();
+        assert_eq!(Value::from_payload(&payload).unwrap().as_json(), json);
+        let payload = payload.replace("{\"v\":\"unit\"}", "null");
+        assert!(payload.contains("null"));
+        assert_eq!(Value::from_payload(&payload).unwrap().as_json(), json);
+        assert_eq!(Value::from_payload("null").unwrap(), Value::Unit);
+        assert!(Value::from_payload("{\"v\":\"integer\",\"c\":\"one\"}").is_err());
+    }
+
+    #[test]
+    fn help_omits_missing_descriptions() {
+        let mut function = Function::new(vec![
+            Parameter::new(String::from("text"), String::from("string"), None, None, None),
+            Parameter::new(String::from("times"), String::from("integer"), None, None, None),
+        ], None, String::from("string"));
+        assert_eq!(function.help("repeat"), "repeat(text: string, times: integer) -> string");
+
+        // Blank descriptions count as missing, and parameter descriptions are kept on one line
+        function.description = Some(String::from("  \n"));
+        function.parameters[1].description = Some(String::from("How often to\nrepeat it."));
+        assert_eq!(function.help("repeat"), "repeat(text: string, times: integer) -> string\n\n  Parameters:\n    times: How often to repeat it.");
+    }
+}
+
+
+
+
+
 /***** CUSTOM TYPES *****/
 /// Shortcut for defining a hashmap with string keys.
 type Map<T> = std::collections::HashMap<String, T>;
@@ -20,6 +132,40 @@ type Map<T> = std::collections::HashMap<String, T>;
 
 
 
+/***** HELPER FUNCTIONS *****/
+/// Formats the documentation of a package function.
+/// 
+/// **Arguments**
+///  * `name`: The name of the function.
+///  * `parameters`: The parameters of the function.
+///  * `return_type`: The type that the function returns, if known.
+///  * `description`: The description of the function, if documented.
+/// 
+/// **Returns**  
+/// The signature on the first line, followed by the (indented) description and a line for every documented parameter.
+fn format_help(name: &str, parameters: &[Parameter], return_type: Option<&str>, description: Option<&str>) -> String {
+    let signature: Vec<String> = parameters.iter().map(|p| format!("{}: {}", p.name, p.data_type)).collect();
+    let mut help = format!("{}({})", name, signature.join(", "));
+    if let Some(return_type) = return_type { help.push_str(&format!(" -> {}", return_type)); }
+
+    if let Some(description) = description.map(str::trim).filter(|d| !d.is_empty()) {
+        for line in description.lines() { help.push_str(&format!("\n  {}", line)); }
+    }
+    let documented: Vec<&Parameter> = parameters.iter().filter(|p| p.description.as_deref().map(|d| !d.trim().is_empty()).unwrap_or(false)).collect();
+    if !documented.is_empty() {
+        help.push_str("\n\n  Parameters:");
+        for parameter in documented {
+            let description: Vec<&str> = parameter.description.as_deref().unwrap_or_default().split_whitespace().collect();
+            help.push_str(&format!("\n    {}: {}", parameter.name, description.join(" ")));
+        }
+    }
+    help
+}
+
+
+
+
+
 /***** DSL AST STRUCTS *****/
 /// Defines a function parameter in the DSL's AST.
 #[skip_serializing_none]
@@ -32,6 +178,8 @@ pub struct Parameter {
     pub name: String,
     pub optional: Option<bool>,
     pub secret: Option<String>,
+    /// What the parameter means, as documented by the package author.
+    pub description: Option<String>,
 }
 
 impl Parameter {
@@ -51,6 +199,7 @@ impl Parameter {
             name,
             optional,
             secret,
+            description: None,
         }
     }
 }
@@ -65,6 +214,13 @@ pub struct Function {
     pub parameters: Vec<Parameter>,
     pub pattern: Option<CallPattern>,
     pub return_type: String,
+    /// What the function does, as documented by the package author.
+    pub description: Option<String>,
+    /// Whether the function always returns the same value for the same arguments (and has no side effects), so that its results may be reused.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pure: bool,
+    /// How many calls to the function may run at the same time when it is mapped over an array of inputs, if the package limits it.
+    pub concurrency: Option<u32>,
 }
 
 impl Function {
@@ -80,8 +236,22 @@ impl Function {
             parameters,
             pattern,
             return_type,
+            description: None,
+            pure: false,
+            concurrency: None,
         }
     }
+
+    /// Formats the documentation of this function, as shown by `help()` and the REPL's `:doc`.
+    /// 
+    /// **Arguments**
+    ///  * `name`: The name under which the function is known.
+    /// 
+    /// **Returns**  
+    /// The signature of the function, followed by its description and that of its parameters (if documented).
+    pub fn help(&self, name: &str) -> String {
+        format_help(name, &self.parameters, Some(&self.return_type), self.description.as_deref())
+    }
 }
 
 
@@ -118,6 +288,8 @@ impl CallPattern {
 pub struct Type {
     pub name: String,
     pub properties: Vec<Property>,
+    /// What the type represents, as documented by the package author.
+    pub description: Option<String>,
 }
 
 impl Type {
@@ -128,7 +300,7 @@ impl Type {
         name: String,
         properties: Vec<Property>,
     ) -> Self {
-        Type { name, properties }
+        Type { name, properties, description: None }
     }
 }
 
@@ -146,6 +318,8 @@ pub struct Property {
     pub optional: Option<bool>,
     pub properties: Option<Vec<Property>>,
     pub secret: Option<bool>,
+    /// What the property means, as documented by the package author.
+    pub description: Option<String>,
 }
 
 impl Property {
@@ -167,6 +341,7 @@ impl Property {
             optional,
             properties,
             secret,
+            description: None,
         }
     }
 
@@ -184,6 +359,7 @@ impl Property {
             optional: None,
             properties: None,
             secret: None,
+            description: None,
         }
     }
 
@@ -191,7 +367,9 @@ impl Property {
     ///
     ///
     pub fn into_parameter(self) -> Parameter {
-        Parameter::new(self.name, self.data_type, self.optional, self.default, None)
+        let mut parameter = Parameter::new(self.name, self.data_type, self.optional, self.default, None);
+        parameter.description = self.description;
+        parameter
     }
 }
 
@@ -243,6 +421,7 @@ pub enum Value {
     },
     Boolean(bool),
     Integer(i64),
+    Map(Map<Value>),
     Pointer {
         #[serde(rename = "type")]
         data_type: String,
@@ -319,7 +498,10 @@ impl Value {
             JValue::String(s) => Value::Unicode(s.clone()),
             JValue::Array(a) => {
                 let entries: Vec<Value> = a.iter().map(Value::from_json).collect();
-                let data_type = format!("{}[]", entries.first().unwrap().data_type());
+                let data_type = match entries.first() {
+                    Some(entry) => format!("{}[]", entry.data_type()),
+                    None        => String::from("unit[]"),
+                };
 
                 Value::Array { data_type, entries }
             }
@@ -336,6 +518,29 @@ impl Value {
         }
     }
 
+    /// Parses a Value as it is serialized in the payload of a job's result (i.e., with its type tags). Any JSON `null` in it is read as Unit, like `from_json()` does.
+    /// 
+    /// **Arguments**
+    ///  * `payload`: The JSON-encoded Value.
+    /// 
+    /// **Returns**  
+    /// The parsed Value, or a serde_json::Error if the payload was not a valid Value.
+    pub fn from_payload(payload: &str) -> std::result::Result<Self, serde_json::Error> {
+        /// Replaces every null in the given JSON with a tagged Unit.
+        fn replace_nulls(value: &mut JValue) {
+            match value {
+                JValue::Null          => { *value = json!({ "v": "unit" }); },
+                JValue::Array(values) => values.iter_mut().for_each(replace_nulls),
+                JValue::Object(map)   => map.values_mut().for_each(replace_nulls),
+                _                     => {},
+            }
+        }
+
+        let mut payload: JValue = serde_json::from_str(payload)?;
+        replace_nulls(&mut payload);
+        serde_json::from_value(payload)
+    }
+
     /* TIM */
     /// **Edited: Changed return type to String instead of &str.**
     ///
@@ -346,6 +551,7 @@ impl Value {
             Array { data_type, .. } => data_type.clone(),
             Boolean(_) => "boolean".to_string(),
             Integer(_) => "integer".to_string(),
+            Map(_) => "map".to_string(),
             Pointer { data_type, .. } => data_type.clone(),
             Real(_) => "real".to_string(),
             Struct { data_type, .. } => data_type.clone(),
@@ -358,6 +564,24 @@ impl Value {
     }
     /*******/
 
+    /// Checks whether this Value may be passed for a parameter of the given type.
+    /// 
+    /// Arrays are checked element-wise (so empty arrays match any array type), since the type that arrays carry depends on where they were created. The `any` type matches everything.
+    /// 
+    /// **Arguments**
+    ///  * `data_type`: The declared type of the parameter, e.g. `integer`, `string[]` or the name of a class.
+    /// 
+    /// **Returns**  
+    /// Whether the Value is of that type.
+    pub fn conforms_to(&self, data_type: &str) -> bool {
+        if data_type == "any" { return true; }
+        match (self, data_type.strip_suffix("[]")) {
+            (Value::Array{ entries, .. }, Some(element_type)) => entries.iter().all(|entry| entry.conforms_to(element_type)),
+            (Value::Array{ .. }, None)                        => false,
+            (value, _)                                        => value.data_type() == data_type,
+        }
+    }
+
     ///
     ///
     ///
@@ -411,6 +635,14 @@ impl Value {
             Array { entries, .. } => json!(entries.iter().map(|e| e.as_json()).collect::<JValue>()),
             Boolean(b) => json!(b),
             Integer(i) => json!(i),
+            Map(entries) => {
+                let mut object = Map::<JValue>::new();
+                for (key, value) in entries {
+                    object.insert(key.clone(), value.as_json());
+                }
+
+                json!(object)
+            }
             Pointer { .. } => unimplemented!(),
             Real(r) => json!(r),
             Struct { data_type, properties } => match data_type.as_str() {
@@ -454,6 +686,14 @@ impl Display for Value {
             }
             Boolean(b) => b.to_string(),
             Integer(i) => i.to_string(),
+            Map(entries) => {
+                let mut entries = entries
+                    .iter()
+                    .map(|(k, v)| format!("{:?}: {}", k, v))
+                    .collect::<Vec<String>>();
+                entries.sort();
+                format!("{{{}}}", entries.join(", "))
+            }
             Pointer { variable, .. } => format!("@{}", variable),
             Real(r) => r.to_string(),
             Struct { properties, data_type } => {
@@ -484,12 +724,20 @@ impl PartialEq for Value {
         use Value::*;
 
         match (self, other) {
-            (Array { .. }, Array { .. }) => unimplemented!(),
+            // The element type of an array is inferred, so only its entries matter (e.g., for empty arrays)
+            (Array { entries: lhs, .. }, Array { entries: rhs, .. }) => lhs.eq(rhs),
             (Boolean(lhs), Boolean(rhs)) => lhs.eq(rhs),
             (Integer(lhs), Integer(rhs)) => lhs.eq(rhs),
-            (Pointer { .. }, Pointer { .. }) => unimplemented!(),
+            (Map(lhs), Map(rhs)) => lhs.eq(rhs),
+            (
+                Pointer { data_type: lhs_type, variable: lhs, .. },
+                Pointer { data_type: rhs_type, variable: rhs, .. },
+            ) => lhs_type.eq(rhs_type) && lhs.eq(rhs),
             (Real(lhs), Real(rhs)) => lhs.eq(rhs),
-            (Struct { .. }, Struct { .. }) => unimplemented!(),
+            (
+                Struct { data_type: lhs_type, properties: lhs },
+                Struct { data_type: rhs_type, properties: rhs },
+            ) => lhs_type.eq(rhs_type) && lhs.eq(rhs),
             (Unicode(lhs), Unicode(rhs)) => lhs.eq(rhs),
             (Unit, Unit) => true,
             _ => false,
@@ -531,6 +779,41 @@ pub struct FunctionExt {
     pub package: String,
     pub parameters: Vec<Parameter>,
     pub version: Version,
+    /// The type of the value that the function returns, if known.
+    pub return_type: Option<String>,
+    /// What the function does, as documented by the package author.
+    pub description: Option<String>,
+    /// What a location needs to offer to run the function (i.e., the requirements of its package).
+    #[serde(default)]
+    pub requirements: PackageRequirements,
+    /// The environment variables that the package of the function declares.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub environment: HashMap<String, EnvironmentVariable>,
+    /// The environment variables that the script set for calls to the function with `with_env()`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// Whether the package declares the function as pure, so that the driver may reuse its results (see `brane-drv`'s `calls` module).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pure: bool,
+    /// How many calls to the function may run at the same time when it is mapped over an array of inputs (see the `map_call` builtin), if the package limits it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<u32>,
+}
+
+impl FunctionExt {
+    /// Formats the documentation of this function, as shown by `help()` and the REPL's `:doc`.
+    /// 
+    /// **Returns**  
+    /// The signature of the function (and the package that provides it), followed by its description and that of its parameters (if documented).
+    pub fn help(&self) -> String {
+        let help = format_help(&self.name, &self.parameters, self.return_type.as_deref(), self.description.as_deref());
+        let mut lines = help.splitn(2, '\n');
+        let signature = lines.next().unwrap_or_default();
+        match lines.next() {
+            Some(rest) => format!("{} (from {} {})\n{}", signature, self.package, self.version, rest),
+            None       => format!("{} (from {} {})", signature, self.package, self.version),
+        }
+    }
 }
 
 /* TIM */
@@ -561,6 +844,9 @@ pub struct SpecFunction {
 pub struct Bytecode {
     pub code: Vec<u8>,
     pub constants: Vec<Value>,
+    /// The line in the source of every instruction, as the difference with the previous one.
+    #[serde(default)]
+    pub lines: Vec<i32>,
 }
 
 
@@ -0,0 +1,204 @@
+/* COMPARE.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 22:31:15
+ * Last edited:
+ *   15 Oct 2026, 22:31:15
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Compares an expected Value (e.g., from the `tests` section of a
+ *   container file) to the Value a function actually returned, listing
+ *   every place where they differ.
+ *
+ *   The comparison is deep, but forgiving about the things that an
+ *   expectation written in YAML cannot express:
+ *    - An integer and a real are equal if they are numerically equal,
+ *      and two reals are equal if they differ by at most REAL_TOLERANCE
+ *      (relative to the largest of them, or absolute below 1).
+ *    - Structs and maps are compared by their fields. The name of a
+ *      struct's type is only compared if both sides name one (i.e., an
+ *      expectation written as a plain mapping matches any struct).
+ *    - Arrays are compared element by element; their element type is
+ *      not compared, as that follows from the elements.
+ *    - Everything else (booleans, strings and unit) must be the same
+ *      kind of value with the same contents.
+**/
+
+use std::fmt::{Display, Formatter, Result as FResult};
+
+use crate::common::Value;
+
+
+/***** CONSTANTS *****/
+/// The largest relative difference between two reals that still counts as equal.
+pub const REAL_TOLERANCE: f64 = 1e-9;
+/// The type name that `Value::from_json()` gives to structs, which therefore doesn't name a type.
+const ANONYMOUS_TYPE: &str = "anonymous";
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_numbers_are_coerced() {
+        assert!(compare(&Value::Integer(2), &Value::Real(2.0)).is_empty());
+        assert!(compare(&Value::Real(2.0), &Value::Integer(2)).is_empty());
+        assert!(compare(&Value::Real(0.1 + 0.2), &Value::Real(0.3)).is_empty());
+        assert_eq!(compare(&Value::Integer(2), &Value::Real(2.5)), vec![ Difference{ path: String::new(), expected: String::from("2"), actual: String::from("2.5") } ]);
+        assert_eq!(compare(&Value::Integer(1), &Value::Unicode(String::from("1"))).len(), 1);
+    }
+
+    #[test]
+    fn test_structs_are_compared_by_field() {
+        let expected = Value::from_json(&json!({ "x": 1, "tags": [ "a", "b" ], "inner": { "ok": true } }));
+        let mut actual = Value::from_json(&json!({ "x": 1.0, "tags": [ "a", "b" ], "inner": { "ok": true } }));
+        if let Value::Struct{ data_type, .. } = &mut actual { *data_type = String::from("Point"); }
+        assert!(compare(&expected, &actual).is_empty());
+
+        // Named types must match, though
+        let mut named = expected.clone();
+        if let Value::Struct{ data_type, .. } = &mut named { *data_type = String::from("Line"); }
+        assert_eq!(compare(&named, &actual), vec![ Difference{ path: String::new(), expected: String::from("a Line"), actual: String::from("a Point") } ]);
+
+        let actual = Value::from_json(&json!({ "x": 2, "tags": [ "a" ], "inner": { "ok": true, "extra": null } }));
+        let differences: Vec<String> = compare(&expected, &actual).iter().map(|difference| difference.path.clone()).collect();
+        assert_eq!(differences, vec![ ".inner.extra", ".tags", ".x" ]);
+    }
+
+    #[test]
+    fn test_difference_display() {
+        let differences = compare(&Value::from_json(&json!({ "xs": [ 1, 2 ] })), &Value::from_json(&json!({ "xs": [ 1, 3 ] })));
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].to_string(), "at '.xs[1]':\n  - 2\n  + 3");
+    }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// A place where the actual Value differs from the expected one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    /// Where in the Value they differ (e.g., '.points[2].x'), or an empty string for the Value itself.
+    pub path     : String,
+    /// What we expected there.
+    pub expected : String,
+    /// What we got there.
+    pub actual   : String,
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        if !self.path.is_empty() { writeln!(f, "at '{}':", self.path)?; }
+        write!(f, "  - {}\n  + {}", self.expected, self.actual)
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Compares the expected Value to the actual one (see the top of this file for the rules).
+/// 
+/// **Arguments**
+///  * `expected`: The Value we expected.
+///  * `actual`: The Value we got.
+/// 
+/// **Returns**  
+/// Every place where they differ, sorted by path (so an empty list means they are equal).
+pub fn compare(expected: &Value, actual: &Value) -> Vec<Difference> {
+    let mut differences = vec![];
+    compare_at(String::new(), expected, actual, &mut differences);
+    differences.sort_by(|lhs, rhs| lhs.path.cmp(&rhs.path));
+    differences
+}
+
+
+
+/// Compares the expected Value to the actual one at the given path, adding any differences to the given list.
+fn compare_at(path: String, expected: &Value, actual: &Value, differences: &mut Vec<Difference>) {
+    use Value::*;
+    match (expected, actual) {
+        (Integer(lhs), Integer(rhs))    => { if lhs != rhs { differences.push(difference(path, expected, actual)); } },
+        (Integer(lhs), Real(rhs))       => { if !reals_equal(*lhs as f64, *rhs) { differences.push(difference(path, expected, actual)); } },
+        (Real(lhs), Integer(rhs))       => { if !reals_equal(*lhs, *rhs as f64) { differences.push(difference(path, expected, actual)); } },
+        (Real(lhs), Real(rhs))          => { if !reals_equal(*lhs, *rhs) { differences.push(difference(path, expected, actual)); } },
+        (Boolean(lhs), Boolean(rhs))    => { if lhs != rhs { differences.push(difference(path, expected, actual)); } },
+        (Unicode(lhs), Unicode(rhs))    => { if lhs != rhs { differences.push(difference(path, expected, actual)); } },
+        (Unit, Unit)                    => {},
+
+        (Array{ entries: lhs, .. }, Array{ entries: rhs, .. }) => {
+            if lhs.len() != rhs.len() {
+                differences.push(Difference{ path, expected: format!("{} element(s): {}", lhs.len(), expected), actual: format!("{} element(s): {}", rhs.len(), actual) });
+                return;
+            }
+            for (i, (lhs, rhs)) in lhs.iter().zip(rhs).enumerate() {
+                compare_at(format!("{}[{}]", path, i), lhs, rhs, differences);
+            }
+        },
+
+        (Struct{ .. } | Map(_), Struct{ .. } | Map(_)) => {
+            let (lhs_type, lhs) = fields(expected);
+            let (rhs_type, rhs) = fields(actual);
+            if let (Some(lhs_type), Some(rhs_type)) = (lhs_type, rhs_type) {
+                if lhs_type != rhs_type {
+                    differences.push(Difference{ path, expected: format!("a {}", lhs_type), actual: format!("a {}", rhs_type) });
+                    return;
+                }
+            }
+
+            for (name, lhs) in lhs {
+                match rhs.get(name) {
+                    Some(rhs) => compare_at(format!("{}.{}", path, name), lhs, rhs, differences),
+                    None      => differences.push(Difference{ path: format!("{}.{}", path, name), expected: lhs.to_string(), actual: String::from("(missing)") }),
+                }
+            }
+            for (name, rhs) in rhs {
+                if !lhs.contains_key(name) { differences.push(Difference{ path: format!("{}.{}", path, name), expected: String::from("(missing)"), actual: rhs.to_string() }); }
+            }
+        },
+
+        _ => differences.push(difference(path, expected, actual)),
+    }
+}
+
+/// Returns a Difference that shows both Values as they are.
+#[inline]
+fn difference(path: String, expected: &Value, actual: &Value) -> Difference {
+    Difference{ path, expected: describe(expected), actual: describe(actual) }
+}
+
+/// Formats a Value for a Difference, quoting strings so that they can't be confused with other values.
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Unicode(string) => format!("{:?}", string),
+        value                  => value.to_string(),
+    }
+}
+
+/// Returns the name of the type (if it names one) and the fields of a struct or map.
+fn fields(value: &Value) -> (Option<&str>, &std::collections::HashMap<String, Value>) {
+    match value {
+        Value::Struct{ data_type, properties } => (Some(data_type.as_str()).filter(|data_type| *data_type != ANONYMOUS_TYPE), properties),
+        Value::Map(properties)                 => (None, properties),
+        _                                      => unreachable!(),
+    }
+}
+
+/// Checks whether two reals are equal up to REAL_TOLERANCE.
+#[inline]
+fn reals_equal(lhs: f64, rhs: f64) -> bool {
+    lhs == rhs || (lhs - rhs).abs() <= REAL_TOLERANCE * lhs.abs().max(rhs.abs()).max(1.0)
+}
@@ -2,14 +2,14 @@ use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FResult};
 use std::fs::{self, File};
 use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
 use crate::common::{CallPattern, Parameter, Type};
-use crate::package::PackageKind;
+use crate::package::{EnvironmentVariable, PackageDependency, PackageKind, PackageRequirements, RESERVED_ENVIRONMENT_PREFIX};
 use crate::version::Version;
 
 
@@ -20,6 +20,229 @@ type Map<T> = std::collections::HashMap<String, T>;
 
 
 
+/***** CONSTANTS *****/
+/// The data types that may be used in a container file without declaring them in its `types` section.
+pub const BUILTIN_TYPES: [&str; 8] = [ "boolean", "integer", "real", "string", "unit", "Artifact", "Directory", "File" ];
+/// The type that actions which declare `outputs` return: a struct with their `output` as `value`, plus the `artifacts` that the branelet stored.
+pub const OUTPUT_TYPE: &str = "Output";
+/// The kinds of entrypoint that a container file may define.
+pub const ENTRYPOINT_KINDS: [&str; 2] = [ "service", "task" ];
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// Container file that passes validation, given a working directory with 'run.sh' in it.
+    const VALID_CONTAINER: &str = r#"
+name: test
+version: 1.0.0
+kind: ecu
+
+files:
+  - run.sh
+
+entrypoint:
+  kind: task
+  exec: run.sh
+
+actions:
+  'add':
+    input:
+      - type: integer
+        name: a
+      - type: Point[]
+        name: b
+    output:
+      - type: Point
+        name: c
+
+types:
+  'Point':
+    name: Point
+    properties:
+      - type: real
+        name: x
+      - type: real
+        name: y
+"#;
+
+
+
+    /// Creates a fresh working directory with an entrypoint script in it.
+    fn create_workdir() -> PathBuf {
+        let workdir = std::env::temp_dir().join(format!("brane-container-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&workdir).expect("Could not create test working directory");
+        fs::write(workdir.join("run.sh"), "#!/bin/bash\n").expect("Could not create test entrypoint");
+        workdir
+    }
+
+    /// Validates the given container file in a fresh working directory, returning the violations found.
+    fn validate(container: &str) -> Vec<ContainerValidationError> {
+        let workdir = create_workdir();
+        let container = ContainerInfo::from_string(container.to_string()).expect("Could not parse test container file");
+        let res = container.validate(&workdir);
+        fs::remove_dir_all(&workdir).expect("Could not remove test working directory");
+        match res {
+            Ok(())                                             => vec![],
+            Err(ContainerInfoError::ValidationError{ errors }) => errors,
+            Err(err)                                           => { panic!("Unexpected error: {}", err); }
+        }
+    }
+
+
+
+    #[test]
+    fn test_validate_ok() {
+        assert_eq!(validate(VALID_CONTAINER), vec![]);
+    }
+
+    #[test]
+    fn test_validate_illegal_name() {
+        let errors = validate(&VALID_CONTAINER.replace("name: test", "name: 1test").replace("'add':", "'add-one':"));
+        assert_eq!(errors, vec![
+            ContainerValidationError::IllegalName{ key: "name".into(), name: "1test".into() },
+            ContainerValidationError::IllegalName{ key: "actions.add-one".into(), name: "add-one".into() },
+        ]);
+    }
+
+    #[test]
+    fn test_validate_entrypoint() {
+        let errors = validate(&VALID_CONTAINER.replace("kind: task\n  exec: run.sh", "kind: job\n  exec: ''"));
+        assert_eq!(errors, vec![
+            ContainerValidationError::IllegalEntrypointKind{ key: "entrypoint.kind".into(), kind: "job".into() },
+            ContainerValidationError::MissingEntrypoint{ key: "entrypoint.exec".into() },
+        ]);
+
+        let errors = validate(&VALID_CONTAINER.replace("exec: run.sh", "exec: main.sh"));
+        assert_eq!(errors, vec![ ContainerValidationError::MissingFile{ key: "entrypoint.exec".into(), path: "main.sh".into() } ]);
+    }
+
+    #[test]
+    fn test_validate_unknown_type() {
+        let errors = validate(&VALID_CONTAINER.replace("type: Point[]", "type: Line[]").replace("type: real\n        name: y", "type: double\n        name: y"));
+        assert_eq!(errors, vec![
+            ContainerValidationError::UnknownType{ key: "actions.add.input[1].type".into(), data_type: "Line[]".into() },
+            ContainerValidationError::UnknownType{ key: "types.Point.properties[1].type".into(), data_type: "double".into() },
+        ]);
+    }
+
+    #[test]
+    fn test_validate_duplicate_name() {
+        let errors = validate(&VALID_CONTAINER.replace("name: b", "name: a"));
+        assert_eq!(errors, vec![ ContainerValidationError::DuplicateName{ key: "actions.add.input[1].name".into(), name: "a".into() } ]);
+    }
+
+    #[test]
+    fn test_validate_files() {
+        let errors = validate(&VALID_CONTAINER.replace("  - run.sh", "  - run.sh\n  - data.csv\n  - ../secret.txt"));
+        assert_eq!(errors, vec![
+            ContainerValidationError::MissingFile{ key: "files[1]".into(), path: "data.csv".into() },
+            ContainerValidationError::UnsafePath{ key: "files[2]".into(), path: "../secret.txt".into() },
+        ]);
+    }
+
+    #[test]
+    fn test_validate_outputs() {
+        let errors = validate(&VALID_CONTAINER.replace("    output:\n      - type: Point", "    outputs:\n      - 'results/*.csv'\n      - ./plot.png\n    output:\n      - type: Point"));
+        assert_eq!(errors, vec![]);
+
+        let errors = validate(&VALID_CONTAINER.replace("    output:\n      - type: Point", "    outputs:\n      - ''\n      - /tmp/*.csv\n      - 'results/../../*'\n    output:\n      - type: Point"));
+        assert_eq!(errors, vec![
+            ContainerValidationError::IllegalOutputPattern{ key: "actions.add.outputs[0]".into(), pattern: "".into() },
+            ContainerValidationError::IllegalOutputPattern{ key: "actions.add.outputs[1]".into(), pattern: "/tmp/*.csv".into() },
+            ContainerValidationError::IllegalOutputPattern{ key: "actions.add.outputs[2]".into(), pattern: "results/../../*".into() },
+        ]);
+    }
+
+    #[test]
+    fn test_outputs_change_the_return_type() {
+        let container = ContainerInfo::from_string(VALID_CONTAINER.to_string()).unwrap();
+        assert_eq!(container.actions["add"].return_type(), "Point");
+
+        let container = ContainerInfo::from_string(VALID_CONTAINER.replace("    output:\n      - type: Point", "    outputs:\n      - ./plot.png\n    output:\n      - type: Point")).unwrap();
+        assert_eq!(container.actions["add"].return_type(), OUTPUT_TYPE);
+    }
+
+    #[test]
+    fn test_validate_concurrency() {
+        let errors = validate(&VALID_CONTAINER.replace("    output:\n      - type: Point", "    concurrency: 4\n    output:\n      - type: Point"));
+        assert_eq!(errors, vec![]);
+
+        let errors = validate(&VALID_CONTAINER.replace("    output:\n      - type: Point", "    concurrency: 0\n    output:\n      - type: Point"));
+        assert_eq!(errors, vec![ ContainerValidationError::ZeroConcurrency{ key: "actions.add.concurrency".into() } ]);
+    }
+
+    #[test]
+    fn test_validate_no_actions() {
+        let container = &VALID_CONTAINER[..VALID_CONTAINER.find("actions:").unwrap()];
+        let errors = validate(&format!("{}actions: {{}}\n", container));
+        assert_eq!(errors, vec![ ContainerValidationError::NoActions{ key: "actions".into() } ]);
+    }
+
+    #[test]
+    fn test_validate_package_dependencies() {
+        let errors = validate(&format!("{}\npackageDependencies:\n  - name: base64\n    version-req: ^1.0\n  - name: data_init\n", VALID_CONTAINER));
+        assert_eq!(errors, vec![]);
+
+        let errors = validate(&format!("{}\npackageDependencies:\n  - name: base64\n  - name: test\n  - name: base64\n    version-req: ^1.0\n", VALID_CONTAINER));
+        assert_eq!(errors, vec![
+            ContainerValidationError::SelfDependency{ key: "packageDependencies[1].name".into() },
+            ContainerValidationError::DuplicateName{ key: "packageDependencies[2].name".into(), name: "base64".into() },
+        ]);
+    }
+
+    #[test]
+    fn test_validate_tests() {
+        let errors = validate(&format!("{}\ntests:\n  - function: add\n    input:\n      a: 1\n      b: []\n    output: {{ x: 0, y: 0 }}\n  - name: fails\n    function: add\n    exitCode: 1\n", VALID_CONTAINER));
+        assert_eq!(errors, vec![]);
+
+        let errors = validate(&format!("{}\ntests:\n  - function: sub\n  - function: add\n    output: 3\n    exitCode: 2\n", VALID_CONTAINER));
+        assert_eq!(errors, vec![
+            ContainerValidationError::UnknownAction{ key: "tests[0].function".into(), name: "sub".into() },
+            ContainerValidationError::ConflictingExpectation{ key: "tests[1]".into(), exit_code: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_validate_environment() {
+        let errors = validate(&format!("{}\nenvironment:\n  LANG: C.UTF-8\n  OMP_NUM_THREADS:\n    default: '4'\n    configurable: true\n  API_URL:\n    configurable: true\n", VALID_CONTAINER));
+        assert_eq!(errors, vec![]);
+
+        let errors = validate(&format!("{}\nenvironment:\n  BRANE_JOB_ID: spoofed\n  1THREADS:\n    default: '4'\n", VALID_CONTAINER));
+        assert_eq!(errors, vec![
+            ContainerValidationError::IllegalName{ key: "environment.1THREADS".into(), name: "1THREADS".into() },
+            ContainerValidationError::ReservedEnvironmentVariable{ key: "environment.BRANE_JOB_ID".into(), name: "BRANE_JOB_ID".into() },
+        ]);
+    }
+
+    #[test]
+    fn test_environment_declarations() {
+        let container = ContainerInfo::from_string(format!("{}\nenvironment:\n  LANG: C.UTF-8\n  OMP_NUM_THREADS:\n    default: '4'\n    configurable: true\n  API_URL: {{}}\n", VALID_CONTAINER)).unwrap();
+        let environment = container.environment.as_ref().unwrap();
+        assert_eq!(environment["LANG"], EnvironmentVariable::Fixed("C.UTF-8".into()));
+        assert_eq!((environment["LANG"].default_value(), environment["LANG"].is_configurable()), (Some("C.UTF-8"), false));
+        assert_eq!((environment["OMP_NUM_THREADS"].default_value(), environment["OMP_NUM_THREADS"].is_configurable()), (Some("4"), true));
+        assert_eq!((environment["API_URL"].default_value(), environment["API_URL"].is_configurable()), (None, false));
+
+        // The declarations end up in the image, so the branelet can export them
+        let local = LocalContainerInfo::from(&container);
+        let mut buffer = vec![];
+        local.to_writer(&mut buffer).unwrap();
+        let local = LocalContainerInfo::from_reader(buffer.as_slice()).unwrap();
+        assert_eq!(&local.environment, environment);
+    }
+}
+
+
+
+
+
 /***** ERRORS *****/
 /// Collect errors relating to the LocalContainer specification.
 #[derive(Debug)]
@@ -33,6 +256,8 @@ pub enum LocalContainerInfoError {
     FileCreateError{ path: PathBuf, err: std::io::Error },
     /// Could not write to the given writer
     FileWriteError{ err: serde_yaml::Error },
+    /// Could not convert the LocalContainerInfo to a value with sorted keys
+    FileSerializeError{ err: serde_json::Error },
 }
 
 impl Display for LocalContainerInfoError {
@@ -43,6 +268,7 @@ impl Display for LocalContainerInfoError {
 
             LocalContainerInfoError::FileCreateError{ path, err } => write!(f, "Could not create local container file '{}': {}", path.display(), err),
             LocalContainerInfoError::FileWriteError{ err }        => write!(f, "Could not serialize & write local container file: {}", err),
+            LocalContainerInfoError::FileSerializeError{ err }    => write!(f, "Could not serialize local container file: {}", err),
         }
     }
 }
@@ -51,6 +277,94 @@ impl Error for LocalContainerInfoError {}
 
 
 
+/// Describes a single problem found while validating a ContainerInfo. Each of them refers to the offending key in the container file.
+#[derive(Debug, PartialEq)]
+pub enum ContainerValidationError {
+    /// A name (of the package, an action, a parameter or a type) is not a valid identifier
+    IllegalName{ key: String, name: String },
+    /// The same name is used twice in a list where it should be unique
+    DuplicateName{ key: String, name: String },
+
+    /// The entrypoint does not define a file to execute
+    MissingEntrypoint{ key: String },
+    /// The entrypoint is of an unknown kind
+    IllegalEntrypointKind{ key: String, kind: String },
+    /// The package does not define any actions
+    NoActions{ key: String },
+    /// A data type is neither a builtin type nor declared in the types section
+    UnknownType{ key: String, data_type: String },
+    /// The package declares a dependency on itself
+    SelfDependency{ key: String },
+    /// A test case refers to an action that the package does not define
+    UnknownAction{ key: String, name: String },
+    /// A test case expects an output from a call that it also expects to fail
+    ConflictingExpectation{ key: String, exit_code: i32 },
+    /// An environment variable has a name that Brane reserves for itself
+    ReservedEnvironmentVariable{ key: String, name: String },
+    /// An output pattern is empty or could match files outside of the working directory
+    IllegalOutputPattern{ key: String, pattern: String },
+    /// An action allows no calls to run at the same time
+    ZeroConcurrency{ key: String },
+
+    /// A referenced file does not exist in the working directory
+    MissingFile{ key: String, path: PathBuf },
+    /// A referenced file points outside of the working directory
+    UnsafePath{ key: String, path: PathBuf },
+}
+
+impl ContainerValidationError {
+    /// Returns the key in the container file that this error refers to.
+    #[inline]
+    pub fn key(&self) -> &str {
+        use ContainerValidationError::*;
+        match self {
+            IllegalName{ key, .. }                 |
+            DuplicateName{ key, .. }               |
+            MissingEntrypoint{ key }               |
+            IllegalEntrypointKind{ key, .. }       |
+            NoActions{ key }                       |
+            UnknownType{ key, .. }                 |
+            SelfDependency{ key }                  |
+            UnknownAction{ key, .. }               |
+            ConflictingExpectation{ key, .. }      |
+            ReservedEnvironmentVariable{ key, .. } |
+            IllegalOutputPattern{ key, .. }        |
+            ZeroConcurrency{ key }                 |
+            MissingFile{ key, .. }                 |
+            UnsafePath{ key, .. }                  => key,
+        }
+    }
+}
+
+impl Display for ContainerValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        use ContainerValidationError::*;
+        match self {
+            IllegalName{ key, name }   => write!(f, "{}: '{}' is not a valid identifier (use letters, digits and underscores, not starting with a digit)", key, name),
+            DuplicateName{ key, name } => write!(f, "{}: name '{}' is already used", key, name),
+
+            MissingEntrypoint{ key }           => write!(f, "{}: no file to execute is specified", key),
+            IllegalEntrypointKind{ key, kind } => write!(f, "{}: unknown entrypoint kind '{}' (expected one of {})", key, kind, ENTRYPOINT_KINDS.iter().map(|k| format!("'{}'", k)).collect::<Vec<String>>().join(", ")),
+            NoActions{ key }                   => write!(f, "{}: package does not define any actions", key),
+            UnknownType{ key, data_type }      => write!(f, "{}: unknown type '{}' (expected one of {}, or a type declared in the 'types' section)", key, data_type, BUILTIN_TYPES.iter().map(|t| format!("'{}'", t)).collect::<Vec<String>>().join(", ")),
+            SelfDependency{ key }              => write!(f, "{}: package cannot depend on itself", key),
+            UnknownAction{ key, name }         => write!(f, "{}: package does not define an action '{}'", key, name),
+
+            ConflictingExpectation{ key, exit_code } => write!(f, "{}: test case expects both an output and a failure (exit code {})", key, exit_code),
+            ReservedEnvironmentVariable{ key, name } => write!(f, "{}: environment variable '{}' is reserved (names starting with '{}' are set by Brane itself)", key, name, RESERVED_ENVIRONMENT_PREFIX),
+            IllegalOutputPattern{ key, pattern }     => write!(f, "{}: output pattern '{}' must be a non-empty path relative to the working directory (without '..')", key, pattern),
+            ZeroConcurrency{ key }                   => write!(f, "{}: concurrency must be at least 1 (omit it to use the driver's default)", key),
+
+            MissingFile{ key, path } => write!(f, "{}: file '{}' does not exist in the working directory", key, path.display()),
+            UnsafePath{ key, path }  => write!(f, "{}: path '{}' points outside of the working directory", key, path.display()),
+        }
+    }
+}
+
+impl Error for ContainerValidationError {}
+
+
+
 /// Collects errors relating to the Container specification.
 #[derive(Debug)]
 pub enum ContainerInfoError {
@@ -63,6 +377,9 @@ pub enum ContainerInfoError {
     FileCreateError{ path: PathBuf, err: std::io::Error },
     /// Could not write to the given writer
     FileWriteError{ err: serde_yaml::Error },
+
+    /// The container file is syntactically correct, but not a valid package
+    ValidationError{ errors: Vec<ContainerValidationError> },
 }
 
 impl Display for ContainerInfoError {
@@ -73,6 +390,14 @@ impl Display for ContainerInfoError {
 
             ContainerInfoError::FileCreateError{ path, err } => write!(f, "Could not create container file '{}': {}", path.display(), err),
             ContainerInfoError::FileWriteError{ err }        => write!(f, "Could not serialize & write container file: {}", err),
+
+            ContainerInfoError::ValidationError{ errors } => {
+                write!(f, "Container file is invalid ({} problem{} found):", errors.len(), if errors.len() == 1 { "" } else { "s" })?;
+                for err in errors {
+                    write!(f, "\n - {}", err)?;
+                }
+                Ok(())
+            },
         }
     }
 }
@@ -83,21 +408,103 @@ impl Error for ContainerInfoError {}
 
 
 
+/***** HELPER FUNCTIONS *****/
+/// Checks whether the given name is a valid identifier, i.e., consists of only letters, digits and underscores and does not start with a digit.
+/// 
+/// **Arguments**
+///  * `name`: The name to check.
+/// 
+/// **Returns**  
+/// True if it is valid, or false otherwise.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _                                              => false,
+    }
+}
+
+/// Checks whether the given data type is known, i.e., a builtin type, a declared type or an array of either of those.
+/// 
+/// **Arguments**
+///  * `data_type`: The data type to check.
+///  * `types`: The types declared in the container file.
+/// 
+/// **Returns**  
+/// True if it is known, or false otherwise.
+fn is_known_type(data_type: &str, types: &Option<Map<Type>>) -> bool {
+    // Strip any array brackets
+    let mut data_type = data_type;
+    while let Some(element_type) = data_type.strip_suffix("[]") { data_type = element_type; }
+
+    // Look it up
+    BUILTIN_TYPES.contains(&data_type) || types.as_ref().map(|types| types.contains_key(data_type)).unwrap_or(false)
+}
+
+/// Checks whether the given path (as found in a container file) points to an existing file in the working directory.
+/// 
+/// **Arguments**
+///  * `key`: The key in the container file where we found the path.
+///  * `path`: The path to check.
+///  * `workdir`: The working directory the path is relative to.
+///  * `errors`: The list of errors to add to if the path is not OK.
+fn validate_path(key: String, path: &str, workdir: &Path, errors: &mut Vec<ContainerValidationError>) {
+    let path = PathBuf::from(path);
+
+    // Make sure it does not escape the working directory
+    if path.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+        errors.push(ContainerValidationError::UnsafePath{ key, path });
+        return;
+    }
+
+    // Make sure it exists
+    if !workdir.join(&path).exists() {
+        errors.push(ContainerValidationError::MissingFile{ key, path });
+    }
+}
+
+/// Checks the names and types of a list of parameters.
+/// 
+/// **Arguments**
+///  * `key`: The key in the container file where we found the list.
+///  * `parameters`: The list of (name, data type) pairs to check.
+///  * `types`: The types declared in the container file.
+///  * `errors`: The list of errors to add any problems to.
+fn validate_parameters<'a>(key: &str, parameters: impl Iterator<Item = (&'a str, &'a str)>, types: &Option<Map<Type>>, errors: &mut Vec<ContainerValidationError>) {
+    let mut names: Vec<&str> = vec![];
+    for (i, (name, data_type)) in parameters.enumerate() {
+        // Check the name
+        if !is_identifier(name) { errors.push(ContainerValidationError::IllegalName{ key: format!("{}[{}].name", key, i), name: name.to_string() }); }
+        else if names.contains(&name) { errors.push(ContainerValidationError::DuplicateName{ key: format!("{}[{}].name", key, i), name: name.to_string() }); }
+        names.push(name);
+
+        // Check the type
+        if !is_known_type(data_type, types) { errors.push(ContainerValidationError::UnknownType{ key: format!("{}[{}].type", key, i), data_type: data_type.to_string() }); }
+    }
+}
+
+
+
+
+
 /***** SPECIFICATIONS *****/
 /// Specifies the contents of a contaienr info YAML file that is inside the container itself.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LocalContainerInfo {
     /// The name of the package
-    pub name       : String,
+    pub name        : String,
     /// The kind of the package
-    pub kind       : PackageKind,
+    pub kind        : PackageKind,
     /// The entrypoint to the package
-    pub entrypoint : Entrypoint,
+    pub entrypoint  : Entrypoint,
     /// The list of actions that this package can do.
-    pub actions    : Map<Action>,
+    pub actions     : Map<Action>,
     /// The list of types that are declared in this package.
-    pub types      : Map<Type>,
+    pub types       : Map<Type>,
+    /// The environment variables that this package declares.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub environment : Map<EnvironmentVariable>,
 }
 
 impl LocalContainerInfo {
@@ -171,6 +578,8 @@ impl LocalContainerInfo {
 
     /// Writes the LocalContainerInfo to the given writer.
     /// 
+    /// The keys are written in sorted order (instead of in the random order of the maps), as the file ends up in the package image and should be the same for every build.
+    /// 
     /// **Generic types**
     ///  * `W`: The type of the writer, which implements Write.
     /// 
@@ -180,8 +589,9 @@ impl LocalContainerInfo {
     /// **Returns**  
     /// Nothing on success, or a LocalContainerInfoError otherwise.
     pub fn to_writer<W: Write>(&self, writer: W) -> Result<(), LocalContainerInfoError> {
-        // Simply write with serde
-        match serde_yaml::to_writer(writer, self) {
+        // Go through a JSON value to sort the keys, then write with serde
+        let value = serde_json::to_value(self).map_err(|err| LocalContainerInfoError::FileSerializeError{ err })?;
+        match serde_yaml::to_writer(writer, &value) {
             Ok(())   => Ok(()),
             Err(err) => Err(LocalContainerInfoError::FileWriteError{ err }),
         }
@@ -191,11 +601,12 @@ impl LocalContainerInfo {
 impl From<ContainerInfo> for LocalContainerInfo {
     fn from(container_info: ContainerInfo) -> Self {
         Self {
-            name       : container_info.name,
-            kind       : container_info.kind,
-            entrypoint : container_info.entrypoint,
-            actions    : container_info.actions,
-            types      : container_info.types.unwrap_or_default(),
+            name        : container_info.name,
+            kind        : container_info.kind,
+            entrypoint  : container_info.entrypoint,
+            actions     : container_info.actions,
+            types       : container_info.types.unwrap_or_default(),
+            environment : container_info.environment.unwrap_or_default(),
         }
     }
 }
@@ -203,11 +614,12 @@ impl From<ContainerInfo> for LocalContainerInfo {
 impl From<&ContainerInfo> for LocalContainerInfo {
     fn from(container_info: &ContainerInfo) -> Self {
         Self {
-            name       : container_info.name.clone(),
-            kind       : container_info.kind,
-            entrypoint : container_info.entrypoint.clone(),
-            actions    : container_info.actions.clone(),
-            types      : container_info.types.as_ref().cloned().unwrap_or_default(),
+            name        : container_info.name.clone(),
+            kind        : container_info.kind,
+            entrypoint  : container_info.entrypoint.clone(),
+            actions     : container_info.actions.clone(),
+            types       : container_info.types.as_ref().cloned().unwrap_or_default(),
+            environment : container_info.environment.as_ref().cloned().unwrap_or_default(),
         }
     }
 }
@@ -231,24 +643,32 @@ pub struct ContainerInfo {
     pub description : Option<String>,
 
     /// The functions that this package supports.
-    pub actions    : Map<Action>,
+    pub actions     : Map<Action>,
     /// The entrypoint of the image
-    pub entrypoint : Entrypoint,
+    pub entrypoint  : Entrypoint,
     /// The types that this package adds.
-    pub types      : Option<Map<Type>>,
+    pub types       : Option<Map<Type>>,
 
     /// The base image to use for the package image.
     pub base         : Option<String>,
     /// The dependencies, as install commands for sudo apt-get install -y <...>
     pub dependencies : Option<Vec<String>>,
-    /// Any environment variables that the user wants to be set
-    pub environment  : Option<Map<String>>,
+    /// Any environment variables that the user wants to be set, either with a fixed value or declared with a default and whether scripts may set them at call time
+    pub environment  : Option<Map<EnvironmentVariable>>,
     /// The list of additional files to copy to the image
     pub files        : Option<Vec<String>>,
     /// An extra script to run to initialize the working directory
     pub initialize   : Option<Vec<String>>,
     /// An extra script to run to install the image(?)
     pub install      : Option<Vec<String>>,
+
+    /// The other Brane packages that this package depends on
+    pub package_dependencies : Option<Vec<PackageDependency>>,
+    /// What a location needs to offer to run the jobs of this package
+    pub requirements         : Option<PackageRequirements>,
+
+    /// Test cases for the package's actions, which are run against the built image by `brane build --test`
+    pub tests : Option<Vec<TestCase>>,
 }
 
 #[allow(unused)]
@@ -313,6 +733,123 @@ impl ContainerInfo {
 
 
 
+    /// Checks whether the ContainerInfo describes a package that can actually be built.
+    /// 
+    /// This checks that all names are valid identifiers (and unique where needed), that the entrypoint is sensible, that all data types are known and that all referenced files exist in the working directory. Rather than stopping at the first problem, all of them are collected and returned at once.
+    /// 
+    /// **Generic types**
+    ///  * `P`: The Path-like type of the working directory.
+    /// 
+    /// **Arguments**
+    ///  * `workdir`: The working directory that any files in the ContainerInfo are relative to.
+    /// 
+    /// **Returns**  
+    /// Nothing if the ContainerInfo is valid, or a ContainerInfoError::ValidationError with all problems found otherwise.
+    pub fn validate<P: AsRef<Path>>(&self, workdir: P) -> Result<(), ContainerInfoError> {
+        let workdir = workdir.as_ref();
+        let mut errors: Vec<ContainerValidationError> = vec![];
+
+        // Check the package name
+        if !is_identifier(&self.name) { errors.push(ContainerValidationError::IllegalName{ key: "name".to_string(), name: self.name.clone() }); }
+
+        // Check the entrypoint
+        if !ENTRYPOINT_KINDS.contains(&self.entrypoint.kind.as_str()) {
+            errors.push(ContainerValidationError::IllegalEntrypointKind{ key: "entrypoint.kind".to_string(), kind: self.entrypoint.kind.clone() });
+        }
+        if self.entrypoint.exec.trim().is_empty() {
+            errors.push(ContainerValidationError::MissingEntrypoint{ key: "entrypoint.exec".to_string() });
+        } else {
+            validate_path("entrypoint.exec".to_string(), &self.entrypoint.exec, workdir, &mut errors);
+        }
+
+        // Check the actions (sorted, to report problems in a predictable order)
+        if self.actions.is_empty() { errors.push(ContainerValidationError::NoActions{ key: "actions".to_string() }); }
+        let mut actions: Vec<(&String, &Action)> = self.actions.iter().collect();
+        actions.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+        for (name, action) in actions {
+            let key = format!("actions.{}", name);
+            if !is_identifier(name) { errors.push(ContainerValidationError::IllegalName{ key: key.clone(), name: name.clone() }); }
+
+            // Check its in- and output
+            if let Some(input) = &action.input {
+                validate_parameters(&format!("{}.input", key), input.iter().map(|p| (p.name.as_str(), p.data_type.as_str())), &self.types, &mut errors);
+            }
+            if let Some(output) = &action.output {
+                validate_parameters(&format!("{}.output", key), output.iter().map(|p| (p.name.as_str(), p.data_type.as_str())), &self.types, &mut errors);
+            }
+
+            // Check the files it leaves behind
+            if let Some(outputs) = &action.outputs {
+                for (i, pattern) in outputs.iter().enumerate() {
+                    let path = PathBuf::from(pattern);
+                    if pattern.trim().is_empty() || path.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+                        errors.push(ContainerValidationError::IllegalOutputPattern{ key: format!("{}.outputs[{}]", key, i), pattern: pattern.clone() });
+                    }
+                }
+            }
+
+            // Check how many of it may run at once
+            if action.concurrency == Some(0) { errors.push(ContainerValidationError::ZeroConcurrency{ key: format!("{}.concurrency", key) }); }
+        }
+
+        // Check the types
+        if let Some(types) = &self.types {
+            let mut sorted_types: Vec<(&String, &Type)> = types.iter().collect();
+            sorted_types.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+            for (name, data_type) in sorted_types {
+                let key = format!("types.{}", name);
+                if !is_identifier(name) { errors.push(ContainerValidationError::IllegalName{ key: key.clone(), name: name.clone() }); }
+                else if BUILTIN_TYPES.contains(&name.as_str()) { errors.push(ContainerValidationError::DuplicateName{ key: key.clone(), name: name.clone() }); }
+
+                // Check its properties
+                validate_parameters(&format!("{}.properties", key), data_type.properties.iter().map(|p| (p.name.as_str(), p.data_type.as_str())), &self.types, &mut errors);
+            }
+        }
+
+        // Check the files to copy
+        if let Some(files) = &self.files {
+            for (i, file) in files.iter().enumerate() {
+                validate_path(format!("files[{}]", i), file, workdir, &mut errors);
+            }
+        }
+
+        // Check the dependencies on other packages
+        if let Some(dependencies) = &self.package_dependencies {
+            let mut names: Vec<&str> = Vec::with_capacity(dependencies.len());
+            for (i, dependency) in dependencies.iter().enumerate() {
+                let key = format!("packageDependencies[{}].name", i);
+                if !is_identifier(&dependency.name) { errors.push(ContainerValidationError::IllegalName{ key, name: dependency.name.clone() }); }
+                else if dependency.name == self.name { errors.push(ContainerValidationError::SelfDependency{ key }); }
+                else if names.contains(&dependency.name.as_str()) { errors.push(ContainerValidationError::DuplicateName{ key, name: dependency.name.clone() }); }
+                names.push(&dependency.name);
+            }
+        }
+
+        // Check the environment variables
+        if let Some(environment) = &self.environment {
+            let mut names: Vec<&String> = environment.keys().collect();
+            names.sort();
+            for name in names {
+                let key = format!("environment.{}", name);
+                if !is_identifier(name) { errors.push(ContainerValidationError::IllegalName{ key, name: name.clone() }); }
+                else if name.starts_with(RESERVED_ENVIRONMENT_PREFIX) { errors.push(ContainerValidationError::ReservedEnvironmentVariable{ key, name: name.clone() }); }
+            }
+        }
+
+        // Check the test cases
+        if let Some(tests) = &self.tests {
+            for (i, test) in tests.iter().enumerate() {
+                if !self.actions.contains_key(&test.function) { errors.push(ContainerValidationError::UnknownAction{ key: format!("tests[{}].function", i), name: test.function.clone() }); }
+                if test.output.is_some() && test.expected_exit_code() != 0 { errors.push(ContainerValidationError::ConflictingExpectation{ key: format!("tests[{}]", i), exit_code: test.expected_exit_code() }); }
+            }
+        }
+
+        // Done
+        if errors.is_empty() { Ok(()) } else { Err(ContainerInfoError::ValidationError{ errors }) }
+    }
+
+
+
     /// Writes the ContainerInfo to the given location.
     /// 
     /// **Generic types**
@@ -369,6 +906,51 @@ pub struct Action {
     pub pattern: Option<CallPattern>,
     pub input: Option<Vec<Parameter>>,
     pub output: Option<Vec<Parameter>>,
+    /// Whether the action always returns the same output for the same input (and has no side effects). If so, the driver may reuse its results instead of running it again.
+    pub pure: Option<bool>,
+    /// Glob patterns (relative to the directory the package runs in) of the files that the action produces. branelet collects them as artifacts after the call.
+    pub outputs: Option<Vec<String>>,
+    /// How many calls to the action may run at the same time when it is mapped over an array of inputs (see the `map_call` builtin). Defaults to what the driver allows.
+    pub concurrency: Option<u32>,
+}
+
+
+
+/// Defines the YAML of a test case for one of the actions in a package.
+#[skip_serializing_none]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestCase {
+    /// An optional name to refer to the test case by in reports.
+    pub name      : Option<String>,
+    /// The action to call.
+    pub function  : String,
+    /// The arguments to call it with.
+    pub input     : Option<Map<serde_json::Value>>,
+    /// The value that the action should return (see `specifications::compare` for how it is compared). If omitted, the output is not checked.
+    pub output    : Option<serde_json::Value>,
+    /// The exit code that the call should have, where a non-zero code means that the call is expected to fail. Defaults to 0.
+    pub exit_code : Option<i32>,
+}
+
+impl Action {
+    /// Returns the type that calls to the action return, which is an Output whenever the action declares files as outputs.
+    /// 
+    /// **Returns**  
+    /// The type of the first `output` parameter (or 'unit' if there is none), or `OUTPUT_TYPE` if the action has `outputs`.
+    pub fn return_type(&self) -> String {
+        if self.outputs.as_ref().map(|outputs| !outputs.is_empty()).unwrap_or(false) { return String::from(OUTPUT_TYPE); }
+        match self.output.as_ref().and_then(|output| output.first()) {
+            Some(output) => output.data_type.to_string(),
+            None         => String::from("unit"),
+        }
+    }
+}
+
+impl TestCase {
+    /// Returns the exit code that the call is expected to have.
+    #[inline]
+    pub fn expected_exit_code(&self) -> i32 { self.exit_code.unwrap_or(0) }
 }
 
 
@@ -73,3 +73,75 @@ impl std::fmt::Display for EncodeDecodeError {
 }
 
 impl std::error::Error for EncodeDecodeError {}
+
+
+
+/// Errors that relate to signing packages or verifying their signatures
+#[derive(Debug)]
+pub enum SigningError {
+    /// The package has no image digest, so there is nothing to sign
+    MissingDigest{ name: String, version: String },
+    /// Could not serialize the metadata of the package
+    SerializeError{ err: serde_json::Error },
+
+    /// A (signing or public) key is not a valid ed25519 key
+    IllegalKey{ err: String },
+    /// A signature was made with another algorithm than the one we support
+    UnsupportedAlgorithm{ algorithm: String },
+    /// A signature is not a valid ed25519 signature
+    IllegalSignature{ err: String },
+
+    /// The package was not signed at all
+    Unsigned{ name: String, version: String },
+    /// The package was signed with a key that we do not trust
+    UnknownKey{ public_key: String },
+    /// The signature does not match the metadata of the package (i.e., either was tampered with)
+    InvalidSignature{ name: String, version: String, key: String },
+}
+
+impl std::fmt::Display for SigningError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SigningError::MissingDigest{ name, version } => write!(f, "Package '{}' (version {}) has no image digest; build it before signing it", name, version),
+            SigningError::SerializeError{ err }          => write!(f, "Could not serialize package metadata: {}", err),
+
+            SigningError::IllegalKey{ err }                 => write!(f, "Illegal ed25519 key: {}", err),
+            SigningError::UnsupportedAlgorithm{ algorithm } => write!(f, "Unsupported signature algorithm '{}' (only '{}' is supported)", algorithm, crate::signing::SIGNATURE_ALGORITHM),
+            SigningError::IllegalSignature{ err }           => write!(f, "Illegal ed25519 signature: {}", err),
+
+            SigningError::Unsigned{ name, version }              => write!(f, "Package '{}' (version {}) is not signed", name, version),
+            SigningError::UnknownKey{ public_key }               => write!(f, "Package is signed with key '{}', which is not in the trust store", public_key),
+            SigningError::InvalidSignature{ name, version, key } => write!(f, "Signature of package '{}' (version {}) by key '{}' does not match its metadata; it may have been tampered with", name, version, key),
+        }
+    }
+}
+
+impl std::error::Error for SigningError {}
+
+
+
+/// Errors that relate to setting the environment variables of a package at call time
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EnvironmentError {
+    /// The variable is one that Brane sets itself
+    Reserved{ name: String },
+    /// The package does not declare the variable
+    Undeclared{ package: String, name: String, configurable: Vec<String> },
+    /// The package declares the variable, but not as configurable
+    NotConfigurable{ package: String, name: String },
+}
+
+impl std::fmt::Display for EnvironmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnvironmentError::Reserved{ name } => write!(f, "Environment variable '{}' is reserved by Brane (variables starting with '{}' cannot be set)", name, crate::package::RESERVED_ENVIRONMENT_PREFIX),
+            EnvironmentError::Undeclared{ package, name, configurable } => {
+                if configurable.is_empty() { write!(f, "Package '{}' does not declare environment variable '{}' (it has no configurable variables)", package, name) }
+                else { write!(f, "Package '{}' does not declare environment variable '{}' (its configurable variables are {})", package, name, configurable.iter().map(|name| format!("'{}'", name)).collect::<Vec<String>>().join(", ")) }
+            },
+            EnvironmentError::NotConfigurable{ package, name } => write!(f, "Environment variable '{}' of package '{}' is not configurable (declare it with 'configurable: true' to allow setting it at call time)", name, package),
+        }
+    }
+}
+
+impl std::error::Error for EnvironmentError {}
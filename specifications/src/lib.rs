@@ -2,9 +2,12 @@
 extern crate anyhow;
 
 pub mod common;
+pub mod compare;
 pub mod container;
 pub mod errors;
 pub mod registry;
 pub mod package;
+pub mod pretty;
+pub mod signing;
 pub mod status;
 pub mod version;
@@ -7,7 +7,7 @@ use chrono::{DateTime, Utc};
 // use semver::Version;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JValue;
-use serde_with::skip_serializing_none;
+use serde_with::{serde_as, skip_serializing_none, DisplayFromStr};
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 use tar::Archive;
@@ -15,7 +15,8 @@ use uuid::Uuid;
 
 use crate::common::{Function, Type};
 use crate::container::ContainerInfo;
-use crate::version::Version;
+use crate::errors::EnvironmentError;
+use crate::version::{Version, VersionConstraint};
 
 
 /***** CUSTOM TYPES *****/
@@ -138,6 +139,10 @@ pub enum PackageIndexError{
     DuplicatePackage{ name: String, version: String },
     /// Could not parse a version string as one
     IllegalVersion{ package: String, raw: String, err: semver::Error },
+    /// No version of the given package is known
+    UnknownPackage{ name: String },
+    /// Versions of the given package are known, but none of them satisfies the constraint
+    NoMatchingVersion{ name: String, constraint: VersionConstraint, available: Vec<Version> },
 
     /// We could not do a request to some server to get a JSON file
     RequestFailed{ url: String, err: reqwest::Error },
@@ -159,6 +164,8 @@ impl std::fmt::Display for PackageIndexError {
         match self {
             PackageIndexError::DuplicatePackage{ name, version }   => write!(f, "Encountered duplicate version {} of package '{}'", version, name),
             PackageIndexError::IllegalVersion{ package, raw, err } => write!(f, "Could not parse version string '{}' in package.yml of package '{}' to a Version: {}", raw, package, err),
+            PackageIndexError::UnknownPackage{ name }              => write!(f, "Unknown package '{}'", name),
+            PackageIndexError::NoMatchingVersion{ name, constraint, available } => write!(f, "No version of package '{}' satisfies '{}' (available: {})", name, constraint, available.iter().map(|version| version.to_string()).collect::<Vec<String>>().join(", ")),
 
             PackageIndexError::RequestFailed{ url, err }     => write!(f, "Could not send a request to '{}': {}", url, err),
             PackageIndexError::ResponseNot200{ url, status } => write!(f, "Request sent to '{}' returned status {}", url, status),
@@ -263,6 +270,166 @@ impl std::fmt::Display for PackageKind {
 
 
 
+/// Defines a dependency of a package on (a range of versions of) another package.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PackageDependency {
+    /// The name of the package we depend on.
+    pub name        : String,
+    /// The versions of the package we accept, as a semver requirement (e.g., `^1.0`). Matches any version if omitted.
+    #[serde_as(as = "DisplayFromStr")]
+    #[serde(rename = "version-req", default)]
+    pub version_req : semver::VersionReq,
+}
+
+impl PackageDependency {
+    /// Returns whether the given version satisfies this dependency.
+    /// 
+    /// **Arguments**
+    ///  * `version`: The version to check. An unresolved 'latest' version never matches.
+    /// 
+    /// **Returns**  
+    /// true if the version is accepted by the version requirement, or false otherwise.
+    pub fn matches(&self, version: &Version) -> bool {
+        if version.is_latest() { return false; }
+        self.version_req.matches(&version.to_semver())
+    }
+}
+
+impl std::fmt::Display for PackageDependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.version_req)
+    }
+}
+
+
+
+/// Defines what a location needs to offer to run the jobs of a package, used to pick a location for calls that don't name one.
+#[skip_serializing_none]
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageRequirements {
+    /// Whether the jobs need a GPU.
+    #[serde(default)]
+    pub gpu        : bool,
+    /// The minimum amount of memory the jobs need, as a size (e.g., '4GiB').
+    #[serde(alias = "min_memory")]
+    pub min_memory : Option<String>,
+    /// The operating system the jobs need (e.g., 'linux').
+    pub os         : Option<String>,
+    /// The CPU architecture the jobs need (e.g., 'amd64').
+    pub arch       : Option<String>,
+}
+
+impl PackageRequirements {
+    /// Returns whether there are no requirements at all.
+    #[inline]
+    pub fn is_empty(&self) -> bool { !self.gpu && self.min_memory.is_none() && self.os.is_none() && self.arch.is_none() }
+}
+
+impl std::fmt::Display for PackageRequirements {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() { return write!(f, "no requirements"); }
+
+        let mut parts: Vec<String> = vec![];
+        if self.gpu { parts.push(String::from("gpu")); }
+        if let Some(min_memory) = &self.min_memory { parts.push(format!("min_memory {}", min_memory)); }
+        if let Some(os) = &self.os { parts.push(format!("os {}", os)); }
+        if let Some(arch) = &self.arch { parts.push(format!("arch {}", arch)); }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+
+
+/// The prefix of the environment variables that Brane sets itself, which packages may not declare and scripts may not set.
+pub const RESERVED_ENVIRONMENT_PREFIX: &str = "BRANE_";
+
+/// Declares an environment variable of a package, as done in the `environment` section of its container.yml.
+/// 
+/// Variables are either given a fixed value (`NAME: value`, which is baked into the image), or declared with an optional default and whether scripts may set them at call time (`with_env()`).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum EnvironmentVariable {
+    /// The variable always has the given value.
+    Fixed(String),
+    /// The variable has the given default (if any), and scripts may set it if it is configurable.
+    Declared {
+        /// The value that the variable has if the script does not set it.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        default      : Option<String>,
+        /// Whether scripts may set the variable at call time.
+        #[serde(default)]
+        configurable : bool,
+    },
+}
+
+impl EnvironmentVariable {
+    /// Returns the value that the variable has if the script does not set it, if any.
+    #[inline]
+    pub fn default_value(&self) -> Option<&str> {
+        match self {
+            EnvironmentVariable::Fixed(value)            => Some(value),
+            EnvironmentVariable::Declared{ default, .. } => default.as_deref(),
+        }
+    }
+
+    /// Returns whether scripts may set the variable at call time.
+    #[inline]
+    pub fn is_configurable(&self) -> bool { matches!(self, EnvironmentVariable::Declared{ configurable: true, .. }) }
+}
+
+/// Checks that the given environment variables may be set for a call to a function of the given package.
+/// 
+/// **Arguments**
+///  * `package`: The name of the package.
+///  * `declared`: The environment variables that the package declares.
+///  * `env`: The variables to set, by name.
+/// 
+/// **Returns**  
+/// Nothing if every variable is declared by the package as configurable, or an EnvironmentError for the first (by name) that isn't otherwise.
+pub fn check_environment(package: &str, declared: &Map<EnvironmentVariable>, env: &Map<String>) -> Result<(), EnvironmentError> {
+    let mut names: Vec<&String> = env.keys().collect();
+    names.sort();
+    for name in names {
+        if name.starts_with(RESERVED_ENVIRONMENT_PREFIX) { return Err(EnvironmentError::Reserved{ name: name.clone() }); }
+        match declared.get(name) {
+            Some(variable) if variable.is_configurable() => {},
+            Some(_) => { return Err(EnvironmentError::NotConfigurable{ package: package.to_string(), name: name.clone() }); },
+            None    => {
+                let mut configurable: Vec<String> = declared.iter().filter(|(_, variable)| variable.is_configurable()).map(|(name, _)| name.clone()).collect();
+                configurable.sort();
+                return Err(EnvironmentError::Undeclared{ package: package.to_string(), name: name.clone(), configurable });
+            },
+        }
+    }
+    Ok(())
+}
+
+
+
+/// Records how a package image was built reproducibly (see `brane build --reproducible`), so that someone else can rebuild it and compare.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReproducibleBuild {
+    /// The SOURCE_DATE_EPOCH that all timestamps in the image were set to.
+    pub source_date_epoch : u64,
+    /// The SHA-256 hashes of the files in the build context (e.g., 'Dockerfile' or 'container/branelet'), by their path in the package directory.
+    #[serde(default)]
+    pub inputs            : std::collections::BTreeMap<String, String>,
+}
+
+/// Records which init binary (branelet) was put in the package image for one of the architectures it was built for.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct InitBinary {
+    /// The architecture (e.g., 'amd64' or 'arm64') that the binary was chosen for.
+    pub arch   : String,
+    /// The SHA-256 hash of the binary (as `sha256:<hex>`).
+    pub sha256 : String,
+}
+
+
+
 /// The PackageInfo struct, which might be used alongside a Docker container to define its metadata.
 #[skip_serializing_none]
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -292,6 +459,25 @@ pub struct PackageInfo {
     pub functions : Map<Function>,
     /// The types that this package adds.
     pub types     : Map<Type>,
+
+    /// The other packages that this package depends on.
+    #[serde(default)]
+    pub dependencies : Vec<PackageDependency>,
+    /// The platforms (e.g., 'linux/amd64') that the package image was built for. Empty if unknown (e.g., for packages built before this was recorded).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub platforms    : Vec<String>,
+    /// What a location needs to offer to run the jobs of this package.
+    #[serde(default, skip_serializing_if = "PackageRequirements::is_empty")]
+    pub requirements : PackageRequirements,
+    /// How the package image was built reproducibly, or None if it was a regular build.
+    #[serde(default)]
+    pub reproducible : Option<ReproducibleBuild>,
+    /// The init binaries (branelets) in the package image, one for every architecture it was built for. Empty if unknown (e.g., for packages built before this was recorded).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub init         : Vec<InitBinary>,
+    /// The environment variables that the package declares, which scripts may set at call time if they are configurable.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub environment  : Map<EnvironmentVariable>,
 }
 
 #[allow(unused)]
@@ -307,6 +493,7 @@ impl PackageInfo {
     ///  * `detached`: Whether or not the functions in this package run detached (i.e., asynchronous).
     ///  * `functions`: The functions that this package supports.
     ///  * `types`: The types that this package adds.
+    ///  * `dependencies`: The other packages that this package depends on.
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         name: String,
@@ -317,6 +504,7 @@ impl PackageInfo {
         detached: bool,
         functions: Map<Function>,
         types: Map<Type>,
+        dependencies: Vec<PackageDependency>,
     ) -> PackageInfo {
         // Generate new ID & note the time
         let id = Uuid::new_v4();
@@ -337,6 +525,13 @@ impl PackageInfo {
             detached,
             functions,
             types,
+
+            dependencies,
+            platforms    : vec![],
+            requirements : PackageRequirements::default(),
+            reproducible : None,
+            init         : vec![],
+            environment  : Map::new(),
         }
     }
 
@@ -506,24 +701,21 @@ impl From<ContainerInfo> for PackageInfo {
         // Construct Function descriptions from the Actions
         let mut functions = Map::<Function>::with_capacity(container.actions.len());
         for (action_name, action) in container.actions {
-            // Get the return values of the function
-            let function_output = action.output.unwrap_or_default();
-
-            // Wrap that in the three parameters needed for a function
+            // Wrap the action in the three parameters needed for a function
+            let return_type = action.return_type();
             let arguments = action.input.unwrap_or_default();
             let pattern = action.pattern;
-            let return_type = match function_output.first() {
-                Some(output) => output.data_type.to_string(),
-                None         => String::from("unit"),
-            };
 
             // Save the function under the original name
-            let function = Function::new(arguments, pattern, return_type);
+            let mut function = Function::new(arguments, pattern, return_type);
+            function.description = action.description;
+            function.pure = action.pure.unwrap_or(false);
+            function.concurrency = action.concurrency;
             functions.insert(action_name, function);
         }
 
         // Put it an other values in the new instance
-        PackageInfo::new(
+        let mut package = PackageInfo::new(
             container.name,
             container.version,
             container.kind,
@@ -532,7 +724,11 @@ impl From<ContainerInfo> for PackageInfo {
             container.entrypoint.kind == *"service",
             functions,
             container.types.unwrap_or_default(),
-        )
+            container.package_dependencies.unwrap_or_default(),
+        );
+        package.requirements = container.requirements.unwrap_or_default();
+        package.environment = container.environment.unwrap_or_default();
+        package
     }
 }
 
@@ -541,24 +737,21 @@ impl From<&ContainerInfo> for PackageInfo {
         // Construct Function descriptions from the Actions
         let mut functions = Map::<Function>::with_capacity(container.actions.len());
         for (action_name, action) in &container.actions {
-            // Get the return values of the function
-            let function_output = action.output.clone().unwrap_or_default();
-
-            // Wrap that in the three parameters needed for a function
+            // Wrap the action in the three parameters needed for a function
+            let return_type = action.return_type();
             let arguments = action.input.clone().unwrap_or_default();
             let pattern = action.pattern.clone();
-            let return_type = match function_output.first() {
-                Some(output) => output.data_type.to_string(),
-                None         => String::from("unit"),
-            };
 
             // Save the function under the original name
-            let function = Function::new(arguments, pattern, return_type);
+            let mut function = Function::new(arguments, pattern, return_type);
+            function.description = action.description.clone();
+            function.pure = action.pure.unwrap_or(false);
+            function.concurrency = action.concurrency;
             functions.insert(action_name.clone(), function);
         }
 
         // Put it and other clones in the new instance
-        PackageInfo::new(
+        let mut package = PackageInfo::new(
             container.name.clone(),
             container.version.clone(),
             container.kind,
@@ -576,7 +769,14 @@ impl From<&ContainerInfo> for PackageInfo {
                 Some(types) => types.clone(),
                 None        => Map::new(),
             },
-        )
+            match container.package_dependencies.as_ref() {
+                Some(dependencies) => dependencies.clone(),
+                None               => Vec::new(),
+            },
+        );
+        package.requirements = container.requirements.clone().unwrap_or_default();
+        package.environment = container.environment.clone().unwrap_or_default();
+        package
     }
 }
 
@@ -587,8 +787,8 @@ impl From<&ContainerInfo> for PackageInfo {
 pub struct PackageIndex {
     /// The list of packages stored in the index.
     pub packages : Map<PackageInfo>,
-    /// Cache of the standard 'latest' packages so we won't have to search every time.
-    pub latest   : Map<(Version, String)>,
+    /// The versions of every package (from oldest to newest), by the name of the package, so lookups by name don't have to search all packages.
+    pub by_name  : Map<Vec<Version>>,
 }
 
 impl PackageIndex {
@@ -603,28 +803,17 @@ impl PackageIndex {
     /// **Arguments**
     ///  * `packages`: The map of packages to base this index on. Each key should be <name>-<version> (i.e., every package version is a separate entry).
     pub fn new(packages: Map<PackageInfo>) -> Self {
-        // Compute the latest versions for each package
-        let mut latest: Map<(Version, String)> = Map::with_capacity(packages.len());
-        for (key, package) in packages.iter() {
-            // Check if the package name has already been added
-            if !latest.contains_key(&package.name) {
-                latest.insert(package.name.clone(), (package.version.clone(), key.clone()));
-                continue;
-            }
-
-            // Check if the existing version is later or not
-            let latest_package: &mut (Version, String) = latest.get_mut(&package.name).unwrap();
-            if package.version >= latest_package.0 {
-                // It is; update the version to point to the latest version of this package
-                latest_package.0 = package.version.clone();
-                latest_package.1 = key.clone();
-            }
+        // Collect the versions of each package
+        let mut by_name: Map<Vec<Version>> = Map::with_capacity(packages.len());
+        for package in packages.values() {
+            by_name.entry(package.name.clone()).or_default().push(package.version.clone());
         }
+        for versions in by_name.values_mut() { versions.sort(); }
 
-        // Create the index with the packages and the latest version cache
+        // Create the index with the packages and their versions
         PackageIndex {
             packages,
-            latest,
+            by_name,
         }
     }
 
@@ -757,31 +946,104 @@ impl PackageIndex {
         name: &str,
         version: Option<&Version>,
     ) -> Option<&PackageInfo> {
-        // Resolve the package version
-        let version = match version {
-            Some(version) => version,
-            None          => match self.get_latest_version(name) {
-                Some(version) => version,
-                None          => { return None; }
-            },
-        };
+        let constraint = version.cloned().map(VersionConstraint::from).unwrap_or_default();
+        self.resolve(name, &constraint).ok()
+    }
 
-        // Try to return the package info matching to this name/version pair
-        self.packages.get(&format!("{}-{}", name, version))
+    /// Returns the latest version of the given package that satisfies the given constraint.
+    /// 
+    /// **Arguments**
+    ///  * `name`: The name of the package.
+    ///  * `constraint`: The VersionConstraint that the version must satisfy.
+    /// 
+    /// **Returns**  
+    /// An (immuteable) reference to the selected package, or a PackageIndexError if the package is unknown or none of its versions satisfies the constraint (which lists the versions there are).
+    pub fn resolve(
+        &self,
+        name: &str,
+        constraint: &VersionConstraint,
+    ) -> Result<&PackageInfo, PackageIndexError> {
+        let versions = self.versions(name);
+        if versions.is_empty() { return Err(PackageIndexError::UnknownPackage{ name: name.to_string() }); }
+
+        match constraint.best_match(versions.iter().copied()) {
+            Some(version) => self.packages.get(&format!("{}-{}", name, version)).ok_or_else(|| PackageIndexError::UnknownPackage{ name: name.to_string() }),
+            None          => Err(PackageIndexError::NoMatchingVersion{ name: name.to_string(), constraint: constraint.clone(), available: versions.into_iter().cloned().collect() }),
+        }
     }
 
-    /// Returns the latest version of the given package.
+    /// Returns the names of the packages that are known to this index.
+    /// 
+    /// **Returns**  
+    /// The names, sorted alphabetically.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.by_name.keys().map(|name| name.as_str()).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Returns the versions of the given package that are known to this index.
     /// 
     /// **Arguments**
     ///  * `name`: The name of the package.
     /// 
     /// **Returns**  
-    /// An (immuteable) reference to the version if this package if known, or else None.
-    #[inline]
-    fn get_latest_version(
+    /// The versions, from oldest to newest (or an empty list if the package is unknown).
+    pub fn versions(
+        &self,
+        name: &str,
+    ) -> Vec<&Version> {
+        self.by_name.get(name).map(|versions| versions.iter().collect()).unwrap_or_default()
+    }
+
+    /// Returns the latest version of the given package that satisfies the given dependency.
+    /// 
+    /// **Arguments**
+    ///  * `dependency`: The dependency to find a package for.
+    /// 
+    /// **Returns**  
+    /// An (immuteable) reference to the matching package if there is one, or else None.
+    pub fn get_matching(
+        &self,
+        dependency: &PackageDependency,
+    ) -> Option<&PackageInfo> {
+        let version = self.versions(&dependency.name).into_iter().rev().find(|version| dependency.matches(version))?;
+        self.packages.get(&format!("{}-{}", dependency.name, version))
+    }
+
+    /// Returns the dependencies of the given package that cannot be satisfied by this index.
+    /// 
+    /// **Arguments**
+    ///  * `package`: The package to check the dependencies of.
+    /// 
+    /// **Returns**  
+    /// The list of dependencies for which no matching package is known. If empty, all dependencies are satisfied.
+    pub fn missing_dependencies<'a>(
+        &self,
+        package: &'a PackageInfo,
+    ) -> Vec<&'a PackageDependency> {
+        package.dependencies
+            .iter()
+            .filter(|dependency| self.get_matching(dependency).is_none())
+            .collect()
+    }
+
+    /// Returns the packages in this index that depend on the package with the given name.
+    /// 
+    /// **Arguments**
+    ///  * `name`: The name of the package to find the dependents of.
+    /// 
+    /// **Returns**  
+    /// The list of packages that declare a dependency on the given package (in any version).
+    pub fn dependents(
         &self,
         name: &str,
-    ) -> Option<&Version> {
-        self.latest.get(name).map(|(version, _)| version)
+    ) -> Vec<&PackageInfo> {
+        let mut dependents: Vec<&PackageInfo> = self.packages
+            .values()
+            .filter(|package| package.dependencies.iter().any(|dependency| dependency.name == name))
+            .collect();
+        dependents.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name).then(lhs.version.cmp(&rhs.version)));
+        dependents
     }
 }
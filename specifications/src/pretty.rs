@@ -0,0 +1,347 @@
+/* PRETTY.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 23:12:40
+ * Last edited:
+ *   15 Oct 2026, 23:12:40
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Renders Values for humans to read (e.g., by the print() builtin or
+ *   when showing the result of a script).
+ *
+ *   There are two layouts:
+ *    - The indented layout puts every entry of an array, map or struct
+ *      on its own line, unless it only has plain entries and fits on a
+ *      single line.
+ *    - The compact layout always uses a single line.
+ *   Both sort the fields of maps and structs by name, quote nested
+ *   strings and cut arrays off after a maximum number of entries. A
+ *   string on its own is shown as-is.
+**/
+
+use std::collections::HashMap;
+
+use crate::common::Value;
+
+
+/***** CONSTANTS *****/
+/// The number of columns that the indented layout tries to stay within.
+pub const DEFAULT_WIDTH: usize = 80;
+/// The number of entries of an array that are shown before it is cut off.
+pub const DEFAULT_MAX_ENTRIES: usize = 100;
+/// What every level of the indented layout is indented with.
+const INDENT: &str = "  ";
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Returns a Point struct.
+    fn point(x: i64, y: i64) -> Value {
+        Value::Struct{ data_type: String::from("Point"), properties: vec![ (String::from("y"), Value::Integer(y)), (String::from("x"), Value::Integer(x)) ].into_iter().collect() }
+    }
+
+    /// Returns an array of Points with the given number of entries.
+    fn points(n: i64) -> Value {
+        Value::Array{ data_type: String::from("Point[]"), entries: (0..n).map(|i| point(i, -i)).collect() }
+    }
+
+    #[test]
+    fn test_plain_values() {
+        assert_eq!(pretty(&Value::Integer(42)), "42");
+        assert_eq!(pretty(&Value::Real(1.5)), "1.5");
+        assert_eq!(pretty(&Value::Boolean(true)), "true");
+        assert_eq!(pretty(&Value::Unit), "unit");
+        assert_eq!(pretty(&Value::Unicode(String::from("hello\nworld"))), "hello\nworld");
+        assert_eq!(compact(&Value::Unicode(String::from("hello"))), "hello");
+    }
+
+    #[test]
+    fn test_small_values_stay_on_one_line() {
+        assert_eq!(pretty(&point(1, 2)), "Point {x: 1, y: 2}");
+        assert_eq!(pretty(&Value::from_json(&json!([ 1, "two", null ]))), "[1, \"two\", unit]");
+        assert_eq!(pretty(&Value::from_json(&json!([]))), "[]");
+        assert_eq!(pretty(&Value::Map(Default::default())), "{}");
+    }
+
+    #[test]
+    fn test_nested_values_are_indented() {
+        let mut line = Value::from_json(&json!({ "name": "diagonal", "tags": [], "meta": { "author": "alice", "version": 2 } }));
+        if let Value::Struct{ properties, .. } = &mut line { properties.insert(String::from("points"), points(2)); }
+        assert_eq!(pretty(&line), "\
+anonymous {
+  meta: anonymous {author: \"alice\", version: 2},
+  name: \"diagonal\",
+  points: [
+    Point {x: 0, y: 0},
+    Point {x: 1, y: -1}
+  ],
+  tags: []
+}");
+        assert_eq!(compact(&line), "anonymous {meta: anonymous {author: \"alice\", version: 2}, name: \"diagonal\", points: [Point {x: 0, y: 0}, Point {x: 1, y: -1}], tags: []}");
+    }
+
+    #[test]
+    fn test_maps_are_sorted_and_quoted() {
+        let mut map = HashMap::new();
+        map.insert(String::from("b"), Value::from_json(&json!([ [ 1, 2 ], [ 3 ] ])));
+        map.insert(String::from("a"), Value::Unicode(String::from("x")));
+        assert_eq!(pretty(&Value::Map(map)), "\
+{
+  \"a\": \"x\",
+  \"b\": [
+    [1, 2],
+    [3]
+  ]
+}");
+    }
+
+    #[test]
+    fn test_long_lines_are_broken() {
+        let words = Value::Array{ data_type: String::from("string[]"), entries: (0..12).map(|i| Value::Unicode(format!("word{}", i))).collect() };
+        let text = pretty(&words);
+        assert_eq!(text.lines().count(), 14);
+        assert!(text.lines().all(|line| line.len() <= DEFAULT_WIDTH));
+        assert_eq!(text.lines().nth(1), Some("  \"word0\","));
+        assert_eq!(compact(&words).lines().count(), 1);
+    }
+
+    #[test]
+    fn test_huge_arrays_are_cut_off() {
+        let printer = Printer{ max_entries: 2, ..Default::default() };
+        assert_eq!(printer.pretty(&points(5)), "\
+[
+  Point {x: 0, y: 0},
+  Point {x: 1, y: -1},
+  ... (3 more)
+]");
+        assert_eq!(printer.compact(&Value::from_json(&json!([ 1, 2, 3 ]))), "[1, 2, ... (1 more)]");
+        assert_eq!(printer.compact(&Value::from_json(&json!([ 1, 2 ]))), "[1, 2]");
+    }
+
+    #[test]
+    fn test_echo_picks_the_layout() {
+        assert_eq!(echo(&points(2)), "[Point {x: 0, y: 0}, Point {x: 1, y: -1}]");
+        assert_eq!(echo(&points(5)), pretty(&points(5)));
+    }
+}
+
+
+
+
+
+/***** HELPER STRUCTS *****/
+/// The (named) entries of an array, map or struct, as they are rendered.
+struct Entries<'a> {
+    /// The opening bracket (including the type name of a struct).
+    open  : String,
+    /// The closing bracket.
+    close : &'static str,
+    /// The entries that are shown, as (prefix, value) pairs and sorted by name.
+    shown : Vec<(String, &'a Value)>,
+    /// The number of entries that were cut off.
+    more  : usize,
+}
+
+impl<'a> Entries<'a> {
+    /// Collects the entries of the given Value, cutting arrays off after `max_entries`.
+    /// 
+    /// **Returns**  
+    /// The Entries, or None if the Value is not an array, map or struct.
+    fn of(value: &'a Value, max_entries: usize) -> Option<Self> {
+        match value {
+            Value::Array{ entries, .. } => Some(Self {
+                open  : String::from("["),
+                close : "]",
+                shown : entries.iter().take(max_entries).map(|value| (String::new(), value)).collect(),
+                more  : entries.len().saturating_sub(max_entries),
+            }),
+            Value::Map(properties) => Some(Self {
+                open  : String::from("{"),
+                close : "}",
+                shown : sorted(properties).into_iter().map(|(name, value)| (format!("{:?}: ", name), value)).collect(),
+                more  : 0,
+            }),
+            Value::Struct{ data_type, properties } => Some(Self {
+                open  : format!("{} {{", data_type),
+                close : "}",
+                shown : sorted(properties).into_iter().map(|(name, value)| (format!("{}: ", name), value)).collect(),
+                more  : 0,
+            }),
+            _ => None,
+        }
+    }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// Renders Values with a configurable width and array cut-off.
+#[derive(Clone, Debug)]
+pub struct Printer {
+    /// The number of columns that the indented layout tries to stay within.
+    pub width       : usize,
+    /// The number of entries of an array that are shown before the rest is summarized as '... (N more)'.
+    pub max_entries : usize,
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Self {
+            width       : DEFAULT_WIDTH,
+            max_entries : DEFAULT_MAX_ENTRIES,
+        }
+    }
+}
+
+impl Printer {
+    /// Renders the given Value in the indented layout.
+    /// 
+    /// **Arguments**
+    ///  * `value`: The Value to render.
+    /// 
+    /// **Returns**  
+    /// The (possibly multi-line) text of the Value.
+    pub fn pretty(&self, value: &Value) -> String {
+        match value {
+            Value::Unicode(text) => text.clone(),
+            value                => self.indented(value, 0, 0),
+        }
+    }
+
+    /// Renders the given Value in the compact layout.
+    /// 
+    /// **Arguments**
+    ///  * `value`: The Value to render.
+    /// 
+    /// **Returns**  
+    /// The text of the Value on a single line (unless it's a string with newlines in it).
+    pub fn compact(&self, value: &Value) -> String {
+        match value {
+            Value::Unicode(text) => text.clone(),
+            value                => self.single_line(value),
+        }
+    }
+
+    /// Renders the given Value in the compact layout if that fits within the width, or in the indented layout otherwise.
+    /// 
+    /// **Arguments**
+    ///  * `value`: The Value to render.
+    /// 
+    /// **Returns**  
+    /// The text of the Value.
+    pub fn echo(&self, value: &Value) -> String {
+        let text = self.compact(value);
+        if text.len() <= self.width { text } else { self.pretty(value) }
+    }
+
+
+
+    /// Renders a Value on a single line.
+    fn single_line(&self, value: &Value) -> String {
+        match Entries::of(value, self.max_entries) {
+            Some(entries) => {
+                let mut parts: Vec<String> = entries.shown.into_iter().map(|(name, value)| format!("{}{}", name, self.single_line(value))).collect();
+                if entries.more > 0 { parts.push(format!("... ({} more)", entries.more)); }
+                format!("{}{}{}", entries.open, parts.join(", "), entries.close)
+            },
+            None => plain(value),
+        }
+    }
+
+    /// Renders a Value in the indented layout, where it is nested `level` deep and starts at the given column.
+    fn indented(&self, value: &Value, level: usize, column: usize) -> String {
+        let entries = match Entries::of(value, self.max_entries) {
+            Some(entries) => entries,
+            None          => { return plain(value); }
+        };
+
+        // Keep it on one line if it has nothing to indent
+        if entries.shown.iter().all(|(_, value)| is_flat(value)) {
+            let line = self.single_line(value);
+            if column + line.len() <= self.width { return line; }
+        }
+
+        let indent = INDENT.repeat(level + 1);
+        let mut lines: Vec<String> = entries.shown.into_iter().map(|(name, value)| {
+            format!("{}{}{}", indent, name, self.indented(value, level + 1, indent.len() + name.len()))
+        }).collect();
+        if entries.more > 0 { lines.push(format!("{}... ({} more)", indent, entries.more)); }
+        format!("{}\n{}\n{}{}", entries.open, lines.join(",\n"), INDENT.repeat(level), entries.close)
+    }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Renders the given Value in the indented layout, with the default width and array cut-off.
+/// 
+/// **Arguments**
+///  * `value`: The Value to render.
+/// 
+/// **Returns**  
+/// The (possibly multi-line) text of the Value.
+#[inline]
+pub fn pretty(value: &Value) -> String { Printer::default().pretty(value) }
+
+/// Renders the given Value in the compact layout, with the default array cut-off.
+/// 
+/// **Arguments**
+///  * `value`: The Value to render.
+/// 
+/// **Returns**  
+/// The text of the Value on a single line (unless it's a string with newlines in it).
+#[inline]
+pub fn compact(value: &Value) -> String { Printer::default().compact(value) }
+
+/// Renders the given Value in the compact layout if it fits within the default width, or in the indented layout otherwise (e.g., for a REPL).
+/// 
+/// **Arguments**
+///  * `value`: The Value to render.
+/// 
+/// **Returns**  
+/// The text of the Value.
+#[inline]
+pub fn echo(value: &Value) -> String { Printer::default().echo(value) }
+
+
+
+/// Returns the fields of a map or struct, sorted by name.
+#[inline]
+fn sorted(properties: &HashMap<String, Value>) -> Vec<(&String, &Value)> {
+    let mut properties: Vec<(&String, &Value)> = properties.iter().collect();
+    properties.sort_by(|lhs, rhs| lhs.0.cmp(rhs.0));
+    properties
+}
+
+/// Returns whether the given Value needs no indentation (i.e., it has no entries).
+#[inline]
+fn is_flat(value: &Value) -> bool {
+    match value {
+        Value::Array{ entries, .. } => entries.is_empty(),
+        Value::Map(properties) | Value::Struct{ properties, .. } => properties.is_empty(),
+        _ => true,
+    }
+}
+
+/// Renders a Value without entries, quoting strings so they can't be confused with other values.
+#[inline]
+fn plain(value: &Value) -> String {
+    match value {
+        Value::Unicode(text) => format!("{:?}", text),
+        value                => value.to_string(),
+    }
+}
@@ -4,7 +4,7 @@
  * Created:
  *   08 May 2022, 13:57:01
  * Last edited:
- *   08 May 2022, 14:07:07
+ *   15 Oct 2026, 21:52:40
  * Auto updated?
  *   Yes
  *
@@ -17,12 +17,18 @@ use std::error::Error;
 use std::io::ErrorKind;
 use std::fmt::{Display, Formatter, Result as FResult};
 use std::fs::File;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
 
+/***** CONSTANTS *****/
+/// The platform that packages run on if the registry config does not say otherwise.
+pub const DEFAULT_CLUSTER_PLATFORM: &str = "linux/amd64";
+
+
 /***** ERRORS *****/
 /// Defines possible errors when loading a RegistryConfig file.
 #[derive(Debug)]
@@ -33,6 +39,10 @@ pub enum RegistryConfigError {
     FileOpenError{ path: PathBuf, err: std::io::Error },
     /// Could not parse the given file.
     FileParseError{ path: PathBuf, err: serde_yaml::Error },
+    /// Could not serialize the config.
+    SerializeError{ err: serde_yaml::Error },
+    /// Could not write the given file.
+    FileWriteError{ path: PathBuf, err: std::io::Error },
 }
 
 impl Display for RegistryConfigError {
@@ -42,6 +52,8 @@ impl Display for RegistryConfigError {
             NotLoggedIn{ path }         => write!(f, "You are not logged in; run the 'login' subcommand first (or registry file '{}' is missing)", path.display()),
             FileOpenError{ path, err }  => write!(f, "Could not open registry file '{}': {}", path.display(), err),
             FileParseError{ path, err } => write!(f, "Could not parse registry file '{}': {}", path.display(), err),
+            SerializeError{ err }       => write!(f, "Could not serialize registry file: {}", err),
+            FileWriteError{ path, err } => write!(f, "Could not write registry file '{}': {}", path.display(), err),
         }
     }
 }
@@ -60,8 +72,37 @@ impl Error for RegistryConfigError {}
 pub struct RegistryConfig {
     /// The endpoint of the remote registry.
     pub url: String,
-    /// The username with which we sign packages.
-    pub username: String,
+    /// The username with which we sign packages. Only stored here if the credentials are kept in plaintext (see `insecure_store`), or by older brane-cli versions.
+    pub username: Option<String>,
+    /// The token with which we authenticate to the registry. Only stored here if the credentials are kept in plaintext.
+    pub token: Option<String>,
+    /// If true, the user explicitly chose to keep the credentials in this file instead of the OS keyring.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub insecure_store: bool,
+    /// If true, the registry is a standard OCI registry (e.g., Harbor or GHCR) instead of a Brane registry, and `url` may include the namespace to keep packages in.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub oci: bool,
+    /// The platform (e.g., 'linux/arm64') that the default cluster of the registry runs packages on, if not DEFAULT_CLUSTER_PLATFORM.
+    pub platform: Option<String>,
+    /// How to refresh the token, if we got it through OpenID Connect (see `brane login --oidc`). Only stored here if the credentials are kept in plaintext.
+    pub oidc: Option<OidcSession>,
+}
+
+
+
+/// Remembers how to get a new access token from the OpenID Connect provider that gave us the current one.
+#[skip_serializing_none]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OidcSession {
+    /// The token endpoint of the provider.
+    pub token_endpoint: String,
+    /// The client ID with which we logged in.
+    pub client_id: String,
+    /// The refresh token, if the provider gave us one.
+    pub refresh_token: Option<String>,
+    /// When the access token expires, as seconds since the Unix epoch (if the provider told us).
+    pub expires_at: Option<i64>,
 }
 
 impl RegistryConfig {
@@ -91,4 +132,28 @@ impl RegistryConfig {
             Err(err)   => Err(RegistryConfigError::FileParseError{ path: path.to_path_buf(), err }),
         }
     }
+
+    /// Writes the RegistryConfig to the given file, overwriting it if it exists.
+    /// 
+    /// # Arguments
+    /// - `path`: The Path to the file to write.
+    /// 
+    /// # Returns
+    /// Nothing on success, or else a RegistryConfigError.
+    pub fn to_path(&self, path: &Path) -> Result<(), RegistryConfigError> {
+        let contents = match serde_yaml::to_string(self) {
+            Ok(contents) => contents,
+            Err(err)     => { return Err(RegistryConfigError::SerializeError{ err }); }
+        };
+        match File::create(path).and_then(|mut handle| handle.write_all(contents.as_bytes())) {
+            Ok(_)    => Ok(()),
+            Err(err) => Err(RegistryConfigError::FileWriteError{ path: path.to_path_buf(), err }),
+        }
+    }
+
+    /// Returns the platform that packages pushed to this registry must have been built for.
+    #[inline]
+    pub fn platform(&self) -> &str {
+        self.platform.as_deref().unwrap_or(DEFAULT_CLUSTER_PLATFORM)
+    }
 }
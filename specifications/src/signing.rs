@@ -0,0 +1,345 @@
+/* SIGNING.rs
+ *   by Lut99
+ *
+ * Created:
+ *   15 Oct 2026, 23:59:52
+ * Last edited:
+ *   15 Oct 2026, 23:59:52
+ * Auto updated?
+ *   Yes
+ *
+ * Description:
+ *   Signs the metadata of packages with ed25519 keys and verifies such
+ *   (detached) signatures against a set of trusted keys, so that whoever
+ *   pulls a package can check that its metadata and image digest are
+ *   what its author published.
+ *
+ *   What is signed is a canonical serialization of the metadata: the
+ *   name, version, kind, digest and functions of the package, as JSON
+ *   without whitespace and with the keys of every object sorted.
+**/
+
+use std::convert::TryFrom;
+use std::fmt::Write as _;
+
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier, PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JValue};
+
+use crate::errors::SigningError;
+use crate::package::PackageInfo;
+
+
+/***** CONSTANTS *****/
+/// The algorithm that package signatures are made with.
+pub const SIGNATURE_ALGORITHM: &str = "ed25519";
+/// The number of bytes of the seed that a signing key is made from.
+pub const SEED_LENGTH: usize = SECRET_KEY_LENGTH;
+
+
+
+
+
+/***** UNIT TESTS *****/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{Function, Parameter};
+    use crate::package::PackageKind;
+    use crate::version::Version;
+
+    fn package() -> PackageInfo {
+        let mut functions = std::collections::HashMap::new();
+        functions.insert(String::from("add"), Function::new(vec![ Parameter::new(String::from("a"), String::from("integer"), None, None, None), Parameter::new(String::from("b"), String::from("integer"), None, None, None) ], None, String::from("integer")));
+        functions.insert(String::from("neg"), Function::new(vec![ Parameter::new(String::from("a"), String::from("integer"), None, None, None) ], None, String::from("integer")));
+        let mut info = PackageInfo::new(String::from("arith"), Version::new(1, 0, 0), PackageKind::Ecu, vec![ String::from("alice") ], String::from("Arithmetic"), false, functions, Default::default(), vec![]);
+        info.digest = Some(String::from("sha256:0123456789abcdef"));
+        info
+    }
+
+    fn key(seed: u8) -> SigningKey { SigningKey::from_seed(&[ seed; SEED_LENGTH ]).unwrap() }
+
+    #[test]
+    fn test_valid_signature() {
+        let info = package();
+        let signing = key(1);
+        let signature = sign_package(&info, &signing).unwrap();
+        assert_eq!(signature.algorithm, SIGNATURE_ALGORITHM);
+
+        let trusted = vec![ key(2).trusted("other"), signing.trusted("alice") ];
+        assert_eq!(verify_package(&info, &signature, &trusted).unwrap().name, "alice");
+
+        // Metadata that isn't signed (e.g., the description or owners) may change
+        let mut described = info.clone();
+        described.description = String::from("Does arithmetic");
+        described.owners.push(String::from("bob"));
+        assert!(verify_package(&described, &signature, &trusted).is_ok());
+
+        // Keys survive a round-trip through their files
+        let reread = SigningKey::from_base64(&signing.to_base64()).unwrap();
+        assert_eq!(sign_package(&info, &reread).unwrap(), signature);
+        assert!(verify_package(&info, &signature, &[ TrustedKey::from_base64("alice", &signing.trusted("alice").to_base64()).unwrap() ]).is_ok());
+    }
+
+    #[test]
+    fn test_tampered_metadata() {
+        let info = package();
+        let signing = key(1);
+        let signature = sign_package(&info, &signing).unwrap();
+        let trusted = vec![ signing.trusted("alice") ];
+
+        let mut digest = info.clone();
+        digest.digest = Some(String::from("sha256:fedcba9876543210"));
+        assert!(matches!(verify_package(&digest, &signature, &trusted), Err(SigningError::InvalidSignature{ .. })));
+
+        let mut functions = info.clone();
+        functions.functions.remove("neg");
+        assert!(matches!(verify_package(&functions, &signature, &trusted), Err(SigningError::InvalidSignature{ .. })));
+
+        let mut version = info;
+        version.version = Version::new(1, 0, 1);
+        assert!(matches!(verify_package(&version, &signature, &trusted), Err(SigningError::InvalidSignature{ .. })));
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let info = package();
+        let signature = sign_package(&info, &key(1)).unwrap();
+        assert!(matches!(verify_package(&info, &signature, &[ key(2).trusted("other") ]), Err(SigningError::UnknownKey{ .. })));
+        assert!(matches!(verify_package(&info, &signature, &[]), Err(SigningError::UnknownKey{ .. })));
+
+        let other = PackageSignature{ algorithm: String::from("rsa"), ..signature };
+        assert!(matches!(verify_package(&info, &other, &[ key(1).trusted("alice") ]), Err(SigningError::UnsupportedAlgorithm{ .. })));
+    }
+
+    #[test]
+    fn test_canonical_form() {
+        assert_eq!(canonical_json(&json!({ "b": [ 1, "two", null ], "a": { "d": true, "c": 1.5 } })), r#"{"a":{"c":1.5,"d":true},"b":[1,"two",null]}"#);
+
+        // The order in which functions were added doesn't matter
+        let info = package();
+        let mut reordered = info.clone();
+        let mut functions: Vec<(String, Function)> = info.functions.clone().into_iter().collect();
+        functions.reverse();
+        reordered.functions = functions.into_iter().collect();
+        assert_eq!(canonical_metadata(&info).unwrap(), canonical_metadata(&reordered).unwrap());
+
+        let mut undigested = info;
+        undigested.digest = None;
+        assert!(matches!(canonical_metadata(&undigested), Err(SigningError::MissingDigest{ .. })));
+    }
+}
+
+
+
+
+
+/***** LIBRARY STRUCTS *****/
+/// The detached signature over the metadata of a package (see `canonical_metadata()`).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PackageSignature {
+    /// The algorithm that made the signature (always `SIGNATURE_ALGORITHM`, for now).
+    pub algorithm  : String,
+    /// The public key that belongs to the key that made the signature, base64-encoded.
+    pub public_key : String,
+    /// The signature itself, base64-encoded.
+    pub signature  : String,
+}
+
+
+
+/// A key to sign packages with.
+pub struct SigningKey {
+    /// The key pair itself.
+    keypair : Keypair,
+}
+
+impl SigningKey {
+    /// Constructor for the SigningKey, which derives the key from the given seed.
+    /// 
+    /// **Arguments**
+    ///  * `seed`: The `SEED_LENGTH` (random) bytes to derive the key from.
+    /// 
+    /// **Returns**  
+    /// The new SigningKey, or a SigningError if the seed has the wrong length.
+    pub fn from_seed(seed: &[u8]) -> Result<Self, SigningError> {
+        let secret = SecretKey::from_bytes(seed).map_err(|err| SigningError::IllegalKey{ err: format!("{}", err) })?;
+        let public = PublicKey::from(&secret);
+        Ok(Self { keypair: Keypair{ secret, public } })
+    }
+
+    /// Constructor for the SigningKey, which reads its seed from the contents of a key file.
+    /// 
+    /// **Arguments**
+    ///  * `raw`: The base64-encoded seed of the key (see `SigningKey::to_base64()`).
+    /// 
+    /// **Returns**  
+    /// The new SigningKey, or a SigningError if it is not a valid key.
+    pub fn from_base64(raw: &str) -> Result<Self, SigningError> {
+        let seed = base64::decode(raw.trim()).map_err(|err| SigningError::IllegalKey{ err: format!("{}", err) })?;
+        Self::from_seed(&seed)
+    }
+
+    /// Returns the seed of this key base64-encoded, as stored in its key file.
+    #[inline]
+    pub fn to_base64(&self) -> String { base64::encode(self.keypair.secret.as_bytes()) }
+
+    /// Returns the public half of this key as a TrustedKey, i.e., as those who trust it would know it.
+    /// 
+    /// **Arguments**
+    ///  * `name`: The name to know the key by.
+    #[inline]
+    pub fn trusted(&self, name: &str) -> TrustedKey { TrustedKey{ name: name.to_string(), key: self.keypair.public } }
+}
+
+
+
+/// A public key that signatures are trusted from.
+#[derive(Clone, Debug)]
+pub struct TrustedKey {
+    /// The name of the key (e.g., of the file that it was read from), which is used when reporting who signed a package.
+    pub name : String,
+    /// The key itself.
+    key      : PublicKey,
+}
+
+impl TrustedKey {
+    /// Constructor for the TrustedKey.
+    /// 
+    /// **Arguments**
+    ///  * `name`: The name to know the key by.
+    ///  * `raw`: The base64-encoded public key.
+    /// 
+    /// **Returns**  
+    /// The new TrustedKey, or a SigningError if it is not a valid public key.
+    pub fn from_base64(name: &str, raw: &str) -> Result<Self, SigningError> {
+        let bytes = base64::decode(raw.trim()).map_err(|err| SigningError::IllegalKey{ err: format!("{}", err) })?;
+        if bytes.len() != PUBLIC_KEY_LENGTH { return Err(SigningError::IllegalKey{ err: format!("public key has {} bytes instead of {}", bytes.len(), PUBLIC_KEY_LENGTH) }); }
+        let key = PublicKey::from_bytes(&bytes).map_err(|err| SigningError::IllegalKey{ err: format!("{}", err) })?;
+        Ok(Self { name: name.to_string(), key })
+    }
+
+    /// Returns the public key base64-encoded, as stored in its file and in signatures.
+    #[inline]
+    pub fn to_base64(&self) -> String { base64::encode(self.key.as_bytes()) }
+}
+
+
+
+
+
+/***** LIBRARY FUNCTIONS *****/
+/// Returns the canonical serialization of the metadata of the given package, which is what its signature is made over.
+/// 
+/// **Arguments**
+///  * `info`: The PackageInfo of the package.
+/// 
+/// **Returns**  
+/// The name, version, kind, digest and functions of the package as canonical JSON (see `canonical_json()`), or a SigningError if the package has no digest (i.e., was never built) or could not be serialized.
+pub fn canonical_metadata(info: &PackageInfo) -> Result<String, SigningError> {
+    let digest = match &info.digest {
+        Some(digest) => digest,
+        None         => { return Err(SigningError::MissingDigest{ name: info.name.clone(), version: info.version.to_string() }); }
+    };
+    let functions = serde_json::to_value(&info.functions).map_err(|err| SigningError::SerializeError{ err })?;
+    let kind = serde_json::to_value(&info.kind).map_err(|err| SigningError::SerializeError{ err })?;
+
+    Ok(canonical_json(&json!({
+        "name"      : info.name,
+        "version"   : info.version.to_string(),
+        "kind"      : kind,
+        "digest"    : digest,
+        "functions" : functions,
+    })))
+}
+
+/// Serializes the given JSON canonically: without whitespace and with the keys of every object sorted, so that the same JSON always serializes to the same string.
+/// 
+/// **Arguments**
+///  * `value`: The JSON to serialize.
+/// 
+/// **Returns**  
+/// The canonical serialization.
+pub fn canonical_json(value: &JValue) -> String {
+    let mut result = String::new();
+    write_canonical(value, &mut result);
+    result
+}
+
+/// Signs the metadata of the given package.
+/// 
+/// **Arguments**
+///  * `info`: The PackageInfo of the package to sign.
+///  * `key`: The SigningKey to sign with.
+/// 
+/// **Returns**  
+/// The PackageSignature, or a SigningError if the package could not be canonicalized (see `canonical_metadata()`).
+pub fn sign_package(info: &PackageInfo, key: &SigningKey) -> Result<PackageSignature, SigningError> {
+    let metadata = canonical_metadata(info)?;
+    let signature = key.keypair.sign(metadata.as_bytes());
+    Ok(PackageSignature {
+        algorithm  : SIGNATURE_ALGORITHM.to_string(),
+        public_key : base64::encode(key.keypair.public.as_bytes()),
+        signature  : base64::encode(signature.to_bytes()),
+    })
+}
+
+/// Verifies the given signature over the metadata of the given package.
+/// 
+/// **Arguments**
+///  * `info`: The PackageInfo of the package, as it was received.
+///  * `signature`: The PackageSignature that came with it.
+///  * `trusted`: The keys to trust signatures from.
+/// 
+/// **Returns**  
+/// The TrustedKey that made the signature if it is valid, or a SigningError if the signature was made by a key that isn't trusted or does not match the metadata.
+pub fn verify_package<'k>(info: &PackageInfo, signature: &PackageSignature, trusted: &'k [TrustedKey]) -> Result<&'k TrustedKey, SigningError> {
+    if signature.algorithm != SIGNATURE_ALGORITHM { return Err(SigningError::UnsupportedAlgorithm{ algorithm: signature.algorithm.clone() }); }
+
+    // Only trusted keys count
+    let key = match trusted.iter().find(|key| key.to_base64() == signature.public_key.trim()) {
+        Some(key) => key,
+        None      => { return Err(SigningError::UnknownKey{ public_key: signature.public_key.clone() }); }
+    };
+
+    let bytes = base64::decode(signature.signature.trim()).map_err(|err| SigningError::IllegalSignature{ err: format!("{}", err) })?;
+    let raw = Signature::try_from(bytes.as_slice()).map_err(|err| SigningError::IllegalSignature{ err: format!("{}", err) })?;
+    let metadata = canonical_metadata(info)?;
+    match key.key.verify(metadata.as_bytes(), &raw) {
+        Ok(_)  => Ok(key),
+        Err(_) => Err(SigningError::InvalidSignature{ name: info.name.clone(), version: info.version.to_string(), key: key.name.clone() }),
+    }
+}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Writes the canonical serialization of the given JSON to the given string (see `canonical_json()`).
+fn write_canonical(value: &JValue, result: &mut String) {
+    match value {
+        JValue::Array(values) => {
+            result.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 { result.push(','); }
+                write_canonical(value, result);
+            }
+            result.push(']');
+        },
+        JValue::Object(object) => {
+            let mut keys: Vec<&String> = object.keys().collect();
+            keys.sort();
+
+            result.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 { result.push(','); }
+                let _ = write!(result, "{}:", JValue::String(key.clone()));
+                write_canonical(&object[key], result);
+            }
+            result.push('}');
+        },
+        // Scalars are serialized the way serde_json does it, which already has no whitespace
+        value => { let _ = write!(result, "{}", value); },
+    }
+}
@@ -4,13 +4,15 @@
  * Created:
  *   23 Mar 2022, 15:15:12
  * Last edited:
- *   08 May 2022, 22:47:44
+ *   16 Oct 2026, 00:00:20
  * Auto updated?
  *   Yes
  *
  * Description:
  *   Implements a new Version struct, which is like semver's Version but with
- *   support to select 'latest' versions.
+ *   support to select 'latest' versions. Also implements the
+ *   VersionConstraint, which selects a version out of a list of them
+ *   (e.g., when importing a package).
 **/
 
 use std::cmp::{Ordering};
@@ -69,6 +71,82 @@ mod tests {
         assert_eq!(Version::from_str("42.b.c"),  Err(ParseError::MinorParseError{ raw: String::from("b"), err: u64::from_str("b").unwrap_err() }));
     }
 
+    #[test]
+    fn test_parse_prerelease() {
+        // Prereleases and build metadata are kept, with or without a 'v' in front
+        for (raw, pre, build) in [
+            ("1.2.0-rc.1", Some("rc.1"), None),
+            ("v0.10.0-rc1", Some("rc1"), None),
+            ("1.2.0+build.5", None, Some("build.5")),
+            ("1.2.0-alpha.beta+exp.sha.5114f85", Some("alpha.beta"), Some("exp.sha.5114f85")),
+            ("1.2.0-x-y-z.-", Some("x-y-z.-"), None),
+            ("1.2.0+001", None, Some("001")),
+            ("1.2.0+a-b", None, Some("a-b")),
+            ("1.2-rc.1", Some("rc.1"), None),
+            ("1-0", Some("0"), None),
+        ] {
+            let version = Version::from_str(raw).unwrap_or_else(|err| panic!("Could not parse '{}': {}", raw, err));
+            assert_eq!(version.pre.as_deref(), pre, "prerelease of '{}'", raw);
+            assert_eq!(version.build.as_deref(), build, "build metadata of '{}'", raw);
+            assert_eq!(version.is_prerelease(), pre.is_some());
+
+            // They survive a round-trip through their string representation
+            let round = Version::from_str(&version.to_string()).unwrap();
+            assert_eq!(round, version);
+            assert_eq!(round.build, version.build);
+        }
+        assert_eq!(Version::from_str("v1.2.0-rc.1+b7").unwrap().to_string(), "1.2.0-rc.1+b7");
+        assert_eq!(Version::from_str("1.2-rc.1").unwrap().to_string(), "1.2.0-rc.1");
+
+        // Malformed prereleases and build metadata are errors
+        for raw in [ "1.2.0-", "1.2.0-rc..1", "1.2.0-rc.01", "1.2.0-00", "1.2.0-r_c", "1.2.0-rc.1.", "1.2.0-é" ] {
+            assert!(matches!(Version::from_str(raw), Err(ParseError::PrereleaseParseError{ .. })), "Expected '{}' to have an illegal prerelease", raw);
+        }
+        for raw in [ "1.2.0+", "1.2.0+a..b", "1.2.0+a+b", "1.2.0-rc.1+", "1.2.0+a_b" ] {
+            assert!(matches!(Version::from_str(raw), Err(ParseError::BuildParseError{ .. })), "Expected '{}' to have illegal build metadata", raw);
+        }
+        assert_eq!(Version::from_str("1.a-rc.1"), Err(ParseError::MinorParseError{ raw: String::from("a"), err: u64::from_str("a").unwrap_err() }));
+        assert!(Version::from_str("latest-rc.1").is_err());
+    }
+
+    #[test]
+    fn test_prerelease_order() {
+        // The example from the semver specification, in increasing order
+        let ordered = [
+            "1.0.0-alpha", "1.0.0-alpha.1", "1.0.0-alpha.beta", "1.0.0-beta", "1.0.0-beta.2", "1.0.0-beta.11", "1.0.0-rc.1", "1.0.0",
+            "1.0.1-0", "1.0.1-1", "1.0.1-a", "1.0.1", "1.1.0-rc.1", "1.1.0", "2.0.0-0.3.7", "2.0.0-x.7.z.92", "2.0.0",
+        ];
+        let versions: Vec<Version> = ordered.iter().map(|raw| Version::from_str(raw).unwrap()).collect();
+        for (i, lhs) in versions.iter().enumerate() {
+            for (j, rhs) in versions.iter().enumerate() {
+                assert_eq!(lhs.cmp(rhs), i.cmp(&j), "Comparing '{}' with '{}'", lhs, rhs);
+                assert_eq!(lhs == rhs, i == j, "Comparing '{}' with '{}'", lhs, rhs);
+
+                // Semver agrees
+                let semversion = semver::Version::parse(ordered[j]).unwrap();
+                assert_eq!(lhs.partial_cmp(&semversion), Some(i.cmp(&j)), "Comparing '{}' with semver '{}'", lhs, semversion);
+                assert_eq!(*lhs == semversion, i == j, "Comparing '{}' with semver '{}'", lhs, semversion);
+            }
+        }
+
+        // Build metadata plays no role
+        assert_eq!(Version::from_str("1.0.0+a").unwrap(), Version::from_str("1.0.0+b").unwrap());
+        assert_eq!(Version::from_str("1.0.0-rc.1+a").unwrap().cmp(&Version::from_str("1.0.0-rc.1").unwrap()), Ordering::Equal);
+
+        // Existing comparisons are unaffected
+        assert!(Version::from_str("19.0.0-rc.1").unwrap() < Version::new(19, 0, 0));
+        assert!(Version::from_str("20.10.17+dfsg1").unwrap() >= Version::new(19, 0, 0));
+        assert!(Version::from_str("v0.10.0-rc1").unwrap() > Version::new(0, 7, 0));
+
+        // And 'latest' resolves to the newest, even if that's a prerelease
+        let mut latest = Version::latest();
+        latest.resolve_latest(versions.iter().take(8).cloned()).unwrap();
+        assert_eq!(latest, Version::new(1, 0, 0));
+        let mut latest = Version::latest();
+        latest.resolve_latest(vec![ Version::new(1, 0, 0), Version::from_str("1.1.0-rc.1").unwrap() ]).unwrap();
+        assert_eq!(latest.to_string(), "1.1.0-rc.1");
+    }
+
     #[test]
     fn test_resolve() {
         // Create a 'latest' version
@@ -116,6 +194,14 @@ mod tests {
         assert_eq!(semversion.minor, version.minor);
         assert_eq!(semversion.patch, version.patch);
 
+        // Prereleases and build metadata carry over both ways
+        let semversion = semver::Version::parse("1.2.0-rc.1+b7").unwrap();
+        let version    = Version::from(&semversion);
+        assert_eq!(version.pre.as_deref(), Some("rc.1"));
+        assert_eq!(version.build.as_deref(), Some("b7"));
+        assert_eq!(version.to_semver(), semversion);
+        assert_eq!(Version::new(1, 2, 0).to_semver(), semver::Version::new(1, 2, 0));
+
         // Check the eq
         assert_eq!(Version::new(42, 21, 10), semver::Version::new(42, 21, 10));
         assert_ne!(Version::latest(), semver::Version::new(u64::MAX, u64::MAX, u64::MAX));
@@ -143,6 +229,9 @@ mod tests {
         assert_ser_tokens(&Version::latest(), &[
             Token::Str("latest"), 
         ]);
+        assert_ser_tokens(&Version::from_str("1.2.0-rc.1+b7").unwrap(), &[
+            Token::Str("1.2.0-rc.1+b7"), 
+        ]);
     }
 
     #[test]
@@ -157,6 +246,9 @@ mod tests {
         assert_de_tokens(&Version::latest(), &[
             Token::Str("latest"), 
         ]);
+        assert_de_tokens(&Version::from_str("1.2.0-rc.1").unwrap(), &[
+            Token::Str("1.2.0-rc.1"), 
+        ]);
 
         // Check for the same errors as test_parse()
         assert_de_tokens_error::<Version>(&[
@@ -178,6 +270,90 @@ mod tests {
             Token::Str("42.b.c"),
         ], &format!("{}", ParseError::MinorParseError{ raw: String::from("b"), err: u64::from_str("b").unwrap_err() }));
     }
+
+
+
+    #[test]
+    fn test_constraint_parse() {
+        assert_eq!(VersionConstraint::from_str("latest").unwrap(), VersionConstraint::Latest);
+        assert_eq!(VersionConstraint::from_str("").unwrap(), VersionConstraint::Latest);
+        assert_eq!(VersionConstraint::from_str("1.2.0").unwrap(), VersionConstraint::Exact(Version::new(1, 2, 0)));
+        assert_eq!(VersionConstraint::from_str("v1.2").unwrap(), VersionConstraint::Exact(Version::new(1, 2, 0)));
+        assert_eq!(VersionConstraint::from_str("^1.2").unwrap(), VersionConstraint::Range(semver::VersionReq::parse("^1.2").unwrap()));
+        assert_eq!(VersionConstraint::from_str(" >=1.2, <2 ").unwrap(), VersionConstraint::Range(semver::VersionReq::parse(">=1.2, <2").unwrap()));
+        assert!(matches!(VersionConstraint::from_str(">=a"), Err(ConstraintParseError{ raw, .. }) if raw == ">=a"));
+
+        // Constraints survive a round-trip through their string representation
+        for raw in [ "latest", "1.2.0", "^1.2", ">=1.2, <2", "~0.3" ] {
+            let constraint = VersionConstraint::from_str(raw).unwrap();
+            assert_eq!(VersionConstraint::from_str(&constraint.to_string()).unwrap(), constraint);
+        }
+    }
+
+    #[test]
+    fn test_constraint_best_match() {
+        let versions = vec![ Version::new(1, 0, 0), Version::new(1, 2, 0), Version::new(1, 4, 1), Version::new(2, 0, 0), Version::new(0, 9, 0) ];
+        let best = |raw: &str| VersionConstraint::from_str(raw).unwrap().best_match(&versions).cloned();
+
+        // The latest of the matching versions is taken
+        assert_eq!(best("latest"), Some(Version::new(2, 0, 0)));
+        assert_eq!(best("1.2.0"), Some(Version::new(1, 2, 0)));
+        assert_eq!(best("^1.2"), Some(Version::new(1, 4, 1)));
+        assert_eq!(best(">=1.0, <1.4"), Some(Version::new(1, 2, 0)));
+        assert_eq!(best("<1"), Some(Version::new(0, 9, 0)));
+        assert_eq!(best("~1.4"), Some(Version::new(1, 4, 1)));
+
+        // Or nothing, if none match
+        assert_eq!(best("1.3.0"), None);
+        assert_eq!(best(">=3"), None);
+        assert_eq!(VersionConstraint::Latest.best_match(&[]), None);
+
+        // Unresolved versions never match
+        assert!(!VersionConstraint::Latest.matches(&Version::latest()));
+
+        // Like in semver, ranges only match prereleases if they name one of the same version
+        let rc = Version::from_str("1.5.0-rc.1").unwrap();
+        assert!(!VersionConstraint::from_str("^1.2").unwrap().matches(&rc));
+        assert!(VersionConstraint::from_str(">=1.5.0-rc.0, <2").unwrap().matches(&rc));
+        assert!(VersionConstraint::from_str("1.5.0-rc.1").unwrap().matches(&rc));
+        assert!(!VersionConstraint::from_str("1.5.0").unwrap().matches(&rc));
+    }
+
+
+
+    #[test]
+    fn test_compatibility() {
+        // Equal versions are compatible
+        assert_eq!(Version::new(1, 2, 3).compatibility(&Version::new(1, 2, 3)), Compatibility::Compatible);
+        // As are versions that only differ in their patch or minor number
+        assert_eq!(Version::new(1, 2, 3).compatibility(&Version::new(1, 2, 9)), Compatibility::Compatible);
+        assert_eq!(Version::new(1, 5, 0).compatibility(&Version::new(1, 2, 3)), Compatibility::Compatible);
+
+        // But versions with a different major number are not
+        assert_eq!(Version::new(1, 2, 3).compatibility(&Version::new(2, 0, 0)), Compatibility::Older);
+        assert_eq!(Version::new(2, 0, 0).compatibility(&Version::new(1, 2, 3)), Compatibility::Newer);
+
+        // Before 1.0.0, the minor number acts as the major number
+        assert_eq!(Version::new(0, 5, 1).compatibility(&Version::new(0, 5, 7)), Compatibility::Compatible);
+        assert_eq!(Version::new(0, 5, 1).compatibility(&Version::new(0, 6, 0)), Compatibility::Older);
+        assert_eq!(Version::new(0, 6, 0).compatibility(&Version::new(0, 5, 1)), Compatibility::Newer);
+    }
+}
+
+
+
+
+
+/***** ENUMS *****/
+/// The verdict of Version::compatibility().
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compatibility {
+    /// The two versions can work together
+    Compatible,
+    /// This version is incompatible with the other because it is older
+    Older,
+    /// This version is incompatible with the other because it is newer
+    Newer,
 }
 
 
@@ -221,6 +397,10 @@ pub enum ParseError {
     MinorParseError{ raw: String, err: std::num::ParseIntError },
     /// Could not parse the patch version number
     PatchParseError{ raw: String, err: std::num::ParseIntError },
+    /// The prerelease part (after the '-') is not a dot-separated list of valid identifiers
+    PrereleaseParseError{ raw: String },
+    /// The build metadata (after the '+') is not a dot-separated list of valid identifiers
+    BuildParseError{ raw: String },
 }
 
 impl Display for ParseError {
@@ -230,6 +410,8 @@ impl Display for ParseError {
             ParseError::MajorParseError{ raw, err } => write!(f, "Could not parse major version number '{}': {}", raw, err),
             ParseError::MinorParseError{ raw, err } => write!(f, "Could not parse minor version number '{}': {}", raw, err),
             ParseError::PatchParseError{ raw, err } => write!(f, "Could not parse patch version number '{}': {}", raw, err),
+            ParseError::PrereleaseParseError{ raw } => write!(f, "Could not parse prerelease '{}': expected dot-separated identifiers of ASCII letters, digits and hyphens, where numbers have no leading zeros", raw),
+            ParseError::BuildParseError{ raw }      => write!(f, "Could not parse build metadata '{}': expected dot-separated identifiers of ASCII letters, digits and hyphens", raw),
         }
     }
 }
@@ -238,6 +420,73 @@ impl Error for ParseError {}
 
 
 
+/// Defines the error for when a VersionConstraint cannot be parsed.
+#[derive(Debug)]
+pub struct ConstraintParseError {
+    /// The string that we tried to parse.
+    pub raw : String,
+    /// Why it isn't a valid semver requirement.
+    pub err : semver::Error,
+}
+
+impl Display for ConstraintParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        write!(f, "Could not parse version constraint '{}': {}", self.raw, self.err)
+    }
+}
+
+impl Error for ConstraintParseError {}
+
+
+
+
+
+/***** HELPER FUNCTIONS *****/
+/// Checks whether the given string is a valid prerelease or build metadata part of a version (i.e., dot-separated, non-empty identifiers of ASCII letters, digits and hyphens).
+/// 
+/// **Arguments**
+///  * `raw`: The part to check (without the '-' or '+' in front of it).
+///  * `prerelease`: Whether it's a prerelease, whose numeric identifiers may not have leading zeros.
+/// 
+/// **Returns**  
+/// true if the part is valid, or false otherwise.
+fn valid_identifiers(raw: &str, prerelease: bool) -> bool {
+    raw.split('.').all(|identifier| {
+        !identifier.is_empty()
+            && identifier.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            && !(prerelease && identifier.len() > 1 && identifier.starts_with('0') && identifier.chars().all(|c| c.is_ascii_digit()))
+    })
+}
+
+/// Compares two prereleases by the precedence rules of semver: identifiers are compared one by one, numbers numerically and lower than anything else, and the rest in ASCII order. If all of them are equal, the one with the most identifiers is the greater one.
+/// 
+/// **Arguments**
+///  * `lhs`: The prerelease of the left version.
+///  * `rhs`: The prerelease of the right version.
+/// 
+/// **Returns**  
+/// The Ordering of the two prereleases.
+fn cmp_prerelease(lhs: &str, rhs: &str) -> Ordering {
+    let mut lhs = lhs.split('.');
+    let mut rhs = rhs.split('.');
+    loop {
+        let order = match (lhs.next(), rhs.next()) {
+            (Some(l), Some(r)) => match (u64::from_str(l), u64::from_str(r)) {
+                (Ok(l), Ok(r))   => l.cmp(&r),
+                (Ok(_), Err(_))  => Ordering::Less,
+                (Err(_), Ok(_))  => Ordering::Greater,
+                (Err(_), Err(_)) => l.cmp(r),
+            },
+            (Some(_), None) => { return Ordering::Greater; },
+            (None, Some(_)) => { return Ordering::Less; },
+            (None, None)    => { return Ordering::Equal; },
+        };
+        if order.is_ne() { return order; }
+    }
+}
+
+
+
 
 
 /***** HELPER STRUCTS *****/
@@ -274,6 +523,10 @@ pub struct Version {
     pub minor : u64,
     /// The patch version number. If all three are set to u64::MAX, is interpreted as an unresolved 'latest' version number.
     pub patch : u64,
+    /// The prerelease (e.g., `rc.1` in `1.2.0-rc.1`), which makes the version precede the release with the same numbers.
+    pub pre   : Option<String>,
+    /// The build metadata (e.g., `dfsg1` in `20.10.17+dfsg1`), which is kept but plays no role in comparisons.
+    pub build : Option<String>,
 }
 
 impl Version {
@@ -285,16 +538,15 @@ impl Version {
     ///  * `minor`: The minor version number.
     ///  * `patch`: The patch version number.
     pub const fn new(major: u64, minor: u64, patch: u64) -> Self {
-        // Create the version
-        let result = Self {
+        // If it's latest, panic; otherwise, create the version
+        if major == u64::MAX && minor == u64::MAX && patch == u64::MAX { panic!("A version with all numbers set to 9,223,372,036,854,775,807 (64-bit, unsigned integer max) cannot be created; use 'latest' instead"); }
+        Self {
             major,
             minor,
             patch,
-        };
-
-        // If it's latest, panic; otherwise, return
-        if result.is_latest() { panic!("A version with all numbers set to 9,223,372,036,854,775,807 (64-bit, unsigned integer max) cannot be created; use 'latest' instead"); }
-        result
+            pre   : None,
+            build : None,
+        }
     }
 
     /// Constructor for the Version that sets it to an (unresolved) 'latest' version.
@@ -304,6 +556,8 @@ impl Version {
             major : u64::MAX,
             minor : u64::MAX,
             patch : u64::MAX,
+            pre   : None,
+            build : None,
         }
     }
 
@@ -357,6 +611,42 @@ impl Version {
     pub const fn is_latest(&self) -> bool {
         self.major == u64::MAX && self.minor == u64::MAX && self.patch == u64::MAX
     }
+
+    /// Returns whether or not this Version is a prerelease (e.g., `1.2.0-rc.1`).
+    #[inline]
+    pub const fn is_prerelease(&self) -> bool {
+        self.pre.is_some()
+    }
+
+    /// Converts this Version to a semver Version, e.g. to match it against a semver requirement.
+    /// 
+    /// **Returns**  
+    /// The equivalent semver::Version, including the prerelease and build metadata.
+    pub fn to_semver(&self) -> semver::Version {
+        semver::Version {
+            major : self.major,
+            minor : self.minor,
+            patch : self.patch,
+            pre   : self.pre.as_deref().and_then(|pre| semver::Prerelease::new(pre).ok()).unwrap_or(semver::Prerelease::EMPTY),
+            build : self.build.as_deref().and_then(|build| semver::BuildMetadata::new(build).ok()).unwrap_or(semver::BuildMetadata::EMPTY),
+        }
+    }
+
+    /// Checks if something with this version can work together with something with the other version.
+    /// 
+    /// This follows the caret rules of semver: versions are compatible if their major numbers match, or, for versions before 1.0.0, if their minor numbers match as well.
+    /// 
+    /// **Arguments**
+    ///  * `other`: The version to check against.
+    /// 
+    /// **Returns**  
+    /// Compatible if the two versions are compatible, or otherwise whether this version is the Older or the Newer one.
+    pub fn compatibility(&self, other: &Version) -> Compatibility {
+        let compatible = if self.major == 0 && other.major == 0 { self.minor == other.minor } else { self.major == other.major };
+        if compatible { Compatibility::Compatible }
+        else if self < other { Compatibility::Older }
+        else { Compatibility::Newer }
+    }
 }
 
 impl Default for Version {
@@ -372,7 +662,8 @@ impl PartialEq for Version {
     fn eq(&self, other: &Self) -> bool {
         self.major == other.major &&
         self.minor == other.minor &&
-        self.patch == other.patch
+        self.patch == other.patch &&
+        self.pre == other.pre
     }
 }
 
@@ -387,7 +678,16 @@ impl Ord for Version {
         if order.is_ne() { return order; }
 
         // Compare the patch
-        self.patch.cmp(&other.patch)
+        let order = self.patch.cmp(&other.patch);
+        if order.is_ne() { return order; }
+
+        // Compare the prerelease, which precedes the release
+        match (&self.pre, &other.pre) {
+            (Some(lhs), Some(rhs)) => cmp_prerelease(lhs, rhs),
+            (Some(_), None)        => Ordering::Less,
+            (None, Some(_))        => Ordering::Greater,
+            (None, None)           => Ordering::Equal,
+        }
     }
 }
 
@@ -407,6 +707,22 @@ impl FromStr for Version {
             return Ok(Self::latest());
         }
 
+        // Split off the build metadata and the prerelease (in that order, since the metadata may contain hyphens)
+        let (s, build) = match s.find('+') {
+            Some(pos) => (&s[..pos], Some(&s[pos + 1..])),
+            None      => (s, None),
+        };
+        let (s, pre) = match s.find('-') {
+            Some(pos) => (&s[..pos], Some(&s[pos + 1..])),
+            None      => (s, None),
+        };
+        if let Some(pre) = pre {
+            if !valid_identifiers(pre, true) { return Err(ParseError::PrereleaseParseError{ raw: pre.to_string() }); }
+        }
+        if let Some(build) = build {
+            if !valid_identifiers(build, false) { return Err(ParseError::BuildParseError{ raw: build.to_string() }); }
+        }
+
         // Otherwise, see if we can split the string into multiple slices
         // Compute the possible dot posses first
         let dot1 = s.find('.');
@@ -468,6 +784,8 @@ impl FromStr for Version {
             major,
             minor,
             patch,
+            pre   : pre.map(String::from),
+            build : build.map(String::from),
         };
 
         // If this version is latest, then error
@@ -481,7 +799,10 @@ impl Display for Version {
         if self.is_latest() {
             write!(f, "latest")
         } else {
-            write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+            write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+            if let Some(pre) = &self.pre { write!(f, "-{}", pre)?; }
+            if let Some(build) = &self.build { write!(f, "+{}", build)?; }
+            Ok(())
         }
     }
 }
@@ -494,7 +815,8 @@ impl PartialEq<semver::Version> for Version {
         !self.is_latest() &&
         self.major == other.major &&
         self.minor == other.minor &&
-        self.patch == other.patch
+        self.patch == other.patch &&
+        self.pre.as_deref().unwrap_or("") == other.pre.as_str()
     }
 }
 
@@ -504,27 +826,15 @@ impl PartialOrd<semver::Version> for Version {
         // Do not compare if latest
         if self.is_latest() { return None; }
 
-        // Compare the major number
-        let order = self.major.cmp(&other.major);
-        if order.is_ne() { return Some(order); }
-
-        // Compare the minor number
-        let order = self.minor.cmp(&other.minor);
-        if order.is_ne() { return Some(order); }
-
-        // Compare the patch
-        Some(self.patch.cmp(&other.patch))
+        // Compare like two of our own
+        Some(self.cmp(&Version::from(other)))
     }
 }
 
 impl From<semver::Version> for Version {
     #[inline]
     fn from(version: semver::Version) -> Self {
-        Self {
-            major : version.major,
-            minor : version.minor,
-            patch : version.patch,
-        }
+        Self::from(&version)
     }
 }
 
@@ -535,6 +845,8 @@ impl From<&semver::Version> for Version {
             major : version.major,
             minor : version.minor,
             patch : version.patch,
+            pre   : if version.pre.is_empty() { None } else { Some(version.pre.to_string()) },
+            build : if version.build.is_empty() { None } else { Some(version.build.to_string()) },
         }
     }
 }
@@ -558,3 +870,87 @@ impl<'de> Deserialize<'de> for Version {
         deserializer.deserialize_str(VersionVisitor)
     }
 }
+
+
+
+
+
+/***** VERSION CONSTRAINT *****/
+/// Restricts which versions of a package are acceptable (e.g., when importing it).
+#[derive(Clone, Debug, PartialEq)]
+pub enum VersionConstraint {
+    /// Any version will do, so the latest one is taken.
+    Latest,
+    /// Only this exact version will do (written as a plain version, e.g., `1.2.0`).
+    Exact(Version),
+    /// Any version that satisfies the semver requirement (e.g., `^1.2` or `>=1.2, <2`) will do, of which the latest one is taken.
+    Range(semver::VersionReq),
+}
+
+impl VersionConstraint {
+    /// Returns whether the given version satisfies this constraint.
+    /// 
+    /// **Arguments**
+    ///  * `version`: The version to check. An unresolved 'latest' version never matches.
+    /// 
+    /// **Returns**  
+    /// true if the version is acceptable, or false otherwise.
+    pub fn matches(&self, version: &Version) -> bool {
+        if version.is_latest() { return false; }
+        match self {
+            VersionConstraint::Latest       => true,
+            VersionConstraint::Exact(exact) => version == exact,
+            VersionConstraint::Range(req)   => req.matches(&version.to_semver()),
+        }
+    }
+
+    /// Selects the best version out of the given ones, which is the latest one that satisfies this constraint.
+    /// 
+    /// **Arguments**
+    ///  * `versions`: The versions to choose from (e.g., the installed versions of a package).
+    /// 
+    /// **Returns**  
+    /// The selected version, or None if none of them satisfies this constraint.
+    pub fn best_match<'a, I: IntoIterator<Item=&'a Version>>(&self, versions: I) -> Option<&'a Version> {
+        versions.into_iter().filter(|version| self.matches(version)).max()
+    }
+}
+
+impl Default for VersionConstraint {
+    #[inline]
+    fn default() -> Self { VersionConstraint::Latest }
+}
+
+impl From<Version> for VersionConstraint {
+    /// Converts a Version into a constraint that only accepts that version, or any version if it's 'latest'.
+    #[inline]
+    fn from(version: Version) -> Self {
+        if version.is_latest() { VersionConstraint::Latest } else { VersionConstraint::Exact(version) }
+    }
+}
+
+impl FromStr for VersionConstraint {
+    type Err = ConstraintParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() { return Ok(VersionConstraint::Latest); }
+
+        // Plain versions (and 'latest') are taken literally, so '1.2.0' means exactly that version instead of semver's '^1.2.0'
+        if let Ok(version) = Version::from_str(s) { return Ok(Self::from(version)); }
+        match semver::VersionReq::parse(s) {
+            Ok(req)  => Ok(VersionConstraint::Range(req)),
+            Err(err) => Err(ConstraintParseError{ raw: s.to_string(), err }),
+        }
+    }
+}
+
+impl Display for VersionConstraint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FResult {
+        match self {
+            VersionConstraint::Latest       => write!(f, "latest"),
+            VersionConstraint::Exact(exact) => write!(f, "{}", exact),
+            VersionConstraint::Range(req)   => write!(f, "{}", req),
+        }
+    }
+}